@@ -25,4 +25,34 @@ echo "Result: " . ("Hello" . " " . "World") . "\n\n";
 echo "Test 4: Complex Constant Expression\n";
 echo "Complex result: " . ((5 + 3) * 2) . "\n\n";
 
+// Test 5: Constants propagated through variables assigned once
+echo "Test 5: Constant Propagation\n";
+$width = 8;
+$height = 5;
+$unit = "cm";
+echo "Area: " . ($width * $height) . " $unit\n";
+echo "Builtins: " . strlen("folded") . " " . strtoupper($unit) . " " . count([1, 2, 3]) . "\n";
+if ($width > 10) {
+    echo "Wide\n\n";
+} else {
+    echo "Narrow\n\n";
+}
+
+// Test 6: Small functions and getters inlined into their callers
+echo "Test 6: Inlining\n";
+function square($x) { return $x * $x; }
+
+class Box {
+    private $size;
+    public function __construct($size) { $this->size = $size; }
+    public function getSize() { return $this->size; }
+}
+
+$box = new Box(3);
+$sum = 0;
+for ($i = 0; $i < 100; $i++) {
+    $sum = $sum + square($i) + $box->getSize();
+}
+echo "Sum: " . $sum . "\n\n";
+
 echo "Constant folding complete!\n";
@@ -1,14 +1,16 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use anyhow::Result;
 use std::fs;
 use std::path::PathBuf;
+use edge_php_compiler::{BackendKind, CompilerOptions, Feature, OptLevel};
 
 #[cfg(not(target_arch = "wasm32"))]
-use wasm_opt::{OptimizationOptions, Feature};
+use wasm_opt::OptimizationOptions;
 
+mod serve;
 mod test_v2;
 
 #[derive(Parser)]
@@ -25,6 +27,42 @@ enum Commands {
     Run {
         /// The PHP file to run
         file: PathBuf,
+        /// Stop after this many function calls and loop iterations
+        #[arg(long, value_name = "UNITS")]
+        fuel: Option<u64>,
+        /// Stop when memory would grow past this many bytes
+        #[arg(long, value_name = "BYTES")]
+        memory_limit: Option<usize>,
+        /// Stop after this many seconds
+        #[arg(long, value_name = "SECONDS")]
+        max_execution_time: Option<f64>,
+        /// Directory to load Wasm extensions from, besides ./extensions (repeatable)
+        #[arg(long = "extension-dir", value_name = "DIR")]
+        extension_dirs: Vec<PathBuf>,
+        /// Run with the interpreter instead of compiling, which starts faster
+        /// but cannot call Wasm extensions
+        #[arg(long)]
+        interpret: bool,
+    },
+    /// Serve HTTP requests with a PHP file, compiled as a request handler
+    Serve {
+        /// The PHP file handling every request
+        file: PathBuf,
+        /// Address to listen on
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// Stop a request after this many function calls and loop iterations
+        #[arg(long, value_name = "UNITS")]
+        fuel: Option<u64>,
+        /// Stop a request when memory would grow past this many bytes
+        #[arg(long, value_name = "BYTES")]
+        memory_limit: Option<usize>,
+        /// Stop a request after this many seconds
+        #[arg(long, value_name = "SECONDS")]
+        max_execution_time: Option<f64>,
+        /// Directory to load Wasm extensions from, besides ./extensions (repeatable)
+        #[arg(long = "extension-dir", value_name = "DIR")]
+        extension_dirs: Vec<PathBuf>,
     },
     /// Parse a PHP file and output the AST
     Parse {
@@ -41,6 +79,78 @@ enum Commands {
         /// Optimize the generated WASM using wasm-opt
         #[arg(long)]
         optimize: bool,
+        /// Backend: gc (WasmGC) or linear (linear memory, for engines without WasmGC)
+        #[arg(long, value_name = "BACKEND", default_value = "gc")]
+        backend: BackendKind,
+        /// Optimization level: 0, 1, 2, 3 or s
+        #[arg(short = 'O', value_name = "LEVEL", default_value = "2")]
+        opt_level: OptLevel,
+        /// Do not unroll counted loops
+        #[arg(long)]
+        no_loop_unrolling: bool,
+        /// Do not run escape analysis
+        #[arg(long)]
+        no_escape_analysis: bool,
+        /// Keep every value boxed
+        #[arg(long)]
+        no_unboxing: bool,
+        /// Emit every runtime helper, used or not
+        #[arg(long)]
+        no_tree_shaking: bool,
+        /// Do not compile specialized copies of functions
+        #[arg(long)]
+        no_specialization: bool,
+        /// Compile every function from the AST, without the mid-level IR
+        #[arg(long)]
+        no_ir: bool,
+        /// Do not evaluate constant expressions or propagate constants at compile time
+        #[arg(long)]
+        no_constant_folding: bool,
+        /// Do not inline small functions and methods into their callers
+        #[arg(long)]
+        no_inlining: bool,
+        /// Do not hoist array counts out of loop conditions or strength-reduce loop counters
+        #[arg(long)]
+        no_loop_optimizations: bool,
+        /// Keep every array element boxed instead of packing int, float and string arrays
+        #[arg(long)]
+        no_typed_arrays: bool,
+        /// What to write: the module (wasm) or the mid-level IR of each function (ir)
+        #[arg(long, value_name = "KIND", default_value = "wasm")]
+        emit: Emit,
+        /// Initial memory size in 64KB pages
+        #[arg(long, value_name = "PAGES", default_value_t = 1)]
+        memory_pages: u32,
+        /// Maximum memory size in 64KB pages
+        #[arg(long, value_name = "PAGES")]
+        max_memory_pages: Option<u32>,
+        /// Target features to enable: gc (exceptions, tail-calls and simd are not supported yet)
+        #[arg(long = "enable", value_name = "FEATURE", value_delimiter = ',')]
+        enable_features: Vec<Feature>,
+        /// Target features to disable
+        #[arg(long = "disable", value_name = "FEATURE", value_delimiter = ',')]
+        disable_features: Vec<Feature>,
+        /// Emit a name section with PHP function, method, helper and variable names
+        #[arg(short = 'g', long)]
+        debug_info: bool,
+        /// Write a source map next to the output (`<output>.map`)
+        #[arg(long)]
+        source_map: bool,
+        /// Print the inferred parameter, return and variable types to stderr
+        #[arg(long)]
+        type_report: bool,
+        /// Charge fuel on function entries and loop iterations (see `run --fuel`)
+        #[arg(long)]
+        fuel_metering: bool,
+        /// Export `handle_request` instead of `_start`, for serving HTTP requests
+        #[arg(long)]
+        http_handler: bool,
+        /// Directory searched for relative include/require paths (repeatable)
+        #[arg(short = 'I', long = "include-path")]
+        include_paths: Vec<PathBuf>,
+        /// composer.json whose `autoload` section maps namespaces to directories
+        #[arg(long)]
+        composer: Option<PathBuf>,
         /// Deprecated: kept for compatibility
         #[arg(long, hide = true)]
         v2: bool,
@@ -49,40 +159,169 @@ enum Commands {
     TestV2,
 }
 
+/// What `compile` writes
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Emit {
+    /// The Wasm module
+    Wasm,
+    /// The mid-level IR of every function, after its passes
+    Ir,
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Run { file } => {
+        Commands::Run { file, fuel, memory_limit, max_execution_time, extension_dirs, interpret } => {
+            let source = fs::read_to_string(&file)?;
+            let limits = runtime_limits(fuel, memory_limit, max_execution_time);
+            let mut runtime = edge_php_runtime::Runtime::with_limits(limits)?;
+            let result = if interpret {
+                runtime.interpret_php(&source)
+            } else {
+                link_extensions(&mut runtime, extension_dirs)?;
+                let compiled = edge_php_compiler::Compiler::with_options(runtime.compiler_options()).compile(&source);
+                match compiled {
+                    Ok(wasm_bytes) => {
+                        runtime.load_module(&wasm_bytes)?;
+                        runtime.execute()
+                    }
+                    Err(error @ edge_php_compiler::CompilerError::ParserError(_)) => return Err(error.into()),
+                    // What the compiler does not support yet, the interpreter may
+                    Err(error) => {
+                        eprintln!("note: {}; running with the interpreter instead", error);
+                        runtime.interpret_php(&source)
+                    }
+                }
+            };
+            print!("{}", runtime.output());
+            if let Err(error) = result {
+                // Like PHP, a script stopped by a limit or an uncaught error exits with status 255
+                if error.is::<edge_php_runtime::FatalError>() || error.is::<edge_php_runtime::context::PhpError>() {
+                    eprintln!("{}", error);
+                    std::process::exit(255);
+                }
+                return Err(error);
+            }
+        }
+        Commands::Serve { file, listen, fuel, memory_limit, max_execution_time, extension_dirs } => {
             let source = fs::read_to_string(&file)?;
-            let mut runtime = edge_php_runtime::Runtime::new()?;
-            let output = runtime.execute_php(&source)?;
-            println!("{}", output);
+            let limits = runtime_limits(fuel, memory_limit, max_execution_time);
+            let mut runtime = edge_php_runtime::Runtime::with_limits(limits)?;
+            link_extensions(&mut runtime, extension_dirs)?;
+            runtime.load_php_handler(&source)?;
+            serve::serve(runtime, &listen)?;
         }
         Commands::Parse { file } => {
             let source = fs::read_to_string(&file)?;
             let ast = edge_php_parser::parse(&source)?;
             println!("{:#?}", ast);
         }
-        Commands::Compile { file, output, optimize, v2: _ } => {
-            let source = fs::read_to_string(&file)?;
-            
-            // Use CompilerV2 as the main compiler
-            let compiler = edge_php_compiler::Compiler::new();
-            let mut wasm_bytes = compiler.compile(&source)?;
-            
-            // Apply optimization if requested and available
-            if optimize {
-                wasm_bytes = optimize_wasm(wasm_bytes)?;
+        Commands::Compile {
+            file, output, optimize, backend, opt_level, no_loop_unrolling, no_escape_analysis, no_unboxing,
+            no_tree_shaking, no_specialization, no_ir, no_constant_folding, no_inlining, no_loop_optimizations, no_typed_arrays, emit, memory_pages, max_memory_pages, enable_features, disable_features,
+            debug_info, source_map, type_report, fuel_metering, http_handler, include_paths, composer, v2: _,
+        } => {
+            // The file is the entry point of a project: its includes and
+            // autoloaded classes are linked into the same module
+            let mut project = edge_php_compiler::Project::new(&file);
+            for path in include_paths {
+                project.add_include_path(path);
+            }
+            if let Some(composer) = composer {
+                project.load_composer_json(&composer)?;
             }
             
+            let mut options = CompilerOptions::new()
+                .backend(backend)
+                .opt_level(opt_level)
+                .initial_memory_pages(memory_pages)
+                .max_memory_pages(max_memory_pages)
+                .debug_info(debug_info)
+                .type_report(type_report)
+                .fuel_metering(fuel_metering)
+                .http_handler(http_handler);
+            // Passes only get turned off here; the level decides the rest
+            if no_loop_unrolling {
+                options = options.loop_unrolling(false);
+            }
+            if no_escape_analysis {
+                options = options.escape_analysis(false);
+            }
+            if no_unboxing {
+                options = options.unboxing(false);
+            }
+            if no_tree_shaking {
+                options = options.tree_shaking(false);
+            }
+            if no_specialization {
+                options = options.specialization(false);
+            }
+            if no_ir {
+                options = options.mid_level_ir(false);
+            }
+            if no_constant_folding {
+                options = options.constant_folding(false);
+            }
+            if no_inlining {
+                options = options.inlining(false);
+            }
+            if no_loop_optimizations {
+                options = options.loop_optimizations(false);
+            }
+            if no_typed_arrays {
+                options = options.typed_arrays(false);
+            }
+            if emit == Emit::Ir {
+                options = options.ir_dump(true);
+            }
+            for feature in enable_features {
+                options = options.enable_feature(feature);
+            }
+            for feature in disable_features {
+                options = options.disable_feature(feature);
+            }
+
+            let output_given = output.is_some();
             let output_path = output.unwrap_or_else(|| {
                 let mut path = file.clone();
                 path.set_extension("wasm");
                 path
             });
+            let mut map_path = output_path.clone().into_os_string();
+            map_path.push(".map");
+            let map_path = PathBuf::from(map_path);
+            if source_map {
+                // The map sits next to the module, so its file name is the URL
+                let url = map_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                options = options.source_map_url(url);
+            }
+
+            let compiler = edge_php_compiler::Compiler::with_options(options.clone());
+            let module = compiler.compile_project_module(&project)?;
+            if emit == Emit::Ir {
+                let ir = module.ir.unwrap_or_default();
+                if output_given {
+                    fs::write(&output_path, ir)?;
+                } else {
+                    print!("{}", ir);
+                }
+                return Ok(());
+            }
+            let mut wasm_bytes = module.wasm;
+            
+            // Apply optimization if requested and available
+            if optimize {
+                wasm_bytes = optimize_wasm(wasm_bytes, &options)?;
+            }
             
             fs::write(&output_path, &wasm_bytes)?;
+            if let Some(map) = module.source_map {
+                fs::write(&map_path, map)?;
+            }
+            if let Some(report) = module.type_report {
+                eprint!("{}", report);
+            }
             
             if optimize {
                 println!("Compiled and optimized successfully to: {}", output_path.display());
@@ -98,9 +337,33 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+fn runtime_limits(fuel: Option<u64>, memory_limit: Option<usize>, max_execution_time: Option<f64>) -> edge_php_runtime::Limits {
+    let mut limits = edge_php_runtime::Limits::new();
+    if let Some(fuel) = fuel {
+        limits = limits.fuel(fuel);
+    }
+    if let Some(bytes) = memory_limit {
+        limits = limits.memory_limit(bytes);
+    }
+    if let Some(seconds) = max_execution_time {
+        limits = limits.max_execution_time(std::time::Duration::from_secs_f64(seconds));
+    }
+    limits
+}
+
+/// Link every Wasm extension found in `dirs` and on the default search paths
+fn link_extensions(runtime: &mut edge_php_runtime::Runtime, dirs: Vec<PathBuf>) -> Result<()> {
+    let mut manager = edge_php_runtime::extension::ExtensionManager::new();
+    for dir in dirs.into_iter().rev() {
+        manager.add_search_path(dir);
+    }
+    manager.discover()?;
+    runtime.link_extensions(&manager)
+}
+
 /// Optimize WASM bytecode using wasm-opt
 #[cfg(not(target_arch = "wasm32"))]
-fn optimize_wasm(wasm_bytes: Vec<u8>) -> Result<Vec<u8>> {
+fn optimize_wasm(wasm_bytes: Vec<u8>, options: &CompilerOptions) -> Result<Vec<u8>> {
     use std::io::Write;
     use tempfile::NamedTempFile;
     
@@ -113,12 +376,31 @@ fn optimize_wasm(wasm_bytes: Vec<u8>) -> Result<Vec<u8>> {
     let output_file = NamedTempFile::new()?;
     let output_path = output_file.path();
     
-    // Apply aggressive optimization (equivalent to -O4/new_opt_level_4)
-    // Enable GC features that EdgePHP uses (struct.get, array.new, etc.)
-    OptimizationOptions::new_opt_level_4()
-        .enable_feature(Feature::Gc)
-        .enable_feature(Feature::ReferenceTypes)
-        .run(input_path, output_path)?;
+    // Aggressive optimization (equivalent to -O4/new_opt_level_4), or -Os when
+    // optimizing for size, limited to the features the target supports
+    let mut wasm_opt = match options.level() {
+        OptLevel::Os => OptimizationOptions::new_optimize_for_size(),
+        _ => OptimizationOptions::new_opt_level_4(),
+    };
+    let features = options.target_features();
+    wasm_opt.enable_feature(wasm_opt::Feature::ReferenceTypes);
+    // The linear-memory backend copies and clears blocks with memory.copy/fill
+    // and converts floats with saturating truncation
+    if options.backend_kind() == BackendKind::LinearMemory {
+        wasm_opt.enable_feature(wasm_opt::Feature::BulkMemory);
+        wasm_opt.enable_feature(wasm_opt::Feature::TruncSat);
+    }
+    for (enabled, feature) in [
+        (features.gc, wasm_opt::Feature::Gc),
+        (features.exceptions, wasm_opt::Feature::ExceptionHandling),
+        (features.tail_calls, wasm_opt::Feature::TailCall),
+        (features.simd, wasm_opt::Feature::Simd),
+    ] {
+        if enabled {
+            wasm_opt.enable_feature(feature);
+        }
+    }
+    wasm_opt.run(input_path, output_path)?;
     
     // Read optimized bytes from output file
     let optimized_bytes = std::fs::read(output_path)?;
@@ -128,7 +410,7 @@ fn optimize_wasm(wasm_bytes: Vec<u8>) -> Result<Vec<u8>> {
 
 /// Fallback for wasm32 targets where wasm-opt is not available
 #[cfg(target_arch = "wasm32")]
-fn optimize_wasm(wasm_bytes: Vec<u8>) -> Result<Vec<u8>> {
+fn optimize_wasm(wasm_bytes: Vec<u8>, _options: &CompilerOptions) -> Result<Vec<u8>> {
     eprintln!("Warning: WASM optimization is not available on wasm32 targets");
     Ok(wasm_bytes)
 }
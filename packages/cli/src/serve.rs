@@ -0,0 +1,122 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+//! `edge-php serve`: a minimal HTTP/1.1 server for trying request handlers
+//! locally. One request per connection, served one at a time.
+
+use anyhow::{anyhow, Result};
+use edge_php_runtime::{FatalError, Request, Response, Runtime};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+/// Largest request body accepted; bigger ones are answered with 413
+const MAX_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+/// How long a client may take to send its request or read the response
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The request's Content-Length is over [`MAX_BODY_SIZE`]
+#[derive(Debug)]
+struct BodyTooLarge;
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Request body larger than {} bytes", MAX_BODY_SIZE)
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+pub fn serve(mut runtime: Runtime, listen: &str) -> Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    println!("Listening on http://{}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        // A failed accept only loses that connection
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("{}", error);
+                continue;
+            }
+        };
+        let timeouts = stream.set_read_timeout(Some(IO_TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(IO_TIMEOUT)));
+        if let Err(error) = timeouts {
+            eprintln!("{}", error);
+            continue;
+        }
+        let response = match read_request(&mut stream) {
+            Ok(request) => runtime.handle_request(&request).unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                let status = if error.is::<FatalError>() { 503 } else { 500 };
+                text_response(status, "The script failed")
+            }),
+            Err(error) if error.is::<BodyTooLarge>() => text_response(413, &error.to_string()),
+            Err(error) => text_response(400, &error.to_string()),
+        };
+        if let Err(error) = write_response(&mut stream, &response) {
+            eprintln!("{}", error);
+        }
+    }
+    Ok(())
+}
+
+fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let remote_addr = stream.peer_addr()?.ip().to_string();
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(uri), Some(protocol)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(anyhow!("Malformed request line"));
+    };
+    let mut request = Request::new(method, uri).protocol(protocol).remote_addr(remote_addr);
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':').ok_or_else(|| anyhow!("Malformed header"))?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            content_length = value.parse().map_err(|_| anyhow!("Malformed Content-Length"))?;
+        }
+        request = request.header(name, value);
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(BodyTooLarge.into());
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(request.body(body))
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> Result<()> {
+    // The reason phrase may be empty
+    let mut head = format!("HTTP/1.1 {} \r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if response.header("Content-Type").is_none() {
+        head.push_str("Content-Type: text/html; charset=UTF-8\r\n");
+    }
+    head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", response.body.len()));
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&response.body)?;
+    Ok(())
+}
+
+fn text_response(status: u16, text: &str) -> Response {
+    Response {
+        status,
+        headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+        body: text.as_bytes().to_vec(),
+    }
+}
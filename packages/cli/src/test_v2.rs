@@ -1,10 +1,49 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-/// Test module for the new compiler with tagged value system
+//! Test module for the new compiler with tagged value system
 
-use edge_php_compiler::{Compiler, CompilerError};
-use edge_php_parser::parse;
+use edge_php_compiler::Compiler;
+
+pub fn run_compiler_v2_test() {
+    println!("Testing Compiler with tagged value system...
+");
+    
+    let test_cases = vec![
+        ("Basic echo", r#"<?php echo "Hello, World!"; ?>"#),
+        ("Variables", r#"<?php $x = 42; echo $x; ?>"#),
+        ("Arithmetic", r#"<?php $a = 5; $b = 3; echo $a + $b; ?>"#),
+        ("Float", r#"<?php $pi = 3.14159; echo $pi; ?>"#),
+        ("Boolean", r#"<?php $flag = true; echo $flag; ?>"#),
+    ];
+    
+    for (name, source) in test_cases {
+        println!("Testing: {}", name);
+        println!("Source: {}", source);
+        
+        let compiler = Compiler::new();
+        match compiler.compile(source) {
+            Ok(wasm_bytes) => {
+                println!("✓ Successfully compiled to {} bytes of WASM", wasm_bytes.len());
+                
+                // Show first few bytes of WASM
+                print!("  WASM header: ");
+                for (i, byte) in wasm_bytes.iter().take(16).enumerate() {
+                    if i > 0 && i % 4 == 0 {
+                        print!(" ");
+                    }
+                    print!("{:02x}", byte);
+                }
+                println!("
+");
+            }
+            Err(e) => {
+                println!("✗ Compilation failed: {:?}
+", e);
+            }
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -101,43 +140,3 @@ while ($i < 5) {
         }
     }
 }
-
-pub fn run_compiler_v2_test() {
-    println!("Testing Compiler with tagged value system...
-");
-    
-    let test_cases = vec![
-        ("Basic echo", r#"<?php echo "Hello, World!"; ?>"#),
-        ("Variables", r#"<?php $x = 42; echo $x; ?>"#),
-        ("Arithmetic", r#"<?php $a = 5; $b = 3; echo $a + $b; ?>"#),
-        ("Float", r#"<?php $pi = 3.14159; echo $pi; ?>"#),
-        ("Boolean", r#"<?php $flag = true; echo $flag; ?>"#),
-    ];
-    
-    for (name, source) in test_cases {
-        println!("Testing: {}", name);
-        println!("Source: {}", source);
-        
-        let compiler = Compiler::new();
-        match compiler.compile(source) {
-            Ok(wasm_bytes) => {
-                println!("✓ Successfully compiled to {} bytes of WASM", wasm_bytes.len());
-                
-                // Show first few bytes of WASM
-                print!("  WASM header: ");
-                for (i, byte) in wasm_bytes.iter().take(16).enumerate() {
-                    if i > 0 && i % 4 == 0 {
-                        print!(" ");
-                    }
-                    print!("{:02x}", byte);
-                }
-                println!("
-");
-            }
-            Err(e) => {
-                println!("✗ Compilation failed: {:?}
-", e);
-            }
-        }
-    }
-}
@@ -16,16 +16,16 @@ impl Compiler {
     fn add_add_function(&mut self) {
         let php_value_ref = self.get_php_value_type();
         let mut body = vec![];
-        let mut locals = vec![];
-        
         // Local variables
-        locals.push((1, ValType::I32)); // left_type
-        locals.push((1, ValType::I32)); // right_type
-        locals.push((1, ValType::I64)); // left_int
-        locals.push((1, ValType::I64)); // right_int
-        locals.push((1, ValType::F64)); // left_float
-        locals.push((1, ValType::F64)); // right_float
-        locals.push((1, ValType::I32)); // is_float
+        let locals = vec![
+            (1, ValType::I32), // left_type
+            (1, ValType::I32), // right_type
+            (1, ValType::I64), // left_int
+            (1, ValType::I64), // right_int
+            (1, ValType::F64), // left_float
+            (1, ValType::F64), // right_float
+            (1, ValType::I32), // is_float
+        ];
         
         // Get left type
         body.push(Instruction::LocalGet(0));
@@ -166,16 +166,16 @@ impl Compiler {
     fn add_subtract_function(&mut self) {
         let php_value_ref = self.get_php_value_type();
         let mut body = vec![];
-        let mut locals = vec![];
-        
         // Local variables
-        locals.push((1, ValType::I32)); // left_type
-        locals.push((1, ValType::I32)); // right_type
-        locals.push((1, ValType::I64)); // left_int
-        locals.push((1, ValType::I64)); // right_int
-        locals.push((1, ValType::F64)); // left_float
-        locals.push((1, ValType::F64)); // right_float
-        locals.push((1, ValType::I32)); // is_float
+        let locals = vec![
+            (1, ValType::I32), // left_type
+            (1, ValType::I32), // right_type
+            (1, ValType::I64), // left_int
+            (1, ValType::I64), // right_int
+            (1, ValType::F64), // left_float
+            (1, ValType::F64), // right_float
+            (1, ValType::I32), // is_float
+        ];
         
         // Get left type
         body.push(Instruction::LocalGet(0));
@@ -316,15 +316,15 @@ impl Compiler {
     fn add_multiply_function(&mut self) {
         let php_value_ref = self.get_php_value_type();
         let mut body = vec![];
-        let mut locals = vec![];
-        
         // Local variables
-        locals.push((1, ValType::I32)); // left_type
-        locals.push((1, ValType::I32)); // right_type
-        locals.push((1, ValType::F64)); // left_float
-        locals.push((1, ValType::F64)); // right_float
-        locals.push((1, ValType::F64)); // result_float
-        locals.push((1, ValType::I32)); // is_float
+        let locals = vec![
+            (1, ValType::I32), // left_type
+            (1, ValType::I32), // right_type
+            (1, ValType::F64), // left_float
+            (1, ValType::F64), // right_float
+            (1, ValType::F64), // result_float
+            (1, ValType::I32), // is_float
+        ];
         
         // Get types
         body.push(Instruction::LocalGet(0));
@@ -459,13 +459,13 @@ impl Compiler {
     
     fn add_divide_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        
         // Local variables
-        locals.push((1, ValType::I32)); // left_type
-        locals.push((1, ValType::I32)); // right_type
-        locals.push((1, ValType::F64)); // left_float
-        locals.push((1, ValType::F64)); // right_float
+        let locals = vec![
+            (1, ValType::I32), // left_type
+            (1, ValType::I32), // right_type
+            (1, ValType::F64), // left_float
+            (1, ValType::F64), // right_float
+        ];
         
         // Get types
         body.push(Instruction::LocalGet(0));
@@ -559,16 +559,16 @@ impl Compiler {
     
     fn add_modulo_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        
         // Local variables
-        locals.push((1, ValType::I32)); // left_type
-        locals.push((1, ValType::I32)); // right_type
-        locals.push((1, ValType::I64)); // left_int
-        locals.push((1, ValType::I64)); // right_int
-        locals.push((1, ValType::F64)); // left_float
-        locals.push((1, ValType::F64)); // right_float
-        locals.push((1, ValType::I32)); // is_float
+        let locals = vec![
+            (1, ValType::I32), // left_type
+            (1, ValType::I32), // right_type
+            (1, ValType::I64), // left_int
+            (1, ValType::I64), // right_int
+            (1, ValType::F64), // left_float
+            (1, ValType::F64), // right_float
+            (1, ValType::I32), // is_float
+        ];
         
         // Get types
         body.push(Instruction::LocalGet(0));
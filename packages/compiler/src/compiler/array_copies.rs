@@ -0,0 +1,177 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Arrays as values
+//
+// An array is one PhpValue that every holder shares: element writes and the
+// builtins that change an array in place (`array_push`, `sort`, ...) change
+// it for all of them. PHP arrays are values, so a stored array is copied
+// (`array_copy`) where a holder could otherwise see another one's changes:
+//
+// - assigning a variable's array to another variable, when the scope changes
+//   either of them in place or either is a global or static;
+// - assigning the result of a user function or method to a variable the scope
+//   changes in place, since it may be an array its caller still holds;
+// - storing a variable's array, a property's or a call result in a property,
+//   an element or an array literal;
+// - returning a property's array or a global's;
+// - binding a parameter, or a foreach value, the body changes in place.
+//
+// Arrays that only one holder changes are not copied, and neither are new
+// arrays (literals and the results of builtins).
+
+use super::core::*;
+use super::type_inference::InferredType;
+use edge_php_parser::ast::*;
+use edge_php_parser::visit::{self, Visitor};
+use std::collections::HashSet;
+use wasm_encoder::*;
+
+/// Builtins that change the array passed to them in place
+const IN_PLACE_BUILTINS: [&str; 5] = ["array_push", "array_pop", "array_shift", "array_unshift", "sort"];
+
+/// Where a stored value goes
+pub(super) enum Store<'a> {
+    Variable(&'a str),
+    Return,
+    /// A property, an element or an array literal
+    Other,
+}
+
+/// Where a value that may be an array comes from
+enum Source {
+    Variable(String),
+    /// A user function or method
+    Call,
+    /// A property, or something made of several sources
+    Shared,
+}
+
+/// Collects the variables whose arrays a scope changes in place, outside
+/// nested declarations
+#[derive(Default)]
+pub(super) struct ChangedArrays(pub(super) HashSet<String>);
+
+impl ChangedArrays {
+    pub(super) fn of(statements: &[Statement]) -> HashSet<String> {
+        let mut changed = ChangedArrays::default();
+        for stmt in statements {
+            changed.visit_statement(stmt);
+        }
+        changed.0
+    }
+
+    /// The variable holding the array an element of `target` is in
+    fn add_element(&mut self, target: &Expression) {
+        if let Expression::ArrayAccess { array, .. } = target {
+            if let Some(name) = root_variable(array) {
+                self.0.insert(name.clone());
+            }
+        }
+    }
+}
+
+impl Visitor for ChangedArrays {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        if !matches!(stmt, Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. }) {
+            visit::walk_statement(self, stmt);
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Assignment { left, .. } => match &**left {
+                Expression::List(items) => items.iter().flatten().for_each(|item| self.add_element(&item.value)),
+                target => self.add_element(target),
+            },
+            Expression::Unary { op: UnaryOp::PreIncrement | UnaryOp::PostIncrement | UnaryOp::PreDecrement | UnaryOp::PostDecrement, expr } => {
+                self.add_element(expr);
+            }
+            Expression::FunctionCall { name, args } if IN_PLACE_BUILTINS.contains(&name.as_str()) => {
+                if let Some(Expression::Variable(array)) = args.first() {
+                    self.0.insert(array.clone());
+                }
+            }
+            _ => {}
+        }
+        visit::walk_expression(self, expr);
+    }
+}
+
+/// The variable `$a` of `$a`, `$a[0]` or `$a[0][1]`
+fn root_variable(expr: &Expression) -> Option<&String> {
+    match expr {
+        Expression::Variable(name) => Some(name),
+        Expression::ArrayAccess { array, .. } => root_variable(array),
+        _ => None,
+    }
+}
+
+impl Compiler {
+    /// Compile a value about to be stored, copied when it is an array another
+    /// holder may change or see changed
+    pub(super) fn compile_stored_value(&mut self, value: Expression, store: Store) -> Result<(), String> {
+        let copy = self.stored_value_needs_copy(&value, &store);
+        self.compile_expression(value)?;
+        if copy {
+            self.emit(Instruction::Call(self.array_copy_fn_idx));
+        }
+        Ok(())
+    }
+
+    fn stored_value_needs_copy(&mut self, value: &Expression, store: &Store) -> bool {
+        let Some(source) = self.array_source(value) else { return false };
+        if !matches!(self.type_inference.infer_expression(value), InferredType::Array | InferredType::ArrayOf(_) | InferredType::Dynamic) {
+            return false;
+        }
+        match (source, store) {
+            (_, Store::Other) | (Source::Shared, _) => true,
+            (Source::Variable(name), Store::Variable(target)) => self.changes_array(&name) || self.changes_array(target),
+            (Source::Variable(name), Store::Return) => !self.is_loop_local(&name),
+            (Source::Call, Store::Variable(target)) => self.changes_array(target),
+            (Source::Call, Store::Return) => false,
+        }
+    }
+
+    fn array_source(&self, value: &Expression) -> Option<Source> {
+        match value {
+            Expression::Variable(name) => (name != "this").then(|| Source::Variable(name.clone())),
+            Expression::ArrayAccess { .. } => Some(root_variable(value).map_or(Source::Shared, |name| Source::Variable(name.clone()))),
+            Expression::PropertyAccess { .. } | Expression::NullsafePropertyAccess { .. } | Expression::StaticPropertyAccess { .. } => Some(Source::Shared),
+            Expression::FunctionCall { name, .. } => self.functions.contains_key(name).then_some(Source::Call),
+            Expression::MethodCall { .. } | Expression::NullsafeMethodCall { .. } | Expression::StaticMethodCall { .. } | Expression::Invoke { .. } => {
+                Some(Source::Call)
+            }
+            Expression::Ternary { then_expr, else_expr, .. } => {
+                (self.array_source(then_expr).is_some() || self.array_source(else_expr).is_some()).then_some(Source::Shared)
+            }
+            Expression::Coalesce { left, right } => {
+                (self.array_source(left).is_some() || self.array_source(right).is_some()).then_some(Source::Shared)
+            }
+            Expression::Assignment { right, .. } => self.array_source(right).map(|_| Source::Shared),
+            _ => None,
+        }
+    }
+
+    /// Whether the array of a variable may change in place while another
+    /// holder has it: this scope changes it, or other code can
+    fn changes_array(&self, name: &str) -> bool {
+        self.changed_arrays.contains(name) || !self.is_loop_local(name)
+    }
+
+    /// Copy the arrays of the parameters the body changes in place, so the
+    /// caller's stay as they were
+    pub(super) fn copy_changed_parameters(&mut self, params: &[Parameter]) {
+        for param in params {
+            if !self.changed_arrays.contains(&param.name) {
+                continue;
+            }
+            let Some(VariableInfo { local_idx, storage_type: VariableStorage::Boxed, .. }) = self.variables.get(&param.name).cloned() else {
+                continue;
+            };
+            self.emit(Instruction::LocalGet(local_idx));
+            self.emit(Instruction::Call(self.array_copy_fn_idx));
+            self.emit(Instruction::LocalSet(local_idx));
+        }
+    }
+}
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::core::*;
+use super::packed_arrays::PackedKind;
+use super::array_copies::Store;
 use edge_php_parser::ast::*;
 use wasm_encoder::*;
 
@@ -11,22 +13,25 @@ impl Compiler {
     /// PHP arrays are ordered maps that can store mixed key types (int/string)
     /// and maintain insertion order. This implementation uses WASM-GC for
     /// efficient memory management.
-    
     pub(super) fn add_array_functions(&mut self) {
         // Simple array functions (numeric indices only)
         self.add_create_array_function();
         self.add_array_get_function();
+        self.add_array_get_quiet_function();
         self.add_array_set_function();
         self.add_count_function();           // PHP count() function
         self.add_array_push_function();
         self.add_array_pop_function();
         self.add_array_shift_function();
         self.add_array_unshift_function();
+        self.add_array_unset_function();
         self.add_in_array_function();
         self.add_array_keys_function();
         self.add_array_values_function();
         self.add_array_merge_function();
         self.add_array_slice_function();
+        self.add_array_copy_function();
+        self.add_packed_array_functions();
         
         // Hash array functions (associative arrays with string/mixed keys)
         self.add_create_hash_array_function();
@@ -76,6 +81,11 @@ impl Compiler {
     
     /// Compiles simple array with only numeric indices
     fn compile_simple_array_literal(&mut self, elements: Vec<ArrayElement>) -> Result<(), String> {
+        // Ints, floats or strings only: typed storage
+        if let Some(kind) = self.packed_literal_kind(&elements) {
+            return self.compile_packed_array_literal(kind, elements);
+        }
+
         // Create empty array first
         self.emit(Instruction::Call(self.create_array_fn_idx));
         
@@ -100,7 +110,7 @@ impl Compiler {
             }
             
             // Compile value
-            self.compile_expression(element.value)?;
+            self.compile_stored_value(element.value, Store::Other)?;
             
             // Call array_set(array, key, value)
             self.emit(Instruction::Call(self.array_set_fn_idx));
@@ -147,7 +157,7 @@ impl Compiler {
                     self.compile_expression(Expression::Literal(Literal::String(key_str)))?;
 
                     // Compile value
-                    self.compile_expression(element.value)?;
+                    self.compile_stored_value(element.value, Store::Other)?;
 
                     // Call fast_hash_array_set(array, hash, key_string, value)
                     self.emit(Instruction::Call(self.fast_hash_array_set_fn_idx));
@@ -167,7 +177,7 @@ impl Compiler {
                 }
 
                 // Compile value
-                self.compile_expression(element.value)?;
+                self.compile_stored_value(element.value, Store::Other)?;
 
                 // Call hash_array_set(array, key, value)
                 self.emit(Instruction::Call(self.hash_array_set_fn_idx));
@@ -181,145 +191,15 @@ impl Compiler {
         Ok(())
     }
     
-    /// Compiles array access: $array[$key]
-    pub(super) fn compile_array_access(&mut self, array_expr: Expression, index_expr: Expression) -> Result<(), String> {
-        // PHASE 3C: Detect literal keys for optimization
-        match &index_expr {
-            Expression::Literal(Literal::String(key_str)) => {
-                // Optimized path for literal string keys
-                // Compile array expression
-                self.compile_expression(array_expr)?;
-
-                // Pre-compute hash using string interning
-                let (_intern_id, hash) = self.intern_string(key_str);
-
-                // Push hash as i32 constant
-                self.emit(Instruction::I32Const(hash));
-
-                // Push key string as PhpValue
-                self.compile_expression(index_expr)?;
-
-                // Call fast_hash_array_get(array, hash, key_string)
-                self.emit(Instruction::Call(self.fast_hash_array_get_fn_idx));
-            }
-            Expression::Literal(Literal::Integer(key_int)) => {
-                // Optimized path for literal integer keys
-                // Compile array expression
-                self.compile_expression(array_expr)?;
-
-                // Push integer key directly as i64
-                self.emit(Instruction::I64Const(*key_int));
-
-                // Call fast_array_get_int(array, int_key)
-                self.emit(Instruction::Call(self.fast_array_get_int_fn_idx));
-            }
-            _ => {
-                // Standard path for non-literal keys
-                // Compile array expression
-                self.compile_expression(array_expr)?;
-
-                // Compile index expression
-                self.compile_expression(index_expr)?;
-
-                // Call array_get(array, key)
-                self.emit(Instruction::Call(self.array_get_fn_idx));
-            }
-        }
-
-        Ok(())
-    }
-    
-    /// Compiles array assignment: $array[$key] = $value
-    pub(super) fn compile_array_assignment(&mut self, array_expr: Expression, index_expr: Expression, value_expr: Expression) -> Result<(), String> {
-        // We need to handle the case where array_set might return a new array (after conversion)
-        // Extract the variable name if array_expr is a variable
-        let var_name = match &array_expr {
-            Expression::Variable(name) => Some(name.clone()),
-            _ => None,
-        };
-
-        // PHASE 3C: Detect literal keys for optimization
-        match &index_expr {
-            Expression::Literal(Literal::String(key_str)) => {
-                // Optimized path for literal string keys
-                // Compile array expression
-                self.compile_expression(array_expr)?;
-
-                // Pre-compute hash using string interning
-                let (_intern_id, hash) = self.intern_string(key_str);
-
-                // Push hash as i32 constant
-                self.emit(Instruction::I32Const(hash));
-
-                // Push key string as PhpValue
-                self.compile_expression(index_expr)?;
-
-                // Compile value expression
-                self.compile_expression(value_expr)?;
-
-                // Call fast_hash_array_set(array, hash, key_string, value)
-                self.emit(Instruction::Call(self.fast_hash_array_set_fn_idx));
-            }
-            Expression::Literal(Literal::Integer(key_int)) => {
-                // Optimized path for literal integer keys
-                // Compile array expression
-                self.compile_expression(array_expr)?;
-
-                // Push integer key directly as i64
-                self.emit(Instruction::I64Const(*key_int));
-
-                // Compile value expression
-                self.compile_expression(value_expr)?;
-
-                // Call fast_array_set_int(array, int_key, value)
-                self.emit(Instruction::Call(self.fast_array_set_int_fn_idx));
-            }
-            _ => {
-                // Standard path for non-literal keys
-                // Compile array expression
-                self.compile_expression(array_expr)?;
-
-                // Compile index expression
-                self.compile_expression(index_expr)?;
-
-                // Compile value expression
-                self.compile_expression(value_expr)?;
-
-                // Call array_set(array, key, value) - returns the (potentially new) array
-                self.emit(Instruction::Call(self.array_set_fn_idx));
-            }
-        }
-
-        // If the array was a variable, update it with the returned value
-        if let Some(name) = var_name {
-            // Get or create local for this variable
-            let local_idx = if let Some(var_info) = self.variables.get(&name).cloned() {
-                var_info.local_idx
-            } else {
-                let idx = self.allocate_local(self.get_php_value_type());
-                self.variables.insert(name.clone(), VariableInfo {
-                    local_idx: idx,
-                    storage_type: VariableStorage::Boxed,
-                    class_type: None,
-                });
-                idx
-            };
-
-            // Duplicate the result for both storing and returning
-            self.emit(Instruction::LocalTee(local_idx));
-        }
-
-        Ok(())
-    }
-    
     /// Creates an empty PHP array using WASM-GC
     fn add_create_array_function(&mut self) {
         let php_value_ref = self.get_php_value_type();
         let create_array_type = self.builder.add_type(vec![], vec![php_value_ref]);
         
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_array_type())); // array ref local
+        let locals = vec![
+            (1, self.get_array_type()), // array ref local
+        ];
         
         // Create empty array with reasonable initial capacity
         body.push(Instruction::I32Const(16)); // Initial capacity
@@ -343,14 +223,15 @@ impl Compiler {
     /// Handles both simple arrays and hash tables
     fn add_array_get_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::Ref(RefType {
-            nullable: true,
-            heap_type: HeapType::Abstract {
-                shared: false,
-                ty: AbstractHeapType::Any,
-            },
-        }))); // anyref for array field
+        let locals = vec![
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Abstract {
+                    shared: false,
+                    ty: AbstractHeapType::Any,
+                },
+            })), // anyref for array field
+        ];
         
         // Get array field from PhpValue
         body.push(Instruction::LocalGet(0)); // array parameter
@@ -372,6 +253,22 @@ impl Compiler {
         
         body.push(Instruction::Else);
         
+        // Typed storage boxes the element
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::RefTestNullable(HeapType::Concrete(self.gc_types.php_array)));
+        body.push(Instruction::I32Eqz);
+        body.push(Instruction::If(BlockType::Result(self.get_php_value_type())));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        body.push(Instruction::I32WrapI64);
+        body.push(Instruction::Call(self.array_element_fn_idx));
+        
+        body.push(Instruction::Else);
+        
         // It's a simple array - get integer index and access directly
         // Cast to simple array first
         body.push(Instruction::LocalGet(2));
@@ -389,32 +286,144 @@ impl Compiler {
         // Get element at index
         body.push(Instruction::ArrayGet(self.gc_types.php_array));
         
+        body.push(Instruction::End);
         body.push(Instruction::End);
         
         self.builder.set_function_at_index(self.array_get_fn_idx, self.values_to_value_type_idx, locals, body);
     }
     
+    /// Gets value from array by key without trapping: array_get_quiet(array, key) -> value
+    /// Returns null when the container is not an array, the key is missing or the index is
+    /// out of bounds. Used by ??, isset(), empty() and list() destructuring.
+    fn add_array_get_quiet_function(&mut self) {
+        let mut body = vec![];
+        let locals = vec![
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Abstract {
+                    shared: false,
+                    ty: AbstractHeapType::Any,
+                },
+            })), // anyref for array field
+            (1, self.get_php_value_type()), // normalized key
+            (1, ValType::I64), // integer index
+        ];
+        
+        // Not an array - nothing to look up
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_TYPE,
+        });
+        body.push(Instruction::I32Const(TYPE_ARRAY as i32));
+        body.push(Instruction::I32Ne);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::Call(self.create_null_fn_idx));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+        
+        // Get array field from PhpValue
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::LocalSet(2));
+        
+        // Hash tables already return null for missing keys
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::RefTestNullable(HeapType::Concrete(self.gc_types.php_hash_table)));
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::Call(self.hash_array_get_fn_idx));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+        
+        // Simple array: only integer keys (after normalizing numeric strings) can match
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::Call(self.normalize_key_fn_idx));
+        body.push(Instruction::LocalTee(3));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_TYPE,
+        });
+        body.push(Instruction::I32Const(TYPE_INT as i32));
+        body.push(Instruction::I32Ne);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::Call(self.create_null_fn_idx));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+        
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        body.push(Instruction::LocalSet(4));
+        
+        // Bounds check against the logical length (index < 0 || index >= length)
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::I64Const(0));
+        body.push(Instruction::I64LtS);
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT, // logical length stored here for simple arrays
+        });
+        body.push(Instruction::I64GeS);
+        body.push(Instruction::I32Or);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::Call(self.create_null_fn_idx));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+        
+        // Get element, mapping empty slots to null; typed storage boxes it
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::I32WrapI64);
+        body.push(Instruction::Call(self.array_element_fn_idx));
+        body.push(Instruction::LocalTee(3));
+        body.push(Instruction::RefIsNull);
+        body.push(Instruction::If(BlockType::Result(self.get_php_value_type())));
+        body.push(Instruction::Call(self.create_null_fn_idx));
+        body.push(Instruction::Else);
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::End);
+        
+        self.builder.set_function_at_index(self.array_get_quiet_fn_idx, self.values_to_value_type_idx, locals, body);
+    }
+    
     /// Sets value in array by key: array_set(array, key, value) -> array
     /// Handles both simple arrays and hash tables
     fn add_array_set_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::Ref(RefType {
-            nullable: true,
-            heap_type: HeapType::Abstract {
-                shared: false,
-                ty: AbstractHeapType::Any,
-            },
-        }))); // anyref for array field
-        locals.push((1, ValType::I32)); // key type
-        locals.push((1, self.get_php_value_type())); // new hash array
-        locals.push((1, ValType::Ref(RefType {
-            nullable: true,
-            heap_type: HeapType::Concrete(self.gc_types.php_array),
-        }))); // simple array ref
-        locals.push((1, ValType::I32)); // array length
-        locals.push((1, ValType::I32)); // loop counter
-        locals.push((1, self.get_php_value_type())); // element value
+        let mut locals = vec![
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Abstract {
+                    shared: false,
+                    ty: AbstractHeapType::Any,
+                },
+            })), // anyref for array field
+            (1, ValType::I32), // key type
+            (1, self.get_php_value_type()), // new hash array
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(self.gc_types.php_array),
+            })), // simple array ref
+            (1, ValType::I32), // array length
+            (1, ValType::I32), // loop counter
+            (1, self.get_php_value_type()), // element value
+            (1, ValType::I64), // integer key
+        ];
+        for kind in PackedKind::ALL {
+            locals.push((1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(self.packed_array_type(kind)),
+            }))); // typed storage
+        }
         
         // Get array field from PhpValue
         body.push(Instruction::LocalGet(0)); // array parameter
@@ -446,24 +455,77 @@ impl Compiler {
         
         body.push(Instruction::End);
         
-        // Try simple array if key is integer
+        // Typed storage takes a value of its element type at an integer key
+        // up to the length
         body.push(Instruction::LocalGet(4)); // key type
         body.push(Instruction::I32Const(TYPE_INT as i32));
         body.push(Instruction::I32Eq);
         body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        body.push(Instruction::LocalSet(10));
+        for (position, kind) in PackedKind::ALL.into_iter().enumerate() {
+            body.push(Instruction::LocalGet(2));
+            body.push(Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_TYPE,
+            });
+            body.push(Instruction::I32Const(kind.tag() as i32));
+            body.push(Instruction::I32Eq);
+            body.push(Instruction::If(BlockType::Empty));
+            self.emit_packed_store(&mut body, kind, 0, 3, 10, 11 + position as u32, 7, &[
+                Instruction::LocalGet(2),
+                Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index: kind.field(),
+                },
+            ]);
+            body.push(Instruction::End);
+        }
+        body.push(Instruction::End);
         
-        // Handle simple array with integer key
-        body.push(Instruction::LocalGet(3));
-        body.push(Instruction::RefCastNullable(HeapType::Concrete(self.gc_types.php_array)));
+        // Anything else is stored boxed
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Call(self.array_deoptimize_fn_idx));
+        body.push(Instruction::LocalSet(3));
         
-        // Get index from key
-        body.push(Instruction::LocalGet(1)); // key parameter
+        // Try simple array if key is an integer that overwrites or appends;
+        // a gap or a negative key makes it a hash table
+        body.push(Instruction::LocalGet(4)); // key type
+        body.push(Instruction::I32Const(TYPE_INT as i32));
+        body.push(Instruction::I32Eq);
+        body.push(Instruction::LocalGet(1));
         body.push(Instruction::StructGet {
             struct_type_index: self.gc_types.php_value,
             field_index: PHPVALUE_INT,
         });
-        body.push(Instruction::I32WrapI64); // index
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        }); // logical length
+        body.push(Instruction::I64LeU);
+        body.push(Instruction::I32And);
+        body.push(Instruction::If(BlockType::Empty));
         
+        // Handle simple array with integer key, growing it when full
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::RefCastNullable(HeapType::Concrete(self.gc_types.php_array)));
+        body.push(Instruction::LocalSet(6));
+        let index = [
+            Instruction::LocalGet(1), // key parameter
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            },
+            Instruction::I32WrapI64,
+        ];
+        self.emit_ensure_capacity(&mut body, self.gc_types.php_array, 0, 6, &index, 7);
+        body.push(Instruction::LocalGet(6));
+        body.extend_from_slice(&index);
         body.push(Instruction::LocalGet(2)); // value parameter
         body.push(Instruction::ArraySet(self.gc_types.php_array));
         
@@ -504,7 +566,7 @@ impl Compiler {
         
         body.push(Instruction::Else);
         
-        // Key is not integer - need to convert simple array to hash table
+        // Any other key - need to convert simple array to hash table
         // Create a new hash table
         body.push(Instruction::Call(self.create_hash_array_fn_idx));
         body.push(Instruction::LocalSet(5)); // Store new hash array in local 5
@@ -595,14 +657,15 @@ impl Compiler {
     /// Returns the logical length for simple arrays or count for hash arrays
     fn add_count_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::Ref(RefType {
-            nullable: true,
-            heap_type: HeapType::Abstract {
-                shared: false,
-                ty: AbstractHeapType::Any,
-            },
-        }))); // anyref for array field
+        let locals = vec![
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Abstract {
+                    shared: false,
+                    ty: AbstractHeapType::Any,
+                },
+            })), // anyref for array field
+        ];
         
         // Get array field from PhpValue
         body.push(Instruction::LocalGet(0)); // array parameter
@@ -644,8 +707,9 @@ impl Compiler {
     /// Pushes value to end of array: array_push(array, value) -> array
     fn add_array_push_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_php_value_type())); // local for key PhpValue
+        let locals = vec![
+            (1, self.get_php_value_type()), // local for key PhpValue
+        ];
         
         // Get current length and convert to PhpValue key
         body.push(Instruction::LocalGet(0)); // array
@@ -666,10 +730,11 @@ impl Compiler {
     /// Pops and returns last element from array: array_pop(array) -> value
     fn add_array_pop_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // count local
-        locals.push((1, self.get_php_value_type())); // key PhpValue
-        locals.push((1, self.get_php_value_type())); // result value
+        let locals = vec![
+            (1, ValType::I32), // count local
+            (1, self.get_php_value_type()), // key PhpValue
+            (1, self.get_php_value_type()), // result value
+        ];
 
         // Get array count
         body.push(Instruction::LocalGet(0)); // array
@@ -713,10 +778,11 @@ impl Compiler {
     /// Removes and returns first element: array_shift(array) -> value
     fn add_array_shift_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // count local
-        locals.push((1, self.get_php_value_type())); // key PhpValue for 0
-        locals.push((1, self.get_php_value_type())); // result value
+        let locals = vec![
+            (1, ValType::I32), // count local
+            (1, self.get_php_value_type()), // key PhpValue for 0
+            (1, self.get_php_value_type()), // result value
+        ];
 
         // Get array count
         body.push(Instruction::LocalGet(0)); // array
@@ -755,8 +821,9 @@ impl Compiler {
     /// Adds elements to beginning: array_unshift(array, value) -> array length
     fn add_array_unshift_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_php_value_type())); // key for index 0
+        let locals = vec![
+            (1, self.get_php_value_type()), // key for index 0
+        ];
 
         // For simplicity, we'll insert at index 0 and shift existing elements
         // This is a simplified implementation - full implementation would shift all elements
@@ -775,18 +842,212 @@ impl Compiler {
         self.builder.set_function_at_index(self.array_unshift_fn_idx, self.values_to_value_type_idx, locals, body);
     }
 
+    /// Removes an element: array_unset(array, key) -> array. The array changes
+    /// in place; a packed array loses its order of keys and becomes a hash table.
+    fn add_array_unset_function(&mut self) {
+        let mut body = vec![];
+        let locals = vec![
+            (1, self.get_php_value_type()), // local 2: normalized key
+            (1, ValType::I32), // local 3: hash
+            (1, self.get_hash_table_type()), // local 4: hash table
+            (1, self.get_array_entry_type()), // local 5: entry
+            (1, self.get_array_type()), // local 6: simple array
+            (1, ValType::I32), // local 7: length
+            (1, ValType::I32), // local 8: loop index
+            (1, self.get_php_value_type()), // local 9: new hash array
+            (1, self.get_php_value_type()), // local 10: element
+        ];
+
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::Call(self.normalize_key_fn_idx));
+        body.push(Instruction::LocalSet(2));
+
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::RefTestNullable(HeapType::Concrete(self.gc_types.php_hash_table)));
+        body.push(Instruction::If(BlockType::Empty));
+
+        // Hash table: empty the key's bucket if the key is in it
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::RefCastNullable(HeapType::Concrete(self.gc_types.php_hash_table)));
+        body.push(Instruction::LocalSet(4));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::Call(self.key_to_string_fn_idx));
+        body.push(Instruction::Call(self.hash_string_fn_idx));
+        body.push(Instruction::LocalSet(3));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_BUCKETS,
+        });
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_SIZE,
+        });
+        body.push(Instruction::I32RemU);
+        body.push(Instruction::ArrayGet(self.gc_types.php_hash_array));
+        body.push(Instruction::LocalTee(5));
+        body.push(Instruction::RefIsNull);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+        body.push(Instruction::LocalGet(5));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_array_entry,
+            field_index: ARRAYENTRY_KEY,
+        });
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::Call(self.equal_fn_idx));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        body.push(Instruction::I32WrapI64);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_BUCKETS,
+        });
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_SIZE,
+        });
+        body.push(Instruction::I32RemU);
+        body.push(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_array_entry)));
+        body.push(Instruction::ArraySet(self.gc_types.php_hash_array));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_COUNT,
+        });
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Sub);
+        body.push(Instruction::StructSet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_COUNT,
+        });
+        body.push(Instruction::End);
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+
+        // Packed array: only an integer key below the length is in it
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Call(self.array_deoptimize_fn_idx));
+        body.push(Instruction::LocalSet(6));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        body.push(Instruction::I32WrapI64);
+        body.push(Instruction::LocalSet(7));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_TYPE,
+        });
+        body.push(Instruction::I32Const(TYPE_INT as i32));
+        body.push(Instruction::I32Ne);
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        body.push(Instruction::LocalGet(7));
+        body.push(Instruction::I64ExtendI32U);
+        body.push(Instruction::I64GeU);
+        body.push(Instruction::I32Or);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+
+        // Copy every other element into a hash table, which becomes the storage
+        body.push(Instruction::Call(self.create_hash_array_fn_idx));
+        body.push(Instruction::LocalSet(9));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalSet(8));
+        body.push(Instruction::Block(BlockType::Empty));
+        body.push(Instruction::Loop(BlockType::Empty));
+        body.push(Instruction::LocalGet(8));
+        body.push(Instruction::LocalGet(7));
+        body.push(Instruction::I32GeU);
+        body.push(Instruction::BrIf(1));
+        body.push(Instruction::LocalGet(6));
+        body.push(Instruction::LocalGet(8));
+        body.push(Instruction::ArrayGet(self.gc_types.php_array));
+        body.push(Instruction::LocalSet(10));
+        body.push(Instruction::LocalGet(10));
+        body.push(Instruction::RefIsNull);
+        body.push(Instruction::LocalGet(8));
+        body.push(Instruction::I64ExtendI32U);
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        body.push(Instruction::I64Eq);
+        body.push(Instruction::I32Or);
+        body.push(Instruction::I32Eqz);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(9));
+        body.push(Instruction::LocalGet(8));
+        body.push(Instruction::I64ExtendI32U);
+        body.push(Instruction::Call(self.create_int_fn_idx));
+        body.push(Instruction::LocalGet(10));
+        body.push(Instruction::Call(self.hash_array_set_fn_idx));
+        body.push(Instruction::Drop);
+        body.push(Instruction::End);
+        body.push(Instruction::LocalGet(8));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalSet(8));
+        body.push(Instruction::Br(0));
+        body.push(Instruction::End); // End loop
+        body.push(Instruction::End); // End block
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::LocalGet(9));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::StructSet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::LocalGet(0));
+
+        self.builder.set_function_at_index(self.array_unset_fn_idx, self.values_to_value_type_idx, locals, body);
+    }
+
     /// Checks if value exists in array: in_array(needle, haystack) -> bool
     fn add_in_array_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // count
-        locals.push((1, ValType::I32)); // loop index
-        locals.push((1, self.get_php_value_type())); // current element
-        locals.push((1, self.get_php_value_type())); // result (to store found value)
+        let locals = vec![
+            (1, ValType::I32), // count
+            (1, ValType::I32), // loop index
+            (1, self.get_php_value_type()), // current element
+            (1, self.get_php_value_type()), // result (to store found value)
+        ];
 
         // Initialize result to false
-        body.push(Instruction::I64Const(0));
-        body.push(Instruction::Call(self.create_int_fn_idx)); // PhpValue false (int 0)
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::Call(self.create_bool_fn_idx));
         body.push(Instruction::LocalSet(5)); // result = false
 
         // Get array count
@@ -831,8 +1092,8 @@ impl Compiler {
         body.push(Instruction::If(BlockType::Empty));
 
         // Found! Set result to true and break
-        body.push(Instruction::I64Const(1));
-        body.push(Instruction::Call(self.create_int_fn_idx)); // PhpValue true (int 1)
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::Call(self.create_bool_fn_idx));
         body.push(Instruction::LocalSet(5)); // result = true
         body.push(Instruction::Br(2)); // break out of loop and block
 
@@ -857,14 +1118,14 @@ impl Compiler {
     /// Gets all array keys: array_keys(array) -> array
     fn add_array_keys_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-
         // Local 1: count (i32)
         // Local 2: result array (array ref)
         // Local 3: loop index (i32)
-        locals.push((1, ValType::I32)); // count
-        locals.push((1, self.get_array_type())); // result array
-        locals.push((1, ValType::I32)); // loop index
+        let locals = vec![
+            (1, ValType::I32), // count
+            (1, self.get_array_type()), // result array
+            (1, ValType::I32), // loop index
+        ];
 
         // Get array count using count function
         body.push(Instruction::LocalGet(0));
@@ -934,14 +1195,163 @@ impl Compiler {
     }
     
     /// Gets all array values: array_values(array) -> array  
+    /// Returns a new packed array of the values in iteration order, keyed from 0
     fn add_array_values_function(&mut self) {
         let mut body = vec![];
-        
-        // For simplified implementation, return the same array
-        // TODO: Implement proper value extraction with reindexing
+        let mut locals = vec![
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Abstract {
+                    shared: false,
+                    ty: AbstractHeapType::Any,
+                },
+            })), // local 1: storage (simple array or hash table)
+            (1, self.get_array_type()), // local 2: result array
+            (1, ValType::I32), // local 3: source length
+            (1, ValType::I32), // local 4: loop index
+            (1, ValType::I32), // local 5: result length
+            (1, self.get_hash_array_type()), // local 6: buckets
+            (1, self.get_array_entry_type()), // local 7: current entry
+            (1, self.get_php_value_type()), // local 8: current element
+        ];
+        for kind in PackedKind::ALL {
+            locals.push((1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(self.packed_array_type(kind)),
+            }))); // locals 9-11: typed storage copies
+        }
+
+        // Not an array - returned as is
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_TYPE,
+        });
+        body.push(Instruction::I32Const(TYPE_ARRAY as i32));
+        body.push(Instruction::I32Ne);
+        body.push(Instruction::If(BlockType::Empty));
         body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::LocalSet(1));
+
+        // Typed storage never has holes: copy it
+        for (position, kind) in PackedKind::ALL.into_iter().enumerate() {
+            self.emit_packed_copy(&mut body, kind, 0, 1, 9 + position as u32);
+        }
+
+        // Hash table: the values of every bucket entry, like foreach visits them
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::RefTestNullable(HeapType::Concrete(self.gc_types.php_hash_table)));
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::RefCastNonNull(HeapType::Concrete(self.gc_types.php_hash_table)));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_COUNT,
+        });
+        body.push(Instruction::ArrayNewDefault(self.gc_types.php_array));
+        body.push(Instruction::LocalSet(2));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::RefCastNonNull(HeapType::Concrete(self.gc_types.php_hash_table)));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_BUCKETS,
+        });
+        body.push(Instruction::LocalTee(6));
+        body.push(Instruction::ArrayLen);
+        body.push(Instruction::LocalSet(3));
+        body.push(Instruction::Block(BlockType::Empty));
+        body.push(Instruction::Loop(BlockType::Empty));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::I32GeU);
+        body.push(Instruction::BrIf(1));
+        body.push(Instruction::LocalGet(6));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::ArrayGet(self.gc_types.php_hash_array));
+        body.push(Instruction::LocalTee(7));
+        body.push(Instruction::RefIsNull);
+        body.push(Instruction::I32Eqz);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::LocalGet(5));
+        body.push(Instruction::LocalGet(7));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_array_entry,
+            field_index: ARRAYENTRY_VALUE,
+        });
+        body.push(Instruction::ArraySet(self.gc_types.php_array));
+        body.push(Instruction::LocalGet(5));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalSet(5));
+        body.push(Instruction::End);
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalSet(4));
+        body.push(Instruction::Br(0));
+        body.push(Instruction::End); // End loop
+        body.push(Instruction::End); // End block
+        body.push(Instruction::Else);
+
+        // Simple array: the elements up to the length, without holes
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        body.push(Instruction::I32WrapI64);
+        body.push(Instruction::LocalTee(3));
+        body.push(Instruction::ArrayNewDefault(self.gc_types.php_array));
+        body.push(Instruction::LocalSet(2));
+        body.push(Instruction::Block(BlockType::Empty));
+        body.push(Instruction::Loop(BlockType::Empty));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::I32GeU);
+        body.push(Instruction::BrIf(1));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::Call(self.array_element_fn_idx));
+        body.push(Instruction::LocalTee(8));
+        body.push(Instruction::RefIsNull);
+        body.push(Instruction::I32Eqz);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::LocalGet(5));
+        body.push(Instruction::LocalGet(8));
+        body.push(Instruction::ArraySet(self.gc_types.php_array));
+        body.push(Instruction::LocalGet(5));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalSet(5));
+        body.push(Instruction::End);
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalSet(4));
+        body.push(Instruction::Br(0));
+        body.push(Instruction::End); // End loop
+        body.push(Instruction::End); // End block
+        body.push(Instruction::End);
+
+        body.push(Instruction::I32Const(TYPE_ARRAY as i32));
+        body.push(Instruction::LocalGet(5));
+        body.push(Instruction::I64ExtendI32U);
+        body.push(Instruction::F64Const(0.0.into()));
+        body.push(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string)));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::StructNew(self.gc_types.php_value));
         
-        self.builder.set_function_at_index(self.array_values_fn_idx, self.value_to_value_type_idx, vec![], body);
+        self.builder.set_function_at_index(self.array_values_fn_idx, self.value_to_value_type_idx, locals, body);
     }
     
     /// Helper to get array type reference
@@ -958,9 +1368,10 @@ impl Compiler {
         let create_hash_array_type = self.builder.add_type(vec![], vec![php_value_ref]);
         
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_hash_array_type())); // buckets array
-        locals.push((1, self.get_hash_table_type())); // hash table struct
+        let locals = vec![
+            (1, self.get_hash_array_type()), // buckets array
+            (1, self.get_hash_table_type()), // hash table struct
+        ];
         
         // Create empty buckets array with initial capacity
         body.push(Instruction::I32Const(16)); // Initial capacity for hash table
@@ -991,14 +1402,15 @@ impl Compiler {
     /// Gets value from hash array by key: hash_array_get(array, key) -> value
     fn add_hash_array_get_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_hash_table_type())); // hash table
-        locals.push((1, ValType::I32)); // hash value
-        locals.push((1, ValType::I32)); // bucket index
-        locals.push((1, self.get_hash_array_type())); // buckets array
-        locals.push((1, self.get_array_entry_type())); // current entry
-        locals.push((1, self.get_php_value_type())); // key string for comparison
-        locals.push((1, self.get_php_value_type())); // normalized key
+        let locals = vec![
+            (1, self.get_hash_table_type()), // hash table
+            (1, ValType::I32), // hash value
+            (1, ValType::I32), // bucket index
+            (1, self.get_hash_array_type()), // buckets array
+            (1, self.get_array_entry_type()), // current entry
+            (1, self.get_php_value_type()), // key string for comparison
+            (1, self.get_php_value_type()), // normalized key
+        ];
         
         // Get hash table from PhpValue
         body.push(Instruction::LocalGet(0)); // array parameter
@@ -1117,13 +1529,14 @@ impl Compiler {
     /// Sets value in hash array by key: hash_array_set(array, key, value) -> array
     fn add_hash_array_set_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_hash_table_type())); // local 3: hash table
-        locals.push((1, ValType::I32)); // local 4: hash value
-        locals.push((1, ValType::I32)); // local 5: bucket index
-        locals.push((1, self.get_array_entry_type())); // local 6: new entry
-        locals.push((1, self.get_php_value_type())); // local 7: normalized key
-        locals.push((1, self.get_array_entry_type())); // local 8: existing entry
+        let locals = vec![
+            (1, self.get_hash_table_type()), // local 3: hash table
+            (1, ValType::I32), // local 4: hash value
+            (1, ValType::I32), // local 5: bucket index
+            (1, self.get_array_entry_type()), // local 6: new entry
+            (1, self.get_php_value_type()), // local 7: normalized key
+            (1, self.get_array_entry_type()), // local 8: existing entry
+        ];
         
         // Get hash table from PhpValue
         body.push(Instruction::LocalGet(0)); // array parameter
@@ -1314,11 +1727,12 @@ impl Compiler {
     /// Hash function for string keys using FNV-1a algorithm
     fn add_hash_string_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // hash value
-        locals.push((1, ValType::I32)); // string length
-        locals.push((1, ValType::I32)); // loop counter
-        locals.push((1, ValType::I32)); // current char
+        let locals = vec![
+            (1, ValType::I32), // hash value
+            (1, ValType::I32), // string length
+            (1, ValType::I32), // loop counter
+            (1, ValType::I32), // current char
+        ];
         
         // Check key type
         body.push(Instruction::LocalGet(0)); // key parameter
@@ -1424,11 +1838,11 @@ impl Compiler {
     
     /// Convert key to string for comparison (PHP key type casting)
     fn add_key_to_string_function(&mut self) {
-        let mut body = vec![];
-        
-        // For now, just call the existing to_string function
-        body.push(Instruction::LocalGet(0)); // key parameter
-        body.push(Instruction::Call(self.to_string_fn_idx));
+        // For now, just call the existing to_string function
+        let body = vec![
+            Instruction::LocalGet(0), // key parameter
+            Instruction::Call(self.to_string_fn_idx),
+        ];
         
         let locals = vec![]; // No locals needed
         self.builder.set_function_at_index(self.key_to_string_fn_idx, self.value_to_value_type_idx, locals, body);
@@ -1464,9 +1878,10 @@ impl Compiler {
     /// - Other types are converted to strings
     fn add_normalize_key_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // key type
-        locals.push((1, self.get_php_value_type())); // result for checking
+        let locals = vec![
+            (1, ValType::I32), // key type
+            (1, self.get_php_value_type()), // result for checking
+        ];
         
         // Get key type
         body.push(Instruction::LocalGet(0));
@@ -1532,16 +1947,17 @@ impl Compiler {
     /// Returns integer PhpValue if successful, original string PhpValue if not
     fn add_string_to_int_if_numeric_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // string length
-        locals.push((1, ValType::I32)); // current position
-        locals.push((1, ValType::I64)); // accumulated value
-        locals.push((1, ValType::I32)); // is negative
-        locals.push((1, ValType::I32)); // current char
-        locals.push((1, ValType::Ref(RefType {
-            nullable: true,
-            heap_type: HeapType::Concrete(self.gc_types.php_string),
-        }))); // string ref
+        let locals = vec![
+            (1, ValType::I32), // string length
+            (1, ValType::I32), // current position
+            (1, ValType::I64), // accumulated value
+            (1, ValType::I32), // is negative
+            (1, ValType::I32), // current char
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(self.gc_types.php_string),
+            })), // string ref
+        ];
         
         // Get string from PhpValue
         body.push(Instruction::LocalGet(0));
@@ -1672,18 +2088,20 @@ impl Compiler {
 
     fn add_string_to_float_if_numeric_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // string length
-        locals.push((1, ValType::I32)); // current position
-        locals.push((1, ValType::F64)); // accumulated value
-        locals.push((1, ValType::I32)); // is negative
-        locals.push((1, ValType::I32)); // current char
-        locals.push((1, ValType::Ref(RefType {
-            nullable: true,
-            heap_type: HeapType::Concrete(self.gc_types.php_string),
-        }))); // string ref
-        locals.push((1, ValType::I32)); // has_decimal_point
-        locals.push((1, ValType::F64)); // decimal_multiplier
+        let locals = vec![
+            (1, ValType::I32), // string length
+            (1, ValType::I32), // current position
+            (1, ValType::F64), // accumulated value
+            (1, ValType::I32), // is negative
+            (1, ValType::I32), // current char
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(self.gc_types.php_string),
+            })), // string ref
+            (1, ValType::I32), // has_decimal_point
+            (1, ValType::F64), // decimal_multiplier
+            (1, ValType::I32), // has_digit
+        ];
         
         // Get string from PhpValue
         body.push(Instruction::LocalGet(0));
@@ -1718,27 +2136,49 @@ impl Compiler {
         body.push(Instruction::LocalSet(7)); // has_decimal_point = false
         body.push(Instruction::F64Const(1.0.into()));
         body.push(Instruction::LocalSet(8)); // decimal_multiplier = 1.0
-        
-        // Check for leading minus sign
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalSet(9)); // has_digit = false
+
+        // Skip leading whitespace
+        body.push(Instruction::Block(BlockType::Empty));
+        body.push(Instruction::Loop(BlockType::Empty));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::I32Eq);
+        body.push(Instruction::BrIf(1));
         body.push(Instruction::LocalGet(6));
         body.push(Instruction::RefAsNonNull);
-        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::ArrayGetU(self.gc_types.php_string));
+        Self::push_is_whitespace(&mut body, 5);
+        body.push(Instruction::I32Eqz);
+        body.push(Instruction::BrIf(1));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalSet(2));
+        body.push(Instruction::Br(0));
+        body.push(Instruction::End);
+        body.push(Instruction::End);
+
+        // Check for a minus sign
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::I32LtU);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(6));
+        body.push(Instruction::RefAsNonNull);
+        body.push(Instruction::LocalGet(2));
         body.push(Instruction::ArrayGetU(self.gc_types.php_string));
         body.push(Instruction::I32Const(45)); // '-' character
         body.push(Instruction::I32Eq);
         body.push(Instruction::If(BlockType::Empty));
         body.push(Instruction::I32Const(1));
         body.push(Instruction::LocalSet(4)); // is_negative = true
+        body.push(Instruction::LocalGet(2));
         body.push(Instruction::I32Const(1));
-        body.push(Instruction::LocalSet(2)); // position = 1
-        
-        // If only minus sign, not numeric
-        body.push(Instruction::LocalGet(1));
-        body.push(Instruction::I32Const(1));
-        body.push(Instruction::I32Eq);
-        body.push(Instruction::If(BlockType::Empty));
-        body.push(Instruction::LocalGet(0)); // Return original
-        body.push(Instruction::Return);
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalSet(2)); // position++
         body.push(Instruction::End);
         body.push(Instruction::End);
         
@@ -1781,6 +2221,11 @@ impl Compiler {
         
         body.push(Instruction::Else);
         
+        // Trailing whitespace ends the number; only more whitespace may follow
+        body.push(Instruction::LocalGet(5));
+        Self::push_is_whitespace(&mut body, 5);
+        body.push(Instruction::BrIf(2));
+
         // Check if digit (char >= '0' && char <= '9')
         body.push(Instruction::LocalGet(5));
         body.push(Instruction::I32Const(48)); // '0'
@@ -1801,6 +2246,8 @@ impl Compiler {
         body.push(Instruction::End);
         
         // Process digit
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::LocalSet(9)); // has_digit = true
         body.push(Instruction::LocalGet(7)); // has_decimal_point
         body.push(Instruction::If(BlockType::Empty));
         
@@ -1846,6 +2293,39 @@ impl Compiler {
         body.push(Instruction::Br(0)); // Continue loop
         body.push(Instruction::End); // End loop
         body.push(Instruction::End); // End block
+
+        // Anything after trailing whitespace but more whitespace is not numeric
+        body.push(Instruction::Block(BlockType::Empty));
+        body.push(Instruction::Loop(BlockType::Empty));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::I32Eq);
+        body.push(Instruction::BrIf(1));
+        body.push(Instruction::LocalGet(6));
+        body.push(Instruction::RefAsNonNull);
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::ArrayGetU(self.gc_types.php_string));
+        Self::push_is_whitespace(&mut body, 5);
+        body.push(Instruction::I32Eqz);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalSet(2));
+        body.push(Instruction::Br(0));
+        body.push(Instruction::End);
+        body.push(Instruction::End);
+
+        // A sign, a point or whitespace alone is not a number
+        body.push(Instruction::LocalGet(9));
+        body.push(Instruction::I32Eqz);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
         
         // Apply sign if negative
         body.push(Instruction::LocalGet(4)); // is_negative
@@ -1863,16 +2343,31 @@ impl Compiler {
         let string_to_float_type = self.builder.add_type(vec![self.get_php_value_type()], vec![self.get_php_value_type()]);
         self.builder.set_function_at_index(self.string_to_float_if_numeric_fn_idx, string_to_float_type, locals, body);
     }
+
+    /// Replace the byte on the stack, saved to `char_local`, with whether it is
+    /// whitespace a numeric string may start or end with: space, \t, \n, \v, \f or \r
+    fn push_is_whitespace(body: &mut Vec<Instruction<'static>>, char_local: u32) {
+        body.push(Instruction::LocalTee(char_local));
+        body.push(Instruction::I32Const(32)); // ' '
+        body.push(Instruction::I32Eq);
+        body.push(Instruction::LocalGet(char_local));
+        body.push(Instruction::I32Const(9)); // '\t'
+        body.push(Instruction::I32Sub);
+        body.push(Instruction::I32Const(5));
+        body.push(Instruction::I32LtU); // '\t'..='\r'
+        body.push(Instruction::I32Or);
+    }
     
     /// Merges arrays: array_merge(array1, array2) -> array
     fn add_array_merge_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_php_value_type())); // local 2: result array
-        locals.push((1, ValType::I32)); // local 3: index for copying
-        locals.push((1, ValType::I32)); // local 4: array1 count
-        locals.push((1, ValType::I32)); // local 5: array2 count
-        locals.push((1, self.get_php_value_type())); // local 6: current value
+        let locals = vec![
+            (1, self.get_php_value_type()), // local 2: result array
+            (1, ValType::I32), // local 3: index for copying
+            (1, ValType::I32), // local 4: array1 count
+            (1, ValType::I32), // local 5: array2 count
+            (1, self.get_php_value_type()), // local 6: current value
+        ];
         
         // Create new array for result
         body.push(Instruction::Call(self.create_array_fn_idx));
@@ -2009,14 +2504,15 @@ impl Compiler {
     /// Extracts slice of array: array_slice(array, offset, length) -> array
     fn add_array_slice_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_php_value_type())); // local 3: result array
-        locals.push((1, ValType::I32)); // local 4: array count
-        locals.push((1, ValType::I32)); // local 5: start offset (normalized)
-        locals.push((1, ValType::I32)); // local 6: slice length
-        locals.push((1, ValType::I32)); // local 7: current index
-        locals.push((1, ValType::I32)); // local 8: items copied
-        locals.push((1, self.get_php_value_type())); // local 9: current value
+        let locals = vec![
+            (1, self.get_php_value_type()), // local 3: result array
+            (1, ValType::I32), // local 4: array count
+            (1, ValType::I32), // local 5: start offset (normalized)
+            (1, ValType::I32), // local 6: slice length
+            (1, ValType::I32), // local 7: current index
+            (1, ValType::I32), // local 8: items copied
+            (1, self.get_php_value_type()), // local 9: current value
+        ];
         
         // Create new array for result
         body.push(Instruction::Call(self.create_array_fn_idx));
@@ -2180,6 +2676,233 @@ impl Compiler {
         self.builder.set_function_at_index(self.array_slice_fn_idx, slice_type, locals, body);
     }
 
+    /// Copies an array value: array_copy(value) -> value
+    /// Nested arrays are copied as well (PHP arrays are values); objects stay shared.
+    /// Anything that is not an array is returned unchanged.
+    fn add_array_copy_function(&mut self) {
+        let mut body = vec![];
+        let locals = vec![
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Abstract {
+                    shared: false,
+                    ty: AbstractHeapType::Any,
+                },
+            })), // local 1: storage (simple array or hash table)
+            (1, self.get_array_type()), // local 2: copied simple array
+            (1, ValType::I32), // local 3: length
+            (1, ValType::I32), // local 4: loop index
+            (1, self.get_hash_table_type()), // local 5: source hash table
+            (1, self.get_hash_array_type()), // local 6: copied buckets
+            (1, self.get_array_entry_type()), // local 7: current entry
+            (1, self.get_php_value_type()), // local 8: current element
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(self.gc_types.php_int_array),
+            })), // local 9: copied int storage
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(self.gc_types.php_float_array),
+            })), // local 10: copied float storage
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(self.gc_types.php_string_array),
+            })), // local 11: copied string storage
+        ];
+
+        // Not an array - nothing to copy
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::RefIsNull);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_TYPE,
+        });
+        body.push(Instruction::I32Const(TYPE_ARRAY as i32));
+        body.push(Instruction::I32Ne);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::LocalSet(1));
+
+        // Typed storage holds no nested arrays
+        for (position, kind) in PackedKind::ALL.into_iter().enumerate() {
+            self.emit_packed_copy(&mut body, kind, 0, 1, 9 + position as u32);
+        }
+
+        // Result PhpValue: same type tag and logical length, new storage
+        body.push(Instruction::I32Const(TYPE_ARRAY as i32));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        body.push(Instruction::F64Const(0.0.into()));
+        body.push(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string)));
+
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::RefTestNullable(HeapType::Concrete(self.gc_types.php_hash_table)));
+        body.push(Instruction::If(BlockType::Result(ValType::Ref(RefType {
+            nullable: true,
+            heap_type: HeapType::Abstract {
+                shared: false,
+                ty: AbstractHeapType::Any,
+            },
+        }))));
+
+        // Hash table: copy every bucket entry
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::RefCastNonNull(HeapType::Concrete(self.gc_types.php_hash_table)));
+        body.push(Instruction::LocalSet(5));
+        body.push(Instruction::LocalGet(5));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_BUCKETS,
+        });
+        body.push(Instruction::ArrayLen);
+        body.push(Instruction::LocalTee(3));
+        body.push(Instruction::ArrayNewDefault(self.gc_types.php_hash_array));
+        body.push(Instruction::LocalSet(6));
+
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalSet(4));
+        body.push(Instruction::Block(BlockType::Empty));
+        body.push(Instruction::Loop(BlockType::Empty));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::I32GeU);
+        body.push(Instruction::BrIf(1));
+
+        body.push(Instruction::LocalGet(5));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_BUCKETS,
+        });
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::ArrayGet(self.gc_types.php_hash_array));
+        body.push(Instruction::LocalTee(7));
+        body.push(Instruction::RefIsNull);
+        body.push(Instruction::I32Eqz);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(6));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::LocalGet(7));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_array_entry,
+            field_index: ARRAYENTRY_KEY,
+        });
+        body.push(Instruction::LocalGet(7));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_array_entry,
+            field_index: ARRAYENTRY_VALUE,
+        });
+        body.push(Instruction::Call(self.array_copy_fn_idx));
+        body.push(Instruction::LocalGet(7));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_array_entry,
+            field_index: ARRAYENTRY_HASH,
+        });
+        body.push(Instruction::LocalGet(7));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_array_entry,
+            field_index: ARRAYENTRY_NEXT,
+        });
+        body.push(Instruction::StructNew(self.gc_types.php_array_entry));
+        body.push(Instruction::ArraySet(self.gc_types.php_hash_array));
+        body.push(Instruction::End);
+
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalSet(4));
+        body.push(Instruction::Br(0));
+        body.push(Instruction::End); // End loop
+        body.push(Instruction::End); // End block
+
+        body.push(Instruction::LocalGet(6));
+        body.push(Instruction::LocalGet(5));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_SIZE,
+        });
+        body.push(Instruction::LocalGet(5));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_COUNT,
+        });
+        body.push(Instruction::LocalGet(5));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_hash_table,
+            field_index: HASHTABLE_NEXT_KEY,
+        });
+        body.push(Instruction::StructNew(self.gc_types.php_hash_table));
+
+        body.push(Instruction::Else);
+
+        // Simple array: copy the storage, then copy nested arrays
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::RefCastNonNull(HeapType::Concrete(self.gc_types.php_array)));
+        body.push(Instruction::ArrayLen);
+        body.push(Instruction::LocalTee(3));
+        body.push(Instruction::ArrayNewDefault(self.gc_types.php_array));
+        body.push(Instruction::LocalSet(2));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::RefCastNonNull(HeapType::Concrete(self.gc_types.php_array)));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::ArrayCopy {
+            array_type_index_dst: self.gc_types.php_array,
+            array_type_index_src: self.gc_types.php_array,
+        });
+
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalSet(4));
+        body.push(Instruction::Block(BlockType::Empty));
+        body.push(Instruction::Loop(BlockType::Empty));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::I32GeU);
+        body.push(Instruction::BrIf(1));
+
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::ArrayGet(self.gc_types.php_array));
+        body.push(Instruction::LocalSet(8));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::LocalGet(8));
+        body.push(Instruction::Call(self.array_copy_fn_idx));
+        body.push(Instruction::ArraySet(self.gc_types.php_array));
+
+        body.push(Instruction::LocalGet(4));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalSet(4));
+        body.push(Instruction::Br(0));
+        body.push(Instruction::End); // End loop
+        body.push(Instruction::End); // End block
+
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::End);
+
+        body.push(Instruction::StructNew(self.gc_types.php_value));
+
+        self.builder.set_function_at_index(self.array_copy_fn_idx, self.value_to_value_type_idx, locals, body);
+    }
+
     // ========================================================================
     // PHASE 3C: Optimized Array Access Functions
     // ========================================================================
@@ -2189,12 +2912,13 @@ impl Compiler {
     /// Parameters: array (param 0), hash (param 1), key_string (param 2)
     fn add_fast_hash_array_get_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_hash_table_type())); // hash table (local 3)
-        locals.push((1, ValType::I32)); // bucket index (local 4)
-        locals.push((1, self.get_hash_array_type())); // buckets array (local 5)
-        locals.push((1, self.get_array_entry_type())); // current entry (local 6)
-        locals.push((1, self.get_php_value_type())); // stored key (local 7)
+        let locals = vec![
+            (1, self.get_hash_table_type()), // hash table (local 3)
+            (1, ValType::I32), // bucket index (local 4)
+            (1, self.get_hash_array_type()), // buckets array (local 5)
+            (1, self.get_array_entry_type()), // current entry (local 6)
+            (1, self.get_php_value_type()), // stored key (local 7)
+        ];
 
         // Get hash table from PhpValue
         body.push(Instruction::LocalGet(0)); // array parameter
@@ -2311,11 +3035,12 @@ impl Compiler {
     /// Parameters: array (param 0), hash (param 1), key_string (param 2), value (param 3)
     fn add_fast_hash_array_set_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_hash_table_type())); // local 4: hash table
-        locals.push((1, ValType::I32)); // local 5: bucket index
-        locals.push((1, self.get_array_entry_type())); // local 6: new entry
-        locals.push((1, self.get_array_entry_type())); // local 7: existing entry
+        let locals = vec![
+            (1, self.get_hash_table_type()), // local 4: hash table
+            (1, ValType::I32), // local 5: bucket index
+            (1, self.get_array_entry_type()), // local 6: new entry
+            (1, self.get_array_entry_type()), // local 7: existing entry
+        ];
 
         // Get hash table from PhpValue
         body.push(Instruction::LocalGet(0)); // array parameter
@@ -2475,14 +3200,15 @@ impl Compiler {
     /// Parameters: array (param 0), int_key (param 1 as i64)
     fn add_fast_array_get_int_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::Ref(RefType {
-            nullable: true,
-            heap_type: HeapType::Abstract {
-                shared: false,
-                ty: AbstractHeapType::Any,
-            },
-        }))); // anyref for array field (local 2)
+        let locals = vec![
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Abstract {
+                    shared: false,
+                    ty: AbstractHeapType::Any,
+                },
+            })), // anyref for array field (local 2)
+        ];
 
         // Get array field from PhpValue
         body.push(Instruction::LocalGet(0)); // array parameter
@@ -2505,18 +3231,12 @@ impl Compiler {
 
         body.push(Instruction::Else);
 
-        // It's a simple array - direct access with unboxed integer!
+        // It's a packed array - direct access with unboxed integer!
         // OPTIMIZATION: No boxing needed, use i64 directly
-        body.push(Instruction::LocalGet(2));
-        body.push(Instruction::RefCastNullable(HeapType::Concrete(self.gc_types.php_array)));
-        body.push(Instruction::RefAsNonNull); // Convert to non-null ref
-
-        // Use integer key directly (already i64, just convert to i32)
+        body.push(Instruction::LocalGet(0));
         body.push(Instruction::LocalGet(1)); // int key as i64
         body.push(Instruction::I32WrapI64); // Convert to i32 for array index
-
-        // Get element at index
-        body.push(Instruction::ArrayGet(self.gc_types.php_array));
+        body.push(Instruction::Call(self.array_element_fn_idx));
 
         body.push(Instruction::End);
 
@@ -2531,14 +3251,15 @@ impl Compiler {
     /// Parameters: array (param 0), int_key (param 1 as i64), value (param 2)
     fn add_fast_array_set_int_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::Ref(RefType {
-            nullable: true,
-            heap_type: HeapType::Abstract {
-                shared: false,
-                ty: AbstractHeapType::Any,
-            },
-        }))); // anyref for array field (local 3)
+        let locals = vec![
+            (1, ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Abstract {
+                    shared: false,
+                    ty: AbstractHeapType::Any,
+                },
+            })), // anyref for array field (local 3)
+        ];
 
         // Get array field from PhpValue
         body.push(Instruction::LocalGet(0)); // array parameter
@@ -2565,8 +3286,8 @@ impl Compiler {
 
         // It's a simple array - direct set with unboxed integer!
         // OPTIMIZATION: No boxing needed, use i64 directly
-        body.push(Instruction::LocalGet(3));
-        body.push(Instruction::RefCastNullable(HeapType::Concrete(self.gc_types.php_array)));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Call(self.array_deoptimize_fn_idx));
 
         // Use integer key directly (convert i64 to i32 for array index)
         body.push(Instruction::LocalGet(1)); // int key as i64
@@ -0,0 +1,59 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Backends turn a resolved and linked program into a module. The WasmGC
+// backend in this directory keeps PHP values on the engine's GC heap; the
+// linear-memory backend (`compiler_manual_gc`) manages its own heap, for
+// engines that do not support WasmGC. Both take the same options, so the
+// choice is one `CompilerOptions::backend` away.
+
+use super::constant_folding;
+use super::core::Compiler;
+use super::debug_info::CompiledModule;
+use super::options::BackendKind;
+use crate::compiler_manual_gc;
+use crate::error::CompilerError;
+use edge_php_parser::ast::{Program, Statement};
+use edge_php_parser::visit::{self, Visitor};
+use std::collections::HashSet;
+
+/// A code generator for programs whose names are resolved and whose includes
+/// are linked. Backends are chosen at run time, so they are taken boxed.
+pub trait Backend {
+    fn compile_program(self: Box<Self>, program: Program) -> Result<CompiledModule, CompilerError>;
+}
+
+impl Backend for Compiler {
+    fn compile_program(self: Box<Self>, program: Program) -> Result<CompiledModule, CompilerError> {
+        self.compile_gc_program(program)
+    }
+}
+
+impl Compiler {
+    /// Fold constants, then hand the program to the backend the options select
+    pub(super) fn compile_with_backend(self, mut program: Program) -> Result<CompiledModule, CompilerError> {
+        self.options.validate()?;
+        if self.options.constant_folding_enabled() {
+            let mut functions = FunctionNames(self.options.imported_functions().iter().map(|function| function.name.clone()).collect());
+            visit::walk_program(&mut functions, &program);
+            constant_folding::fold_program(&mut program, functions.0);
+        }
+        let backend: Box<dyn Backend> = match self.options.backend_kind() {
+            BackendKind::WasmGc => Box::new(self),
+            BackendKind::LinearMemory => Box::new(compiler_manual_gc::Compiler::new(self.options, self.sources)),
+        };
+        backend.compile_program(program)
+    }
+}
+
+/// The functions a call may resolve to other than builtins
+struct FunctionNames(HashSet<String>);
+
+impl Visitor for FunctionNames {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        if let Statement::Function { name, .. } = stmt {
+            self.0.insert(name.clone());
+        }
+        visit::walk_statement(self, stmt);
+    }
+}
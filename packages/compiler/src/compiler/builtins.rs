@@ -31,6 +31,8 @@ impl Compiler {
             "array_keys" => self.compile_array_keys_builtin(args),
             "array_values" => self.compile_array_values_builtin(args),
             "array_merge" => self.compile_array_merge_builtin(args),
+            "array_sum" => self.compile_array_sum_builtin(args),
+            "sort" => self.compile_sort_builtin(args),
 
             // String functions
             "strlen" => self.compile_strlen(args),
@@ -53,9 +55,18 @@ impl Compiler {
             "sqrt" => self.compile_sqrt(args),
             "pow" => self.compile_pow(args),
 
+            // HTTP responses and the request body
+            "header" | "http_response_code" | "headers_sent" | "setcookie" => self.compile_response_builtin(name, args),
+            "file_get_contents" => self.compile_file_get_contents(args),
+
+            // Output buffering
+            "ob_start" | "ob_get_contents" | "ob_get_length" | "ob_get_level" | "ob_get_clean" | "ob_get_flush"
+            | "ob_end_flush" | "ob_end_clean" | "ob_flush" | "ob_clean" | "flush" => self.compile_output_builtin(name, args),
+
             // Utility
             "isset" => self.compile_isset(args),
             "empty" => self.compile_empty(args),
+            "unset" => self.compile_unset(args),
 
             _ => Ok(false),  // Not recognized
         }
@@ -330,6 +341,33 @@ impl Compiler {
         Ok(true)
     }
 
+    fn compile_array_sum_builtin(&mut self, args: Vec<Expression>) -> Result<bool, String> {
+        if args.len() != 1 {
+            return Err("array_sum() expects exactly 1 parameter".to_string());
+        }
+
+        self.compile_expression(args[0].clone())?;
+        self.emit(Instruction::Call(self.array_sum_fn_idx));
+        Ok(true)
+    }
+
+    fn compile_sort_builtin(&mut self, args: Vec<Expression>) -> Result<bool, String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("sort() expects 1 or 2 parameters".to_string());
+        }
+        if !matches!(args[0], Expression::Variable(_) | Expression::ArrayAccess { .. } | Expression::PropertyAccess { .. } | Expression::StaticPropertyAccess { .. }) {
+            return Err("sort(): Argument #1 ($array) could not be passed by reference".to_string());
+        }
+        if args.len() == 2 {
+            return Err("sort() flags are not supported".to_string());
+        }
+
+        // The array is sorted in place, so the variable sees the new order
+        self.compile_expression(args[0].clone())?;
+        self.emit(Instruction::Call(self.array_sort_fn_idx));
+        Ok(true)
+    }
+
     // ===== UTILITY FUNCTIONS =====
 
     fn compile_isset(&mut self, args: Vec<Expression>) -> Result<bool, String> {
@@ -337,25 +375,77 @@ impl Compiler {
             return Err("isset() expects at least 1 parameter".to_string());
         }
 
-        // For now, simple implementation - check if not null
-        self.compile_expression(args[0].clone())?;
-        self.emit(Instruction::StructGet {
-            struct_type_index: self.gc_types.php_value,
-            field_index: PHPVALUE_TYPE,
-        });
-        self.emit(Instruction::I32Const(TYPE_NULL as i32));
-        self.emit(Instruction::I32Ne);
+        // isset($a, $b['x'], $c->d) is true only if every argument is set (not null).
+        // Arguments are fetched quietly: missing keys/properties just count as unset.
+        // Properties a class does not declare are checked through its __isset.
+        for (i, arg) in args.into_iter().enumerate() {
+            if let Expression::PropertyAccess { object, property } = arg {
+                self.compile_property_isset(*object, &property)?;
+            } else {
+                self.compile_quiet_fetch(arg)?;
+                self.emit(Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index: PHPVALUE_TYPE,
+                });
+                self.emit(Instruction::I32Const(TYPE_NULL as i32));
+                self.emit(Instruction::I32Ne);
+            }
+            if i > 0 {
+                self.emit(Instruction::I32And);
+            }
+        }
         self.emit(Instruction::Call(self.create_bool_fn_idx));
         Ok(true)
     }
 
+    fn compile_unset(&mut self, args: Vec<Expression>) -> Result<bool, String> {
+        // unset($a, $obj->prop, $a[$k]): variables and declared properties become
+        // null, properties a class does not declare go to its __unset and array
+        // elements are removed
+        for arg in args {
+            match arg {
+                Expression::Variable(_) => {
+                    self.compile_expression_void(Expression::Assignment {
+                        left: Box::new(arg),
+                        right: Box::new(Expression::Literal(Literal::Null)),
+                    })?;
+                }
+                Expression::PropertyAccess { object, property } => {
+                    self.compile_property_unset(*object, &property)?;
+                }
+                Expression::StaticPropertyAccess { class, property } => {
+                    return Err(format!("Attempt to unset static property {}::${}", class, property));
+                }
+                Expression::ArrayAccess { .. } if self.globals_element(&arg).is_some() => {
+                    self.compile_expression_void(Expression::Assignment {
+                        left: Box::new(arg),
+                        right: Box::new(Expression::Literal(Literal::Null)),
+                    })?;
+                }
+                Expression::ArrayAccess { array, index } => {
+                    self.check_globals_write(&array)?;
+                    self.compile_expression(*array.clone())?;
+                    self.compile_expression(*index)?;
+                    self.emit(Instruction::Call(self.array_unset_fn_idx));
+                    match self.array_base_variable(&array)? {
+                        Some(var_info) => self.emit_variable_set(&var_info),
+                        None => self.emit(Instruction::Drop),
+                    }
+                }
+                _ => return Err("Cannot unset this expression".to_string()),
+            }
+        }
+        self.emit(Instruction::Call(self.create_null_fn_idx));
+        Ok(true)
+    }
+
     fn compile_empty(&mut self, args: Vec<Expression>) -> Result<bool, String> {
         if args.len() != 1 {
             return Err("empty() expects exactly 1 parameter".to_string());
         }
 
-        // Convert to bool and negate
-        self.compile_expression(args[0].clone())?;
+        // Convert to bool and negate (missing keys/properties are empty, no warning)
+        self.compile_quiet_fetch(args[0].clone())?;
         self.emit(Instruction::Call(self.to_bool_fn_idx));
 
         // Get bool value
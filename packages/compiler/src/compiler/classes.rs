@@ -4,6 +4,7 @@
 // PHASE 5: Class and Object-Oriented Programming Support
 
 use super::core::*;
+use super::array_copies::Store;
 use edge_php_parser::ast::*;
 use wasm_encoder::*;
 use std::collections::HashMap;
@@ -14,8 +15,21 @@ pub struct ClassInfo {
     pub name: String,
     pub class_id: u32,  // Unique identifier for this class
     pub properties: Vec<PropertyInfo>,
+    pub static_properties: HashMap<String, super::properties::StaticPropertyInfo>,
     pub methods: HashMap<String, MethodInfo>,
     pub constructor_idx: Option<u32>,  // Function index of constructor
+    pub constructor_params: Vec<Parameter>,
+    pub constants: HashMap<String, Expression>,  // Compiled inline wherever they are used
+    pub interfaces: Vec<String>,
+    pub enum_info: Option<super::enums::EnumInfo>,  // Set for enums
+}
+
+/// Information about a declared interface
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    pub name: String,
+    pub methods: Vec<String>,
+    pub constants: HashMap<String, Expression>,
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +38,22 @@ pub struct PropertyInfo {
     pub index: u32,  // Index in the object's property array
     pub visibility: Visibility,
     pub default_value: Option<Expression>,
+    pub property_type: Option<Type>,  // Enforced on every write
+    pub is_readonly: bool,  // Starts uninitialised and can be written once, from inside the class
+}
+
+/// How a class resolves a property access at runtime
+#[derive(Debug, Clone)]
+enum PropertySlot {
+    Declared(u32),  // Index in the object's property array
+    Hook(u32),      // Magic method (__get, __set, __isset, __unset) function index
+}
+
+/// How a class resolves a method call at runtime
+#[derive(Debug, Clone)]
+enum MethodTarget {
+    Method(MethodInfo),
+    CallHook(u32),  // __call function index
 }
 
 #[derive(Debug, Clone)]
@@ -37,43 +67,107 @@ pub struct MethodInfo {
 impl Compiler {
     /// PHASE 5A: Compile class definition (wrapper that calls both phases)
     /// This is for compatibility when classes are compiled outside the main compile loop
-    pub(super) fn compile_class_definition(&mut self, name: &str, members: &[ClassMember]) -> Result<(), String> {
-        self.register_class_metadata(name, members)?;
+    pub(super) fn compile_class_definition(&mut self, name: &str, is_readonly: bool, implements: &[String], members: &[ClassMember]) -> Result<(), String> {
+        self.register_class_metadata(name, is_readonly, implements, members)?;
         self.compile_class_methods(name, members)?;
-        Ok(())
+        self.emit_static_property_init(name)
     }
 
-    /// PHASE 5A: Register class metadata (properties only, no method compilation)
-    pub(super) fn register_class_metadata(&mut self, name: &str, members: &[ClassMember]) -> Result<(), String> {
+    /// PHASE 5A: Register class metadata (properties and method signatures, no method compilation)
+    /// Method function indices are reserved here so that any method body can dispatch
+    /// to methods of classes that are compiled later.
+    pub(super) fn register_class_metadata(&mut self, name: &str, is_readonly_class: bool, implements: &[String], members: &[ClassMember]) -> Result<(), String> {
+        self.check_interface_methods("Class", name, implements, members)?;
+
         let class_id = self.next_class_id;
         self.next_class_id += 1;
 
         let mut properties = Vec::new();
+        let mut static_properties = HashMap::new();
+        let mut methods = HashMap::new();
+        let mut constructor_idx = None;
+        let mut constructor_params = Vec::new();
+        let mut constants = HashMap::new();
 
         // Collect all properties
         let mut property_index = 0u32;
         for member in members {
             match member {
-                ClassMember::Property { name: prop_name, visibility, property_type: _, default } => {
+                ClassMember::Property { name: prop_name, visibility, is_static, is_readonly, property_type, default } => {
+                    let is_readonly = *is_readonly || is_readonly_class;
+                    Self::check_property_modifiers(name, prop_name, *is_static, is_readonly, property_type.as_ref(), default.as_ref())?;
+                    if let Some(property_type) = property_type {
+                        self.check_property_declaration(name, prop_name, property_type, default.as_ref())?;
+                    }
+                    if *is_static {
+                        static_properties.insert(prop_name.clone(), self.add_static_property(visibility, property_type, default));
+                        continue;
+                    }
                     properties.push(PropertyInfo {
                         name: prop_name.clone(),
                         index: property_index,
                         visibility: visibility.clone(),
                         default_value: default.clone(),
+                        property_type: property_type.clone(),
+                        is_readonly,
                     });
                     property_index += 1;
                 }
-                _ => {}
+                ClassMember::Method { name: method_name, visibility, is_static, params, .. } => {
+                    Self::check_magic_method_signature(name, method_name, *is_static, params)?;
+                    let function_idx = self.builder.reserve_function_index();
+                    self.name_function(function_idx, &format!("{}::{}", name, method_name));
+                    if method_name == "__construct" {
+                        // Promoted parameters declare properties in parameter order
+                        for param in params {
+                            if let Some(property) = self.promoted_property(name, is_readonly_class, param, property_index)? {
+                                properties.push(property);
+                                property_index += 1;
+                            }
+                        }
+                        constructor_idx = Some(function_idx);
+                        constructor_params = params.clone();
+                    } else {
+                        Self::check_no_promotion(params)?;
+                        methods.insert(method_name.clone(), MethodInfo {
+                            name: method_name.clone(),
+                            function_idx,
+                            visibility: visibility.clone(),
+                            params: params.clone(),
+                        });
+                    }
+                }
+                ClassMember::Constructor { params, .. } => {
+                    let function_idx = self.builder.reserve_function_index();
+                    self.name_function(function_idx, &format!("{}::__construct", name));
+                    constructor_idx = Some(function_idx);
+                    constructor_params = params.clone();
+                }
+                ClassMember::AbstractMethod { name: method_name, .. } => {
+                    return Err(format!("Non-abstract method {}::{}() must contain body", name, method_name));
+                }
+                ClassMember::Constant { name: const_name, value, .. } => {
+                    Self::check_constant_expression(value)?;
+                    constants.insert(const_name.clone(), value.clone());
+                }
+                // Enum cases are registered by register_enum_metadata
+                ClassMember::Case { .. } => {}
             }
         }
+        Self::check_property_names(name, &properties, &static_properties)?;
 
-        // Store class metadata (without methods/constructor yet)
+        // Store class metadata (method bodies are compiled in compile_class_methods)
         let class_info = ClassInfo {
             name: name.to_string(),
             class_id,
             properties,
-            methods: HashMap::new(),  // Empty for now
-            constructor_idx: None,    // None for now
+            static_properties,
+            methods,
+            constructor_idx,
+            constructor_params,
+            constants,
+            interfaces: implements.to_vec(),
+            enum_info: None,
         };
 
         self.classes.insert(name.to_string(), class_info);
@@ -81,52 +175,87 @@ impl Compiler {
         Ok(())
     }
 
+    /// Register an interface: its constants and the methods implementors must define
+    pub(super) fn register_interface(&mut self, name: &str, members: &[ClassMember]) {
+        let mut methods = Vec::new();
+        let mut constants = HashMap::new();
+        for member in members {
+            match member {
+                ClassMember::AbstractMethod { name: method_name, .. } => methods.push(method_name.clone()),
+                ClassMember::Constant { name: const_name, value, .. } => {
+                    constants.insert(const_name.clone(), value.clone());
+                }
+                _ => {}
+            }
+        }
+        self.interfaces.insert(name.to_string(), InterfaceInfo {
+            name: name.to_string(),
+            methods,
+            constants,
+        });
+    }
+
+    /// Every method of the implemented interfaces must be defined by the class or enum
+    pub(super) fn check_interface_methods(&self, kind: &str, name: &str, implements: &[String], members: &[ClassMember]) -> Result<(), String> {
+        let mut missing = Vec::new();
+        for interface in implements {
+            let info = self.interfaces.get(interface)
+                .ok_or_else(|| format!("Interface \"{}\" not found", interface))?;
+            for method in &info.methods {
+                let defined = members.iter().any(|member| {
+                    matches!(member, ClassMember::Method { name, .. } if name.eq_ignore_ascii_case(method))
+                });
+                if !defined {
+                    missing.push(format!("{}::{}", info.name, method));
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} {} contains {} abstract method{} and must therefore be declared abstract or implement the remaining methods ({})",
+                kind, name, missing.len(), if missing.len() == 1 { "" } else { "s" }, missing.join(", ")
+            ))
+        }
+    }
+
     /// PHASE 5A: Compile class methods and constructor (after all classes are registered)
     pub(super) fn compile_class_methods(&mut self, name: &str, members: &[ClassMember]) -> Result<(), String> {
-        let mut methods = HashMap::new();
-        let mut constructor_idx = None;
+        let class_info = self.classes.get(name)
+            .ok_or_else(|| format!("Unknown class: {}", name))?
+            .clone();
 
-        // Compile methods and constructor
+        // Compile methods and constructor into the indices reserved at registration
         for member in members {
             match member {
-                ClassMember::Method { name: method_name, visibility, params, body, return_type: _ } => {
+                ClassMember::Method { name: method_name, params, body, return_type, .. } => {
                     // Check if this is a constructor (__construct is a special method)
-                    if method_name == "__construct" {
-                        // This is the constructor - compile it and store the index
-                        let ctor_idx = self.compile_method(name, method_name, params, body)?;
-                        constructor_idx = Some(ctor_idx);
+                    let func_idx = if method_name == "__construct" {
+                        class_info.constructor_idx
                     } else {
-                        // Regular method
-                        let method_idx = self.compile_method(name, method_name, params, body)?;
-
-                        methods.insert(method_name.clone(), MethodInfo {
-                            name: method_name.clone(),
-                            function_idx: method_idx,
-                            visibility: visibility.clone(),
-                            params: params.clone(),
-                        });
+                        class_info.methods.get(method_name).map(|m| m.function_idx)
                     }
+                    .ok_or_else(|| format!("Method {}::{} was not registered", name, method_name))?;
+
+                    self.compile_method(name, method_name, func_idx, params, return_type.as_ref(), body)?;
                 }
                 ClassMember::Constructor { visibility: _, params, body } => {
                     // Some parsers might use a dedicated Constructor variant
-                    let ctor_idx = self.compile_constructor(name, params, body)?;
-                    constructor_idx = Some(ctor_idx);
+                    let ctor_idx = class_info.constructor_idx
+                        .ok_or_else(|| format!("Constructor of {} was not registered", name))?;
+                    self.compile_constructor(name, ctor_idx, params, body)?;
                 }
                 _ => {}
             }
         }
 
-        // Update class info with methods and constructor
-        if let Some(class_info) = self.classes.get_mut(name) {
-            class_info.methods = methods;
-            class_info.constructor_idx = constructor_idx;
-        }
-
         Ok(())
     }
 
     /// Compile a method (function with $this as implicit first parameter)
-    fn compile_method(&mut self, class_name: &str, method_name: &str, params: &[Parameter], body: &Block) -> Result<u32, String> {
+    fn compile_method(&mut self, class_name: &str, method_name: &str, func_idx: u32, params: &[Parameter], return_type: Option<&Type>, body: &Block) -> Result<(), String> {
         // Methods are like functions but with $this as the first parameter
         // Type: ($this: PhpValue, param1: PhpValue, ...) -> PhpValue
         let param_count = params.len() + 1; // +1 for $this
@@ -134,21 +263,22 @@ impl Compiler {
         let result_types = vec![self.get_php_value_type()];
         let func_type = self.builder.add_type(param_types, result_types);
 
-        // Reserve function index
-        let func_idx = self.builder.reserve_function_index();
-
         // Save current context
         let saved_function = self.current_function.take();
         let saved_variables = self.variables.clone();
         let saved_block_depth = self.block_depth;
+        let saved_static_scope = std::mem::replace(&mut self.static_scope, format!("{}::{}", class_name, method_name));
+        let saved_class = self.current_class.replace(class_name.to_string());
 
         // Create new function context
         self.current_function = Some(FunctionContext {
             locals: vec![],
             body: vec![],
             local_count: param_count as u32,
+            locations: Vec::new(),
         });
         self.variables.clear();
+        self.bind_superglobals();
         self.block_depth = 0;
         self.free_locals.clear();
 
@@ -167,6 +297,11 @@ impl Compiler {
                 class_type: None,
             });
         }
+        let function = format!("{}::{}", class_name, method_name);
+        let saved_types = self.enter_typed_function(&function, params, return_type, 1, body)?;
+        if method_name == "__construct" {
+            self.emit_promoted_assignments(params)?;
+        }
 
         // Compile method body
         for stmt in &body.statements {
@@ -174,34 +309,41 @@ impl Compiler {
         }
 
         // Default return null
-        self.emit(Instruction::Call(self.create_null_fn_idx));
-        self.emit(Instruction::Return);
+        self.emit_implicit_return()?;
+        self.leave_typed_function(saved_types);
 
         // Get compiled function
-        let func_ctx = self.current_function.take().unwrap();
-        self.builder.set_function_at_index(func_idx, func_type, func_ctx.locals, func_ctx.body);
+        self.name_locals(func_idx);
+        self.finish_function(func_idx, func_type);
 
         // Restore context
         self.current_function = saved_function;
         self.variables = saved_variables;
         self.block_depth = saved_block_depth;
+        self.static_scope = saved_static_scope;
+        self.current_class = saved_class;
 
-        Ok(func_idx)
+        Ok(())
     }
 
     /// Compile a constructor (special method that initializes object)
-    fn compile_constructor(&mut self, class_name: &str, params: &[Parameter], body: &Block) -> Result<u32, String> {
+    fn compile_constructor(&mut self, class_name: &str, func_idx: u32, params: &[Parameter], body: &Block) -> Result<(), String> {
         // Constructor is like a method - compile the same way
-        self.compile_method(class_name, "__construct", params, body)
+        self.compile_method(class_name, "__construct", func_idx, params, None, body)
     }
 
     /// PHASE 5A: Compile new expression (object instantiation)
     pub(super) fn compile_new_expression(&mut self, class_name: &str, args: Vec<Expression>) -> Result<(), String> {
-        // Look up class info
+        // Look up class info (`new self` / `new static` inside a class)
+        let class_name = &self.resolve_class_name(class_name)?;
         let class_info = self.classes.get(class_name)
             .ok_or_else(|| format!("Unknown class: {}", class_name))?
             .clone();
 
+        if class_info.enum_info.is_some() {
+            return Err(format!("Cannot instantiate enum {}", class_name));
+        }
+
         let property_count = class_info.properties.len() as i32;
 
         // Create properties array
@@ -217,9 +359,15 @@ impl Compiler {
         }));
         self.emit(Instruction::LocalSet(props_local));
 
-        // Initialize properties with default values
+        // Initialize properties with default values; readonly ones start uninitialised
         for prop in &class_info.properties {
-            if let Some(default_expr) = &prop.default_value {
+            if prop.is_readonly {
+                let sentinel = self.readonly_sentinel();
+                self.emit(Instruction::LocalGet(props_local));
+                self.emit(Instruction::I32Const(prop.index as i32));
+                self.emit(Instruction::GlobalGet(sentinel));
+                self.emit(Instruction::ArraySet(self.gc_types.php_array));
+            } else if let Some(default_expr) = &prop.default_value {
                 // Set property to default value
                 self.emit(Instruction::LocalGet(props_local));
                 self.emit(Instruction::I32Const(prop.index as i32));
@@ -257,31 +405,50 @@ impl Compiler {
             // Push $this (the wrapped object)
             self.emit(Instruction::LocalGet(wrapped_obj_local));
 
-            // Push constructor arguments
-            for arg in args {
-                self.compile_expression(arg)?;
-            }
+            // Push constructor arguments, filling in parameter defaults
+            self.compile_call_arguments(args, &class_info.constructor_params)?;
 
             // Call constructor
             self.emit(Instruction::Call(constructor_idx));
             self.emit(Instruction::Drop);  // Constructor return value ignored
         }
 
+        self.register_for_destruction(class_name, wrapped_obj_local);
+
         // Return the wrapped object
         self.emit(Instruction::LocalGet(wrapped_obj_local));
 
         Ok(())
     }
 
+    /// Class of an object expression when it is known at compile time
+    pub(super) fn static_class_of(&self, object_expr: &Expression) -> Option<String> {
+        match object_expr {
+            Expression::Variable(var_name) => self.variables.get(var_name).and_then(|v| v.class_type.clone()),
+            Expression::New { class, .. } => self.resolve_class_name(class).ok(),
+            Expression::ClassConstant { class, name } => self.enum_case_class(class, name),
+            Expression::Clone(object) => self.static_class_of(object),
+            // Calls whose every return value is an instance of one class
+            Expression::FunctionCall { name, .. } if self.functions.contains_key(name) => {
+                self.class_of_type(&self.type_inference.return_type(name))
+            }
+            Expression::MethodCall { object, method, .. } => {
+                let class = self.static_class_of(object)?;
+                self.class_of_type(&self.type_inference.return_type(&format!("{}::{}", class, method)))
+            }
+            Expression::StaticMethodCall { class, method, .. } => {
+                let class = self.resolve_class_name(class).ok()?;
+                self.class_of_type(&self.type_inference.return_type(&format!("{}::{}", class, method)))
+            }
+            _ => None,
+        }
+    }
+
     /// PHASE 5A: Compile property access ($obj->prop)
     /// OPTIMIZATION: Uses compile-time class type information when available
     pub(super) fn compile_property_access(&mut self, object_expr: Expression, property_name: &str) -> Result<(), String> {
         // Try to determine object's class at compile time
-        let class_name = if let Expression::Variable(var_name) = &object_expr {
-            self.variables.get(var_name).and_then(|v| v.class_type.clone())
-        } else {
-            None
-        };
+        let class_name = self.static_class_of(&object_expr);
 
         if let Some(class_name) = class_name {
             // OPTIMIZATION: Compile-time class known - direct property index lookup
@@ -289,11 +456,18 @@ impl Compiler {
                 .ok_or_else(|| format!("Unknown class: {}", class_name))?
                 .clone();
 
-            let property = class_info.properties.iter()
-                .find(|p| p.name == property_name)
-                .ok_or_else(|| format!("Unknown property: {}::{}", class_name, property_name))?;
+            let Some(property) = class_info.properties.iter().find(|p| p.name == property_name) else {
+                // Undeclared property: __get($name)
+                let getter = class_info.methods.get("__get")
+                    .ok_or_else(|| format!("Unknown property: {}::{}", class_name, property_name))?;
+                self.compile_expression(object_expr)?;
+                self.compile_string_literal(property_name)?;
+                self.emit(Instruction::Call(getter.function_idx));
+                return Ok(());
+            };
 
             let property_index = property.index;
+            let is_readonly = property.is_readonly;
 
             // Compile object expression
             self.compile_expression(object_expr)?;
@@ -316,22 +490,24 @@ impl Compiler {
             // Get the specific property from the array
             self.emit(Instruction::I32Const(property_index as i32));
             self.emit(Instruction::ArrayGet(self.gc_types.php_array));
+            if is_readonly {
+                self.emit_readonly_read_check(&class_name, property_name)?;
+            }
 
             Ok(())
         } else {
-            // Runtime class lookup required - not yet implemented
-            Err(format!("Runtime property access not yet implemented: {}", property_name))
+            // Runtime class lookup - dispatch on the object's class_id
+            self.compile_expression(object_expr)?;
+            let object_local = self.allocate_local(self.get_php_value_type());
+            self.emit(Instruction::LocalSet(object_local));
+            self.emit_dynamic_property_get(object_local, property_name, false)
         }
     }
 
     /// PHASE 5A: Compile property assignment ($obj->prop = value)
     pub(super) fn compile_property_assignment(&mut self, object_expr: Expression, property_name: &str, value_expr: Expression) -> Result<(), String> {
         // Try to determine object's class at compile time
-        let class_name = if let Expression::Variable(var_name) = &object_expr {
-            self.variables.get(var_name).and_then(|v| v.class_type.clone())
-        } else {
-            None
-        };
+        let class_name = self.static_class_of(&object_expr);
 
         if let Some(class_name) = class_name {
             // OPTIMIZATION: Compile-time class known - direct property index lookup
@@ -339,11 +515,29 @@ impl Compiler {
                 .ok_or_else(|| format!("Unknown class: {}", class_name))?
                 .clone();
 
-            let property = class_info.properties.iter()
-                .find(|p| p.name == property_name)
-                .ok_or_else(|| format!("Unknown property: {}::{}", class_name, property_name))?;
+            if class_info.enum_info.is_some() {
+                return Err(format!("Cannot modify readonly property {}::${}", class_name, property_name));
+            }
+
+            let Some(property) = class_info.properties.iter().find(|p| p.name == property_name) else {
+                // Undeclared property: __set($name, $value), the assignment yields $value
+                let setter = class_info.methods.get("__set")
+                    .ok_or_else(|| format!("Unknown property: {}::{}", class_name, property_name))?;
+                let (object_local, value_local) = self.compile_dynamic_property_operands(object_expr, value_expr)?;
+                self.emit_magic_set(object_local, property_name, value_local, setter.function_idx)?;
+                self.emit(Instruction::LocalGet(value_local));
+                return Ok(());
+            };
+
+            if property.is_readonly {
+                let property = property.clone();
+                let value_local = self.compile_readonly_assignment(&class_name, &property, object_expr, value_expr)?;
+                self.emit(Instruction::LocalGet(value_local));
+                return Ok(());
+            }
 
             let property_index = property.index;
+            let property_type = property.property_type.clone();
 
             // Compile object expression
             self.compile_expression(object_expr)?;
@@ -365,28 +559,31 @@ impl Compiler {
 
             // Set the property in the array
             self.emit(Instruction::I32Const(property_index as i32));
-            self.compile_expression(value_expr)?;
+            self.compile_stored_value(value_expr, Store::Other)?;
+            if let Some(property_type) = &property_type {
+                self.emit_property_type_enforcement(&class_name, property_name, property_type)?;
+            }
 
             // Keep value for result
             let result_local = self.allocate_local(self.get_php_value_type());
             self.emit(Instruction::LocalTee(result_local));
             self.emit(Instruction::ArraySet(self.gc_types.php_array));
+            self.emit(Instruction::LocalGet(result_local));
 
             Ok(())
         } else {
-            // Runtime class lookup required - not yet implemented
-            Err(format!("Runtime property assignment not yet implemented: {}", property_name))
+            // Runtime class lookup - dispatch on the object's class_id
+            let (object_local, value_local) = self.compile_dynamic_property_operands(object_expr, value_expr)?;
+            self.emit_dynamic_property_set(object_local, property_name, value_local)?;
+            self.emit(Instruction::LocalGet(value_local));
+            Ok(())
         }
     }
 
     /// PHASE 5A: Compile property assignment in void context (no return value needed)
     pub(super) fn compile_property_assignment_void(&mut self, object_expr: Expression, property_name: &str, value_expr: Expression) -> Result<(), String> {
         // Try to determine object's class at compile time
-        let class_name = if let Expression::Variable(var_name) = &object_expr {
-            self.variables.get(var_name).and_then(|v| v.class_type.clone())
-        } else {
-            None
-        };
+        let class_name = self.static_class_of(&object_expr);
 
         if let Some(class_name) = class_name {
             // OPTIMIZATION: Compile-time class known - direct property index lookup
@@ -394,11 +591,26 @@ impl Compiler {
                 .ok_or_else(|| format!("Unknown class: {}", class_name))?
                 .clone();
 
-            let property = class_info.properties.iter()
-                .find(|p| p.name == property_name)
-                .ok_or_else(|| format!("Unknown property: {}::{}", class_name, property_name))?;
+            if class_info.enum_info.is_some() {
+                return Err(format!("Cannot modify readonly property {}::${}", class_name, property_name));
+            }
+
+            let Some(property) = class_info.properties.iter().find(|p| p.name == property_name) else {
+                // Undeclared property: __set($name, $value)
+                let setter = class_info.methods.get("__set")
+                    .ok_or_else(|| format!("Unknown property: {}::{}", class_name, property_name))?;
+                let (object_local, value_local) = self.compile_dynamic_property_operands(object_expr, value_expr)?;
+                return self.emit_magic_set(object_local, property_name, value_local, setter.function_idx);
+            };
+
+            if property.is_readonly {
+                let property = property.clone();
+                self.compile_readonly_assignment(&class_name, &property, object_expr, value_expr)?;
+                return Ok(());
+            }
 
             let property_index = property.index;
+            let property_type = property.property_type.clone();
 
             // Compile object expression
             self.compile_expression(object_expr)?;
@@ -420,24 +632,24 @@ impl Compiler {
 
             // Set the property in the array (void context - no result needed)
             self.emit(Instruction::I32Const(property_index as i32));
-            self.compile_expression(value_expr)?;
+            self.compile_stored_value(value_expr, Store::Other)?;
+            if let Some(property_type) = &property_type {
+                self.emit_property_type_enforcement(&class_name, property_name, property_type)?;
+            }
             self.emit(Instruction::ArraySet(self.gc_types.php_array));
 
             Ok(())
         } else {
-            // Runtime class lookup required - not yet implemented
-            Err(format!("Runtime property assignment not yet implemented: {}", property_name))
+            // Runtime class lookup - dispatch on the object's class_id
+            let (object_local, value_local) = self.compile_dynamic_property_operands(object_expr, value_expr)?;
+            self.emit_dynamic_property_set(object_local, property_name, value_local)
         }
     }
 
     /// PHASE 5B: Compile method call ($obj->method())
     pub(super) fn compile_method_call(&mut self, object_expr: Expression, method_name: &str, args: Vec<Expression>) -> Result<(), String> {
         // Try to determine object's class at compile time
-        let class_name = if let Expression::Variable(var_name) = &object_expr {
-            self.variables.get(var_name).and_then(|v| v.class_type.clone())
-        } else {
-            None
-        };
+        let class_name = self.static_class_of(&object_expr);
 
         if let Some(class_name) = class_name {
             // OPTIMIZATION: Compile-time class known - direct method dispatch
@@ -445,25 +657,538 @@ impl Compiler {
                 .ok_or_else(|| format!("Unknown class: {}", class_name))?
                 .clone();
 
-            let method_info = class_info.methods.get(method_name)
-                .ok_or_else(|| format!("Unknown method: {}::{}", class_name, method_name))?
-                .clone();
+            let Some(method_info) = class_info.methods.get(method_name).cloned() else {
+                // Undefined method: __call($name, $arguments)
+                let call_hook = class_info.methods.get("__call")
+                    .ok_or_else(|| format!("Unknown method: {}::{}", class_name, method_name))?;
+                self.compile_expression(object_expr)?;
+                self.compile_string_literal(method_name)?;
+                self.compile_magic_call_arguments(args)?;
+                self.emit(Instruction::Call(call_hook.function_idx));
+                return Ok(());
+            };
+
+            let key = format!("{}::{}", class_name, method_name);
+            if let Some(candidate) = self.inline_candidate(&key, args.len()) {
+                return self.compile_inlined_call(&key, candidate, Some((object_expr, class_name)), args);
+            }
 
             // Compile object expression (this becomes $this)
             self.compile_expression(object_expr)?;
 
             // Compile arguments
-            for arg in args {
-                self.compile_expression(arg)?;
-            }
+            self.compile_call_arguments(args, &method_info.params)?;
 
             // Call method directly
             self.emit(Instruction::Call(method_info.function_idx));
 
             Ok(())
         } else {
-            // Runtime class lookup required - not yet implemented
-            Err(format!("Runtime method calls not yet implemented: {}", method_name))
+            // Runtime class lookup - dispatch on the object's class_id
+            self.compile_dynamic_method_call(object_expr, method_name, args)
+        }
+    }
+
+    /// Compile call arguments against a parameter list: missing arguments take the
+    /// parameter default (or null), extra arguments are evaluated and discarded
+    pub(super) fn compile_call_arguments(&mut self, args: Vec<Expression>, params: &[Parameter]) -> Result<(), String> {
+        let arg_count = args.len();
+        for (i, arg) in args.into_iter().enumerate() {
+            self.compile_expression(arg)?;
+            if i >= params.len() {
+                self.emit(Instruction::Drop);
+            }
+        }
+        for param in params.iter().skip(arg_count) {
+            match &param.default {
+                Some(default) => self.compile_expression(default.clone())?,
+                None => self.emit(Instruction::Call(self.create_null_fn_idx)),
+            }
+        }
+        Ok(())
+    }
+
+    // ===== RUNTIME CLASS DISPATCH =====
+    // When the class of an object is not known at compile time, property and method
+    // access compiles to a chain of class_id comparisons over every class that
+    // declares the member, or handles it through a magic method (__get, __call, ...).
+    // All classes are registered before any code is compiled, so the candidate set
+    // is complete.
+
+    /// Evaluate object and value (in PHP order) into locals for a dynamic property write
+    fn compile_dynamic_property_operands(&mut self, object_expr: Expression, value_expr: Expression) -> Result<(u32, u32), String> {
+        self.compile_expression(object_expr)?;
+        let object_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalSet(object_local));
+        self.compile_stored_value(value_expr, Store::Other)?;
+        let value_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalSet(value_local));
+        Ok((object_local, value_local))
+    }
+
+    /// How each class resolves a property: a declared slot, or the class's magic
+    /// `hook` (__get, __set, __isset or __unset) when it does not declare it.
+    /// Enum properties are read-only, so enums are left out of `writable` lookups.
+    fn property_candidates(&self, property_name: &str, writable: bool, hook: &str) -> Vec<(u32, PropertySlot)> {
+        let mut candidates: Vec<(u32, PropertySlot)> = self.classes.values()
+            .filter(|class| !(writable && class.enum_info.is_some()))
+            .filter_map(|class| {
+                let slot = match class.properties.iter().find(|p| p.name == property_name) {
+                    Some(property) => PropertySlot::Declared(property.index),
+                    None => PropertySlot::Hook(class.methods.get(hook)?.function_idx),
+                };
+                Some((class.class_id, slot))
+            })
+            .collect();
+        candidates.sort_by_key(|(class_id, _)| *class_id);
+        candidates
+    }
+
+    /// The declaring class and declaration of a property, by class id
+    fn declared_property(&self, class_id: u32, property_name: &str) -> Option<(String, PropertyInfo)> {
+        let class = self.classes.values().find(|class| class.class_id == class_id)?;
+        let property = class.properties.iter().find(|p| p.name == property_name)?;
+        Some((class.name.clone(), property.clone()))
+    }
+
+    /// Push the php_object held by a boxed PhpValue local (caller checked TYPE_OBJECT)
+    fn emit_unwrap_object(&mut self, object_local: u32) {
+        self.emit(Instruction::LocalGet(object_local));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        self.emit(Instruction::RefCastNonNull(HeapType::Concrete(self.gc_types.php_object)));
+    }
+
+    /// Push `value.type == TYPE_OBJECT` for a boxed PhpValue local
+    fn emit_is_object(&mut self, value_local: u32) {
+        self.emit(Instruction::LocalGet(value_local));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_TYPE,
+        });
+        self.emit(Instruction::I32Const(TYPE_OBJECT as i32));
+        self.emit(Instruction::I32Eq);
+    }
+
+    /// Store the object of a PhpValue local (caller checked TYPE_OBJECT) into a new
+    /// php_object local
+    pub(super) fn emit_object_ref_local(&mut self, object_local: u32) -> u32 {
+        let object_ref_local = self.allocate_local(ValType::Ref(RefType {
+            nullable: true,
+            heap_type: HeapType::Concrete(self.gc_types.php_object),
+        }));
+        self.emit_unwrap_object(object_local);
+        self.emit(Instruction::LocalSet(object_ref_local));
+        object_ref_local
+    }
+
+    /// Push `object.class_id == class_id` for a php_object local
+    fn emit_class_id_check(&mut self, object_ref_local: u32, class_id: u32) {
+        self.emit(Instruction::LocalGet(object_ref_local));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_object,
+            field_index: OBJECT_CLASS_ID,
+        });
+        self.emit(Instruction::I32Const(class_id as i32));
+        self.emit(Instruction::I32Eq);
+    }
+
+    /// Push the properties array of a php_object local
+    pub(super) fn emit_object_properties(&mut self, object_ref_local: u32) {
+        self.emit(Instruction::LocalGet(object_ref_local));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_object,
+            field_index: OBJECT_PROPERTIES,
+        });
+    }
+
+    /// Read a property from an object whose class is only known at runtime.
+    /// Non-objects and classes without the property (or __get) read as null, and so
+    /// do uninitialised readonly properties when `quiet` is set.
+    pub(super) fn emit_dynamic_property_get(&mut self, object_local: u32, property_name: &str, quiet: bool) -> Result<(), String> {
+        let candidates = self.property_candidates(property_name, false, "__get");
+        let php_value_type = self.get_php_value_type();
+
+        self.emit_is_object(object_local);
+        self.emit(Instruction::If(BlockType::Result(php_value_type)));
+
+        if !candidates.is_empty() {
+            let object_ref_local = self.emit_object_ref_local(object_local);
+
+            for (class_id, slot) in &candidates {
+                self.emit_class_id_check(object_ref_local, *class_id);
+                self.emit(Instruction::If(BlockType::Result(php_value_type)));
+                match slot {
+                    PropertySlot::Declared(property_index) => {
+                        self.emit_object_properties(object_ref_local);
+                        self.emit(Instruction::I32Const(*property_index as i32));
+                        self.emit(Instruction::ArrayGet(self.gc_types.php_array));
+                        if let Some((class_name, property)) = self.declared_property(*class_id, property_name) {
+                            if property.is_readonly && !quiet {
+                                self.emit_readonly_read_check(&class_name, property_name)?;
+                            }
+                        }
+                    }
+                    PropertySlot::Hook(getter_idx) => {
+                        self.emit(Instruction::LocalGet(object_local));
+                        self.compile_string_literal(property_name)?;
+                        self.emit(Instruction::Call(*getter_idx));
+                    }
+                }
+                self.emit(Instruction::Else);
+            }
+        }
+
+        // Undeclared property
+        self.emit(Instruction::Call(self.create_null_fn_idx));
+        for _ in &candidates {
+            self.emit(Instruction::End);
+        }
+
+        self.emit(Instruction::Else);
+        // Property read on a non-object
+        self.emit(Instruction::Call(self.create_null_fn_idx));
+        self.emit(Instruction::End);
+        Ok(())
+    }
+
+    /// Write a property on an object whose class is only known at runtime
+    fn emit_dynamic_property_set(&mut self, object_local: u32, property_name: &str, value_local: u32) -> Result<(), String> {
+        let candidates = self.property_candidates(property_name, true, "__set");
+        if candidates.is_empty() {
+            return Err(format!("Unknown property: {}", property_name));
+        }
+
+        self.emit_is_object(object_local);
+        self.emit(Instruction::If(BlockType::Empty));
+        let object_ref_local = self.emit_object_ref_local(object_local);
+
+        for (class_id, slot) in &candidates {
+            self.emit_class_id_check(object_ref_local, *class_id);
+            self.emit(Instruction::If(BlockType::Empty));
+            match slot {
+                PropertySlot::Declared(property_index) => {
+                    let (class_name, property) = self.declared_property(*class_id, property_name)
+                        .ok_or_else(|| format!("Unknown property: {}", property_name))?;
+                    if property.is_readonly {
+                        self.emit_readonly_guard(object_ref_local, *property_index, &class_name, property_name, false)?;
+                    }
+                    self.emit_object_properties(object_ref_local);
+                    self.emit(Instruction::I32Const(*property_index as i32));
+                    self.emit(Instruction::LocalGet(value_local));
+                    if let Some(property_type) = &property.property_type {
+                        self.emit_property_type_enforcement(&class_name, property_name, property_type)?;
+                        // The assignment evaluates to the coerced value
+                        self.emit(Instruction::LocalTee(value_local));
+                    }
+                    self.emit(Instruction::ArraySet(self.gc_types.php_array));
+                }
+                PropertySlot::Hook(setter_idx) => {
+                    self.emit_magic_set(object_local, property_name, value_local, *setter_idx)?;
+                }
+            }
+            self.emit(Instruction::End);
+        }
+
+        self.emit(Instruction::End);
+        Ok(())
+    }
+
+    /// `__set($name, $value)` on the object in `object_local`
+    fn emit_magic_set(&mut self, object_local: u32, property_name: &str, value_local: u32, setter_idx: u32) -> Result<(), String> {
+        self.emit(Instruction::LocalGet(object_local));
+        self.compile_string_literal(property_name)?;
+        self.emit(Instruction::LocalGet(value_local));
+        self.emit(Instruction::Call(setter_idx));
+        self.emit(Instruction::Drop);
+        Ok(())
+    }
+
+    /// `isset($obj->prop)`: pushes an i32 that is 1 when the property is set (not null),
+    /// asking __isset for properties the class does not declare
+    pub(super) fn compile_property_isset(&mut self, object_expr: Expression, property_name: &str) -> Result<(), String> {
+        self.compile_quiet_fetch(object_expr)?;
+        let object_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalSet(object_local));
+
+        let candidates = self.property_candidates(property_name, false, "__isset");
+
+        self.emit_is_object(object_local);
+        self.emit(Instruction::If(BlockType::Result(ValType::I32)));
+
+        if !candidates.is_empty() {
+            let object_ref_local = self.emit_object_ref_local(object_local);
+
+            for (class_id, slot) in &candidates {
+                self.emit_class_id_check(object_ref_local, *class_id);
+                self.emit(Instruction::If(BlockType::Result(ValType::I32)));
+                match slot {
+                    PropertySlot::Declared(property_index) => {
+                        self.emit_object_properties(object_ref_local);
+                        self.emit(Instruction::I32Const(*property_index as i32));
+                        self.emit(Instruction::ArrayGet(self.gc_types.php_array));
+                        self.emit(Instruction::StructGet {
+                            struct_type_index: self.gc_types.php_value,
+                            field_index: PHPVALUE_TYPE,
+                        });
+                        self.emit(Instruction::I32Const(TYPE_NULL as i32));
+                        self.emit(Instruction::I32Ne);
+                    }
+                    PropertySlot::Hook(isset_idx) => {
+                        self.emit(Instruction::LocalGet(object_local));
+                        self.compile_string_literal(property_name)?;
+                        self.emit(Instruction::Call(*isset_idx));
+                        self.emit(Instruction::Call(self.to_bool_fn_idx));
+                        self.emit(Instruction::StructGet {
+                            struct_type_index: self.gc_types.php_value,
+                            field_index: PHPVALUE_INT,
+                        });
+                        self.emit(Instruction::I32WrapI64);
+                    }
+                }
+                self.emit(Instruction::Else);
+            }
+        }
+
+        // Undeclared property
+        self.emit(Instruction::I32Const(0));
+        for _ in &candidates {
+            self.emit(Instruction::End);
+        }
+
+        self.emit(Instruction::Else);
+        self.emit(Instruction::I32Const(0));
+        self.emit(Instruction::End);
+        Ok(())
+    }
+
+    /// `unset($obj->prop)`: declared properties become null, undeclared ones go to __unset
+    pub(super) fn compile_property_unset(&mut self, object_expr: Expression, property_name: &str) -> Result<(), String> {
+        self.compile_expression(object_expr)?;
+        let object_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalSet(object_local));
+
+        let candidates = self.property_candidates(property_name, true, "__unset");
+        if candidates.is_empty() {
+            return Ok(());
+        }
+
+        self.emit_is_object(object_local);
+        self.emit(Instruction::If(BlockType::Empty));
+        let object_ref_local = self.emit_object_ref_local(object_local);
+
+        for (class_id, slot) in &candidates {
+            self.emit_class_id_check(object_ref_local, *class_id);
+            self.emit(Instruction::If(BlockType::Empty));
+            match slot {
+                PropertySlot::Declared(property_index) => {
+                    let (class_name, property) = self.declared_property(*class_id, property_name)
+                        .ok_or_else(|| format!("Unknown property: {}", property_name))?;
+                    if property.is_readonly {
+                        // Unsetting an uninitialised readonly property leaves it uninitialised
+                        self.emit_readonly_guard(object_ref_local, *property_index, &class_name, property_name, true)?;
+                    } else {
+                        self.emit_object_properties(object_ref_local);
+                        self.emit(Instruction::I32Const(*property_index as i32));
+                        self.emit(Instruction::Call(self.create_null_fn_idx));
+                        self.emit(Instruction::ArraySet(self.gc_types.php_array));
+                    }
+                }
+                PropertySlot::Hook(unset_idx) => {
+                    self.emit(Instruction::LocalGet(object_local));
+                    self.compile_string_literal(property_name)?;
+                    self.emit(Instruction::Call(*unset_idx));
+                    self.emit(Instruction::Drop);
+                }
+            }
+            self.emit(Instruction::End);
+        }
+
+        self.emit(Instruction::End);
+        Ok(())
+    }
+
+    /// Call a method on an object whose class is only known at runtime.
+    /// Classes without the method fall back to their __call; calling an undefined
+    /// method (or a method on a non-object) is fatal.
+    pub(super) fn compile_dynamic_method_call(&mut self, object_expr: Expression, method_name: &str, args: Vec<Expression>) -> Result<(), String> {
+        // __invoke is never routed through __call
+        let call_hook = if method_name == "__invoke" { None } else { Some("__call") };
+        let mut candidates: Vec<(u32, MethodTarget)> = self.classes.values()
+            .filter_map(|class| {
+                let target = match class.methods.get(method_name) {
+                    Some(method) => MethodTarget::Method(method.clone()),
+                    None => MethodTarget::CallHook(class.methods.get(call_hook?)?.function_idx),
+                };
+                Some((class.class_id, target))
+            })
+            .collect();
+        candidates.sort_by_key(|(class_id, _)| *class_id);
+
+        if candidates.is_empty() {
+            return Err(format!("Call to undefined method {}()", method_name));
+        }
+
+        let php_value_type = self.get_php_value_type();
+
+        // Evaluate object and arguments once, left to right
+        self.compile_expression(object_expr)?;
+        let object_local = self.allocate_local(php_value_type);
+        self.emit(Instruction::LocalSet(object_local));
+
+        let mut arg_locals = Vec::with_capacity(args.len());
+        for arg in args {
+            self.compile_expression(arg)?;
+            let arg_local = self.allocate_local(php_value_type);
+            self.emit(Instruction::LocalSet(arg_local));
+            arg_locals.push(arg_local);
+        }
+
+        let class_id_local = self.allocate_local(ValType::I32);
+
+        self.emit_is_object(object_local);
+        self.emit(Instruction::If(BlockType::Result(php_value_type)));
+        self.emit_unwrap_object(object_local);
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_object,
+            field_index: OBJECT_CLASS_ID,
+        });
+        self.emit(Instruction::LocalSet(class_id_local));
+
+        for (class_id, target) in &candidates {
+            self.emit(Instruction::LocalGet(class_id_local));
+            self.emit(Instruction::I32Const(*class_id as i32));
+            self.emit(Instruction::I32Eq);
+            self.emit(Instruction::If(BlockType::Result(php_value_type)));
+
+            self.emit(Instruction::LocalGet(object_local));
+            match target {
+                MethodTarget::Method(method_info) => {
+                    for (i, param) in method_info.params.iter().enumerate() {
+                        if let Some(arg_local) = arg_locals.get(i) {
+                            self.emit(Instruction::LocalGet(*arg_local));
+                        } else if let Some(default) = &param.default {
+                            self.compile_expression(default.clone())?;
+                        } else {
+                            self.emit(Instruction::Call(self.create_null_fn_idx));
+                        }
+                    }
+                    self.emit(Instruction::Call(method_info.function_idx));
+                }
+                MethodTarget::CallHook(call_idx) => {
+                    self.compile_string_literal(method_name)?;
+                    let temps: Vec<String> = arg_locals.iter()
+                        .map(|&arg_local| self.bind_temp_variable(arg_local, None))
+                        .collect();
+                    let args = temps.iter().map(|name| Expression::Variable(name.clone())).collect();
+                    let result = self.compile_magic_call_arguments(args);
+                    for name in &temps {
+                        self.variables.remove(name);
+                    }
+                    result?;
+                    self.emit(Instruction::Call(*call_idx));
+                }
+            }
+
+            self.emit(Instruction::Else);
+        }
+
+        // The object's class does not define the method
+        self.emit(Instruction::Unreachable);
+        for _ in &candidates {
+            self.emit(Instruction::End);
+        }
+
+        self.emit(Instruction::Else);
+        // Method call on a non-object
+        self.emit(Instruction::Unreachable);
+        self.emit(Instruction::End);
+
+        Ok(())
+    }
+
+    // ===== NULLSAFE OPERATOR =====
+
+    /// Whether the access chain of an expression ($a?->b->c()[0]) contains a ?-> link
+    pub(super) fn has_nullsafe_in_chain(expr: &Expression) -> bool {
+        match expr {
+            Expression::NullsafeMethodCall { .. } | Expression::NullsafePropertyAccess { .. } => true,
+            Expression::MethodCall { object, .. } | Expression::PropertyAccess { object, .. } => {
+                Self::has_nullsafe_in_chain(object)
+            }
+            Expression::ArrayAccess { array, .. } => Self::has_nullsafe_in_chain(array),
+            _ => false,
+        }
+    }
+
+    /// Compile an access chain containing ?->. The innermost ?-> link is split off: its
+    /// object is evaluated once, and if it is null the whole remaining chain short-circuits
+    /// to null. Otherwise the rest of the chain (where further ?-> links are handled
+    /// recursively) is compiled against a temporary holding the object.
+    pub(super) fn compile_nullsafe_chain(&mut self, mut expr: Expression) -> Result<(), String> {
+        let temp_local = self.allocate_local(self.get_php_value_type());
+        let temp_name = self.next_temp_name();
+
+        let object = Self::take_innermost_nullsafe(&mut expr, &temp_name)
+            .ok_or_else(|| "Expected nullsafe access in chain".to_string())?;
+        let class_type = self.static_class_of(&object);
+
+        self.compile_expression(object)?;
+        self.emit(Instruction::LocalTee(temp_local));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_TYPE,
+        });
+        self.emit(Instruction::I32Const(TYPE_NULL as i32));
+        self.emit(Instruction::I32Eq);
+        self.emit(Instruction::If(BlockType::Result(self.get_php_value_type())));
+        self.emit(Instruction::Call(self.create_null_fn_idx));
+        self.emit(Instruction::Else);
+
+        self.variables.insert(temp_name.clone(), VariableInfo {
+            local_idx: temp_local,
+            storage_type: VariableStorage::Boxed,
+            class_type,
+        });
+        let result = self.compile_expression(expr);
+        self.variables.remove(&temp_name);
+        result?;
+
+        self.emit(Instruction::End);
+        Ok(())
+    }
+
+    /// Replace the innermost ?-> link of a chain with a plain -> on `temp_name`,
+    /// returning the object expression it was applied to
+    fn take_innermost_nullsafe(expr: &mut Expression, temp_name: &str) -> Option<Expression> {
+        let inner = match expr {
+            Expression::MethodCall { object, .. }
+            | Expression::PropertyAccess { object, .. }
+            | Expression::NullsafeMethodCall { object, .. }
+            | Expression::NullsafePropertyAccess { object, .. } => Self::take_innermost_nullsafe(object, temp_name),
+            Expression::ArrayAccess { array, .. } => Self::take_innermost_nullsafe(array, temp_name),
+            _ => return None,
+        };
+        if inner.is_some() {
+            return inner;
+        }
+
+        let temp = Box::new(Expression::Variable(temp_name.to_string()));
+        match std::mem::replace(expr, Expression::Literal(Literal::Null)) {
+            Expression::NullsafeMethodCall { object, method, args } => {
+                *expr = Expression::MethodCall { object: temp, method, args };
+                Some(*object)
+            }
+            Expression::NullsafePropertyAccess { object, property } => {
+                *expr = Expression::PropertyAccess { object: temp, property };
+                Some(*object)
+            }
+            other => {
+                *expr = other;
+                None
+            }
         }
     }
 }
@@ -0,0 +1,510 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Constant folding and propagation over the AST, before either backend runs
+//
+// Expressions on literals are evaluated at compile time: operators (with the
+// IR's rules, see `ir::passes::fold_binary`, so both agree), `!`, unary
+// minus, casts, ternaries, `??`, `&&` and `||` on a constant left operand,
+// and the pure builtins `strlen`, `strtolower`, `strtoupper` and `abs` on
+// literals and `count` of array literals. `if` statements keep only the
+// branches a constant condition leaves.
+//
+// Constants also propagate through variables. A variable its scope assigns
+// exactly once, in a statement of the scope's own statement list (not in a
+// branch or a loop), becomes that literal in every later statement. Every
+// other occurrence must be a plain read: a variable passed directly to a call
+// may be taken by reference, and one used as an array, object or `??`
+// operand keeps its variable. Scopes that reach their variables in ways the
+// AST does not show (`include`, `compact`, `extract`, `get_defined_vars`) are
+// left alone, and so are top-level variables named by `global` or
+// `$GLOBALS`. The assignments themselves stay.
+
+use super::ir::lower::PURE_BUILTINS;
+use super::ir::passes::{fold_binary, fold_builtin, fold_cast};
+use super::ir::{CastKind, Constant};
+use super::type_inference::SUPERGLOBALS;
+use edge_php_parser::ast::*;
+use edge_php_parser::visit::{self, Visitor};
+use edge_php_parser::visit_mut::{self, VisitorMut};
+use std::collections::{HashMap, HashSet};
+
+/// Builtins that can see or change the variables of the scope calling them
+const SCOPE_FUNCTIONS: [&str; 3] = ["compact", "extract", "get_defined_vars"];
+
+/// Fold and propagate the constants of a resolved program. Calls to
+/// `user_functions`, the defined and imported functions, are never folded.
+pub fn fold_program(program: &mut Program, user_functions: HashSet<String>) {
+    let mut folder = ConstantFolder { user_functions };
+    visit_mut::walk_program_mut(&mut folder, program);
+
+    let mut facts = ProgramFacts::default();
+    visit::walk_program(&mut facts, program);
+    let statements = program.items.iter_mut()
+        .filter_map(|item| match item {
+            ProgramItem::PhpBlock { statements } => Some(statements),
+            ProgramItem::InlineContent(_) => None,
+        })
+        .flatten()
+        .collect();
+    if !facts.dynamic_globals {
+        folder.propagate(statements, &[], &facts.globals);
+    }
+    visit_mut::walk_program_mut(&mut Scopes(&mut folder), program);
+}
+
+struct ConstantFolder {
+    user_functions: HashSet<String>,
+}
+
+impl ConstantFolder {
+    fn fold(&self, expr: &Expression) -> Option<Expression> {
+        match expr {
+            Expression::Binary { op: op @ (BinaryOp::And | BinaryOp::Or), left, right } => {
+                let left = constant(left)?.truthy();
+                // The right operand decides only when the left one does not
+                if left == (*op == BinaryOp::Or) {
+                    return Some(literal(Constant::Bool(left)));
+                }
+                Some(literal(Constant::Bool(constant(right)?.truthy())))
+            }
+            Expression::Binary { op, left, right } => fold_binary(op, constant(left)?, constant(right)?).map(literal),
+            Expression::Unary { op: UnaryOp::Not, expr } => Some(literal(Constant::Bool(!constant(expr)?.truthy()))),
+            Expression::Unary { op: UnaryOp::Negate, expr } => match constant(expr)? {
+                Constant::Int(n) => n.checked_neg().map(|n| literal(Constant::Int(n))),
+                Constant::Float(x) => Some(literal(Constant::Float(-x))),
+                _ => None,
+            },
+            Expression::Cast { cast_type, expr } => {
+                let value = constant(expr)?;
+                let kind = match cast_type {
+                    Type::Bool => return Some(literal(Constant::Bool(value.truthy()))),
+                    Type::Int => CastKind::Int,
+                    Type::Float => CastKind::Float,
+                    Type::String => CastKind::String,
+                    _ => return None,
+                };
+                fold_cast(kind, value).map(literal)
+            }
+            Expression::Ternary { condition, then_expr, else_expr } => {
+                let chosen = if constant(condition)?.truthy() { then_expr } else { else_expr };
+                Some((**chosen).clone())
+            }
+            Expression::Coalesce { left, right } => match constant(left)? {
+                Constant::Null => Some((**right).clone()),
+                _ => Some((**left).clone()),
+            },
+            Expression::FunctionCall { name, args } if !self.user_functions.contains(name) => {
+                match (name.as_str(), args.as_slice()) {
+                    ("count" | "sizeof", [Expression::Array(elements)]) => {
+                        let keyless = elements.iter().all(|element| element.key.is_none() && is_literal_value(&element.value));
+                        keyless.then(|| literal(Constant::Int(elements.len() as i64)))
+                    }
+                    _ => {
+                        let args = args.iter().map(constant).collect::<Option<Vec<_>>>()?;
+                        fold_builtin(name, &args).map(literal)
+                    }
+                }
+            }
+            Expression::Literal(Literal::InterpolatedString(parts)) => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        InterpolatedPart::Text(part) => text.push_str(part),
+                        _ => return None,
+                    }
+                }
+                Some(Expression::Literal(Literal::String(text)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Replace the variables `scope` assigns a constant once with it, in the
+    /// statements after the assignment
+    fn propagate(&mut self, mut scope: Vec<&mut Statement>, params: &[Parameter], excluded: &HashSet<String>) {
+        let mut occurrences = Occurrences::default();
+        for stmt in &scope {
+            occurrences.visit_statement(stmt);
+        }
+        if occurrences.opaque {
+            return;
+        }
+        let candidates: HashSet<String> = occurrences.writes.into_iter()
+            .filter(|(name, writes)| {
+                *writes == 1
+                    && !occurrences.blocked.contains(name)
+                    && !excluded.contains(name)
+                    && !params.iter().any(|param| &param.name == name)
+                    && !SUPERGLOBALS.contains(&name.as_str())
+            })
+            .map(|(name, _)| name)
+            .collect();
+        if candidates.is_empty() {
+            return;
+        }
+
+        let mut known = HashMap::new();
+        for stmt in scope.iter_mut() {
+            if !known.is_empty() {
+                Substitution(&known).visit_statement_mut(stmt);
+                self.visit_statement_mut(stmt);
+            }
+            if let Statement::Expression(Expression::Assignment { left, right }) = &**stmt {
+                if let (Expression::Variable(name), Expression::Literal(value)) = (&**left, &**right) {
+                    if candidates.contains(name) && !matches!(value, Literal::InterpolatedString(_)) {
+                        known.insert(name.clone(), value.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl VisitorMut for ConstantFolder {
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        visit_mut::walk_statement_mut(self, stmt);
+        let Statement::If { condition, elseif_blocks, .. } = &*stmt else { return };
+        if constant(condition).is_none() && elseif_blocks.iter().all(|elseif| constant(&elseif.condition).is_none()) {
+            return;
+        }
+        let empty = Statement::Block(Block { statements: Vec::new() });
+        if let Statement::If { condition, then_block, elseif_blocks, else_block } = std::mem::replace(stmt, empty) {
+            let mut branches = vec![(condition, then_block)];
+            branches.extend(elseif_blocks.into_iter().map(|elseif| (elseif.condition, elseif.then_block)));
+            let mut otherwise = else_block;
+            let mut kept = Vec::new();
+            for (condition, block) in branches {
+                match constant(&condition).map(|value| value.truthy()) {
+                    Some(false) => {}
+                    // Later branches never run
+                    Some(true) => {
+                        otherwise = Some(block);
+                        break;
+                    }
+                    None => kept.push((condition, block)),
+                }
+            }
+            let mut kept = kept.into_iter();
+            *stmt = match kept.next() {
+                Some((condition, then_block)) => Statement::If {
+                    condition,
+                    then_block,
+                    elseif_blocks: kept.map(|(condition, then_block)| ElseIfBlock { condition, then_block }).collect(),
+                    else_block: otherwise,
+                },
+                None => Statement::Block(otherwise.unwrap_or(Block { statements: Vec::new() })),
+            };
+        }
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        visit_mut::walk_expression_mut(self, expr);
+        if let Some(folded) = self.fold(expr) {
+            *expr = folded;
+        }
+    }
+}
+
+fn constant(expr: &Expression) -> Option<Constant> {
+    match expr {
+        Expression::Literal(literal) => Constant::from_literal(literal),
+        _ => None,
+    }
+}
+
+fn literal(constant: Constant) -> Expression {
+    Expression::Literal(constant.into_literal())
+}
+
+/// An expression without side effects whose value is known, for the
+/// elements `count` may drop
+fn is_literal_value(expr: &Expression) -> bool {
+    match expr {
+        Expression::Literal(Literal::InterpolatedString(_)) => false,
+        Expression::Literal(_) => true,
+        Expression::Array(elements) => elements.iter().all(|element| {
+            element.key.as_ref().is_none_or(is_literal_value) && is_literal_value(&element.value)
+        }),
+        _ => false,
+    }
+}
+
+/// Propagates into the scope of every function, method and constructor
+struct Scopes<'f>(&'f mut ConstantFolder);
+
+impl VisitorMut for Scopes<'_> {
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        if let Statement::Function { params, body, .. } = stmt {
+            self.0.propagate(body.statements.iter_mut().collect(), params, &HashSet::new());
+        }
+        visit_mut::walk_statement_mut(self, stmt);
+    }
+
+    fn visit_class_member_mut(&mut self, member: &mut ClassMember) {
+        if let ClassMember::Method { params, body, .. } | ClassMember::Constructor { params, body, .. } = member {
+            self.0.propagate(body.statements.iter_mut().collect(), params, &HashSet::new());
+        }
+        visit_mut::walk_class_member_mut(self, member);
+    }
+}
+
+/// How the statements of one scope use its variables; nested declarations
+/// are scopes of their own and are not visited
+#[derive(Default)]
+struct Occurrences {
+    /// Assignments to each variable as a whole
+    writes: HashMap<String, usize>,
+    /// Variables used other than by assigning or reading them
+    blocked: HashSet<String>,
+    /// The scope's variables may be reached by name
+    opaque: bool,
+}
+
+impl Occurrences {
+    /// Every variable in `expr` may change or be referenced
+    fn block(&mut self, expr: &Expression) {
+        struct Variables<'o>(&'o mut HashSet<String>);
+
+        impl Visitor for Variables<'_> {
+            fn visit_expression(&mut self, expr: &Expression) {
+                if let Expression::Variable(name) = expr {
+                    self.0.insert(name.clone());
+                }
+                visit::walk_expression(self, expr);
+            }
+        }
+
+        Variables(&mut self.blocked).visit_expression(expr);
+    }
+
+    /// A variable used directly as this operand is blocked
+    fn block_operand(&mut self, expr: &Expression) {
+        if let Expression::Variable(name) = expr {
+            self.blocked.insert(name.clone());
+        }
+    }
+}
+
+impl Visitor for Occurrences {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. } => return,
+            Statement::Global(names) => self.blocked.extend(names.iter().cloned()),
+            Statement::Static(variables) => self.blocked.extend(variables.iter().map(|variable| variable.name.clone())),
+            Statement::Foreach { key, value, array, .. } => {
+                self.blocked.extend(key.iter().cloned());
+                self.block(value);
+                self.block_operand(array);
+            }
+            _ => {}
+        }
+        visit::walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Assignment { left, .. } => match &**left {
+                Expression::Variable(name) => *self.writes.entry(name.clone()).or_default() += 1,
+                target => self.block(target),
+            },
+            Expression::Unary { op: UnaryOp::PreIncrement | UnaryOp::PostIncrement | UnaryOp::PreDecrement | UnaryOp::PostDecrement, expr } => {
+                self.block(expr);
+            }
+            Expression::FunctionCall { name, args } => {
+                if SCOPE_FUNCTIONS.contains(&name.as_str()) {
+                    self.opaque = true;
+                }
+                if !PURE_BUILTINS.contains(&name.as_str()) && !matches!(name.as_str(), "count" | "sizeof") {
+                    args.iter().for_each(|arg| self.block_operand(arg));
+                }
+            }
+            Expression::MethodCall { object, args, .. } | Expression::NullsafeMethodCall { object, args, .. } | Expression::Invoke { callee: object, args } => {
+                self.block_operand(object);
+                args.iter().for_each(|arg| self.block_operand(arg));
+            }
+            Expression::StaticMethodCall { args, .. } | Expression::New { args, .. } => {
+                args.iter().for_each(|arg| self.block_operand(arg));
+            }
+            Expression::PropertyAccess { object, .. } | Expression::NullsafePropertyAccess { object, .. } | Expression::Clone(object) => {
+                self.block_operand(object);
+            }
+            Expression::ArrayAccess { array, .. } => self.block_operand(array),
+            Expression::Coalesce { left, .. } => self.block_operand(left),
+            Expression::List(_) => self.block(expr),
+            Expression::Include { .. } => self.opaque = true,
+            _ => {}
+        }
+        visit::walk_expression(self, expr);
+    }
+}
+
+/// Replaces reads of the known variables with their values
+struct Substitution<'k>(&'k HashMap<String, Literal>);
+
+impl VisitorMut for Substitution<'_> {
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        if !matches!(stmt, Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. }) {
+            visit_mut::walk_statement_mut(self, stmt);
+        }
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        match expr {
+            Expression::Variable(name) => {
+                if let Some(value) = self.0.get(name) {
+                    *expr = Expression::Literal(value.clone());
+                }
+            }
+            Expression::Literal(Literal::InterpolatedString(parts)) => {
+                for part in parts.iter_mut() {
+                    if let InterpolatedPart::Variable(name) = part {
+                        // Floats keep their variable: their text depends on the runtime's formatting
+                        let text = match self.0.get(name) {
+                            Some(Literal::String(s)) => s.clone(),
+                            Some(Literal::Integer(n)) => n.to_string(),
+                            Some(Literal::Boolean(b)) => if *b { "1" } else { "" }.to_string(),
+                            Some(Literal::Null) => String::new(),
+                            _ => continue,
+                        };
+                        *part = InterpolatedPart::Text(text);
+                    }
+                }
+                visit_mut::walk_expression_mut(self, expr);
+            }
+            _ => visit_mut::walk_expression_mut(self, expr),
+        }
+    }
+}
+
+/// Top-level variables that code outside the top-level scope can reach
+#[derive(Default)]
+struct ProgramFacts {
+    globals: HashSet<String>,
+    /// `$GLOBALS` is used, which reaches any of them
+    dynamic_globals: bool,
+}
+
+impl Visitor for ProgramFacts {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        if let Statement::Global(names) = stmt {
+            self.globals.extend(names.iter().cloned());
+        }
+        visit::walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        if matches!(expr, Expression::Variable(name) if name == "GLOBALS") {
+            self.dynamic_globals = true;
+        }
+        visit::walk_expression(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `source` after folding, with its functions as the user functions
+    fn fold(source: &str) -> Program {
+        struct Functions(HashSet<String>);
+
+        impl Visitor for Functions {
+            fn visit_statement(&mut self, stmt: &Statement) {
+                if let Statement::Function { name, .. } = stmt {
+                    self.0.insert(name.clone());
+                }
+                visit::walk_statement(self, stmt);
+            }
+        }
+
+        let mut program = edge_php_parser::parse(source).unwrap();
+        let mut functions = Functions(HashSet::new());
+        visit::walk_program(&mut functions, &program);
+        fold_program(&mut program, functions.0);
+        program
+    }
+
+    fn assert_folds(source: &str, expected: &str) {
+        assert_eq!(fold(source), edge_php_parser::parse(expected).unwrap(), "folding {}", source);
+    }
+
+    fn assert_unchanged(source: &str) {
+        assert_folds(source, source);
+    }
+
+    #[test]
+    fn test_expressions() {
+        assert_folds(
+            r#"<?php echo 2 + 3 * 4, -(5), !0, (int) 7.5, 0 ? "a" : "b", strlen("abc"), count([1, 2]);"#,
+            r#"<?php echo 14, -5, true, 7, "b", 3, 2;"#,
+        );
+        // Calls to user functions stay, even when a builtin has the name
+        assert_unchanged("<?php function strlen2($s) { return 1; } echo strlen2(\"abc\");");
+    }
+
+    #[test]
+    fn test_short_circuits() {
+        // A constant left operand that decides drops the right one, calls and all
+        assert_folds(
+            "<?php function f() { return 1; } echo false && f(), true || f(), null ?? f(), 0 ?? f();",
+            "<?php function f() { return 1; } echo false, true, f(), 0;",
+        );
+        // Both operands constant
+        assert_folds("<?php echo true && 0, false || \"x\";", "<?php echo false, true;");
+        // A left operand that does not decide keeps the right one
+        assert_unchanged("<?php function f() { return 1; } echo true && f(), false || f();");
+        // A variable on the left is never folded
+        assert_unchanged("<?php function f() { return 1; } echo $x && f(), $x ?? f();");
+    }
+
+    #[test]
+    fn test_if_branches() {
+        assert_folds(
+            "<?php if (0) { echo 1; } elseif ($x) { echo 2; } elseif (1) { echo 3; } else { echo 4; }",
+            "<?php if ($x) { echo 2; } else { echo 3; }",
+        );
+        assert_folds("<?php if (false) { echo 1; }", "<?php {}");
+    }
+
+    #[test]
+    fn test_propagation() {
+        assert_folds(
+            "<?php function f($c) { $a = 2; $b = 1; if ($c) { $b = 3; } echo $a * 3, $b, strlen(\"$a\"); }",
+            "<?php function f($c) { $a = 2; $b = 1; if ($c) { $b = 3; } echo 6, $b, 1; }",
+        );
+        // Only the statements after the assignment, and never parameters
+        assert_folds(
+            "<?php function f($p) { echo $a; $a = 1; $p = 2; echo $a, $p; }",
+            "<?php function f($p) { echo $a; $a = 1; $p = 2; echo 1, $p; }",
+        );
+        // A pure builtin cannot change its argument
+        assert_folds("<?php $s = \"ab\"; echo strlen($s);", "<?php $s = \"ab\"; echo 2;");
+    }
+
+    #[test]
+    fn test_propagation_blockers() {
+        // `global` in the function itself, and in any function for the top level
+        assert_unchanged("<?php function f() { global $n; $n = 1; echo $n; }");
+        assert_unchanged("<?php $n = 1; function f() { global $n; $n = 2; } f(); echo $n;");
+        // `$GLOBALS` reaches every top-level variable
+        assert_unchanged("<?php $n = 1; function f() { $GLOBALS['n'] = 2; } f(); echo $n;");
+        // Builtins and `include` reaching the scope by name
+        assert_unchanged("<?php function f() { $a = 1; extract(['a' => 2]); echo $a; }");
+        assert_unchanged("<?php function f() { $a = 1; $b = compact('a'); echo $a; }");
+        assert_unchanged("<?php function f() { $a = 1; include 'a.php'; echo $a; }");
+        // Foreach targets are assignments too
+        assert_unchanged("<?php function f() { $v = 1; foreach ([2] as $v) { } echo $v; }");
+        assert_unchanged("<?php function f() { $k = 1; foreach ([2] as $k => $v) { } echo $k; }");
+        // Arguments that a call may take by reference
+        assert_unchanged("<?php function f() { $a = 1; settype($a, 'string'); echo $a; }");
+        assert_unchanged("<?php function g($x) { } function f() { $a = 1; g($a); echo $a; }");
+        // Operands of increments, array writes and `??`
+        assert_unchanged("<?php function f() { $a = 1; $a++; echo $a; }");
+        assert_unchanged("<?php function f() { $a = 1; $a[0] = 2; echo $a; }");
+        assert_unchanged("<?php function f() { $a = 1; echo $a ?? 2; }");
+        // Assigned more than once or in a branch
+        assert_unchanged("<?php function f($c) { $a = 1; $a = 2; echo $a; }");
+        assert_unchanged("<?php function f($c) { if ($c) { $a = 1; } echo $a; }");
+    }
+}
@@ -4,8 +4,10 @@
 use edge_php_parser::*;
 use wasm_encoder::*;
 use crate::{error::CompilerError, wasm_builder::WasmBuilder};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use super::type_inference::TypeInference;
+use super::options::CompilerOptions;
+use super::debug_info::{source_map, CompiledModule};
 
 /// WasmGC type indices
 #[derive(Debug, Clone, Copy)]
@@ -17,6 +19,9 @@ pub(super) struct GcTypes {
     pub php_hash_array: u32, // Hash table array (ordered map)
     pub php_hash_table: u32, // Hash table struct (contains buckets + metadata)
     pub php_object: u32,     // PHASE 5: Object type (class_id + properties)
+    pub php_int_array: u32,    // Packed array of unboxed ints
+    pub php_float_array: u32,  // Packed array of unboxed floats
+    pub php_string_array: u32, // Packed array of string refs
 }
 
 /// Type tags for PhpValue type field
@@ -34,7 +39,6 @@ pub(super) const PHPVALUE_INT: u32 = 1;
 pub(super) const PHPVALUE_FLOAT: u32 = 2;
 pub(super) const PHPVALUE_STRING: u32 = 3;
 pub(super) const PHPVALUE_ARRAY: u32 = 4;
-pub(super) const PHPVALUE_OBJECT: u32 = 5;  // PHASE 5: Dedicated object field
 
 /// Field indices for ArrayEntry struct
 pub(super) const ARRAYENTRY_KEY: u32 = 0;
@@ -68,6 +72,9 @@ pub(super) enum VariableStorage {
     UnboxedInt,
     /// Stored as unboxed f64 (known float)
     UnboxedFloat,
+    /// Stored as boxed PhpValue in a wasm global (`global`, `static`, `$GLOBALS`);
+    /// `local_idx` is unused
+    Global(u32),
 }
 
 pub struct Compiler {
@@ -75,12 +82,16 @@ pub struct Compiler {
     pub(super) current_function: Option<FunctionContext>,
     pub(super) variables: HashMap<String, VariableInfo>,  // Maps var names to variable info
     pub(super) functions: HashMap<String, u32>,
-    pub(super) string_constants: HashMap<String, u32>,  // Maps strings to global indices
+    pub(super) specialized_functions: HashMap<String, u32>,  // Copies of functions by key ("fib<int>")
+    pub(super) ir_listing: String,  // Text of the functions compiled through the mid-level IR
+    pub(super) function_params: HashMap<String, Vec<edge_php_parser::ast::Parameter>>,  // Defaults for call sites
     pub(super) gc_types: GcTypes,
 
     // PHASE 5: Class support
     pub(super) classes: HashMap<String, super::classes::ClassInfo>,
     pub(super) next_class_id: u32,
+    pub(super) interfaces: HashMap<String, super::classes::InterfaceInfo>,
+    pub(super) current_class: Option<String>,  // Class whose method is being compiled (resolves `self::`)
 
     // Type inference for optimization
     pub(super) type_inference: TypeInference,
@@ -102,6 +113,49 @@ pub struct Compiler {
     pub(super) string_intern_table: HashMap<String, (u32, i32)>,
     pub(super) next_intern_id: u32,
 
+    // Counter for compiler-generated temporaries bound into `variables`
+    // (nullsafe chains, destructuring); names start with '#' so they never clash with PHP
+    pub(super) next_temp_var_id: u32,
+
+    // Module-level variable storage: top-level variables reachable from functions
+    // (name -> wasm global) and function statics ((scope, name) -> wasm global)
+    pub(super) global_variables: HashMap<String, u32>,
+    pub(super) static_variables: HashMap<(String, String), u32>,
+    pub(super) static_scope: String,  // Function or Class::method being compiled, "" for top-level code
+
+    // Superglobals ($_GET, $_SERVER, ...) bound in every scope, and the
+    // header()/setcookie() state when the program builds a response
+    pub(super) superglobals: HashMap<String, u32>,
+    pub(super) response: Option<super::http::ResponseState>,
+
+    // The output buffer stack, and the ob_start() callbacks by id - 1
+    pub(super) output: Option<super::output::OutputState>,
+    pub(super) ob_callbacks: Vec<String>,
+
+    // Functions from outside the module by PHP name, and the helpers moving
+    // strings through linear memory (string_from_memory, write_bytes)
+    pub(super) imported_functions: HashMap<String, (u32, super::imports::ImportedFunction)>,
+    pub(super) memory_helpers: Option<(u32, u32)>,
+
+    // Type declarations: `declare(strict_types=1)` and the return type being enforced
+    pub(super) strict_types: bool,
+    pub(super) return_type: Option<super::types::ReturnTypeInfo>,
+
+    // The specialized copy of a user function being compiled, if it is one
+    pub(super) specialization: Option<super::type_inference::Specialization>,
+
+    // Functions and methods ("Class::method") whose calls compile their
+    // returned expression, and the inlined calls being compiled
+    pub(super) inline_candidates: HashMap<String, super::inlining::InlineCandidate>,
+    pub(super) inlining: Vec<String>,
+    // Functions returning an expression that changes nothing (see InlineCandidate::pure)
+    pub(super) pure_functions: HashSet<String>,
+    // Variables whose arrays the scope being compiled changes in place (see array_copies.rs)
+    pub(super) changed_arrays: HashSet<String>,
+
+    pub(super) options: CompilerOptions,
+    pub(super) sources: Vec<(String, String)>,  // Name and text of the compiled files, for source maps
+
     /// Import indices
     pub(super) print_fn_idx: u32,
     
@@ -144,11 +198,26 @@ pub struct Compiler {
     pub(super) array_pop_fn_idx: u32,     // PHP array_pop() function
     pub(super) array_shift_fn_idx: u32,   // PHP array_shift() function
     pub(super) array_unshift_fn_idx: u32, // PHP array_unshift() function
+    pub(super) array_unset_fn_idx: u32,   // unset($array[$key])
     pub(super) in_array_fn_idx: u32,      // PHP in_array() function
     pub(super) array_keys_fn_idx: u32,
     pub(super) array_values_fn_idx: u32,
     pub(super) array_merge_fn_idx: u32,   // PHP array_merge() function
     pub(super) array_slice_fn_idx: u32,   // PHP array_slice() function
+    pub(super) array_get_quiet_fn_idx: u32, // Lookup that yields null for missing keys/non-arrays (??, isset, list())
+    pub(super) array_copy_fn_idx: u32,    // Recursive copy of an array value (clone)
+
+    /// Packed int, float and string arrays
+    pub(super) array_deoptimize_fn_idx: u32, // Typed storage -> boxed php_array, in place
+    pub(super) array_element_fn_idx: u32,    // Boxed element of any packed storage
+    pub(super) array_get_int_fn_idx: u32,    // Unboxed read of an int element
+    pub(super) array_get_float_fn_idx: u32,  // Unboxed read of a float element
+    pub(super) array_set_int_fn_idx: u32,    // Unboxed write of an int element
+    pub(super) array_set_float_fn_idx: u32,  // Unboxed write of a float element
+    pub(super) array_sum_fn_idx: u32,        // PHP array_sum() function
+    pub(super) array_sort_fn_idx: u32,       // PHP sort() function, in place
+    pub(super) sort_less_fn_idx: u32,        // sort() ordering of two values
+    pub(super) string_compare_fn_idx: u32,   // Byte-wise string ordering (-1, 0, 1)
 
     /// Hash array function indices (for associative arrays)
     pub(super) create_hash_array_fn_idx: u32,
@@ -168,6 +237,16 @@ pub struct Compiler {
     
     /// String operation function indices
     pub(super) string_equals_fn_idx: u32, // String content comparison
+
+    /// Magic method helpers (bodies depend on the registered classes)
+    pub(super) object_to_string_fn_idx: u32, // __toString dispatch
+    pub(super) clone_object_fn_idx: u32,     // Shallow object copy with array properties copied
+    pub(super) destructor_registry: Option<u32>, // Global array of objects whose class has __destruct
+    pub(super) readonly_sentinel: Option<u32>, // Global marking uninitialised readonly properties
+
+    /// Type declaration helpers (bodies depend on the registered classes)
+    pub(super) debug_type_fn_idx: u32,   // Type name of a value for TypeError messages
+    pub(super) coerce_value_fn_idx: u32, // Coercive-mode conversion to a declared scalar type
     
     /// Type indices for function signatures
     pub(super) value_to_value_type_idx: u32,
@@ -175,13 +254,13 @@ pub struct Compiler {
     pub(super) i32_to_value_type_idx: u32,
     pub(super) i64_to_value_type_idx: u32,
     pub(super) f64_to_value_type_idx: u32,
-    pub(super) print_type_idx: u32,
 }
 
 pub(super) struct FunctionContext {
     pub(super) locals: Vec<(u32, ValType)>,
     pub(super) body: Vec<Instruction<'static>>,
     pub(super) local_count: u32,
+    pub(super) locations: Vec<(usize, SourceLocation)>,  // Body position -> statement, for source maps
 }
 
 #[derive(Debug, Clone)]
@@ -190,22 +269,25 @@ pub(super) struct LoopContext {
     pub break_depth: u32,
     /// Branch depth to continue the loop (for continue)  
     pub continue_depth: u32,
-    /// Loop type to handle continue differently
-    pub loop_type: LoopType,
     /// The block nesting level when entering the loop
     pub entry_block_depth: u32,
 }
 
-#[derive(Debug, Clone)]
-pub(super) enum LoopType {
-    While,
-    For,
-    Foreach,
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Compiler {
     pub fn new() -> Self {
+        Self::with_options(CompilerOptions::default())
+    }
+
+    pub fn with_options(options: CompilerOptions) -> Self {
         let mut builder = WasmBuilder::new();
+        builder.set_tree_shaking(options.tree_shaking_enabled());
+        builder.set_fuel_metering(options.fuel_metering_enabled());
         
         // First, define the GC types
         let gc_types = Self::define_gc_types(&mut builder);
@@ -213,6 +295,22 @@ impl Compiler {
         // Import host print function
         let print_type_idx = builder.add_type(vec![ValType::I32], vec![]);
         let print_fn_idx = builder.add_import_func("env", "print", print_type_idx);
+
+        // Runtime helpers are named `php_<helper>` in the name section
+        let debug_info = options.debug_info_enabled();
+        macro_rules! reserve_helper {
+            ($name:literal) => {{
+                let idx = builder.reserve_function_index();
+                if debug_info {
+                    builder.set_function_name(idx, concat!("php_", $name));
+                }
+                idx
+            }};
+        }
+        if debug_info {
+            builder.set_function_name(print_fn_idx, "env.print");
+        }
+        let imported_functions = Self::add_imported_functions(&mut builder, &options);
         
         // Define function type signatures
         let php_value_ref = ValType::Ref(RefType {
@@ -227,77 +325,102 @@ impl Compiler {
         let f64_to_value_type_idx = builder.add_type(vec![ValType::F64], vec![php_value_ref]);
         
         // Reserve function indices
-        let create_null_fn_idx = builder.reserve_function_index();
-        let create_bool_fn_idx = builder.reserve_function_index();
-        let create_int_fn_idx = builder.reserve_function_index();
-        let create_float_fn_idx = builder.reserve_function_index();
-        let create_string_fn_idx = builder.reserve_function_index();
-        let print_value_fn_idx = builder.reserve_function_index();
+        let create_null_fn_idx = reserve_helper!("create_null");
+        let create_bool_fn_idx = reserve_helper!("create_bool");
+        let create_int_fn_idx = reserve_helper!("create_int");
+        let create_float_fn_idx = reserve_helper!("create_float");
+        let create_string_fn_idx = reserve_helper!("create_string");
+        let print_value_fn_idx = reserve_helper!("print_value");
         
-        let add_fn_idx = builder.reserve_function_index();
-        let subtract_fn_idx = builder.reserve_function_index();
-        let multiply_fn_idx = builder.reserve_function_index();
-        let divide_fn_idx = builder.reserve_function_index();
-        let modulo_fn_idx = builder.reserve_function_index();
-        let concat_fn_idx = builder.reserve_function_index();
-        let to_string_fn_idx = builder.reserve_function_index();
-        let to_bool_fn_idx = builder.reserve_function_index();
-        let to_int_fn_idx = builder.reserve_function_index();
-        let to_float_fn_idx = builder.reserve_function_index();
-        let int_to_string_fn_idx = builder.reserve_function_index();
-        let float_to_string_fn_idx = builder.reserve_function_index();
-        let equal_fn_idx = builder.reserve_function_index();
-        let not_equal_fn_idx = builder.reserve_function_index();
-        let identical_fn_idx = builder.reserve_function_index();
-        let not_identical_fn_idx = builder.reserve_function_index();
-        let greater_than_fn_idx = builder.reserve_function_index();
-        let less_than_fn_idx = builder.reserve_function_index();
-        let less_than_or_equal_fn_idx = builder.reserve_function_index();
-        let greater_than_or_equal_fn_idx = builder.reserve_function_index();
+        let add_fn_idx = reserve_helper!("add");
+        let subtract_fn_idx = reserve_helper!("subtract");
+        let multiply_fn_idx = reserve_helper!("multiply");
+        let divide_fn_idx = reserve_helper!("divide");
+        let modulo_fn_idx = reserve_helper!("modulo");
+        let concat_fn_idx = reserve_helper!("concat");
+        let to_string_fn_idx = reserve_helper!("to_string");
+        let to_bool_fn_idx = reserve_helper!("to_bool");
+        let to_int_fn_idx = reserve_helper!("to_int");
+        let to_float_fn_idx = reserve_helper!("to_float");
+        let int_to_string_fn_idx = reserve_helper!("int_to_string");
+        let float_to_string_fn_idx = reserve_helper!("float_to_string");
+        let equal_fn_idx = reserve_helper!("equal");
+        let not_equal_fn_idx = reserve_helper!("not_equal");
+        let identical_fn_idx = reserve_helper!("identical");
+        let not_identical_fn_idx = reserve_helper!("not_identical");
+        let greater_than_fn_idx = reserve_helper!("greater_than");
+        let less_than_fn_idx = reserve_helper!("less_than");
+        let less_than_or_equal_fn_idx = reserve_helper!("less_than_or_equal");
+        let greater_than_or_equal_fn_idx = reserve_helper!("greater_than_or_equal");
         
         // Reserve array function indices
-        let create_array_fn_idx = builder.reserve_function_index();
-        let array_get_fn_idx = builder.reserve_function_index();
-        let array_set_fn_idx = builder.reserve_function_index();
-        let count_fn_idx = builder.reserve_function_index();        // PHP count() function
-        let array_push_fn_idx = builder.reserve_function_index();
-        let array_pop_fn_idx = builder.reserve_function_index();
-        let array_shift_fn_idx = builder.reserve_function_index();
-        let array_unshift_fn_idx = builder.reserve_function_index();
-        let in_array_fn_idx = builder.reserve_function_index();
-        let array_keys_fn_idx = builder.reserve_function_index();
-        let array_values_fn_idx = builder.reserve_function_index();
-        let array_merge_fn_idx = builder.reserve_function_index();  // PHP array_merge() function
-        let array_slice_fn_idx = builder.reserve_function_index();  // PHP array_slice() function
+        let create_array_fn_idx = reserve_helper!("create_array");
+        let array_get_fn_idx = reserve_helper!("array_get");
+        let array_set_fn_idx = reserve_helper!("array_set");
+        let count_fn_idx = reserve_helper!("count");        // PHP count() function
+        let array_push_fn_idx = reserve_helper!("array_push");
+        let array_pop_fn_idx = reserve_helper!("array_pop");
+        let array_shift_fn_idx = reserve_helper!("array_shift");
+        let array_unshift_fn_idx = reserve_helper!("array_unshift");
+        let array_unset_fn_idx = reserve_helper!("array_unset");
+        let in_array_fn_idx = reserve_helper!("in_array");
+        let array_keys_fn_idx = reserve_helper!("array_keys");
+        let array_values_fn_idx = reserve_helper!("array_values");
+        let array_merge_fn_idx = reserve_helper!("array_merge");  // PHP array_merge() function
+        let array_slice_fn_idx = reserve_helper!("array_slice");  // PHP array_slice() function
+        let array_get_quiet_fn_idx = reserve_helper!("array_get_quiet");
+        let array_copy_fn_idx = reserve_helper!("array_copy");
+        let array_deoptimize_fn_idx = reserve_helper!("array_deoptimize");
+        let array_element_fn_idx = reserve_helper!("array_element");
+        let array_get_int_fn_idx = reserve_helper!("array_get_int");
+        let array_get_float_fn_idx = reserve_helper!("array_get_float");
+        let array_set_int_fn_idx = reserve_helper!("array_set_int");
+        let array_set_float_fn_idx = reserve_helper!("array_set_float");
+        let array_sum_fn_idx = reserve_helper!("array_sum");
+        let array_sort_fn_idx = reserve_helper!("array_sort");
+        let sort_less_fn_idx = reserve_helper!("sort_less");
+        let string_compare_fn_idx = reserve_helper!("string_compare");
         
         // Reserve hash array function indices (for associative arrays)
-        let create_hash_array_fn_idx = builder.reserve_function_index();
-        let hash_array_get_fn_idx = builder.reserve_function_index();
-        let hash_array_set_fn_idx = builder.reserve_function_index();
-        let hash_string_fn_idx = builder.reserve_function_index();
-        let key_to_string_fn_idx = builder.reserve_function_index();
-        let normalize_key_fn_idx = builder.reserve_function_index();
-        let string_to_int_if_numeric_fn_idx = builder.reserve_function_index();
-        let string_to_float_if_numeric_fn_idx = builder.reserve_function_index();
+        let create_hash_array_fn_idx = reserve_helper!("create_hash_array");
+        let hash_array_get_fn_idx = reserve_helper!("hash_array_get");
+        let hash_array_set_fn_idx = reserve_helper!("hash_array_set");
+        let hash_string_fn_idx = reserve_helper!("hash_string");
+        let key_to_string_fn_idx = reserve_helper!("key_to_string");
+        let normalize_key_fn_idx = reserve_helper!("normalize_key");
+        let string_to_int_if_numeric_fn_idx = reserve_helper!("string_to_int_if_numeric");
+        let string_to_float_if_numeric_fn_idx = reserve_helper!("string_to_float_if_numeric");
 
         // PHASE 3C: Reserve optimized array function indices
-        let fast_hash_array_get_fn_idx = builder.reserve_function_index();
-        let fast_hash_array_set_fn_idx = builder.reserve_function_index();
-        let fast_array_get_int_fn_idx = builder.reserve_function_index();
-        let fast_array_set_int_fn_idx = builder.reserve_function_index();
+        let fast_hash_array_get_fn_idx = reserve_helper!("fast_hash_array_get");
+        let fast_hash_array_set_fn_idx = reserve_helper!("fast_hash_array_set");
+        let fast_array_get_int_fn_idx = reserve_helper!("fast_array_get_int");
+        let fast_array_set_int_fn_idx = reserve_helper!("fast_array_set_int");
 
         // Reserve string operation function indices
-        let string_equals_fn_idx = builder.reserve_function_index();
+        let string_equals_fn_idx = reserve_helper!("string_equals");
+
+        // Reserve magic method helper indices
+        let object_to_string_fn_idx = reserve_helper!("object_to_string");
+        let clone_object_fn_idx = reserve_helper!("clone_object");
+
+        // Reserve type declaration helper indices
+        let debug_type_fn_idx = reserve_helper!("debug_type");
+        let coerce_value_fn_idx = reserve_helper!("coerce_value");
         
         Compiler {
             builder,
             current_function: None,
             variables: HashMap::new(),
             functions: HashMap::new(),
-            string_constants: HashMap::new(),
+            specialized_functions: HashMap::new(),
+            ir_listing: String::new(),
+            function_params: HashMap::new(),
             gc_types,
             classes: HashMap::new(),               // PHASE 5: Class storage
             next_class_id: 0,                      // PHASE 5: Class ID counter
+            interfaces: HashMap::new(),
+            current_class: None,
             type_inference: TypeInference::new(),
             loop_stack: Vec::new(),
             block_depth: 0,
@@ -305,6 +428,25 @@ impl Compiler {
             escape_analyzer: super::escape_analysis::EscapeAnalyzer::new(),
             string_intern_table: HashMap::new(),  // PHASE 3C: String interning
             next_intern_id: 0,                     // PHASE 3C: Counter for intern IDs
+            next_temp_var_id: 0,
+            global_variables: HashMap::new(),
+            static_variables: HashMap::new(),
+            static_scope: String::new(),
+            superglobals: HashMap::new(),
+            response: None,
+            output: None,
+            ob_callbacks: Vec::new(),
+            imported_functions,
+            memory_helpers: None,
+            strict_types: false,
+            return_type: None,
+            specialization: None,
+            inline_candidates: HashMap::new(),
+            inlining: Vec::new(),
+            pure_functions: HashSet::new(),
+            changed_arrays: HashSet::new(),
+            options,
+            sources: Vec::new(),
             print_fn_idx,
             create_null_fn_idx,
             create_bool_fn_idx,
@@ -340,11 +482,24 @@ impl Compiler {
             array_pop_fn_idx,
             array_shift_fn_idx,
             array_unshift_fn_idx,
+            array_unset_fn_idx,
             in_array_fn_idx,
             array_keys_fn_idx,
             array_values_fn_idx,
             array_merge_fn_idx,
             array_slice_fn_idx,
+            array_get_quiet_fn_idx,
+            array_copy_fn_idx,
+            array_deoptimize_fn_idx,
+            array_element_fn_idx,
+            array_get_int_fn_idx,
+            array_get_float_fn_idx,
+            array_set_int_fn_idx,
+            array_set_float_fn_idx,
+            array_sum_fn_idx,
+            array_sort_fn_idx,
+            sort_less_fn_idx,
+            string_compare_fn_idx,
             create_hash_array_fn_idx,
             hash_array_get_fn_idx,
             hash_array_set_fn_idx,
@@ -358,12 +513,17 @@ impl Compiler {
             fast_array_get_int_fn_idx,
             fast_array_set_int_fn_idx,
             string_equals_fn_idx,
+            object_to_string_fn_idx,
+            clone_object_fn_idx,
+            destructor_registry: None,
+            readonly_sentinel: None,
+            debug_type_fn_idx,
+            coerce_value_fn_idx,
             value_to_value_type_idx,
             values_to_value_type_idx,
             i32_to_value_type_idx,
             i64_to_value_type_idx,
             f64_to_value_type_idx,
-            print_type_idx,
         }
     }
     
@@ -519,6 +679,24 @@ impl Compiler {
         };
         let php_object = builder.add_struct_type(php_object_struct);
 
+        // Packed arrays whose elements type inference proved to be all ints,
+        // all floats or all strings; the PhpValue keeps their logical length
+        let php_int_array = builder.add_array_type(ArrayType(FieldType {
+            element_type: StorageType::Val(ValType::I64),
+            mutable: true,
+        }));
+        let php_float_array = builder.add_array_type(ArrayType(FieldType {
+            element_type: StorageType::Val(ValType::F64),
+            mutable: true,
+        }));
+        let php_string_array = builder.add_array_type(ArrayType(FieldType {
+            element_type: StorageType::Val(ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(php_string),
+            })),
+            mutable: true,
+        }));
+
         GcTypes {
             php_value,
             php_string,
@@ -527,28 +705,89 @@ impl Compiler {
             php_hash_array,
             php_hash_table,
             php_object,  // PHASE 5
+            php_int_array,
+            php_float_array,
+            php_string_array,
         }
     }
     
-    pub fn compile(mut self, source: &str) -> Result<Vec<u8>, CompilerError> {
-        let program = parse(source)?;
+    pub fn compile(self, source: &str) -> Result<Vec<u8>, CompilerError> {
+        Ok(self.compile_module("input.php", source)?.wasm)
+    }
+
+    /// Compile a program whose names are resolved and whose includes are linked
+    pub(super) fn compile_gc_program(mut self, program: Program) -> Result<CompiledModule, CompilerError> {
+        self.strict_types = Self::strict_types_declaration(&program)?;
 
         // Run type inference pass for optimization
+        self.type_inference.set_max_specializations(self.options.max_specializations());
         self.type_inference.analyze_program(&program);
 
         // PHASE 3A: Run escape analysis to determine which values can stay unboxed
-        self.escape_analyzer.analyze_program(&program);
+        if self.options.escape_analysis_enabled() {
+            self.escape_analyzer.analyze_program(&program);
+        }
+
+        // Output buffers, superglobals and the response state; the output
+        // helpers (print_value among them) depend on the latter
+        self.allocate_output_state();
+        self.allocate_http_state(&program)?;
+        self.add_output_functions()?;
 
         // Add runtime operation functions
         self.add_runtime_functions();
 
+        // Top-level variables used through `global` / `$GLOBALS` live in wasm globals
+        self.allocate_global_variables(&program);
+
         // PHASE 5: Four-pass compilation for classes, functions, and main code
-        // Pass 1a: Register all class metadata (properties only)
+        // Pass 0: Register interfaces, which classes and enums are checked against
+        for item in &program.items {
+            if let ProgramItem::PhpBlock { statements } = item {
+                for stmt in statements {
+                    if let Statement::Interface { name, members } = stmt {
+                        self.register_interface(name, members);
+                    }
+                }
+            }
+        }
+
+        // Pass 1a: Register all class and enum metadata (properties only)
         for item in &program.items {
             if let ProgramItem::PhpBlock { statements } = item {
                 for stmt in statements {
-                    if let Statement::Class { name, extends, implements, members } = stmt {
-                        self.register_class_metadata(name, members)?;
+                    match stmt {
+                        // `final` needs no checks while classes cannot extend one another
+                        Statement::Class { name, is_final: _, is_readonly, extends: _, implements, members } => {
+                            self.register_class_metadata(name, *is_readonly, implements, members)?;
+                        }
+                        Statement::Enum { name, backing_type, implements, members } => {
+                            self.register_enum_metadata(name, backing_type.as_ref(), implements, members)?;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // Helpers that dispatch on class_id need the full set of classes
+        self.add_magic_method_functions()?;
+        self.add_type_check_functions()?;
+        self.allocate_destructor_registry();
+
+        for item in &program.items {
+            if let ProgramItem::PhpBlock { statements } = item {
+                self.collect_inline_candidates(statements);
+            }
+        }
+
+        // Functions get their indices up front, so that methods and code above a
+        // declaration can call them
+        for item in &program.items {
+            if let ProgramItem::PhpBlock { statements } = item {
+                for stmt in statements {
+                    if let Statement::Function { name, params, .. } = stmt {
+                        self.declare_function(name, params)?;
                     }
                 }
             }
@@ -558,8 +797,10 @@ impl Compiler {
         for item in &program.items {
             if let ProgramItem::PhpBlock { statements } = item {
                 for stmt in statements {
-                    if let Statement::Class { name, extends, implements, members } = stmt {
-                        self.compile_class_methods(name, members)?;
+                    match stmt {
+                        Statement::Class { name, members, .. } => self.compile_class_methods(name, members)?,
+                        Statement::Enum { name, members, .. } => self.compile_enum_methods(name, members)?,
+                        _ => {}
                     }
                 }
             }
@@ -569,8 +810,8 @@ impl Compiler {
         for item in &program.items {
             if let ProgramItem::PhpBlock { statements } = item {
                 for stmt in statements {
-                    if let Statement::Function { name, params, body, return_type: _ } = stmt {
-                        self.compile_function_definition(name, params, body.clone())?;
+                    if let Statement::Function { name, params, body, return_type } = stmt {
+                        self.compile_function_definition(name, params, return_type.as_ref(), body.clone())?;
                     }
                 }
             }
@@ -586,15 +827,33 @@ impl Compiler {
             locals: vec![],
             body: vec![],
             local_count: 0,
+            locations: Vec::new(),
         });
 
+        self.init_output_buffer()?;
+        self.bind_global_variables();
+        self.bind_superglobals();
+        self.init_superglobals();
+        self.init_enum_cases()?;
+        self.init_static_properties()?;
+        self.init_destructor_registry();
+
         // Pass 3: Compile all program items (non-class, non-function statements)
+        let top_level: Vec<Statement> = program.items.iter()
+            .filter_map(|item| match item {
+                ProgramItem::PhpBlock { statements } => Some(statements),
+                ProgramItem::InlineContent(_) => None,
+            })
+            .flatten()
+            .cloned()
+            .collect();
+        self.changed_arrays = super::array_copies::ChangedArrays::of(&top_level);
         for item in program.items {
             match item {
                 ProgramItem::PhpBlock { statements } => {
                     for stmt in statements {
-                        // Skip class and function definitions - already compiled in passes 1 and 2
-                        if !matches!(stmt, Statement::Class { .. } | Statement::Function { .. }) {
+                        // Skip declarations - already compiled in passes 0 to 2
+                        if !matches!(stmt, Statement::Class { .. } | Statement::Function { .. } | Statement::Enum { .. } | Statement::Interface { .. }) {
                             self.compile_statement(stmt)?;
                         }
                     }
@@ -605,37 +864,70 @@ impl Compiler {
             }
         }
         
+        // Objects with a destructor are destroyed when the script ends
+        self.run_destructors();
+        self.emit_output_shutdown()?;
+
         // Finish main function
-        if let Some(func) = self.current_function.take() {
-            let main_idx = self.builder.reserve_function_index();
-            self.builder.set_function_at_index(main_idx, main_type, func.locals, func.body);
+        let main_idx = self.builder.reserve_function_index();
+        self.name_function(main_idx, "{main}");
+        self.name_locals(main_idx);
+        self.finish_function(main_idx, main_type);
+        if self.options.http_handler_enabled() {
+            self.add_request_handler(main_idx)?;
+        } else {
             self.builder.add_export("_start", ExportKind::Func, main_idx);
         }
+        self.export_output_flush()?;
+        self.export_extern_buffer();
+        self.add_ob_callback_function()?;
         
         // Memory is still needed for print function
+        let (initial_pages, max_pages) = self.options.memory_pages();
         self.builder.add_memory(MemoryType {
-            minimum: 1,
-            maximum: None,
+            minimum: initial_pages as u64,
+            maximum: max_pages.map(u64::from),
             memory64: false,
             shared: false,
             page_size_log2: None,
         });
         self.builder.add_export("memory", ExportKind::Memory, 0);
         
-        Ok(self.builder.build())
+        if let Some(url) = self.options.get_source_map_url() {
+            self.builder.set_source_map_url(url);
+        }
+        let source_map_enabled = self.options.source_map_enabled();
+        let (wasm, locations) = self.builder.build_with_locations();
+        let source_map = source_map_enabled.then(|| source_map(&self.sources, &locations));
+        let type_report = self.options.type_report_enabled().then(|| self.type_inference.report());
+        let ir = self.options.ir_dump_enabled().then(|| std::mem::take(&mut self.ir_listing));
+        Ok(CompiledModule { wasm, source_map, type_report, ir })
     }
     
     fn compile_inline_content(&mut self, content: &str) -> Result<(), String> {
-        // For now, create a temporary string in memory for printing
-        // In a full implementation, we'd convert the GC string to linear memory
-        let offset = 0x1000; // Temporary location
-        self.builder.add_data(offset, content.as_bytes().to_vec());
-        
-        self.emit(Instruction::I32Const(offset as i32));
-        self.emit(Instruction::Call(self.print_fn_idx));
+        // Inline HTML is echoed like any other string
+        self.compile_string_literal(content)?;
+        self.emit(Instruction::Call(self.print_value_fn_idx));
         Ok(())
     }
     
+    /// Abort with an uncaught error, as PHP does for an exception nobody catches.
+    /// Expects the message (a PhpValue) on the stack.
+    pub(super) fn emit_uncaught_error(&mut self, error_class: &str) -> Result<(), String> {
+        let message_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalSet(message_local));
+
+        self.compile_string_literal(&format!("PHP Fatal error:  Uncaught {}: ", error_class))?;
+        self.emit(Instruction::LocalGet(message_local));
+        self.emit(Instruction::Call(self.concat_fn_idx));
+        self.compile_string_literal("\n")?;
+        self.emit(Instruction::Call(self.concat_fn_idx));
+        self.emit(Instruction::Call(self.print_value_fn_idx));
+        self.emit_output_flush_all()?;
+        self.emit(Instruction::Unreachable);
+        Ok(())
+    }
+
     pub(super) fn emit(&mut self, instruction: Instruction<'static>) {
         if let Some(func) = &mut self.current_function {
             func.body.push(instruction);
@@ -668,10 +960,56 @@ impl Compiler {
         let type_key = Self::val_type_to_key(&val_type);
         self.free_locals
             .entry(type_key)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(idx);
     }
 
+    /// Load a variable's stored value (unboxed locals push their raw i64/f64)
+    pub(super) fn emit_variable_get(&mut self, var_info: &VariableInfo) {
+        match var_info.storage_type {
+            VariableStorage::Global(global_idx) => self.emit(Instruction::GlobalGet(global_idx)),
+            _ => self.emit(Instruction::LocalGet(var_info.local_idx)),
+        }
+    }
+
+    /// Store the value on the stack into a variable's storage
+    pub(super) fn emit_variable_set(&mut self, var_info: &VariableInfo) {
+        match var_info.storage_type {
+            VariableStorage::Global(global_idx) => self.emit(Instruction::GlobalSet(global_idx)),
+            _ => self.emit(Instruction::LocalSet(var_info.local_idx)),
+        }
+    }
+
+    /// Store the value on the stack into a variable's storage, leaving it on the stack
+    pub(super) fn emit_variable_tee(&mut self, var_info: &VariableInfo) {
+        match var_info.storage_type {
+            VariableStorage::Global(global_idx) => {
+                self.emit(Instruction::GlobalSet(global_idx));
+                self.emit(Instruction::GlobalGet(global_idx));
+            }
+            _ => self.emit(Instruction::LocalTee(var_info.local_idx)),
+        }
+    }
+
+    /// Bind a boxed PhpValue local to a fresh compiler temporary so it can be
+    /// referenced as `Expression::Variable` when desugaring. Callers remove it from
+    /// `variables` once the desugared expression has been compiled.
+    pub(super) fn bind_temp_variable(&mut self, local_idx: u32, class_type: Option<String>) -> String {
+        let name = self.next_temp_name();
+        self.variables.insert(name.clone(), VariableInfo {
+            local_idx,
+            storage_type: VariableStorage::Boxed,
+            class_type,
+        });
+        name
+    }
+
+    pub(super) fn next_temp_name(&mut self) -> String {
+        let name = format!("#tmp{}", self.next_temp_var_id);
+        self.next_temp_var_id += 1;
+        name
+    }
+
     /// Convert ValType to a string key for the free_locals HashMap
     fn val_type_to_key(val_type: &ValType) -> String {
         match val_type {
@@ -679,7 +1017,7 @@ impl Compiler {
             ValType::I64 => "i64".to_string(),
             ValType::F32 => "f32".to_string(),
             ValType::F64 => "f64".to_string(),
-            ValType::Ref(_) => "ref".to_string(),  // All refs treated the same for now
+            ValType::Ref(ref_type) => format!("{:?}", ref_type),  // A local only fits its own reference type
             _ => "other".to_string(),
         }
     }
@@ -0,0 +1,229 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Debugging metadata: the `name` section and source maps
+//
+// With debug info on, every function the module keeps is named - user
+// functions by their PHP name, methods as `Class::method`, runtime helpers as
+// `php_<helper>` - and PHP variables name the locals they live in.
+//
+// Source maps need to know where statements start, so sources are parsed with
+// `Statement::Location` markers in front of every statement. Compiling a
+// marker ties the next instruction of the current function to that location;
+// the builder turns instruction positions into module offsets, which are the
+// generated "columns" of a WebAssembly source map (v3, on a single line).
+
+use super::core::*;
+use crate::error::CompilerError;
+use edge_php_parser::ast::*;
+
+/// A compiled module and, when requested, its source map (JSON), type report
+/// and mid-level IR
+#[derive(Debug, Clone)]
+pub struct CompiledModule {
+    pub wasm: Vec<u8>,
+    pub source_map: Option<String>,
+    pub type_report: Option<String>,
+    pub ir: Option<String>,
+}
+
+impl Compiler {
+    /// Compile a single source to a module and its source map. `name` is the
+    /// file name the source map lists for it.
+    pub fn compile_module(mut self, name: &str, source: &str) -> Result<CompiledModule, CompilerError> {
+        let mut program = if self.options.source_map_enabled() {
+            edge_php_parser::parse_with_locations(source)?
+        } else {
+            edge_php_parser::parse(source)?
+        };
+        Self::resolve_names(&mut program)?;
+        self.sources.push((name.to_string(), source.to_string()));
+        self.compile_with_backend(program)
+    }
+
+    /// Record a function's PHP name for the `name` section when debug info is on
+    pub(super) fn name_function(&mut self, function_idx: u32, name: &str) {
+        if self.options.debug_info_enabled() {
+            self.builder.set_function_name(function_idx, name);
+        }
+    }
+
+    /// Name the locals of the function being compiled after its PHP variables
+    pub(super) fn name_locals(&mut self, function_idx: u32) {
+        if !self.options.debug_info_enabled() {
+            return;
+        }
+        let names = self.variables.iter()
+            // Compiler temporaries start with '#'; globals do not live in locals
+            .filter(|(name, info)| !name.starts_with('#') && !matches!(info.storage_type, VariableStorage::Global(_)))
+            .map(|(name, info)| (info.local_idx, format!("${}", name)))
+            .collect();
+        self.builder.set_local_names(function_idx, names);
+    }
+
+    /// Hand the function being compiled to the builder, with its source locations
+    pub(super) fn finish_function(&mut self, function_idx: u32, type_idx: u32) {
+        let func_ctx = self.current_function.take().expect("a function is being compiled");
+        if !func_ctx.locations.is_empty() {
+            self.builder.set_source_locations(function_idx, func_ctx.locations);
+        }
+        self.builder.set_function_at_index(function_idx, type_idx, func_ctx.locals, func_ctx.body);
+    }
+
+    /// The code emitted next comes from `location`
+    pub(super) fn record_location(&mut self, location: SourceLocation) {
+        if let Some(func) = &mut self.current_function {
+            let position = func.body.len();
+            // A statement that emitted nothing (e.g. a declaration) gives way to the next one
+            if func.locations.last().is_some_and(|(start, _)| *start == position) {
+                func.locations.pop();
+            }
+            func.locations.push((position, location));
+        }
+    }
+}
+
+/// The source map of `sources` for the code offsets the builder reported
+pub(crate) fn source_map(sources: &[(String, String)], locations: &[(usize, SourceLocation)]) -> String {
+    let mut mappings = String::new();
+    let (mut offset, mut file, mut line, mut column) = (0i64, 0i64, 0i64, 0i64);
+    for (position, (code_offset, location)) in locations.iter().enumerate() {
+        if position > 0 {
+            mappings.push(',');
+        }
+        // Source map lines and columns count from 0
+        let fields = [
+            *code_offset as i64 - offset,
+            location.file as i64 - file,
+            location.line as i64 - 1 - line,
+            location.column as i64 - 1 - column,
+        ];
+        for field in fields {
+            encode_vlq(field, &mut mappings);
+        }
+        offset = *code_offset as i64;
+        file = location.file as i64;
+        line = location.line as i64 - 1;
+        column = location.column as i64 - 1;
+    }
+
+    serde_json::json!({
+        "version": 3,
+        "sources": sources.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+        "sourcesContent": sources.iter().map(|(_, content)| content).collect::<Vec<_>>(),
+        "names": [],
+        "mappings": mappings,
+    })
+    .to_string()
+}
+
+/// Append `value` as a base64 VLQ
+fn encode_vlq(value: i64, out: &mut String) {
+    const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut vlq = (if value < 0 { ((-value) << 1) | 1 } else { value << 1 }) as u64;
+    loop {
+        let mut digit = (vlq & 0b11111) as usize;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Compiler, CompilerOptions};
+    use std::collections::HashMap;
+
+    const SOURCE: &str = "<?php
+function square($x) {
+    return $x * $x;
+}
+function greet($name) {
+    echo \"hi \", $name, \"\\n\";
+}
+greet(\"ada\");
+echo square(3);
+";
+
+    /// Source map segments as absolute [code offset, file, line, column]
+    fn decode_mappings(mappings: &str) -> Vec<[i64; 4]> {
+        const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut state = [0i64; 4];
+        mappings.split(',').map(|segment| {
+            let (mut value, mut shift, mut field) = (0i64, 0, 0);
+            for byte in segment.bytes() {
+                let digit = BASE64.iter().position(|&b| b == byte).unwrap() as i64;
+                value |= (digit & 0b11111) << shift;
+                if digit & 0b100000 != 0 {
+                    shift += 5;
+                    continue;
+                }
+                state[field] += if value & 1 == 1 { -(value >> 1) } else { value >> 1 };
+                (value, shift, field) = (0, 0, field + 1);
+            }
+            state
+        }).collect()
+    }
+
+    #[test]
+    fn test_name_section_and_source_map() {
+        let options = CompilerOptions::new().debug_info(true).source_map(true).inlining(false);
+        let module = Compiler::with_options(options).compile_module("square.php", SOURCE).unwrap();
+
+        // Function and local names, and where each function's code is
+        let mut function_names = HashMap::new();
+        let mut local_names: HashMap<u32, Vec<String>> = HashMap::new();
+        let mut bodies = Vec::new();
+        let mut imports = 0;
+        for payload in wasmparser::Parser::new(0).parse_all(&module.wasm) {
+            match payload.unwrap() {
+                wasmparser::Payload::ImportSection(reader) => imports = reader.count(),
+                wasmparser::Payload::CodeSectionEntry(body) => bodies.push(body.range()),
+                wasmparser::Payload::CustomSection(section) => {
+                    if let wasmparser::KnownCustom::Name(reader) = section.as_known() {
+                        for name in reader {
+                            match name.unwrap() {
+                                wasmparser::Name::Function(map) => {
+                                    for naming in map {
+                                        let naming = naming.unwrap();
+                                        function_names.insert(naming.name.to_string(), naming.index);
+                                    }
+                                }
+                                wasmparser::Name::Local(map) => {
+                                    for function in map {
+                                        let function = function.unwrap();
+                                        let names = function.names.into_iter().map(|naming| naming.unwrap().name.to_string());
+                                        local_names.insert(function.index, names.collect());
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for name in ["square", "greet", "{main}", "php_print_value", "env.print"] {
+            assert!(function_names.contains_key(name), "{} missing from {:?}", name, function_names);
+        }
+        assert!(local_names[&function_names["square"]].contains(&"$x".to_string()));
+        assert!(local_names[&function_names["greet"]].contains(&"$name".to_string()));
+
+        // `return $x * $x;` (line 3, column 5) maps into square's code
+        let map: serde_json::Value = serde_json::from_str(&module.source_map.unwrap()).unwrap();
+        assert_eq!(map["version"], 3);
+        assert_eq!(map["sources"], serde_json::json!(["square.php"]));
+        let segments = decode_mappings(map["mappings"].as_str().unwrap());
+        let [offset, file, _, _] = *segments.iter().find(|segment| segment[2..] == [2, 4]).unwrap();
+        assert_eq!(file, 0);
+        let square = &bodies[(function_names["square"] - imports) as usize];
+        assert!(square.contains(&(offset as usize)), "offset {} outside {:?}", offset, square);
+        assert!(segments.iter().any(|segment| segment[2] == 7), "no mapping for greet(\"ada\")");
+    }
+}
@@ -0,0 +1,452 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Enums (PHP 8.1): pure and backed
+//
+// An enum is registered as a class whose cases are singleton objects. Each case
+// object is created once at the start of `_start` and kept in a wasm global, so
+// `Suit::Hearts` is a global.get and `===` compares object identity. Case objects
+// carry the read-only `name` property, and `value` for backed enums. Backed enums
+// get generated `from()`/`tryFrom()` functions that search the cases.
+
+use super::core::*;
+use edge_php_parser::ast::*;
+use wasm_encoder::*;
+use std::collections::HashSet;
+
+/// Enum part of a ClassInfo
+#[derive(Debug, Clone)]
+pub struct EnumInfo {
+    pub backing_type: Option<Type>,  // Type::Int or Type::String for backed enums
+    pub cases: Vec<EnumCaseInfo>,    // Declaration order, as returned by cases()
+    pub from_fn_idx: Option<u32>,
+    pub try_from_fn_idx: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumCaseInfo {
+    pub name: String,
+    pub value: Option<Literal>,  // Literal::Integer or Literal::String for backed enums
+    pub global_idx: u32,         // Holds the case singleton
+}
+
+const ENUM_NAME_PROPERTY: u32 = 0;
+const ENUM_VALUE_PROPERTY: u32 = 1;
+
+impl Compiler {
+    /// Register an enum: methods and constants like a class, plus its cases
+    pub(super) fn register_enum_metadata(&mut self, name: &str, backing_type: Option<&Type>, implements: &[String], members: &[ClassMember]) -> Result<(), String> {
+        if let Some(backing_type) = backing_type {
+            if !matches!(backing_type, Type::Int | Type::String) {
+                return Err(format!(
+                    "Enum backing type must be int or string, {} given",
+                    super::types::type_name(backing_type)
+                ));
+            }
+        }
+
+        for member in members {
+            match member {
+                ClassMember::Property { .. } => {
+                    return Err(format!("Enum {} cannot include properties", name));
+                }
+                ClassMember::Constructor { .. } => {
+                    return Err(format!("Enum {} cannot include magic method __construct", name));
+                }
+                ClassMember::Method { name: method_name, .. } if method_name.eq_ignore_ascii_case("__construct") => {
+                    return Err(format!("Enum {} cannot include magic method __construct", name));
+                }
+                _ => {}
+            }
+        }
+
+        let cases = self.collect_enum_cases(name, backing_type, members)?;
+
+        self.check_interface_methods("Enum", name, implements, members)?;
+        self.register_class_metadata(name, false, implements, members)?;
+
+        let mut properties = vec![super::classes::PropertyInfo {
+            name: "name".to_string(),
+            index: ENUM_NAME_PROPERTY,
+            visibility: Visibility::Public,
+            default_value: None,
+            property_type: Some(Type::String),
+            is_readonly: false,  // Always initialised, and writes to enums are rejected outright
+        }];
+        if backing_type.is_some() {
+            properties.push(super::classes::PropertyInfo {
+                name: "value".to_string(),
+                index: ENUM_VALUE_PROPERTY,
+                visibility: Visibility::Public,
+                default_value: None,
+                property_type: backing_type.cloned(),
+                is_readonly: false,
+            });
+        }
+
+        let (from_fn_idx, try_from_fn_idx) = if backing_type.is_some() {
+            let from_fn_idx = self.builder.reserve_function_index();
+            let try_from_fn_idx = self.builder.reserve_function_index();
+            self.name_function(from_fn_idx, &format!("{}::from", name));
+            self.name_function(try_from_fn_idx, &format!("{}::tryFrom", name));
+            (Some(from_fn_idx), Some(try_from_fn_idx))
+        } else {
+            (None, None)
+        };
+
+        let class_info = self.classes.get_mut(name)
+            .ok_or_else(|| format!("Enum {} was not registered", name))?;
+        if let Some(case) = cases.iter().find(|case| class_info.constants.contains_key(&case.name)) {
+            return Err(format!("Cannot redefine class constant {}::{}", name, case.name));
+        }
+        class_info.properties = properties;
+        class_info.enum_info = Some(EnumInfo {
+            backing_type: backing_type.cloned(),
+            cases,
+            from_fn_idx,
+            try_from_fn_idx,
+        });
+
+        Ok(())
+    }
+
+    /// Validate the cases of an enum and allocate a global for each singleton
+    fn collect_enum_cases(&mut self, name: &str, backing_type: Option<&Type>, members: &[ClassMember]) -> Result<Vec<EnumCaseInfo>, String> {
+        let mut cases: Vec<EnumCaseInfo> = Vec::new();
+        let mut names = HashSet::new();
+
+        for member in members {
+            let ClassMember::Case { name: case_name, value } = member else {
+                continue;
+            };
+            if !names.insert(case_name.clone()) {
+                return Err(format!("Cannot redefine class constant {}::{}", name, case_name));
+            }
+
+            let value = match (backing_type, value) {
+                (None, None) => None,
+                (None, Some(_)) => {
+                    return Err(format!("Case {} of non-backed enum {} must not have a value", case_name, name));
+                }
+                (Some(_), None) => {
+                    return Err(format!("Case {} of backed enum {} must have a value", case_name, name));
+                }
+                (Some(backing_type), Some(expr)) => {
+                    let literal = Self::enum_case_literal(expr)
+                        .ok_or_else(|| format!("Enum case value of {}::{} must be a literal", name, case_name))?;
+                    let matches_backing_type = matches!(
+                        (backing_type, &literal),
+                        (Type::Int, Literal::Integer(_)) | (Type::String, Literal::String(_))
+                    );
+                    if !matches_backing_type {
+                        let expected = if *backing_type == Type::Int { "int" } else { "string" };
+                        return Err(format!("Enum case type {} does not match enum backing type {}",
+                            Self::literal_type_name(&literal), expected));
+                    }
+                    if let Some(duplicate) = cases.iter().find(|case| case.value.as_ref() == Some(&literal)) {
+                        return Err(format!("Duplicate value in enum {} for cases {} and {}", name, duplicate.name, case_name));
+                    }
+                    Some(literal)
+                }
+            };
+
+            let global_idx = self.add_php_value_global();
+
+            cases.push(EnumCaseInfo {
+                name: case_name.clone(),
+                value,
+                global_idx,
+            });
+        }
+
+        Ok(cases)
+    }
+
+    /// Backing values are constant expressions; only (negated) literals are supported
+    fn enum_case_literal(expr: &Expression) -> Option<Literal> {
+        match expr {
+            Expression::Literal(Literal::InterpolatedString(_)) => None,
+            Expression::Literal(literal) => Some(literal.clone()),
+            Expression::Unary { op: UnaryOp::Negate, expr } => match expr.as_ref() {
+                Expression::Literal(Literal::Integer(n)) => Some(Literal::Integer(-n)),
+                Expression::Literal(Literal::Float(f)) => Some(Literal::Float(-f)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn literal_type_name(literal: &Literal) -> &'static str {
+        match literal {
+            Literal::Integer(_) => "int",
+            Literal::Float(_) => "float",
+            Literal::String(_) | Literal::InterpolatedString(_) => "string",
+            Literal::Boolean(_) => "bool",
+            Literal::Null => "null",
+        }
+    }
+
+    /// Compile enum methods, then the generated from()/tryFrom() of backed enums
+    pub(super) fn compile_enum_methods(&mut self, name: &str, members: &[ClassMember]) -> Result<(), String> {
+        self.compile_class_methods(name, members)?;
+
+        let enum_info = self.enum_info(name)?.clone();
+        if let (Some(from_fn_idx), Some(try_from_fn_idx)) = (enum_info.from_fn_idx, enum_info.try_from_fn_idx) {
+            self.compile_enum_from(name, &enum_info, from_fn_idx, false)?;
+            self.compile_enum_from(name, &enum_info, try_from_fn_idx, true)?;
+        }
+        Ok(())
+    }
+
+    /// from($value) / tryFrom($value): the case with a matching backing value.
+    /// No match is an uncaught ValueError for from() and null for tryFrom().
+    fn compile_enum_from(&mut self, name: &str, enum_info: &EnumInfo, func_idx: u32, is_try: bool) -> Result<(), String> {
+        let php_value_type = self.get_php_value_type();
+        let func_type = self.builder.add_type(vec![php_value_type], vec![php_value_type]);
+
+        let saved_function = self.current_function.take();
+        let saved_block_depth = self.block_depth;
+        self.current_function = Some(FunctionContext {
+            locals: vec![],
+            body: vec![],
+            local_count: 1,
+            locations: Vec::new(),
+        });
+        self.block_depth = 0;
+        self.free_locals.clear();
+
+        let is_int = enum_info.backing_type == Some(Type::Int);
+
+        // Coerce the argument to the backing type once
+        let key_local = if is_int {
+            let key_local = self.allocate_local(ValType::I64);
+            self.emit(Instruction::LocalGet(0));
+            self.emit(Instruction::Call(self.to_int_fn_idx));
+            self.emit(Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            });
+            self.emit(Instruction::LocalSet(key_local));
+            key_local
+        } else {
+            let key_local = self.allocate_local(php_value_type);
+            self.emit(Instruction::LocalGet(0));
+            self.emit(Instruction::Call(self.to_string_fn_idx));
+            self.emit(Instruction::LocalSet(key_local));
+            key_local
+        };
+
+        for case in &enum_info.cases {
+            match &case.value {
+                Some(Literal::Integer(n)) => {
+                    self.emit(Instruction::LocalGet(key_local));
+                    self.emit(Instruction::I64Const(*n));
+                    self.emit(Instruction::I64Eq);
+                }
+                Some(Literal::String(s)) => {
+                    self.emit(Instruction::LocalGet(key_local));
+                    self.compile_string_literal(s)?;
+                    self.emit(Instruction::Call(self.string_equals_fn_idx));
+                    self.emit(Instruction::StructGet {
+                        struct_type_index: self.gc_types.php_value,
+                        field_index: PHPVALUE_INT,
+                    });
+                    self.emit(Instruction::I32WrapI64);
+                }
+                _ => continue,
+            }
+            self.emit(Instruction::If(BlockType::Empty));
+            self.emit(Instruction::GlobalGet(case.global_idx));
+            self.emit(Instruction::Return);
+            self.emit(Instruction::End);
+        }
+
+        if is_try {
+            self.emit(Instruction::Call(self.create_null_fn_idx));
+        } else {
+            // 5 is not a valid backing value for enum Suit / "X" is not a valid ...
+            let quote = if is_int { "" } else { "\"" };
+            self.compile_string_literal(quote)?;
+            self.emit(Instruction::LocalGet(0));
+            self.emit(Instruction::Call(self.to_string_fn_idx));
+            self.emit(Instruction::Call(self.concat_fn_idx));
+            self.compile_string_literal(&format!("{} is not a valid backing value for enum {}", quote, name))?;
+            self.emit(Instruction::Call(self.concat_fn_idx));
+            self.emit_uncaught_error("ValueError")?;
+        }
+
+        self.finish_function(func_idx, func_type);
+
+        self.current_function = saved_function;
+        self.block_depth = saved_block_depth;
+        Ok(())
+    }
+
+    /// Create the case singletons at the start of `_start`
+    pub(super) fn init_enum_cases(&mut self) -> Result<(), String> {
+        let mut enums: Vec<(u32, EnumInfo)> = self.classes.values()
+            .filter_map(|class| class.enum_info.clone().map(|info| (class.class_id, info)))
+            .collect();
+        enums.sort_by_key(|(class_id, _)| *class_id);
+
+        for (class_id, enum_info) in enums {
+            let property_count = if enum_info.backing_type.is_some() { 2 } else { 1 };
+
+            for case in &enum_info.cases {
+                let props_local = self.allocate_local(ValType::Ref(RefType {
+                    nullable: true,
+                    heap_type: HeapType::Concrete(self.gc_types.php_array),
+                }));
+                self.emit(Instruction::Call(self.create_null_fn_idx));
+                self.emit(Instruction::I32Const(property_count));
+                self.emit(Instruction::ArrayNew(self.gc_types.php_array));
+                self.emit(Instruction::LocalSet(props_local));
+
+                self.emit(Instruction::LocalGet(props_local));
+                self.emit(Instruction::I32Const(ENUM_NAME_PROPERTY as i32));
+                self.compile_string_literal(&case.name)?;
+                self.emit(Instruction::ArraySet(self.gc_types.php_array));
+
+                if let Some(value) = &case.value {
+                    self.emit(Instruction::LocalGet(props_local));
+                    self.emit(Instruction::I32Const(ENUM_VALUE_PROPERTY as i32));
+                    self.compile_expression(Expression::Literal(value.clone()))?;
+                    self.emit(Instruction::ArraySet(self.gc_types.php_array));
+                }
+
+                // Wrap the object in a PhpValue
+                self.emit(Instruction::I32Const(TYPE_OBJECT as i32));
+                self.emit(Instruction::I64Const(0));
+                self.emit(Instruction::F64Const(0.0.into()));
+                self.emit(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string)));
+                self.emit(Instruction::I32Const(class_id as i32));
+                self.emit(Instruction::LocalGet(props_local));
+                self.emit(Instruction::StructNew(self.gc_types.php_object));
+                self.emit(Instruction::StructNew(self.gc_types.php_value));
+                self.emit(Instruction::GlobalSet(case.global_idx));
+            }
+        }
+        Ok(())
+    }
+
+    fn enum_info(&self, name: &str) -> Result<&EnumInfo, String> {
+        self.classes.get(name)
+            .and_then(|class| class.enum_info.as_ref())
+            .ok_or_else(|| format!("{} is not an enum", name))
+    }
+
+    /// `self` refers to the class whose method is being compiled. Classes cannot be
+    /// extended, so `static` always names that class too.
+    pub(super) fn resolve_class_name(&self, class: &str) -> Result<String, String> {
+        if class.eq_ignore_ascii_case("self") || class.eq_ignore_ascii_case("static") {
+            self.current_class.clone()
+                .ok_or_else(|| format!("Cannot use \"{}\" when no class scope is active", class))
+        } else {
+            Ok(class.to_string())
+        }
+    }
+
+    /// The enum of `Enum::Case`, when it names a case
+    pub(super) fn enum_case_class(&self, class: &str, name: &str) -> Option<String> {
+        let class = self.resolve_class_name(class).ok()?;
+        let enum_info = self.classes.get(&class)?.enum_info.as_ref()?;
+        enum_info.cases.iter().any(|case| case.name == name).then_some(class)
+    }
+
+    /// `Class::NAME`: an enum case, a class or interface constant, or `Class::class`
+    pub(super) fn compile_class_constant(&mut self, class: &str, name: &str) -> Result<(), String> {
+        let class = self.resolve_class_name(class)?;
+
+        if name == "class" {
+            return self.compile_string_literal(&class);
+        }
+
+        if let Some(class_info) = self.classes.get(&class) {
+            if let Some(case) = class_info.enum_info.as_ref().and_then(|e| e.cases.iter().find(|c| c.name == name)) {
+                self.emit(Instruction::GlobalGet(case.global_idx));
+                return Ok(());
+            }
+
+            // Constants of the class itself, then of the interfaces it implements
+            let constant = class_info.constants.get(name).cloned().or_else(|| {
+                class_info.interfaces.iter()
+                    .find_map(|interface| self.interfaces.get(interface)?.constants.get(name).cloned())
+            });
+            if let Some(value) = constant {
+                return self.compile_constant_expression(&class, value);
+            }
+        } else if let Some(value) = self.interfaces.get(&class).and_then(|i| i.constants.get(name).cloned()) {
+            return self.compile_constant_expression(&class, value);
+        }
+
+        Err(format!("Undefined constant {}::{}", class, name))
+    }
+
+    /// Constant expressions are compiled where they are used, in the scope of their class
+    pub(super) fn compile_constant_expression(&mut self, class: &str, value: Expression) -> Result<(), String> {
+        let saved_class = self.current_class.replace(class.to_string());
+        let result = self.compile_expression(value);
+        self.current_class = saved_class;
+        result
+    }
+
+    /// `Class::method(...)`: enum built-ins, or a method called without an object
+    pub(super) fn compile_static_method_call(&mut self, class: &str, method: &str, args: Vec<Expression>) -> Result<(), String> {
+        let class = self.resolve_class_name(class)?;
+        let Some(class_info) = self.classes.get(&class).cloned() else {
+            // Static methods of extension classes are imported as `Class::method`
+            if self.compile_imported_call(&format!("{}::{}", class, method), args)? {
+                return Ok(());
+            }
+            return Err(format!("Class \"{}\" not found", class));
+        };
+
+        if let Some(enum_info) = &class_info.enum_info {
+            match (method, enum_info.from_fn_idx, enum_info.try_from_fn_idx) {
+                ("cases", _, _) => {
+                    for arg in args {
+                        self.compile_expression(arg)?;
+                        self.emit(Instruction::Drop);
+                    }
+                    let cases = enum_info.cases.iter()
+                        .map(|case| ArrayElement {
+                            key: None,
+                            value: Expression::ClassConstant { class: class.clone(), name: case.name.clone() },
+                        })
+                        .collect();
+                    return self.compile_array_literal(cases);
+                }
+                ("from", Some(fn_idx), _) | ("tryFrom", _, Some(fn_idx)) => {
+                    let mut args = args.into_iter();
+                    let value = args.next()
+                        .ok_or_else(|| format!("{}::{}() expects exactly 1 argument, 0 given", class, method))?;
+                    self.compile_expression(value)?;
+                    self.emit(Instruction::Call(fn_idx));
+                    for extra in args {
+                        self.compile_expression(extra)?;
+                        self.emit(Instruction::Drop);
+                    }
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        let Some(method_info) = class_info.methods.get(method).cloned() else {
+            // Undefined method: __callStatic($name, $arguments)
+            let call_hook = class_info.methods.get("__callStatic")
+                .ok_or_else(|| format!("Call to undefined method {}::{}()", class, method))?;
+            self.emit(Instruction::Call(self.create_null_fn_idx));
+            self.compile_string_literal(method)?;
+            self.compile_magic_call_arguments(args)?;
+            self.emit(Instruction::Call(call_hook.function_idx));
+            return Ok(());
+        };
+
+        // No object: $this is null
+        self.emit(Instruction::Call(self.create_null_fn_idx));
+        self.compile_call_arguments(args, &method_info.params)?;
+        self.emit(Instruction::Call(method_info.function_idx));
+        Ok(())
+    }
+}
@@ -1,20 +1,20 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-/// Escape Analysis for EdgePHP
-///
-/// This module determines which values can be kept as unboxed WASM locals
-/// vs which need to be materialized as PhpValue structs.
-///
-/// A value "escapes" if it:
-/// - Is returned from a function
-/// - Is stored in an array/object
-/// - Is passed to echo or other output functions
-/// - Is captured by a closure
-/// - Has its reference taken
-///
-/// Non-escaping values can stay as raw i64/f64 in WASM locals,
-/// avoiding allocation overhead.
+//! Escape Analysis for EdgePHP
+//!
+//! This module determines which values can be kept as unboxed WASM locals
+//! vs which need to be materialized as PhpValue structs.
+//!
+//! A value "escapes" if it:
+//! - Is returned from a function
+//! - Is stored in an array/object
+//! - Is passed to echo or other output functions
+//! - Is captured by a closure
+//! - Has its reference taken
+//!
+//! Non-escaping values can stay as raw i64/f64 in WASM locals,
+//! avoiding allocation overhead.
 
 use edge_php_parser::ast::*;
 use std::collections::{HashMap, HashSet};
@@ -76,6 +76,12 @@ pub struct EscapeAnalyzer {
     scope_stack: Vec<HashSet<String>>,
 }
 
+impl Default for EscapeAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EscapeAnalyzer {
     pub fn new() -> Self {
         EscapeAnalyzer {
@@ -153,7 +159,7 @@ impl EscapeAnalyzer {
                 self.analyze_expression(array, false);
 
                 // Loop variables ESCAPE (they're exposed to user code)
-                self.mark_variable_as_escaping(value);
+                self.mark_expression_as_escaping(value);
                 if let Some(key_var) = key {
                     self.mark_variable_as_escaping(key_var);
                 }
@@ -207,6 +213,10 @@ impl EscapeAnalyzer {
                     self.mark_expression_as_escaping(array);
                     self.mark_expression_as_escaping(index);
                     self.mark_expression_as_escaping(right);
+                } else if let Expression::List(_) = &**left {
+                    // Destructuring - targets receive boxed array elements
+                    self.mark_expression_as_escaping(left);
+                    self.analyze_expression(right, false);
                 }
             }
             Expression::Binary { left, right, .. } => {
@@ -222,6 +232,22 @@ impl EscapeAnalyzer {
                     }
                 }
             }
+            Expression::Literal(Literal::InterpolatedString(parts)) => {
+                // Interpolated values are converted to strings for output
+                for part in parts {
+                    match part {
+                        InterpolatedPart::Variable(name) => {
+                            self.record_variable_use(name, UseContext::Output);
+                        }
+                        InterpolatedPart::Expression(expr) => self.analyze_expression(expr, true),
+                        InterpolatedPart::Text(_) => {}
+                    }
+                }
+            }
+            Expression::Coalesce { left, right } => {
+                self.analyze_expression(left, escapes);
+                self.analyze_expression(right, escapes);
+            }
             Expression::ArrayAccess { array, index } => {
                 // Reading from array doesn't make it escape
                 self.analyze_expression(array, false);
@@ -250,6 +276,11 @@ impl EscapeAnalyzer {
             Expression::Assignment { right, .. } => {
                 self.mark_expression_as_escaping(right);
             }
+            Expression::List(items) => {
+                for item in items.iter().flatten() {
+                    self.mark_expression_as_escaping(&item.value);
+                }
+            }
             _ => {}
         }
     }
@@ -3,6 +3,8 @@
 
 use super::core::*;
 use super::type_inference::InferredType;
+use super::packed_arrays::PackedKind;
+use super::array_copies::Store;
 use wasm_encoder::*;
 use edge_php_parser::ast::*;
 use edge_php_parser::InterpolatedPart;
@@ -22,6 +24,16 @@ impl Compiler {
     }
 
     pub(super) fn compile_expression(&mut self, expr: Expression) -> Result<(), String> {
+        // $a?->b->c(): the whole chain short-circuits to null
+        if Self::has_nullsafe_in_chain(&expr) {
+            return self.compile_nullsafe_chain(expr);
+        }
+
+        if let Some(var_info) = self.globals_element(&expr) {
+            self.emit_variable_get(&var_info);
+            return Ok(());
+        }
+
         match expr {
             Expression::Literal(Literal::Integer(n)) => {
                 self.emit(Instruction::I64Const(n));
@@ -115,6 +127,9 @@ impl Compiler {
                 self.compile_property_access(*object, &property)?;
                 Ok(())
             }
+            Expression::StaticPropertyAccess { class, property } => {
+                self.compile_static_property_access(&class, &property)
+            }
             Expression::MethodCall { object, method, args } => {
                 // PHASE 5: Method call ($obj->method())
                 self.compile_method_call(*object, &method, args)?;
@@ -130,11 +145,29 @@ impl Compiler {
                 self.compile_unary_op(op, *expr)?;
                 Ok(())
             }
+            Expression::Coalesce { left, right } => {
+                self.compile_coalesce(*left, *right)?;
+                Ok(())
+            }
+            Expression::ClassConstant { class, name } => {
+                self.compile_class_constant(&class, &name)
+            }
+            Expression::StaticMethodCall { class, method, args } => {
+                self.compile_static_method_call(&class, &method, args)
+            }
+            Expression::Clone(expr) => self.compile_clone(*expr),
+            Expression::Invoke { callee, args } => self.compile_invoke(*callee, args),
+            Expression::List(_) => Err("Cannot use list() outside of an assignment".to_string()),
+            // Includes are linked before compilation when compiling a project
+            Expression::Include { kind, .. } => Err(format!(
+                "{} needs to know which file it is in; compile the file as a project",
+                kind.keyword()
+            )),
             _ => Err(format!("Unsupported expression: {:?}", expr))
         }
     }
     
-    fn compile_string_literal(&mut self, s: &str) -> Result<(), String> {
+    pub(super) fn compile_string_literal(&mut self, s: &str) -> Result<(), String> {
         // Create a GC string array
         let len = s.len();
         self.emit(Instruction::I32Const(len as i32));
@@ -160,7 +193,7 @@ impl Compiler {
     
     fn compile_variable_load(&mut self, name: &str) -> Result<(), String> {
         if let Some(var_info) = self.variables.get(name).cloned() {
-            self.emit(Instruction::LocalGet(var_info.local_idx));
+            self.emit_variable_get(&var_info);
 
             // Box unboxed values on load
             match var_info.storage_type {
@@ -170,11 +203,15 @@ impl Compiler {
                 VariableStorage::UnboxedFloat => {
                     self.emit_inline_box_float(); // PHASE 3B: Inlined
                 }
-                VariableStorage::Boxed => {
+                VariableStorage::Boxed | VariableStorage::Global(_) => {
                     // Already boxed, nothing to do
                 }
             }
             Ok(())
+        } else if name == "GLOBALS" {
+            self.compile_globals_array()
+        } else if self.compile_imported_constant(name)? {
+            Ok(())
         } else {
             // Variable not found, return null
             self.emit(Instruction::Call(self.create_null_fn_idx));
@@ -183,6 +220,13 @@ impl Compiler {
     }
 
     fn compile_assignment(&mut self, left: Expression, right: Expression) -> Result<(), String> {
+        if let Some(var_info) = self.globals_element(&left) {
+            // $GLOBALS['name'] = value
+            self.compile_stored_value(right, Store::Other)?;
+            self.emit_variable_tee(&var_info);
+            return Ok(());
+        }
+
         match left {
             Expression::Variable(name) => {
                 // Check if variable exists and what type it should be
@@ -224,24 +268,24 @@ impl Compiler {
                         }
                         _ => {
                             // Default: compile normally and store boxed
-                            self.compile_expression(right)?;
-                            self.emit(Instruction::LocalTee(var_info.local_idx));
+                            self.compile_stored_value(right, Store::Variable(&name))?;
+                            let value_local = self.allocate_local(self.get_php_value_type());
+                            self.emit(Instruction::LocalTee(value_local));
+                            self.emit_store_boxed(&var_info);
+                            self.emit(Instruction::LocalGet(value_local));
                         }
                     }
                 } else {
-                    // New variable - decide storage type based on inference
-                    let (storage_type, wasm_type) = match right_type {
-                        InferredType::Int => (VariableStorage::UnboxedInt, ValType::I64),
-                        InferredType::Float => (VariableStorage::UnboxedFloat, ValType::F64),
+                    // New variable - decide storage type from every value it is assigned
+                    let variable_type = self.type_inference.get_variable_type(&name);
+                    let (storage_type, wasm_type) = match variable_type {
+                        InferredType::Int if self.options.unboxing_enabled() => (VariableStorage::UnboxedInt, ValType::I64),
+                        InferredType::Float if self.options.unboxing_enabled() => (VariableStorage::UnboxedFloat, ValType::F64),
                         _ => (VariableStorage::Boxed, self.get_php_value_type()),
                     };
 
                     // PHASE 5: Track class type for object assignments
-                    let class_type = if let Expression::New { ref class, .. } = right {
-                        Some(class.clone())
-                    } else {
-                        None
-                    };
+                    let class_type = self.class_of_type(&variable_type);
 
                     let local_idx = self.allocate_local(wasm_type);
                     self.variables.insert(name.clone(), VariableInfo {
@@ -253,25 +297,21 @@ impl Compiler {
                     match storage_type {
                         VariableStorage::UnboxedInt => {
                             // OPTIMIZATION: Compile as unboxed int directly
-                            self.compile_expression_as_unboxed_int(right)?;
+                            self.compile_expression_as_unboxed(right, &right_type, &variable_type)?;
                             self.emit(Instruction::LocalTee(local_idx));
                             self.emit_inline_box_int(); // PHASE 3B: Inlined
                         }
                         VariableStorage::UnboxedFloat => {
                             // Compile right, extract float, store unboxed
-                            self.compile_expression(right)?;
-                            self.emit(Instruction::StructGet {
-                                struct_type_index: self.gc_types.php_value,
-                                field_index: PHPVALUE_FLOAT,
-                            });
+                            self.compile_expression_as_unboxed(right, &right_type, &variable_type)?;
                             self.emit(Instruction::LocalSet(local_idx));
                             // For result, reload and box
                             self.emit(Instruction::LocalGet(local_idx));
                             self.emit_inline_box_float(); // PHASE 3B: Inlined
                         }
-                        VariableStorage::Boxed => {
+                        VariableStorage::Boxed | VariableStorage::Global(_) => {
                             // Store boxed
-                            self.compile_expression(right)?;
+                            self.compile_stored_value(right, Store::Variable(&name))?;
                             self.emit(Instruction::LocalTee(local_idx));
                         }
                     }
@@ -279,22 +319,29 @@ impl Compiler {
             }
             Expression::ArrayAccess { ref array, ref index } => {
                 // Array assignment: $array[$index] = $value
-                self.compile_expression(*array.clone())?;
-                self.compile_expression(*index.clone())?;
-                self.compile_expression(right)?;
-                self.emit(Instruction::Call(self.array_set_fn_idx));
+                self.check_globals_write(array)?;
+                self.compile_element_store(array, index, right)?;
 
                 // array_set returns the array, so we need to store it back
-                if let Expression::Variable(var_name) = &**array {
-                    let var_info = self.variables.get(var_name).cloned()
-                        .ok_or_else(|| format!("Variable {} not found", var_name))?;
-                    self.emit(Instruction::LocalTee(var_info.local_idx));
+                if let Some(var_info) = self.array_base_variable(array)? {
+                    self.emit_variable_tee(&var_info);
                 }
             }
             Expression::PropertyAccess { object, property } => {
                 // PHASE 5: Property assignment: $obj->prop = value
                 self.compile_property_assignment(*object, &property, right)?;
             }
+            Expression::StaticPropertyAccess { class, property } => {
+                self.compile_static_property_assignment(&class, &property, right, true)?;
+            }
+            Expression::List(items) => {
+                // [$a, $b] = $arr evaluates to $arr
+                self.compile_expression(right)?;
+                let source_local = self.allocate_local(self.get_php_value_type());
+                self.emit(Instruction::LocalSet(source_local));
+                self.compile_destructuring(items, source_local)?;
+                self.emit(Instruction::LocalGet(source_local));
+            }
             _ => return Err("Can only assign to variables, array elements, or object properties".to_string()),
         }
 
@@ -303,6 +350,12 @@ impl Compiler {
 
     /// OPTIMIZATION: Compile assignment for side effects only - no return value needed
     fn compile_assignment_void(&mut self, left: Expression, right: Expression) -> Result<(), String> {
+        if let Some(var_info) = self.globals_element(&left) {
+            self.compile_stored_value(right, Store::Other)?;
+            self.emit_variable_set(&var_info);
+            return Ok(());
+        }
+
         match left {
             Expression::Variable(name) => {
                 let right_type = self.type_inference.infer_expression(&right);
@@ -324,18 +377,21 @@ impl Compiler {
                             self.emit(Instruction::LocalSet(var_info.local_idx));
                         }
                         _ => {
-                            self.compile_expression(right)?;
-                            self.emit(Instruction::LocalSet(var_info.local_idx));
+                            self.compile_stored_value(right, Store::Variable(&name))?;
+                            self.emit_store_boxed(&var_info);
                         }
                     }
                 } else {
                     // New variable - decide storage type WITHOUT boxing result
                     // PHASE 3A: Check escape analysis to decide if we can keep unboxed
-                    let can_keep_unboxed = self.escape_analyzer.can_keep_unboxed(&name);
+                    let can_keep_unboxed = self.options.unboxing_enabled()
+                        && self.options.escape_analysis_enabled()
+                        && self.escape_analyzer.can_keep_unboxed(&name);
 
+                    let variable_type = self.type_inference.get_variable_type(&name);
                     let (storage_type, wasm_type) = if can_keep_unboxed {
                         // Variable doesn't escape - can use unboxed storage!
-                        match right_type {
+                        match variable_type {
                             InferredType::Int => (VariableStorage::UnboxedInt, ValType::I64),
                             InferredType::Float => (VariableStorage::UnboxedFloat, ValType::F64),
                             _ => (VariableStorage::Boxed, self.get_php_value_type()),
@@ -346,11 +402,7 @@ impl Compiler {
                     };
 
                     // PHASE 5: Track class type for object assignments
-                    let class_type = if let Expression::New { ref class, .. } = right {
-                        Some(class.clone())
-                    } else {
-                        None
-                    };
+                    let class_type = self.class_of_type(&variable_type);
 
                     let local_idx = self.allocate_local(wasm_type);
                     self.variables.insert(name.clone(), VariableInfo {
@@ -360,20 +412,12 @@ impl Compiler {
                     });
 
                     match storage_type {
-                        VariableStorage::UnboxedInt => {
-                            self.compile_expression_as_unboxed_int(right)?;
+                        VariableStorage::UnboxedInt | VariableStorage::UnboxedFloat => {
+                            self.compile_expression_as_unboxed(right, &right_type, &variable_type)?;
                             self.emit(Instruction::LocalSet(local_idx));
                         }
-                        VariableStorage::UnboxedFloat => {
-                            self.compile_expression(right)?;
-                            self.emit(Instruction::StructGet {
-                                struct_type_index: self.gc_types.php_value,
-                                field_index: PHPVALUE_FLOAT,
-                            });
-                            self.emit(Instruction::LocalSet(local_idx));
-                        }
-                        VariableStorage::Boxed => {
-                            self.compile_expression(right)?;
+                        VariableStorage::Boxed | VariableStorage::Global(_) => {
+                            self.compile_stored_value(right, Store::Variable(&name))?;
                             self.emit(Instruction::LocalSet(local_idx));
                         }
                     }
@@ -381,16 +425,12 @@ impl Compiler {
             }
             Expression::ArrayAccess { ref array, ref index } => {
                 // Array assignment in void context
-                self.compile_expression(*array.clone())?;
-                self.compile_expression(*index.clone())?;
-                self.compile_expression(right)?;
-                self.emit(Instruction::Call(self.array_set_fn_idx));
+                self.check_globals_write(array)?;
+                self.compile_element_store(array, index, right)?;
 
                 // array_set returns the array, store it back and drop
-                if let Expression::Variable(var_name) = &**array {
-                    let var_info = self.variables.get(var_name).cloned()
-                        .ok_or_else(|| format!("Variable {} not found", var_name))?;
-                    self.emit(Instruction::LocalSet(var_info.local_idx));
+                if let Some(var_info) = self.array_base_variable(array)? {
+                    self.emit_variable_set(&var_info);
                 } else {
                     self.emit(Instruction::Drop);
                 }
@@ -399,13 +439,191 @@ impl Compiler {
                 // PHASE 5: Property assignment (void context): $obj->prop = value
                 self.compile_property_assignment_void(*object, &property, right)?;
             }
+            Expression::StaticPropertyAccess { class, property } => {
+                self.compile_static_property_assignment(&class, &property, right, false)?;
+            }
+            Expression::List(items) => {
+                self.compile_expression(right)?;
+                let source_local = self.allocate_local(self.get_php_value_type());
+                self.emit(Instruction::LocalSet(source_local));
+                self.compile_destructuring(items, source_local)?;
+            }
             _ => return Err("Can only assign to variables, array elements, or object properties".to_string()),
         }
 
         Ok(())
     }
 
+    /// Compile the value of a new int or float variable as an unboxed i64/f64. The
+    /// variable's type holds for every value it is assigned, but where this value
+    /// is not known to have it on its own, it is converted
+    pub(super) fn compile_expression_as_unboxed(&mut self, expr: Expression, expr_type: &InferredType, variable_type: &InferredType) -> Result<(), String> {
+        match (variable_type, expr_type) {
+            (InferredType::Int, InferredType::Int) => self.compile_expression_as_unboxed_int(expr),
+            (InferredType::Float, InferredType::Float) => self.compile_expression_as_unboxed_float(expr),
+            (InferredType::Int, _) => {
+                self.compile_expression(expr)?;
+                self.emit(Instruction::Call(self.to_int_fn_idx));
+                self.emit(Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index: PHPVALUE_INT,
+                });
+                Ok(())
+            }
+            _ => {
+                self.compile_expression(expr)?;
+                self.emit(Instruction::Call(self.to_float_fn_idx));
+                self.emit(Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index: PHPVALUE_FLOAT,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// The class of every value of an inferred type, when it is one the compiler knows
+    pub(super) fn class_of_type(&self, ty: &InferredType) -> Option<String> {
+        match ty {
+            InferredType::Object(class) if self.classes.contains_key(class) => Some(class.clone()),
+            _ => None,
+        }
+    }
+
+    /// Store the boxed PhpValue on the stack into an existing variable, unboxing it
+    /// when the variable lives in an i64/f64 local
+    fn emit_store_boxed(&mut self, var_info: &VariableInfo) {
+        match var_info.storage_type {
+            VariableStorage::UnboxedInt => {
+                self.emit(Instruction::Call(self.to_int_fn_idx));
+                self.emit(Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index: PHPVALUE_INT,
+                });
+            }
+            VariableStorage::UnboxedFloat => {
+                self.emit(Instruction::Call(self.to_float_fn_idx));
+                self.emit(Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index: PHPVALUE_FLOAT,
+                });
+            }
+            VariableStorage::Boxed | VariableStorage::Global(_) => {}
+        }
+        self.emit_variable_set(var_info);
+    }
+
+    /// The variable an array write stores the updated array back into:
+    /// `$arr[...] = v`, `$GLOBALS['arr'][...] = v` or `Class::$arr[...] = v`
+    pub(super) fn array_base_variable(&self, array: &Expression) -> Result<Option<VariableInfo>, String> {
+        if let Some(var_info) = self.globals_element(array) {
+            return Ok(Some(var_info));
+        }
+        match array {
+            Expression::Variable(var_name) => self.variables.get(var_name).cloned()
+                .map(Some)
+                .ok_or_else(|| format!("Variable {} not found", var_name)),
+            Expression::StaticPropertyAccess { class, property } => self.static_property_variable(class, property).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Destructure the array held in `source_local` into a list() pattern.
+    /// Missing elements assign null without a warning.
+    fn compile_destructuring(&mut self, items: Vec<Option<ArrayElement>>, source_local: u32) -> Result<(), String> {
+        for (position, item) in items.into_iter().enumerate() {
+            // Skipped slot: [, $b]
+            let Some(ArrayElement { key, value: target }) = item else {
+                continue;
+            };
+
+            // Positional elements use their slot index as key
+            let key = key.unwrap_or(Expression::Literal(Literal::Integer(position as i64)));
+
+            self.emit(Instruction::LocalGet(source_local));
+            self.compile_expression(key)?;
+            self.emit(Instruction::Call(self.array_get_quiet_fn_idx));
+            let element_local = self.allocate_local(self.get_php_value_type());
+            self.emit(Instruction::LocalSet(element_local));
+
+            match target {
+                Expression::List(nested) => {
+                    self.compile_destructuring(nested, element_local)?;
+                }
+                Expression::Variable(name) if !self.variables.contains_key(&name) => {
+                    self.variables.insert(name, VariableInfo {
+                        local_idx: element_local,
+                        storage_type: VariableStorage::Boxed,
+                        class_type: None,
+                    });
+                }
+                target => {
+                    // Existing variable, $arr[...] or $obj->prop: reuse the regular assignment path
+                    let temp_name = self.bind_temp_variable(element_local, None);
+                    let result = self.compile_assignment_void(target, Expression::Variable(temp_name.clone()));
+                    self.variables.remove(&temp_name);
+                    result?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile an operand of ??, isset() or empty(): undefined variables, missing array
+    /// keys, out-of-range indexes and undeclared properties yield null instead of failing
+    pub(super) fn compile_quiet_fetch(&mut self, expr: Expression) -> Result<(), String> {
+        if let Some(var_info) = self.globals_element(&expr) {
+            self.emit_variable_get(&var_info);
+            return Ok(());
+        }
+
+        match expr {
+            Expression::ArrayAccess { array, index } => {
+                self.compile_quiet_fetch(*array)?;
+                self.compile_expression(*index)?;
+                self.emit(Instruction::Call(self.array_get_quiet_fn_idx));
+                Ok(())
+            }
+            Expression::PropertyAccess { object, property }
+            | Expression::NullsafePropertyAccess { object, property } => {
+                // A null object reads as null either way, so ?-> needs no extra handling here
+                self.compile_quiet_fetch(*object)?;
+                let object_local = self.allocate_local(self.get_php_value_type());
+                self.emit(Instruction::LocalSet(object_local));
+                self.emit_dynamic_property_get(object_local, &property, true)
+            }
+            // Variables already load as null when undefined
+            other => self.compile_expression(other),
+        }
+    }
+
+    /// $left ?? $right - right is only evaluated when left is null or unset
+    fn compile_coalesce(&mut self, left: Expression, right: Expression) -> Result<(), String> {
+        self.compile_quiet_fetch(left)?;
+        let left_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalTee(left_local));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_TYPE,
+        });
+        self.emit(Instruction::I32Const(TYPE_NULL as i32));
+        self.emit(Instruction::I32Ne);
+        self.emit(Instruction::If(BlockType::Result(self.get_php_value_type())));
+        self.emit(Instruction::LocalGet(left_local));
+        self.emit(Instruction::Else);
+        self.compile_expression(right)?;
+        self.emit(Instruction::End);
+        Ok(())
+    }
+
     fn compile_binary_op(&mut self, left: Expression, op: BinaryOp, right: Expression) -> Result<(), String> {
+        // && and || short-circuit, so they are control flow rather than a helper call
+        if matches!(op, BinaryOp::And | BinaryOp::Or) {
+            self.compile_expression_as_bool_i32(Expression::Binary { left: Box::new(left), op, right: Box::new(right) })?;
+            self.emit(Instruction::Call(self.create_bool_fn_idx));
+            return Ok(());
+        }
+
         // OPTIMIZATION 1: Constant Folding - Evaluate constant expressions at compile time
         if let (Expression::Literal(Literal::Integer(a)), Expression::Literal(Literal::Integer(b))) = (&left, &right) {
             match op {
@@ -692,6 +910,21 @@ impl Compiler {
                 });
                 Ok(())
             }
+            Expression::FunctionCall { name, args } => {
+                // A specialized copy returning ints hands over its i64 as is
+                self.compile_call_as_unboxed(name, args, InferredType::Int, PHPVALUE_INT)
+            }
+            Expression::ArrayAccess { array, index } => {
+                // Typed storage hands over its elements unboxed
+                if !self.compile_unboxed_element_read(&array, &index, PackedKind::Int)? {
+                    self.compile_expression(Expression::ArrayAccess { array, index })?;
+                    self.emit(Instruction::StructGet {
+                        struct_type_index: self.gc_types.php_value,
+                        field_index: PHPVALUE_INT,
+                    });
+                }
+                Ok(())
+            }
             _ => {
                 // Default: compile as boxed then extract
                 self.compile_expression(expr)?;
@@ -735,6 +968,20 @@ impl Compiler {
                     Err(format!("Variable {} not found", name))
                 }
             }
+            Expression::FunctionCall { name, args } => {
+                self.compile_call_as_unboxed(name, args, InferredType::Float, PHPVALUE_FLOAT)
+            }
+            Expression::ArrayAccess { array, index } => {
+                // Typed storage hands over its elements unboxed
+                if !self.compile_unboxed_element_read(&array, &index, PackedKind::Float)? {
+                    self.compile_expression(Expression::ArrayAccess { array, index })?;
+                    self.emit(Instruction::StructGet {
+                        struct_type_index: self.gc_types.php_value,
+                        field_index: PHPVALUE_FLOAT,
+                    });
+                }
+                Ok(())
+            }
             _ => {
                 // Default: compile as boxed then extract
                 self.compile_expression(expr)?;
@@ -747,6 +994,26 @@ impl Compiler {
         }
     }
 
+    /// Compile a call whose result is known to be an int or a float (`unboxed`)
+    /// and leave it unboxed: straight from a specialized copy returning it,
+    /// otherwise from the `field_index` of the boxed result
+    fn compile_call_as_unboxed(&mut self, name: String, args: Vec<Expression>, unboxed: InferredType, field_index: u32) -> Result<(), String> {
+        if self.functions.contains_key(&name) {
+            if let Some(specialization) = self.specialization_of_call(&name, &args) {
+                if specialization.return_type == unboxed {
+                    let params = self.function_params.get(&name).cloned().unwrap_or_default();
+                    return self.compile_specialized_call(&specialization, args, &params);
+                }
+            }
+        }
+        self.compile_expression(Expression::FunctionCall { name, args })?;
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index,
+        });
+        Ok(())
+    }
+
     // ============================================================================
     // SPECIALIZED INTEGER ARITHMETIC (Fast Path)
     // ============================================================================
@@ -920,6 +1187,23 @@ impl Compiler {
     /// Compile expression as a raw i32 boolean (0 or 1) for use in control flow
     /// This avoids boxing to PhpValue and immediately unboxing in if/for/while conditions
     pub(super) fn compile_expression_as_bool_i32(&mut self, expr: Expression) -> Result<(), String> {
+        // The right operand of && and || only runs when the left one does not decide
+        if let Expression::Binary { left, op: op @ (BinaryOp::And | BinaryOp::Or), right } = expr {
+            self.compile_expression_as_bool_i32(*left)?;
+            self.emit(Instruction::If(BlockType::Result(ValType::I32)));
+            if op == BinaryOp::And {
+                self.compile_expression_as_bool_i32(*right)?;
+                self.emit(Instruction::Else);
+                self.emit(Instruction::I32Const(0));
+            } else {
+                self.emit(Instruction::I32Const(1));
+                self.emit(Instruction::Else);
+                self.compile_expression_as_bool_i32(*right)?;
+            }
+            self.emit(Instruction::End);
+            return Ok(());
+        }
+
         // Check if this is a comparison we can optimize
         if let Expression::Binary { left, op, right } = &expr {
             let left_type = self.type_inference.infer_expression(left);
@@ -1006,10 +1290,17 @@ impl Compiler {
     fn compile_function_call(&mut self, name: &str, args: Vec<Expression>) -> Result<(), String> {
         // PHASE 4: Check for user-defined functions first
         if let Some(&func_idx) = self.functions.get(name) {
-            // User-defined function - compile arguments and call
-            for arg in args {
-                self.compile_expression(arg)?;
+            // User-defined function - compile arguments (missing ones take their defaults) and call
+            let params = self.function_params.get(name).cloned().unwrap_or_default();
+            if let Some(specialization) = self.specialization_of_call(name, &args) {
+                self.compile_specialized_call(&specialization, args, &params)?;
+                self.emit_box_unboxed(&specialization.return_type);
+                return Ok(());
+            }
+            if let Some(candidate) = self.inline_candidate(name, args.len()) {
+                return self.compile_inlined_call(name, candidate, None, args);
             }
+            self.compile_call_arguments(args, &params)?;
             self.emit(Instruction::Call(func_idx));
             return Ok(());
         }
@@ -1122,6 +1413,8 @@ impl Compiler {
                 
                 Ok(())
             }
+            // Functions from extensions and the host
+            _ if self.compile_imported_call(name, args.clone())? => Ok(()),
             _ => Err(format!("Unknown function: {}", name))
         }
     }
@@ -1154,6 +1447,10 @@ impl Compiler {
                     // Convert to string
                     self.emit(Instruction::Call(self.to_string_fn_idx));
                 }
+                InterpolatedPart::Expression(expr) => {
+                    self.compile_expression(expr)?;
+                    self.emit(Instruction::Call(self.to_string_fn_idx));
+                }
             }
             
             // If not the first part, concatenate with previous result
@@ -1175,7 +1472,7 @@ impl Compiler {
     /// Inline box i64 to PhpValue (replaces call to create_int_fn)
     /// Assumes i64 value is already on stack
     #[inline]
-    fn emit_inline_box_int(&mut self) {
+    pub(super) fn emit_inline_box_int(&mut self) {
         // PHASE 3B: Inline PhpValue creation for i64
         // Stack on entry: [i64_value]
         // Need to save i64, then build struct fields in order: [i32, i64, f64, ref, ref]
@@ -1201,7 +1498,7 @@ impl Compiler {
     /// Inline box f64 to PhpValue (replaces call to create_float_fn)
     /// Assumes f64 value is already on stack
     #[inline]
-    fn emit_inline_box_float(&mut self) {
+    pub(super) fn emit_inline_box_float(&mut self) {
         // PHASE 3B: Inline PhpValue creation for f64
         // Stack on entry: [f64_value]
         // Need to save f64, then build struct fields in order: [i32, i64, f64, ref, ref]
@@ -1359,16 +1656,14 @@ impl Compiler {
                 None
             };
 
-            // Load current value
-            self.emit(Instruction::LocalGet(var_info.local_idx));
-
             if let Some(result_local) = result_local {
                 // For postfix, save the old value to return later
+                self.compile_variable_load(var_name)?;
                 self.emit(Instruction::LocalSet(result_local));
             }
 
             // Load value again for modification
-            self.emit(Instruction::LocalGet(var_info.local_idx));
+            self.compile_variable_load(var_name)?;
 
             // Convert to int (PHP behavior: increment/decrement work on int/float)
             // For simplicity, we'll work with int. TODO: Handle floats properly
@@ -1392,20 +1687,54 @@ impl Compiler {
             self.emit(Instruction::Call(self.create_int_fn_idx));
 
             // Store back to variable
-            self.emit(Instruction::LocalSet(var_info.local_idx));
+            self.emit_store_boxed(&var_info);
 
             // Return the appropriate value
             if is_prefix {
                 // For prefix, return the new value
-                self.emit(Instruction::LocalGet(var_info.local_idx));
+                self.compile_variable_load(var_name)?;
             } else {
                 // For postfix, return the old value
                 self.emit(Instruction::LocalGet(result_local.unwrap()));
             }
 
             Ok(())
+        } else if Self::is_member_increment_target(&expr) {
+            self.compile_member_increment_decrement(expr, is_increment, is_prefix)
         } else {
-            Err(format!("Increment/decrement only works on variables, got: {:?}", expr))
+            Err(format!("Increment/decrement only works on variables and properties, got: {:?}", expr))
         }
     }
+
+    /// `Class::$n` and `$var->prop`, whose object can be read twice without side effects
+    fn is_member_increment_target(expr: &Expression) -> bool {
+        match expr {
+            Expression::StaticPropertyAccess { .. } => true,
+            Expression::PropertyAccess { object, .. } => matches!(**object, Expression::Variable(_)),
+            _ => false,
+        }
+    }
+
+    /// `++Class::$n` / `$this->n--`: an assignment of the value plus or minus one
+    fn compile_member_increment_decrement(&mut self, target: Expression, is_increment: bool, is_prefix: bool) -> Result<(), String> {
+        let op = if is_increment { BinaryOp::Add } else { BinaryOp::Subtract };
+        let one = Box::new(Expression::Literal(Literal::Integer(1)));
+
+        if is_prefix {
+            let updated = Expression::Binary { op, left: Box::new(target.clone()), right: one };
+            return self.compile_assignment(target, updated);
+        }
+
+        // Postfix: the expression is the value from before the update
+        self.compile_expression(target.clone())?;
+        let old_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalSet(old_local));
+        let old_name = self.bind_temp_variable(old_local, None);
+        let updated = Expression::Binary { op, left: Box::new(Expression::Variable(old_name.clone())), right: one };
+        let result = self.compile_assignment_void(target, updated);
+        self.variables.remove(&old_name);
+        result?;
+        self.emit(Instruction::LocalGet(old_local));
+        Ok(())
+    }
 }
@@ -0,0 +1,224 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Variable scopes: `global`, function `static` variables and `$GLOBALS`
+//
+// Function bodies only see their own variables. Top-level variables that a
+// function can reach (`global $x` or `$GLOBALS['x']` anywhere in the program)
+// are kept in wasm globals instead of `_start` locals; statics get one wasm
+// global per function and name. Both are bound as VariableStorage::Global, so
+// every read and write goes straight to the shared storage.
+
+use super::core::*;
+use edge_php_parser::ast::*;
+use edge_php_parser::visit::{self, Visitor};
+use wasm_encoder::*;
+use std::collections::BTreeSet;
+
+const GLOBALS_VAR: &str = "GLOBALS";
+
+/// Finds the top-level variables that need module-level storage
+#[derive(Default)]
+struct GlobalScan {
+    names: BTreeSet<String>,
+    /// `$GLOBALS` is used as a whole or with a computed key
+    dynamic: bool,
+}
+
+impl Visitor for GlobalScan {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        if let Statement::Global(names) = stmt {
+            self.names.extend(names.iter().cloned());
+        }
+        visit::walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::ArrayAccess { array, index } if is_globals(array) => {
+                match literal_name(index) {
+                    Some(name) => {
+                        self.names.insert(name.to_string());
+                    }
+                    None => self.dynamic = true,
+                }
+                self.visit_expression(index);
+            }
+            Expression::Variable(name) if name == GLOBALS_VAR => self.dynamic = true,
+            _ => visit::walk_expression(self, expr),
+        }
+    }
+}
+
+/// Collects every variable used by top-level code, outside functions and classes
+#[derive(Default)]
+struct TopLevelVariables(BTreeSet<String>);
+
+impl Visitor for TopLevelVariables {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Function { .. }
+            | Statement::Class { .. }
+            | Statement::Enum { .. }
+            | Statement::Interface { .. } => {}
+            Statement::Foreach { key: Some(key), .. } => {
+                self.0.insert(key.clone());
+                visit::walk_statement(self, stmt);
+            }
+            _ => visit::walk_statement(self, stmt),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        if let Expression::Variable(name) = expr {
+            if name != GLOBALS_VAR {
+                self.0.insert(name.clone());
+            }
+        }
+        visit::walk_expression(self, expr);
+    }
+}
+
+fn is_globals(expr: &Expression) -> bool {
+    matches!(expr, Expression::Variable(name) if name == GLOBALS_VAR)
+}
+
+fn literal_name(expr: &Expression) -> Option<&str> {
+    match expr {
+        Expression::Literal(Literal::String(name)) => Some(name),
+        _ => None,
+    }
+}
+
+impl Compiler {
+    /// Allocate module-level storage for the top-level variables reachable from
+    /// functions. A dynamic `$GLOBALS` makes every top-level variable reachable.
+    pub(super) fn allocate_global_variables(&mut self, program: &Program) {
+        let mut scan = GlobalScan::default();
+        visit::walk_program(&mut scan, program);
+
+        if scan.dynamic {
+            let mut top_level = TopLevelVariables::default();
+            visit::walk_program(&mut top_level, program);
+            scan.names.extend(top_level.0);
+        }
+
+        // Superglobals have storage of their own
+        scan.names.retain(|name| !self.superglobals.contains_key(name));
+        for name in scan.names {
+            let global_idx = self.add_php_value_global();
+            self.global_variables.insert(name, global_idx);
+        }
+    }
+
+    /// A mutable PhpValue wasm global, starting out as a null reference
+    pub(super) fn add_php_value_global(&mut self) -> u32 {
+        let php_value_type = self.get_php_value_type();
+        let null_ref = Instruction::RefNull(HeapType::Concrete(self.gc_types.php_value));
+        self.builder.add_global(php_value_type, true, null_ref)
+    }
+
+    /// Bind the module-level variables in `_start`, starting out as null
+    pub(super) fn bind_global_variables(&mut self) {
+        let mut globals: Vec<(String, u32)> = self.global_variables.iter()
+            .map(|(name, &global_idx)| (name.clone(), global_idx))
+            .collect();
+        globals.sort();
+
+        for (name, global_idx) in globals {
+            self.emit(Instruction::Call(self.create_null_fn_idx));
+            self.emit(Instruction::GlobalSet(global_idx));
+            self.bind_global(name, global_idx);
+        }
+    }
+
+    pub(super) fn bind_global(&mut self, name: String, global_idx: u32) {
+        self.variables.insert(name, VariableInfo {
+            local_idx: 0,
+            storage_type: VariableStorage::Global(global_idx),
+            class_type: None,
+        });
+    }
+
+    /// `global $a, $b;` - bind the names to the top-level variables for the rest of the scope
+    pub(super) fn compile_global_statement(&mut self, names: Vec<String>) -> Result<(), String> {
+        for name in names {
+            if self.superglobals.contains_key(&name) {
+                continue;
+            }
+            let global_idx = *self.global_variables.get(&name)
+                .ok_or_else(|| format!("No module-level storage for global ${}", name))?;
+            self.bind_global(name, global_idx);
+        }
+        Ok(())
+    }
+
+    /// `static $n = init;` - the wasm global starts as a null ref, which marks the
+    /// variable as uninitialised, so the initialiser only runs the first time
+    pub(super) fn compile_static_statement(&mut self, variables: Vec<StaticVariable>) -> Result<(), String> {
+        for StaticVariable { name, default } in variables {
+            let key = (self.static_scope.clone(), name.clone());
+            let global_idx = match self.static_variables.get(&key) {
+                Some(&global_idx) => global_idx,
+                None => {
+                    let global_idx = self.add_php_value_global();
+                    self.static_variables.insert(key, global_idx);
+                    global_idx
+                }
+            };
+
+            self.emit(Instruction::GlobalGet(global_idx));
+            self.emit(Instruction::RefIsNull);
+            self.emit(Instruction::If(BlockType::Empty));
+            match default {
+                Some(expr) => self.compile_expression(expr)?,
+                None => self.emit(Instruction::Call(self.create_null_fn_idx)),
+            }
+            self.emit(Instruction::GlobalSet(global_idx));
+            self.emit(Instruction::End);
+
+            self.bind_global(name, global_idx);
+        }
+        Ok(())
+    }
+
+    /// `$GLOBALS['name']` resolves at compile time to the top-level variable itself
+    pub(super) fn globals_element(&self, expr: &Expression) -> Option<VariableInfo> {
+        let Expression::ArrayAccess { array, index } = expr else {
+            return None;
+        };
+        if !is_globals(array) {
+            return None;
+        }
+        let global_idx = *self.global_variables.get(literal_name(index)?)?;
+        Some(VariableInfo {
+            local_idx: 0,
+            storage_type: VariableStorage::Global(global_idx),
+            class_type: None,
+        })
+    }
+
+    /// Writes through `$GLOBALS` need a literal name to find the variable's storage
+    pub(super) fn check_globals_write(&self, array: &Expression) -> Result<(), String> {
+        if is_globals(array) {
+            return Err("Writing to $GLOBALS requires a literal variable name, e.g. $GLOBALS['name']".to_string());
+        }
+        Ok(())
+    }
+
+    /// `$GLOBALS` as a value: an array snapshot of the top-level variables
+    pub(super) fn compile_globals_array(&mut self) -> Result<(), String> {
+        let mut globals: Vec<(String, u32)> = self.global_variables.iter()
+            .map(|(name, &global_idx)| (name.clone(), global_idx))
+            .collect();
+        globals.sort();
+
+        self.emit(Instruction::Call(self.create_hash_array_fn_idx));
+        for (name, global_idx) in globals {
+            self.compile_string_literal(&name)?;
+            self.emit(Instruction::GlobalGet(global_idx));
+            self.emit(Instruction::Call(self.hash_array_set_fn_idx));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1606 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// HTTP requests and responses: superglobals, header(), cookies and the
+// request handler entry point
+//
+// Superglobals live in wasm globals and are bound in every scope, so functions
+// see them without `global`. header(), http_response_code(), setcookie() and
+// headers_sent() keep the response status and header lines in module state;
+// the first byte of output sends the headers, after which they are frozen.
+//
+// With `CompilerOptions::http_handler` the module exports `handle_request`
+// instead of `_start`, and output goes to the response body instead of
+// `env.print`. The host writes the request at the offset in the exported
+// `request_buffer` global (growing the memory if needed) and calls
+// `handle_request(len) -> ptr`. All integers are little-endian:
+//
+//   request  := request_time:i64 input_len:u32 input entry_count:u32 entry*
+//   entry    := target:u8 shape:u8 key_len:u32 key [sub_len:u32 sub] value
+//   value    := 0:u8 len:u32 bytes | 1:u8 int:i64
+//   response := status:u32 header_count:u32 (len:u32 "Name: value")* body_len:u32 body
+//
+// `target` is the index of the superglobal in SUPERGLOBALS; entries for
+// $_GET and $_POST also go into $_REQUEST. `shape` 0 stores `$t[key]`, 1
+// stores `$t[key][sub]` (only then is `sub` present) and 2 appends to
+// `$t[key][]`. The input is what `file_get_contents('php://input')` returns
+// and the request time becomes `$_SERVER['REQUEST_TIME']`.
+
+use super::core::*;
+use edge_php_parser::ast::*;
+use edge_php_parser::visit::{self, Visitor};
+use wasm_encoder::*;
+use std::collections::BTreeSet;
+
+/// Superglobals, in the order of the request's `target` byte
+pub(super) const SUPERGLOBALS: [&str; 6] = ["_SERVER", "_GET", "_POST", "_COOKIE", "_FILES", "_REQUEST"];
+const REQUEST_TARGET: usize = 5;
+
+/// Where hosts write the request: past the chunk handed to `env.print`
+const REQUEST_BUFFER: i32 = 0x10000;
+
+const RESPONSE_FUNCTIONS: [&str; 5] = ["header", "http_response_code", "headers_sent", "setcookie", "file_get_contents"];
+
+/// Module state behind header() and setcookie()
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ResponseState {
+    status: u32,        // i32 global, 200 until changed
+    headers: u32,       // Header lines as string PhpValues (a php_array grown by doubling)
+    header_count: u32,  // i32 global
+    headers_sent: u32,  // i32 global, set by the first byte of output
+    input: u32,         // php://input as a PhpValue, null outside a request
+    request_time: u32,  // i64 global, for cookie Max-Age
+    header_fn: u32,
+    setcookie_fn: u32,
+    rawurlencode_fn: u32,
+    cookie_date_fn: u32,
+}
+
+impl ResponseState {
+    /// The i32 global output_write() sets once output leaves the buffers
+    pub(super) fn headers_sent_global(&self) -> u32 {
+        self.headers_sent
+    }
+}
+
+/// Finds the superglobals a program uses and whether it touches the response
+#[derive(Default)]
+struct HttpScan {
+    superglobals: BTreeSet<String>,
+    response: bool,
+}
+
+impl Visitor for HttpScan {
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Variable(name) if SUPERGLOBALS.contains(&name.as_str()) => {
+                self.superglobals.insert(name.clone());
+            }
+            Expression::FunctionCall { name, .. } if RESPONSE_FUNCTIONS.contains(&name.as_str()) => {
+                self.response = true;
+            }
+            _ => {}
+        }
+        visit::walk_expression(self, expr);
+    }
+}
+
+fn mem(offset: u64) -> MemArg {
+    MemArg { offset, align: 0, memory_index: 0 }
+}
+
+impl Compiler {
+    /// Allocate the superglobals the program uses (all of them for a request
+    /// handler) and the response state, and add the helpers behind them
+    pub(super) fn allocate_http_state(&mut self, program: &Program) -> Result<(), String> {
+        let mut scan = HttpScan::default();
+        visit::walk_program(&mut scan, program);
+        let handler = self.options.http_handler_enabled();
+
+        for name in SUPERGLOBALS {
+            if handler || scan.superglobals.contains(name) {
+                let global_idx = self.add_php_value_global();
+                self.superglobals.insert(name.to_string(), global_idx);
+            }
+        }
+        if !handler && !scan.response {
+            return Ok(());
+        }
+
+        let array_ref = ValType::Ref(RefType {
+            nullable: true,
+            heap_type: HeapType::Concrete(self.gc_types.php_array),
+        });
+        let response = ResponseState {
+            status: self.builder.add_global(ValType::I32, true, Instruction::I32Const(200)),
+            headers: self.builder.add_global(array_ref, true, Instruction::RefNull(HeapType::Concrete(self.gc_types.php_array))),
+            header_count: self.builder.add_global(ValType::I32, true, Instruction::I32Const(0)),
+            headers_sent: self.builder.add_global(ValType::I32, true, Instruction::I32Const(0)),
+            input: self.add_php_value_global(),
+            request_time: self.builder.add_global(ValType::I64, true, Instruction::I64Const(0)),
+            header_fn: self.reserve_http_helper("header"),
+            setcookie_fn: self.reserve_http_helper("setcookie"),
+            rawurlencode_fn: self.reserve_http_helper("rawurlencode"),
+            cookie_date_fn: self.reserve_http_helper("cookie_date"),
+        };
+        self.response = Some(response);
+
+        self.add_header_function(response)?;
+        self.add_setcookie_function(response)?;
+        self.add_rawurlencode_function(response)?;
+        self.add_cookie_date_function(response)
+    }
+
+    fn reserve_http_helper(&mut self, name: &str) -> u32 {
+        let func_idx = self.builder.reserve_function_index();
+        self.name_function(func_idx, &format!("php_{}", name));
+        func_idx
+    }
+
+    fn response_state(&self) -> Result<ResponseState, String> {
+        self.response.ok_or_else(|| "The response state was not allocated".to_string())
+    }
+
+    /// Bind the superglobals in the scope being compiled
+    pub(super) fn bind_superglobals(&mut self) {
+        let superglobals: Vec<(String, u32)> = self.superglobals.iter()
+            .map(|(name, &global_idx)| (name.clone(), global_idx))
+            .collect();
+        for (name, global_idx) in superglobals {
+            self.bind_global(name, global_idx);
+        }
+    }
+
+    /// Superglobals that no request filled in start out as empty arrays
+    pub(super) fn init_superglobals(&mut self) {
+        let mut globals: Vec<u32> = self.superglobals.values().copied().collect();
+        globals.sort();
+        for global_idx in globals {
+            self.emit(Instruction::GlobalGet(global_idx));
+            self.emit(Instruction::RefIsNull);
+            self.emit(Instruction::If(BlockType::Empty));
+            self.emit(Instruction::Call(self.create_hash_array_fn_idx));
+            self.emit(Instruction::GlobalSet(global_idx));
+            self.emit(Instruction::End);
+        }
+    }
+
+    // ===== BUILTINS =====
+
+    /// header(), http_response_code(), headers_sent(), setcookie()
+    pub(super) fn compile_response_builtin(&mut self, name: &str, args: Vec<Expression>) -> Result<bool, String> {
+        let response = self.response_state()?;
+        match name {
+            "header" => {
+                if args.is_empty() || args.len() > 3 {
+                    return Err("header() expects 1 to 3 parameters".to_string());
+                }
+                let mut args = args.into_iter();
+                self.compile_expression(args.next().unwrap())?;
+                match args.next() {
+                    Some(replace) => self.compile_expression(replace)?,
+                    None => {
+                        self.emit(Instruction::I32Const(1));
+                        self.emit(Instruction::Call(self.create_bool_fn_idx));
+                    }
+                }
+                match args.next() {
+                    Some(code) => self.compile_expression(code)?,
+                    None => {
+                        self.emit(Instruction::I64Const(0));
+                        self.emit(Instruction::Call(self.create_int_fn_idx));
+                    }
+                }
+                self.emit(Instruction::Call(response.header_fn));
+            }
+            "http_response_code" => match args.len() {
+                0 => {
+                    self.emit(Instruction::GlobalGet(response.status));
+                    self.emit(Instruction::I64ExtendI32S);
+                    self.emit(Instruction::Call(self.create_int_fn_idx));
+                }
+                1 => {
+                    // The previous code, or false once the headers are sent
+                    let code_local = self.allocate_local(ValType::I32);
+                    self.compile_expression(args.into_iter().next().unwrap())?;
+                    self.emit(Instruction::Call(self.to_int_fn_idx));
+                    self.emit(Instruction::StructGet {
+                        struct_type_index: self.gc_types.php_value,
+                        field_index: PHPVALUE_INT,
+                    });
+                    self.emit(Instruction::I32WrapI64);
+                    self.emit(Instruction::LocalSet(code_local));
+                    self.emit(Instruction::GlobalGet(response.headers_sent));
+                    self.emit(Instruction::If(BlockType::Result(self.get_php_value_type())));
+                    self.emit(Instruction::I32Const(0));
+                    self.emit(Instruction::Call(self.create_bool_fn_idx));
+                    self.emit(Instruction::Else);
+                    self.emit(Instruction::GlobalGet(response.status));
+                    self.emit(Instruction::I64ExtendI32S);
+                    self.emit(Instruction::Call(self.create_int_fn_idx));
+                    self.emit(Instruction::LocalGet(code_local));
+                    self.emit(Instruction::GlobalSet(response.status));
+                    self.emit(Instruction::End);
+                    self.free_local(code_local, ValType::I32);
+                }
+                _ => return Err("http_response_code() expects at most 1 parameter".to_string()),
+            },
+            "headers_sent" => {
+                if !args.is_empty() {
+                    return Err("headers_sent() does not support the $filename and $line parameters".to_string());
+                }
+                self.emit(Instruction::GlobalGet(response.headers_sent));
+                self.emit(Instruction::Call(self.create_bool_fn_idx));
+            }
+            "setcookie" => self.compile_setcookie(response, args)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// setcookie($name, $value, $expires, $path, $domain, $secure, $httponly)
+    /// or setcookie($name, $value, [options]) with a literal options array
+    fn compile_setcookie(&mut self, response: ResponseState, args: Vec<Expression>) -> Result<(), String> {
+        if args.is_empty() || args.len() > 7 {
+            return Err("setcookie() expects 1 to 7 parameters".to_string());
+        }
+        let string = |s: &str| Expression::Literal(Literal::String(s.to_string()));
+        let int = |i: i64| Expression::Literal(Literal::Integer(i));
+        let boolean = |b: bool| Expression::Literal(Literal::Boolean(b));
+
+        let mut args = args.into_iter();
+        let name = args.next().unwrap();
+        let value = args.next().unwrap_or_else(|| string(""));
+        // name, value, expires, path, domain, secure, httponly, samesite
+        let mut params = vec![name, value, int(0), string(""), string(""), boolean(false), boolean(false), string("")];
+        match args.next() {
+            Some(Expression::Array(options)) => {
+                if args.next().is_some() {
+                    return Err("setcookie(): Expects exactly 3 arguments when argument #3 ($expires_or_options) is an array".to_string());
+                }
+                for ArrayElement { key, value } in options {
+                    let option = match key {
+                        Some(Expression::Literal(Literal::String(option))) => option.to_ascii_lowercase(),
+                        _ => return Err("setcookie() options must have literal string keys".to_string()),
+                    };
+                    let position = match option.as_str() {
+                        "expires" => 2,
+                        "path" => 3,
+                        "domain" => 4,
+                        "secure" => 5,
+                        "httponly" => 6,
+                        "samesite" => 7,
+                        _ => return Err(format!("setcookie(): option \"{}\" is invalid", option)),
+                    };
+                    params[position] = value;
+                }
+            }
+            Some(expires) => {
+                params[2] = expires;
+                for (position, arg) in (3..7).zip(args) {
+                    params[position] = arg;
+                }
+            }
+            None => {}
+        }
+
+        for param in params {
+            self.compile_expression(param)?;
+        }
+        self.emit(Instruction::Call(response.setcookie_fn));
+        Ok(())
+    }
+
+    /// file_get_contents('php://input'): the request body
+    pub(super) fn compile_file_get_contents(&mut self, args: Vec<Expression>) -> Result<bool, String> {
+        match args.as_slice() {
+            [Expression::Literal(Literal::String(path))] if path == "php://input" => {}
+            _ => return Err("file_get_contents() can only read php://input".to_string()),
+        }
+        let response = self.response_state()?;
+        self.emit(Instruction::GlobalGet(response.input));
+        self.emit(Instruction::RefIsNull);
+        self.emit(Instruction::If(BlockType::Result(self.get_php_value_type())));
+        self.compile_string_literal("")?;
+        self.emit(Instruction::Else);
+        self.emit(Instruction::GlobalGet(response.input));
+        self.emit(Instruction::End);
+        Ok(true)
+    }
+
+    // ===== RUNTIME HELPERS =====
+
+    /// Lowercase the ASCII byte on the stack, using `temp` as scratch
+    fn emit_ascii_lowercase(&mut self, temp: u32) {
+        self.emit(Instruction::LocalTee(temp));
+        self.emit(Instruction::I32Const(b'A' as i32));
+        self.emit(Instruction::I32Sub);
+        self.emit(Instruction::I32Const(26));
+        self.emit(Instruction::I32LtU);
+        self.emit(Instruction::I32Const(5));
+        self.emit(Instruction::I32Shl);
+        self.emit(Instruction::LocalGet(temp));
+        self.emit(Instruction::I32Or);
+    }
+
+    /// Push the byte array of a PhpValue converted to string
+    fn emit_string_bytes(&mut self, value_local: u32) {
+        self.emit(Instruction::LocalGet(value_local));
+        self.emit(Instruction::Call(self.to_string_fn_idx));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_STRING,
+        });
+    }
+
+    /// Append the string on top of a local holding a string PhpValue
+    fn emit_append(&mut self, result_local: u32) {
+        let value_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalSet(value_local));
+        self.emit(Instruction::LocalGet(result_local));
+        self.emit(Instruction::LocalGet(value_local));
+        self.emit(Instruction::Call(self.concat_fn_idx));
+        self.emit(Instruction::LocalSet(result_local));
+        self.free_local(value_local, self.get_php_value_type());
+    }
+
+    fn emit_append_literal(&mut self, result_local: u32, s: &str) -> Result<(), String> {
+        self.compile_string_literal(s)?;
+        self.emit_append(result_local);
+        Ok(())
+    }
+
+    /// header(line, replace, code) -> null. A status line sets the status,
+    /// `Location` turns a non-redirect status into 302, and with `replace`
+    /// the line replaces earlier headers of the same name. Lines with a
+    /// newline and lines sent after output are dropped.
+    fn add_header_function(&mut self, response: ResponseState) -> Result<(), String> {
+        self.compile_helper_function(response.header_fn, 3, |this| {
+            let string_type = this.get_string_type();
+            let line = this.allocate_local(string_type);
+            let other = this.allocate_local(string_type);
+            let length = this.allocate_local(ValType::I32);
+            let colon = this.allocate_local(ValType::I32);
+            let i = this.allocate_local(ValType::I32);
+            let j = this.allocate_local(ValType::I32);
+            let kept = this.allocate_local(ValType::I32);
+            let byte = this.allocate_local(ValType::I32);
+            let code = this.allocate_local(ValType::I32);
+            let same_name = this.allocate_local(ValType::I32);
+            let grown = this.allocate_local(ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(this.gc_types.php_array),
+            }));
+
+            this.emit(Instruction::GlobalGet(response.headers_sent));
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::Call(this.create_null_fn_idx));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+
+            this.emit_string_bytes(0);
+            this.emit(Instruction::LocalTee(line));
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::LocalSet(length));
+
+            // One header per call: a newline would smuggle in another one.
+            // Also find the colon ending the name.
+            this.emit(Instruction::I32Const(-1));
+            this.emit(Instruction::LocalSet(colon));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalSet(i));
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::LocalGet(length));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::LocalGet(line));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::ArrayGetU(this.gc_types.php_string));
+            this.emit(Instruction::LocalTee(byte));
+            this.emit(Instruction::I32Const(b'\n' as i32));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::LocalGet(byte));
+            this.emit(Instruction::I32Const(b'\r' as i32));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::I32Or);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::Call(this.create_null_fn_idx));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalGet(byte));
+            this.emit(Instruction::I32Const(b':' as i32));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::LocalGet(colon));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::I32LtS);
+            this.emit(Instruction::I32And);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::LocalSet(colon));
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(i));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+
+            // "HTTP/1.1 404 Not Found": the digits after the first space are the status
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::LocalSet(same_name));
+            for (position, expected) in b"http/".iter().enumerate() {
+                this.emit(Instruction::LocalGet(length));
+                this.emit(Instruction::I32Const(position as i32));
+                this.emit(Instruction::I32GtU);
+                this.emit(Instruction::If(BlockType::Result(ValType::I32)));
+                this.emit(Instruction::LocalGet(line));
+                this.emit(Instruction::I32Const(position as i32));
+                this.emit(Instruction::ArrayGetU(this.gc_types.php_string));
+                this.emit_ascii_lowercase(byte);
+                this.emit(Instruction::I32Const(*expected as i32));
+                this.emit(Instruction::I32Eq);
+                this.emit(Instruction::Else);
+                this.emit(Instruction::I32Const(0));
+                this.emit(Instruction::End);
+                this.emit(Instruction::LocalGet(same_name));
+                this.emit(Instruction::I32And);
+                this.emit(Instruction::LocalSet(same_name));
+            }
+            this.emit(Instruction::LocalGet(same_name));
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalSet(code));
+            this.emit(Instruction::I32Const(5));
+            this.emit(Instruction::LocalSet(i));
+            // Skip to the first space
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::LocalGet(length));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::LocalGet(line));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::ArrayGetU(this.gc_types.php_string));
+            this.emit(Instruction::I32Const(b' ' as i32));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(i));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+            // Read the digits after it
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalTee(i));
+            this.emit(Instruction::LocalGet(length));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::LocalGet(line));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::ArrayGetU(this.gc_types.php_string));
+            this.emit(Instruction::I32Const(b'0' as i32));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::LocalTee(byte));
+            this.emit(Instruction::I32Const(10));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::LocalGet(code));
+            this.emit(Instruction::I32Const(10));
+            this.emit(Instruction::I32Mul);
+            this.emit(Instruction::LocalGet(byte));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(code));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalGet(code));
+            this.emit(Instruction::I32Const(100));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::I32Const(900));
+            this.emit(Instruction::I32LtU);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(code));
+            this.emit(Instruction::GlobalSet(response.status));
+            this.emit(Instruction::End);
+            this.emit(Instruction::Call(this.create_null_fn_idx));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+
+            // A line without a name is not a header
+            this.emit(Instruction::LocalGet(colon));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32LtS);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::Call(this.create_null_fn_idx));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+
+            // Location redirects unless the status already is 201 or 3xx
+            this.emit(Instruction::LocalGet(colon));
+            this.emit(Instruction::I32Const(8));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::LocalSet(same_name));
+            for (position, expected) in b"location".iter().enumerate() {
+                this.emit(Instruction::LocalGet(same_name));
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(line));
+                this.emit(Instruction::I32Const(position as i32));
+                this.emit(Instruction::ArrayGetU(this.gc_types.php_string));
+                this.emit_ascii_lowercase(byte);
+                this.emit(Instruction::I32Const(*expected as i32));
+                this.emit(Instruction::I32Eq);
+                this.emit(Instruction::LocalSet(same_name));
+                this.emit(Instruction::End);
+            }
+            this.emit(Instruction::LocalGet(same_name));
+            this.emit(Instruction::GlobalGet(response.status));
+            this.emit(Instruction::I32Const(201));
+            this.emit(Instruction::I32Ne);
+            this.emit(Instruction::I32And);
+            this.emit(Instruction::GlobalGet(response.status));
+            this.emit(Instruction::I32Const(300));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::I32Const(100));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::I32And);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::I32Const(302));
+            this.emit(Instruction::GlobalSet(response.status));
+            this.emit(Instruction::End);
+
+            // Replacing: keep only the headers with another name
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::Call(this.to_bool_fn_idx));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            });
+            this.emit(Instruction::I32WrapI64);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalSet(j));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalSet(kept));
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(j));
+            this.emit(Instruction::GlobalGet(response.header_count));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::GlobalGet(response.headers));
+            this.emit(Instruction::LocalGet(j));
+            this.emit(Instruction::ArrayGet(this.gc_types.php_array));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_STRING,
+            });
+            this.emit(Instruction::LocalSet(other));
+            // Compare the names up to and including the colon, ignoring case
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalSet(same_name));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalSet(i));
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::LocalGet(other));
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::LocalGet(line));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::ArrayGetU(this.gc_types.php_string));
+            this.emit_ascii_lowercase(byte);
+            this.emit(Instruction::LocalGet(other));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::ArrayGetU(this.gc_types.php_string));
+            this.emit_ascii_lowercase(code);
+            this.emit(Instruction::I32Ne);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::LocalGet(colon));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::LocalSet(same_name));
+            this.emit(Instruction::Br(2));
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(i));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalGet(same_name));
+            this.emit(Instruction::I32Eqz);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::GlobalGet(response.headers));
+            this.emit(Instruction::LocalGet(kept));
+            this.emit(Instruction::GlobalGet(response.headers));
+            this.emit(Instruction::LocalGet(j));
+            this.emit(Instruction::ArrayGet(this.gc_types.php_array));
+            this.emit(Instruction::ArraySet(this.gc_types.php_array));
+            this.emit(Instruction::LocalGet(kept));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(kept));
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalGet(j));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(j));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalGet(kept));
+            this.emit(Instruction::GlobalSet(response.header_count));
+            this.emit(Instruction::End);
+
+            // Make room: the list starts out null and doubles when full
+            this.emit(Instruction::GlobalGet(response.headers));
+            this.emit(Instruction::RefIsNull);
+            this.emit(Instruction::If(BlockType::Result(ValType::I32)));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::Else);
+            this.emit(Instruction::GlobalGet(response.headers));
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::End);
+            this.emit(Instruction::GlobalGet(response.header_count));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::GlobalGet(response.header_count));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Shl);
+            this.emit(Instruction::I32Const(8));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::ArrayNewDefault(this.gc_types.php_array));
+            this.emit(Instruction::LocalSet(grown));
+            this.emit(Instruction::GlobalGet(response.header_count));
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(grown));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::GlobalGet(response.headers));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::GlobalGet(response.header_count));
+            this.emit(Instruction::ArrayCopy {
+                array_type_index_dst: this.gc_types.php_array,
+                array_type_index_src: this.gc_types.php_array,
+            });
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalGet(grown));
+            this.emit(Instruction::GlobalSet(response.headers));
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::GlobalGet(response.headers));
+            this.emit(Instruction::GlobalGet(response.header_count));
+            this.emit(Instruction::LocalGet(line));
+            this.emit(Instruction::Call(this.create_string_fn_idx));
+            this.emit(Instruction::ArraySet(this.gc_types.php_array));
+            this.emit(Instruction::GlobalGet(response.header_count));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::GlobalSet(response.header_count));
+
+            // An explicit response code wins
+            this.emit(Instruction::LocalGet(2));
+            this.emit(Instruction::Call(this.to_int_fn_idx));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            });
+            this.emit(Instruction::I64Const(0));
+            this.emit(Instruction::I64GtS);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(2));
+            this.emit(Instruction::Call(this.to_int_fn_idx));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            });
+            this.emit(Instruction::I32WrapI64);
+            this.emit(Instruction::GlobalSet(response.status));
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::Call(this.create_null_fn_idx));
+            Ok(())
+        })
+    }
+
+    /// setcookie(name, value, expires, path, domain, secure, httponly, samesite) -> true,
+    /// worded like PHP's Set-Cookie header. An empty value deletes the cookie.
+    fn add_setcookie_function(&mut self, response: ResponseState) -> Result<(), String> {
+        self.compile_helper_function(response.setcookie_fn, 8, |this| {
+            let php_value_type = this.get_php_value_type();
+            let line = this.allocate_local(php_value_type);
+            let expires = this.allocate_local(ValType::I64);
+
+            this.emit_string_bytes(0);
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::I32Eqz);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.compile_string_literal("setcookie(): Argument #1 ($name) cannot be empty")?;
+            this.emit_uncaught_error("ValueError")?;
+            this.emit(Instruction::End);
+
+            this.compile_string_literal("Set-Cookie: ")?;
+            this.emit(Instruction::LocalSet(line));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::Call(this.to_string_fn_idx));
+            this.emit_append(line);
+            this.emit_append_literal(line, "=")?;
+
+            this.emit_string_bytes(1);
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::I32Eqz);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit_append_literal(line, "deleted; expires=Thu, 01 Jan 1970 00:00:01 GMT; Max-Age=0")?;
+            this.emit(Instruction::Else);
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::Call(response.rawurlencode_fn));
+            this.emit_append(line);
+            this.emit(Instruction::LocalGet(2));
+            this.emit(Instruction::Call(this.to_int_fn_idx));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            });
+            this.emit(Instruction::LocalTee(expires));
+            this.emit(Instruction::I64Const(0));
+            this.emit(Instruction::I64Ne);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit_append_literal(line, "; expires=")?;
+            this.emit(Instruction::LocalGet(expires));
+            this.emit(Instruction::Call(response.cookie_date_fn));
+            this.emit_append(line);
+            this.emit_append_literal(line, "; Max-Age=")?;
+            // Seconds from the request until expiry, at least 0
+            this.emit(Instruction::LocalGet(expires));
+            this.emit(Instruction::GlobalGet(response.request_time));
+            this.emit(Instruction::I64Sub);
+            this.emit(Instruction::LocalSet(expires));
+            this.emit(Instruction::LocalGet(expires));
+            this.emit(Instruction::I64Const(0));
+            this.emit(Instruction::LocalGet(expires));
+            this.emit(Instruction::I64Const(0));
+            this.emit(Instruction::I64GtS);
+            this.emit(Instruction::Select);
+            this.emit(Instruction::Call(this.int_to_string_fn_idx));
+            this.emit(Instruction::Call(this.create_string_fn_idx));
+            this.emit_append(line);
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+
+            for (param, attribute) in [(3, "; path="), (4, "; domain=")] {
+                this.emit_string_bytes(param);
+                this.emit(Instruction::ArrayLen);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit_append_literal(line, attribute)?;
+                this.emit(Instruction::LocalGet(param));
+                this.emit(Instruction::Call(this.to_string_fn_idx));
+                this.emit_append(line);
+                this.emit(Instruction::End);
+            }
+            for (param, flag) in [(5, "; secure"), (6, "; HttpOnly")] {
+                this.emit(Instruction::LocalGet(param));
+                this.emit(Instruction::Call(this.to_bool_fn_idx));
+                this.emit(Instruction::StructGet {
+                    struct_type_index: this.gc_types.php_value,
+                    field_index: PHPVALUE_INT,
+                });
+                this.emit(Instruction::I32WrapI64);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit_append_literal(line, flag)?;
+                this.emit(Instruction::End);
+            }
+            this.emit_string_bytes(7);
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit_append_literal(line, "; SameSite=")?;
+            this.emit(Instruction::LocalGet(7));
+            this.emit(Instruction::Call(this.to_string_fn_idx));
+            this.emit_append(line);
+            this.emit(Instruction::End);
+
+            // Every cookie gets its own Set-Cookie header
+            this.emit(Instruction::LocalGet(line));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::Call(this.create_bool_fn_idx));
+            this.emit(Instruction::I64Const(0));
+            this.emit(Instruction::Call(this.create_int_fn_idx));
+            this.emit(Instruction::Call(response.header_fn));
+            this.emit(Instruction::Drop);
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::Call(this.create_bool_fn_idx));
+            Ok(())
+        })
+    }
+
+    /// rawurlencode(value) -> string: everything but letters, digits and
+    /// `-_.~` becomes %XX
+    fn add_rawurlencode_function(&mut self, response: ResponseState) -> Result<(), String> {
+        self.compile_helper_function(response.rawurlencode_fn, 1, |this| {
+            let string_type = this.get_string_type();
+            let input = this.allocate_local(string_type);
+            let output = this.allocate_local(string_type);
+            let i = this.allocate_local(ValType::I32);
+            let o = this.allocate_local(ValType::I32);
+            let byte = this.allocate_local(ValType::I32);
+            let digit = this.allocate_local(ValType::I32);
+
+            // Leaves whether `byte` is passed through as is on the stack
+            let emit_unreserved = |this: &mut Self| {
+                this.emit(Instruction::LocalGet(byte));
+                this.emit(Instruction::I32Const(0x20));
+                this.emit(Instruction::I32Or);
+                this.emit(Instruction::I32Const(b'a' as i32));
+                this.emit(Instruction::I32Sub);
+                this.emit(Instruction::I32Const(26));
+                this.emit(Instruction::I32LtU);
+                this.emit(Instruction::LocalGet(byte));
+                this.emit(Instruction::I32Const(b'0' as i32));
+                this.emit(Instruction::I32Sub);
+                this.emit(Instruction::I32Const(10));
+                this.emit(Instruction::I32LtU);
+                this.emit(Instruction::I32Or);
+                for c in [b'-', b'_', b'.', b'~'] {
+                    this.emit(Instruction::LocalGet(byte));
+                    this.emit(Instruction::I32Const(c as i32));
+                    this.emit(Instruction::I32Eq);
+                    this.emit(Instruction::I32Or);
+                }
+            };
+            let emit_loop = |this: &mut Self, body: &dyn Fn(&mut Self)| {
+                this.emit(Instruction::I32Const(0));
+                this.emit(Instruction::LocalSet(i));
+                this.emit(Instruction::Block(BlockType::Empty));
+                this.emit(Instruction::Loop(BlockType::Empty));
+                this.emit(Instruction::LocalGet(i));
+                this.emit(Instruction::LocalGet(input));
+                this.emit(Instruction::ArrayLen);
+                this.emit(Instruction::I32GeU);
+                this.emit(Instruction::BrIf(1));
+                this.emit(Instruction::LocalGet(input));
+                this.emit(Instruction::LocalGet(i));
+                this.emit(Instruction::ArrayGetU(this.gc_types.php_string));
+                this.emit(Instruction::LocalSet(byte));
+                body(this);
+                this.emit(Instruction::LocalGet(i));
+                this.emit(Instruction::I32Const(1));
+                this.emit(Instruction::I32Add);
+                this.emit(Instruction::LocalSet(i));
+                this.emit(Instruction::Br(0));
+                this.emit(Instruction::End);
+                this.emit(Instruction::End);
+            };
+
+            this.emit_string_bytes(0);
+            this.emit(Instruction::LocalSet(input));
+
+            // Measure: 1 byte per unreserved character, 3 for the others
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalSet(o));
+            emit_loop(this, &|this| {
+                this.emit(Instruction::LocalGet(o));
+                this.emit(Instruction::I32Const(1));
+                this.emit(Instruction::I32Const(3));
+                emit_unreserved(this);
+                this.emit(Instruction::Select);
+                this.emit(Instruction::I32Add);
+                this.emit(Instruction::LocalSet(o));
+            });
+            this.emit(Instruction::LocalGet(o));
+            this.emit(Instruction::ArrayNewDefault(this.gc_types.php_string));
+            this.emit(Instruction::LocalSet(output));
+
+            // Encode
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalSet(o));
+            emit_loop(this, &|this| {
+                emit_unreserved(this);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(output));
+                this.emit(Instruction::LocalGet(o));
+                this.emit(Instruction::LocalGet(byte));
+                this.emit(Instruction::ArraySet(this.gc_types.php_string));
+                this.emit(Instruction::LocalGet(o));
+                this.emit(Instruction::I32Const(1));
+                this.emit(Instruction::I32Add);
+                this.emit(Instruction::LocalSet(o));
+                this.emit(Instruction::Else);
+                this.emit(Instruction::LocalGet(output));
+                this.emit(Instruction::LocalGet(o));
+                this.emit(Instruction::I32Const(b'%' as i32));
+                this.emit(Instruction::ArraySet(this.gc_types.php_string));
+                for (offset, shift) in [(1, 4), (2, 0)] {
+                    this.emit(Instruction::LocalGet(output));
+                    this.emit(Instruction::LocalGet(o));
+                    this.emit(Instruction::I32Const(offset));
+                    this.emit(Instruction::I32Add);
+                    // Uppercase hex digit
+                    this.emit(Instruction::LocalGet(byte));
+                    this.emit(Instruction::I32Const(shift));
+                    this.emit(Instruction::I32ShrU);
+                    this.emit(Instruction::I32Const(15));
+                    this.emit(Instruction::I32And);
+                    this.emit(Instruction::LocalTee(digit));
+                    this.emit(Instruction::I32Const(b'0' as i32));
+                    this.emit(Instruction::I32Add);
+                    this.emit(Instruction::LocalGet(digit));
+                    this.emit(Instruction::I32Const((b'A' - 10) as i32));
+                    this.emit(Instruction::I32Add);
+                    this.emit(Instruction::LocalGet(digit));
+                    this.emit(Instruction::I32Const(10));
+                    this.emit(Instruction::I32LtU);
+                    this.emit(Instruction::Select);
+                    this.emit(Instruction::ArraySet(this.gc_types.php_string));
+                }
+                this.emit(Instruction::LocalGet(o));
+                this.emit(Instruction::I32Const(3));
+                this.emit(Instruction::I32Add);
+                this.emit(Instruction::LocalSet(o));
+                this.emit(Instruction::End);
+            });
+
+            this.emit(Instruction::LocalGet(output));
+            this.emit(Instruction::Call(this.create_string_fn_idx));
+            Ok(())
+        })
+    }
+
+    /// cookie_date(timestamp: i64) -> "Thu, 01 Jan 1970 00:00:01 GMT"
+    fn add_cookie_date_function(&mut self, response: ResponseState) -> Result<(), String> {
+        let php_value_type = self.get_php_value_type();
+        self.compile_typed_helper_function(response.cookie_date_fn, vec![ValType::I64], vec![php_value_type], |this| {
+            let days = this.allocate_local(ValType::I64);
+            let seconds = this.allocate_local(ValType::I64);
+            let weekday = this.allocate_local(ValType::I64);
+            let era = this.allocate_local(ValType::I64);
+            let day_of_era = this.allocate_local(ValType::I64);
+            let year_of_era = this.allocate_local(ValType::I64);
+            let day_of_year = this.allocate_local(ValType::I64);
+            let month_index = this.allocate_local(ValType::I64);
+            let year = this.allocate_local(ValType::I64);
+            let month = this.allocate_local(ValType::I64);
+            let day = this.allocate_local(ValType::I64);
+            let name = this.allocate_local(ValType::I32);
+            let output = this.allocate_local(this.get_string_type());
+
+            // Floored division by a day
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::I64Const(86400));
+            this.emit(Instruction::I64DivS);
+            this.emit(Instruction::LocalSet(days));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::I64Const(86400));
+            this.emit(Instruction::I64RemS);
+            this.emit(Instruction::LocalTee(seconds));
+            this.emit(Instruction::I64Const(0));
+            this.emit(Instruction::I64LtS);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(seconds));
+            this.emit(Instruction::I64Const(86400));
+            this.emit(Instruction::I64Add);
+            this.emit(Instruction::LocalSet(seconds));
+            this.emit(Instruction::LocalGet(days));
+            this.emit(Instruction::I64Const(1));
+            this.emit(Instruction::I64Sub);
+            this.emit(Instruction::LocalSet(days));
+            this.emit(Instruction::End);
+
+            // 1970-01-01 was a Thursday
+            this.emit(Instruction::LocalGet(days));
+            this.emit(Instruction::I64Const(4));
+            this.emit(Instruction::I64Add);
+            this.emit(Instruction::I64Const(7));
+            this.emit(Instruction::I64RemS);
+            this.emit(Instruction::LocalTee(weekday));
+            this.emit(Instruction::I64Const(0));
+            this.emit(Instruction::I64LtS);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(weekday));
+            this.emit(Instruction::I64Const(7));
+            this.emit(Instruction::I64Add);
+            this.emit(Instruction::LocalSet(weekday));
+            this.emit(Instruction::End);
+
+            // Civil date from days since the epoch (eras of 400 years starting in March)
+            this.emit(Instruction::LocalGet(days));
+            this.emit(Instruction::I64Const(719468));
+            this.emit(Instruction::I64Add);
+            this.emit(Instruction::LocalTee(days));
+            this.emit(Instruction::LocalGet(days));
+            this.emit(Instruction::I64Const(146096));
+            this.emit(Instruction::I64Sub);
+            this.emit(Instruction::LocalGet(days));
+            this.emit(Instruction::I64Const(0));
+            this.emit(Instruction::I64GeS);
+            this.emit(Instruction::Select);
+            this.emit(Instruction::I64Const(146097));
+            this.emit(Instruction::I64DivS);
+            this.emit(Instruction::LocalTee(era));
+            this.emit(Instruction::I64Const(146097));
+            this.emit(Instruction::I64Mul);
+            this.emit(Instruction::LocalGet(days));
+            this.emit(Instruction::I64Sub);
+            this.emit(Instruction::I64Const(-1));
+            this.emit(Instruction::I64Mul);
+            this.emit(Instruction::LocalSet(day_of_era));
+            // (doe - doe/1460 + doe/36524 - doe/146096) / 365
+            this.emit(Instruction::LocalGet(day_of_era));
+            for (divisor, add) in [(1460, false), (36524, true), (146096, false)] {
+                this.emit(Instruction::LocalGet(day_of_era));
+                this.emit(Instruction::I64Const(divisor));
+                this.emit(Instruction::I64DivS);
+                this.emit(if add { Instruction::I64Add } else { Instruction::I64Sub });
+            }
+            this.emit(Instruction::I64Const(365));
+            this.emit(Instruction::I64DivS);
+            this.emit(Instruction::LocalTee(year_of_era));
+            this.emit(Instruction::LocalGet(era));
+            this.emit(Instruction::I64Const(400));
+            this.emit(Instruction::I64Mul);
+            this.emit(Instruction::I64Add);
+            this.emit(Instruction::LocalSet(year));
+            // doy = doe - (365 * yoe + yoe/4 - yoe/100)
+            this.emit(Instruction::LocalGet(day_of_era));
+            this.emit(Instruction::LocalGet(year_of_era));
+            this.emit(Instruction::I64Const(365));
+            this.emit(Instruction::I64Mul);
+            this.emit(Instruction::LocalGet(year_of_era));
+            this.emit(Instruction::I64Const(4));
+            this.emit(Instruction::I64DivS);
+            this.emit(Instruction::I64Add);
+            this.emit(Instruction::LocalGet(year_of_era));
+            this.emit(Instruction::I64Const(100));
+            this.emit(Instruction::I64DivS);
+            this.emit(Instruction::I64Sub);
+            this.emit(Instruction::I64Sub);
+            this.emit(Instruction::LocalTee(day_of_year));
+            // mp = (5 * doy + 2) / 153
+            this.emit(Instruction::I64Const(5));
+            this.emit(Instruction::I64Mul);
+            this.emit(Instruction::I64Const(2));
+            this.emit(Instruction::I64Add);
+            this.emit(Instruction::I64Const(153));
+            this.emit(Instruction::I64DivS);
+            this.emit(Instruction::LocalSet(month_index));
+            // d = doy - (153 * mp + 2) / 5 + 1
+            this.emit(Instruction::LocalGet(day_of_year));
+            this.emit(Instruction::LocalGet(month_index));
+            this.emit(Instruction::I64Const(153));
+            this.emit(Instruction::I64Mul);
+            this.emit(Instruction::I64Const(2));
+            this.emit(Instruction::I64Add);
+            this.emit(Instruction::I64Const(5));
+            this.emit(Instruction::I64DivS);
+            this.emit(Instruction::I64Sub);
+            this.emit(Instruction::I64Const(1));
+            this.emit(Instruction::I64Add);
+            this.emit(Instruction::LocalSet(day));
+            // m = mp < 10 ? mp + 3 : mp - 9, and January and February count to the next year
+            this.emit(Instruction::LocalGet(month_index));
+            this.emit(Instruction::I64Const(3));
+            this.emit(Instruction::I64Add);
+            this.emit(Instruction::LocalGet(month_index));
+            this.emit(Instruction::I64Const(9));
+            this.emit(Instruction::I64Sub);
+            this.emit(Instruction::LocalGet(month_index));
+            this.emit(Instruction::I64Const(10));
+            this.emit(Instruction::I64LtS);
+            this.emit(Instruction::Select);
+            this.emit(Instruction::LocalTee(month));
+            this.emit(Instruction::I64Const(2));
+            this.emit(Instruction::I64LeS);
+            this.emit(Instruction::I64ExtendI32U);
+            this.emit(Instruction::LocalGet(year));
+            this.emit(Instruction::I64Add);
+            this.emit(Instruction::LocalSet(year));
+
+            this.emit(Instruction::I32Const(29));
+            this.emit(Instruction::ArrayNewDefault(this.gc_types.php_string));
+            this.emit(Instruction::LocalSet(output));
+            let set_byte = |this: &mut Self, position: i32, emit_byte: &dyn Fn(&mut Self)| {
+                this.emit(Instruction::LocalGet(output));
+                this.emit(Instruction::I32Const(position));
+                emit_byte(this);
+                this.emit(Instruction::ArraySet(this.gc_types.php_string));
+            };
+            // Three-letter names, packed into an i32 by index
+            let emit_name = |this: &mut Self, index: u32, names: &[&str], position: i32| {
+                this.emit(Instruction::I32Const(0));
+                this.emit(Instruction::LocalSet(name));
+                for (i, text) in names.iter().enumerate() {
+                    let bytes = text.as_bytes();
+                    let packed = bytes[0] as i32 | (bytes[1] as i32) << 8 | (bytes[2] as i32) << 16;
+                    this.emit(Instruction::LocalGet(index));
+                    this.emit(Instruction::I64Const(i as i64));
+                    this.emit(Instruction::I64Eq);
+                    this.emit(Instruction::If(BlockType::Empty));
+                    this.emit(Instruction::I32Const(packed));
+                    this.emit(Instruction::LocalSet(name));
+                    this.emit(Instruction::End);
+                }
+                for shift in 0..3 {
+                    set_byte(this, position + shift, &|this| {
+                        this.emit(Instruction::LocalGet(name));
+                        this.emit(Instruction::I32Const(shift * 8));
+                        this.emit(Instruction::I32ShrU);
+                        this.emit(Instruction::I32Const(0xff));
+                        this.emit(Instruction::I32And);
+                    });
+                }
+            };
+            // Zero-padded decimal digits of a local
+            let emit_digits = |this: &mut Self, value: u32, width: u32, position: i32| {
+                for i in 0..width {
+                    let divisor = 10i64.pow(width - 1 - i);
+                    set_byte(this, position + i as i32, &|this| {
+                        this.emit(Instruction::LocalGet(value));
+                        this.emit(Instruction::I64Const(divisor));
+                        this.emit(Instruction::I64DivS);
+                        this.emit(Instruction::I64Const(10));
+                        this.emit(Instruction::I64RemS);
+                        this.emit(Instruction::I32WrapI64);
+                        this.emit(Instruction::I32Const(b'0' as i32));
+                        this.emit(Instruction::I32Add);
+                    });
+                }
+            };
+            let emit_literal = |this: &mut Self, text: &str, position: i32| {
+                for (i, byte) in text.bytes().enumerate() {
+                    set_byte(this, position + i as i32, &|this| this.emit(Instruction::I32Const(byte as i32)));
+                }
+            };
+
+            emit_name(this, weekday, &["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"], 0);
+            emit_literal(this, ", ", 3);
+            emit_digits(this, day, 2, 5);
+            emit_literal(this, " ", 7);
+            this.emit(Instruction::LocalGet(month));
+            this.emit(Instruction::I64Const(1));
+            this.emit(Instruction::I64Sub);
+            this.emit(Instruction::LocalSet(month));
+            emit_name(this, month, &["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"], 8);
+            emit_literal(this, " ", 11);
+            emit_digits(this, year, 4, 12);
+            emit_literal(this, " ", 16);
+            // Hours, minutes and seconds reuse the date locals
+            this.emit(Instruction::LocalGet(seconds));
+            this.emit(Instruction::I64Const(3600));
+            this.emit(Instruction::I64DivU);
+            this.emit(Instruction::LocalSet(day));
+            this.emit(Instruction::LocalGet(seconds));
+            this.emit(Instruction::I64Const(60));
+            this.emit(Instruction::I64DivU);
+            this.emit(Instruction::I64Const(60));
+            this.emit(Instruction::I64RemU);
+            this.emit(Instruction::LocalSet(month));
+            this.emit(Instruction::LocalGet(seconds));
+            this.emit(Instruction::I64Const(60));
+            this.emit(Instruction::I64RemU);
+            this.emit(Instruction::LocalSet(seconds));
+            emit_digits(this, day, 2, 17);
+            emit_literal(this, ":", 19);
+            emit_digits(this, month, 2, 20);
+            emit_literal(this, ":", 22);
+            emit_digits(this, seconds, 2, 23);
+            emit_literal(this, " GMT", 25);
+
+            this.emit(Instruction::LocalGet(output));
+            this.emit(Instruction::Call(this.create_string_fn_idx));
+            Ok(())
+        })
+    }
+
+    // ===== REQUEST HANDLER =====
+
+    /// Export `handle_request(len) -> ptr` around the main function, and the
+    /// `request_buffer` offset hosts write the request at
+    pub(super) fn add_request_handler(&mut self, main_idx: u32) -> Result<(), String> {
+        let response = self.response_state()?;
+        let (string_from_memory_fn, write_bytes_fn) = self.memory_string_helpers()?;
+        let request_store_fn = self.reserve_http_helper("request_store");
+        let handle_request_fn = self.builder.reserve_function_index();
+        self.name_function(handle_request_fn, "handle_request");
+
+        self.add_request_store_function(request_store_fn)?;
+
+        let mut targets: Vec<(usize, u32)> = SUPERGLOBALS.iter().enumerate()
+            .filter_map(|(target, name)| Some((target, *self.superglobals.get(*name)?)))
+            .collect();
+        let request_global = targets.pop()
+            .filter(|(target, _)| *target == REQUEST_TARGET)
+            .map(|(_, global_idx)| global_idx)
+            .ok_or_else(|| "A request handler needs every superglobal".to_string())?;
+
+        self.compile_typed_helper_function(handle_request_fn, vec![ValType::I32], vec![ValType::I32], |this| {
+            let php_value_type = this.get_php_value_type();
+            let ptr = this.allocate_local(ValType::I32);
+            let count = this.allocate_local(ValType::I32);
+            let target = this.allocate_local(ValType::I32);
+            let shape = this.allocate_local(ValType::I32);
+            let length = this.allocate_local(ValType::I32);
+            let key = this.allocate_local(php_value_type);
+            let sub = this.allocate_local(php_value_type);
+            let value = this.allocate_local(php_value_type);
+            let header = this.allocate_local(this.get_string_type());
+            let out = this.allocate_local(ValType::I32);
+
+            // Reads a u32 length at ptr + offset and the string after it, moving ptr past both
+            let read_string = |this: &mut Self, offset: i32, into: u32| {
+                this.emit(Instruction::LocalGet(ptr));
+                this.emit(Instruction::I32Load(mem(offset as u64)));
+                this.emit(Instruction::LocalSet(length));
+                this.emit(Instruction::LocalGet(ptr));
+                this.emit(Instruction::I32Const(offset + 4));
+                this.emit(Instruction::I32Add);
+                this.emit(Instruction::LocalGet(length));
+                this.emit(Instruction::Call(string_from_memory_fn));
+                this.emit(Instruction::LocalSet(into));
+                this.emit(Instruction::LocalGet(ptr));
+                this.emit(Instruction::I32Const(offset + 4));
+                this.emit(Instruction::I32Add);
+                this.emit(Instruction::LocalGet(length));
+                this.emit(Instruction::I32Add);
+                this.emit(Instruction::LocalSet(ptr));
+            };
+
+            // A fresh response and superglobals for every request
+            this.emit(Instruction::I32Const(200));
+            this.emit(Instruction::GlobalSet(response.status));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::GlobalSet(response.header_count));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::GlobalSet(response.headers_sent));
+            this.emit(Instruction::I32Const(8));
+            this.emit(Instruction::ArrayNewDefault(this.gc_types.php_array));
+            this.emit(Instruction::GlobalSet(response.headers));
+            for &(_, global_idx) in targets.iter().chain([(REQUEST_TARGET, request_global)].iter()) {
+                this.emit(Instruction::Call(this.create_hash_array_fn_idx));
+                this.emit(Instruction::GlobalSet(global_idx));
+            }
+
+            this.emit(Instruction::I32Const(REQUEST_BUFFER));
+            this.emit(Instruction::LocalSet(ptr));
+            this.emit(Instruction::LocalGet(ptr));
+            this.emit(Instruction::I64Load(mem(0)));
+            this.emit(Instruction::GlobalSet(response.request_time));
+            read_string(this, 8, value);
+            this.emit(Instruction::LocalGet(value));
+            this.emit(Instruction::GlobalSet(response.input));
+
+            let server_global = targets[0].1;
+            this.emit(Instruction::GlobalGet(server_global));
+            this.compile_string_literal("REQUEST_TIME")?;
+            this.emit(Instruction::GlobalGet(response.request_time));
+            this.emit(Instruction::Call(this.create_int_fn_idx));
+            this.emit(Instruction::Call(this.array_set_fn_idx));
+            this.emit(Instruction::GlobalSet(server_global));
+
+            this.emit(Instruction::LocalGet(ptr));
+            this.emit(Instruction::I32Load(mem(0)));
+            this.emit(Instruction::LocalSet(count));
+            this.emit(Instruction::LocalGet(ptr));
+            this.emit(Instruction::I32Const(4));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(ptr));
+
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(count));
+            this.emit(Instruction::I32Eqz);
+            this.emit(Instruction::BrIf(1));
+
+            this.emit(Instruction::LocalGet(ptr));
+            this.emit(Instruction::I32Load8U(mem(0)));
+            this.emit(Instruction::LocalSet(target));
+            this.emit(Instruction::LocalGet(ptr));
+            this.emit(Instruction::I32Load8U(mem(1)));
+            this.emit(Instruction::LocalSet(shape));
+            read_string(this, 2, key);
+            this.emit(Instruction::Call(this.create_null_fn_idx));
+            this.emit(Instruction::LocalSet(sub));
+            this.emit(Instruction::LocalGet(shape));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::If(BlockType::Empty));
+            read_string(this, 0, sub);
+            this.emit(Instruction::End);
+
+            // Ints are stored as they are, everything else as a string
+            this.emit(Instruction::LocalGet(ptr));
+            this.emit(Instruction::I32Load8U(mem(0)));
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(ptr));
+            this.emit(Instruction::I64Load(mem(1)));
+            this.emit(Instruction::Call(this.create_int_fn_idx));
+            this.emit(Instruction::LocalSet(value));
+            this.emit(Instruction::LocalGet(ptr));
+            this.emit(Instruction::I32Const(9));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(ptr));
+            this.emit(Instruction::Else);
+            read_string(this, 1, value);
+            this.emit(Instruction::End);
+
+            let emit_store = |this: &mut Self, global_idx: u32| {
+                this.emit(Instruction::GlobalGet(global_idx));
+                this.emit(Instruction::LocalGet(shape));
+                this.emit(Instruction::LocalGet(key));
+                this.emit(Instruction::LocalGet(sub));
+                this.emit(Instruction::LocalGet(value));
+                this.emit(Instruction::Call(request_store_fn));
+                this.emit(Instruction::GlobalSet(global_idx));
+            };
+            for &(index, global_idx) in &targets {
+                this.emit(Instruction::LocalGet(target));
+                this.emit(Instruction::I32Const(index as i32));
+                this.emit(Instruction::I32Eq);
+                this.emit(Instruction::If(BlockType::Empty));
+                emit_store(this, global_idx);
+                this.emit(Instruction::End);
+            }
+            // $_REQUEST holds $_GET and $_POST
+            this.emit(Instruction::LocalGet(target));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::I32Const(2));
+            this.emit(Instruction::I32LtU);
+            this.emit(Instruction::If(BlockType::Empty));
+            emit_store(this, request_global);
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::LocalGet(count));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::LocalSet(count));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::Call(main_idx));
+
+            // The response goes after the request, 8-aligned
+            this.emit(Instruction::I32Const(REQUEST_BUFFER + 7));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::I32Const(-8));
+            this.emit(Instruction::I32And);
+            this.emit(Instruction::LocalTee(out));
+            // Status and header count, written as the length and first
+            // bytes of an empty string so they get room in memory
+            this.emit(Instruction::I32Const(4));
+            this.emit(Instruction::ArrayNewDefault(this.gc_types.php_string));
+            this.emit(Instruction::I32Const(4));
+            this.emit(Instruction::Call(write_bytes_fn));
+            this.emit(Instruction::LocalSet(ptr));
+            this.emit(Instruction::LocalGet(out));
+            this.emit(Instruction::GlobalGet(response.status));
+            this.emit(Instruction::I32Store(mem(0)));
+            this.emit(Instruction::LocalGet(out));
+            this.emit(Instruction::GlobalGet(response.header_count));
+            this.emit(Instruction::I32Store(mem(4)));
+
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalSet(count));
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(count));
+            this.emit(Instruction::GlobalGet(response.header_count));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::GlobalGet(response.headers));
+            this.emit(Instruction::LocalGet(count));
+            this.emit(Instruction::ArrayGet(this.gc_types.php_array));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_STRING,
+            });
+            this.emit(Instruction::LocalSet(header));
+            this.emit(Instruction::LocalGet(ptr));
+            this.emit(Instruction::LocalGet(header));
+            this.emit(Instruction::LocalGet(header));
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::Call(write_bytes_fn));
+            this.emit(Instruction::LocalSet(ptr));
+            this.emit(Instruction::LocalGet(count));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(count));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+
+            // The body is what the script left in the output buffer
+            this.emit(Instruction::LocalGet(ptr));
+            this.emit_output_bytes()?;
+            this.emit(Instruction::Call(write_bytes_fn));
+            this.emit(Instruction::Drop);
+            this.emit(Instruction::LocalGet(out));
+            Ok(())
+        })?;
+
+        self.builder.add_export("handle_request", ExportKind::Func, handle_request_fn);
+        let request_buffer = self.builder.add_global(ValType::I32, false, Instruction::I32Const(REQUEST_BUFFER));
+        self.builder.add_export("request_buffer", ExportKind::Global, request_buffer);
+        Ok(())
+    }
+
+    /// string_from_memory and write_bytes, added the first time they are needed
+    pub(super) fn memory_string_helpers(&mut self) -> Result<(u32, u32), String> {
+        if let Some(helpers) = self.memory_helpers {
+            return Ok(helpers);
+        }
+        let helpers = (self.reserve_http_helper("string_from_memory"), self.reserve_http_helper("write_bytes"));
+        self.memory_helpers = Some(helpers);
+        self.add_string_from_memory_function(helpers.0)?;
+        self.add_write_bytes_function(helpers.1)?;
+        Ok(helpers)
+    }
+
+    /// string_from_memory(ptr, len) -> string PhpValue
+    fn add_string_from_memory_function(&mut self, func_idx: u32) -> Result<(), String> {
+        let php_value_type = self.get_php_value_type();
+        self.compile_typed_helper_function(func_idx, vec![ValType::I32, ValType::I32], vec![php_value_type], |this| {
+            let string = this.allocate_local(this.get_string_type());
+            let i = this.allocate_local(ValType::I32);
+
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::ArrayNewDefault(this.gc_types.php_string));
+            this.emit(Instruction::LocalSet(string));
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::LocalGet(string));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::I32Load8U(mem(0)));
+            this.emit(Instruction::ArraySet(this.gc_types.php_string));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(i));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalGet(string));
+            this.emit(Instruction::Call(this.create_string_fn_idx));
+            Ok(())
+        })
+    }
+
+    /// write_bytes(ptr, string, len) -> end: the length as a u32 and the first
+    /// `len` bytes of the string, growing the memory to fit
+    fn add_write_bytes_function(&mut self, func_idx: u32) -> Result<(), String> {
+        let string_type = self.get_string_type();
+        self.compile_typed_helper_function(func_idx, vec![ValType::I32, string_type, ValType::I32], vec![ValType::I32], |this| {
+            let end = this.allocate_local(ValType::I32);
+            let i = this.allocate_local(ValType::I32);
+
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::I32Const(4));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalGet(2));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalTee(end));
+            this.emit(Instruction::MemorySize(0));
+            this.emit(Instruction::I32Const(16));
+            this.emit(Instruction::I32Shl);
+            this.emit(Instruction::I32GtU);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(end));
+            this.emit(Instruction::I32Const(0xffff));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::I32Const(16));
+            this.emit(Instruction::I32ShrU);
+            this.emit(Instruction::MemorySize(0));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::MemoryGrow(0));
+            this.emit(Instruction::I32Const(-1));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::Unreachable);
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::LocalGet(2));
+            this.emit(Instruction::I32Store(mem(0)));
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::LocalGet(2));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::ArrayGetU(this.gc_types.php_string));
+            this.emit(Instruction::I32Store8(mem(4)));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(i));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalGet(end));
+            Ok(())
+        })
+    }
+
+    /// request_store(array, shape, key, sub, value) -> array: `$a[key] = value`,
+    /// `$a[key][sub] = value` or `$a[key][] = value` for shapes 0, 1 and 2
+    fn add_request_store_function(&mut self, func_idx: u32) -> Result<(), String> {
+        let php_value_type = self.get_php_value_type();
+        let params = vec![php_value_type, ValType::I32, php_value_type, php_value_type, php_value_type];
+        self.compile_typed_helper_function(func_idx, params, vec![php_value_type], |this| {
+            let inner = this.allocate_local(php_value_type);
+
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::I32Eqz);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::LocalGet(2));
+            this.emit(Instruction::LocalGet(4));
+            this.emit(Instruction::Call(this.array_set_fn_idx));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+
+            // The nested array, created by its first entry
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::LocalGet(2));
+            this.emit(Instruction::Call(this.array_get_quiet_fn_idx));
+            this.emit(Instruction::LocalTee(inner));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_TYPE,
+            });
+            this.emit(Instruction::I32Const(TYPE_ARRAY as i32));
+            this.emit(Instruction::I32Ne);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::Call(this.create_hash_array_fn_idx));
+            this.emit(Instruction::LocalSet(inner));
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::If(BlockType::Result(php_value_type)));
+            this.emit(Instruction::LocalGet(inner));
+            this.emit(Instruction::LocalGet(3));
+            this.emit(Instruction::LocalGet(4));
+            this.emit(Instruction::Call(this.array_set_fn_idx));
+            this.emit(Instruction::Else);
+            this.emit(Instruction::LocalGet(inner));
+            this.emit(Instruction::LocalGet(4));
+            this.emit(Instruction::Call(this.array_push_fn_idx));
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalSet(inner));
+
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::LocalGet(2));
+            this.emit(Instruction::LocalGet(inner));
+            this.emit(Instruction::Call(this.array_set_fn_idx));
+            Ok(())
+        })
+    }
+}
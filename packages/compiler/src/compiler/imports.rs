@@ -0,0 +1,290 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Imports: functions and constants provided from outside the module
+//
+// Embedders declare functions with PHP-level signatures through
+// `CompilerOptions::import_function` (host functions, written like
+// `kv_get(string $key): string`, are imported from `env`), and constants
+// through `CompilerOptions::constant`. A call the program cannot resolve to a user
+// function or builtin becomes a call to the import `<module>.<name>`; a
+// static call on an unknown class looks for `Class::method`. Constants are
+// compiled as literals wherever the name is not a variable.
+//
+// Arguments are converted to the declared types: ints, floats and bools cross
+// as i64, f64 and i32, strings as a pointer and a length into the module's
+// memory (copied there from EXTERN_BUFFER on). A string result is written by
+// the host at EXTERN_BUFFER, exported as the `extern_buffer` global, and the
+// import returns its length. The memory grows as needed on both sides.
+
+use super::core::*;
+use super::options::CompilerOptions;
+use crate::wasm_builder::WasmBuilder;
+use edge_php_parser::ast::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use wasm_encoder::*;
+
+/// Where string arguments and results are copied: past the chunk handed to
+/// `env.print`. A request handler reads its request from here too, before
+/// any script code runs.
+pub const EXTERN_BUFFER: i32 = 0x10000;
+
+/// How an imported function's parameters and result cross the module boundary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportType {
+    /// `int`, an i64
+    Int,
+    /// `float`, an f64
+    Float,
+    /// `bool`, an i32 that is 0 or 1
+    Bool,
+    /// `string`, a pointer and a length (two i32s); a result only has the length
+    String,
+}
+
+impl FromStr for ImportType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "int" => Ok(ImportType::Int),
+            "float" => Ok(ImportType::Float),
+            "bool" => Ok(ImportType::Bool),
+            "string" => Ok(ImportType::String),
+            _ => Err(format!("unknown type '{}' (expected int, float, bool or string)", name)),
+        }
+    }
+}
+
+impl fmt::Display for ImportType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ImportType::Int => "int",
+            ImportType::Float => "float",
+            ImportType::Bool => "bool",
+            ImportType::String => "string",
+        })
+    }
+}
+
+/// A function the module imports, callable from PHP by `name`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedFunction {
+    /// The wasm import module
+    pub module: String,
+    /// The PHP name, `function` or `Class::method`, which is also the import name
+    pub name: String,
+    pub params: Vec<ImportType>,
+    /// `None` returns null to PHP
+    pub returns: Option<ImportType>,
+}
+
+impl ImportedFunction {
+    pub fn new(module: impl Into<String>, name: impl Into<String>) -> Self {
+        ImportedFunction { module: module.into(), name: name.into(), params: Vec::new(), returns: None }
+    }
+
+    pub fn param(mut self, ty: ImportType) -> Self {
+        self.params.push(ty);
+        self
+    }
+
+    pub fn returns(mut self, ty: ImportType) -> Self {
+        self.returns = Some(ty);
+        self
+    }
+
+    /// A host function imported as `env.<name>`, from a PHP-style signature
+    /// like `kv_get(string $key): string`. Without a return type, or with
+    /// `void`, the call returns null.
+    pub fn host(signature: &str) -> Result<Self, String> {
+        Self::parse_signature("env", signature)
+    }
+
+    pub fn parse_signature(module: impl Into<String>, signature: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("invalid signature '{}': {}", signature, reason);
+        let (name, rest) = signature.split_once('(').ok_or_else(|| invalid("expected a parameter list"))?;
+        let (params, returns) = rest.split_once(')').ok_or_else(|| invalid("expected ')'"))?;
+        let name = name.trim().strip_prefix("function ").unwrap_or(name.trim()).trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == ':') {
+            return Err(invalid("expected a function name"));
+        }
+
+        let mut function = ImportedFunction::new(module, name);
+        for param in params.split(',').map(str::trim).filter(|param| !param.is_empty()) {
+            // The variable name only documents the parameter
+            let ty = param.split_whitespace().next().unwrap_or_default();
+            if ty.starts_with('$') {
+                return Err(invalid(&format!("{} has no type", ty)));
+            }
+            function.params.push(ty.parse().map_err(|e: String| invalid(&e))?);
+        }
+        let returns = returns.trim();
+        if !returns.is_empty() {
+            let ty = returns.strip_prefix(':').ok_or_else(|| invalid("expected ':' before the return type"))?.trim();
+            if ty != "void" {
+                function.returns = Some(ty.parse().map_err(|e: String| invalid(&e))?);
+            }
+        }
+        Ok(function)
+    }
+
+    /// The import's wasm parameters and results
+    fn signature(&self) -> (Vec<ValType>, Vec<ValType>) {
+        let params = self.params.iter()
+            .flat_map(|ty| match ty {
+                ImportType::Int => vec![ValType::I64],
+                ImportType::Float => vec![ValType::F64],
+                ImportType::Bool => vec![ValType::I32],
+                ImportType::String => vec![ValType::I32, ValType::I32],
+            })
+            .collect();
+        let results = self.returns.iter()
+            .map(|ty| match ty {
+                ImportType::Int => ValType::I64,
+                ImportType::Float => ValType::F64,
+                ImportType::Bool | ImportType::String => ValType::I32,
+            })
+            .collect();
+        (params, results)
+    }
+}
+
+/// The value of a constant declared with `CompilerOptions::constant`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstantValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl ConstantValue {
+    fn to_literal(&self) -> Literal {
+        match self {
+            ConstantValue::Null => Literal::Null,
+            ConstantValue::Bool(value) => Literal::Boolean(*value),
+            ConstantValue::Int(value) => Literal::Integer(*value),
+            ConstantValue::Float(value) => Literal::Float(*value),
+            ConstantValue::String(value) => Literal::String(value.clone()),
+        }
+    }
+}
+
+impl Compiler {
+    /// Import the declared functions. Imports precede every defined function,
+    /// so this runs before the first helper index is reserved.
+    pub(super) fn add_imported_functions(builder: &mut WasmBuilder, options: &CompilerOptions) -> HashMap<String, (u32, ImportedFunction)> {
+        let mut imported = HashMap::new();
+        for function in options.imported_functions() {
+            let (params, results) = function.signature();
+            let type_idx = builder.add_type(params, results);
+            let func_idx = builder.add_import_func(&function.module, &function.name, type_idx);
+            if options.debug_info_enabled() {
+                builder.set_function_name(func_idx, &format!("{}.{}", function.module, function.name));
+            }
+            imported.insert(function.name.clone(), (func_idx, function.clone()));
+        }
+        imported
+    }
+
+    /// Export where string results go, when anything is imported
+    pub(super) fn export_extern_buffer(&mut self) {
+        if !self.imported_functions.is_empty() {
+            let extern_buffer = self.builder.add_global(ValType::I32, false, Instruction::I32Const(EXTERN_BUFFER));
+            self.builder.add_export("extern_buffer", ExportKind::Global, extern_buffer);
+        }
+    }
+
+    /// A declared constant, compiled as its literal
+    pub(super) fn compile_imported_constant(&mut self, name: &str) -> Result<bool, String> {
+        let Some(literal) = self.options.constant_value(name).map(ConstantValue::to_literal) else {
+            return Ok(false);
+        };
+        self.compile_expression(Expression::Literal(literal))?;
+        Ok(true)
+    }
+
+    /// Call an imported function, if one has this name
+    pub(super) fn compile_imported_call(&mut self, name: &str, args: Vec<Expression>) -> Result<bool, String> {
+        let Some((func_idx, function)) = self.imported_functions.get(name).cloned() else {
+            return Ok(false);
+        };
+        if args.len() != function.params.len() {
+            return Err(format!(
+                "{}() expects exactly {} argument{}, {} given",
+                name,
+                function.params.len(),
+                if function.params.len() == 1 { "" } else { "s" },
+                args.len()
+            ));
+        }
+
+        let strings = function.params.contains(&ImportType::String) || function.returns == Some(ImportType::String);
+        let (string_from_memory_fn, write_bytes_fn) = if strings {
+            self.memory_string_helpers()?
+        } else {
+            (0, 0)
+        };
+        let ptr = self.allocate_local(ValType::I32);
+        let string = self.allocate_local(self.get_string_type());
+        self.emit(Instruction::I32Const(EXTERN_BUFFER));
+        self.emit(Instruction::LocalSet(ptr));
+
+        for (arg, ty) in args.into_iter().zip(&function.params) {
+            self.compile_expression(arg)?;
+            match ty {
+                ImportType::Int => {
+                    self.emit(Instruction::Call(self.to_int_fn_idx));
+                    self.emit(Instruction::StructGet { struct_type_index: self.gc_types.php_value, field_index: PHPVALUE_INT });
+                }
+                ImportType::Float => {
+                    self.emit(Instruction::Call(self.to_float_fn_idx));
+                    self.emit(Instruction::StructGet { struct_type_index: self.gc_types.php_value, field_index: PHPVALUE_FLOAT });
+                }
+                ImportType::Bool => {
+                    self.emit(Instruction::Call(self.to_bool_fn_idx));
+                    self.emit(Instruction::StructGet { struct_type_index: self.gc_types.php_value, field_index: PHPVALUE_INT });
+                    self.emit(Instruction::I32WrapI64);
+                }
+                ImportType::String => {
+                    self.emit(Instruction::Call(self.to_string_fn_idx));
+                    self.emit(Instruction::StructGet { struct_type_index: self.gc_types.php_value, field_index: PHPVALUE_STRING });
+                    self.emit(Instruction::LocalSet(string));
+                    // write_bytes puts a length before the bytes
+                    self.emit(Instruction::LocalGet(ptr));
+                    self.emit(Instruction::I32Const(4));
+                    self.emit(Instruction::I32Add);
+                    self.emit(Instruction::LocalGet(string));
+                    self.emit(Instruction::ArrayLen);
+                    self.emit(Instruction::LocalGet(ptr));
+                    self.emit(Instruction::LocalGet(string));
+                    self.emit(Instruction::LocalGet(string));
+                    self.emit(Instruction::ArrayLen);
+                    self.emit(Instruction::Call(write_bytes_fn));
+                    self.emit(Instruction::LocalSet(ptr));
+                }
+            }
+        }
+        self.emit(Instruction::Call(func_idx));
+
+        match function.returns {
+            None => self.emit(Instruction::Call(self.create_null_fn_idx)),
+            Some(ImportType::Int) => self.emit(Instruction::Call(self.create_int_fn_idx)),
+            Some(ImportType::Float) => self.emit(Instruction::Call(self.create_float_fn_idx)),
+            Some(ImportType::Bool) => self.emit(Instruction::Call(self.create_bool_fn_idx)),
+            Some(ImportType::String) => {
+                self.emit(Instruction::LocalSet(ptr));
+                self.emit(Instruction::I32Const(EXTERN_BUFFER));
+                self.emit(Instruction::LocalGet(ptr));
+                self.emit(Instruction::Call(string_from_memory_fn));
+            }
+        }
+        self.free_local(string, self.get_string_type());
+        self.free_local(ptr, ValType::I32);
+        Ok(true)
+    }
+}
@@ -0,0 +1,418 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Inlining of small functions and methods
+//
+// A function or method whose body is one `return` of a small expression of
+// its parameters (`function square($x) { return $x * $x; }`, a getter
+// returning `$this->name`) is a candidate. Calls to a candidate compile its
+// expression in the caller instead: the arguments are evaluated in order into
+// temporaries (see `Compiler::bind_temp_variable`), which the expression reads
+// in place of the parameters. Literal arguments are substituted directly, and
+// so are variable arguments when nothing in the expression can change them
+// between the call and the read. Candidates calling other candidates inline
+// them too, up to `MAX_INLINE_DEPTH` levels; direct recursion is never a
+// candidate. Extra arguments are evaluated and dropped, as a call drops
+// them, and parameters left out take their defaults; a call that leaves out a
+// parameter without one stays a call.
+//
+// Functions with declared parameter or return types stay calls, since the call
+// is where their types are checked, and so do expressions that depend on the
+// function they are in (`self::`, `static::`, `func_get_args()`). Specialized
+// calls (see specialization.rs) are kept: their unboxed signature already
+// does better than a boxed inlined expression. Functions compiled through
+// the mid-level IR inline the same candidates while lowering.
+
+use super::core::*;
+use super::ir::lower::PURE_BUILTINS;
+use edge_php_parser::ast::*;
+use edge_php_parser::visit_mut::{self, VisitorMut};
+use std::collections::HashMap;
+use wasm_encoder::*;
+
+/// Expression nodes an inlined body may have
+const MAX_INLINE_SIZE: usize = 12;
+
+/// Nested inlined calls compiled into one call site
+pub(super) const MAX_INLINE_DEPTH: usize = 4;
+
+/// Builtins that see the function calling them
+const CONTEXT_FUNCTIONS: [&str; 6] = ["func_get_args", "func_num_args", "func_get_arg", "compact", "extract", "get_defined_vars"];
+
+/// A function or method whose calls compile its returned expression
+#[derive(Debug, Clone)]
+pub(super) struct InlineCandidate {
+    pub params: Vec<Parameter>,
+    pub body: Expression,
+    /// The expression calls nothing but pure builtins, so nothing it does
+    /// can change a variable of the caller
+    pub pure: bool,
+}
+
+impl InlineCandidate {
+    /// The candidate for a function or method, if it is one
+    fn new(name: &str, params: &[Parameter], body: &Block, return_type: Option<&Type>, allow_this: bool) -> Option<InlineCandidate> {
+        if return_type.is_some() || params.iter().any(|param| param.param_type.is_some() || param.promoted.is_some()) {
+            return None;
+        }
+        let mut statements = body.statements.iter().filter(|stmt| !matches!(stmt, Statement::Location(_)));
+        let (Some(Statement::Return(Some(body))), None) = (statements.next(), statements.next()) else {
+            return None;
+        };
+        let mut check = BodyCheck { name, params, allow_this, size: 0, pure: true };
+        (check.expression(body) && check.size <= MAX_INLINE_SIZE).then(|| InlineCandidate {
+            params: params.to_vec(),
+            body: body.clone(),
+            pure: check.pure,
+        })
+    }
+
+    /// Whether a call passing `arg_count` arguments gives every parameter
+    /// without a default one
+    pub(super) fn accepts(&self, arg_count: usize) -> bool {
+        self.params.iter().skip(arg_count).all(|param| param.default.is_some())
+    }
+
+    /// Whether the mid-level IR can lower the expression; the candidates it
+    /// cannot are called from IR functions
+    pub(super) fn is_ir_expression(&self) -> bool {
+        fn lowers(expr: &Expression) -> bool {
+            match expr {
+                Expression::Variable(name) => name != "this",
+                Expression::Literal(Literal::InterpolatedString(parts)) => parts.iter().all(|part| match part {
+                    InterpolatedPart::Expression(expr) => lowers(expr),
+                    InterpolatedPart::Variable(name) => name != "this",
+                    InterpolatedPart::Text(_) => true,
+                }),
+                Expression::Literal(_) => true,
+                Expression::Binary { left, right, .. } => lowers(left) && lowers(right),
+                Expression::Unary { expr, .. } => lowers(expr),
+                Expression::Ternary { condition, then_expr, else_expr } => lowers(condition) && lowers(then_expr) && lowers(else_expr),
+                Expression::Cast { cast_type: Type::Int | Type::Float | Type::String | Type::Bool, expr } => lowers(expr),
+                Expression::FunctionCall { args, .. } => args.iter().all(lowers),
+                _ => false,
+            }
+        }
+        lowers(&self.body)
+    }
+}
+
+/// Whether a returned expression can be inlined
+struct BodyCheck<'a> {
+    name: &'a str,
+    params: &'a [Parameter],
+    allow_this: bool,
+    size: usize,
+    pure: bool,
+}
+
+impl BodyCheck<'_> {
+    fn variable(&self, name: &str) -> bool {
+        (name == "this" && self.allow_this) || self.params.iter().any(|param| param.name == name)
+    }
+
+    fn expressions(&mut self, exprs: &[Expression]) -> bool {
+        exprs.iter().all(|expr| self.expression(expr))
+    }
+
+    fn expression(&mut self, expr: &Expression) -> bool {
+        self.size += 1;
+        if self.size > MAX_INLINE_SIZE {
+            return false;
+        }
+        match expr {
+            Expression::Variable(name) => self.variable(name),
+            Expression::Literal(Literal::InterpolatedString(parts)) => parts.iter().all(|part| match part {
+                InterpolatedPart::Text(_) => true,
+                InterpolatedPart::Variable(name) => self.variable(name),
+                InterpolatedPart::Expression(expr) => self.expression(expr),
+            }),
+            Expression::Literal(_) => true,
+            Expression::Binary { left, right, .. } | Expression::Coalesce { left, right } => self.expression(left) && self.expression(right),
+            Expression::Unary { op: UnaryOp::Not | UnaryOp::Negate, expr } => self.expression(expr),
+            Expression::Ternary { condition, then_expr, else_expr } => {
+                self.expression(condition) && self.expression(then_expr) && self.expression(else_expr)
+            }
+            Expression::Cast { expr, .. } => self.expression(expr),
+            Expression::FunctionCall { name, args } => {
+                if name == self.name || CONTEXT_FUNCTIONS.contains(&name.as_str()) {
+                    return false;
+                }
+                self.pure &= PURE_BUILTINS.contains(&name.as_str());
+                self.expressions(args)
+            }
+            Expression::PropertyAccess { object, .. } | Expression::NullsafePropertyAccess { object, .. } => {
+                // __get may run anything
+                self.pure = false;
+                self.expression(object)
+            }
+            Expression::MethodCall { object, args, .. } | Expression::NullsafeMethodCall { object, args, .. } => {
+                self.pure = false;
+                self.expression(object) && self.expressions(args)
+            }
+            Expression::ArrayAccess { array, index } => self.expression(array) && self.expression(index),
+            Expression::Array(elements) => elements.iter().all(|element| {
+                element.key.as_ref().is_none_or(|key| self.expression(key)) && self.expression(&element.value)
+            }),
+            Expression::ClassConstant { class, .. } => !is_relative_class(class),
+            Expression::StaticPropertyAccess { class, .. } => {
+                self.pure = false;
+                !is_relative_class(class)
+            }
+            Expression::StaticMethodCall { class, args, .. } | Expression::New { class, args } => {
+                self.pure = false;
+                !is_relative_class(class) && self.expressions(args)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Class names that mean a different class in the caller
+fn is_relative_class(class: &str) -> bool {
+    matches!(class.to_lowercase().as_str(), "self" | "static" | "parent")
+}
+
+/// Replaces the parameters of an inlined expression with their arguments
+struct Substitution<'a>(&'a HashMap<String, Expression>);
+
+impl VisitorMut for Substitution<'_> {
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        match expr {
+            Expression::Variable(name) => {
+                if let Some(arg) = self.0.get(name) {
+                    *expr = arg.clone();
+                }
+            }
+            Expression::Literal(Literal::InterpolatedString(parts)) => {
+                for part in parts.iter_mut() {
+                    if let InterpolatedPart::Variable(name) = part {
+                        *part = match self.0.get(name) {
+                            Some(Expression::Variable(arg)) => InterpolatedPart::Variable(arg.clone()),
+                            Some(arg) => InterpolatedPart::Expression(arg.clone()),
+                            None => continue,
+                        };
+                    }
+                }
+                visit_mut::walk_expression_mut(self, expr);
+            }
+            _ => visit_mut::walk_expression_mut(self, expr),
+        }
+    }
+}
+
+impl Compiler {
+    /// Find the functions and methods whose calls are compiled inline, and
+    /// the pure functions among them, which are found with inlining off too
+    pub(super) fn collect_inline_candidates(&mut self, statements: &[Statement]) {
+        let inlining = self.options.inlining_enabled();
+        for stmt in statements {
+            match stmt {
+                Statement::Function { name, params, body, return_type } => {
+                    let Some(candidate) = InlineCandidate::new(name, params, body, return_type.as_ref(), false) else { continue };
+                    if candidate.pure {
+                        self.pure_functions.insert(name.clone());
+                    }
+                    if inlining {
+                        self.inline_candidates.insert(name.clone(), candidate);
+                    }
+                }
+                Statement::Class { .. } if !inlining => {}
+                Statement::Class { name: class, members, .. } => {
+                    for member in members {
+                        if let ClassMember::Method { is_static, name, params, body, return_type, .. } = member {
+                            let key = format!("{}::{}", class, name);
+                            if let Some(candidate) = InlineCandidate::new(&key, params, body, return_type.as_ref(), !is_static) {
+                                self.inline_candidates.insert(key, candidate);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The candidate a call to `key` with `arg_count` arguments inlines,
+    /// unless the call is already inside it or too many inlined calls deep
+    pub(super) fn inline_candidate(&self, key: &str, arg_count: usize) -> Option<InlineCandidate> {
+        if self.inlining.len() >= MAX_INLINE_DEPTH || self.inlining.iter().any(|name| name == key) {
+            return None;
+        }
+        self.inline_candidates.get(key).filter(|candidate| candidate.accepts(arg_count)).cloned()
+    }
+
+    /// Compile a call to a candidate as its expression; `this` is the object
+    /// of a method call
+    pub(super) fn compile_inlined_call(&mut self, key: &str, candidate: InlineCandidate, this: Option<(Expression, String)>, args: Vec<Expression>) -> Result<(), String> {
+        let substitute_variables = candidate.pure
+            && args.iter().chain(this.as_ref().map(|(object, _)| object))
+                .all(|arg| matches!(arg, Expression::Variable(_) | Expression::Literal(_)));
+        let mut bindings = HashMap::new();
+        let mut temps = Vec::new();
+        let mut bind = |compiler: &mut Compiler, name: &str, arg: Expression, class_type: Option<String>| -> Result<(), String> {
+            let value = match arg {
+                Expression::Literal(ref literal) if !matches!(literal, Literal::InterpolatedString(_)) => arg,
+                Expression::Variable(_) if substitute_variables => arg,
+                arg => {
+                    compiler.compile_expression(arg)?;
+                    let local = compiler.allocate_local(compiler.get_php_value_type());
+                    compiler.emit(Instruction::LocalSet(local));
+                    let temp = compiler.bind_temp_variable(local, class_type);
+                    temps.push(temp.clone());
+                    Expression::Variable(temp)
+                }
+            };
+            bindings.insert(name.to_string(), value);
+            Ok(())
+        };
+
+        if let Some((object, class)) = this {
+            bind(self, "this", object, Some(class))?;
+        }
+        let arg_count = args.len();
+        for (position, arg) in args.into_iter().enumerate() {
+            match candidate.params.get(position) {
+                Some(param) => bind(self, &param.name, arg, None)?,
+                // Extra arguments are evaluated and dropped
+                None => {
+                    self.compile_expression(arg)?;
+                    self.emit(Instruction::Drop);
+                }
+            }
+        }
+        for param in candidate.params.iter().skip(arg_count) {
+            if let Some(default) = param.default.clone() {
+                bind(self, &param.name, default, None)?;
+            }
+        }
+
+        let mut body = candidate.body;
+        Substitution(&bindings).visit_expression_mut(&mut body);
+        self.inlining.push(key.to_string());
+        let result = self.compile_expression(body);
+        self.inlining.pop();
+        for temp in temps {
+            self.variables.remove(&temp);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Compiler, CompilerOptions, OptLevel};
+
+    /// The candidates of the functions in `source`, by name
+    fn candidates(source: &str) -> HashMap<String, InlineCandidate> {
+        let program = edge_php_parser::parse(source).unwrap();
+        let mut candidates = HashMap::new();
+        for item in &program.items {
+            if let ProgramItem::PhpBlock { statements } = item {
+                for stmt in statements {
+                    if let Statement::Function { name, params, body, return_type } = stmt {
+                        if let Some(candidate) = InlineCandidate::new(name, params, body, return_type.as_ref(), false) {
+                            candidates.insert(name.clone(), candidate);
+                        }
+                    }
+                }
+            }
+        }
+        candidates
+    }
+
+    /// The user functions (not `php_` helpers) the top-level code calls, in
+    /// order
+    fn main_calls(source: &str) -> Vec<String> {
+        let options = CompilerOptions::new().opt_level(OptLevel::O2).debug_info(true).specialization(false);
+        let wasm = Compiler::with_options(options).compile(source).unwrap();
+
+        let mut names = HashMap::new();
+        let mut bodies = Vec::new();
+        let mut imports = 0;
+        for payload in wasmparser::Parser::new(0).parse_all(&wasm) {
+            match payload.unwrap() {
+                wasmparser::Payload::ImportSection(reader) => imports = reader.count(),
+                wasmparser::Payload::CodeSectionEntry(body) => bodies.push(body),
+                wasmparser::Payload::CustomSection(section) => {
+                    if let wasmparser::KnownCustom::Name(reader) = section.as_known() {
+                        for name in reader {
+                            if let wasmparser::Name::Function(map) = name.unwrap() {
+                                for naming in map {
+                                    let naming = naming.unwrap();
+                                    names.insert(naming.index, naming.name.to_string());
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let position = bodies.iter().enumerate()
+            .position(|(position, _)| names.get(&(imports + position as u32)).map(String::as_str) == Some("{main}"))
+            .unwrap();
+        let mut callees = Vec::new();
+        for operator in bodies[position].get_operators_reader().unwrap() {
+            if let wasmparser::Operator::Call { function_index } = operator.unwrap() {
+                let callee = names.get(&function_index).cloned().unwrap_or_default();
+                if !callee.is_empty() && !callee.starts_with("php_") {
+                    callees.push(callee);
+                }
+            }
+        }
+        callees
+    }
+
+    #[test]
+    fn test_candidates_and_purity() {
+        let candidates = candidates(r#"<?php
+function square($x) { return $x * $x; }
+function size($s) { return strlen($s) + 1; }
+function bumped($x) { return bump() + $x; }
+function name($o) { return $o->name; }
+function first($o) { return $o->first(); }
+function typed(int $x) { return $x; }
+function returns($x): int { return $x; }
+function twice($x) { $y = $x; return $y * 2; }
+function down($n) { return $n ? down($n - 1) : 0; }
+function args() { return func_get_args(); }
+function outside($x) { return $x + $y; }
+function big($x) { return $x + $x + $x + $x + $x + $x + $x; }
+"#);
+        let mut names: Vec<_> = candidates.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["bumped", "first", "name", "size", "square"]);
+
+        // Only an expression that cannot change the caller's variables
+        // reads variable arguments in place
+        assert!(candidates["square"].pure);
+        assert!(candidates["size"].pure);
+        assert!(!candidates["bumped"].pure);
+        assert!(!candidates["name"].pure);
+        assert!(!candidates["first"].pure);
+    }
+
+    #[test]
+    fn test_arity() {
+        let candidates = candidates("<?php function pair($a, $b = 2) { return $a . $b; }");
+        let pair = &candidates["pair"];
+        assert!(!pair.accepts(0));
+        assert!(pair.accepts(1));
+        assert!(pair.accepts(2));
+        assert!(pair.accepts(3));
+    }
+
+    #[test]
+    fn test_short_calls_stay_calls() {
+        let calls = main_calls(r#"<?php
+function pair($a, $b = 2) { return $a . $b; }
+echo pair(1);
+echo pair(1, 3, 4);
+echo pair();
+"#);
+        assert_eq!(calls, ["pair"]);
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Control-flow analysis: reverse postorder, predecessors, dominators and
+// natural loops of the blocks reachable from the entry
+//
+// Dominators are computed with the iterative algorithm of Cooper, Harvey and
+// Kennedy over the reverse postorder. An edge is a back edge when it goes to
+// a block that comes no later in reverse postorder, which for the reducible
+// graphs lowering produces means the target dominates the source.
+
+use super::{BlockId, Function, ENTRY};
+use std::collections::{HashMap, HashSet};
+
+pub struct Cfg {
+    rpo: Vec<BlockId>,
+    /// Position of each reachable block in `rpo`
+    order: HashMap<BlockId, usize>,
+    /// One entry per edge, so a block branching twice to the same target is listed twice
+    preds: HashMap<BlockId, Vec<BlockId>>,
+    unique_preds: HashMap<BlockId, Vec<BlockId>>,
+    idom: HashMap<BlockId, BlockId>,
+}
+
+/// A natural loop: its header and every block of its body, the header included
+pub struct Loop {
+    pub header: BlockId,
+    pub blocks: HashSet<BlockId>,
+}
+
+impl Cfg {
+    pub fn new(function: &Function) -> Self {
+        // Postorder with an explicit stack: (block, next successor to visit)
+        let mut postorder = Vec::new();
+        let mut visited = HashSet::from([ENTRY]);
+        let mut stack = vec![(ENTRY, 0)];
+        while let Some((block, next)) = stack.last_mut() {
+            let successors: Vec<BlockId> = function.block(*block).terminator.edges().iter().map(|edge| edge.block).collect();
+            if let Some(&successor) = successors.get(*next) {
+                *next += 1;
+                if visited.insert(successor) {
+                    stack.push((successor, 0));
+                }
+            } else {
+                postorder.push(*block);
+                stack.pop();
+            }
+        }
+        let rpo: Vec<BlockId> = postorder.into_iter().rev().collect();
+        let order: HashMap<BlockId, usize> = rpo.iter().enumerate().map(|(position, &block)| (block, position)).collect();
+
+        let mut preds: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+        let mut unique_preds: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+        for &block in &rpo {
+            for edge in function.block(block).terminator.edges() {
+                preds.entry(edge.block).or_default().push(block);
+                let unique = unique_preds.entry(edge.block).or_default();
+                if !unique.contains(&block) {
+                    unique.push(block);
+                }
+            }
+        }
+
+        let mut cfg = Cfg { rpo, order, preds, unique_preds, idom: HashMap::new() };
+        cfg.compute_dominators();
+        cfg
+    }
+
+    fn compute_dominators(&mut self) {
+        self.idom.insert(ENTRY, ENTRY);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in self.rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for &pred in self.unique_preds(block) {
+                    if !self.idom.contains_key(&pred) {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(current) => self.intersect(pred, current),
+                    });
+                }
+                if let Some(new_idom) = new_idom {
+                    if self.idom.get(&block) != Some(&new_idom) {
+                        self.idom.insert(block, new_idom);
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    fn intersect(&self, mut a: BlockId, mut b: BlockId) -> BlockId {
+        while a != b {
+            while self.order[&a] > self.order[&b] {
+                a = self.idom[&a];
+            }
+            while self.order[&b] > self.order[&a] {
+                b = self.idom[&b];
+            }
+        }
+        a
+    }
+
+    /// The reachable blocks in reverse postorder, entry first
+    pub fn rpo(&self) -> &[BlockId] {
+        &self.rpo
+    }
+
+    pub fn reachable(&self) -> HashSet<BlockId> {
+        self.rpo.iter().copied().collect()
+    }
+
+    pub fn order(&self, block: BlockId) -> usize {
+        self.order[&block]
+    }
+
+    /// The source block of every edge into `block`
+    pub fn preds(&self, block: BlockId) -> &[BlockId] {
+        self.preds.get(&block).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn unique_preds(&self, block: BlockId) -> &[BlockId] {
+        self.unique_preds.get(&block).map_or(&[], Vec::as_slice)
+    }
+
+    /// The immediate dominator of a block other than the entry
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        self.idom.get(&block).copied().filter(|_| block != ENTRY)
+    }
+
+    pub fn dominates(&self, a: BlockId, mut b: BlockId) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom(b) {
+                Some(parent) => b = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// The blocks `block` immediately dominates, in reverse postorder
+    pub fn dominator_children(&self, block: BlockId) -> Vec<BlockId> {
+        self.rpo.iter().copied().filter(|&child| self.idom(child) == Some(block)).collect()
+    }
+
+    pub fn is_back_edge(&self, from: BlockId, to: BlockId) -> bool {
+        self.order[&to] <= self.order[&from]
+    }
+
+    /// The natural loops, innermost (smallest) first; loops sharing a header
+    /// are merged
+    pub fn loops(&self) -> Vec<Loop> {
+        let mut loops: Vec<Loop> = Vec::new();
+        for &header in &self.rpo {
+            let latches: Vec<BlockId> = self.unique_preds(header).iter().copied()
+                .filter(|&pred| self.is_back_edge(pred, header))
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+            let mut blocks = HashSet::from([header]);
+            let mut work = latches;
+            while let Some(block) = work.pop() {
+                if blocks.insert(block) {
+                    work.extend(self.unique_preds(block));
+                }
+            }
+            loops.push(Loop { header, blocks });
+        }
+        loops.sort_by_key(|lp| lp.blocks.len());
+        loops
+    }
+
+    /// The block every edge entering the loop comes from, if there is just one
+    /// and it goes nowhere else
+    pub fn preheader(&self, function: &Function, lp: &Loop) -> Option<BlockId> {
+        let mut entries = self.unique_preds(lp.header).iter().filter(|pred| !lp.blocks.contains(pred));
+        let preheader = *entries.next()?;
+        if entries.next().is_some() || function.block(preheader).terminator.edges().len() != 1 {
+            return None;
+        }
+        Some(preheader)
+    }
+}
@@ -0,0 +1,460 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Wasm emission from the IR
+//
+// Every value gets a local of its representation, except that function
+// parameters live in the parameter locals and raw constants are emitted
+// again at every use. Jumps copy their arguments into the target's parameter
+// locals: all arguments are pushed first and then stored in reverse, so a
+// loop passing values around in a cycle reads them all before writing any.
+//
+// The graph is turned back into structured control flow after Ramsey,
+// "Beyond Relooper": walking the dominator tree, a block reached by a single
+// forward edge is emitted in place at that edge, a block reached by several
+// becomes a Wasm `block` right after its immediate dominator's code (jumps to
+// it are a `br` to the end of that `block`), and a loop header wraps its code
+// in a `loop` that back edges `br` to.
+
+use super::cfg::Cfg;
+use super::*;
+use crate::compiler::core::*;
+use edge_php_parser::ast::Expression;
+use std::collections::HashSet;
+use wasm_encoder::{BlockType, Instruction, ValType};
+
+/// A Wasm construct enclosing the code being emitted
+enum Frame {
+    If,
+    /// The `loop` back edges to this header branch to
+    Loop(BlockId),
+    /// The `block` whose end this block's code follows
+    Block(BlockId),
+}
+
+struct EmitState<'f> {
+    function: &'f Function,
+    cfg: Cfg,
+    locals: HashMap<Value, u32>,
+    frames: Vec<Frame>,
+    /// Blocks reached by more than one forward edge
+    merges: HashSet<BlockId>,
+    loop_headers: HashSet<BlockId>,
+}
+
+impl Compiler {
+    /// Emit the body of a lowered function into the function being compiled,
+    /// whose parameter locals are its parameters
+    pub(in crate::compiler) fn emit_ir_function(&mut self, function: &Function) -> Result<(), String> {
+        let cfg = Cfg::new(function);
+        let mut merges = HashSet::new();
+        let mut loop_headers = HashSet::new();
+        for &block in cfg.rpo() {
+            let forward = cfg.preds(block).iter().filter(|&&pred| !cfg.is_back_edge(pred, block)).count();
+            if forward > 1 {
+                merges.insert(block);
+            }
+            if cfg.preds(block).iter().any(|&pred| cfg.is_back_edge(pred, block)) {
+                loop_headers.insert(block);
+            }
+        }
+
+        let mut locals = HashMap::new();
+        for &block in cfg.rpo() {
+            let data = function.block(block);
+            for &value in data.params.iter().chain(&data.insts) {
+                match function.inst(value) {
+                    Some(Inst::Param(position)) => {
+                        locals.insert(value, *position as u32);
+                    }
+                    Some(inst) if !has_result(inst) || is_rematerialized(function, value) => {}
+                    _ => {
+                        let val_type = self.repr_val_type(function.repr(value));
+                        locals.insert(value, self.allocate_local(val_type));
+                    }
+                }
+            }
+        }
+
+        let mut state = EmitState { function, cfg, locals, frames: Vec::new(), merges, loop_headers };
+        self.emit_ir_tree(&mut state, ENTRY)?;
+        // Every path has returned
+        self.emit(Instruction::Unreachable);
+        Ok(())
+    }
+
+    fn repr_val_type(&self, repr: Repr) -> ValType {
+        match repr {
+            Repr::Boxed => self.get_php_value_type(),
+            Repr::I64 => ValType::I64,
+            Repr::F64 => ValType::F64,
+            Repr::I32 => ValType::I32,
+        }
+    }
+
+    /// A block and the blocks it immediately dominates
+    fn emit_ir_tree(&mut self, state: &mut EmitState, block: BlockId) -> Result<(), String> {
+        let mut merges: Vec<BlockId> = state.cfg.dominator_children(block).into_iter()
+            .filter(|child| state.merges.contains(child))
+            .collect();
+        // The merge block that comes last is nested innermost
+        merges.sort_by_key(|&child| std::cmp::Reverse(state.cfg.order(child)));
+        if state.loop_headers.contains(&block) {
+            self.emit(Instruction::Loop(BlockType::Empty));
+            state.frames.push(Frame::Loop(block));
+            self.emit_ir_node_within(state, block, &merges)?;
+            state.frames.pop();
+            self.emit(Instruction::End);
+        } else {
+            self.emit_ir_node_within(state, block, &merges)?;
+        }
+        Ok(())
+    }
+
+    /// A block's code inside one Wasm `block` per merge block it dominates, each
+    /// merge block following the end of its `block`
+    fn emit_ir_node_within(&mut self, state: &mut EmitState, block: BlockId, merges: &[BlockId]) -> Result<(), String> {
+        let Some((&merge, inner)) = merges.split_first() else {
+            let function = state.function;
+            for &value in &function.block(block).insts {
+                self.emit_ir_inst(state, value)?;
+            }
+            return self.emit_ir_terminator(state, block);
+        };
+        self.emit(Instruction::Block(BlockType::Empty));
+        state.frames.push(Frame::Block(merge));
+        self.emit_ir_node_within(state, block, inner)?;
+        state.frames.pop();
+        self.emit(Instruction::End);
+        self.emit_ir_tree(state, merge)
+    }
+
+    fn emit_ir_terminator(&mut self, state: &mut EmitState, block: BlockId) -> Result<(), String> {
+        let function = state.function;
+        match &function.block(block).terminator {
+            Terminator::Jump(edge) => self.emit_ir_edge(state, block, edge)?,
+            Terminator::Branch(cond, then_edge, else_edge) => {
+                self.emit_ir_use(state, *cond, Repr::I32)?;
+                self.emit(Instruction::If(BlockType::Empty));
+                state.frames.push(Frame::If);
+                self.emit_ir_edge(state, block, then_edge)?;
+                self.emit(Instruction::Else);
+                self.emit_ir_edge(state, block, else_edge)?;
+                state.frames.pop();
+                self.emit(Instruction::End);
+            }
+            Terminator::Return(value) => {
+                self.emit_ir_use(state, *value, function.return_repr)?;
+                self.emit(Instruction::Return);
+            }
+            Terminator::Unreachable => self.emit(Instruction::Unreachable),
+        }
+        Ok(())
+    }
+
+    /// Pass the edge's arguments and continue at its target
+    fn emit_ir_edge(&mut self, state: &mut EmitState, from: BlockId, edge: &Edge) -> Result<(), String> {
+        let function = state.function;
+        let params = &function.block(edge.block).params;
+        for (&arg, &param) in edge.args.iter().zip(params) {
+            self.emit_ir_use(state, arg, function.repr(param))?;
+        }
+        for param in params.iter().rev() {
+            self.emit(Instruction::LocalSet(state.locals[param]));
+        }
+
+        let target = if state.cfg.is_back_edge(from, edge.block) {
+            Frame::Loop(edge.block)
+        } else if state.merges.contains(&edge.block) {
+            Frame::Block(edge.block)
+        } else {
+            return self.emit_ir_tree(state, edge.block);
+        };
+        let depth = state.frames.iter().rev()
+            .position(|frame| match (frame, &target) {
+                (Frame::Loop(a), Frame::Loop(b)) | (Frame::Block(a), Frame::Block(b)) => a == b,
+                _ => false,
+            })
+            .ok_or("IR branch to a block outside the enclosing constructs")?;
+        self.emit(Instruction::Br(depth as u32));
+        Ok(())
+    }
+
+    /// Push a value in representation `repr`
+    fn emit_ir_use(&mut self, state: &EmitState, value: Value, repr: Repr) -> Result<(), String> {
+        let function = state.function;
+        if is_rematerialized(function, value) {
+            let constant = function.constant(value).expect("a constant").clone();
+            return self.emit_ir_constant(&constant, repr);
+        }
+        let local = *state.locals.get(&value).ok_or_else(|| format!("IR value {} used before it is defined", value))?;
+        self.emit(Instruction::LocalGet(local));
+        self.emit_ir_convert(function.repr(value), repr);
+        Ok(())
+    }
+
+    fn emit_ir_constant(&mut self, constant: &Constant, repr: Repr) -> Result<(), String> {
+        let natural = match constant {
+            Constant::Int(n) => {
+                self.emit(Instruction::I64Const(*n));
+                Repr::I64
+            }
+            Constant::Float(x) => {
+                self.emit(Instruction::F64Const((*x).into()));
+                Repr::F64
+            }
+            Constant::Bool(b) => {
+                self.emit(Instruction::I32Const(*b as i32));
+                Repr::I32
+            }
+            Constant::String(s) => {
+                self.compile_string_literal(s)?;
+                Repr::Boxed
+            }
+            Constant::Null => {
+                self.emit(Instruction::Call(self.create_null_fn_idx));
+                Repr::Boxed
+            }
+        };
+        self.emit_ir_convert(natural, repr);
+        Ok(())
+    }
+
+    /// Convert the value on the stack between representations
+    fn emit_ir_convert(&mut self, from: Repr, to: Repr) {
+        let php_value = self.gc_types.php_value;
+        let unbox = |compiler: &mut Self, helper: u32, field_index: u32| {
+            compiler.emit(Instruction::Call(helper));
+            compiler.emit(Instruction::StructGet { struct_type_index: php_value, field_index });
+        };
+        match (from, to) {
+            _ if from == to => {}
+            (Repr::I64, Repr::Boxed) => self.emit_inline_box_int(),
+            (Repr::F64, Repr::Boxed) => self.emit_inline_box_float(),
+            (Repr::I32, Repr::Boxed) => self.emit(Instruction::Call(self.create_bool_fn_idx)),
+            (Repr::Boxed, Repr::I64) => unbox(self, self.to_int_fn_idx, PHPVALUE_INT),
+            (Repr::Boxed, Repr::F64) => unbox(self, self.to_float_fn_idx, PHPVALUE_FLOAT),
+            (Repr::Boxed, Repr::I32) => {
+                unbox(self, self.to_bool_fn_idx, PHPVALUE_INT);
+                self.emit(Instruction::I32WrapI64);
+            }
+            (Repr::I64, Repr::F64) => self.emit(Instruction::F64ConvertI64S),
+            (Repr::F64, Repr::I64) => self.emit(Instruction::I64TruncSatF64S),
+            (Repr::I32, Repr::I64) => self.emit(Instruction::I64ExtendI32U),
+            (Repr::I32, Repr::F64) => self.emit(Instruction::F64ConvertI32U),
+            (Repr::I64, Repr::I32) => {
+                self.emit(Instruction::I64Const(0));
+                self.emit(Instruction::I64Ne);
+            }
+            (Repr::F64, Repr::I32) => {
+                self.emit(Instruction::F64Const(0.0.into()));
+                self.emit(Instruction::F64Ne);
+            }
+            _ => unreachable!("every pair of representations is handled"),
+        }
+    }
+
+    fn emit_ir_inst(&mut self, state: &EmitState, value: Value) -> Result<(), String> {
+        let function = state.function;
+        let inst = function.inst(value).expect("blocks hold instructions");
+        if matches!(inst, Inst::Param(_)) || is_rematerialized(function, value) {
+            return Ok(());
+        }
+        let operands = inst_operands(inst);
+        let wanted = operand_reprs(function, value);
+        let natural = match inst {
+            Inst::Const(constant) => {
+                self.emit_ir_constant(constant, function.repr(value))?;
+                function.repr(value)
+            }
+            Inst::Binary(op, ..) => {
+                self.emit_ir_use(state, operands[0], wanted[0])?;
+                self.emit_ir_use(state, operands[1], wanted[1])?;
+                self.emit_ir_binary(op, wanted[0])?
+            }
+            Inst::Neg(operand) => match wanted[0] {
+                Repr::I64 => {
+                    self.emit(Instruction::I64Const(0));
+                    self.emit_ir_use(state, *operand, Repr::I64)?;
+                    self.emit(Instruction::I64Sub);
+                    Repr::I64
+                }
+                Repr::F64 => {
+                    self.emit_ir_use(state, *operand, Repr::F64)?;
+                    self.emit(Instruction::F64Neg);
+                    Repr::F64
+                }
+                _ => {
+                    self.emit_ir_use(state, *operand, Repr::Boxed)?;
+                    self.emit_boxed_negation();
+                    Repr::Boxed
+                }
+            },
+            Inst::Not(operand) => {
+                self.emit_ir_use(state, *operand, Repr::I32)?;
+                self.emit(Instruction::I32Eqz);
+                Repr::I32
+            }
+            Inst::Truthy(operand) => {
+                self.emit_ir_use(state, *operand, wanted[0])?;
+                self.emit_ir_convert(wanted[0], Repr::I32);
+                Repr::I32
+            }
+            Inst::Cast(kind, operand) => {
+                self.emit_ir_use(state, *operand, wanted[0])?;
+                match (kind, wanted[0]) {
+                    (CastKind::Int, Repr::I32) => {
+                        self.emit(Instruction::I64ExtendI32U);
+                        Repr::I64
+                    }
+                    (CastKind::Float, Repr::I64) => {
+                        self.emit(Instruction::F64ConvertI64S);
+                        Repr::F64
+                    }
+                    (CastKind::Int | CastKind::Float, repr @ (Repr::I64 | Repr::F64)) => repr,
+                    (kind, _) => {
+                        let helper = match kind {
+                            CastKind::Int => self.to_int_fn_idx,
+                            CastKind::Float => self.to_float_fn_idx,
+                            CastKind::String => self.to_string_fn_idx,
+                        };
+                        self.emit(Instruction::Call(helper));
+                        Repr::Boxed
+                    }
+                }
+            }
+            Inst::Box(operand) | Inst::Unbox(operand) | Inst::IntToFloat(operand) => {
+                self.emit_ir_use(state, *operand, wanted[0])?;
+                wanted[0]
+            }
+            Inst::Call(call) => {
+                for (&arg, &repr) in call.args.iter().zip(&wanted) {
+                    self.emit_ir_use(state, arg, repr)?;
+                }
+                let func_idx = match &call.copy {
+                    Some(copy) => self.specialized_functions[&copy.key],
+                    None => self.functions[&call.function],
+                };
+                self.emit(Instruction::Call(func_idx));
+                call_result_repr(call)
+            }
+            Inst::Builtin(name, args) => {
+                // The builtins compile from expressions, so the arguments are
+                // bound to temporaries
+                let mut temps = Vec::new();
+                for &arg in args {
+                    self.emit_ir_use(state, arg, Repr::Boxed)?;
+                    let local = self.allocate_local(self.get_php_value_type());
+                    self.emit(Instruction::LocalSet(local));
+                    temps.push((self.bind_temp_variable(local, None), local));
+                }
+                let exprs = temps.iter().map(|(name, _)| Expression::Variable(name.clone())).collect();
+                let compiled = self.compile_builtin_function(name, exprs)?;
+                for (name, local) in temps {
+                    self.variables.remove(&name);
+                    self.free_local(local, self.get_php_value_type());
+                }
+                if !compiled {
+                    return Err(format!("{}() is not a builtin", name));
+                }
+                Repr::Boxed
+            }
+            Inst::Echo(operand) => {
+                self.emit_ir_use(state, *operand, Repr::Boxed)?;
+                self.emit(Instruction::Call(self.print_value_fn_idx));
+                return Ok(());
+            }
+            Inst::Location(location) => {
+                self.record_location(*location);
+                return Ok(());
+            }
+            Inst::Param(_) => unreachable!("parameters live in their locals"),
+        };
+        self.emit_ir_convert(natural, function.repr(value));
+        self.emit(Instruction::LocalSet(state.locals[&value]));
+        Ok(())
+    }
+
+    /// A binary operator on two operands in representation `domain`; the
+    /// representation of its result
+    fn emit_ir_binary(&mut self, op: &BinaryOp, domain: Repr) -> Result<Repr, String> {
+        let instruction = match (domain, op) {
+            (Repr::I64, BinaryOp::Add) => Instruction::I64Add,
+            (Repr::I64, BinaryOp::Subtract) => Instruction::I64Sub,
+            (Repr::I64, BinaryOp::Multiply) => Instruction::I64Mul,
+            (Repr::I64, BinaryOp::Modulo) => Instruction::I64RemS,
+            (Repr::I64, BinaryOp::Equal | BinaryOp::Identical) => Instruction::I64Eq,
+            (Repr::I64, BinaryOp::NotEqual | BinaryOp::NotIdentical) => Instruction::I64Ne,
+            (Repr::I64, BinaryOp::LessThan) => Instruction::I64LtS,
+            (Repr::I64, BinaryOp::LessThanOrEqual) => Instruction::I64LeS,
+            (Repr::I64, BinaryOp::GreaterThan) => Instruction::I64GtS,
+            (Repr::I64, BinaryOp::GreaterThanOrEqual) => Instruction::I64GeS,
+            (Repr::F64, BinaryOp::Add) => Instruction::F64Add,
+            (Repr::F64, BinaryOp::Subtract) => Instruction::F64Sub,
+            (Repr::F64, BinaryOp::Multiply) => Instruction::F64Mul,
+            (Repr::F64, BinaryOp::Divide) => Instruction::F64Div,
+            (Repr::F64, BinaryOp::Equal | BinaryOp::Identical) => Instruction::F64Eq,
+            (Repr::F64, BinaryOp::NotEqual | BinaryOp::NotIdentical) => Instruction::F64Ne,
+            (Repr::F64, BinaryOp::LessThan) => Instruction::F64Lt,
+            (Repr::F64, BinaryOp::LessThanOrEqual) => Instruction::F64Le,
+            (Repr::F64, BinaryOp::GreaterThan) => Instruction::F64Gt,
+            (Repr::F64, BinaryOp::GreaterThanOrEqual) => Instruction::F64Ge,
+            (Repr::I32, BinaryOp::Equal | BinaryOp::Identical) => Instruction::I32Eq,
+            (Repr::I32, BinaryOp::NotEqual | BinaryOp::NotIdentical) => Instruction::I32Ne,
+            (Repr::Boxed, op) => {
+                let helper = match op {
+                    BinaryOp::Add => self.add_fn_idx,
+                    BinaryOp::Subtract => self.subtract_fn_idx,
+                    BinaryOp::Multiply => self.multiply_fn_idx,
+                    BinaryOp::Divide => self.divide_fn_idx,
+                    BinaryOp::Modulo => self.modulo_fn_idx,
+                    BinaryOp::Concat => self.concat_fn_idx,
+                    BinaryOp::Equal => self.equal_fn_idx,
+                    BinaryOp::NotEqual => self.not_equal_fn_idx,
+                    BinaryOp::Identical => self.identical_fn_idx,
+                    BinaryOp::NotIdentical => self.not_identical_fn_idx,
+                    BinaryOp::GreaterThan => self.greater_than_fn_idx,
+                    BinaryOp::LessThan => self.less_than_fn_idx,
+                    BinaryOp::GreaterThanOrEqual => self.greater_than_or_equal_fn_idx,
+                    BinaryOp::LessThanOrEqual => self.less_than_or_equal_fn_idx,
+                    BinaryOp::And | BinaryOp::Or => return Err("&& and || are lowered to branches".to_string()),
+                };
+                self.emit(Instruction::Call(helper));
+                return Ok(Repr::Boxed);
+            }
+            (domain, op) => return Err(format!("no {} form of {:?}", domain, op)),
+        };
+        let result = if is_comparison(op) { Repr::I32 } else { domain };
+        self.emit(instruction);
+        Ok(result)
+    }
+
+    /// Negate the PhpValue on the stack: ints stay ints, anything else becomes a float
+    fn emit_boxed_negation(&mut self) {
+        let php_value = self.gc_types.php_value;
+        let operand = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalTee(operand));
+        self.emit(Instruction::StructGet { struct_type_index: php_value, field_index: PHPVALUE_TYPE });
+        self.emit(Instruction::I32Const(TYPE_INT as i32));
+        self.emit(Instruction::I32Eq);
+        self.emit(Instruction::If(BlockType::Result(self.get_php_value_type())));
+        self.emit(Instruction::I64Const(0));
+        self.emit(Instruction::LocalGet(operand));
+        self.emit(Instruction::StructGet { struct_type_index: php_value, field_index: PHPVALUE_INT });
+        self.emit(Instruction::I64Sub);
+        self.emit_inline_box_int();
+        self.emit(Instruction::Else);
+        self.emit(Instruction::LocalGet(operand));
+        self.emit(Instruction::Call(self.to_float_fn_idx));
+        self.emit(Instruction::StructGet { struct_type_index: php_value, field_index: PHPVALUE_FLOAT });
+        self.emit(Instruction::F64Neg);
+        self.emit_inline_box_float();
+        self.emit(Instruction::End);
+        self.free_local(operand, self.get_php_value_type());
+    }
+}
+
+/// Raw constants are cheaper to emit again than to keep in a local
+fn is_rematerialized(function: &Function, value: Value) -> bool {
+    function.constant(value).is_some() && function.repr(value) != Repr::Boxed
+}
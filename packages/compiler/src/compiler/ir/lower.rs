@@ -0,0 +1,662 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Lowering of function bodies from the AST into SSA
+//
+// SSA is built directly while the body is walked, after Braun et al., "Simple
+// and Efficient Construction of Static Single Assignment Form": each block
+// remembers the last value written to each variable, and reading a variable
+// a block has not written looks through its predecessors. Blocks whose
+// predecessors are not all known yet (loop headers until their back edges
+// exist) are unsealed; reads there add a block parameter that gets its
+// arguments when the block is sealed. Parameters that turn out to always get
+// the same value are removed at the end.
+//
+// Statements after `return`, `break` and `continue` go into a block nothing
+// jumps to, which is dropped with the other unreachable blocks.
+
+use super::*;
+use crate::compiler::core::Compiler;
+use crate::compiler::inlining::{InlineCandidate, MAX_INLINE_DEPTH};
+use crate::compiler::type_inference::SUPERGLOBALS;
+use edge_php_parser::ast::{Block as AstBlock, Expression, InterpolatedPart, Literal, Parameter, Statement, Type, UnaryOp};
+use std::collections::HashSet;
+
+/// Builtins without side effects that the IR calls like operators; their
+/// arguments are passed by value
+pub const PURE_BUILTINS: [&str; 22] = [
+    "strlen", "strtolower", "strtoupper", "trim", "substr", "strpos", "str_replace",
+    "abs", "min", "max", "round", "floor", "ceil", "sqrt", "pow",
+    "is_int", "is_integer", "is_float", "is_string", "is_bool", "is_numeric", "is_null",
+];
+
+/// Where `break` and `continue` go in the innermost loop
+struct LoopTargets {
+    continue_to: BlockId,
+    break_to: BlockId,
+}
+
+struct FunctionBuilder<'a> {
+    compiler: &'a Compiler,
+    function: Function,
+    current: BlockId,
+    /// The value each block last wrote to each variable
+    definitions: HashMap<(String, BlockId), Value>,
+    sealed: HashSet<BlockId>,
+    /// Parameters of unsealed blocks that get their arguments on sealing
+    incomplete: HashMap<BlockId, Vec<(String, Value)>>,
+    loops: Vec<LoopTargets>,
+    /// The calls being inlined (see the `inlining` module), innermost last,
+    /// with the values of their parameters
+    inlined: Vec<(String, HashMap<String, Value>)>,
+}
+
+impl Compiler {
+    /// Lower a user function, or one of its specialized copies, into the IR.
+    /// Bodies the IR cannot express are refused with the reason.
+    pub(in crate::compiler) fn lower_function(&self, name: &str, params: &[Parameter], return_type: Option<&Type>, body: &AstBlock, specialization: Option<&Specialization>) -> Result<Function, String> {
+        if params.iter().any(|param| param.param_type.is_some()) || return_type.is_some() {
+            return Err("declared types".to_string());
+        }
+        if params.iter().any(|param| param.promoted.is_some()) {
+            return Err("promoted parameters".to_string());
+        }
+        let key = specialization.map_or(name, |specialization| specialization.key.as_str());
+        let return_repr = specialization
+            .and_then(|specialization| Repr::unboxed(&specialization.return_type))
+            .filter(|repr| *repr != Repr::I32)
+            .unwrap_or(Repr::Boxed);
+        let param_names = params.iter().map(|param| param.name.clone()).collect();
+        let mut builder = FunctionBuilder {
+            compiler: self,
+            function: Function::new(key, param_names, return_repr),
+            current: ENTRY,
+            definitions: HashMap::new(),
+            sealed: HashSet::from([ENTRY]),
+            incomplete: HashMap::new(),
+            loops: Vec::new(),
+            inlined: Vec::new(),
+        };
+
+        for (position, param) in params.iter().enumerate() {
+            // A copy takes its int and float parameters unboxed
+            let (ty, repr) = match specialization.map(|specialization| &specialization.params[position]) {
+                Some(InferredType::Int) => (InferredType::Int, Repr::I64),
+                Some(InferredType::Float) => (InferredType::Float, Repr::F64),
+                _ => (self.type_inference.parameter_type(key, position), Repr::Boxed),
+            };
+            let value = builder.function.append(ENTRY, Inst::Param(position), ty);
+            builder.function.value_mut(value).repr = repr;
+            builder.write(&param.name, value);
+        }
+
+        builder.lower_block(body)?;
+        if !builder.is_dead() {
+            let null = builder.constant(Constant::Null);
+            builder.terminate(Terminator::Return(null));
+        }
+        let mut function = builder.function;
+        function.remove_unreachable_blocks();
+        function.remove_trivial_params();
+        Ok(function)
+    }
+}
+
+impl FunctionBuilder<'_> {
+    fn lower_block(&mut self, block: &AstBlock) -> Result<(), String> {
+        for statement in &block.statements {
+            self.lower_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn lower_statement(&mut self, statement: &Statement) -> Result<(), String> {
+        match statement {
+            Statement::Expression(expr) => {
+                self.lower_expression(expr)?;
+            }
+            Statement::Echo(exprs) => {
+                for expr in exprs {
+                    let value = self.lower_expression(expr)?;
+                    self.append(Inst::Echo(value), InferredType::Null);
+                }
+            }
+            Statement::Return(expr) => {
+                let value = match expr {
+                    Some(expr) => self.lower_expression(expr)?,
+                    None => self.constant(Constant::Null),
+                };
+                self.terminate(Terminator::Return(value));
+            }
+            Statement::If { condition, then_block, elseif_blocks, else_block } => {
+                let join = self.function.add_block();
+                let mut branches: Vec<(&Expression, &AstBlock)> = vec![(condition, then_block)];
+                branches.extend(elseif_blocks.iter().map(|elseif| (&elseif.condition, &elseif.then_block)));
+                for (condition, body) in branches {
+                    let cond = self.lower_condition(condition)?;
+                    let then_bb = self.function.add_block();
+                    let else_bb = self.function.add_block();
+                    self.terminate(Terminator::Branch(cond, edge(then_bb), edge(else_bb)));
+                    self.seal(then_bb);
+                    self.seal(else_bb);
+                    self.current = then_bb;
+                    self.lower_block(body)?;
+                    self.jump(join, Vec::new());
+                    self.current = else_bb;
+                }
+                if let Some(else_block) = else_block {
+                    self.lower_block(else_block)?;
+                }
+                self.jump(join, Vec::new());
+                self.seal(join);
+                self.current = join;
+            }
+            Statement::While { condition, body } => {
+                let header = self.function.add_block();
+                self.jump(header, Vec::new());
+                self.current = header;
+                let cond = self.lower_condition(condition)?;
+                let body_bb = self.function.add_block();
+                let exit = self.function.add_block();
+                self.terminate(Terminator::Branch(cond, edge(body_bb), edge(exit)));
+                self.seal(body_bb);
+                self.current = body_bb;
+                self.lower_loop_body(body, header, exit)?;
+                self.jump(header, Vec::new());
+                self.seal(header);
+                self.seal(exit);
+                self.current = exit;
+            }
+            Statement::DoWhile { body, condition } => {
+                let body_bb = self.function.add_block();
+                let cond_bb = self.function.add_block();
+                let exit = self.function.add_block();
+                self.jump(body_bb, Vec::new());
+                self.current = body_bb;
+                self.lower_loop_body(body, cond_bb, exit)?;
+                self.jump(cond_bb, Vec::new());
+                self.seal(cond_bb);
+                self.current = cond_bb;
+                let cond = self.lower_condition(condition)?;
+                self.terminate(Terminator::Branch(cond, edge(body_bb), edge(exit)));
+                self.seal(body_bb);
+                self.seal(exit);
+                self.current = exit;
+            }
+            Statement::For { init, condition, update, body } => {
+                if let Some(init) = init {
+                    self.lower_statement(init)?;
+                }
+                let header = self.function.add_block();
+                let body_bb = self.function.add_block();
+                let update_bb = self.function.add_block();
+                let exit = self.function.add_block();
+                self.jump(header, Vec::new());
+                self.current = header;
+                match condition {
+                    Some(condition) => {
+                        let cond = self.lower_condition(condition)?;
+                        self.terminate(Terminator::Branch(cond, edge(body_bb), edge(exit)));
+                    }
+                    None => self.jump(body_bb, Vec::new()),
+                }
+                self.seal(body_bb);
+                self.current = body_bb;
+                self.lower_loop_body(body, update_bb, exit)?;
+                self.jump(update_bb, Vec::new());
+                self.seal(update_bb);
+                self.current = update_bb;
+                if let Some(update) = update {
+                    self.lower_expression(update)?;
+                }
+                self.jump(header, Vec::new());
+                self.seal(header);
+                self.seal(exit);
+                self.current = exit;
+            }
+            Statement::Break | Statement::Continue => {
+                let targets = self.loops.last().ok_or("break or continue outside a loop")?;
+                let target = if matches!(statement, Statement::Break) { targets.break_to } else { targets.continue_to };
+                self.jump(target, Vec::new());
+            }
+            Statement::Block(block) => self.lower_block(block)?,
+            Statement::Location(location) => {
+                self.append(Inst::Location(*location), InferredType::Null);
+            }
+            Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. } => {
+                return Err("nested declarations".to_string());
+            }
+            Statement::Foreach { .. } => return Err("foreach".to_string()),
+            Statement::Switch { .. } => return Err("switch".to_string()),
+            Statement::Global(_) | Statement::Static(_) => return Err("global or static variables".to_string()),
+            _ => return Err("unsupported statement".to_string()),
+        }
+        Ok(())
+    }
+
+    fn lower_loop_body(&mut self, body: &AstBlock, continue_to: BlockId, break_to: BlockId) -> Result<(), String> {
+        self.loops.push(LoopTargets { continue_to, break_to });
+        let result = self.lower_block(body);
+        self.loops.pop();
+        result
+    }
+
+    /// The truth value of a condition
+    fn lower_condition(&mut self, condition: &Expression) -> Result<Value, String> {
+        let value = self.lower_expression(condition)?;
+        Ok(self.append(Inst::Truthy(value), InferredType::Bool))
+    }
+
+    fn lower_expression(&mut self, expr: &Expression) -> Result<Value, String> {
+        match expr {
+            Expression::Literal(Literal::InterpolatedString(parts)) => self.lower_interpolated_string(parts),
+            Expression::Literal(literal) => Ok(self.constant(Constant::from_literal(literal).unwrap_or(Constant::Null))),
+            Expression::Variable(name) => self.read_variable(name),
+            Expression::Assignment { left, right } => {
+                let Expression::Variable(name) = &**left else {
+                    return Err("assignment to an array element, property or list".to_string());
+                };
+                check_variable(name)?;
+                let value = self.lower_expression(right)?;
+                self.write(name, value);
+                Ok(value)
+            }
+            Expression::Binary { left, op: op @ (BinaryOp::And | BinaryOp::Or), right } => {
+                // Short-circuit: the right operand only runs when it decides the result
+                let left = self.lower_condition(left)?;
+                let right_bb = self.function.add_block();
+                let join = self.function.add_block();
+                let result = self.function.add_block_param(join, InferredType::Bool);
+                let short = self.constant(Constant::Bool(matches!(op, BinaryOp::Or)));
+                let (then_edge, else_edge) = match op {
+                    BinaryOp::And => (edge(right_bb), Edge { block: join, args: vec![short] }),
+                    _ => (Edge { block: join, args: vec![short] }, edge(right_bb)),
+                };
+                self.terminate(Terminator::Branch(left, then_edge, else_edge));
+                self.seal(right_bb);
+                self.current = right_bb;
+                let right = self.lower_condition(right)?;
+                self.jump(join, vec![right]);
+                self.seal(join);
+                self.current = join;
+                Ok(result)
+            }
+            Expression::Binary { left, op, right } => {
+                let left = self.lower_expression(left)?;
+                let right = self.lower_expression(right)?;
+                Ok(self.append(Inst::Binary(op.clone(), left, right), InferredType::Dynamic))
+            }
+            Expression::Unary { op, expr } => self.lower_unary(op, expr),
+            Expression::Ternary { condition, then_expr, else_expr } => {
+                let cond = self.lower_condition(condition)?;
+                let then_bb = self.function.add_block();
+                let else_bb = self.function.add_block();
+                let join = self.function.add_block();
+                let result = self.function.add_block_param(join, InferredType::Dynamic);
+                self.terminate(Terminator::Branch(cond, edge(then_bb), edge(else_bb)));
+                self.seal(then_bb);
+                self.seal(else_bb);
+                self.current = then_bb;
+                let then_value = self.lower_expression(then_expr)?;
+                self.jump(join, vec![then_value]);
+                self.current = else_bb;
+                let else_value = self.lower_expression(else_expr)?;
+                self.jump(join, vec![else_value]);
+                self.seal(join);
+                self.current = join;
+                Ok(result)
+            }
+            Expression::Cast { cast_type, expr } => {
+                let value = self.lower_expression(expr)?;
+                let (inst, ty) = match cast_type {
+                    Type::Int => (Inst::Cast(CastKind::Int, value), InferredType::Int),
+                    Type::Float => (Inst::Cast(CastKind::Float, value), InferredType::Float),
+                    Type::String => (Inst::Cast(CastKind::String, value), InferredType::String),
+                    Type::Bool => (Inst::Truthy(value), InferredType::Bool),
+                    _ => return Err("casts to arrays and objects".to_string()),
+                };
+                Ok(self.append(inst, ty))
+            }
+            Expression::FunctionCall { name, args } => self.lower_call(name, args),
+            _ => Err("arrays, objects or other unsupported expressions".to_string()),
+        }
+    }
+
+    fn lower_unary(&mut self, op: &UnaryOp, expr: &Expression) -> Result<Value, String> {
+        let (is_increment, is_prefix) = match op {
+            UnaryOp::Not => {
+                let cond = self.lower_condition(expr)?;
+                return Ok(self.append(Inst::Not(cond), InferredType::Bool));
+            }
+            UnaryOp::Negate => {
+                let value = self.lower_expression(expr)?;
+                return Ok(self.append(Inst::Neg(value), InferredType::Dynamic));
+            }
+            UnaryOp::PreIncrement => (true, true),
+            UnaryOp::PostIncrement => (true, false),
+            UnaryOp::PreDecrement => (false, true),
+            UnaryOp::PostDecrement => (false, false),
+        };
+        let Expression::Variable(name) = expr else {
+            return Err("increment of an array element or property".to_string());
+        };
+        check_variable(name)?;
+        // The value is converted to an int first, as the AST path does
+        let old = self.read(name, self.current);
+        let as_int = self.append(Inst::Cast(CastKind::Int, old), InferredType::Int);
+        let one = self.constant(Constant::Int(1));
+        let op = if is_increment { BinaryOp::Add } else { BinaryOp::Subtract };
+        let new = self.append(Inst::Binary(op, as_int, one), InferredType::Int);
+        self.write(name, new);
+        Ok(if is_prefix { new } else { old })
+    }
+
+    /// The parts converted to strings and concatenated
+    fn lower_interpolated_string(&mut self, parts: &[InterpolatedPart]) -> Result<Value, String> {
+        let mut result = None;
+        for part in parts {
+            let value = match part {
+                InterpolatedPart::Text(text) => self.constant(Constant::String(text.clone())),
+                InterpolatedPart::Variable(name) => {
+                    let value = self.read_variable(name)?;
+                    self.append(Inst::Cast(CastKind::String, value), InferredType::String)
+                }
+                InterpolatedPart::Expression(expr) => {
+                    let value = self.lower_expression(expr)?;
+                    self.append(Inst::Cast(CastKind::String, value), InferredType::String)
+                }
+            };
+            result = Some(match result {
+                Some(left) => self.append(Inst::Binary(BinaryOp::Concat, left, value), InferredType::String),
+                None => value,
+            });
+        }
+        Ok(result.unwrap_or_else(|| self.constant(Constant::String(String::new()))))
+    }
+
+    /// The value of a variable where the current block is, or of a parameter
+    /// inside an inlined call
+    fn read_variable(&mut self, name: &str) -> Result<Value, String> {
+        if let Some((_, params)) = self.inlined.last() {
+            return Ok(params[name]);
+        }
+        check_variable(name)?;
+        Ok(self.read(name, self.current))
+    }
+
+    fn lower_call(&mut self, name: &str, args: &[Expression]) -> Result<Value, String> {
+        let compiler = self.compiler;
+        if let Some(params) = compiler.functions.contains_key(name).then(|| compiler.function_params.get(name)).flatten() {
+            if let Some(candidate) = self.inline_candidate(name, args.len()) {
+                return self.lower_inlined_call(name, &candidate, args);
+            }
+            let mut values = Vec::new();
+            for (position, arg) in args.iter().enumerate() {
+                let value = self.lower_expression(arg)?;
+                // Extra arguments are evaluated and dropped
+                if position < params.len() {
+                    values.push(value);
+                }
+            }
+            for param in params.iter().skip(args.len()) {
+                let value = match &param.default {
+                    Some(default) => self.lower_expression(default)?,
+                    None => self.constant(Constant::Null),
+                };
+                values.push(value);
+            }
+            let call = Call { function: name.to_string(), args: values, copy: None };
+            return Ok(self.append(Inst::Call(call), InferredType::Dynamic));
+        }
+        if PURE_BUILTINS.contains(&name) {
+            let values = args.iter().map(|arg| self.lower_expression(arg)).collect::<Result<_, _>>()?;
+            return Ok(self.append(Inst::Builtin(name.to_string(), values), InferredType::Dynamic));
+        }
+        Err(format!("call to {}()", name))
+    }
+
+    /// The candidate a call to `name` with `arg_count` arguments inlines; the
+    /// same limits apply as in the AST path
+    fn inline_candidate(&self, name: &str, arg_count: usize) -> Option<InlineCandidate> {
+        if self.inlined.len() >= MAX_INLINE_DEPTH || self.inlined.iter().any(|(inlined, _)| inlined == name) {
+            return None;
+        }
+        self.compiler.inline_candidates.get(name).filter(|candidate| candidate.accepts(arg_count) && candidate.is_ir_expression()).cloned()
+    }
+
+    /// The returned expression of a candidate, on the values of the arguments
+    fn lower_inlined_call(&mut self, name: &str, candidate: &InlineCandidate, args: &[Expression]) -> Result<Value, String> {
+        let mut params = HashMap::new();
+        for (position, arg) in args.iter().enumerate() {
+            let value = self.lower_expression(arg)?;
+            if let Some(param) = candidate.params.get(position) {
+                params.insert(param.name.clone(), value);
+            }
+        }
+        for param in candidate.params.iter().skip(args.len()) {
+            if let Some(default) = &param.default {
+                let value = self.lower_expression(default)?;
+                params.insert(param.name.clone(), value);
+            }
+        }
+        self.inlined.push((name.to_string(), params));
+        let result = self.lower_expression(&candidate.body);
+        self.inlined.pop();
+        result
+    }
+
+    fn append(&mut self, inst: Inst, ty: InferredType) -> Value {
+        self.function.append(self.current, inst, ty)
+    }
+
+    fn constant(&mut self, constant: Constant) -> Value {
+        let ty = constant.ty();
+        self.append(Inst::Const(constant), ty)
+    }
+
+    /// Whether the current block can never run: it was started after a jump
+    /// and nothing jumps to it
+    fn is_dead(&self) -> bool {
+        self.current != ENTRY && self.sealed.contains(&self.current) && self.preds(self.current).is_empty()
+    }
+
+    /// End the current block; what follows goes into a block nothing jumps to
+    fn terminate(&mut self, terminator: Terminator) {
+        let terminator = if self.is_dead() { Terminator::Unreachable } else { terminator };
+        self.function.block_mut(self.current).terminator = terminator;
+        self.current = self.function.add_block();
+        self.sealed.insert(self.current);
+    }
+
+    fn jump(&mut self, target: BlockId, args: Vec<Value>) {
+        self.terminate(Terminator::Jump(Edge { block: target, args }));
+    }
+
+    /// The blocks with an edge into `block`
+    fn preds(&self, block: BlockId) -> Vec<BlockId> {
+        self.function.blocks.iter().enumerate()
+            .filter(|(_, data)| data.terminator.edges().iter().any(|edge| edge.block == block))
+            .map(|(index, _)| BlockId(index as u32))
+            .collect()
+    }
+
+    fn write(&mut self, name: &str, value: Value) {
+        self.definitions.insert((name.to_string(), self.current), value);
+    }
+
+    fn read(&mut self, name: &str, block: BlockId) -> Value {
+        if let Some(&value) = self.definitions.get(&(name.to_string(), block)) {
+            return value;
+        }
+        let value = if !self.sealed.contains(&block) {
+            let param = self.function.add_block_param(block, InferredType::Dynamic);
+            self.incomplete.entry(block).or_default().push((name.to_string(), param));
+            param
+        } else {
+            let preds = self.preds(block);
+            match preds.as_slice() {
+                // Undefined variables are null
+                [] => {
+                    let null = self.function.new_value(Inst::Const(Constant::Null), InferredType::Null);
+                    self.function.block_mut(block).insts.insert(0, null);
+                    null
+                }
+                [pred] => self.read(name, *pred),
+                _ => {
+                    let param = self.function.add_block_param(block, InferredType::Dynamic);
+                    self.definitions.insert((name.to_string(), block), param);
+                    self.add_param_args(name, block);
+                    param
+                }
+            }
+        };
+        self.definitions.insert((name.to_string(), block), value);
+        value
+    }
+
+    /// Pass the value of `name` at the end of each predecessor to the newest
+    /// parameter of `block`
+    fn add_param_args(&mut self, name: &str, block: BlockId) {
+        for pred in self.preds(block) {
+            let value = self.read(name, pred);
+            for edge in self.function.block_mut(pred).terminator.edges_mut() {
+                if edge.block == block {
+                    edge.args.push(value);
+                }
+            }
+        }
+    }
+
+    /// Every edge into `block` exists now
+    fn seal(&mut self, block: BlockId) {
+        for (name, _) in self.incomplete.remove(&block).unwrap_or_default() {
+            self.add_param_args(&name, block);
+        }
+        self.sealed.insert(block);
+    }
+}
+
+fn edge(block: BlockId) -> Edge {
+    Edge { block, args: Vec::new() }
+}
+
+fn check_variable(name: &str) -> Result<(), String> {
+    if name == "this" || SUPERGLOBALS.contains(&name) {
+        return Err(format!("${}", name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Compiler, CompilerOptions, OptLevel};
+
+    /// The IR as lowered, with only types inferred
+    fn lowered(source: &str) -> String {
+        let options = CompilerOptions::new()
+            .opt_level(OptLevel::O0)
+            .mid_level_ir(true)
+            .ir_dump(true);
+        Compiler::with_options(options).compile_module("lower.php", source).unwrap().ir.unwrap()
+    }
+
+    #[test]
+    fn test_lower_control_flow() {
+        let ir = lowered(r#"<?php
+function pick($a, $b) {
+    if ($a > $b && $b > 0) {
+        $m = $a;
+    } else {
+        $m = $b;
+    }
+    while ($m > 10) {
+        $m = $m - 3;
+    }
+    echo "m=", $m;
+    return abs($m) . "";
+}
+echo pick(12, 5);
+"#);
+        // `&&` becomes a branch, variables assigned on both arms and in the
+        // loop become block parameters, and pure builtins stay instructions
+        assert_eq!(ir, r#"function pick($a, $b) -> boxed {
+bb0:
+    v0: int = param 0
+    v1: int = param 1
+    v2: bool = gt v0, v1
+    v3: bool = truthy v2
+    v5: bool = const false
+    branch v3, bb2, bb3(v5)
+bb2:
+    v6: int = const 0
+    v7: bool = gt v1, v6
+    v8: bool = truthy v7
+    jump bb3(v8)
+bb3(v4: bool):
+    v9: bool = truthy v4
+    branch v9, bb6, bb7
+bb7:
+    jump bb1(v1)
+bb6:
+    jump bb1(v0)
+bb1(v18: int):
+    jump bb11(v18)
+bb11(v12: int):
+    v13: int = const 10
+    v14: bool = gt v12, v13
+    v15: bool = truthy v14
+    branch v15, bb13, bb14
+bb14:
+    v19: string = const "m="
+    echo v19
+    echo v12
+    v22: mixed = builtin abs(v12)
+    v23: string = const ""
+    v24: string = concat v22, v23
+    return v24
+bb13:
+    v16: int = const 3
+    v17: int = sub v12, v16
+    jump bb11(v17)
+}
+
+"#);
+    }
+
+    #[test]
+    fn test_unsupported_bodies_fall_back() {
+        let ir = lowered(r#"<?php
+function g($x) { global $y; return $x; }
+function s($x) { static $n = 0; return $x; }
+function f($x) { foreach ([1] as $v) {} return $x; }
+function t(int $x) { return $x; }
+function o($x) { return count([1]) + $x; }
+function w($x) { switch ($x) { case 1: return 2; } return 3; }
+function a($x) { return [$x]; }
+function e($x) { $x[0] = 1; return $x; }
+class K {
+    public function m($x) { return $x + 1; }
+}
+echo g(1), s(1), f(1), t(1), o(1), w(1), a(1)[0], e([0])[0], (new K())->m(1);
+"#);
+        // Methods and top-level code are never lowered, so the dump skips them
+        assert_eq!(ir, "\
+; g is compiled from the AST: global or static variables
+
+; s is compiled from the AST: global or static variables
+
+; f is compiled from the AST: foreach
+
+; t is compiled from the AST: declared types
+
+; o is compiled from the AST: call to count()
+
+; w is compiled from the AST: switch
+
+; a is compiled from the AST: arrays, objects or other unsupported expressions
+
+; e is compiled from the AST: assignment to an array element, property or list
+
+");
+    }
+}
@@ -0,0 +1,808 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Mid-level IR: typed SSA between the AST and Wasm emission
+//
+// User function bodies are lowered from the AST into a control-flow graph of
+// basic blocks in SSA form (lower.rs). Blocks take parameters instead of phi
+// nodes: a jump passes one argument per parameter of its target. Every value
+// has an inferred type and a representation - a boxed PhpValue or a raw
+// i64/f64/i32 - so the decision which values stay unboxed is made on the IR
+// and not while the AST is walked.
+//
+// A pass manager (passes.rs) runs type inference, constant propagation, common
+// subexpression elimination, loop-invariant code motion, dead code
+// elimination and the unboxing pass over each function; emit.rs then turns
+// the graph back into structured Wasm control flow.
+//
+// Out of scope: only "simple" user functions are lowered. Top-level code and
+// methods never are, and neither are functions whose bodies use arrays,
+// objects, globals or statics, declared types, `switch` or `foreach`, or call
+// anything but user functions and a few pure builtins. Those silently fall
+// back to the AST path, which compiles them exactly as with the IR off; only
+// the IR dump (`--emit ir`) notes which functions fell back and why.
+
+pub mod cfg;   // Predecessors, dominators and loops
+pub mod lower; // AST to IR
+pub mod passes; // The pass manager and the optimization passes
+pub mod emit;  // IR to Wasm
+
+use super::core::Compiler;
+use super::type_inference::{InferredType, Specialization};
+use edge_php_parser::ast::{BinaryOp, Block as AstBlock, Literal, Parameter, SourceLocation, Type};
+use std::collections::HashMap;
+use std::fmt;
+
+pub use passes::{Pass, PassManager};
+
+/// An SSA value: the result of an instruction or a block parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+/// How a value is held at run time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Repr {
+    /// A PhpValue reference
+    Boxed,
+    /// A raw int
+    I64,
+    /// A raw float
+    F64,
+    /// A raw bool
+    I32,
+}
+
+impl Repr {
+    /// The unboxed representation of values of a type, if it has one
+    pub fn unboxed(ty: &InferredType) -> Option<Repr> {
+        match ty {
+            InferredType::Int => Some(Repr::I64),
+            InferredType::Float => Some(Repr::F64),
+            InferredType::Bool => Some(Repr::I32),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Null,
+}
+
+impl Constant {
+    pub fn ty(&self) -> InferredType {
+        match self {
+            Constant::Int(_) => InferredType::Int,
+            Constant::Float(_) => InferredType::Float,
+            Constant::Bool(_) => InferredType::Bool,
+            Constant::String(_) => InferredType::String,
+            Constant::Null => InferredType::Null,
+        }
+    }
+
+    /// The value of `(bool)` on this constant
+    pub fn truthy(&self) -> bool {
+        match self {
+            Constant::Int(n) => *n != 0,
+            Constant::Float(f) => *f != 0.0,
+            Constant::Bool(b) => *b,
+            Constant::String(s) => !s.is_empty() && s != "0",
+            Constant::Null => false,
+        }
+    }
+
+    /// The constant a scalar literal stands for
+    pub fn from_literal(literal: &Literal) -> Option<Constant> {
+        match literal {
+            Literal::Integer(n) => Some(Constant::Int(*n)),
+            Literal::Float(x) => Some(Constant::Float(*x)),
+            Literal::Boolean(b) => Some(Constant::Bool(*b)),
+            Literal::String(s) => Some(Constant::String(s.clone())),
+            Literal::Null => Some(Constant::Null),
+            Literal::InterpolatedString(_) => None,
+        }
+    }
+
+    pub fn into_literal(self) -> Literal {
+        match self {
+            Constant::Int(n) => Literal::Integer(n),
+            Constant::Float(x) => Literal::Float(x),
+            Constant::Bool(b) => Literal::Boolean(b),
+            Constant::String(s) => Literal::String(s),
+            Constant::Null => Literal::Null,
+        }
+    }
+}
+
+/// Target type of a cast; `(bool)` is lowered to `Truthy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastKind {
+    Int,
+    Float,
+    String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    Const(Constant),
+    /// The function's parameter at this position
+    Param(usize),
+    /// Any binary operator but `&&` and `||`, which are control flow
+    Binary(BinaryOp, Value, Value),
+    Neg(Value),
+    /// Negation of an i32 truth value
+    Not(Value),
+    /// Whether a value is truthy
+    Truthy(Value),
+    Cast(CastKind, Value),
+    /// Box a raw value into a PhpValue
+    Box(Value),
+    /// Take the raw value out of a PhpValue, converting it to the result's representation
+    Unbox(Value),
+    IntToFloat(Value),
+    Call(Call),
+    /// One of the pure builtins the IR knows (see `lower::PURE_BUILTINS`)
+    Builtin(String, Vec<Value>),
+    Echo(Value),
+    /// The code emitted next comes from this source location
+    Location(SourceLocation),
+}
+
+/// A call of a user function
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub function: String,
+    /// One argument per parameter, defaults filled in
+    pub args: Vec<Value>,
+    /// The specialized copy the call goes to, once the argument types are known
+    pub copy: Option<Specialization>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub block: BlockId,
+    /// One per parameter of the target block
+    pub args: Vec<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(Edge),
+    /// Branch on an i32 truth value
+    Branch(Value, Edge, Edge),
+    Return(Value),
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn edges(&self) -> Vec<&Edge> {
+        match self {
+            Terminator::Jump(edge) => vec![edge],
+            Terminator::Branch(_, then_edge, else_edge) => vec![then_edge, else_edge],
+            _ => vec![],
+        }
+    }
+
+    pub fn edges_mut(&mut self) -> Vec<&mut Edge> {
+        match self {
+            Terminator::Jump(edge) => vec![edge],
+            Terminator::Branch(_, then_edge, else_edge) => vec![then_edge, else_edge],
+            _ => vec![],
+        }
+    }
+
+    /// Every value the terminator uses
+    pub fn operands(&self) -> Vec<Value> {
+        let mut operands = match self {
+            Terminator::Branch(cond, ..) | Terminator::Return(cond) => vec![*cond],
+            _ => vec![],
+        };
+        for edge in self.edges() {
+            operands.extend(&edge.args);
+        }
+        operands
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Def {
+    Inst(Inst),
+    /// A parameter of this block
+    BlockParam(BlockId),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueData {
+    pub def: Def,
+    pub ty: InferredType,
+    pub repr: Repr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub params: Vec<Value>,
+    pub insts: Vec<Value>,
+    pub terminator: Terminator,
+}
+
+/// A function in SSA form; block 0 is the entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// The PHP name, or the key of a specialized copy ("fib<int>")
+    pub name: String,
+    pub param_names: Vec<String>,
+    /// How the function returns its result
+    pub return_repr: Repr,
+    pub blocks: Vec<Block>,
+    pub values: Vec<ValueData>,
+}
+
+pub const ENTRY: BlockId = BlockId(0);
+
+impl Compiler {
+    /// Lower a user function (or one of its copies) and run the passes over
+    /// it; None if the function is compiled from the AST instead
+    pub(super) fn compile_to_ir(&mut self, name: &str, params: &[Parameter], return_type: Option<&Type>, body: &AstBlock, specialization: Option<&Specialization>) -> Option<Function> {
+        if !self.options.mid_level_ir_enabled() {
+            return None;
+        }
+        match self.lower_function(name, params, return_type, body, specialization) {
+            Ok(mut function) => {
+                PassManager::for_options(&self.options).run(&mut function, &self.type_inference);
+                if self.options.ir_dump_enabled() {
+                    self.ir_listing.push_str(&format!("{}\n", function));
+                }
+                Some(function)
+            }
+            Err(reason) => {
+                if self.options.ir_dump_enabled() {
+                    let key = specialization.map_or(name, |specialization| specialization.key.as_str());
+                    self.ir_listing.push_str(&format!("; {} is compiled from the AST: {}\n\n", key, reason));
+                }
+                None
+            }
+        }
+    }
+}
+
+impl Function {
+    pub fn new(name: &str, param_names: Vec<String>, return_repr: Repr) -> Self {
+        let mut function = Function {
+            name: name.to_string(),
+            param_names,
+            return_repr,
+            blocks: Vec::new(),
+            values: Vec::new(),
+        };
+        function.add_block();
+        function
+    }
+
+    pub fn add_block(&mut self) -> BlockId {
+        self.blocks.push(Block { params: Vec::new(), insts: Vec::new(), terminator: Terminator::Unreachable });
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    pub fn block(&self, block: BlockId) -> &Block {
+        &self.blocks[block.0 as usize]
+    }
+
+    pub fn block_mut(&mut self, block: BlockId) -> &mut Block {
+        &mut self.blocks[block.0 as usize]
+    }
+
+    pub fn value(&self, value: Value) -> &ValueData {
+        &self.values[value.0 as usize]
+    }
+
+    pub fn value_mut(&mut self, value: Value) -> &mut ValueData {
+        &mut self.values[value.0 as usize]
+    }
+
+    pub fn ty(&self, value: Value) -> &InferredType {
+        &self.value(value).ty
+    }
+
+    pub fn repr(&self, value: Value) -> Repr {
+        self.value(value).repr
+    }
+
+    pub fn inst(&self, value: Value) -> Option<&Inst> {
+        match &self.value(value).def {
+            Def::Inst(inst) => Some(inst),
+            Def::BlockParam(_) => None,
+        }
+    }
+
+    pub fn constant(&self, value: Value) -> Option<&Constant> {
+        match self.inst(value) {
+            Some(Inst::Const(constant)) => Some(constant),
+            _ => None,
+        }
+    }
+
+    /// A value defined by `inst`, not yet placed in a block
+    pub fn new_value(&mut self, inst: Inst, ty: InferredType) -> Value {
+        self.values.push(ValueData { def: Def::Inst(inst), ty, repr: Repr::Boxed });
+        Value(self.values.len() as u32 - 1)
+    }
+
+    /// Append an instruction to a block
+    pub fn append(&mut self, block: BlockId, inst: Inst, ty: InferredType) -> Value {
+        let value = self.new_value(inst, ty);
+        self.block_mut(block).insts.push(value);
+        value
+    }
+
+    pub fn add_block_param(&mut self, block: BlockId, ty: InferredType) -> Value {
+        self.values.push(ValueData { def: Def::BlockParam(block), ty, repr: Repr::Boxed });
+        let value = Value(self.values.len() as u32 - 1);
+        self.block_mut(block).params.push(value);
+        value
+    }
+
+    /// Values an instruction uses
+    pub fn operands(&self, value: Value) -> Vec<Value> {
+        match self.inst(value) {
+            Some(inst) => inst_operands(inst),
+            None => Vec::new(),
+        }
+    }
+
+    /// Whether the instruction defining `value` can be removed, moved or
+    /// merged with an equal one: it has no side effects and cannot trap.
+    /// Converting objects to strings may run `__toString`, so only
+    /// conversions of known scalars count.
+    pub fn is_pure(&self, value: Value) -> bool {
+        let scalar = |value: &Value| matches!(self.ty(*value),
+            InferredType::Int | InferredType::Float | InferredType::String | InferredType::Bool | InferredType::Null);
+        match self.inst(value) {
+            Some(Inst::Const(_) | Inst::Neg(_) | Inst::Not(_) | Inst::Truthy(_) | Inst::Box(_) | Inst::Unbox(_) | Inst::IntToFloat(_)) => true,
+            Some(Inst::Binary(BinaryOp::Divide | BinaryOp::Modulo, left, right)) => {
+                scalar(left) && self.constant(*right).is_some_and(|divisor| divisor.truthy() && divisor.ty().is_numeric())
+            }
+            Some(Inst::Binary(_, left, right)) => scalar(left) && scalar(right),
+            Some(Inst::Cast(_, operand)) => scalar(operand),
+            Some(Inst::Builtin(_, args)) => args.iter().all(scalar),
+            _ => false,
+        }
+    }
+
+    /// Replace every use of a key of `replacements` by its value
+    pub fn replace_uses(&mut self, replacements: &HashMap<Value, Value>) {
+        if replacements.is_empty() {
+            return;
+        }
+        let resolve = |mut value: Value| {
+            while let Some(&replacement) = replacements.get(&value) {
+                value = replacement;
+            }
+            value
+        };
+        for data in &mut self.values {
+            if let Def::Inst(inst) = &mut data.def {
+                for operand in inst_operands_mut(inst) {
+                    *operand = resolve(*operand);
+                }
+            }
+        }
+        for block in &mut self.blocks {
+            match &mut block.terminator {
+                Terminator::Branch(cond, ..) | Terminator::Return(cond) => *cond = resolve(*cond),
+                _ => {}
+            }
+            for edge in block.terminator.edges_mut() {
+                for arg in &mut edge.args {
+                    *arg = resolve(*arg);
+                }
+            }
+        }
+    }
+
+    /// How many times each value is used by the reachable blocks
+    pub fn use_counts(&self, reachable: &[BlockId]) -> HashMap<Value, usize> {
+        let mut counts = HashMap::new();
+        for &block in reachable {
+            for &value in &self.block(block).insts {
+                for operand in self.operands(value) {
+                    *counts.entry(operand).or_insert(0) += 1;
+                }
+            }
+            for operand in self.block(block).terminator.operands() {
+                *counts.entry(operand).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// Drop the blocks that cannot be reached from the entry
+    pub fn remove_unreachable_blocks(&mut self) -> bool {
+        let reachable = cfg::Cfg::new(self).reachable();
+        let mut changed = false;
+        for (index, block) in self.blocks.iter_mut().enumerate() {
+            if !reachable.contains(&BlockId(index as u32)) && (!block.insts.is_empty() || !block.params.is_empty() || block.terminator != Terminator::Unreachable) {
+                *block = Block { params: Vec::new(), insts: Vec::new(), terminator: Terminator::Unreachable };
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Remove block parameters that always receive the same value (ignoring
+    /// the parameter itself, passed around a loop), using that value instead
+    pub fn remove_trivial_params(&mut self) -> bool {
+        let mut changed = false;
+        loop {
+            let cfg = cfg::Cfg::new(self);
+            let mut replacements = HashMap::new();
+            for &block in cfg.rpo() {
+                let incoming = self.incoming_args(&cfg, block);
+                for (position, &param) in self.block(block).params.iter().enumerate() {
+                    let mut same = None;
+                    let trivial = incoming.iter().all(|args| {
+                        let arg = args[position];
+                        if arg == param || Some(arg) == same {
+                            return true;
+                        }
+                        if same.is_none() {
+                            same = Some(arg);
+                            return true;
+                        }
+                        false
+                    });
+                    if let (true, Some(same)) = (trivial, same) {
+                        replacements.insert(param, same);
+                    }
+                }
+            }
+            if replacements.is_empty() {
+                return changed;
+            }
+            self.replace_uses(&replacements);
+            self.remove_params(|param| replacements.contains_key(&param));
+            changed = true;
+        }
+    }
+
+    /// The arguments every reachable edge into `block` passes
+    pub fn incoming_args(&self, cfg: &cfg::Cfg, block: BlockId) -> Vec<Vec<Value>> {
+        let mut incoming = Vec::new();
+        for &pred in cfg.unique_preds(block) {
+            for edge in self.block(pred).terminator.edges() {
+                if edge.block == block {
+                    incoming.push(edge.args.clone());
+                }
+            }
+        }
+        incoming
+    }
+
+    /// Remove the block parameters `remove` picks, and their arguments
+    pub fn remove_params(&mut self, remove: impl Fn(Value) -> bool) {
+        let kept: Vec<Vec<bool>> = self.blocks.iter()
+            .map(|block| block.params.iter().map(|&param| !remove(param)).collect())
+            .collect();
+        for block in &mut self.blocks {
+            for edge in block.terminator.edges_mut() {
+                let keep = &kept[edge.block.0 as usize];
+                if keep.len() == edge.args.len() {
+                    let mut position = 0;
+                    edge.args.retain(|_| {
+                        position += 1;
+                        keep[position - 1]
+                    });
+                }
+            }
+        }
+        for (block, keep) in self.blocks.iter_mut().zip(&kept) {
+            let mut position = 0;
+            block.params.retain(|_| {
+                position += 1;
+                keep[position - 1]
+            });
+        }
+    }
+}
+
+/// Values an instruction uses
+pub fn inst_operands(inst: &Inst) -> Vec<Value> {
+    match inst {
+        Inst::Binary(_, left, right) => vec![*left, *right],
+        Inst::Neg(operand) | Inst::Not(operand) | Inst::Truthy(operand) | Inst::Cast(_, operand)
+        | Inst::Box(operand) | Inst::Unbox(operand) | Inst::IntToFloat(operand) | Inst::Echo(operand) => vec![*operand],
+        Inst::Call(call) => call.args.clone(),
+        Inst::Builtin(_, args) => args.clone(),
+        Inst::Const(_) | Inst::Param(_) | Inst::Location(_) => Vec::new(),
+    }
+}
+
+pub fn inst_operands_mut(inst: &mut Inst) -> Vec<&mut Value> {
+    match inst {
+        Inst::Binary(_, left, right) => vec![left, right],
+        Inst::Neg(operand) | Inst::Not(operand) | Inst::Truthy(operand) | Inst::Cast(_, operand)
+        | Inst::Box(operand) | Inst::Unbox(operand) | Inst::IntToFloat(operand) | Inst::Echo(operand) => vec![operand],
+        Inst::Call(call) => call.args.iter_mut().collect(),
+        Inst::Builtin(_, args) => args.iter_mut().collect(),
+        Inst::Const(_) | Inst::Param(_) | Inst::Location(_) => Vec::new(),
+    }
+}
+
+/// Whether an instruction produces a value
+pub fn has_result(inst: &Inst) -> bool {
+    !matches!(inst, Inst::Echo(_) | Inst::Location(_))
+}
+
+/// Comparison operators, which give bools
+pub fn is_comparison(op: &BinaryOp) -> bool {
+    matches!(op, BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Identical | BinaryOp::NotIdentical
+        | BinaryOp::LessThan | BinaryOp::LessThanOrEqual | BinaryOp::GreaterThan | BinaryOp::GreaterThanOrEqual)
+}
+
+/// The representation a comparison of values of these types can be made in
+/// without the runtime helpers
+pub fn comparison_repr(op: &BinaryOp, left: &InferredType, right: &InferredType) -> Option<Repr> {
+    let identity = matches!(op, BinaryOp::Identical | BinaryOp::NotIdentical);
+    match (left, right) {
+        (InferredType::Int, InferredType::Int) => Some(Repr::I64),
+        (InferredType::Float, InferredType::Float) => Some(Repr::F64),
+        (left, right) if left.is_numeric() && right.is_numeric() && !identity => Some(Repr::F64),
+        (InferredType::Bool, InferredType::Bool)
+            if matches!(op, BinaryOp::Equal | BinaryOp::NotEqual) || identity => Some(Repr::I32),
+        _ => None,
+    }
+}
+
+/// The representation each operand of `value`'s instruction is used in, given
+/// the representation of `value` itself
+pub fn operand_reprs(function: &Function, value: Value) -> Vec<Repr> {
+    let repr = function.repr(value);
+    let Some(inst) = function.inst(value) else { return Vec::new() };
+    match inst {
+        Inst::Binary(op, left, right) if is_comparison(op) => {
+            let domain = comparison_repr(op, function.ty(*left), function.ty(*right))
+                .filter(|_| repr != Repr::Boxed)
+                .unwrap_or(Repr::Boxed);
+            vec![domain, domain]
+        }
+        Inst::Binary(BinaryOp::Concat, ..) => vec![Repr::Boxed, Repr::Boxed],
+        Inst::Binary(..) => {
+            let domain = if matches!(repr, Repr::I64 | Repr::F64) { repr } else { Repr::Boxed };
+            vec![domain, domain]
+        }
+        Inst::Neg(_) => vec![if matches!(repr, Repr::I64 | Repr::F64) { repr } else { Repr::Boxed }],
+        Inst::Not(_) => vec![Repr::I32],
+        // Any representation can be tested as is
+        Inst::Truthy(operand) | Inst::Box(operand) => vec![function.repr(*operand)],
+        Inst::Cast(kind, operand) => vec![match (kind, function.ty(*operand)) {
+            (CastKind::Int | CastKind::Float, InferredType::Int) => Repr::I64,
+            (CastKind::Int, InferredType::Bool) => Repr::I32,
+            (CastKind::Float, InferredType::Float) => Repr::F64,
+            _ => Repr::Boxed,
+        }],
+        Inst::Unbox(_) => vec![Repr::Boxed],
+        Inst::IntToFloat(_) => vec![Repr::I64],
+        Inst::Call(call) => match &call.copy {
+            Some(copy) => call.args.iter().enumerate()
+                .map(|(position, _)| match copy.params.get(position) {
+                    Some(InferredType::Int) => Repr::I64,
+                    Some(InferredType::Float) => Repr::F64,
+                    _ => Repr::Boxed,
+                })
+                .collect(),
+            None => vec![Repr::Boxed; call.args.len()],
+        },
+        Inst::Builtin(_, args) => vec![Repr::Boxed; args.len()],
+        Inst::Echo(_) => vec![Repr::Boxed],
+        Inst::Const(_) | Inst::Param(_) | Inst::Location(_) => Vec::new(),
+    }
+}
+
+/// The representation a call's result comes back in
+pub fn call_result_repr(call: &Call) -> Repr {
+    match call.copy.as_ref().map(|copy| &copy.return_type) {
+        Some(InferredType::Int) => Repr::I64,
+        Some(InferredType::Float) => Repr::F64,
+        _ => Repr::Boxed,
+    }
+}
+
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Repr::Boxed => write!(f, "boxed"),
+            Repr::I64 => write!(f, "i64"),
+            Repr::F64 => write!(f, "f64"),
+            Repr::I32 => write!(f, "i32"),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Int(n) => write!(f, "{}", n),
+            Constant::Float(x) => write!(f, "{:?}", x),
+            Constant::Bool(b) => write!(f, "{}", b),
+            Constant::String(s) => write!(f, "{:?}", s),
+            Constant::Null => write!(f, "null"),
+        }
+    }
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.block)?;
+        if !self.args.is_empty() {
+            write!(f, "({})", list(&self.args))?;
+        }
+        Ok(())
+    }
+}
+
+/// Textual form of an instruction; equal pure instructions print the same, so
+/// common subexpression elimination uses it as their key
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Inst::Const(constant) => write!(f, "const {}", constant),
+            Inst::Param(position) => write!(f, "param {}", position),
+            Inst::Binary(op, left, right) => write!(f, "{} {}, {}", binary_name(op), left, right),
+            Inst::Neg(operand) => write!(f, "neg {}", operand),
+            Inst::Not(operand) => write!(f, "not {}", operand),
+            Inst::Truthy(operand) => write!(f, "truthy {}", operand),
+            Inst::Cast(kind, operand) => write!(f, "cast.{} {}", format!("{:?}", kind).to_lowercase(), operand),
+            Inst::Box(operand) => write!(f, "box {}", operand),
+            Inst::Unbox(operand) => write!(f, "unbox {}", operand),
+            Inst::IntToFloat(operand) => write!(f, "int_to_float {}", operand),
+            Inst::Call(call) => {
+                let callee = call.copy.as_ref().map_or(&call.function, |copy| &copy.key);
+                write!(f, "call {}({})", callee, list(&call.args))
+            }
+            Inst::Builtin(name, args) => write!(f, "builtin {}({})", name, list(args)),
+            Inst::Echo(operand) => write!(f, "echo {}", operand),
+            Inst::Location(location) => write!(f, "location {}:{}", location.line, location.column),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self.param_names.iter().map(|name| format!("${}", name)).collect();
+        writeln!(f, "function {}({}) -> {} {{", self.name, params.join(", "), self.return_repr)?;
+        let cfg = cfg::Cfg::new(self);
+        for &block in cfg.rpo() {
+            let data = self.block(block);
+            write!(f, "{}", block)?;
+            if !data.params.is_empty() {
+                let params: Vec<String> = data.params.iter().map(|&param| format!("{}: {}", param, self.type_label(param))).collect();
+                write!(f, "({})", params.join(", "))?;
+            }
+            writeln!(f, ":")?;
+            for &value in &data.insts {
+                let inst = self.inst(value).expect("blocks hold instructions");
+                if has_result(inst) {
+                    writeln!(f, "    {}: {} = {}", value, self.type_label(value), inst)?;
+                } else {
+                    writeln!(f, "    {}", inst)?;
+                }
+            }
+            match &data.terminator {
+                Terminator::Jump(edge) => writeln!(f, "    jump {}", edge)?,
+                Terminator::Branch(cond, then_edge, else_edge) => writeln!(f, "    branch {}, {}, {}", cond, then_edge, else_edge)?,
+                Terminator::Return(value) => writeln!(f, "    return {}", value)?,
+                Terminator::Unreachable => writeln!(f, "    unreachable")?,
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Function {
+    /// "int", or "int as i64" once the value is unboxed
+    fn type_label(&self, value: Value) -> String {
+        match self.repr(value) {
+            Repr::Boxed => self.ty(value).to_string(),
+            repr => format!("{} as {}", self.ty(value), repr),
+        }
+    }
+}
+
+fn binary_name(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "add",
+        BinaryOp::Subtract => "sub",
+        BinaryOp::Multiply => "mul",
+        BinaryOp::Divide => "div",
+        BinaryOp::Modulo => "mod",
+        BinaryOp::Equal => "eq",
+        BinaryOp::NotEqual => "ne",
+        BinaryOp::Identical => "identical",
+        BinaryOp::NotIdentical => "not_identical",
+        BinaryOp::LessThan => "lt",
+        BinaryOp::LessThanOrEqual => "le",
+        BinaryOp::GreaterThan => "gt",
+        BinaryOp::GreaterThanOrEqual => "ge",
+        BinaryOp::And => "and",
+        BinaryOp::Or => "or",
+        BinaryOp::Concat => "concat",
+    }
+}
+
+fn list(values: &[Value]) -> String {
+    values.iter().map(Value::to_string).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Compiler, CompilerOptions, OptLevel};
+
+    const SOURCE: &str = "<?php
+function poly($n) {
+    $total = 0;
+    for ($i = 0; $i < $n; $i++) {
+        $k = $n * 2;
+        $total = $total + $i * $k;
+    }
+    $unused = $n + 1;
+    return $total;
+}
+function first($list) { return $list[0]; }
+echo poly(4), first([1]);
+";
+
+    #[test]
+    fn test_dump() {
+        let options = CompilerOptions::new().opt_level(OptLevel::O2).ir_dump(true);
+        let ir = Compiler::with_options(options).compile_module("poly.php", SOURCE).unwrap().ir.unwrap();
+        // $k is hoisted and unboxed, $unused is gone
+        assert_eq!(ir, r#"function poly($n) -> boxed {
+bb0:
+    v0: int = param 0
+    v1: int as i64 = const 0
+    v7: int as i64 = const 2
+    v17: int as i64 = unbox v0
+    v8: int as i64 = mul v17, v7
+    v13: int as i64 = const 1
+    jump bb1(v1, v1)
+bb1(v3: int as i64, v9: int as i64):
+    v5: bool as i32 = lt v3, v17
+    branch v5, bb2, bb4
+bb4:
+    v19: int = box v9
+    return v19
+bb2:
+    v10: int as i64 = mul v3, v8
+    v11: int as i64 = add v9, v10
+    jump bb3
+bb3:
+    v14: int as i64 = add v3, v13
+    jump bb1(v14, v11)
+}
+
+; first is compiled from the AST: arrays, objects or other unsupported expressions
+
+"#);
+
+        let module = Compiler::with_options(CompilerOptions::new().opt_level(OptLevel::O2)).compile_module("poly.php", SOURCE).unwrap();
+        assert_eq!(module.ir, None);
+    }
+}
@@ -0,0 +1,863 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Passes over the IR and the pass manager that runs them
+//
+// The pipeline depends on the compiler options: types are always inferred;
+// from -O1 on, constants are propagated (folding branches on constants and
+// dropping the blocks they make unreachable), equal pure instructions are
+// merged, loop-invariant ones are hoisted into the loop's preheader and
+// unused ones are removed; with unboxing on, the unboxing pass then picks a
+// representation for every value and inserts the conversions between them,
+// which are merged and hoisted in turn.
+//
+// An instruction only counts as pure when it can neither have side effects
+// nor trap (see `Function::is_pure`), so every pass here may move, merge or
+// drop pure instructions freely.
+
+use super::cfg::Cfg;
+use super::*;
+use crate::compiler::options::{CompilerOptions, OptLevel};
+use crate::compiler::type_inference::{binary_result_type, join, TypeInference};
+use std::collections::HashSet;
+
+/// Rounds of type inference before it gives up and leaves every type dynamic
+const MAX_TYPE_ROUNDS: usize = 64;
+
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    /// Run over one function; whether the function changed
+    fn run(&mut self, function: &mut Function, program: &TypeInference) -> bool;
+}
+
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The pipeline the options ask for
+    pub fn for_options(options: &CompilerOptions) -> Self {
+        let mut manager = PassManager::new();
+        manager.add(InferTypes);
+        if options.level() != OptLevel::O0 {
+            manager.add(ConstantPropagation);
+            manager.add(InferTypes);
+            manager.add(CommonSubexpressionElimination);
+            manager.add(LoopInvariantCodeMotion);
+            manager.add(DeadCodeElimination);
+        }
+        if options.unboxing_enabled() {
+            manager.add(Unboxing);
+            if options.level() != OptLevel::O0 {
+                manager.add(CommonSubexpressionElimination);
+                manager.add(LoopInvariantCodeMotion);
+                manager.add(DeadCodeElimination);
+            }
+        }
+        manager
+    }
+
+    pub fn add(&mut self, pass: impl Pass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    pub fn pass_names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    pub fn run(&mut self, function: &mut Function, program: &TypeInference) {
+        for pass in &mut self.passes {
+            pass.run(function, program);
+        }
+    }
+}
+
+/// Flow-sensitive types: block parameters join the types of their arguments,
+/// instructions follow the same rules as the AST analysis, and calls get the
+/// type their callee (or the specialized copy they go to) returns
+pub struct InferTypes;
+
+impl Pass for InferTypes {
+    fn name(&self) -> &'static str {
+        "infer-types"
+    }
+
+    fn run(&mut self, function: &mut Function, program: &TypeInference) -> bool {
+        let cfg = Cfg::new(function);
+        // None while no value is known to reach it
+        let mut types: HashMap<Value, Option<InferredType>> = HashMap::new();
+        let mut copies: HashMap<Value, Option<Specialization>> = HashMap::new();
+        let mut settled = false;
+        for _ in 0..MAX_TYPE_ROUNDS {
+            let mut changed = false;
+            for &block in cfg.rpo() {
+                let incoming = function.incoming_args(&cfg, block);
+                for (position, &param) in function.block(block).params.iter().enumerate() {
+                    let ty = incoming.iter()
+                        .map(|args| types.get(&args[position]).cloned().flatten())
+                        .fold(None, join);
+                    if types.get(&param) != Some(&ty) {
+                        types.insert(param, ty);
+                        changed = true;
+                    }
+                }
+                for &value in &function.block(block).insts {
+                    let (ty, copy) = infer_inst(function, value, &types, program);
+                    if types.get(&value) != Some(&ty) {
+                        types.insert(value, ty);
+                        changed = true;
+                    }
+                    copies.insert(value, copy);
+                }
+            }
+            if !changed {
+                settled = true;
+                break;
+            }
+        }
+
+        let mut changed = false;
+        for (value, ty) in types {
+            let ty = match ty {
+                Some(ty) if settled => ty,
+                _ => InferredType::Dynamic,
+            };
+            let data = function.value_mut(value);
+            if !matches!(data.def, Def::Inst(Inst::Param(_) | Inst::Const(_))) && data.ty != ty {
+                data.ty = ty;
+                changed = true;
+            }
+        }
+        for (value, copy) in copies {
+            if let Def::Inst(Inst::Call(call)) = &mut function.value_mut(value).def {
+                let copy = copy.filter(|_| settled);
+                if call.copy != copy {
+                    call.copy = copy;
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+}
+
+fn infer_inst(function: &Function, value: Value, types: &HashMap<Value, Option<InferredType>>, program: &TypeInference) -> (Option<InferredType>, Option<Specialization>) {
+    let type_of = |value: &Value| types.get(value).cloned().flatten();
+    match function.inst(value).expect("blocks hold instructions") {
+        Inst::Call(call) => {
+            let Some(arg_types) = call.args.iter().map(type_of).collect::<Option<Vec<_>>>() else { return (None, None) };
+            let copy = copy_for(program, &call.function, &arg_types);
+            let ty = copy.as_ref().map_or_else(|| program.return_type(&call.function), |copy| copy.return_type.clone());
+            (Some(ty), copy)
+        }
+        inst => (inst_type(function, value, inst, &type_of), None),
+    }
+}
+
+fn inst_type(function: &Function, value: Value, inst: &Inst, type_of: &dyn Fn(&Value) -> Option<InferredType>) -> Option<InferredType> {
+    match inst {
+        Inst::Const(constant) => Some(constant.ty()),
+        Inst::Param(_) | Inst::Unbox(_) => Some(function.ty(value).clone()),
+        Inst::Binary(op, _, _) if is_comparison(op) => Some(InferredType::Bool),
+        Inst::Binary(op, left, right) => Some(binary_result_type(&type_of(left)?, op, &type_of(right)?)),
+        Inst::Neg(operand) => Some(match type_of(operand)? {
+            ty @ (InferredType::Int | InferredType::Float) => ty,
+            _ => InferredType::Dynamic,
+        }),
+        Inst::Not(_) | Inst::Truthy(_) => Some(InferredType::Bool),
+        Inst::Cast(CastKind::Int, _) => Some(InferredType::Int),
+        Inst::Cast(CastKind::Float, _) | Inst::IntToFloat(_) => Some(InferredType::Float),
+        Inst::Cast(CastKind::String, _) => Some(InferredType::String),
+        Inst::Box(operand) => type_of(operand),
+        Inst::Builtin(name, _) => Some(builtin_type(name)),
+        Inst::Call(_) | Inst::Echo(_) | Inst::Location(_) => Some(InferredType::Null),
+    }
+}
+
+/// The specialized copy a call with arguments of these types may go to. Its
+/// other parameters were typed from the calls the AST analysis sent there,
+/// so the arguments for them must fit those types.
+fn copy_for(program: &TypeInference, function: &str, arg_types: &[InferredType]) -> Option<Specialization> {
+    let copy = program.specialization_for(function, arg_types)?;
+    let fits = copy.params.iter().zip(arg_types).enumerate().all(|(position, (param, arg))| {
+        let ty = program.parameter_type(&copy.key, position);
+        param.is_numeric() || ty == InferredType::Dynamic || ty == *arg
+    });
+    fits.then_some(copy)
+}
+
+/// Result type of a pure builtin, as the AST analysis has it
+fn builtin_type(name: &str) -> InferredType {
+    match name {
+        "strlen" => InferredType::Int,
+        name if name.starts_with("is_") => InferredType::Bool,
+        _ => InferredType::Dynamic,
+    }
+}
+
+/// Fold instructions on constants, branches on constants and casts of values
+/// that already have the type; then drop what became unreachable
+pub struct ConstantPropagation;
+
+impl Pass for ConstantPropagation {
+    fn name(&self) -> &'static str {
+        "constant-propagation"
+    }
+
+    fn run(&mut self, function: &mut Function, _program: &TypeInference) -> bool {
+        let mut changed = false;
+        // Values whose uses already went to an operand; they stay, unused,
+        // until dead code elimination
+        let mut forwarded = HashSet::new();
+        loop {
+            let cfg = Cfg::new(function);
+            let mut replacements = HashMap::new();
+            for &block in cfg.rpo() {
+                for value in function.block(block).insts.clone() {
+                    let inst = function.inst(value).expect("blocks hold instructions");
+                    if matches!(inst, Inst::Const(_)) || forwarded.contains(&value) {
+                        continue;
+                    }
+                    if let Some(constant) = fold(function, inst) {
+                        let data = function.value_mut(value);
+                        data.ty = constant.ty();
+                        data.def = Def::Inst(Inst::Const(constant));
+                        changed = true;
+                    } else if let Some(same) = identity(function, inst) {
+                        replacements.insert(value, same);
+                    }
+                }
+                let terminator = &mut function.blocks[block.0 as usize].terminator;
+                if let Terminator::Branch(cond, then_edge, else_edge) = terminator {
+                    let taken = match function.values[cond.0 as usize].def {
+                        Def::Inst(Inst::Const(Constant::Bool(true))) => Some(then_edge.clone()),
+                        Def::Inst(Inst::Const(Constant::Bool(false))) => Some(else_edge.clone()),
+                        _ if then_edge == else_edge => Some(then_edge.clone()),
+                        _ => None,
+                    };
+                    if let Some(taken) = taken {
+                        *terminator = Terminator::Jump(taken);
+                        changed = true;
+                    }
+                }
+            }
+            if replacements.is_empty() {
+                break;
+            }
+            function.replace_uses(&replacements);
+            forwarded.extend(replacements.into_keys());
+            changed = true;
+        }
+        changed |= function.remove_unreachable_blocks();
+        changed |= function.remove_trivial_params();
+        changed
+    }
+}
+
+/// The constant an instruction on constants computes, where it is certain
+fn fold(function: &Function, inst: &Inst) -> Option<Constant> {
+    let constant = |value: &Value| function.constant(*value).cloned();
+    match inst {
+        Inst::Truthy(operand) => Some(Constant::Bool(constant(operand)?.truthy())),
+        Inst::Not(operand) => match constant(operand)? {
+            Constant::Bool(b) => Some(Constant::Bool(!b)),
+            _ => None,
+        },
+        Inst::Neg(operand) => match constant(operand)? {
+            Constant::Int(n) => n.checked_neg().map(Constant::Int),
+            Constant::Float(x) => Some(Constant::Float(-x)),
+            _ => None,
+        },
+        Inst::IntToFloat(operand) => match constant(operand)? {
+            Constant::Int(n) => Some(Constant::Float(n as f64)),
+            _ => None,
+        },
+        Inst::Cast(kind, operand) => fold_cast(*kind, constant(operand)?),
+        Inst::Binary(op, left, right) => fold_binary(op, constant(left)?, constant(right)?),
+        Inst::Builtin(name, args) => {
+            let args = args.iter().map(constant).collect::<Option<Vec<_>>>()?;
+            fold_builtin(name, &args)
+        }
+        _ => None,
+    }
+}
+
+/// A cast of a constant, for the casts whose result does not depend on
+/// float formatting or numeric-string parsing
+pub(in crate::compiler) fn fold_cast(kind: CastKind, constant: Constant) -> Option<Constant> {
+    match (kind, constant) {
+        (CastKind::Int, Constant::Int(n)) => Some(Constant::Int(n)),
+        (CastKind::Int, Constant::Bool(b)) => Some(Constant::Int(b as i64)),
+        (CastKind::Int, Constant::Null) => Some(Constant::Int(0)),
+        (CastKind::Int, Constant::Float(x)) if x.is_finite() && x.abs() < 9.0e18 => Some(Constant::Int(x.trunc() as i64)),
+        (CastKind::Float, Constant::Int(n)) => Some(Constant::Float(n as f64)),
+        (CastKind::Float, Constant::Float(x)) => Some(Constant::Float(x)),
+        (CastKind::String, Constant::String(s)) => Some(Constant::String(s)),
+        (CastKind::String, Constant::Int(n)) => Some(Constant::String(n.to_string())),
+        (CastKind::String, Constant::Bool(b)) => Some(Constant::String(if b { "1" } else { "" }.to_string())),
+        (CastKind::String, Constant::Null) => Some(Constant::String(String::new())),
+        _ => None,
+    }
+}
+
+/// A binary operator on constants; None where the result is not certain at
+/// compile time (overflow, division by zero, loose string comparisons)
+pub(in crate::compiler) fn fold_binary(op: &BinaryOp, left: Constant, right: Constant) -> Option<Constant> {
+    use Constant::*;
+    let compare = |ordering: Option<std::cmp::Ordering>| -> Option<Constant> {
+        use std::cmp::Ordering::*;
+        let result = match op {
+            BinaryOp::Equal | BinaryOp::Identical => ordering == Some(Equal),
+            BinaryOp::NotEqual | BinaryOp::NotIdentical => ordering != Some(Equal),
+            BinaryOp::LessThan => ordering == Some(Less),
+            BinaryOp::LessThanOrEqual => matches!(ordering, Some(Less | Equal)),
+            BinaryOp::GreaterThan => ordering == Some(Greater),
+            BinaryOp::GreaterThanOrEqual => matches!(ordering, Some(Greater | Equal)),
+            _ => return None,
+        };
+        Some(Bool(result))
+    };
+    match (left, right) {
+        (Int(a), Int(b)) => match op {
+            BinaryOp::Add => a.checked_add(b).map(Int),
+            BinaryOp::Subtract => a.checked_sub(b).map(Int),
+            BinaryOp::Multiply => a.checked_mul(b).map(Int),
+            // Division always gives a float
+            BinaryOp::Divide if b != 0 => Some(Float(a as f64 / b as f64)),
+            BinaryOp::Modulo => a.checked_rem(b).map(Int),
+            BinaryOp::Concat => Some(String(format!("{}{}", a, b))),
+            op if is_comparison(op) => compare(Some(a.cmp(&b))),
+            _ => None,
+        },
+        (left @ (Int(_) | Float(_)), right @ (Int(_) | Float(_))) => {
+            let identity = matches!(op, BinaryOp::Identical | BinaryOp::NotIdentical);
+            let mixed = matches!((&left, &right), (Int(_), _) | (_, Int(_)));
+            let (a, b) = (as_float(&left), as_float(&right));
+            match op {
+                BinaryOp::Add => Some(Float(a + b)),
+                BinaryOp::Subtract => Some(Float(a - b)),
+                BinaryOp::Multiply => Some(Float(a * b)),
+                BinaryOp::Divide if b != 0.0 => Some(Float(a / b)),
+                _ if identity && mixed => Some(Bool(matches!(op, BinaryOp::NotIdentical))),
+                op if is_comparison(op) => compare(a.partial_cmp(&b)),
+                _ => None,
+            }
+        }
+        (Bool(a), Bool(b)) if matches!(op, BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Identical | BinaryOp::NotIdentical) => {
+            compare(Some(a.cmp(&b)))
+        }
+        (String(a), String(b)) => match op {
+            BinaryOp::Concat => Some(String(a + &b)),
+            BinaryOp::Identical | BinaryOp::NotIdentical => compare(Some(a.cmp(&b))),
+            _ => None,
+        },
+        (String(a), Int(b)) if *op == BinaryOp::Concat => Some(String(format!("{}{}", a, b))),
+        (Int(a), String(b)) if *op == BinaryOp::Concat => Some(String(format!("{}{}", a, b))),
+        (Null, Null) if matches!(op, BinaryOp::Identical | BinaryOp::NotIdentical) => compare(Some(std::cmp::Ordering::Equal)),
+        _ => None,
+    }
+}
+
+/// A pure builtin called with constant arguments
+pub(in crate::compiler) fn fold_builtin(name: &str, args: &[Constant]) -> Option<Constant> {
+    match (name, args) {
+        ("strlen", [Constant::String(s)]) => Some(Constant::Int(s.len() as i64)),
+        // Case conversion is ASCII-only since PHP 8.2
+        ("strtolower", [Constant::String(s)]) => Some(Constant::String(s.to_ascii_lowercase())),
+        ("strtoupper", [Constant::String(s)]) => Some(Constant::String(s.to_ascii_uppercase())),
+        ("abs", [Constant::Int(n)]) => n.checked_abs().map(Constant::Int),
+        ("abs", [Constant::Float(x)]) => Some(Constant::Float(x.abs())),
+        _ => None,
+    }
+}
+
+fn as_float(constant: &Constant) -> f64 {
+    match constant {
+        Constant::Int(n) => *n as f64,
+        Constant::Float(x) => *x,
+        _ => 0.0,
+    }
+}
+
+/// The operand an instruction passes through unchanged
+fn identity(function: &Function, inst: &Inst) -> Option<Value> {
+    match inst {
+        Inst::Truthy(operand) if *function.ty(*operand) == InferredType::Bool => Some(*operand),
+        Inst::Cast(CastKind::Int, operand) if *function.ty(*operand) == InferredType::Int => Some(*operand),
+        Inst::Cast(CastKind::Float, operand) if *function.ty(*operand) == InferredType::Float => Some(*operand),
+        Inst::Cast(CastKind::String, operand) if *function.ty(*operand) == InferredType::String => Some(*operand),
+        _ => None,
+    }
+}
+
+/// Merge a pure instruction into an equal one that dominates it
+pub struct CommonSubexpressionElimination;
+
+impl Pass for CommonSubexpressionElimination {
+    fn name(&self) -> &'static str {
+        "cse"
+    }
+
+    fn run(&mut self, function: &mut Function, _program: &TypeInference) -> bool {
+        let cfg = Cfg::new(function);
+        let mut available: HashMap<String, Vec<(Value, BlockId)>> = HashMap::new();
+        let mut replacements: HashMap<Value, Value> = HashMap::new();
+        for &block in cfg.rpo() {
+            for &value in &function.block(block).insts {
+                if !function.is_pure(value) {
+                    continue;
+                }
+                let mut inst = function.inst(value).expect("blocks hold instructions").clone();
+                for operand in inst_operands_mut(&mut inst) {
+                    *operand = replacements.get(operand).copied().unwrap_or(*operand);
+                }
+                // Equal instructions may still produce different representations
+                let key = format!("{} {}", inst, function.repr(value));
+                let candidates = available.entry(key).or_default();
+                match candidates.iter().find(|(_, defined_in)| cfg.dominates(*defined_in, block)) {
+                    Some(&(existing, _)) => {
+                        replacements.insert(value, existing);
+                    }
+                    None => candidates.push((value, block)),
+                }
+            }
+        }
+        if replacements.is_empty() {
+            return false;
+        }
+        function.replace_uses(&replacements);
+        for block in &mut function.blocks {
+            block.insts.retain(|value| !replacements.contains_key(value));
+        }
+        true
+    }
+}
+
+/// Hoist pure instructions whose operands are all defined outside a loop into
+/// the loop's preheader, innermost loops first
+pub struct LoopInvariantCodeMotion;
+
+impl Pass for LoopInvariantCodeMotion {
+    fn name(&self) -> &'static str {
+        "licm"
+    }
+
+    fn run(&mut self, function: &mut Function, _program: &TypeInference) -> bool {
+        let cfg = Cfg::new(function);
+        let mut defined_in: HashMap<Value, BlockId> = HashMap::new();
+        for &block in cfg.rpo() {
+            let data = function.block(block);
+            for &value in data.params.iter().chain(&data.insts) {
+                defined_in.insert(value, block);
+            }
+        }
+        let mut changed = false;
+        for lp in cfg.loops() {
+            let Some(preheader) = cfg.preheader(function, &lp) else { continue };
+            for &block in cfg.rpo().iter().filter(|block| lp.blocks.contains(block)) {
+                let mut kept = Vec::new();
+                for value in function.block(block).insts.clone() {
+                    let invariant = function.is_pure(value)
+                        && function.operands(value).iter().all(|operand| !lp.blocks.contains(&defined_in[operand]));
+                    if invariant {
+                        function.block_mut(preheader).insts.push(value);
+                        defined_in.insert(value, preheader);
+                        changed = true;
+                    } else {
+                        kept.push(value);
+                    }
+                }
+                function.block_mut(block).insts = kept;
+            }
+        }
+        changed
+    }
+}
+
+/// Remove pure instructions nothing uses, and block parameters nothing uses -
+/// the stores into variables that are never read again
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination {
+    fn name(&self) -> &'static str {
+        "dce"
+    }
+
+    fn run(&mut self, function: &mut Function, _program: &TypeInference) -> bool {
+        let cfg = Cfg::new(function);
+        let mut live: HashSet<Value> = HashSet::new();
+        let mut work: Vec<Value> = Vec::new();
+        for &block in cfg.rpo() {
+            let data = function.block(block);
+            work.extend(data.insts.iter().filter(|&&value| !function.is_pure(value)));
+            match &data.terminator {
+                Terminator::Branch(cond, ..) | Terminator::Return(cond) => work.push(*cond),
+                _ => {}
+            }
+        }
+        while let Some(value) = work.pop() {
+            if !live.insert(value) {
+                continue;
+            }
+            match function.value(value).def {
+                Def::Inst(_) => work.extend(function.operands(value)),
+                // A live parameter needs what every edge passes to it
+                Def::BlockParam(block) => {
+                    let position = function.block(block).params.iter().position(|&param| param == value).expect("a parameter of its block");
+                    work.extend(function.incoming_args(&cfg, block).iter().map(|args| args[position]));
+                }
+            }
+        }
+
+        let mut changed = false;
+        for &block in cfg.rpo() {
+            let data = function.block_mut(block);
+            let before = data.insts.len() + data.params.len();
+            data.insts.retain(|value| live.contains(value));
+            if data.insts.len() + data.params.iter().filter(|param| live.contains(param)).count() != before {
+                changed = true;
+            }
+        }
+        function.remove_params(|param| !live.contains(&param));
+        changed
+    }
+}
+
+/// Give every value a representation - ints, floats and bools raw wherever an
+/// instruction can produce them raw - and convert between representations
+/// where a use needs another one
+pub struct Unboxing;
+
+impl Pass for Unboxing {
+    fn name(&self) -> &'static str {
+        "unboxing"
+    }
+
+    fn run(&mut self, function: &mut Function, _program: &TypeInference) -> bool {
+        let cfg = Cfg::new(function);
+        for &block in cfg.rpo() {
+            for param in function.block(block).params.clone() {
+                function.value_mut(param).repr = Repr::unboxed(function.ty(param)).unwrap_or(Repr::Boxed);
+            }
+            for value in function.block(block).insts.clone() {
+                function.value_mut(value).repr = chosen_repr(function, value);
+            }
+        }
+
+        for &block in cfg.rpo() {
+            let mut insts = Vec::new();
+            for value in function.block(block).insts.clone() {
+                let wanted = operand_reprs(function, value);
+                for (position, repr) in wanted.into_iter().enumerate() {
+                    let operand = function.operands(value)[position];
+                    if function.repr(operand) != repr {
+                        let converted = convert(function, operand, repr, &mut insts);
+                        if let Def::Inst(inst) = &mut function.value_mut(value).def {
+                            *inst_operands_mut(inst)[position] = converted;
+                        }
+                    }
+                }
+                insts.push(value);
+            }
+
+            let mut terminator = function.block(block).terminator.clone();
+            match &mut terminator {
+                Terminator::Branch(cond, ..) => *cond = convert(function, *cond, Repr::I32, &mut insts),
+                Terminator::Return(value) => *value = convert(function, *value, function.return_repr, &mut insts),
+                _ => {}
+            }
+            for edge in terminator.edges_mut() {
+                let params = function.block(edge.block).params.clone();
+                for (arg, param) in edge.args.iter_mut().zip(params) {
+                    *arg = convert(function, *arg, function.repr(param), &mut insts);
+                }
+            }
+            let data = function.block_mut(block);
+            data.insts = insts;
+            data.terminator = terminator;
+        }
+        true
+    }
+}
+
+/// The representation an instruction produces its result in
+fn chosen_repr(function: &Function, value: Value) -> Repr {
+    let ty = function.ty(value);
+    let numeric = |operand: &Value| function.ty(*operand).is_numeric();
+    match function.inst(value).expect("blocks hold instructions") {
+        Inst::Param(_) => function.repr(value),
+        Inst::Const(_) => Repr::unboxed(ty).unwrap_or(Repr::Boxed),
+        Inst::Call(call) => call_result_repr(call),
+        Inst::Binary(op, ..) if is_comparison(op) => Repr::I32,
+        Inst::Binary(BinaryOp::Concat, ..) => Repr::Boxed,
+        Inst::Binary(_, left, right) if numeric(left) && numeric(right) => match ty {
+            InferredType::Int => Repr::I64,
+            InferredType::Float => Repr::F64,
+            _ => Repr::Boxed,
+        },
+        Inst::Neg(_) | Inst::Cast(CastKind::Int | CastKind::Float, _) | Inst::Unbox(_) | Inst::IntToFloat(_) => {
+            Repr::unboxed(ty).unwrap_or(Repr::Boxed)
+        }
+        Inst::Not(_) | Inst::Truthy(_) => Repr::I32,
+        _ => Repr::Boxed,
+    }
+}
+
+/// `value` in representation `to`, converting it with instructions appended to `insts`
+fn convert(function: &mut Function, value: Value, to: Repr, insts: &mut Vec<Value>) -> Value {
+    let from = function.repr(value);
+    let (inst, ty) = match (from, to) {
+        _ if from == to => return value,
+        (_, Repr::Boxed) => (Inst::Box(value), function.ty(value).clone()),
+        (Repr::Boxed, Repr::I64) => (Inst::Unbox(value), InferredType::Int),
+        (Repr::Boxed, Repr::F64) => (Inst::Unbox(value), InferredType::Float),
+        (Repr::Boxed, _) => (Inst::Unbox(value), InferredType::Bool),
+        (Repr::I64, Repr::F64) => (Inst::IntToFloat(value), InferredType::Float),
+        _ => {
+            let boxed = convert(function, value, Repr::Boxed, insts);
+            return convert(function, boxed, to, insts);
+        }
+    };
+    let converted = function.new_value(inst, ty);
+    function.value_mut(converted).repr = to;
+    insts.push(converted);
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(function: &mut Function, passes: &mut [&mut dyn Pass]) -> String {
+        let program = TypeInference::new();
+        for pass in passes {
+            pass.run(function, &program);
+        }
+        function.to_string()
+    }
+
+    fn int(function: &mut Function, block: BlockId, n: i64) -> Value {
+        function.append(block, Inst::Const(Constant::Int(n)), InferredType::Int)
+    }
+
+    fn binary(function: &mut Function, block: BlockId, op: BinaryOp, left: Value, right: Value) -> Value {
+        function.append(block, Inst::Binary(op, left, right), InferredType::Dynamic)
+    }
+
+    /// `for ($i = 0; $i < 10; $i++) { echo $x * (2 + 3); } return $i;`
+    fn counting_loop() -> Function {
+        let mut function = Function::new("f", vec!["x".to_string()], Repr::Boxed);
+        let (header, body, exit) = (function.add_block(), function.add_block(), function.add_block());
+        let x = function.append(ENTRY, Inst::Param(0), InferredType::Dynamic);
+        let zero = int(&mut function, ENTRY, 0);
+        function.block_mut(ENTRY).terminator = Terminator::Jump(Edge { block: header, args: vec![zero] });
+
+        let i = function.add_block_param(header, InferredType::Dynamic);
+        let ten = int(&mut function, header, 10);
+        let less = binary(&mut function, header, BinaryOp::LessThan, i, ten);
+        let cond = function.append(header, Inst::Truthy(less), InferredType::Bool);
+        function.block_mut(header).terminator = Terminator::Branch(
+            cond,
+            Edge { block: body, args: vec![] },
+            Edge { block: exit, args: vec![] },
+        );
+
+        let two = int(&mut function, body, 2);
+        let three = int(&mut function, body, 3);
+        let five = binary(&mut function, body, BinaryOp::Add, two, three);
+        let product = binary(&mut function, body, BinaryOp::Multiply, x, five);
+        function.append(body, Inst::Echo(product), InferredType::Null);
+        let one = int(&mut function, body, 1);
+        let next = binary(&mut function, body, BinaryOp::Add, i, one);
+        function.block_mut(body).terminator = Terminator::Jump(Edge { block: header, args: vec![next] });
+
+        function.block_mut(exit).terminator = Terminator::Return(i);
+        function
+    }
+
+    #[test]
+    fn test_infer_types() {
+        let mut function = counting_loop();
+        // The counter joins two ints; arithmetic on the dynamic parameter stays dynamic
+        assert_eq!(run(&mut function, &mut [&mut InferTypes]), r#"function f($x) -> boxed {
+bb0:
+    v0: mixed = param 0
+    v1: int = const 0
+    jump bb1(v1)
+bb1(v2: int):
+    v3: int = const 10
+    v4: bool = lt v2, v3
+    v5: bool = truthy v4
+    branch v5, bb2, bb3
+bb3:
+    return v2
+bb2:
+    v6: int = const 2
+    v7: int = const 3
+    v8: int = add v6, v7
+    v9: mixed = mul v0, v8
+    echo v9
+    v11: int = const 1
+    v12: int = add v2, v11
+    jump bb1(v12)
+}
+"#);
+    }
+
+    #[test]
+    fn test_constant_propagation() {
+        let mut function = Function::new("f", vec![], Repr::Boxed);
+        let (then_block, else_block) = (function.add_block(), function.add_block());
+        let two = int(&mut function, ENTRY, 2);
+        let three = int(&mut function, ENTRY, 3);
+        let sum = binary(&mut function, ENTRY, BinaryOp::Add, two, three);
+        let greater = binary(&mut function, ENTRY, BinaryOp::GreaterThan, sum, two);
+        let cond = function.append(ENTRY, Inst::Truthy(greater), InferredType::Bool);
+        function.block_mut(ENTRY).terminator = Terminator::Branch(
+            cond,
+            Edge { block: then_block, args: vec![] },
+            Edge { block: else_block, args: vec![] },
+        );
+        function.block_mut(then_block).terminator = Terminator::Return(sum);
+        function.block_mut(else_block).terminator = Terminator::Return(two);
+        // The branch on a constant becomes a jump and the other arm is gone
+        assert_eq!(run(&mut function, &mut [&mut InferTypes, &mut ConstantPropagation]), r#"function f() -> boxed {
+bb0:
+    v0: int = const 2
+    v1: int = const 3
+    v2: int = const 5
+    v3: bool = const true
+    v4: bool = const true
+    jump bb1
+bb1:
+    return v2
+}
+"#);
+    }
+
+    #[test]
+    fn test_common_subexpression_elimination() {
+        let mut function = Function::new("f", vec!["x".to_string()], Repr::Boxed);
+        let x = function.append(ENTRY, Inst::Param(0), InferredType::Int);
+        let two = int(&mut function, ENTRY, 2);
+        let first = binary(&mut function, ENTRY, BinaryOp::Multiply, x, two);
+        let second = binary(&mut function, ENTRY, BinaryOp::Multiply, x, two);
+        function.append(ENTRY, Inst::Echo(first), InferredType::Null);
+        function.append(ENTRY, Inst::Echo(first), InferredType::Null);
+        let sum = binary(&mut function, ENTRY, BinaryOp::Add, first, second);
+        function.block_mut(ENTRY).terminator = Terminator::Return(sum);
+        // The second multiplication is the first one
+        assert_eq!(run(&mut function, &mut [&mut InferTypes, &mut CommonSubexpressionElimination]), r#"function f($x) -> boxed {
+bb0:
+    v0: int = param 0
+    v1: int = const 2
+    v2: int = mul v0, v1
+    echo v2
+    echo v2
+    v6: int = add v2, v2
+    return v6
+}
+"#);
+    }
+
+    #[test]
+    fn test_loop_invariant_code_motion() {
+        let mut function = counting_loop();
+        // Constants and their sum move to the preheader; the product with the
+        // parameter may throw, so it stays in the loop
+        assert_eq!(run(&mut function, &mut [&mut InferTypes, &mut LoopInvariantCodeMotion]), r#"function f($x) -> boxed {
+bb0:
+    v0: mixed = param 0
+    v1: int = const 0
+    v3: int = const 10
+    v6: int = const 2
+    v7: int = const 3
+    v8: int = add v6, v7
+    v11: int = const 1
+    jump bb1(v1)
+bb1(v2: int):
+    v4: bool = lt v2, v3
+    v5: bool = truthy v4
+    branch v5, bb2, bb3
+bb3:
+    return v2
+bb2:
+    v9: mixed = mul v0, v8
+    echo v9
+    v12: int = add v2, v11
+    jump bb1(v12)
+}
+"#);
+    }
+
+    #[test]
+    fn test_dead_code_elimination() {
+        let mut function = Function::new("f", vec!["x".to_string()], Repr::Boxed);
+        let x = function.append(ENTRY, Inst::Param(0), InferredType::Int);
+        let one = int(&mut function, ENTRY, 1);
+        binary(&mut function, ENTRY, BinaryOp::Add, x, one);
+        let zero = int(&mut function, ENTRY, 0);
+        binary(&mut function, ENTRY, BinaryOp::Divide, x, zero);
+        function.append(ENTRY, Inst::Echo(x), InferredType::Null);
+        function.block_mut(ENTRY).terminator = Terminator::Return(x);
+        // The unused addition goes; the division may throw, so it stays
+        assert_eq!(run(&mut function, &mut [&mut InferTypes, &mut DeadCodeElimination]), r#"function f($x) -> boxed {
+bb0:
+    v0: int = param 0
+    v3: int = const 0
+    v4: mixed = div v0, v3
+    echo v0
+    return v0
+}
+"#);
+    }
+
+    #[test]
+    fn test_unboxing() {
+        let mut function = counting_loop();
+        // The counter is an i64 throughout and is boxed where it leaves the function
+        assert_eq!(run(&mut function, &mut [&mut InferTypes, &mut Unboxing]), r#"function f($x) -> boxed {
+bb0:
+    v0: mixed = param 0
+    v1: int as i64 = const 0
+    jump bb1(v1)
+bb1(v2: int as i64):
+    v3: int as i64 = const 10
+    v4: bool as i32 = lt v2, v3
+    v5: bool as i32 = truthy v4
+    branch v5, bb2, bb3
+bb3:
+    v13: int = box v2
+    return v13
+bb2:
+    v6: int as i64 = const 2
+    v7: int as i64 = const 3
+    v8: int as i64 = add v6, v7
+    v14: int = box v8
+    v9: mixed = mul v0, v14
+    echo v9
+    v11: int as i64 = const 1
+    v12: int as i64 = add v2, v11
+    jump bb1(v12)
+}
+"#);
+    }
+
+    #[test]
+    fn test_pipeline_for_options() {
+        let pipeline = |options: CompilerOptions| PassManager::for_options(&options).pass_names();
+        assert_eq!(pipeline(CompilerOptions::new().opt_level(OptLevel::O0)), ["infer-types"]);
+        assert_eq!(pipeline(CompilerOptions::new().opt_level(OptLevel::O2).unboxing(false)), [
+            "infer-types", "constant-propagation", "infer-types", "cse", "licm", "dce",
+        ]);
+        assert_eq!(pipeline(CompilerOptions::new().opt_level(OptLevel::O2)), [
+            "infer-types", "constant-propagation", "infer-types", "cse", "licm", "dce", "unboxing", "cse", "licm", "dce",
+        ]);
+    }
+}
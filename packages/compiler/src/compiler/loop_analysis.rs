@@ -1,12 +1,26 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-/// Loop unrolling analysis and optimization
-///
-/// This module analyzes loops to determine if they can be safely unrolled for performance.
-/// Only simple, counted loops with predictable bounds are unrolled.
+//! Loop unrolling analysis and optimization
+//!
+//! This module analyzes loops to determine if they can be safely unrolled for performance.
+//! Only counted loops with predictable bounds are unrolled: the counter starts at an int,
+//! grows by a constant and is compared against a bound the body never changes.
 
+use super::ir::lower::PURE_BUILTINS;
 use edge_php_parser::ast::*;
+use edge_php_parser::visit::{self, Visitor};
+use edge_php_parser::visit_mut::{self, VisitorMut};
+use std::collections::{HashMap, HashSet};
+
+/// Statements an unrolled body may have, nested ones included; each copy adds them again
+const MAX_UNROLL_STATEMENTS: usize = 16;
+
+/// Builtins that read the arrays passed to them without changing them
+const ARRAY_READERS: [&str; 14] = [
+    "count", "sizeof", "isset", "empty", "in_array", "array_key_exists", "array_sum", "array_product",
+    "array_keys", "array_values", "implode", "join", "array_search", "is_array",
+];
 
 #[derive(Debug, Clone)]
 pub struct LoopUnrollInfo {
@@ -48,7 +62,7 @@ impl LoopUnrollInfo {
             return None;
         }
 
-        // Extract update: $i = $i + 1
+        // Extract update: $i = $i + 1, $i += 1, $i++
         let (update_var, increment) = Self::analyze_update(update)?;
 
         // Verify counter variable matches
@@ -56,10 +70,11 @@ impl LoopUnrollInfo {
             return None;
         }
 
-        // Check if loop body is safe to unroll (no break/continue/return)
-        let safe_body = Self::is_body_safe(body);
-
-        let can_unroll = safe_body && increment > 0;
+        // The copies only check the bound once per round, so the body must leave the
+        // counter and the bound alone
+        let writes = LoopWrites::of(body);
+        let bound_written = matches!(&end_bound, LoopBound::Variable(bound) if writes.may_write(bound));
+        let can_unroll = Self::is_body_safe(body) && increment > 0 && !writes.may_write(&counter_var) && !bound_written;
 
         Some(LoopUnrollInfo {
             counter_var,
@@ -76,17 +91,14 @@ impl LoopUnrollInfo {
     fn analyze_init(init: &Option<Box<Statement>>) -> Option<(String, i64)> {
         let init = init.as_ref()?;
 
-        match &**init {
-            Statement::Expression(Expression::Assignment { left, right }) => {
-                // Left side must be simple variable
-                if let Expression::Variable(var_name) = &**left {
-                    // Right side must be integer literal
-                    if let Expression::Literal(Literal::Integer(value)) = &**right {
-                        return Some((var_name.clone(), *value));
-                    }
+        if let Statement::Expression(Expression::Assignment { left, right }) = &**init {
+            // Left side must be simple variable
+            if let Expression::Variable(var_name) = &**left {
+                // Right side must be integer literal
+                if let Expression::Literal(Literal::Integer(value)) = &**right {
+                    return Some((var_name.clone(), *value));
                 }
             }
-            _ => {}
         }
 
         None
@@ -119,102 +131,339 @@ impl LoopUnrollInfo {
         None
     }
 
-    /// Analyze loop update: $i = $i + 1
+    /// Analyze loop update: $i = $i + 1 (and so $i += 1), $i = 1 + $i, $i++ or ++$i
     fn analyze_update(update: &Option<Expression>) -> Option<(String, i64)> {
-        let update = update.as_ref()?;
-
-        match update {
+        match update.as_ref()? {
+            Expression::Unary { op: UnaryOp::PostIncrement | UnaryOp::PreIncrement, expr } => match &**expr {
+                Expression::Variable(var_name) => Some((var_name.clone(), 1)),
+                _ => None,
+            },
             Expression::Assignment { left, right } => {
-                // Left side must be variable
-                if let Expression::Variable(var_name) = &**left {
-                    // Right side must be $i + constant
-                    if let Expression::Binary { left: add_left, op: BinaryOp::Add, right: add_right } = &**right {
-                        // Check if left operand is same variable
-                        if let Expression::Variable(add_var) = &**add_left {
-                            if add_var == var_name {
-                                // Right operand must be constant
-                                if let Expression::Literal(Literal::Integer(inc)) = &**add_right {
-                                    return Some((var_name.clone(), *inc));
-                                }
-                            }
-                        }
+                // Left side must be variable, right side the same variable plus a constant
+                let Expression::Variable(var_name) = &**left else { return None };
+                let Expression::Binary { left: add_left, op: BinaryOp::Add, right: add_right } = &**right else { return None };
+                match (&**add_left, &**add_right) {
+                    (Expression::Variable(add_var), Expression::Literal(Literal::Integer(inc)))
+                    | (Expression::Literal(Literal::Integer(inc)), Expression::Variable(add_var)) if add_var == var_name => {
+                        Some((var_name.clone(), *inc))
                     }
+                    _ => None,
                 }
             }
-            _ => {}
+            _ => None,
         }
-
-        None
     }
 
-    /// Check if loop body is safe to unroll (no control flow that breaks iteration)
+    /// Check if loop body is small enough to copy and declares nothing (a declaration
+    /// compiled once per copy would be declared twice). `break`, `continue` and
+    /// `return` are fine: every copy gets its own continue target.
     fn is_body_safe(body: &Block) -> bool {
-        for stmt in &body.statements {
-            if Self::has_control_flow(stmt) {
-                return false;
+        let mut size = BodySize::default();
+        visit::walk_block(&mut size, body);
+        !size.declares && size.statements <= MAX_UNROLL_STATEMENTS
+    }
+
+    /// Calculate total iterations if possible
+    pub fn calculate_iterations(&self) -> Option<usize> {
+        match (&self.start_value, &self.end_bound) {
+            (Some(start), LoopBound::Constant(end)) => {
+                let iterations = match self.comparison {
+                    BinaryOp::LessThan => (end - start + self.increment - 1) / self.increment,
+                    BinaryOp::LessThanOrEqual => (end - start) / self.increment + 1,
+                    _ => return None,
+                };
+
+                if iterations > 0 && iterations < 10000 {
+                    Some(iterations as usize)
+                } else {
+                    None
+                }
             }
+            _ => None,
         }
-        true
     }
+}
+
+/// Counts the statements of a loop body and notices declarations in it
+#[derive(Default)]
+struct BodySize {
+    statements: usize,
+    declares: bool,
+}
 
-    /// Check if statement contains break/continue/return
-    fn has_control_flow(stmt: &Statement) -> bool {
+impl Visitor for BodySize {
+    fn visit_statement(&mut self, stmt: &Statement) {
         match stmt {
-            Statement::Break | Statement::Continue => true,
-            Statement::Return(_) => true,
-            Statement::If { then_block, elseif_blocks, else_block, .. } => {
-                // Check then block
-                for s in &then_block.statements {
-                    if Self::has_control_flow(s) {
-                        return true;
-                    }
-                }
+            Statement::Location(_) => return,
+            Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. } => {
+                self.declares = true;
+                return;
+            }
+            _ => self.statements += 1,
+        }
+        visit::walk_statement(self, stmt);
+    }
+}
 
-                // Check elseif blocks
-                for elseif in elseif_blocks {
-                    for s in &elseif.then_block.statements {
-                        if Self::has_control_flow(s) {
-                            return true;
-                        }
-                    }
-                }
+/// Variables a loop may change while it runs. Arrays and variables passed to
+/// calls count as changed, except for builtins that only read them. What the
+/// functions it calls change is not known here: see `LoopWrites::runs_code`.
+#[derive(Debug, Default)]
+pub struct LoopWrites {
+    variables: HashSet<String>,
+    /// The loop can change variables it does not name (include, extract, ...)
+    opaque: bool,
+    /// Functions the loop calls, or names as a callback
+    calls: HashSet<String>,
+    /// The loop calls methods or closures, which may run any code
+    dynamic_calls: bool,
+}
+
+impl LoopWrites {
+    pub fn of(body: &Block) -> Self {
+        let mut writes = LoopWrites::default();
+        visit::walk_block(&mut writes, body);
+        writes
+    }
+
+    /// Add what an expression run with the body writes: a `for` update or condition
+    pub fn add_expression(&mut self, expr: &Expression) {
+        self.visit_expression(expr);
+    }
+
+    pub fn may_write(&self, name: &str) -> bool {
+        self.opaque || self.variables.contains(name)
+    }
 
-                // Check else block
-                if let Some(else_b) = else_block {
-                    for s in &else_b.statements {
-                        if Self::has_control_flow(s) {
-                            return true;
+    /// Whether the loop may run code besides its own and the functions
+    /// `is_pure` accepts, which can change what the loop does not name (its
+    /// globals, say)
+    pub fn runs_code(&self, is_pure: impl Fn(&str) -> bool) -> bool {
+        self.dynamic_calls || self.calls.iter().any(|name| !is_pure(name))
+    }
+
+    /// The variable holding an assigned array element, property or variable
+    fn write(&mut self, target: &Expression) {
+        match target {
+            Expression::Variable(name) => {
+                self.variables.insert(name.clone());
+            }
+            Expression::ArrayAccess { array: inner, .. }
+            | Expression::PropertyAccess { object: inner, .. }
+            | Expression::NullsafePropertyAccess { object: inner, .. } => self.write(inner),
+            Expression::List(elements) => elements.iter().flatten().for_each(|element| self.write(&element.value)),
+            _ => {}
+        }
+    }
+}
+
+impl Visitor for LoopWrites {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            // Nested declarations have scopes of their own
+            Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. } => return,
+            Statement::Global(names) => self.variables.extend(names.iter().cloned()),
+            Statement::Static(variables) => self.variables.extend(variables.iter().map(|variable| variable.name.clone())),
+            Statement::Foreach { key, value, .. } => {
+                self.variables.extend(key.iter().cloned());
+                self.write(value);
+            }
+            _ => {}
+        }
+        visit::walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Assignment { left, .. } => self.write(left),
+            Expression::Unary { op: UnaryOp::PreIncrement | UnaryOp::PostIncrement | UnaryOp::PreDecrement | UnaryOp::PostDecrement, expr } => {
+                self.write(expr);
+            }
+            Expression::FunctionCall { name, args } => {
+                if matches!(name.as_str(), "compact" | "extract" | "get_defined_vars") {
+                    self.opaque = true;
+                }
+                if !PURE_BUILTINS.contains(&name.as_str()) && !ARRAY_READERS.contains(&name.as_str()) {
+                    args.iter().for_each(|arg| self.write(arg));
+                    self.calls.insert(name.clone());
+                    // Callbacks named by a string (usort, array_map, ...)
+                    for arg in args {
+                        if let Expression::Literal(Literal::String(callback)) = arg {
+                            self.calls.insert(callback.clone());
                         }
                     }
                 }
-
-                false
             }
-            Statement::While { body, .. } | Statement::For { body, .. } => {
-                // Nested loops - conservative: don't unroll outer loop
-                true
+            Expression::MethodCall { args, .. }
+            | Expression::NullsafeMethodCall { args, .. }
+            | Expression::StaticMethodCall { args, .. }
+            | Expression::New { args, .. }
+            | Expression::Invoke { args, .. } => {
+                args.iter().for_each(|arg| self.write(arg));
+                self.dynamic_calls = true;
             }
-            _ => false,
+            Expression::Include { .. } => self.opaque = true,
+            _ => {}
         }
+        visit::walk_expression(self, expr);
     }
+}
 
-    /// Calculate total iterations if possible
-    pub fn calculate_iterations(&self) -> Option<usize> {
-        match (&self.start_value, &self.end_bound) {
-            (Some(start), LoopBound::Constant(end)) => {
-                let iterations = match self.comparison {
-                    BinaryOp::LessThan => (end - start) / self.increment,
-                    BinaryOp::LessThanOrEqual => (end - start + 1) / self.increment,
-                    _ => return None,
-                };
+/// The arrays a loop condition counts with `count($array)` or `sizeof($array)`
+pub fn counted_arrays(condition: &Expression) -> Vec<String> {
+    struct Counts(Vec<String>);
 
-                if iterations > 0 && iterations < 10000 {
-                    Some(iterations as usize)
-                } else {
-                    None
+    impl Visitor for Counts {
+        fn visit_expression(&mut self, expr: &Expression) {
+            if let Some(array) = counted_array(expr) {
+                if !self.0.contains(array) {
+                    self.0.push(array.clone());
                 }
             }
+            visit::walk_expression(self, expr);
+        }
+    }
+
+    let mut counts = Counts(Vec::new());
+    counts.visit_expression(condition);
+    counts.0
+}
+
+fn counted_array(expr: &Expression) -> Option<&String> {
+    match expr {
+        Expression::FunctionCall { name, args } if matches!(name.as_str(), "count" | "sizeof") => match args.as_slice() {
+            [Expression::Variable(array)] => Some(array),
             _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Replace the counts of arrays in `counts` with the variables holding them
+pub fn replace_counts(condition: &mut Expression, counts: &HashMap<String, String>) {
+    struct Replace<'a>(&'a HashMap<String, String>);
+
+    impl VisitorMut for Replace<'_> {
+        fn visit_expression_mut(&mut self, expr: &mut Expression) {
+            if let Some(count) = counted_array(expr).and_then(|array| self.0.get(array)) {
+                *expr = Expression::Variable(count.clone());
+                return;
+            }
+            visit_mut::walk_expression_mut(self, expr);
+        }
+    }
+
+    Replace(counts).visit_expression_mut(condition);
+}
+
+/// The factor of a product of the counter and an int constant: `$i * 4` or `4 * $i`
+fn scale_of(expr: &Expression, counter: &str) -> Option<i64> {
+    let Expression::Binary { left, op: BinaryOp::Multiply, right } = expr else { return None };
+    match (&**left, &**right) {
+        (Expression::Variable(var), Expression::Literal(Literal::Integer(scale)))
+        | (Expression::Literal(Literal::Integer(scale)), Expression::Variable(var)) if var == counter && *scale != 1 => Some(*scale),
+        _ => None,
+    }
+}
+
+/// The factors the body multiplies the counter by; each can become an
+/// induction variable of its own that grows by `factor * increment`
+pub fn counter_scales(body: &Block, counter: &str) -> Vec<i64> {
+    struct Scales<'a> {
+        counter: &'a str,
+        scales: Vec<i64>,
+    }
+
+    impl Visitor for Scales<'_> {
+        fn visit_statement(&mut self, stmt: &Statement) {
+            if !matches!(stmt, Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. }) {
+                visit::walk_statement(self, stmt);
+            }
+        }
+
+        fn visit_expression(&mut self, expr: &Expression) {
+            if let Some(scale) = scale_of(expr, self.counter) {
+                if !self.scales.contains(&scale) {
+                    self.scales.push(scale);
+                }
+                return;
+            }
+            visit::walk_expression(self, expr);
         }
     }
+
+    let mut scales = Scales { counter, scales: Vec::new() };
+    visit::walk_block(&mut scales, body);
+    scales.scales
+}
+
+/// Replace the products of the counter with the induction variables holding them
+pub fn replace_scaled_counters(body: &mut Block, counter: &str, variables: &HashMap<i64, String>) {
+    struct Replace<'a> {
+        counter: &'a str,
+        variables: &'a HashMap<i64, String>,
+    }
+
+    impl VisitorMut for Replace<'_> {
+        fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+            if !matches!(stmt, Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. }) {
+                visit_mut::walk_statement_mut(self, stmt);
+            }
+        }
+
+        fn visit_expression_mut(&mut self, expr: &mut Expression) {
+            if let Some(variable) = scale_of(expr, self.counter).and_then(|scale| self.variables.get(&scale)) {
+                *expr = Expression::Variable(variable.clone());
+                return;
+            }
+            visit_mut::walk_expression_mut(self, expr);
+        }
+    }
+
+    visit_mut::walk_block_mut(&mut Replace { counter, variables }, body);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the body of the first loop in `source` writes
+    fn loop_writes(source: &str) -> LoopWrites {
+        let program = edge_php_parser::parse(source).unwrap();
+        let statements = program.items.iter()
+            .find_map(|item| match item {
+                ProgramItem::PhpBlock { statements } => Some(statements),
+                ProgramItem::InlineContent(_) => None,
+            })
+            .unwrap();
+        statements.iter()
+            .find_map(|stmt| match stmt {
+                Statement::For { body, .. } | Statement::While { body, .. } => Some(LoopWrites::of(body)),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_writes() {
+        let writes = loop_writes("<?php while ($i < count($a)) { $b[0] = 1; $i++; sort($c); echo strlen($d), count($e); }");
+        for name in ["b", "i", "c"] {
+            assert!(writes.may_write(name), "{}", name);
+        }
+        for name in ["a", "d", "e"] {
+            assert!(!writes.may_write(name), "{}", name);
+        }
+        assert!(loop_writes("<?php while ($i < 3) { extract($x); }").may_write("a"));
+        assert!(loop_writes("<?php while ($i < 3) { include 'a.php'; }").may_write("a"));
+    }
+
+    #[test]
+    fn test_runs_code() {
+        let is_pure = |name: &str| name != "grow" && name != "cmp";
+        assert!(!loop_writes("<?php while ($i < 3) { echo strlen($s), square($i); }").runs_code(is_pure));
+        assert!(loop_writes("<?php while ($i < 3) { grow(); }").runs_code(is_pure));
+        assert!(loop_writes("<?php while ($i < 3) { usort($a, 'cmp'); }").runs_code(is_pure));
+        assert!(loop_writes("<?php while ($i < 3) { $o->grow(); }").runs_code(is_pure));
+        assert!(loop_writes("<?php while ($i < 3) { $f(); }").runs_code(is_pure));
+    }
 }
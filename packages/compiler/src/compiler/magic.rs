@@ -0,0 +1,415 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Magic methods: __toString, __get/__set/__isset/__unset, __call/__callStatic,
+// __invoke, __clone and __destruct
+
+use super::core::*;
+use super::classes::MethodInfo;
+use edge_php_parser::ast::*;
+use wasm_encoder::*;
+
+impl Compiler {
+    /// The magic method a class defines, if any
+    pub(super) fn magic_method(&self, class_name: &str, method: &str) -> Option<MethodInfo> {
+        self.classes.get(class_name)?.methods.get(method).cloned()
+    }
+
+    /// (class_id, method) for every class defining the magic method, by class_id
+    pub(super) fn magic_candidates(&self, method: &str) -> Vec<(u32, MethodInfo)> {
+        let mut candidates: Vec<(u32, MethodInfo)> = self.classes.values()
+            .filter_map(|class| class.methods.get(method).map(|m| (class.class_id, m.clone())))
+            .collect();
+        candidates.sort_by_key(|(class_id, _)| *class_id);
+        candidates
+    }
+
+    /// Magic methods must be declared with PHP's fixed signatures
+    pub(super) fn check_magic_method_signature(class_name: &str, method: &str, is_static: bool, params: &[Parameter]) -> Result<(), String> {
+        let arity = match method {
+            "__toString" | "__clone" | "__destruct" => Some(0),
+            "__get" | "__isset" | "__unset" => Some(1),
+            "__set" | "__call" | "__callStatic" => Some(2),
+            "__invoke" => None,
+            _ => return Ok(()),
+        };
+
+        match (method == "__callStatic", is_static) {
+            (true, false) => return Err(format!("Method {}::{}() must be static", class_name, method)),
+            (false, true) => return Err(format!("Method {}::{}() cannot be static", class_name, method)),
+            _ => {}
+        }
+
+        match arity {
+            Some(0) if !params.is_empty() => {
+                Err(format!("Method {}::{}() cannot take arguments", class_name, method))
+            }
+            Some(n) if n > 0 && params.len() != n => Err(format!(
+                "Method {}::{}() must take exactly {} argument{}",
+                class_name, method, n, if n == 1 { "" } else { "s" }
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // ===== RUNTIME HELPERS =====
+    // These bodies dispatch on class_id, so they are generated once every class
+    // has been registered (their indices are reserved up front for to_string).
+
+    pub(super) fn add_magic_method_functions(&mut self) -> Result<(), String> {
+        self.add_object_to_string_function()?;
+        self.add_clone_object_function()?;
+        Ok(())
+    }
+
+    /// Compile a helper function body into a reserved index (params are PhpValues)
+    pub(super) fn compile_helper_function(
+        &mut self,
+        func_idx: u32,
+        param_count: u32,
+        compile_body: impl FnOnce(&mut Self) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let php_value_type = self.get_php_value_type();
+        let params = vec![php_value_type; param_count as usize];
+        self.compile_typed_helper_function(func_idx, params, vec![php_value_type], compile_body)
+    }
+
+    /// Compile a helper function body with any signature into a reserved index
+    pub(super) fn compile_typed_helper_function(
+        &mut self,
+        func_idx: u32,
+        params: Vec<ValType>,
+        results: Vec<ValType>,
+        compile_body: impl FnOnce(&mut Self) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let param_count = params.len() as u32;
+        let func_type = self.builder.add_type(params, results);
+
+        let saved_function = self.current_function.take();
+        let saved_block_depth = self.block_depth;
+        let saved_free_locals = std::mem::take(&mut self.free_locals);
+        self.current_function = Some(FunctionContext {
+            locals: vec![],
+            body: vec![],
+            local_count: param_count,
+            locations: Vec::new(),
+        });
+        self.block_depth = 0;
+
+        let result = compile_body(self);
+
+        self.finish_function(func_idx, func_type);
+
+        self.current_function = saved_function;
+        self.block_depth = saved_block_depth;
+        self.free_locals = saved_free_locals;
+        result
+    }
+
+    /// object_to_string(object) -> string: calls the class's __toString
+    fn add_object_to_string_function(&mut self) -> Result<(), String> {
+        let mut classes: Vec<(u32, String, Option<MethodInfo>)> = self.classes.values()
+            .map(|class| (class.class_id, class.name.clone(), class.methods.get("__toString").cloned()))
+            .collect();
+        classes.sort_by_key(|(class_id, _, _)| *class_id);
+
+        self.compile_helper_function(self.object_to_string_fn_idx, 1, |this| {
+            let class_id_local = this.allocate_local(ValType::I32);
+            this.emit_object_class_id(0);
+            this.emit(Instruction::LocalSet(class_id_local));
+
+            for (class_id, class_name, to_string) in &classes {
+                this.emit(Instruction::LocalGet(class_id_local));
+                this.emit(Instruction::I32Const(*class_id as i32));
+                this.emit(Instruction::I32Eq);
+                this.emit(Instruction::If(BlockType::Empty));
+                match to_string {
+                    Some(method) => {
+                        this.emit(Instruction::LocalGet(0));
+                        this.emit(Instruction::Call(method.function_idx));
+                        this.emit(Instruction::Call(this.to_string_fn_idx));
+                        this.emit(Instruction::Return);
+                    }
+                    None => {
+                        this.compile_string_literal(&format!("Object of class {} could not be converted to string", class_name))?;
+                        this.emit_uncaught_error("Error")?;
+                    }
+                }
+                this.emit(Instruction::End);
+            }
+
+            this.emit(Instruction::Unreachable);
+            Ok(())
+        })
+    }
+
+    /// clone_object(object) -> object: a new object of the same class with a copy of
+    /// the properties. Array properties are copied (arrays are values); objects held
+    /// in properties stay shared, as in PHP - __clone is where those get deep-copied.
+    fn add_clone_object_function(&mut self) -> Result<(), String> {
+        let mut enums: Vec<(u32, String)> = self.classes.values()
+            .filter(|class| class.enum_info.is_some())
+            .map(|class| (class.class_id, class.name.clone()))
+            .collect();
+        enums.sort();
+
+        self.compile_helper_function(self.clone_object_fn_idx, 1, |this| {
+            let array_ref_type = ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(this.gc_types.php_array),
+            });
+
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_TYPE,
+            });
+            this.emit(Instruction::I32Const(TYPE_OBJECT as i32));
+            this.emit(Instruction::I32Ne);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.compile_string_literal("__clone method called on non-object")?;
+            this.emit_uncaught_error("Error")?;
+            this.emit(Instruction::End);
+
+            let class_id_local = this.allocate_local(ValType::I32);
+            this.emit_object_class_id(0);
+            this.emit(Instruction::LocalSet(class_id_local));
+
+            // Enum cases are singletons
+            for (class_id, enum_name) in &enums {
+                this.emit(Instruction::LocalGet(class_id_local));
+                this.emit(Instruction::I32Const(*class_id as i32));
+                this.emit(Instruction::I32Eq);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.compile_string_literal(&format!("Trying to clone an uncloneable object of class {}", enum_name))?;
+                this.emit_uncaught_error("Error")?;
+                this.emit(Instruction::End);
+            }
+
+            // Copy the properties array, then copy the arrays it holds
+            let source_local = this.allocate_local(array_ref_type);
+            let copy_local = this.allocate_local(array_ref_type);
+            let length_local = this.allocate_local(ValType::I32);
+            let index_local = this.allocate_local(ValType::I32);
+
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_ARRAY,
+            });
+            this.emit(Instruction::RefCastNonNull(HeapType::Concrete(this.gc_types.php_object)));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_object,
+                field_index: OBJECT_PROPERTIES,
+            });
+            this.emit(Instruction::LocalTee(source_local));
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::LocalTee(length_local));
+            this.emit(Instruction::ArrayNewDefault(this.gc_types.php_array));
+            this.emit(Instruction::LocalSet(copy_local));
+
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalSet(index_local));
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(index_local));
+            this.emit(Instruction::LocalGet(length_local));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::LocalGet(copy_local));
+            this.emit(Instruction::LocalGet(index_local));
+            this.emit(Instruction::LocalGet(source_local));
+            this.emit(Instruction::LocalGet(index_local));
+            this.emit(Instruction::ArrayGet(this.gc_types.php_array));
+            this.emit(Instruction::Call(this.array_copy_fn_idx));
+            this.emit(Instruction::ArraySet(this.gc_types.php_array));
+            this.emit(Instruction::LocalGet(index_local));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(index_local));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::I32Const(TYPE_OBJECT as i32));
+            this.emit(Instruction::I64Const(0));
+            this.emit(Instruction::F64Const(0.0.into()));
+            this.emit(Instruction::RefNull(HeapType::Concrete(this.gc_types.php_string)));
+            this.emit(Instruction::LocalGet(class_id_local));
+            this.emit(Instruction::LocalGet(copy_local));
+            this.emit(Instruction::StructNew(this.gc_types.php_object));
+            this.emit(Instruction::StructNew(this.gc_types.php_value));
+            Ok(())
+        })
+    }
+
+    /// Push the class_id of the object held by a boxed PhpValue local
+    pub(super) fn emit_object_class_id(&mut self, object_local: u32) {
+        self.emit(Instruction::LocalGet(object_local));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        self.emit(Instruction::RefCastNonNull(HeapType::Concrete(self.gc_types.php_object)));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_object,
+            field_index: OBJECT_CLASS_ID,
+        });
+    }
+
+    /// Call a magic method on the object in `object_local` for every candidate class,
+    /// dropping the result. The object must be known to be an object.
+    fn emit_magic_dispatch_void(&mut self, object_local: u32, candidates: &[(u32, MethodInfo)]) {
+        if candidates.is_empty() {
+            return;
+        }
+        let class_id_local = self.allocate_local(ValType::I32);
+        self.emit_object_class_id(object_local);
+        self.emit(Instruction::LocalSet(class_id_local));
+
+        for (class_id, method) in candidates {
+            self.emit(Instruction::LocalGet(class_id_local));
+            self.emit(Instruction::I32Const(*class_id as i32));
+            self.emit(Instruction::I32Eq);
+            self.emit(Instruction::If(BlockType::Empty));
+            self.emit(Instruction::LocalGet(object_local));
+            self.emit(Instruction::Call(method.function_idx));
+            self.emit(Instruction::Drop);
+            self.emit(Instruction::End);
+        }
+    }
+
+    // ===== CLONE =====
+
+    /// `clone $obj`: copy the object, then run __clone on the copy
+    pub(super) fn compile_clone(&mut self, expr: Expression) -> Result<(), String> {
+        let class_name = self.static_class_of(&expr);
+
+        if let Some(class_name) = &class_name {
+            if self.classes.get(class_name).is_some_and(|class| class.enum_info.is_some()) {
+                return Err(format!("Trying to clone an uncloneable object of class {}", class_name));
+            }
+        }
+
+        self.compile_expression(expr)?;
+        self.emit(Instruction::Call(self.clone_object_fn_idx));
+        let clone_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalSet(clone_local));
+
+        match class_name {
+            Some(class_name) => {
+                if let Some(method) = self.magic_method(&class_name, "__clone") {
+                    self.emit(Instruction::LocalGet(clone_local));
+                    self.emit(Instruction::Call(method.function_idx));
+                    self.emit(Instruction::Drop);
+                }
+            }
+            None => {
+                let candidates = self.magic_candidates("__clone");
+                self.emit_magic_dispatch_void(clone_local, &candidates);
+            }
+        }
+
+        self.emit(Instruction::LocalGet(clone_local));
+        Ok(())
+    }
+
+    // ===== __invoke =====
+
+    /// `$obj(...)`: calls the object's __invoke
+    pub(super) fn compile_invoke(&mut self, callee: Expression, args: Vec<Expression>) -> Result<(), String> {
+        if let Some(class_name) = self.static_class_of(&callee) {
+            if self.magic_method(&class_name, "__invoke").is_none() {
+                return Err(format!("Object of type {} is not callable", class_name));
+            }
+            return self.compile_method_call(callee, "__invoke", args);
+        }
+
+        if self.magic_candidates("__invoke").is_empty() {
+            return Err("Value not callable: no class defines __invoke()".to_string());
+        }
+        self.compile_dynamic_method_call(callee, "__invoke", args)
+    }
+
+    /// Build the `$arguments` array passed to __call / __callStatic
+    pub(super) fn compile_magic_call_arguments(&mut self, args: Vec<Expression>) -> Result<(), String> {
+        let elements = args.into_iter()
+            .map(|value| ArrayElement { key: None, value })
+            .collect();
+        self.compile_array_literal(elements)
+    }
+
+    // ===== DESTRUCTORS =====
+    // Without reference counting there is no point at which an object is known to be
+    // unreachable, so destructors run when the script ends: every object of a class
+    // with __destruct is recorded when it is created, and `_start` finishes by
+    // destroying them in creation order.
+
+    /// Allocate the registry global when any class has a destructor
+    pub(super) fn allocate_destructor_registry(&mut self) {
+        if !self.magic_candidates("__destruct").is_empty() {
+            self.destructor_registry = Some(self.add_php_value_global());
+        }
+    }
+
+    /// Start `_start` with an empty registry
+    pub(super) fn init_destructor_registry(&mut self) {
+        if let Some(registry) = self.destructor_registry {
+            self.emit(Instruction::Call(self.create_array_fn_idx));
+            self.emit(Instruction::GlobalSet(registry));
+        }
+    }
+
+    /// Record a newly created object whose class has __destruct
+    pub(super) fn register_for_destruction(&mut self, class_name: &str, object_local: u32) {
+        if let Some(registry) = self.destructor_registry {
+            if self.magic_method(class_name, "__destruct").is_some() {
+                self.emit(Instruction::GlobalGet(registry));
+                self.emit(Instruction::LocalGet(object_local));
+                self.emit(Instruction::Call(self.array_push_fn_idx));
+                self.emit(Instruction::GlobalSet(registry));
+            }
+        }
+    }
+
+    /// End `_start` by calling __destruct on every recorded object
+    pub(super) fn run_destructors(&mut self) {
+        let Some(registry) = self.destructor_registry else {
+            return;
+        };
+        let candidates = self.magic_candidates("__destruct");
+
+        let count_local = self.allocate_local(ValType::I32);
+        let index_local = self.allocate_local(ValType::I32);
+        let object_local = self.allocate_local(self.get_php_value_type());
+
+        self.emit(Instruction::GlobalGet(registry));
+        self.emit(Instruction::Call(self.count_fn_idx));
+        self.emit(Instruction::LocalSet(count_local));
+        self.emit(Instruction::I32Const(0));
+        self.emit(Instruction::LocalSet(index_local));
+
+        self.emit(Instruction::Block(BlockType::Empty));
+        self.emit(Instruction::Loop(BlockType::Empty));
+        self.emit(Instruction::LocalGet(index_local));
+        self.emit(Instruction::LocalGet(count_local));
+        self.emit(Instruction::I32GeU);
+        self.emit(Instruction::BrIf(1));
+
+        self.emit(Instruction::GlobalGet(registry));
+        self.emit(Instruction::LocalGet(index_local));
+        self.emit(Instruction::I64ExtendI32U);
+        self.emit(Instruction::Call(self.create_int_fn_idx));
+        self.emit(Instruction::Call(self.array_get_fn_idx));
+        self.emit(Instruction::LocalSet(object_local));
+        self.emit_magic_dispatch_void(object_local, &candidates);
+
+        self.emit(Instruction::LocalGet(index_local));
+        self.emit(Instruction::I32Const(1));
+        self.emit(Instruction::I32Add);
+        self.emit(Instruction::LocalSet(index_local));
+        self.emit(Instruction::Br(0));
+        self.emit(Instruction::End);
+        self.emit(Instruction::End);
+    }
+}
@@ -7,10 +7,35 @@ pub mod expressions;
 pub mod statements;
 pub mod arithmetic;
 pub mod arrays;
+pub mod packed_arrays; // Int, float and string arrays with typed storage and their deoptimization
+pub mod array_copies;  // Copies that keep arrays values when a PhpValue has several holders
 pub mod type_inference;
 pub mod loop_analysis;
 pub mod escape_analysis;
 pub mod classes;  // PHASE 5: OOP support
 pub mod builtins; // PHASE 6: Built-in functions
+pub mod globals;  // global, static and $GLOBALS
+pub mod enums;    // Pure and backed enums
+pub mod magic;    // Magic methods (__toString, __get, __call, __clone, ...)
+pub mod types;    // Parameter, return and property type declarations
+pub mod properties; // Static, readonly and promoted properties
+pub mod namespaces; // Namespaces, `use` imports and magic constants
+pub mod project;    // Multi-file projects: include/require and PSR-4 autoloading
+pub mod options;    // Optimization levels, target features, memory limits and debug info
+pub mod debug_info; // Name section and source maps
+pub mod backend;    // The Backend trait and backend selection
+pub mod http;       // Superglobals, header(), cookies and the request handler entry point
+pub mod output;     // Output buffering: the echo buffer, ob_* functions and flushes to the host
+pub mod imports;    // Functions and constants provided by extensions and the host
+pub mod specialization; // Copies of user functions taking and returning unboxed ints and floats
+pub mod ir;             // SSA mid-level IR: lowering, passes, dump and Wasm emission
+pub mod constant_folding; // Compile-time evaluation and propagation of constants over the AST
+pub mod inlining;         // Calls to small functions and methods compiled as their returned expression
 
 pub use core::Compiler;
+pub use project::Project;
+pub use debug_info::CompiledModule;
+pub use backend::Backend;
+pub(crate) use debug_info::source_map;
+pub use options::{BackendKind, CompilerOptions, Feature, OptLevel, TargetFeatures};
+pub use imports::{ConstantValue, ImportType, ImportedFunction};
@@ -0,0 +1,287 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Namespaces, `use` imports and magic constants
+//
+// Name resolution rewrites a parsed file so that the rest of the compiler only
+// ever sees fully qualified names, without the leading backslash: declarations
+// get the namespace they appear in as a prefix, and class and function
+// references are resolved through the file's imports. Namespace blocks are
+// flattened into the surrounding statements and `use` statements are dropped.
+//
+// Unqualified function calls inside a namespace fall back to the global
+// function unless the project declares one in that namespace, as PHP does at
+// runtime; that is why the resolver is handed every declared function.
+
+use super::core::*;
+use edge_php_parser::ast::*;
+use edge_php_parser::visit_mut::{self, VisitorMut};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Class names that are never namespace-relative
+fn is_special_class_name(name: &str) -> bool {
+    ["self", "static", "parent", "object"].iter().any(|special| name.eq_ignore_ascii_case(special))
+}
+
+fn qualify(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}\\{}", namespace, name)
+    }
+}
+
+pub(super) struct NameResolver<'a> {
+    file: Option<&'a Path>,
+    functions: &'a HashSet<String>, // Every function the program declares, fully qualified
+    namespace: String,
+    class_imports: HashMap<String, String>,    // Lowercase alias -> class or namespace
+    function_imports: HashMap<String, String>, // Lowercase alias -> function
+    class_name: Option<String>,
+    function_name: Option<String>,
+    pub declared_classes: Vec<String>,
+    pub declared_functions: Vec<String>,
+    pub referenced_classes: HashSet<String>,
+}
+
+impl<'a> NameResolver<'a> {
+    pub(super) fn new(file: Option<&'a Path>, functions: &'a HashSet<String>) -> Self {
+        Self {
+            file,
+            functions,
+            namespace: String::new(),
+            class_imports: HashMap::new(),
+            function_imports: HashMap::new(),
+            class_name: None,
+            function_name: None,
+            declared_classes: Vec::new(),
+            declared_functions: Vec::new(),
+            referenced_classes: HashSet::new(),
+        }
+    }
+
+    pub(super) fn resolve_program(&mut self, program: &mut Program) -> Result<(), String> {
+        for item in &mut program.items {
+            if let ProgramItem::PhpBlock { statements } = item {
+                let mut resolved = Vec::with_capacity(statements.len());
+                for stmt in std::mem::take(statements) {
+                    self.resolve_top_level(stmt, &mut resolved)?;
+                }
+                *statements = resolved;
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_top_level(&mut self, stmt: Statement, resolved: &mut Vec<Statement>) -> Result<(), String> {
+        match stmt {
+            Statement::Namespace(namespace) => {
+                self.enter_namespace(&namespace.name);
+                for stmt in namespace.body.statements {
+                    if matches!(stmt, Statement::Namespace(_)) {
+                        return Err("Namespace declarations cannot be nested".to_string());
+                    }
+                    self.resolve_top_level(stmt, resolved)?;
+                }
+                self.enter_namespace("");
+            }
+            Statement::Use(use_statement) => {
+                for item in use_statement.items {
+                    self.import(item)?;
+                }
+            }
+            mut stmt => {
+                self.visit_statement_mut(&mut stmt);
+                resolved.push(stmt);
+            }
+        }
+        Ok(())
+    }
+
+    /// Imports are scoped to the namespace block they appear in
+    fn enter_namespace(&mut self, name: &str) {
+        self.namespace = name.to_string();
+        self.class_imports.clear();
+        self.function_imports.clear();
+    }
+
+    fn import(&mut self, item: UseItem) -> Result<(), String> {
+        let alias = item.alias.clone()
+            .unwrap_or_else(|| item.path.rsplit('\\').next().unwrap_or(&item.path).to_string());
+        let imports = match item.kind {
+            UseKind::Class => &mut self.class_imports,
+            UseKind::Function => &mut self.function_imports,
+            // Constants are not supported yet, so there is nothing to resolve
+            UseKind::Constant => return Ok(()),
+        };
+        if let Some(existing) = imports.get(&alias.to_ascii_lowercase()) {
+            if !existing.eq_ignore_ascii_case(&item.path) {
+                return Err(format!(
+                    "Cannot use {} as {} because the name is already in use",
+                    item.path, alias
+                ));
+            }
+        }
+        imports.insert(alias.to_ascii_lowercase(), item.path);
+        Ok(())
+    }
+
+    /// `\A\B` is fully qualified, `namespace\B` is relative to the current
+    /// namespace and `A\B` goes through the import of `A` when there is one.
+    fn resolve_qualified(&self, name: &str) -> String {
+        if let Some(fully_qualified) = name.strip_prefix('\\') {
+            return fully_qualified.to_string();
+        }
+        let (first, rest) = match name.split_once('\\') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (name, None),
+        };
+        if first.eq_ignore_ascii_case("namespace") {
+            if let Some(rest) = rest {
+                return qualify(&self.namespace, rest);
+            }
+        }
+        match self.class_imports.get(&first.to_ascii_lowercase()) {
+            Some(import) => match rest {
+                Some(rest) => format!("{}\\{}", import, rest),
+                None => import.clone(),
+            },
+            None => qualify(&self.namespace, name),
+        }
+    }
+
+    fn resolve_class(&mut self, name: &str) -> String {
+        if is_special_class_name(name) {
+            return name.to_string();
+        }
+        let resolved = self.resolve_qualified(name);
+        self.referenced_classes.insert(resolved.clone());
+        resolved
+    }
+
+    fn resolve_function(&self, name: &str) -> String {
+        if name.contains('\\') {
+            return self.resolve_qualified(name);
+        }
+        if let Some(import) = self.function_imports.get(&name.to_ascii_lowercase()) {
+            return import.clone();
+        }
+        let namespaced = qualify(&self.namespace, name);
+        if self.functions.contains(&namespaced) {
+            namespaced
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn magic_constant(&self, constant: MagicConstant) -> String {
+        let path = |path: Option<&Path>| path.map(|path| path.display().to_string()).unwrap_or_default();
+        match constant {
+            MagicConstant::Dir => path(self.file.and_then(Path::parent)),
+            MagicConstant::File => path(self.file),
+            MagicConstant::Namespace => self.namespace.clone(),
+            MagicConstant::Class => self.class_name.clone().unwrap_or_default(),
+            MagicConstant::Function => self.function_name.clone().unwrap_or_default(),
+            MagicConstant::Method => match (&self.class_name, &self.function_name) {
+                (Some(class), Some(function)) => format!("{}::{}", class, function),
+                (_, function) => function.clone().unwrap_or_default(),
+            },
+        }
+    }
+}
+
+impl VisitorMut for NameResolver<'_> {
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        match stmt {
+            Statement::Function { name, .. } => {
+                *name = qualify(&self.namespace, name);
+                self.declared_functions.push(name.clone());
+                let outer = self.function_name.replace(name.clone());
+                visit_mut::walk_statement_mut(self, stmt);
+                self.function_name = outer;
+            }
+            Statement::Class { name, extends, implements, .. } => {
+                *name = qualify(&self.namespace, name);
+                if let Some(parent) = extends {
+                    *parent = self.resolve_class(parent);
+                }
+                for interface in implements.iter_mut() {
+                    *interface = self.resolve_class(interface);
+                }
+                self.declared_classes.push(name.clone());
+                let outer = self.class_name.replace(name.clone());
+                visit_mut::walk_statement_mut(self, stmt);
+                self.class_name = outer;
+            }
+            Statement::Enum { name, implements, .. } => {
+                *name = qualify(&self.namespace, name);
+                for interface in implements.iter_mut() {
+                    *interface = self.resolve_class(interface);
+                }
+                self.declared_classes.push(name.clone());
+                let outer = self.class_name.replace(name.clone());
+                visit_mut::walk_statement_mut(self, stmt);
+                self.class_name = outer;
+            }
+            Statement::Interface { name, .. } => {
+                *name = qualify(&self.namespace, name);
+                self.declared_classes.push(name.clone());
+                let outer = self.class_name.replace(name.clone());
+                visit_mut::walk_statement_mut(self, stmt);
+                self.class_name = outer;
+            }
+            _ => visit_mut::walk_statement_mut(self, stmt),
+        }
+    }
+
+    fn visit_class_member_mut(&mut self, member: &mut ClassMember) {
+        let name = match member {
+            ClassMember::Method { name, .. } | ClassMember::AbstractMethod { name, .. } => Some(name.clone()),
+            ClassMember::Constructor { .. } => Some("__construct".to_string()),
+            _ => None,
+        };
+        let outer = std::mem::replace(&mut self.function_name, name);
+        visit_mut::walk_class_member_mut(self, member);
+        self.function_name = outer;
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        match expr {
+            Expression::New { class, .. }
+            | Expression::StaticMethodCall { class, .. }
+            | Expression::ClassConstant { class, .. }
+            | Expression::StaticPropertyAccess { class, .. } => {
+                *class = self.resolve_class(class);
+            }
+            Expression::FunctionCall { name, .. } => {
+                *name = self.resolve_function(name);
+            }
+            Expression::MagicConstant(constant) => {
+                *expr = Expression::Literal(Literal::String(self.magic_constant(*constant)));
+                return;
+            }
+            _ => {}
+        }
+        visit_mut::walk_expression_mut(self, expr);
+    }
+
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        if let Type::Object(name) = ty {
+            *name = self.resolve_class(name);
+        }
+        visit_mut::walk_type_mut(self, ty);
+    }
+}
+
+impl Compiler {
+    /// Resolve the names of a program compiled on its own, from source text
+    pub(super) fn resolve_names(program: &mut Program) -> Result<(), String> {
+        let no_functions = HashSet::new();
+        let mut collector = NameResolver::new(None, &no_functions);
+        collector.resolve_program(&mut program.clone())?;
+        let functions = collector.declared_functions.into_iter().collect();
+
+        NameResolver::new(None, &functions).resolve_program(program)
+    }
+}
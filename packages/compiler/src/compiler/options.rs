@@ -0,0 +1,674 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compiler options: optimization level, per-pass toggles, memory limits,
+//! target features, debug info, fuel metering, the backend, and the functions
+//! and constants the module imports.
+//!
+//! An optimization level picks a default for every pass and the pass toggles
+//! override it, whatever order they are set in:
+//! `CompilerOptions::new().opt_level(OptLevel::O1).loop_unrolling(true)` is
+//! `-O1` with loop unrolling turned back on.
+
+use super::imports::{ConstantValue, ImportedFunction};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptLevel {
+    /// No optimizations: every value is boxed and every helper is emitted
+    O0,
+    /// Unboxing (guided by escape analysis), tree shaking, and the mid-level
+    /// IR's constant propagation, CSE, loop-invariant code motion and dead
+    /// code elimination
+    O1,
+    /// Everything in `O1` plus 4x unrolling of counted loops and up to two
+    /// specialized copies of each function
+    #[default]
+    O2,
+    /// Everything in `O2` with 8x loop unrolling and up to four copies of each function
+    O3,
+    /// Like `O1`: the passes that shrink the module, none that grow it
+    Os,
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level.trim_start_matches('O') {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            "s" => Ok(OptLevel::Os),
+            _ => Err(format!("unknown optimization level '{}' (expected 0, 1, 2, 3 or s)", level)),
+        }
+    }
+}
+
+impl fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self {
+            OptLevel::O0 => "0",
+            OptLevel::O1 => "1",
+            OptLevel::O2 => "2",
+            OptLevel::O3 => "3",
+            OptLevel::Os => "s",
+        };
+        write!(f, "O{}", level)
+    }
+}
+
+/// How PHP values are represented in the generated module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackendKind {
+    /// GC structs and arrays managed by the engine (needs the gc proposal)
+    #[default]
+    WasmGc,
+    /// Tagged blocks in linear memory with a built-in allocator and
+    /// mark-sweep collector, for engines without WasmGC
+    LinearMemory,
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "gc" | "wasm-gc" => Ok(BackendKind::WasmGc),
+            "linear" | "linear-memory" => Ok(BackendKind::LinearMemory),
+            _ => Err(format!("unknown backend '{}' (expected gc or linear)", name)),
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BackendKind::WasmGc => "gc",
+            BackendKind::LinearMemory => "linear",
+        })
+    }
+}
+
+/// A WebAssembly proposal the target engine may support
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Gc,
+    Exceptions,
+    TailCalls,
+    Simd,
+}
+
+impl FromStr for Feature {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "gc" => Ok(Feature::Gc),
+            "exceptions" | "exception-handling" => Ok(Feature::Exceptions),
+            "tail-calls" | "tail-call" => Ok(Feature::TailCalls),
+            "simd" => Ok(Feature::Simd),
+            _ => Err(format!("unknown feature '{}' (expected gc, exceptions, tail-calls or simd)", name)),
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Feature::Gc => "gc",
+            Feature::Exceptions => "exceptions",
+            Feature::TailCalls => "tail-calls",
+            Feature::Simd => "simd",
+        })
+    }
+}
+
+/// The proposals the generated module may rely on. Reference types and the
+/// MVP are always assumed. Only `gc` changes the generated code so far;
+/// validation rejects the others until code generation uses them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetFeatures {
+    pub gc: bool,
+    pub exceptions: bool,
+    pub tail_calls: bool,
+    pub simd: bool,
+}
+
+impl Default for TargetFeatures {
+    fn default() -> Self {
+        TargetFeatures { gc: true, exceptions: false, tail_calls: false, simd: false }
+    }
+}
+
+impl TargetFeatures {
+    pub fn set(&mut self, feature: Feature, enabled: bool) {
+        match feature {
+            Feature::Gc => self.gc = enabled,
+            Feature::Exceptions => self.exceptions = enabled,
+            Feature::TailCalls => self.tail_calls = enabled,
+            Feature::Simd => self.simd = enabled,
+        }
+    }
+
+    pub fn contains(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::Gc => self.gc,
+            Feature::Exceptions => self.exceptions,
+            Feature::TailCalls => self.tail_calls,
+            Feature::Simd => self.simd,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompilerOptions {
+    backend: BackendKind,
+    opt_level: OptLevel,
+    loop_unrolling: Option<bool>,
+    loop_optimizations: Option<bool>,
+    escape_analysis: Option<bool>,
+    unboxing: Option<bool>,
+    tree_shaking: Option<bool>,
+    specialization: Option<bool>,
+    mid_level_ir: Option<bool>,
+    constant_folding: Option<bool>,
+    inlining: Option<bool>,
+    typed_arrays: Option<bool>,
+    initial_memory_pages: u32,
+    max_memory_pages: Option<u32>,
+    features: TargetFeatures,
+    debug_info: bool,
+    source_map: bool,
+    source_map_url: Option<String>,
+    type_report: bool,
+    ir_dump: bool,
+    fuel_metering: bool,
+    http_handler: bool,
+    imported_functions: Vec<ImportedFunction>,
+    constants: Vec<(String, ConstantValue)>,
+}
+
+impl Default for CompilerOptions {
+    fn default() -> Self {
+        CompilerOptions {
+            backend: BackendKind::default(),
+            opt_level: OptLevel::default(),
+            loop_unrolling: None,
+            loop_optimizations: None,
+            escape_analysis: None,
+            unboxing: None,
+            tree_shaking: None,
+            specialization: None,
+            mid_level_ir: None,
+            constant_folding: None,
+            inlining: None,
+            typed_arrays: None,
+            initial_memory_pages: 1, // print_value's buffer; PHP values live on the GC heap
+            max_memory_pages: None,
+            features: TargetFeatures::default(),
+            debug_info: false,
+            source_map: false,
+            source_map_url: None,
+            type_report: false,
+            ir_dump: false,
+            fuel_metering: false,
+            http_handler: false,
+            imported_functions: Vec::new(),
+            constants: Vec::new(),
+        }
+    }
+}
+
+impl CompilerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Which backend generates the module
+    pub fn backend(mut self, backend: BackendKind) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn opt_level(mut self, level: OptLevel) -> Self {
+        self.opt_level = level;
+        self
+    }
+
+    /// Unroll counted `for` loops
+    pub fn loop_unrolling(mut self, enabled: bool) -> Self {
+        self.loop_unrolling = Some(enabled);
+        self
+    }
+
+    /// Count arrays a loop condition counts once before the loop, when the
+    /// loop leaves them alone, and turn products of a `for` counter and a
+    /// constant into induction variables of their own
+    pub fn loop_optimizations(mut self, enabled: bool) -> Self {
+        self.loop_optimizations = Some(enabled);
+        self
+    }
+
+    /// Keep variables that never escape their function unboxed. Without it,
+    /// only assignments used as expressions and typed parameters are unboxed.
+    pub fn escape_analysis(mut self, enabled: bool) -> Self {
+        self.escape_analysis = Some(enabled);
+        self
+    }
+
+    /// Store known ints and floats as raw i64/f64 locals instead of PhpValues
+    pub fn unboxing(mut self, enabled: bool) -> Self {
+        self.unboxing = Some(enabled);
+        self
+    }
+
+    /// Leave out the runtime helpers and types the program never uses, on
+    /// either backend
+    pub fn tree_shaking(mut self, enabled: bool) -> Self {
+        self.tree_shaking = Some(enabled);
+        self
+    }
+
+    /// Compile copies of user functions for the int and float arguments they
+    /// are called with from loops and other functions; the copies take and
+    /// return unboxed i64/f64 values, so this needs unboxing
+    pub fn specialization(mut self, enabled: bool) -> Self {
+        self.specialization = Some(enabled);
+        self
+    }
+
+    /// Compile user functions through the SSA mid-level IR (see the `ir`
+    /// module), which propagates constants, merges common subexpressions,
+    /// hoists loop-invariant code and removes dead code before emitting Wasm;
+    /// functions it cannot express are compiled from the AST as before
+    pub fn mid_level_ir(mut self, enabled: bool) -> Self {
+        self.mid_level_ir = Some(enabled);
+        self
+    }
+
+    /// Evaluate expressions on constants at compile time, drop the branches
+    /// of `if` statements on constants, and replace variables assigned one
+    /// constant with it (see the `constant_folding` module)
+    pub fn constant_folding(mut self, enabled: bool) -> Self {
+        self.constant_folding = Some(enabled);
+        self
+    }
+
+    /// Replace calls to functions and methods that return one small
+    /// expression of their parameters with that expression
+    pub fn inlining(mut self, enabled: bool) -> Self {
+        self.inlining = Some(enabled);
+        self
+    }
+
+    /// Store arrays that type inference proves hold only ints, floats or
+    /// strings as packed `i64`, `f64` or string storage instead of boxed
+    /// PhpValues (see the `packed_arrays` module)
+    pub fn typed_arrays(mut self, enabled: bool) -> Self {
+        self.typed_arrays = Some(enabled);
+        self
+    }
+
+    pub fn initial_memory_pages(mut self, pages: u32) -> Self {
+        self.initial_memory_pages = pages;
+        self
+    }
+
+    /// `None` lets the memory grow without limit
+    pub fn max_memory_pages(mut self, pages: Option<u32>) -> Self {
+        self.max_memory_pages = pages;
+        self
+    }
+
+    pub fn features(mut self, features: TargetFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn enable_feature(mut self, feature: Feature) -> Self {
+        self.features.set(feature, true);
+        self
+    }
+
+    pub fn disable_feature(mut self, feature: Feature) -> Self {
+        self.features.set(feature, false);
+        self
+    }
+
+    /// Emit a `name` section naming functions, methods, runtime helpers and
+    /// the locals holding PHP variables, so traces show PHP names
+    pub fn debug_info(mut self, enabled: bool) -> Self {
+        self.debug_info = enabled;
+        self
+    }
+
+    /// Produce a source map from code offsets to PHP files, lines and columns
+    /// (see [`crate::Compiler::compile_module`])
+    pub fn source_map(mut self, enabled: bool) -> Self {
+        self.source_map = enabled;
+        self
+    }
+
+    /// Produce a source map and record where it is in the module's
+    /// `sourceMappingURL` section
+    pub fn source_map_url(mut self, url: impl Into<String>) -> Self {
+        self.source_map = true;
+        self.source_map_url = Some(url.into());
+        self
+    }
+
+    /// Describe what type inference found - the parameter and return types of
+    /// every function and method, and the type of every variable - in
+    /// [`crate::CompiledModule::type_report`]
+    pub fn type_report(mut self, enabled: bool) -> Self {
+        self.type_report = enabled;
+        self
+    }
+
+    /// Print every function compiled through the mid-level IR, after its
+    /// passes, into [`crate::CompiledModule::ir`], and note why the others
+    /// were not
+    pub fn ir_dump(mut self, enabled: bool) -> Self {
+        self.ir_dump = enabled;
+        self
+    }
+
+    /// Charge one unit of the exported `fuel` global on every function entry
+    /// and loop iteration and trap when it runs out, so hosts can bound how
+    /// long untrusted code runs
+    pub fn fuel_metering(mut self, enabled: bool) -> Self {
+        self.fuel_metering = enabled;
+        self
+    }
+
+    /// Export `handle_request` instead of `_start`, so a host can serve HTTP
+    /// requests with the module: it fills the superglobals and collects the
+    /// status, headers and body instead of printing output
+    pub fn http_handler(mut self, enabled: bool) -> Self {
+        self.http_handler = enabled;
+        self
+    }
+
+    /// Let the program call a function the module imports, such as a host
+    /// function (`ImportedFunction::host`) or an extension's (see the
+    /// `imports` module for how values cross)
+    pub fn import_function(mut self, function: ImportedFunction) -> Self {
+        self.imported_functions.push(function);
+        self
+    }
+
+    /// Define a global constant, compiled into the module as a literal
+    pub fn constant(mut self, name: impl Into<String>, value: ConstantValue) -> Self {
+        self.constants.push((name.into(), value));
+        self
+    }
+
+    pub fn backend_kind(&self) -> BackendKind {
+        self.backend
+    }
+
+    pub fn level(&self) -> OptLevel {
+        self.opt_level
+    }
+
+    pub fn loop_unrolling_enabled(&self) -> bool {
+        self.loop_unrolling.unwrap_or(matches!(self.opt_level, OptLevel::O2 | OptLevel::O3))
+    }
+
+    pub fn loop_optimizations_enabled(&self) -> bool {
+        self.loop_optimizations.unwrap_or(self.opt_level != OptLevel::O0)
+    }
+
+    pub fn escape_analysis_enabled(&self) -> bool {
+        self.escape_analysis.unwrap_or(self.opt_level != OptLevel::O0)
+    }
+
+    pub fn unboxing_enabled(&self) -> bool {
+        self.unboxing.unwrap_or(self.opt_level != OptLevel::O0)
+    }
+
+    pub fn tree_shaking_enabled(&self) -> bool {
+        self.tree_shaking.unwrap_or(self.opt_level != OptLevel::O0)
+    }
+
+    pub fn specialization_enabled(&self) -> bool {
+        self.specialization.unwrap_or(matches!(self.opt_level, OptLevel::O2 | OptLevel::O3))
+    }
+
+    pub fn mid_level_ir_enabled(&self) -> bool {
+        self.mid_level_ir.unwrap_or(self.opt_level != OptLevel::O0)
+    }
+
+    pub fn constant_folding_enabled(&self) -> bool {
+        self.constant_folding.unwrap_or(self.opt_level != OptLevel::O0)
+    }
+
+    pub fn inlining_enabled(&self) -> bool {
+        self.inlining.unwrap_or(matches!(self.opt_level, OptLevel::O2 | OptLevel::O3))
+    }
+
+    pub fn typed_arrays_enabled(&self) -> bool {
+        self.typed_arrays.unwrap_or(self.opt_level != OptLevel::O0)
+    }
+
+    /// How many specialized copies of one function the module may contain
+    pub fn max_specializations(&self) -> usize {
+        match self.opt_level {
+            _ if !self.specialization_enabled() || !self.unboxing_enabled() => 0,
+            OptLevel::O3 => 4,
+            _ => 2,
+        }
+    }
+
+    /// How many copies of a loop body an unrolled loop runs per iteration
+    pub fn unroll_factor(&self) -> usize {
+        if self.opt_level == OptLevel::O3 { 8 } else { 4 }
+    }
+
+    /// Initial and maximum memory size in pages
+    pub fn memory_pages(&self) -> (u32, Option<u32>) {
+        (self.initial_memory_pages, self.max_memory_pages)
+    }
+
+    pub fn target_features(&self) -> TargetFeatures {
+        self.features
+    }
+
+    pub fn debug_info_enabled(&self) -> bool {
+        self.debug_info
+    }
+
+    pub fn source_map_enabled(&self) -> bool {
+        self.source_map
+    }
+
+    pub fn get_source_map_url(&self) -> Option<&str> {
+        self.source_map_url.as_deref()
+    }
+
+    pub fn type_report_enabled(&self) -> bool {
+        self.type_report
+    }
+
+    pub fn ir_dump_enabled(&self) -> bool {
+        self.ir_dump
+    }
+
+    pub fn fuel_metering_enabled(&self) -> bool {
+        self.fuel_metering
+    }
+
+    pub fn http_handler_enabled(&self) -> bool {
+        self.http_handler
+    }
+
+    pub fn imported_functions(&self) -> &[ImportedFunction] {
+        &self.imported_functions
+    }
+
+    /// The value of a constant; a later definition wins
+    pub fn constant_value(&self, name: &str) -> Option<&ConstantValue> {
+        self.constants.iter().rev().find(|(constant, _)| constant == name).map(|(_, value)| value)
+    }
+
+    /// Reject combinations the compiler cannot produce a module for
+    pub(super) fn validate(&self) -> Result<(), String> {
+        if self.backend == BackendKind::WasmGc && !self.features.gc {
+            return Err("the WasmGC backend needs the gc feature".to_string());
+        }
+        if let Some(feature) = [Feature::Exceptions, Feature::TailCalls, Feature::Simd].into_iter().find(|feature| self.features.contains(*feature)) {
+            return Err(format!("the {} feature is not supported yet", feature));
+        }
+        // print_value copies strings into linear memory, so at least one page is needed
+        if self.initial_memory_pages == 0 {
+            return Err("the module needs at least one page of memory".to_string());
+        }
+        if let Some(max) = self.max_memory_pages {
+            if max < self.initial_memory_pages {
+                return Err(format!(
+                    "the maximum memory ({} pages) is smaller than the initial memory ({} pages)",
+                    max, self.initial_memory_pages
+                ));
+            }
+        }
+        if self.http_handler {
+            if self.backend != BackendKind::WasmGc {
+                return Err("HTTP handler mode needs the WasmGC backend".to_string());
+            }
+            // Requests are written past the first page
+            if self.max_memory_pages.is_some_and(|max| max < 2) {
+                return Err("HTTP handler mode needs at least two pages of memory".to_string());
+            }
+        }
+        if !self.imported_functions.is_empty() && self.backend != BackendKind::WasmGc {
+            return Err("imported functions need the WasmGC backend".to_string());
+        }
+        if self.imported_functions.iter().any(|function| function.module == "env" && function.name == "print") {
+            return Err("env.print is the module's output; a host function cannot be named print".to_string());
+        }
+        let mut names = std::collections::HashSet::new();
+        if let Some(function) = self.imported_functions.iter().find(|function| !names.insert(&function.name)) {
+            return Err(format!("the function {} is imported twice", function.name));
+        }
+        if self.initial_memory_pages > 65536 || self.max_memory_pages.is_some_and(|max| max > 65536) {
+            return Err("a 32-bit memory has at most 65536 pages (4GB)".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compiler;
+
+    /// Initial and maximum pages of the memory `wasm` defines
+    fn memory_limits(wasm: &[u8]) -> (u64, Option<u64>) {
+        for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+            if let wasmparser::Payload::MemorySection(reader) = payload.unwrap() {
+                let memory = reader.into_iter().next().unwrap().unwrap();
+                return (memory.initial, memory.maximum);
+            }
+        }
+        panic!("no memory section");
+    }
+
+    fn compile_error(options: CompilerOptions) -> String {
+        Compiler::with_options(options).compile("<?php echo 1;").unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_opt_level_presets() {
+        let passes = |options: &CompilerOptions| [
+            options.escape_analysis_enabled(),
+            options.unboxing_enabled(),
+            options.tree_shaking_enabled(),
+            options.mid_level_ir_enabled(),
+            options.constant_folding_enabled(),
+            options.loop_optimizations_enabled(),
+            options.typed_arrays_enabled(),
+            options.loop_unrolling_enabled(),
+            options.specialization_enabled(),
+            options.inlining_enabled(),
+        ];
+        let level = |level| CompilerOptions::new().opt_level(level);
+
+        assert_eq!(passes(&level(OptLevel::O0)), [false; 10]);
+        let shrinking = [true, true, true, true, true, true, true, false, false, false];
+        assert_eq!(passes(&level(OptLevel::O1)), shrinking);
+        assert_eq!(passes(&level(OptLevel::Os)), shrinking);
+        assert_eq!(passes(&level(OptLevel::O2)), [true; 10]);
+        assert_eq!(passes(&level(OptLevel::O3)), [true; 10]);
+        assert_eq!(CompilerOptions::new(), level(OptLevel::O2));
+
+        assert_eq!(level(OptLevel::O0).max_specializations(), 0);
+        assert_eq!(level(OptLevel::O1).max_specializations(), 0);
+        assert_eq!(level(OptLevel::O2).max_specializations(), 2);
+        assert_eq!(level(OptLevel::O3).max_specializations(), 4);
+        assert_eq!(level(OptLevel::O2).unroll_factor(), 4);
+        assert_eq!(level(OptLevel::O3).unroll_factor(), 8);
+
+        assert_eq!("3".parse::<OptLevel>(), Ok(OptLevel::O3));
+        assert_eq!("Os".parse::<OptLevel>(), Ok(OptLevel::Os));
+        assert!("4".parse::<OptLevel>().is_err());
+    }
+
+    #[test]
+    fn test_pass_toggles_override_level() {
+        // Toggles win whether they are set before or after the level
+        let o1 = CompilerOptions::new().loop_unrolling(true).opt_level(OptLevel::O1);
+        assert!(o1.loop_unrolling_enabled());
+        assert!(!o1.specialization_enabled());
+
+        let o0 = CompilerOptions::new().opt_level(OptLevel::O0).tree_shaking(true).inlining(true);
+        assert!(o0.tree_shaking_enabled() && o0.inlining_enabled());
+        assert!(!o0.unboxing_enabled());
+
+        let o3 = CompilerOptions::new().opt_level(OptLevel::O3).typed_arrays(false).loop_optimizations(false);
+        assert!(!o3.typed_arrays_enabled() && !o3.loop_optimizations_enabled());
+        assert!(o3.mid_level_ir_enabled());
+
+        // Specialized copies take unboxed values, so either toggle turns them off
+        assert_eq!(CompilerOptions::new().opt_level(OptLevel::O3).specialization(false).max_specializations(), 0);
+        assert_eq!(CompilerOptions::new().opt_level(OptLevel::O3).unboxing(false).max_specializations(), 0);
+        assert_eq!(CompilerOptions::new().opt_level(OptLevel::O1).specialization(true).max_specializations(), 2);
+    }
+
+    #[test]
+    fn test_memory_pages() {
+        let compile = |options: CompilerOptions| Compiler::with_options(options).compile("<?php echo 1;").unwrap();
+        assert_eq!(memory_limits(&compile(CompilerOptions::new())), (1, None));
+        let limited = CompilerOptions::new().initial_memory_pages(3).max_memory_pages(Some(10));
+        assert_eq!(memory_limits(&compile(limited.clone())), (3, Some(10)));
+        let (_, maximum) = memory_limits(&compile(limited.initial_memory_pages(40).max_memory_pages(Some(64)).backend(BackendKind::LinearMemory)));
+        assert_eq!(maximum, Some(64));
+
+        assert!(compile_error(CompilerOptions::new().initial_memory_pages(0)).contains("at least one page"));
+        assert!(compile_error(CompilerOptions::new().initial_memory_pages(4).max_memory_pages(Some(2))).contains("smaller than the initial memory"));
+        assert!(compile_error(CompilerOptions::new().max_memory_pages(Some(70000))).contains("65536 pages"));
+        assert!(compile_error(CompilerOptions::new().max_memory_pages(Some(1)).backend(BackendKind::LinearMemory)).contains("needs at least"));
+    }
+
+    #[test]
+    fn test_target_features() {
+        assert_eq!("tail_call".parse::<Feature>(), Ok(Feature::TailCalls));
+        assert_eq!("Exception-Handling".parse::<Feature>(), Ok(Feature::Exceptions));
+        assert_eq!(
+            "avx".parse::<Feature>(),
+            Err("unknown feature 'avx' (expected gc, exceptions, tail-calls or simd)".to_string())
+        );
+
+        // Nothing is generated with these yet
+        for feature in [Feature::Exceptions, Feature::TailCalls, Feature::Simd] {
+            let error = compile_error(CompilerOptions::new().enable_feature(feature));
+            assert!(error.contains(&format!("the {} feature is not supported yet", feature)), "{}", error);
+        }
+
+        assert!(compile_error(CompilerOptions::new().disable_feature(Feature::Gc)).contains("needs the gc feature"));
+        let linear = CompilerOptions::new().backend(BackendKind::LinearMemory).disable_feature(Feature::Gc);
+        assert!(Compiler::with_options(linear).compile("<?php echo 1;").is_ok());
+    }
+}
@@ -0,0 +1,909 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Output: the buffer behind echo, ob_* output buffering and flushing to the host
+//
+// Output never goes straight to `env.print`. It is appended to the innermost
+// ob_start() buffer, or with none active to the module's own output buffer,
+// which is handed to the host in chunks of up to OUTPUT_CHUNK bytes once it
+// fills up and when the script ends. A request handler collects it in the
+// response body instead.
+//
+// Buffers are `ob_buffer` structs: the bytes, the used length, the id of the
+// ob_start() callback (0 for none) and whether the callback has run yet. The
+// callbacks are user functions named by a string, either a literal or a value
+// looked up among the user functions when ob_start() runs; closures, methods
+// and invokable objects are not supported. `php_ob_callback` dispatches on the
+// id and is compiled last, once every callback is known.
+//
+// A trap would lose what is still buffered, so fatal errors flush everything
+// before trapping, and the exported `flush_output` lets hosts do the same
+// after a trap of their own (an exhausted limit).
+
+use super::core::*;
+use edge_php_parser::ast::*;
+use wasm_encoder::*;
+
+/// Where chunks are copied for `env.print`, NUL-terminated
+const OUTPUT_BUFFER: i32 = 0x1000;
+/// Flush threshold and largest chunk handed to the host at once
+const OUTPUT_CHUNK: i32 = 0x8000;
+
+// ob_buffer fields
+const BUFFER_BYTES: u32 = 0;
+const BUFFER_LENGTH: u32 = 1;
+const BUFFER_CALLBACK: u32 = 2;
+const BUFFER_STARTED: u32 = 3;
+
+// The $phase passed to callbacks (PHP_OUTPUT_HANDLER_*)
+const PHASE_START: i32 = 1;
+const PHASE_CLEAN: i32 = 2;
+const PHASE_FLUSH: i32 = 4;
+const PHASE_FINAL: i32 = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct OutputState {
+    buffer_type: u32,
+    stack_type: u32,
+    base: u32,    // The module's output buffer
+    stack: u32,   // ob_start() buffers, innermost last
+    level: u32,   // i32 global, ob_get_level()
+    append_fn: u32,
+    write_fn: u32,
+    flush_fn: u32,
+    flush_all_fn: u32,
+    start_fn: u32,
+    drain_fn: u32,
+    end_fn: u32,
+    contents_fn: u32,
+    callback_fn: u32,
+}
+
+impl Compiler {
+    /// Add the buffer types and globals and reserve the output helpers.
+    /// Their bodies follow in `add_output_functions`, once the response
+    /// state they route to is known.
+    pub(super) fn allocate_output_state(&mut self) {
+        let buffer_type = self.builder.add_struct_type(StructType {
+            fields: Box::new([
+                FieldType {
+                    element_type: StorageType::Val(self.get_string_type()),
+                    mutable: true,
+                },
+                FieldType { element_type: StorageType::Val(ValType::I32), mutable: true },
+                FieldType { element_type: StorageType::Val(ValType::I32), mutable: false },
+                FieldType { element_type: StorageType::Val(ValType::I32), mutable: true },
+            ]),
+        });
+        let buffer_ref = ValType::Ref(RefType { nullable: true, heap_type: HeapType::Concrete(buffer_type) });
+        let stack_type = self.builder.add_array_type(ArrayType(FieldType {
+            element_type: StorageType::Val(buffer_ref),
+            mutable: true,
+        }));
+        let stack_ref = ValType::Ref(RefType { nullable: true, heap_type: HeapType::Concrete(stack_type) });
+
+        let base = self.builder.add_global(buffer_ref, true, Instruction::RefNull(HeapType::Concrete(buffer_type)));
+        let stack = self.builder.add_global(stack_ref, true, Instruction::RefNull(HeapType::Concrete(stack_type)));
+        let level = self.builder.add_global(ValType::I32, true, Instruction::I32Const(0));
+
+        let mut reserve = |name: &str| {
+            let func_idx = self.builder.reserve_function_index();
+            self.name_function(func_idx, &format!("php_{}", name));
+            func_idx
+        };
+        self.output = Some(OutputState {
+            buffer_type,
+            stack_type,
+            base,
+            stack,
+            level,
+            append_fn: reserve("buffer_append"),
+            write_fn: reserve("output_write"),
+            flush_fn: reserve("output_flush"),
+            flush_all_fn: reserve("output_flush_all"),
+            start_fn: reserve("ob_start"),
+            drain_fn: reserve("ob_drain"),
+            end_fn: reserve("ob_end"),
+            contents_fn: reserve("ob_contents"),
+            callback_fn: reserve("ob_callback"),
+        });
+    }
+
+    pub(super) fn output_state(&self) -> Result<OutputState, String> {
+        self.output.ok_or_else(|| "The output state was not allocated".to_string())
+    }
+
+    fn buffer_ref_type(&self, output: OutputState) -> ValType {
+        ValType::Ref(RefType { nullable: true, heap_type: HeapType::Concrete(output.buffer_type) })
+    }
+
+    /// A new, empty ob_buffer with room for `capacity` bytes
+    fn emit_new_buffer(&mut self, capacity: i32, callback_local: Option<u32>) -> Result<(), String> {
+        let output = self.output_state()?;
+        self.emit(Instruction::I32Const(capacity));
+        self.emit(Instruction::ArrayNewDefault(self.gc_types.php_string));
+        self.emit(Instruction::I32Const(0));
+        match callback_local {
+            Some(local) => self.emit(Instruction::LocalGet(local)),
+            None => self.emit(Instruction::I32Const(0)),
+        }
+        self.emit(Instruction::I32Const(0));
+        self.emit(Instruction::StructNew(output.buffer_type));
+        Ok(())
+    }
+
+    /// Push the bytes and length of the ob_buffer on the stack
+    fn emit_buffer_bytes(&mut self, emit_buffer: impl Fn(&mut Self)) -> Result<(), String> {
+        let output = self.output_state()?;
+        emit_buffer(self);
+        self.emit(Instruction::StructGet { struct_type_index: output.buffer_type, field_index: BUFFER_BYTES });
+        emit_buffer(self);
+        self.emit(Instruction::StructGet { struct_type_index: output.buffer_type, field_index: BUFFER_LENGTH });
+        Ok(())
+    }
+
+    /// Push the bytes and length of the output buffer, the body of a response
+    pub(super) fn emit_output_bytes(&mut self) -> Result<(), String> {
+        let output = self.output_state()?;
+        self.emit_buffer_bytes(|this| this.emit(Instruction::GlobalGet(output.base)))
+    }
+
+    /// Start the main function with an empty output buffer
+    pub(super) fn init_output_buffer(&mut self) -> Result<(), String> {
+        let output = self.output_state()?;
+        self.emit_new_buffer(OUTPUT_CHUNK, None)?;
+        self.emit(Instruction::GlobalSet(output.base));
+        Ok(())
+    }
+
+    /// At the end of the script every ob_start() buffer is flushed through its
+    /// callback, and then the output buffer to the host
+    pub(super) fn emit_output_shutdown(&mut self) -> Result<(), String> {
+        let output = self.output_state()?;
+        self.emit(Instruction::Block(BlockType::Empty));
+        self.emit(Instruction::Loop(BlockType::Empty));
+        self.emit(Instruction::GlobalGet(output.level));
+        self.emit(Instruction::I32Eqz);
+        self.emit(Instruction::BrIf(1));
+        self.emit(Instruction::I32Const(PHASE_FINAL));
+        self.emit(Instruction::I32Const(0));
+        self.emit(Instruction::Call(output.end_fn));
+        self.emit(Instruction::Drop);
+        self.emit(Instruction::Br(0));
+        self.emit(Instruction::End);
+        self.emit(Instruction::End);
+        if !self.options.http_handler_enabled() {
+            self.emit(Instruction::Call(output.flush_fn));
+        }
+        Ok(())
+    }
+
+    /// Hand everything still buffered to the host before a fatal error traps
+    pub(super) fn emit_output_flush_all(&mut self) -> Result<(), String> {
+        let output = self.output_state()?;
+        self.emit(Instruction::Call(output.flush_all_fn));
+        Ok(())
+    }
+
+    /// Export `flush_output` for hosts whose call trapped
+    pub(super) fn export_output_flush(&mut self) -> Result<(), String> {
+        let output = self.output_state()?;
+        if !self.options.http_handler_enabled() {
+            self.builder.add_export("flush_output", ExportKind::Func, output.flush_all_fn);
+        }
+        Ok(())
+    }
+
+    // ===== BUILTINS =====
+
+    /// ob_start(), ob_get_contents(), ob_get_clean(), ob_end_flush(), ... and flush()
+    pub(super) fn compile_output_builtin(&mut self, name: &str, args: Vec<Expression>) -> Result<bool, String> {
+        let output = self.output_state()?;
+        if name == "ob_start" {
+            return self.compile_ob_start(output, args).map(|()| true);
+        }
+        if !args.is_empty() {
+            return Err(format!("{}() expects exactly 0 arguments, {} given", name, args.len()));
+        }
+
+        match name {
+            "ob_get_level" => {
+                self.emit(Instruction::GlobalGet(output.level));
+                self.emit(Instruction::I64ExtendI32U);
+                self.emit(Instruction::Call(self.create_int_fn_idx));
+            }
+            "ob_get_contents" => {
+                self.emit(Instruction::Call(output.contents_fn));
+            }
+            "ob_get_length" => {
+                let contents_local = self.allocate_local(self.get_php_value_type());
+                self.emit(Instruction::Call(output.contents_fn));
+                self.emit(Instruction::LocalTee(contents_local));
+                self.emit(Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index: PHPVALUE_TYPE,
+                });
+                self.emit(Instruction::I32Const(TYPE_STRING as i32));
+                self.emit(Instruction::I32Eq);
+                self.emit(Instruction::If(BlockType::Result(self.get_php_value_type())));
+                self.emit(Instruction::LocalGet(contents_local));
+                self.emit(Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index: PHPVALUE_STRING,
+                });
+                self.emit(Instruction::ArrayLen);
+                self.emit(Instruction::I64ExtendI32U);
+                self.emit(Instruction::Call(self.create_int_fn_idx));
+                self.emit(Instruction::Else);
+                self.emit(Instruction::LocalGet(contents_local));
+                self.emit(Instruction::End);
+                self.free_local(contents_local, self.get_php_value_type());
+            }
+            "flush" => {
+                if !self.options.http_handler_enabled() {
+                    self.emit(Instruction::Call(output.flush_fn));
+                }
+                self.emit(Instruction::Call(self.create_null_fn_idx));
+            }
+            _ => {
+                // (phase, discard, pop buffer, return contents)
+                let (phase, discard, pop, contents) = match name {
+                    "ob_get_clean" => (PHASE_CLEAN | PHASE_FINAL, true, true, true),
+                    "ob_get_flush" => (PHASE_FINAL, false, true, true),
+                    "ob_end_clean" => (PHASE_CLEAN | PHASE_FINAL, true, true, false),
+                    "ob_end_flush" => (PHASE_FINAL, false, true, false),
+                    "ob_clean" => (PHASE_CLEAN, true, false, false),
+                    "ob_flush" => (PHASE_FLUSH, false, false, false),
+                    _ => return Ok(false),
+                };
+                // Without a buffer there is nothing to do and the result is false
+                self.emit(Instruction::GlobalGet(output.level));
+                self.emit(Instruction::I32Eqz);
+                self.emit(Instruction::If(BlockType::Result(self.get_php_value_type())));
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::Call(self.create_bool_fn_idx));
+                self.emit(Instruction::Else);
+                self.emit(Instruction::I32Const(phase));
+                self.emit(Instruction::I32Const(discard as i32));
+                self.emit(Instruction::Call(if pop { output.end_fn } else { output.drain_fn }));
+                if !contents {
+                    self.emit(Instruction::Drop);
+                    self.emit(Instruction::I32Const(1));
+                    self.emit(Instruction::Call(self.create_bool_fn_idx));
+                }
+                self.emit(Instruction::End);
+            }
+        }
+        Ok(true)
+    }
+
+    /// ob_start($callback = null, $chunk_size = 0, $flags = PHP_OUTPUT_HANDLER_STDFLAGS)
+    fn compile_ob_start(&mut self, output: OutputState, args: Vec<Expression>) -> Result<(), String> {
+        if args.len() > 3 {
+            return Err(format!("ob_start() expects at most 3 arguments, {} given", args.len()));
+        }
+        let mut args = args.into_iter();
+        let callback_local = self.allocate_local(ValType::I32);
+        match args.next() {
+            None | Some(Expression::Literal(Literal::Null)) => {
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::LocalSet(callback_local));
+            }
+            Some(Expression::Literal(Literal::String(name))) => {
+                let name = name.trim_start_matches('\\').to_string();
+                if !self.functions.contains_key(&name) {
+                    return Err(format!(
+                        "ob_start(): Argument #1 ($callback) must be a valid callback, function \"{}\" not found or invalid function name",
+                        name
+                    ));
+                }
+                let id = self.ob_callback_id(&name);
+                self.emit(Instruction::I32Const(id));
+                self.emit(Instruction::LocalSet(callback_local));
+            }
+            Some(callback) => self.compile_dynamic_ob_callback(callback, callback_local)?,
+        }
+        match args.next() {
+            None | Some(Expression::Literal(Literal::Integer(0))) => {}
+            Some(_) => return Err("ob_start() does not support a chunk size".to_string()),
+        }
+        // The flags only matter for ob_clean()/ob_end_*() restrictions, which are not enforced
+        if let Some(flags) = args.next() {
+            self.compile_expression(flags)?;
+            self.emit(Instruction::Drop);
+        }
+
+        self.emit(Instruction::LocalGet(callback_local));
+        self.emit(Instruction::Call(output.start_fn));
+        self.free_local(callback_local, ValType::I32);
+        Ok(())
+    }
+
+    /// The id `php_ob_callback` dispatches to the user function `name` by
+    fn ob_callback_id(&mut self, name: &str) -> i32 {
+        match self.ob_callbacks.iter().position(|callback| callback == name) {
+            Some(index) => index as i32 + 1,
+            None => {
+                self.ob_callbacks.push(name.to_string());
+                self.ob_callbacks.len() as i32
+            }
+        }
+    }
+
+    /// A callback only known at run time, such as a function name in a
+    /// variable: compared with every user function that can take the two
+    /// callback arguments, as PHP looks the name up when ob_start() runs
+    fn compile_dynamic_ob_callback(&mut self, callback: Expression, callback_local: u32) -> Result<(), String> {
+        let mut candidates: Vec<String> = self.function_params.iter()
+            .filter(|(_, params)| params.iter().filter(|param| param.default.is_none()).count() <= 2)
+            .map(|(name, _)| name.clone())
+            .collect();
+        candidates.sort();
+
+        let value_local = self.allocate_local(self.get_php_value_type());
+        self.compile_expression(callback)?;
+        self.emit(Instruction::LocalSet(value_local));
+
+        self.emit(Instruction::Block(BlockType::Empty));
+        self.emit(Instruction::I32Const(0));
+        self.emit(Instruction::LocalSet(callback_local));
+        self.compile_expression(Expression::Literal(Literal::Null))?;
+        self.emit_identical_to(value_local);
+        self.emit(Instruction::BrIf(0));
+        for name in candidates {
+            let id = self.ob_callback_id(&name);
+            self.emit(Instruction::I32Const(id));
+            self.emit(Instruction::LocalSet(callback_local));
+            self.compile_string_literal(&name)?;
+            self.emit_identical_to(value_local);
+            self.emit(Instruction::BrIf(0));
+        }
+        self.compile_string_literal("ob_start(): Argument #1 ($callback) must be a valid callback or null, function \"")?;
+        self.emit(Instruction::LocalGet(value_local));
+        self.emit(Instruction::Call(self.concat_fn_idx));
+        self.compile_string_literal("\" not found or invalid function name")?;
+        self.emit(Instruction::Call(self.concat_fn_idx));
+        self.emit_uncaught_error("TypeError")?;
+        self.emit(Instruction::End);
+
+        self.free_local(value_local, self.get_php_value_type());
+        Ok(())
+    }
+
+    /// Replace the value on the stack with whether it is identical to `local`, as an i32
+    fn emit_identical_to(&mut self, local: u32) {
+        self.emit(Instruction::LocalGet(local));
+        self.emit(Instruction::Call(self.identical_fn_idx));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        self.emit(Instruction::I32WrapI64);
+    }
+
+    // ===== RUNTIME HELPERS =====
+
+    /// Compile the output helpers and print_value
+    pub(super) fn add_output_functions(&mut self) -> Result<(), String> {
+        let output = self.output_state()?;
+        self.add_buffer_append_function(output)?;
+        self.add_output_write_function(output)?;
+        self.add_output_flush_function(output)?;
+        self.add_output_flush_all_function(output)?;
+        self.add_ob_start_function(output)?;
+        self.add_ob_drain_function(output)?;
+        self.add_ob_end_function(output)?;
+        self.add_ob_contents_function(output)?;
+        self.add_print_value_function(output)
+    }
+
+    /// print_value(value): echo
+    fn add_print_value_function(&mut self, output: OutputState) -> Result<(), String> {
+        let php_value_type = self.get_php_value_type();
+        self.compile_typed_helper_function(self.print_value_fn_idx, vec![php_value_type], vec![], |this| {
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::Call(this.to_string_fn_idx));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_STRING,
+            });
+            this.emit(Instruction::Call(output.write_fn));
+            Ok(())
+        })
+    }
+
+    /// buffer_append(buffer, string): doubles the buffer when it is full
+    fn add_buffer_append_function(&mut self, output: OutputState) -> Result<(), String> {
+        let params = vec![self.buffer_ref_type(output), self.get_string_type()];
+        self.compile_typed_helper_function(output.append_fn, params, vec![], |this| {
+            let needed = this.allocate_local(ValType::I32);
+            let capacity = this.allocate_local(ValType::I32);
+            let grown = this.allocate_local(this.get_string_type());
+            let field = |field_index| Instruction::StructGet { struct_type_index: output.buffer_type, field_index };
+
+            this.emit(Instruction::LocalGet(0));
+            this.emit(field(BUFFER_LENGTH));
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalTee(needed));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(field(BUFFER_BYTES));
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Shl);
+            this.emit(Instruction::LocalTee(capacity));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32ShrU);
+            this.emit(Instruction::I32GtU);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(needed));
+            this.emit(Instruction::LocalGet(capacity));
+            this.emit(Instruction::LocalGet(needed));
+            this.emit(Instruction::LocalGet(capacity));
+            this.emit(Instruction::I32GtU);
+            this.emit(Instruction::Select);
+            this.emit(Instruction::ArrayNewDefault(this.gc_types.php_string));
+            this.emit(Instruction::LocalTee(grown));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(field(BUFFER_BYTES));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(field(BUFFER_LENGTH));
+            this.emit(Instruction::ArrayCopy {
+                array_type_index_dst: this.gc_types.php_string,
+                array_type_index_src: this.gc_types.php_string,
+            });
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::LocalGet(grown));
+            this.emit(Instruction::StructSet { struct_type_index: output.buffer_type, field_index: BUFFER_BYTES });
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::LocalGet(0));
+            this.emit(field(BUFFER_BYTES));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(field(BUFFER_LENGTH));
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::ArrayCopy {
+                array_type_index_dst: this.gc_types.php_string,
+                array_type_index_src: this.gc_types.php_string,
+            });
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::LocalGet(needed));
+            this.emit(Instruction::StructSet { struct_type_index: output.buffer_type, field_index: BUFFER_LENGTH });
+            Ok(())
+        })
+    }
+
+    /// output_write(string): to the innermost ob_start() buffer, the response
+    /// body or the output buffer, which goes to the host once it is full
+    fn add_output_write_function(&mut self, output: OutputState) -> Result<(), String> {
+        let response = self.response;
+        let handler = self.options.http_handler_enabled();
+        self.compile_typed_helper_function(output.write_fn, vec![self.get_string_type()], vec![], |this| {
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::GlobalGet(output.stack));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::ArrayGet(output.stack_type));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::Call(output.append_fn));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+
+            // Output that leaves the buffers sends the headers
+            if let Some(response) = response {
+                this.emit(Instruction::LocalGet(0));
+                this.emit(Instruction::ArrayLen);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::I32Const(1));
+                this.emit(Instruction::GlobalSet(response.headers_sent_global()));
+                this.emit(Instruction::End);
+            }
+
+            this.emit(Instruction::GlobalGet(output.base));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::Call(output.append_fn));
+            // A request handler's output buffer is the response body
+            if handler {
+                return Ok(());
+            }
+            this.emit(Instruction::GlobalGet(output.base));
+            this.emit(Instruction::StructGet { struct_type_index: output.buffer_type, field_index: BUFFER_LENGTH });
+            this.emit(Instruction::I32Const(OUTPUT_CHUNK));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::Call(output.flush_fn));
+            this.emit(Instruction::End);
+            Ok(())
+        })
+    }
+
+    /// output_flush(): hand the output buffer to `env.print`, a chunk at a time
+    fn add_output_flush_function(&mut self, output: OutputState) -> Result<(), String> {
+        self.compile_typed_helper_function(output.flush_fn, vec![], vec![], |this| {
+            let offset = this.allocate_local(ValType::I32);
+            let chunk = this.allocate_local(ValType::I32);
+            let i = this.allocate_local(ValType::I32);
+            let bytes = this.allocate_local(this.get_string_type());
+            let length = this.allocate_local(ValType::I32);
+
+            this.emit(Instruction::GlobalGet(output.base));
+            this.emit(Instruction::RefIsNull);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+            this.emit_buffer_bytes(|this| this.emit(Instruction::GlobalGet(output.base)))?;
+            this.emit(Instruction::LocalSet(length));
+            this.emit(Instruction::LocalSet(bytes));
+
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(offset));
+            this.emit(Instruction::LocalGet(length));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            // chunk = min(length - offset, OUTPUT_CHUNK)
+            this.emit(Instruction::LocalGet(length));
+            this.emit(Instruction::LocalGet(offset));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::LocalTee(chunk));
+            this.emit(Instruction::I32Const(OUTPUT_CHUNK));
+            this.emit(Instruction::LocalGet(chunk));
+            this.emit(Instruction::I32Const(OUTPUT_CHUNK));
+            this.emit(Instruction::I32LtU);
+            this.emit(Instruction::Select);
+            this.emit(Instruction::LocalSet(chunk));
+
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::LocalSet(i));
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::LocalGet(chunk));
+            this.emit(Instruction::I32GeU);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::LocalGet(bytes));
+            this.emit(Instruction::LocalGet(offset));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::ArrayGetU(this.gc_types.php_string));
+            this.emit(Instruction::I32Store8(MemArg { offset: OUTPUT_BUFFER as u64, align: 0, memory_index: 0 }));
+            this.emit(Instruction::LocalGet(i));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(i));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::LocalGet(chunk));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::I32Store8(MemArg { offset: OUTPUT_BUFFER as u64, align: 0, memory_index: 0 }));
+            this.emit(Instruction::I32Const(OUTPUT_BUFFER));
+            this.emit(Instruction::Call(this.print_fn_idx));
+            this.emit(Instruction::LocalGet(offset));
+            this.emit(Instruction::LocalGet(chunk));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::LocalSet(offset));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::GlobalGet(output.base));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::StructSet { struct_type_index: output.buffer_type, field_index: BUFFER_LENGTH });
+            Ok(())
+        })
+    }
+
+    /// output_flush_all(): for fatal errors, everything buffered as it is,
+    /// without running callbacks, then to the host
+    fn add_output_flush_all_function(&mut self, output: OutputState) -> Result<(), String> {
+        let handler = self.options.http_handler_enabled();
+        self.compile_typed_helper_function(output.flush_all_fn, vec![], vec![], |this| {
+            let bytes = this.allocate_local(this.get_string_type());
+            let length = this.allocate_local(ValType::I32);
+            let copy = this.allocate_local(this.get_string_type());
+            let buffer = this.allocate_local(this.buffer_ref_type(output));
+
+            this.emit(Instruction::Block(BlockType::Empty));
+            this.emit(Instruction::Loop(BlockType::Empty));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::I32Eqz);
+            this.emit(Instruction::BrIf(1));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::GlobalSet(output.level));
+            this.emit(Instruction::GlobalGet(output.stack));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::ArrayGet(output.stack_type));
+            this.emit(Instruction::LocalSet(buffer));
+            this.emit_buffer_bytes(|this| this.emit(Instruction::LocalGet(buffer)))?;
+            this.emit(Instruction::LocalSet(length));
+            this.emit(Instruction::LocalSet(bytes));
+            this.emit_string_prefix(bytes, length, copy);
+            this.emit(Instruction::LocalGet(copy));
+            this.emit(Instruction::Call(output.write_fn));
+            this.emit(Instruction::Br(0));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+            if !handler {
+                this.emit(Instruction::Call(output.flush_fn));
+            }
+            Ok(())
+        })
+    }
+
+    /// Copy the first `length` bytes of a string ref into a new one
+    fn emit_string_prefix(&mut self, bytes: u32, length: u32, into: u32) {
+        self.emit(Instruction::LocalGet(length));
+        self.emit(Instruction::ArrayNewDefault(self.gc_types.php_string));
+        self.emit(Instruction::LocalTee(into));
+        self.emit(Instruction::I32Const(0));
+        self.emit(Instruction::LocalGet(bytes));
+        self.emit(Instruction::I32Const(0));
+        self.emit(Instruction::LocalGet(length));
+        self.emit(Instruction::ArrayCopy {
+            array_type_index_dst: self.gc_types.php_string,
+            array_type_index_src: self.gc_types.php_string,
+        });
+    }
+
+    /// ob_start(callback) -> true: push a buffer, growing the stack when full
+    fn add_ob_start_function(&mut self, output: OutputState) -> Result<(), String> {
+        let php_value_type = self.get_php_value_type();
+        self.compile_typed_helper_function(output.start_fn, vec![ValType::I32], vec![php_value_type], |this| {
+            let grown = this.allocate_local(ValType::Ref(RefType {
+                nullable: true,
+                heap_type: HeapType::Concrete(output.stack_type),
+            }));
+
+            this.emit(Instruction::GlobalGet(output.stack));
+            this.emit(Instruction::RefIsNull);
+            this.emit(Instruction::If(BlockType::Result(ValType::I32)));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::Else);
+            this.emit(Instruction::GlobalGet(output.stack));
+            this.emit(Instruction::ArrayLen);
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::End);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Shl);
+            this.emit(Instruction::I32Const(4));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::ArrayNewDefault(output.stack_type));
+            this.emit(Instruction::LocalSet(grown));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(grown));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::GlobalGet(output.stack));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::ArrayCopy {
+                array_type_index_dst: output.stack_type,
+                array_type_index_src: output.stack_type,
+            });
+            this.emit(Instruction::End);
+            this.emit(Instruction::LocalGet(grown));
+            this.emit(Instruction::GlobalSet(output.stack));
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::GlobalGet(output.stack));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit_new_buffer(256, Some(0))?;
+            this.emit(Instruction::ArraySet(output.stack_type));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::GlobalSet(output.level));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::Call(this.create_bool_fn_idx));
+            Ok(())
+        })
+    }
+
+    /// ob_drain(phase, discard) -> contents: empty the innermost buffer, passing
+    /// its contents through the callback and, unless discarded, on to the
+    /// next buffer out. A callback returning false passes them on unchanged.
+    fn add_ob_drain_function(&mut self, output: OutputState) -> Result<(), String> {
+        let php_value_type = self.get_php_value_type();
+        let params = vec![ValType::I32, ValType::I32];
+        self.compile_typed_helper_function(output.drain_fn, params, vec![php_value_type], |this| {
+            let buffer = this.allocate_local(this.buffer_ref_type(output));
+            let bytes = this.allocate_local(this.get_string_type());
+            let length = this.allocate_local(ValType::I32);
+            let copy = this.allocate_local(this.get_string_type());
+            let contents = this.allocate_local(php_value_type);
+            let result = this.allocate_local(php_value_type);
+
+            this.emit(Instruction::GlobalGet(output.stack));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::ArrayGet(output.stack_type));
+            this.emit(Instruction::LocalSet(buffer));
+            this.emit_buffer_bytes(|this| this.emit(Instruction::LocalGet(buffer)))?;
+            this.emit(Instruction::LocalSet(length));
+            this.emit(Instruction::LocalSet(bytes));
+            this.emit_string_prefix(bytes, length, copy);
+            this.emit(Instruction::LocalGet(copy));
+            this.emit(Instruction::Call(this.create_string_fn_idx));
+            this.emit(Instruction::LocalTee(contents));
+            this.emit(Instruction::LocalSet(result));
+            this.emit(Instruction::LocalGet(buffer));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::StructSet { struct_type_index: output.buffer_type, field_index: BUFFER_LENGTH });
+
+            this.emit(Instruction::LocalGet(buffer));
+            this.emit(Instruction::StructGet { struct_type_index: output.buffer_type, field_index: BUFFER_CALLBACK });
+            this.emit(Instruction::If(BlockType::Empty));
+            // The first call also gets PHP_OUTPUT_HANDLER_START
+            this.emit(Instruction::LocalGet(buffer));
+            this.emit(Instruction::StructGet { struct_type_index: output.buffer_type, field_index: BUFFER_CALLBACK });
+            this.emit(Instruction::LocalGet(contents));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::I32Const(PHASE_START));
+            this.emit(Instruction::LocalGet(buffer));
+            this.emit(Instruction::StructGet { struct_type_index: output.buffer_type, field_index: BUFFER_STARTED });
+            this.emit(Instruction::Select);
+            this.emit(Instruction::I32Or);
+            this.emit(Instruction::I64ExtendI32U);
+            this.emit(Instruction::Call(this.create_int_fn_idx));
+            this.emit(Instruction::Call(output.callback_fn));
+            this.emit(Instruction::LocalSet(result));
+            this.emit(Instruction::LocalGet(buffer));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::StructSet { struct_type_index: output.buffer_type, field_index: BUFFER_STARTED });
+            // false: pass the contents on unchanged
+            this.emit(Instruction::LocalGet(result));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_TYPE,
+            });
+            this.emit(Instruction::I32Const(TYPE_BOOL as i32));
+            this.emit(Instruction::I32Eq);
+            this.emit(Instruction::LocalGet(result));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            });
+            this.emit(Instruction::I64Eqz);
+            this.emit(Instruction::I32And);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(contents));
+            this.emit(Instruction::LocalSet(result));
+            this.emit(Instruction::End);
+            this.emit(Instruction::End);
+
+            // Written one level out
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::I32Eqz);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::GlobalSet(output.level));
+            this.emit(Instruction::LocalGet(result));
+            this.emit(Instruction::Call(this.to_string_fn_idx));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_STRING,
+            });
+            this.emit(Instruction::Call(output.write_fn));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Add);
+            this.emit(Instruction::GlobalSet(output.level));
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::LocalGet(contents));
+            Ok(())
+        })
+    }
+
+    /// ob_end(phase, discard) -> contents: drain the innermost buffer and remove it
+    fn add_ob_end_function(&mut self, output: OutputState) -> Result<(), String> {
+        let php_value_type = self.get_php_value_type();
+        let params = vec![ValType::I32, ValType::I32];
+        self.compile_typed_helper_function(output.end_fn, params, vec![php_value_type], |this| {
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::Call(output.drain_fn));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::I32Const(1));
+            this.emit(Instruction::I32Sub);
+            this.emit(Instruction::GlobalSet(output.level));
+            this.emit(Instruction::GlobalGet(output.stack));
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::RefNull(HeapType::Concrete(output.buffer_type)));
+            this.emit(Instruction::ArraySet(output.stack_type));
+            Ok(())
+        })
+    }
+
+    /// ob_contents() -> the innermost buffer's contents, or false without one
+    fn add_ob_contents_function(&mut self, output: OutputState) -> Result<(), String> {
+        let php_value_type = self.get_php_value_type();
+        self.compile_typed_helper_function(output.contents_fn, vec![], vec![php_value_type], |this| {
+            let bytes = this.allocate_local(this.get_string_type());
+            let length = this.allocate_local(ValType::I32);
+            let copy = this.allocate_local(this.get_string_type());
+
+            this.emit(Instruction::GlobalGet(output.level));
+            this.emit(Instruction::I32Eqz);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::Call(this.create_bool_fn_idx));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+            this.emit_buffer_bytes(|this| {
+                this.emit(Instruction::GlobalGet(output.stack));
+                this.emit(Instruction::GlobalGet(output.level));
+                this.emit(Instruction::I32Const(1));
+                this.emit(Instruction::I32Sub);
+                this.emit(Instruction::ArrayGet(output.stack_type));
+            })?;
+            this.emit(Instruction::LocalSet(length));
+            this.emit(Instruction::LocalSet(bytes));
+            this.emit_string_prefix(bytes, length, copy);
+            this.emit(Instruction::LocalGet(copy));
+            this.emit(Instruction::Call(this.create_string_fn_idx));
+            Ok(())
+        })
+    }
+
+    /// ob_callback(id, buffer, phase) -> the result of the id's callback.
+    /// Compiled last, when every ob_start() call has registered its callback.
+    pub(super) fn add_ob_callback_function(&mut self) -> Result<(), String> {
+        let output = self.output_state()?;
+        let php_value_type = self.get_php_value_type();
+        let callbacks = self.ob_callbacks.clone();
+        let params = vec![ValType::I32, php_value_type, php_value_type];
+
+        // The callbacks are called like PHP code, with the arguments bound to variables
+        let saved_variables = std::mem::take(&mut self.variables);
+        for (name, local_idx) in [("#ob_buffer", 1), ("#ob_phase", 2)] {
+            self.variables.insert(name.to_string(), VariableInfo {
+                local_idx,
+                storage_type: VariableStorage::Boxed,
+                class_type: None,
+            });
+        }
+        let result = self.compile_typed_helper_function(output.callback_fn, params, vec![php_value_type], |this| {
+            for (index, name) in callbacks.into_iter().enumerate() {
+                this.emit(Instruction::LocalGet(0));
+                this.emit(Instruction::I32Const(index as i32 + 1));
+                this.emit(Instruction::I32Eq);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.compile_expression(Expression::FunctionCall {
+                    name,
+                    args: vec![
+                        Expression::Variable("#ob_buffer".to_string()),
+                        Expression::Variable("#ob_phase".to_string()),
+                    ],
+                })?;
+                this.emit(Instruction::Return);
+                this.emit(Instruction::End);
+            }
+            this.emit(Instruction::I32Const(0));
+            this.emit(Instruction::Call(this.create_bool_fn_idx));
+            Ok(())
+        });
+        self.variables = saved_variables;
+        result
+    }
+}
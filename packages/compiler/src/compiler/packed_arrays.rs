@@ -0,0 +1,1144 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Packed arrays with typed element storage
+//
+// A packed array normally keeps a boxed PhpValue per slot (`php_array`). When
+// type inference proves that an array literal holds only ints, only floats or
+// only strings, its storage is a `php_int_array` (i64), `php_float_array`
+// (f64) or `php_string_array` (string refs) instead; an empty `[]` gets one
+// when every value the program stores into arrays has that type. Like any
+// packed array, the PhpValue keeps the logical length in its int field and
+// the storage in its array field, and storage grows by doubling.
+//
+// Writing a value of another type, a key past the end, a negative key or a
+// string key deoptimizes the array: `array_deoptimize` boxes every element
+// into a `php_array` and installs it in the same PhpValue, and the generic
+// code continues from there (a key past the end or a negative key then
+// turns it into a hash table). Readers that do not know the typed storage
+// (`array_get`, `foreach`, `array_values`, ...) box single elements through
+// `array_element`. Reads and writes of int and float elements whose types
+// are known compile to `array_get_int`/`array_set_int` and the float
+// variants, which touch the storage without boxing. `array_sum()` and
+// `sort()` work on the typed storage directly.
+
+use super::core::*;
+use super::type_inference::InferredType;
+use super::array_copies::Store;
+use edge_php_parser::ast::*;
+use wasm_encoder::*;
+
+/// Element type of a typed packed array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum PackedKind {
+    Int,
+    Float,
+    String,
+}
+
+impl PackedKind {
+    pub(super) const ALL: [PackedKind; 3] = [PackedKind::Int, PackedKind::Float, PackedKind::String];
+
+    /// The kind storing elements of an inferred type
+    pub(super) fn of_element(element: &InferredType) -> Option<PackedKind> {
+        match element {
+            InferredType::Int => Some(PackedKind::Int),
+            InferredType::Float => Some(PackedKind::Float),
+            InferredType::String => Some(PackedKind::String),
+            _ => None,
+        }
+    }
+
+    fn inferred_type(self) -> InferredType {
+        match self {
+            PackedKind::Int => InferredType::Int,
+            PackedKind::Float => InferredType::Float,
+            PackedKind::String => InferredType::String,
+        }
+    }
+
+    /// Type tag of the boxed elements
+    pub(super) fn tag(self) -> u32 {
+        match self {
+            PackedKind::Int => TYPE_INT,
+            PackedKind::Float => TYPE_FLOAT,
+            PackedKind::String => TYPE_STRING,
+        }
+    }
+
+    /// PhpValue field holding the unboxed element
+    pub(super) fn field(self) -> u32 {
+        match self {
+            PackedKind::Int => PHPVALUE_INT,
+            PackedKind::Float => PHPVALUE_FLOAT,
+            PackedKind::String => PHPVALUE_STRING,
+        }
+    }
+}
+
+impl Compiler {
+    pub(super) fn add_packed_array_functions(&mut self) {
+        self.add_array_deoptimize_function();
+        self.add_array_element_function();
+        self.add_array_get_unboxed_function(PackedKind::Int);
+        self.add_array_get_unboxed_function(PackedKind::Float);
+        self.add_array_set_unboxed_function(PackedKind::Int);
+        self.add_array_set_unboxed_function(PackedKind::Float);
+        self.add_array_sum_function();
+        self.add_array_sort_function();
+        self.add_sort_less_function();
+        self.add_string_compare_function();
+    }
+
+    pub(super) fn packed_array_type(&self, kind: PackedKind) -> u32 {
+        match kind {
+            PackedKind::Int => self.gc_types.php_int_array,
+            PackedKind::Float => self.gc_types.php_float_array,
+            PackedKind::String => self.gc_types.php_string_array,
+        }
+    }
+
+    fn packed_storage_type(&self, kind: PackedKind) -> ValType {
+        self.array_ref_type(self.packed_array_type(kind))
+    }
+
+    fn packed_element_type(&self, kind: PackedKind) -> ValType {
+        match kind {
+            PackedKind::Int => ValType::I64,
+            PackedKind::Float => ValType::F64,
+            PackedKind::String => self.array_ref_type(self.gc_types.php_string),
+        }
+    }
+
+    fn array_ref_type(&self, array_type: u32) -> ValType {
+        ValType::Ref(RefType {
+            nullable: true,
+            heap_type: HeapType::Concrete(array_type),
+        })
+    }
+
+    fn any_ref_type(&self) -> ValType {
+        ValType::Ref(RefType {
+            nullable: true,
+            heap_type: HeapType::Abstract {
+                shared: false,
+                ty: AbstractHeapType::Any,
+            },
+        })
+    }
+
+    /// Box the unboxed element on the stack
+    fn emit_box_element(&self, body: &mut Vec<Instruction<'static>>, kind: PackedKind) {
+        body.push(Instruction::Call(match kind {
+            PackedKind::Int => self.create_int_fn_idx,
+            PackedKind::Float => self.create_float_fn_idx,
+            PackedKind::String => self.create_string_fn_idx,
+        }));
+    }
+
+    fn emit_length(&self, body: &mut Vec<Instruction<'static>>, array_local: u32) {
+        body.push(Instruction::LocalGet(array_local));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT, // logical length of packed arrays
+        });
+    }
+
+    /// Make room for slot `index` (an i32) in the storage held in
+    /// `storage_local`: when it is full, storage of at least twice the size
+    /// replaces it in the PhpValue and in the local
+    pub(super) fn emit_ensure_capacity(&self, body: &mut Vec<Instruction<'static>>, array_type: u32, array_local: u32, storage_local: u32, index: &[Instruction<'static>], scratch_local: u32) {
+        body.extend_from_slice(index);
+        body.push(Instruction::LocalGet(storage_local));
+        body.push(Instruction::ArrayLen);
+        body.push(Instruction::I32GeU);
+        body.push(Instruction::If(BlockType::Empty));
+
+        // max(2 * capacity, 16, index + 1)
+        body.push(Instruction::LocalGet(storage_local));
+        body.push(Instruction::ArrayLen);
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Shl);
+        body.push(Instruction::LocalTee(scratch_local));
+        body.push(Instruction::I32Const(16));
+        body.push(Instruction::LocalGet(scratch_local));
+        body.push(Instruction::I32Const(16));
+        body.push(Instruction::I32GtU);
+        body.push(Instruction::Select);
+        body.push(Instruction::LocalSet(scratch_local));
+        body.extend_from_slice(index);
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalGet(scratch_local));
+        body.extend_from_slice(index);
+        body.push(Instruction::LocalGet(scratch_local));
+        body.push(Instruction::I32GeU);
+        body.push(Instruction::Select);
+        body.push(Instruction::LocalSet(scratch_local));
+
+        // New storage into the PhpValue, then the old slots into it
+        body.push(Instruction::LocalGet(array_local));
+        body.push(Instruction::LocalGet(scratch_local));
+        body.push(Instruction::ArrayNewDefault(array_type));
+        body.push(Instruction::StructSet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::LocalGet(array_local));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::RefCastNullable(HeapType::Concrete(array_type)));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalGet(storage_local));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalGet(storage_local));
+        body.push(Instruction::ArrayLen);
+        body.push(Instruction::ArrayCopy {
+            array_type_index_dst: array_type,
+            array_type_index_src: array_type,
+        });
+        body.push(Instruction::LocalGet(array_local));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::RefCastNullable(HeapType::Concrete(array_type)));
+        body.push(Instruction::LocalSet(storage_local));
+
+        body.push(Instruction::End);
+    }
+
+    /// Store `element` (unboxed) at i64 `index_local` and return the array when
+    /// the storage in `storage_local` is typed as `kind` and the index is at
+    /// most the length; otherwise fall through
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn emit_packed_store(&self, body: &mut Vec<Instruction<'static>>, kind: PackedKind, array_local: u32, storage_local: u32, index_local: u32, typed_local: u32, scratch_local: u32, element: &[Instruction<'static>]) {
+        let array_type = self.packed_array_type(kind);
+        body.push(Instruction::LocalGet(storage_local));
+        body.push(Instruction::RefTestNullable(HeapType::Concrete(array_type)));
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(storage_local));
+        body.push(Instruction::RefCastNullable(HeapType::Concrete(array_type)));
+        body.push(Instruction::LocalSet(typed_local));
+
+        // Overwrite or append; a gap would leave a hole
+        body.push(Instruction::LocalGet(index_local));
+        self.emit_length(body, array_local);
+        body.push(Instruction::I64LeU);
+        body.push(Instruction::If(BlockType::Empty));
+        self.emit_ensure_capacity(body, array_type, array_local, typed_local, &[Instruction::LocalGet(index_local), Instruction::I32WrapI64], scratch_local);
+        body.push(Instruction::LocalGet(typed_local));
+        body.push(Instruction::LocalGet(index_local));
+        body.push(Instruction::I32WrapI64);
+        body.extend_from_slice(element);
+        body.push(Instruction::ArraySet(array_type));
+
+        body.push(Instruction::LocalGet(index_local));
+        self.emit_length(body, array_local);
+        body.push(Instruction::I64Eq);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(array_local));
+        body.push(Instruction::LocalGet(index_local));
+        body.push(Instruction::I64Const(1));
+        body.push(Instruction::I64Add);
+        body.push(Instruction::StructSet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        body.push(Instruction::End);
+
+        body.push(Instruction::LocalGet(array_local));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+        body.push(Instruction::End);
+    }
+
+    /// Return a copy of the array in `array_local` when its storage (in
+    /// `storage_local`) is typed as `kind`; the copy holds only the logical length
+    pub(super) fn emit_packed_copy(&self, body: &mut Vec<Instruction<'static>>, kind: PackedKind, array_local: u32, storage_local: u32, typed_local: u32) {
+        let array_type = self.packed_array_type(kind);
+        body.push(Instruction::LocalGet(storage_local));
+        body.push(Instruction::RefTestNullable(HeapType::Concrete(array_type)));
+        body.push(Instruction::If(BlockType::Empty));
+        self.emit_length(body, array_local);
+        body.push(Instruction::I32WrapI64);
+        body.push(Instruction::ArrayNewDefault(array_type));
+        body.push(Instruction::LocalSet(typed_local));
+        body.push(Instruction::LocalGet(typed_local));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalGet(storage_local));
+        body.push(Instruction::RefCastNullable(HeapType::Concrete(array_type)));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalGet(typed_local));
+        body.push(Instruction::ArrayLen);
+        body.push(Instruction::ArrayCopy {
+            array_type_index_dst: array_type,
+            array_type_index_src: array_type,
+        });
+        body.push(Instruction::I32Const(TYPE_ARRAY as i32));
+        self.emit_length(body, array_local);
+        body.push(Instruction::F64Const(0.0.into()));
+        body.push(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string)));
+        body.push(Instruction::LocalGet(typed_local));
+        body.push(Instruction::StructNew(self.gc_types.php_value));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+    }
+
+    /// array_deoptimize(array) -> php_array: replace typed storage with boxed
+    /// elements in place and return the boxed storage. Any other storage is
+    /// returned as a php_array.
+    fn add_array_deoptimize_function(&mut self) {
+        let mut locals = vec![
+            (1, self.any_ref_type()),                        // local 1: storage
+            (1, self.array_ref_type(self.gc_types.php_array)), // local 2: boxed storage
+            (1, ValType::I32),                               // local 3: length
+            (1, ValType::I32),                               // local 4: index
+        ];
+        let mut body = vec![];
+
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::LocalSet(1));
+
+        for (position, kind) in PackedKind::ALL.into_iter().enumerate() {
+            let array_type = self.packed_array_type(kind);
+            let typed_local = 5 + position as u32;
+            locals.push((1, self.packed_storage_type(kind)));
+
+            body.push(Instruction::LocalGet(1));
+            body.push(Instruction::RefTestNullable(HeapType::Concrete(array_type)));
+            body.push(Instruction::If(BlockType::Empty));
+            body.push(Instruction::LocalGet(1));
+            body.push(Instruction::RefCastNullable(HeapType::Concrete(array_type)));
+            body.push(Instruction::LocalTee(typed_local));
+
+            // Same capacity, every element boxed
+            body.push(Instruction::ArrayLen);
+            body.push(Instruction::ArrayNewDefault(self.gc_types.php_array));
+            body.push(Instruction::LocalSet(2));
+            self.emit_length(&mut body, 0);
+            body.push(Instruction::I32WrapI64);
+            body.push(Instruction::LocalSet(3));
+            body.push(Instruction::I32Const(0));
+            body.push(Instruction::LocalSet(4));
+            body.push(Instruction::Block(BlockType::Empty));
+            body.push(Instruction::Loop(BlockType::Empty));
+            body.push(Instruction::LocalGet(4));
+            body.push(Instruction::LocalGet(3));
+            body.push(Instruction::I32GeU);
+            body.push(Instruction::BrIf(1));
+            body.push(Instruction::LocalGet(2));
+            body.push(Instruction::LocalGet(4));
+            body.push(Instruction::LocalGet(typed_local));
+            body.push(Instruction::LocalGet(4));
+            body.push(Instruction::ArrayGet(array_type));
+            self.emit_box_element(&mut body, kind);
+            body.push(Instruction::ArraySet(self.gc_types.php_array));
+            body.push(Instruction::LocalGet(4));
+            body.push(Instruction::I32Const(1));
+            body.push(Instruction::I32Add);
+            body.push(Instruction::LocalSet(4));
+            body.push(Instruction::Br(0));
+            body.push(Instruction::End); // End loop
+            body.push(Instruction::End); // End block
+
+            body.push(Instruction::LocalGet(0));
+            body.push(Instruction::LocalGet(2));
+            body.push(Instruction::StructSet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_ARRAY,
+            });
+            body.push(Instruction::LocalGet(2));
+            body.push(Instruction::Return);
+            body.push(Instruction::End);
+        }
+
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::RefCastNullable(HeapType::Concrete(self.gc_types.php_array)));
+
+        let deoptimize_type = self.builder.add_type(vec![self.get_php_value_type()], vec![self.array_ref_type(self.gc_types.php_array)]);
+        self.builder.set_function_at_index(self.array_deoptimize_fn_idx, deoptimize_type, locals, body);
+    }
+
+    /// array_element(array, index) -> value: the element at an i32 index of a
+    /// packed array of any storage, boxed; a null ref for holes, indices past
+    /// the length and other storage
+    fn add_array_element_function(&mut self) {
+        let locals = vec![(1, self.any_ref_type())]; // local 2: storage
+        let mut body = vec![];
+
+        body.push(Instruction::LocalGet(1));
+        self.emit_length(&mut body, 0);
+        body.push(Instruction::I32WrapI64);
+        body.push(Instruction::I32GeU);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_value)));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::LocalSet(2));
+
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::RefTestNullable(HeapType::Concrete(self.gc_types.php_array)));
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::RefCastNonNull(HeapType::Concrete(self.gc_types.php_array)));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::ArrayGet(self.gc_types.php_array));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+
+        for kind in PackedKind::ALL {
+            let array_type = self.packed_array_type(kind);
+            body.push(Instruction::LocalGet(2));
+            body.push(Instruction::RefTestNullable(HeapType::Concrete(array_type)));
+            body.push(Instruction::If(BlockType::Empty));
+            body.push(Instruction::LocalGet(2));
+            body.push(Instruction::RefCastNonNull(HeapType::Concrete(array_type)));
+            body.push(Instruction::LocalGet(1));
+            body.push(Instruction::ArrayGet(array_type));
+            self.emit_box_element(&mut body, kind);
+            body.push(Instruction::Return);
+            body.push(Instruction::End);
+        }
+
+        body.push(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_value)));
+
+        let element_type = self.builder.add_type(vec![self.get_php_value_type(), ValType::I32], vec![self.get_php_value_type()]);
+        self.builder.set_function_at_index(self.array_element_fn_idx, element_type, locals, body);
+    }
+
+    /// array_get_int(array, index) -> i64 and array_get_float(array, index) -> f64:
+    /// read straight from typed storage, or look the key up and convert
+    fn add_array_get_unboxed_function(&mut self, kind: PackedKind) {
+        let array_type = self.packed_array_type(kind);
+        let locals = vec![(1, self.any_ref_type())]; // local 2: storage
+        let mut body = vec![
+            Instruction::LocalGet(0),
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_ARRAY,
+            },
+            Instruction::LocalTee(2),
+            Instruction::RefTestNullable(HeapType::Concrete(array_type)),
+            Instruction::If(BlockType::Empty),
+            Instruction::LocalGet(1),
+        ];
+        self.emit_length(&mut body, 0);
+        body.push(Instruction::I64LtU);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::RefCastNonNull(HeapType::Concrete(array_type)));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::I32WrapI64);
+        body.push(Instruction::ArrayGet(array_type));
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+        body.push(Instruction::End);
+
+        // Boxed, deoptimized or missing: a missing key reads as null
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::Call(self.create_int_fn_idx));
+        body.push(Instruction::Call(self.array_get_quiet_fn_idx));
+        body.push(Instruction::Call(match kind {
+            PackedKind::Float => self.to_float_fn_idx,
+            _ => self.to_int_fn_idx,
+        }));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: kind.field(),
+        });
+
+        let (fn_idx, get_type) = match kind {
+            PackedKind::Float => (self.array_get_float_fn_idx, self.builder.add_type(vec![self.get_php_value_type(), ValType::I64], vec![ValType::F64])),
+            _ => (self.array_get_int_fn_idx, self.builder.add_type(vec![self.get_php_value_type(), ValType::I64], vec![ValType::I64])),
+        };
+        self.builder.set_function_at_index(fn_idx, get_type, locals, body);
+    }
+
+    /// array_set_int(array, index, i64) -> array and array_set_float(array, index, f64) -> array:
+    /// write straight into typed storage, or box and go through array_set
+    fn add_array_set_unboxed_function(&mut self, kind: PackedKind) {
+        let element_type = self.packed_element_type(kind);
+        let locals = vec![
+            (1, self.any_ref_type()),          // local 3: storage
+            (1, self.packed_storage_type(kind)), // local 4: typed storage
+            (1, ValType::I32),                 // local 5: scratch
+        ];
+        let mut body = vec![];
+
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::LocalSet(3));
+        self.emit_packed_store(&mut body, kind, 0, 3, 1, 4, 5, &[Instruction::LocalGet(2)]);
+
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::Call(self.create_int_fn_idx));
+        body.push(Instruction::LocalGet(2));
+        self.emit_box_element(&mut body, kind);
+        body.push(Instruction::Call(self.array_set_fn_idx));
+
+        let fn_idx = match kind {
+            PackedKind::Float => self.array_set_float_fn_idx,
+            _ => self.array_set_int_fn_idx,
+        };
+        let set_type = self.builder.add_type(vec![self.get_php_value_type(), ValType::I64, element_type], vec![self.get_php_value_type()]);
+        self.builder.set_function_at_index(fn_idx, set_type, locals, body);
+    }
+
+    /// PHP array_sum() function: array_sum(array) -> int|float
+    /// Int and float storage is summed unboxed, anything else element by element with `+`
+    fn add_array_sum_function(&mut self) {
+        let locals = vec![
+            (1, self.any_ref_type()),                               // local 1: storage
+            (1, ValType::I32),                                      // local 2: length
+            (1, ValType::I32),                                      // local 3: index
+            (1, ValType::I64),                                      // local 4: int sum
+            (1, ValType::F64),                                      // local 5: float sum
+            (1, self.packed_storage_type(PackedKind::Int)),         // local 6: int storage
+            (1, self.packed_storage_type(PackedKind::Float)),       // local 7: float storage
+            (1, self.get_php_value_type()),                         // local 8: sum
+            (1, self.get_php_value_type()),                         // local 9: packed values
+            (1, self.get_php_value_type()),                         // local 10: element
+        ];
+        // Not an array - nothing to add up
+        let mut body = vec![
+            Instruction::LocalGet(0),
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_TYPE,
+            },
+            Instruction::I32Const(TYPE_ARRAY as i32),
+            Instruction::I32Ne,
+            Instruction::If(BlockType::Empty),
+            Instruction::I64Const(0),
+            Instruction::Call(self.create_int_fn_idx),
+            Instruction::Return,
+            Instruction::End,
+
+            Instruction::LocalGet(0),
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_ARRAY,
+            },
+            Instruction::LocalSet(1),
+        ];
+        self.emit_length(&mut body, 0);
+        body.push(Instruction::I32WrapI64);
+        body.push(Instruction::LocalSet(2));
+
+        for (kind, typed_local, sum_local) in [(PackedKind::Int, 6, 4), (PackedKind::Float, 7, 5)] {
+            let array_type = self.packed_array_type(kind);
+            body.push(Instruction::LocalGet(1));
+            body.push(Instruction::RefTestNullable(HeapType::Concrete(array_type)));
+            body.push(Instruction::If(BlockType::Empty));
+            body.push(Instruction::LocalGet(1));
+            body.push(Instruction::RefCastNullable(HeapType::Concrete(array_type)));
+            body.push(Instruction::LocalSet(typed_local));
+            body.push(Instruction::Block(BlockType::Empty));
+            body.push(Instruction::Loop(BlockType::Empty));
+            body.push(Instruction::LocalGet(3));
+            body.push(Instruction::LocalGet(2));
+            body.push(Instruction::I32GeU);
+            body.push(Instruction::BrIf(1));
+            body.push(Instruction::LocalGet(sum_local));
+            body.push(Instruction::LocalGet(typed_local));
+            body.push(Instruction::LocalGet(3));
+            body.push(Instruction::ArrayGet(array_type));
+            body.push(match kind {
+                PackedKind::Float => Instruction::F64Add,
+                _ => Instruction::I64Add,
+            });
+            body.push(Instruction::LocalSet(sum_local));
+            body.push(Instruction::LocalGet(3));
+            body.push(Instruction::I32Const(1));
+            body.push(Instruction::I32Add);
+            body.push(Instruction::LocalSet(3));
+            body.push(Instruction::Br(0));
+            body.push(Instruction::End); // End loop
+            body.push(Instruction::End); // End block
+            body.push(Instruction::LocalGet(sum_local));
+            self.emit_box_element(&mut body, kind);
+            body.push(Instruction::Return);
+            body.push(Instruction::End);
+        }
+
+        // Other storage: add up the reindexed values like `$sum + $value`
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Call(self.array_values_fn_idx));
+        body.push(Instruction::LocalTee(9));
+        body.push(Instruction::Call(self.count_fn_idx));
+        body.push(Instruction::LocalSet(2));
+        body.push(Instruction::I64Const(0));
+        body.push(Instruction::Call(self.create_int_fn_idx));
+        body.push(Instruction::LocalSet(8));
+        body.push(Instruction::Block(BlockType::Empty));
+        body.push(Instruction::Loop(BlockType::Empty));
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::I32GeU);
+        body.push(Instruction::BrIf(1));
+        body.push(Instruction::LocalGet(9));
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::Call(self.array_element_fn_idx));
+        body.push(Instruction::LocalTee(10));
+        body.push(Instruction::RefIsNull);
+        body.push(Instruction::I32Eqz);
+        body.push(Instruction::If(BlockType::Empty));
+        body.push(Instruction::LocalGet(8));
+        body.push(Instruction::LocalGet(10));
+        body.push(Instruction::Call(self.add_fn_idx));
+        body.push(Instruction::LocalSet(8));
+        body.push(Instruction::End);
+        body.push(Instruction::LocalGet(3));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Add);
+        body.push(Instruction::LocalSet(3));
+        body.push(Instruction::Br(0));
+        body.push(Instruction::End); // End loop
+        body.push(Instruction::End); // End block
+        body.push(Instruction::LocalGet(8));
+
+        self.builder.set_function_at_index(self.array_sum_fn_idx, self.value_to_value_type_idx, locals, body);
+    }
+
+    /// PHP sort() function: sort(array) -> bool
+    /// Sorts in place with a heapsort and reindexes from 0. Int and float
+    /// storage compares unboxed; strings and boxed values order as PHP
+    /// compares them (`sort_less`).
+    fn add_array_sort_function(&mut self) {
+        // Locals 1-5 are shared by every storage, each storage then has its
+        // own array and element locals
+        let mut locals = vec![
+            (1, self.any_ref_type()), // local 1: storage
+            (1, ValType::I32),        // local 2: length
+            (1, ValType::I32),        // local 3: heapify start / sorted end
+            (1, ValType::I32),        // local 4: sift root
+            (1, ValType::I32),        // local 5: sift child
+        ];
+        let mut body = vec![
+            Instruction::LocalGet(0),
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_TYPE,
+            },
+            Instruction::I32Const(TYPE_ARRAY as i32),
+            Instruction::I32Ne,
+            Instruction::If(BlockType::Empty),
+            Instruction::I32Const(0),
+            Instruction::Call(self.create_bool_fn_idx),
+            Instruction::Return,
+            Instruction::End,
+
+            Instruction::LocalGet(0),
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_ARRAY,
+            },
+            Instruction::LocalSet(1),
+        ];
+        self.emit_length(&mut body, 0);
+        body.push(Instruction::I32WrapI64);
+        body.push(Instruction::LocalSet(2));
+
+        let mut next_local = 6;
+        for kind in PackedKind::ALL {
+            let array_type = self.packed_array_type(kind);
+            let element_type = self.packed_element_type(kind);
+            let (storage, a, b) = (next_local, next_local + 1, next_local + 2);
+            locals.push((1, self.packed_storage_type(kind)));
+            locals.push((2, element_type));
+            next_local += 3;
+
+            body.push(Instruction::LocalGet(1));
+            body.push(Instruction::RefTestNullable(HeapType::Concrete(array_type)));
+            body.push(Instruction::If(BlockType::Empty));
+            body.push(Instruction::LocalGet(1));
+            body.push(Instruction::RefCastNullable(HeapType::Concrete(array_type)));
+            body.push(Instruction::LocalSet(storage));
+            self.emit_heapsort(&mut body, array_type, storage, a, b, &|compiler: &Compiler, body: &mut Vec<Instruction<'static>>| {
+                match kind {
+                    PackedKind::Int => {
+                        body.push(Instruction::LocalGet(a));
+                        body.push(Instruction::LocalGet(b));
+                        body.push(Instruction::I64LtS);
+                    }
+                    PackedKind::Float => {
+                        body.push(Instruction::LocalGet(a));
+                        body.push(Instruction::LocalGet(b));
+                        body.push(Instruction::F64Lt);
+                    }
+                    PackedKind::String => {
+                        // A string that cannot be numeric orders byte-wise
+                        // against any other; only two candidates get boxed
+                        compiler.emit_maybe_numeric_string(body, a);
+                        compiler.emit_maybe_numeric_string(body, b);
+                        body.push(Instruction::I32And);
+                        body.push(Instruction::If(BlockType::Result(ValType::I32)));
+                        body.push(Instruction::LocalGet(a));
+                        body.push(Instruction::Call(compiler.create_string_fn_idx));
+                        body.push(Instruction::LocalGet(b));
+                        body.push(Instruction::Call(compiler.create_string_fn_idx));
+                        body.push(Instruction::Call(compiler.sort_less_fn_idx));
+                        body.push(Instruction::Else);
+                        body.push(Instruction::LocalGet(a));
+                        body.push(Instruction::LocalGet(b));
+                        body.push(Instruction::Call(compiler.string_compare_fn_idx));
+                        body.push(Instruction::I32Const(0));
+                        body.push(Instruction::I32LtS);
+                        body.push(Instruction::End);
+                    }
+                }
+            });
+            body.push(Instruction::I32Const(1));
+            body.push(Instruction::Call(self.create_bool_fn_idx));
+            body.push(Instruction::Return);
+            body.push(Instruction::End);
+        }
+
+        // Boxed elements or a hash table: take over the reindexed values first
+        let (storage, a, b) = (next_local, next_local + 1, next_local + 2);
+        locals.push((1, self.array_ref_type(self.gc_types.php_array)));
+        locals.push((2, self.get_php_value_type()));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Call(self.array_values_fn_idx));
+        body.push(Instruction::LocalTee(a));
+        body.push(Instruction::Call(self.array_deoptimize_fn_idx));
+        body.push(Instruction::LocalSet(storage));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::LocalGet(storage));
+        body.push(Instruction::StructSet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_ARRAY,
+        });
+        body.push(Instruction::LocalGet(0));
+        self.emit_length(&mut body, a);
+        body.push(Instruction::StructSet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        self.emit_length(&mut body, a);
+        body.push(Instruction::I32WrapI64);
+        body.push(Instruction::LocalSet(2));
+        self.emit_heapsort(&mut body, self.gc_types.php_array, storage, a, b, &|compiler: &Compiler, body: &mut Vec<Instruction<'static>>| {
+            body.push(Instruction::LocalGet(a));
+            body.push(Instruction::LocalGet(b));
+            body.push(Instruction::Call(compiler.sort_less_fn_idx));
+        });
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::Call(self.create_bool_fn_idx));
+
+        self.builder.set_function_at_index(self.array_sort_fn_idx, self.value_to_value_type_idx, locals, body);
+    }
+
+    /// Heapsort the first `length` (local 2) elements of the storage in
+    /// `storage`, using locals 3-5 of `add_array_sort_function`. `less` pushes
+    /// whether the element in local `a` orders before the one in local `b`.
+    fn emit_heapsort(&self, body: &mut Vec<Instruction<'static>>, array_type: u32, storage: u32, a: u32, b: u32, less: &dyn Fn(&Compiler, &mut Vec<Instruction<'static>>)) {
+        let (length, cursor, root, child) = (2, 3, 4, 5);
+
+        // Move the element at `root` down below its larger children, within
+        // the first `limit` elements
+        let sift_down = |body: &mut Vec<Instruction<'static>>, limit: u32| {
+            body.push(Instruction::Block(BlockType::Empty));
+            body.push(Instruction::Loop(BlockType::Empty));
+            body.push(Instruction::LocalGet(root));
+            body.push(Instruction::I32Const(1));
+            body.push(Instruction::I32Shl);
+            body.push(Instruction::I32Const(1));
+            body.push(Instruction::I32Add);
+            body.push(Instruction::LocalTee(child));
+            body.push(Instruction::LocalGet(limit));
+            body.push(Instruction::I32GeU);
+            body.push(Instruction::BrIf(1));
+
+            // The right child when it is larger
+            body.push(Instruction::LocalGet(child));
+            body.push(Instruction::I32Const(1));
+            body.push(Instruction::I32Add);
+            body.push(Instruction::LocalGet(limit));
+            body.push(Instruction::I32LtU);
+            body.push(Instruction::If(BlockType::Empty));
+            body.push(Instruction::LocalGet(storage));
+            body.push(Instruction::LocalGet(child));
+            body.push(Instruction::ArrayGet(array_type));
+            body.push(Instruction::LocalSet(a));
+            body.push(Instruction::LocalGet(storage));
+            body.push(Instruction::LocalGet(child));
+            body.push(Instruction::I32Const(1));
+            body.push(Instruction::I32Add);
+            body.push(Instruction::ArrayGet(array_type));
+            body.push(Instruction::LocalSet(b));
+            less(self, body);
+            body.push(Instruction::If(BlockType::Empty));
+            body.push(Instruction::LocalGet(child));
+            body.push(Instruction::I32Const(1));
+            body.push(Instruction::I32Add);
+            body.push(Instruction::LocalSet(child));
+            body.push(Instruction::End);
+            body.push(Instruction::End);
+
+            // Done once the root is not smaller than that child
+            body.push(Instruction::LocalGet(storage));
+            body.push(Instruction::LocalGet(root));
+            body.push(Instruction::ArrayGet(array_type));
+            body.push(Instruction::LocalSet(a));
+            body.push(Instruction::LocalGet(storage));
+            body.push(Instruction::LocalGet(child));
+            body.push(Instruction::ArrayGet(array_type));
+            body.push(Instruction::LocalSet(b));
+            less(self, body);
+            body.push(Instruction::I32Eqz);
+            body.push(Instruction::BrIf(1));
+
+            body.push(Instruction::LocalGet(storage));
+            body.push(Instruction::LocalGet(root));
+            body.push(Instruction::LocalGet(b));
+            body.push(Instruction::ArraySet(array_type));
+            body.push(Instruction::LocalGet(storage));
+            body.push(Instruction::LocalGet(child));
+            body.push(Instruction::LocalGet(a));
+            body.push(Instruction::ArraySet(array_type));
+            body.push(Instruction::LocalGet(child));
+            body.push(Instruction::LocalSet(root));
+            body.push(Instruction::Br(0));
+            body.push(Instruction::End); // End loop
+            body.push(Instruction::End); // End block
+        };
+
+        // Build the heap: sift down every parent, the last one first
+        body.push(Instruction::LocalGet(length));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32ShrU);
+        body.push(Instruction::LocalSet(cursor));
+        body.push(Instruction::Block(BlockType::Empty));
+        body.push(Instruction::Loop(BlockType::Empty));
+        body.push(Instruction::LocalGet(cursor));
+        body.push(Instruction::I32Eqz);
+        body.push(Instruction::BrIf(1));
+        body.push(Instruction::LocalGet(cursor));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Sub);
+        body.push(Instruction::LocalTee(cursor));
+        body.push(Instruction::LocalSet(root));
+        sift_down(body, length);
+        body.push(Instruction::Br(0));
+        body.push(Instruction::End); // End loop
+        body.push(Instruction::End); // End block
+
+        // Swap the largest element behind the heap until it is empty
+        body.push(Instruction::LocalGet(length));
+        body.push(Instruction::LocalSet(cursor));
+        body.push(Instruction::Block(BlockType::Empty));
+        body.push(Instruction::Loop(BlockType::Empty));
+        body.push(Instruction::LocalGet(cursor));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32LeU);
+        body.push(Instruction::BrIf(1));
+        body.push(Instruction::LocalGet(cursor));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::I32Sub);
+        body.push(Instruction::LocalSet(cursor));
+        body.push(Instruction::LocalGet(storage));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::ArrayGet(array_type));
+        body.push(Instruction::LocalSet(a));
+        body.push(Instruction::LocalGet(storage));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalGet(storage));
+        body.push(Instruction::LocalGet(cursor));
+        body.push(Instruction::ArrayGet(array_type));
+        body.push(Instruction::ArraySet(array_type));
+        body.push(Instruction::LocalGet(storage));
+        body.push(Instruction::LocalGet(cursor));
+        body.push(Instruction::LocalGet(a));
+        body.push(Instruction::ArraySet(array_type));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::LocalSet(root));
+        sift_down(body, cursor);
+        body.push(Instruction::Br(0));
+        body.push(Instruction::End); // End loop
+        body.push(Instruction::End); // End block
+    }
+
+    /// Whether the string in `string_local` may be numeric: it is empty or
+    /// starts with whitespace, a sign, a dot or a digit (anything up to '9')
+    fn emit_maybe_numeric_string(&self, body: &mut Vec<Instruction<'static>>, string_local: u32) {
+        body.push(Instruction::LocalGet(string_local));
+        body.push(Instruction::ArrayLen);
+        body.push(Instruction::I32Eqz);
+        body.push(Instruction::If(BlockType::Result(ValType::I32)));
+        body.push(Instruction::I32Const(1));
+        body.push(Instruction::Else);
+        body.push(Instruction::LocalGet(string_local));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::ArrayGetU(self.gc_types.php_string));
+        body.push(Instruction::I32Const(b'9' as i32));
+        body.push(Instruction::I32LeU);
+        body.push(Instruction::End);
+    }
+
+    /// sort_less(a, b) -> i32: whether sort() puts a before b. Two numeric
+    /// strings compare as numbers and other strings byte-wise; everything
+    /// else as `<` does.
+    fn add_sort_less_function(&mut self) {
+        let locals = vec![(2, self.get_php_value_type())]; // locals 2-3: numeric forms of two strings
+        let mut body = vec![
+            Instruction::LocalGet(0),
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_TYPE,
+            },
+            Instruction::I32Const(TYPE_STRING as i32),
+            Instruction::I32Eq,
+            Instruction::LocalGet(1),
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_TYPE,
+            },
+            Instruction::I32Const(TYPE_STRING as i32),
+            Instruction::I32Eq,
+            Instruction::I32And,
+            Instruction::If(BlockType::Empty),
+            Instruction::LocalGet(0),
+            Instruction::Call(self.string_to_float_if_numeric_fn_idx),
+            Instruction::LocalSet(2),
+            Instruction::LocalGet(1),
+            Instruction::Call(self.string_to_float_if_numeric_fn_idx),
+            Instruction::LocalSet(3),
+        ];
+        for local in [2, 3] {
+            body.push(Instruction::LocalGet(local));
+            body.push(Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_TYPE,
+            });
+            body.push(Instruction::I32Const(TYPE_FLOAT as i32));
+            body.push(Instruction::I32Eq);
+        }
+        body.push(Instruction::I32And);
+        body.push(Instruction::If(BlockType::Empty));
+        for local in [2, 3] {
+            body.push(Instruction::LocalGet(local));
+            body.push(Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_FLOAT,
+            });
+        }
+        body.push(Instruction::F64Lt);
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+        for local in [0, 1] {
+            body.push(Instruction::LocalGet(local));
+            body.push(Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_STRING,
+            });
+        }
+        body.push(Instruction::Call(self.string_compare_fn_idx));
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::I32LtS);
+        body.push(Instruction::Return);
+        body.push(Instruction::End);
+
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::Call(self.less_than_fn_idx));
+        body.push(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        body.push(Instruction::I32WrapI64);
+
+        let less_type = self.builder.add_type(vec![self.get_php_value_type(), self.get_php_value_type()], vec![ValType::I32]);
+        self.builder.set_function_at_index(self.sort_less_fn_idx, less_type, locals, body);
+    }
+
+    /// string_compare(a, b) -> i32: -1, 0 or 1 comparing the bytes of two
+    /// strings; a prefix orders before the longer string
+    fn add_string_compare_function(&mut self) {
+        let locals = vec![
+            (1, ValType::I32), // local 2: length of a
+            (1, ValType::I32), // local 3: length of b
+            (1, ValType::I32), // local 4: index
+            (1, ValType::I32), // local 5: byte difference
+        ];
+        let body = vec![
+            Instruction::LocalGet(0),
+            Instruction::ArrayLen,
+            Instruction::LocalSet(2),
+            Instruction::LocalGet(1),
+            Instruction::ArrayLen,
+            Instruction::LocalSet(3),
+
+            Instruction::Block(BlockType::Empty),
+            Instruction::Loop(BlockType::Empty),
+            Instruction::LocalGet(4),
+            Instruction::LocalGet(2),
+            Instruction::I32GeU,
+            Instruction::LocalGet(4),
+            Instruction::LocalGet(3),
+            Instruction::I32GeU,
+            Instruction::I32Or,
+            Instruction::BrIf(1),
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(4),
+            Instruction::ArrayGetU(self.gc_types.php_string),
+            Instruction::LocalGet(1),
+            Instruction::LocalGet(4),
+            Instruction::ArrayGetU(self.gc_types.php_string),
+            Instruction::I32Sub,
+            Instruction::LocalTee(5),
+            Instruction::If(BlockType::Empty),
+            Instruction::LocalGet(5),
+            Instruction::I32Const(0),
+            Instruction::I32GtS,
+            Instruction::LocalGet(5),
+            Instruction::I32Const(0),
+            Instruction::I32LtS,
+            Instruction::I32Sub,
+            Instruction::Return,
+            Instruction::End,
+            Instruction::LocalGet(4),
+            Instruction::I32Const(1),
+            Instruction::I32Add,
+            Instruction::LocalSet(4),
+            Instruction::Br(0),
+            Instruction::End, // End loop
+            Instruction::End, // End block
+
+            Instruction::LocalGet(2),
+            Instruction::LocalGet(3),
+            Instruction::I32GtU,
+            Instruction::LocalGet(2),
+            Instruction::LocalGet(3),
+            Instruction::I32LtU,
+            Instruction::I32Sub,
+        ];
+
+        let string_ref = self.array_ref_type(self.gc_types.php_string);
+        let compare_type = self.builder.add_type(vec![string_ref, string_ref], vec![ValType::I32]);
+        self.builder.set_function_at_index(self.string_compare_fn_idx, compare_type, locals, body);
+    }
+
+    /// The typed storage a simple array literal gets: every element is a
+    /// value of one packed kind, or the literal is empty and every value the
+    /// program stores into arrays has that kind
+    pub(super) fn packed_literal_kind(&mut self, elements: &[ArrayElement]) -> Option<PackedKind> {
+        if !self.options.typed_arrays_enabled() || elements.iter().any(|element| element.key.is_some()) {
+            return None;
+        }
+        if elements.is_empty() {
+            return PackedKind::of_element(&self.type_inference.stored_element_type()?);
+        }
+        let literal_type = self.type_inference.infer_expression(&Expression::Array(elements.to_vec()));
+        let kind = PackedKind::of_element(&literal_type.element_type())?;
+        elements.iter()
+            .all(|element| self.type_inference.infer_expression(&element.value) == kind.inferred_type())
+            .then_some(kind)
+    }
+
+    /// Compile a simple array literal into typed storage of `kind`
+    pub(super) fn compile_packed_array_literal(&mut self, kind: PackedKind, elements: Vec<ArrayElement>) -> Result<(), String> {
+        let array_type = self.packed_array_type(kind);
+        let length = elements.len();
+
+        self.emit(Instruction::I32Const(TYPE_ARRAY as i32));
+        self.emit(Instruction::I64Const(length as i64));
+        self.emit(Instruction::F64Const(0.0.into()));
+        self.emit(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string)));
+        if elements.is_empty() {
+            // Same initial capacity as create_array
+            self.emit(Instruction::I32Const(16));
+            self.emit(Instruction::ArrayNewDefault(array_type));
+        } else {
+            let element_type = kind.inferred_type();
+            for element in elements {
+                match kind {
+                    PackedKind::String => {
+                        self.compile_expression(element.value)?;
+                        self.emit(Instruction::StructGet {
+                            struct_type_index: self.gc_types.php_value,
+                            field_index: PHPVALUE_STRING,
+                        });
+                    }
+                    _ => self.compile_expression_as_unboxed(element.value, &element_type, &element_type)?,
+                }
+            }
+            self.emit(Instruction::ArrayNewFixed {
+                array_type_index: array_type,
+                array_size: length as u32,
+            });
+        }
+        self.emit(Instruction::StructNew(self.gc_types.php_value));
+        Ok(())
+    }
+
+    /// `$array[$index] = value`: leave array_set's result on the stack. An int
+    /// index and an int or float value into an array of that element type
+    /// are written without boxing.
+    pub(super) fn compile_element_store(&mut self, array: &Expression, index: &Expression, right: Expression) -> Result<(), String> {
+        let kind = self.unboxed_element_store(array, index, &right);
+        self.compile_expression(array.clone())?;
+        match kind {
+            Some(kind) => {
+                let element_type = kind.inferred_type();
+                self.compile_expression_as_unboxed(index.clone(), &InferredType::Int, &InferredType::Int)?;
+                self.compile_expression_as_unboxed(right, &element_type, &element_type)?;
+                self.emit(Instruction::Call(match kind {
+                    PackedKind::Float => self.array_set_float_fn_idx,
+                    _ => self.array_set_int_fn_idx,
+                }));
+            }
+            None => {
+                self.compile_expression(index.clone())?;
+                self.compile_stored_value(right, Store::Other)?;
+                self.emit(Instruction::Call(self.array_set_fn_idx));
+            }
+        }
+        Ok(())
+    }
+
+    fn unboxed_element_store(&mut self, array: &Expression, index: &Expression, right: &Expression) -> Option<PackedKind> {
+        if !self.options.typed_arrays_enabled() || self.type_inference.infer_expression(index) != InferredType::Int {
+            return None;
+        }
+        let element_type = self.type_inference.infer_expression(array).element_type();
+        let kind = PackedKind::of_element(&element_type).filter(|kind| *kind != PackedKind::String)?;
+        (self.type_inference.infer_expression(right) == element_type).then_some(kind)
+    }
+
+    /// Read `$array[$index]` unboxed when the array's elements are known to be
+    /// ints or floats (`kind`) and the index is an int
+    pub(super) fn compile_unboxed_element_read(&mut self, array: &Expression, index: &Expression, kind: PackedKind) -> Result<bool, String> {
+        if !self.options.typed_arrays_enabled()
+            || self.type_inference.infer_expression(index) != InferredType::Int
+            || self.type_inference.infer_expression(array).element_type() != kind.inferred_type()
+        {
+            return Ok(false);
+        }
+        self.compile_expression(array.clone())?;
+        self.compile_expression_as_unboxed(index.clone(), &InferredType::Int, &InferredType::Int)?;
+        self.emit(Instruction::Call(match kind {
+            PackedKind::Float => self.array_get_float_fn_idx,
+            _ => self.array_get_int_fn_idx,
+        }));
+        Ok(true)
+    }
+}
@@ -0,0 +1,536 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Multi-file projects: include/require and PSR-4 autoloading
+//
+// A project is compiled from an entry file. Every `include`/`require` whose
+// path is known at compile time (string literals, `__DIR__`, `__FILE__`,
+// `dirname()` and concatenations of those) is loaded, and every class that is
+// referenced but not declared is looked up in the PSR-4 map. Each file's
+// names are then resolved on their own, and the included files are spliced
+// into the entry file to form a single program, which compiles to a single
+// module.
+//
+// Splicing follows PHP's execution order where it can be decided statically:
+// an include at the top level of a file is replaced by the included file's
+// statements, `*_once` includes are dropped after the first one, and
+// `$config = require 'config.php';` takes the value the file returns. Classes
+// and functions declared by a file included inside a block or function are
+// hoisted, as are autoloaded files and the `files` autoload entries of
+// composer.json, which run before the entry file.
+//
+// Relative include paths starting with `./` or `../` are relative to the
+// including file; other relative paths are looked up in the include paths and
+// then next to the including file. `declare(strict_types=1)` is taken from the
+// entry file.
+
+use super::core::*;
+use super::debug_info::CompiledModule;
+use super::namespaces::NameResolver;
+use crate::error::CompilerError;
+use edge_php_parser::ast::*;
+use edge_php_parser::{parse, parse_with_locations};
+use edge_php_parser::visit::{self, Visitor};
+use edge_php_parser::visit_mut::{self, VisitorMut};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Includes nested deeper than this are assumed to be recursive
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+/// An entry file together with where its includes and classes are found
+#[derive(Debug, Clone)]
+pub struct Project {
+    entry: PathBuf,
+    include_paths: Vec<PathBuf>,
+    autoload: Vec<(String, PathBuf)>, // PSR-4 namespace prefix and base directory
+    autoload_files: Vec<PathBuf>,     // Always loaded, before the entry file
+}
+
+impl Project {
+    pub fn new(entry: impl Into<PathBuf>) -> Self {
+        Self {
+            entry: entry.into(),
+            include_paths: Vec::new(),
+            autoload: Vec::new(),
+            autoload_files: Vec::new(),
+        }
+    }
+
+    /// A directory searched for relative include paths
+    pub fn add_include_path(&mut self, path: impl Into<PathBuf>) {
+        self.include_paths.push(path.into());
+    }
+
+    /// Map a namespace prefix such as `App\` to the directory its classes live in
+    pub fn add_psr4(&mut self, prefix: &str, directory: impl Into<PathBuf>) {
+        let mut prefix = prefix.trim_start_matches('\\').to_string();
+        if !prefix.is_empty() && !prefix.ends_with('\\') {
+            prefix.push('\\');
+        }
+        self.autoload.push((prefix, directory.into()));
+    }
+
+    /// Read the `psr-4` and `files` entries of a composer.json `autoload` section.
+    /// Paths are relative to the directory of composer.json.
+    pub fn load_composer_json(&mut self, path: &Path) -> Result<(), CompilerError> {
+        let invalid = |message: String| CompilerError::CompilationError {
+            message: format!("Invalid {}: {}", path.display(), message),
+        };
+        let source = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let composer: serde_json::Value = serde_json::from_str(&source).map_err(|e| invalid(e.to_string()))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+
+        let Some(autoload) = composer.get("autoload") else { return Ok(()) };
+        if let Some(psr4) = autoload.get("psr-4") {
+            let psr4 = psr4.as_object().ok_or_else(|| invalid("autoload.psr-4 must be an object".to_string()))?;
+            for (prefix, directories) in psr4 {
+                let directories = match directories {
+                    serde_json::Value::Array(directories) => directories.iter().collect(),
+                    directory => vec![directory],
+                };
+                for directory in directories {
+                    let directory = directory.as_str()
+                        .ok_or_else(|| invalid(format!("autoload.psr-4 paths for \"{}\" must be strings", prefix)))?;
+                    self.add_psr4(prefix, base.join(directory));
+                }
+            }
+        }
+        if let Some(files) = autoload.get("files") {
+            let files = files.as_array().ok_or_else(|| invalid("autoload.files must be an array".to_string()))?;
+            for file in files {
+                let file = file.as_str().ok_or_else(|| invalid("autoload.files entries must be strings".to_string()))?;
+                self.autoload_files.push(base.join(file));
+            }
+        }
+        Ok(())
+    }
+
+    /// Where a class would live according to the PSR-4 map; the longest
+    /// matching prefix is tried first.
+    fn autoload_path(&self, class: &str) -> Option<PathBuf> {
+        let mut candidates: Vec<_> = self.autoload.iter()
+            .filter(|(prefix, _)| class.starts_with(prefix.as_str()))
+            .collect();
+        candidates.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        candidates.into_iter()
+            .map(|(prefix, directory)| directory.join(format!("{}.php", class[prefix.len()..].replace('\\', "/"))))
+            .find(|path| path.is_file())
+    }
+
+    fn resolve_include(&self, path: &str, from: &Path) -> Option<PathBuf> {
+        let path = Path::new(path);
+        let from_directory = from.parent().unwrap_or(Path::new("."));
+        let candidates: Vec<PathBuf> = if path.is_absolute() {
+            vec![path.to_path_buf()]
+        } else if path.starts_with(".") || path.starts_with("..") {
+            vec![from_directory.join(path)]
+        } else {
+            self.include_paths.iter()
+                .map(|directory| directory.join(path))
+                .chain(std::iter::once(from_directory.join(path)))
+                .collect()
+        };
+        candidates.into_iter().find(|candidate| candidate.is_file())
+    }
+}
+
+/// The path of an include, when it is known at compile time
+fn literal_path(expr: &Expression, file: &Path) -> Option<String> {
+    match expr {
+        Expression::Literal(Literal::String(path)) => Some(path.clone()),
+        Expression::MagicConstant(MagicConstant::Dir) => Some(file.parent()?.display().to_string()),
+        Expression::MagicConstant(MagicConstant::File) => Some(file.display().to_string()),
+        Expression::Binary { op: BinaryOp::Concat, left, right } => {
+            Some(literal_path(left, file)? + &literal_path(right, file)?)
+        }
+        Expression::FunctionCall { name, args } if name.trim_start_matches('\\').eq_ignore_ascii_case("dirname") => {
+            let levels = match args.get(1) {
+                None => 1,
+                Some(Expression::Literal(Literal::Integer(levels))) if *levels >= 1 => *levels as usize,
+                Some(_) => return None,
+            };
+            let path = PathBuf::from(literal_path(args.first()?, file)?);
+            Some(path.ancestors().nth(levels).unwrap_or(Path::new("")).display().to_string())
+        }
+        _ => None,
+    }
+}
+
+fn is_declaration(stmt: &Statement) -> bool {
+    matches!(stmt, Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. })
+}
+
+/// Collects the includes of a file
+#[derive(Default)]
+struct Includes(Vec<(IncludeKind, Expression)>);
+
+impl Visitor for Includes {
+    fn visit_expression(&mut self, expr: &Expression) {
+        if let Expression::Include { kind, path } = expr {
+            self.0.push((*kind, (**path).clone()));
+        }
+        visit::walk_expression(self, expr);
+    }
+}
+
+struct SourceFile {
+    path: PathBuf,
+    source: String,
+    program: Program,
+    resolved: Option<Program>,
+}
+
+/// Sets the file of the `Statement::Location` markers of one file
+struct LocationFile(u32);
+
+impl VisitorMut for LocationFile {
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        match stmt {
+            Statement::Location(location) => location.file = self.0,
+            _ => visit_mut::walk_statement_mut(self, stmt),
+        }
+    }
+}
+
+struct Linker<'a> {
+    project: &'a Project,
+    locations: bool,           // Parse with `Statement::Location` markers, for source maps
+    files: Vec<SourceFile>,
+    index: HashMap<PathBuf, usize>,
+    functions: HashSet<String>,
+    included: HashSet<usize>,  // Files already spliced in, for the `*_once` variants
+    hoisted: Vec<Statement>,   // Declarations from files included inside a block
+    depth: usize,
+    error: Option<String>,
+}
+
+impl<'a> Linker<'a> {
+    fn new(project: &'a Project, locations: bool) -> Self {
+        Self {
+            project,
+            locations,
+            files: Vec::new(),
+            index: HashMap::new(),
+            functions: HashSet::new(),
+            included: HashSet::new(),
+            hoisted: Vec::new(),
+            depth: 0,
+            error: None,
+        }
+    }
+
+    /// Load a file and, recursively, everything it includes
+    fn load(&mut self, path: &Path) -> Result<usize, String> {
+        let path = fs::canonicalize(path).map_err(|e| format!("Failed opening '{}': {}", path.display(), e))?;
+        if let Some(&index) = self.index.get(&path) {
+            return Ok(index);
+        }
+
+        let source = fs::read_to_string(&path).map_err(|e| format!("Failed opening '{}': {}", path.display(), e))?;
+        let mut program = if self.locations { parse_with_locations(&source) } else { parse(&source) }
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Compiler::strict_types_declaration(&program).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let mut includes = Includes::default();
+        visit::walk_program(&mut includes, &program);
+
+        let index = self.files.len();
+        visit_mut::walk_program_mut(&mut LocationFile(index as u32), &mut program);
+        self.index.insert(path.clone(), index);
+        self.files.push(SourceFile { path: path.clone(), source, program, resolved: None });
+
+        for (kind, include) in includes.0 {
+            let target = self.include_target(kind, &include, &path)?;
+            self.load(&target)?;
+        }
+        Ok(index)
+    }
+
+    fn include_target(&self, kind: IncludeKind, path: &Expression, from: &Path) -> Result<PathBuf, String> {
+        let literal = literal_path(path, from).ok_or_else(|| format!(
+            "{}: the path of {} must be known at compile time",
+            from.display(),
+            kind.keyword()
+        ))?;
+        self.project.resolve_include(&literal, from).ok_or_else(|| match kind {
+            IncludeKind::Require | IncludeKind::RequireOnce => {
+                format!("{}: Failed opening required '{}'", from.display(), literal)
+            }
+            IncludeKind::Include | IncludeKind::IncludeOnce => {
+                format!("{}: Failed opening '{}' for inclusion", from.display(), literal)
+            }
+        })
+    }
+
+    /// Resolve a file's names without keeping the result, to find out what
+    /// it declares and which classes it uses
+    fn scan(&self, index: usize) -> Result<NameResolver<'_>, String> {
+        let file = &self.files[index];
+        let mut resolver = NameResolver::new(Some(&file.path), &self.functions);
+        resolver.resolve_program(&mut file.program.clone())
+            .map_err(|e| format!("{}: {}", file.path.display(), e))?;
+        Ok(resolver)
+    }
+
+    /// Load the PSR-4 files of classes that are used but not declared, until
+    /// no more turn up
+    fn autoload(&mut self) -> Result<(), String> {
+        let mut attempted = HashSet::new();
+        loop {
+            let mut declared = HashSet::new();
+            let mut referenced = HashSet::new();
+            for index in 0..self.files.len() {
+                let resolver = self.scan(index)?;
+                declared.extend(resolver.declared_classes);
+                referenced.extend(resolver.referenced_classes);
+            }
+
+            let mut missing: Vec<_> = referenced.difference(&declared)
+                .filter(|class| attempted.insert((*class).clone()))
+                .cloned()
+                .collect();
+            missing.sort();
+
+            let loaded = self.files.len();
+            for class in missing {
+                if let Some(path) = self.project.autoload_path(&class) {
+                    self.load(&path)?;
+                }
+            }
+            if self.files.len() == loaded {
+                return Ok(());
+            }
+        }
+    }
+
+    fn resolve(&mut self) -> Result<(), String> {
+        let mut functions = HashSet::new();
+        for index in 0..self.files.len() {
+            functions.extend(self.scan(index)?.declared_functions);
+        }
+        self.functions = functions;
+
+        for index in 0..self.files.len() {
+            let file = &self.files[index];
+            let mut program = file.program.clone();
+            NameResolver::new(Some(&file.path), &self.functions)
+                .resolve_program(&mut program)
+                .map_err(|e| format!("{}: {}", file.path.display(), e))?;
+            self.files[index].resolved = Some(program);
+        }
+        Ok(())
+    }
+
+    fn resolved(&self, index: usize) -> Program {
+        self.files[index].resolved.clone().expect("files are resolved before they are linked")
+    }
+
+    /// The statements of a file, with its own includes spliced in
+    fn file_statements(&mut self, index: usize) -> Result<Vec<Statement>, String> {
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(format!("{}: includes are nested too deeply", self.files[index].path.display()));
+        }
+        self.depth += 1;
+
+        let program = self.resolved(index);
+        let mut statements = Vec::new();
+        for item in program.items {
+            match item {
+                ProgramItem::PhpBlock { statements: block } => statements.extend(block),
+                ProgramItem::InlineContent(content) => {
+                    statements.push(Statement::Echo(vec![Expression::Literal(Literal::String(content))]));
+                }
+            }
+        }
+        statements.retain(|stmt| !matches!(stmt, Statement::Declare(_)));
+
+        let spliced = self.splice(statements, index);
+        self.depth -= 1;
+        spliced
+    }
+
+    /// Replace the top-level includes of `statements` by the included files
+    /// and the nested ones by blocks
+    fn splice(&mut self, statements: Vec<Statement>, from: usize) -> Result<Vec<Statement>, String> {
+        let mut spliced = Vec::with_capacity(statements.len());
+        for mut stmt in statements {
+            match stmt {
+                Statement::Expression(Expression::Include { kind, path }) => {
+                    let Some(target) = self.target_index(kind, &path, from)? else { continue };
+                    let mut body = self.file_statements(target)?;
+                    // A `return` at the end of an included file only ends that file
+                    if matches!(body.last(), Some(Statement::Return(_))) {
+                        body.pop();
+                    }
+                    if body.iter().any(|stmt| matches!(stmt, Statement::Return(_))) {
+                        return Err(format!(
+                            "{}: returning early from an included file is not supported",
+                            self.files[target].path.display()
+                        ));
+                    }
+                    spliced.extend(body);
+                }
+                _ => {
+                    let mut nested = NestedIncludes { linker: self, from };
+                    nested.visit_statement_mut(&mut stmt);
+                    if let Some(error) = self.error.take() {
+                        return Err(error);
+                    }
+                    spliced.push(stmt);
+                }
+            }
+        }
+        Ok(spliced)
+    }
+
+    /// The file an include refers to, or None when a `*_once` include has
+    /// nothing left to do
+    fn target_index(&mut self, kind: IncludeKind, path: &Expression, from: usize) -> Result<Option<usize>, String> {
+        let target = self.include_target(kind, path, &self.files[from].path.clone())?;
+        let target = fs::canonicalize(&target).map_err(|e| e.to_string())?;
+        let index = self.index[&target];
+        if !self.included.insert(index) && kind.is_once() {
+            return Ok(None);
+        }
+        Ok(Some(index))
+    }
+
+    /// Name and text of every loaded file, in the order of their location markers
+    /// Every loaded file and its source, named relative to the entry file's
+    /// directory when they are under it
+    fn sources(&self) -> Vec<(String, String)> {
+        let root = self.files.first().and_then(|entry| entry.path.parent().map(Path::to_path_buf));
+        self.files.iter().map(|file| {
+            let path = root.as_deref().and_then(|root| file.path.strip_prefix(root).ok()).unwrap_or(&file.path);
+            (path.display().to_string(), file.source.clone())
+        }).collect()
+    }
+
+    fn link(&mut self) -> Result<Program, String> {
+        let entry = self.load(&self.project.entry.clone())?;
+        let mut preloaded = Vec::new();
+        for file in &self.project.autoload_files {
+            preloaded.push(self.load(file)?);
+        }
+        self.autoload()?;
+        self.resolve()?;
+
+        self.included.insert(entry);
+        let mut program = self.resolved(entry);
+        for item in &mut program.items {
+            if let ProgramItem::PhpBlock { statements } = item {
+                *statements = self.splice(std::mem::take(statements), entry)?;
+            }
+        }
+
+        // composer's `files` run first, then the declarations of autoloaded
+        // classes; neither may come before the entry file's `declare`
+        let mut prelude = Vec::new();
+        if let Some(ProgramItem::PhpBlock { statements }) = program.items.first_mut() {
+            let declares = statements.iter()
+                .take_while(|stmt| matches!(stmt, Statement::Declare(_) | Statement::Location(_)))
+                .count();
+            prelude.extend(statements.drain(..declares));
+        }
+        for index in preloaded {
+            if self.included.insert(index) {
+                let statements = self.file_statements(index)?;
+                prelude.extend(statements);
+            }
+        }
+        for index in 0..self.files.len() {
+            if self.included.insert(index) {
+                let statements = self.file_statements(index)?;
+                prelude.extend(statements);
+            }
+        }
+        prelude.append(&mut self.hoisted);
+
+        program.items.insert(0, ProgramItem::PhpBlock { statements: prelude });
+        Ok(program)
+    }
+}
+
+/// Splices includes found inside blocks, function bodies and expressions
+struct NestedIncludes<'l, 'a> {
+    linker: &'l mut Linker<'a>,
+    from: usize,
+}
+
+impl NestedIncludes<'_, '_> {
+    fn fail(&mut self, error: String) {
+        self.linker.error.get_or_insert(error);
+    }
+
+    /// The included file's statements, without its declarations, which are hoisted
+    fn included_statements(&mut self, kind: IncludeKind, path: &Expression) -> Result<Option<Vec<Statement>>, String> {
+        let Some(target) = self.linker.target_index(kind, path, self.from)? else { return Ok(None) };
+        let (declarations, statements): (Vec<_>, Vec<_>) = self.linker.file_statements(target)?
+            .into_iter()
+            .partition(is_declaration);
+        self.linker.hoisted.extend(declarations);
+        Ok(Some(statements))
+    }
+}
+
+impl VisitorMut for NestedIncludes<'_, '_> {
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        if let Statement::Expression(Expression::Include { kind, path }) = stmt {
+            match self.included_statements(*kind, path) {
+                Ok(statements) => *stmt = Statement::Block(Block { statements: statements.unwrap_or_default() }),
+                Err(error) => self.fail(error),
+            }
+            return;
+        }
+        visit_mut::walk_statement_mut(self, stmt);
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        if let Expression::Include { kind, path } = expr {
+            // `$config = require 'config.php';` - the file must consist of a `return`
+            let value = match self.included_statements(*kind, path) {
+                Ok(None) => Ok(Expression::Literal(Literal::Boolean(true))),
+                Ok(Some(statements)) => {
+                    let statements: Vec<_> = statements.into_iter()
+                        .filter(|stmt| !matches!(stmt, Statement::Location(_)))
+                        .collect();
+                    match <[Statement; 1]>::try_from(statements) {
+                        Ok([Statement::Return(value)]) => Ok(value.unwrap_or(Expression::Literal(Literal::Null))),
+                        _ => Err(format!(
+                            "{}: the value of {} is only supported for files that consist of a return statement",
+                            self.linker.files[self.from].path.display(),
+                            kind.keyword()
+                        )),
+                    }
+                }
+                Err(error) => Err(error),
+            };
+            match value {
+                Ok(value) => *expr = value,
+                Err(error) => self.fail(error),
+            }
+            return;
+        }
+        visit_mut::walk_expression_mut(self, expr);
+    }
+}
+
+impl Compiler {
+    /// Compile a project into a single module
+    pub fn compile_project(self, project: &Project) -> Result<Vec<u8>, CompilerError> {
+        Ok(self.compile_project_module(project)?.wasm)
+    }
+
+    /// Compile a project into a single module and, when requested, the
+    /// source map covering all of its files
+    pub fn compile_project_module(mut self, project: &Project) -> Result<CompiledModule, CompilerError> {
+        let mut linker = Linker::new(project, self.options.source_map_enabled());
+        let program = linker.link()?;
+        if self.options.source_map_enabled() {
+            self.sources = linker.sources();
+        }
+        self.compile_with_backend(program)
+    }
+}
@@ -0,0 +1,310 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Static, readonly and promoted properties
+//
+// Static properties get one wasm global per class and name, initialised from
+// their defaults when the class is declared. A readonly property starts out
+// holding a sentinel PhpValue: a null that no other value is `ref.eq` to.
+// Writes check that the slot still holds the sentinel and that they come from
+// inside the declaring class; reads check that it no longer does. Promoted
+// constructor parameters declare a property each and are assigned to it when
+// the constructor is entered.
+
+use super::core::*;
+use super::classes::PropertyInfo;
+use super::array_copies::Store;
+use edge_php_parser::ast::*;
+use edge_php_parser::visit::{self, Visitor};
+use wasm_encoder::*;
+use std::collections::HashMap;
+
+/// A property shared by every instance of its class
+#[derive(Debug, Clone)]
+pub struct StaticPropertyInfo {
+    pub global_idx: u32,  // Holds the value
+    pub visibility: Visibility,
+    pub default_value: Option<Expression>,
+    pub property_type: Option<Type>,  // Enforced on every write
+}
+
+/// Finds `new` inside an expression
+#[derive(Default)]
+struct NewExpressions(bool);
+
+impl Visitor for NewExpressions {
+    fn visit_expression(&mut self, expr: &Expression) {
+        if matches!(expr, Expression::New { .. }) {
+            self.0 = true;
+        }
+        visit::walk_expression(self, expr);
+    }
+}
+
+impl Compiler {
+    /// Readonly properties need a type and no default, and cannot be static
+    pub(super) fn check_property_modifiers(class_name: &str, property_name: &str, is_static: bool, is_readonly: bool, property_type: Option<&Type>, default: Option<&Expression>) -> Result<(), String> {
+        if is_readonly {
+            if is_static {
+                return Err(format!("Static property {}::${} cannot be readonly", class_name, property_name));
+            }
+            if property_type.is_none() {
+                return Err(format!("Readonly property {}::${} must have type", class_name, property_name));
+            }
+            if default.is_some() {
+                return Err(format!("Readonly property {}::${} cannot have default value", class_name, property_name));
+            }
+        }
+        match default {
+            Some(default) => Self::check_constant_expression(default),
+            None => Ok(()),
+        }
+    }
+
+    /// `new` is allowed in parameter defaults, but not in property defaults or constants
+    pub(super) fn check_constant_expression(expr: &Expression) -> Result<(), String> {
+        let mut finder = NewExpressions::default();
+        finder.visit_expression(expr);
+        if finder.0 {
+            return Err("New expressions are not supported in this context".to_string());
+        }
+        Ok(())
+    }
+
+    /// The property a promoted constructor parameter declares
+    pub(super) fn promoted_property(&mut self, class_name: &str, is_readonly_class: bool, param: &Parameter, index: u32) -> Result<Option<PropertyInfo>, String> {
+        let Some(promoted) = &param.promoted else {
+            return Ok(None);
+        };
+        let is_readonly = promoted.is_readonly || is_readonly_class;
+        Self::check_property_modifiers(class_name, &param.name, false, is_readonly, param.param_type.as_ref(), None)?;
+        if let Some(param_type) = &param.param_type {
+            self.check_property_declaration(class_name, &param.name, param_type, None)?;
+        }
+        Ok(Some(PropertyInfo {
+            name: param.name.clone(),
+            index,
+            visibility: promoted.visibility.clone(),
+            default_value: None,  // The parameter default applies to the argument instead
+            property_type: param.param_type.clone(),
+            is_readonly,
+        }))
+    }
+
+    pub(super) fn check_no_promotion(params: &[Parameter]) -> Result<(), String> {
+        if params.iter().any(|param| param.promoted.is_some()) {
+            return Err("Cannot declare promoted property outside a constructor".to_string());
+        }
+        Ok(())
+    }
+
+    /// A name can only be declared once, whether as a property, a static property
+    /// or a promoted parameter
+    pub(super) fn check_property_names(class_name: &str, properties: &[PropertyInfo], static_properties: &HashMap<String, StaticPropertyInfo>) -> Result<(), String> {
+        for (i, property) in properties.iter().enumerate() {
+            let redeclared = properties[..i].iter().any(|other| other.name == property.name)
+                || static_properties.contains_key(&property.name);
+            if redeclared {
+                return Err(format!("Cannot redeclare {}::${}", class_name, property.name));
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn add_static_property(&mut self, visibility: &Visibility, property_type: &Option<Type>, default: &Option<Expression>) -> StaticPropertyInfo {
+        StaticPropertyInfo {
+            global_idx: self.add_php_value_global(),
+            visibility: visibility.clone(),
+            default_value: default.clone(),
+            property_type: property_type.clone(),
+        }
+    }
+
+    /// Start `_start` by giving the static properties of every class their default
+    pub(super) fn init_static_properties(&mut self) -> Result<(), String> {
+        let mut classes: Vec<(u32, String)> = self.classes.values()
+            .map(|class| (class.class_id, class.name.clone()))
+            .collect();
+        classes.sort();
+        for (_, class_name) in classes {
+            self.emit_static_property_init(&class_name)?;
+        }
+        Ok(())
+    }
+
+    /// Set the static properties of a class to their defaults, in the class scope
+    pub(super) fn emit_static_property_init(&mut self, class_name: &str) -> Result<(), String> {
+        let Some(class_info) = self.classes.get(class_name) else {
+            return Ok(());
+        };
+        let mut properties: Vec<StaticPropertyInfo> = class_info.static_properties.values().cloned().collect();
+        properties.sort_by_key(|property| property.global_idx);
+
+        for property in properties {
+            match property.default_value {
+                Some(default) => self.compile_constant_expression(class_name, default)?,
+                None => self.emit(Instruction::Call(self.create_null_fn_idx)),
+            }
+            self.emit(Instruction::GlobalSet(property.global_idx));
+        }
+        Ok(())
+    }
+
+    /// Resolve `Class::$name`; `self` and `static` name the class being compiled
+    fn static_property(&self, class: &str, property_name: &str) -> Result<(String, StaticPropertyInfo), String> {
+        let class = self.resolve_class_name(class)?;
+        let class_info = self.classes.get(&class)
+            .ok_or_else(|| format!("Class \"{}\" not found", class))?;
+        let property = class_info.static_properties.get(property_name)
+            .ok_or_else(|| format!("Access to undeclared static property {}::${}", class, property_name))?
+            .clone();
+        Ok((class, property))
+    }
+
+    /// `Class::$name` as a variable, for writes that store an updated array back
+    pub(super) fn static_property_variable(&self, class: &str, property_name: &str) -> Result<VariableInfo, String> {
+        let (_, property) = self.static_property(class, property_name)?;
+        Ok(VariableInfo {
+            local_idx: 0,
+            storage_type: VariableStorage::Global(property.global_idx),
+            class_type: None,
+        })
+    }
+
+    pub(super) fn compile_static_property_access(&mut self, class: &str, property_name: &str) -> Result<(), String> {
+        let (_, property) = self.static_property(class, property_name)?;
+        self.emit(Instruction::GlobalGet(property.global_idx));
+        Ok(())
+    }
+
+    /// `Class::$name = value`, leaving the assigned value on the stack when `keep_result` is set
+    pub(super) fn compile_static_property_assignment(&mut self, class: &str, property_name: &str, value_expr: Expression, keep_result: bool) -> Result<(), String> {
+        let (class, property) = self.static_property(class, property_name)?;
+        self.compile_stored_value(value_expr, Store::Other)?;
+        if let Some(property_type) = &property.property_type {
+            self.emit_property_type_enforcement(&class, property_name, property_type)?;
+        }
+        self.emit(Instruction::GlobalSet(property.global_idx));
+        if keep_result {
+            self.emit(Instruction::GlobalGet(property.global_idx));
+        }
+        Ok(())
+    }
+
+    /// The global holding the sentinel of uninitialised readonly properties
+    pub(super) fn readonly_sentinel(&mut self) -> u32 {
+        if let Some(global_idx) = self.readonly_sentinel {
+            return global_idx;
+        }
+        let sentinel = vec![
+            Instruction::I32Const(TYPE_NULL as i32),
+            Instruction::I64Const(0),
+            Instruction::F64Const(0.0.into()),
+            Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string)),
+            Instruction::RefNull(HeapType::Abstract { shared: false, ty: AbstractHeapType::Any }),
+            Instruction::StructNew(self.gc_types.php_value),
+        ];
+        let php_value_type = self.get_php_value_type();
+        let global_idx = self.builder.add_global_expr(php_value_type, false, sentinel);
+        self.readonly_sentinel = Some(global_idx);
+        global_idx
+    }
+
+    /// Push whether the readonly property at `property_index` of a php_object local
+    /// still holds the sentinel
+    fn emit_readonly_uninitialized(&mut self, object_ref_local: u32, property_index: u32) {
+        let sentinel = self.readonly_sentinel();
+        self.emit_object_properties(object_ref_local);
+        self.emit(Instruction::I32Const(property_index as i32));
+        self.emit(Instruction::ArrayGet(self.gc_types.php_array));
+        self.emit(Instruction::GlobalGet(sentinel));
+        self.emit(Instruction::RefEq);
+    }
+
+    /// Throw an Error unless a readonly property can be written (or unset) here: from
+    /// inside its class, while it is still uninitialised
+    pub(super) fn emit_readonly_guard(&mut self, object_ref_local: u32, property_index: u32, class_name: &str, property_name: &str, is_unset: bool) -> Result<(), String> {
+        let property = format!("{}::${}", class_name, property_name);
+        let action = if is_unset { "unset" } else { "modify" };
+
+        if self.current_class.as_deref() == Some(class_name) {
+            self.emit_readonly_uninitialized(object_ref_local, property_index);
+            self.emit(Instruction::I32Eqz);
+            self.emit(Instruction::If(BlockType::Empty));
+            self.compile_string_literal(&format!("Cannot {} readonly property {}", action, property))?;
+            self.emit_uncaught_error("Error")?;
+            self.emit(Instruction::End);
+            return Ok(());
+        }
+
+        let scope = match &self.current_class {
+            Some(class) => format!("scope {}", class),
+            None => "global scope".to_string(),
+        };
+        if is_unset {
+            self.compile_string_literal(&format!("Cannot unset readonly property {} from {}", property, scope))?;
+            return self.emit_uncaught_error("Error");
+        }
+        self.emit_readonly_uninitialized(object_ref_local, property_index);
+        self.emit(Instruction::If(BlockType::Empty));
+        self.compile_string_literal(&format!("Cannot initialize readonly property {} from {}", property, scope))?;
+        self.emit_uncaught_error("Error")?;
+        self.emit(Instruction::Else);
+        self.compile_string_literal(&format!("Cannot modify readonly property {}", property))?;
+        self.emit_uncaught_error("Error")?;
+        self.emit(Instruction::End);
+        Ok(())
+    }
+
+    /// Throw an Error when the property value on the stack is the readonly sentinel
+    pub(super) fn emit_readonly_read_check(&mut self, class_name: &str, property_name: &str) -> Result<(), String> {
+        let sentinel = self.readonly_sentinel();
+        let value_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalTee(value_local));
+        self.emit(Instruction::GlobalGet(sentinel));
+        self.emit(Instruction::RefEq);
+        self.emit(Instruction::If(BlockType::Empty));
+        self.compile_string_literal(&format!(
+            "Typed property {}::${} must not be accessed before initialization", class_name, property_name
+        ))?;
+        self.emit_uncaught_error("Error")?;
+        self.emit(Instruction::End);
+        self.emit(Instruction::LocalGet(value_local));
+        Ok(())
+    }
+
+    /// `$obj->prop = value` for a readonly property of a known class. Returns the
+    /// local holding the assigned value.
+    pub(super) fn compile_readonly_assignment(&mut self, class_name: &str, property: &PropertyInfo, object_expr: Expression, value_expr: Expression) -> Result<u32, String> {
+        self.compile_expression(object_expr)?;
+        let object_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalSet(object_local));
+        let object_ref_local = self.emit_object_ref_local(object_local);
+
+        self.compile_stored_value(value_expr, Store::Other)?;
+        if let Some(property_type) = &property.property_type {
+            self.emit_property_type_enforcement(class_name, &property.name, property_type)?;
+        }
+        let value_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalSet(value_local));
+
+        self.emit_readonly_guard(object_ref_local, property.index, class_name, &property.name, false)?;
+        self.emit_object_properties(object_ref_local);
+        self.emit(Instruction::I32Const(property.index as i32));
+        self.emit(Instruction::LocalGet(value_local));
+        self.emit(Instruction::ArraySet(self.gc_types.php_array));
+        Ok(value_local)
+    }
+
+    /// Assign promoted constructor parameters to their properties: `$this->id = $id`
+    pub(super) fn emit_promoted_assignments(&mut self, params: &[Parameter]) -> Result<(), String> {
+        for param in params.iter().filter(|param| param.promoted.is_some()) {
+            self.compile_property_assignment_void(
+                Expression::Variable("this".to_string()),
+                &param.name,
+                Expression::Variable(param.name.clone()),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -11,7 +11,6 @@ impl Compiler {
         self.add_create_int_function();
         self.add_create_float_function();
         self.add_create_string_function();
-        self.add_print_value_function();
         self.add_arithmetic_functions();
         self.add_comparison_functions();
         self.add_string_functions();
@@ -22,70 +21,70 @@ impl Compiler {
         let php_value_ref = self.get_php_value_type();
         let null_type = self.builder.add_type(vec![], vec![php_value_ref]);
         
-        let mut body = vec![];
-        
         // Create new PhpValue struct
-        body.push(Instruction::I32Const(TYPE_NULL as i32));  // type tag
-        body.push(Instruction::I64Const(0));                 // int value (unused)
-        body.push(Instruction::F64Const(0.0.into()));        // float value (unused)
-        body.push(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string))); // null string
-        body.push(Instruction::RefNull(HeapType::Abstract {
-            shared: false,
-            ty: AbstractHeapType::Any,
-        }));      // null array
-        body.push(Instruction::StructNew(self.gc_types.php_value));
+        let body = vec![
+            Instruction::I32Const(TYPE_NULL as i32),  // type tag
+            Instruction::I64Const(0),                 // int value (unused)
+            Instruction::F64Const(0.0.into()),        // float value (unused)
+            Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string)), // null string
+            Instruction::RefNull(HeapType::Abstract {
+                shared: false,
+                ty: AbstractHeapType::Any,
+            }),      // null array
+            Instruction::StructNew(self.gc_types.php_value),
+        ];
         
         self.builder.set_function_at_index(self.create_null_fn_idx, null_type, vec![], body);
     }
     
     fn add_create_bool_function(&mut self) {
-        let mut body = vec![];
-        
         // Parameter: i32 (0 or 1)
-        body.push(Instruction::I32Const(TYPE_BOOL as i32));  // type tag
-        body.push(Instruction::LocalGet(0));                 // bool value
-        body.push(Instruction::I64ExtendI32S);               // extend to i64
-        body.push(Instruction::F64Const(0.0.into()));       // float value (unused)
-        body.push(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string))); // null string
-        body.push(Instruction::RefNull(HeapType::Abstract {
-            shared: false,
-            ty: AbstractHeapType::Any,
-        }));      // null array
-        body.push(Instruction::StructNew(self.gc_types.php_value));
+        let body = vec![
+            Instruction::I32Const(TYPE_BOOL as i32),  // type tag
+            Instruction::LocalGet(0),                 // bool value
+            Instruction::I64ExtendI32S,               // extend to i64
+            Instruction::F64Const(0.0.into()),       // float value (unused)
+            Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string)), // null string
+            Instruction::RefNull(HeapType::Abstract {
+                shared: false,
+                ty: AbstractHeapType::Any,
+            }),      // null array
+            Instruction::StructNew(self.gc_types.php_value),
+        ];
         
         self.builder.set_function_at_index(self.create_bool_fn_idx, self.i32_to_value_type_idx, vec![], body);
     }
     
     fn add_create_int_function(&mut self) {
-        let mut body = vec![];
-        
         // Parameter: i64
-        body.push(Instruction::I32Const(TYPE_INT as i32));   // type tag
-        body.push(Instruction::LocalGet(0));                 // int value
-        body.push(Instruction::F64Const(0.0.into()));       // float value (unused)
-        body.push(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string))); // null string
-        body.push(Instruction::RefNull(HeapType::Abstract {
-            shared: false,
-            ty: AbstractHeapType::Any,
-        }));      // null array
-        body.push(Instruction::StructNew(self.gc_types.php_value));
+        let body = vec![
+            Instruction::I32Const(TYPE_INT as i32),   // type tag
+            Instruction::LocalGet(0),                 // int value
+            Instruction::F64Const(0.0.into()),       // float value (unused)
+            Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string)), // null string
+            Instruction::RefNull(HeapType::Abstract {
+                shared: false,
+                ty: AbstractHeapType::Any,
+            }),      // null array
+            Instruction::StructNew(self.gc_types.php_value),
+        ];
         
         self.builder.set_function_at_index(self.create_int_fn_idx, self.i64_to_value_type_idx, vec![], body);
     }
     
     fn add_create_float_function(&mut self) {
-        let mut body = vec![];
-        
         // Parameter: f64
-        body.push(Instruction::I32Const(TYPE_FLOAT as i32)); // type tag
-        body.push(Instruction::I64Const(0));                 // int value (unused)
-        body.push(Instruction::LocalGet(0));                 // float value
-        body.push(Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string))); // null string
-        body.push(Instruction::RefNull(HeapType::Abstract {
-            shared: false,
-            ty: AbstractHeapType::Any,
-        }));      // null array
-        body.push(Instruction::StructNew(self.gc_types.php_value));
+        let body = vec![
+            Instruction::I32Const(TYPE_FLOAT as i32), // type tag
+            Instruction::I64Const(0),                 // int value (unused)
+            Instruction::LocalGet(0),                 // float value
+            Instruction::RefNull(HeapType::Concrete(self.gc_types.php_string)), // null string
+            Instruction::RefNull(HeapType::Abstract {
+                shared: false,
+                ty: AbstractHeapType::Any,
+            }),      // null array
+            Instruction::StructNew(self.gc_types.php_value),
+        ];
         
         self.builder.set_function_at_index(self.create_float_fn_idx, self.f64_to_value_type_idx, vec![], body);
     }
@@ -95,100 +94,22 @@ impl Compiler {
         let string_ref = self.get_string_type();
         let create_string_type = self.builder.add_type(vec![string_ref], vec![php_value_ref]);
         
-        let mut body = vec![];
-        
         // Parameter: string array ref
-        body.push(Instruction::I32Const(TYPE_STRING as i32)); // type tag
-        body.push(Instruction::I64Const(0));                  // int value (unused)
-        body.push(Instruction::F64Const(0.0.into()));        // float value (unused)
-        body.push(Instruction::LocalGet(0));                  // string ref
-        body.push(Instruction::RefNull(HeapType::Abstract {
-            shared: false,
-            ty: AbstractHeapType::Any,
-        }));       // null array
-        body.push(Instruction::StructNew(self.gc_types.php_value));
+        let body = vec![
+            Instruction::I32Const(TYPE_STRING as i32), // type tag
+            Instruction::I64Const(0),                  // int value (unused)
+            Instruction::F64Const(0.0.into()),        // float value (unused)
+            Instruction::LocalGet(0),                  // string ref
+            Instruction::RefNull(HeapType::Abstract {
+                shared: false,
+                ty: AbstractHeapType::Any,
+            }),       // null array
+            Instruction::StructNew(self.gc_types.php_value),
+        ];
         
         self.builder.set_function_at_index(self.create_string_fn_idx, create_string_type, vec![], body);
     }
     
-    fn add_print_value_function(&mut self) {
-        let php_value_ref = self.get_php_value_type();
-        let print_value_type = self.builder.add_type(vec![php_value_ref], vec![]);
-        
-        let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // type tag
-        locals.push((1, self.get_string_type())); // string ref
-        locals.push((1, ValType::I32)); // string length
-        locals.push((1, ValType::I32)); // memory offset
-        locals.push((1, ValType::I32)); // loop index
-        
-        // Convert input to string first
-        body.push(Instruction::LocalGet(0));
-        body.push(Instruction::Call(self.to_string_fn_idx));
-        
-        // Extract the string from the PhpValue
-        body.push(Instruction::StructGet {
-            struct_type_index: self.gc_types.php_value,
-            field_index: PHPVALUE_STRING,
-        });
-        body.push(Instruction::LocalSet(2)); // string ref
-        
-        // Get string length
-        body.push(Instruction::LocalGet(2));
-        body.push(Instruction::ArrayLen);
-        body.push(Instruction::LocalSet(3)); // string length
-        
-        // Use a fixed memory offset (we'll use a simple buffer)
-        body.push(Instruction::I32Const(0x1000)); // fixed offset
-        body.push(Instruction::LocalSet(4)); // memory offset
-        
-        // Copy string to linear memory at the fixed offset
-        body.push(Instruction::I32Const(0));
-        body.push(Instruction::LocalSet(5)); // loop index
-        
-        body.push(Instruction::Block(BlockType::Empty));
-        body.push(Instruction::Loop(BlockType::Empty));
-        
-        // Check if done copying
-        body.push(Instruction::LocalGet(5));
-        body.push(Instruction::LocalGet(3));
-        body.push(Instruction::I32GeU);
-        body.push(Instruction::BrIf(1));
-        
-        // Copy character: memory[offset + index] = string[index]
-        body.push(Instruction::LocalGet(4));
-        body.push(Instruction::LocalGet(5));
-        body.push(Instruction::I32Add);
-        body.push(Instruction::LocalGet(2));
-        body.push(Instruction::LocalGet(5));
-        body.push(Instruction::ArrayGetU(self.gc_types.php_string));
-        body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Increment index
-        body.push(Instruction::LocalGet(5));
-        body.push(Instruction::I32Const(1));
-        body.push(Instruction::I32Add);
-        body.push(Instruction::LocalSet(5));
-        
-        body.push(Instruction::Br(0));
-        body.push(Instruction::End);
-        body.push(Instruction::End);
-        
-        // Add null terminator
-        body.push(Instruction::LocalGet(4));
-        body.push(Instruction::LocalGet(3));
-        body.push(Instruction::I32Add);
-        body.push(Instruction::I32Const(0));
-        body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Call print with memory offset
-        body.push(Instruction::LocalGet(4));
-        body.push(Instruction::Call(self.print_fn_idx));
-        
-        self.builder.set_function_at_index(self.print_value_fn_idx, print_value_type, locals, body);
-    }
-    
     pub(super) fn add_comparison_functions(&mut self) {
         self.add_string_equals_function();
         self.add_equal_function();
@@ -203,12 +124,13 @@ impl Compiler {
     
     fn add_string_equals_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_string_type())); // left string
-        locals.push((1, self.get_string_type())); // right string
-        locals.push((1, ValType::I32)); // left length
-        locals.push((1, ValType::I32)); // right length
-        locals.push((1, ValType::I32)); // loop counter
+        let locals = vec![
+            (1, self.get_string_type()), // left string
+            (1, self.get_string_type()), // right string
+            (1, ValType::I32), // left length
+            (1, ValType::I32), // right length
+            (1, ValType::I32), // loop counter
+        ];
         
         // Get string references
         body.push(Instruction::LocalGet(0));
@@ -321,13 +243,14 @@ impl Compiler {
     
     fn add_equal_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // left_type
-        locals.push((1, ValType::I32)); // right_type
-        locals.push((1, ValType::F64)); // left_numeric
-        locals.push((1, ValType::F64)); // right_numeric
-        locals.push((1, ValType::I32)); // left_is_numeric
-        locals.push((1, ValType::I32)); // right_is_numeric
+        let locals = vec![
+            (1, ValType::I32), // left_type
+            (1, ValType::I32), // right_type
+            (1, ValType::F64), // left_numeric
+            (1, ValType::F64), // right_numeric
+            (1, ValType::I32), // left_is_numeric
+            (1, ValType::I32), // right_is_numeric
+        ];
         
         // Get types
         body.push(Instruction::LocalGet(0));
@@ -441,9 +364,18 @@ impl Compiler {
         
         body.push(Instruction::Else);
         
+        // Objects: same instance (enum cases are singletons)
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::I32Const(TYPE_OBJECT as i32));
+        body.push(Instruction::I32Eq);
+        body.push(Instruction::If(BlockType::Result(self.get_php_value_type())));
+        self.push_object_identity(&mut body);
+        body.push(Instruction::Else);
+        
         // For other same types, default to false
         body.push(Instruction::I32Const(0));
         body.push(Instruction::Call(self.create_bool_fn_idx));
+        body.push(Instruction::End); // End object check
         
         body.push(Instruction::End); // End null check
         body.push(Instruction::End); // End bool check
@@ -743,29 +675,30 @@ impl Compiler {
     }
     
     fn add_not_equal_function(&mut self) {
-        let mut body = vec![];
-        
         // Call equal function and negate result
-        body.push(Instruction::LocalGet(0));
-        body.push(Instruction::LocalGet(1));
-        body.push(Instruction::Call(self.equal_fn_idx));
-        
-        // Get boolean value and negate
-        body.push(Instruction::StructGet {
-            struct_type_index: self.gc_types.php_value,
-            field_index: PHPVALUE_INT,
-        });
-        body.push(Instruction::I64Eqz);
-        body.push(Instruction::Call(self.create_bool_fn_idx));
+        let body = vec![
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(1),
+            Instruction::Call(self.equal_fn_idx),
+
+            // Get boolean value and negate
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            },
+            Instruction::I64Eqz,
+            Instruction::Call(self.create_bool_fn_idx),
+        ];
         
         self.builder.set_function_at_index(self.not_equal_fn_idx, self.values_to_value_type_idx, vec![], body);
     }
     
     fn add_identical_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // left_type
-        locals.push((1, ValType::I32)); // right_type
+        let locals = vec![
+            (1, ValType::I32), // left_type
+            (1, ValType::I32), // right_type
+        ];
         
         // Get types
         body.push(Instruction::LocalGet(0));
@@ -885,10 +818,19 @@ impl Compiler {
         
         body.push(Instruction::Else);
         
-        // Other types (arrays, objects) - for now default to false
-        // TODO: Implement proper array/object comparison
+        // Objects are identical when they are the same instance
+        body.push(Instruction::LocalGet(2));
+        body.push(Instruction::I32Const(TYPE_OBJECT as i32));
+        body.push(Instruction::I32Eq);
+        body.push(Instruction::If(BlockType::Result(self.get_php_value_type())));
+        self.push_object_identity(&mut body);
+        body.push(Instruction::Else);
+        
+        // Other types (arrays) - for now default to false
+        // TODO: Implement proper array comparison
         body.push(Instruction::I32Const(0));
         body.push(Instruction::Call(self.create_bool_fn_idx));
+        body.push(Instruction::End); // End object check
         
         body.push(Instruction::End); // End null check
         body.push(Instruction::End); // End float check
@@ -901,32 +843,48 @@ impl Compiler {
         self.builder.set_function_at_index(self.identical_fn_idx, self.values_to_value_type_idx, locals, body);
     }
     
+    /// Push a bool PhpValue: whether the objects of params 0 and 1 are the same instance
+    fn push_object_identity(&self, body: &mut Vec<Instruction<'static>>) {
+        let eq_ref = HeapType::Abstract { shared: false, ty: AbstractHeapType::Eq };
+        for param in 0..2 {
+            body.push(Instruction::LocalGet(param));
+            body.push(Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_ARRAY,  // Objects share the array/object field
+            });
+            body.push(Instruction::RefCastNullable(eq_ref));
+        }
+        body.push(Instruction::RefEq);
+        body.push(Instruction::Call(self.create_bool_fn_idx));
+    }
+    
     fn add_not_identical_function(&mut self) {
-        let mut body = vec![];
-        
         // Call identical function and negate result
-        body.push(Instruction::LocalGet(0));
-        body.push(Instruction::LocalGet(1));
-        body.push(Instruction::Call(self.identical_fn_idx));
-        
-        // Get boolean value and negate
-        body.push(Instruction::StructGet {
-            struct_type_index: self.gc_types.php_value,
-            field_index: PHPVALUE_INT,
-        });
-        body.push(Instruction::I64Eqz);
-        body.push(Instruction::Call(self.create_bool_fn_idx));
+        let body = vec![
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(1),
+            Instruction::Call(self.identical_fn_idx),
+
+            // Get boolean value and negate
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            },
+            Instruction::I64Eqz,
+            Instruction::Call(self.create_bool_fn_idx),
+        ];
         
         self.builder.set_function_at_index(self.not_identical_fn_idx, self.values_to_value_type_idx, vec![], body);
     }
     
     fn add_greater_than_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // left_type
-        locals.push((1, ValType::I32)); // right_type
-        locals.push((1, ValType::F64)); // left_num
-        locals.push((1, ValType::F64)); // right_num
+        let locals = vec![
+            (1, ValType::I32), // left_type
+            (1, ValType::I32), // right_type
+            (1, ValType::F64), // left_num
+            (1, ValType::F64), // right_num
+        ];
         
         // Get types
         body.push(Instruction::LocalGet(0));
@@ -1020,11 +978,12 @@ impl Compiler {
     
     fn add_less_than_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // left_type
-        locals.push((1, ValType::I32)); // right_type
-        locals.push((1, ValType::F64)); // left_num
-        locals.push((1, ValType::F64)); // right_num
+        let locals = vec![
+            (1, ValType::I32), // left_type
+            (1, ValType::I32), // right_type
+            (1, ValType::F64), // left_num
+            (1, ValType::F64), // right_num
+        ];
         
         // Get left type
         body.push(Instruction::LocalGet(0));
@@ -1132,39 +1091,39 @@ impl Compiler {
     }
     
     fn add_greater_than_or_equal_function(&mut self) {
-        let mut body = vec![];
-        
         // >= is !(left < right)
-        body.push(Instruction::LocalGet(0));
-        body.push(Instruction::LocalGet(1));
-        body.push(Instruction::Call(self.less_than_fn_idx));
-        
-        // Negate result
-        body.push(Instruction::StructGet {
-            struct_type_index: self.gc_types.php_value,
-            field_index: PHPVALUE_INT,
-        });
-        body.push(Instruction::I64Eqz);
-        body.push(Instruction::Call(self.create_bool_fn_idx));
+        let body = vec![
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(1),
+            Instruction::Call(self.less_than_fn_idx),
+
+            // Negate result
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            },
+            Instruction::I64Eqz,
+            Instruction::Call(self.create_bool_fn_idx),
+        ];
         
         self.builder.set_function_at_index(self.greater_than_or_equal_fn_idx, self.values_to_value_type_idx, vec![], body);
     }
     
     fn add_less_than_or_equal_function(&mut self) {
-        let mut body = vec![];
-        
         // <= is !(left > right)  
-        body.push(Instruction::LocalGet(0));
-        body.push(Instruction::LocalGet(1));
-        body.push(Instruction::Call(self.greater_than_fn_idx));
-        
-        // Negate result
-        body.push(Instruction::StructGet {
-            struct_type_index: self.gc_types.php_value,
-            field_index: PHPVALUE_INT,
-        });
-        body.push(Instruction::I64Eqz);
-        body.push(Instruction::Call(self.create_bool_fn_idx));
+        let body = vec![
+            Instruction::LocalGet(0),
+            Instruction::LocalGet(1),
+            Instruction::Call(self.greater_than_fn_idx),
+
+            // Negate result
+            Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            },
+            Instruction::I64Eqz,
+            Instruction::Call(self.create_bool_fn_idx),
+        ];
         
         self.builder.set_function_at_index(self.less_than_or_equal_fn_idx, self.values_to_value_type_idx, vec![], body);
     }
@@ -1173,12 +1132,13 @@ impl Compiler {
         let int_to_string_type_idx = self.builder.add_type(vec![ValType::I64], vec![self.get_string_type()]);
         
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_string_type())); // result string
-        locals.push((1, ValType::I64)); // working value
-        locals.push((1, ValType::I32)); // digit count
-        locals.push((1, ValType::I32)); // current digit
-        locals.push((1, ValType::I32)); // string index
+        let locals = vec![
+            (1, self.get_string_type()), // result string
+            (1, ValType::I64), // working value
+            (1, ValType::I32), // digit count
+            (1, ValType::I32), // current digit
+            (1, ValType::I32), // string index
+        ];
         
         // Handle zero case
         body.push(Instruction::LocalGet(0));
@@ -1277,8 +1237,9 @@ impl Compiler {
         let float_to_string_type_idx = self.builder.add_type(vec![ValType::F64], vec![self.get_string_type()]);
         
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_string_type())); // string ref local
+        let locals = vec![
+            (1, self.get_string_type()), // string ref local
+        ];
         
         // Check if it's an integer value (e.g., 1.0, 2.0)
         body.push(Instruction::LocalGet(0));
@@ -1402,9 +1363,10 @@ impl Compiler {
     
     fn add_to_string_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // type tag
-        locals.push((1, self.get_string_type())); // string ref for result
+        let locals = vec![
+            (1, ValType::I32), // type tag
+            (1, self.get_string_type()), // string ref for result
+        ];
         
         // Get the type of the input PhpValue
         body.push(Instruction::LocalGet(0));
@@ -1497,11 +1459,22 @@ impl Compiler {
         
         body.push(Instruction::Else);
         
+        // Objects convert through their class's __toString
+        body.push(Instruction::LocalGet(1));
+        body.push(Instruction::I32Const(TYPE_OBJECT as i32));
+        body.push(Instruction::I32Eq);
+        body.push(Instruction::If(BlockType::Result(self.get_php_value_type())));
+        body.push(Instruction::LocalGet(0));
+        body.push(Instruction::Call(self.object_to_string_fn_idx));
+        
+        body.push(Instruction::Else);
+        
         // For other types (null, array, etc), default to empty string
         body.push(Instruction::I32Const(0)); // length 0
         body.push(Instruction::ArrayNewDefault(self.gc_types.php_string));
         body.push(Instruction::Call(self.create_string_fn_idx));
         
+        body.push(Instruction::End); // End object check
         body.push(Instruction::End); // End boolean check
         
         body.push(Instruction::End); // End float check
@@ -1513,8 +1486,9 @@ impl Compiler {
     
     fn add_to_bool_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // type tag
+        let locals = vec![
+            (1, ValType::I32), // type tag
+        ];
         
         // Get type tag
         body.push(Instruction::LocalGet(0));
@@ -1623,8 +1597,9 @@ impl Compiler {
 
     fn add_to_int_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // type tag
+        let locals = vec![
+            (1, ValType::I32), // type tag
+        ];
 
         // Get type tag
         body.push(Instruction::LocalGet(0));
@@ -1717,8 +1692,9 @@ impl Compiler {
 
     fn add_to_float_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, ValType::I32)); // type tag
+        let locals = vec![
+            (1, ValType::I32), // type tag
+        ];
 
         // Get type tag
         body.push(Instruction::LocalGet(0));
@@ -1812,14 +1788,15 @@ impl Compiler {
 
     fn add_concat_function(&mut self) {
         let mut body = vec![];
-        let mut locals = vec![];
-        locals.push((1, self.get_string_type())); // left_string
-        locals.push((1, self.get_string_type())); // right_string
-        locals.push((1, ValType::I32)); // left_length
-        locals.push((1, ValType::I32)); // right_length
-        locals.push((1, ValType::I32)); // total_length
-        locals.push((1, self.get_string_type())); // result_string
-        locals.push((1, ValType::I32)); // loop_index
+        let locals = vec![
+            (1, self.get_string_type()), // left_string
+            (1, self.get_string_type()), // right_string
+            (1, ValType::I32), // left_length
+            (1, ValType::I32), // right_length
+            (1, ValType::I32), // total_length
+            (1, self.get_string_type()), // result_string
+            (1, ValType::I32), // loop_index
+        ];
         
         // Convert both operands to strings
         body.push(Instruction::LocalGet(0));
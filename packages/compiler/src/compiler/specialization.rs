@@ -0,0 +1,240 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Function specialization: copies of user functions for unboxed arguments
+//
+// Type inference picks the copies (see type_inference.rs): a function called
+// with int or float arguments from a loop or from a function body gets a copy
+// for those argument types, up to `CompilerOptions::max_specializations`
+// copies per function. A copy takes its int and float parameters as i64/f64
+// and, when every value it returns is an int or a float, returns an i64/f64 as
+// well; its other parameters and its other results stay boxed PhpValues.
+//
+// The function itself is still compiled with the boxed signature and is the
+// generic entry point: callbacks, calls with other argument types and calls
+// made once from top-level code go there. Direct calls whose argument types
+// match a copy call the copy, and callers that want an unboxed result (int
+// arithmetic on `fib($n - 1)`) take its i64/f64 result without boxing it.
+
+use super::core::*;
+use super::type_inference::{InferredType, Specialization};
+use super::types::WrittenVariables;
+use edge_php_parser::ast::*;
+use wasm_encoder::*;
+
+impl Compiler {
+    /// Reserve the function indices of the specialized copies of a function
+    pub(super) fn declare_specializations(&mut self, name: &str) {
+        for specialization in self.type_inference.specializations(name) {
+            let func_idx = self.builder.reserve_function_index();
+            self.name_function(func_idx, &specialization.key);
+            self.specialized_functions.insert(specialization.key, func_idx);
+        }
+    }
+
+    /// The wasm type a parameter or result of a copy has
+    pub(super) fn specialized_val_type(&self, ty: &InferredType) -> ValType {
+        match ty {
+            InferredType::Int => ValType::I64,
+            InferredType::Float => ValType::F64,
+            _ => self.get_php_value_type(),
+        }
+    }
+
+    /// Storage of a parameter of the body being compiled, which is passed
+    /// unboxed in a specialized copy
+    pub(super) fn parameter_storage(&self, position: usize) -> VariableStorage {
+        match self.specialization.as_ref().map(|specialization| &specialization.params[position]) {
+            Some(InferredType::Int) => VariableStorage::UnboxedInt,
+            Some(InferredType::Float) => VariableStorage::UnboxedFloat,
+            _ => VariableStorage::Boxed,
+        }
+    }
+
+    /// A parameter passed unboxed that the body also assigns values of other
+    /// types to is boxed into a local of its own on entry
+    pub(super) fn box_reassigned_parameters(&mut self, params: &[Parameter], written: &WrittenVariables, first_local: u32) {
+        let Some(specialization) = self.specialization.clone() else { return };
+        for (position, (param, ty)) in params.iter().zip(&specialization.params).enumerate() {
+            if !ty.is_numeric() || !written.0.contains(&param.name) || self.type_inference.get_variable_type(&param.name) == *ty {
+                continue;
+            }
+            let boxed_local = self.allocate_local(self.get_php_value_type());
+            self.emit(Instruction::LocalGet(first_local + position as u32));
+            self.emit_box_unboxed(ty);
+            self.emit(Instruction::LocalSet(boxed_local));
+            self.variables.insert(param.name.clone(), VariableInfo {
+                local_idx: boxed_local,
+                storage_type: VariableStorage::Boxed,
+                class_type: None,
+            });
+        }
+    }
+
+    /// The type the copy being compiled returns unboxed, if it does
+    pub(super) fn unboxed_return_type(&self) -> Option<InferredType> {
+        self.specialization.as_ref()
+            .map(|specialization| specialization.return_type.clone())
+            .filter(InferredType::is_numeric)
+    }
+
+    /// Return the PhpValue on the stack, as an i64/f64 from a copy that returns one
+    pub(super) fn emit_return(&mut self) {
+        match self.unboxed_return_type() {
+            Some(InferredType::Int) => {
+                self.emit(Instruction::Call(self.to_int_fn_idx));
+                self.emit(Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index: PHPVALUE_INT,
+                });
+            }
+            Some(_) => {
+                self.emit(Instruction::Call(self.to_float_fn_idx));
+                self.emit(Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index: PHPVALUE_FLOAT,
+                });
+            }
+            None => {}
+        }
+        self.emit(Instruction::Return);
+    }
+
+    /// The copy of user function `name` that a call with these arguments goes to
+    pub(super) fn specialization_of_call(&mut self, name: &str, args: &[Expression]) -> Option<Specialization> {
+        let arg_types: Vec<InferredType> = args.iter().map(|arg| self.type_inference.infer_expression(arg)).collect();
+        self.type_inference.specialization_for(name, &arg_types)
+    }
+
+    /// Call a specialized copy. Its result stays on the stack as it returns it:
+    /// an i64 or f64 for ints and floats, a PhpValue otherwise.
+    pub(super) fn compile_specialized_call(&mut self, specialization: &Specialization, args: Vec<Expression>, params: &[Parameter]) -> Result<(), String> {
+        let arg_count = args.len();
+        for (position, arg) in args.into_iter().enumerate() {
+            match specialization.params.get(position) {
+                Some(ty) if ty.is_numeric() => {
+                    let arg_type = self.type_inference.infer_expression(&arg);
+                    self.compile_expression_as_unboxed(arg, &arg_type, ty)?;
+                }
+                Some(_) => self.compile_expression(arg)?,
+                None => {
+                    self.compile_expression(arg)?;
+                    self.emit(Instruction::Drop);
+                }
+            }
+        }
+        for (param, ty) in params.iter().zip(&specialization.params).skip(arg_count) {
+            match &param.default {
+                Some(default) if ty.is_numeric() => {
+                    let default_type = self.type_inference.infer_expression(default);
+                    self.compile_expression_as_unboxed(default.clone(), &default_type, ty)?;
+                }
+                Some(default) => self.compile_expression(default.clone())?,
+                None => self.emit(Instruction::Call(self.create_null_fn_idx)),
+            }
+        }
+        self.emit(Instruction::Call(self.specialized_functions[&specialization.key]));
+        Ok(())
+    }
+
+    /// Box the i64/f64 on the stack, if `ty` is passed unboxed
+    pub(super) fn emit_box_unboxed(&mut self, ty: &InferredType) {
+        match ty {
+            InferredType::Int => self.emit_inline_box_int(),
+            InferredType::Float => self.emit_inline_box_float(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Compiler, CompilerOptions, OptLevel};
+    use std::collections::HashMap;
+
+    /// The user functions (not `php_` helpers) each function calls, in order
+    fn user_calls(source: &str, opt_level: OptLevel) -> HashMap<String, Vec<String>> {
+        let options = CompilerOptions::new().opt_level(opt_level).debug_info(true).inlining(false);
+        let wasm = Compiler::with_options(options).compile(source).unwrap();
+
+        let mut names = HashMap::new();
+        let mut bodies = Vec::new();
+        let mut imports = 0;
+        for payload in wasmparser::Parser::new(0).parse_all(&wasm) {
+            match payload.unwrap() {
+                wasmparser::Payload::ImportSection(reader) => imports = reader.count(),
+                wasmparser::Payload::CodeSectionEntry(body) => bodies.push(body),
+                wasmparser::Payload::CustomSection(section) => {
+                    if let wasmparser::KnownCustom::Name(reader) = section.as_known() {
+                        for name in reader {
+                            if let wasmparser::Name::Function(map) = name.unwrap() {
+                                for naming in map {
+                                    let naming = naming.unwrap();
+                                    names.insert(naming.index, naming.name.to_string());
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut calls = HashMap::new();
+        for (position, body) in bodies.into_iter().enumerate() {
+            let caller = names.get(&(imports + position as u32)).cloned().unwrap_or_default();
+            let mut callees = Vec::new();
+            for operator in body.get_operators_reader().unwrap() {
+                if let wasmparser::Operator::Call { function_index } = operator.unwrap() {
+                    let callee = names.get(&function_index).cloned().unwrap_or_default();
+                    if !callee.is_empty() && !callee.starts_with("php_") {
+                        callees.push(callee);
+                    }
+                }
+            }
+            calls.insert(caller, callees);
+        }
+        calls
+    }
+
+    #[test]
+    fn test_call_sites_and_copies() {
+        let calls = user_calls(r#"<?php
+function sq($x) { return $x * $x; }
+function add($a, $b) { return $a + $b; }
+echo sq(3);
+for ($i = 0; $i < 3; $i++) {
+    echo sq($i), sq(1.5), sq("2"), add($i, 0.5), add(0.5, $i), add($i, "x");
+}
+"#, OptLevel::O2);
+        // Calls from loops make copies for their int and float arguments, up
+        // to two per function; any call whose arguments match a copy goes to it
+        assert_eq!(calls["{main}"], [
+            "sq<int>", "sq<int>", "sq<float>", "sq", "add<int, float>", "add<float, int>", "add",
+        ]);
+        assert!(!calls.contains_key("add<int, mixed>"));
+    }
+
+    #[test]
+    fn test_copies_per_function_by_level() {
+        let source = r#"<?php
+function add($a, $b) { return $a + $b; }
+function run($n) {
+    // $n is an int: run() is only called with one
+    return add($n, 1) + add($n, 1.5) + add(0.5, $n) + add(0.5, 1.5) + add($n, "2");
+}
+echo run(1);
+"#;
+        let calls = user_calls(source, OptLevel::O2);
+        assert_eq!(calls["run"], ["add<int, int>", "add<int, float>", "add", "add", "add"]);
+
+        let calls = user_calls(source, OptLevel::O3);
+        assert_eq!(calls["run"], [
+            "add<int, int>", "add<int, float>", "add<float, int>", "add<float, float>", "add",
+        ]);
+
+        let calls = user_calls(source, OptLevel::O1);
+        assert_eq!(calls["{main}"], ["run"]);
+        assert_eq!(calls["run"], ["add", "add", "add", "add", "add"]);
+    }
+}
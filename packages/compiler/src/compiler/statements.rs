@@ -3,12 +3,18 @@
 
 use super::core::*;
 use super::loop_analysis::*;
+use super::packed_arrays::PackedKind;
+use super::type_inference::Specialization;
 use edge_php_parser::ast::*;
 use wasm_encoder::*;
 
 impl Compiler {
     pub(super) fn compile_statement(&mut self, stmt: Statement) -> Result<(), String> {
         match stmt {
+            Statement::Location(location) => {
+                self.record_location(location);
+                Ok(())
+            }
             Statement::Echo(expressions) => {
                 for expr in expressions {
                     self.compile_expression(expr)?;
@@ -58,7 +64,12 @@ impl Compiler {
                 self.block_depth -= 1;
                 Ok(())
             }
-            Statement::While { condition, body } => {
+            Statement::While { mut condition, body } => {
+                // OPTIMIZATION: Count arrays the loop leaves alone once, before it
+                let mut writes = LoopWrites::of(&body);
+                writes.add_expression(&condition);
+                let hoisted = self.hoist_loop_counts(&mut condition, &writes)?;
+
                 // Use a block to properly handle loop exit
                 self.emit(Instruction::Block(BlockType::Empty)); // Block to break out of
                 self.emit(Instruction::Loop(BlockType::Empty));   // Loop to continue in
@@ -67,7 +78,6 @@ impl Compiler {
                 self.loop_stack.push(super::core::LoopContext {
                     break_depth: 1,
                     continue_depth: 0,
-                    loop_type: super::core::LoopType::While,
                     entry_block_depth: self.block_depth,
                 });
                 
@@ -91,6 +101,7 @@ impl Compiler {
                 
                 // Pop loop context
                 self.loop_stack.pop();
+                self.remove_temporaries(hoisted);
                 
                 Ok(())
             }
@@ -104,7 +115,6 @@ impl Compiler {
                 self.loop_stack.push(super::core::LoopContext {
                     break_depth: 1,
                     continue_depth: 0,
-                    loop_type: super::core::LoopType::While, // Reuse While type
                     entry_block_depth: self.block_depth,
                 });
 
@@ -128,79 +138,7 @@ impl Compiler {
                 Ok(())
             }
             Statement::For { init, condition, update, body } => {
-                // OPTIMIZATION: Try to unroll simple counted loops
-                if let Some(unroll_info) = LoopUnrollInfo::analyze(&init, &condition, &update, &body) {
-                    if unroll_info.can_unroll {
-                        // Try unrolled compilation
-                        if let Ok(()) = self.compile_for_unrolled(&init, &condition, &update, &body, &unroll_info) {
-                            return Ok(());
-                        }
-                        // If unrolling fails, fall through to normal compilation
-                    }
-                }
-
-                // FALLBACK: Normal for loop compilation
-                // FOR LOOP: Uses a special structure to handle continue jumping to update
-                // Structure: init; block(break) { loop { condition; block(continue) { body } update; } }
-
-                // INIT: Execute once before loop
-                if let Some(init_stmt) = init {
-                    self.compile_statement(*init_stmt)?;
-                }
-                
-                // OUTER BLOCK: Break target
-                self.emit(Instruction::Block(BlockType::Empty));
-                self.block_depth += 1; // Track that we're in a block
-                
-                // LOOP: Main loop
-                self.emit(Instruction::Loop(BlockType::Empty));
-                
-                // CONDITION: Check if we should continue
-                if let Some(cond) = condition {
-                    // OPTIMIZATION: Compile condition as raw i32 boolean (no boxing!)
-                    self.compile_expression_as_bool_i32(cond)?;
-                    self.emit(Instruction::I32Eqz);
-                    self.emit(Instruction::BrIf(1)); // Exit to break block if false
-                }
-                
-                // INNER BLOCK: Continue target - continue will exit this block
-                self.emit(Instruction::Block(BlockType::Empty));
-                self.block_depth += 1; // Another block level
-                
-                // Push loop context AFTER creating the blocks
-                // At this point we're inside both the break block and continue block
-                self.loop_stack.push(super::core::LoopContext {
-                    break_depth: 2,    // Exit both inner block and loop to reach break block
-                    continue_depth: 0, // Exit inner block to reach update
-                    loop_type: super::core::LoopType::For,
-                    entry_block_depth: self.block_depth, // Current depth when entering loop body
-                });
-                
-                // BODY: Execute loop body
-                for stmt in body.statements {
-                    self.compile_statement(stmt)?;
-                }
-                
-                // Pop loop context before the blocks end
-                self.loop_stack.pop();
-                
-                self.emit(Instruction::End); // End inner block (continue target)
-                self.block_depth -= 1;
-                
-                // UPDATE: This is where continue lands
-                if let Some(update_expr) = update {
-                    self.compile_expression(update_expr)?;
-                    self.emit(Instruction::Drop);
-                }
-                
-                // Jump back to loop start
-                self.emit(Instruction::Br(0));
-                
-                self.emit(Instruction::End); // End loop
-                self.emit(Instruction::End); // End outer block
-                self.block_depth -= 1;
-                
-                Ok(())
+                self.compile_for(init, condition, update, body)
             }
             Statement::Break => {
                 // Use loop context stack to get the correct break depth
@@ -229,21 +167,33 @@ impl Compiler {
             Statement::Foreach { array, key, value, body } => {
                 self.compile_foreach(array, key, value, body)
             }
-            Statement::Function { name, params, body, return_type: _ } => {
+            Statement::Function { name, params, body, return_type } => {
                 // PHASE 4: User-defined functions
-                self.compile_function_definition(&name, &params, body.clone())
+                self.compile_function_definition(&name, &params, return_type.as_ref(), body.clone())
             }
             Statement::Return(expr_opt) => {
                 // PHASE 4: Return statement
                 self.compile_return_statement(&expr_opt)
             }
-            Statement::Class { name, extends: _, implements: _, members } => {
+            Statement::Class { name, is_readonly, implements, members, .. } => {
                 // PHASE 5: Class definition
-                self.compile_class_definition(&name, &members)
+                self.compile_class_definition(&name, is_readonly, &implements, &members)
             }
+            Statement::Enum { name, .. } => Err(format!("Enum {} must be declared at the top level", name)),
+            Statement::Interface { name, .. } => Err(format!("Interface {} must be declared at the top level", name)),
             Statement::Switch { expr, cases } => {
                 self.compile_switch(expr, cases)
             }
+            Statement::Global(names) => self.compile_global_statement(names),
+            // Directives are read before compilation (see strict_types_declaration)
+            Statement::Declare(_) => Ok(()),
+            Statement::Static(variables) => self.compile_static_statement(variables),
+            Statement::Block(block) => {
+                for stmt in block.statements {
+                    self.compile_statement(stmt)?;
+                }
+                Ok(())
+            }
             _ => Err(format!("Unsupported statement: {:?}", stmt))
         }
     }
@@ -292,7 +242,7 @@ impl Compiler {
         Ok(())
     }
 
-    fn compile_foreach(&mut self, array: Expression, key: Option<String>, value: String, body: Block) -> Result<(), String> {
+    fn compile_foreach(&mut self, array: Expression, key: Option<String>, value: Expression, body: Block) -> Result<(), String> {
         // FOREACH LOOP: Iterates over array elements (both simple and hash arrays)
         // This implementation handles both numeric arrays and associative arrays (hash tables)
         
@@ -303,34 +253,44 @@ impl Compiler {
         let array_local = self.allocate_local(self.get_php_value_type());
         self.emit(Instruction::LocalSet(array_local));
         
-        // Get or create locals for key and value variables
-        let key_var_local = if let Some(key_name) = &key {
-            let idx = if let Some(var_info) = self.variables.get(key_name).cloned() {
-                var_info.local_idx
-            } else {
-                let new_idx = self.allocate_local(self.get_php_value_type());
-                self.variables.insert(key_name.clone(), VariableInfo {
-                    local_idx: new_idx,
-                    storage_type: VariableStorage::Boxed,
-                    class_type: None,
-                });
-                new_idx
-            };
-            Some(idx)
-        } else {
-            None
+        // Get or create locals for key and value variables. Targets that can't be
+        // iterated into directly are assigned at the top of the body instead.
+        let mut prologue = Vec::new();
+        let mut temp_names = Vec::new();
+        let key_var_local = key.as_ref()
+            .map(|key_name| self.foreach_target_local(key_name, &mut prologue, &mut temp_names));
+
+        // foreach ($arr as [$a, $b]) - iterate into a temporary and destructure it at the top of the body
+        let value_var_local = match value {
+            // An element array the body changes in place is copied into the variable, so the iterated array keeps it
+            Expression::Variable(name) if self.changed_arrays.contains(&name) => {
+                let temp_local = self.allocate_local(self.get_php_value_type());
+                let temp_name = self.bind_temp_variable(temp_local, None);
+                prologue.push(Statement::Expression(Expression::Assignment {
+                    left: Box::new(Expression::Variable(name)),
+                    right: Box::new(Expression::Variable(temp_name.clone())),
+                }));
+                temp_names.push(temp_name);
+                temp_local
+            }
+            Expression::Variable(name) => self.foreach_target_local(&name, &mut prologue, &mut temp_names),
+            pattern @ Expression::List(_) => {
+                let temp_local = self.allocate_local(self.get_php_value_type());
+                let temp_name = self.bind_temp_variable(temp_local, None);
+                prologue.push(Statement::Expression(Expression::Assignment {
+                    left: Box::new(pattern),
+                    right: Box::new(Expression::Variable(temp_name.clone())),
+                }));
+                temp_names.push(temp_name);
+                temp_local
+            }
+            _ => return Err("foreach value must be a variable or list() pattern".to_string()),
         };
-
-        let value_var_local = if let Some(var_info) = self.variables.get(&value).cloned() {
-            var_info.local_idx
+        let body = if prologue.is_empty() {
+            body
         } else {
-            let new_idx = self.allocate_local(self.get_php_value_type());
-            self.variables.insert(value.clone(), VariableInfo {
-                local_idx: new_idx,
-                storage_type: VariableStorage::Boxed,
-                class_type: None,
-            });
-            new_idx
+            prologue.extend(body.statements);
+            Block { statements: prologue }
         };
         
         // Get array field from PhpValue
@@ -354,7 +314,7 @@ impl Compiler {
         self.emit(Instruction::If(BlockType::Empty));
         
         // It's a hash table - use hash table iteration
-        self.compile_foreach_hash_table(array_local, array_field_local, key_var_local, value_var_local, &body)?;
+        self.compile_foreach_hash_table(array_field_local, key_var_local, value_var_local, &body)?;
         
         self.emit(Instruction::Else);
         
@@ -363,13 +323,85 @@ impl Compiler {
         
         self.emit(Instruction::End);
         
+        for temp_name in temp_names {
+            self.variables.remove(&temp_name);
+        }
+        
         Ok(())
     }
+
+    /// Local a foreach key/value is written to. Global and static variables live in
+    /// wasm globals, so they are iterated into a temporary that the body copies over.
+    fn foreach_target_local(&mut self, name: &str, prologue: &mut Vec<Statement>, temp_names: &mut Vec<String>) -> u32 {
+        let is_global = matches!(
+            self.variables.get(name),
+            Some(VariableInfo { storage_type: VariableStorage::Global(_), .. })
+        );
+        if !is_global {
+            return self.boxed_variable_local(name);
+        }
+
+        let temp_local = self.allocate_local(self.get_php_value_type());
+        let temp_name = self.bind_temp_variable(temp_local, None);
+        prologue.push(Statement::Expression(Expression::Assignment {
+            left: Box::new(Expression::Variable(name.to_string())),
+            right: Box::new(Expression::Variable(temp_name.clone())),
+        }));
+        temp_names.push(temp_name);
+        temp_local
+    }
+
+    /// Get the local of a variable that must hold a boxed PhpValue (foreach key/value).
+    /// A variable previously stored unboxed is rebound to a fresh boxed local.
+    fn boxed_variable_local(&mut self, name: &str) -> u32 {
+        if let Some(var_info) = self.variables.get(name) {
+            if var_info.storage_type == VariableStorage::Boxed {
+                return var_info.local_idx;
+            }
+        }
+        let new_idx = self.allocate_local(self.get_php_value_type());
+        self.variables.insert(name.to_string(), VariableInfo {
+            local_idx: new_idx,
+            storage_type: VariableStorage::Boxed,
+            class_type: None,
+        });
+        new_idx
+    }
     
+    /// Iterate a packed array by index, reading its slots directly. Slots past
+    /// the logical length and holes are never visited; typed storage (see
+    /// packed_arrays.rs) is read through `array_element`.
     fn compile_foreach_simple_array(&mut self, array_local: u32, array_field_local: u32, key_var_local: Option<u32>, value_var_local: u32, body: &Block) -> Result<(), String> {
-        // Get array count for iteration
+        // Objects share the array field; only packed arrays are iterated here
+        self.emit(Instruction::LocalGet(array_field_local));
+        self.emit(Instruction::RefTestNullable(HeapType::Concrete(self.gc_types.php_array)));
+        for kind in PackedKind::ALL {
+            self.emit(Instruction::LocalGet(array_field_local));
+            self.emit(Instruction::RefTestNullable(HeapType::Concrete(self.packed_array_type(kind))));
+            self.emit(Instruction::I32Or);
+        }
+        self.emit(Instruction::If(BlockType::Empty));
+
+        // Cast boxed storage once, outside the loop; it stays null for typed storage
+        let elements_local = self.allocate_local(ValType::Ref(RefType {
+            nullable: true,
+            heap_type: HeapType::Concrete(self.gc_types.php_array),
+        }));
+        self.emit(Instruction::LocalGet(array_field_local));
+        self.emit(Instruction::RefTestNullable(HeapType::Concrete(self.gc_types.php_array)));
+        self.emit(Instruction::If(BlockType::Empty));
+        self.emit(Instruction::LocalGet(array_field_local));
+        self.emit(Instruction::RefCastNullable(HeapType::Concrete(self.gc_types.php_array)));
+        self.emit(Instruction::LocalSet(elements_local));
+        self.emit(Instruction::End);
+
+        // The logical length is kept in the int field of packed arrays
         self.emit(Instruction::LocalGet(array_local));
-        self.emit(Instruction::Call(self.count_fn_idx));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_INT,
+        });
+        self.emit(Instruction::I32WrapI64);
         let count_local = self.allocate_local(ValType::I32);
         self.emit(Instruction::LocalSet(count_local));
         
@@ -391,24 +423,25 @@ impl Compiler {
         self.emit(Instruction::I32GeU);
         self.emit(Instruction::BrIf(1)); // Break if index >= count
         
-        // Get current element value
+        // Get current element straight from the storage
+        self.emit(Instruction::LocalGet(elements_local));
+        self.emit(Instruction::RefIsNull);
+        self.emit(Instruction::If(BlockType::Result(self.get_php_value_type())));
         self.emit(Instruction::LocalGet(array_local));
         self.emit(Instruction::LocalGet(index_local));
-        self.emit(Instruction::I64ExtendI32U);
-        self.emit(Instruction::Call(self.create_int_fn_idx));
-        self.emit(Instruction::Call(self.array_get_fn_idx));
+        self.emit(Instruction::Call(self.array_element_fn_idx));
+        self.emit(Instruction::Else);
+        self.emit(Instruction::LocalGet(elements_local));
+        self.emit(Instruction::LocalGet(index_local));
+        self.emit(Instruction::ArrayGet(self.gc_types.php_array));
+        self.emit(Instruction::End);
         
-        // Check if value is null (sparse array)
+        // Skip holes (sparse array); a null value is still an element
         self.emit(Instruction::LocalTee(value_var_local));
-        self.emit(Instruction::StructGet {
-            struct_type_index: self.gc_types.php_value,
-            field_index: PHPVALUE_TYPE,
-        });
-        self.emit(Instruction::I32Const(TYPE_NULL as i32));
-        self.emit(Instruction::I32Eq);
+        self.emit(Instruction::RefIsNull);
         self.emit(Instruction::If(BlockType::Empty));
         
-        // Skip null values - increment index and continue
+        // Skip the hole - increment index and continue
         self.emit(Instruction::LocalGet(index_local));
         self.emit(Instruction::I32Const(1));
         self.emit(Instruction::I32Add);
@@ -434,7 +467,6 @@ impl Compiler {
         self.loop_stack.push(super::core::LoopContext {
             break_depth: 2,    // Exit both inner block and loop
             continue_depth: 0, // Exit inner block only
-            loop_type: super::core::LoopType::Foreach,
             entry_block_depth: self.block_depth,
         });
         
@@ -461,11 +493,13 @@ impl Compiler {
         self.emit(Instruction::End); // End loop
         self.emit(Instruction::End); // End outer block
         self.block_depth -= 1;
+
+        self.emit(Instruction::End); // End packed array check
         
         Ok(())
     }
     
-    fn compile_foreach_hash_table(&mut self, array_local: u32, array_field_local: u32, key_var_local: Option<u32>, value_var_local: u32, body: &Block) -> Result<(), String> {
+    fn compile_foreach_hash_table(&mut self, array_field_local: u32, key_var_local: Option<u32>, value_var_local: u32, body: &Block) -> Result<(), String> {
         // Cast to hash table
         self.emit(Instruction::LocalGet(array_field_local));
         self.emit(Instruction::RefCastNullable(HeapType::Concrete(self.gc_types.php_hash_table)));
@@ -569,7 +603,6 @@ impl Compiler {
         self.loop_stack.push(super::core::LoopContext {
             break_depth: 2,    // Exit both inner block and loop
             continue_depth: 0, // Exit inner block only
-            loop_type: super::core::LoopType::Foreach,
             entry_block_depth: self.block_depth,
         });
         
@@ -602,105 +635,330 @@ impl Compiler {
         Ok(())
     }
 
+    /// Compile a for loop: its init, then the loop, unrolled when it is counted
+    fn compile_for(&mut self, init: Option<Box<Statement>>, mut condition: Option<Expression>, update: Option<Expression>, mut body: Block) -> Result<(), String> {
+        // INIT: Execute once before loop
+        if let Some(init_stmt) = init.clone() {
+            self.compile_statement(*init_stmt)?;
+        }
+
+        // What the loop can change besides the counter, which only the update may
+        let mut writes = LoopWrites::of(&body);
+        if let Some(cond) = &condition {
+            writes.add_expression(cond);
+        }
+        let mut update_writes = LoopWrites::default();
+        if let Some(update_expr) = &update {
+            update_writes.add_expression(update_expr);
+        }
+
+        // Functions the loop calls may change its globals and the arrays in them
+        let runs_code = self.runs_impure_code(&writes) || self.runs_impure_code(&update_writes);
+
+        // OPTIMIZATION: Count arrays the loop leaves alone once, before it
+        let mut temporaries = Vec::new();
+        if let Some(cond) = condition.as_mut() {
+            let hoistable = |array: &str| !writes.may_write(array) && !update_writes.may_write(array);
+            temporaries = self.hoist_loop_counts_where(cond, runs_code, hoistable)?;
+        }
+
+        let analysis = LoopUnrollInfo::analyze(&init, &condition, &update, &body)
+            .filter(|info| !writes.may_write(&info.counter_var) && self.is_loop_local(&info.counter_var));
+
+        // OPTIMIZATION: Strength reduction - `$i * 4` becomes a variable that grows by 4 * increment
+        let mut inductions = Vec::new();
+        if let Some(info) = analysis.as_ref().filter(|_| self.options.loop_optimizations_enabled()) {
+            let mut variables = std::collections::HashMap::new();
+            for scale in counter_scales(&body, &info.counter_var) {
+                let start = info.start_value.and_then(|start| start.checked_mul(scale));
+                let (Some(start), Some(step)) = (start, info.increment.checked_mul(scale)) else { continue };
+                let local = self.allocate_local(ValType::I64);
+                self.emit(Instruction::I64Const(start));
+                self.emit(Instruction::LocalSet(local));
+                let name = self.bind_int_temporary(local);
+                variables.insert(scale, name.clone());
+                temporaries.push(name);
+                inductions.push((local, step));
+            }
+            replace_scaled_counters(&mut body, &info.counter_var, &variables);
+        }
+
+        // OPTIMIZATION: Unroll counted loops
+        let unroll_info = analysis
+            .filter(|info| info.can_unroll && self.options.loop_unrolling_enabled())
+            .map(|mut info| {
+                info.unroll_factor = self.options.unroll_factor();
+                info
+            })
+            .filter(|info| self.is_int_bound(&info.end_bound))
+            // The copies read a variable bound once per round
+            .filter(|info| matches!(info.end_bound, LoopBound::Constant(_)) || !runs_code)
+            // Too few iterations to fill one round of copies
+            .filter(|info| info.calculate_iterations().is_none_or(|iterations| iterations >= info.unroll_factor));
+        let result = match (unroll_info, condition) {
+            (Some(unroll_info), Some(cond)) => self.compile_for_unrolled(cond, &update, &inductions, &body, &unroll_info),
+            (_, condition) => self.compile_for_loop(condition.as_ref(), &update, &inductions, &body, 0),
+        };
+        self.remove_temporaries(temporaries);
+        result
+    }
+
+    /// A for loop after its init: condition, body and update. `outer_blocks`
+    /// are blocks around it that `break` exits as well.
+    // FOR LOOP: Uses a special structure to handle continue jumping to update
+    // Structure: block(break) { loop { condition; block(continue) { body } update; } }
+    fn compile_for_loop(&mut self, condition: Option<&Expression>, update: &Option<Expression>, inductions: &[(u32, i64)], body: &Block, outer_blocks: u32) -> Result<(), String> {
+        // OUTER BLOCK: Break target
+        self.emit(Instruction::Block(BlockType::Empty));
+        self.block_depth += 1; // Track that we're in a block
+
+        // LOOP: Main loop
+        self.emit(Instruction::Loop(BlockType::Empty));
+
+        // CONDITION: Check if we should continue
+        if let Some(cond) = condition {
+            // OPTIMIZATION: Compile condition as raw i32 boolean (no boxing!)
+            self.compile_expression_as_bool_i32(cond.clone())?;
+            self.emit(Instruction::I32Eqz);
+            self.emit(Instruction::BrIf(1)); // Exit to break block if false
+        }
+
+        // BODY: Inside the continue block; break exits it, the loop and the outer block
+        self.compile_loop_copy(body, 2 + outer_blocks)?;
+        self.compile_for_update(update, inductions)?;
+
+        // Jump back to loop start
+        self.emit(Instruction::Br(0));
+
+        self.emit(Instruction::End); // End loop
+        self.emit(Instruction::End); // End outer block
+        self.block_depth -= 1;
+
+        Ok(())
+    }
+
     /// Compile an unrolled for loop (4x unrolling by default)
+    ///
+    /// The main loop runs the body `unroll_factor` times per round and checks once
+    /// per round that that many iterations remain; an epilogue loop runs the rest.
+    /// Every copy has its own continue target before its update, and break leaves
+    /// both loops.
+    // Structure: block(break) { block(epilogue) { loop { guard; (block(continue) { body } update;)*N } }
+    //            loop { condition; block(continue) { body } update; } }
     fn compile_for_unrolled(
         &mut self,
-        init: &Option<Box<Statement>>,
-        condition: &Option<Expression>,
+        condition: Expression,
         update: &Option<Expression>,
+        inductions: &[(u32, i64)],
         body: &Block,
         unroll_info: &LoopUnrollInfo,
     ) -> Result<(), String> {
         let unroll_factor = unroll_info.unroll_factor;
 
-        // INIT: Execute once before loop
-        if let Some(init_stmt) = init {
-            self.compile_statement(*init_stmt.clone())?;
-        }
+        // GUARD: $i + (N - 1) * increment < bound - every copy of this round stays in bounds
+        let last_offset = (unroll_factor as i64 - 1) * unroll_info.increment;
+        let bound = match &unroll_info.end_bound {
+            LoopBound::Constant(n) => Expression::Literal(Literal::Integer(*n)),
+            LoopBound::Variable(name) => Expression::Variable(name.clone()),
+        };
+        let guard = Expression::Binary {
+            left: Box::new(Expression::Binary {
+                left: Box::new(Expression::Variable(unroll_info.counter_var.clone())),
+                op: BinaryOp::Add,
+                right: Box::new(Expression::Literal(Literal::Integer(last_offset))),
+            }),
+            op: unroll_info.comparison.clone(),
+            right: Box::new(bound),
+        };
 
         // OUTER BLOCK: Break target
         self.emit(Instruction::Block(BlockType::Empty));
         self.block_depth += 1;
 
+        // EPILOGUE BLOCK: Left when less than a round remains
+        self.emit(Instruction::Block(BlockType::Empty));
+        self.block_depth += 1;
+
         // LOOP: Main unrolled loop
         self.emit(Instruction::Loop(BlockType::Empty));
+        self.compile_expression_as_bool_i32(guard)?;
+        self.emit(Instruction::I32Eqz);
+        self.emit(Instruction::BrIf(1)); // To the epilogue
 
-        // CONDITION: Check if we have at least unroll_factor iterations left
-        if let Some(cond) = condition {
-            // For unrolled loop, we need to check if counter + (unroll_factor-1) * increment < bound
-            // For simplicity, check the base condition - the remainder loop will handle the last few
-            self.compile_expression_as_bool_i32(cond.clone())?;
-            self.emit(Instruction::I32Eqz);
-            self.emit(Instruction::BrIf(1)); // Exit if condition false
+        // UNROLLED BODY: Execute body unroll_factor times
+        for _ in 0..unroll_factor {
+            // Break exits the continue block, the loop, the epilogue block and the outer block
+            self.compile_loop_copy(body, 3)?;
+            self.compile_for_update(update, inductions)?;
         }
 
-        // INNER BLOCK: Continue target
+        // Jump back to loop start
+        self.emit(Instruction::Br(0));
+
+        self.emit(Instruction::End); // End loop
+        self.emit(Instruction::End); // End epilogue block
+        self.block_depth -= 1;
+
+        // EPILOGUE: The remaining iterations, one at a time
+        self.compile_for_loop(Some(&condition), update, inductions, body, 1)?;
+
+        self.emit(Instruction::End); // End outer block
+        self.block_depth -= 1;
+
+        Ok(())
+    }
+
+    /// One copy of a loop body in its continue block; continue lands after it
+    fn compile_loop_copy(&mut self, body: &Block, break_depth: u32) -> Result<(), String> {
+        // INNER BLOCK: Continue target - continue will exit this block
         self.emit(Instruction::Block(BlockType::Empty));
         self.block_depth += 1;
 
-        // Push loop context
+        // Push loop context AFTER creating the blocks
         self.loop_stack.push(super::core::LoopContext {
-            break_depth: 2,
-            continue_depth: 0,
-            loop_type: super::core::LoopType::For,
-            entry_block_depth: self.block_depth,
+            break_depth,
+            continue_depth: 0, // Exit inner block to reach update
+            entry_block_depth: self.block_depth, // Current depth when entering loop body
         });
 
-        // UNROLLED BODY: Execute body unroll_factor times
-        for unroll_iter in 0..unroll_factor {
-            // Skip condition check after first iteration in unrolled body
-            if unroll_iter > 0 {
-                // Check if we've reached the end
-                if let Some(cond) = condition {
-                    self.compile_expression_as_bool_i32(cond.clone())?;
-                    self.emit(Instruction::I32Eqz);
-                    self.emit(Instruction::BrIf(2)); // Exit loop if done
-                }
-            }
+        for stmt in &body.statements {
+            self.compile_statement(stmt.clone())?;
+        }
 
-            // Execute loop body
-            for stmt in &body.statements {
-                self.compile_statement(stmt.clone())?;
-            }
+        // Pop loop context before the blocks end
+        self.loop_stack.pop();
+
+        self.emit(Instruction::End); // End inner block (continue target)
+        self.block_depth -= 1;
+        Ok(())
+    }
 
-            // Execute update (increment counter)
-            if let Some(update_expr) = update {
-                self.compile_expression_void(update_expr.clone())?;
+    /// The update of a for loop, and the induction variables growing with its counter
+    fn compile_for_update(&mut self, update: &Option<Expression>, inductions: &[(u32, i64)]) -> Result<(), String> {
+        if let Some(update_expr) = update {
+            self.compile_expression_void(update_expr.clone())?;
+        }
+        for &(local, step) in inductions {
+            self.emit(Instruction::LocalGet(local));
+            self.emit(Instruction::I64Const(step));
+            self.emit(Instruction::I64Add);
+            self.emit(Instruction::LocalSet(local));
+        }
+        Ok(())
+    }
+
+    /// Count each array the condition counts once, into an unboxed temporary
+    /// the condition reads instead, when the loop cannot change the array
+    fn hoist_loop_counts(&mut self, condition: &mut Expression, writes: &LoopWrites) -> Result<Vec<String>, String> {
+        let runs_code = self.runs_impure_code(writes);
+        self.hoist_loop_counts_where(condition, runs_code, |array| !writes.may_write(array))
+    }
+
+    /// `runs_code`: the loop calls code that may change any array
+    fn hoist_loop_counts_where(&mut self, condition: &mut Expression, runs_code: bool, unchanged: impl Fn(&str) -> bool) -> Result<Vec<String>, String> {
+        let mut counts = std::collections::HashMap::new();
+        if !self.options.loop_optimizations_enabled() || runs_code {
+            return Ok(Vec::new());
+        }
+        for array in counted_arrays(condition) {
+            if !unchanged(&array) || !self.is_loop_local(&array) {
+                continue;
             }
+            self.compile_expression(Expression::Variable(array.clone()))?;
+            self.emit(Instruction::Call(self.count_fn_idx));
+            self.emit(Instruction::I64ExtendI32U);
+            let local = self.allocate_local(ValType::I64);
+            self.emit(Instruction::LocalSet(local));
+            counts.insert(array, self.bind_int_temporary(local));
         }
+        replace_counts(condition, &counts);
+        Ok(counts.into_values().collect())
+    }
 
-        // Pop loop context
-        self.loop_stack.pop();
+    /// Bind an i64 local to a temporary variable that type inference knows is an int
+    fn bind_int_temporary(&mut self, local: u32) -> String {
+        let name = self.next_temp_name();
+        self.variables.insert(name.clone(), VariableInfo {
+            local_idx: local,
+            storage_type: VariableStorage::UnboxedInt,
+            class_type: None,
+        });
+        self.type_inference.declare_temporary(&name, super::type_inference::InferredType::Int);
+        name
+    }
 
-        self.emit(Instruction::End); // End inner block
-        self.block_depth -= 1;
+    fn remove_temporaries(&mut self, names: Vec<String>) {
+        for name in names {
+            self.variables.remove(&name);
+        }
+    }
 
-        // Jump back to loop start
-        self.emit(Instruction::Br(0));
+    /// Whether a loop calls user code other than pure functions, which may change
+    /// variables the loop does not name
+    fn runs_impure_code(&self, writes: &LoopWrites) -> bool {
+        writes.runs_code(|name| !self.functions.contains_key(name) || self.pure_functions.contains(name))
+    }
 
-        self.emit(Instruction::End); // End loop
-        self.emit(Instruction::End); // End outer block
-        self.block_depth -= 1;
+    /// A variable only the code of this scope can change: not a superglobal and not
+    /// kept in a wasm global, which functions the loop calls can reach
+    pub(super) fn is_loop_local(&self, name: &str) -> bool {
+        !super::type_inference::SUPERGLOBALS.contains(&name)
+            && !matches!(self.variables.get(name), Some(VariableInfo { storage_type: VariableStorage::Global(_), .. }))
+    }
+
+    /// A bound the unrolled guard can add to: an int constant or a variable known to hold an int
+    fn is_int_bound(&mut self, bound: &LoopBound) -> bool {
+        match bound {
+            LoopBound::Constant(_) => true,
+            LoopBound::Variable(name) => {
+                self.is_loop_local(name)
+                    && self.type_inference.infer_expression(&Expression::Variable(name.clone())) == super::type_inference::InferredType::Int
+            }
+        }
+    }
+
+    /// PHASE 4: Compile user-defined function and its specialized copies
+    pub(super) fn compile_function_definition(&mut self, name: &str, params: &[edge_php_parser::ast::Parameter], return_type: Option<&edge_php_parser::ast::Type>, body: Block) -> Result<(), String> {
+        Self::check_no_promotion(params)?;
 
+        // Top-level functions are declared before anything is compiled; others
+        // reserve their index before the body, which enables recursion
+        let func_idx = match self.functions.get(name) {
+            Some(&func_idx) => func_idx,
+            None => self.declare_function(name, params)?,
+        };
+        self.compile_function_body(name, func_idx, params, return_type, &body, None)?;
+
+        for specialization in self.type_inference.specializations(name) {
+            let func_idx = self.specialized_functions[&specialization.key];
+            self.compile_function_body(name, func_idx, params, return_type, &body, Some(specialization))?;
+        }
         Ok(())
     }
 
-    /// PHASE 4: Compile user-defined function
-    pub(super) fn compile_function_definition(&mut self, name: &str, params: &[edge_php_parser::ast::Parameter], body: Block) -> Result<(), String> {
-        // Create function type: (params...) -> (result)
-        // All parameters and return value are PhpValue
-        let param_types = vec![self.get_php_value_type(); params.len()];
-        let result_types = vec![self.get_php_value_type()];
-        let func_type = self.builder.add_type(param_types, result_types);
+    /// Compile a body of a user function: the generic one, which takes and
+    /// returns PhpValues, or a specialized copy
+    fn compile_function_body(&mut self, name: &str, func_idx: u32, params: &[edge_php_parser::ast::Parameter], return_type: Option<&edge_php_parser::ast::Type>, body: &Block, specialization: Option<Specialization>) -> Result<(), String> {
+        // Bodies the mid-level IR can express are compiled through it
+        let lowered = self.compile_to_ir(name, params, return_type, body, specialization.as_ref());
 
-        // Reserve function index BEFORE compiling body (enables recursion/forward references)
-        let func_idx = self.builder.reserve_function_index();
-        self.functions.insert(name.to_string(), func_idx);
+        // Create function type: (params...) -> (result)
+        // Parameters and the return value are PhpValues, except where a copy unboxes them
+        let (param_types, result_type) = match &specialization {
+            Some(specialization) => (
+                specialization.params.iter().map(|ty| self.specialized_val_type(ty)).collect(),
+                self.specialized_val_type(&specialization.return_type),
+            ),
+            None => (vec![self.get_php_value_type(); params.len()], self.get_php_value_type()),
+        };
+        let func_type = self.builder.add_type(param_types, vec![result_type]);
 
         // Save current function context
         let saved_function = self.current_function.take();
         let saved_variables = self.variables.clone();
         let saved_block_depth = self.block_depth;
+        let saved_static_scope = std::mem::replace(&mut self.static_scope, name.to_string());
+        let saved_specialization = std::mem::replace(&mut self.specialization, specialization);
 
         // Create new function context
         // local_count starts at params.len() because parameters occupy the first N local indices
@@ -708,8 +966,10 @@ impl Compiler {
             locals: vec![],
             body: vec![],
             local_count: params.len() as u32,
+            locations: Vec::new(),
         });
         self.variables.clear();
+        self.bind_superglobals();
         self.block_depth = 0;
 
         // PHASE 2C: Clear the local pool for the new function
@@ -720,49 +980,54 @@ impl Compiler {
         for (idx, param) in params.iter().enumerate() {
             self.variables.insert(param.name.clone(), VariableInfo {
                 local_idx: idx as u32,
-                storage_type: VariableStorage::Boxed,
+                storage_type: self.parameter_storage(idx),
                 class_type: None,
             });
         }
+        if let Some(function) = lowered {
+            self.emit_ir_function(&function)?;
+        } else {
+            let saved_types = self.enter_typed_function(name, params, return_type, 0, body)?;
 
-        // Compile function body
-        for stmt in body.statements {
-            self.compile_statement(stmt)?;
-        }
+            // Compile function body
+            for stmt in body.statements.clone() {
+                self.compile_statement(stmt)?;
+            }
 
-        // If no explicit return, return null
-        self.emit(Instruction::Call(self.create_null_fn_idx));
-        self.emit(Instruction::Return);
+            // If no explicit return, return null
+            self.emit_implicit_return()?;
+            self.leave_typed_function(saved_types);
+        }
 
         // Get the compiled function
-        let func_ctx = self.current_function.take().unwrap();
-
-        // Use deferred compilation (same pattern as runtime functions)
-        // Note: func_idx was already reserved and added to functions map before compiling body
-        self.builder.set_function_at_index(func_idx, func_type, func_ctx.locals, func_ctx.body);
+        self.name_locals(func_idx);
+        self.finish_function(func_idx, func_type);
 
         // Restore previous function context
         self.current_function = saved_function;
         self.variables = saved_variables;
         self.block_depth = saved_block_depth;
+        self.static_scope = saved_static_scope;
+        self.specialization = saved_specialization;
 
         Ok(())
     }
 
-    /// PHASE 4: Compile return statement
-    fn compile_return_statement(&mut self, expr_opt: &Option<Expression>) -> Result<(), String> {
-        if let Some(expr) = expr_opt {
-            // Compile the return expression
-            self.compile_expression(expr.clone())?;
-        } else {
-            // No expression - return null
-            self.emit(Instruction::Call(self.create_null_fn_idx));
+    pub(super) fn declare_function(&mut self, name: &str, params: &[edge_php_parser::ast::Parameter]) -> Result<u32, String> {
+        if self.functions.contains_key(name) {
+            return Err(format!("Cannot redeclare function {}()", name));
         }
+        let func_idx = self.builder.reserve_function_index();
+        self.name_function(func_idx, name);
+        self.functions.insert(name.to_string(), func_idx);
+        self.function_params.insert(name.to_string(), params.to_vec());
+        self.declare_specializations(name);
+        Ok(func_idx)
+    }
 
-        // Emit return instruction
-        self.emit(Instruction::Return);
-
-        Ok(())
+    /// PHASE 4: Compile return statement (checked against the declared return type)
+    fn compile_return_statement(&mut self, expr_opt: &Option<Expression>) -> Result<(), String> {
+        self.compile_typed_return(expr_opt.clone())
     }
 
     fn compile_switch(&mut self, expr: Expression, cases: Vec<edge_php_parser::ast::SwitchCase>) -> Result<(), String> {
@@ -779,12 +1044,10 @@ impl Compiler {
         self.loop_stack.push(super::core::LoopContext {
             break_depth: 0,  // Break exits the block we just created
             continue_depth: 0,
-            loop_type: super::core::LoopType::While, // Reuse While type
             entry_block_depth: self.block_depth,
         });
 
         let mut default_case: Option<&edge_php_parser::ast::SwitchCase> = None;
-        let mut has_matched = false;
 
         // First pass: handle all case statements (not default)
         for case in &cases {
@@ -1,8 +1,55 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
+// Type inference across the call graph
+//
+// Every scope - the main program, each function and each method, keyed
+// "Class::method" - gets its own variable types, and every user function and
+// method gets a signature. Parameter types come from the declaration when
+// there is one, and otherwise from the arguments of every call site (a
+// missing argument contributes its default, or null); return types come from
+// the declaration or from every returned value. Calls that cannot be seen
+// statically make a function's parameters dynamic: its name appearing in a
+// string literal (callbacks), or it being a magic method the runtime calls.
+// Method calls whose receiver is unknown count as calls of every method with
+// that name.
+//
+// The analysis starts out knowing nothing ("never" in reports) and widens
+// until nothing changes. Types are joined exactly - an int and a float give
+// dynamic - because a type is only useful to code generation when every value
+// really has it.
+//
+// Functions called with int or float arguments from a loop or another
+// function body get specialized copies (see the specialization module): a
+// copy is keyed by the argument types it takes unboxed, like "fib<int>", and
+// is analyzed as a scope of its own with those parameter types. Direct calls
+// whose arguments match a copy's types go to it. A function gets at most as
+// many copies as the optimization level allows, and copies that nothing calls
+// once the analysis settles are dropped.
+//
+// Arrays are values in PHP, but compiled code may share them between
+// variables, so element types are not tracked per array: every type stored
+// into any array element anywhere in the program is joined into the element
+// type of every array. Reads of missing keys are not modelled.
+
+use super::types::WrittenVariables;
 use edge_php_parser::ast::*;
-use std::collections::HashMap;
+use edge_php_parser::visit::{self, Visitor};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// Key of the top-level scope
+const MAIN_SCOPE: &str = "{main}";
+
+/// Arrays nested deeper than this are only known to be arrays
+const MAX_ARRAY_DEPTH: usize = 2;
+
+/// Rounds over the whole program before the analysis gives up on a program
+/// and leaves every type dynamic
+const MAX_ROUNDS: usize = 64;
+
+/// Variables the runtime fills in, which assignments never fully describe
+pub(super) const SUPERGLOBALS: [&str; 9] = ["GLOBALS", "_GET", "_POST", "_SERVER", "_COOKIE", "_FILES", "_REQUEST", "_ENV", "_SESSION"];
 
 /// Inferred type information for compile-time optimization
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -19,6 +66,10 @@ pub enum InferredType {
     Null,
     /// Known array (element types may vary)
     Array,
+    /// Array whose elements all have the given type
+    ArrayOf(Box<InferredType>),
+    /// Instance of exactly this class or enum
+    Object(String),
     /// Unknown type - requires runtime type checking
     Dynamic,
 }
@@ -34,19 +85,137 @@ impl InferredType {
         !matches!(self, InferredType::Dynamic)
     }
 
-    /// Merge two types (used for phi nodes in control flow)
-    pub fn merge(&self, other: &InferredType) -> InferredType {
+    /// Check if this is an array, whether or not its element type is known
+    pub fn is_array(&self) -> bool {
+        matches!(self, InferredType::Array | InferredType::ArrayOf(_))
+    }
+
+    /// An array of `element`, normalized so that equal types compare equal
+    pub fn array_of(element: InferredType) -> InferredType {
+        match element {
+            InferredType::Dynamic => InferredType::Array,
+            element if element.array_depth() >= MAX_ARRAY_DEPTH => InferredType::ArrayOf(Box::new(InferredType::Array)),
+            element => InferredType::ArrayOf(Box::new(element)),
+        }
+    }
+
+    /// Type of the elements of an array of this type
+    pub fn element_type(&self) -> InferredType {
+        match self {
+            InferredType::ArrayOf(element) => (**element).clone(),
+            _ => InferredType::Dynamic,
+        }
+    }
+
+    fn array_depth(&self) -> usize {
+        match self {
+            InferredType::Array => 1,
+            InferredType::ArrayOf(element) => 1 + element.array_depth(),
+            _ => 0,
+        }
+    }
+
+    /// The type a declaration guarantees once it is enforced; Dynamic unless it is
+    /// a single scalar or array type
+    pub fn from_declaration(declared: &Type) -> InferredType {
+        match declared {
+            Type::Int => InferredType::Int,
+            Type::Float => InferredType::Float,
+            Type::String => InferredType::String,
+            Type::Bool => InferredType::Bool,
+            Type::Array => InferredType::Array,
+            _ => InferredType::Dynamic,
+        }
+    }
+
+    /// The type of a value that has either type; only equal types (or arrays,
+    /// whose element types are joined) stay known
+    pub fn unify(&self, other: &InferredType) -> InferredType {
         if self == other {
             self.clone()
-        } else if self.is_numeric() && other.is_numeric() {
+        } else if self.is_array() && other.is_array() {
+            InferredType::array_of(self.element_type().unify(&other.element_type()))
+        } else {
+            InferredType::Dynamic
+        }
+    }
+
+    /// Merge two types (used for phi nodes in control flow)
+    pub fn merge(&self, other: &InferredType) -> InferredType {
+        if self.is_numeric() && other.is_numeric() && self != other {
             // Int + Float = Float
             InferredType::Float
         } else {
-            InferredType::Dynamic
+            self.unify(other)
+        }
+    }
+}
+
+impl fmt::Display for InferredType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InferredType::Int => write!(f, "int"),
+            InferredType::Float => write!(f, "float"),
+            InferredType::String => write!(f, "string"),
+            InferredType::Bool => write!(f, "bool"),
+            InferredType::Null => write!(f, "null"),
+            InferredType::Array => write!(f, "array"),
+            InferredType::ArrayOf(element) => write!(f, "{}[]", element),
+            InferredType::Object(class) => write!(f, "{}", class),
+            InferredType::Dynamic => write!(f, "mixed"),
+        }
+    }
+}
+
+/// Join of two possibly unknown types; None means no value has been seen yet
+pub(super) fn join(a: Option<InferredType>, b: Option<InferredType>) -> Option<InferredType> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.unify(&b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Type of the result of a binary operator on values of these types
+pub(super) fn binary_result_type(left: &InferredType, op: &BinaryOp, right: &InferredType) -> InferredType {
+    match op {
+        BinaryOp::Add if left.is_array() && right.is_array() => left.unify(right),
+        BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply => {
+            if left == &InferredType::Int && right == &InferredType::Int {
+                InferredType::Int
+            } else if left.is_numeric() && right.is_numeric() {
+                InferredType::Float
+            } else {
+                InferredType::Dynamic
+            }
+        }
+        // int / int is an int when it divides evenly
+        BinaryOp::Divide => {
+            if left.is_numeric() && right.is_numeric() && (left == &InferredType::Float || right == &InferredType::Float) {
+                InferredType::Float
+            } else {
+                InferredType::Dynamic
+            }
         }
+        BinaryOp::Modulo => {
+            if left.is_numeric() && right.is_numeric() {
+                InferredType::Int
+            } else {
+                InferredType::Dynamic
+            }
+        }
+        BinaryOp::Concat => InferredType::String,
+        BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Identical | BinaryOp::NotIdentical |
+        BinaryOp::GreaterThan | BinaryOp::LessThan | BinaryOp::GreaterThanOrEqual |
+        BinaryOp::LessThanOrEqual => InferredType::Bool,
+        BinaryOp::And | BinaryOp::Or => InferredType::Bool,
     }
 }
 
+fn type_label(ty: &Option<InferredType>) -> String {
+    ty.as_ref().map_or_else(|| "never".to_string(), |ty| ty.to_string())
+}
+
 /// Type information for a specific variable
 #[derive(Debug, Clone)]
 pub struct VariableTypeInfo {
@@ -57,265 +226,1030 @@ pub struct VariableTypeInfo {
     pub assignment_count: usize,
 }
 
+/// What is known about the calls of a user function or method
+#[derive(Debug, Clone)]
+struct Signature {
+    params: Vec<ParamSignature>,
+    /// Joined type of every returned value; None while none has been seen
+    return_type: Option<InferredType>,
+    /// The return type is declared, so returned values are not collected
+    declared_return: bool,
+}
+
+#[derive(Debug, Clone)]
+struct ParamSignature {
+    name: String,
+    /// Joined type of every argument; None while no call has been seen
+    inferred_type: Option<InferredType>,
+    /// Declared, or called in ways the analysis cannot see: calls do not change it
+    fixed: bool,
+    /// The type comes from a declaration, which converts other arguments
+    declared: bool,
+    /// Type a call without this argument passes
+    missing: InferredType,
+}
+
+/// A function or method body to analyze
+struct Callable<'p> {
+    key: String,
+    class: Option<String>,
+    params: &'p [Parameter],
+    body: &'p Block,
+    return_type: Option<&'p Type>,
+}
+
+/// A specialized copy of a user function, for calls whose arguments have its
+/// parameter types
+#[derive(Debug, Clone, PartialEq)]
+pub struct Specialization {
+    /// Scope of the copy and its name in debug info, e.g. "fib<int>"
+    pub key: String,
+    /// Int and float parameters are passed unboxed, Dynamic ones boxed
+    pub params: Vec<InferredType>,
+    /// Int and float results are returned unboxed, anything else boxed
+    pub return_type: InferredType,
+}
+
+/// Type state of the scope being compiled, saved while a nested body compiles
+pub struct ScopeTypes {
+    variable_types: HashMap<String, VariableTypeInfo>,
+    declared_types: HashMap<String, InferredType>,
+    current_class: Option<String>,
+}
+
 /// Type inference engine for compile-time optimization
 pub struct TypeInference {
-    /// Maps variable names to their inferred types
+    /// Maps variable names to their inferred types, in the scope being analyzed or compiled
     variable_types: HashMap<String, VariableTypeInfo>,
+    /// Variable types of every scope
+    scope_types: HashMap<String, HashMap<String, VariableTypeInfo>>,
     /// Track if we're in the first pass (collecting assignments)
     first_pass: bool,
+    /// Enforced parameter types of the function being compiled; they take
+    /// precedence over the name-based types above
+    declared_types: HashMap<String, InferredType>,
+    /// Signatures of user functions ("name") and methods ("Class::method")
+    signatures: HashMap<String, Signature>,
+    /// Signature keys of the methods with a given lowercase name
+    methods: HashMap<String, Vec<String>>,
+    /// Declared classes and enums by lowercase name, and the cases of each enum
+    classes: HashMap<String, String>,
+    enum_cases: HashMap<String, Vec<String>>,
+    /// Class of `$this`, `self` and `static` in the current scope
+    current_class: Option<String>,
+    /// Every type stored into an array element; None while there is none
+    element_types: Option<InferredType>,
+    /// Whether assignments, calls and returns are being recorded
+    analyzing: bool,
+    /// Variables the scope being analyzed assigns somewhere; reading one that
+    /// has no type yet gives no type instead of Dynamic
+    assigned: HashSet<String>,
+    /// Joined type of the values the scope being analyzed returns
+    returns: Option<InferredType>,
+    /// A variable type changed during this walk of the scope
+    scope_changed: bool,
+    /// A signature or the array element type changed during this round
+    changed: bool,
+    /// The analysis did not settle, so nothing is known
+    gave_up: bool,
+    /// Most specialized copies of one function; 0 turns specialization off
+    max_specializations: usize,
+    /// Parameter types and key of every copy of each function that may be
+    /// specialized, and the (function, key) of every copy in creation order
+    specializations: HashMap<String, Vec<(Vec<InferredType>, String)>>,
+    copies: Vec<(String, String)>,
+    /// Copies each scope called in its latest walk, and in the walk in progress
+    specialized_calls: HashMap<String, HashSet<String>>,
+    called: HashSet<String>,
+    /// The expression being analyzed is in a function body, or in a loop:
+    /// calls from there make specialized copies
+    in_function: bool,
+    loop_depth: usize,
+}
+
+impl Default for TypeInference {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TypeInference {
     pub fn new() -> Self {
         TypeInference {
             variable_types: HashMap::new(),
+            scope_types: HashMap::new(),
             first_pass: true,
+            declared_types: HashMap::new(),
+            signatures: HashMap::new(),
+            methods: HashMap::new(),
+            classes: HashMap::new(),
+            enum_cases: HashMap::new(),
+            current_class: None,
+            element_types: None,
+            analyzing: false,
+            assigned: HashSet::new(),
+            returns: None,
+            scope_changed: false,
+            changed: false,
+            gave_up: false,
+            max_specializations: 0,
+            specializations: HashMap::new(),
+            copies: Vec::new(),
+            specialized_calls: HashMap::new(),
+            called: HashSet::new(),
+            in_function: false,
+            loop_depth: 0,
         }
     }
 
+    /// Let each user function have up to `max` specialized copies
+    pub fn set_max_specializations(&mut self, max: usize) {
+        self.max_specializations = max;
+    }
+
     /// Run type inference on a program
     pub fn analyze_program(&mut self, program: &Program) {
-        // First pass: collect variable assignments and count them
-        self.first_pass = true;
-        for item in &program.items {
-            match item {
-                ProgramItem::PhpBlock { statements } => {
-                    for stmt in statements {
-                        self.analyze_statement(stmt);
-                    }
-                }
-                ProgramItem::InlineContent(_) => {}
+        let main: Vec<&Statement> = program.items.iter()
+            .flat_map(|item| match item {
+                ProgramItem::PhpBlock { statements } => statements.iter(),
+                ProgramItem::InlineContent(_) => [].iter(),
+            })
+            .collect();
+
+        let mut callables = Vec::new();
+        for stmt in &main {
+            self.collect_declarations(stmt, &mut callables);
+        }
+
+        let mut facts = ProgramFacts::default();
+        visit::walk_program(&mut facts, program);
+        for callable in &callables {
+            let signature = self.initial_signature(callable, &facts.strings);
+            self.signatures.insert(callable.key.clone(), signature);
+            // A copy of a function declaring others would declare them twice
+            if self.max_specializations > 0 && callable.class.is_none() && !declares_anything(callable.body) {
+                self.specializations.insert(callable.key.clone(), Vec::new());
+            }
+        }
+        let functions: HashMap<&str, &Callable> = callables.iter()
+            .filter(|callable| callable.class.is_none())
+            .map(|callable| (callable.key.as_str(), callable))
+            .collect();
+
+        // Top-level variables that functions can write to are never known
+        let mut main_dynamic = facts.globals;
+        if facts.dynamic_globals {
+            let mut written = WrittenVariables::default();
+            for stmt in &main {
+                written.visit_statement(stmt);
+            }
+            main_dynamic.extend(written.0);
+        }
+
+        self.analyzing = true;
+        let mut settled = false;
+        for round in 0..MAX_ROUNDS {
+            self.changed = false;
+            self.analyze_scope(MAIN_SCOPE, None, &[], &main, &main_dynamic, round == 0);
+            for callable in &callables {
+                let body: Vec<&Statement> = callable.body.statements.iter().collect();
+                let returns = self.analyze_scope(&callable.key, callable.class.clone(), callable.params, &body, &HashSet::new(), round == 0);
+                self.record_returns(&callable.key, returns, can_complete(&callable.body.statements));
             }
+            // Copies made during this round are analyzed in it too
+            let mut position = 0;
+            while let Some((function, key)) = self.copies.get(position).cloned() {
+                let callable = functions[function.as_str()];
+                let body: Vec<&Statement> = callable.body.statements.iter().collect();
+                let first_walk = !self.scope_types.contains_key(&key);
+                let returns = self.analyze_scope(&key, None, callable.params, &body, &HashSet::new(), first_walk);
+                self.record_returns(&key, returns, can_complete(&callable.body.statements));
+                position += 1;
+            }
+            if !self.changed {
+                settled = true;
+                break;
+            }
+        }
+        self.analyzing = false;
+
+        if !settled {
+            self.gave_up = true;
+            self.scope_types.clear();
+            self.signatures.clear();
+            self.specializations.clear();
+            self.copies.clear();
+            self.element_types = Some(InferredType::Dynamic);
+        } else {
+            let roots = std::iter::once(MAIN_SCOPE).chain(callables.iter().map(|callable| callable.key.as_str()));
+            self.drop_uncalled_copies(roots);
         }
 
         // Second pass: finalize types based on stability
-        self.first_pass = false;
-        for (_, info) in self.variable_types.iter_mut() {
+        for info in self.scope_types.values_mut().flat_map(|scope| scope.values_mut()) {
             // If variable is assigned multiple times, mark as potentially unstable
-            if info.assignment_count > 1 {
-                // For now, keep the type but mark as unstable
-                // In future, we could do more sophisticated analysis
-                info.is_stable = false;
+            info.is_stable = info.assignment_count <= 1;
+        }
+        self.variable_types = self.scope_types.get(MAIN_SCOPE).cloned().unwrap_or_default();
+        self.current_class = None;
+    }
+
+    /// Collect the functions, methods, classes and enums declared in a statement
+    fn collect_declarations<'p>(&mut self, stmt: &'p Statement, callables: &mut Vec<Callable<'p>>) {
+        match stmt {
+            Statement::Function { name, params, body, return_type } => {
+                callables.push(Callable { key: name.clone(), class: None, params, body, return_type: return_type.as_ref() });
+                for stmt in &body.statements {
+                    self.collect_declarations(stmt, callables);
+                }
+            }
+            Statement::Class { name, members, .. } | Statement::Enum { name, members, .. } => {
+                self.classes.insert(name.to_lowercase(), name.clone());
+                for member in members {
+                    let (method, params, body, return_type) = match member {
+                        ClassMember::Method { name, params, body, return_type, .. } => (name.as_str(), params, body, return_type.as_ref()),
+                        ClassMember::Constructor { params, body, .. } => ("__construct", params, body, None),
+                        ClassMember::Case { name: case, .. } => {
+                            self.enum_cases.entry(name.clone()).or_default().push(case.clone());
+                            continue;
+                        }
+                        _ => continue,
+                    };
+                    let key = format!("{}::{}", name, method);
+                    self.methods.entry(method.to_lowercase()).or_default().push(key.clone());
+                    callables.push(Callable { key, class: Some(name.clone()), params, body, return_type });
+                    for stmt in &body.statements {
+                        self.collect_declarations(stmt, callables);
+                    }
+                }
+            }
+            Statement::If { then_block, elseif_blocks, else_block, .. } => {
+                let blocks = std::iter::once(then_block)
+                    .chain(elseif_blocks.iter().map(|elseif| &elseif.then_block))
+                    .chain(else_block);
+                for block in blocks {
+                    for stmt in &block.statements {
+                        self.collect_declarations(stmt, callables);
+                    }
+                }
+            }
+            Statement::While { body, .. }
+            | Statement::DoWhile { body, .. }
+            | Statement::For { body, .. }
+            | Statement::Foreach { body, .. }
+            | Statement::Block(body) => {
+                for stmt in &body.statements {
+                    self.collect_declarations(stmt, callables);
+                }
+            }
+            Statement::Namespace(namespace) => {
+                for stmt in &namespace.body.statements {
+                    self.collect_declarations(stmt, callables);
+                }
+            }
+            Statement::Switch { cases, .. } => {
+                for stmt in cases.iter().flat_map(|case| &case.statements) {
+                    self.collect_declarations(stmt, callables);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Declared parameter and return types; the rest is unknown until calls are seen
+    fn initial_signature(&self, callable: &Callable, strings: &HashSet<String>) -> Signature {
+        let name = callable.key.rsplit("::").next().unwrap_or(&callable.key).to_lowercase();
+        let short_name = name.rsplit('\\').next().unwrap_or(&name);
+        let is_magic = callable.class.is_some() && name.starts_with("__") && name != "__construct";
+        let called_dynamically = is_magic || strings.contains(&name) || strings.contains(short_name);
+
+        let params = callable.params.iter().map(|param| {
+            let declared = param.param_type.as_ref().map(|declared| match param.default {
+                // `int $x = null` is implicitly nullable
+                Some(Expression::Literal(Literal::Null)) => InferredType::Dynamic,
+                _ => self.declared_type(declared, callable.class.as_deref()),
+            });
+            let missing = match &param.default {
+                Some(Expression::Literal(literal)) => literal_type(literal),
+                Some(_) => InferredType::Dynamic,
+                None => InferredType::Null,
+            };
+            ParamSignature {
+                name: param.name.clone(),
+                fixed: declared.is_some() || called_dynamically,
+                declared: declared.is_some(),
+                inferred_type: declared.or(called_dynamically.then_some(InferredType::Dynamic)),
+                missing,
+            }
+        }).collect();
+
+        let return_type = callable.return_type.map(|declared| match declared {
+            Type::Void => Some(InferredType::Null),
+            Type::Never => None,
+            declared => Some(self.declared_type(declared, callable.class.as_deref())),
+        });
+        Signature {
+            params,
+            declared_return: return_type.is_some(),
+            return_type: return_type.flatten(),
+        }
+    }
+
+    /// The type an enforced declaration guarantees; exact for scalars, arrays and classes
+    fn declared_type(&self, declared: &Type, class: Option<&str>) -> InferredType {
+        match declared {
+            Type::Object(name) if name.eq_ignore_ascii_case("self") || name.eq_ignore_ascii_case("static") => {
+                class.map_or(InferredType::Dynamic, |class| InferredType::Object(class.to_string()))
+            }
+            Type::Object(name) => self.classes.get(&name.to_lowercase())
+                .map_or(InferredType::Dynamic, |class| InferredType::Object(class.clone())),
+            declared => InferredType::from_declaration(declared),
+        }
+    }
+
+    /// Walk a scope until its variable types stop changing, returning the joined
+    /// type of its return values
+    fn analyze_scope(&mut self, scope: &str, class: Option<String>, params: &[Parameter], body: &[&Statement], dynamic: &HashSet<String>, first_round: bool) -> Option<InferredType> {
+        self.variable_types = self.scope_types.remove(scope).unwrap_or_default();
+        self.current_class = class;
+        self.first_pass = first_round;
+        self.in_function = scope != MAIN_SCOPE;
+        self.loop_depth = 0;
+
+        let mut written = WrittenVariables::default();
+        for stmt in body {
+            written.visit_statement(stmt);
+        }
+        self.assigned = written.0.into_iter().chain(params.iter().map(|param| param.name.clone())).collect();
+
+        let param_types: Vec<Option<InferredType>> = self.signatures.get(scope)
+            .map(|signature| signature.params.iter().map(|param| param.inferred_type.clone()).collect())
+            .unwrap_or_default();
+        for (param, param_type) in params.iter().zip(param_types) {
+            if let Some(param_type) = param_type {
+                self.record_variable_type(&param.name, param_type);
+            }
+        }
+        for name in dynamic {
+            self.record_variable_type(name, InferredType::Dynamic);
+        }
+
+        loop {
+            self.scope_changed = false;
+            self.returns = None;
+            self.called.clear();
+            for stmt in body {
+                self.analyze_statement(stmt);
+            }
+            self.first_pass = false;
+            if !self.scope_changed {
+                break;
             }
         }
+
+        self.specialized_calls.insert(scope.to_string(), std::mem::take(&mut self.called));
+        self.scope_types.insert(scope.to_string(), std::mem::take(&mut self.variable_types));
+        self.returns.take()
+    }
+
+    fn record_returns(&mut self, key: &str, returns: Option<InferredType>, falls_through: bool) {
+        let Some(signature) = self.signatures.get_mut(key) else { return };
+        if signature.declared_return {
+            return;
+        }
+        let returns = join(returns, falls_through.then_some(InferredType::Null));
+        let joined = join(signature.return_type.clone(), returns);
+        if joined != signature.return_type {
+            signature.return_type = joined;
+            self.changed = true;
+        }
     }
 
     fn analyze_statement(&mut self, stmt: &Statement) {
         match stmt {
             Statement::Expression(expr) => {
-                self.infer_expression(expr);
+                self.infer(expr);
             }
             Statement::Echo(expressions) => {
                 for expr in expressions {
-                    self.infer_expression(expr);
+                    self.infer(expr);
                 }
             }
+            Statement::Return(expr) => {
+                let returned = match expr {
+                    Some(expr) => self.infer(expr),
+                    None => Some(InferredType::Null),
+                };
+                self.returns = join(self.returns.take(), returned);
+            }
             Statement::If { condition, then_block, elseif_blocks, else_block } => {
-                self.infer_expression(condition);
-                for stmt in &then_block.statements {
-                    self.analyze_statement(stmt);
-                }
+                self.infer(condition);
+                self.analyze_block(then_block);
                 for elseif in elseif_blocks {
-                    self.infer_expression(&elseif.condition);
-                    for stmt in &elseif.then_block.statements {
-                        self.analyze_statement(stmt);
-                    }
+                    self.infer(&elseif.condition);
+                    self.analyze_block(&elseif.then_block);
                 }
                 if let Some(else_block) = else_block {
-                    for stmt in &else_block.statements {
-                        self.analyze_statement(stmt);
-                    }
+                    self.analyze_block(else_block);
                 }
             }
-            Statement::While { condition, body } => {
-                self.infer_expression(condition);
-                for stmt in &body.statements {
-                    self.analyze_statement(stmt);
-                }
+            Statement::While { condition, body } | Statement::DoWhile { body, condition } => {
+                self.loop_depth += 1;
+                self.infer(condition);
+                self.analyze_block(body);
+                self.loop_depth -= 1;
             }
             Statement::For { init, condition, update, body } => {
                 if let Some(init_stmt) = init {
                     self.analyze_statement(init_stmt);
                 }
+                self.loop_depth += 1;
                 if let Some(cond_expr) = condition {
-                    self.infer_expression(cond_expr);
+                    self.infer(cond_expr);
                 }
                 if let Some(update_expr) = update {
-                    self.infer_expression(update_expr);
-                }
-                for stmt in &body.statements {
-                    self.analyze_statement(stmt);
+                    self.infer(update_expr);
                 }
+                self.analyze_block(body);
+                self.loop_depth -= 1;
             }
             Statement::Foreach { array, key, value, body } => {
-                self.infer_expression(array);
-                // Key and value types depend on array, mark as dynamic for now
+                let array_type = self.infer(array);
+                // Keys may be ints or strings
                 if let Some(key_var) = key {
                     self.record_variable_type(key_var, InferredType::Dynamic);
                 }
-                self.record_variable_type(value, InferredType::Dynamic);
-                for stmt in &body.statements {
-                    self.analyze_statement(stmt);
+                match (value, array_type) {
+                    (Expression::Variable(name), Some(array_type)) => {
+                        self.record_variable_type(name, array_type.element_type());
+                    }
+                    (Expression::Variable(_), None) => {}
+                    (value, _) => self.record_target_dynamic(value),
                 }
+                self.loop_depth += 1;
+                self.analyze_block(body);
+                self.loop_depth -= 1;
             }
-            Statement::Break | Statement::Continue => {}
-            Statement::Block(block) => {
-                for stmt in &block.statements {
-                    self.analyze_statement(stmt);
+            Statement::Switch { expr, cases } => {
+                self.infer(expr);
+                for case in cases {
+                    if let Some(value) = &case.value {
+                        self.infer(value);
+                    }
+                    for stmt in &case.statements {
+                        self.analyze_statement(stmt);
+                    }
                 }
             }
-            // Ignore unimplemented statements
-            _ => {}
+            // Shared with other scopes or calls
+            Statement::Global(names) => {
+                for name in names {
+                    self.record_variable_type(name, InferredType::Dynamic);
+                }
+            }
+            Statement::Static(variables) => {
+                for variable in variables {
+                    if let Some(default) = &variable.default {
+                        self.infer(default);
+                    }
+                    self.record_variable_type(&variable.name, InferredType::Dynamic);
+                }
+            }
+            Statement::Block(block) => self.analyze_block(block),
+            Statement::Namespace(namespace) => self.analyze_block(&namespace.body),
+            // Declarations are scopes of their own
+            Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. } => {}
+            Statement::Break | Statement::Continue | Statement::Use(_) | Statement::Declare(_) | Statement::Location(_) => {}
+        }
+    }
+
+    fn analyze_block(&mut self, block: &Block) {
+        for stmt in &block.statements {
+            self.analyze_statement(stmt);
         }
     }
 
     /// Infer the type of an expression
     pub fn infer_expression(&mut self, expr: &Expression) -> InferredType {
+        self.infer(expr).unwrap_or(InferredType::Dynamic)
+    }
+
+    /// Type of an expression, recording assignments, calls and array stores
+    /// while the program is analyzed; None if it has no value yet
+    fn infer(&mut self, expr: &Expression) -> Option<InferredType> {
         match expr {
-            Expression::Literal(lit) => self.infer_literal(lit),
+            Expression::Literal(Literal::InterpolatedString(parts)) => {
+                for part in parts {
+                    if let InterpolatedPart::Expression(expr) = part {
+                        self.infer(expr);
+                    }
+                }
+                Some(InferredType::String)
+            }
+            Expression::Literal(lit) => Some(literal_type(lit)),
             Expression::Variable(name) => {
-                self.variable_types
-                    .get(name)
-                    .map(|info| info.inferred_type.clone())
-                    .unwrap_or(InferredType::Dynamic)
+                if name == "this" {
+                    return Some(self.current_class.clone().map_or(InferredType::Dynamic, InferredType::Object));
+                }
+                if let Some(declared) = self.declared_types.get(name) {
+                    return Some(declared.clone());
+                }
+                match self.variable_types.get(name) {
+                    Some(info) => Some(info.inferred_type.clone()),
+                    None if self.analyzing && self.assigned.contains(name) => None,
+                    None => Some(InferredType::Dynamic),
+                }
             }
             Expression::Binary { left, op, right } => {
-                let left_type = self.infer_expression(left);
-                let right_type = self.infer_expression(right);
-                self.infer_binary_result(&left_type, op, &right_type)
+                let left_type = self.infer(left);
+                let right_type = self.infer(right);
+                match op {
+                    BinaryOp::Concat => Some(InferredType::String),
+                    BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Identical | BinaryOp::NotIdentical |
+                    BinaryOp::GreaterThan | BinaryOp::LessThan | BinaryOp::GreaterThanOrEqual |
+                    BinaryOp::LessThanOrEqual | BinaryOp::And | BinaryOp::Or => Some(InferredType::Bool),
+                    _ => Some(binary_result_type(&left_type?, op, &right_type?)),
+                }
             }
             Expression::Assignment { left, right } => {
-                let right_type = self.infer_expression(right);
+                let right_type = self.infer(right);
 
                 // Record variable type
-                if let Expression::Variable(name) = &**left {
-                    self.record_variable_type(name, right_type.clone());
+                match &**left {
+                    Expression::Variable(name) => {
+                        if let Some(right_type) = &right_type {
+                            self.record_variable_type(name, right_type.clone());
+                        }
+                    }
+                    // Destructured elements can hold anything
+                    Expression::List(_) => self.record_target_dynamic(left),
+                    Expression::ArrayAccess { array, index } => {
+                        self.infer(array);
+                        self.infer(index);
+                        self.record_element_store(array, right_type.clone());
+                    }
+                    Expression::PropertyAccess { object, .. } => {
+                        self.infer(object);
+                    }
+                    _ => {}
                 }
 
                 right_type
             }
             Expression::FunctionCall { name, args } => {
                 // Analyze arguments
-                for arg in args {
-                    self.infer_expression(arg);
+                let arg_types: Vec<Option<InferredType>> = args.iter().map(|arg| self.infer(arg)).collect();
+
+                // User functions take precedence over built-ins
+                if self.signatures.contains_key(name) {
+                    self.record_call(name, &arg_types);
+                    if let Some(key) = self.specialize_call(name, &arg_types) {
+                        self.record_call(&key, &arg_types);
+                        return self.signatures[&key].return_type.clone();
+                    }
+                    return self.signatures[name].return_type.clone();
                 }
 
                 // Infer return type based on function
                 match name.as_str() {
-                    "count" => InferredType::Int,
-                    "array" => InferredType::Array,
-                    "is_null" | "isset" | "empty" => InferredType::Bool,
-                    _ => InferredType::Dynamic,
+                    "count" | "sizeof" | "strlen" => Some(InferredType::Int),
+                    "array_sum" => match arg_types.first().cloned().flatten().map(|ty| ty.element_type()) {
+                        Some(InferredType::Int) => Some(InferredType::Int),
+                        Some(InferredType::Float) => Some(InferredType::Float),
+                        _ => Some(InferredType::Dynamic),
+                    },
+                    "sort" => Some(InferredType::Bool),
+                    "array" => self.infer_array_literal(arg_types),
+                    "is_int" | "is_integer" | "is_long" | "is_float" | "is_double" | "is_real" | "is_string"
+                    | "is_bool" | "is_array" | "is_object" | "is_null" | "isset" | "empty" => Some(InferredType::Bool),
+                    "array_push" | "array_unshift" => {
+                        for arg_type in arg_types.into_iter().skip(1) {
+                            self.record_element(arg_type);
+                        }
+                        Some(InferredType::Int)
+                    }
+                    "unset" => {
+                        for arg in args {
+                            if let Expression::Variable(name) = arg {
+                                self.record_variable_type(name, InferredType::Null);
+                            }
+                        }
+                        Some(InferredType::Null)
+                    }
+                    _ => Some(InferredType::Dynamic),
                 }
             }
-            Expression::Array(_) => InferredType::Array,
+            Expression::Array(elements) => {
+                let mut element_types = Vec::new();
+                for element in elements {
+                    if let Some(key) = &element.key {
+                        self.infer(key);
+                    }
+                    element_types.push(self.infer(&element.value));
+                }
+                self.infer_array_literal(element_types)
+            }
             Expression::ArrayAccess { array, index } => {
-                self.infer_expression(array);
-                self.infer_expression(index);
-                InferredType::Dynamic // Array element type unknown
+                let array_type = self.infer(array);
+                self.infer(index);
+                match array_type? {
+                    InferredType::String => Some(InferredType::String),
+                    array_type => Some(array_type.element_type()),
+                }
             }
             Expression::Ternary { condition, then_expr, else_expr } => {
-                self.infer_expression(condition);
-                let then_type = self.infer_expression(then_expr);
-                let else_type = self.infer_expression(else_expr);
-                then_type.merge(&else_type)
+                self.infer(condition);
+                let then_type = self.infer(then_expr);
+                let else_type = self.infer(else_expr);
+                join(then_type, else_type)
+            }
+            Expression::Unary { op, expr } => {
+                let operand_type = self.infer(expr);
+                match op {
+                    UnaryOp::Not => Some(InferredType::Bool),
+                    UnaryOp::Negate => match operand_type? {
+                        InferredType::Int => Some(InferredType::Int),
+                        InferredType::Float => Some(InferredType::Float),
+                        _ => Some(InferredType::Dynamic),
+                    },
+                    // Compiled increments and decrements always store an int
+                    UnaryOp::PreIncrement | UnaryOp::PostIncrement | UnaryOp::PreDecrement | UnaryOp::PostDecrement => {
+                        match &**expr {
+                            Expression::Variable(name) => self.record_variable_type(name, InferredType::Int),
+                            Expression::ArrayAccess { .. } => self.record_element(Some(InferredType::Dynamic)),
+                            _ => {}
+                        }
+                        match op {
+                            UnaryOp::PreIncrement | UnaryOp::PreDecrement => Some(InferredType::Int),
+                            _ => operand_type,
+                        }
+                    }
+                }
             }
-            Expression::Unary { expr, .. } => {
-                // Unary operations generally return same type or bool
-                self.infer_expression(expr)
+            Expression::MethodCall { object, method, args } | Expression::NullsafeMethodCall { object, method, args } => {
+                let object_type = self.infer(object);
+                let arg_types: Vec<Option<InferredType>> = args.iter().map(|arg| self.infer(arg)).collect();
+                self.record_method_call(method, &arg_types);
+                match object_type? {
+                    InferredType::Object(class) => self.method_return_type(&class, method),
+                    // The nullsafe operator may short-circuit to null
+                    _ => Some(InferredType::Dynamic),
+                }
             }
-            Expression::MethodCall { object, args, .. } => {
-                self.infer_expression(object);
-                for arg in args {
-                    self.infer_expression(arg);
+            Expression::StaticMethodCall { class, method, args } => {
+                let arg_types: Vec<Option<InferredType>> = args.iter().map(|arg| self.infer(arg)).collect();
+                self.record_method_call(method, &arg_types);
+                match self.resolve_class(class) {
+                    Some(class) => self.method_return_type(&class, method),
+                    None => Some(InferredType::Dynamic),
+                }
+            }
+            Expression::New { class, args } => {
+                let arg_types: Vec<Option<InferredType>> = args.iter().map(|arg| self.infer(arg)).collect();
+                let Some(class) = self.resolve_class(class) else { return Some(InferredType::Dynamic) };
+                self.record_call(&format!("{}::__construct", class), &arg_types);
+                Some(InferredType::Object(class))
+            }
+            Expression::PropertyAccess { object, .. } | Expression::NullsafePropertyAccess { object, .. } => {
+                self.infer(object);
+                Some(InferredType::Dynamic) // Property type unknown
+            }
+            Expression::Coalesce { left, right } => {
+                let left_type = self.infer(left);
+                let right_type = self.infer(right);
+                // Either operand may be the result at runtime, so only an exact match is known
+                match left_type {
+                    Some(InferredType::Null) => right_type,
+                    left_type => join(left_type, right_type),
                 }
-                InferredType::Dynamic // Method return type unknown
             }
-            Expression::PropertyAccess { object, .. } => {
-                self.infer_expression(object);
-                InferredType::Dynamic // Property type unknown
+            Expression::List(_) => Some(InferredType::Dynamic),
+            Expression::ClassConstant { class, name } => {
+                // Enum case or constant
+                let case_class = self.resolve_class(class)
+                    .filter(|class| self.enum_cases.get(class).is_some_and(|cases| cases.contains(name)));
+                Some(case_class.map_or(InferredType::Dynamic, InferredType::Object))
             }
-            Expression::New { args, .. } => {
+            Expression::StaticPropertyAccess { .. } => Some(InferredType::Dynamic),
+            Expression::Include { .. } | Expression::MagicConstant(_) => Some(InferredType::Dynamic),
+            Expression::Clone(expr) => match self.infer(expr)? {
+                InferredType::Object(class) => Some(InferredType::Object(class)),
+                _ => Some(InferredType::Dynamic),
+            },
+            Expression::Invoke { callee, args } => {
+                self.infer(callee);
                 for arg in args {
-                    self.infer_expression(arg);
+                    self.infer(arg);
                 }
-                InferredType::Dynamic // Object type
+                Some(InferredType::Dynamic) // __invoke return type unknown
             }
             Expression::Cast { cast_type, expr } => {
                 // Analyze the expression being cast
-                self.infer_expression(expr);
+                let operand_type = self.infer(expr);
 
                 // Return the target type
-                match cast_type {
+                Some(match cast_type {
                     Type::Int => InferredType::Int,
                     Type::Float => InferredType::Float,
                     Type::String => InferredType::String,
                     Type::Bool => InferredType::Bool,
-                    Type::Array => InferredType::Array,
+                    Type::Array => match operand_type? {
+                        array_type if array_type.is_array() => array_type,
+                        _ => InferredType::Array,
+                    },
+                    _ => InferredType::Dynamic,
+                })
+            }
+        }
+    }
+
+    /// An array holding values of these types, or of any type stored into arrays
+    fn infer_array_literal(&self, element_types: Vec<Option<InferredType>>) -> Option<InferredType> {
+        if element_types.is_empty() {
+            return Some(InferredType::Array);
+        }
+        let mut element_type = self.element_types.clone();
+        for ty in element_types {
+            element_type = join(element_type, Some(ty?));
+        }
+        element_type.map(InferredType::array_of)
+    }
+
+    fn resolve_class(&self, class: &str) -> Option<String> {
+        if class.eq_ignore_ascii_case("self") || class.eq_ignore_ascii_case("static") {
+            self.current_class.clone()
+        } else {
+            self.classes.get(&class.to_lowercase()).cloned()
+        }
+    }
+
+    /// Return type of `Class::method`; Dynamic when the class has no such method
+    fn method_return_type(&self, class: &str, method: &str) -> Option<InferredType> {
+        match self.signatures.get(&format!("{}::{}", class, method)) {
+            Some(signature) => signature.return_type.clone(),
+            None => Some(InferredType::Dynamic),
+        }
+    }
+
+    /// Join the argument types of a call into the callee's parameter types
+    fn record_call(&mut self, key: &str, arg_types: &[Option<InferredType>]) {
+        if !self.analyzing {
+            return;
+        }
+        let Some(signature) = self.signatures.get_mut(key) else { return };
+        for (position, param) in signature.params.iter_mut().enumerate() {
+            if param.fixed {
+                continue;
+            }
+            let arg_type = match arg_types.get(position) {
+                Some(Some(arg_type)) => arg_type.clone(),
+                Some(None) => continue,
+                None => param.missing.clone(),
+            };
+            let joined = join(param.inferred_type.clone(), Some(arg_type));
+            if joined != param.inferred_type {
+                param.inferred_type = joined;
+                self.changed = true;
+            }
+        }
+    }
+
+    /// Parameter types of the copy of `function` for these arguments: ints and
+    /// floats are kept (unless a declaration converts them), anything else is
+    /// passed boxed. None when no argument would be unboxed.
+    fn specialization_params(&self, function: &str, arg_types: &[Option<InferredType>]) -> Option<Vec<InferredType>> {
+        let signature = self.signatures.get(function)?;
+        let params: Vec<InferredType> = signature.params.iter().enumerate()
+            .map(|(position, param)| {
+                let arg_type = match arg_types.get(position) {
+                    Some(arg_type) => arg_type.clone(),
+                    None => Some(param.missing.clone()),
+                };
+                match arg_type {
+                    Some(ty) if ty.is_numeric() && (!param.declared || param.inferred_type.as_ref() == Some(&ty)) => ty,
                     _ => InferredType::Dynamic,
                 }
+            })
+            .collect();
+        params.iter().any(InferredType::is_numeric).then_some(params)
+    }
+
+    /// Key of the copy of `function` a call with these arguments goes to. While
+    /// the program is analyzed, a call from a loop or a function body makes the
+    /// copy if the function may have another one.
+    fn specialize_call(&mut self, function: &str, arg_types: &[Option<InferredType>]) -> Option<String> {
+        let params = self.specialization_params(function, arg_types)?;
+        if let Some(key) = self.copy_of(function, &params) {
+            self.called.insert(key.clone());
+            return Some(key);
+        }
+        let hot = self.in_function || self.loop_depth > 0;
+        if !self.analyzing || !hot || self.specializations.get(function)?.len() >= self.max_specializations {
+            return None;
+        }
+
+        let labels: Vec<String> = params.iter().map(ToString::to_string).collect();
+        let key = format!("{}<{}>", function, labels.join(", "));
+        let generic = &self.signatures[function];
+        let signature = Signature {
+            params: generic.params.iter().zip(&params)
+                .map(|(param, ty)| match ty {
+                    ty if ty.is_numeric() => ParamSignature { inferred_type: Some(ty.clone()), fixed: true, ..param.clone() },
+                    _ if param.fixed => param.clone(),
+                    _ => ParamSignature { inferred_type: None, ..param.clone() },
+                })
+                .collect(),
+            return_type: if generic.declared_return { generic.return_type.clone() } else { None },
+            declared_return: generic.declared_return,
+        };
+        self.signatures.insert(key.clone(), signature);
+        self.specializations.entry(function.to_string()).or_default().push((params, key.clone()));
+        self.copies.push((function.to_string(), key.clone()));
+        self.called.insert(key.clone());
+        self.changed = true;
+        Some(key)
+    }
+
+    fn copy_of(&self, function: &str, params: &[InferredType]) -> Option<String> {
+        self.specializations.get(function)?.iter()
+            .find(|(copy_params, _)| copy_params == params)
+            .map(|(_, key)| key.clone())
+    }
+
+    /// Forget the copies that no scope reachable from `roots` calls
+    fn drop_uncalled_copies<'k>(&mut self, roots: impl Iterator<Item = &'k str>) {
+        let mut pending: Vec<String> = roots.map(str::to_string).collect();
+        let mut live = HashSet::new();
+        while let Some(scope) = pending.pop() {
+            for key in self.specialized_calls.get(&scope).into_iter().flatten() {
+                if live.insert(key.clone()) {
+                    pending.push(key.clone());
+                }
             }
         }
+
+        for (_, key) in self.copies.iter().filter(|(_, key)| !live.contains(key)) {
+            self.signatures.remove(key);
+            self.scope_types.remove(key);
+        }
+        self.copies.retain(|(_, key)| live.contains(key));
+        for copies in self.specializations.values_mut() {
+            copies.retain(|(_, key)| live.contains(key));
+        }
+        self.specialized_calls.clear();
     }
 
-    fn infer_literal(&self, lit: &Literal) -> InferredType {
-        match lit {
-            Literal::Integer(_) => InferredType::Int,
-            Literal::Float(_) => InferredType::Float,
-            Literal::String(_) => InferredType::String,
-            Literal::Boolean(_) => InferredType::Bool,
-            Literal::Null => InferredType::Null,
-            Literal::InterpolatedString(_) => InferredType::String,
+    /// A call of a method on an object of unknown class may reach any method of that name
+    fn record_method_call(&mut self, method: &str, arg_types: &[Option<InferredType>]) {
+        let keys = self.methods.get(&method.to_lowercase()).cloned().unwrap_or_default();
+        for key in keys {
+            self.record_call(&key, arg_types);
         }
     }
 
-    fn infer_binary_result(&self, left: &InferredType, op: &BinaryOp, right: &InferredType) -> InferredType {
-        match op {
-            BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide => {
-                if left == &InferredType::Int && right == &InferredType::Int {
-                    InferredType::Int
-                } else if left.is_numeric() && right.is_numeric() {
-                    InferredType::Float
-                } else {
-                    InferredType::Dynamic
+    /// `$array[...] = value`: the value joins every array's element type, and
+    /// the variable holding the array becomes an array if it was not one
+    fn record_element_store(&mut self, array: &Expression, stored: Option<InferredType>) {
+        match array {
+            Expression::Variable(name) => {
+                self.record_element(stored);
+                if let Some(element_type) = self.element_types.clone() {
+                    self.record_variable_type(name, InferredType::array_of(element_type));
                 }
             }
-            BinaryOp::Modulo => {
-                if left.is_numeric() && right.is_numeric() {
-                    InferredType::Int
-                } else {
-                    InferredType::Dynamic
+            // Nested writes store arrays and scalars alike
+            Expression::ArrayAccess { array, .. } => {
+                self.record_element(Some(InferredType::Dynamic));
+                let mut base = &**array;
+                while let Expression::ArrayAccess { array, .. } = base {
+                    base = array;
+                }
+                if let Expression::Variable(name) = base {
+                    self.record_variable_type(name, InferredType::Array);
                 }
             }
-            BinaryOp::Concat => InferredType::String,
-            BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Identical | BinaryOp::NotIdentical |
-            BinaryOp::GreaterThan | BinaryOp::LessThan | BinaryOp::GreaterThanOrEqual |
-            BinaryOp::LessThanOrEqual => InferredType::Bool,
-            BinaryOp::And | BinaryOp::Or => InferredType::Bool,
+            _ => self.record_element(stored),
         }
     }
 
-    fn record_variable_type(&mut self, name: &str, inferred_type: InferredType) {
-        let entry = self.variable_types.entry(name.to_string()).or_insert(VariableTypeInfo {
-            inferred_type: InferredType::Dynamic,
-            is_stable: true,
-            assignment_count: 0,
-        });
+    fn record_element(&mut self, stored: Option<InferredType>) {
+        if !self.analyzing {
+            return;
+        }
+        let joined = join(self.element_types.clone(), stored);
+        if joined != self.element_types {
+            self.element_types = joined;
+            self.changed = true;
+        }
+    }
 
-        entry.assignment_count += 1;
+    /// Record every variable in an assignment target (plain variable or list() pattern) as dynamic
+    fn record_target_dynamic(&mut self, target: &Expression) {
+        match target {
+            Expression::Variable(name) => self.record_variable_type(name, InferredType::Dynamic),
+            Expression::List(items) => {
+                for item in items.iter().flatten() {
+                    self.record_target_dynamic(&item.value);
+                }
+            }
+            _ => {}
+        }
+    }
 
-        if self.first_pass {
-            if entry.assignment_count == 1 {
+    fn record_variable_type(&mut self, name: &str, inferred_type: InferredType) {
+        if !self.analyzing {
+            return;
+        }
+        let inferred_type = if SUPERGLOBALS.contains(&name) { InferredType::Dynamic } else { inferred_type };
+        match self.variable_types.get_mut(name) {
+            Some(info) => {
+                // Multiple assignments - join types
+                let joined = info.inferred_type.unify(&inferred_type);
+                if joined != info.inferred_type {
+                    info.inferred_type = joined;
+                    self.scope_changed = true;
+                }
+                if self.first_pass {
+                    info.assignment_count += 1;
+                }
+            }
+            None => {
                 // First assignment
-                entry.inferred_type = inferred_type;
-            } else {
-                // Multiple assignments - merge types
-                let merged = entry.inferred_type.merge(&inferred_type);
-                entry.inferred_type = merged;
+                self.variable_types.insert(name.to_string(), VariableTypeInfo {
+                    inferred_type,
+                    is_stable: true,
+                    assignment_count: usize::from(self.first_pass),
+                });
+                self.scope_changed = true;
             }
         }
     }
 
+    /// Switch to the variable types of a function or method ("Class::method") and
+    /// its enforced parameter types, returning those of the enclosing scope
+    pub fn enter_scope(&mut self, scope: &str, declared_types: HashMap<String, InferredType>) -> ScopeTypes {
+        let variable_types = self.scope_types.get(scope).cloned().unwrap_or_default();
+        let class = scope.rsplit_once("::").map(|(class, _)| class.to_string());
+        ScopeTypes {
+            variable_types: std::mem::replace(&mut self.variable_types, variable_types),
+            declared_types: std::mem::replace(&mut self.declared_types, declared_types),
+            current_class: std::mem::replace(&mut self.current_class, class),
+        }
+    }
+
+    /// Give a variable the compiler introduces (an induction variable, a
+    /// hoisted count) its type for the rest of the scope
+    pub fn declare_temporary(&mut self, name: &str, ty: InferredType) {
+        self.declared_types.insert(name.to_string(), ty);
+    }
+
+    /// Type of every value stored into an array element anywhere in the
+    /// program; None when nothing is stored
+    pub fn stored_element_type(&self) -> Option<InferredType> {
+        self.element_types.clone()
+    }
+
+    /// Restore the enclosing scope's types after its nested body
+    pub fn leave_scope(&mut self, saved: ScopeTypes) {
+        self.variable_types = saved.variable_types;
+        self.declared_types = saved.declared_types;
+        self.current_class = saved.current_class;
+    }
+
+    /// Type of every argument a function or method ("Class::method") receives at `position`
+    pub fn parameter_type(&self, function: &str, position: usize) -> InferredType {
+        self.signatures.get(function)
+            .and_then(|signature| signature.params.get(position))
+            .and_then(|param| param.inferred_type.clone())
+            .unwrap_or(InferredType::Dynamic)
+    }
+
+    /// Type of every value a function or method ("Class::method") returns
+    pub fn return_type(&self, function: &str) -> InferredType {
+        self.signatures.get(function)
+            .and_then(|signature| signature.return_type.clone())
+            .unwrap_or(InferredType::Dynamic)
+    }
+
+    /// The specialized copies of a user function
+    pub fn specializations(&self, function: &str) -> Vec<Specialization> {
+        self.specializations.get(function).into_iter().flatten()
+            .map(|(params, key)| Specialization {
+                key: key.clone(),
+                params: params.clone(),
+                return_type: self.return_type(key),
+            })
+            .collect()
+    }
+
+    /// The copy of a user function that a call with arguments of these types goes to
+    pub fn specialization_for(&self, function: &str, arg_types: &[InferredType]) -> Option<Specialization> {
+        let arg_types: Vec<Option<InferredType>> = arg_types.iter().cloned().map(Some).collect();
+        let params = self.specialization_params(function, &arg_types)?;
+        let key = self.copy_of(function, &params)?;
+        Some(Specialization { return_type: self.return_type(&key), key, params })
+    }
+
     /// Get the inferred type for a variable
     pub fn get_variable_type(&self, name: &str) -> InferredType {
         self.variable_types
@@ -331,4 +1265,279 @@ impl TypeInference {
             .map(|info| info.is_stable)
             .unwrap_or(false)
     }
+
+    /// What was inferred: the main program's variables, then the signature and
+    /// variables of every function and method
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        if self.gave_up {
+            report.push_str("type inference did not settle; every type is mixed\n");
+            return report;
+        }
+
+        let mut scopes: Vec<&String> = self.scope_types.keys().filter(|scope| *scope != MAIN_SCOPE).collect();
+        scopes.sort();
+        for scope in std::iter::once(&MAIN_SCOPE.to_string()).chain(scopes) {
+            match self.signatures.get(scope) {
+                Some(signature) => {
+                    let params: Vec<String> = signature.params.iter()
+                        .map(|param| format!("{} ${}", type_label(&param.inferred_type), param.name))
+                        .collect();
+                    let kind = if scope.contains("::") { "method" } else { "function" };
+                    report.push_str(&format!(
+                        "{} {}({}): {}\n", kind, scope, params.join(", "), type_label(&signature.return_type)
+                    ));
+                }
+                None => report.push_str(&format!("{}\n", scope)),
+            }
+            let variables: BTreeMap<&String, &VariableTypeInfo> = self.scope_types.get(scope)
+                .map(|variables| variables.iter().collect())
+                .unwrap_or_default();
+            for (name, info) in variables {
+                report.push_str(&format!("  ${}: {}\n", name, info.inferred_type));
+            }
+        }
+        report
+    }
+}
+
+fn literal_type(lit: &Literal) -> InferredType {
+    match lit {
+        Literal::Integer(_) => InferredType::Int,
+        Literal::Float(_) => InferredType::Float,
+        Literal::String(_) => InferredType::String,
+        Literal::Boolean(_) => InferredType::Bool,
+        Literal::Null => InferredType::Null,
+        Literal::InterpolatedString(_) => InferredType::String,
+    }
+}
+
+/// Whether a body declares functions, classes, enums or interfaces
+fn declares_anything(body: &Block) -> bool {
+    #[derive(Default)]
+    struct Declarations(bool);
+
+    impl Visitor for Declarations {
+        fn visit_statement(&mut self, stmt: &Statement) {
+            match stmt {
+                Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. } => self.0 = true,
+                _ => visit::walk_statement(self, stmt),
+            }
+        }
+    }
+
+    let mut declarations = Declarations::default();
+    visit::walk_block(&mut declarations, body);
+    declarations.0
+}
+
+/// Whether execution can run past the end of a body, which then returns null
+fn can_complete(statements: &[Statement]) -> bool {
+    let last = statements.iter().rev().find(|stmt| !matches!(stmt, Statement::Location(_)));
+    match last {
+        Some(Statement::Return(_)) => false,
+        Some(Statement::Block(block)) => can_complete(&block.statements),
+        Some(Statement::If { then_block, elseif_blocks, else_block: Some(else_block), .. }) => {
+            can_complete(&then_block.statements)
+                || elseif_blocks.iter().any(|elseif| can_complete(&elseif.then_block.statements))
+                || can_complete(&else_block.statements)
+        }
+        _ => true,
+    }
+}
+
+/// Program-wide facts the analysis starts from
+#[derive(Default)]
+struct ProgramFacts {
+    /// Lowercase string literals, and the method part of "Class::method" ones:
+    /// any of them may name a callback
+    strings: HashSet<String>,
+    /// Top-level variables named by `global` or `$GLOBALS['name']`
+    globals: HashSet<String>,
+    /// `$GLOBALS` is used as a whole or with a computed key
+    dynamic_globals: bool,
+}
+
+impl Visitor for ProgramFacts {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        if let Statement::Global(names) = stmt {
+            self.globals.extend(names.iter().cloned());
+        }
+        visit::walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Literal(Literal::String(string)) => {
+                let string = string.to_lowercase();
+                if let Some((_, method)) = string.rsplit_once("::") {
+                    self.strings.insert(method.to_string());
+                }
+                self.strings.insert(string);
+            }
+            Expression::ArrayAccess { array, index } if matches!(&**array, Expression::Variable(name) if name == "GLOBALS") => {
+                match &**index {
+                    Expression::Literal(Literal::String(name)) => {
+                        self.globals.insert(name.clone());
+                    }
+                    _ => self.dynamic_globals = true,
+                }
+                self.visit_expression(index);
+                return;
+            }
+            Expression::Variable(name) if name == "GLOBALS" => self.dynamic_globals = true,
+            _ => {}
+        }
+        visit::walk_expression(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze(source: &str, max_specializations: usize) -> TypeInference {
+        let program = edge_php_parser::parse(source).unwrap();
+        let mut inference = TypeInference::new();
+        inference.set_max_specializations(max_specializations);
+        inference.analyze_program(&program);
+        inference
+    }
+
+    fn variable_type(inference: &mut TypeInference, scope: &str, name: &str) -> InferredType {
+        let saved = inference.enter_scope(scope, HashMap::new());
+        let ty = inference.get_variable_type(name);
+        inference.leave_scope(saved);
+        ty
+    }
+
+    #[test]
+    fn test_return_and_parameter_types() {
+        let inference = analyze(r#"<?php
+function half($v) { return $v / 2; }
+function label(string $s, $n = 1) { return $s . $n; }
+function either($x) { return $x; }
+function nothing() { }
+function sometimes($x) { if ($x) { return 1; } }
+echo half(4.0), label("a"), label("b", 2);
+echo either(1), either(2.5), nothing(), sometimes(true);
+"#, 0);
+        assert_eq!(inference.parameter_type("half", 0), InferredType::Float);
+        assert_eq!(inference.return_type("half"), InferredType::Float);
+        // Declared types win over call sites; a missing argument contributes its default
+        assert_eq!(inference.parameter_type("label", 0), InferredType::String);
+        assert_eq!(inference.parameter_type("label", 1), InferredType::Int);
+        assert_eq!(inference.return_type("label"), InferredType::String);
+        // An int and a float join to dynamic rather than to a number
+        assert_eq!(inference.parameter_type("either", 0), InferredType::Dynamic);
+        assert_eq!(inference.return_type("either"), InferredType::Dynamic);
+        assert_eq!(inference.return_type("nothing"), InferredType::Null);
+        // Falling off the end returns null as well
+        assert_eq!(inference.return_type("sometimes"), InferredType::Dynamic);
+    }
+
+    #[test]
+    fn test_callbacks_and_magic_methods_are_dynamic() {
+        let inference = analyze(r#"<?php
+function shout($buffer) { return strtoupper($buffer); }
+class Bag {
+    public function __get($name) { return 1; }
+}
+ob_start("shout");
+echo shout("a");
+"#, 0);
+        assert_eq!(inference.parameter_type("shout", 0), InferredType::Dynamic);
+        assert_eq!(inference.parameter_type("Bag::__get", 0), InferredType::Dynamic);
+        assert_eq!(inference.return_type("Bag::__get"), InferredType::Int);
+    }
+
+    #[test]
+    fn test_class_tracking() {
+        let mut inference = analyze(r#"<?php
+class Point {
+    public function __construct(public int $x) {}
+    public function moved(int $by) { return new Point($this->x + $by); }
+    public function self_() { return $this; }
+}
+function make($x) { return new Point($x); }
+$p = make(3);
+$q = $p;
+$r = $q->moved(1)->self_();
+$s = $r->x;
+$t = $p;
+$t = 5;
+"#, 0);
+        let point = InferredType::Object("Point".to_string());
+        assert_eq!(inference.return_type("make"), point);
+        assert_eq!(inference.return_type("Point::moved"), point);
+        assert_eq!(inference.return_type("Point::self_"), point);
+        assert_eq!(variable_type(&mut inference, MAIN_SCOPE, "p"), point);
+        assert_eq!(variable_type(&mut inference, MAIN_SCOPE, "q"), point);
+        assert_eq!(variable_type(&mut inference, MAIN_SCOPE, "r"), point);
+        // Property types are not tracked
+        assert_eq!(variable_type(&mut inference, MAIN_SCOPE, "s"), InferredType::Dynamic);
+        // One variable holding an object and an int is dynamic
+        assert_eq!(variable_type(&mut inference, MAIN_SCOPE, "t"), InferredType::Dynamic);
+    }
+
+    #[test]
+    fn test_call_graph_with_recursion() {
+        let inference = analyze(r#"<?php
+function fact($n) { if ($n <= 1) { return 1; } return $n * fact($n - 1); }
+function is_even($n) { if ($n == 0) { return true; } return is_odd($n - 1); }
+function is_odd($n) { if ($n == 0) { return false; } return is_even($n - 1); }
+function outer($x) { return inner($x) + 1; }
+function inner($y) { return $y * 2; }
+function forever($n) { return forever($n + 1); }
+echo fact(5), is_even(4), outer(2.5);
+"#, 0);
+        assert_eq!(inference.parameter_type("fact", 0), InferredType::Int);
+        assert_eq!(inference.return_type("fact"), InferredType::Int);
+        assert_eq!(inference.parameter_type("is_odd", 0), InferredType::Int);
+        assert_eq!(inference.return_type("is_even"), InferredType::Bool);
+        assert_eq!(inference.return_type("is_odd"), InferredType::Bool);
+        // Parameter types flow through the call graph
+        assert_eq!(inference.parameter_type("inner", 0), InferredType::Float);
+        assert_eq!(inference.return_type("outer"), InferredType::Float);
+        // Never called and never returning: nothing is known
+        assert_eq!(inference.parameter_type("forever", 0), InferredType::Dynamic);
+    }
+
+    #[test]
+    fn test_report() {
+        let inference = analyze(r#"<?php
+class Point {
+    public function __construct(public int $x) {}
+}
+function make($x) { return new Point($x); }
+function fact($n) { if ($n <= 1) { return 1; } return $n * fact($n - 1); }
+$p = make(3);
+$f = fact(5);
+"#, 2);
+        assert_eq!(inference.report(), "\
+{main}
+  $f: int
+  $p: Point
+method Point::__construct(int $x): null
+  $x: int
+function fact(int $n): int
+  $n: int
+function fact<int>(int $n): int
+  $n: int
+function make(int $x): Point
+  $x: int
+");
+    }
+
+    #[test]
+    fn test_report_only_with_the_option() {
+        use crate::{Compiler, CompilerOptions};
+
+        let source = "<?php function twice($n) { return $n * 2; } echo twice(21);";
+        let module = Compiler::with_options(CompilerOptions::new().type_report(true)).compile_module("twice.php", source).unwrap();
+        let report = module.type_report.unwrap();
+        assert!(report.contains("function twice(int $n): int\n"), "{}", report);
+        let module = Compiler::with_options(CompilerOptions::new()).compile_module("twice.php", source).unwrap();
+        assert_eq!(module.type_report, None);
+    }
 }
@@ -0,0 +1,920 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Type declarations: parameter, return and property types
+//
+// Declared types are enforced where PHP enforces them: on function and method
+// entry, on return and on writes to typed properties. A value that does not
+// match is converted in coercive mode (numeric strings to int or float, scalars
+// to string or bool, ...) and is an uncaught TypeError otherwise. Under
+// `declare(strict_types=1)` the only conversion left is int to float.
+//
+// Enforced declarations are facts: parameters of a scalar type that the body
+// never writes to are handed to type inference, and int/float ones stay unboxed.
+
+use super::array_copies::{ChangedArrays, Store};
+use super::core::*;
+use super::type_inference::{InferredType, ScopeTypes};
+use edge_php_parser::ast::*;
+use edge_php_parser::visit::{self, Visitor};
+use wasm_encoder::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Every type tag, what `mixed` accepts
+const ALL_TAGS: u32 = (1 << (TYPE_OBJECT + 1)) - 1;
+/// Tags of the scalar types a value can be coerced to
+const SCALAR_TAGS: u32 = (1 << TYPE_BOOL) | (1 << TYPE_INT) | (1 << TYPE_FLOAT) | (1 << TYPE_STRING);
+/// Set in the coerce_value mask under strict_types
+const STRICT_MODE_BIT: u32 = 1 << 8;
+
+/// A declared type lowered to the checks it needs at runtime
+#[derive(Debug, Clone, Default)]
+pub(super) struct TypeCheck {
+    tags: u32,                // One bit per TYPE_* tag accepted as is
+    bool_value: Option<bool>, // `false` or `true` without `bool`
+    class_ids: Vec<u32>,      // Objects of these classes
+    pub(super) name: String,  // The declaration as PHP prints it
+}
+
+impl TypeCheck {
+    fn accepts_everything(&self) -> bool {
+        self.tags == ALL_TAGS
+    }
+
+    /// Whether a value with this tag matches without coercion (objects aside)
+    fn accepts_tag(&self, tag: u32) -> bool {
+        self.tags & (1 << tag) != 0
+    }
+}
+
+/// The declared return type of the function being compiled
+#[derive(Debug, Clone)]
+pub(super) struct ReturnTypeInfo {
+    function: String, // "f" or "A::m", as PHP names it in errors
+    declared: Type,
+    check: Option<TypeCheck>, // None for void and never
+}
+
+/// Type state of the enclosing function while a nested body is compiled
+pub(super) struct SavedTypeScope {
+    return_type: Option<ReturnTypeInfo>,
+    inferred_types: ScopeTypes,
+    changed_arrays: HashSet<String>,
+}
+
+/// A type declaration as PHP prints it in error messages
+pub(crate) fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Int => "int".to_string(),
+        Type::Float => "float".to_string(),
+        Type::String => "string".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Array => "array".to_string(),
+        Type::Object(name) => name.clone(),
+        Type::Mixed => "mixed".to_string(),
+        Type::Void => "void".to_string(),
+        Type::Null => "null".to_string(),
+        Type::False => "false".to_string(),
+        Type::True => "true".to_string(),
+        Type::Never => "never".to_string(),
+        Type::Iterable => "iterable".to_string(),
+        Type::Callable => "callable".to_string(),
+        Type::Nullable(inner) => format!("?{}", type_name(inner)),
+        Type::Union(types) => types.iter().map(type_name).collect::<Vec<_>>().join("|"),
+        Type::Intersection(types) => types.iter().map(type_name).collect::<Vec<_>>().join("&"),
+    }
+}
+
+/// `T $x = null` declares an implicitly nullable parameter
+pub(crate) fn implicitly_nullable(ty: &Type) -> Type {
+    match ty {
+        Type::Mixed | Type::Null | Type::Nullable(_) => ty.clone(),
+        Type::Union(types) if types.contains(&Type::Null) => ty.clone(),
+        Type::Union(types) => {
+            let mut types = types.clone();
+            types.push(Type::Null);
+            Type::Union(types)
+        }
+        _ => Type::Nullable(Box::new(ty.clone())),
+    }
+}
+
+/// Collects the variables a function body writes to, outside nested declarations
+#[derive(Default)]
+pub(super) struct WrittenVariables(pub(super) BTreeSet<String>);
+
+impl WrittenVariables {
+    fn add_target(&mut self, target: &Expression) {
+        match target {
+            Expression::Variable(name) => {
+                self.0.insert(name.clone());
+            }
+            Expression::List(items) => {
+                for item in items.iter().flatten() {
+                    self.add_target(&item.value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Visitor for WrittenVariables {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Function { .. }
+            | Statement::Class { .. }
+            | Statement::Enum { .. }
+            | Statement::Interface { .. } => return,
+            Statement::Foreach { key, value, .. } => {
+                self.0.extend(key.iter().cloned());
+                self.add_target(value);
+            }
+            Statement::Global(names) => self.0.extend(names.iter().cloned()),
+            Statement::Static(variables) => self.0.extend(variables.iter().map(|v| v.name.clone())),
+            _ => {}
+        }
+        visit::walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Assignment { left, .. } => self.add_target(left),
+            Expression::Unary {
+                op: UnaryOp::PreIncrement | UnaryOp::PostIncrement | UnaryOp::PreDecrement | UnaryOp::PostDecrement,
+                expr,
+            } => self.add_target(expr),
+            Expression::FunctionCall { name, args } if name == "unset" => {
+                for arg in args {
+                    self.add_target(arg);
+                }
+            }
+            _ => {}
+        }
+        visit::walk_expression(self, expr);
+    }
+}
+
+impl Compiler {
+    // ===== DECLARATIONS =====
+
+    /// Whether the script starts with `declare(strict_types=1);`
+    pub(super) fn strict_types_declaration(program: &Program) -> Result<bool, String> {
+        let statements = program.items.iter().flat_map(|item| match item {
+            ProgramItem::PhpBlock { statements } => statements.iter(),
+            ProgramItem::InlineContent(_) => [].iter(),
+        }).filter(|stmt| !matches!(stmt, Statement::Location(_)));
+
+        let mut strict_types = false;
+        for (position, stmt) in statements.enumerate() {
+            let Statement::Declare(directives) = stmt else { continue };
+            for (name, value) in directives {
+                match name.to_ascii_lowercase().as_str() {
+                    "strict_types" => {
+                        if position != 0 {
+                            return Err("strict_types declaration must be the very first statement in the script".to_string());
+                        }
+                        strict_types = match value {
+                            Expression::Literal(Literal::Integer(0)) => false,
+                            Expression::Literal(Literal::Integer(1)) => true,
+                            _ => return Err("strict_types declaration must have 0 or 1 as its value".to_string()),
+                        };
+                    }
+                    "ticks" | "encoding" => {}
+                    _ => return Err(format!("Unsupported declare '{}'", name)),
+                }
+            }
+        }
+        Ok(strict_types)
+    }
+
+    /// Lower a declared type to its runtime check. `self` and `static` resolve
+    /// to the class being compiled.
+    pub(super) fn lower_type(&self, ty: &Type) -> Result<TypeCheck, String> {
+        let mut check = TypeCheck { name: type_name(ty), ..TypeCheck::default() };
+        self.add_type_to_check(ty, &mut check)?;
+        Ok(check)
+    }
+
+    fn add_type_to_check(&self, ty: &Type, check: &mut TypeCheck) -> Result<(), String> {
+        match ty {
+            Type::Int => check.tags |= 1 << TYPE_INT,
+            Type::Float => check.tags |= 1 << TYPE_FLOAT,
+            Type::String => check.tags |= 1 << TYPE_STRING,
+            Type::Bool => check.tags |= 1 << TYPE_BOOL,
+            Type::Array | Type::Iterable => check.tags |= 1 << TYPE_ARRAY,
+            Type::Null => check.tags |= 1 << TYPE_NULL,
+            Type::False | Type::True => {
+                let value = matches!(ty, Type::True);
+                match check.bool_value {
+                    // false|true is bool
+                    Some(other) if other != value => check.tags |= 1 << TYPE_BOOL,
+                    _ => check.bool_value = Some(value),
+                }
+            }
+            Type::Mixed => check.tags = ALL_TAGS,
+            Type::Callable => {
+                // Function names, and objects with __invoke
+                check.tags |= 1 << TYPE_STRING;
+                check.class_ids.extend(self.magic_candidates("__invoke").into_iter().map(|(class_id, _)| class_id));
+            }
+            Type::Object(name) if name.eq_ignore_ascii_case("object") => check.tags |= 1 << TYPE_OBJECT,
+            Type::Object(name) => check.class_ids.extend(self.classes_of_type(name)?),
+            Type::Nullable(inner) => {
+                if matches!(**inner, Type::Mixed | Type::Null) {
+                    return Err(format!("{} cannot be marked as nullable", type_name(inner)));
+                }
+                check.tags |= 1 << TYPE_NULL;
+                self.add_type_to_check(inner, check)?;
+            }
+            Type::Union(types) => {
+                for ty in types {
+                    if matches!(ty, Type::Mixed) {
+                        return Err("Type mixed can only be used as a standalone type".to_string());
+                    }
+                    self.add_type_to_check(ty, check)?;
+                }
+            }
+            Type::Intersection(types) => {
+                let mut class_ids: Option<Vec<u32>> = None;
+                for ty in types {
+                    let Type::Object(name) = ty else {
+                        return Err(format!("Type {} cannot be part of an intersection type", type_name(ty)));
+                    };
+                    let satisfying = self.classes_of_type(name)?;
+                    class_ids = Some(match class_ids {
+                        None => satisfying,
+                        Some(ids) => ids.into_iter().filter(|id| satisfying.contains(id)).collect(),
+                    });
+                }
+                check.class_ids.extend(class_ids.unwrap_or_default());
+            }
+            Type::Void | Type::Never => {
+                return Err(format!("{} can only be used as a standalone return type", type_name(ty)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Class ids of the classes and enums that are, or implement, `name`
+    fn classes_of_type(&self, name: &str) -> Result<Vec<u32>, String> {
+        let name = self.resolve_class_type(name)?;
+        let mut class_ids: Vec<u32> = self.classes.values()
+            .filter(|class| {
+                class.name.eq_ignore_ascii_case(&name)
+                    || class.interfaces.iter().any(|interface| interface.eq_ignore_ascii_case(&name))
+            })
+            .map(|class| class.class_id)
+            .collect();
+        class_ids.sort();
+        Ok(class_ids)
+    }
+
+    /// `self` and `static` name the class being compiled
+    fn resolve_class_type(&self, name: &str) -> Result<String, String> {
+        if name.eq_ignore_ascii_case("self") || name.eq_ignore_ascii_case("static") {
+            self.current_class.clone()
+                .ok_or_else(|| format!("Cannot use \"{}\" when no class scope is active", name))
+        } else {
+            Ok(name.to_string())
+        }
+    }
+
+    /// The class a value of this declared type always is an instance of, if any
+    fn exact_class_of_type(&self, ty: &Type) -> Option<String> {
+        let Type::Object(name) = ty else { return None };
+        let name = self.resolve_class_type(name).ok()?;
+        let class = self.classes.values().find(|class| class.name.eq_ignore_ascii_case(&name))?;
+        let implementors = self.classes.values()
+            .filter(|other| other.interfaces.iter().any(|interface| interface.eq_ignore_ascii_case(&name)))
+            .count();
+        (implementors == 0).then(|| class.name.clone())
+    }
+
+    /// A property type must be one a property can have, and a literal default
+    /// must already be of that type
+    pub(super) fn check_property_declaration(&mut self, class_name: &str, property_name: &str, property_type: &Type, default: Option<&Expression>) -> Result<(), String> {
+        if matches!(property_type, Type::Void | Type::Never | Type::Callable) {
+            return Err(format!(
+                "Property {}::${} cannot have type {}", class_name, property_name, type_name(property_type)
+            ));
+        }
+
+        let saved_class = self.current_class.replace(class_name.to_string());
+        let check = self.lower_type(property_type);
+        self.current_class = saved_class;
+        let check = check?;
+
+        let Some(Expression::Literal(literal)) = default else { return Ok(()) };
+        let (tag, given) = match literal {
+            Literal::Integer(_) => (TYPE_INT, "int"),
+            Literal::Float(_) => (TYPE_FLOAT, "float"),
+            Literal::String(_) | Literal::InterpolatedString(_) => (TYPE_STRING, "string"),
+            Literal::Boolean(_) => (TYPE_BOOL, "bool"),
+            Literal::Null => (TYPE_NULL, "null"),
+        };
+        let matches = check.accepts_tag(tag)
+            || (tag == TYPE_INT && check.accepts_tag(TYPE_FLOAT))
+            || matches!((literal, check.bool_value), (Literal::Boolean(b), Some(value)) if *b == value);
+        if matches {
+            Ok(())
+        } else {
+            Err(format!(
+                "Cannot use {} as default value for property {}::${} of type {}",
+                given, class_name, property_name, check.name
+            ))
+        }
+    }
+
+    // ===== FUNCTION BOUNDARIES =====
+
+    /// Enter a function body: enforce the declared parameter types (parameters
+    /// start at `first_local`), register the return type, switch type inference
+    /// to the body's variables and hand it the parameters the body never writes
+    /// to. Undeclared parameters have the type inferred from every call site.
+    /// A specialized copy has its own types, and its unboxed parameters need
+    /// no checks.
+    pub(super) fn enter_typed_function(&mut self, function: &str, params: &[Parameter], return_type: Option<&Type>, first_local: u32, body: &Block) -> Result<SavedTypeScope, String> {
+        let return_type = match return_type {
+            Some(declared) => Some(ReturnTypeInfo {
+                function: function.to_string(),
+                declared: declared.clone(),
+                check: match declared {
+                    Type::Void | Type::Never => None,
+                    _ => Some(self.lower_type(declared)?),
+                },
+            }),
+            None => None,
+        };
+
+        let mut written = WrittenVariables::default();
+        visit::walk_block(&mut written, body);
+
+        let scope = self.specialization.as_ref()
+            .map_or_else(|| function.to_string(), |specialization| specialization.key.clone());
+        let mut declared_types = HashMap::new();
+        for (position, param) in params.iter().enumerate() {
+            let local_idx = first_local + position as u32;
+            let specialized = self.specialization.as_ref()
+                .map(|specialization| specialization.params[position].clone())
+                .filter(InferredType::is_numeric);
+            if let Some(specialized) = specialized {
+                if !written.0.contains(&param.name) {
+                    declared_types.insert(param.name.clone(), specialized);
+                }
+                continue;
+            }
+            let (inferred, class_type) = match &param.param_type {
+                Some(declared) => {
+                    if matches!(declared, Type::Void | Type::Never) {
+                        return Err(format!("{} cannot be used as a parameter type", type_name(declared)));
+                    }
+                    let declared = match param.default {
+                        Some(Expression::Literal(Literal::Null)) => implicitly_nullable(declared),
+                        _ => declared.clone(),
+                    };
+                    let check = self.lower_type(&declared)?;
+                    let prefix = format!(
+                        "{}(): Argument #{} (${}) must be of type {}, ", function, position + 1, param.name, check.name
+                    );
+                    self.emit_enforce_type(local_idx, &check, &prefix, " given")?;
+                    (InferredType::from_declaration(&declared), self.exact_class_of_type(&declared))
+                }
+                // Undeclared parameters have the type of every argument passed to them
+                None => match self.type_inference.parameter_type(&scope, position) {
+                    InferredType::Object(class) if self.classes.contains_key(&class) => (InferredType::Object(class.clone()), Some(class)),
+                    InferredType::Object(_) => (InferredType::Dynamic, None),
+                    inferred => (inferred, None),
+                },
+            };
+
+            if written.0.contains(&param.name) {
+                continue;
+            }
+            let storage = match inferred {
+                _ if !self.options.unboxing_enabled() => None,
+                InferredType::Int => Some((VariableStorage::UnboxedInt, ValType::I64, PHPVALUE_INT)),
+                InferredType::Float => Some((VariableStorage::UnboxedFloat, ValType::F64, PHPVALUE_FLOAT)),
+                _ => None,
+            };
+            if inferred.is_known() {
+                declared_types.insert(param.name.clone(), inferred);
+            }
+            if let Some((storage_type, wasm_type, field_index)) = storage {
+                let unboxed_local = self.allocate_local(wasm_type);
+                self.emit(Instruction::LocalGet(local_idx));
+                self.emit(Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index,
+                });
+                self.emit(Instruction::LocalSet(unboxed_local));
+                self.variables.insert(param.name.clone(), VariableInfo {
+                    local_idx: unboxed_local,
+                    storage_type,
+                    class_type: None,
+                });
+            } else if let Some(class_name) = class_type {
+                if let Some(var_info) = self.variables.get_mut(&param.name) {
+                    var_info.class_type = Some(class_name);
+                }
+            }
+        }
+
+        let inferred_types = self.type_inference.enter_scope(&scope, declared_types);
+        self.box_reassigned_parameters(params, &written, first_local);
+        let changed_arrays = std::mem::replace(&mut self.changed_arrays, ChangedArrays::of(&body.statements));
+        self.copy_changed_parameters(params);
+        Ok(SavedTypeScope {
+            return_type: std::mem::replace(&mut self.return_type, return_type),
+            inferred_types,
+            changed_arrays,
+        })
+    }
+
+    /// Restore the enclosing function's type state after its nested body
+    pub(super) fn leave_typed_function(&mut self, saved: SavedTypeScope) {
+        self.return_type = saved.return_type;
+        self.type_inference.leave_scope(saved.inferred_types);
+        self.changed_arrays = saved.changed_arrays;
+    }
+
+    /// Compile `return [expr];` against the declared return type
+    pub(super) fn compile_typed_return(&mut self, expr: Option<Expression>) -> Result<(), String> {
+        let Some(return_type) = self.return_type.clone() else {
+            match (expr, self.unboxed_return_type()) {
+                (Some(expr), Some(unboxed)) => {
+                    let expr_type = self.type_inference.infer_expression(&expr);
+                    self.compile_expression_as_unboxed(expr, &expr_type, &unboxed)?;
+                    self.emit(Instruction::Return);
+                    return Ok(());
+                }
+                (Some(expr), None) => self.compile_stored_value(expr, Store::Return)?,
+                (None, _) => self.emit(Instruction::Call(self.create_null_fn_idx)),
+            }
+            self.emit_return();
+            return Ok(());
+        };
+
+        match (&return_type.declared, expr) {
+            (Type::Void, Some(_)) => Err("A void function must not return a value".to_string()),
+            (Type::Never, _) => Err("A never-returning function must not return".to_string()),
+            (Type::Void, None) => {
+                self.emit(Instruction::Call(self.create_null_fn_idx));
+                self.emit_return();
+                Ok(())
+            }
+            (_, None) => Err("A function with return type must return a value".to_string()),
+            (_, Some(expr)) => {
+                let check = return_type.check.as_ref().expect("checked return type");
+                self.compile_stored_value(expr, Store::Return)?;
+                let value_local = self.allocate_local(self.get_php_value_type());
+                self.emit(Instruction::LocalSet(value_local));
+                let prefix = format!(
+                    "{}(): Return value must be of type {}, ", return_type.function, check.name
+                );
+                self.emit_enforce_type(value_local, check, &prefix, " returned")?;
+                self.emit(Instruction::LocalGet(value_local));
+                self.emit_return();
+                Ok(())
+            }
+        }
+    }
+
+    /// Falling off the end of a function returns null, which only functions
+    /// without a return type (or returning void) may do. Copies returning
+    /// unboxed values cannot get there.
+    pub(super) fn emit_implicit_return(&mut self) -> Result<(), String> {
+        if let Some(return_type) = self.return_type.clone() {
+            let message = match &return_type.check {
+                Some(check) => Some(format!(
+                    "{}(): Return value must be of type {}, none returned", return_type.function, check.name
+                )),
+                None if return_type.declared == Type::Never => Some(format!(
+                    "{}(): never-returning function must not implicitly return", return_type.function
+                )),
+                None => None,
+            };
+            if let Some(message) = message {
+                self.compile_string_literal(&message)?;
+                self.emit_uncaught_error("TypeError")?;
+            }
+        }
+        if self.unboxed_return_type().is_some() {
+            self.emit(Instruction::Unreachable);
+            return Ok(());
+        }
+        self.emit(Instruction::Call(self.create_null_fn_idx));
+        self.emit(Instruction::Return);
+        Ok(())
+    }
+
+    /// Enforce a typed property on the value on top of the stack (which stays there)
+    pub(super) fn emit_property_type_enforcement(&mut self, class_name: &str, property_name: &str, property_type: &Type) -> Result<(), String> {
+        let saved_class = self.current_class.replace(class_name.to_string());
+        let check = self.lower_type(property_type);
+        self.current_class = saved_class;
+        let check = check?;
+
+        let value_local = self.allocate_local(self.get_php_value_type());
+        self.emit(Instruction::LocalSet(value_local));
+        let suffix = format!(" to property {}::${} of type {}", class_name, property_name, check.name);
+        self.emit_enforce_type(value_local, &check, "Cannot assign ", &suffix)?;
+        self.emit(Instruction::LocalGet(value_local));
+        Ok(())
+    }
+
+    // ===== RUNTIME CHECKS =====
+
+    /// Leave a value of the declared type in `value_local`: values that do not match
+    /// are coerced, or are an uncaught TypeError reading `{prefix}{type}{suffix}`
+    fn emit_enforce_type(&mut self, value_local: u32, check: &TypeCheck, prefix: &str, suffix: &str) -> Result<(), String> {
+        if check.accepts_everything() {
+            return Ok(());
+        }
+
+        self.emit_type_matches(value_local, check);
+        self.emit(Instruction::I32Eqz);
+        self.emit(Instruction::If(BlockType::Empty));
+
+        let targets = check.tags & SCALAR_TAGS;
+        let coerce_mask = if self.strict_types {
+            (targets & (1 << TYPE_FLOAT) != 0).then_some(targets | STRICT_MODE_BIT)
+        } else {
+            (targets != 0).then_some(targets)
+        };
+
+        match coerce_mask {
+            Some(mask) => {
+                let coerced_local = self.allocate_local(self.get_php_value_type());
+                self.emit(Instruction::LocalGet(value_local));
+                self.emit(Instruction::I64Const(mask as i64));
+                self.emit(Instruction::Call(self.create_int_fn_idx));
+                self.emit(Instruction::Call(self.coerce_value_fn_idx));
+                self.emit(Instruction::LocalTee(coerced_local));
+                self.emit(Instruction::StructGet {
+                    struct_type_index: self.gc_types.php_value,
+                    field_index: PHPVALUE_TYPE,
+                });
+                self.emit(Instruction::I32Const(TYPE_NULL as i32));
+                self.emit(Instruction::I32Eq);
+                self.emit(Instruction::If(BlockType::Empty));
+                self.emit_type_error(value_local, prefix, suffix)?;
+                self.emit(Instruction::End);
+                self.emit(Instruction::LocalGet(coerced_local));
+                self.emit(Instruction::LocalSet(value_local));
+            }
+            None => self.emit_type_error(value_local, prefix, suffix)?,
+        }
+
+        self.emit(Instruction::End);
+        Ok(())
+    }
+
+    /// Push 1 when the value in `value_local` matches the type as it is
+    fn emit_type_matches(&mut self, value_local: u32, check: &TypeCheck) {
+        // (tags >> value.type) & 1
+        self.emit(Instruction::I32Const(check.tags as i32));
+        self.emit_value_tag(value_local);
+        self.emit(Instruction::I32ShrU);
+        self.emit(Instruction::I32Const(1));
+        self.emit(Instruction::I32And);
+
+        if let (Some(value), false) = (check.bool_value, check.accepts_tag(TYPE_BOOL)) {
+            self.emit_value_tag(value_local);
+            self.emit(Instruction::I32Const(TYPE_BOOL as i32));
+            self.emit(Instruction::I32Eq);
+            self.emit(Instruction::LocalGet(value_local));
+            self.emit(Instruction::StructGet {
+                struct_type_index: self.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            });
+            self.emit(Instruction::I64Const(value as i64));
+            self.emit(Instruction::I64Eq);
+            self.emit(Instruction::I32And);
+            self.emit(Instruction::I32Or);
+        }
+
+        if !check.class_ids.is_empty() && !check.accepts_tag(TYPE_OBJECT) {
+            self.emit_value_tag(value_local);
+            self.emit(Instruction::I32Const(TYPE_OBJECT as i32));
+            self.emit(Instruction::I32Eq);
+            self.emit(Instruction::If(BlockType::Result(ValType::I32)));
+            let class_id_local = self.allocate_local(ValType::I32);
+            self.emit_object_class_id(value_local);
+            self.emit(Instruction::LocalSet(class_id_local));
+            self.emit(Instruction::I32Const(0));
+            for class_id in &check.class_ids {
+                self.emit(Instruction::LocalGet(class_id_local));
+                self.emit(Instruction::I32Const(*class_id as i32));
+                self.emit(Instruction::I32Eq);
+                self.emit(Instruction::I32Or);
+            }
+            self.emit(Instruction::Else);
+            self.emit(Instruction::I32Const(0));
+            self.emit(Instruction::End);
+            self.emit(Instruction::I32Or);
+        }
+    }
+
+    fn emit_value_tag(&mut self, value_local: u32) {
+        self.emit(Instruction::LocalGet(value_local));
+        self.emit(Instruction::StructGet {
+            struct_type_index: self.gc_types.php_value,
+            field_index: PHPVALUE_TYPE,
+        });
+    }
+
+    /// Uncaught TypeError `{prefix}{type of value}{suffix}`
+    fn emit_type_error(&mut self, value_local: u32, prefix: &str, suffix: &str) -> Result<(), String> {
+        self.compile_string_literal(prefix)?;
+        self.emit(Instruction::LocalGet(value_local));
+        self.emit(Instruction::Call(self.debug_type_fn_idx));
+        self.emit(Instruction::Call(self.concat_fn_idx));
+        self.compile_string_literal(suffix)?;
+        self.emit(Instruction::Call(self.concat_fn_idx));
+        self.emit_uncaught_error("TypeError")
+    }
+
+    // ===== RUNTIME HELPERS =====
+    // Both name classes or dispatch on class_id, so they are generated once every
+    // class has been registered.
+
+    pub(super) fn add_type_check_functions(&mut self) -> Result<(), String> {
+        self.add_debug_type_function()?;
+        self.add_coerce_value_function()
+    }
+
+    /// debug_type(value) -> string: the type name PHP prints in a TypeError
+    fn add_debug_type_function(&mut self) -> Result<(), String> {
+        let mut classes: Vec<(u32, String)> = self.classes.values()
+            .map(|class| (class.class_id, class.name.clone()))
+            .collect();
+        classes.sort_by_key(|(class_id, _)| *class_id);
+
+        self.compile_helper_function(self.debug_type_fn_idx, 1, |this| {
+            for (tag, name) in [
+                (TYPE_NULL, "null"),
+                (TYPE_BOOL, "bool"),
+                (TYPE_INT, "int"),
+                (TYPE_FLOAT, "float"),
+                (TYPE_STRING, "string"),
+                (TYPE_ARRAY, "array"),
+            ] {
+                this.emit_value_tag(0);
+                this.emit(Instruction::I32Const(tag as i32));
+                this.emit(Instruction::I32Eq);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.compile_string_literal(name)?;
+                this.emit(Instruction::Return);
+                this.emit(Instruction::End);
+            }
+
+            // Objects: the class name
+            let class_id_local = this.allocate_local(ValType::I32);
+            this.emit_object_class_id(0);
+            this.emit(Instruction::LocalSet(class_id_local));
+            for (class_id, name) in &classes {
+                this.emit(Instruction::LocalGet(class_id_local));
+                this.emit(Instruction::I32Const(*class_id as i32));
+                this.emit(Instruction::I32Eq);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.compile_string_literal(name)?;
+                this.emit(Instruction::Return);
+                this.emit(Instruction::End);
+            }
+            this.compile_string_literal("object")
+        })
+    }
+
+    /// coerce_value(value, mask) -> value: converts a value that does not match its
+    /// declared type to one of the scalar types in `mask` (bits of their type tags),
+    /// following PHP's preference order int, float, string, bool. Returns null when
+    /// no conversion is allowed. STRICT_MODE_BIT only allows int to float.
+    fn add_coerce_value_function(&mut self) -> Result<(), String> {
+        let stringable: Vec<u32> = self.magic_candidates("__toString").into_iter()
+            .map(|(class_id, _)| class_id)
+            .collect();
+
+        self.compile_helper_function(self.coerce_value_fn_idx, 2, |this| {
+            let tag_local = this.allocate_local(ValType::I32);
+            let mask_local = this.allocate_local(ValType::I32);
+            let parsed_local = this.allocate_local(this.get_php_value_type());
+            let float_local = this.allocate_local(ValType::F64);
+
+            this.emit_value_tag(0);
+            this.emit(Instruction::LocalSet(tag_local));
+            this.emit(Instruction::LocalGet(1));
+            this.emit(Instruction::StructGet {
+                struct_type_index: this.gc_types.php_value,
+                field_index: PHPVALUE_INT,
+            });
+            this.emit(Instruction::I32WrapI64);
+            this.emit(Instruction::LocalSet(mask_local));
+
+            let emit_mask_has = |this: &mut Self, bits: u32| {
+                this.emit(Instruction::LocalGet(mask_local));
+                this.emit(Instruction::I32Const(bits as i32));
+                this.emit(Instruction::I32And);
+                this.emit(Instruction::I32Const(0));
+                this.emit(Instruction::I32Ne);
+            };
+            let emit_tag_is = |this: &mut Self, tag: u32| {
+                this.emit(Instruction::LocalGet(tag_local));
+                this.emit(Instruction::I32Const(tag as i32));
+                this.emit(Instruction::I32Eq);
+            };
+            let emit_int_to_float = |this: &mut Self| {
+                this.emit(Instruction::LocalGet(0));
+                this.emit(Instruction::StructGet {
+                    struct_type_index: this.gc_types.php_value,
+                    field_index: PHPVALUE_INT,
+                });
+                this.emit(Instruction::F64ConvertI64S);
+                this.emit(Instruction::Call(this.create_float_fn_idx));
+                this.emit(Instruction::Return);
+            };
+
+            // strict_types: int is accepted where float is
+            emit_mask_has(this, STRICT_MODE_BIT);
+            this.emit(Instruction::If(BlockType::Empty));
+            emit_tag_is(this, TYPE_INT);
+            emit_mask_has(this, 1 << TYPE_FLOAT);
+            this.emit(Instruction::I32And);
+            this.emit(Instruction::If(BlockType::Empty));
+            emit_int_to_float(this);
+            this.emit(Instruction::End);
+            this.emit(Instruction::Call(this.create_null_fn_idx));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+
+            // Objects convert to string through __toString
+            emit_tag_is(this, TYPE_OBJECT);
+            this.emit(Instruction::If(BlockType::Empty));
+            if !stringable.is_empty() {
+                emit_mask_has(this, 1 << TYPE_STRING);
+                this.emit(Instruction::If(BlockType::Empty));
+                let class_id_local = this.allocate_local(ValType::I32);
+                this.emit_object_class_id(0);
+                this.emit(Instruction::LocalSet(class_id_local));
+                this.emit(Instruction::I32Const(0));
+                for class_id in &stringable {
+                    this.emit(Instruction::LocalGet(class_id_local));
+                    this.emit(Instruction::I32Const(*class_id as i32));
+                    this.emit(Instruction::I32Eq);
+                    this.emit(Instruction::I32Or);
+                }
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(0));
+                this.emit(Instruction::Call(this.object_to_string_fn_idx));
+                this.emit(Instruction::Return);
+                this.emit(Instruction::End);
+                this.emit(Instruction::End);
+            }
+            this.emit(Instruction::Call(this.create_null_fn_idx));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+
+            // null and arrays never convert
+            emit_tag_is(this, TYPE_NULL);
+            emit_tag_is(this, TYPE_ARRAY);
+            this.emit(Instruction::I32Or);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::Call(this.create_null_fn_idx));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+
+            // int: from bool, integral floats (any float when neither float nor
+            // string is allowed) and integer numeric strings
+            emit_mask_has(this, 1 << TYPE_INT);
+            this.emit(Instruction::If(BlockType::Empty));
+            {
+                emit_tag_is(this, TYPE_BOOL);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(0));
+                this.emit(Instruction::StructGet {
+                    struct_type_index: this.gc_types.php_value,
+                    field_index: PHPVALUE_INT,
+                });
+                this.emit(Instruction::Call(this.create_int_fn_idx));
+                this.emit(Instruction::Return);
+                this.emit(Instruction::End);
+
+                emit_tag_is(this, TYPE_FLOAT);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(0));
+                this.emit(Instruction::StructGet {
+                    struct_type_index: this.gc_types.php_value,
+                    field_index: PHPVALUE_FLOAT,
+                });
+                this.emit(Instruction::LocalTee(float_local));
+                this.emit(Instruction::LocalGet(float_local));
+                this.emit(Instruction::F64Trunc);
+                this.emit(Instruction::F64Eq);
+                emit_mask_has(this, (1 << TYPE_FLOAT) | (1 << TYPE_STRING));
+                this.emit(Instruction::I32Eqz);
+                this.emit(Instruction::I32Or);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(float_local));
+                this.emit(Instruction::I64TruncSatF64S);
+                this.emit(Instruction::Call(this.create_int_fn_idx));
+                this.emit(Instruction::Return);
+                this.emit(Instruction::End);
+                this.emit(Instruction::End);
+
+                emit_tag_is(this, TYPE_STRING);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(0));
+                this.emit(Instruction::Call(this.string_to_int_if_numeric_fn_idx));
+                this.emit(Instruction::LocalTee(parsed_local));
+                this.emit(Instruction::StructGet {
+                    struct_type_index: this.gc_types.php_value,
+                    field_index: PHPVALUE_TYPE,
+                });
+                this.emit(Instruction::I32Const(TYPE_INT as i32));
+                this.emit(Instruction::I32Eq);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(parsed_local));
+                this.emit(Instruction::Return);
+                this.emit(Instruction::End);
+                // "1.5" truncates when float is not an option
+                emit_mask_has(this, 1 << TYPE_FLOAT);
+                this.emit(Instruction::I32Eqz);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(0));
+                this.emit(Instruction::Call(this.string_to_float_if_numeric_fn_idx));
+                this.emit(Instruction::LocalTee(parsed_local));
+                this.emit(Instruction::StructGet {
+                    struct_type_index: this.gc_types.php_value,
+                    field_index: PHPVALUE_TYPE,
+                });
+                this.emit(Instruction::I32Const(TYPE_FLOAT as i32));
+                this.emit(Instruction::I32Eq);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(parsed_local));
+                this.emit(Instruction::StructGet {
+                    struct_type_index: this.gc_types.php_value,
+                    field_index: PHPVALUE_FLOAT,
+                });
+                this.emit(Instruction::I64TruncSatF64S);
+                this.emit(Instruction::Call(this.create_int_fn_idx));
+                this.emit(Instruction::Return);
+                this.emit(Instruction::End);
+                this.emit(Instruction::End);
+                this.emit(Instruction::End);
+            }
+            this.emit(Instruction::End);
+
+            // float: from int, bool and numeric strings
+            emit_mask_has(this, 1 << TYPE_FLOAT);
+            this.emit(Instruction::If(BlockType::Empty));
+            {
+                emit_tag_is(this, TYPE_INT);
+                emit_tag_is(this, TYPE_BOOL);
+                this.emit(Instruction::I32Or);
+                this.emit(Instruction::If(BlockType::Empty));
+                emit_int_to_float(this);
+                this.emit(Instruction::End);
+
+                emit_tag_is(this, TYPE_STRING);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(0));
+                this.emit(Instruction::Call(this.string_to_float_if_numeric_fn_idx));
+                this.emit(Instruction::LocalTee(parsed_local));
+                this.emit(Instruction::StructGet {
+                    struct_type_index: this.gc_types.php_value,
+                    field_index: PHPVALUE_TYPE,
+                });
+                this.emit(Instruction::I32Const(TYPE_FLOAT as i32));
+                this.emit(Instruction::I32Eq);
+                this.emit(Instruction::If(BlockType::Empty));
+                this.emit(Instruction::LocalGet(parsed_local));
+                this.emit(Instruction::Return);
+                this.emit(Instruction::End);
+                this.emit(Instruction::End);
+            }
+            this.emit(Instruction::End);
+
+            // string: from bool, int and float
+            emit_mask_has(this, 1 << TYPE_STRING);
+            emit_tag_is(this, TYPE_STRING);
+            this.emit(Instruction::I32Eqz);
+            this.emit(Instruction::I32And);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::Call(this.to_string_fn_idx));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+
+            // bool: from any scalar
+            emit_mask_has(this, 1 << TYPE_BOOL);
+            this.emit(Instruction::If(BlockType::Empty));
+            this.emit(Instruction::LocalGet(0));
+            this.emit(Instruction::Call(this.to_bool_fn_idx));
+            this.emit(Instruction::Return);
+            this.emit(Instruction::End);
+
+            this.emit(Instruction::Call(this.create_null_fn_idx));
+            Ok(())
+        })
+    }
+}
@@ -1,608 +1,219 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
+// Arithmetic on PHP values: operands are converted to numbers, int results
+// that overflow become floats, and division by zero is an uncaught error
+
 use super::core::*;
-use wasm_encoder::*;
+use wasm_encoder::Instruction::*;
+use wasm_encoder::{BlockType, ValType};
+
+/// Operations of the `arith` helper
+pub(super) const OP_ADD: i32 = 0;
+pub(super) const OP_SUB: i32 = 1;
+pub(super) const OP_MUL: i32 = 2;
+pub(super) const OP_DIV: i32 = 3;
+pub(super) const OP_MOD: i32 = 4;
 
 impl Compiler {
     pub(super) fn add_arithmetic_functions(&mut self) {
-        self.add_add_function();
-        self.add_subtract_function();
-        self.add_multiply_function();
-        self.add_divide_function();
+        self.add_arith_function();
+        self.add_neg_function();
+        self.add_incdec_function();
+        self.add_math_round_function();
+        self.add_math_pow_function();
+    }
+
+    fn add_arith_function(&mut self) {
+        // arith(a, b, op) -> value
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (a, b, op) = (0, 1, 2);
+        let (x, y, r) = (3, 4, 5);
+        let (fx, fy) = (6, 7);
+        let division_by_zero = self.string_literal("PHP Fatal error:  Uncaught DivisionByZeroError: Division by zero\n");
+        let modulo_by_zero = self.string_literal("PHP Fatal error:  Uncaught DivisionByZeroError: Modulo by zero\n");
+        let body = vec![
+            LocalGet(op), I32Const(OP_MOD), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(b), Call(self.h.to_int), LocalTee(y), I64Eqz,
+                If(BlockType::Empty), I32Const(modulo_by_zero as i32), Call(self.h.fatal), End,
+                LocalGet(y), I64Const(-1), I64Eq,
+                If(BlockType::Empty), I64Const(0), Call(self.h.new_int), Return, End,
+                LocalGet(a), Call(self.h.to_int), LocalGet(y), I64RemS, Call(self.h.new_int), Return,
+            End,
+            LocalGet(a), Call(self.h.to_number), LocalSet(a),
+            LocalGet(b), Call(self.h.to_number), LocalSet(b),
+
+            LocalGet(a), I32Load(mem32(0)), I32Const(TAG_INT), I32Eq,
+            LocalGet(b), I32Load(mem32(0)), I32Const(TAG_INT), I32Eq, I32And,
+            If(BlockType::Empty),
+                LocalGet(a), I64Load(mem64(VALUE_DATA)), LocalSet(x),
+                LocalGet(b), I64Load(mem64(VALUE_DATA)), LocalSet(y),
+                LocalGet(op), I32Const(OP_ADD), I32Eq,
+                If(BlockType::Empty),
+                    LocalGet(x), LocalGet(y), I64Add, LocalSet(r),
+                    // Overflow when the operands share a sign the result lacks
+                    LocalGet(x), LocalGet(r), I64Xor, LocalGet(y), LocalGet(r), I64Xor, I64And, I64Const(0), I64GeS,
+                    If(BlockType::Empty), LocalGet(r), Call(self.h.new_int), Return, End,
+                End,
+                LocalGet(op), I32Const(OP_SUB), I32Eq,
+                If(BlockType::Empty),
+                    LocalGet(x), LocalGet(y), I64Sub, LocalSet(r),
+                    LocalGet(x), LocalGet(y), I64Xor, LocalGet(x), LocalGet(r), I64Xor, I64And, I64Const(0), I64GeS,
+                    If(BlockType::Empty), LocalGet(r), Call(self.h.new_int), Return, End,
+                End,
+                LocalGet(op), I32Const(OP_MUL), I32Eq,
+                If(BlockType::Empty),
+                    LocalGet(x), F64ConvertI64S, LocalGet(y), F64ConvertI64S, F64Mul, F64Abs,
+                    F64Const(9.2e18.into()), F64Lt,
+                    If(BlockType::Empty), LocalGet(x), LocalGet(y), I64Mul, Call(self.h.new_int), Return, End,
+                End,
+                LocalGet(op), I32Const(OP_DIV), I32Eq,
+                If(BlockType::Empty),
+                    LocalGet(y), I64Eqz,
+                    If(BlockType::Empty), I32Const(division_by_zero as i32), Call(self.h.fatal), End,
+                    // Exact quotients stay ints
+                    LocalGet(y), I64Const(-1), I64Ne,
+                    If(BlockType::Empty),
+                        LocalGet(x), LocalGet(y), I64RemS, I64Eqz,
+                        If(BlockType::Empty), LocalGet(x), LocalGet(y), I64DivS, Call(self.h.new_int), Return, End,
+                    End,
+                End,
+            End,
+
+            // Float arithmetic
+            LocalGet(a), Call(self.h.to_float), LocalSet(fx),
+            LocalGet(b), Call(self.h.to_float), LocalSet(fy),
+            LocalGet(op), I32Const(OP_ADD), I32Eq,
+            If(BlockType::Empty), LocalGet(fx), LocalGet(fy), F64Add, Call(self.h.new_float), Return, End,
+            LocalGet(op), I32Const(OP_SUB), I32Eq,
+            If(BlockType::Empty), LocalGet(fx), LocalGet(fy), F64Sub, Call(self.h.new_float), Return, End,
+            LocalGet(op), I32Const(OP_MUL), I32Eq,
+            If(BlockType::Empty), LocalGet(fx), LocalGet(fy), F64Mul, Call(self.h.new_float), Return, End,
+            LocalGet(fy), F64Const(0.0.into()), F64Eq,
+            If(BlockType::Empty), I32Const(division_by_zero as i32), Call(self.h.fatal), End,
+            LocalGet(fx), LocalGet(fy), F64Div, Call(self.h.new_float),
+        ];
+        let locals = vec![(3, ValType::I64), (2, ValType::F64)];
+        self.builder.set_function_at_index(self.h.arith, ty, locals, body);
     }
-    
-    fn add_add_function(&mut self) {
-        // add function - PHP addition with type coercion
-        let mut add_body = vec![];
-        let mut add_locals = vec![];
-        add_locals.push((2, ValType::I32)); // left_type, right_type
-        add_locals.push((2, ValType::I64)); // left_val_int, right_val_int
-        add_locals.push((1, ValType::I32)); // result_ptr
-        add_locals.push((2, ValType::F64)); // left_val_float, right_val_float
-        add_locals.push((1, ValType::I32)); // result_is_float
-        
-        // Load type tags
-        add_body.push(Instruction::LocalGet(0)); // left value ptr
-        add_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        add_body.push(Instruction::LocalSet(2)); // left_type
-        
-        add_body.push(Instruction::LocalGet(1)); // right value ptr
-        add_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        add_body.push(Instruction::LocalSet(3)); // right_type
-        
-        // Initialize result_is_float to 0
-        add_body.push(Instruction::I32Const(0));
-        add_body.push(Instruction::LocalSet(9)); // result_is_float = false
-        
-        // Convert left operand to numeric
-        add_body.push(Instruction::LocalGet(2)); // left_type
-        add_body.push(Instruction::I32Const(TYPE_INT as i32));
-        add_body.push(Instruction::I32Eq);
-        add_body.push(Instruction::If(BlockType::Empty));
-        // Left is integer
-        add_body.push(Instruction::LocalGet(0));
-        add_body.push(Instruction::I32Const(4));
-        add_body.push(Instruction::I32Add);
-        add_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        add_body.push(Instruction::LocalSet(4)); // left_val_int
-        add_body.push(Instruction::LocalGet(4));
-        add_body.push(Instruction::F64ConvertI64S);
-        add_body.push(Instruction::LocalSet(7)); // left_val_float
-        add_body.push(Instruction::Else);
-        
-        add_body.push(Instruction::LocalGet(2)); // left_type
-        add_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        add_body.push(Instruction::I32Eq);
-        add_body.push(Instruction::If(BlockType::Empty));
-        // Left is float
-        add_body.push(Instruction::LocalGet(0));
-        add_body.push(Instruction::I32Const(4));
-        add_body.push(Instruction::I32Add);
-        add_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        add_body.push(Instruction::LocalSet(7)); // left_val_float
-        add_body.push(Instruction::I32Const(1));
-        add_body.push(Instruction::LocalSet(9)); // result_is_float = true
-        add_body.push(Instruction::Else);
-        // Left is neither int nor float - treat as 0
-        add_body.push(Instruction::F64Const(0.0.into()));
-        add_body.push(Instruction::LocalSet(7)); // left_val_float = 0
-        add_body.push(Instruction::End);
-        add_body.push(Instruction::End);
-        
-        // Convert right operand to numeric
-        add_body.push(Instruction::LocalGet(3)); // right_type
-        add_body.push(Instruction::I32Const(TYPE_INT as i32));
-        add_body.push(Instruction::I32Eq);
-        add_body.push(Instruction::If(BlockType::Empty));
-        // Right is integer
-        add_body.push(Instruction::LocalGet(1));
-        add_body.push(Instruction::I32Const(4));
-        add_body.push(Instruction::I32Add);
-        add_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        add_body.push(Instruction::LocalSet(5)); // right_val_int
-        add_body.push(Instruction::LocalGet(5));
-        add_body.push(Instruction::F64ConvertI64S);
-        add_body.push(Instruction::LocalSet(8)); // right_val_float
-        add_body.push(Instruction::Else);
-        
-        add_body.push(Instruction::LocalGet(3)); // right_type
-        add_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        add_body.push(Instruction::I32Eq);
-        add_body.push(Instruction::If(BlockType::Empty));
-        // Right is float
-        add_body.push(Instruction::LocalGet(1));
-        add_body.push(Instruction::I32Const(4));
-        add_body.push(Instruction::I32Add);
-        add_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        add_body.push(Instruction::LocalSet(8)); // right_val_float
-        add_body.push(Instruction::I32Const(1));
-        add_body.push(Instruction::LocalSet(9)); // result_is_float = true
-        add_body.push(Instruction::Else);
-        // Right is neither int nor float - treat as 0
-        add_body.push(Instruction::F64Const(0.0.into()));
-        add_body.push(Instruction::LocalSet(8)); // right_val_float = 0
-        add_body.push(Instruction::End);
-        add_body.push(Instruction::End);
-        
-        // Allocate result
-        add_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        add_body.push(Instruction::LocalSet(6)); // result_ptr
-        
-        // Check if result should be float
-        add_body.push(Instruction::LocalGet(9)); // result_is_float
-        add_body.push(Instruction::If(BlockType::Empty));
-        
-        // Result is float
-        add_body.push(Instruction::LocalGet(6));
-        add_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        add_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        add_body.push(Instruction::LocalGet(6));
-        add_body.push(Instruction::I32Const(4));
-        add_body.push(Instruction::I32Add);
-        add_body.push(Instruction::LocalGet(7)); // left_val_float
-        add_body.push(Instruction::LocalGet(8)); // right_val_float
-        add_body.push(Instruction::F64Add);
-        add_body.push(Instruction::F64Store(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        
-        add_body.push(Instruction::Else);
-        
-        // Check if both are integers
-        add_body.push(Instruction::LocalGet(2)); // left_type
-        add_body.push(Instruction::I32Const(TYPE_INT as i32));
-        add_body.push(Instruction::I32Eq);
-        add_body.push(Instruction::LocalGet(3)); // right_type
-        add_body.push(Instruction::I32Const(TYPE_INT as i32));
-        add_body.push(Instruction::I32Eq);
-        add_body.push(Instruction::I32And);
-        add_body.push(Instruction::If(BlockType::Empty));
-        
-        // Both integers - do integer addition
-        add_body.push(Instruction::LocalGet(6));
-        add_body.push(Instruction::I32Const(TYPE_INT as i32));
-        add_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        add_body.push(Instruction::LocalGet(6));
-        add_body.push(Instruction::I32Const(4));
-        add_body.push(Instruction::I32Add);
-        add_body.push(Instruction::LocalGet(4)); // left_val_int
-        add_body.push(Instruction::LocalGet(5)); // right_val_int
-        add_body.push(Instruction::I64Add);
-        add_body.push(Instruction::I64Store(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        
-        add_body.push(Instruction::Else);
-        
-        // Mixed types - result is float
-        add_body.push(Instruction::LocalGet(6));
-        add_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        add_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        add_body.push(Instruction::LocalGet(6));
-        add_body.push(Instruction::I32Const(4));
-        add_body.push(Instruction::I32Add);
-        add_body.push(Instruction::LocalGet(7)); // left_val_float
-        add_body.push(Instruction::LocalGet(8)); // right_val_float
-        add_body.push(Instruction::F64Add);
-        add_body.push(Instruction::F64Store(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        
-        add_body.push(Instruction::End);
-        add_body.push(Instruction::End);
-        
-        // Return result
-        add_body.push(Instruction::LocalGet(6));
-        
-        self.builder.set_function_at_index(self.add_fn_idx, self.binary_op_type_idx, add_locals, add_body);
+
+    fn add_neg_function(&mut self) {
+        // neg(value) -> value
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (value, x) = (0, 1);
+        let body = vec![
+            LocalGet(value), Call(self.h.to_number), LocalTee(value), I32Load(mem32(0)), I32Const(TAG_INT), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), I64Load(mem64(VALUE_DATA)), LocalTee(x), I64Const(i64::MIN), I64Ne,
+                If(BlockType::Empty), I64Const(0), LocalGet(x), I64Sub, Call(self.h.new_int), Return, End,
+            End,
+            LocalGet(value), Call(self.h.to_float), F64Neg, Call(self.h.new_float),
+        ];
+        self.builder.set_function_at_index(self.h.neg, ty, vec![(1, ValType::I64)], body);
     }
-    
-    fn add_subtract_function(&mut self) {
-        // subtract function - PHP subtraction with type coercion
-        let mut subtract_body = vec![];
-        let mut subtract_locals = vec![];
-        subtract_locals.push((2, ValType::I32)); // left_type, right_type
-        subtract_locals.push((2, ValType::I64)); // left_val_int, right_val_int
-        subtract_locals.push((1, ValType::I32)); // result_ptr
-        subtract_locals.push((2, ValType::F64)); // left_val_float, right_val_float
-        subtract_locals.push((1, ValType::I32)); // result_is_float
-        
-        // Load type tags
-        subtract_body.push(Instruction::LocalGet(0)); // left value ptr
-        subtract_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        subtract_body.push(Instruction::LocalSet(2)); // left_type
-        
-        subtract_body.push(Instruction::LocalGet(1)); // right value ptr
-        subtract_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        subtract_body.push(Instruction::LocalSet(3)); // right_type
-        
-        // Initialize result_is_float to 0
-        subtract_body.push(Instruction::I32Const(0));
-        subtract_body.push(Instruction::LocalSet(9)); // result_is_float = false
-        
-        // Convert left operand to numeric
-        subtract_body.push(Instruction::LocalGet(2)); // left_type
-        subtract_body.push(Instruction::I32Const(TYPE_INT as i32));
-        subtract_body.push(Instruction::I32Eq);
-        subtract_body.push(Instruction::If(BlockType::Empty));
-        // Left is integer
-        subtract_body.push(Instruction::LocalGet(0));
-        subtract_body.push(Instruction::I32Const(4));
-        subtract_body.push(Instruction::I32Add);
-        subtract_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        subtract_body.push(Instruction::LocalSet(4)); // left_val_int
-        subtract_body.push(Instruction::LocalGet(4));
-        subtract_body.push(Instruction::F64ConvertI64S);
-        subtract_body.push(Instruction::LocalSet(7)); // left_val_float
-        subtract_body.push(Instruction::Else);
-        
-        subtract_body.push(Instruction::LocalGet(2)); // left_type
-        subtract_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        subtract_body.push(Instruction::I32Eq);
-        subtract_body.push(Instruction::If(BlockType::Empty));
-        // Left is float
-        subtract_body.push(Instruction::LocalGet(0));
-        subtract_body.push(Instruction::I32Const(4));
-        subtract_body.push(Instruction::I32Add);
-        subtract_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        subtract_body.push(Instruction::LocalSet(7)); // left_val_float
-        subtract_body.push(Instruction::I32Const(1));
-        subtract_body.push(Instruction::LocalSet(9)); // result_is_float = true
-        subtract_body.push(Instruction::Else);
-        // Left is neither int nor float - treat as 0
-        subtract_body.push(Instruction::F64Const(0.0.into()));
-        subtract_body.push(Instruction::LocalSet(7)); // left_val_float = 0
-        subtract_body.push(Instruction::End);
-        subtract_body.push(Instruction::End);
-        
-        // Convert right operand to numeric
-        subtract_body.push(Instruction::LocalGet(3)); // right_type
-        subtract_body.push(Instruction::I32Const(TYPE_INT as i32));
-        subtract_body.push(Instruction::I32Eq);
-        subtract_body.push(Instruction::If(BlockType::Empty));
-        // Right is integer
-        subtract_body.push(Instruction::LocalGet(1));
-        subtract_body.push(Instruction::I32Const(4));
-        subtract_body.push(Instruction::I32Add);
-        subtract_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        subtract_body.push(Instruction::LocalSet(5)); // right_val_int
-        subtract_body.push(Instruction::LocalGet(5));
-        subtract_body.push(Instruction::F64ConvertI64S);
-        subtract_body.push(Instruction::LocalSet(8)); // right_val_float
-        subtract_body.push(Instruction::Else);
-        
-        subtract_body.push(Instruction::LocalGet(3)); // right_type
-        subtract_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        subtract_body.push(Instruction::I32Eq);
-        subtract_body.push(Instruction::If(BlockType::Empty));
-        // Right is float
-        subtract_body.push(Instruction::LocalGet(1));
-        subtract_body.push(Instruction::I32Const(4));
-        subtract_body.push(Instruction::I32Add);
-        subtract_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        subtract_body.push(Instruction::LocalSet(8)); // right_val_float
-        subtract_body.push(Instruction::I32Const(1));
-        subtract_body.push(Instruction::LocalSet(9)); // result_is_float = true
-        subtract_body.push(Instruction::Else);
-        // Right is neither int nor float - treat as 0
-        subtract_body.push(Instruction::F64Const(0.0.into()));
-        subtract_body.push(Instruction::LocalSet(8)); // right_val_float = 0
-        subtract_body.push(Instruction::End);
-        subtract_body.push(Instruction::End);
-        
-        // Allocate result
-        subtract_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        subtract_body.push(Instruction::LocalSet(6)); // result_ptr
-        
-        // Check if result should be float
-        subtract_body.push(Instruction::LocalGet(9)); // result_is_float
-        subtract_body.push(Instruction::If(BlockType::Empty));
-        
-        // Result is float
-        subtract_body.push(Instruction::LocalGet(6));
-        subtract_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        subtract_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        subtract_body.push(Instruction::LocalGet(6));
-        subtract_body.push(Instruction::I32Const(4));
-        subtract_body.push(Instruction::I32Add);
-        subtract_body.push(Instruction::LocalGet(7)); // left_val_float
-        subtract_body.push(Instruction::LocalGet(8)); // right_val_float
-        subtract_body.push(Instruction::F64Sub);
-        subtract_body.push(Instruction::F64Store(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        
-        subtract_body.push(Instruction::Else);
-        
-        // Check if both are integers
-        subtract_body.push(Instruction::LocalGet(2)); // left_type
-        subtract_body.push(Instruction::I32Const(TYPE_INT as i32));
-        subtract_body.push(Instruction::I32Eq);
-        subtract_body.push(Instruction::LocalGet(3)); // right_type
-        subtract_body.push(Instruction::I32Const(TYPE_INT as i32));
-        subtract_body.push(Instruction::I32Eq);
-        subtract_body.push(Instruction::I32And);
-        subtract_body.push(Instruction::If(BlockType::Empty));
-        
-        // Both integers - do integer subtraction
-        subtract_body.push(Instruction::LocalGet(6));
-        subtract_body.push(Instruction::I32Const(TYPE_INT as i32));
-        subtract_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        subtract_body.push(Instruction::LocalGet(6));
-        subtract_body.push(Instruction::I32Const(4));
-        subtract_body.push(Instruction::I32Add);
-        subtract_body.push(Instruction::LocalGet(4)); // left_val_int
-        subtract_body.push(Instruction::LocalGet(5)); // right_val_int
-        subtract_body.push(Instruction::I64Sub);
-        subtract_body.push(Instruction::I64Store(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        
-        subtract_body.push(Instruction::Else);
-        
-        // Mixed types - result is float
-        subtract_body.push(Instruction::LocalGet(6));
-        subtract_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        subtract_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        subtract_body.push(Instruction::LocalGet(6));
-        subtract_body.push(Instruction::I32Const(4));
-        subtract_body.push(Instruction::I32Add);
-        subtract_body.push(Instruction::LocalGet(7)); // left_val_float
-        subtract_body.push(Instruction::LocalGet(8)); // right_val_float
-        subtract_body.push(Instruction::F64Sub);
-        subtract_body.push(Instruction::F64Store(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        
-        subtract_body.push(Instruction::End);
-        subtract_body.push(Instruction::End);
-        
-        // Return result
-        subtract_body.push(Instruction::LocalGet(6));
-        
-        self.builder.set_function_at_index(self.subtract_fn_idx, self.binary_op_type_idx, subtract_locals, subtract_body);
+
+    fn add_incdec_function(&mut self) {
+        // incdec(value, delta) -> value; delta is 1 or -1
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (value, delta, tag) = (0, 1, 2);
+        let one = self.int_literal(1);
+        let body = vec![
+            LocalGet(value), I32Load(mem32(0)), LocalTee(tag), I32Eqz,
+            If(BlockType::Empty),
+                // null++ is 1, null-- stays null
+                I32Const(one as i32), I32Const(NULL_VALUE as i32), LocalGet(delta), I32Const(0), I32GtS, Select,
+                Return,
+            End,
+            LocalGet(tag), I32Const(TAG_STRING), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), Call(self.h.parse_number), Drop,
+                GlobalGet(self.g.numeric), I32Const(2), I32Ne,
+                If(BlockType::Empty), LocalGet(value), Return, End,
+            End,
+            LocalGet(tag), I32Const(TAG_INT), I32Eq,
+            LocalGet(tag), I32Const(TAG_FLOAT), I32Eq, I32Or,
+            LocalGet(tag), I32Const(TAG_STRING), I32Eq, I32Or,
+            If(BlockType::Empty),
+                LocalGet(value), I32Const(one as i32),
+                I32Const(OP_ADD), I32Const(OP_SUB), LocalGet(delta), I32Const(0), I32GtS, Select,
+                Call(self.h.arith), Return,
+            End,
+            // Booleans, arrays and objects are left alone
+            LocalGet(value),
+        ];
+        self.builder.set_function_at_index(self.h.incdec, ty, vec![(1, ValType::I32)], body);
     }
-    
-    fn add_multiply_function(&mut self) {
-        // multiply(left: *PhpValue, right: *PhpValue) -> *PhpValue
-        // Handles PHP type coercion for multiplication
-        let mut multiply_body = vec![];
-        let mut multiply_locals = vec![];
-        multiply_locals.push((1, ValType::I32)); // left_type
-        multiply_locals.push((1, ValType::I32)); // right_type
-        multiply_locals.push((1, ValType::I64)); // left_val_int
-        multiply_locals.push((1, ValType::I64)); // right_val_int
-        multiply_locals.push((1, ValType::I32)); // result_ptr
-        multiply_locals.push((1, ValType::F64)); // left_val_float
-        multiply_locals.push((1, ValType::F64)); // right_val_float
-        multiply_locals.push((1, ValType::I32)); // result_is_float
-        
-        // Get types
-        multiply_body.push(Instruction::LocalGet(0));
-        multiply_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        multiply_body.push(Instruction::LocalSet(2)); // left_type
-        
-        multiply_body.push(Instruction::LocalGet(1));
-        multiply_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        multiply_body.push(Instruction::LocalSet(3)); // right_type
-        
-        // Initialize result_is_float to false
-        multiply_body.push(Instruction::I32Const(0));
-        multiply_body.push(Instruction::LocalSet(9)); // result_is_float = false
-        
-        // Convert left operand to numeric
-        multiply_body.push(Instruction::LocalGet(2)); // left_type
-        multiply_body.push(Instruction::I32Const(TYPE_INT as i32));
-        multiply_body.push(Instruction::I32Eq);
-        multiply_body.push(Instruction::If(BlockType::Empty));
-        // Left is int - load and convert to float
-        multiply_body.push(Instruction::LocalGet(0));
-        multiply_body.push(Instruction::I32Const(4));
-        multiply_body.push(Instruction::I32Add);
-        multiply_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        multiply_body.push(Instruction::LocalSet(4)); // left_val_int
-        multiply_body.push(Instruction::LocalGet(4));
-        multiply_body.push(Instruction::F64ConvertI64S);
-        multiply_body.push(Instruction::LocalSet(7)); // left_val_float
-        multiply_body.push(Instruction::Else);
-        
-        multiply_body.push(Instruction::LocalGet(2)); // left_type
-        multiply_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        multiply_body.push(Instruction::I32Eq);
-        multiply_body.push(Instruction::If(BlockType::Empty));
-        // Left is float
-        multiply_body.push(Instruction::LocalGet(0));
-        multiply_body.push(Instruction::I32Const(4));
-        multiply_body.push(Instruction::I32Add);
-        multiply_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        multiply_body.push(Instruction::LocalSet(7)); // left_val_float
-        multiply_body.push(Instruction::I32Const(1));
-        multiply_body.push(Instruction::LocalSet(9)); // result_is_float = true
-        multiply_body.push(Instruction::Else);
-        // Left is neither int nor float - treat as 0
-        multiply_body.push(Instruction::F64Const(0.0.into()));
-        multiply_body.push(Instruction::LocalSet(7)); // left_val_float = 0
-        multiply_body.push(Instruction::End);
-        multiply_body.push(Instruction::End);
-        
-        // Convert right operand to numeric
-        multiply_body.push(Instruction::LocalGet(3)); // right_type
-        multiply_body.push(Instruction::I32Const(TYPE_INT as i32));
-        multiply_body.push(Instruction::I32Eq);
-        multiply_body.push(Instruction::If(BlockType::Empty));
-        // Right is int - load and convert to float
-        multiply_body.push(Instruction::LocalGet(1));
-        multiply_body.push(Instruction::I32Const(4));
-        multiply_body.push(Instruction::I32Add);
-        multiply_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        multiply_body.push(Instruction::LocalSet(5)); // right_val_int
-        multiply_body.push(Instruction::LocalGet(5));
-        multiply_body.push(Instruction::F64ConvertI64S);
-        multiply_body.push(Instruction::LocalSet(8)); // right_val_float
-        multiply_body.push(Instruction::Else);
-        
-        multiply_body.push(Instruction::LocalGet(3)); // right_type
-        multiply_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        multiply_body.push(Instruction::I32Eq);
-        multiply_body.push(Instruction::If(BlockType::Empty));
-        // Right is float
-        multiply_body.push(Instruction::LocalGet(1));
-        multiply_body.push(Instruction::I32Const(4));
-        multiply_body.push(Instruction::I32Add);
-        multiply_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        multiply_body.push(Instruction::LocalSet(8)); // right_val_float
-        multiply_body.push(Instruction::I32Const(1));
-        multiply_body.push(Instruction::LocalSet(9)); // result_is_float = true
-        multiply_body.push(Instruction::Else);
-        // Right is neither int nor float - treat as 0
-        multiply_body.push(Instruction::F64Const(0.0.into()));
-        multiply_body.push(Instruction::LocalSet(8)); // right_val_float = 0
-        multiply_body.push(Instruction::End);
-        multiply_body.push(Instruction::End);
-        
-        // Allocate result
-        multiply_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        multiply_body.push(Instruction::LocalSet(6)); // result_ptr
-        
-        // Always use float multiplication and check if we can store as int
-        multiply_body.push(Instruction::LocalGet(7)); // left_val_float
-        multiply_body.push(Instruction::LocalGet(8)); // right_val_float
-        multiply_body.push(Instruction::F64Mul);
-        multiply_body.push(Instruction::LocalSet(7)); // reuse left_val_float for result
-        
-        // Check if result is integer and not forced to float
-        multiply_body.push(Instruction::LocalGet(9)); // result_is_float
-        multiply_body.push(Instruction::I32Eqz);
-        multiply_body.push(Instruction::If(BlockType::Empty));
-        // Check if result can be represented as integer
-        multiply_body.push(Instruction::LocalGet(7)); // result float
-        multiply_body.push(Instruction::F64Trunc);
-        multiply_body.push(Instruction::LocalGet(7));
-        multiply_body.push(Instruction::F64Eq);
-        multiply_body.push(Instruction::If(BlockType::Empty));
-        // Result is integer - store as int
-        multiply_body.push(Instruction::LocalGet(6));
-        multiply_body.push(Instruction::I32Const(TYPE_INT as i32));
-        multiply_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        multiply_body.push(Instruction::LocalGet(6));
-        multiply_body.push(Instruction::I32Const(4));
-        multiply_body.push(Instruction::I32Add);
-        multiply_body.push(Instruction::LocalGet(7));
-        multiply_body.push(Instruction::I64TruncF64S);
-        multiply_body.push(Instruction::I64Store(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        multiply_body.push(Instruction::Else);
-        // Result has decimals - store as float
-        multiply_body.push(Instruction::LocalGet(6));
-        multiply_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        multiply_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        multiply_body.push(Instruction::LocalGet(6));
-        multiply_body.push(Instruction::I32Const(4));
-        multiply_body.push(Instruction::I32Add);
-        multiply_body.push(Instruction::LocalGet(7));
-        multiply_body.push(Instruction::F64Store(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        multiply_body.push(Instruction::End);
-        multiply_body.push(Instruction::Else);
-        // Forced to float
-        multiply_body.push(Instruction::LocalGet(6));
-        multiply_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        multiply_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        multiply_body.push(Instruction::LocalGet(6));
-        multiply_body.push(Instruction::I32Const(4));
-        multiply_body.push(Instruction::I32Add);
-        multiply_body.push(Instruction::LocalGet(7));
-        multiply_body.push(Instruction::F64Store(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        multiply_body.push(Instruction::End);
-        
-        // Return result pointer
-        multiply_body.push(Instruction::LocalGet(6));
-        
-        self.builder.set_function_at_index(self.multiply_fn_idx, self.binary_op_type_idx, multiply_locals, multiply_body);
+
+    fn add_math_round_function(&mut self) {
+        // math_round(value, precision) -> float, halves rounded away from zero
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (value, precision, places, n) = (0, 1, 2, 3);
+        let (scale, x) = (4, 5);
+        let round_half_away = [F64Abs, F64Const(0.5.into()), F64Add, F64Floor, LocalGet(x), F64Copysign];
+        let mut body = vec![
+            LocalGet(value), Call(self.h.to_float), LocalSet(x),
+            LocalGet(precision), Call(self.h.to_int), I32WrapI64, LocalTee(places),
+            I32Const(0), LocalGet(places), I32Sub, LocalGet(places), I32Const(0), I32GeS, Select, LocalSet(n),
+            F64Const(1.0.into()), LocalSet(scale),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(n), I32Eqz, BrIf(1),
+                LocalGet(scale), F64Const(10.0.into()), F64Mul, LocalSet(scale),
+                LocalGet(n), I32Const(1), I32Sub, LocalSet(n),
+                Br(0),
+            End,
+            End,
+            LocalGet(places), I32Const(0), I32GeS,
+            If(BlockType::Empty),
+                LocalGet(x), LocalGet(scale), F64Mul, LocalTee(x),
+        ];
+        body.extend(round_half_away.clone());
+        body.extend([
+                LocalGet(scale), F64Div, Call(self.h.new_float), Return,
+            End,
+            LocalGet(x), LocalGet(scale), F64Div, LocalTee(x),
+        ]);
+        body.extend(round_half_away);
+        body.extend([LocalGet(scale), F64Mul, Call(self.h.new_float)]);
+        let locals = vec![(2, ValType::I32), (2, ValType::F64)];
+        self.builder.set_function_at_index(self.h.math_round, ty, locals, body);
     }
-    
-    fn add_divide_function(&mut self) {
-        // divide(left: *PhpValue, right: *PhpValue) -> *PhpValue
-        // Handles PHP type coercion for division
-        let mut divide_body = vec![];
-        let mut divide_locals = vec![];
-        divide_locals.push((1, ValType::I32)); // left_type
-        divide_locals.push((1, ValType::I32)); // right_type
-        divide_locals.push((1, ValType::I64)); // left_val_int
-        divide_locals.push((1, ValType::I64)); // right_val_int
-        divide_locals.push((1, ValType::I32)); // result_ptr
-        divide_locals.push((1, ValType::F64)); // left_val_float
-        divide_locals.push((1, ValType::F64)); // right_val_float
-        
-        // Get types
-        divide_body.push(Instruction::LocalGet(0));
-        divide_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        divide_body.push(Instruction::LocalSet(2)); // left_type
-        
-        divide_body.push(Instruction::LocalGet(1));
-        divide_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        divide_body.push(Instruction::LocalSet(3)); // right_type
-        
-        // Convert left operand to numeric
-        divide_body.push(Instruction::LocalGet(2)); // left_type
-        divide_body.push(Instruction::I32Const(TYPE_INT as i32));
-        divide_body.push(Instruction::I32Eq);
-        divide_body.push(Instruction::If(BlockType::Empty));
-        // Left is int - load and convert to float
-        divide_body.push(Instruction::LocalGet(0));
-        divide_body.push(Instruction::I32Const(4));
-        divide_body.push(Instruction::I32Add);
-        divide_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        divide_body.push(Instruction::LocalSet(4)); // left_val_int
-        divide_body.push(Instruction::LocalGet(4));
-        divide_body.push(Instruction::F64ConvertI64S);
-        divide_body.push(Instruction::LocalSet(7)); // left_val_float
-        divide_body.push(Instruction::Else);
-        
-        divide_body.push(Instruction::LocalGet(2)); // left_type
-        divide_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        divide_body.push(Instruction::I32Eq);
-        divide_body.push(Instruction::If(BlockType::Empty));
-        // Left is float
-        divide_body.push(Instruction::LocalGet(0));
-        divide_body.push(Instruction::I32Const(4));
-        divide_body.push(Instruction::I32Add);
-        divide_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        divide_body.push(Instruction::LocalSet(7)); // left_val_float
-        divide_body.push(Instruction::Else);
-        // Left is neither int nor float - treat as 0
-        divide_body.push(Instruction::F64Const(0.0.into()));
-        divide_body.push(Instruction::LocalSet(7)); // left_val_float = 0
-        divide_body.push(Instruction::End);
-        divide_body.push(Instruction::End);
-        
-        // Convert right operand to numeric
-        divide_body.push(Instruction::LocalGet(3)); // right_type
-        divide_body.push(Instruction::I32Const(TYPE_INT as i32));
-        divide_body.push(Instruction::I32Eq);
-        divide_body.push(Instruction::If(BlockType::Empty));
-        // Right is int - load and convert to float
-        divide_body.push(Instruction::LocalGet(1));
-        divide_body.push(Instruction::I32Const(4));
-        divide_body.push(Instruction::I32Add);
-        divide_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        divide_body.push(Instruction::LocalSet(5)); // right_val_int
-        divide_body.push(Instruction::LocalGet(5));
-        divide_body.push(Instruction::F64ConvertI64S);
-        divide_body.push(Instruction::LocalSet(8)); // right_val_float
-        divide_body.push(Instruction::Else);
-        
-        divide_body.push(Instruction::LocalGet(3)); // right_type
-        divide_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        divide_body.push(Instruction::I32Eq);
-        divide_body.push(Instruction::If(BlockType::Empty));
-        // Right is float
-        divide_body.push(Instruction::LocalGet(1));
-        divide_body.push(Instruction::I32Const(4));
-        divide_body.push(Instruction::I32Add);
-        divide_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        divide_body.push(Instruction::LocalSet(8)); // right_val_float
-        divide_body.push(Instruction::Else);
-        // Right is neither int nor float - treat as 0
-        divide_body.push(Instruction::F64Const(0.0.into()));
-        divide_body.push(Instruction::LocalSet(8)); // right_val_float = 0
-        divide_body.push(Instruction::End);
-        divide_body.push(Instruction::End);
-        
-        // Allocate result
-        divide_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        divide_body.push(Instruction::LocalSet(6)); // result_ptr
-        
-        // Division in PHP always returns float (except for integer division operator //)
-        divide_body.push(Instruction::LocalGet(6));
-        divide_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        divide_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Perform division and store result
-        divide_body.push(Instruction::LocalGet(6));
-        divide_body.push(Instruction::I32Const(4));
-        divide_body.push(Instruction::I32Add);
-        divide_body.push(Instruction::LocalGet(7)); // left_val_float
-        divide_body.push(Instruction::LocalGet(8)); // right_val_float
-        divide_body.push(Instruction::F64Div);
-        divide_body.push(Instruction::F64Store(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        
-        // Return result pointer
-        divide_body.push(Instruction::LocalGet(6));
-        
-        self.builder.set_function_at_index(self.divide_fn_idx, self.binary_op_type_idx, divide_locals, divide_body);
+
+    fn add_math_pow_function(&mut self) {
+        // math_pow(base, exponent) -> value; an int when both are ints, the
+        // exponent is not negative and the result fits. Exponents must be
+        // integral: there is no wasm instruction for the general case.
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (base, exponent, n) = (0, 1, 2);
+        let (x, e, result) = (3, 4, 5);
+        let body = vec![
+            LocalGet(base), Call(self.h.to_number), LocalTee(base), Call(self.h.to_float), LocalSet(x),
+            LocalGet(exponent), Call(self.h.to_number), LocalTee(exponent), Call(self.h.to_float), LocalTee(e),
+            F64Trunc, LocalGet(e), F64Ne,
+            If(BlockType::Empty), F64Const(f64::NAN.into()), Call(self.h.new_float), Return, End,
+            // Square and multiply
+            LocalGet(e), F64Abs, I64TruncSatF64U, LocalSet(n),
+            F64Const(1.0.into()), LocalSet(result),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(n), I64Eqz, BrIf(1),
+                LocalGet(n), I64Const(1), I64And, I32WrapI64,
+                If(BlockType::Empty), LocalGet(result), LocalGet(x), F64Mul, LocalSet(result), End,
+                LocalGet(x), LocalGet(x), F64Mul, LocalSet(x),
+                LocalGet(n), I64Const(1), I64ShrU, LocalSet(n),
+                Br(0),
+            End,
+            End,
+            LocalGet(e), F64Const(0.0.into()), F64Lt,
+            If(BlockType::Empty), F64Const(1.0.into()), LocalGet(result), F64Div, Call(self.h.new_float), Return, End,
+            LocalGet(base), I32Load(mem32(0)), I32Const(TAG_INT), I32Eq,
+            LocalGet(exponent), I32Load(mem32(0)), I32Const(TAG_INT), I32Eq, I32And,
+            LocalGet(result), F64Abs, F64Const(9007199254740992.0.into()), F64Lt, I32And,
+            If(BlockType::Empty), LocalGet(result), I64TruncSatF64S, Call(self.h.new_int), Return, End,
+            LocalGet(result), Call(self.h.new_float),
+        ];
+        let locals = vec![(1, ValType::I64), (3, ValType::F64)];
+        self.builder.set_function_at_index(self.h.math_pow, ty, locals, body);
     }
 }
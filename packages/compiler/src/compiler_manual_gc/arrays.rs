@@ -0,0 +1,626 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Arrays are ordered hash tables: entries in insertion order, and buckets
+// heading chains of entries with the same hash. Keys are ints or strings;
+// strings holding a canonical int ("12", not "012") are int keys.
+
+use super::core::*;
+use wasm_encoder::Instruction::*;
+use wasm_encoder::{BlockType, Instruction, ValType};
+
+const INITIAL_CAPACITY: i32 = 8;
+
+/// Run `body` for each live entry of the array in `array`, with the entry
+/// address in `entry`
+pub(super) fn for_each_entry(array: u32, index: u32, entry: u32, body: Vec<Instruction<'static>>) -> Vec<Instruction<'static>> {
+    let mut code = vec![
+        I32Const(0), LocalSet(index),
+        Block(BlockType::Empty),
+        Loop(BlockType::Empty),
+            LocalGet(index), LocalGet(array), I32Load(mem32(ARR_USED)), I32GeU, BrIf(1),
+            LocalGet(array), I32Load(mem32(ARR_ENTRIES)), LocalGet(index), I32Const(ENTRY_SIZE), I32Mul, I32Add,
+            LocalTee(entry), I32Load(mem32(ENTRY_KEY_STR)), I32Const(-1), I32Ne,
+            If(BlockType::Empty),
+    ];
+    code.extend(body);
+    code.extend([
+            End,
+            LocalGet(index), I32Const(1), I32Add, LocalSet(index),
+            Br(0),
+        End,
+        End,
+    ]);
+    code
+}
+
+impl Compiler {
+    pub(super) fn add_array_functions(&mut self) {
+        self.add_arr_new_function();
+        self.add_norm_key_function();
+        self.add_key_hash_function();
+        self.add_entry_key_function();
+        self.add_arr_find_function();
+        self.add_arr_insert_function();
+        self.add_arr_get_function();
+        self.add_arr_slot_function();
+        self.add_arr_append_function();
+        self.add_arr_grow_function();
+        self.add_arr_delete_function();
+        self.add_arr_copy_function();
+        self.add_copy_value_function();
+        self.add_place_array_function();
+        self.add_arr_extend_function();
+        self.add_count_function();
+        self.add_in_array_function();
+        self.add_array_keys_function();
+        self.add_array_values_function();
+        self.add_array_merge_function();
+        self.add_array_slice_function();
+        self.add_array_pop_function();
+        self.add_array_shift_function();
+        self.add_array_unshift_function();
+    }
+
+    fn add_arr_new_function(&mut self) {
+        // arr_new(capacity hint) -> empty array
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (hint, cap, array) = (0, 1, 2);
+        let body = vec![
+            I32Const(INITIAL_CAPACITY), LocalSet(cap),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(cap), LocalGet(hint), I32GeU, BrIf(1),
+                LocalGet(cap), I32Const(1), I32Shl, LocalSet(cap),
+                Br(0),
+            End,
+            End,
+            I32Const(ARRAY_SIZE), I32Const(KIND_VALUE), Call(self.h.alloc), LocalTee(array),
+            I32Const(TAG_ARRAY), I32Store(mem32(0)),
+            LocalGet(array), LocalGet(cap), I32Store(mem32(ARR_CAP)),
+            LocalGet(array), LocalGet(cap), I32Const(ENTRY_SIZE), I32Mul, I32Const(KIND_DATA), Call(self.h.alloc),
+            I32Store(mem32(ARR_ENTRIES)),
+            LocalGet(array), LocalGet(cap), I32Const(2), I32Shl, I32Const(KIND_DATA), Call(self.h.alloc),
+            I32Store(mem32(ARR_BUCKETS)),
+            LocalGet(array),
+        ];
+        self.builder.set_function_at_index(self.h.arr_new, ty, vec![(2, ValType::I32)], body);
+    }
+
+    fn add_norm_key_function(&mut self) {
+        // norm_key(key) -> int or string value used as the key
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (key, tag, len, c, number) = (0, 1, 2, 3, 4);
+        let empty = self.string_literal("");
+        let body = vec![
+            LocalGet(key), I32Load(mem32(0)), LocalTee(tag), I32Const(TAG_INT), I32Eq,
+            If(BlockType::Empty), LocalGet(key), Return, End,
+            LocalGet(tag), I32Const(TAG_STRING), I32Eq,
+            If(BlockType::Empty),
+                // Cheap checks first: a sign or digit first, a digit last
+                LocalGet(key), I32Load(mem32(STR_LEN)), LocalTee(len), I32Eqz,
+                LocalGet(len), I32Const(20), I32GtU, I32Or,
+                If(BlockType::Empty), LocalGet(key), Return, End,
+                LocalGet(key), I32Load8U(mem8(STR_BYTES)), LocalTee(c), I32Const(b'-' as i32), I32Ne,
+                LocalGet(c), I32Const(b'0' as i32), I32Sub, I32Const(9), I32GtU, I32And,
+                LocalGet(key), LocalGet(len), I32Add, I32Load8U(mem8(STR_BYTES - 1)), I32Const(b'0' as i32), I32Sub,
+                I32Const(9), I32GtU, I32Or,
+                If(BlockType::Empty), LocalGet(key), Return, End,
+                LocalGet(key), Call(self.h.parse_number), LocalTee(number), I32Load(mem32(0)), I32Const(TAG_INT), I32Eq,
+                If(BlockType::Empty),
+                    // Only the canonical spelling of the int
+                    LocalGet(number), I64Load(mem64(VALUE_DATA)), Call(self.h.int_to_string), LocalGet(key), Call(self.h.str_eq),
+                    If(BlockType::Empty), LocalGet(number), Return, End,
+                End,
+                LocalGet(key), Return,
+            End,
+            LocalGet(tag), I32Eqz,
+            If(BlockType::Empty), I32Const(empty as i32), Return, End,
+            LocalGet(key), Call(self.h.to_int), Call(self.h.new_int),
+        ];
+        self.builder.set_function_at_index(self.h.norm_key, ty, vec![(4, ValType::I32)], body);
+    }
+
+    fn add_key_hash_function(&mut self) {
+        // key_hash(int key, string key or 0) -> i32
+        let ty = self.builder.add_type(vec![ValType::I64, ValType::I32], vec![ValType::I32]);
+        let (int_key, str_key, hash, i, end) = (0, 1, 2, 3, 4);
+        let body = vec![
+            LocalGet(str_key), I32Eqz,
+            If(BlockType::Empty),
+                LocalGet(int_key), LocalGet(int_key), I64Const(32), I64ShrU, I64Xor, I32WrapI64,
+                I32Const(0x9E37_79B1_u32 as i32), I32Mul, Return,
+            End,
+            // FNV-1a
+            I32Const(0x811C_9DC5_u32 as i32), LocalSet(hash),
+            LocalGet(str_key), I32Const(STR_BYTES as i32), I32Add, LocalTee(i),
+            LocalGet(str_key), I32Load(mem32(STR_LEN)), I32Add, LocalSet(end),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(end), I32GeU, BrIf(1),
+                LocalGet(hash), LocalGet(i), I32Load8U(mem8(0)), I32Xor, I32Const(0x0100_0193), I32Mul, LocalSet(hash),
+                LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                Br(0),
+            End,
+            End,
+            LocalGet(hash),
+        ];
+        self.builder.set_function_at_index(self.h.key_hash, ty, vec![(3, ValType::I32)], body);
+    }
+
+    fn add_entry_key_function(&mut self) {
+        // entry_key(entry) -> key value
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let entry = 0;
+        let body = vec![
+            LocalGet(entry), I32Load(mem32(ENTRY_KEY_STR)),
+            If(BlockType::Result(ValType::I32)),
+                LocalGet(entry), I32Load(mem32(ENTRY_KEY_STR)),
+            Else,
+                LocalGet(entry), I64Load(mem64(ENTRY_KEY_INT)), Call(self.h.new_int),
+            End,
+        ];
+        self.builder.set_function_at_index(self.h.entry_key, ty, vec![], body);
+    }
+
+    fn add_arr_find_function(&mut self) {
+        // arr_find(array, int key, string key or 0) -> entry or 0
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I64, ValType::I32], vec![ValType::I32]);
+        let (array, int_key, str_key, hash, next, entry, key) = (0, 1, 2, 3, 4, 5, 6);
+        let body = vec![
+            LocalGet(int_key), LocalGet(str_key), Call(self.h.key_hash), LocalSet(hash),
+            LocalGet(array), I32Load(mem32(ARR_BUCKETS)),
+            LocalGet(hash), LocalGet(array), I32Load(mem32(ARR_CAP)), I32Const(1), I32Sub, I32And, I32Const(2), I32Shl, I32Add,
+            I32Load(mem32(0)), LocalSet(next),
+            Loop(BlockType::Empty),
+                LocalGet(next), I32Eqz, If(BlockType::Empty), I32Const(0), Return, End,
+                LocalGet(array), I32Load(mem32(ARR_ENTRIES)), LocalGet(next), I32Const(1), I32Sub, I32Const(ENTRY_SIZE), I32Mul, I32Add,
+                LocalTee(entry), I32Load(mem32(ENTRY_HASH)), LocalGet(hash), I32Eq,
+                If(BlockType::Empty),
+                    LocalGet(entry), I32Load(mem32(ENTRY_KEY_STR)), LocalSet(key),
+                    LocalGet(str_key), I32Eqz,
+                    If(BlockType::Empty),
+                        LocalGet(key), I32Eqz, LocalGet(entry), I64Load(mem64(ENTRY_KEY_INT)), LocalGet(int_key), I64Eq, I32And,
+                        If(BlockType::Empty), LocalGet(entry), Return, End,
+                    Else,
+                        LocalGet(key), I32Const(1), I32Add, I32Const(1), I32GtU,
+                        If(BlockType::Empty),
+                            LocalGet(key), LocalGet(str_key), Call(self.h.str_eq),
+                            If(BlockType::Empty), LocalGet(entry), Return, End,
+                        End,
+                    End,
+                End,
+                LocalGet(entry), I32Load(mem32(ENTRY_NEXT)), LocalSet(next),
+                Br(0),
+            End,
+            Unreachable,
+        ];
+        self.builder.set_function_at_index(self.h.arr_find, ty, vec![(4, ValType::I32)], body);
+    }
+
+    fn add_arr_insert_function(&mut self) {
+        // arr_insert(array, int key, string key or 0) -> new entry holding null
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I64, ValType::I32], vec![ValType::I32]);
+        let (array, int_key, str_key, used, entry, hash, bucket) = (0, 1, 2, 3, 4, 5, 6);
+        let body = vec![
+            LocalGet(array), I32Load(mem32(ARR_USED)), LocalGet(array), I32Load(mem32(ARR_CAP)), I32Eq,
+            If(BlockType::Empty), LocalGet(array), Call(self.h.arr_grow), End,
+            LocalGet(array), I32Load(mem32(ARR_USED)), LocalSet(used),
+            LocalGet(array), I32Load(mem32(ARR_ENTRIES)), LocalGet(used), I32Const(ENTRY_SIZE), I32Mul, I32Add, LocalTee(entry),
+            LocalGet(int_key), I64Store(mem64(ENTRY_KEY_INT)),
+            LocalGet(entry), LocalGet(str_key), I32Store(mem32(ENTRY_KEY_STR)),
+            LocalGet(entry), I32Const(NULL_VALUE as i32), I32Store(mem32(ENTRY_VALUE)),
+            LocalGet(entry), LocalGet(int_key), LocalGet(str_key), Call(self.h.key_hash), LocalTee(hash), I32Store(mem32(ENTRY_HASH)),
+            LocalGet(array), I32Load(mem32(ARR_BUCKETS)),
+            LocalGet(hash), LocalGet(array), I32Load(mem32(ARR_CAP)), I32Const(1), I32Sub, I32And, I32Const(2), I32Shl, I32Add,
+            LocalSet(bucket),
+            LocalGet(entry), LocalGet(bucket), I32Load(mem32(0)), I32Store(mem32(ENTRY_NEXT)),
+            LocalGet(bucket), LocalGet(used), I32Const(1), I32Add, I32Store(mem32(0)),
+            LocalGet(array), LocalGet(used), I32Const(1), I32Add, I32Store(mem32(ARR_USED)),
+            LocalGet(array), LocalGet(array), I32Load(mem32(ARR_COUNT)), I32Const(1), I32Add, I32Store(mem32(ARR_COUNT)),
+            // Appending continues after the largest int key
+            LocalGet(str_key), I32Eqz,
+            LocalGet(int_key), LocalGet(array), I64Load(mem64(ARR_NEXT)), I64GeS, I32And,
+            LocalGet(int_key), I64Const(i64::MAX), I64Ne, I32And,
+            If(BlockType::Empty),
+                LocalGet(array), LocalGet(int_key), I64Const(1), I64Add, I64Store(mem64(ARR_NEXT)),
+            End,
+            LocalGet(entry),
+        ];
+        self.builder.set_function_at_index(self.h.arr_insert, ty, vec![(4, ValType::I32)], body);
+    }
+
+    /// Split the normalized key in `key` into `int_key` and `str_key`
+    fn split_key(key: u32, int_key: u32, str_key: u32) -> Vec<Instruction<'static>> {
+        vec![
+            LocalGet(key), I32Load(mem32(0)), I32Const(TAG_INT), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(key), I64Load(mem64(VALUE_DATA)), LocalSet(int_key),
+                I32Const(0), LocalSet(str_key),
+            Else,
+                I64Const(0), LocalSet(int_key),
+                LocalGet(key), LocalSet(str_key),
+            End,
+        ]
+    }
+
+    fn add_arr_get_function(&mut self) {
+        // arr_get(container, key) -> value; null for missing keys and non-arrays
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (container, key, tag, str_key, entry, len, result) = (0, 1, 2, 3, 4, 5, 6);
+        let int_key = 7;
+        let empty = self.string_literal("");
+        let mut body = vec![
+            LocalGet(container), I32Load(mem32(0)), LocalTee(tag), I32Const(TAG_ARRAY), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(key), Call(self.h.norm_key), LocalSet(key),
+        ];
+        body.extend(Self::split_key(key, int_key, str_key));
+        body.extend([
+                LocalGet(container), LocalGet(int_key), LocalGet(str_key), Call(self.h.arr_find), LocalTee(entry),
+                If(BlockType::Empty), LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), Return, End,
+                I32Const(NULL_VALUE as i32), Return,
+            End,
+            // String offsets, counted from the end when negative
+            LocalGet(tag), I32Const(TAG_STRING), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(container), I32Load(mem32(STR_LEN)), LocalSet(len),
+                LocalGet(key), Call(self.h.to_int), LocalTee(int_key), I64Const(0), I64LtS,
+                If(BlockType::Empty), LocalGet(int_key), LocalGet(len), I64ExtendI32U, I64Add, LocalSet(int_key), End,
+                LocalGet(int_key), LocalGet(len), I64ExtendI32U, I64GeU,
+                If(BlockType::Empty), I32Const(empty as i32), Return, End,
+                I32Const(1), Call(self.h.new_string), LocalTee(result),
+                LocalGet(container), LocalGet(int_key), I32WrapI64, I32Add, I32Load8U(mem8(STR_BYTES)),
+                I32Store8(mem8(STR_BYTES)),
+                LocalGet(result), Return,
+            End,
+            I32Const(NULL_VALUE as i32),
+        ]);
+        let locals = vec![(5, ValType::I32), (1, ValType::I64)];
+        self.builder.set_function_at_index(self.h.arr_get, ty, locals, body);
+    }
+
+    fn add_arr_slot_function(&mut self) {
+        // arr_slot(array, key) -> address of the value, adding the key if needed
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (array, key, str_key, entry) = (0, 1, 2, 3);
+        let int_key = 4;
+        let mut body = vec![LocalGet(key), Call(self.h.norm_key), LocalSet(key)];
+        body.extend(Self::split_key(key, int_key, str_key));
+        body.extend([
+            LocalGet(array), LocalGet(int_key), LocalGet(str_key), Call(self.h.arr_find), LocalTee(entry), I32Eqz,
+            If(BlockType::Empty),
+                LocalGet(array), LocalGet(int_key), LocalGet(str_key), Call(self.h.arr_insert), LocalSet(entry),
+            End,
+            LocalGet(entry), I32Const(ENTRY_VALUE as i32), I32Add,
+        ]);
+        let locals = vec![(2, ValType::I32), (1, ValType::I64)];
+        self.builder.set_function_at_index(self.h.arr_slot, ty, locals, body);
+    }
+
+    fn add_arr_append_function(&mut self) {
+        // arr_append(array, value)
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![]);
+        let (array, value) = (0, 1);
+        let body = vec![
+            LocalGet(array), LocalGet(array), I64Load(mem64(ARR_NEXT)), I32Const(0), Call(self.h.arr_insert),
+            LocalGet(value), I32Store(mem32(ENTRY_VALUE)),
+        ];
+        self.builder.set_function_at_index(self.h.arr_append, ty, vec![], body);
+    }
+
+    fn add_arr_grow_function(&mut self) {
+        // arr_grow(array): rebuild the table without tombstones, doubling it
+        // unless removing the tombstones frees enough room
+        let ty = self.builder.add_type(vec![ValType::I32], vec![]);
+        let (array, cap, entries, buckets, i, j, entry, target, bucket) = (0, 1, 2, 3, 4, 5, 6, 7, 8);
+        let body = vec![
+            LocalGet(array), I32Load(mem32(ARR_CAP)), LocalSet(cap),
+            LocalGet(cap), I32Const(1), I32Shl, LocalGet(cap),
+            LocalGet(array), I32Load(mem32(ARR_COUNT)), I32Const(1), I32Shl, LocalGet(cap), I32GtU, Select, LocalSet(cap),
+            LocalGet(cap), I32Const(ENTRY_SIZE), I32Mul, I32Const(KIND_DATA), Call(self.h.alloc), LocalSet(entries),
+            LocalGet(cap), I32Const(2), I32Shl, I32Const(KIND_DATA), Call(self.h.alloc), LocalSet(buckets),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(array), I32Load(mem32(ARR_USED)), I32GeU, BrIf(1),
+                LocalGet(array), I32Load(mem32(ARR_ENTRIES)), LocalGet(i), I32Const(ENTRY_SIZE), I32Mul, I32Add, LocalTee(entry),
+                I32Load(mem32(ENTRY_KEY_STR)), I32Const(-1), I32Ne,
+                If(BlockType::Empty),
+                    LocalGet(entries), LocalGet(j), I32Const(ENTRY_SIZE), I32Mul, I32Add, LocalTee(target),
+                    LocalGet(entry), I32Const(ENTRY_SIZE), MEMORY_COPY,
+                    LocalGet(buckets), LocalGet(target), I32Load(mem32(ENTRY_HASH)), LocalGet(cap), I32Const(1), I32Sub, I32And,
+                    I32Const(2), I32Shl, I32Add, LocalSet(bucket),
+                    LocalGet(target), LocalGet(bucket), I32Load(mem32(0)), I32Store(mem32(ENTRY_NEXT)),
+                    LocalGet(j), I32Const(1), I32Add, LocalSet(j),
+                    LocalGet(bucket), LocalGet(j), I32Store(mem32(0)),
+                End,
+                LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                Br(0),
+            End,
+            End,
+            LocalGet(array), LocalGet(entries), I32Store(mem32(ARR_ENTRIES)),
+            LocalGet(array), LocalGet(buckets), I32Store(mem32(ARR_BUCKETS)),
+            LocalGet(array), LocalGet(cap), I32Store(mem32(ARR_CAP)),
+            LocalGet(array), LocalGet(j), I32Store(mem32(ARR_USED)),
+        ];
+        self.builder.set_function_at_index(self.h.arr_grow, ty, vec![(8, ValType::I32)], body);
+    }
+
+    fn add_arr_delete_function(&mut self) {
+        // arr_delete(array, key)
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![]);
+        let (array, key, str_key, entry) = (0, 1, 2, 3);
+        let int_key = 4;
+        let mut body = vec![LocalGet(key), Call(self.h.norm_key), LocalSet(key)];
+        body.extend(Self::split_key(key, int_key, str_key));
+        body.extend([
+            LocalGet(array), LocalGet(int_key), LocalGet(str_key), Call(self.h.arr_find), LocalTee(entry),
+            If(BlockType::Empty),
+                LocalGet(entry), I32Const(-1), I32Store(mem32(ENTRY_KEY_STR)),
+                LocalGet(array), LocalGet(array), I32Load(mem32(ARR_COUNT)), I32Const(1), I32Sub, I32Store(mem32(ARR_COUNT)),
+            End,
+        ]);
+        let locals = vec![(2, ValType::I32), (1, ValType::I64)];
+        self.builder.set_function_at_index(self.h.arr_delete, ty, locals, body);
+    }
+
+    fn add_arr_copy_function(&mut self) {
+        // arr_copy(array) -> a copy, with nested arrays copied too
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (array, copy, i, entry) = (0, 1, 2, 3);
+        let mut body = vec![
+            LocalGet(array), I32Load(mem32(ARR_CAP)), Call(self.h.arr_new), LocalTee(copy),
+            I32Load(mem32(ARR_ENTRIES)), LocalGet(array), I32Load(mem32(ARR_ENTRIES)),
+            LocalGet(array), I32Load(mem32(ARR_USED)), I32Const(ENTRY_SIZE), I32Mul, MEMORY_COPY,
+            LocalGet(copy), I32Load(mem32(ARR_BUCKETS)), LocalGet(array), I32Load(mem32(ARR_BUCKETS)),
+            LocalGet(array), I32Load(mem32(ARR_CAP)), I32Const(2), I32Shl, MEMORY_COPY,
+            LocalGet(copy), LocalGet(array), I32Load(mem32(ARR_COUNT)), I32Store(mem32(ARR_COUNT)),
+            LocalGet(copy), LocalGet(array), I32Load(mem32(ARR_USED)), I32Store(mem32(ARR_USED)),
+            LocalGet(copy), LocalGet(array), I64Load(mem64(ARR_NEXT)), I64Store(mem64(ARR_NEXT)),
+        ];
+        body.extend(for_each_entry(copy, i, entry, vec![
+            LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), I32Load(mem32(0)), I32Const(TAG_ARRAY), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(entry), LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), Call(self.h.arr_copy), I32Store(mem32(ENTRY_VALUE)),
+            End,
+        ]));
+        body.push(LocalGet(copy));
+        self.builder.set_function_at_index(self.h.arr_copy, ty, vec![(3, ValType::I32)], body);
+    }
+
+    fn add_copy_value_function(&mut self) {
+        // copy_value(value) -> the value to store when assigning it
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let body = vec![
+            LocalGet(0), I32Load(mem32(0)), I32Const(TAG_ARRAY), I32Eq,
+            If(BlockType::Result(ValType::I32)),
+                LocalGet(0), Call(self.h.arr_copy),
+            Else,
+                LocalGet(0),
+            End,
+        ];
+        self.builder.set_function_at_index(self.h.copy_value, ty, vec![], body);
+    }
+
+    fn add_place_array_function(&mut self) {
+        // place_array(address) -> the array stored there, creating it for null
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (address, value) = (0, 1);
+        let message = self.string_literal("PHP Fatal error:  Uncaught Error: Cannot use a scalar value as an array\n");
+        let body = vec![
+            LocalGet(address), I32Load(mem32(0)), LocalTee(value), I32Load(mem32(0)), I32Const(TAG_ARRAY), I32Eq,
+            If(BlockType::Empty), LocalGet(value), Return, End,
+            LocalGet(value), I32Load(mem32(0)), I32Const(TAG_NULL), I32Ne,
+            If(BlockType::Empty), I32Const(message as i32), Call(self.h.fatal), End,
+            LocalGet(address), I32Const(0), Call(self.h.arr_new), LocalTee(value), I32Store(mem32(0)),
+            LocalGet(value),
+        ];
+        self.builder.set_function_at_index(self.h.place_array, ty, vec![(1, ValType::I32)], body);
+    }
+
+    fn add_arr_extend_function(&mut self) {
+        // arr_extend(target, source): add the entries of `source`; string keys
+        // are kept, int keys are renumbered
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![]);
+        let (target, source, i, entry, key, slot) = (0, 1, 2, 3, 4, 5);
+        let body = for_each_entry(source, i, entry, vec![
+            LocalGet(entry), I32Load(mem32(ENTRY_KEY_STR)), LocalTee(key),
+            If(BlockType::Empty),
+                LocalGet(target), I64Const(0), LocalGet(key), Call(self.h.arr_find), LocalTee(slot), I32Eqz,
+                If(BlockType::Empty),
+                    LocalGet(target), I64Const(0), LocalGet(key), Call(self.h.arr_insert), LocalSet(slot),
+                End,
+                LocalGet(slot), LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), Call(self.h.copy_value),
+                I32Store(mem32(ENTRY_VALUE)),
+            Else,
+                LocalGet(target), LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), Call(self.h.copy_value),
+                Call(self.h.arr_append),
+            End,
+        ]);
+        self.builder.set_function_at_index(self.h.arr_extend, ty, vec![(4, ValType::I32)], body);
+    }
+
+    fn add_count_function(&mut self) {
+        // count(value) -> int
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let message = self.string_literal(
+            "PHP Fatal error:  Uncaught TypeError: count(): Argument #1 ($value) must be of type Countable|array\n");
+        let body = vec![
+            LocalGet(0), I32Load(mem32(0)), I32Const(TAG_ARRAY), I32Ne,
+            If(BlockType::Empty), I32Const(message as i32), Call(self.h.fatal), End,
+            LocalGet(0), I32Load(mem32(ARR_COUNT)), I64ExtendI32U, Call(self.h.new_int),
+        ];
+        self.builder.set_function_at_index(self.h.count, ty, vec![], body);
+    }
+
+    fn add_in_array_function(&mut self) {
+        // in_array(needle, haystack, strict) -> bool
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (needle, haystack, strict, i, entry) = (0, 1, 2, 3, 4);
+        let mut body = vec![
+            LocalGet(haystack), I32Load(mem32(0)), I32Const(TAG_ARRAY), I32Ne,
+            If(BlockType::Empty), I32Const(FALSE_VALUE as i32), Return, End,
+            LocalGet(strict), Call(self.h.to_bool), LocalSet(strict),
+        ];
+        body.extend(for_each_entry(haystack, i, entry, vec![
+            LocalGet(strict),
+            If(BlockType::Result(ValType::I32)),
+                LocalGet(needle), LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), Call(self.h.strict_eq),
+            Else,
+                LocalGet(needle), LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), Call(self.h.loose_eq),
+            End,
+            If(BlockType::Empty), I32Const(TRUE_VALUE as i32), Return, End,
+        ]));
+        body.push(I32Const(FALSE_VALUE as i32));
+        self.builder.set_function_at_index(self.h.in_array, ty, vec![(2, ValType::I32)], body);
+    }
+
+    fn add_array_keys_function(&mut self) {
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (array, result, i, entry) = (0, 1, 2, 3);
+        let mut body = vec![LocalGet(array), I32Load(mem32(ARR_COUNT)), Call(self.h.arr_new), LocalSet(result)];
+        body.extend(for_each_entry(array, i, entry, vec![
+            LocalGet(result), LocalGet(entry), Call(self.h.entry_key), Call(self.h.arr_append),
+        ]));
+        body.push(LocalGet(result));
+        self.builder.set_function_at_index(self.h.array_keys, ty, vec![(3, ValType::I32)], body);
+    }
+
+    fn add_array_values_function(&mut self) {
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (array, result, i, entry) = (0, 1, 2, 3);
+        let mut body = vec![LocalGet(array), I32Load(mem32(ARR_COUNT)), Call(self.h.arr_new), LocalSet(result)];
+        body.extend(for_each_entry(array, i, entry, vec![
+            LocalGet(result), LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), Call(self.h.copy_value), Call(self.h.arr_append),
+        ]));
+        body.push(LocalGet(result));
+        self.builder.set_function_at_index(self.h.array_values, ty, vec![(3, ValType::I32)], body);
+    }
+
+    fn add_array_merge_function(&mut self) {
+        // array_merge(a, b)
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (a, b, result) = (0, 1, 2);
+        let body = vec![
+            LocalGet(a), I32Load(mem32(ARR_COUNT)), LocalGet(b), I32Load(mem32(ARR_COUNT)), I32Add,
+            Call(self.h.arr_new), LocalTee(result), LocalGet(a), Call(self.h.arr_extend),
+            LocalGet(result), LocalGet(b), Call(self.h.arr_extend),
+            LocalGet(result),
+        ];
+        self.builder.set_function_at_index(self.h.array_merge, ty, vec![(1, ValType::I32)], body);
+    }
+
+    fn add_array_slice_function(&mut self) {
+        // array_slice(array, offset, length or null, preserve_keys)
+        let ty = self.builder.add_type(vec![ValType::I32; 4], vec![ValType::I32]);
+        let (array, offset_value, length_value, preserve, n, offset, length, position, result, i, entry) =
+            (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10);
+        let mut body = vec![
+            LocalGet(array), I32Load(mem32(ARR_COUNT)), LocalSet(n),
+            LocalGet(offset_value), Call(self.h.to_int), I32WrapI64, LocalTee(offset), I32Const(0), I32LtS,
+            If(BlockType::Empty),
+                LocalGet(n), LocalGet(offset), I32Add, LocalSet(offset),
+                LocalGet(offset), I32Const(0), I32LtS, If(BlockType::Empty), I32Const(0), LocalSet(offset), End,
+            End,
+            LocalGet(offset), LocalGet(n), I32GtS, If(BlockType::Empty), LocalGet(n), LocalSet(offset), End,
+            LocalGet(length_value), I32Load(mem32(0)), I32Eqz,
+            If(BlockType::Empty),
+                LocalGet(n), LocalGet(offset), I32Sub, LocalSet(length),
+            Else,
+                LocalGet(length_value), Call(self.h.to_int), I32WrapI64, LocalTee(length), I32Const(0), I32LtS,
+                If(BlockType::Empty),
+                    LocalGet(n), LocalGet(offset), I32Sub, LocalGet(length), I32Add, LocalSet(length),
+                End,
+            End,
+            LocalGet(preserve), Call(self.h.to_bool), LocalSet(preserve),
+            LocalGet(length), I32Const(0), LocalGet(length), I32Const(0), I32GtS, Select, Call(self.h.arr_new), LocalSet(result),
+        ];
+        body.extend(for_each_entry(array, i, entry, vec![
+            LocalGet(position), LocalGet(offset), I32GeS,
+            LocalGet(position), LocalGet(offset), LocalGet(length), I32Add, I32LtS, I32And,
+            If(BlockType::Empty),
+                LocalGet(entry), I32Load(mem32(ENTRY_KEY_STR)), LocalGet(preserve), I32Or,
+                If(BlockType::Empty),
+                    LocalGet(result), LocalGet(entry), Call(self.h.entry_key), Call(self.h.arr_slot),
+                    LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), Call(self.h.copy_value), I32Store(mem32(0)),
+                Else,
+                    LocalGet(result), LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), Call(self.h.copy_value),
+                    Call(self.h.arr_append),
+                End,
+            End,
+            LocalGet(position), I32Const(1), I32Add, LocalSet(position),
+        ]));
+        body.push(LocalGet(result));
+        self.builder.set_function_at_index(self.h.array_slice, ty, vec![(7, ValType::I32)], body);
+    }
+
+    fn add_array_pop_function(&mut self) {
+        // array_pop(array) -> the last value, or null
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (array, i, entry) = (0, 1, 2);
+        let body = vec![
+            LocalGet(array), I32Load(mem32(ARR_USED)), LocalSet(i),
+            Loop(BlockType::Empty),
+                LocalGet(i), I32Eqz, If(BlockType::Empty), I32Const(NULL_VALUE as i32), Return, End,
+                LocalGet(array), I32Load(mem32(ARR_ENTRIES)), LocalGet(i), I32Const(1), I32Sub, LocalTee(i),
+                I32Const(ENTRY_SIZE), I32Mul, I32Add, LocalTee(entry), I32Load(mem32(ENTRY_KEY_STR)), I32Const(-1), I32Eq,
+                BrIf(0),
+            End,
+            // The next appended key reuses a popped int key
+            LocalGet(entry), I32Load(mem32(ENTRY_KEY_STR)), I32Eqz,
+            LocalGet(entry), I64Load(mem64(ENTRY_KEY_INT)), I64Const(1), I64Add, LocalGet(array), I64Load(mem64(ARR_NEXT)), I64Eq,
+            I32And,
+            If(BlockType::Empty),
+                LocalGet(array), LocalGet(entry), I64Load(mem64(ENTRY_KEY_INT)), I64Store(mem64(ARR_NEXT)),
+            End,
+            LocalGet(entry), I32Const(-1), I32Store(mem32(ENTRY_KEY_STR)),
+            LocalGet(array), LocalGet(array), I32Load(mem32(ARR_COUNT)), I32Const(1), I32Sub, I32Store(mem32(ARR_COUNT)),
+            LocalGet(entry), I32Load(mem32(ENTRY_VALUE)),
+        ];
+        self.builder.set_function_at_index(self.h.array_pop, ty, vec![(2, ValType::I32)], body);
+    }
+
+    /// Replace the table of `array` with the one of `source`
+    fn take_table(array: u32, source: u32) -> Vec<Instruction<'static>> {
+        vec![
+            LocalGet(array), I32Const(4), I32Add, LocalGet(source), I32Const(4), I32Add,
+            I32Const(ARRAY_SIZE - 4), MEMORY_COPY,
+        ]
+    }
+
+    fn add_array_shift_function(&mut self) {
+        // array_shift(array) -> the first value, or null; int keys are renumbered
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (array, i, entry, value, result) = (0, 1, 2, 3, 4);
+        let mut body = vec![
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(array), I32Load(mem32(ARR_USED)), I32GeU,
+                If(BlockType::Empty), I32Const(NULL_VALUE as i32), Return, End,
+                LocalGet(array), I32Load(mem32(ARR_ENTRIES)), LocalGet(i), I32Const(ENTRY_SIZE), I32Mul, I32Add, LocalTee(entry),
+                LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                I32Load(mem32(ENTRY_KEY_STR)), I32Const(-1), I32Eq, BrIf(0),
+            End,
+            LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), LocalSet(value),
+            LocalGet(entry), I32Const(-1), I32Store(mem32(ENTRY_KEY_STR)),
+            LocalGet(array), LocalGet(array), I32Load(mem32(ARR_COUNT)), I32Const(1), I32Sub, I32Store(mem32(ARR_COUNT)),
+            LocalGet(array), I32Load(mem32(ARR_COUNT)), Call(self.h.arr_new), LocalTee(result), LocalGet(array), Call(self.h.arr_extend),
+        ];
+        body.extend(Self::take_table(array, result));
+        body.push(LocalGet(value));
+        self.builder.set_function_at_index(self.h.array_shift, ty, vec![(4, ValType::I32)], body);
+    }
+
+    fn add_array_unshift_function(&mut self) {
+        // array_unshift(array, value) -> the new count
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (array, value, result) = (0, 1, 2);
+        let mut body = vec![
+            LocalGet(array), I32Load(mem32(ARR_COUNT)), I32Const(1), I32Add, Call(self.h.arr_new), LocalTee(result),
+            LocalGet(value), Call(self.h.arr_append),
+            LocalGet(result), LocalGet(array), Call(self.h.arr_extend),
+        ];
+        body.extend(Self::take_table(array, result));
+        body.extend([LocalGet(array), I32Load(mem32(ARR_COUNT)), I64ExtendI32U, Call(self.h.new_int)]);
+        self.builder.set_function_at_index(self.h.array_unshift, ty, vec![(1, ValType::I32)], body);
+    }
+}
@@ -0,0 +1,355 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Built-in PHP functions: the same set as the WasmGC backend. Most are a
+// call to a runtime helper; functions that change an array take the address
+// of the variable holding it.
+
+use super::core::*;
+use edge_php_parser::ast::*;
+use wasm_encoder::{BlockType, Instruction, ValType};
+
+impl Compiler {
+    /// Compile a call to a built-in function. Returns false for other names.
+    pub(super) fn compile_builtin(&mut self, name: &str, args: &[Expression]) -> Result<bool, String> {
+        let h = self.h;
+        match name {
+            "is_int" | "is_integer" | "is_long" => self.compile_type_check(name, args, TAG_INT)?,
+            "is_float" | "is_double" | "is_real" => self.compile_type_check(name, args, TAG_FLOAT)?,
+            "is_string" => self.compile_type_check(name, args, TAG_STRING)?,
+            "is_bool" => self.compile_type_check(name, args, TAG_BOOL)?,
+            "is_array" => self.compile_type_check(name, args, TAG_ARRAY)?,
+            "is_object" => self.compile_type_check(name, args, TAG_OBJECT)?,
+            "is_null" => self.compile_type_check(name, args, TAG_NULL)?,
+
+            // The old `array(...)` syntax
+            "array" => {
+                let elements: Vec<ArrayElement> = args.iter().map(|arg| ArrayElement { key: None, value: arg.clone() }).collect();
+                self.compile_array_literal(&elements)?;
+            }
+            "count" | "sizeof" => self.compile_helper_call(name, args, 1, 1, h.count)?,
+            "array_push" => self.compile_array_push(args)?,
+            "array_pop" => self.compile_array_update(name, args, h.array_pop)?,
+            "array_shift" => self.compile_array_update(name, args, h.array_shift)?,
+            "array_unshift" => self.compile_array_unshift(args)?,
+            "in_array" => self.compile_helper_call(name, args, 2, 3, h.in_array)?,
+            "array_keys" => self.compile_helper_call(name, args, 1, 1, h.array_keys)?,
+            "array_values" => self.compile_helper_call(name, args, 1, 1, h.array_values)?,
+            "array_merge" => self.compile_array_merge(args)?,
+            "array_slice" => self.compile_helper_call(name, args, 2, 4, h.array_slice)?,
+
+            "strlen" => {
+                self.compile_helper_call(name, args, 1, 1, h.to_string)?;
+                self.emit_all([
+                    Instruction::I32Load(mem32(STR_LEN)),
+                    Instruction::I64ExtendI32U,
+                    Instruction::Call(h.new_int),
+                ]);
+            }
+            "substr" => self.compile_helper_call(name, args, 2, 3, h.substr)?,
+            "strpos" => self.compile_helper_call(name, args, 2, 3, h.strpos)?,
+            "strtolower" => self.compile_helper_call(name, args, 1, 1, h.strtolower)?,
+            "strtoupper" => self.compile_helper_call(name, args, 1, 1, h.strtoupper)?,
+            "trim" => self.compile_helper_call(name, args, 1, 2, h.trim)?,
+            "str_replace" => self.compile_helper_call(name, args, 3, 3, h.str_replace)?,
+            "explode" => self.compile_helper_call(name, args, 2, 3, h.explode)?,
+            "implode" | "join" if args.len() == 1 => {
+                let empty = self.string_literal("");
+                self.emit(Instruction::I32Const(empty as i32));
+                self.compile_helper_call(name, args, 1, 1, h.implode)?;
+            }
+            "implode" | "join" => self.compile_helper_call(name, args, 2, 2, h.implode)?,
+
+            "abs" => self.compile_abs(args)?,
+            "min" => self.compile_min_max(name, args, -1)?,
+            "max" => self.compile_min_max(name, args, 1)?,
+            "round" => {
+                if args.len() == 1 {
+                    self.compile_helper_call(name, args, 1, 1, h.to_number)?;
+                    let zero = self.int_literal(0);
+                    self.emit_all([Instruction::I32Const(zero as i32), Instruction::Call(h.math_round)]);
+                } else {
+                    self.compile_helper_call(name, args, 2, 2, h.math_round)?;
+                }
+            }
+            "floor" => self.compile_float_function(name, args, Instruction::F64Floor)?,
+            "ceil" => self.compile_float_function(name, args, Instruction::F64Ceil)?,
+            "sqrt" => self.compile_float_function(name, args, Instruction::F64Sqrt)?,
+            "pow" => self.compile_helper_call(name, args, 2, 2, h.math_pow)?,
+
+            "isset" => {
+                for (i, arg) in args.iter().enumerate() {
+                    self.compile_isset(arg)?;
+                    if i > 0 {
+                        self.emit(Instruction::I32And);
+                    }
+                }
+                if args.is_empty() {
+                    return Err("isset() expects at least 1 parameter".to_string());
+                }
+                self.emit_bool_value();
+            }
+            "empty" => {
+                let [arg] = args else {
+                    return Err("empty() expects exactly 1 parameter".to_string());
+                };
+                self.compile_expression(arg)?;
+                self.emit_all([Instruction::Call(h.to_bool), Instruction::I32Eqz]);
+                self.emit_bool_value();
+            }
+            "unset" => {
+                for arg in args {
+                    self.compile_unset(arg)?;
+                }
+                self.emit(Instruction::I32Const(NULL_VALUE as i32));
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Pass `min..=max` arguments to a helper; left out optional arguments
+    /// are null
+    fn compile_helper_call(&mut self, name: &str, args: &[Expression], min: usize, max: usize, helper: u32) -> Result<(), String> {
+        if args.len() < min || args.len() > max {
+            return Err(match min == max {
+                true => format!("{}() expects exactly {} parameters, {} given", name, min, args.len()),
+                false => format!("{}() expects {} to {} parameters, {} given", name, min, max, args.len()),
+            });
+        }
+        for arg in args {
+            self.compile_expression(arg)?;
+        }
+        for _ in args.len()..max {
+            self.emit(Instruction::I32Const(NULL_VALUE as i32));
+        }
+        self.emit(Instruction::Call(helper));
+        Ok(())
+    }
+
+    fn compile_type_check(&mut self, name: &str, args: &[Expression], tag: i32) -> Result<(), String> {
+        let [arg] = args else {
+            return Err(format!("{}() expects exactly 1 parameter", name));
+        };
+        self.compile_expression(arg)?;
+        self.emit_all([Instruction::I32Load(mem32(0)), Instruction::I32Const(tag), Instruction::I32Eq]);
+        self.emit_bool_value();
+        Ok(())
+    }
+
+    /// The array held by the first argument, which must be a variable,
+    /// element or property
+    fn compile_array_arg(&mut self, name: &str, args: &[Expression]) -> Result<u32, String> {
+        let Some(target) = args.first() else {
+            return Err(format!("{}() expects an array as its first parameter", name));
+        };
+        let array = self.allocate_local(ValType::I32);
+        self.compile_place(target)?;
+        self.emit_all([Instruction::Call(self.h.place_array), Instruction::LocalSet(array)]);
+        Ok(array)
+    }
+
+    fn compile_array_push(&mut self, args: &[Expression]) -> Result<(), String> {
+        let array = self.compile_array_arg("array_push", args)?;
+        for arg in &args[1..] {
+            self.emit(Instruction::LocalGet(array));
+            self.compile_argument(arg)?;
+            self.emit(Instruction::Call(self.h.arr_append));
+        }
+        self.emit_all([
+            Instruction::LocalGet(array),
+            Instruction::I32Load(mem32(ARR_COUNT)),
+            Instruction::I64ExtendI32U,
+            Instruction::Call(self.h.new_int),
+        ]);
+        Ok(())
+    }
+
+    fn compile_array_update(&mut self, name: &str, args: &[Expression], helper: u32) -> Result<(), String> {
+        if args.len() != 1 {
+            return Err(format!("{}() expects exactly 1 parameter", name));
+        }
+        let array = self.compile_array_arg(name, args)?;
+        self.emit_all([Instruction::LocalGet(array), Instruction::Call(helper)]);
+        Ok(())
+    }
+
+    fn compile_array_unshift(&mut self, args: &[Expression]) -> Result<(), String> {
+        let array = self.compile_array_arg("array_unshift", args)?;
+        let values: Vec<u32> = args[1..].iter()
+            .map(|arg| {
+                self.compile_argument(arg)?;
+                let value = self.allocate_local(ValType::I32);
+                self.emit(Instruction::LocalSet(value));
+                Ok(value)
+            })
+            .collect::<Result<_, String>>()?;
+        // Prepending the last value first keeps the values in order
+        for value in values.into_iter().rev() {
+            self.emit_all([
+                Instruction::LocalGet(array),
+                Instruction::LocalGet(value),
+                Instruction::Call(self.h.array_unshift),
+                Instruction::Drop,
+            ]);
+        }
+        self.emit_all([
+            Instruction::LocalGet(array),
+            Instruction::I32Load(mem32(ARR_COUNT)),
+            Instruction::I64ExtendI32U,
+            Instruction::Call(self.h.new_int),
+        ]);
+        Ok(())
+    }
+
+    fn compile_array_merge(&mut self, args: &[Expression]) -> Result<(), String> {
+        let Some((first, rest)) = args.split_first() else {
+            self.emit_all([Instruction::I32Const(0), Instruction::Call(self.h.arr_new)]);
+            return Ok(());
+        };
+        self.compile_expression(first)?;
+        if rest.is_empty() {
+            // Renumbers the keys
+            self.emit_all([Instruction::I32Const(0), Instruction::Call(self.h.arr_new), Instruction::Call(self.h.array_merge)]);
+        }
+        for arg in rest {
+            self.compile_expression(arg)?;
+            self.emit(Instruction::Call(self.h.array_merge));
+        }
+        Ok(())
+    }
+
+    fn compile_abs(&mut self, args: &[Expression]) -> Result<(), String> {
+        self.compile_helper_call("abs", args, 1, 1, self.h.to_number)?;
+        let value = self.allocate_local(ValType::I32);
+        let zero = self.int_literal(0);
+        self.emit_all([
+            Instruction::LocalTee(value),
+            Instruction::I32Const(zero as i32),
+            Instruction::Call(self.h.compare),
+            Instruction::I32Const(0),
+            Instruction::I32LtS,
+            Instruction::If(BlockType::Result(ValType::I32)),
+            Instruction::LocalGet(value),
+            Instruction::Call(self.h.neg),
+            Instruction::Else,
+            Instruction::LocalGet(value),
+            Instruction::End,
+        ]);
+        Ok(())
+    }
+
+    /// `min()` and `max()` of their arguments, or of the values of a single
+    /// array argument. `sign` is the comparison result that replaces the best.
+    fn compile_min_max(&mut self, name: &str, args: &[Expression], sign: i32) -> Result<(), String> {
+        let best = self.allocate_local(ValType::I32);
+        let replace = |compiler: &mut Self, candidate: Vec<Instruction<'static>>| {
+            let mut code = candidate.clone();
+            code.extend([Instruction::LocalGet(best), Instruction::Call(compiler.h.compare), Instruction::I32Const(sign), Instruction::I32Eq]);
+            code.push(Instruction::If(BlockType::Empty));
+            code.extend(candidate);
+            code.extend([Instruction::LocalSet(best), Instruction::End]);
+            code
+        };
+        match args {
+            [] => return Err(format!("{}() expects at least 1 parameter", name)),
+            [array] => {
+                let (source, index, entry) = (
+                    self.allocate_local(ValType::I32),
+                    self.allocate_local(ValType::I32),
+                    self.allocate_local(ValType::I32),
+                );
+                let first = self.allocate_local(ValType::I32);
+                self.compile_expression(array)?;
+                self.emit_all([
+                    Instruction::LocalSet(source),
+                    Instruction::I32Const(1),
+                    Instruction::LocalSet(first),
+                    Instruction::I32Const(FALSE_VALUE as i32),
+                    Instruction::LocalSet(best),
+                ]);
+                let value = vec![Instruction::LocalGet(entry), Instruction::I32Load(mem32(ENTRY_VALUE))];
+                let mut body = vec![Instruction::LocalGet(first), Instruction::If(BlockType::Empty)];
+                body.extend(value.clone());
+                body.extend([Instruction::LocalSet(best), Instruction::I32Const(0), Instruction::LocalSet(first), Instruction::End]);
+                body.extend(replace(self, value));
+                let code = super::arrays::for_each_entry(source, index, entry, body);
+                self.emit_all(code);
+            }
+            [first, rest @ ..] => {
+                self.compile_expression(first)?;
+                self.emit(Instruction::LocalSet(best));
+                for arg in rest {
+                    let candidate = self.compile_to_local(arg)?;
+                    let code = replace(self, vec![Instruction::LocalGet(candidate)]);
+                    self.emit_all(code);
+                }
+            }
+        }
+        self.emit(Instruction::LocalGet(best));
+        Ok(())
+    }
+
+    fn compile_float_function(&mut self, name: &str, args: &[Expression], op: Instruction<'static>) -> Result<(), String> {
+        self.compile_helper_call(name, args, 1, 1, self.h.to_float)?;
+        self.emit_all([op, Instruction::Call(self.h.new_float)]);
+        Ok(())
+    }
+
+    /// `isset(...)` of one expression, as an i32
+    fn compile_isset(&mut self, expr: &Expression) -> Result<(), String> {
+        match expr {
+            Expression::PropertyAccess { object, property } if self.this_prop(object, property).is_none() => {
+                self.compile_expression(object)?;
+                let name = self.string_literal(property);
+                self.emit_all([Instruction::I32Const(name as i32), Instruction::Call(self.h.prop_isset)]);
+            }
+            Expression::Variable(_) | Expression::ArrayAccess { .. } | Expression::PropertyAccess { .. }
+            | Expression::StaticPropertyAccess { .. } | Expression::NullsafePropertyAccess { .. } => {
+                self.compile_expression(expr)?;
+                self.emit_all([Instruction::I32Load(mem32(0)), Instruction::I32Const(TAG_NULL), Instruction::I32Ne]);
+            }
+            _ => return Err("Cannot use isset() on the result of an expression".to_string()),
+        }
+        Ok(())
+    }
+
+    fn compile_unset(&mut self, expr: &Expression) -> Result<(), String> {
+        match expr {
+            Expression::Variable(name) => {
+                let loc = self.variable(name);
+                self.emit_release(loc);
+                let null = self.allocate_local(ValType::I32);
+                self.emit_all([Instruction::I32Const(NULL_VALUE as i32), Instruction::LocalSet(null)]);
+                self.emit_store(loc, null);
+            }
+            Expression::ArrayAccess { array, index } => {
+                // Arrays have a single owner, so the value read is the array to change
+                let container = self.compile_to_local(array)?;
+                let key = self.compile_to_local(index)?;
+                self.emit_all([
+                    Instruction::LocalGet(container),
+                    Instruction::I32Load(mem32(0)),
+                    Instruction::I32Const(TAG_ARRAY),
+                    Instruction::I32Eq,
+                    Instruction::If(BlockType::Empty),
+                    Instruction::LocalGet(container),
+                    Instruction::LocalGet(key),
+                    Instruction::Call(self.h.arr_delete),
+                    Instruction::End,
+                ]);
+            }
+            Expression::PropertyAccess { object, property } => {
+                self.compile_expression(object)?;
+                let name = self.string_literal(property);
+                self.emit_all([
+                    Instruction::I32Const(name as i32),
+                    Instruction::I32Const(self.scope_id()),
+                    Instruction::Call(self.h.prop_unset),
+                ]);
+            }
+            _ => return Err("Cannot unset the result of an expression".to_string()),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,751 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Classes, interfaces and enums. Every class gets an id and a function that
+// allocates an object with its property defaults. Methods are called through
+// one dispatch function per method name, which branches on the class id, so
+// an object's class only needs to be known at runtime.
+
+use super::core::*;
+use super::types::DeclaredType;
+use edge_php_parser::ast::*;
+use edge_php_parser::visit::{self, Visitor};
+use std::collections::HashMap;
+use wasm_encoder::{Instruction, ValType};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum ClassKind {
+    Class,
+    Interface,
+    Enum,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct PropInfo {
+    pub(super) name: String,
+    /// The class declaring it, which TypeErrors name
+    pub(super) class: String,
+    pub(super) declared: Option<DeclaredType>,
+    pub(super) default: Option<Expression>,
+    /// Written once, from inside the declaring class
+    pub(super) readonly: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct StaticProp {
+    pub(super) slot: u32,
+    pub(super) class: String,
+    pub(super) declared: Option<DeclaredType>,
+    pub(super) default: Option<Expression>,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct MethodInfo {
+    pub(super) idx: u32,
+    pub(super) params: Vec<Parameter>,
+    pub(super) is_static: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct ClassInfo {
+    pub(super) id: u32,
+    pub(super) kind: ClassKind,
+    pub(super) parent: Option<String>,
+    pub(super) interfaces: Vec<String>,
+    /// Instance properties in object order; a parent's come first
+    pub(super) props: Vec<PropInfo>,
+    pub(super) static_props: HashMap<String, StaticProp>,
+    /// Own and inherited methods by lowercase name
+    pub(super) methods: HashMap<String, MethodInfo>,
+    pub(super) constants: HashMap<String, Expression>,
+    /// Enum cases: name, static slot of the case object and backing value
+    pub(super) cases: Vec<(String, u32, Option<Expression>)>,
+    /// Allocates an object with the property defaults
+    pub(super) init: u32,
+}
+
+impl ClassInfo {
+    pub(super) fn prop_index(&self, name: &str) -> Option<usize> {
+        self.props.iter().position(|prop| prop.name == name)
+    }
+}
+
+/// The largest argument count of each method name's call sites
+struct CallArities(HashMap<String, usize>);
+
+impl Visitor for CallArities {
+    fn visit_expression(&mut self, expr: &Expression) {
+        let call = match expr {
+            Expression::MethodCall { method, args, .. }
+            | Expression::NullsafeMethodCall { method, args, .. } => Some((method.to_lowercase(), args.len())),
+            Expression::Invoke { args, .. } => Some(("__invoke".to_string(), args.len())),
+            _ => None,
+        };
+        if let Some((method, count)) = call {
+            let arity = self.0.entry(method).or_insert(0);
+            *arity = (*arity).max(count);
+        }
+        visit::walk_expression(self, expr);
+    }
+}
+
+impl Compiler {
+    /// Give every class, interface and enum an id, its properties and the
+    /// function indices of its methods
+    pub(super) fn register_classes(&mut self, statements: &[&Statement]) -> Result<(), String> {
+        let decls: HashMap<&str, &Statement> = statements.iter()
+            .filter_map(|stmt| match stmt {
+                Statement::Class { name, .. } | Statement::Interface { name, .. } | Statement::Enum { name, .. } => {
+                    Some((name.as_str(), *stmt))
+                }
+                _ => None,
+            })
+            .collect();
+        for stmt in statements {
+            if let Statement::Class { name, .. } | Statement::Interface { name, .. } | Statement::Enum { name, .. } = stmt {
+                self.register_class(name, &decls, &mut Vec::new())?;
+            }
+        }
+
+        let mut arities = CallArities(HashMap::new());
+        for stmt in statements {
+            arities.visit_statement(stmt);
+        }
+        self.call_arities = arities.0;
+        Ok(())
+    }
+
+    fn register_class(&mut self, name: &str, decls: &HashMap<&str, &Statement>, pending: &mut Vec<String>) -> Result<(), String> {
+        if self.classes.contains_key(name) {
+            return Ok(());
+        }
+        if pending.iter().any(|class| class == name) {
+            return Err(format!("Class {} extends itself", name));
+        }
+        let (kind, parent, interfaces, members, readonly_class) = match decls[name] {
+            Statement::Class { extends, implements, members, is_readonly, .. } => {
+                (ClassKind::Class, extends.clone(), implements.clone(), members, *is_readonly)
+            }
+            Statement::Interface { members, .. } => (ClassKind::Interface, None, Vec::new(), members, false),
+            Statement::Enum { implements, members, .. } => (ClassKind::Enum, None, implements.clone(), members, false),
+            _ => unreachable!("only class-like statements are registered"),
+        };
+        pending.push(name.to_string());
+        for class in parent.iter().chain(&interfaces) {
+            if !decls.contains_key(class.as_str()) {
+                return Err(format!("Class \"{}\" not found", class));
+            }
+            self.register_class(class, decls, pending)?;
+        }
+        pending.pop();
+
+        let (mut props, mut methods) = match &parent {
+            Some(parent) => (self.classes[parent].props.clone(), self.classes[parent].methods.clone()),
+            None => (Vec::new(), HashMap::new()),
+        };
+        if kind == ClassKind::Enum {
+            let backed = matches!(decls[name], Statement::Enum { backing_type: Some(_), .. });
+            props.push(PropInfo { name: "name".to_string(), class: name.to_string(), declared: None, default: None, readonly: false });
+            if backed {
+                props.push(PropInfo { name: "value".to_string(), class: name.to_string(), declared: None, default: None, readonly: false });
+            }
+        }
+
+        let mut static_props = HashMap::new();
+        let mut constants = HashMap::new();
+        let mut cases = Vec::new();
+        let class_name = name.to_string();
+        let add_prop = |props: &mut Vec<PropInfo>, prop: PropInfo| match props.iter_mut().find(|p| p.name == prop.name) {
+            Some(existing) => *existing = prop,
+            None => props.push(prop),
+        };
+        for member in members {
+            match member {
+                ClassMember::Property { is_static: false, name, property_type, default, is_readonly, .. } => {
+                    let declared = property_type.as_ref().and_then(DeclaredType::of);
+                    let readonly = *is_readonly || readonly_class;
+                    add_prop(&mut props, PropInfo { name: name.clone(), class: class_name.clone(), declared, default: default.clone(), readonly });
+                }
+                ClassMember::Property { is_static: true, name, property_type, default, .. } => {
+                    let slot = self.root_slot();
+                    let declared = property_type.as_ref().and_then(DeclaredType::of);
+                    static_props.insert(name.clone(), StaticProp { slot, class: class_name.clone(), declared, default: default.clone() });
+                }
+                ClassMember::Method { is_static, name: method, params, .. } => {
+                    // Promoted constructor parameters are properties too
+                    for (param, promoted) in params.iter().filter_map(|param| Some((param, param.promoted.as_ref()?))) {
+                        let declared = param.param_type.as_ref().and_then(DeclaredType::of);
+                        let readonly = promoted.is_readonly || readonly_class;
+                        add_prop(&mut props, PropInfo { name: param.name.clone(), class: class_name.clone(), declared, default: None, readonly });
+                    }
+                    let idx = self.builder.reserve_function_index();
+                    self.name_function(idx, &format!("{}::{}", name, method));
+                    methods.insert(method.to_lowercase(), MethodInfo {
+                        idx,
+                        params: params.clone(),
+                        is_static: *is_static,
+                    });
+                }
+                ClassMember::Constructor { params, .. } => {
+                    for (param, promoted) in params.iter().filter_map(|param| Some((param, param.promoted.as_ref()?))) {
+                        let declared = param.param_type.as_ref().and_then(DeclaredType::of);
+                        let readonly = promoted.is_readonly || readonly_class;
+                        add_prop(&mut props, PropInfo { name: param.name.clone(), class: class_name.clone(), declared, default: None, readonly });
+                    }
+                    let idx = self.builder.reserve_function_index();
+                    self.name_function(idx, &format!("{}::__construct", name));
+                    methods.insert("__construct".to_string(), MethodInfo {
+                        idx,
+                        params: params.clone(),
+                        is_static: false,
+                    });
+                }
+                ClassMember::Constant { name, value, .. } => {
+                    constants.insert(name.clone(), value.clone());
+                }
+                ClassMember::Case { name, value } => {
+                    cases.push((name.clone(), self.root_slot(), value.clone()));
+                }
+                ClassMember::AbstractMethod { .. } => {}
+            }
+        }
+
+        let init = self.builder.reserve_function_index();
+        self.name_function(init, &format!("{}::{{new}}", name));
+        let id = self.class_names.len() as u32;
+        self.class_names.push(name.to_string());
+        self.classes.insert(name.to_string(), ClassInfo {
+            id,
+            kind,
+            parent,
+            interfaces,
+            props,
+            static_props,
+            methods,
+            constants,
+            cases,
+            init,
+        });
+        Ok(())
+    }
+
+    /// Compile the methods and the allocation function of every class
+    pub(super) fn compile_classes(&mut self, statements: &[&Statement]) -> Result<(), String> {
+        for stmt in statements {
+            let (name, members) = match stmt {
+                Statement::Class { name, members, .. } | Statement::Enum { name, members, .. } => (name, members),
+                _ => continue,
+            };
+            self.current_class = Some(name.clone());
+            for member in members {
+                match member {
+                    ClassMember::Method { name: method, params, body, return_type, .. } => {
+                        let info = self.classes[name].methods[&method.to_lowercase()].clone();
+                        let scope = format!("{}::{}", name, method);
+                        self.compile_callable(&scope, info.idx, !info.is_static, params, return_type.as_ref(), &body.statements)?;
+                    }
+                    ClassMember::Constructor { params, body, .. } => {
+                        let info = self.classes[name].methods["__construct"].clone();
+                        let scope = format!("{}::__construct", name);
+                        self.compile_callable(&scope, info.idx, true, params, None, &body.statements)?;
+                    }
+                    _ => {}
+                }
+            }
+            self.compile_class_init(name)?;
+            self.current_class = None;
+        }
+        Ok(())
+    }
+
+    /// `C::{new}()`: allocate an object and set its property defaults
+    fn compile_class_init(&mut self, name: &str) -> Result<(), String> {
+        let class = self.classes[name].clone();
+        self.begin_function(&format!("{}::{{new}}", name), &[]);
+        let object = self.allocate_local(ValType::I32);
+        self.emit_all([
+            Instruction::I32Const(class.id as i32),
+            Instruction::I32Const(class.props.len() as i32),
+            Instruction::Call(self.h.obj_new),
+            Instruction::LocalSet(object),
+        ]);
+        for (i, prop) in class.props.iter().enumerate() {
+            if let Some(default) = &prop.default {
+                self.emit(Instruction::LocalGet(object));
+                self.compile_expression(default)?;
+                self.emit(Instruction::I32Store(mem32(OBJ_PROPS + i as u64 * 4)));
+            } else if prop.readonly {
+                // Uninitialised until the first write
+                self.emit_all([
+                    Instruction::LocalGet(object),
+                    Instruction::I32Const(MISSING_VALUE as i32),
+                    Instruction::I32Store(mem32(OBJ_PROPS + i as u64 * 4)),
+                ]);
+            }
+        }
+        self.emit(Instruction::LocalGet(object));
+        self.emit_return();
+        let ty = self.value_function_type(0);
+        self.finish_function(class.init, ty);
+        Ok(())
+    }
+
+    /// At the start of the script: create the enum cases, then set the static
+    /// property defaults
+    pub(super) fn init_classes(&mut self) -> Result<(), String> {
+        let mut names = self.class_names.clone();
+        names.retain(|name| self.classes[name].kind != ClassKind::Interface);
+        let value = self.allocate_local(ValType::I32);
+        for name in &names {
+            let class = self.classes[name].clone();
+            self.current_class = Some(name.clone());
+            for (case, slot, backing) in &class.cases {
+                let text = self.string_literal(case);
+                self.emit_all([
+                    Instruction::I32Const(class.id as i32),
+                    Instruction::I32Const(class.props.len() as i32),
+                    Instruction::Call(self.h.obj_new),
+                    Instruction::LocalTee(value),
+                    Instruction::I32Const(text as i32),
+                    Instruction::I32Store(mem32(OBJ_PROPS)),
+                ]);
+                if let Some(backing) = backing {
+                    self.emit(Instruction::LocalGet(value));
+                    self.compile_expression(backing)?;
+                    self.emit(Instruction::I32Store(mem32(OBJ_PROPS + 4)));
+                }
+                self.emit_store(VarLoc::Static(*slot), value);
+            }
+            self.current_class = None;
+        }
+        for name in &names {
+            let mut statics: Vec<(String, StaticProp)> = self.classes[name].static_props.clone().into_iter().collect();
+            statics.sort_by_key(|(_, prop)| prop.slot);
+            self.current_class = Some(name.clone());
+            for (_, prop) in statics {
+                if let Some(default) = &prop.default {
+                    self.compile_expression(default)?;
+                    self.emit(Instruction::LocalSet(value));
+                    self.emit_store(VarLoc::Static(prop.slot), value);
+                }
+            }
+            self.current_class = None;
+        }
+        Ok(())
+    }
+
+    /// The class a name in a class context refers to
+    pub(super) fn resolve_class(&self, class: &str) -> Result<String, String> {
+        let resolved = match class {
+            "self" | "static" => self.current_class.clone()
+                .ok_or_else(|| format!("Cannot use \"{}\" when no class scope is active", class))?,
+            "parent" => {
+                let current = self.current_class.as_ref()
+                    .ok_or("Cannot use \"parent\" when no class scope is active")?;
+                self.classes[current].parent.clone()
+                    .ok_or("Cannot use \"parent\" when current class scope has no parent")?
+            }
+            name => name.to_string(),
+        };
+        if !self.classes.contains_key(&resolved) {
+            return Err(format!("Class \"{}\" not found", resolved));
+        }
+        Ok(resolved)
+    }
+
+    /// Ids of a class and all classes extending it
+    fn subclass_ids(&self, class: &str) -> Vec<(u32, String)> {
+        let mut ids: Vec<(u32, String)> = self.classes.iter()
+            .filter(|(name, _)| {
+                let mut current = Some(name.as_str());
+                while let Some(name) = current {
+                    if name == class {
+                        return true;
+                    }
+                    current = self.classes[name].parent.as_deref();
+                }
+                false
+            })
+            .map(|(name, info)| (info.id, name.clone()))
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// `new C(...)`
+    pub(super) fn compile_new(&mut self, class: &str, args: &[Expression]) -> Result<(), String> {
+        let resolved = self.resolve_class(class)?;
+        let kind = self.classes[&resolved].kind;
+        if kind != ClassKind::Class {
+            let what = if kind == ClassKind::Enum { "enum" } else { "interface" };
+            return Err(format!("Cannot instantiate {} {}", what, resolved));
+        }
+        let subclasses = self.subclass_ids(&resolved);
+        let this = self.current_function.as_ref().and_then(|func| func.vars.get("this").copied());
+        let (this, true) = (this, class == "static" && subclasses.len() > 1) else {
+            return self.compile_new_of(&resolved, args);
+        };
+        let this = this.ok_or("Cannot use \"static\" when no object is bound")?;
+
+        // `new static` creates an object of the class of $this
+        let arg_locals = self.compile_args_to_locals(args)?;
+        let class_id = self.allocate_local(ValType::I32);
+        let result = self.allocate_local(ValType::I32);
+        self.emit_load(this);
+        self.emit(Instruction::I32Load(mem32(OBJ_CLASS)));
+        self.emit(Instruction::LocalSet(class_id));
+        for (id, name) in subclasses {
+            self.emit_all([
+                Instruction::LocalGet(class_id),
+                Instruction::I32Const(id as i32),
+                Instruction::I32Eq,
+                Instruction::If(wasm_encoder::BlockType::Empty),
+            ]);
+            self.emit_new_object(&name, &arg_locals);
+            self.emit(Instruction::LocalSet(result));
+            self.emit(Instruction::End);
+        }
+        self.emit(Instruction::LocalGet(result));
+        Ok(())
+    }
+
+    fn compile_new_of(&mut self, class: &str, args: &[Expression]) -> Result<(), String> {
+        let arg_locals = self.compile_args_to_locals(args)?;
+        self.emit_new_object(class, &arg_locals);
+        Ok(())
+    }
+
+    /// Evaluate arguments left to right into locals
+    fn compile_args_to_locals(&mut self, args: &[Expression]) -> Result<Vec<u32>, String> {
+        let mut locals = Vec::with_capacity(args.len());
+        for arg in args {
+            self.compile_argument(arg)?;
+            let local = self.allocate_local(ValType::I32);
+            self.emit(Instruction::LocalSet(local));
+            locals.push(local);
+        }
+        Ok(locals)
+    }
+
+    /// Allocate an object, run its constructor and register its destructor;
+    /// leaves the object on the stack
+    fn emit_new_object(&mut self, class: &str, arg_locals: &[u32]) {
+        let info = self.classes[class].clone();
+        let object = self.allocate_local(ValType::I32);
+        self.emit(Instruction::Call(info.init));
+        self.emit(Instruction::LocalSet(object));
+        if let Some(constructor) = info.methods.get("__construct") {
+            self.emit(Instruction::LocalGet(object));
+            self.emit_padded_args(arg_locals, constructor.params.len());
+            self.emit(Instruction::Call(constructor.idx));
+            self.emit(Instruction::Drop);
+        }
+        if info.methods.contains_key("__destruct") {
+            let (registry, _) = self.destructor_registry();
+            self.emit_all([
+                Instruction::I32Const(registry as i32),
+                Instruction::Call(self.h.place_array),
+                Instruction::LocalGet(object),
+                Instruction::Call(self.h.arr_append),
+            ]);
+        }
+        self.emit(Instruction::LocalGet(object));
+    }
+
+    /// Push argument locals, filling missing parameters with the marker and
+    /// leaving out extra arguments
+    fn emit_padded_args(&mut self, arg_locals: &[u32], count: usize) {
+        for i in 0..count {
+            match arg_locals.get(i) {
+                Some(&local) => self.emit(Instruction::LocalGet(local)),
+                None => self.emit(Instruction::I32Const(MISSING_VALUE as i32)),
+            }
+        }
+    }
+
+    fn destructor_registry(&mut self) -> (u32, u32) {
+        match self.destructors {
+            Some(slots) => slots,
+            None => {
+                let slots = (self.root_slot(), self.root_slot());
+                self.destructors = Some(slots);
+                slots
+            }
+        }
+    }
+
+    /// Whether any class has a destructor, so letting go of objects matters
+    pub(super) fn has_destructors(&self) -> bool {
+        self.classes.values().any(|info| info.methods.contains_key("__destruct"))
+    }
+
+    /// Before a variable is overwritten: if it holds an object with a
+    /// destructor, the next safepoint looks for objects nothing refers to
+    pub(super) fn emit_release(&mut self, loc: VarLoc) {
+        if self.has_destructors() {
+            self.emit_load(loc);
+            self.emit(Instruction::Call(self.h.gc_release));
+        }
+    }
+
+    /// Objects with a destructor are destroyed when nothing refers to them
+    /// any more (see gc_destruct) or when the script ends, in the order they
+    /// were created
+    pub(super) fn run_destructors(&mut self) {
+        let Some((registry, _)) = self.destructors else { return };
+        let (dispatch, arity) = self.dispatcher("__destruct");
+        let (array, index, entry) = (
+            self.allocate_local(ValType::I32),
+            self.allocate_local(ValType::I32),
+            self.allocate_local(ValType::I32),
+        );
+        self.emit_all([
+            Instruction::I32Const(0),
+            Instruction::I32Load(mem32(registry as u64)),
+            Instruction::LocalSet(array),
+        ]);
+        let mut call = vec![Instruction::LocalGet(entry), Instruction::I32Load(mem32(ENTRY_VALUE))];
+        call.extend((0..arity).map(|_| Instruction::I32Const(MISSING_VALUE as i32)));
+        call.extend([Instruction::Call(dispatch), Instruction::Drop]);
+        let code = super::arrays::for_each_entry(array, index, entry, call);
+        self.emit_all(code);
+    }
+
+    /// The dispatch function of a method name: (object, arguments...) -> value
+    pub(super) fn dispatcher(&mut self, name: &str) -> (u32, usize) {
+        let method = name.to_lowercase();
+        if let Some(&(idx, arity, _)) = self.dispatchers.get(&method) {
+            return (idx, arity);
+        }
+        let declared = self.classes.values()
+            .filter_map(|class| class.methods.get(&method))
+            .map(|info| info.params.len())
+            .max()
+            .unwrap_or(0);
+        let arity = declared.max(self.call_arities.get(&method).copied().unwrap_or(0));
+        let idx = self.builder.reserve_function_index();
+        self.name_function(idx, &format!("php_dispatch_{}", method));
+        self.dispatchers.insert(method, (idx, arity, name.to_string()));
+        (idx, arity)
+    }
+
+    /// `$object->method(...)`, with the object on the stack
+    pub(super) fn emit_method_call(&mut self, method: &str, args: &[Expression]) -> Result<(), String> {
+        let (dispatch, arity) = self.dispatcher(method);
+        for arg in args {
+            self.compile_argument(arg)?;
+        }
+        for _ in args.len()..arity {
+            self.emit(Instruction::I32Const(MISSING_VALUE as i32));
+        }
+        self.emit(Instruction::Call(dispatch));
+        Ok(())
+    }
+
+    /// `C::method(...)`, `parent::method(...)`, `self::method(...)`
+    pub(super) fn compile_static_call(&mut self, class: &str, method: &str, args: &[Expression]) -> Result<(), String> {
+        let resolved = self.resolve_class(class)?;
+        let info = self.classes[&resolved].clone();
+        let lower = method.to_lowercase();
+        if info.kind == ClassKind::Enum && matches!(lower.as_str(), "cases" | "from" | "tryfrom") && !info.methods.contains_key(&lower) {
+            return self.compile_enum_function(&resolved, &lower, args);
+        }
+        if let Some(target) = info.methods.get(&lower) {
+            let arg_locals = self.compile_args_to_locals(args)?;
+            if !target.is_static {
+                let this = self.current_function.as_ref().and_then(|func| func.vars.get("this").copied())
+                    .ok_or_else(|| format!("Non-static method {}::{}() cannot be called statically", resolved, method))?;
+                self.emit_load(this);
+            }
+            self.emit_padded_args(&arg_locals, target.params.len());
+            self.emit(Instruction::Call(target.idx));
+            return Ok(());
+        }
+        if let Some(hook) = info.methods.get("__callstatic") {
+            let hook = hook.clone();
+            let name = self.string_literal(method);
+            let array = self.compile_args_array(args)?;
+            self.emit(Instruction::I32Const(name as i32));
+            self.emit(Instruction::LocalGet(array));
+            self.emit_padded_args(&[], hook.params.len().saturating_sub(2));
+            self.emit(Instruction::Call(hook.idx));
+            return Ok(());
+        }
+        Err(format!("Call to undefined method {}::{}()", resolved, method))
+    }
+
+    /// The arguments as an array, for __call and __callStatic
+    fn compile_args_array(&mut self, args: &[Expression]) -> Result<u32, String> {
+        let array = self.allocate_local(ValType::I32);
+        self.emit_all([
+            Instruction::I32Const(args.len() as i32),
+            Instruction::Call(self.h.arr_new),
+            Instruction::LocalSet(array),
+        ]);
+        for arg in args {
+            self.emit(Instruction::LocalGet(array));
+            self.compile_argument(arg)?;
+            self.emit(Instruction::Call(self.h.arr_append));
+        }
+        Ok(array)
+    }
+
+    /// `Suit::cases()`, `Suit::from()` and `Suit::tryFrom()`
+    fn compile_enum_function(&mut self, class: &str, function: &str, args: &[Expression]) -> Result<(), String> {
+        let cases = self.classes[class].cases.clone();
+        if function == "cases" {
+            let array = self.allocate_local(ValType::I32);
+            self.emit_all([
+                Instruction::I32Const(cases.len() as i32),
+                Instruction::Call(self.h.arr_new),
+                Instruction::LocalSet(array),
+            ]);
+            for (_, slot, _) in &cases {
+                self.emit(Instruction::LocalGet(array));
+                self.emit_load(VarLoc::Static(*slot));
+                self.emit(Instruction::Call(self.h.arr_append));
+            }
+            self.emit(Instruction::LocalGet(array));
+            return Ok(());
+        }
+
+        let [arg] = args else {
+            return Err(format!("{}::{}() expects exactly 1 argument", class, function));
+        };
+        let value = self.allocate_local(ValType::I32);
+        let result = self.allocate_local(ValType::I32);
+        self.compile_expression(arg)?;
+        self.emit(Instruction::LocalSet(value));
+        self.emit_all([Instruction::I32Const(NULL_VALUE as i32), Instruction::LocalSet(result)]);
+        for (_, slot, _) in &cases {
+            self.emit_load(VarLoc::Static(*slot));
+            self.emit_all([
+                Instruction::I32Load(mem32(OBJ_PROPS + 4)),
+                Instruction::LocalGet(value),
+                Instruction::Call(self.h.loose_eq),
+                Instruction::If(wasm_encoder::BlockType::Empty),
+            ]);
+            self.emit_load(VarLoc::Static(*slot));
+            self.emit_all([Instruction::LocalSet(result), Instruction::End]);
+        }
+        if function == "from" {
+            // "X" is not a valid backing value for enum Suit
+            let prefix = self.string_literal("PHP Fatal error:  Uncaught ValueError: ");
+            let suffix = self.string_literal(&format!(" is not a valid backing value for enum {}\n", class));
+            let quote = self.string_literal("\"");
+            self.emit_all([
+                Instruction::LocalGet(result),
+                Instruction::I32Eqz,
+                Instruction::If(wasm_encoder::BlockType::Empty),
+                Instruction::I32Const(prefix as i32),
+                Instruction::LocalGet(value),
+                Instruction::I32Load(mem32(0)),
+                Instruction::I32Const(TAG_STRING),
+                Instruction::I32Eq,
+                Instruction::If(wasm_encoder::BlockType::Result(ValType::I32)),
+                Instruction::I32Const(quote as i32),
+                Instruction::LocalGet(value),
+                Instruction::Call(self.h.concat),
+                Instruction::I32Const(quote as i32),
+                Instruction::Call(self.h.concat),
+                Instruction::Else,
+                Instruction::LocalGet(value),
+                Instruction::End,
+                Instruction::Call(self.h.concat),
+                Instruction::I32Const(suffix as i32),
+                Instruction::Call(self.h.concat),
+                Instruction::Call(self.h.fatal),
+                Instruction::End,
+            ]);
+        }
+        self.emit(Instruction::LocalGet(result));
+        Ok(())
+    }
+
+    /// `C::NAME`: a constant, an enum case or `C::class`
+    pub(super) fn compile_class_constant(&mut self, class: &str, name: &str) -> Result<(), String> {
+        let resolved = self.resolve_class(class)?;
+        if name == "class" {
+            let text = self.string_literal(&resolved);
+            self.emit(Instruction::I32Const(text as i32));
+            return Ok(());
+        }
+        if let Some((_, slot, _)) = self.classes[&resolved].cases.iter().find(|(case, _, _)| case == name) {
+            self.emit_load(VarLoc::Static(*slot));
+            return Ok(());
+        }
+        let (owner, value) = self.find_constant(&resolved, name)
+            .ok_or_else(|| format!("Undefined constant {}::{}", resolved, name))?;
+        // Constants are compiled where they are used, in their class's scope
+        let saved = self.current_class.replace(owner);
+        let result = self.compile_expression(&value);
+        self.current_class = saved;
+        result
+    }
+
+    fn find_constant(&self, class: &str, name: &str) -> Option<(String, Expression)> {
+        let info = &self.classes[class];
+        if let Some(value) = info.constants.get(name) {
+            return Some((class.to_string(), value.clone()));
+        }
+        info.parent.iter().chain(&info.interfaces)
+            .find_map(|class| self.find_constant(class, name))
+    }
+
+    /// The static property `C::$name`
+    pub(super) fn static_prop(&self, class: &str, name: &str) -> Result<StaticProp, String> {
+        let resolved = self.resolve_class(class)?;
+        let mut current = Some(resolved.as_str());
+        while let Some(class) = current {
+            if let Some(prop) = self.classes[class].static_props.get(name) {
+                return Ok(prop.clone());
+            }
+            current = self.classes[class].parent.as_deref();
+        }
+        Err(format!("Access to undeclared static property {}::${}", resolved, name))
+    }
+
+    /// The slot index and declaration of `$this->name` when the property is
+    /// declared by the class being compiled
+    pub(super) fn this_prop(&self, object: &Expression, name: &str) -> Option<(usize, PropInfo)> {
+        if !matches!(object, Expression::Variable(var) if var == "this") {
+            return None;
+        }
+        let class = self.classes.get(self.current_class.as_ref()?)?;
+        let index = class.prop_index(name)?;
+        Some((index, class.props[index].clone()))
+    }
+
+    /// The id of the class being compiled, or -1 at global scope; the
+    /// property helpers check readonly writes against it
+    pub(super) fn scope_id(&self) -> i32 {
+        self.current_class.as_ref().map_or(-1, |class| self.classes[class].id as i32)
+    }
+
+    /// Stop with an Error unless the readonly property `$this->{prop}` at
+    /// `index` can be written here
+    pub(super) fn emit_readonly_this_check(&mut self, index: usize, prop: &PropInfo) {
+        if !prop.readonly {
+            return;
+        }
+        let label = format!("{}::${}", prop.class, prop.name);
+        let modify = self.string_literal(&format!("PHP Fatal error:  Uncaught Error: Cannot modify readonly property {}\n", label));
+        let this = self.variable("this");
+        self.emit_load(this);
+        self.emit_all([
+            Instruction::I32Load(mem32(OBJ_PROPS + index as u64 * 4)),
+            Instruction::I32Const(MISSING_VALUE as i32),
+            Instruction::I32Ne,
+            Instruction::If(wasm_encoder::BlockType::Empty),
+            Instruction::I32Const(modify as i32),
+            Instruction::Call(self.h.fatal),
+            Instruction::End,
+        ]);
+        if self.current_class.as_deref() != Some(prop.class.as_str()) {
+            let scope = self.current_class.clone().unwrap_or_default();
+            let message = format!("PHP Fatal error:  Uncaught Error: Cannot initialize readonly property {} from scope {}\n", label, scope);
+            let message = self.string_literal(&message);
+            self.emit_all([Instruction::I32Const(message as i32), Instruction::Call(self.h.fatal)]);
+        }
+    }
+}
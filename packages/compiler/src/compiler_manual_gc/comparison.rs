@@ -1,464 +1,264 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
+// Comparisons with PHP 8 semantics: numeric strings compare as numbers,
+// null and booleans compare as booleans, arrays compare by size and then
+// entry by entry, objects of the same class compare property by property.
+
 use super::core::*;
-use wasm_encoder::*;
+use wasm_encoder::Instruction::*;
+use wasm_encoder::{BlockType, Instruction, ValType};
+
+/// Compare the int or float values in `x` and `y`: -1, 0 or 1
+fn number_cmp(x: u32, y: u32, float_cmp: u32) -> Vec<Instruction<'static>> {
+    vec![
+        LocalGet(x), I32Load(mem32(0)), I32Const(TAG_INT), I32Eq,
+        LocalGet(y), I32Load(mem32(0)), I32Const(TAG_INT), I32Eq, I32And,
+        If(BlockType::Result(ValType::I32)),
+            LocalGet(x), I64Load(mem64(VALUE_DATA)), LocalGet(y), I64Load(mem64(VALUE_DATA)), I64GtS,
+            LocalGet(x), I64Load(mem64(VALUE_DATA)), LocalGet(y), I64Load(mem64(VALUE_DATA)), I64LtS,
+            I32Sub,
+        Else,
+            LocalGet(x), I32Load(mem32(0)), I32Const(TAG_INT), I32Eq,
+            If(BlockType::Result(ValType::F64)),
+                LocalGet(x), I64Load(mem64(VALUE_DATA)), F64ConvertI64S,
+            Else,
+                LocalGet(x), F64Load(mem64(VALUE_DATA)),
+            End,
+            LocalGet(y), I32Load(mem32(0)), I32Const(TAG_INT), I32Eq,
+            If(BlockType::Result(ValType::F64)),
+                LocalGet(y), I64Load(mem64(VALUE_DATA)), F64ConvertI64S,
+            Else,
+                LocalGet(y), F64Load(mem64(VALUE_DATA)),
+            End,
+            Call(float_cmp),
+        End,
+    ]
+}
 
 impl Compiler {
     pub(super) fn add_comparison_functions(&mut self) {
-        self.add_greater_than_function();
-        self.add_less_than_function();
-        self.add_equal_function();
-        self.add_not_equal_function();
+        self.add_loose_eq_function();
+        self.add_strict_eq_function();
+        self.add_compare_function();
     }
-    
-    fn add_greater_than_function(&mut self) {
-        let mut gt_body = vec![];
-        let mut gt_locals = vec![];
-        gt_locals.push((1, ValType::I32)); // result_ptr
-        gt_locals.push((1, ValType::I32)); // left_type
-        gt_locals.push((1, ValType::I32)); // right_type
-        gt_locals.push((1, ValType::I64)); // left_int
-        gt_locals.push((1, ValType::I64)); // right_int
-        gt_locals.push((1, ValType::F64)); // left_float
-        gt_locals.push((1, ValType::F64)); // right_float
-        gt_locals.push((1, ValType::I32)); // result
-        
-        // Allocate result
-        gt_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        gt_body.push(Instruction::LocalSet(2)); // result_ptr
-        
-        // Set result type to bool
-        gt_body.push(Instruction::LocalGet(2));
-        gt_body.push(Instruction::I32Const(TYPE_BOOL as i32));
-        gt_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Get types
-        gt_body.push(Instruction::LocalGet(0));
-        gt_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        gt_body.push(Instruction::LocalSet(3)); // left_type
-        
-        gt_body.push(Instruction::LocalGet(1));
-        gt_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        gt_body.push(Instruction::LocalSet(4)); // right_type
-        
-        // Check if both are integers
-        gt_body.push(Instruction::LocalGet(3));
-        gt_body.push(Instruction::I32Const(TYPE_INT as i32));
-        gt_body.push(Instruction::I32Eq);
-        gt_body.push(Instruction::LocalGet(4));
-        gt_body.push(Instruction::I32Const(TYPE_INT as i32));
-        gt_body.push(Instruction::I32Eq);
-        gt_body.push(Instruction::I32And);
-        gt_body.push(Instruction::If(BlockType::Empty));
-        
-        // Both integers - compare directly
-        gt_body.push(Instruction::LocalGet(0));
-        gt_body.push(Instruction::I32Const(4));
-        gt_body.push(Instruction::I32Add);
-        gt_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        gt_body.push(Instruction::LocalSet(5)); // left_int
-        
-        gt_body.push(Instruction::LocalGet(1));
-        gt_body.push(Instruction::I32Const(4));
-        gt_body.push(Instruction::I32Add);
-        gt_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        gt_body.push(Instruction::LocalSet(6)); // right_int
-        
-        gt_body.push(Instruction::LocalGet(5));
-        gt_body.push(Instruction::LocalGet(6));
-        gt_body.push(Instruction::I64GtS);
-        gt_body.push(Instruction::LocalSet(9)); // result
-        
-        gt_body.push(Instruction::Else);
-        
-        // Convert to floats for comparison
-        // Convert left to float
-        gt_body.push(Instruction::LocalGet(3));
-        gt_body.push(Instruction::I32Const(TYPE_INT as i32));
-        gt_body.push(Instruction::I32Eq);
-        gt_body.push(Instruction::If(BlockType::Empty));
-        gt_body.push(Instruction::LocalGet(0));
-        gt_body.push(Instruction::I32Const(4));
-        gt_body.push(Instruction::I32Add);
-        gt_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        gt_body.push(Instruction::F64ConvertI64S);
-        gt_body.push(Instruction::LocalSet(7)); // left_float
-        gt_body.push(Instruction::Else);
-        gt_body.push(Instruction::LocalGet(3));
-        gt_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        gt_body.push(Instruction::I32Eq);
-        gt_body.push(Instruction::If(BlockType::Empty));
-        gt_body.push(Instruction::LocalGet(0));
-        gt_body.push(Instruction::I32Const(4));
-        gt_body.push(Instruction::I32Add);
-        gt_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        gt_body.push(Instruction::LocalSet(7)); // left_float
-        gt_body.push(Instruction::Else);
-        // Other types become 0.0
-        gt_body.push(Instruction::F64Const(0.0.into()));
-        gt_body.push(Instruction::LocalSet(7)); // left_float
-        gt_body.push(Instruction::End);
-        gt_body.push(Instruction::End);
-        
-        // Convert right to float
-        gt_body.push(Instruction::LocalGet(4));
-        gt_body.push(Instruction::I32Const(TYPE_INT as i32));
-        gt_body.push(Instruction::I32Eq);
-        gt_body.push(Instruction::If(BlockType::Empty));
-        gt_body.push(Instruction::LocalGet(1));
-        gt_body.push(Instruction::I32Const(4));
-        gt_body.push(Instruction::I32Add);
-        gt_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        gt_body.push(Instruction::F64ConvertI64S);
-        gt_body.push(Instruction::LocalSet(8)); // right_float
-        gt_body.push(Instruction::Else);
-        gt_body.push(Instruction::LocalGet(4));
-        gt_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        gt_body.push(Instruction::I32Eq);
-        gt_body.push(Instruction::If(BlockType::Empty));
-        gt_body.push(Instruction::LocalGet(1));
-        gt_body.push(Instruction::I32Const(4));
-        gt_body.push(Instruction::I32Add);
-        gt_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        gt_body.push(Instruction::LocalSet(8)); // right_float
-        gt_body.push(Instruction::Else);
-        // Other types become 0.0
-        gt_body.push(Instruction::F64Const(0.0.into()));
-        gt_body.push(Instruction::LocalSet(8)); // right_float
-        gt_body.push(Instruction::End);
-        gt_body.push(Instruction::End);
-        
-        // Compare floats
-        gt_body.push(Instruction::LocalGet(7));
-        gt_body.push(Instruction::LocalGet(8));
-        gt_body.push(Instruction::F64Gt);
-        gt_body.push(Instruction::LocalSet(9)); // result
-        
-        gt_body.push(Instruction::End);
-        
-        // Store result
-        gt_body.push(Instruction::LocalGet(2));
-        gt_body.push(Instruction::I32Const(4));
-        gt_body.push(Instruction::I32Add);
-        gt_body.push(Instruction::LocalGet(9));
-        gt_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Return result
-        gt_body.push(Instruction::LocalGet(2));
-        
-        self.builder.set_function_at_index(self.greater_than_fn_idx, self.binary_op_type_idx, gt_locals, gt_body);
+
+    fn add_loose_eq_function(&mut self) {
+        // loose_eq(a, b) -> i32, for ==
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (a, b) = (0, 1);
+        let body = vec![
+            LocalGet(a), LocalGet(b), I32Eq, If(BlockType::Empty), I32Const(1), Return, End,
+            LocalGet(a), LocalGet(b), Call(self.h.compare), I32Eqz,
+        ];
+        self.builder.set_function_at_index(self.h.loose_eq, ty, vec![], body);
     }
-    
-    fn add_less_than_function(&mut self) {
-        let mut lt_body = vec![];
-        let mut lt_locals = vec![];
-        lt_locals.push((1, ValType::I32)); // result_ptr
-        lt_locals.push((1, ValType::I32)); // left_type
-        lt_locals.push((1, ValType::I32)); // right_type
-        lt_locals.push((1, ValType::I64)); // left_int
-        lt_locals.push((1, ValType::I64)); // right_int
-        lt_locals.push((1, ValType::F64)); // left_float
-        lt_locals.push((1, ValType::F64)); // right_float
-        lt_locals.push((1, ValType::I32)); // result
-        
-        // Allocate result
-        lt_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        lt_body.push(Instruction::LocalSet(2)); // result_ptr
-        
-        // Set result type to bool
-        lt_body.push(Instruction::LocalGet(2));
-        lt_body.push(Instruction::I32Const(TYPE_BOOL as i32));
-        lt_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Get types
-        lt_body.push(Instruction::LocalGet(0));
-        lt_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        lt_body.push(Instruction::LocalSet(3)); // left_type
-        
-        lt_body.push(Instruction::LocalGet(1));
-        lt_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        lt_body.push(Instruction::LocalSet(4)); // right_type
-        
-        // Check if both are integers
-        lt_body.push(Instruction::LocalGet(3));
-        lt_body.push(Instruction::I32Const(TYPE_INT as i32));
-        lt_body.push(Instruction::I32Eq);
-        lt_body.push(Instruction::LocalGet(4));
-        lt_body.push(Instruction::I32Const(TYPE_INT as i32));
-        lt_body.push(Instruction::I32Eq);
-        lt_body.push(Instruction::I32And);
-        lt_body.push(Instruction::If(BlockType::Empty));
-        
-        // Both integers - compare directly
-        lt_body.push(Instruction::LocalGet(0));
-        lt_body.push(Instruction::I32Const(4));
-        lt_body.push(Instruction::I32Add);
-        lt_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        lt_body.push(Instruction::LocalSet(5)); // left_int
-        
-        lt_body.push(Instruction::LocalGet(1));
-        lt_body.push(Instruction::I32Const(4));
-        lt_body.push(Instruction::I32Add);
-        lt_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        lt_body.push(Instruction::LocalSet(6)); // right_int
-        
-        lt_body.push(Instruction::LocalGet(5));
-        lt_body.push(Instruction::LocalGet(6));
-        lt_body.push(Instruction::I64LtS);
-        lt_body.push(Instruction::LocalSet(9)); // result
-        
-        lt_body.push(Instruction::Else);
-        
-        // Convert to floats for comparison
-        // Convert left to float
-        lt_body.push(Instruction::LocalGet(3));
-        lt_body.push(Instruction::I32Const(TYPE_INT as i32));
-        lt_body.push(Instruction::I32Eq);
-        lt_body.push(Instruction::If(BlockType::Empty));
-        lt_body.push(Instruction::LocalGet(0));
-        lt_body.push(Instruction::I32Const(4));
-        lt_body.push(Instruction::I32Add);
-        lt_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        lt_body.push(Instruction::F64ConvertI64S);
-        lt_body.push(Instruction::LocalSet(7)); // left_float
-        lt_body.push(Instruction::Else);
-        lt_body.push(Instruction::LocalGet(3));
-        lt_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        lt_body.push(Instruction::I32Eq);
-        lt_body.push(Instruction::If(BlockType::Empty));
-        lt_body.push(Instruction::LocalGet(0));
-        lt_body.push(Instruction::I32Const(4));
-        lt_body.push(Instruction::I32Add);
-        lt_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        lt_body.push(Instruction::LocalSet(7)); // left_float
-        lt_body.push(Instruction::Else);
-        // Other types become 0.0
-        lt_body.push(Instruction::F64Const(0.0.into()));
-        lt_body.push(Instruction::LocalSet(7)); // left_float
-        lt_body.push(Instruction::End);
-        lt_body.push(Instruction::End);
-        
-        // Convert right to float
-        lt_body.push(Instruction::LocalGet(4));
-        lt_body.push(Instruction::I32Const(TYPE_INT as i32));
-        lt_body.push(Instruction::I32Eq);
-        lt_body.push(Instruction::If(BlockType::Empty));
-        lt_body.push(Instruction::LocalGet(1));
-        lt_body.push(Instruction::I32Const(4));
-        lt_body.push(Instruction::I32Add);
-        lt_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        lt_body.push(Instruction::F64ConvertI64S);
-        lt_body.push(Instruction::LocalSet(8)); // right_float
-        lt_body.push(Instruction::Else);
-        lt_body.push(Instruction::LocalGet(4));
-        lt_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        lt_body.push(Instruction::I32Eq);
-        lt_body.push(Instruction::If(BlockType::Empty));
-        lt_body.push(Instruction::LocalGet(1));
-        lt_body.push(Instruction::I32Const(4));
-        lt_body.push(Instruction::I32Add);
-        lt_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        lt_body.push(Instruction::LocalSet(8)); // right_float
-        lt_body.push(Instruction::Else);
-        // Other types become 0.0
-        lt_body.push(Instruction::F64Const(0.0.into()));
-        lt_body.push(Instruction::LocalSet(8)); // right_float
-        lt_body.push(Instruction::End);
-        lt_body.push(Instruction::End);
-        
-        // Compare floats
-        lt_body.push(Instruction::LocalGet(7));
-        lt_body.push(Instruction::LocalGet(8));
-        lt_body.push(Instruction::F64Lt);
-        lt_body.push(Instruction::LocalSet(9)); // result
-        
-        lt_body.push(Instruction::End);
-        
-        // Store result
-        lt_body.push(Instruction::LocalGet(2));
-        lt_body.push(Instruction::I32Const(4));
-        lt_body.push(Instruction::I32Add);
-        lt_body.push(Instruction::LocalGet(9));
-        lt_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Return result
-        lt_body.push(Instruction::LocalGet(2));
-        
-        self.builder.set_function_at_index(self.less_than_fn_idx, self.binary_op_type_idx, lt_locals, lt_body);
+
+    fn add_strict_eq_function(&mut self) {
+        // strict_eq(a, b) -> i32, for ===
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (a, b, tag, i, j, ea, eb, used_a, used_b, key_a, key_b) = (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10);
+        // Advance `entry` to the next live entry of `array` from `index`; 0 at the end
+        let next_live = |array: u32, index: u32, used: u32, entry: u32| vec![
+            I32Const(0), LocalSet(entry),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(index), LocalGet(used), I32GeU, BrIf(1),
+                LocalGet(array), I32Load(mem32(ARR_ENTRIES)), LocalGet(index), I32Const(ENTRY_SIZE), I32Mul, I32Add,
+                LocalSet(entry),
+                LocalGet(index), I32Const(1), I32Add, LocalSet(index),
+                LocalGet(entry), I32Load(mem32(ENTRY_KEY_STR)), I32Const(-1), I32Ne, BrIf(1),
+                I32Const(0), LocalSet(entry),
+                Br(0),
+            End,
+            End,
+        ];
+        let mut body = vec![
+            LocalGet(a), LocalGet(b), I32Eq, If(BlockType::Empty), I32Const(1), Return, End,
+            LocalGet(a), I32Load(mem32(0)), LocalTee(tag), LocalGet(b), I32Load(mem32(0)), I32Ne,
+            If(BlockType::Empty), I32Const(0), Return, End,
+            LocalGet(tag), I32Const(TAG_NULL), I32Eq, If(BlockType::Empty), I32Const(1), Return, End,
+            LocalGet(tag), I32Const(TAG_BOOL), I32Eq, LocalGet(tag), I32Const(TAG_INT), I32Eq, I32Or,
+            If(BlockType::Empty),
+                LocalGet(a), I64Load(mem64(VALUE_DATA)), LocalGet(b), I64Load(mem64(VALUE_DATA)), I64Eq, Return,
+            End,
+            LocalGet(tag), I32Const(TAG_FLOAT), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(a), F64Load(mem64(VALUE_DATA)), LocalGet(b), F64Load(mem64(VALUE_DATA)), F64Eq, Return,
+            End,
+            LocalGet(tag), I32Const(TAG_STRING), I32Eq,
+            If(BlockType::Empty), LocalGet(a), LocalGet(b), Call(self.h.str_eq), Return, End,
+            LocalGet(tag), I32Const(TAG_ARRAY), I32Ne,
+            // Objects are identical only when they are the same object
+            If(BlockType::Empty), I32Const(0), Return, End,
+
+            // Arrays: the same pairs in the same order
+            LocalGet(a), I32Load(mem32(ARR_COUNT)), LocalGet(b), I32Load(mem32(ARR_COUNT)), I32Ne,
+            If(BlockType::Empty), I32Const(0), Return, End,
+            LocalGet(a), I32Load(mem32(ARR_USED)), LocalSet(used_a),
+            LocalGet(b), I32Load(mem32(ARR_USED)), LocalSet(used_b),
+            Loop(BlockType::Empty),
+        ];
+        body.extend(next_live(a, i, used_a, ea));
+        body.extend(next_live(b, j, used_b, eb));
+        body.extend([
+                LocalGet(ea), I32Eqz, If(BlockType::Empty), I32Const(1), Return, End,
+                LocalGet(ea), I32Load(mem32(ENTRY_KEY_STR)), LocalTee(key_a),
+                LocalGet(eb), I32Load(mem32(ENTRY_KEY_STR)), LocalTee(key_b), I32Or,
+                If(BlockType::Empty),
+                    LocalGet(key_a), I32Eqz, LocalGet(key_b), I32Eqz, I32Or,
+                    If(BlockType::Empty), I32Const(0), Return, End,
+                    LocalGet(key_a), LocalGet(key_b), Call(self.h.str_eq), I32Eqz,
+                    If(BlockType::Empty), I32Const(0), Return, End,
+                Else,
+                    LocalGet(ea), I64Load(mem64(ENTRY_KEY_INT)), LocalGet(eb), I64Load(mem64(ENTRY_KEY_INT)), I64Ne,
+                    If(BlockType::Empty), I32Const(0), Return, End,
+                End,
+                LocalGet(ea), I32Load(mem32(ENTRY_VALUE)), LocalGet(eb), I32Load(mem32(ENTRY_VALUE)), Call(self.h.strict_eq),
+                I32Eqz, If(BlockType::Empty), I32Const(0), Return, End,
+                Br(0),
+            End,
+            Unreachable,
+        ]);
+        self.builder.set_function_at_index(self.h.strict_eq, ty, vec![(9, ValType::I32)], body);
     }
-    
-    fn add_equal_function(&mut self) {
-        let mut eq_body = vec![];
-        let mut eq_locals = vec![];
-        eq_locals.push((1, ValType::I32)); // result_ptr
-        eq_locals.push((1, ValType::I32)); // left_type
-        eq_locals.push((1, ValType::I32)); // right_type
-        eq_locals.push((1, ValType::I64)); // left_int
-        eq_locals.push((1, ValType::I64)); // right_int
-        eq_locals.push((1, ValType::F64)); // left_float
-        eq_locals.push((1, ValType::F64)); // right_float
-        eq_locals.push((1, ValType::I32)); // result
-        
-        // Allocate result
-        eq_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        eq_body.push(Instruction::LocalSet(2)); // result_ptr
-        
-        // Set result type to bool
-        eq_body.push(Instruction::LocalGet(2));
-        eq_body.push(Instruction::I32Const(TYPE_BOOL as i32));
-        eq_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Get types
-        eq_body.push(Instruction::LocalGet(0));
-        eq_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        eq_body.push(Instruction::LocalSet(3)); // left_type
-        
-        eq_body.push(Instruction::LocalGet(1));
-        eq_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        eq_body.push(Instruction::LocalSet(4)); // right_type
-        
-        // Check if both are integers
-        eq_body.push(Instruction::LocalGet(3));
-        eq_body.push(Instruction::I32Const(TYPE_INT as i32));
-        eq_body.push(Instruction::I32Eq);
-        eq_body.push(Instruction::LocalGet(4));
-        eq_body.push(Instruction::I32Const(TYPE_INT as i32));
-        eq_body.push(Instruction::I32Eq);
-        eq_body.push(Instruction::I32And);
-        eq_body.push(Instruction::If(BlockType::Empty));
-        
-        // Both integers - compare directly
-        eq_body.push(Instruction::LocalGet(0));
-        eq_body.push(Instruction::I32Const(4));
-        eq_body.push(Instruction::I32Add);
-        eq_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        eq_body.push(Instruction::LocalSet(5)); // left_int
-        
-        eq_body.push(Instruction::LocalGet(1));
-        eq_body.push(Instruction::I32Const(4));
-        eq_body.push(Instruction::I32Add);
-        eq_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        eq_body.push(Instruction::LocalSet(6)); // right_int
-        
-        eq_body.push(Instruction::LocalGet(5));
-        eq_body.push(Instruction::LocalGet(6));
-        eq_body.push(Instruction::I64Eq);
-        eq_body.push(Instruction::LocalSet(9)); // result
-        
-        eq_body.push(Instruction::Else);
-        
-        // PHP loose comparison - convert to same type
-        // For simplicity, convert both to float for comparison
-        
-        // Convert left to float
-        eq_body.push(Instruction::LocalGet(3));
-        eq_body.push(Instruction::I32Const(TYPE_INT as i32));
-        eq_body.push(Instruction::I32Eq);
-        eq_body.push(Instruction::If(BlockType::Empty));
-        eq_body.push(Instruction::LocalGet(0));
-        eq_body.push(Instruction::I32Const(4));
-        eq_body.push(Instruction::I32Add);
-        eq_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        eq_body.push(Instruction::F64ConvertI64S);
-        eq_body.push(Instruction::LocalSet(7)); // left_float
-        eq_body.push(Instruction::Else);
-        eq_body.push(Instruction::LocalGet(3));
-        eq_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        eq_body.push(Instruction::I32Eq);
-        eq_body.push(Instruction::If(BlockType::Empty));
-        eq_body.push(Instruction::LocalGet(0));
-        eq_body.push(Instruction::I32Const(4));
-        eq_body.push(Instruction::I32Add);
-        eq_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        eq_body.push(Instruction::LocalSet(7)); // left_float
-        eq_body.push(Instruction::Else);
-        // Other types become 0.0
-        eq_body.push(Instruction::F64Const(0.0.into()));
-        eq_body.push(Instruction::LocalSet(7)); // left_float
-        eq_body.push(Instruction::End);
-        eq_body.push(Instruction::End);
-        
-        // Convert right to float
-        eq_body.push(Instruction::LocalGet(4));
-        eq_body.push(Instruction::I32Const(TYPE_INT as i32));
-        eq_body.push(Instruction::I32Eq);
-        eq_body.push(Instruction::If(BlockType::Empty));
-        eq_body.push(Instruction::LocalGet(1));
-        eq_body.push(Instruction::I32Const(4));
-        eq_body.push(Instruction::I32Add);
-        eq_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        eq_body.push(Instruction::F64ConvertI64S);
-        eq_body.push(Instruction::LocalSet(8)); // right_float
-        eq_body.push(Instruction::Else);
-        eq_body.push(Instruction::LocalGet(4));
-        eq_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        eq_body.push(Instruction::I32Eq);
-        eq_body.push(Instruction::If(BlockType::Empty));
-        eq_body.push(Instruction::LocalGet(1));
-        eq_body.push(Instruction::I32Const(4));
-        eq_body.push(Instruction::I32Add);
-        eq_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        eq_body.push(Instruction::LocalSet(8)); // right_float
-        eq_body.push(Instruction::Else);
-        // Other types become 0.0
-        eq_body.push(Instruction::F64Const(0.0.into()));
-        eq_body.push(Instruction::LocalSet(8)); // right_float
-        eq_body.push(Instruction::End);
-        eq_body.push(Instruction::End);
-        
-        // Compare floats
-        eq_body.push(Instruction::LocalGet(7));
-        eq_body.push(Instruction::LocalGet(8));
-        eq_body.push(Instruction::F64Eq);
-        eq_body.push(Instruction::LocalSet(9)); // result
-        
-        eq_body.push(Instruction::End);
-        
-        // Store result
-        eq_body.push(Instruction::LocalGet(2));
-        eq_body.push(Instruction::I32Const(4));
-        eq_body.push(Instruction::I32Add);
-        eq_body.push(Instruction::LocalGet(9));
-        eq_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Return result
-        eq_body.push(Instruction::LocalGet(2));
-        
-        self.builder.set_function_at_index(self.equal_fn_idx, self.binary_op_type_idx, eq_locals, eq_body);
+
+    fn add_compare_function(&mut self) {
+        // compare(a, b) -> -1, 0 or 1, for <, >, <=> and ==; values that cannot
+        // be ordered compare as 1
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (a, b, ta, tb, x, y, i, entry, found, c, count) = (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10);
+        let float_cmp = self.float_cmp_function();
+        let number_cmp = |x: u32, y: u32| number_cmp(x, y, float_cmp);
+        let is_number = |tag: u32| vec![LocalGet(tag), I32Const(TAG_INT), I32Eq, LocalGet(tag), I32Const(TAG_FLOAT), I32Eq, I32Or];
+        let mut body = vec![
+            LocalGet(a), I32Load(mem32(0)), LocalSet(ta),
+            LocalGet(b), I32Load(mem32(0)), LocalSet(tb),
+
+            // Two strings: numerically if both are numeric
+            LocalGet(ta), I32Const(TAG_STRING), I32Eq, LocalGet(tb), I32Const(TAG_STRING), I32Eq, I32And,
+            If(BlockType::Empty),
+                LocalGet(a), Call(self.h.parse_number), LocalSet(x),
+                GlobalGet(self.g.numeric), I32Const(2), I32Eq,
+                If(BlockType::Empty),
+                    LocalGet(b), Call(self.h.parse_number), LocalSet(y),
+                    GlobalGet(self.g.numeric), I32Const(2), I32Eq,
+                    If(BlockType::Empty),
+        ];
+        body.extend(number_cmp(x, y));
+        body.extend([
+                        Return,
+                    End,
+                End,
+                LocalGet(a), LocalGet(b), Call(self.h.str_cmp), Return,
+            End,
+            // null against a string: as the empty string
+            LocalGet(ta), I32Eqz, LocalGet(tb), I32Const(TAG_STRING), I32Eq, I32And,
+            If(BlockType::Empty), I32Const(-1), I32Const(0), LocalGet(b), I32Load(mem32(STR_LEN)), Select, Return, End,
+            LocalGet(tb), I32Eqz, LocalGet(ta), I32Const(TAG_STRING), I32Eq, I32And,
+            If(BlockType::Empty), LocalGet(a), I32Load(mem32(STR_LEN)), I32Const(0), I32Ne, Return, End,
+            // null or a bool on either side: as booleans
+            LocalGet(ta), I32Const(TAG_BOOL), I32LeU, LocalGet(tb), I32Const(TAG_BOOL), I32LeU, I32Or,
+            If(BlockType::Empty),
+                LocalGet(a), Call(self.h.to_bool), LocalTee(x), LocalGet(b), Call(self.h.to_bool), LocalTee(y), I32GtU,
+                LocalGet(x), LocalGet(y), I32LtU, I32Sub, Return,
+            End,
+        ]);
+        body.extend(is_number(ta));
+        body.extend(is_number(tb));
+        body.extend([
+            I32And,
+            If(BlockType::Empty),
+        ]);
+        body.extend(number_cmp(a, b));
+        body.extend([
+                Return,
+            End,
+        ]);
+        // A number and a string: numerically if the string is numeric
+        for (number, string, sign) in [(a, b, 1), (b, a, -1)] {
+            let (tn, ts) = if number == a { (ta, tb) } else { (tb, ta) };
+            body.extend(is_number(tn));
+            body.extend([
+                LocalGet(ts), I32Const(TAG_STRING), I32Eq, I32And,
+                If(BlockType::Empty),
+                    LocalGet(string), Call(self.h.parse_number), LocalSet(y),
+                    LocalGet(number), LocalSet(x),
+                    GlobalGet(self.g.numeric), I32Const(2), I32Eq,
+                    If(BlockType::Empty),
+            ]);
+            body.extend(number_cmp(x, y));
+            body.extend([I32Const(sign), I32Mul, Return,
+                    End,
+                    LocalGet(number), Call(self.h.to_string), LocalGet(string), Call(self.h.str_cmp),
+                    I32Const(sign), I32Mul, Return,
+                End,
+            ]);
+        }
+        body.extend([
+            // Arrays: the smaller one first, then entry by entry
+            LocalGet(ta), I32Const(TAG_ARRAY), I32Eq, LocalGet(tb), I32Const(TAG_ARRAY), I32Eq, I32And,
+            If(BlockType::Empty),
+                LocalGet(a), I32Load(mem32(ARR_COUNT)), LocalTee(x), LocalGet(b), I32Load(mem32(ARR_COUNT)), LocalTee(y), I32Ne,
+                If(BlockType::Empty), LocalGet(x), LocalGet(y), I32GtU, LocalGet(x), LocalGet(y), I32LtU, I32Sub, Return, End,
+                LocalGet(a), I32Load(mem32(ARR_USED)), LocalSet(count),
+                Block(BlockType::Empty),
+                Loop(BlockType::Empty),
+                    LocalGet(i), LocalGet(count), I32GeU, BrIf(1),
+                    LocalGet(a), I32Load(mem32(ARR_ENTRIES)), LocalGet(i), I32Const(ENTRY_SIZE), I32Mul, I32Add, LocalTee(entry),
+                    I32Load(mem32(ENTRY_KEY_STR)), I32Const(-1), I32Ne,
+                    If(BlockType::Empty),
+                        LocalGet(b), LocalGet(entry), I64Load(mem64(ENTRY_KEY_INT)), LocalGet(entry), I32Load(mem32(ENTRY_KEY_STR)),
+                        Call(self.h.arr_find), LocalTee(found), I32Eqz,
+                        If(BlockType::Empty), I32Const(1), Return, End,
+                        LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), LocalGet(found), I32Load(mem32(ENTRY_VALUE)),
+                        Call(self.h.compare), LocalTee(c),
+                        If(BlockType::Empty), LocalGet(c), Return, End,
+                    End,
+                    LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                    Br(0),
+                End,
+                End,
+                I32Const(0), Return,
+            End,
+            LocalGet(ta), I32Const(TAG_ARRAY), I32Eq, If(BlockType::Empty), I32Const(1), Return, End,
+            LocalGet(tb), I32Const(TAG_ARRAY), I32Eq, If(BlockType::Empty), I32Const(-1), Return, End,
+
+            // Objects of the same class: property by property
+            LocalGet(ta), I32Const(TAG_OBJECT), I32Eq, LocalGet(tb), I32Const(TAG_OBJECT), I32Eq, I32And,
+            If(BlockType::Empty),
+                LocalGet(a), LocalGet(b), I32Eq, If(BlockType::Empty), I32Const(0), Return, End,
+                LocalGet(a), I32Load(mem32(OBJ_CLASS)), LocalGet(b), I32Load(mem32(OBJ_CLASS)), I32Ne,
+                If(BlockType::Empty), I32Const(1), Return, End,
+                LocalGet(a), I32Load(mem32(OBJ_PROP_COUNT)), LocalSet(count),
+                Block(BlockType::Empty),
+                Loop(BlockType::Empty),
+                    LocalGet(i), LocalGet(count), I32GeU, BrIf(1),
+                    LocalGet(a), LocalGet(i), I32Const(2), I32Shl, I32Add, I32Load(mem32(OBJ_PROPS)),
+                    LocalGet(b), LocalGet(i), I32Const(2), I32Shl, I32Add, I32Load(mem32(OBJ_PROPS)),
+                    Call(self.h.compare), LocalTee(c),
+                    If(BlockType::Empty), LocalGet(c), Return, End,
+                    LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                    Br(0),
+                End,
+                End,
+                LocalGet(a), I32Load(mem32(OBJ_DYNAMIC)), LocalGet(b), I32Load(mem32(OBJ_DYNAMIC)), Call(self.h.compare),
+                Return,
+            End,
+            I32Const(1),
+        ]);
+        self.builder.set_function_at_index(self.h.compare, ty, vec![(9, ValType::I32)], body);
     }
-    
-    fn add_not_equal_function(&mut self) {
-        let mut neq_body = vec![];
-        let mut neq_locals = vec![];
-        neq_locals.push((1, ValType::I32)); // result_ptr
-        neq_locals.push((1, ValType::I32)); // bool_addr
-        
-        // Call equal function
-        neq_body.push(Instruction::LocalGet(0));
-        neq_body.push(Instruction::LocalGet(1));
-        neq_body.push(Instruction::Call(self.equal_fn_idx));
-        
-        // Store result
-        neq_body.push(Instruction::LocalSet(2)); // result_ptr
-        
-        // Get address of boolean value
-        neq_body.push(Instruction::LocalGet(2));
-        neq_body.push(Instruction::I32Const(4));
-        neq_body.push(Instruction::I32Add);
-        neq_body.push(Instruction::LocalSet(3)); // bool_addr
-        
-        // Load boolean value, invert it, and store back
-        neq_body.push(Instruction::LocalGet(3)); // address
-        neq_body.push(Instruction::LocalGet(3)); // address again
-        neq_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        neq_body.push(Instruction::I32Const(1));
-        neq_body.push(Instruction::I32Xor); // Flip the bit (0->1, 1->0)
-        neq_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Return result
-        neq_body.push(Instruction::LocalGet(2));
-        
-        self.builder.set_function_at_index(self.not_equal_fn_idx, self.binary_op_type_idx, neq_locals, neq_body);
+
+    /// float_cmp(x, y) -> -1, 0 or 1; NaN compares as 1
+    fn float_cmp_function(&mut self) -> u32 {
+        let ty = self.builder.add_type(vec![ValType::F64, ValType::F64], vec![ValType::I32]);
+        let body = vec![
+            LocalGet(0), LocalGet(1), F64Lt, If(BlockType::Empty), I32Const(-1), Return, End,
+            LocalGet(0), LocalGet(1), F64Ne,
+        ];
+        let idx = self.builder.add_function(ty, vec![], body);
+        self.name_function(idx, "php_float_cmp");
+        idx
     }
 }
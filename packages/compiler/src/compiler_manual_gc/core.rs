@@ -1,349 +1,748 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-use edge_php_parser::*;
-use wasm_encoder::*;
+use edge_php_parser::ast::*;
+use wasm_encoder::{ExportKind, Instruction, MemArg, MemoryType, ValType};
+use crate::compiler::{source_map, Backend, CompiledModule, CompilerOptions};
 use crate::{error::CompilerError, wasm_builder::WasmBuilder};
 use std::collections::HashMap;
+use super::runtime::Helpers;
 
-/// Memory layout constants matching runtime
-pub(super) const HEAP_START: u32 = 0x100000;  // 1MB
-pub(super) const RUNTIME_DATA_START: u32 = 0x1000;  // 4KB
-pub(super) const STRING_TABLE_START: u32 = 0x10000;  // 64KB
+// Linear memory layout:
+//
+//   0x0000  the null value (all zeros, so a zeroed slot reads as null)
+//   0x0010  false, 0x0020 true, 0x0030 the marker for omitted arguments
+//   0x0040  scratch space for number formatting
+//   0x0400  static data: literals, the small int cache, global and static
+//           variable slots, free lists and the GC root table
+//           root stack: function frames and temporaries (ROOT_STACK_SIZE)
+//           heap: blocks with an 8 byte header, collected by mark-sweep
+//
+// A PHP value is a pointer to a value block starting with its type tag.
+// Values are immutable except arrays and objects; arrays are copied when they
+// are assigned, so every array has a single owner and is updated in place.
 
 /// PHP value type tags
-pub(super) const TYPE_NULL: u8 = 0;
-pub(super) const TYPE_BOOL: u8 = 1;
-pub(super) const TYPE_INT: u8 = 2;
-pub(super) const TYPE_FLOAT: u8 = 3;
-pub(super) const TYPE_STRING: u8 = 4;
-pub(super) const TYPE_ARRAY: u8 = 5;
-pub(super) const TYPE_OBJECT: u8 = 6;
-pub(super) const TYPE_RESOURCE: u8 = 7;
-pub(super) const TYPE_REFERENCE: u8 = 8;
-
-/// Size of a PHP value in bytes
-pub(super) const VALUE_SIZE: u32 = 16;
+pub(super) const TAG_NULL: i32 = 0;
+pub(super) const TAG_BOOL: i32 = 1;
+pub(super) const TAG_INT: i32 = 2;
+pub(super) const TAG_FLOAT: i32 = 3;
+pub(super) const TAG_STRING: i32 = 4;
+pub(super) const TAG_ARRAY: i32 = 5;
+pub(super) const TAG_OBJECT: i32 = 6;
 
-pub struct Compiler {
-    pub(super) builder: WasmBuilder,
-    
-    /// Maps variable names to their memory addresses
-    pub(super) variables: HashMap<String, u32>,
-    
-    /// User-defined functions
-    pub(super) functions: HashMap<String, u32>,
-    
-    /// String literals to be added to data section
-    pub(super) strings: Vec<String>,
-    
-    /// Next free heap address
-    pub(super) heap_ptr: u32,
-    
-    /// Current function being compiled
-    pub(super) current_function: Option<FunctionContext>,
-    
-    /// Import indices
-    pub(super) print_fn_idx: u32,
-    pub(super) alloc_value_fn_idx: u32,
-    pub(super) alloc_string_fn_idx: u32,
-    
-    /// Type indices for runtime functions
-    pub(super) alloc_value_type_idx: u32,
-    pub(super) alloc_string_type_idx: u32,
-    pub(super) binary_op_type_idx: u32,
-    pub(super) unary_op_type_idx: u32,
-    
-    /// Runtime function indices
-    pub(super) add_fn_idx: u32,
-    pub(super) subtract_fn_idx: u32,
-    pub(super) multiply_fn_idx: u32,
-    pub(super) divide_fn_idx: u32,
-    pub(super) concat_fn_idx: u32,
-    pub(super) to_bool_fn_idx: u32,
-    pub(super) to_string_fn_idx: u32,
-    pub(super) greater_than_fn_idx: u32,
-    pub(super) less_than_fn_idx: u32,
-    pub(super) equal_fn_idx: u32,
-    pub(super) not_equal_fn_idx: u32,
-    pub(super) int_to_string_fn_idx: u32,
-    pub(super) float_to_string_fn_idx: u32,
-    pub(super) is_null_fn_idx: u32,
-    pub(super) isset_fn_idx: u32,
-    pub(super) empty_fn_idx: u32,
-    
-    /// Garbage collection function indices
-    pub(super) incref_fn_idx: u32,
-    pub(super) decref_fn_idx: u32,
-    pub(super) free_value_fn_idx: u32,
-    pub(super) free_string_fn_idx: u32,
+/// Heap block kinds, stored in the block header
+pub(super) const KIND_FREE: i32 = 0;
+pub(super) const KIND_VALUE: i32 = 1;  // Starts with a type tag, traced by the collector
+pub(super) const KIND_DATA: i32 = 2;   // Owned by a value (array entries and buckets)
+
+/// Block header: size (including the header) and kind; the mark byte follows the kind
+pub(super) const HEADER_SIZE: i32 = 8;
+pub(super) const HEADER_KIND: u64 = 4;
+pub(super) const HEADER_MARK: u64 = 5;
+
+/// Scalar values: tag, then an i64, f64 or bool at VALUE_DATA
+pub(super) const VALUE_DATA: u64 = 8;
+pub(super) const VALUE_SIZE: i32 = 16;
+
+/// Strings: tag, length, then the bytes and a NUL terminator for `env.print`
+pub(super) const STR_LEN: u64 = 4;
+pub(super) const STR_BYTES: u64 = 8;
+
+/// Arrays: ordered hash tables. Entries are kept in insertion order; deleted
+/// entries stay behind as tombstones until the table is rebuilt.
+pub(super) const ARR_COUNT: u64 = 4;    // Live entries
+pub(super) const ARR_USED: u64 = 8;     // Entries used, tombstones included
+pub(super) const ARR_CAP: u64 = 12;     // Entry capacity, a power of two
+pub(super) const ARR_NEXT: u64 = 16;    // Next integer key (i64)
+pub(super) const ARR_ENTRIES: u64 = 24;
+pub(super) const ARR_BUCKETS: u64 = 28; // Index + 1 of the first entry of each hash chain
+pub(super) const ARRAY_SIZE: i32 = 32;
+
+pub(super) const ENTRY_KEY_INT: u64 = 0;  // i64
+pub(super) const ENTRY_KEY_STR: u64 = 8;  // String key, 0 for integer keys, -1 once deleted
+pub(super) const ENTRY_VALUE: u64 = 12;
+pub(super) const ENTRY_HASH: u64 = 16;
+pub(super) const ENTRY_NEXT: u64 = 20;    // Index + 1 of the next entry in the chain
+pub(super) const ENTRY_SIZE: i32 = 24;
+
+/// Objects: tag, class id, property count, dynamic properties (an array or
+/// null), then the declared properties
+pub(super) const OBJ_CLASS: u64 = 4;
+pub(super) const OBJ_PROP_COUNT: u64 = 8;
+pub(super) const OBJ_DYNAMIC: u64 = 12;
+pub(super) const OBJ_PROPS: u64 = 16;
+
+pub(super) const NULL_VALUE: u32 = 0;
+pub(super) const FALSE_VALUE: u32 = 0x10;
+pub(super) const TRUE_VALUE: u32 = 0x20;
+/// Passed for parameters a call leaves out; reads as null
+pub(super) const MISSING_VALUE: u32 = 0x30;
+pub(super) const SCRATCH: u32 = 0x40;
+pub(super) const STATIC_START: u32 = 0x400;
+
+/// Ints in this range are preallocated, so loop counters do not allocate
+pub(super) const SMALL_INT_MIN: i64 = -128;
+pub(super) const SMALL_INT_COUNT: u32 = 1152;
+
+pub(super) const ROOT_STACK_SIZE: u32 = 1 << 20;
+/// Bytes allocated before the first collection; later the live size
+pub(super) const MIN_GC_THRESHOLD: i32 = 1 << 20;
+/// Free lists: one per block size up to SMALL_BLOCK_MAX, plus one for larger blocks
+pub(super) const SMALL_BLOCK_MAX: i32 = 256;
+pub(super) const FREE_LIST_COUNT: u32 = 33;
+
+pub(super) const MEMORY_COPY: Instruction<'static> = Instruction::MemoryCopy { src_mem: 0, dst_mem: 0 };
+
+pub(super) fn mem8(offset: u64) -> MemArg {
+    MemArg { offset, align: 0, memory_index: 0 }
+}
+
+pub(super) fn mem32(offset: u64) -> MemArg {
+    MemArg { offset, align: 2, memory_index: 0 }
+}
+
+pub(super) fn mem64(offset: u64) -> MemArg {
+    MemArg { offset, align: 3, memory_index: 0 }
+}
+
+/// Wasm globals holding the allocator and collector state
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Globals {
+    pub sp: u32,           // Top of the root stack
+    pub stack_base: u32,
+    pub stack_limit: u32,
+    pub heap_start: u32,
+    pub heap_top: u32,     // Bump pointer
+    pub heap_end: u32,     // End of memory
+    pub allocated: u32,    // Bytes allocated since the last collection
+    pub threshold: u32,
+    pub mark_top: u32,     // The collector's mark stack grows from the top of the root stack
+    pub mark_overflow: u32,
+    pub released: u32,     // A variable let go of an object with a destructor
+    pub destructing: u32,  // Destructors of unreachable objects are running
+    pub numeric: u32,      // How much of the last parsed string was numeric
+}
+
+/// Where a PHP variable lives
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum VarLoc {
+    /// Slot of the current function's frame on the root stack
+    Frame(u32),
+    /// Static slot: top-level variables, `static` variables, static properties
+    Static(u32),
 }
 
 pub(super) struct FunctionContext {
     pub(super) locals: Vec<(u32, ValType)>,
     pub(super) body: Vec<Instruction<'static>>,
     pub(super) local_count: u32,
+    pub(super) params: u32,
+    pub(super) vars: HashMap<String, VarLoc>,
+    /// None for top-level code, whose variables are static
+    pub(super) frame: Option<Frame>,
+    /// Nesting of blocks, loops and ifs, and the levels `break`/`continue` target
+    pub(super) depth: u32,
+    pub(super) loops: Vec<(u32, u32)>,
+    pub(super) locations: Vec<(usize, SourceLocation)>,
+    /// Name of the function or method, for `static` variables
+    pub(super) scope: String,
+    /// Declared return type
+    pub(super) return_type: Option<Type>,
+}
+
+pub(super) struct Frame {
+    pub(super) fp: u32,         // Local holding the frame address
+    pub(super) base: u32,       // Local holding the end of the frame, where temporaries start
+    pub(super) slots: u32,
+    pub(super) size_patches: Vec<usize>,
+}
+
+pub(super) struct FunctionInfo {
+    pub(super) idx: u32,
+    pub(super) params: Vec<Parameter>,
+}
+
+pub struct Compiler {
+    pub(super) builder: WasmBuilder,
+    pub(super) options: CompilerOptions,
+    pub(super) sources: Vec<(String, String)>,
+    pub(super) h: Helpers,
+    pub(super) g: Globals,
+    pub(super) print_fn_idx: u32,
+    pub(super) current_function: Option<FunctionContext>,
+    pub(super) functions: HashMap<String, FunctionInfo>,
+    pub(super) classes: HashMap<String, super::classes::ClassInfo>,
+    pub(super) class_names: Vec<String>,  // By class id
+    pub(super) current_class: Option<String>,
+    /// Method dispatch functions by lowercase name: index, argument count
+    /// and the name as first called
+    pub(super) dispatchers: HashMap<String, (u32, usize, String)>,
+    /// The most arguments any call passes to a method name
+    pub(super) call_arities: HashMap<String, usize>,
+    /// Root slots of the array of objects to destroy at the end of the script
+    /// and of the unreachable ones being destroyed before
+    pub(super) destructors: Option<(u32, u32)>,
+    pub(super) strict_types: bool,
+    /// Types of functions taking and returning values, by parameter count
+    value_types: HashMap<usize, u32>,
+
+    /// Memory image from STATIC_START, written as data segments
+    pub(super) statics: Vec<u8>,
+    pub(super) string_literals: HashMap<String, u32>,
+    pub(super) int_literals: HashMap<i64, u32>,
+    pub(super) float_literals: HashMap<u64, u32>,
+    pub(super) small_ints: u32,
+    pub(super) free_lists: u32,
+    /// Top-level variables, in the order they were first seen
+    pub(super) global_vars: Vec<(String, u32)>,
+    /// `static` variables by scope and name: value slot and initialized flag
+    pub(super) static_vars: HashMap<(String, String), (u32, u32)>,
+    /// Static slots the collector scans
+    pub(super) root_slots: Vec<u32>,
 }
 
 impl Compiler {
-    pub fn new() -> Self {
+    pub fn new(options: CompilerOptions, sources: Vec<(String, String)>) -> Self {
         let mut builder = WasmBuilder::new();
-        
-        // Import host functions
-        let print_type = builder.add_type(vec![ValType::I32, ValType::I32], vec![]);
+        builder.set_tree_shaking(options.tree_shaking_enabled());
+        builder.set_fuel_metering(options.fuel_metering_enabled());
+
+        let print_type = builder.add_type(vec![ValType::I32], vec![]);
         let print_fn_idx = builder.add_import_func("env", "print", print_type);
-        
-        // We'll implement alloc_value as an internal function, not an import!
-        let alloc_value_type_idx = builder.add_type(vec![], vec![ValType::I32]);
-        let alloc_value_fn_idx = builder.reserve_function_index();
-        
-        let alloc_string_type_idx = builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
-        let alloc_string_fn_idx = builder.reserve_function_index();
-        
-        // Runtime operation functions (will be added later)
-        let binary_op_type_idx = builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
-        let add_fn_idx = builder.reserve_function_index();
-        let subtract_fn_idx = builder.reserve_function_index();
-        let multiply_fn_idx = builder.reserve_function_index();
-        let divide_fn_idx = builder.reserve_function_index();
-        let concat_fn_idx = builder.reserve_function_index();
-        let greater_than_fn_idx = builder.reserve_function_index();
-        let less_than_fn_idx = builder.reserve_function_index();
-        let equal_fn_idx = builder.reserve_function_index();
-        let not_equal_fn_idx = builder.reserve_function_index();
-        
-        let unary_op_type_idx = builder.add_type(vec![ValType::I32], vec![ValType::I32]);
-        let to_bool_fn_idx = builder.reserve_function_index();
-        let to_string_fn_idx = builder.reserve_function_index();
-        
-        // int_to_string: takes i64, returns string pointer
-        let _int_to_string_type_idx = builder.add_type(vec![ValType::I64], vec![ValType::I32]);
-        let int_to_string_fn_idx = builder.reserve_function_index();
-        
-        // float_to_string: takes f64, returns string pointer
-        let _float_to_string_type_idx = builder.add_type(vec![ValType::F64], vec![ValType::I32]);
-        let float_to_string_fn_idx = builder.reserve_function_index();
-        
-        // is_null: takes PhpValue*, returns PhpValue* (boolean)
-        let is_null_fn_idx = builder.reserve_function_index();
-        
-        // isset: takes PhpValue*, returns PhpValue* (boolean)
-        let isset_fn_idx = builder.reserve_function_index();
-        
-        // empty: takes PhpValue*, returns PhpValue* (boolean)
-        let empty_fn_idx = builder.reserve_function_index();
-        
-        // Garbage collection functions
-        let incref_fn_idx = builder.reserve_function_index();
-        let decref_fn_idx = builder.reserve_function_index();
-        let free_value_fn_idx = builder.reserve_function_index();
-        let free_string_fn_idx = builder.reserve_function_index();
-        
-        Compiler {
+        if options.debug_info_enabled() {
+            builder.set_function_name(print_fn_idx, "env.print");
+        }
+        let h = Helpers::reserve(&mut builder, options.debug_info_enabled());
+
+        let mut global = || builder.add_global(ValType::I32, true, Instruction::I32Const(0));
+        let g = Globals {
+            sp: global(),
+            stack_base: global(),
+            stack_limit: global(),
+            heap_start: global(),
+            heap_top: global(),
+            heap_end: global(),
+            allocated: global(),
+            threshold: global(),
+            mark_top: global(),
+            mark_overflow: global(),
+            released: global(),
+            destructing: global(),
+            numeric: global(),
+        };
+
+        let mut compiler = Compiler {
             builder,
-            variables: HashMap::new(),
-            functions: HashMap::new(),
-            strings: Vec::new(),
-            heap_ptr: HEAP_START,
-            current_function: None,
+            options,
+            sources,
+            h,
+            g,
             print_fn_idx,
-            alloc_value_fn_idx,
-            alloc_string_fn_idx,
-            alloc_value_type_idx,
-            alloc_string_type_idx,
-            binary_op_type_idx,
-            unary_op_type_idx,
-            add_fn_idx,
-            subtract_fn_idx,
-            multiply_fn_idx,
-            divide_fn_idx,
-            concat_fn_idx,
-            to_bool_fn_idx,
-            to_string_fn_idx,
-            greater_than_fn_idx,
-            less_than_fn_idx,
-            equal_fn_idx,
-            not_equal_fn_idx,
-            int_to_string_fn_idx,
-            float_to_string_fn_idx,
-            is_null_fn_idx,
-            isset_fn_idx,
-            empty_fn_idx,
-            incref_fn_idx,
-            decref_fn_idx,
-            free_value_fn_idx,
-            free_string_fn_idx,
+            current_function: None,
+            functions: HashMap::new(),
+            classes: HashMap::new(),
+            class_names: Vec::new(),
+            current_class: None,
+            dispatchers: HashMap::new(),
+            call_arities: HashMap::new(),
+            destructors: None,
+            strict_types: false,
+            value_types: HashMap::new(),
+            statics: Vec::new(),
+            string_literals: HashMap::new(),
+            int_literals: HashMap::new(),
+            float_literals: HashMap::new(),
+            small_ints: 0,
+            free_lists: 0,
+            global_vars: Vec::new(),
+            static_vars: HashMap::new(),
+            root_slots: Vec::new(),
+        };
+        compiler.small_ints = compiler.static_alloc(SMALL_INT_COUNT * VALUE_SIZE as u32);
+        compiler.free_lists = compiler.static_alloc(FREE_LIST_COUNT * 4);
+        compiler
+    }
+
+    /// Reserve zeroed static memory, 8 byte aligned
+    pub(super) fn static_alloc(&mut self, size: u32) -> u32 {
+        let addr = STATIC_START + self.statics.len() as u32;
+        let padded = (size as usize + 7) & !7;
+        self.statics.resize(self.statics.len() + padded, 0);
+        addr
+    }
+
+    pub(super) fn static_write(&mut self, addr: u32, bytes: &[u8]) {
+        let start = (addr - STATIC_START) as usize;
+        self.statics[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// A static slot the collector treats as a root
+    pub(super) fn root_slot(&mut self) -> u32 {
+        let slot = self.static_alloc(4);
+        self.root_slots.push(slot);
+        slot
+    }
+
+    /// The static string value of a literal
+    pub(super) fn string_literal(&mut self, s: &str) -> u32 {
+        if let Some(&addr) = self.string_literals.get(s) {
+            return addr;
         }
+        let addr = self.static_alloc(STR_BYTES as u32 + s.len() as u32 + 1);
+        self.static_write(addr, &TAG_STRING.to_le_bytes());
+        self.static_write(addr + STR_LEN as u32, &(s.len() as u32).to_le_bytes());
+        self.static_write(addr + STR_BYTES as u32, s.as_bytes());
+        self.string_literals.insert(s.to_string(), addr);
+        addr
     }
-    
-    pub fn compile(mut self, source: &str) -> Result<Vec<u8>, CompilerError> {
-        let program = parse(source)?;
-        
-        // Add runtime operation functions
-        self.add_runtime_functions();
-        
-        // Set up main function
-        let main_type = self.builder.add_type(vec![], vec![]);
-        self.current_function = Some(FunctionContext {
-            locals: vec![],
-            body: vec![],
-            local_count: 0,
-        });
-        
-        // Initialize heap pointer at address 0
-        self.emit(Instruction::I32Const(0)); // Address to store at
-        self.emit(Instruction::I32Const(HEAP_START as i32)); // Value to store
-        self.emit(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        // Compile all program items
-        eprintln!("DEBUG: Compiling {} items", program.items.len());
-        for item in program.items {
-            match item {
-                ProgramItem::PhpBlock { statements } => {
-                    for stmt in statements {
-                        self.compile_statement(stmt)?;
-                    }
-                }
-                ProgramItem::InlineContent(content) => {
-                    // For inline content, we need to echo it
-                    self.compile_inline_content(&content)?;
-                }
-            }
+
+    pub(super) fn int_literal(&mut self, value: i64) -> u32 {
+        if (SMALL_INT_MIN..SMALL_INT_MIN + SMALL_INT_COUNT as i64).contains(&value) {
+            return self.small_ints + (value - SMALL_INT_MIN) as u32 * VALUE_SIZE as u32;
         }
-        eprintln!("DEBUG: All items compiled");
-        
-        // Finish main function
-        if let Some(func) = self.current_function.take() {
-            // Reserve an index for main function to ensure it comes after runtime functions
-            let main_idx = self.builder.reserve_function_index();
-            eprintln!("DEBUG: Main function will be at index {}", main_idx);
-            self.builder.set_function_at_index(main_idx, main_type, func.locals, func.body);
-            self.builder.add_export("_start", ExportKind::Func, main_idx);
+        if let Some(&addr) = self.int_literals.get(&value) {
+            return addr;
         }
-        
-        // Add static "output" string for testing
-        self.builder.add_data(0x1000, b"output".to_vec());
-        
-        // Add static strings for integer conversion (temporary)
-        // "42" at 0x2000
-        self.builder.add_data(0x2000, 2u32.to_le_bytes().to_vec()); // length
-        self.builder.add_data(0x2004, 0u32.to_le_bytes().to_vec()); // hash (0 for now)
-        self.builder.add_data(0x2008, b"42".to_vec());
-        
-        // "10" at 0x2100
-        self.builder.add_data(0x2100, 2u32.to_le_bytes().to_vec()); // length
-        self.builder.add_data(0x2104, 0u32.to_le_bytes().to_vec()); // hash
-        self.builder.add_data(0x2108, b"10".to_vec());
-        
-        // "5" at 0x2200
-        self.builder.add_data(0x2200, 1u32.to_le_bytes().to_vec()); // length
-        self.builder.add_data(0x2204, 0u32.to_le_bytes().to_vec()); // hash
-        self.builder.add_data(0x2208, b"5".to_vec());
-        
-        // "15" at 0x2300
-        self.builder.add_data(0x2300, 2u32.to_le_bytes().to_vec()); // length
-        self.builder.add_data(0x2304, 0u32.to_le_bytes().to_vec()); // hash
-        self.builder.add_data(0x2308, b"15".to_vec());
-        
-        // "0" at 0x3000 for int_to_string
-        self.builder.add_data(0x3000, b"0".to_vec());
-        
-        // Empty string at 0x2400 for boolean false
-        self.builder.add_data(0x2400, 0u32.to_le_bytes().to_vec()); // length = 0
-        self.builder.add_data(0x2404, 0u32.to_le_bytes().to_vec()); // hash = 0
-        
-        // "1" at 0x2500 for boolean true
-        self.builder.add_data(0x2500, 1u32.to_le_bytes().to_vec()); // length = 1
-        self.builder.add_data(0x2504, 0u32.to_le_bytes().to_vec()); // hash = 0
-        self.builder.add_data(0x2508, b"1".to_vec());
-        
-        // "3.14" at 0x2600 for float (temporary)
-        self.builder.add_data(0x2600, b"3.14".to_vec());
-        
-        // Add string data to data section
-        let mut string_offset = STRING_TABLE_START;
-        for string in &self.strings {
-            let bytes = string.as_bytes();
-            let len = bytes.len() as u32;
-            
-            // Add string header (length and hash)
-            self.builder.add_data(string_offset, len.to_le_bytes().to_vec());
-            string_offset += 4;
-            
-            let hash = hash_string(string);
-            self.builder.add_data(string_offset, hash.to_le_bytes().to_vec());
-            string_offset += 4;
-            
-            // Add string data
-            self.builder.add_data(string_offset, bytes.to_vec());
-            string_offset += len;
-            
-            // Align to 4 bytes
-            string_offset = (string_offset + 3) & !3;
+        let addr = self.static_alloc(VALUE_SIZE as u32);
+        self.static_write(addr, &TAG_INT.to_le_bytes());
+        self.static_write(addr + VALUE_DATA as u32, &value.to_le_bytes());
+        self.int_literals.insert(value, addr);
+        addr
+    }
+
+    pub(super) fn float_literal(&mut self, value: f64) -> u32 {
+        if let Some(&addr) = self.float_literals.get(&value.to_bits()) {
+            return addr;
         }
-        
-        Ok(self.builder.build())
+        let addr = self.static_alloc(VALUE_SIZE as u32);
+        self.static_write(addr, &TAG_FLOAT.to_le_bytes());
+        self.static_write(addr + VALUE_DATA as u32, &value.to_le_bytes());
+        self.float_literals.insert(value.to_bits(), addr);
+        addr
     }
-    
-    fn compile_inline_content(&mut self, content: &str) -> Result<(), String> {
-        let s_ref = self.builder.add_string(content);
-        self.emit(Instruction::I32Const(s_ref.start as i32));
-        self.emit(Instruction::I32Const(s_ref.len as i32));
-        self.emit(Instruction::Call(self.print_fn_idx));
-        Ok(())
+
+    /// The static slot of a top-level variable
+    pub(super) fn global_slot(&mut self, name: &str) -> u32 {
+        if let Some((_, slot)) = self.global_vars.iter().find(|(n, _)| n == name) {
+            return *slot;
+        }
+        let slot = self.root_slot();
+        self.global_vars.push((name.to_string(), slot));
+        slot
     }
-    
+
     pub(super) fn emit(&mut self, instruction: Instruction<'static>) {
         if let Some(func) = &mut self.current_function {
+            match instruction {
+                Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) => func.depth += 1,
+                Instruction::End => func.depth -= 1,
+                _ => {}
+            }
             func.body.push(instruction);
         }
     }
-    
+
+    pub(super) fn emit_all(&mut self, instructions: impl IntoIterator<Item = Instruction<'static>>) {
+        for instruction in instructions {
+            self.emit(instruction);
+        }
+    }
+
     pub(super) fn allocate_local(&mut self, val_type: ValType) -> u32 {
-        if let Some(func) = &mut self.current_function {
-            let idx = func.local_count;
-            func.local_count += 1;
-            func.locals.push((1, val_type));
-            idx
-        } else {
-            0
+        let func = self.current_function.as_mut().expect("a function is being compiled");
+        let idx = func.local_count;
+        func.local_count += 1;
+        func.locals.push((1, val_type));
+        idx
+    }
+
+    pub(super) fn depth(&self) -> u32 {
+        self.current_function.as_ref().map_or(0, |func| func.depth)
+    }
+
+    /// Where a variable of the current function lives, creating it if needed
+    pub(super) fn variable(&mut self, name: &str) -> VarLoc {
+        let func = self.current_function.as_mut().expect("a function is being compiled");
+        if let Some(&loc) = func.vars.get(name) {
+            return loc;
         }
+        let loc = match &mut func.frame {
+            Some(frame) => {
+                frame.slots += 1;
+                VarLoc::Frame(frame.slots - 1)
+            }
+            None => VarLoc::Static(0),
+        };
+        let loc = match loc {
+            VarLoc::Static(_) => VarLoc::Static(self.global_slot(name)),
+            frame => frame,
+        };
+        self.current_function.as_mut().unwrap().vars.insert(name.to_string(), loc);
+        loc
     }
-    
-    pub(super) fn get_or_create_local(&mut self, name: &str) -> u32 {
-        if let Some(idx) = self.variables.get(name) {
-            *idx
-        } else {
-            let idx = self.allocate_local(ValType::I32);
-            self.variables.insert(name.to_string(), idx);
-            idx
+
+    /// A hidden frame slot (or static slot at the top level) keeping a value
+    /// alive across statements
+    pub(super) fn hidden_slot(&mut self) -> VarLoc {
+        let func = self.current_function.as_mut().expect("a function is being compiled");
+        match &mut func.frame {
+            Some(frame) => {
+                frame.slots += 1;
+                VarLoc::Frame(frame.slots - 1)
+            }
+            None => VarLoc::Static(self.root_slot()),
         }
     }
-    
-    pub(super) fn get_local(&self, name: &str) -> Option<u32> {
-        self.variables.get(name).copied()
+
+    pub(super) fn emit_load(&mut self, loc: VarLoc) {
+        match loc {
+            VarLoc::Frame(slot) => {
+                let fp = self.frame_pointer();
+                self.emit(Instruction::LocalGet(fp));
+                self.emit(Instruction::I32Load(mem32(slot as u64 * 4)));
+            }
+            VarLoc::Static(addr) => {
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::I32Load(mem32(addr as u64)));
+            }
+        }
     }
-    
-    pub(super) fn current_local(&self) -> u32 {
+
+    /// Store the value in `local` to a variable
+    pub(super) fn emit_store(&mut self, loc: VarLoc, local: u32) {
+        match loc {
+            VarLoc::Frame(slot) => {
+                let fp = self.frame_pointer();
+                self.emit(Instruction::LocalGet(fp));
+                self.emit(Instruction::LocalGet(local));
+                self.emit(Instruction::I32Store(mem32(slot as u64 * 4)));
+            }
+            VarLoc::Static(addr) => {
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::LocalGet(local));
+                self.emit(Instruction::I32Store(mem32(addr as u64)));
+            }
+        }
+    }
+
+    fn frame_pointer(&self) -> u32 {
         self.current_function.as_ref()
-            .map(|f| f.local_count)
-            .unwrap_or(0)
+            .and_then(|func| func.frame.as_ref())
+            .map(|frame| frame.fp)
+            .expect("frame slots belong to a function")
     }
-    
-    pub(super) fn allocate_heap(&mut self, size: u32) -> u32 {
-        let addr = self.heap_ptr;
-        self.heap_ptr += size;
-        // Align to 8 bytes
-        self.heap_ptr = (self.heap_ptr + 7) & !7;
-        addr
+
+    /// Start a function: allocate its frame on the root stack and move the
+    /// parameters into the first slots
+    pub(super) fn begin_function(&mut self, scope: &str, params: &[String]) {
+        let param_count = params.len() as u32;
+        let mut func = FunctionContext {
+            locals: vec![],
+            body: vec![],
+            local_count: param_count,
+            params: param_count,
+            vars: HashMap::new(),
+            frame: Some(Frame { fp: 0, base: 0, slots: 0, size_patches: Vec::new() }),
+            depth: 0,
+            loops: Vec::new(),
+            locations: Vec::new(),
+            scope: scope.to_string(),
+            return_type: None,
+        };
+        for (i, name) in params.iter().enumerate() {
+            func.vars.insert(name.clone(), VarLoc::Frame(i as u32));
+        }
+        func.frame.as_mut().unwrap().slots = param_count;
+        self.current_function = Some(func);
+
+        let fp = self.allocate_local(ValType::I32);
+        let base = self.allocate_local(ValType::I32);
+        let frame = self.current_function.as_mut().unwrap().frame.as_mut().unwrap();
+        frame.fp = fp;
+        frame.base = base;
+
+        // fp = sp; base = sp = fp + frame size
+        self.emit_all([
+            Instruction::GlobalGet(self.g.sp),
+            Instruction::LocalTee(fp),
+        ]);
+        self.emit_frame_size();
+        self.emit_all([
+            Instruction::I32Add,
+            Instruction::LocalTee(base),
+            Instruction::GlobalSet(self.g.sp),
+            Instruction::LocalGet(base),
+            Instruction::GlobalGet(self.g.stack_limit),
+            Instruction::I32GtU,
+            Instruction::If(wasm_encoder::BlockType::Empty),
+            Instruction::Call(self.h.stack_overflow),
+            Instruction::End,
+            Instruction::LocalGet(fp),
+            Instruction::I32Const(0),
+        ]);
+        self.emit_frame_size();
+        self.emit(Instruction::MemoryFill(0));
+        for i in 0..param_count {
+            self.emit_all([
+                Instruction::LocalGet(fp),
+                Instruction::LocalGet(i),
+                Instruction::I32Store(mem32(i as u64 * 4)),
+            ]);
+        }
+    }
+
+    /// The frame size is only known once the body is compiled
+    fn emit_frame_size(&mut self) {
+        let func = self.current_function.as_mut().unwrap();
+        let position = func.body.len();
+        func.frame.as_mut().unwrap().size_patches.push(position);
+        func.body.push(Instruction::I32Const(0));
+    }
+
+    /// Return the value on the stack: pop the frame and leave the value as the
+    /// caller's first temporary, so it stays reachable
+    pub(super) fn emit_return(&mut self) {
+        let func = self.current_function.as_ref().unwrap();
+        match &func.frame {
+            Some(frame) => {
+                let fp = frame.fp;
+                let result = self.allocate_local(ValType::I32);
+                self.emit_all([
+                    Instruction::LocalSet(result),
+                    Instruction::LocalGet(fp),
+                    Instruction::LocalGet(result),
+                    Instruction::I32Store(mem32(0)),
+                    Instruction::LocalGet(fp),
+                    Instruction::I32Const(4),
+                    Instruction::I32Add,
+                    Instruction::GlobalSet(self.g.sp),
+                    Instruction::LocalGet(result),
+                    Instruction::Return,
+                ]);
+            }
+            None => {
+                // `return` at the top level ends the script
+                self.emit(Instruction::Drop);
+                self.emit(Instruction::Return);
+            }
+        }
+    }
+
+    /// Finish the function being compiled and hand it to the builder
+    pub(super) fn finish_function(&mut self, idx: u32, type_idx: u32) {
+        let mut func = self.current_function.take().expect("a function is being compiled");
+        if let Some(frame) = &func.frame {
+            for &position in &frame.size_patches {
+                func.body[position] = Instruction::I32Const(frame.slots as i32 * 4);
+            }
+        }
+        if self.options.debug_info_enabled() {
+            // Parameters are the only PHP variables held in wasm locals
+            let mut names: Vec<(u32, String)> = func.vars.iter()
+                .filter_map(|(name, loc)| match loc {
+                    VarLoc::Frame(slot) if *slot < func.params => Some((*slot, format!("${}", name))),
+                    _ => None,
+                })
+                .collect();
+            names.sort();
+            self.builder.set_local_names(idx, names);
+        }
+        if !func.locations.is_empty() {
+            self.builder.set_source_locations(idx, func.locations);
+        }
+        self.builder.set_function_at_index(idx, type_idx, func.locals, func.body);
+    }
+
+    /// The type of a PHP function with `params` parameters
+    pub(super) fn value_function_type(&mut self, params: usize) -> u32 {
+        if let Some(&ty) = self.value_types.get(&params) {
+            return ty;
+        }
+        let ty = self.builder.add_type(vec![ValType::I32; params], vec![ValType::I32]);
+        self.value_types.insert(params, ty);
+        ty
+    }
+
+    pub(super) fn name_function(&mut self, idx: u32, name: &str) {
+        if self.options.debug_info_enabled() {
+            self.builder.set_function_name(idx, name);
+        }
+    }
+
+    /// The code emitted next comes from `location`
+    pub(super) fn record_location(&mut self, location: SourceLocation) {
+        if let Some(func) = &mut self.current_function {
+            let position = func.body.len();
+            if func.locations.last().is_some_and(|(start, _)| *start == position) {
+                func.locations.pop();
+            }
+            func.locations.push((position, location));
+        }
+    }
+}
+
+impl Backend for Compiler {
+    fn compile_program(self: Box<Self>, program: Program) -> Result<CompiledModule, CompilerError> {
+        (*self).compile(program)
+    }
+}
+
+impl Compiler {
+    fn compile(mut self, program: Program) -> Result<CompiledModule, CompilerError> {
+        self.strict_types = program.items.iter().any(|item| matches!(item,
+            ProgramItem::PhpBlock { statements } if statements.iter().any(|stmt| matches!(stmt,
+                Statement::Declare(directives) if directives.iter().any(|(name, value)|
+                    name.eq_ignore_ascii_case("strict_types") && *value == Expression::Literal(Literal::Integer(1)))))));
+
+        let statements: Vec<&Statement> = program.items.iter()
+            .filter_map(|item| match item {
+                ProgramItem::PhpBlock { statements } => Some(statements.iter()),
+                ProgramItem::InlineContent(_) => None,
+            })
+            .flatten()
+            .collect();
+
+        // Classes and functions are known before any code runs
+        self.register_classes(&statements)?;
+        for stmt in &statements {
+            if let Statement::Function { name, params, .. } = stmt {
+                self.declare_function(name, params)?;
+            }
+        }
+        self.compile_classes(&statements)?;
+        for stmt in &statements {
+            if let Statement::Function { name, params, body, return_type } = stmt {
+                self.compile_function(name, params, return_type.as_ref(), &body.statements)?;
+            }
+        }
+
+        self.current_function = Some(FunctionContext {
+            locals: vec![],
+            body: vec![],
+            local_count: 0,
+            params: 0,
+            vars: HashMap::new(),
+            frame: None,
+            depth: 0,
+            loops: Vec::new(),
+            locations: Vec::new(),
+            scope: String::new(),
+            return_type: None,
+        });
+        self.init_classes()?;
+        for item in program.items {
+            match item {
+                ProgramItem::PhpBlock { statements } => {
+                    for stmt in statements {
+                        if !matches!(stmt, Statement::Class { .. } | Statement::Function { .. } | Statement::Enum { .. } | Statement::Interface { .. }) {
+                            self.compile_statement(&stmt)?;
+                        }
+                    }
+                }
+                ProgramItem::InlineContent(content) => {
+                    let text = self.string_literal(&content);
+                    self.emit(Instruction::I32Const(text as i32));
+                    self.emit(Instruction::Call(self.h.echo));
+                }
+            }
+        }
+        self.run_destructors();
+
+        // The runtime is generated last: it needs every static slot and class
+        self.add_runtime_functions();
+        let (heap_start, pages) = self.layout()?;
+
+        // Set up the allocator and the root stack before the script runs; enum
+        // cases are allocated before the first statement's safepoint sets `sp`
+        let main_body = std::mem::take(&mut self.current_function.as_mut().unwrap().body);
+        let mut init = vec![
+            Instruction::I32Const((heap_start - ROOT_STACK_SIZE - 8) as i32),
+            Instruction::GlobalSet(self.g.stack_base),
+            Instruction::I32Const((heap_start - ROOT_STACK_SIZE - 8) as i32),
+            Instruction::GlobalSet(self.g.sp),
+            Instruction::I32Const((heap_start - 8) as i32),
+            Instruction::GlobalSet(self.g.stack_limit),
+            Instruction::I32Const(heap_start as i32),
+            Instruction::GlobalSet(self.g.heap_start),
+            Instruction::I32Const(heap_start as i32),
+            Instruction::GlobalSet(self.g.heap_top),
+            Instruction::I32Const((pages * 65536) as i32),
+            Instruction::GlobalSet(self.g.heap_end),
+            Instruction::I32Const(MIN_GC_THRESHOLD),
+            Instruction::GlobalSet(self.g.threshold),
+            Instruction::Call(self.h.init_small_ints),
+        ];
+        let init_len = init.len();
+        init.extend(main_body);
+        let func = self.current_function.as_mut().unwrap();
+        func.body = init;
+        for (position, _) in &mut func.locations {
+            *position += init_len;
+        }
+
+        let main_idx = self.builder.reserve_function_index();
+        let main_type = self.builder.add_type(vec![], vec![]);
+        self.name_function(main_idx, "{main}");
+        self.finish_function(main_idx, main_type);
+        self.builder.add_export("_start", ExportKind::Func, main_idx);
+
+        let (_, max_pages) = self.options.memory_pages();
+        self.builder.add_memory(MemoryType {
+            minimum: pages as u64,
+            maximum: max_pages.map(u64::from),
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        self.builder.add_export("memory", ExportKind::Memory, 0);
+
+        if let Some(url) = self.options.get_source_map_url() {
+            self.builder.set_source_map_url(url);
+        }
+        let source_map_enabled = self.options.source_map_enabled();
+        let (wasm, locations) = self.builder.build_with_locations();
+        let source_map = source_map_enabled.then(|| source_map(&self.sources, &locations));
+        Ok(CompiledModule { wasm, source_map, type_report: None, ir: None })
     }
 }
 
-/// Simple string hash function (DJB2)
-pub(super) fn hash_string(s: &str) -> u32 {
-    let mut hash = 5381u32;
-    for byte in s.bytes() {
-        hash = hash.wrapping_mul(33).wrapping_add(byte as u32);
+impl Compiler {
+    /// Place the root stack and the heap after the static data and write the
+    /// static data segments. Returns the heap start and the initial page count.
+    fn layout(&mut self) -> Result<(u32, u32), String> {
+        let static_end = STATIC_START + self.statics.len() as u32;
+        let heap_start = static_end + 8 + ROOT_STACK_SIZE + 8;
+        // Room for the heap to start without growing
+        let needed = (heap_start + MIN_GC_THRESHOLD as u32).div_ceil(65536);
+        let (initial, max) = self.options.memory_pages();
+        if max.is_some_and(|max| max < needed) {
+            return Err(format!("the linear-memory backend needs at least {} pages of memory", needed));
+        }
+
+        self.builder.add_data(FALSE_VALUE, TAG_BOOL.to_le_bytes().to_vec());
+        let mut true_value = TAG_BOOL.to_le_bytes().to_vec();
+        true_value.extend_from_slice(&[0; 4]);
+        true_value.extend_from_slice(&1i64.to_le_bytes());
+        self.builder.add_data(TRUE_VALUE, true_value);
+
+        // Zero runs are left out of the data segments
+        let mut start = 0;
+        while start < self.statics.len() {
+            if self.statics[start] == 0 {
+                start += 1;
+                continue;
+            }
+            let mut end = start;
+            let mut zeros = 0;
+            while end < self.statics.len() && zeros < 16 {
+                zeros = if self.statics[end] == 0 { zeros + 1 } else { 0 };
+                end += 1;
+            }
+            let end = end - zeros;
+            self.builder.add_data(STATIC_START + start as u32, self.statics[start..end].to_vec());
+            start = end;
+        }
+        Ok((heap_start, initial.max(needed)))
     }
-    hash
 }
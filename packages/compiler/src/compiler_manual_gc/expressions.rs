@@ -1,333 +1,611 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
+// Expressions. Every expression leaves a value pointer on the stack;
+// conditions can also be compiled to a plain i32 to skip the boolean value.
+// Writes go through the address of the slot holding a value, so nested
+// arrays are created and updated in place.
+
+use super::arithmetic::{OP_ADD, OP_DIV, OP_MOD, OP_MUL, OP_SUB};
+use super::classes::PropInfo;
 use super::core::*;
-use edge_php_parser::ast;
-use wasm_encoder::*;
-use crate::error::CompilerError;
+use super::globals::GLOBALS_VAR;
+use edge_php_parser::ast::*;
+use wasm_encoder::{BlockType, Instruction, ValType};
 
 impl Compiler {
-    pub(super) fn compile_expression(&mut self, expr: ast::Expression) -> Result<(), CompilerError> {
+    pub(super) fn compile_expression(&mut self, expr: &Expression) -> Result<(), String> {
         match expr {
-            ast::Expression::Literal(lit) => self.compile_literal(lit),
-            ast::Expression::Variable(name) => {
-                if let Some(&addr) = self.variables.get(&name) {
-                    eprintln!("DEBUG: Loading variable '{}' from address 0x{:x}", name, addr);
-                    // Load the pointer from the variable's memory location
-                    self.emit(Instruction::I32Const(addr as i32));
-                    eprintln!("DEBUG: Emitted I32Const({}) for variable load", addr);
-                    self.emit(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-                    
-                    // Debug: Print what we loaded
-                    eprintln!("DEBUG: Loaded value pointer from variable '{}'", name);
-                    
-                    // Incref the loaded value since we're creating a new reference to it
-                    // TEMPORARILY DISABLED FOR DEBUGGING
-                    // let temp_local = self.allocate_local(ValType::I32);
-                    // self.emit(Instruction::LocalTee(temp_local)); // Keep value on stack and save to temp
-                    // self.emit(Instruction::Call(self.incref_fn_idx));
-                    // self.emit(Instruction::LocalGet(temp_local)); // Put value back on stack
-                } else {
-                    return Err(CompilerError::UndefinedVariable { name });
-                }
+            Expression::Literal(literal) => self.compile_literal(literal),
+            Expression::Variable(name) if name == GLOBALS_VAR => {
+                self.emit(Instruction::Call(self.h.globals_array));
                 Ok(())
             }
-            ast::Expression::Assignment { left, right } => {
-                if let ast::Expression::Variable(name) = *left {
-                    // Compile right side (returns Value pointer)
-                    self.compile_expression(*right)?;
-                    
-                    // Save value for result
-                    let local_idx = self.allocate_local(ValType::I32);
-                    self.emit(Instruction::LocalSet(local_idx));
-                    eprintln!("DEBUG: Assignment - compiled right side, value pointer saved to local {}", local_idx);
-                    
-                    // Store in variable table
-                    let (addr, is_new) = if let Some(&addr) = self.variables.get(&name) {
-                        (addr, false)
-                    } else {
-                        // Variables need static addresses, not runtime allocation
-                        // Use a separate variable space starting after heap
-                        let var_index = self.variables.len() as u32;
-                        let addr = 0x200000 + (var_index * 4); // Variables at 2MB + offset
-                        eprintln!("DEBUG: Allocated variable '{}' at address 0x{:x}", name, addr);
-                        self.variables.insert(name.clone(), addr);
-                        (addr, true)
-                    };
-                    
-                    // If not a new variable, load and decref the old value
-                    // TEMPORARILY DISABLED FOR DEBUGGING
-                    // if !is_new {
-                    //     // Load old value
-                    //     self.emit(Instruction::I32Const(addr as i32));
-                    //     self.emit(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-                    //     // Call decref on old value
-                    //     self.emit(Instruction::Call(self.decref_fn_idx));
-                    // }
-                    
-                    // Incref the new value
-                    // TEMPORARILY DISABLED FOR DEBUGGING
-                    // self.emit(Instruction::LocalGet(local_idx));
-                    // self.emit(Instruction::Call(self.incref_fn_idx));
-                    
-                    // Store the value pointer to the variable's memory location
-                    eprintln!("DEBUG: Storing variable at address 0x{:x}", addr);
-                    self.emit(Instruction::I32Const(addr as i32));
-                    eprintln!("DEBUG: Emitted I32Const({}) for variable store", addr);
-                    self.emit(Instruction::LocalGet(local_idx)); // Get the value we saved
-                    self.emit(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-                    // Stack: []
-                    
-                    // Put value back on stack as the assignment result
-                    self.emit(Instruction::LocalGet(local_idx));
-                    Ok(())
-                } else {
-                    Err(CompilerError::CompilationError {
-                        message: "Invalid assignment target".to_string(),
-                    })
-                }
+            Expression::Variable(name) => {
+                let loc = self.variable(name);
+                self.emit_load(loc);
+                Ok(())
             }
-            ast::Expression::Binary { op, left, right } => {
-                // Compile operands
-                self.compile_expression(*left)?;
-                self.compile_expression(*right)?;
-                
-                // Call appropriate operation
-                match op {
-                    ast::BinaryOp::Add => self.emit(Instruction::Call(self.add_fn_idx)),
-                    ast::BinaryOp::Concat => self.emit(Instruction::Call(self.concat_fn_idx)),
-                    ast::BinaryOp::Subtract => {
-                        self.emit(Instruction::Call(self.subtract_fn_idx));
-                    }
-                    ast::BinaryOp::Multiply => {
-                        self.emit(Instruction::Call(self.multiply_fn_idx));
-                    }
-                    ast::BinaryOp::Divide => {
-                        self.emit(Instruction::Call(self.divide_fn_idx));
-                    }
-                    ast::BinaryOp::GreaterThan => {
-                        self.emit(Instruction::Call(self.greater_than_fn_idx));
-                    }
-                    ast::BinaryOp::LessThan => {
-                        self.emit(Instruction::Call(self.less_than_fn_idx));
-                    }
-                    ast::BinaryOp::Equal => {
-                        self.emit(Instruction::Call(self.equal_fn_idx));
-                    }
-                    ast::BinaryOp::NotEqual => {
-                        self.emit(Instruction::Call(self.not_equal_fn_idx));
-                    }
-                    _ => {
-                        return Err(CompilerError::CompilationError {
-                            message: format!("Binary operator not yet implemented: {:?}", op),
-                        });
-                    }
+            Expression::Assignment { left, right } => self.compile_assignment(left, right),
+            Expression::Binary { op, left, right } => self.compile_binary(op, left, right),
+            Expression::Unary { op, expr } => self.compile_unary(op, expr),
+            Expression::FunctionCall { name, args } => {
+                let name = name.trim_start_matches('\\');
+                if self.compile_builtin(&name.to_lowercase(), args)? {
+                    return Ok(());
                 }
+                self.compile_user_call(name, args)
+            }
+            Expression::MethodCall { object, method, args } => {
+                self.compile_expression(object)?;
+                self.emit_method_call(method, args)
+            }
+            Expression::NullsafeMethodCall { object, method, args } => {
+                let value = self.compile_to_local(object)?;
+                self.emit_if_not_null(value, |compiler| {
+                    compiler.emit(Instruction::LocalGet(value));
+                    compiler.emit_method_call(method, args)
+                })
+            }
+            Expression::PropertyAccess { object, property } => self.compile_property_read(object, property),
+            Expression::NullsafePropertyAccess { object, property } => {
+                let value = self.compile_to_local(object)?;
+                let name = self.string_literal(property);
+                self.emit_if_not_null(value, |compiler| {
+                    compiler.emit_all([
+                        Instruction::LocalGet(value),
+                        Instruction::I32Const(name as i32),
+                        Instruction::Call(compiler.h.prop_get),
+                    ]);
+                    Ok(())
+                })
+            }
+            Expression::StaticPropertyAccess { class, property } => {
+                let slot = self.static_prop(class, property)?.slot;
+                self.emit_load(VarLoc::Static(slot));
                 Ok(())
             }
-            ast::Expression::FunctionCall { name, args } => {
-                // Handle built-in functions
-                match name.as_str() {
-                    "is_null" => {
-                        if args.len() != 1 {
-                            return Err(CompilerError::CompilationError {
-                                message: "is_null() expects exactly 1 argument".to_string(),
-                            });
-                        }
-                        // Compile the argument
-                        self.compile_expression(args.into_iter().next().unwrap())?;
-                        // Call is_null function
-                        self.emit(Instruction::Call(self.is_null_fn_idx));
-                        Ok(())
-                    }
-                    "isset" => {
-                        if args.len() != 1 {
-                            return Err(CompilerError::CompilationError {
-                                message: "isset() expects exactly 1 argument".to_string(),
-                            });
-                        }
-                        // Compile the argument
-                        self.compile_expression(args.into_iter().next().unwrap())?;
-                        // Call isset function
-                        self.emit(Instruction::Call(self.isset_fn_idx));
-                        Ok(())
-                    }
-                    "empty" => {
-                        if args.len() != 1 {
-                            return Err(CompilerError::CompilationError {
-                                message: "empty() expects exactly 1 argument".to_string(),
-                            });
+            Expression::ArrayAccess { array, index } => self.compile_array_read(array, index),
+            Expression::Array(elements) => self.compile_array_literal(elements),
+            Expression::New { class, args } => self.compile_new(class, args),
+            Expression::Ternary { condition, then_expr, else_expr } => {
+                self.compile_condition(condition)?;
+                self.emit(Instruction::If(BlockType::Result(ValType::I32)));
+                self.compile_expression(then_expr)?;
+                self.emit(Instruction::Else);
+                self.compile_expression(else_expr)?;
+                self.emit(Instruction::End);
+                Ok(())
+            }
+            Expression::Cast { cast_type, expr } => self.compile_cast(cast_type, expr),
+            Expression::Coalesce { left, right } => {
+                let value = self.compile_to_local(left)?;
+                self.emit_all([
+                    Instruction::LocalGet(value),
+                    Instruction::I32Load(mem32(0)),
+                    Instruction::If(BlockType::Result(ValType::I32)),
+                    Instruction::LocalGet(value),
+                    Instruction::Else,
+                ]);
+                self.compile_expression(right)?;
+                self.emit(Instruction::End);
+                Ok(())
+            }
+            Expression::ClassConstant { class, name } => self.compile_class_constant(class, name),
+            Expression::StaticMethodCall { class, method, args } => self.compile_static_call(class, method, args),
+            Expression::Clone(expr) => {
+                self.compile_expression(expr)?;
+                self.emit(Instruction::Call(self.h.clone_object));
+                Ok(())
+            }
+            Expression::Invoke { callee, args } => {
+                self.compile_expression(callee)?;
+                self.emit_method_call("__invoke", args)
+            }
+            Expression::List(_) => Err("Cannot use list() outside of an assignment".to_string()),
+            // Includes are linked before compilation when compiling a project
+            Expression::Include { kind, .. } => Err(format!(
+                "{} needs to know which file it is in; compile the file as a project",
+                kind.keyword()
+            )),
+            Expression::MagicConstant(_) => Err("Magic constants are resolved before compilation".to_string()),
+        }
+    }
+
+    fn compile_literal(&mut self, literal: &Literal) -> Result<(), String> {
+        let value = match literal {
+            Literal::Integer(n) => self.int_literal(*n),
+            Literal::Float(f) => self.float_literal(*f),
+            Literal::String(s) => self.string_literal(s),
+            Literal::Boolean(true) => TRUE_VALUE,
+            Literal::Boolean(false) => FALSE_VALUE,
+            Literal::Null => NULL_VALUE,
+            Literal::InterpolatedString(parts) => {
+                let empty = self.string_literal("");
+                self.emit(Instruction::I32Const(empty as i32));
+                for part in parts {
+                    match part {
+                        InterpolatedPart::Text(text) => {
+                            let text = self.string_literal(text);
+                            self.emit(Instruction::I32Const(text as i32));
                         }
-                        // Compile the argument
-                        self.compile_expression(args.into_iter().next().unwrap())?;
-                        // Call empty function
-                        self.emit(Instruction::Call(self.empty_fn_idx));
-                        Ok(())
-                    }
-                    _ => {
-                        Err(CompilerError::CompilationError {
-                            message: format!("Unknown function: {}", name),
-                        })
+                        InterpolatedPart::Variable(name) => self.compile_expression(&Expression::Variable(name.clone()))?,
+                        InterpolatedPart::Expression(expr) => self.compile_expression(expr)?,
                     }
+                    self.emit(Instruction::Call(self.h.concat));
                 }
+                return Ok(());
             }
-            _ => {
-                Err(CompilerError::CompilationError {
-                    message: format!("Expression type not implemented: {:?}", expr)
-                })
+        };
+        self.emit(Instruction::I32Const(value as i32));
+        Ok(())
+    }
+
+    /// Compile an expression into a new local
+    pub(super) fn compile_to_local(&mut self, expr: &Expression) -> Result<u32, String> {
+        self.compile_expression(expr)?;
+        let local = self.allocate_local(ValType::I32);
+        self.emit(Instruction::LocalSet(local));
+        Ok(local)
+    }
+
+    /// The value of `then` unless the value in `value` is null, for `?->`
+    fn emit_if_not_null(&mut self, value: u32, then: impl FnOnce(&mut Self) -> Result<(), String>) -> Result<(), String> {
+        self.emit_all([
+            Instruction::LocalGet(value),
+            Instruction::I32Load(mem32(0)),
+            Instruction::If(BlockType::Result(ValType::I32)),
+        ]);
+        then(self)?;
+        self.emit_all([
+            Instruction::Else,
+            Instruction::I32Const(NULL_VALUE as i32),
+            Instruction::End,
+        ]);
+        Ok(())
+    }
+
+    /// Turn the i32 on the stack into a boolean value
+    pub(super) fn emit_bool_value(&mut self) {
+        self.emit_all([
+            Instruction::If(BlockType::Result(ValType::I32)),
+            Instruction::I32Const(TRUE_VALUE as i32),
+            Instruction::Else,
+            Instruction::I32Const(FALSE_VALUE as i32),
+            Instruction::End,
+        ]);
+    }
+
+    /// Compile a condition to an i32
+    pub(super) fn compile_condition(&mut self, expr: &Expression) -> Result<(), String> {
+        match expr {
+            Expression::Binary { op, left, right } if Self::is_comparison(op) => self.compile_comparison(op, left, right),
+            Expression::Binary { op: BinaryOp::And, left, right } => {
+                self.compile_condition(left)?;
+                self.emit(Instruction::If(BlockType::Result(ValType::I32)));
+                self.compile_condition(right)?;
+                self.emit_all([Instruction::Else, Instruction::I32Const(0), Instruction::End]);
+                Ok(())
+            }
+            Expression::Binary { op: BinaryOp::Or, left, right } => {
+                self.compile_condition(left)?;
+                self.emit_all([Instruction::If(BlockType::Result(ValType::I32)), Instruction::I32Const(1), Instruction::Else]);
+                self.compile_condition(right)?;
+                self.emit(Instruction::End);
+                Ok(())
+            }
+            Expression::Unary { op: UnaryOp::Not, expr } => {
+                self.compile_condition(expr)?;
+                self.emit(Instruction::I32Eqz);
+                Ok(())
+            }
+            Expression::Literal(Literal::Boolean(value)) => {
+                self.emit(Instruction::I32Const(*value as i32));
+                Ok(())
+            }
+            expr => {
+                self.compile_expression(expr)?;
+                self.emit(Instruction::Call(self.h.to_bool));
+                Ok(())
+            }
+        }
+    }
+
+    fn is_comparison(op: &BinaryOp) -> bool {
+        matches!(op,
+            BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Identical | BinaryOp::NotIdentical
+            | BinaryOp::LessThan | BinaryOp::LessThanOrEqual | BinaryOp::GreaterThan | BinaryOp::GreaterThanOrEqual)
+    }
+
+    /// A comparison as an i32. `>` and `>=` compare the operands swapped, as
+    /// PHP does, so values that cannot be ordered are never greater.
+    fn compile_comparison(&mut self, op: &BinaryOp, left: &Expression, right: &Expression) -> Result<(), String> {
+        let a = self.compile_to_local(left)?;
+        let b = self.compile_to_local(right)?;
+        let (first, second) = match op {
+            BinaryOp::GreaterThan | BinaryOp::GreaterThanOrEqual => (b, a),
+            _ => (a, b),
+        };
+        self.emit_all([Instruction::LocalGet(first), Instruction::LocalGet(second)]);
+        match op {
+            BinaryOp::Equal => self.emit(Instruction::Call(self.h.loose_eq)),
+            BinaryOp::NotEqual => self.emit_all([Instruction::Call(self.h.loose_eq), Instruction::I32Eqz]),
+            BinaryOp::Identical => self.emit(Instruction::Call(self.h.strict_eq)),
+            BinaryOp::NotIdentical => self.emit_all([Instruction::Call(self.h.strict_eq), Instruction::I32Eqz]),
+            BinaryOp::LessThan | BinaryOp::GreaterThan => {
+                self.emit_all([Instruction::Call(self.h.compare), Instruction::I32Const(0), Instruction::I32LtS]);
             }
+            _ => self.emit_all([Instruction::Call(self.h.compare), Instruction::I32Const(0), Instruction::I32LeS]),
         }
+        Ok(())
     }
-    
-    fn compile_literal(&mut self, lit: ast::Literal) -> Result<(), CompilerError> {
-        match lit {
-            ast::Literal::Integer(value) => {
-                // Allocate a new PhpValue
-                self.emit(Instruction::Call(self.alloc_value_fn_idx));
-                let local_idx = self.allocate_local(ValType::I32);
-                self.emit(Instruction::LocalSet(local_idx));
-                
-                // Set type tag to integer
-                self.emit(Instruction::LocalGet(local_idx));
-                self.emit(Instruction::I32Const(TYPE_INT as i32));
-                self.emit(Instruction::I32Store8(MemArg {
-                    offset: 0,
-                    align: 0,
-                    memory_index: 0,
-                }));
-                
-                // Store the integer value
-                self.emit(Instruction::LocalGet(local_idx));
-                self.emit(Instruction::I32Const(4)); // Offset for value
-                self.emit(Instruction::I32Add);
-                self.emit(Instruction::I64Const(value));
-                self.emit(Instruction::I64Store(MemArg {
-                    offset: 0,
-                    align: 3, // 8-byte alignment
-                    memory_index: 0,
-                }));
-                
-                // Leave pointer to new PhpValue on the stack
-                self.emit(Instruction::LocalGet(local_idx));
+
+    fn compile_binary(&mut self, op: &BinaryOp, left: &Expression, right: &Expression) -> Result<(), String> {
+        let arith = match op {
+            BinaryOp::Add => OP_ADD,
+            BinaryOp::Subtract => OP_SUB,
+            BinaryOp::Multiply => OP_MUL,
+            BinaryOp::Divide => OP_DIV,
+            BinaryOp::Modulo => OP_MOD,
+            BinaryOp::Concat => {
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+                self.emit(Instruction::Call(self.h.concat));
+                return Ok(());
+            }
+            _ => {
+                let expr = Expression::Binary { op: op.clone(), left: Box::new(left.clone()), right: Box::new(right.clone()) };
+                self.compile_condition(&expr)?;
+                self.emit_bool_value();
+                return Ok(());
+            }
+        };
+        self.compile_expression(left)?;
+        self.compile_expression(right)?;
+        self.emit_all([Instruction::I32Const(arith), Instruction::Call(self.h.arith)]);
+        Ok(())
+    }
+
+    fn compile_unary(&mut self, op: &UnaryOp, expr: &Expression) -> Result<(), String> {
+        match op {
+            UnaryOp::Not => {
+                self.compile_condition(expr)?;
+                self.emit(Instruction::I32Eqz);
+                self.emit_bool_value();
                 Ok(())
             }
-            ast::Literal::Float(value) => {
-                // Allocate a new PhpValue
-                self.emit(Instruction::Call(self.alloc_value_fn_idx));
-                let local_idx = self.allocate_local(ValType::I32);
-                self.emit(Instruction::LocalSet(local_idx));
-                
-                // Set type tag to float
-                self.emit(Instruction::LocalGet(local_idx));
-                self.emit(Instruction::I32Const(TYPE_FLOAT as i32));
-                self.emit(Instruction::I32Store8(MemArg {
-                    offset: 0,
-                    align: 0,
-                    memory_index: 0,
-                }));
-                
-                // Store the float value
-                self.emit(Instruction::LocalGet(local_idx));
-                self.emit(Instruction::I32Const(4)); // Offset for value
-                self.emit(Instruction::I32Add);
-                self.emit(Instruction::F64Const(value.into()));
-                self.emit(Instruction::F64Store(MemArg {
-                    offset: 0,
-                    align: 3, // 8-byte alignment
-                    memory_index: 0,
-                }));
-                
-                // Leave pointer to new PhpValue on the stack
-                self.emit(Instruction::LocalGet(local_idx));
+            UnaryOp::Negate => {
+                self.compile_expression(expr)?;
+                self.emit(Instruction::Call(self.h.neg));
                 Ok(())
             }
-            ast::Literal::String(value) => {
-                // Allocate a new PhpValue
-                self.emit(Instruction::Call(self.alloc_value_fn_idx));
-                let local_idx = self.allocate_local(ValType::I32);
-                self.emit(Instruction::LocalSet(local_idx));
-                
-                // Set type tag to string
-                self.emit(Instruction::LocalGet(local_idx));
-                self.emit(Instruction::I32Const(TYPE_STRING as i32));
-                self.emit(Instruction::I32Store8(MemArg {
-                    offset: 0,
-                    align: 0,
-                    memory_index: 0,
-                }));
-                
-                // Allocate memory for the string and store it
-                let s_ref = self.builder.add_string(&value);
-                self.emit(Instruction::I32Const(s_ref.start as i32));
-                self.emit(Instruction::I32Const(s_ref.len as i32));
-                self.emit(Instruction::Call(self.alloc_string_fn_idx));
-                
-                // Store the string pointer in the PhpValue
-                let str_ptr_local = self.allocate_local(ValType::I32);
-                self.emit(Instruction::LocalSet(str_ptr_local)); // string pointer
-                self.emit(Instruction::LocalGet(local_idx));
-                self.emit(Instruction::I32Const(8)); // Offset for string pointer
-                self.emit(Instruction::I32Add);
-                self.emit(Instruction::LocalGet(str_ptr_local));
-                self.emit(Instruction::I32Store(MemArg {
-                    offset: 0,
-                    align: 2,
-                    memory_index: 0,
-                }));
-                
-                // Leave pointer to new PhpValue on the stack
-                self.emit(Instruction::LocalGet(local_idx));
+            UnaryOp::PreIncrement => self.compile_incdec(expr, 1, true),
+            UnaryOp::PreDecrement => self.compile_incdec(expr, -1, true),
+            UnaryOp::PostIncrement => self.compile_incdec(expr, 1, false),
+            UnaryOp::PostDecrement => self.compile_incdec(expr, -1, false),
+        }
+    }
+
+    /// `++$x`, `$x--`, ...: leaves the new value (prefix) or the old one
+    fn compile_incdec(&mut self, target: &Expression, delta: i32, prefix: bool) -> Result<(), String> {
+        let (old, new) = (self.allocate_local(ValType::I32), self.allocate_local(ValType::I32));
+        if let Some((index, prop)) = self.this_prop_for(target) {
+            // Declared properties of $this are updated in place
+            self.emit_readonly_this_check(index, &prop);
+            let this = self.variable("this");
+            self.emit_load(this);
+            self.emit_load(this);
+            self.emit_all([
+                Instruction::I32Load(mem32(OBJ_PROPS + index as u64 * 4)),
+                Instruction::LocalTee(old),
+                Instruction::I32Const(delta),
+                Instruction::Call(self.h.incdec),
+            ]);
+            self.emit_property_check(&prop.class, &prop.name, &prop.declared);
+            self.emit_all([
+                Instruction::LocalTee(new),
+                Instruction::I32Store(mem32(OBJ_PROPS + index as u64 * 4)),
+            ]);
+        } else if let Expression::PropertyAccess { object, property } = target {
+            let object = self.compile_to_local(object)?;
+            let name = self.string_literal(property);
+            self.emit_all([
+                Instruction::LocalGet(object),
+                Instruction::I32Const(name as i32),
+                Instruction::LocalGet(object),
+                Instruction::I32Const(name as i32),
+                Instruction::Call(self.h.prop_get),
+                Instruction::LocalTee(old),
+                Instruction::I32Const(delta),
+                Instruction::Call(self.h.incdec),
+                Instruction::LocalTee(new),
+                Instruction::I32Const(self.strict_types as i32),
+                Instruction::I32Const(self.scope_id()),
+                Instruction::Call(self.h.prop_set),
+            ]);
+        } else {
+            let address = self.allocate_local(ValType::I32);
+            self.compile_place(target)?;
+            self.emit_all([
+                Instruction::LocalTee(address),
+                Instruction::LocalGet(address),
+                Instruction::I32Load(mem32(0)),
+                Instruction::LocalTee(old),
+                Instruction::I32Const(delta),
+                Instruction::Call(self.h.incdec),
+                Instruction::LocalTee(new),
+                Instruction::I32Store(mem32(0)),
+            ]);
+        }
+        self.emit(Instruction::LocalGet(if prefix { new } else { old }));
+        Ok(())
+    }
+
+    /// The declared property of `$this` an expression refers to, if any
+    fn this_prop_for(&self, expr: &Expression) -> Option<(usize, PropInfo)> {
+        match expr {
+            Expression::PropertyAccess { object, property } => self.this_prop(object, property),
+            _ => None,
+        }
+    }
+
+    fn compile_property_read(&mut self, object: &Expression, property: &str) -> Result<(), String> {
+        if let Some((index, _)) = self.this_prop(object, property) {
+            let this = self.variable("this");
+            self.emit_load(this);
+            self.emit(Instruction::I32Load(mem32(OBJ_PROPS + index as u64 * 4)));
+            return Ok(());
+        }
+        self.compile_expression(object)?;
+        let name = self.string_literal(property);
+        self.emit_all([Instruction::I32Const(name as i32), Instruction::Call(self.h.prop_get)]);
+        Ok(())
+    }
+
+    fn compile_array_read(&mut self, array: &Expression, index: &Expression) -> Result<(), String> {
+        if let Some(slot) = self.globals_slot(array, index) {
+            self.emit_load(VarLoc::Static(slot));
+            return Ok(());
+        }
+        if matches!(array, Expression::Variable(var) if var == GLOBALS_VAR) {
+            // `$GLOBALS[$name]`: find the variable at runtime
+            let address = self.allocate_local(ValType::I32);
+            self.compile_expression(index)?;
+            self.emit_all([
+                Instruction::Call(self.h.global_find),
+                Instruction::LocalTee(address),
+                Instruction::If(BlockType::Result(ValType::I32)),
+                Instruction::LocalGet(address),
+                Instruction::I32Load(mem32(0)),
+                Instruction::Else,
+                Instruction::I32Const(NULL_VALUE as i32),
+                Instruction::End,
+            ]);
+            return Ok(());
+        }
+        self.compile_expression(array)?;
+        self.compile_expression(index)?;
+        self.emit(Instruction::Call(self.h.arr_get));
+        Ok(())
+    }
+
+    pub(super) fn compile_array_literal(&mut self, elements: &[ArrayElement]) -> Result<(), String> {
+        let array = self.allocate_local(ValType::I32);
+        self.emit_all([
+            Instruction::I32Const(elements.len() as i32),
+            Instruction::Call(self.h.arr_new),
+            Instruction::LocalSet(array),
+        ]);
+        for element in elements {
+            match &element.key {
+                Some(key) => {
+                    let value = self.allocate_local(ValType::I32);
+                    self.emit(Instruction::LocalGet(array));
+                    self.compile_expression(key)?;
+                    self.compile_argument(&element.value)?;
+                    self.emit_all([
+                        Instruction::LocalSet(value),
+                        Instruction::Call(self.h.arr_slot),
+                        Instruction::LocalGet(value),
+                        Instruction::I32Store(mem32(0)),
+                    ]);
+                }
+                None => {
+                    self.emit(Instruction::LocalGet(array));
+                    self.compile_argument(&element.value)?;
+                    self.emit(Instruction::Call(self.h.arr_append));
+                }
+            }
+        }
+        self.emit(Instruction::LocalGet(array));
+        Ok(())
+    }
+
+    fn compile_cast(&mut self, cast_type: &Type, expr: &Expression) -> Result<(), String> {
+        self.compile_expression(expr)?;
+        match cast_type {
+            Type::Int => self.emit_all([Instruction::Call(self.h.to_int), Instruction::Call(self.h.new_int)]),
+            Type::Float => self.emit_all([Instruction::Call(self.h.to_float), Instruction::Call(self.h.new_float)]),
+            Type::String => self.emit(Instruction::Call(self.h.to_string)),
+            Type::Bool => {
+                self.emit(Instruction::Call(self.h.to_bool));
+                self.emit_bool_value();
+            }
+            Type::Array => {
+                // Arrays are copied, null becomes [], other values are wrapped
+                let value = self.allocate_local(ValType::I32);
+                let array = self.allocate_local(ValType::I32);
+                self.emit_all([
+                    Instruction::LocalTee(value),
+                    Instruction::I32Load(mem32(0)),
+                    Instruction::I32Const(TAG_ARRAY),
+                    Instruction::I32Eq,
+                    Instruction::If(BlockType::Result(ValType::I32)),
+                    Instruction::LocalGet(value),
+                    Instruction::Call(self.h.arr_copy),
+                    Instruction::Else,
+                    Instruction::I32Const(1),
+                    Instruction::Call(self.h.arr_new),
+                    Instruction::LocalSet(array),
+                    Instruction::LocalGet(value),
+                    Instruction::I32Load(mem32(0)),
+                    Instruction::If(BlockType::Empty),
+                    Instruction::LocalGet(array),
+                    Instruction::LocalGet(value),
+                    Instruction::Call(self.h.arr_append),
+                    Instruction::End,
+                    Instruction::LocalGet(array),
+                    Instruction::End,
+                ]);
+            }
+            _ => return Err(format!("Unsupported cast to {:?}", cast_type)),
+        }
+        Ok(())
+    }
+
+    /// Leave the address of the slot a variable, array element or property
+    /// is stored in, creating arrays on the way
+    pub(super) fn compile_place(&mut self, target: &Expression) -> Result<(), String> {
+        match target {
+            Expression::Variable(name) if name == GLOBALS_VAR => {
+                Err("Cannot re-assign $GLOBALS".to_string())
+            }
+            Expression::Variable(name) => {
+                let loc = self.variable(name);
+                self.emit_address(loc);
+                Ok(())
+            }
+            Expression::ArrayAccess { array, index } => {
+                if let Some(slot) = self.globals_slot(array, index) {
+                    self.emit(Instruction::I32Const(slot as i32));
+                    return Ok(());
+                }
+                if matches!(&**array, Expression::Variable(var) if var == GLOBALS_VAR) {
+                    return Err("Writing to $GLOBALS requires a literal variable name, e.g. $GLOBALS['name']".to_string());
+                }
+                self.compile_place(array)?;
+                self.emit(Instruction::Call(self.h.place_array));
+                self.compile_expression(index)?;
+                self.emit(Instruction::Call(self.h.arr_slot));
                 Ok(())
             }
-            ast::Literal::Boolean(value) => {
-                // Allocate a new PhpValue
-                self.emit(Instruction::Call(self.alloc_value_fn_idx));
-                let local_idx = self.allocate_local(ValType::I32);
-                self.emit(Instruction::LocalSet(local_idx));
-                
-                // Set type tag to bool
-                self.emit(Instruction::LocalGet(local_idx));
-                self.emit(Instruction::I32Const(TYPE_BOOL as i32));
-                self.emit(Instruction::I32Store8(MemArg {
-                    offset: 0,
-                    align: 0,
-                    memory_index: 0,
-                }));
-                
-                // Store the boolean value
-                self.emit(Instruction::LocalGet(local_idx));
-                self.emit(Instruction::I32Const(4)); // Offset for value
-                self.emit(Instruction::I32Add);
-                self.emit(Instruction::I32Const(if value { 1 } else { 0 }));
-                self.emit(Instruction::I32Store8(MemArg {
-                    offset: 0,
-                    align: 0,
-                    memory_index: 0,
-                }));
-                
-                // Leave pointer to new PhpValue on the stack
-                self.emit(Instruction::LocalGet(local_idx));
+            Expression::PropertyAccess { object, property } => {
+                self.compile_expression(object)?;
+                let name = self.string_literal(property);
+                self.emit_all([Instruction::I32Const(name as i32), Instruction::Call(self.h.prop_place)]);
                 Ok(())
             }
-            ast::Literal::Null => {
-                // Allocate a new PhpValue
-                self.emit(Instruction::Call(self.alloc_value_fn_idx));
-                let local_idx = self.allocate_local(ValType::I32);
-                self.emit(Instruction::LocalSet(local_idx));
-                
-                // Set type tag to null
-                self.emit(Instruction::LocalGet(local_idx));
-                self.emit(Instruction::I32Const(TYPE_NULL as i32));
-                self.emit(Instruction::I32Store8(MemArg {
-                    offset: 0,
-                    align: 0,
-                    memory_index: 0,
-                }));
-                
-                // Leave pointer to new PhpValue on the stack
-                self.emit(Instruction::LocalGet(local_idx));
+            Expression::StaticPropertyAccess { class, property } => {
+                let slot = self.static_prop(class, property)?.slot;
+                self.emit(Instruction::I32Const(slot as i32));
                 Ok(())
             }
+            _ => Err(format!("Cannot assign to {:?}", target)),
+        }
+    }
+
+    /// The address of a variable's slot
+    fn emit_address(&mut self, loc: VarLoc) {
+        match loc {
+            VarLoc::Frame(slot) => {
+                let fp = self.current_function.as_ref().unwrap().frame.as_ref().unwrap().fp;
+                self.emit_all([
+                    Instruction::LocalGet(fp),
+                    Instruction::I32Const(slot as i32 * 4),
+                    Instruction::I32Add,
+                ]);
+            }
+            VarLoc::Static(addr) => self.emit(Instruction::I32Const(addr as i32)),
+        }
+    }
+
+    fn compile_assignment(&mut self, left: &Expression, right: &Expression) -> Result<(), String> {
+        let value = self.allocate_local(ValType::I32);
+        self.compile_argument(right)?;
+        self.emit(Instruction::LocalSet(value));
+        if let Expression::Variable(name) = left {
+            if name != GLOBALS_VAR {
+                let loc = self.variable(name);
+                self.emit_release(loc);
+            }
+        }
+        self.assign_to(left, value)?;
+        self.emit(Instruction::LocalGet(value));
+        Ok(())
+    }
+
+    /// Store the value in `value`, which the target may keep, to a target
+    pub(super) fn assign_to(&mut self, target: &Expression, value: u32) -> Result<(), String> {
+        match target {
+            Expression::Variable(name) if name != GLOBALS_VAR => {
+                let loc = self.variable(name);
+                self.emit_store(loc, value);
+            }
+            Expression::PropertyAccess { object, property } => {
+                if let Some((index, prop)) = self.this_prop(object, property) {
+                    self.emit_readonly_this_check(index, &prop);
+                    let this = self.variable("this");
+                    self.emit_load(this);
+                    self.emit(Instruction::LocalGet(value));
+                    self.emit_property_check(&prop.class, &prop.name, &prop.declared);
+                    self.emit(Instruction::I32Store(mem32(OBJ_PROPS + index as u64 * 4)));
+                    return Ok(());
+                }
+                self.compile_expression(object)?;
+                let name = self.string_literal(property);
+                self.emit_all([
+                    Instruction::I32Const(name as i32),
+                    Instruction::LocalGet(value),
+                    Instruction::I32Const(self.strict_types as i32),
+                    Instruction::I32Const(self.scope_id()),
+                    Instruction::Call(self.h.prop_set),
+                ]);
+            }
+            Expression::StaticPropertyAccess { class, property } => {
+                let prop = self.static_prop(class, property)?;
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::LocalGet(value));
+                self.emit_property_check(&prop.class, property, &prop.declared);
+                self.emit(Instruction::I32Store(mem32(prop.slot as u64)));
+            }
+            Expression::List(elements) => self.destructure(elements, value)?,
+            Expression::Array(elements) => {
+                let elements: Vec<Option<ArrayElement>> = elements.iter().cloned().map(Some).collect();
+                self.destructure(&elements, value)?;
+            }
+            target => {
+                self.compile_place(target)?;
+                self.emit_all([Instruction::LocalGet(value), Instruction::I32Store(mem32(0))]);
+            }
+        }
+        Ok(())
+    }
+
+    /// `[$a, $b] = $array`, `['x' => $x] = $array`
+    fn destructure(&mut self, elements: &[Option<ArrayElement>], source: u32) -> Result<(), String> {
+        let mut position = 0;
+        for element in elements {
+            let Some(element) = element else {
+                position += 1;
+                continue;
+            };
+            let item = self.allocate_local(ValType::I32);
+            self.emit(Instruction::LocalGet(source));
+            match &element.key {
+                Some(key) => self.compile_expression(key)?,
+                None => {
+                    let key = self.int_literal(position);
+                    self.emit(Instruction::I32Const(key as i32));
+                    position += 1;
+                }
+            }
+            self.emit_all([
+                Instruction::Call(self.h.arr_get),
+                Instruction::Call(self.h.copy_value),
+                Instruction::LocalSet(item),
+            ]);
+            self.assign_to(&element.value, item)?;
         }
+        Ok(())
     }
 }
@@ -0,0 +1,171 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// User functions and methods. Every PHP callable is a wasm function taking
+// and returning values; methods take `$this` first. Callers pass the marker
+// for omitted arguments, which the callee replaces with the default.
+
+use super::core::*;
+use super::types::DeclaredType;
+use crate::compiler::types::{implicitly_nullable, type_name};
+use edge_php_parser::ast::*;
+use wasm_encoder::{Instruction, ValType};
+
+impl Compiler {
+    /// Give a function its index, so calls can be compiled before its body
+    pub(super) fn declare_function(&mut self, name: &str, params: &[Parameter]) -> Result<(), String> {
+        let key = name.to_lowercase();
+        if self.functions.contains_key(&key) {
+            return Err(format!("Cannot redeclare function {}()", name));
+        }
+        let idx = self.builder.reserve_function_index();
+        self.name_function(idx, name);
+        self.functions.insert(key, FunctionInfo { idx, params: params.to_vec() });
+        Ok(())
+    }
+
+    pub(super) fn compile_function(&mut self, name: &str, params: &[Parameter], return_type: Option<&Type>, body: &[Statement]) -> Result<(), String> {
+        let idx = self.functions[&name.to_lowercase()].idx;
+        self.compile_callable(name, idx, false, params, return_type, body)
+    }
+
+    /// Compile a function or method body. Methods get `$this` as their first
+    /// parameter.
+    pub(super) fn compile_callable(&mut self, scope: &str, idx: u32, has_this: bool, params: &[Parameter], return_type: Option<&Type>, body: &[Statement]) -> Result<(), String> {
+        let mut names: Vec<String> = params.iter().map(|param| param.name.clone()).collect();
+        if has_this {
+            names.insert(0, "this".to_string());
+        }
+        self.begin_function(scope, &names);
+        self.current_function.as_mut().unwrap().return_type = return_type.cloned();
+
+        let value = self.allocate_local(ValType::I32);
+        for (position, param) in params.iter().enumerate() {
+            let loc = self.variable(&param.name);
+            if let Some(default) = &param.default {
+                self.emit_load(loc);
+                self.emit_all([
+                    Instruction::I32Const(MISSING_VALUE as i32),
+                    Instruction::I32Eq,
+                    Instruction::If(wasm_encoder::BlockType::Empty),
+                ]);
+                self.compile_expression(default)?;
+                self.emit(Instruction::LocalSet(value));
+                self.emit_store(loc, value);
+                self.emit(Instruction::End);
+            }
+            let declared = match (&param.param_type, &param.default) {
+                (Some(ty), Some(Expression::Literal(Literal::Null))) => DeclaredType::of(&implicitly_nullable(ty)),
+                (Some(ty), _) => DeclaredType::of(ty),
+                (None, _) => None,
+            };
+            if let Some(type_name) = declared.as_ref().map(|declared| declared.name.clone()) {
+                let prefix = format!("{}(): Argument #{} (${}) must be of type {}, ", scope, position + 1, param.name, type_name);
+                self.emit_load(loc);
+                self.emit_check_type(&declared, &prefix, " given");
+                self.emit(Instruction::LocalSet(value));
+                self.emit_store(loc, value);
+            }
+        }
+        // Promoted constructor parameters are assigned before the body runs
+        for param in params.iter().filter(|param| param.promoted.is_some()) {
+            let this = self.variable("this");
+            let name = self.string_literal(&param.name);
+            let loc = self.variable(&param.name);
+            self.emit_load(this);
+            self.emit(Instruction::I32Const(name as i32));
+            self.emit_load(loc);
+            self.emit_all([
+                Instruction::Call(self.h.copy_value),
+                Instruction::I32Const(self.strict_types as i32),
+                Instruction::I32Const(self.scope_id()),
+                Instruction::Call(self.h.prop_set),
+            ]);
+        }
+
+        for stmt in body {
+            self.compile_statement(stmt)?;
+        }
+        // Only functions without a return type, or returning void, may fall off the end
+        let implicit_return = match return_type {
+            None | Some(Type::Void) => None,
+            Some(Type::Never) => Some(format!("{}(): never-returning function must not implicitly return", scope)),
+            Some(ty) => Some(format!("{}(): Return value must be of type {}, none returned", scope, type_name(ty))),
+        };
+        if let Some(message) = implicit_return {
+            let message = self.string_literal(&format!("PHP Fatal error:  Uncaught TypeError: {}\n", message));
+            self.emit_all([Instruction::I32Const(message as i32), Instruction::Call(self.h.fatal)]);
+        }
+        self.emit(Instruction::I32Const(NULL_VALUE as i32));
+        self.emit_return();
+        let ty = self.value_function_type(names.len());
+        self.finish_function(idx, ty);
+        Ok(())
+    }
+
+    /// An argument passed by value: arrays are copied unless the expression
+    /// made a new one
+    pub(super) fn compile_argument(&mut self, arg: &Expression) -> Result<(), String> {
+        self.compile_expression(arg)?;
+        if !Self::is_fresh(arg) {
+            self.emit(Instruction::Call(self.h.copy_value));
+        }
+        Ok(())
+    }
+
+    /// Whether an expression's value is new, so it can be stored without a copy
+    pub(super) fn is_fresh(expr: &Expression) -> bool {
+        matches!(expr,
+            Expression::Literal(_) | Expression::Array(_) | Expression::New { .. } | Expression::Binary { .. }
+            | Expression::FunctionCall { .. } | Expression::MethodCall { .. } | Expression::StaticMethodCall { .. }
+            | Expression::NullsafeMethodCall { .. } | Expression::Invoke { .. } | Expression::Clone(_)
+            | Expression::Cast { .. })
+    }
+
+    /// A call to a user function
+    pub(super) fn compile_user_call(&mut self, name: &str, args: &[Expression]) -> Result<(), String> {
+        let Some(info) = self.functions.get(&name.to_lowercase()) else {
+            return Err(format!("Call to undefined function {}()", name));
+        };
+        let (idx, count) = (info.idx, info.params.len());
+        for arg in &args[..args.len().min(count)] {
+            self.compile_argument(arg)?;
+        }
+        for _ in args.len()..count {
+            self.emit(Instruction::I32Const(MISSING_VALUE as i32));
+        }
+        self.emit(Instruction::Call(idx));
+        // Extra arguments are still evaluated
+        for arg in args.iter().skip(count) {
+            self.compile_expression(arg)?;
+            self.emit(Instruction::Drop);
+        }
+        Ok(())
+    }
+
+    /// `return`: the value is converted to the declared return type
+    pub(super) fn compile_return(&mut self, value: Option<&Expression>) -> Result<(), String> {
+        match value {
+            Some(expr) => {
+                self.compile_expression(expr)?;
+                // A function's own variables die with it; anything else is shared
+                let owned = match expr {
+                    Expression::Variable(name) => matches!(self.current_function.as_ref().unwrap().vars.get(name), Some(VarLoc::Frame(_))),
+                    expr => Self::is_fresh(expr),
+                };
+                if !owned {
+                    self.emit(Instruction::Call(self.h.copy_value));
+                }
+            }
+            None => self.emit(Instruction::I32Const(NULL_VALUE as i32)),
+        }
+        let func = self.current_function.as_ref().unwrap();
+        if let (Some(return_type), Some(_)) = (&func.return_type, &func.frame) {
+            let prefix = format!("{}(): Return value must be of type {}, ", func.scope, type_name(return_type));
+            let declared = DeclaredType::of(return_type);
+            self.emit_check_type(&declared, &prefix, " returned");
+        }
+        self.emit_return();
+        Ok(())
+    }
+}
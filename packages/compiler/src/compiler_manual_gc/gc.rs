@@ -1,185 +1,326 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-use super::core::*;
-use wasm_encoder::*;
+// Mark-sweep collector. Collections only happen at safepoints, at the start
+// of a statement, when the root stack holds just the frames of the active
+// functions: their variables and the hidden slots of loops in progress.
+// Static slots (top-level variables, `static` variables, static properties,
+// enum cases) are listed in a root table. Values below the heap are static
+// data and are never marked; array entries and buckets are marked through
+// the array that owns them.
+//
+// Marking does not recurse: arrays and objects wait on a mark stack in the
+// free part of the root stack. When it fills up, the values that did not fit
+// stay marked but unscanned and the heap is walked for them afterwards.
 
-// PhpValue structure (16 bytes):
-// Offset 0: Type tag (1 byte)
-// Offset 1-3: Reference count (3 bytes, little-endian u32 with high byte always 0)
-// Offset 4-11: Value data (8 bytes) - for int/float/bool
-// Offset 8-15: String/Array pointer (8 bytes) - overlaps with value data for string/array types
+use super::core::*;
+use wasm_encoder::Instruction::*;
+use wasm_encoder::{BlockType, ValType};
 
 impl Compiler {
     pub(super) fn add_gc_functions(&mut self) {
-        self.add_incref_function();
-        self.add_decref_function();
-        self.add_free_value_function();
-        self.add_free_string_function();
+        self.add_gc_mark_function();
+        self.add_gc_scan_function();
+        self.add_gc_drain_function();
+        self.add_gc_sweep_function();
+        self.add_gc_mark_roots_function();
+        self.add_gc_rescan_function();
+        self.add_gc_collect_function();
+        self.add_gc_release_function();
+        self.add_gc_destruct_function();
+        self.add_safepoint_function();
     }
-    
-    fn add_incref_function(&mut self) {
-        // incref(value_ptr: i32) -> void
-        // Increments reference count of a PhpValue
-        let mut incref_body = vec![];
-        let mut incref_locals = vec![];
-        incref_locals.push((1, ValType::I32)); // refcount
-        
-        // Check if pointer is null
-        incref_body.push(Instruction::LocalGet(0));
-        incref_body.push(Instruction::I32Eqz);
-        incref_body.push(Instruction::If(BlockType::Empty));
-        incref_body.push(Instruction::Return);
-        incref_body.push(Instruction::End);
-        
-        // Load current refcount (3 bytes at offset 1)
-        incref_body.push(Instruction::LocalGet(0));
-        incref_body.push(Instruction::I32Const(1));
-        incref_body.push(Instruction::I32Add);
-        incref_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        incref_body.push(Instruction::I32Const(0x00FFFFFF));
-        incref_body.push(Instruction::I32And); // Mask to 3 bytes
-        incref_body.push(Instruction::LocalSet(1));
-        
-        // Increment refcount
-        incref_body.push(Instruction::LocalGet(1));
-        incref_body.push(Instruction::I32Const(1));
-        incref_body.push(Instruction::I32Add);
-        incref_body.push(Instruction::LocalSet(1));
-        
-        // Store back (preserving the high byte)
-        incref_body.push(Instruction::LocalGet(0));
-        incref_body.push(Instruction::I32Const(1));
-        incref_body.push(Instruction::I32Add);
-        incref_body.push(Instruction::LocalGet(1));
-        incref_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        let incref_type_idx = self.builder.add_type(vec![ValType::I32], vec![]);
-        self.builder.set_function_at_index(self.incref_fn_idx, incref_type_idx, incref_locals, incref_body);
+
+    fn add_gc_mark_function(&mut self) {
+        // gc_mark(value): mark a block; arrays and objects go on the mark stack
+        // to have their contents marked, or set the overflow flag when it is full
+        let ty = self.builder.add_type(vec![ValType::I32], vec![]);
+        let (value, tag) = (0, 1);
+        let body = vec![
+            LocalGet(value), GlobalGet(self.g.heap_start), I32LtU, BrIf(0),
+            LocalGet(value), I32Const(HEADER_SIZE), I32Sub, I32Load8U(mem8(HEADER_MARK)), BrIf(0),
+            LocalGet(value), I32Const(HEADER_SIZE), I32Sub, I32Const(1), I32Store8(mem8(HEADER_MARK)),
+            // Entry and bucket blocks are also rooted while they are new
+            LocalGet(value), I32Const(HEADER_SIZE), I32Sub, I32Load8U(mem8(HEADER_KIND)), I32Const(KIND_VALUE), I32Ne, BrIf(0),
+            LocalGet(value), I32Load(mem32(0)), LocalTee(tag), I32Const(TAG_ARRAY), I32Ne,
+            LocalGet(tag), I32Const(TAG_OBJECT), I32Ne, I32And, BrIf(0),
+            GlobalGet(self.g.mark_top), GlobalGet(self.g.stack_limit), I32GeU,
+            If(BlockType::Empty), I32Const(1), GlobalSet(self.g.mark_overflow), Return, End,
+            GlobalGet(self.g.mark_top), LocalGet(value), I32Store(mem32(0)),
+            GlobalGet(self.g.mark_top), I32Const(4), I32Add, GlobalSet(self.g.mark_top),
+        ];
+        self.builder.set_function_at_index(self.h.gc_mark, ty, vec![(1, ValType::I32)], body);
     }
-    
-    fn add_decref_function(&mut self) {
-        // decref(value_ptr: i32) -> void
-        // Decrements reference count and frees if it reaches 0
-        let mut decref_body = vec![];
-        let mut decref_locals = vec![];
-        decref_locals.push((1, ValType::I32)); // refcount
-        decref_locals.push((1, ValType::I32)); // type
-        
-        // Check if pointer is null
-        decref_body.push(Instruction::LocalGet(0));
-        decref_body.push(Instruction::I32Eqz);
-        decref_body.push(Instruction::If(BlockType::Empty));
-        decref_body.push(Instruction::Return);
-        decref_body.push(Instruction::End);
-        
-        // Load current refcount
-        decref_body.push(Instruction::LocalGet(0));
-        decref_body.push(Instruction::I32Const(1));
-        decref_body.push(Instruction::I32Add);
-        decref_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        decref_body.push(Instruction::I32Const(0x00FFFFFF));
-        decref_body.push(Instruction::I32And); // Mask to 3 bytes
-        decref_body.push(Instruction::LocalSet(1));
-        
-        // Check if already 0 (shouldn't happen but be safe)
-        decref_body.push(Instruction::LocalGet(1));
-        decref_body.push(Instruction::I32Eqz);
-        decref_body.push(Instruction::If(BlockType::Empty));
-        decref_body.push(Instruction::Return);
-        decref_body.push(Instruction::End);
-        
-        // Decrement refcount
-        decref_body.push(Instruction::LocalGet(1));
-        decref_body.push(Instruction::I32Const(1));
-        decref_body.push(Instruction::I32Sub);
-        decref_body.push(Instruction::LocalSet(1));
-        
-        // Store back
-        decref_body.push(Instruction::LocalGet(0));
-        decref_body.push(Instruction::I32Const(1));
-        decref_body.push(Instruction::I32Add);
-        decref_body.push(Instruction::LocalGet(1));
-        decref_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        // If refcount is now 0, free the value
-        decref_body.push(Instruction::LocalGet(1));
-        decref_body.push(Instruction::I32Eqz);
-        decref_body.push(Instruction::If(BlockType::Empty));
-        
-        // Call free_value
-        decref_body.push(Instruction::LocalGet(0));
-        decref_body.push(Instruction::Call(self.free_value_fn_idx));
-        
-        decref_body.push(Instruction::End);
-        
-        let decref_type_idx = self.builder.add_type(vec![ValType::I32], vec![]);
-        self.builder.set_function_at_index(self.decref_fn_idx, decref_type_idx, decref_locals, decref_body);
+
+    fn add_gc_scan_function(&mut self) {
+        // gc_scan(value): mark what an array or object holds
+        let ty = self.builder.add_type(vec![ValType::I32], vec![]);
+        let (value, i, count, entries, entry, key) = (0, 1, 2, 3, 4, 5);
+        let heap_start = self.g.heap_start;
+        let mark_block = |ptr: u32| vec![
+            LocalGet(ptr), GlobalGet(heap_start), I32GeU,
+            If(BlockType::Empty),
+                LocalGet(ptr), I32Const(HEADER_SIZE), I32Sub, I32Const(1), I32Store8(mem8(HEADER_MARK)),
+            End,
+        ];
+        let mut body = vec![
+            LocalGet(value), I32Load(mem32(0)), I32Const(TAG_ARRAY), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), I32Load(mem32(ARR_ENTRIES)), LocalSet(entries),
+        ];
+        body.extend(mark_block(entries));
+        body.extend([LocalGet(value), I32Load(mem32(ARR_BUCKETS)), LocalSet(entry)]);
+        body.extend(mark_block(entry));
+        body.extend([
+                LocalGet(value), I32Load(mem32(ARR_USED)), LocalSet(count),
+                Block(BlockType::Empty),
+                Loop(BlockType::Empty),
+                    LocalGet(i), LocalGet(count), I32GeU, BrIf(1),
+                    LocalGet(entries), LocalGet(i), I32Const(ENTRY_SIZE), I32Mul, I32Add, LocalTee(entry),
+                    I32Load(mem32(ENTRY_KEY_STR)), LocalTee(key), I32Const(-1), I32Ne,
+                    If(BlockType::Empty),
+                        LocalGet(key), Call(self.h.gc_mark),
+                        LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), Call(self.h.gc_mark),
+                    End,
+                    LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                    Br(0),
+                End,
+                End,
+                Return,
+            End,
+            LocalGet(value), I32Load(mem32(0)), I32Const(TAG_OBJECT), I32Ne, BrIf(0),
+
+            LocalGet(value), I32Load(mem32(OBJ_DYNAMIC)), Call(self.h.gc_mark),
+            LocalGet(value), I32Load(mem32(OBJ_PROP_COUNT)), LocalSet(count),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(count), I32GeU, BrIf(1),
+                LocalGet(value), LocalGet(i), I32Const(2), I32Shl, I32Add, I32Load(mem32(OBJ_PROPS)),
+                Call(self.h.gc_mark),
+                LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                Br(0),
+            End,
+            End,
+        ]);
+        self.builder.set_function_at_index(self.h.gc_scan, ty, vec![(5, ValType::I32)], body);
     }
-    
-    fn add_free_value_function(&mut self) {
-        // free_value(value_ptr: i32) -> void
-        // Frees a PhpValue and any associated data
-        let mut free_body = vec![];
-        let mut free_locals = vec![];
-        free_locals.push((1, ValType::I32)); // type
-        free_locals.push((1, ValType::I32)); // string_ptr
-        
-        // Check if pointer is null
-        free_body.push(Instruction::LocalGet(0));
-        free_body.push(Instruction::I32Eqz);
-        free_body.push(Instruction::If(BlockType::Empty));
-        free_body.push(Instruction::Return);
-        free_body.push(Instruction::End);
-        
-        // Load type
-        free_body.push(Instruction::LocalGet(0));
-        free_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        free_body.push(Instruction::LocalSet(1));
-        
-        // If type is string, free the string data
-        free_body.push(Instruction::LocalGet(1));
-        free_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        free_body.push(Instruction::I32Eq);
-        free_body.push(Instruction::If(BlockType::Empty));
-        
-        // Load string pointer
-        free_body.push(Instruction::LocalGet(0));
-        free_body.push(Instruction::I32Const(8));
-        free_body.push(Instruction::I32Add);
-        free_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        free_body.push(Instruction::LocalSet(2));
-        
-        // Free string if not null
-        free_body.push(Instruction::LocalGet(2));
-        free_body.push(Instruction::I32Eqz);
-        free_body.push(Instruction::If(BlockType::Empty));
-        free_body.push(Instruction::Else);
-        free_body.push(Instruction::LocalGet(2));
-        free_body.push(Instruction::Call(self.free_string_fn_idx));
-        free_body.push(Instruction::End);
-        
-        free_body.push(Instruction::End);
-        
-        // TODO: When we implement arrays, add array cleanup here
-        
-        // For now, we don't actually free the PhpValue memory itself
-        // In a real implementation, we'd add it to a free list
-        
-        let free_type_idx = self.builder.add_type(vec![ValType::I32], vec![]);
-        self.builder.set_function_at_index(self.free_value_fn_idx, free_type_idx, free_locals, free_body);
+
+    fn add_gc_drain_function(&mut self) {
+        // gc_drain(): scan the values on the mark stack until it is empty
+        let ty = self.builder.add_type(vec![], vec![]);
+        let body = vec![
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                GlobalGet(self.g.mark_top), GlobalGet(self.g.sp), I32LeU, BrIf(1),
+                GlobalGet(self.g.mark_top), I32Const(4), I32Sub, GlobalSet(self.g.mark_top),
+                GlobalGet(self.g.mark_top), I32Load(mem32(0)), Call(self.h.gc_scan),
+                Br(0),
+            End,
+            End,
+        ];
+        self.builder.set_function_at_index(self.h.gc_drain, ty, vec![], body);
+    }
+
+    fn add_gc_sweep_function(&mut self) {
+        // Free every unmarked block, merging neighbours, and set the next threshold
+        let ty = self.builder.add_type(vec![], vec![]);
+        let (ptr, size, run, live) = (0, 1, 2, 3);
+        let body = vec![
+            I32Const(self.free_lists as i32), I32Const(0), I32Const(FREE_LIST_COUNT as i32 * 4), MemoryFill(0),
+            GlobalGet(self.g.heap_start), LocalSet(ptr),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(ptr), GlobalGet(self.g.heap_top), I32GeU, BrIf(1),
+                LocalGet(ptr), I32Load(mem32(0)), LocalSet(size),
+                LocalGet(ptr), I32Load8U(mem8(HEADER_KIND)), I32Const(KIND_FREE), I32Ne,
+                LocalGet(ptr), I32Load8U(mem8(HEADER_MARK)), I32And,
+                If(BlockType::Empty),
+                    LocalGet(ptr), I32Const(0), I32Store8(mem8(HEADER_MARK)),
+                    LocalGet(live), LocalGet(size), I32Add, LocalSet(live),
+                    LocalGet(run),
+                    If(BlockType::Empty),
+                        LocalGet(run), LocalGet(ptr), LocalGet(run), I32Sub, Call(self.h.free_block),
+                        I32Const(0), LocalSet(run),
+                    End,
+                Else,
+                    LocalGet(run), I32Eqz,
+                    If(BlockType::Empty), LocalGet(ptr), LocalSet(run), End,
+                End,
+                LocalGet(ptr), LocalGet(size), I32Add, LocalSet(ptr),
+                Br(0),
+            End,
+            End,
+            // A free run at the end goes back to the bump pointer
+            LocalGet(run),
+            If(BlockType::Empty), LocalGet(run), GlobalSet(self.g.heap_top), End,
+            I32Const(0), GlobalSet(self.g.allocated),
+            LocalGet(live), I32Const(MIN_GC_THRESHOLD), LocalGet(live), I32Const(MIN_GC_THRESHOLD), I32GtU, Select,
+            GlobalSet(self.g.threshold),
+        ];
+        self.builder.set_function_at_index(self.h.gc_sweep, ty, vec![(4, ValType::I32)], body);
+    }
+
+    fn add_gc_mark_roots_function(&mut self) {
+        // gc_mark_roots(): mark the root stack and the static root slots. The
+        // root table lists the slots; no slots are added once the runtime is
+        // being generated
+        let mut table = Vec::with_capacity(self.root_slots.len() * 4);
+        for slot in &self.root_slots {
+            table.extend_from_slice(&slot.to_le_bytes());
+        }
+        let root_table = self.static_alloc(table.len() as u32);
+        self.static_write(root_table, &table);
+
+        let ty = self.builder.add_type(vec![], vec![]);
+        let ptr = 0;
+        let body = vec![
+            GlobalGet(self.g.sp), GlobalSet(self.g.mark_top),
+            I32Const(0), GlobalSet(self.g.mark_overflow),
+            GlobalGet(self.g.stack_base), LocalSet(ptr),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(ptr), GlobalGet(self.g.sp), I32GeU, BrIf(1),
+                LocalGet(ptr), I32Load(mem32(0)), Call(self.h.gc_mark),
+                LocalGet(ptr), I32Const(4), I32Add, LocalSet(ptr),
+                Br(0),
+            End,
+            End,
+            I32Const(root_table as i32), LocalSet(ptr),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(ptr), I32Const((root_table + table.len() as u32) as i32), I32GeU, BrIf(1),
+                LocalGet(ptr), I32Load(mem32(0)), I32Load(mem32(0)), Call(self.h.gc_mark),
+                LocalGet(ptr), I32Const(4), I32Add, LocalSet(ptr),
+                Call(self.h.gc_drain),
+                Br(0),
+            End,
+            End,
+            Call(self.h.gc_drain),
+        ];
+        self.builder.set_function_at_index(self.h.gc_mark_roots, ty, vec![(1, ValType::I32)], body);
     }
-    
-    fn add_free_string_function(&mut self) {
-        // free_string(string_ptr: i32) -> void
-        // For now, we don't actually free string memory
-        // In a real implementation, we'd add it to a free list
-        let mut free_string_body = vec![];
-        
-        // Just return for now
-        free_string_body.push(Instruction::Return);
-        
-        let free_string_type_idx = self.builder.add_type(vec![ValType::I32], vec![]);
-        self.builder.set_function_at_index(self.free_string_fn_idx, free_string_type_idx, vec![], free_string_body);
+
+    fn add_gc_rescan_function(&mut self) {
+        // gc_rescan(): values that did not fit on the mark stack are marked but
+        // not scanned; scan every marked array and object again until none is left
+        let ty = self.builder.add_type(vec![], vec![]);
+        let (ptr, size) = (0, 1);
+        let body = vec![
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                GlobalGet(self.g.mark_overflow), I32Eqz, BrIf(1),
+                I32Const(0), GlobalSet(self.g.mark_overflow),
+                GlobalGet(self.g.heap_start), LocalSet(ptr),
+                Block(BlockType::Empty),
+                Loop(BlockType::Empty),
+                    LocalGet(ptr), GlobalGet(self.g.heap_top), I32GeU, BrIf(1),
+                    LocalGet(ptr), I32Load(mem32(0)), LocalSet(size),
+                    LocalGet(ptr), I32Load8U(mem8(HEADER_MARK)),
+                    LocalGet(ptr), I32Load8U(mem8(HEADER_KIND)), I32Const(KIND_VALUE), I32Eq, I32And,
+                    If(BlockType::Empty),
+                        LocalGet(ptr), I32Const(HEADER_SIZE), I32Add, Call(self.h.gc_scan),
+                        Call(self.h.gc_drain),
+                    End,
+                    LocalGet(ptr), LocalGet(size), I32Add, LocalSet(ptr),
+                    Br(0),
+                End,
+                End,
+                Br(0),
+            End,
+            End,
+        ];
+        self.builder.set_function_at_index(self.h.gc_rescan, ty, vec![(2, ValType::I32)], body);
+    }
+
+    fn add_gc_collect_function(&mut self) {
+        let ty = self.builder.add_type(vec![], vec![]);
+        let body = vec![Call(self.h.gc_mark_roots), Call(self.h.gc_rescan), Call(self.h.gc_sweep)];
+        self.builder.set_function_at_index(self.h.gc_collect, ty, vec![], body);
+    }
+
+    fn add_gc_release_function(&mut self) {
+        // gc_release(value): note that a variable let go of an object whose
+        // class has a destructor
+        let ty = self.builder.add_type(vec![ValType::I32], vec![]);
+        let value = 0;
+        let mut body = vec![LocalGet(value), I32Load(mem32(0)), I32Const(TAG_OBJECT), I32Ne, BrIf(0)];
+        let mut ids: Vec<u32> = self.classes.values()
+            .filter(|info| info.methods.contains_key("__destruct"))
+            .map(|info| info.id)
+            .collect();
+        ids.sort();
+        for id in ids {
+            body.extend([
+                LocalGet(value), I32Load(mem32(OBJ_CLASS)), I32Const(id as i32), I32Eq,
+                If(BlockType::Empty), I32Const(1), GlobalSet(self.g.released), End,
+            ]);
+        }
+        self.builder.set_function_at_index(self.h.gc_release, ty, vec![], body);
+    }
+
+    fn add_gc_destruct_function(&mut self) {
+        // gc_destruct(): destroy the objects with a destructor nothing refers
+        // to. A collection that leaves the registry out finds them unmarked;
+        // they are moved to their own array, kept alive for their destructors
+        // and dropped after
+        let ty = self.builder.add_type(vec![], vec![]);
+        let Some((registry_slot, doomed_slot)) = self.destructors else {
+            self.builder.set_function_at_index(self.h.gc_destruct, ty, vec![], vec![]);
+            return;
+        };
+        let (dispatch, arity) = self.dispatcher("__destruct");
+        let (registry, live, doomed, index, entry, object) = (0, 1, 2, 3, 4, 5);
+        let slot = |addr: u32| mem32(addr as u64);
+
+        let mut body = vec![
+            GlobalGet(self.g.destructing), BrIf(0),
+            I32Const(0), GlobalSet(self.g.released),
+            I32Const(0), I32Load(slot(registry_slot)), LocalTee(registry),
+            I32Load(mem32(0)), I32Const(TAG_ARRAY), I32Ne, BrIf(0),
+            I32Const(0), I32Const(NULL_VALUE as i32), I32Store(slot(registry_slot)),
+            Call(self.h.gc_mark_roots), Call(self.h.gc_rescan),
+            I32Const(0), LocalGet(registry), I32Store(slot(registry_slot)),
+            I32Const(0), Call(self.h.arr_new), LocalSet(live),
+            I32Const(0), Call(self.h.arr_new), LocalSet(doomed),
+        ];
+        body.extend(super::arrays::for_each_entry(registry, index, entry, vec![
+            LocalGet(entry), I32Load(mem32(ENTRY_VALUE)), LocalSet(object),
+            LocalGet(live), LocalGet(doomed),
+            LocalGet(object), I32Const(HEADER_SIZE), I32Sub, I32Load8U(mem8(HEADER_MARK)), Select,
+            LocalGet(object), Call(self.h.arr_append),
+        ]));
+        // The arrays were allocated after marking: mark them and what they hold,
+        // with the mark stack above the root stack entries the allocations added
+        body.extend([
+            I32Const(0), LocalGet(live), I32Store(slot(registry_slot)),
+            I32Const(0), LocalGet(doomed), I32Store(slot(doomed_slot)),
+            GlobalGet(self.g.sp), GlobalSet(self.g.mark_top),
+            LocalGet(live), Call(self.h.gc_mark),
+            LocalGet(doomed), Call(self.h.gc_mark),
+            Call(self.h.gc_drain), Call(self.h.gc_rescan), Call(self.h.gc_sweep),
+            // Destructors that let go of more objects are handled at a later safepoint
+            I32Const(1), GlobalSet(self.g.destructing),
+        ]);
+        let mut call = vec![LocalGet(entry), I32Load(mem32(ENTRY_VALUE))];
+        call.extend((0..arity).map(|_| I32Const(MISSING_VALUE as i32)));
+        call.extend([Call(dispatch), Drop]);
+        body.extend(super::arrays::for_each_entry(doomed, index, entry, call));
+        body.extend([
+            I32Const(0), I32Const(NULL_VALUE as i32), I32Store(slot(doomed_slot)),
+            I32Const(0), GlobalSet(self.g.destructing),
+        ]);
+        self.builder.set_function_at_index(self.h.gc_destruct, ty, vec![(6, ValType::I32)], body);
+    }
+
+    fn add_safepoint_function(&mut self) {
+        let ty = self.builder.add_type(vec![], vec![]);
+        let mut body = vec![
+            GlobalGet(self.g.allocated), GlobalGet(self.g.threshold), I32GtU,
+            If(BlockType::Empty), Call(self.h.gc_collect), End,
+        ];
+        if self.destructors.is_some() {
+            body.extend([GlobalGet(self.g.released), If(BlockType::Empty), Call(self.h.gc_destruct), End]);
+        }
+        self.builder.set_function_at_index(self.h.safepoint, ty, vec![], body);
     }
 }
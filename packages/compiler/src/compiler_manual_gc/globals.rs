@@ -0,0 +1,129 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Variable scopes: `global`, function `static` variables and `$GLOBALS`.
+// Top-level variables live in static slots, so `global $x` and
+// `$GLOBALS['x']` refer to the slot itself; `$GLOBALS` with a computed key
+// looks the name up in a table of every top-level variable.
+
+use super::core::*;
+use edge_php_parser::ast::*;
+use wasm_encoder::Instruction::*;
+use wasm_encoder::{BlockType, Instruction, ValType};
+
+pub(super) const GLOBALS_VAR: &str = "GLOBALS";
+
+impl Compiler {
+    /// `global $a, $b;`
+    pub(super) fn compile_global(&mut self, names: &[String]) {
+        for name in names {
+            let slot = self.global_slot(name);
+            self.current_function.as_mut().unwrap().vars.insert(name.clone(), VarLoc::Static(slot));
+        }
+    }
+
+    /// `static $a = 1;`: the variable keeps its value between calls and is
+    /// initialized the first time the statement runs
+    pub(super) fn compile_static_vars(&mut self, vars: &[StaticVariable]) -> Result<(), String> {
+        for var in vars {
+            let scope = self.current_function.as_ref().unwrap().scope.clone();
+            let key = (scope, var.name.clone());
+            let (slot, flag) = match self.static_vars.get(&key) {
+                Some(&slots) => slots,
+                None => {
+                    let slots = (self.root_slot(), self.static_alloc(4));
+                    self.static_vars.insert(key, slots);
+                    slots
+                }
+            };
+            self.current_function.as_mut().unwrap().vars.insert(var.name.clone(), VarLoc::Static(slot));
+            self.emit_all([
+                Instruction::I32Const(0),
+                Instruction::I32Load(mem32(flag as u64)),
+                Instruction::I32Eqz,
+                Instruction::If(BlockType::Empty),
+                Instruction::I32Const(0),
+                Instruction::I32Const(1),
+                Instruction::I32Store(mem32(flag as u64)),
+            ]);
+            if let Some(default) = &var.default {
+                let value = self.allocate_local(ValType::I32);
+                self.compile_expression(default)?;
+                self.emit(Instruction::LocalSet(value));
+                self.emit_store(VarLoc::Static(slot), value);
+            }
+            self.emit(Instruction::End);
+        }
+        Ok(())
+    }
+
+    /// The slot of `$GLOBALS['name']`, when the key is a literal
+    pub(super) fn globals_slot(&mut self, array: &Expression, index: &Expression) -> Option<u32> {
+        match (array, index) {
+            (Expression::Variable(var), Expression::Literal(Literal::String(name))) if var == GLOBALS_VAR => {
+                Some(self.global_slot(name))
+            }
+            _ => None,
+        }
+    }
+
+    pub(super) fn add_global_functions(&mut self) {
+        let mut globals = self.global_vars.clone();
+        globals.sort();
+        let mut table = Vec::with_capacity(globals.len() * 8);
+        for (name, slot) in &globals {
+            table.extend_from_slice(&self.string_literal(name).to_le_bytes());
+            table.extend_from_slice(&slot.to_le_bytes());
+        }
+        let table_start = self.static_alloc(table.len() as u32);
+        self.static_write(table_start, &table);
+        let table_end = table_start + table.len() as u32;
+        self.add_global_find_function(table_start, table_end);
+        self.add_globals_array_function(table_start, table_end);
+    }
+
+    /// Loop over the global table with the entry address in `entry`
+    fn for_each_global(start: u32, end: u32, entry: u32, body: Vec<Instruction<'static>>) -> Vec<Instruction<'static>> {
+        let mut code = vec![
+            I32Const(start as i32), LocalSet(entry),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(entry), I32Const(end as i32), I32GeU, BrIf(1),
+        ];
+        code.extend(body);
+        code.extend([
+                LocalGet(entry), I32Const(8), I32Add, LocalSet(entry),
+                Br(0),
+            End,
+            End,
+        ]);
+        code
+    }
+
+    fn add_global_find_function(&mut self, start: u32, end: u32) {
+        // global_find(name) -> address of the top-level variable's slot, or 0
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (name, entry) = (0, 1);
+        let mut body = vec![LocalGet(name), Call(self.h.to_string), LocalSet(name)];
+        body.extend(Self::for_each_global(start, end, entry, vec![
+            LocalGet(entry), I32Load(mem32(0)), LocalGet(name), Call(self.h.str_eq),
+            If(BlockType::Empty), LocalGet(entry), I32Load(mem32(4)), Return, End,
+        ]));
+        body.push(I32Const(0));
+        self.builder.set_function_at_index(self.h.global_find, ty, vec![(1, ValType::I32)], body);
+    }
+
+    fn add_globals_array_function(&mut self, start: u32, end: u32) {
+        // globals_array() -> a copy of every top-level variable, by name
+        let ty = self.builder.add_type(vec![], vec![ValType::I32]);
+        let (result, entry) = (0, 1);
+        let mut body = vec![I32Const(((end - start) / 8) as i32), Call(self.h.arr_new), LocalSet(result)];
+        body.extend(Self::for_each_global(start, end, entry, vec![
+            LocalGet(result), LocalGet(entry), I32Load(mem32(0)), Call(self.h.arr_slot),
+            LocalGet(entry), I32Load(mem32(4)), I32Load(mem32(0)), Call(self.h.copy_value),
+            I32Store(mem32(0)),
+        ]));
+        body.push(LocalGet(result));
+        self.builder.set_function_at_index(self.h.globals_array, ty, vec![(2, ValType::I32)], body);
+    }
+}
@@ -1,167 +1,239 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
+// The allocator. Blocks come from exact-size free lists for small sizes, then a
+// first-fit list of larger blocks, then the bump pointer; memory grows when the
+// bump pointer reaches its end. Every new block is pushed on the root stack,
+// so a value stays reachable until the statement that made it is done.
+
 use super::core::*;
-use wasm_encoder::*;
+use wasm_encoder::Instruction::*;
+use wasm_encoder::{BlockType, ValType};
 
 impl Compiler {
     pub(super) fn add_memory_functions(&mut self) {
-        self.add_alloc_value_function();
-        self.add_alloc_string_function();
+        self.add_alloc_function();
+        self.add_grow_function();
+        self.add_free_block_function();
+        self.add_fatal_function();
+        self.add_stack_overflow_function();
+        self.add_init_small_ints_function();
+        self.add_new_int_function();
+        self.add_new_float_function();
+        self.add_new_string_function();
+        self.add_echo_function();
+    }
+
+    fn add_alloc_function(&mut self) {
+        // alloc(size, kind) -> zeroed payload
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (size, kind, total, ptr, prev, cur, block_size) = (0, 1, 2, 3, 4, 5, 6);
+        let free_lists = self.free_lists as u64;
+        let body = vec![
+            // total = max(16, size + header rounded up to 8)
+            LocalGet(size), I32Const(HEADER_SIZE + 7), I32Add, I32Const(-8), I32And, LocalTee(total),
+            I32Const(16), LocalGet(total), I32Const(16), I32GtU, Select, LocalSet(total),
+
+            Block(BlockType::Empty),
+            // Exact-size free list
+            LocalGet(total), I32Const(SMALL_BLOCK_MAX), I32LeU,
+            If(BlockType::Empty),
+                LocalGet(total), I32Const(1), I32ShrU, I32Load(mem32(free_lists)), LocalTee(ptr),
+                If(BlockType::Empty),
+                    LocalGet(total), I32Const(1), I32ShrU,
+                    LocalGet(ptr), I32Load(mem32(HEADER_SIZE as u64)),
+                    I32Store(mem32(free_lists)),
+                    Br(2),
+                End,
+            End,
+            // First fit among the large free blocks, which is where the
+            // collector puts runs of neighbouring garbage
+            Block(BlockType::Empty),
+                I32Const(0), LocalSet(prev),
+                I32Const(0), I32Load(mem32(free_lists)), LocalSet(cur),
+                Block(BlockType::Empty),
+                Loop(BlockType::Empty),
+                    LocalGet(cur), I32Eqz, BrIf(1),
+                    LocalGet(cur), I32Load(mem32(0)), LocalTee(block_size), LocalGet(total), I32GeU,
+                    If(BlockType::Empty),
+                        // Unlink
+                        LocalGet(prev),
+                        If(BlockType::Empty),
+                            LocalGet(prev), LocalGet(cur), I32Load(mem32(HEADER_SIZE as u64)),
+                            I32Store(mem32(HEADER_SIZE as u64)),
+                        Else,
+                            I32Const(0), LocalGet(cur), I32Load(mem32(HEADER_SIZE as u64)),
+                            I32Store(mem32(free_lists)),
+                        End,
+                        // Split off the rest if it can hold a block
+                        LocalGet(block_size), LocalGet(total), I32Sub, I32Const(16), I32GeU,
+                        If(BlockType::Empty),
+                            LocalGet(cur), LocalGet(total), I32Add,
+                            LocalGet(block_size), LocalGet(total), I32Sub,
+                            Call(self.h.free_block),
+                        Else,
+                            LocalGet(block_size), LocalSet(total),
+                        End,
+                        LocalGet(cur), LocalSet(ptr),
+                        Br(4),
+                    End,
+                    LocalGet(cur), LocalSet(prev),
+                    LocalGet(cur), I32Load(mem32(HEADER_SIZE as u64)), LocalSet(cur),
+                    Br(0),
+                End,
+                End,
+            End,
+            // Bump allocation
+            GlobalGet(self.g.heap_top), LocalTee(ptr), LocalGet(total), I32Add, GlobalGet(self.g.heap_end), I32GtU,
+            If(BlockType::Empty),
+                LocalGet(total), Call(self.h.grow),
+            End,
+            LocalGet(ptr), LocalGet(total), I32Add, GlobalSet(self.g.heap_top),
+            End,
+
+            // Header, zeroed payload
+            LocalGet(ptr), LocalGet(total), I32Store(mem32(0)),
+            LocalGet(ptr), LocalGet(kind), I32Store(mem32(HEADER_KIND)),
+            LocalGet(ptr), I32Const(HEADER_SIZE), I32Add, I32Const(0), LocalGet(total), I32Const(HEADER_SIZE), I32Sub,
+            MemoryFill(0),
+            GlobalGet(self.g.allocated), LocalGet(total), I32Add, GlobalSet(self.g.allocated),
+
+            // Root it until the current statement is done
+            GlobalGet(self.g.sp), LocalGet(ptr), I32Const(HEADER_SIZE), I32Add, I32Store(mem32(0)),
+            GlobalGet(self.g.sp), I32Const(4), I32Add, GlobalSet(self.g.sp),
+            GlobalGet(self.g.sp), GlobalGet(self.g.stack_limit), I32GtU,
+            If(BlockType::Empty), Call(self.h.stack_overflow), End,
+            LocalGet(ptr), I32Const(HEADER_SIZE), I32Add,
+        ];
+        self.builder.set_function_at_index(self.h.alloc, ty, vec![(5, ValType::I32)], body);
+    }
+
+    fn add_grow_function(&mut self) {
+        // grow(bytes): make room for `bytes` past the bump pointer, growing by at least half
+        let ty = self.builder.add_type(vec![ValType::I32], vec![]);
+        let (bytes, pages) = (0, 1);
+        let message = self.string_literal("PHP Fatal error:  Allowed memory size exhausted\n");
+        let body = vec![
+            GlobalGet(self.g.heap_top), LocalGet(bytes), I32Add, GlobalGet(self.g.heap_end), I32Sub,
+            I32Const(65535), I32Add, I32Const(16), I32ShrU, LocalTee(pages),
+            MemorySize(0), I32Const(1), I32ShrU, LocalGet(pages), MemorySize(0), I32Const(1), I32ShrU, I32GtU, Select,
+            MemoryGrow(0), I32Const(-1), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(pages), MemoryGrow(0), I32Const(-1), I32Eq,
+                If(BlockType::Empty),
+                    I32Const(message as i32), Call(self.h.fatal),
+                End,
+            End,
+            MemorySize(0), I32Const(16), I32Shl, GlobalSet(self.g.heap_end),
+        ];
+        self.builder.set_function_at_index(self.h.grow, ty, vec![(1, ValType::I32)], body);
+    }
+
+    fn add_free_block_function(&mut self) {
+        // free_block(ptr, size): put a block on its free list
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![]);
+        let (ptr, size, list) = (0, 1, 2);
+        let free_lists = self.free_lists as i32;
+        let body = vec![
+            LocalGet(ptr), LocalGet(size), I32Store(mem32(0)),
+            LocalGet(ptr), I32Const(KIND_FREE), I32Store(mem32(HEADER_KIND)),
+            I32Const(free_lists), LocalGet(size), I32Const(1), I32ShrU, I32Add,
+            I32Const(free_lists),
+            LocalGet(size), I32Const(SMALL_BLOCK_MAX), I32LeU, Select, LocalTee(list),
+            // Link the block in front of the list
+            LocalGet(ptr), LocalGet(list), I32Load(mem32(0)), I32Store(mem32(HEADER_SIZE as u64)),
+            LocalGet(ptr), I32Store(mem32(0)),
+        ];
+        self.builder.set_function_at_index(self.h.free_block, ty, vec![(1, ValType::I32)], body);
+    }
+
+    fn add_fatal_function(&mut self) {
+        // fatal(message): print and abort
+        let ty = self.builder.add_type(vec![ValType::I32], vec![]);
+        let body = vec![
+            LocalGet(0), I32Const(STR_BYTES as i32), I32Add, Call(self.print_fn_idx),
+            Unreachable,
+        ];
+        self.builder.set_function_at_index(self.h.fatal, ty, vec![], body);
+    }
+
+    fn add_stack_overflow_function(&mut self) {
+        let ty = self.builder.add_type(vec![], vec![]);
+        let message = self.string_literal("PHP Fatal error:  Maximum function nesting level reached\n");
+        let body = vec![I32Const(message as i32), Call(self.h.fatal)];
+        self.builder.set_function_at_index(self.h.stack_overflow, ty, vec![], body);
     }
-    
-    fn add_alloc_value_function(&mut self) {
-        // alloc_value function - allocates 16 bytes for a PhpValue
-        let mut alloc_value_body = vec![];
-        
-        eprintln!("DEBUG: Building alloc_value function body");
-        
-        // Load current heap pointer from address 0
-        eprintln!("DEBUG: Adding I32Const(0)");
-        alloc_value_body.push(Instruction::I32Const(0));
-        eprintln!("DEBUG: Adding I32Load");
-        alloc_value_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        // Debug: print what's happening
-        eprintln!("DEBUG: alloc_value function - after I32Load, stack should have heap pointer");
-        
-        // Duplicate the heap pointer on stack - one for return, one for calculating new pointer
-        alloc_value_body.push(Instruction::LocalTee(0)); // Save to local 0 AND keep on stack
-        
-        // Calculate new heap pointer (old + 16)
-        alloc_value_body.push(Instruction::I32Const(16)); // size of PhpValue
-        alloc_value_body.push(Instruction::I32Add); // old + 16
-        alloc_value_body.push(Instruction::LocalSet(1)); // Save new pointer to local 1
-        
-        // Store updated heap pointer at address 0
-        alloc_value_body.push(Instruction::I32Const(0)); // address where we store
-        alloc_value_body.push(Instruction::LocalGet(1)); // get the new heap pointer we calculated
-        alloc_value_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        // Initialize refcount to 1 at offset 1 (3 bytes)
-        alloc_value_body.push(Instruction::LocalGet(0)); // heap pointer
-        alloc_value_body.push(Instruction::I32Const(1)); // offset
-        alloc_value_body.push(Instruction::I32Add);
-        alloc_value_body.push(Instruction::I32Const(1)); // initial refcount = 1
-        alloc_value_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        // Clear the rest of the PhpValue (optional but good practice)
-        alloc_value_body.push(Instruction::LocalGet(0)); // heap pointer
-        alloc_value_body.push(Instruction::I32Const(4)); // offset for value area
-        alloc_value_body.push(Instruction::I32Add);
-        alloc_value_body.push(Instruction::I64Const(0)); // clear 8 bytes
-        alloc_value_body.push(Instruction::I64Store(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        
-        alloc_value_body.push(Instruction::LocalGet(0)); // heap pointer
-        alloc_value_body.push(Instruction::I32Const(12)); // offset for last 4 bytes
-        alloc_value_body.push(Instruction::I32Add);
-        alloc_value_body.push(Instruction::I32Const(0)); // clear 4 bytes
-        alloc_value_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        // Return the original heap pointer (still in local 0)
-        alloc_value_body.push(Instruction::LocalGet(0));
-        
-        eprintln!("DEBUG: Setting alloc_value function at index {}", self.alloc_value_fn_idx);
-        eprintln!("DEBUG: alloc_value locals: {:?}", vec![(2, ValType::I32)]);
-        eprintln!("DEBUG: alloc_value body has {} instructions", alloc_value_body.len());
-        self.builder.set_function_at_index(self.alloc_value_fn_idx, self.alloc_value_type_idx, vec![(2, ValType::I32)], alloc_value_body);
+
+    fn add_init_small_ints_function(&mut self) {
+        let ty = self.builder.add_type(vec![], vec![]);
+        let i = 0;
+        let body = vec![
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), I32Const(SMALL_INT_COUNT as i32), I32GeU, BrIf(1),
+                LocalGet(i), I32Const(VALUE_SIZE), I32Mul, I32Const(TAG_INT),
+                I32Store(mem32(self.small_ints as u64)),
+                LocalGet(i), I32Const(VALUE_SIZE), I32Mul,
+                LocalGet(i), I64ExtendI32U, I64Const(SMALL_INT_MIN), I64Add,
+                I64Store(mem64(self.small_ints as u64 + VALUE_DATA)),
+                LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                Br(0),
+            End,
+            End,
+        ];
+        self.builder.set_function_at_index(self.h.init_small_ints, ty, vec![(1, ValType::I32)], body);
+    }
+
+    fn add_new_int_function(&mut self) {
+        // new_int(i64) -> value
+        let ty = self.builder.add_type(vec![ValType::I64], vec![ValType::I32]);
+        let (value, ptr) = (0, 1);
+        let body = vec![
+            LocalGet(value), I64Const(SMALL_INT_MIN), I64Sub, I64Const(SMALL_INT_COUNT as i64), I64LtU,
+            If(BlockType::Empty),
+                LocalGet(value), I64Const(SMALL_INT_MIN), I64Sub, I32WrapI64, I32Const(VALUE_SIZE), I32Mul,
+                I32Const(self.small_ints as i32), I32Add,
+                Return,
+            End,
+            I32Const(VALUE_SIZE), I32Const(KIND_VALUE), Call(self.h.alloc), LocalTee(ptr),
+            I32Const(TAG_INT), I32Store(mem32(0)),
+            LocalGet(ptr), LocalGet(value), I64Store(mem64(VALUE_DATA)),
+            LocalGet(ptr),
+        ];
+        self.builder.set_function_at_index(self.h.new_int, ty, vec![(1, ValType::I32)], body);
     }
-    
-    fn add_alloc_string_function(&mut self) {
-        // alloc_string function - allocates memory for a string and copies data
-        let mut alloc_string_body = vec![];
-        let mut string_locals = vec![];
-        string_locals.push((1, ValType::I32)); // heap_ptr
-        string_locals.push((1, ValType::I32)); // new_heap_ptr
-        string_locals.push((1, ValType::I32)); // total_size
-        string_locals.push((1, ValType::I32)); // i
-        
-        // Parameters: 0 = data pointer, 1 = length
-        
-        // Calculate total size needed: 8 (header) + length + padding to 4-byte boundary
-        alloc_string_body.push(Instruction::I32Const(8)); // header size
-        alloc_string_body.push(Instruction::LocalGet(1)); // length
-        alloc_string_body.push(Instruction::I32Add);
-        // Align to 4 bytes: (size + 3) & ~3
-        alloc_string_body.push(Instruction::I32Const(3));
-        alloc_string_body.push(Instruction::I32Add);
-        alloc_string_body.push(Instruction::I32Const(-4)); // ~3 in two's complement
-        alloc_string_body.push(Instruction::I32And);
-        alloc_string_body.push(Instruction::LocalSet(4)); // total_size
-        
-        // Load current heap pointer
-        alloc_string_body.push(Instruction::I32Const(0));
-        alloc_string_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        alloc_string_body.push(Instruction::LocalSet(2)); // heap_ptr
-        
-        // Calculate new heap pointer
-        alloc_string_body.push(Instruction::LocalGet(2)); // heap_ptr
-        alloc_string_body.push(Instruction::LocalGet(4)); // total_size
-        alloc_string_body.push(Instruction::I32Add);
-        alloc_string_body.push(Instruction::LocalSet(3)); // new_heap_ptr
-        
-        // Update heap pointer
-        alloc_string_body.push(Instruction::I32Const(0));
-        alloc_string_body.push(Instruction::LocalGet(3)); // new_heap_ptr
-        alloc_string_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        // Write string header - length at offset 0
-        alloc_string_body.push(Instruction::LocalGet(2)); // heap_ptr
-        alloc_string_body.push(Instruction::LocalGet(1)); // length
-        alloc_string_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        // Write string header - hash at offset 4 (0 for now)
-        alloc_string_body.push(Instruction::LocalGet(2)); // heap_ptr
-        alloc_string_body.push(Instruction::I32Const(0)); // hash = 0
-        alloc_string_body.push(Instruction::I32Store(MemArg { offset: 4, align: 2, memory_index: 0 }));
-        
-        // Copy string data byte by byte
-        // i = 0
-        alloc_string_body.push(Instruction::I32Const(0));
-        alloc_string_body.push(Instruction::LocalSet(5)); // i = 0
-        
-        // Block wrapper for loop exit
-        alloc_string_body.push(Instruction::Block(BlockType::Empty));
-        
-        // Loop start
-        alloc_string_body.push(Instruction::Loop(BlockType::Empty));
-        
-        // Check if i < length
-        alloc_string_body.push(Instruction::LocalGet(5)); // i
-        alloc_string_body.push(Instruction::LocalGet(1)); // length
-        alloc_string_body.push(Instruction::I32LtU);
-        alloc_string_body.push(Instruction::I32Eqz);
-        alloc_string_body.push(Instruction::BrIf(1)); // Exit to block end if i >= length
-        
-        // Copy byte: dest[8 + i] = src[i]
-        alloc_string_body.push(Instruction::LocalGet(2)); // heap_ptr
-        alloc_string_body.push(Instruction::I32Const(8)); // header size
-        alloc_string_body.push(Instruction::I32Add);
-        alloc_string_body.push(Instruction::LocalGet(5)); // i
-        alloc_string_body.push(Instruction::I32Add); // dest address
-        
-        alloc_string_body.push(Instruction::LocalGet(0)); // src pointer
-        alloc_string_body.push(Instruction::LocalGet(5)); // i
-        alloc_string_body.push(Instruction::I32Add); // src address
-        alloc_string_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 })); // load byte
-        
-        alloc_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 })); // store byte
-        
-        // i++
-        alloc_string_body.push(Instruction::LocalGet(5)); // i
-        alloc_string_body.push(Instruction::I32Const(1));
-        alloc_string_body.push(Instruction::I32Add);
-        alloc_string_body.push(Instruction::LocalSet(5)); // i++
-        
-        // Continue loop
-        alloc_string_body.push(Instruction::Br(0));
-        alloc_string_body.push(Instruction::End); // End loop
-        
-        alloc_string_body.push(Instruction::End); // End block
-        
-        // Return the allocated string pointer
-        alloc_string_body.push(Instruction::LocalGet(2)); // heap_ptr
-        
-        self.builder.set_function_at_index(self.alloc_string_fn_idx, self.alloc_string_type_idx, string_locals, alloc_string_body);
+
+    fn add_new_float_function(&mut self) {
+        let ty = self.builder.add_type(vec![ValType::F64], vec![ValType::I32]);
+        let (value, ptr) = (0, 1);
+        let body = vec![
+            I32Const(VALUE_SIZE), I32Const(KIND_VALUE), Call(self.h.alloc), LocalTee(ptr),
+            I32Const(TAG_FLOAT), I32Store(mem32(0)),
+            LocalGet(ptr), LocalGet(value), F64Store(mem64(VALUE_DATA)),
+            LocalGet(ptr),
+        ];
+        self.builder.set_function_at_index(self.h.new_float, ty, vec![(1, ValType::I32)], body);
+    }
+
+    fn add_new_string_function(&mut self) {
+        // new_string(len) -> string value whose bytes are still to be written
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (len, ptr) = (0, 1);
+        let body = vec![
+            LocalGet(len), I32Const(STR_BYTES as i32 + 1), I32Add, I32Const(KIND_VALUE), Call(self.h.alloc), LocalTee(ptr),
+            I32Const(TAG_STRING), I32Store(mem32(0)),
+            LocalGet(ptr), LocalGet(len), I32Store(mem32(STR_LEN)),
+            LocalGet(ptr),
+        ];
+        self.builder.set_function_at_index(self.h.new_string, ty, vec![(1, ValType::I32)], body);
+    }
+
+    fn add_echo_function(&mut self) {
+        // Strings end in NUL, so they can be handed to `env.print` as they are
+        let ty = self.builder.add_type(vec![ValType::I32], vec![]);
+        let body = vec![
+            LocalGet(0), Call(self.h.to_string), I32Const(STR_BYTES as i32), I32Add, Call(self.print_fn_idx),
+        ];
+        self.builder.set_function_at_index(self.h.echo, ty, vec![], body);
     }
 }
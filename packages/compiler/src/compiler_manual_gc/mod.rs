@@ -5,13 +5,19 @@ pub mod core;
 pub mod runtime;
 pub mod expressions;
 pub mod statements;
+pub mod functions; // User functions, methods and calls
+pub mod classes;   // Classes, interfaces, enums and method dispatch
+pub mod globals;   // global, static and $GLOBALS
+pub mod builtins;  // Built-in functions
 
 // Runtime function modules
-pub mod memory;
+pub mod memory;     // Allocator
+pub mod gc;         // Mark-sweep collector
+pub mod types;      // Conversions and type declarations
 pub mod arithmetic;
 pub mod strings;
 pub mod comparison;
-pub mod types;
-pub mod gc;
+pub mod arrays;     // Ordered hash tables
+pub mod objects;    // Properties, magic methods and the class table
 
 pub use core::Compiler;
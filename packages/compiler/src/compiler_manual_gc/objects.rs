@@ -0,0 +1,471 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+// Objects. A class table in static memory gives the name and the declared
+// properties of every class id; properties the class does not declare go
+// to an array of dynamic properties, unless the class has a magic method
+// for them. Magic methods and method dispatch are calls chosen by class id.
+
+use super::classes::ClassKind;
+use super::core::*;
+use super::types::DeclaredType;
+use wasm_encoder::Instruction::*;
+use wasm_encoder::{BlockType, Instruction, ValType};
+
+/// Bytes per property in a class table record: name, type mask, TypeError
+/// suffix, readonly label and declaring class id
+const PROP_RECORD_SIZE: i32 = 20;
+
+impl Compiler {
+    pub(super) fn add_object_functions(&mut self) {
+        let class_table = self.add_class_table();
+        self.add_obj_new_function();
+        self.add_prop_find_function(class_table);
+        self.add_prop_get_function();
+        self.add_prop_set_function(class_table);
+        self.add_prop_place_function(class_table);
+        self.add_prop_isset_function();
+        self.add_prop_unset_function(class_table);
+        self.add_clone_object_function();
+        self.add_object_to_string_function(class_table);
+        self.add_type_name_function(class_table);
+        self.add_dispatch_functions();
+    }
+
+    /// The class table: one record address per class id. A record holds the
+    /// class name, the property count, then per property its name, type mask,
+    /// TypeError suffix (0 when untyped), `C::$name` label (0 unless readonly)
+    /// and the id of the class declaring it.
+    fn add_class_table(&mut self) -> u32 {
+        let table = self.static_alloc(self.class_names.len() as u32 * 4);
+        for (id, name) in self.class_names.clone().iter().enumerate() {
+            let class = self.classes[name].clone();
+            let mut record = Vec::with_capacity(8 + class.props.len() * PROP_RECORD_SIZE as usize);
+            record.extend_from_slice(&self.string_literal(name).to_le_bytes());
+            record.extend_from_slice(&(class.props.len() as u32).to_le_bytes());
+            for prop in &class.props {
+                let suffix = match &prop.declared {
+                    Some(declared) => self.string_literal(&format!(
+                        " to property {}::${} of type {}\n", prop.class, prop.name, declared.name
+                    )),
+                    None => 0,
+                };
+                record.extend_from_slice(&self.string_literal(&prop.name).to_le_bytes());
+                record.extend_from_slice(&DeclaredType::mask_of(&prop.declared).to_le_bytes());
+                record.extend_from_slice(&suffix.to_le_bytes());
+                let label = match prop.readonly {
+                    true => self.string_literal(&format!("{}::${}", prop.class, prop.name)),
+                    false => 0,
+                };
+                record.extend_from_slice(&label.to_le_bytes());
+                record.extend_from_slice(&self.classes[&prop.class].id.to_le_bytes());
+            }
+            let addr = self.static_alloc(record.len() as u32);
+            self.static_write(addr, &record);
+            self.static_write(table + id as u32 * 4, &addr.to_le_bytes());
+        }
+        table
+    }
+
+    /// Call the magic `method` of the object in `object` if its class has one,
+    /// then run `then` with the result on the stack
+    fn magic_call(&mut self, method: &str, object: u32, args: &[u32], then: &[Instruction<'static>]) -> Vec<Instruction<'static>> {
+        let mut code = Vec::new();
+        for name in self.class_names.clone() {
+            let class = &self.classes[&name];
+            let Some(info) = class.methods.get(method) else { continue };
+            let (id, idx, params) = (class.id, info.idx, info.params.len());
+            code.extend([
+                LocalGet(object), I32Load(mem32(OBJ_CLASS)), I32Const(id as i32), I32Eq,
+                If(BlockType::Empty),
+                    LocalGet(object),
+            ]);
+            for i in 0..params {
+                code.push(match args.get(i) {
+                    Some(&arg) => LocalGet(arg),
+                    None => I32Const(MISSING_VALUE as i32),
+                });
+            }
+            code.push(Call(idx));
+            code.extend(then.iter().cloned());
+            code.push(End);
+        }
+        code
+    }
+
+    fn add_obj_new_function(&mut self) {
+        // obj_new(class id, property count) -> object with null properties
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (class, count, object) = (0, 1, 2);
+        let body = vec![
+            LocalGet(count), I32Const(2), I32Shl, I32Const(OBJ_PROPS as i32), I32Add,
+            I32Const(KIND_VALUE), Call(self.h.alloc), LocalTee(object),
+            I32Const(TAG_OBJECT), I32Store(mem32(0)),
+            LocalGet(object), LocalGet(class), I32Store(mem32(OBJ_CLASS)),
+            LocalGet(object), LocalGet(count), I32Store(mem32(OBJ_PROP_COUNT)),
+            LocalGet(object),
+        ];
+        self.builder.set_function_at_index(self.h.obj_new, ty, vec![(1, ValType::I32)], body);
+    }
+
+    fn add_prop_find_function(&mut self, class_table: u32) {
+        // prop_find(object, name) -> index of the declared property, or -1
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (object, name, record, count, i) = (0, 1, 2, 3, 4);
+        let body = vec![
+            LocalGet(object), I32Load(mem32(OBJ_CLASS)), I32Const(2), I32Shl,
+            I32Load(mem32(class_table as u64)), LocalTee(record), I32Load(mem32(4)), LocalSet(count),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(count), I32GeU, BrIf(1),
+                LocalGet(record), LocalGet(i), I32Const(PROP_RECORD_SIZE), I32Mul, I32Add, I32Load(mem32(8)), LocalGet(name), Call(self.h.str_eq),
+                If(BlockType::Empty), LocalGet(i), Return, End,
+                LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                Br(0),
+            End,
+            End,
+            I32Const(-1),
+        ];
+        self.builder.set_function_at_index(self.h.prop_find, ty, vec![(3, ValType::I32)], body);
+    }
+
+    /// Address of the declared property `index` of `object`
+    fn prop_address(object: u32, index: u32) -> [Instruction<'static>; 6] {
+        [LocalGet(object), LocalGet(index), I32Const(2), I32Shl, I32Add, I32Const(OBJ_PROPS as i32)]
+    }
+
+    /// Set `record` to the class table record of the declared property `index` of `object`
+    fn prop_record(class_table: u32, object: u32, index: u32, record: u32) -> [Instruction<'static>; 10] {
+        [
+            LocalGet(object), I32Load(mem32(OBJ_CLASS)), I32Const(2), I32Shl, I32Load(mem32(class_table as u64)),
+            LocalGet(index), I32Const(PROP_RECORD_SIZE), I32Mul, I32Add, LocalSet(record),
+        ]
+    }
+
+    /// Stop with the Error `{message}{label}\n` when the readonly property
+    /// `index` of `object` is already initialised; `label` holds its record's label
+    fn readonly_initialized_check(&mut self, message: &str, object: u32, index: u32, label: u32) -> Vec<Instruction<'static>> {
+        let message = self.string_literal(&format!("PHP Fatal error:  Uncaught Error: {}", message));
+        let newline = self.string_literal("\n");
+        let mut code = Self::prop_address(object, index).to_vec();
+        code.extend([
+            I32Add, I32Load(mem32(0)), I32Const(MISSING_VALUE as i32), I32Ne,
+            If(BlockType::Empty),
+                I32Const(message as i32), LocalGet(label), Call(self.h.concat), I32Const(newline as i32), Call(self.h.concat),
+                Call(self.h.fatal),
+            End,
+        ]);
+        code
+    }
+
+    /// Stop with the Error `{message}{label} from {scope}\n` when the class id
+    /// in `scope` (-1 at global scope) did not declare the property of `record`
+    fn readonly_scope_check(&mut self, message: &str, class_table: u32, scope: u32, record: u32, label: u32) -> Vec<Instruction<'static>> {
+        let message = self.string_literal(&format!("PHP Fatal error:  Uncaught Error: {}", message));
+        let from = self.string_literal(" from ");
+        let global = self.string_literal("global scope\n");
+        let class_scope = self.string_literal("scope ");
+        let newline = self.string_literal("\n");
+        vec![
+            LocalGet(scope), LocalGet(record), I32Load(mem32(24)), I32Ne,
+            If(BlockType::Empty),
+                I32Const(message as i32), LocalGet(label), Call(self.h.concat), I32Const(from as i32), Call(self.h.concat),
+                LocalGet(scope), I32Const(0), I32LtS,
+                If(BlockType::Result(ValType::I32)),
+                    I32Const(global as i32),
+                Else,
+                    I32Const(class_scope as i32),
+                    LocalGet(scope), I32Const(2), I32Shl, I32Load(mem32(class_table as u64)), I32Load(mem32(0)), Call(self.h.concat),
+                    I32Const(newline as i32), Call(self.h.concat),
+                End,
+                Call(self.h.concat), Call(self.h.fatal),
+            End,
+        ]
+    }
+
+    fn add_prop_get_function(&mut self) {
+        // prop_get(object, name) -> value; null when the object has no such property
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (object, name, index, dynamic) = (0, 1, 2, 3);
+        let mut body = vec![
+            LocalGet(object), I32Load(mem32(0)), I32Const(TAG_OBJECT), I32Ne,
+            If(BlockType::Empty), I32Const(NULL_VALUE as i32), Return, End,
+            LocalGet(object), LocalGet(name), Call(self.h.prop_find), LocalTee(index), I32Const(0), I32GeS,
+            If(BlockType::Empty),
+        ];
+        body.extend(Self::prop_address(object, index));
+        body.extend([
+                I32Add, I32Load(mem32(0)), Return,
+            End,
+            LocalGet(object), I32Load(mem32(OBJ_DYNAMIC)), LocalTee(dynamic),
+            If(BlockType::Empty),
+                LocalGet(dynamic), I64Const(0), LocalGet(name), Call(self.h.arr_find), LocalTee(index),
+                If(BlockType::Empty), LocalGet(index), I32Load(mem32(ENTRY_VALUE)), Return, End,
+            End,
+        ]);
+        body.extend(self.magic_call("__get", object, &[name], &[Return]));
+        body.push(I32Const(NULL_VALUE as i32));
+        self.builder.set_function_at_index(self.h.prop_get, ty, vec![(2, ValType::I32)], body);
+    }
+
+    fn add_prop_set_function(&mut self, class_table: u32) {
+        // prop_set(object, name, value, strict, scope): store a property,
+        // converting the value to the declared type; readonly properties can
+        // only be initialised from the class id in `scope`
+        let ty = self.builder.add_type(vec![ValType::I32; 5], vec![]);
+        let (object, name, value, strict, scope, index, record, label) = (0, 1, 2, 3, 4, 5, 6, 7);
+        let message = self.string_literal("PHP Fatal error:  Uncaught Error: Attempt to assign property on non-object\n");
+        let type_error = self.string_literal("PHP Fatal error:  Uncaught TypeError: Cannot assign ");
+        let mut body = vec![
+            LocalGet(object), I32Load(mem32(0)), I32Const(TAG_OBJECT), I32Ne,
+            If(BlockType::Empty), I32Const(message as i32), Call(self.h.fatal), End,
+            LocalGet(object), LocalGet(name), Call(self.h.prop_find), LocalTee(index), I32Const(0), I32GeS,
+            If(BlockType::Empty),
+        ];
+        body.extend(Self::prop_record(class_table, object, index, record));
+        body.extend([LocalGet(record), I32Load(mem32(20)), LocalTee(label), If(BlockType::Empty)]);
+        body.extend(self.readonly_initialized_check("Cannot modify readonly property ", object, index, label));
+        body.extend(self.readonly_scope_check("Cannot initialize readonly property ", class_table, scope, record, label));
+        body.push(End);
+        body.extend(Self::prop_address(object, index));
+        body.extend([
+                I32Add,
+                LocalGet(value),
+                LocalGet(record), I32Load(mem32(12)),
+                LocalGet(strict), I32Const(type_error as i32), LocalGet(record), I32Load(mem32(16)),
+                Call(self.h.check_type),
+                I32Store(mem32(0)), Return,
+            End,
+        ]);
+        // Known dynamic properties are set directly, others go to __set
+        body.extend([
+            LocalGet(object), I32Load(mem32(OBJ_DYNAMIC)), LocalTee(record),
+            If(BlockType::Empty),
+                LocalGet(record), I64Const(0), LocalGet(name), Call(self.h.arr_find), LocalTee(record),
+                If(BlockType::Empty), LocalGet(record), LocalGet(value), I32Store(mem32(ENTRY_VALUE)), Return, End,
+            End,
+        ]);
+        body.extend(self.magic_call("__set", object, &[name, value], &[Drop, Return]));
+        body.extend([
+            LocalGet(object), I32Const(OBJ_DYNAMIC as i32), I32Add, Call(self.h.place_array),
+            LocalGet(name), Call(self.h.arr_slot), LocalGet(value), I32Store(mem32(0)),
+        ]);
+        self.builder.set_function_at_index(self.h.prop_set, ty, vec![(3, ValType::I32)], body);
+    }
+
+    fn add_prop_place_function(&mut self, class_table: u32) {
+        // prop_place(object, name) -> address of the property, adding a dynamic
+        // property if needed; for writes into arrays held by properties
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (object, name, index, record, label) = (0, 1, 2, 3, 4);
+        let message = self.string_literal("PHP Fatal error:  Uncaught Error: Attempt to modify property on non-object\n");
+        let mut body = vec![
+            LocalGet(object), I32Load(mem32(0)), I32Const(TAG_OBJECT), I32Ne,
+            If(BlockType::Empty), I32Const(message as i32), Call(self.h.fatal), End,
+            LocalGet(object), LocalGet(name), Call(self.h.prop_find), LocalTee(index), I32Const(0), I32GeS,
+            If(BlockType::Empty),
+        ];
+        body.extend(Self::prop_record(class_table, object, index, record));
+        body.extend([LocalGet(record), I32Load(mem32(20)), LocalTee(label), If(BlockType::Empty)]);
+        body.extend(self.readonly_initialized_check("Cannot modify readonly property ", object, index, label));
+        body.push(End);
+        body.extend(Self::prop_address(object, index));
+        body.extend([
+                I32Add, Return,
+            End,
+            LocalGet(object), I32Const(OBJ_DYNAMIC as i32), I32Add, Call(self.h.place_array),
+            LocalGet(name), Call(self.h.arr_slot),
+        ]);
+        self.builder.set_function_at_index(self.h.prop_place, ty, vec![(3, ValType::I32)], body);
+    }
+
+    fn add_prop_isset_function(&mut self) {
+        // prop_isset(object, name) -> i32
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (object, name, index, dynamic) = (0, 1, 2, 3);
+        let mut body = vec![
+            LocalGet(object), I32Load(mem32(0)), I32Const(TAG_OBJECT), I32Ne,
+            If(BlockType::Empty), I32Const(0), Return, End,
+            LocalGet(object), LocalGet(name), Call(self.h.prop_find), LocalTee(index), I32Const(0), I32GeS,
+            If(BlockType::Empty),
+        ];
+        body.extend(Self::prop_address(object, index));
+        body.extend([
+                I32Add, I32Load(mem32(0)), I32Load(mem32(0)), I32Const(TAG_NULL), I32Ne, Return,
+            End,
+            LocalGet(object), I32Load(mem32(OBJ_DYNAMIC)), LocalTee(dynamic),
+            If(BlockType::Empty),
+                LocalGet(dynamic), I64Const(0), LocalGet(name), Call(self.h.arr_find), LocalTee(index),
+                If(BlockType::Empty),
+                    LocalGet(index), I32Load(mem32(ENTRY_VALUE)), I32Load(mem32(0)), I32Const(TAG_NULL), I32Ne, Return,
+                End,
+            End,
+        ]);
+        body.extend(self.magic_call("__isset", object, &[name], &[Call(self.h.to_bool), Return]));
+        body.push(I32Const(0));
+        self.builder.set_function_at_index(self.h.prop_isset, ty, vec![(2, ValType::I32)], body);
+    }
+
+    fn add_prop_unset_function(&mut self, class_table: u32) {
+        // prop_unset(object, name, scope); readonly properties can only be
+        // unset while uninitialised, from the class id in `scope`
+        let ty = self.builder.add_type(vec![ValType::I32; 3], vec![]);
+        let (object, name, scope, index, dynamic, record, label) = (0, 1, 2, 3, 4, 5, 6);
+        let mut body = vec![
+            LocalGet(object), I32Load(mem32(0)), I32Const(TAG_OBJECT), I32Ne, BrIf(0),
+            LocalGet(object), LocalGet(name), Call(self.h.prop_find), LocalTee(index), I32Const(0), I32GeS,
+            If(BlockType::Empty),
+        ];
+        body.extend(Self::prop_record(class_table, object, index, record));
+        body.extend([LocalGet(record), I32Load(mem32(20)), LocalTee(label), If(BlockType::Empty)]);
+        body.extend(self.readonly_scope_check("Cannot unset readonly property ", class_table, scope, record, label));
+        body.extend(self.readonly_initialized_check("Cannot unset readonly property ", object, index, label));
+        body.push(End);
+        body.extend(Self::prop_address(object, index));
+        body.extend([
+                I32Add, I32Const(NULL_VALUE as i32), I32Store(mem32(0)), Return,
+            End,
+            LocalGet(object), I32Load(mem32(OBJ_DYNAMIC)), LocalTee(dynamic),
+            If(BlockType::Empty),
+                LocalGet(dynamic), I64Const(0), LocalGet(name), Call(self.h.arr_find),
+                If(BlockType::Empty), LocalGet(dynamic), LocalGet(name), Call(self.h.arr_delete), Return, End,
+            End,
+        ]);
+        body.extend(self.magic_call("__unset", object, &[name], &[Drop, Return]));
+        self.builder.set_function_at_index(self.h.prop_unset, ty, vec![(4, ValType::I32)], body);
+    }
+
+    fn add_clone_object_function(&mut self) {
+        // clone_object(object) -> a shallow copy; arrays are copied as on
+        // assignment, then __clone runs on the copy
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (object, copy, i, size) = (0, 1, 2, 3);
+        let message = self.string_literal("PHP Fatal error:  Uncaught Error: __clone method called on non-object\n");
+        let mut body = vec![
+            LocalGet(object), I32Load(mem32(0)), I32Const(TAG_OBJECT), I32Ne,
+            If(BlockType::Empty), I32Const(message as i32), Call(self.h.fatal), End,
+            LocalGet(object), I32Load(mem32(OBJ_PROP_COUNT)), I32Const(2), I32Shl, I32Const(OBJ_PROPS as i32), I32Add, LocalTee(size),
+            I32Const(KIND_VALUE), Call(self.h.alloc), LocalTee(copy),
+            LocalGet(object), LocalGet(size), MEMORY_COPY,
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(copy), I32Load(mem32(OBJ_PROP_COUNT)), I32GeU, BrIf(1),
+                LocalGet(copy), LocalGet(i), I32Const(2), I32Shl, I32Add, LocalTee(size),
+                LocalGet(size), I32Load(mem32(OBJ_PROPS)), Call(self.h.copy_value), I32Store(mem32(OBJ_PROPS)),
+                LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                Br(0),
+            End,
+            End,
+            LocalGet(copy), I32Load(mem32(OBJ_DYNAMIC)),
+            If(BlockType::Empty),
+                LocalGet(copy), LocalGet(copy), I32Load(mem32(OBJ_DYNAMIC)), Call(self.h.arr_copy), I32Store(mem32(OBJ_DYNAMIC)),
+            End,
+        ];
+        body.extend(self.magic_call("__clone", copy, &[], &[Drop]));
+        body.push(LocalGet(copy));
+        self.builder.set_function_at_index(self.h.clone_object, ty, vec![(3, ValType::I32)], body);
+    }
+
+    fn add_object_to_string_function(&mut self, class_table: u32) {
+        // object_to_string(object) -> string, through __toString
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let object = 0;
+        let prefix = self.string_literal("PHP Fatal error:  Uncaught Error: Object of class ");
+        let suffix = self.string_literal(" could not be converted to string\n");
+        let mut body = self.magic_call("__tostring", object, &[], &[Return]);
+        body.extend([
+            I32Const(prefix as i32),
+            LocalGet(object), I32Load(mem32(OBJ_CLASS)), I32Const(2), I32Shl, I32Load(mem32(class_table as u64)), I32Load(mem32(0)),
+            Call(self.h.concat),
+            I32Const(suffix as i32), Call(self.h.concat),
+            Call(self.h.fatal),
+            I32Const(NULL_VALUE as i32),
+        ]);
+        self.builder.set_function_at_index(self.h.object_to_string, ty, vec![], body);
+    }
+
+    fn add_type_name_function(&mut self, class_table: u32) {
+        // type_name(value) -> the type for error messages; the class for objects
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (value, tag) = (0, 1);
+        let mut body = vec![
+            LocalGet(value), I32Load(mem32(0)), LocalTee(tag), I32Const(TAG_OBJECT), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), I32Load(mem32(OBJ_CLASS)), I32Const(2), I32Shl, I32Load(mem32(class_table as u64)), I32Load(mem32(0)),
+                Return,
+            End,
+        ];
+        let names = [(TAG_BOOL, "bool"), (TAG_INT, "int"), (TAG_FLOAT, "float"), (TAG_STRING, "string"), (TAG_ARRAY, "array")];
+        for (type_tag, name) in names {
+            let text = self.string_literal(name);
+            body.extend([
+                LocalGet(tag), I32Const(type_tag), I32Eq,
+                If(BlockType::Empty), I32Const(text as i32), Return, End,
+            ]);
+        }
+        body.push(I32Const(self.string_literal("null") as i32));
+        self.builder.set_function_at_index(self.h.type_name, ty, vec![(1, ValType::I32)], body);
+    }
+
+    /// The bodies of the method dispatch functions
+    fn add_dispatch_functions(&mut self) {
+        let mut dispatchers: Vec<(String, (u32, usize, String))> = self.dispatchers.clone().into_iter().collect();
+        dispatchers.sort_by_key(|(_, (idx, _, _))| *idx);
+        for (method, (idx, arity, display)) in dispatchers {
+            let ty = self.value_function_type(arity + 1);
+            let object = 0;
+            let args: Vec<u32> = (1..=arity as u32).collect();
+            let (array, class) = (arity as u32 + 1, arity as u32 + 2);
+
+            let prefix = self.string_literal(&format!("PHP Fatal error:  Uncaught Error: Call to a member function {}() on ", display));
+            let newline = self.string_literal("\n");
+            let mut body = vec![
+                LocalGet(object), I32Load(mem32(0)), I32Const(TAG_OBJECT), I32Ne,
+                If(BlockType::Empty),
+                    I32Const(prefix as i32), LocalGet(object), Call(self.h.type_name), Call(self.h.concat),
+                    I32Const(newline as i32), Call(self.h.concat), Call(self.h.fatal),
+                End,
+                LocalGet(object), I32Load(mem32(OBJ_CLASS)), LocalSet(class),
+            ];
+            let text = self.string_literal(&display);
+            for name in self.class_names.clone() {
+                let info = &self.classes[&name];
+                if info.kind == ClassKind::Interface {
+                    continue;
+                }
+                if let Some(target) = info.methods.get(&method) {
+                    body.extend([LocalGet(class), I32Const(info.id as i32), I32Eq, If(BlockType::Empty)]);
+                    if !target.is_static {
+                        body.push(LocalGet(object));
+                    }
+                    body.extend(args[..target.params.len()].iter().map(|&arg| LocalGet(arg)));
+                    body.extend([Call(target.idx), Return, End]);
+                } else if let Some(hook) = info.methods.get("__call") {
+                    // __call gets the arguments that were passed as an array
+                    body.extend([
+                        LocalGet(class), I32Const(info.id as i32), I32Eq,
+                        If(BlockType::Empty),
+                            I32Const(arity as i32), Call(self.h.arr_new), LocalSet(array),
+                    ]);
+                    for &arg in &args {
+                        body.extend([
+                            LocalGet(arg), I32Const(MISSING_VALUE as i32), I32Ne,
+                            If(BlockType::Empty), LocalGet(array), LocalGet(arg), Call(self.h.arr_append), End,
+                        ]);
+                    }
+                    body.extend([LocalGet(object), I32Const(text as i32), LocalGet(array)]);
+                    body.extend((2..hook.params.len()).map(|_| I32Const(MISSING_VALUE as i32)));
+                    body.extend([Call(hook.idx), Return, End]);
+                }
+            }
+            let prefix = self.string_literal("PHP Fatal error:  Uncaught Error: Call to undefined method ");
+            let suffix = self.string_literal(&format!("::{}()\n", display));
+            body.extend([
+                I32Const(prefix as i32), LocalGet(object), Call(self.h.type_name), Call(self.h.concat),
+                I32Const(suffix as i32), Call(self.h.concat), Call(self.h.fatal),
+                I32Const(NULL_VALUE as i32),
+            ]);
+            self.builder.set_function_at_index(idx, ty, vec![(2, ValType::I32)], body);
+        }
+    }
+}
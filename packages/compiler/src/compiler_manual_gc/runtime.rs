@@ -2,25 +2,69 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::core::*;
+use crate::wasm_builder::WasmBuilder;
+
+macro_rules! helpers {
+    ($($name:ident),* $(,)?) => {
+        /// Indices of the runtime functions. They are reserved before any PHP
+        /// code is compiled and their bodies are added last, once every class,
+        /// method and static slot is known.
+        #[derive(Debug, Clone, Copy)]
+        pub(super) struct Helpers {
+            $(pub(super) $name: u32,)*
+        }
+
+        impl Helpers {
+            pub(super) fn reserve(builder: &mut WasmBuilder, debug_info: bool) -> Self {
+                $(
+                    let $name = builder.reserve_function_index();
+                    if debug_info {
+                        builder.set_function_name($name, concat!("php_", stringify!($name)));
+                    }
+                )*
+                Helpers { $($name,)* }
+            }
+        }
+    };
+}
+
+helpers! {
+    // memory.rs
+    alloc, grow, free_block, fatal, stack_overflow, init_small_ints,
+    new_int, new_float, new_string, echo,
+    // gc.rs
+    gc_mark, gc_scan, gc_drain, gc_mark_roots, gc_rescan, gc_sweep, gc_collect,
+    gc_release, gc_destruct, safepoint,
+    // types.rs
+    to_bool, to_int, to_float, to_number, coerce, check_type,
+    // arithmetic.rs
+    arith, neg, incdec, math_round, math_pow,
+    // strings.rs
+    to_string, int_to_string, float_to_string, parse_number, concat, mem_eq, str_eq, str_cmp,
+    substr, strpos, strtolower, strtoupper, trim, str_replace, explode, implode,
+    // comparison.rs
+    loose_eq, strict_eq, compare,
+    // arrays.rs
+    arr_new, norm_key, key_hash, entry_key, arr_find, arr_insert, arr_get, arr_slot, arr_append,
+    arr_grow, arr_delete, arr_copy, copy_value, place_array, arr_extend, count, in_array,
+    array_keys, array_values, array_merge, array_slice, array_pop, array_shift, array_unshift,
+    // objects.rs
+    obj_new, prop_find, prop_get, prop_set, prop_place, prop_isset, prop_unset,
+    clone_object, object_to_string, type_name,
+    // globals.rs
+    global_find, globals_array,
+}
 
 impl Compiler {
     pub(super) fn add_runtime_functions(&mut self) {
-        // Add memory management functions
         self.add_memory_functions();
-        
-        // Add arithmetic functions
+        self.add_gc_functions();
+        self.add_type_functions();
         self.add_arithmetic_functions();
-        
-        // Add string functions
         self.add_string_functions();
-        
-        // Add comparison functions
         self.add_comparison_functions();
-        
-        // Add type functions
-        self.add_type_functions();
-        
-        // Add garbage collection functions
-        self.add_gc_functions();
+        self.add_array_functions();
+        self.add_object_functions();
+        self.add_global_functions();
     }
 }
@@ -1,133 +1,286 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
+// Statements. Each statement starts by dropping the temporaries of the one
+// before from the root stack, then gives the collector a chance to run.
+// Loops are a wasm block (the `break` target) around a loop, with `continue`
+// targeting the loop or, when there is code to run first, an inner block.
+
 use super::core::*;
-use edge_php_parser::ast;
-use wasm_encoder::*;
-use crate::error::CompilerError;
+use edge_php_parser::ast::*;
+use wasm_encoder::{BlockType, Instruction, ValType};
 
 impl Compiler {
-    pub(super) fn compile_statement(&mut self, stmt: ast::Statement) -> Result<(), CompilerError> {
+    pub(super) fn compile_statement(&mut self, stmt: &Statement) -> Result<(), String> {
+        if !matches!(stmt, Statement::Location(_) | Statement::Block(_)) {
+            self.emit_safepoint();
+        }
         match stmt {
-            ast::Statement::Expression(expr) => {
-                eprintln!("DEBUG: Compiling expression statement");
+            Statement::Expression(expr) => {
                 self.compile_expression(expr)?;
-                // Result is a Value pointer, decref and drop it
-                // TEMPORARILY DISABLED FOR DEBUGGING
-                // self.emit(Instruction::Call(self.decref_fn_idx));
-                self.emit(Instruction::Drop); // Just drop the value
-                eprintln!("DEBUG: Emitting Drop instruction");
+                self.emit(Instruction::Drop);
             }
-            ast::Statement::Echo(exprs) => {
+            Statement::Echo(exprs) => {
                 for expr in exprs {
-                    // Compile expression (returns Value pointer)
                     self.compile_expression(expr)?;
-                    
-                    // Debug: Print the value pointer
-                    eprintln!("DEBUG: Echo - value pointer on stack before to_string");
-                    
-                    // Convert to string if needed
-                    self.emit(Instruction::Call(self.to_string_fn_idx));
-                    
-                    // Now we have a string Value pointer on stack
-                    // We need to extract the actual string pointer and length
-                    let value_local = self.allocate_local(ValType::I32);
-                    self.emit(Instruction::LocalSet(value_local));
-                    
-                    // Load string pointer from value (at offset 8)
-                    self.emit(Instruction::LocalGet(value_local));
-                    self.emit(Instruction::I32Const(8));
-                    self.emit(Instruction::I32Add);
-                    self.emit(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-                    
-                    // The string pointer points to a string structure with length at offset 0
-                    let str_ptr_local = self.allocate_local(ValType::I32);
-                    self.emit(Instruction::LocalSet(str_ptr_local));
-                    
-                    // Load length from string structure
-                    self.emit(Instruction::LocalGet(str_ptr_local));
-                    self.emit(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-                    let len_local = self.allocate_local(ValType::I32);
-                    self.emit(Instruction::LocalSet(len_local));
-                    
-                    // Push string data pointer (string ptr + 8 for length + hash)
-                    self.emit(Instruction::LocalGet(str_ptr_local));
-                    self.emit(Instruction::I32Const(8));
-                    self.emit(Instruction::I32Add);
-                    
-                    // Push length
-                    self.emit(Instruction::LocalGet(len_local));
-                    
-                    // Call print(ptr, len)
-                    self.emit(Instruction::Call(self.print_fn_idx));
-                    
-                    // Decref the temporary string value
-                    self.emit(Instruction::LocalGet(value_local));
-                    self.emit(Instruction::Call(self.decref_fn_idx));
+                    self.emit(Instruction::Call(self.h.echo));
                 }
             }
-            ast::Statement::If { condition, then_block, else_block } => {
-                // Compile condition
-                self.compile_expression(condition)?;
-                
-                // Convert to boolean
-                self.emit(Instruction::Call(self.to_bool_fn_idx));
-                
-                // Load boolean value from Value
-                self.emit(Instruction::I32Const(4)); // Offset to data union
-                self.emit(Instruction::I32Add);
-                self.emit(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-                
-                // If statement
+            Statement::Return(value) => self.compile_return(value.as_ref())?,
+            Statement::If { condition, then_block, elseif_blocks, else_block } => {
+                self.compile_condition(condition)?;
                 self.emit(Instruction::If(BlockType::Empty));
-                
-                for stmt in then_block.statements {
-                    self.compile_statement(stmt)?;
+                self.compile_block(then_block)?;
+                for elseif in elseif_blocks {
+                    self.emit(Instruction::Else);
+                    self.compile_condition(&elseif.condition)?;
+                    self.emit(Instruction::If(BlockType::Empty));
+                    self.compile_block(&elseif.then_block)?;
                 }
-                
                 if let Some(else_block) = else_block {
                     self.emit(Instruction::Else);
-                    for stmt in else_block.statements {
-                        self.compile_statement(stmt)?;
-                    }
+                    self.compile_block(else_block)?;
                 }
-                
-                self.emit(Instruction::End);
+                for _ in 0..=elseif_blocks.len() {
+                    self.emit(Instruction::End);
+                }
+            }
+            Statement::While { condition, body } => {
+                self.begin_loop();
+                self.emit_safepoint();
+                self.compile_condition(condition)?;
+                self.emit_all([Instruction::I32Eqz, Instruction::BrIf(1)]);
+                self.compile_loop_body(body, false)?;
+                self.end_loop();
             }
-            ast::Statement::While { condition, body } => {
-                // Loop start
-                self.emit(Instruction::Loop(BlockType::Empty));
-                
-                // Compile condition
-                self.compile_expression(condition)?;
-                
-                // Convert to boolean
-                self.emit(Instruction::Call(self.to_bool_fn_idx));
-                
-                // Load boolean value
-                self.emit(Instruction::I32Const(4)); // Offset to data union
-                self.emit(Instruction::I32Add);
-                self.emit(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-                
-                // Break if false
-                self.emit(Instruction::I32Eqz);
-                self.emit(Instruction::BrIf(1)); // Break out of loop
-                
-                // Execute body
-                for stmt in body.statements {
-                    self.compile_statement(stmt)?;
+            Statement::DoWhile { body, condition } => {
+                self.begin_loop();
+                self.compile_loop_body(body, true)?;
+                self.emit_safepoint();
+                self.compile_condition(condition)?;
+                self.emit_all([Instruction::I32Eqz, Instruction::BrIf(1)]);
+                self.end_loop();
+            }
+            Statement::For { init, condition, update, body } => {
+                if let Some(init) = init {
+                    self.compile_statement(init)?;
+                }
+                self.begin_loop();
+                if let Some(condition) = condition {
+                    self.emit_safepoint();
+                    self.compile_condition(condition)?;
+                    self.emit_all([Instruction::I32Eqz, Instruction::BrIf(1)]);
                 }
-                
-                // Continue loop
-                self.emit(Instruction::Br(0));
-                self.emit(Instruction::End);
+                self.compile_loop_body(body, true)?;
+                if let Some(update) = update {
+                    self.emit_safepoint();
+                    self.compile_expression(update)?;
+                    self.emit(Instruction::Drop);
+                }
+                self.end_loop();
+            }
+            Statement::Foreach { array, key, value, body } => self.compile_foreach(array, key.as_deref(), value, body)?,
+            Statement::Switch { expr, cases } => self.compile_switch(expr, cases)?,
+            Statement::Break | Statement::Continue => {
+                let func = self.current_function.as_ref().unwrap();
+                let Some(&(break_depth, continue_depth)) = func.loops.last() else {
+                    let keyword = if matches!(stmt, Statement::Break) { "break" } else { "continue" };
+                    return Err(format!("'{}' not in the 'loop' or 'switch' context", keyword));
+                };
+                let target = if matches!(stmt, Statement::Break) { break_depth } else { continue_depth };
+                self.emit(Instruction::Br(func.depth - target));
             }
-            _ => {
-                return Err(CompilerError::CompilationError {
-                    message: format!("Statement type not implemented: {:?}", stmt)
-                });
+            Statement::Block(block) => self.compile_block(block)?,
+            Statement::Global(names) => self.compile_global(names),
+            Statement::Static(vars) => self.compile_static_vars(vars)?,
+            Statement::Location(location) => self.record_location(*location),
+            // Declarations are compiled before the code that runs
+            Statement::Function { .. } | Statement::Class { .. } | Statement::Enum { .. } | Statement::Interface { .. } => {}
+            Statement::Declare(_) | Statement::Use(_) | Statement::Namespace(_) => {}
+        }
+        Ok(())
+    }
+
+    fn compile_block(&mut self, block: &Block) -> Result<(), String> {
+        for stmt in &block.statements {
+            self.compile_statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    /// Drop the temporaries of the previous statement and let the collector run
+    fn emit_safepoint(&mut self) {
+        let func = self.current_function.as_ref().unwrap();
+        match &func.frame {
+            Some(frame) => self.emit(Instruction::LocalGet(frame.base)),
+            None => self.emit(Instruction::GlobalGet(self.g.stack_base)),
+        }
+        self.emit_all([Instruction::GlobalSet(self.g.sp), Instruction::Call(self.h.safepoint)]);
+    }
+
+    /// Open the `break` block and the loop; `continue` targets the loop until
+    /// the body says otherwise
+    fn begin_loop(&mut self) {
+        self.emit(Instruction::Block(BlockType::Empty));
+        let break_depth = self.depth();
+        self.emit(Instruction::Loop(BlockType::Empty));
+        let continue_depth = self.depth();
+        self.current_function.as_mut().unwrap().loops.push((break_depth, continue_depth));
+    }
+
+    fn end_loop(&mut self) {
+        self.emit_all([Instruction::Br(0), Instruction::End, Instruction::End]);
+        self.current_function.as_mut().unwrap().loops.pop();
+    }
+
+    /// The body of a loop. With `continue_after`, `continue` jumps past the
+    /// body to the code that follows it instead of back to the loop start.
+    fn compile_loop_body(&mut self, body: &Block, continue_after: bool) -> Result<(), String> {
+        if !continue_after {
+            return self.compile_block(body);
+        }
+        self.emit(Instruction::Block(BlockType::Empty));
+        let depth = self.depth();
+        let loops = &mut self.current_function.as_mut().unwrap().loops;
+        let (break_depth, _) = *loops.last().unwrap();
+        loops.push((break_depth, depth));
+        let result = self.compile_block(body);
+        self.current_function.as_mut().unwrap().loops.pop();
+        self.emit(Instruction::End);
+        result
+    }
+
+    /// `foreach`: iterates over a copy of the array, kept in a hidden slot
+    fn compile_foreach(&mut self, array: &Expression, key: Option<&str>, value: &Expression, body: &Block) -> Result<(), String> {
+        let copy = self.hidden_slot();
+        let (source, index, entry, item) = (
+            self.allocate_local(ValType::I32),
+            self.allocate_local(ValType::I32),
+            self.allocate_local(ValType::I32),
+            self.allocate_local(ValType::I32),
+        );
+        self.compile_argument(array)?;
+        self.emit(Instruction::LocalSet(source));
+        self.emit_store(copy, source);
+        self.emit_all([
+            Instruction::LocalGet(source),
+            Instruction::I32Load(mem32(0)),
+            Instruction::I32Const(TAG_ARRAY),
+            Instruction::I32Eq,
+            Instruction::If(BlockType::Empty),
+            Instruction::I32Const(0),
+            Instruction::LocalSet(index),
+        ]);
+        self.begin_loop();
+        self.emit_safepoint();
+        self.emit_load(copy);
+        self.emit_all([
+            Instruction::LocalTee(source),
+            Instruction::I32Load(mem32(ARR_USED)),
+            Instruction::LocalGet(index),
+            Instruction::I32LeU,
+            Instruction::BrIf(1),
+            Instruction::LocalGet(source),
+            Instruction::I32Load(mem32(ARR_ENTRIES)),
+            Instruction::LocalGet(index),
+            Instruction::I32Const(ENTRY_SIZE),
+            Instruction::I32Mul,
+            Instruction::I32Add,
+            Instruction::LocalSet(entry),
+            Instruction::LocalGet(index),
+            Instruction::I32Const(1),
+            Instruction::I32Add,
+            Instruction::LocalSet(index),
+            // Skip deleted entries
+            Instruction::LocalGet(entry),
+            Instruction::I32Load(mem32(ENTRY_KEY_STR)),
+            Instruction::I32Const(-1),
+            Instruction::I32Eq,
+            Instruction::BrIf(0),
+        ]);
+        if let Some(key) = key {
+            let loc = self.variable(key);
+            self.emit_all([
+                Instruction::LocalGet(entry),
+                Instruction::Call(self.h.entry_key),
+                Instruction::LocalSet(item),
+            ]);
+            self.emit_store(loc, item);
+        }
+        self.emit_all([
+            Instruction::LocalGet(entry),
+            Instruction::I32Load(mem32(ENTRY_VALUE)),
+            Instruction::Call(self.h.copy_value),
+            Instruction::LocalSet(item),
+        ]);
+        self.assign_to(value, item)?;
+        self.compile_block(body)?;
+        self.end_loop();
+        self.emit(Instruction::End);
+        // The copy is garbage once the loop is done
+        self.emit_all([Instruction::I32Const(NULL_VALUE as i32), Instruction::LocalSet(source)]);
+        self.emit_store(copy, source);
+        Ok(())
+    }
+
+    /// `switch`: find the first matching case, then run the bodies from there
+    fn compile_switch(&mut self, expr: &Expression, cases: &[SwitchCase]) -> Result<(), String> {
+        let subject = self.compile_to_local(expr)?;
+        let (matched, running) = (self.allocate_local(ValType::I32), self.allocate_local(ValType::I32));
+        self.emit_all([Instruction::I32Const(-1), Instruction::LocalSet(matched)]);
+        for (i, case) in cases.iter().enumerate() {
+            let Some(value) = &case.value else { continue };
+            self.emit_all([
+                Instruction::LocalGet(matched),
+                Instruction::I32Const(-1),
+                Instruction::I32Eq,
+                Instruction::If(BlockType::Empty),
+                Instruction::LocalGet(subject),
+            ]);
+            self.compile_expression(value)?;
+            self.emit_all([
+                Instruction::Call(self.h.loose_eq),
+                Instruction::If(BlockType::Empty),
+                Instruction::I32Const(i as i32),
+                Instruction::LocalSet(matched),
+                Instruction::End,
+                Instruction::End,
+            ]);
+        }
+        if let Some(default) = cases.iter().position(|case| case.value.is_none()) {
+            self.emit_all([
+                Instruction::LocalGet(matched),
+                Instruction::I32Const(-1),
+                Instruction::I32Eq,
+                Instruction::If(BlockType::Empty),
+                Instruction::I32Const(default as i32),
+                Instruction::LocalSet(matched),
+                Instruction::End,
+            ]);
+        }
+
+        // `break` and `continue` leave the switch
+        self.emit(Instruction::Block(BlockType::Empty));
+        let depth = self.depth();
+        self.current_function.as_mut().unwrap().loops.push((depth, depth));
+        self.emit_all([Instruction::I32Const(0), Instruction::LocalSet(running)]);
+        for (i, case) in cases.iter().enumerate() {
+            self.emit_all([
+                Instruction::LocalGet(matched),
+                Instruction::I32Const(i as i32),
+                Instruction::I32Eq,
+                Instruction::LocalGet(running),
+                Instruction::I32Or,
+                Instruction::LocalTee(running),
+                Instruction::If(BlockType::Empty),
+            ]);
+            for stmt in &case.statements {
+                self.compile_statement(stmt)?;
             }
+            self.emit(Instruction::End);
         }
+        self.current_function.as_mut().unwrap().loops.pop();
+        self.emit(Instruction::End);
         Ok(())
     }
 }
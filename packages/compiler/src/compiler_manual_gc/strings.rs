@@ -1,897 +1,877 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
+// Strings: conversions to and from numbers, concatenation, comparison and
+// the string builtins. Floats print like PHP with precision=14.
+
 use super::core::*;
-use wasm_encoder::*;
+use wasm_encoder::Instruction::*;
+use wasm_encoder::{BlockType, Instruction, ValType};
+
+/// Scratch areas for building number strings
+const OUTPUT: i32 = SCRATCH as i32;
+const DIGITS: i32 = SCRATCH as i32 + 0x100;
+
+/// Store a byte at `pos` and advance it
+fn put(pos: u32, byte: Vec<Instruction<'static>>) -> Vec<Instruction<'static>> {
+    let mut code = vec![LocalGet(pos)];
+    code.extend(byte);
+    code.extend([I32Store8(mem8(0)), LocalGet(pos), I32Const(1), I32Add, LocalSet(pos)]);
+    code
+}
+
+/// Whether the byte on the stack is PHP whitespace; consumes it via `c`
+fn is_space(c: u32) -> Vec<Instruction<'static>> {
+    vec![
+        LocalTee(c), I32Const(b' ' as i32), I32Eq,
+        LocalGet(c), I32Const(9), I32Sub, I32Const(4), I32LeU, I32Or,
+    ]
+}
 
 impl Compiler {
     pub(super) fn add_string_functions(&mut self) {
-        self.add_concat_function();
         self.add_to_string_function();
         self.add_int_to_string_function();
         self.add_float_to_string_function();
+        self.add_parse_number_function();
+        self.add_concat_function();
+        self.add_mem_eq_function();
+        self.add_str_eq_function();
+        self.add_str_cmp_function();
+        self.add_substr_function();
+        self.add_strpos_function();
+        self.add_case_function(self.h.strtolower, b'A', b'Z', 32);
+        self.add_case_function(self.h.strtoupper, b'a', b'z', -32);
+        self.add_trim_function();
+        self.add_str_replace_function();
+        self.add_explode_function();
+        self.add_implode_function();
     }
-    
-    fn add_concat_function(&mut self) {
-        let mut concat_body = vec![];
-        let mut concat_locals = vec![];
-        concat_locals.push((1, ValType::I32)); // result_ptr
-        concat_locals.push((1, ValType::I32)); // left_type
-        concat_locals.push((1, ValType::I32)); // right_type
-        concat_locals.push((1, ValType::I32)); // left_str
-        concat_locals.push((1, ValType::I32)); // right_str
-        concat_locals.push((1, ValType::I32)); // left_len
-        concat_locals.push((1, ValType::I32)); // right_len
-        concat_locals.push((1, ValType::I32)); // total_len
-        concat_locals.push((1, ValType::I32)); // result_str
-        concat_locals.push((1, ValType::I32)); // i
-        
-        // Get left type
-        concat_body.push(Instruction::LocalGet(0));
-        concat_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        concat_body.push(Instruction::LocalSet(3)); // left_type
-        
-        // Get right type
-        concat_body.push(Instruction::LocalGet(1));
-        concat_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        concat_body.push(Instruction::LocalSet(4)); // right_type
-        
-        // Convert left to string
-        concat_body.push(Instruction::LocalGet(3)); // left_type
-        concat_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        concat_body.push(Instruction::I32Eq);
-        concat_body.push(Instruction::If(BlockType::Empty));
-        // Already a string
-        concat_body.push(Instruction::LocalGet(0));
-        concat_body.push(Instruction::I32Const(8));
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        concat_body.push(Instruction::LocalSet(5)); // left_str
-        concat_body.push(Instruction::Else);
-        // Convert to string
-        concat_body.push(Instruction::LocalGet(0));
-        concat_body.push(Instruction::Call(self.to_string_fn_idx));
-        // to_string returns a PhpValue*, extract the string pointer from offset 8
-        concat_body.push(Instruction::I32Const(8));
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        concat_body.push(Instruction::LocalSet(5)); // left_str
-        concat_body.push(Instruction::End);
-        
-        // Convert right to string
-        concat_body.push(Instruction::LocalGet(4)); // right_type
-        concat_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        concat_body.push(Instruction::I32Eq);
-        concat_body.push(Instruction::If(BlockType::Empty));
-        // Already a string
-        concat_body.push(Instruction::LocalGet(1));
-        concat_body.push(Instruction::I32Const(8));
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        concat_body.push(Instruction::LocalSet(6)); // right_str
-        concat_body.push(Instruction::Else);
-        // Convert to string
-        concat_body.push(Instruction::LocalGet(1));
-        concat_body.push(Instruction::Call(self.to_string_fn_idx));
-        // to_string returns a PhpValue*, extract the string pointer from offset 8
-        concat_body.push(Instruction::I32Const(8));
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        concat_body.push(Instruction::LocalSet(6)); // right_str
-        concat_body.push(Instruction::End);
-        
-        // Get lengths
-        concat_body.push(Instruction::LocalGet(5)); // left_str
-        concat_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        concat_body.push(Instruction::LocalSet(7)); // left_len
-        
-        concat_body.push(Instruction::LocalGet(6)); // right_str
-        concat_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        concat_body.push(Instruction::LocalSet(8)); // right_len
-        
-        // Calculate total length
-        concat_body.push(Instruction::LocalGet(7)); // left_len
-        concat_body.push(Instruction::LocalGet(8)); // right_len
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::LocalSet(9)); // total_len
-        
-        // Allocate new string
-        concat_body.push(Instruction::I32Const(0)); // dummy data pointer
-        concat_body.push(Instruction::LocalGet(9)); // total_len
-        concat_body.push(Instruction::Call(self.alloc_string_fn_idx));
-        concat_body.push(Instruction::LocalSet(10)); // result_str
-        
-        // Copy left string data
-        concat_body.push(Instruction::I32Const(0));
-        concat_body.push(Instruction::LocalSet(11)); // i = 0
-        
-        concat_body.push(Instruction::Block(BlockType::Empty));
-        concat_body.push(Instruction::Loop(BlockType::Empty));
-        
-        concat_body.push(Instruction::LocalGet(11)); // i
-        concat_body.push(Instruction::LocalGet(7)); // left_len
-        concat_body.push(Instruction::I32LtU);
-        concat_body.push(Instruction::I32Eqz);
-        concat_body.push(Instruction::BrIf(1));
-        
-        // Copy byte
-        concat_body.push(Instruction::LocalGet(10)); // result_str
-        concat_body.push(Instruction::I32Const(8));
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::LocalGet(11)); // i
-        concat_body.push(Instruction::I32Add);
-        
-        concat_body.push(Instruction::LocalGet(5)); // left_str
-        concat_body.push(Instruction::I32Const(8));
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::LocalGet(11)); // i
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        concat_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // i++
-        concat_body.push(Instruction::LocalGet(11));
-        concat_body.push(Instruction::I32Const(1));
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::LocalSet(11));
-        
-        concat_body.push(Instruction::Br(0));
-        concat_body.push(Instruction::End);
-        concat_body.push(Instruction::End);
-        
-        // Copy right string data
-        concat_body.push(Instruction::I32Const(0));
-        concat_body.push(Instruction::LocalSet(11)); // i = 0
-        
-        concat_body.push(Instruction::Block(BlockType::Empty));
-        concat_body.push(Instruction::Loop(BlockType::Empty));
-        
-        concat_body.push(Instruction::LocalGet(11)); // i
-        concat_body.push(Instruction::LocalGet(8)); // right_len
-        concat_body.push(Instruction::I32LtU);
-        concat_body.push(Instruction::I32Eqz);
-        concat_body.push(Instruction::BrIf(1));
-        
-        // Copy byte
-        concat_body.push(Instruction::LocalGet(10)); // result_str
-        concat_body.push(Instruction::I32Const(8));
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::LocalGet(7)); // left_len offset
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::LocalGet(11)); // i
-        concat_body.push(Instruction::I32Add);
-        
-        concat_body.push(Instruction::LocalGet(6)); // right_str
-        concat_body.push(Instruction::I32Const(8));
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::LocalGet(11)); // i
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        concat_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // i++
-        concat_body.push(Instruction::LocalGet(11));
-        concat_body.push(Instruction::I32Const(1));
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::LocalSet(11));
-        
-        concat_body.push(Instruction::Br(0));
-        concat_body.push(Instruction::End);
-        concat_body.push(Instruction::End);
-        
-        // Allocate result PhpValue
-        concat_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        concat_body.push(Instruction::LocalSet(2)); // result_ptr
-        
-        // Set type to string
-        concat_body.push(Instruction::LocalGet(2));
-        concat_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        concat_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Store string pointer
-        concat_body.push(Instruction::LocalGet(2));
-        concat_body.push(Instruction::I32Const(8));
-        concat_body.push(Instruction::I32Add);
-        concat_body.push(Instruction::LocalGet(10)); // result_str
-        concat_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        // Return result
-        concat_body.push(Instruction::LocalGet(2));
-        
-        self.builder.set_function_at_index(self.concat_fn_idx, self.binary_op_type_idx, concat_locals, concat_body);
+
+    /// The powers of ten 10^0 ..= 10^308 as a static f64 table
+    fn powers_of_ten(&mut self) -> u32 {
+        let bytes: Vec<u8> = (0..=308)
+            .flat_map(|k| format!("1e{}", k).parse::<f64>().unwrap().to_le_bytes())
+            .collect();
+        let table = self.static_alloc(bytes.len() as u32);
+        self.static_write(table, &bytes);
+        table
     }
-    
+
     fn add_to_string_function(&mut self) {
-        let mut to_string_body = vec![];
-        let mut to_string_locals = vec![(1, ValType::I32), (1, ValType::I32), (1, ValType::I32), (1, ValType::I32)];
-        
-        // Get type
-        to_string_body.push(Instruction::LocalGet(0));
-        to_string_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        to_string_body.push(Instruction::LocalSet(1)); // type
-        
-        // Check if already string
-        to_string_body.push(Instruction::LocalGet(1));
-        to_string_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        to_string_body.push(Instruction::I32Eq);
-        to_string_body.push(Instruction::If(BlockType::Empty));
-        // Return the PhpValue pointer as-is
-        to_string_body.push(Instruction::LocalGet(0));
-        to_string_body.push(Instruction::Return);
-        to_string_body.push(Instruction::End);
-        
-        // Check if integer
-        to_string_body.push(Instruction::LocalGet(1));
-        to_string_body.push(Instruction::I32Const(TYPE_INT as i32));
-        to_string_body.push(Instruction::I32Eq);
-        to_string_body.push(Instruction::If(BlockType::Empty));
-        
-        // Load the integer value
-        to_string_body.push(Instruction::LocalGet(0));
-        to_string_body.push(Instruction::I32Const(4));
-        to_string_body.push(Instruction::I32Add);
-        to_string_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        
-        // Call int_to_string to convert
-        to_string_body.push(Instruction::Call(self.int_to_string_fn_idx));
-        
-        // Create PhpValue for the string
-        to_string_body.push(Instruction::LocalSet(2)); // Save string pointer
-        to_string_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        to_string_body.push(Instruction::LocalTee(3)); // Save PhpValue pointer
-        to_string_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Store string pointer in PhpValue at offset 8
-        to_string_body.push(Instruction::LocalGet(3));
-        to_string_body.push(Instruction::I32Const(8));
-        to_string_body.push(Instruction::I32Add);
-        to_string_body.push(Instruction::LocalGet(2));
-        to_string_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        to_string_body.push(Instruction::LocalGet(3));
-        to_string_body.push(Instruction::Return);
-        to_string_body.push(Instruction::End);
-        
-        // Check if float
-        to_string_body.push(Instruction::LocalGet(1));
-        to_string_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        to_string_body.push(Instruction::I32Eq);
-        to_string_body.push(Instruction::If(BlockType::Empty));
-        
-        // Convert float to string
-        to_string_body.push(Instruction::LocalGet(0));
-        to_string_body.push(Instruction::I32Const(4));
-        to_string_body.push(Instruction::I32Add);
-        to_string_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        to_string_body.push(Instruction::Call(self.float_to_string_fn_idx));
-        
-        // Create PhpValue for the string
-        to_string_body.push(Instruction::LocalSet(2)); // Save string pointer
-        to_string_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        to_string_body.push(Instruction::LocalTee(3)); // Save PhpValue pointer
-        to_string_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Store string pointer in PhpValue at offset 8
-        to_string_body.push(Instruction::LocalGet(3));
-        to_string_body.push(Instruction::I32Const(8));
-        to_string_body.push(Instruction::I32Add);
-        to_string_body.push(Instruction::LocalGet(2));
-        to_string_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        to_string_body.push(Instruction::LocalGet(3));
-        to_string_body.push(Instruction::Return);
-        to_string_body.push(Instruction::End);
-        
-        // Check if boolean
-        to_string_body.push(Instruction::LocalGet(1));
-        to_string_body.push(Instruction::I32Const(TYPE_BOOL as i32));
-        to_string_body.push(Instruction::I32Eq);
-        to_string_body.push(Instruction::If(BlockType::Empty));
-        
-        // Load boolean value
-        to_string_body.push(Instruction::LocalGet(0));
-        to_string_body.push(Instruction::I32Const(4));
-        to_string_body.push(Instruction::I32Add);
-        to_string_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // If false (0), return empty string, else return "1"
-        to_string_body.push(Instruction::If(BlockType::Empty));
-        
-        // True case - return "1"
-        to_string_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        to_string_body.push(Instruction::LocalTee(2));
-        to_string_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        to_string_body.push(Instruction::LocalGet(2));
-        to_string_body.push(Instruction::I32Const(8)); // Offset 8 for string pointer
-        to_string_body.push(Instruction::I32Add);
-        to_string_body.push(Instruction::I32Const(0x2500)); // "1" string at 0x2500
-        to_string_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        to_string_body.push(Instruction::LocalGet(2));
-        to_string_body.push(Instruction::Return);
-        
-        to_string_body.push(Instruction::Else);
-        
-        // False case - return empty string
-        to_string_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        to_string_body.push(Instruction::LocalTee(2));
-        to_string_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        to_string_body.push(Instruction::LocalGet(2));
-        to_string_body.push(Instruction::I32Const(8)); // Offset 8 for string pointer
-        to_string_body.push(Instruction::I32Add);
-        to_string_body.push(Instruction::I32Const(0x2400)); // Empty string at 0x2400
-        to_string_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        to_string_body.push(Instruction::LocalGet(2));
-        to_string_body.push(Instruction::Return);
-        
-        to_string_body.push(Instruction::End); // End If (true/false)
-        to_string_body.push(Instruction::End); // End If (boolean check)
-        
-        // Check if null
-        to_string_body.push(Instruction::LocalGet(1));
-        to_string_body.push(Instruction::I32Const(TYPE_NULL as i32));
-        to_string_body.push(Instruction::I32Eq);
-        to_string_body.push(Instruction::If(BlockType::Empty));
-        
-        // Return empty string for null
-        to_string_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        to_string_body.push(Instruction::LocalTee(2));
-        to_string_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        to_string_body.push(Instruction::LocalGet(2));
-        to_string_body.push(Instruction::I32Const(8)); // Offset 8 for string pointer
-        to_string_body.push(Instruction::I32Add);
-        to_string_body.push(Instruction::I32Const(0x2400)); // Empty string at 0x2400
-        to_string_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        to_string_body.push(Instruction::LocalGet(2));
-        to_string_body.push(Instruction::Return);
-        to_string_body.push(Instruction::End);
-        
-        // For other types, return empty string for now
-        to_string_body.push(Instruction::I32Const(0));
-        to_string_body.push(Instruction::I32Const(0));
-        to_string_body.push(Instruction::Call(self.alloc_string_fn_idx));
-        
-        // Create PhpValue for the string
-        to_string_body.push(Instruction::LocalSet(2)); // Save string pointer
-        to_string_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        to_string_body.push(Instruction::LocalTee(3)); // Save PhpValue pointer
-        to_string_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Store string pointer in PhpValue at offset 8
-        to_string_body.push(Instruction::LocalGet(3));
-        to_string_body.push(Instruction::I32Const(8));
-        to_string_body.push(Instruction::I32Add);
-        to_string_body.push(Instruction::LocalGet(2));
-        to_string_body.push(Instruction::I32Store(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        
-        to_string_body.push(Instruction::LocalGet(3));
-        
-        self.builder.set_function_at_index(self.to_string_fn_idx, self.unary_op_type_idx, to_string_locals, to_string_body);
+        // to_string(value) -> string value
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (value, tag) = (0, 1);
+        let empty = self.string_literal("");
+        let one = self.string_literal("1");
+        let array = self.string_literal("Array");
+        let body = vec![
+            LocalGet(value), I32Load(mem32(0)), LocalTee(tag), I32Const(TAG_STRING), I32Eq,
+            If(BlockType::Empty), LocalGet(value), Return, End,
+            LocalGet(tag), I32Const(TAG_INT), I32Eq,
+            If(BlockType::Empty), LocalGet(value), I64Load(mem64(VALUE_DATA)), Call(self.h.int_to_string), Return, End,
+            LocalGet(tag), I32Const(TAG_FLOAT), I32Eq,
+            If(BlockType::Empty), LocalGet(value), F64Load(mem64(VALUE_DATA)), Call(self.h.float_to_string), Return, End,
+            LocalGet(tag), I32Const(TAG_BOOL), I32Eq,
+            If(BlockType::Empty),
+                I32Const(one as i32), I32Const(empty as i32), LocalGet(value), I64Load(mem64(VALUE_DATA)), I32WrapI64, Select,
+                Return,
+            End,
+            LocalGet(tag), I32Const(TAG_ARRAY), I32Eq,
+            If(BlockType::Empty), I32Const(array as i32), Return, End,
+            LocalGet(tag), I32Const(TAG_OBJECT), I32Eq,
+            If(BlockType::Empty), LocalGet(value), Call(self.h.object_to_string), Return, End,
+            I32Const(empty as i32),
+        ];
+        self.builder.set_function_at_index(self.h.to_string, ty, vec![(1, ValType::I32)], body);
     }
-    
+
     fn add_int_to_string_function(&mut self) {
-        let int_to_string_type_idx = self.builder.add_type(vec![ValType::I64], vec![ValType::I32]);
-        
-        let mut int_to_string_body = vec![];
-        let mut int_to_string_locals = vec![];
-        int_to_string_locals.push((1, ValType::I64)); // value (param copy)
-        int_to_string_locals.push((1, ValType::I32)); // is_negative
-        int_to_string_locals.push((1, ValType::I32)); // digit_count
-        int_to_string_locals.push((1, ValType::I64)); // temp
-        int_to_string_locals.push((1, ValType::I32)); // total_len
-        int_to_string_locals.push((1, ValType::I32)); // string_ptr
-        int_to_string_locals.push((1, ValType::I32)); // write_pos
-        int_to_string_locals.push((1, ValType::I32)); // digit
-        
-        // Parameter 0 is the i64 value
-        // Copy parameter to local for modification
-        int_to_string_body.push(Instruction::LocalGet(0));
-        int_to_string_body.push(Instruction::LocalSet(1)); // value
-        
-        // Special case for 0
-        int_to_string_body.push(Instruction::LocalGet(1));
-        int_to_string_body.push(Instruction::I64Const(0));
-        int_to_string_body.push(Instruction::I64Eq);
-        int_to_string_body.push(Instruction::If(BlockType::Empty));
-        
-        // Allocate "0" string
-        int_to_string_body.push(Instruction::I32Const(0x3000)); // Static "0" location
-        int_to_string_body.push(Instruction::I32Const(1)); // length 1
-        int_to_string_body.push(Instruction::Call(self.alloc_string_fn_idx));
-        int_to_string_body.push(Instruction::Return);
-        int_to_string_body.push(Instruction::End);
-        
-        // Check if negative
-        int_to_string_body.push(Instruction::LocalGet(1));
-        int_to_string_body.push(Instruction::I64Const(0));
-        int_to_string_body.push(Instruction::I64LtS);
-        int_to_string_body.push(Instruction::LocalSet(2)); // is_negative
-        
-        // If negative, make positive
-        int_to_string_body.push(Instruction::LocalGet(2));
-        int_to_string_body.push(Instruction::If(BlockType::Empty));
-        int_to_string_body.push(Instruction::I64Const(0));
-        int_to_string_body.push(Instruction::LocalGet(1));
-        int_to_string_body.push(Instruction::I64Sub);
-        int_to_string_body.push(Instruction::LocalSet(1)); // value = -value
-        int_to_string_body.push(Instruction::End);
-        
-        // Count digits
-        int_to_string_body.push(Instruction::LocalGet(1));
-        int_to_string_body.push(Instruction::LocalSet(4)); // temp = value
-        int_to_string_body.push(Instruction::I32Const(0));
-        int_to_string_body.push(Instruction::LocalSet(3)); // digit_count = 0
-        
-        // Digit counting loop
-        int_to_string_body.push(Instruction::Block(BlockType::Empty)); // Wrapper block
-        int_to_string_body.push(Instruction::Loop(BlockType::Empty));
-        int_to_string_body.push(Instruction::LocalGet(3));
-        int_to_string_body.push(Instruction::I32Const(1));
-        int_to_string_body.push(Instruction::I32Add);
-        int_to_string_body.push(Instruction::LocalSet(3)); // digit_count++
-        
-        int_to_string_body.push(Instruction::LocalGet(4));
-        int_to_string_body.push(Instruction::I64Const(10));
-        int_to_string_body.push(Instruction::I64DivU);
-        int_to_string_body.push(Instruction::LocalSet(4)); // temp /= 10
-        
-        int_to_string_body.push(Instruction::LocalGet(4));
-        int_to_string_body.push(Instruction::I64Const(0));
-        int_to_string_body.push(Instruction::I64GtU);
-        int_to_string_body.push(Instruction::BrIf(0)); // Continue if temp > 0
-        int_to_string_body.push(Instruction::End); // End loop
-        int_to_string_body.push(Instruction::End); // End block
-        
-        // Calculate total length
-        int_to_string_body.push(Instruction::LocalGet(3)); // digit_count
-        int_to_string_body.push(Instruction::LocalGet(2)); // is_negative
-        int_to_string_body.push(Instruction::I32Add); // Add 1 if negative for '-'
-        int_to_string_body.push(Instruction::LocalSet(5)); // total_len
-        
-        // Write digits in reverse order
-        int_to_string_body.push(Instruction::I32Const(0x3100));
-        int_to_string_body.push(Instruction::LocalGet(5)); // total_len
-        int_to_string_body.push(Instruction::I32Add);
-        int_to_string_body.push(Instruction::I32Const(1));
-        int_to_string_body.push(Instruction::I32Sub); // Last position
-        int_to_string_body.push(Instruction::LocalSet(7)); // write_pos
-        
-        // Write digits loop
-        int_to_string_body.push(Instruction::Block(BlockType::Empty)); // Wrapper block
-        int_to_string_body.push(Instruction::Loop(BlockType::Empty));
-        
-        // Get digit: value % 10
-        int_to_string_body.push(Instruction::LocalGet(1));
-        int_to_string_body.push(Instruction::I64Const(10));
-        int_to_string_body.push(Instruction::I64RemU);
-        int_to_string_body.push(Instruction::I32WrapI64);
-        int_to_string_body.push(Instruction::LocalSet(8)); // digit
-        
-        // Write digit as ASCII
-        int_to_string_body.push(Instruction::LocalGet(7)); // write_pos
-        int_to_string_body.push(Instruction::LocalGet(8)); // digit
-        int_to_string_body.push(Instruction::I32Const(48)); // '0'
-        int_to_string_body.push(Instruction::I32Add);
-        int_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // write_pos--
-        int_to_string_body.push(Instruction::LocalGet(7));
-        int_to_string_body.push(Instruction::I32Const(1));
-        int_to_string_body.push(Instruction::I32Sub);
-        int_to_string_body.push(Instruction::LocalSet(7));
-        
-        // value /= 10
-        int_to_string_body.push(Instruction::LocalGet(1));
-        int_to_string_body.push(Instruction::I64Const(10));
-        int_to_string_body.push(Instruction::I64DivU);
-        int_to_string_body.push(Instruction::LocalSet(1));
-        
-        // Continue if value > 0
-        int_to_string_body.push(Instruction::LocalGet(1));
-        int_to_string_body.push(Instruction::I64Const(0));
-        int_to_string_body.push(Instruction::I64GtU);
-        int_to_string_body.push(Instruction::BrIf(0));
-        int_to_string_body.push(Instruction::End); // End loop
-        int_to_string_body.push(Instruction::End); // End block
-        
-        // Add negative sign if needed
-        int_to_string_body.push(Instruction::LocalGet(2)); // is_negative
-        int_to_string_body.push(Instruction::If(BlockType::Empty));
-        int_to_string_body.push(Instruction::LocalGet(7)); // write_pos
-        int_to_string_body.push(Instruction::I32Const(45)); // '-'
-        int_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        int_to_string_body.push(Instruction::End);
-        
-        // Allocate final string and copy from buffer
-        int_to_string_body.push(Instruction::I32Const(0x3100)); // Buffer start
-        int_to_string_body.push(Instruction::LocalGet(5)); // total_len
-        int_to_string_body.push(Instruction::Call(self.alloc_string_fn_idx));
-        
-        self.builder.set_function_at_index(self.int_to_string_fn_idx, int_to_string_type_idx, int_to_string_locals, int_to_string_body);
+        // int_to_string(i64) -> string value
+        let ty = self.builder.add_type(vec![ValType::I64], vec![ValType::I32]);
+        let (x, magnitude) = (0, 1);
+        let (pos, string) = (2, 3);
+        let body = vec![
+            LocalGet(x), I64Const(0), LocalGet(x), I64Sub, LocalGet(x), I64Const(0), I64GeS, Select, LocalSet(magnitude),
+            I32Const(DIGITS), LocalSet(pos),
+            Loop(BlockType::Empty),
+                LocalGet(pos), I32Const(1), I32Sub, LocalTee(pos),
+                LocalGet(magnitude), I64Const(10), I64RemU, I32WrapI64, I32Const(b'0' as i32), I32Add,
+                I32Store8(mem8(0)),
+                LocalGet(magnitude), I64Const(10), I64DivU, LocalTee(magnitude), I64Const(0), I64Ne, BrIf(0),
+            End,
+            LocalGet(x), I64Const(0), I64LtS,
+            If(BlockType::Empty),
+                LocalGet(pos), I32Const(1), I32Sub, LocalTee(pos), I32Const(b'-' as i32), I32Store8(mem8(0)),
+            End,
+            I32Const(DIGITS), LocalGet(pos), I32Sub, Call(self.h.new_string), LocalTee(string),
+            I32Const(STR_BYTES as i32), I32Add, LocalGet(pos), I32Const(DIGITS), LocalGet(pos), I32Sub, MEMORY_COPY,
+            LocalGet(string),
+        ];
+        let locals = vec![(1, ValType::I64), (2, ValType::I32)];
+        self.builder.set_function_at_index(self.h.int_to_string, ty, locals, body);
     }
-    
+
     fn add_float_to_string_function(&mut self) {
-        let float_to_string_type_idx = self.builder.add_type(vec![ValType::F64], vec![ValType::I32]);
-        
-        let mut float_to_string_body = vec![];
-        let mut float_to_string_locals = vec![];
-        float_to_string_locals.push((1, ValType::F64)); // value (param copy)
-        float_to_string_locals.push((1, ValType::I64)); // integer part
-        float_to_string_locals.push((1, ValType::I32)); // string_ptr
-        float_to_string_locals.push((1, ValType::I32)); // write_pos
-        float_to_string_locals.push((1, ValType::I32)); // is_negative
-        float_to_string_locals.push((1, ValType::F64)); // fractional part
-        float_to_string_locals.push((1, ValType::I32)); // decimal_digits
-        float_to_string_locals.push((1, ValType::I32)); // i
-        float_to_string_locals.push((1, ValType::I32)); // digit (for decimal extraction)
-        
-        // Parameter 0 is the f64 value
-        // Copy parameter to local for modification
-        float_to_string_body.push(Instruction::LocalGet(0));
-        float_to_string_body.push(Instruction::LocalSet(1)); // value
-        
-        // Check for infinity
-        float_to_string_body.push(Instruction::LocalGet(1));
-        float_to_string_body.push(Instruction::F64Const(f64::INFINITY.into()));
-        float_to_string_body.push(Instruction::F64Eq);
-        float_to_string_body.push(Instruction::If(BlockType::Empty));
-        // Return "INF" - write to buffer at 0x3200
-        float_to_string_body.push(Instruction::I32Const(0x3200));
-        float_to_string_body.push(Instruction::I32Const(73)); // 'I'
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        float_to_string_body.push(Instruction::I32Const(0x3201));
-        float_to_string_body.push(Instruction::I32Const(78)); // 'N'
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        float_to_string_body.push(Instruction::I32Const(0x3202));
-        float_to_string_body.push(Instruction::I32Const(70)); // 'F'
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        float_to_string_body.push(Instruction::I32Const(0x3200));
-        float_to_string_body.push(Instruction::I32Const(3));
-        float_to_string_body.push(Instruction::Call(self.alloc_string_fn_idx));
-        float_to_string_body.push(Instruction::Return);
-        float_to_string_body.push(Instruction::End);
-        
-        // Check for negative infinity
-        float_to_string_body.push(Instruction::LocalGet(1));
-        float_to_string_body.push(Instruction::F64Const(f64::NEG_INFINITY.into()));
-        float_to_string_body.push(Instruction::F64Eq);
-        float_to_string_body.push(Instruction::If(BlockType::Empty));
-        // Return "-INF" - write to buffer at 0x3200
-        float_to_string_body.push(Instruction::I32Const(0x3200));
-        float_to_string_body.push(Instruction::I32Const(45)); // '-'
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        float_to_string_body.push(Instruction::I32Const(0x3201));
-        float_to_string_body.push(Instruction::I32Const(73)); // 'I'
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        float_to_string_body.push(Instruction::I32Const(0x3202));
-        float_to_string_body.push(Instruction::I32Const(78)); // 'N'
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        float_to_string_body.push(Instruction::I32Const(0x3203));
-        float_to_string_body.push(Instruction::I32Const(70)); // 'F'
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        float_to_string_body.push(Instruction::I32Const(0x3200));
-        float_to_string_body.push(Instruction::I32Const(4));
-        float_to_string_body.push(Instruction::Call(self.alloc_string_fn_idx));
-        float_to_string_body.push(Instruction::Return);
-        float_to_string_body.push(Instruction::End);
-        
-        // Check if it's a whole number (no fractional part)
-        float_to_string_body.push(Instruction::LocalGet(1));
-        float_to_string_body.push(Instruction::F64Trunc);
-        float_to_string_body.push(Instruction::LocalGet(1));
-        float_to_string_body.push(Instruction::F64Eq);
-        float_to_string_body.push(Instruction::If(BlockType::Empty));
-        
-        // Whole number - convert to integer and use int_to_string
-        float_to_string_body.push(Instruction::LocalGet(1));
-        float_to_string_body.push(Instruction::I64TruncF64S);
-        float_to_string_body.push(Instruction::Call(self.int_to_string_fn_idx));
-        float_to_string_body.push(Instruction::Return);
-        float_to_string_body.push(Instruction::End);
-        
-        // For floats with decimals, we need to format them properly
-        // PHP typically shows up to 14 significant digits
-        
-        // Check if negative
-        float_to_string_body.push(Instruction::LocalGet(1));
-        float_to_string_body.push(Instruction::F64Const(0.0.into()));
-        float_to_string_body.push(Instruction::F64Lt);
-        float_to_string_body.push(Instruction::LocalSet(5)); // is_negative
-        
-        // Make positive if negative
-        float_to_string_body.push(Instruction::LocalGet(5));
-        float_to_string_body.push(Instruction::If(BlockType::Empty));
-        float_to_string_body.push(Instruction::LocalGet(1));
-        float_to_string_body.push(Instruction::F64Neg);
-        float_to_string_body.push(Instruction::LocalSet(1));
-        float_to_string_body.push(Instruction::End);
-        
-        // Get integer part
-        float_to_string_body.push(Instruction::LocalGet(1));
-        float_to_string_body.push(Instruction::I64TruncF64S);
-        float_to_string_body.push(Instruction::LocalSet(2)); // integer_part
-        
-        // Get fractional part
-        float_to_string_body.push(Instruction::LocalGet(1));
-        float_to_string_body.push(Instruction::LocalGet(2));
-        float_to_string_body.push(Instruction::F64ConvertI64S);
-        float_to_string_body.push(Instruction::F64Sub);
-        float_to_string_body.push(Instruction::LocalSet(6)); // fractional_part
-        
-        // Convert integer part to string
-        float_to_string_body.push(Instruction::LocalGet(2));
-        float_to_string_body.push(Instruction::Call(self.int_to_string_fn_idx));
-        float_to_string_body.push(Instruction::LocalSet(3)); // int_str_ptr
-        
-        // Extract decimal digits with proper precision
-        // PHP shows up to 14 significant digits
-        float_to_string_body.push(Instruction::LocalGet(3));
-        float_to_string_body.push(Instruction::LocalSet(4)); // int_str_ptr
-        
-        // Use static buffer at 0x3200 to build the full string
-        float_to_string_body.push(Instruction::I32Const(0x3200));
-        float_to_string_body.push(Instruction::LocalSet(4)); // write_pos
-        
-        // Add negative sign if needed
-        float_to_string_body.push(Instruction::LocalGet(5));
-        float_to_string_body.push(Instruction::If(BlockType::Empty));
-        float_to_string_body.push(Instruction::LocalGet(4));
-        float_to_string_body.push(Instruction::I32Const(45)); // '-'
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        float_to_string_body.push(Instruction::LocalGet(4));
-        float_to_string_body.push(Instruction::I32Const(1));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::LocalSet(4));
-        float_to_string_body.push(Instruction::End);
-        
-        // Copy integer part
-        float_to_string_body.push(Instruction::LocalGet(3));
-        float_to_string_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        float_to_string_body.push(Instruction::LocalSet(7)); // int_len
-        
-        float_to_string_body.push(Instruction::I32Const(0));
-        float_to_string_body.push(Instruction::LocalSet(8)); // i = 0
-        
-        float_to_string_body.push(Instruction::Block(BlockType::Empty));
-        float_to_string_body.push(Instruction::Loop(BlockType::Empty));
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::LocalGet(7));
-        float_to_string_body.push(Instruction::I32GeU);
-        float_to_string_body.push(Instruction::BrIf(1));
-        
-        float_to_string_body.push(Instruction::LocalGet(4));
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::I32Add);
-        
-        float_to_string_body.push(Instruction::LocalGet(3));
-        float_to_string_body.push(Instruction::I32Const(8));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::I32Const(1));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::LocalSet(8));
-        float_to_string_body.push(Instruction::Br(0));
-        float_to_string_body.push(Instruction::End);
-        float_to_string_body.push(Instruction::End);
-        
-        // Update write position
-        float_to_string_body.push(Instruction::LocalGet(4));
-        float_to_string_body.push(Instruction::LocalGet(7));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::LocalSet(4)); // write_pos after integer part
-        
-        // Check if we have a fractional part
-        float_to_string_body.push(Instruction::LocalGet(6));
-        float_to_string_body.push(Instruction::F64Const(0.0.into()));
-        float_to_string_body.push(Instruction::F64Gt);
-        float_to_string_body.push(Instruction::If(BlockType::Empty));
-        
-        // Add decimal point
-        float_to_string_body.push(Instruction::LocalGet(4));
-        float_to_string_body.push(Instruction::I32Const(46)); // '.'
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        float_to_string_body.push(Instruction::LocalGet(4));
-        float_to_string_body.push(Instruction::I32Const(1));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::LocalSet(4));
-        
-        // Extract decimal digits (up to 14 significant digits)
-        // For simplicity, we'll extract up to 6 decimal places
-        float_to_string_body.push(Instruction::LocalGet(6)); // fractional part
-        float_to_string_body.push(Instruction::LocalSet(6)); // working value
-        float_to_string_body.push(Instruction::I32Const(0));
-        float_to_string_body.push(Instruction::LocalSet(7)); // decimal_count = 0
-        float_to_string_body.push(Instruction::I32Const(6)); // max decimals
-        float_to_string_body.push(Instruction::LocalSet(8)); // max_decimals
-        
-        // Extract decimal digits loop
-        float_to_string_body.push(Instruction::Block(BlockType::Empty));
-        float_to_string_body.push(Instruction::Loop(BlockType::Empty));
-        
-        // Check if decimal_count >= max_decimals
-        float_to_string_body.push(Instruction::LocalGet(7));
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::I32GeU);
-        float_to_string_body.push(Instruction::BrIf(1));
-        
-        // Multiply by 10
-        float_to_string_body.push(Instruction::LocalGet(6));
-        float_to_string_body.push(Instruction::F64Const(10.0.into()));
-        float_to_string_body.push(Instruction::F64Mul);
-        float_to_string_body.push(Instruction::LocalSet(6));
-        
-        // Get digit
-        float_to_string_body.push(Instruction::LocalGet(6));
-        float_to_string_body.push(Instruction::I32TruncF64S);
-        float_to_string_body.push(Instruction::LocalTee(9)); // digit
-        
-        // Write digit
-        float_to_string_body.push(Instruction::LocalGet(4));
-        float_to_string_body.push(Instruction::LocalGet(7));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::LocalGet(9));
-        float_to_string_body.push(Instruction::I32Const(48)); // '0'
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Subtract digit from working value
-        float_to_string_body.push(Instruction::LocalGet(6));
-        float_to_string_body.push(Instruction::LocalGet(9));
-        float_to_string_body.push(Instruction::F64ConvertI32S);
-        float_to_string_body.push(Instruction::F64Sub);
-        float_to_string_body.push(Instruction::LocalSet(6));
-        
-        // Increment decimal count
-        float_to_string_body.push(Instruction::LocalGet(7));
-        float_to_string_body.push(Instruction::I32Const(1));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::LocalSet(7));
-        
-        // Check if remaining is very small (< 0.000001)
-        float_to_string_body.push(Instruction::LocalGet(6));
-        float_to_string_body.push(Instruction::F64Const(0.000001.into()));
-        float_to_string_body.push(Instruction::F64Lt);
-        float_to_string_body.push(Instruction::BrIf(1)); // Exit if very small
-        
-        float_to_string_body.push(Instruction::Br(0));
-        float_to_string_body.push(Instruction::End);
-        float_to_string_body.push(Instruction::End);
-        
-        // Remove trailing zeros
-        float_to_string_body.push(Instruction::LocalGet(4));
-        float_to_string_body.push(Instruction::LocalGet(7));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::I32Const(1));
-        float_to_string_body.push(Instruction::I32Sub);
-        float_to_string_body.push(Instruction::LocalSet(8)); // last_pos
-        
-        float_to_string_body.push(Instruction::Block(BlockType::Empty));
-        float_to_string_body.push(Instruction::Loop(BlockType::Empty));
-        
-        // Check if we're at decimal point
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::LocalGet(4));
-        float_to_string_body.push(Instruction::I32LeU);
-        float_to_string_body.push(Instruction::BrIf(1));
-        
-        // Check if last digit is '0'
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        float_to_string_body.push(Instruction::I32Const(48)); // '0'
-        float_to_string_body.push(Instruction::I32Ne);
-        float_to_string_body.push(Instruction::BrIf(1)); // Exit if not '0'
-        
-        // Decrement position and decimal count
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::I32Const(1));
-        float_to_string_body.push(Instruction::I32Sub);
-        float_to_string_body.push(Instruction::LocalSet(8));
-        float_to_string_body.push(Instruction::LocalGet(7));
-        float_to_string_body.push(Instruction::I32Const(1));
-        float_to_string_body.push(Instruction::I32Sub);
-        float_to_string_body.push(Instruction::LocalSet(7));
-        
-        float_to_string_body.push(Instruction::Br(0));
-        float_to_string_body.push(Instruction::End);
-        float_to_string_body.push(Instruction::End);
-        
-        // Update write position to after decimal digits
-        float_to_string_body.push(Instruction::LocalGet(4));
-        float_to_string_body.push(Instruction::LocalGet(7));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::LocalSet(4));
-        
-        // Calculate total length
-        float_to_string_body.push(Instruction::LocalGet(4));
-        float_to_string_body.push(Instruction::I32Const(0x3200));
-        float_to_string_body.push(Instruction::I32Sub);
-        
-        // Allocate and return
-        float_to_string_body.push(Instruction::LocalSet(7)); // Save length
-        float_to_string_body.push(Instruction::I32Const(0x3200));
-        float_to_string_body.push(Instruction::LocalGet(7));
-        float_to_string_body.push(Instruction::Call(self.alloc_string_fn_idx));
-        float_to_string_body.push(Instruction::Return);
-        
-        float_to_string_body.push(Instruction::Else);
-        
-        // No fractional part - handle negative sign for whole numbers
-        float_to_string_body.push(Instruction::LocalGet(5)); // is_negative
-        float_to_string_body.push(Instruction::If(BlockType::Empty));
-        
-        // Negative whole number - create new string with minus sign
-        float_to_string_body.push(Instruction::LocalGet(3));
-        float_to_string_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 }));
-        float_to_string_body.push(Instruction::LocalSet(7)); // int_len
-        
-        // Write minus sign
-        float_to_string_body.push(Instruction::I32Const(0x3200));
-        float_to_string_body.push(Instruction::I32Const(45)); // '-'
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Copy integer string
-        float_to_string_body.push(Instruction::I32Const(0));
-        float_to_string_body.push(Instruction::LocalSet(8)); // i = 0
-        
-        float_to_string_body.push(Instruction::Block(BlockType::Empty));
-        float_to_string_body.push(Instruction::Loop(BlockType::Empty));
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::LocalGet(7));
-        float_to_string_body.push(Instruction::I32GeU);
-        float_to_string_body.push(Instruction::BrIf(1));
-        
-        float_to_string_body.push(Instruction::I32Const(0x3201)); // after minus sign
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::I32Add);
-        
-        float_to_string_body.push(Instruction::LocalGet(3));
-        float_to_string_body.push(Instruction::I32Const(8));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        float_to_string_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        float_to_string_body.push(Instruction::LocalGet(8));
-        float_to_string_body.push(Instruction::I32Const(1));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::LocalSet(8));
-        float_to_string_body.push(Instruction::Br(0));
-        float_to_string_body.push(Instruction::End);
-        float_to_string_body.push(Instruction::End);
-        
-        float_to_string_body.push(Instruction::I32Const(0x3200));
-        float_to_string_body.push(Instruction::LocalGet(7));
-        float_to_string_body.push(Instruction::I32Const(1));
-        float_to_string_body.push(Instruction::I32Add);
-        float_to_string_body.push(Instruction::Call(self.alloc_string_fn_idx));
-        float_to_string_body.push(Instruction::Return);
-        
-        float_to_string_body.push(Instruction::End);
-        
-        // Positive whole number - just return integer string
-        float_to_string_body.push(Instruction::LocalGet(3));
-        float_to_string_body.push(Instruction::Return);
-        
-        float_to_string_body.push(Instruction::End); // End has fractional/else
-        
-        // Default return (should not reach here)
-        float_to_string_body.push(Instruction::LocalGet(3));
-        
-        self.builder.set_function_at_index(self.float_to_string_fn_idx, float_to_string_type_idx, float_to_string_locals, float_to_string_body);
+        // float_to_string(f64) -> string value, formatted like PHP's "%.14G"
+        let ty = self.builder.add_type(vec![ValType::F64], vec![ValType::I32]);
+        let (x, y) = (0, 1);
+        let m = 2;
+        let (e, n, pos, i, k, string) = (3, 4, 5, 6, 7, 8);
+        let p10 = self.powers_of_ten() as u64;
+        let nan = self.string_literal("NAN");
+        let inf = self.string_literal("INF");
+        let minus_inf = self.string_literal("-INF");
+        let zero = self.string_literal("0");
+        let minus_zero = self.string_literal("-0");
+        let digit = |index: Vec<Instruction<'static>>| {
+            let mut code = vec![I32Const(DIGITS)];
+            code.extend(index);
+            code.extend([I32Add, I32Load8U(mem8(0))]);
+            code
+        };
+        let mut body = vec![
+            LocalGet(x), LocalGet(x), F64Ne,
+            If(BlockType::Empty), I32Const(nan as i32), Return, End,
+            LocalGet(x), F64Abs, F64Const(f64::INFINITY.into()), F64Eq,
+            If(BlockType::Empty),
+                I32Const(inf as i32), I32Const(minus_inf as i32), LocalGet(x), F64Const(0.0.into()), F64Gt, Select, Return,
+            End,
+            LocalGet(x), F64Const(0.0.into()), F64Eq,
+            If(BlockType::Empty),
+                I32Const(minus_zero as i32), I32Const(zero as i32), LocalGet(x), I64ReinterpretF64, I64Const(0), I64LtS, Select,
+                Return,
+            End,
+            LocalGet(x), F64Abs, LocalSet(y),
+
+            // Decimal exponent: 10^e <= y < 10^(e+1)
+            LocalGet(y), F64Const(1.0.into()), F64Ge,
+            If(BlockType::Empty),
+                Block(BlockType::Empty),
+                Loop(BlockType::Empty),
+                    LocalGet(e), I32Const(308), I32GeS, BrIf(1),
+                    LocalGet(e), I32Const(1), I32Add, I32Const(3), I32Shl, F64Load(mem64(p10)), LocalGet(y), F64Gt, BrIf(1),
+                    LocalGet(e), I32Const(1), I32Add, LocalSet(e),
+                    Br(0),
+                End,
+                End,
+            Else,
+                I32Const(-1), LocalSet(e),
+                Block(BlockType::Empty),
+                Loop(BlockType::Empty),
+                    LocalGet(e), I32Const(-308), I32LeS, BrIf(1),
+                    LocalGet(y), I32Const(0), LocalGet(e), I32Sub, I32Const(3), I32Shl, F64Load(mem64(p10)), F64Mul,
+                    F64Const(1.0.into()), F64Ge, BrIf(1),
+                    LocalGet(e), I32Const(1), I32Sub, LocalSet(e),
+                    Br(0),
+                End,
+                End,
+            End,
+
+            // 14 significant digits
+            I32Const(13), LocalGet(e), I32Sub, LocalTee(k), I32Const(0), I32GeS,
+            If(BlockType::Empty),
+                LocalGet(k), I32Const(308), I32GtS,
+                If(BlockType::Empty),
+                    LocalGet(y), F64Const(1e308.into()), F64Mul, LocalSet(y),
+                    LocalGet(k), I32Const(308), I32Sub, LocalSet(k),
+                End,
+                LocalGet(y), LocalGet(k), I32Const(3), I32Shl, F64Load(mem64(p10)), F64Mul, LocalSet(y),
+            Else,
+                LocalGet(y), I32Const(0), LocalGet(k), I32Sub, I32Const(3), I32Shl, F64Load(mem64(p10)), F64Div, LocalSet(y),
+            End,
+            LocalGet(y), F64Nearest, I64TruncSatF64U, LocalTee(m), I64Const(100_000_000_000_000), I64GeU,
+            If(BlockType::Empty),
+                LocalGet(m), I64Const(10), I64DivU, LocalSet(m),
+                LocalGet(e), I32Const(1), I32Add, LocalSet(e),
+            End,
+            I32Const(14), LocalSet(i),
+            Loop(BlockType::Empty),
+                I32Const(DIGITS), LocalGet(i), I32Const(1), I32Sub, LocalTee(i), I32Add,
+                LocalGet(m), I64Const(10), I64RemU, I32WrapI64, I32Const(b'0' as i32), I32Add, I32Store8(mem8(0)),
+                LocalGet(m), I64Const(10), I64DivU, LocalSet(m),
+                LocalGet(i), BrIf(0),
+            End,
+            // Without trailing zeros
+            I32Const(14), LocalSet(n),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(n), I32Const(1), I32LeS, BrIf(1),
+        ];
+        body.extend(digit(vec![LocalGet(n), I32Const(1), I32Sub]));
+        body.extend([
+                I32Const(b'0' as i32), I32Ne, BrIf(1),
+                LocalGet(n), I32Const(1), I32Sub, LocalSet(n),
+                Br(0),
+            End,
+            End,
+
+            I32Const(OUTPUT), LocalSet(pos),
+            LocalGet(x), F64Const(0.0.into()), F64Lt,
+            If(BlockType::Empty),
+        ]);
+        body.extend(put(pos, vec![I32Const(b'-' as i32)]));
+        body.extend([
+            End,
+            LocalGet(e), I32Const(-4), I32LtS, LocalGet(e), I32Const(14), I32GeS, I32Or,
+            If(BlockType::Empty),
+                // d.dddE+x
+        ]);
+        body.extend(put(pos, digit(vec![I32Const(0)])));
+        body.extend(put(pos, vec![I32Const(b'.' as i32)]));
+        body.extend([
+                LocalGet(n), I32Const(1), I32Eq,
+                If(BlockType::Empty),
+        ]);
+        body.extend(put(pos, vec![I32Const(b'0' as i32)]));
+        body.extend([
+                Else,
+                    LocalGet(pos), I32Const(DIGITS + 1), LocalGet(n), I32Const(1), I32Sub, MEMORY_COPY,
+                    LocalGet(pos), LocalGet(n), I32Add, I32Const(1), I32Sub, LocalSet(pos),
+                End,
+        ]);
+        body.extend(put(pos, vec![I32Const(b'E' as i32)]));
+        body.extend(put(pos, vec![I32Const(b'+' as i32), I32Const(b'-' as i32), LocalGet(e), I32Const(0), I32GeS, Select]));
+        body.extend([
+                LocalGet(e), I32Const(0), LocalGet(e), I32Sub, LocalGet(e), I32Const(0), I32GeS, Select, LocalTee(e),
+                I32Const(100), I32GeU,
+                If(BlockType::Empty),
+        ]);
+        body.extend(put(pos, vec![LocalGet(e), I32Const(100), I32DivU, I32Const(b'0' as i32), I32Add]));
+        body.extend([
+                End,
+                LocalGet(e), I32Const(10), I32GeU,
+                If(BlockType::Empty),
+        ]);
+        body.extend(put(pos, vec![LocalGet(e), I32Const(10), I32DivU, I32Const(10), I32RemU, I32Const(b'0' as i32), I32Add]));
+        body.extend([End]);
+        body.extend(put(pos, vec![LocalGet(e), I32Const(10), I32RemU, I32Const(b'0' as i32), I32Add]));
+        body.extend([
+            Else,
+                LocalGet(e), I32Const(0), I32GeS,
+                If(BlockType::Empty),
+                    // Integer digits, padded with zeros, then the fraction
+                    I32Const(0), LocalSet(i),
+                    Loop(BlockType::Empty),
+        ]);
+        let integer_digit = {
+            let mut code = digit(vec![LocalGet(i)]);
+            code.extend([I32Const(b'0' as i32), LocalGet(i), LocalGet(n), I32LtS, Select]);
+            code
+        };
+        body.extend(put(pos, integer_digit));
+        body.extend([
+                        LocalGet(i), I32Const(1), I32Add, LocalTee(i), LocalGet(e), I32LeS, BrIf(0),
+                    End,
+                    LocalGet(n), LocalGet(e), I32Const(1), I32Add, I32GtS,
+                    If(BlockType::Empty),
+        ]);
+        body.extend(put(pos, vec![I32Const(b'.' as i32)]));
+        body.extend([
+                        LocalGet(pos), I32Const(DIGITS + 1), LocalGet(e), I32Add, LocalGet(n), LocalGet(e), I32Sub, I32Const(1), I32Sub,
+                        MEMORY_COPY,
+                        LocalGet(pos), LocalGet(n), LocalGet(e), I32Sub, I32Const(1), I32Sub, I32Add, LocalSet(pos),
+                    End,
+                Else,
+                    // 0.000ddd
+        ]);
+        body.extend(put(pos, vec![I32Const(b'0' as i32)]));
+        body.extend(put(pos, vec![I32Const(b'.' as i32)]));
+        body.extend([
+                    LocalGet(pos), I32Const(b'0' as i32), I32Const(-1), LocalGet(e), I32Sub, MemoryFill(0),
+                    LocalGet(pos), I32Const(-1), LocalGet(e), I32Sub, I32Add, LocalSet(pos),
+                    LocalGet(pos), I32Const(DIGITS), LocalGet(n), MEMORY_COPY,
+                    LocalGet(pos), LocalGet(n), I32Add, LocalSet(pos),
+                End,
+            End,
+            LocalGet(pos), I32Const(OUTPUT), I32Sub, Call(self.h.new_string), LocalTee(string),
+            I32Const(STR_BYTES as i32), I32Add, I32Const(OUTPUT), LocalGet(pos), I32Const(OUTPUT), I32Sub, MEMORY_COPY,
+            LocalGet(string),
+        ]);
+        let locals = vec![(1, ValType::F64), (1, ValType::I64), (6, ValType::I32)];
+        self.builder.set_function_at_index(self.h.float_to_string, ty, locals, body);
+    }
+
+    fn add_parse_number_function(&mut self) {
+        // parse_number(string) -> int or float value. Sets `numeric` to 2 when the
+        // whole string is a number, 1 when it only starts with one, 0 otherwise.
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (string, p, end, c, neg, seen, is_float, overflow, dexp, exp, eneg, q) = (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11);
+        let mantissa = 12;
+        let f = 13;
+        let p10 = self.powers_of_ten() as u64;
+        let zero = self.int_literal(0);
+        let digit_at = |ptr: u32| vec![LocalGet(ptr), I32Load8U(mem8(0)), I32Const(b'0' as i32), I32Sub, LocalTee(c), I32Const(9), I32LeU];
+        let skip_spaces = {
+            let mut code = vec![
+                Block(BlockType::Empty),
+                Loop(BlockType::Empty),
+                    LocalGet(p), LocalGet(end), I32GeU, BrIf(1),
+                    LocalGet(p), I32Load8U(mem8(0)),
+            ];
+            code.extend(is_space(c));
+            code.extend([
+                    I32Eqz, BrIf(1),
+                    LocalGet(p), I32Const(1), I32Add, LocalSet(p),
+                    Br(0),
+                End,
+                End,
+            ]);
+            code
+        };
+        let mut body = vec![
+            LocalGet(string), I32Const(STR_BYTES as i32), I32Add, LocalTee(p),
+            LocalGet(string), I32Load(mem32(STR_LEN)), I32Add, LocalSet(end),
+        ];
+        body.extend(skip_spaces.clone());
+        body.extend([
+            LocalGet(p), LocalGet(end), I32LtU,
+            If(BlockType::Empty),
+                LocalGet(p), I32Load8U(mem8(0)), LocalTee(c), I32Const(b'-' as i32), I32Eq,
+                LocalGet(c), I32Const(b'+' as i32), I32Eq, I32Or,
+                If(BlockType::Empty),
+                    LocalGet(c), I32Const(b'-' as i32), I32Eq, LocalSet(neg),
+                    LocalGet(p), I32Const(1), I32Add, LocalSet(p),
+                End,
+            End,
+
+            // Integer digits; those that do not fit only scale the value
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(p), LocalGet(end), I32GeU, BrIf(1),
+        ]);
+        body.extend(digit_at(p));
+        body.extend([
+                I32Eqz, BrIf(1),
+                I32Const(1), LocalSet(seen),
+                LocalGet(mantissa), I64Const(922_337_203_685_477_580), I64LtU,
+                LocalGet(mantissa), I64Const(922_337_203_685_477_580), I64Eq, LocalGet(c), I32Const(7), I32LeU, I32And,
+                I32Or,
+                If(BlockType::Empty),
+                    LocalGet(mantissa), I64Const(10), I64Mul, LocalGet(c), I64ExtendI32U, I64Add, LocalSet(mantissa),
+                Else,
+                    LocalGet(dexp), I32Const(1), I32Add, LocalSet(dexp),
+                    I32Const(1), LocalSet(overflow),
+                End,
+                LocalGet(p), I32Const(1), I32Add, LocalSet(p),
+                Br(0),
+            End,
+            End,
+
+            // Fraction
+            LocalGet(p), LocalGet(end), I32LtU,
+            If(BlockType::Empty),
+                LocalGet(p), I32Load8U(mem8(0)), I32Const(b'.' as i32), I32Eq,
+                If(BlockType::Empty),
+                    LocalGet(p), I32Const(1), I32Add, LocalSet(p),
+                    I32Const(1), LocalSet(is_float),
+                    Block(BlockType::Empty),
+                    Loop(BlockType::Empty),
+                        LocalGet(p), LocalGet(end), I32GeU, BrIf(1),
+        ]);
+        body.extend(digit_at(p));
+        body.extend([
+                        I32Eqz, BrIf(1),
+                        I32Const(1), LocalSet(seen),
+                        LocalGet(mantissa), I64Const(100_000_000_000_000_000), I64LtU,
+                        If(BlockType::Empty),
+                            LocalGet(mantissa), I64Const(10), I64Mul, LocalGet(c), I64ExtendI32U, I64Add, LocalSet(mantissa),
+                            LocalGet(dexp), I32Const(1), I32Sub, LocalSet(dexp),
+                        End,
+                        LocalGet(p), I32Const(1), I32Add, LocalSet(p),
+                        Br(0),
+                    End,
+                    End,
+                End,
+            End,
+            LocalGet(seen), I32Eqz,
+            If(BlockType::Empty),
+                I32Const(0), GlobalSet(self.g.numeric),
+                I32Const(zero as i32), Return,
+            End,
+
+            // Exponent, only when digits follow
+            LocalGet(p), LocalGet(end), I32LtU,
+            If(BlockType::Empty),
+                LocalGet(p), I32Load8U(mem8(0)), I32Const(0x20), I32Or, I32Const(b'e' as i32), I32Eq,
+                If(BlockType::Empty),
+                    LocalGet(p), I32Const(1), I32Add, LocalTee(q), LocalGet(end), I32LtU,
+                    If(BlockType::Empty),
+                        LocalGet(q), I32Load8U(mem8(0)), LocalTee(c), I32Const(b'-' as i32), I32Eq,
+                        LocalGet(c), I32Const(b'+' as i32), I32Eq, I32Or,
+                        If(BlockType::Empty),
+                            LocalGet(c), I32Const(b'-' as i32), I32Eq, LocalSet(eneg),
+                            LocalGet(q), I32Const(1), I32Add, LocalSet(q),
+                        End,
+                    End,
+                    LocalGet(q), LocalGet(end), I32LtU,
+                    If(BlockType::Empty),
+        ]);
+        body.extend(digit_at(q));
+        body.extend([
+                        If(BlockType::Empty),
+                            I32Const(1), LocalSet(is_float),
+                            Block(BlockType::Empty),
+                            Loop(BlockType::Empty),
+                                LocalGet(q), LocalGet(end), I32GeU, BrIf(1),
+        ]);
+        body.extend(digit_at(q));
+        body.extend([
+                                I32Eqz, BrIf(1),
+                                LocalGet(exp), I32Const(10000), I32LtS,
+                                If(BlockType::Empty),
+                                    LocalGet(exp), I32Const(10), I32Mul, LocalGet(c), I32Add, LocalSet(exp),
+                                End,
+                                LocalGet(q), I32Const(1), I32Add, LocalSet(q),
+                                Br(0),
+                            End,
+                            End,
+                            LocalGet(q), LocalSet(p),
+                        End,
+                    End,
+                End,
+            End,
+        ]);
+        body.extend(skip_spaces);
+        body.extend([
+            I32Const(2), I32Const(1), LocalGet(p), LocalGet(end), I32Eq, Select, GlobalSet(self.g.numeric),
+
+            LocalGet(is_float), LocalGet(overflow), I32Or, I32Eqz,
+            If(BlockType::Empty),
+                I64Const(0), LocalGet(mantissa), I64Sub, LocalGet(mantissa), LocalGet(neg), Select,
+                Call(self.h.new_int), Return,
+            End,
+
+            // mantissa * 10^(dexp ± exp), clamped to the table
+            LocalGet(mantissa), F64ConvertI64U, LocalSet(f),
+            LocalGet(dexp), I32Const(0), LocalGet(exp), I32Sub, LocalGet(exp), LocalGet(eneg), Select, I32Add, LocalTee(dexp),
+            I32Const(0), I32GtS,
+            If(BlockType::Empty),
+                LocalGet(dexp), I32Const(308), I32GtS,
+                If(BlockType::Empty),
+                    LocalGet(f), F64Const(1e308.into()), F64Mul, F64Const(10.0.into()), F64Mul, LocalSet(f),
+                Else,
+                    LocalGet(f), LocalGet(dexp), I32Const(3), I32Shl, F64Load(mem64(p10)), F64Mul, LocalSet(f),
+                End,
+            Else,
+                I32Const(0), LocalGet(dexp), I32Sub, LocalTee(dexp), I32Const(308), I32GtS,
+                If(BlockType::Empty),
+                    LocalGet(f), F64Const(1e308.into()), F64Div, LocalSet(f),
+                    LocalGet(dexp), I32Const(308), I32Sub, LocalTee(dexp), I32Const(308), I32GtS,
+                    If(BlockType::Empty), F64Const(0.0.into()), LocalSet(f), I32Const(0), LocalSet(dexp), End,
+                End,
+                LocalGet(f), LocalGet(dexp), I32Const(3), I32Shl, F64Load(mem64(p10)), F64Div, LocalSet(f),
+            End,
+            LocalGet(f), F64Neg, LocalGet(f), LocalGet(neg), Select, Call(self.h.new_float),
+        ]);
+        let locals = vec![(11, ValType::I32), (1, ValType::I64), (1, ValType::F64)];
+        self.builder.set_function_at_index(self.h.parse_number, ty, locals, body);
+    }
+
+    fn add_concat_function(&mut self) {
+        // concat(a, b) -> string value
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (a, b, la, lb, string) = (0, 1, 2, 3, 4);
+        let body = vec![
+            LocalGet(a), Call(self.h.to_string), LocalTee(a), I32Load(mem32(STR_LEN)), LocalSet(la),
+            LocalGet(b), Call(self.h.to_string), LocalTee(b), I32Load(mem32(STR_LEN)), LocalSet(lb),
+            LocalGet(la), I32Eqz, If(BlockType::Empty), LocalGet(b), Return, End,
+            LocalGet(lb), I32Eqz, If(BlockType::Empty), LocalGet(a), Return, End,
+            LocalGet(la), LocalGet(lb), I32Add, Call(self.h.new_string), LocalTee(string),
+            I32Const(STR_BYTES as i32), I32Add, LocalGet(a), I32Const(STR_BYTES as i32), I32Add, LocalGet(la), MEMORY_COPY,
+            LocalGet(string), I32Const(STR_BYTES as i32), I32Add, LocalGet(la), I32Add,
+            LocalGet(b), I32Const(STR_BYTES as i32), I32Add, LocalGet(lb), MEMORY_COPY,
+            LocalGet(string),
+        ];
+        self.builder.set_function_at_index(self.h.concat, ty, vec![(3, ValType::I32)], body);
+    }
+
+    fn add_mem_eq_function(&mut self) {
+        // mem_eq(a, b, len) -> i32
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (a, b, len, i) = (0, 1, 2, 3);
+        let body = vec![
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(len), I32GeU, BrIf(1),
+                LocalGet(a), LocalGet(i), I32Add, I32Load8U(mem8(0)),
+                LocalGet(b), LocalGet(i), I32Add, I32Load8U(mem8(0)), I32Ne,
+                If(BlockType::Empty), I32Const(0), Return, End,
+                LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                Br(0),
+            End,
+            End,
+            I32Const(1),
+        ];
+        self.builder.set_function_at_index(self.h.mem_eq, ty, vec![(1, ValType::I32)], body);
+    }
+
+    fn add_str_eq_function(&mut self) {
+        // str_eq(a, b) -> i32, for two string values
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (a, b) = (0, 1);
+        let body = vec![
+            LocalGet(a), LocalGet(b), I32Eq, If(BlockType::Empty), I32Const(1), Return, End,
+            LocalGet(a), I32Load(mem32(STR_LEN)), LocalGet(b), I32Load(mem32(STR_LEN)), I32Ne,
+            If(BlockType::Empty), I32Const(0), Return, End,
+            LocalGet(a), I32Const(STR_BYTES as i32), I32Add, LocalGet(b), I32Const(STR_BYTES as i32), I32Add,
+            LocalGet(a), I32Load(mem32(STR_LEN)), Call(self.h.mem_eq),
+        ];
+        self.builder.set_function_at_index(self.h.str_eq, ty, vec![], body);
+    }
+
+    fn add_str_cmp_function(&mut self) {
+        // str_cmp(a, b) -> -1, 0 or 1, comparing bytes
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (a, b, la, lb, i, ca, cb) = (0, 1, 2, 3, 4, 5, 6);
+        let body = vec![
+            LocalGet(a), I32Load(mem32(STR_LEN)), LocalSet(la),
+            LocalGet(b), I32Load(mem32(STR_LEN)), LocalSet(lb),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(la), I32GeU, BrIf(1),
+                LocalGet(i), LocalGet(lb), I32GeU, BrIf(1),
+                LocalGet(a), LocalGet(i), I32Add, I32Load8U(mem8(STR_BYTES)), LocalTee(ca),
+                LocalGet(b), LocalGet(i), I32Add, I32Load8U(mem8(STR_BYTES)), LocalTee(cb), I32Ne,
+                If(BlockType::Empty),
+                    I32Const(-1), I32Const(1), LocalGet(ca), LocalGet(cb), I32LtU, Select, Return,
+                End,
+                LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                Br(0),
+            End,
+            End,
+            LocalGet(la), LocalGet(lb), I32GtU, LocalGet(la), LocalGet(lb), I32LtU, I32Sub,
+        ];
+        self.builder.set_function_at_index(self.h.str_cmp, ty, vec![(5, ValType::I32)], body);
+    }
+
+    /// Copy `len` bytes of `string` from `start` into a new string
+    fn copy_substring(&self, string: u32, start: u32, len: u32, result: u32) -> Vec<Instruction<'static>> {
+        vec![
+            LocalGet(len), Call(self.h.new_string), LocalTee(result), I32Const(STR_BYTES as i32), I32Add,
+            LocalGet(string), I32Const(STR_BYTES as i32), I32Add, LocalGet(start), I32Add,
+            LocalGet(len), MEMORY_COPY,
+            LocalGet(result),
+        ]
+    }
+
+    fn add_substr_function(&mut self) {
+        // substr(string, start, length or null)
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (string, start_value, length_value, n, start, len, result) = (0, 1, 2, 3, 4, 5, 6);
+        let empty = self.string_literal("");
+        let mut body = vec![
+            LocalGet(string), Call(self.h.to_string), LocalTee(string), I32Load(mem32(STR_LEN)), LocalSet(n),
+            LocalGet(start_value), Call(self.h.to_int), I32WrapI64, LocalTee(start), I32Const(0), I32LtS,
+            If(BlockType::Empty),
+                LocalGet(n), LocalGet(start), I32Add, LocalTee(start), I32Const(0), I32LtS,
+                If(BlockType::Empty), I32Const(0), LocalSet(start), End,
+            End,
+            LocalGet(start), LocalGet(n), I32GtS,
+            If(BlockType::Empty), I32Const(empty as i32), Return, End,
+            LocalGet(n), LocalGet(start), I32Sub, LocalSet(n),
+            LocalGet(length_value), I32Load(mem32(0)), I32Eqz,
+            If(BlockType::Empty),
+                LocalGet(n), LocalSet(len),
+            Else,
+                LocalGet(length_value), Call(self.h.to_int), I32WrapI64, LocalTee(len), I32Const(0), I32LtS,
+                If(BlockType::Empty),
+                    LocalGet(n), LocalGet(len), I32Add, LocalSet(len),
+                    LocalGet(len), I32Const(0), I32LtS, If(BlockType::Empty), I32Const(0), LocalSet(len), End,
+                End,
+                LocalGet(len), LocalGet(n), I32GtS, If(BlockType::Empty), LocalGet(n), LocalSet(len), End,
+            End,
+        ];
+        body.extend(self.copy_substring(string, start, len, result));
+        self.builder.set_function_at_index(self.h.substr, ty, vec![(4, ValType::I32)], body);
+    }
+
+    fn add_strpos_function(&mut self) {
+        // strpos(haystack, needle, offset or null) -> int or false
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (haystack, needle, offset, n, len, i) = (0, 1, 2, 3, 4, 5);
+        let body = vec![
+            LocalGet(haystack), Call(self.h.to_string), LocalTee(haystack), I32Load(mem32(STR_LEN)), LocalSet(n),
+            LocalGet(needle), Call(self.h.to_string), LocalTee(needle), I32Load(mem32(STR_LEN)), LocalSet(len),
+            LocalGet(offset), I32Load(mem32(0)),
+            If(BlockType::Empty),
+                LocalGet(offset), Call(self.h.to_int), I32WrapI64, LocalTee(i), I32Const(0), I32LtS,
+                If(BlockType::Empty), LocalGet(i), LocalGet(n), I32Add, LocalSet(i), End,
+                LocalGet(i), I32Const(0), I32LtS, LocalGet(i), LocalGet(n), I32GtS, I32Or,
+                If(BlockType::Empty), I32Const(FALSE_VALUE as i32), Return, End,
+            End,
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(len), I32Add, LocalGet(n), I32GtS, BrIf(1),
+                LocalGet(haystack), I32Const(STR_BYTES as i32), I32Add, LocalGet(i), I32Add,
+                LocalGet(needle), I32Const(STR_BYTES as i32), I32Add, LocalGet(len), Call(self.h.mem_eq),
+                If(BlockType::Empty), LocalGet(i), I64ExtendI32U, Call(self.h.new_int), Return, End,
+                LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                Br(0),
+            End,
+            End,
+            I32Const(FALSE_VALUE as i32),
+        ];
+        self.builder.set_function_at_index(self.h.strpos, ty, vec![(3, ValType::I32)], body);
+    }
+
+    fn add_case_function(&mut self, idx: u32, from: u8, to: u8, delta: i32) {
+        // strtolower / strtoupper for ASCII letters
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (string, n, i, c, result) = (0, 1, 2, 3, 4);
+        let body = vec![
+            LocalGet(string), Call(self.h.to_string), LocalTee(string), I32Load(mem32(STR_LEN)), LocalTee(n),
+            Call(self.h.new_string), LocalSet(result),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(n), I32GeU, BrIf(1),
+                LocalGet(result), LocalGet(i), I32Add,
+                LocalGet(string), LocalGet(i), I32Add, I32Load8U(mem8(STR_BYTES)), LocalTee(c),
+                I32Const(delta), I32Add, LocalGet(c),
+                LocalGet(c), I32Const(from as i32), I32Sub, I32Const((to - from) as i32), I32LeU, Select,
+                I32Store8(mem8(STR_BYTES)),
+                LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                Br(0),
+            End,
+            End,
+            LocalGet(result),
+        ];
+        self.builder.set_function_at_index(idx, ty, vec![(4, ValType::I32)], body);
+    }
+
+    fn add_trim_function(&mut self) {
+        // trim(string, characters or null)
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (string, chars, n, start, end, c, j, result) = (0, 1, 2, 3, 4, 5, 6, 7);
+        let whitespace = self.string_literal(" \t\n\r\0\x0B");
+        // Whether the byte in `c` is one of `chars`
+        let in_set = vec![
+            I32Const(0), LocalSet(j),
+            Block(BlockType::Result(ValType::I32)),
+            Loop(BlockType::Empty),
+                I32Const(0), LocalGet(j), LocalGet(chars), I32Load(mem32(STR_LEN)), I32GeU, BrIf(1), Drop,
+                I32Const(1), LocalGet(chars), LocalGet(j), I32Add, I32Load8U(mem8(STR_BYTES)), LocalGet(c), I32Eq, BrIf(1), Drop,
+                LocalGet(j), I32Const(1), I32Add, LocalSet(j),
+                Br(0),
+            End,
+            Unreachable,
+            End,
+        ];
+        let mut body = vec![
+            LocalGet(string), Call(self.h.to_string), LocalTee(string), I32Load(mem32(STR_LEN)), LocalTee(n), LocalSet(end),
+            LocalGet(chars), Call(self.h.to_string), I32Const(whitespace as i32), LocalGet(chars), I32Load(mem32(0)), Select, LocalSet(chars),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(start), LocalGet(end), I32GeU, BrIf(1),
+                LocalGet(string), LocalGet(start), I32Add, I32Load8U(mem8(STR_BYTES)), LocalSet(c),
+        ];
+        body.extend(in_set.clone());
+        body.extend([
+                I32Eqz, BrIf(1),
+                LocalGet(start), I32Const(1), I32Add, LocalSet(start),
+                Br(0),
+            End,
+            End,
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(end), LocalGet(start), I32LeU, BrIf(1),
+                LocalGet(string), LocalGet(end), I32Add, I32Const(1), I32Sub, I32Load8U(mem8(STR_BYTES)), LocalSet(c),
+        ]);
+        body.extend(in_set);
+        body.extend([
+                I32Eqz, BrIf(1),
+                LocalGet(end), I32Const(1), I32Sub, LocalSet(end),
+                Br(0),
+            End,
+            End,
+            LocalGet(start), I32Eqz, LocalGet(end), LocalGet(n), I32Eq, I32And,
+            If(BlockType::Empty), LocalGet(string), Return, End,
+            LocalGet(end), LocalGet(start), I32Sub, LocalSet(end),
+        ]);
+        body.extend(self.copy_substring(string, start, end, result));
+        self.builder.set_function_at_index(self.h.trim, ty, vec![(6, ValType::I32)], body);
+    }
+
+    fn add_str_replace_function(&mut self) {
+        // str_replace(search, replace, subject) for strings
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (search, replace, subject, n, sl, rl, i, o, count, result) = (0, 1, 2, 3, 4, 5, 6, 7, 8, 9);
+        let matches = vec![
+            LocalGet(i), LocalGet(sl), I32Add, LocalGet(n), I32LeU,
+            If(BlockType::Result(ValType::I32)),
+                LocalGet(subject), I32Const(STR_BYTES as i32), I32Add, LocalGet(i), I32Add,
+                LocalGet(search), I32Const(STR_BYTES as i32), I32Add, LocalGet(sl), Call(self.h.mem_eq),
+            Else,
+                I32Const(0),
+            End,
+        ];
+        let mut body = vec![
+            LocalGet(search), Call(self.h.to_string), LocalTee(search), I32Load(mem32(STR_LEN)), LocalSet(sl),
+            LocalGet(replace), Call(self.h.to_string), LocalTee(replace), I32Load(mem32(STR_LEN)), LocalSet(rl),
+            LocalGet(subject), Call(self.h.to_string), LocalTee(subject), I32Load(mem32(STR_LEN)), LocalSet(n),
+            LocalGet(sl), I32Eqz, If(BlockType::Empty), LocalGet(subject), Return, End,
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(n), I32GeU, BrIf(1),
+        ];
+        body.extend(matches.clone());
+        body.extend([
+                If(BlockType::Empty),
+                    LocalGet(count), I32Const(1), I32Add, LocalSet(count),
+                    LocalGet(i), LocalGet(sl), I32Add, LocalSet(i),
+                Else,
+                    LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                End,
+                Br(0),
+            End,
+            End,
+            LocalGet(count), I32Eqz, If(BlockType::Empty), LocalGet(subject), Return, End,
+            LocalGet(n), LocalGet(count), LocalGet(rl), LocalGet(sl), I32Sub, I32Mul, I32Add,
+            Call(self.h.new_string), LocalSet(result),
+            I32Const(0), LocalSet(i),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(n), I32GeU, BrIf(1),
+        ]);
+        body.extend(matches);
+        body.extend([
+                If(BlockType::Empty),
+                    LocalGet(result), I32Const(STR_BYTES as i32), I32Add, LocalGet(o), I32Add,
+                    LocalGet(replace), I32Const(STR_BYTES as i32), I32Add, LocalGet(rl), MEMORY_COPY,
+                    LocalGet(o), LocalGet(rl), I32Add, LocalSet(o),
+                    LocalGet(i), LocalGet(sl), I32Add, LocalSet(i),
+                Else,
+                    LocalGet(result), LocalGet(o), I32Add,
+                    LocalGet(subject), LocalGet(i), I32Add, I32Load8U(mem8(STR_BYTES)),
+                    I32Store8(mem8(STR_BYTES)),
+                    LocalGet(o), I32Const(1), I32Add, LocalSet(o),
+                    LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                End,
+                Br(0),
+            End,
+            End,
+            LocalGet(result),
+        ]);
+        self.builder.set_function_at_index(self.h.str_replace, ty, vec![(7, ValType::I32)], body);
+    }
+
+    fn add_explode_function(&mut self) {
+        // explode(separator, string, limit or null) -> array
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (separator, string, limit_value, n, dl, limit, i, start, array, mark, len, piece) =
+            (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11);
+        let message = self.string_literal(
+            "PHP Fatal error:  Uncaught ValueError: explode(): Argument #1 ($separator) cannot be empty\n");
+        // Append string[start..i] to the array, then drop the temporaries
+        let mut add_piece = vec![LocalGet(i), LocalGet(start), I32Sub, LocalSet(len)];
+        add_piece.extend(self.copy_substring(string, start, len, piece));
+        add_piece.extend([
+            LocalSet(piece),
+            LocalGet(array), LocalGet(piece), Call(self.h.arr_append),
+            LocalGet(mark), GlobalSet(self.g.sp),
+        ]);
+        let mut body = vec![
+            LocalGet(separator), Call(self.h.to_string), LocalTee(separator), I32Load(mem32(STR_LEN)), LocalTee(dl),
+            I32Eqz, If(BlockType::Empty), I32Const(message as i32), Call(self.h.fatal), End,
+            LocalGet(string), Call(self.h.to_string), LocalTee(string), I32Load(mem32(STR_LEN)), LocalSet(n),
+            I32Const(i32::MAX), LocalSet(limit),
+            LocalGet(limit_value), I32Load(mem32(0)),
+            If(BlockType::Empty),
+                LocalGet(limit_value), Call(self.h.to_int), I32WrapI64, LocalTee(limit), I32Const(1), I32LtS,
+                If(BlockType::Empty), I32Const(1), LocalSet(limit), End,
+            End,
+            I32Const(0), Call(self.h.arr_new), LocalSet(array),
+            GlobalGet(self.g.sp), LocalSet(mark),
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+                LocalGet(i), LocalGet(dl), I32Add, LocalGet(n), I32GtU, BrIf(1),
+                LocalGet(array), I32Load(mem32(ARR_COUNT)), I32Const(1), I32Add, LocalGet(limit), I32GeS, BrIf(1),
+                LocalGet(string), I32Const(STR_BYTES as i32), I32Add, LocalGet(i), I32Add,
+                LocalGet(separator), I32Const(STR_BYTES as i32), I32Add, LocalGet(dl), Call(self.h.mem_eq),
+                If(BlockType::Empty),
+        ];
+        body.extend(add_piece.clone());
+        body.extend([
+                    LocalGet(i), LocalGet(dl), I32Add, LocalTee(i), LocalSet(start),
+                Else,
+                    LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                End,
+                Br(0),
+            End,
+            End,
+            LocalGet(n), LocalSet(i),
+        ]);
+        body.extend(add_piece);
+        body.push(LocalGet(array));
+        self.builder.set_function_at_index(self.h.explode, ty, vec![(9, ValType::I32)], body);
+    }
+
+    fn add_implode_function(&mut self) {
+        // implode(separator, array); the separator may come second or be left out
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (separator, array, entries, used, i, value, total, count, result, o, mark, len) =
+            (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11);
+        let empty = self.string_literal("");
+        // Loop over the live entries, with the string of each value in `value`
+        let each = |body: Vec<Instruction<'static>>| {
+            let mut code = vec![
+                I32Const(0), LocalSet(i),
+                Block(BlockType::Empty),
+                Loop(BlockType::Empty),
+                    LocalGet(i), LocalGet(used), I32GeU, BrIf(1),
+                    LocalGet(entries), LocalGet(i), I32Const(ENTRY_SIZE), I32Mul, I32Add, LocalTee(value),
+                    I32Load(mem32(ENTRY_KEY_STR)), I32Const(-1), I32Ne,
+                    If(BlockType::Empty),
+                        LocalGet(value), I32Load(mem32(ENTRY_VALUE)), Call(self.h.to_string), LocalSet(value),
+            ];
+            code.extend(body);
+            code.extend([
+                        LocalGet(mark), GlobalSet(self.g.sp),
+                    End,
+                    LocalGet(i), I32Const(1), I32Add, LocalSet(i),
+                    Br(0),
+                End,
+                End,
+            ]);
+            code
+        };
+        let mut body = vec![
+            LocalGet(separator), I32Load(mem32(0)), I32Const(TAG_ARRAY), I32Eq,
+            If(BlockType::Empty),
+                I32Const(empty as i32), LocalGet(array), LocalGet(array), I32Load(mem32(0)), I32Eqz, Select,
+                LocalGet(separator), LocalSet(array),
+                LocalSet(separator),
+            End,
+            LocalGet(array), I32Load(mem32(0)), I32Const(TAG_ARRAY), I32Ne,
+            If(BlockType::Empty), I32Const(empty as i32), Return, End,
+            LocalGet(separator), Call(self.h.to_string), LocalSet(separator),
+            LocalGet(array), I32Load(mem32(ARR_ENTRIES)), LocalSet(entries),
+            LocalGet(array), I32Load(mem32(ARR_USED)), LocalSet(used),
+            GlobalGet(self.g.sp), LocalSet(mark),
+        ];
+        body.extend(each(vec![
+            LocalGet(total), LocalGet(value), I32Load(mem32(STR_LEN)), I32Add, LocalSet(total),
+            LocalGet(count), I32Const(1), I32Add, LocalSet(count),
+        ]));
+        body.extend([
+            LocalGet(count), I32Eqz, If(BlockType::Empty), I32Const(empty as i32), Return, End,
+            LocalGet(total), LocalGet(count), I32Const(1), I32Sub, LocalGet(separator), I32Load(mem32(STR_LEN)), I32Mul, I32Add,
+            Call(self.h.new_string), LocalSet(result),
+            GlobalGet(self.g.sp), LocalSet(mark),
+            LocalGet(result), I32Const(STR_BYTES as i32), I32Add, LocalSet(o),
+        ]);
+        body.extend(each(vec![
+            LocalGet(o), LocalGet(result), I32Const(STR_BYTES as i32), I32Add, I32Ne,
+            If(BlockType::Empty),
+                LocalGet(o), LocalGet(separator), I32Const(STR_BYTES as i32), I32Add,
+                LocalGet(separator), I32Load(mem32(STR_LEN)), LocalTee(len), MEMORY_COPY,
+                LocalGet(o), LocalGet(len), I32Add, LocalSet(o),
+            End,
+            LocalGet(o), LocalGet(value), I32Const(STR_BYTES as i32), I32Add,
+            LocalGet(value), I32Load(mem32(STR_LEN)), LocalTee(len), MEMORY_COPY,
+            LocalGet(o), LocalGet(len), I32Add, LocalSet(o),
+        ]));
+        body.push(LocalGet(result));
+        self.builder.set_function_at_index(self.h.implode, ty, vec![(10, ValType::I32)], body);
     }
 }
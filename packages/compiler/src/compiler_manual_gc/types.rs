@@ -1,309 +1,293 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
+// Conversions between PHP types, and the coercion of parameter, return and
+// property values to their declared types
+
 use super::core::*;
-use wasm_encoder::*;
+use crate::compiler::types::type_name;
+use edge_php_parser::ast::Type;
+use wasm_encoder::Instruction::*;
+use wasm_encoder::{BlockType, ValType};
+
+/// Declared types as a set of accepted tags, one bit per tag
+pub(super) const MASK_ANY: i32 = 1 << 7;
+
+/// A declared parameter, return or property type: the tags it accepts and
+/// the name TypeErrors print for it
+#[derive(Debug, Clone)]
+pub(super) struct DeclaredType {
+    pub(super) mask: i32,
+    pub(super) name: String,
+}
+
+impl DeclaredType {
+    /// None for types every value matches
+    pub(super) fn of(ty: &Type) -> Option<DeclaredType> {
+        let mask = type_mask(ty);
+        (mask & MASK_ANY == 0).then(|| DeclaredType { mask, name: type_name(ty) })
+    }
+
+    /// The mask of an optional declaration
+    pub(super) fn mask_of(declared: &Option<DeclaredType>) -> i32 {
+        declared.as_ref().map_or(MASK_ANY, |declared| declared.mask)
+    }
+}
+
+pub(super) fn type_mask(ty: &Type) -> i32 {
+    match ty {
+        Type::Int => 1 << TAG_INT,
+        Type::Float => 1 << TAG_FLOAT,
+        Type::String => 1 << TAG_STRING,
+        Type::Bool | Type::False | Type::True => 1 << TAG_BOOL,
+        Type::Array | Type::Iterable => 1 << TAG_ARRAY,
+        Type::Object(_) | Type::Intersection(_) => 1 << TAG_OBJECT,
+        Type::Null => 1 << TAG_NULL,
+        Type::Nullable(inner) => 1 << TAG_NULL | type_mask(inner),
+        Type::Union(types) => types.iter().fold(0, |mask, ty| mask | type_mask(ty)),
+        Type::Mixed | Type::Callable | Type::Void | Type::Never => MASK_ANY,
+    }
+}
 
 impl Compiler {
     pub(super) fn add_type_functions(&mut self) {
         self.add_to_bool_function();
-        self.add_is_null_function();
-        self.add_isset_function();
-        self.add_empty_function();
+        self.add_to_int_function();
+        self.add_to_float_function();
+        self.add_to_number_function();
+        self.add_coerce_function();
+        self.add_check_type_function();
+    }
+
+    /// Convert the value on the stack to a declared type, or stop with an
+    /// uncaught TypeError reading `{prefix}{type of the value}{suffix}`
+    pub(super) fn emit_check_type(&mut self, declared: &Option<DeclaredType>, prefix: &str, suffix: &str) {
+        let Some(declared) = declared else { return };
+        let prefix = self.string_literal(&format!("PHP Fatal error:  Uncaught TypeError: {}", prefix));
+        let suffix = self.string_literal(&format!("{}\n", suffix));
+        self.emit_all([
+            I32Const(declared.mask),
+            I32Const(self.strict_types as i32),
+            I32Const(prefix as i32),
+            I32Const(suffix as i32),
+            Call(self.h.check_type),
+        ]);
+    }
+
+    /// Check the value on the stack against the declared type of a property
+    pub(super) fn emit_property_check(&mut self, class: &str, property: &str, declared: &Option<DeclaredType>) {
+        if let Some(type_name) = declared.as_ref().map(|declared| declared.name.clone()) {
+            let suffix = format!(" to property {}::${} of type {}", class, property, type_name);
+            self.emit_check_type(declared, "Cannot assign ", &suffix);
+        }
     }
-    
+
     fn add_to_bool_function(&mut self) {
-        let mut to_bool_body = vec![];
-        let mut bool_locals = vec![];
-        bool_locals.push((1, ValType::I32)); // type
-        bool_locals.push((1, ValType::I32)); // result
-        
-        // Get type
-        to_bool_body.push(Instruction::LocalGet(0));
-        to_bool_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        to_bool_body.push(Instruction::LocalSet(1)); // type
-        
-        // Check type
-        to_bool_body.push(Instruction::LocalGet(1));
-        to_bool_body.push(Instruction::I32Const(TYPE_NULL as i32));
-        to_bool_body.push(Instruction::I32Eq);
-        to_bool_body.push(Instruction::If(BlockType::Empty));
-        // NULL -> false
-        to_bool_body.push(Instruction::I32Const(0));
-        to_bool_body.push(Instruction::LocalSet(2)); // result = false
-        to_bool_body.push(Instruction::Else);
-        
-        to_bool_body.push(Instruction::LocalGet(1));
-        to_bool_body.push(Instruction::I32Const(TYPE_BOOL as i32));
-        to_bool_body.push(Instruction::I32Eq);
-        to_bool_body.push(Instruction::If(BlockType::Empty));
-        // BOOL -> return as is
-        to_bool_body.push(Instruction::LocalGet(0));
-        to_bool_body.push(Instruction::I32Const(4));
-        to_bool_body.push(Instruction::I32Add);
-        to_bool_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        to_bool_body.push(Instruction::LocalSet(2)); // result
-        to_bool_body.push(Instruction::Else);
-        
-        to_bool_body.push(Instruction::LocalGet(1));
-        to_bool_body.push(Instruction::I32Const(TYPE_INT as i32));
-        to_bool_body.push(Instruction::I32Eq);
-        to_bool_body.push(Instruction::If(BlockType::Empty));
-        // INT -> 0 is false, others true
-        to_bool_body.push(Instruction::LocalGet(0));
-        to_bool_body.push(Instruction::I32Const(4));
-        to_bool_body.push(Instruction::I32Add);
-        to_bool_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        to_bool_body.push(Instruction::I64Const(0));
-        to_bool_body.push(Instruction::I64Ne);
-        to_bool_body.push(Instruction::LocalSet(2)); // result
-        to_bool_body.push(Instruction::Else);
-        
-        to_bool_body.push(Instruction::LocalGet(1));
-        to_bool_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        to_bool_body.push(Instruction::I32Eq);
-        to_bool_body.push(Instruction::If(BlockType::Empty));
-        // FLOAT -> 0.0 is false, others true
-        to_bool_body.push(Instruction::LocalGet(0));
-        to_bool_body.push(Instruction::I32Const(4));
-        to_bool_body.push(Instruction::I32Add);
-        to_bool_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        to_bool_body.push(Instruction::F64Const(0.0.into()));
-        to_bool_body.push(Instruction::F64Ne);
-        to_bool_body.push(Instruction::LocalSet(2)); // result
-        to_bool_body.push(Instruction::Else);
-        
-        to_bool_body.push(Instruction::LocalGet(1));
-        to_bool_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        to_bool_body.push(Instruction::I32Eq);
-        to_bool_body.push(Instruction::If(BlockType::Empty));
-        // STRING -> empty string is false, others true
-        to_bool_body.push(Instruction::LocalGet(0));
-        to_bool_body.push(Instruction::I32Const(8));
-        to_bool_body.push(Instruction::I32Add);
-        to_bool_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 })); // string ptr
-        to_bool_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 })); // string length
-        to_bool_body.push(Instruction::I32Const(0));
-        to_bool_body.push(Instruction::I32Ne);
-        to_bool_body.push(Instruction::LocalSet(2)); // result
-        to_bool_body.push(Instruction::Else);
-        
-        // Other types -> true
-        to_bool_body.push(Instruction::I32Const(1));
-        to_bool_body.push(Instruction::LocalSet(2)); // result = true
-        
-        to_bool_body.push(Instruction::End);
-        to_bool_body.push(Instruction::End);
-        to_bool_body.push(Instruction::End);
-        to_bool_body.push(Instruction::End);
-        to_bool_body.push(Instruction::End);
-        
-        // Return result
-        to_bool_body.push(Instruction::LocalGet(2));
-        
-        self.builder.set_function_at_index(self.to_bool_fn_idx, self.unary_op_type_idx, bool_locals, to_bool_body);
+        // to_bool(value) -> i32
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (value, tag) = (0, 1);
+        let body = vec![
+            LocalGet(value), I32Load(mem32(0)), LocalTee(tag),
+            I32Const(TAG_BOOL), I32Eq, LocalGet(tag), I32Const(TAG_INT), I32Eq, I32Or,
+            If(BlockType::Empty),
+                LocalGet(value), I64Load(mem64(VALUE_DATA)), I64Const(0), I64Ne, Return,
+            End,
+            LocalGet(tag), I32Const(TAG_FLOAT), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), F64Load(mem64(VALUE_DATA)), F64Const(0.0.into()), F64Ne, Return,
+            End,
+            LocalGet(tag), I32Const(TAG_STRING), I32Eq,
+            If(BlockType::Empty),
+                // "" and "0" are false
+                LocalGet(value), I32Load(mem32(STR_LEN)), I32Eqz,
+                LocalGet(value), I32Load(mem32(STR_LEN)), I32Const(1), I32Eq,
+                LocalGet(value), I32Load8U(mem8(STR_BYTES)), I32Const(b'0' as i32), I32Eq, I32And,
+                I32Or, I32Eqz, Return,
+            End,
+            LocalGet(tag), I32Const(TAG_ARRAY), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), I32Load(mem32(ARR_COUNT)), I32Const(0), I32Ne, Return,
+            End,
+            LocalGet(tag), I32Const(TAG_OBJECT), I32Eq,
+        ];
+        self.builder.set_function_at_index(self.h.to_bool, ty, vec![(1, ValType::I32)], body);
     }
-    
-    fn add_is_null_function(&mut self) {
-        let mut is_null_body = vec![];
-        let mut is_null_locals = vec![];
-        is_null_locals.push((1, ValType::I32)); // result_ptr
-        
-        // Allocate result
-        is_null_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        is_null_body.push(Instruction::LocalSet(1)); // result_ptr
-        
-        // Set result type to bool
-        is_null_body.push(Instruction::LocalGet(1));
-        is_null_body.push(Instruction::I32Const(TYPE_BOOL as i32));
-        is_null_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Check if type is NULL
-        is_null_body.push(Instruction::LocalGet(0));
-        is_null_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        is_null_body.push(Instruction::I32Const(TYPE_NULL as i32));
-        is_null_body.push(Instruction::I32Eq);
-        
-        // Store result
-        is_null_body.push(Instruction::LocalGet(1));
-        is_null_body.push(Instruction::I32Const(4));
-        is_null_body.push(Instruction::I32Add);
-        is_null_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Return result
-        is_null_body.push(Instruction::LocalGet(1));
-        
-        self.builder.set_function_at_index(self.is_null_fn_idx, self.unary_op_type_idx, is_null_locals, is_null_body);
+
+    fn add_to_int_function(&mut self) {
+        // to_int(value) -> i64
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I64]);
+        let (value, tag) = (0, 1);
+        let body = vec![
+            LocalGet(value), I32Load(mem32(0)), LocalTee(tag), I32Const(TAG_STRING), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), Call(self.h.parse_number), LocalTee(value), I32Load(mem32(0)), LocalSet(tag),
+            End,
+            LocalGet(tag), I32Const(TAG_BOOL), I32Eq, LocalGet(tag), I32Const(TAG_INT), I32Eq, I32Or,
+            If(BlockType::Empty),
+                LocalGet(value), I64Load(mem64(VALUE_DATA)), Return,
+            End,
+            LocalGet(tag), I32Const(TAG_FLOAT), I32Eq,
+            If(BlockType::Empty),
+                // NaN and infinities become 0
+                LocalGet(value), F64Load(mem64(VALUE_DATA)), F64Abs, F64Const(f64::INFINITY.into()), F64Lt,
+                If(BlockType::Empty),
+                    LocalGet(value), F64Load(mem64(VALUE_DATA)), I64TruncSatF64S, Return,
+                End,
+                I64Const(0), Return,
+            End,
+            LocalGet(tag), I32Const(TAG_ARRAY), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), I32Load(mem32(ARR_COUNT)), I32Const(0), I32Ne, I64ExtendI32U, Return,
+            End,
+            LocalGet(tag), I32Const(TAG_OBJECT), I32Eq, I64ExtendI32U,
+        ];
+        self.builder.set_function_at_index(self.h.to_int, ty, vec![(1, ValType::I32)], body);
     }
-    
-    fn add_isset_function(&mut self) {
-        let mut isset_body = vec![];
-        let mut isset_locals = vec![];
-        isset_locals.push((1, ValType::I32)); // result_ptr
-        
-        // Allocate result
-        isset_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        isset_body.push(Instruction::LocalSet(1)); // result_ptr
-        
-        // Set result type to bool
-        isset_body.push(Instruction::LocalGet(1));
-        isset_body.push(Instruction::I32Const(TYPE_BOOL as i32));
-        isset_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Check if type is NOT NULL
-        isset_body.push(Instruction::LocalGet(0));
-        isset_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        isset_body.push(Instruction::I32Const(TYPE_NULL as i32));
-        isset_body.push(Instruction::I32Ne);
-        
-        // Store result
-        isset_body.push(Instruction::LocalGet(1));
-        isset_body.push(Instruction::I32Const(4));
-        isset_body.push(Instruction::I32Add);
-        isset_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Return result
-        isset_body.push(Instruction::LocalGet(1));
-        
-        self.builder.set_function_at_index(self.isset_fn_idx, self.unary_op_type_idx, isset_locals, isset_body);
+
+    fn add_to_float_function(&mut self) {
+        // to_float(value) -> f64
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::F64]);
+        let (value, tag) = (0, 1);
+        let body = vec![
+            LocalGet(value), I32Load(mem32(0)), LocalTee(tag), I32Const(TAG_STRING), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), Call(self.h.parse_number), LocalTee(value), I32Load(mem32(0)), LocalSet(tag),
+            End,
+            LocalGet(tag), I32Const(TAG_FLOAT), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), F64Load(mem64(VALUE_DATA)), Return,
+            End,
+            LocalGet(value), Call(self.h.to_int), F64ConvertI64S,
+        ];
+        self.builder.set_function_at_index(self.h.to_float, ty, vec![(1, ValType::I32)], body);
     }
-    
-    fn add_empty_function(&mut self) {
-        let mut empty_body = vec![];
-        let mut empty_locals = vec![];
-        empty_locals.push((1, ValType::I32)); // result_ptr
-        empty_locals.push((1, ValType::I32)); // type
-        empty_locals.push((1, ValType::I32)); // is_empty
-        
-        // Allocate result
-        empty_body.push(Instruction::Call(self.alloc_value_fn_idx));
-        empty_body.push(Instruction::LocalSet(1)); // result_ptr
-        
-        // Set result type to bool
-        empty_body.push(Instruction::LocalGet(1));
-        empty_body.push(Instruction::I32Const(TYPE_BOOL as i32));
-        empty_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Get type
-        empty_body.push(Instruction::LocalGet(0));
-        empty_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        empty_body.push(Instruction::LocalSet(2)); // type
-        
-        // Check various empty conditions
-        empty_body.push(Instruction::LocalGet(2));
-        empty_body.push(Instruction::I32Const(TYPE_NULL as i32));
-        empty_body.push(Instruction::I32Eq);
-        empty_body.push(Instruction::If(BlockType::Empty));
-        // NULL is empty
-        empty_body.push(Instruction::I32Const(1));
-        empty_body.push(Instruction::LocalSet(3)); // is_empty = true
-        empty_body.push(Instruction::Else);
-        
-        empty_body.push(Instruction::LocalGet(2));
-        empty_body.push(Instruction::I32Const(TYPE_BOOL as i32));
-        empty_body.push(Instruction::I32Eq);
-        empty_body.push(Instruction::If(BlockType::Empty));
-        // BOOL false is empty
-        empty_body.push(Instruction::LocalGet(0));
-        empty_body.push(Instruction::I32Const(4));
-        empty_body.push(Instruction::I32Add);
-        empty_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        empty_body.push(Instruction::I32Eqz);
-        empty_body.push(Instruction::LocalSet(3)); // is_empty
-        empty_body.push(Instruction::Else);
-        
-        empty_body.push(Instruction::LocalGet(2));
-        empty_body.push(Instruction::I32Const(TYPE_INT as i32));
-        empty_body.push(Instruction::I32Eq);
-        empty_body.push(Instruction::If(BlockType::Empty));
-        // INT 0 is empty
-        empty_body.push(Instruction::LocalGet(0));
-        empty_body.push(Instruction::I32Const(4));
-        empty_body.push(Instruction::I32Add);
-        empty_body.push(Instruction::I64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        empty_body.push(Instruction::I64Const(0));
-        empty_body.push(Instruction::I64Eq);
-        empty_body.push(Instruction::LocalSet(3)); // is_empty
-        empty_body.push(Instruction::Else);
-        
-        empty_body.push(Instruction::LocalGet(2));
-        empty_body.push(Instruction::I32Const(TYPE_FLOAT as i32));
-        empty_body.push(Instruction::I32Eq);
-        empty_body.push(Instruction::If(BlockType::Empty));
-        // FLOAT 0.0 is empty
-        empty_body.push(Instruction::LocalGet(0));
-        empty_body.push(Instruction::I32Const(4));
-        empty_body.push(Instruction::I32Add);
-        empty_body.push(Instruction::F64Load(MemArg { offset: 0, align: 3, memory_index: 0 }));
-        empty_body.push(Instruction::F64Const(0.0.into()));
-        empty_body.push(Instruction::F64Eq);
-        empty_body.push(Instruction::LocalSet(3)); // is_empty
-        empty_body.push(Instruction::Else);
-        
-        empty_body.push(Instruction::LocalGet(2));
-        empty_body.push(Instruction::I32Const(TYPE_STRING as i32));
-        empty_body.push(Instruction::I32Eq);
-        empty_body.push(Instruction::If(BlockType::Empty));
-        // STRING empty is empty, "0" is also empty in PHP
-        empty_body.push(Instruction::LocalGet(0));
-        empty_body.push(Instruction::I32Const(8));
-        empty_body.push(Instruction::I32Add);
-        empty_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 })); // string ptr
-        empty_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 })); // string length
-        empty_body.push(Instruction::LocalSet(2)); // reuse type local for length
-        
-        // Check if length is 0
-        empty_body.push(Instruction::LocalGet(2));
-        empty_body.push(Instruction::I32Const(0));
-        empty_body.push(Instruction::I32Eq);
-        empty_body.push(Instruction::If(BlockType::Empty));
-        empty_body.push(Instruction::I32Const(1));
-        empty_body.push(Instruction::LocalSet(3)); // is_empty = true
-        empty_body.push(Instruction::Else);
-        
-        // Check if string is "0" (length 1, first char is '0')
-        empty_body.push(Instruction::LocalGet(2));
-        empty_body.push(Instruction::I32Const(1));
-        empty_body.push(Instruction::I32Eq);
-        empty_body.push(Instruction::If(BlockType::Empty));
-        empty_body.push(Instruction::LocalGet(0));
-        empty_body.push(Instruction::I32Const(8));
-        empty_body.push(Instruction::I32Add);
-        empty_body.push(Instruction::I32Load(MemArg { offset: 0, align: 2, memory_index: 0 })); // string ptr
-        empty_body.push(Instruction::I32Const(8));
-        empty_body.push(Instruction::I32Add);
-        empty_body.push(Instruction::I32Load8U(MemArg { offset: 0, align: 0, memory_index: 0 })); // first char
-        empty_body.push(Instruction::I32Const(b'0' as i32));
-        empty_body.push(Instruction::I32Eq);
-        empty_body.push(Instruction::LocalSet(3)); // is_empty
-        empty_body.push(Instruction::Else);
-        empty_body.push(Instruction::I32Const(0));
-        empty_body.push(Instruction::LocalSet(3)); // is_empty = false
-        empty_body.push(Instruction::End);
-        
-        empty_body.push(Instruction::End);
-        empty_body.push(Instruction::Else);
-        
-        // Other types are not empty
-        empty_body.push(Instruction::I32Const(0));
-        empty_body.push(Instruction::LocalSet(3)); // is_empty = false
-        
-        empty_body.push(Instruction::End);
-        empty_body.push(Instruction::End);
-        empty_body.push(Instruction::End);
-        empty_body.push(Instruction::End);
-        empty_body.push(Instruction::End);
-        
-        // Store result
-        empty_body.push(Instruction::LocalGet(1));
-        empty_body.push(Instruction::I32Const(4));
-        empty_body.push(Instruction::I32Add);
-        empty_body.push(Instruction::LocalGet(3));
-        empty_body.push(Instruction::I32Store8(MemArg { offset: 0, align: 0, memory_index: 0 }));
-        
-        // Return result
-        empty_body.push(Instruction::LocalGet(1));
-        
-        self.builder.set_function_at_index(self.empty_fn_idx, self.unary_op_type_idx, empty_locals, empty_body);
+
+    fn add_to_number_function(&mut self) {
+        // to_number(value) -> int or float value, for arithmetic
+        let ty = self.builder.add_type(vec![ValType::I32], vec![ValType::I32]);
+        let (value, tag) = (0, 1);
+        let message = self.string_literal("PHP Fatal error:  Uncaught TypeError: Unsupported operand types\n");
+        let body = vec![
+            LocalGet(value), I32Load(mem32(0)), LocalTee(tag), I32Const(TAG_INT), I32Eq,
+            LocalGet(tag), I32Const(TAG_FLOAT), I32Eq, I32Or,
+            If(BlockType::Empty), LocalGet(value), Return, End,
+            LocalGet(tag), I32Const(TAG_STRING), I32Eq,
+            If(BlockType::Empty), LocalGet(value), Call(self.h.parse_number), Return, End,
+            LocalGet(tag), I32Const(TAG_ARRAY), I32GeU,
+            If(BlockType::Empty), I32Const(message as i32), Call(self.h.fatal), End,
+            // null and booleans
+            LocalGet(value), Call(self.h.to_int), Call(self.h.new_int),
+        ];
+        self.builder.set_function_at_index(self.h.to_number, ty, vec![(1, ValType::I32)], body);
+    }
+
+    fn add_coerce_function(&mut self) {
+        // coerce(value, mask, strict) -> value of a type in `mask` where PHP would
+        // convert it; values that cannot be converted are passed through
+        let ty = self.builder.add_type(vec![ValType::I32, ValType::I32, ValType::I32], vec![ValType::I32]);
+        let (value, mask, strict, tag, number, ntag) = (0, 1, 2, 3, 4, 5);
+        let accepts = |bit: i32| [LocalGet(mask), I32Const(1 << bit), I32And, I32Const(0), I32Ne];
+        let mut body = vec![
+            LocalGet(value), I32Load(mem32(0)), LocalSet(tag),
+            LocalGet(mask), I32Const(1), LocalGet(tag), I32Shl, I32Const(MASK_ANY), I32Or, I32And,
+            If(BlockType::Empty), LocalGet(value), Return, End,
+        ];
+        // Ints widen to floats in both modes
+        body.extend([LocalGet(tag), I32Const(TAG_INT), I32Eq]);
+        body.extend(accepts(TAG_FLOAT));
+        body.extend([
+            I32And,
+            If(BlockType::Empty),
+                LocalGet(value), I64Load(mem64(VALUE_DATA)), F64ConvertI64S, Call(self.h.new_float), Return,
+            End,
+            LocalGet(strict),
+            If(BlockType::Empty), LocalGet(value), Return, End,
+
+            LocalGet(tag), I32Const(TAG_OBJECT), I32Eq,
+            If(BlockType::Empty),
+        ]);
+        body.extend(accepts(TAG_STRING));
+        body.extend([
+                If(BlockType::Empty), LocalGet(value), Call(self.h.object_to_string), Return, End,
+                LocalGet(value), Return,
+            End,
+            LocalGet(tag), I32Eqz, LocalGet(tag), I32Const(TAG_ARRAY), I32Eq, I32Or,
+            If(BlockType::Empty), LocalGet(value), Return, End,
+
+            // Scalars: numbers first, if the value is numeric
+            LocalGet(value), LocalSet(number),
+            LocalGet(tag), I32Const(TAG_BOOL), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), I64Load(mem64(VALUE_DATA)), Call(self.h.new_int), LocalSet(number),
+            End,
+            LocalGet(tag), I32Const(TAG_STRING), I32Eq,
+            If(BlockType::Empty),
+                LocalGet(value), Call(self.h.parse_number), LocalSet(number),
+                GlobalGet(self.g.numeric), I32Const(2), I32Ne,
+                If(BlockType::Empty), I32Const(0), LocalSet(number), End,
+            End,
+            LocalGet(number),
+            If(BlockType::Empty),
+                LocalGet(number), I32Load(mem32(0)), LocalSet(ntag),
+        ]);
+        body.extend(accepts(TAG_INT));
+        body.extend([
+                If(BlockType::Empty),
+                    LocalGet(ntag), I32Const(TAG_INT), I32Eq,
+                    If(BlockType::Empty), LocalGet(number), Return, End,
+                    // Floats with an integral value
+                    LocalGet(number), F64Load(mem64(VALUE_DATA)), F64Trunc,
+                    LocalGet(number), F64Load(mem64(VALUE_DATA)), F64Eq,
+                    If(BlockType::Empty),
+                        LocalGet(number), Call(self.h.to_int), Call(self.h.new_int), Return,
+                    End,
+                End,
+        ]);
+        body.extend(accepts(TAG_FLOAT));
+        body.extend([
+                If(BlockType::Empty),
+                    LocalGet(number), Call(self.h.to_float), Call(self.h.new_float), Return,
+                End,
+        ]);
+        body.extend(accepts(TAG_INT));
+        body.extend(accepts(TAG_STRING));
+        body.extend([
+                I32Eqz, I32And,
+                If(BlockType::Empty),
+                    LocalGet(number), Call(self.h.to_int), Call(self.h.new_int), Return,
+                End,
+            End,
+        ]);
+        body.extend(accepts(TAG_STRING));
+        body.extend([
+            If(BlockType::Empty), LocalGet(value), Call(self.h.to_string), Return, End,
+        ]);
+        body.extend(accepts(TAG_BOOL));
+        body.extend([
+            If(BlockType::Empty),
+                I32Const(TRUE_VALUE as i32), I32Const(FALSE_VALUE as i32), LocalGet(value), Call(self.h.to_bool), Select,
+                Return,
+            End,
+            LocalGet(value),
+        ]);
+        self.builder.set_function_at_index(self.h.coerce, ty, vec![(3, ValType::I32)], body);
+    }
+
+    fn add_check_type_function(&mut self) {
+        // check_type(value, mask, strict, prefix, suffix) -> the value coerced to
+        // a type in `mask`; a value that cannot be is an uncaught TypeError
+        // `prefix` + its type + `suffix`
+        let ty = self.builder.add_type(vec![ValType::I32; 5], vec![ValType::I32]);
+        let (value, mask, strict, prefix, suffix, result, tag) = (0, 1, 2, 3, 4, 5, 6);
+        let body = vec![
+            LocalGet(value), LocalGet(mask), LocalGet(strict), Call(self.h.coerce), LocalTee(result),
+            I32Load(mem32(0)), LocalSet(tag),
+            LocalGet(mask), I32Const(1), LocalGet(tag), I32Shl, I32Const(MASK_ANY), I32Or, I32And,
+            If(BlockType::Empty), LocalGet(result), Return, End,
+            LocalGet(prefix), LocalGet(value), Call(self.h.type_name), Call(self.h.concat),
+            LocalGet(suffix), Call(self.h.concat), Call(self.h.fatal),
+            LocalGet(result),
+        ];
+        self.builder.set_function_at_index(self.h.check_type, ty, vec![(2, ValType::I32)], body);
     }
 }
@@ -2,8 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0
 
 pub mod compiler;
+pub mod compiler_manual_gc; // Linear-memory backend for engines without WasmGC
 pub mod wasm_builder;
 pub mod error;
 
-pub use compiler::Compiler;
+pub use compiler::{
+    Backend, BackendKind, CompiledModule, Compiler, CompilerOptions, ConstantValue, Feature, ImportType, ImportedFunction,
+    OptLevel, Project, TargetFeatures,
+};
 pub use error::CompilerError;
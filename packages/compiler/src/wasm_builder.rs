@@ -1,29 +1,70 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
+//! Module assembly with tree shaking.
+//!
+//! Types, functions, globals, imports and exports are kept as plain data until
+//! [`WasmBuilder::build`]. The compiler defines every runtime helper up front;
+//! `build` walks the call graph from the exported functions, drops the
+//! functions nothing reaches and the types nothing refers to, merges identical
+//! function types and only then assigns the final function and type indices.
+//! Indices handed out before `build` are provisional and get rewritten in every
+//! function body, signature, global and export. With tree shaking turned off
+//! everything that was defined is emitted, still renumbered.
+//!
+//! With fuel metering on, `build` also instruments every function: its entry
+//! and the head of each of its loops take one unit from the exported `fuel`
+//! global and trap once it drops below zero. Every back-edge goes through a
+//! loop head, so no loop or recursion runs unmetered.
+
+use edge_php_parser::ast::SourceLocation;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use wasm_encoder::*;
 
+/// A type definition, encoded once the types in use are known
+#[derive(Debug, Clone, PartialEq)]
+enum TypeDef {
+    Func(Vec<ValType>, Vec<ValType>),
+    Struct(StructType),
+    Array(ArrayType),
+}
+
+struct FunctionDef {
+    type_idx: u32,
+    locals: Vec<(u32, ValType)>,
+    body: Vec<Instruction<'static>>,
+}
+
+struct GlobalDef {
+    ty: GlobalType,
+    init: Vec<Instruction<'static>>,
+}
+
 pub struct WasmBuilder {
-    pub module: Module,
-    pub types: TypeSection,
-    pub imports: ImportSection,
-    pub functions: FunctionSection,
-    pub exports: ExportSection,
-    pub code: CodeSection,
+    types: Vec<TypeDef>,
+    imports: Vec<(String, String, u32)>, // Module, name and type of imported functions
+    functions: HashMap<u32, FunctionDef>,
+    exports: Vec<(String, ExportKind, u32)>,
+    globals: Vec<GlobalDef>,
+    function_names: HashMap<u32, String>,
+    local_names: HashMap<u32, Vec<(u32, String)>>,
+    locations: HashMap<u32, Vec<(usize, SourceLocation)>>, // Instruction index -> source, per function
+    source_map_url: Option<String>,
+    tree_shaking: bool,
+    fuel: Option<u32>, // The fuel global, when metering is on
     pub memory: MemorySection,
-    pub globals: GlobalSection,
     pub data: DataSection,
-    
+
     // Track indices
-    type_count: u32,
-    import_func_count: u32,  // Number of imported functions
-    func_count: u32,         // Number of defined functions (in code section)
-    global_count: u32,
     next_func_idx: u32,
     next_data_offset: u32,   // Next available offset for data segments
-    
-    // Deferred functions for out-of-order definitions
-    deferred_functions: Vec<(u32, u32, Vec<(u32, ValType)>, Vec<Instruction<'static>>)>,
+}
+
+impl Default for WasmBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WasmBuilder {
@@ -38,118 +79,122 @@ impl WasmBuilder {
         });
 
         WasmBuilder {
-            module: Module::new(),
-            types: TypeSection::new(),
-            imports: ImportSection::new(),
-            functions: FunctionSection::new(),
-            exports: ExportSection::new(),
-            code: CodeSection::new(),
+            types: Vec::new(),
+            imports: Vec::new(),
+            functions: HashMap::new(),
+            exports: Vec::new(),
+            globals: Vec::new(),
+            function_names: HashMap::new(),
+            local_names: HashMap::new(),
+            locations: HashMap::new(),
+            source_map_url: None,
+            tree_shaking: true,
+            fuel: None,
             memory,
-            globals: GlobalSection::new(),
             data: DataSection::new(),
-            type_count: 0,
-            import_func_count: 0,
-            func_count: 0,
-            global_count: 0,
             next_func_idx: 0,
             next_data_offset: 0x10000,  // Start after runtime data
-            deferred_functions: Vec::new(),
         }
     }
 
+    fn add_type_def(&mut self, def: TypeDef) -> u32 {
+        self.types.push(def);
+        self.types.len() as u32 - 1
+    }
+
     pub fn add_type(&mut self, params: Vec<ValType>, results: Vec<ValType>) -> u32 {
-        let idx = self.type_count;
-        self.types.ty().function(params, results);
-        self.type_count += 1;
-        idx
+        self.add_type_def(TypeDef::Func(params, results))
     }
-    
+
     pub fn add_struct_type(&mut self, struct_type: StructType) -> u32 {
-        let idx = self.type_count;
-        self.types.ty().struct_(struct_type.fields);
-        self.type_count += 1;
-        idx
+        self.add_type_def(TypeDef::Struct(struct_type))
     }
-    
+
     pub fn add_array_type(&mut self, array_type: ArrayType) -> u32 {
-        let idx = self.type_count;
-        self.types.ty().array(&array_type.0.element_type, array_type.0.mutable);
-        self.type_count += 1;
-        idx
+        self.add_type_def(TypeDef::Array(array_type))
     }
 
+    /// Imports must be added before any function index is reserved
     pub fn add_import_func(&mut self, module: &str, name: &str, type_idx: u32) -> u32 {
-        let idx = self.import_func_count;
-        self.imports.import(
-            module,
-            name,
-            EntityType::Function(type_idx),
-        );
-        self.import_func_count += 1;
+        assert_eq!(self.next_func_idx as usize, self.imports.len(), "imports must precede defined functions");
+        self.imports.push((module.to_string(), name.to_string(), type_idx));
         self.next_func_idx += 1;
-        idx
+        self.imports.len() as u32 - 1
     }
-    
+
     pub fn reserve_function_index(&mut self) -> u32 {
         let idx = self.next_func_idx;
         self.next_func_idx += 1;
         idx
     }
-    
+
     pub fn set_function_at_index(&mut self, idx: u32, type_idx: u32, locals: Vec<(u32, ValType)>, body: Vec<Instruction<'static>>) {
-        // Store deferred function for later processing
-        self.deferred_functions.push((idx, type_idx, locals, body));
-    }
-
-    pub fn add_function(&mut self, type_idx: u32, locals: Vec<(u32, ValType)>, body: Vec<Instruction>) -> u32 {
-        // The global function index is import_count + function_section_index
-        let global_idx = self.import_func_count + self.func_count;
-        
-        self.functions.function(type_idx);
-        
-        let mut func = Function::new(locals);
-        for instr in body {
-            func.instruction(&instr);
-        }
-        func.instruction(&Instruction::End);
-        
-        self.code.function(&func);
-        self.func_count += 1;
-        self.next_func_idx += 1;
-        
-        // Return the global function index for use in exports, calls, etc.
-        global_idx
+        self.functions.insert(idx, FunctionDef { type_idx, locals, body });
     }
 
-    pub fn add_export(&mut self, name: &str, kind: ExportKind, idx: u32) {
-        self.exports.export(name, kind, idx);
-    }
-
-    pub fn add_global(&mut self, val_type: ValType, mutable: bool, init: Instruction) -> u32 {
-        let idx = self.global_count;
-        let init_expr = match init {
-            Instruction::I32Const(v) => ConstExpr::i32_const(v),
-            Instruction::I64Const(v) => ConstExpr::i64_const(v),
-            Instruction::F32Const(v) => ConstExpr::f32_const(v),
-            Instruction::F64Const(v) => ConstExpr::f64_const(v),
-            _ => ConstExpr::i32_const(0), // Default fallback
-        };
-        
-        self.globals.global(
-            GlobalType {
-                val_type,
-                mutable,
-                shared: false,
-            },
-            &init_expr,
-        );
-        self.global_count += 1;
+    pub fn add_function(&mut self, type_idx: u32, locals: Vec<(u32, ValType)>, body: Vec<Instruction<'static>>) -> u32 {
+        let idx = self.reserve_function_index();
+        self.set_function_at_index(idx, type_idx, locals, body);
         idx
     }
 
+    /// Name a function in the module's `name` section
+    pub fn set_function_name(&mut self, idx: u32, name: &str) {
+        self.function_names.insert(idx, name.to_string());
+    }
+
+    /// Name a function's locals in the module's `name` section
+    pub fn set_local_names(&mut self, idx: u32, names: Vec<(u32, String)>) {
+        self.local_names.insert(idx, names);
+    }
+
+    /// Where in the source the instructions of a function come from: each
+    /// entry covers the instructions from its index up to the next entry
+    pub fn set_source_locations(&mut self, idx: u32, locations: Vec<(usize, SourceLocation)>) {
+        self.locations.insert(idx, locations);
+    }
+
+    /// Point engines at a source map through a `sourceMappingURL` section
+    pub fn set_source_map_url(&mut self, url: &str) {
+        self.source_map_url = Some(url.to_string());
+    }
+
+    /// With tree shaking off every defined function and type is emitted
+    pub fn set_tree_shaking(&mut self, enabled: bool) {
+        self.tree_shaking = enabled;
+    }
+
+    /// Meter function entries and loop iterations against an exported `fuel`
+    /// global. It starts at `i64::MAX`; hosts set it before running the module.
+    pub fn set_fuel_metering(&mut self, enabled: bool) {
+        if enabled && self.fuel.is_none() {
+            let fuel = self.add_global(ValType::I64, true, Instruction::I64Const(i64::MAX));
+            self.add_export("fuel", ExportKind::Global, fuel);
+            self.fuel = Some(fuel);
+        }
+    }
+
+    pub fn add_export(&mut self, name: &str, kind: ExportKind, idx: u32) {
+        self.exports.push((name.to_string(), kind, idx));
+    }
+
+    pub fn add_global(&mut self, val_type: ValType, mutable: bool, init: Instruction<'static>) -> u32 {
+        self.add_global_expr(val_type, mutable, vec![init])
+    }
+
+    /// Add a global initialised by a constant expression of several
+    /// instructions (e.g. `struct.new`)
+    pub fn add_global_expr(&mut self, val_type: ValType, mutable: bool, init: Vec<Instruction<'static>>) -> u32 {
+        self.globals.push(GlobalDef {
+            ty: GlobalType { val_type, mutable, shared: false },
+            init,
+        });
+        self.globals.len() as u32 - 1
+    }
+
     pub fn add_data(&mut self, offset: u32, data: Vec<u8>) {
         let init_expr = ConstExpr::i32_const(offset as i32);
-        self.data.active(0, &init_expr, data.into_iter());
+        self.data.active(0, &init_expr, data);
     }
 
     pub fn add_string(&mut self, s: &str) -> StringRef {
@@ -164,45 +209,359 @@ impl WasmBuilder {
 
         StringRef { start, len }
     }
-    
+
     pub fn add_memory(&mut self, memory_type: MemoryType) {
         self.memory = MemorySection::new();
         self.memory.memory(memory_type);
     }
 
-    pub fn build(mut self) -> Vec<u8> {
-        // Process deferred functions
-        let mut deferred = std::mem::take(&mut self.deferred_functions);
-        deferred.sort_by_key(|(idx, _, _, _)| *idx);
-        
-        for (_, type_idx, locals, body) in deferred {
-            self.functions.function(type_idx);
-            
-            let mut func = Function::new(locals);
-            for instr in body {
+    /// The defined functions reachable from the exports, by provisional index
+    fn reachable_functions(&mut self) -> Vec<u32> {
+        if !self.tree_shaking {
+            let mut defined: Vec<u32> = self.functions.keys().copied().collect();
+            defined.sort_unstable();
+            return defined;
+        }
+        let mut reachable: Vec<u32> = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        let mut pending: Vec<u32> = self.exports.iter()
+            .filter(|(_, kind, _)| matches!(kind, ExportKind::Func))
+            .map(|(_, _, idx)| *idx)
+            .collect();
+
+        while let Some(idx) = pending.pop() {
+            if (idx as usize) < self.imports.len() || !seen.insert(idx) {
+                continue;
+            }
+            reachable.push(idx);
+            let function = self.functions.get_mut(&idx)
+                .unwrap_or_else(|| panic!("function {} is used but was never defined", idx));
+            for instr in &mut function.body {
+                visit_indices(instr, &mut |callee| pending.push(*callee), &mut |_| {});
+            }
+        }
+        reachable.sort_unstable();
+        reachable
+    }
+
+    /// Mark `type_idx` and every type its definition refers to
+    fn mark_type(&self, type_idx: u32, used: &mut [bool]) {
+        if std::mem::replace(&mut used[type_idx as usize], true) {
+            return;
+        }
+        let mut referenced = Vec::new();
+        let mut def = self.types[type_idx as usize].clone();
+        visit_type_def(&mut def, &mut |idx| referenced.push(*idx));
+        for idx in referenced {
+            self.mark_type(idx, used);
+        }
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        self.build_with_locations().0
+    }
+
+    /// Build the module, also returning the source location of code offsets
+    /// (from the start of the module) in ascending order
+    pub fn build_with_locations(mut self) -> (Vec<u8>, Vec<(usize, SourceLocation)>) {
+        // Functions: keep what the exports reach, imports first
+        let reachable = self.reachable_functions();
+        let import_count = self.imports.len() as u32;
+        let func_map: HashMap<u32, u32> = (0..import_count).map(|idx| (idx, idx))
+            .chain(reachable.iter().enumerate().map(|(position, &idx)| (idx, import_count + position as u32)))
+            .collect();
+        let mut functions: Vec<FunctionDef> = reachable.iter()
+            .map(|idx| self.functions.remove(idx).expect("reachable functions are defined"))
+            .collect();
+
+        // Types: keep what the kept functions, imports and globals refer to
+        let mut used = vec![!self.tree_shaking; self.types.len()];
+        let mut referenced = Vec::new();
+        for (_, _, type_idx) in &self.imports {
+            referenced.push(*type_idx);
+        }
+        for function in &mut functions {
+            referenced.push(function.type_idx);
+            for (_, val_type) in &mut function.locals {
+                visit_val_type(val_type, &mut |idx| referenced.push(*idx));
+            }
+            for instr in &mut function.body {
+                visit_indices(instr, &mut |_| {}, &mut |idx| referenced.push(*idx));
+            }
+        }
+        for global in &mut self.globals {
+            visit_val_type(&mut global.ty.val_type, &mut |idx| referenced.push(*idx));
+            for instr in &mut global.init {
+                visit_indices(instr, &mut |_| {}, &mut |idx| referenced.push(*idx));
+            }
+        }
+        for idx in referenced {
+            self.mark_type(idx, &mut used);
+        }
+
+        // Final type indices; identical function types share one. A type only
+        // refers to types defined before it, so those are already mapped.
+        let mut type_map: HashMap<u32, u32> = HashMap::new();
+        let mut kept_types: Vec<TypeDef> = Vec::new();
+        for (idx, def) in self.types.iter().enumerate() {
+            if !used[idx] {
+                continue;
+            }
+            let mut def = def.clone();
+            visit_type_def(&mut def, &mut |idx| *idx = type_map[idx]);
+            let existing = match def {
+                TypeDef::Func(..) if self.tree_shaking => kept_types.iter().position(|kept| *kept == def),
+                _ => None,
+            };
+            let final_idx = match existing {
+                Some(position) => position as u32,
+                None => {
+                    kept_types.push(def);
+                    kept_types.len() as u32 - 1
+                }
+            };
+            type_map.insert(idx as u32, final_idx);
+        }
+
+        let mut remap_func = |idx: &mut u32| *idx = func_map[idx];
+        let mut remap_type = |idx: &mut u32| *idx = type_map[idx];
+
+        let mut types = TypeSection::new();
+        for def in kept_types {
+            match def {
+                TypeDef::Func(params, results) => { types.ty().function(params, results); }
+                TypeDef::Struct(struct_type) => { types.ty().struct_(struct_type.fields); }
+                TypeDef::Array(array_type) => { types.ty().array(&array_type.0.element_type, array_type.0.mutable); }
+            }
+        }
+
+        let mut imports = ImportSection::new();
+        for (module, name, type_idx) in &self.imports {
+            imports.import(module, name, EntityType::Function(type_map[type_idx]));
+        }
+
+        let mut function_section = FunctionSection::new();
+        let mut code = CodeSection::new();
+        // Source locations by offset from the first function in the code section
+        let mut code_locations = Vec::new();
+        for (idx, mut function) in reachable.iter().zip(functions) {
+            function_section.function(type_map[&function.type_idx]);
+            for (_, val_type) in &mut function.locals {
+                visit_val_type(val_type, &mut remap_type);
+            }
+            let mut func = Function::new(function.locals);
+            let mut locations = self.locations.remove(idx).unwrap_or_default().into_iter().peekable();
+            let mut instruction_offsets = Vec::new();
+            if let Some(fuel) = self.fuel {
+                consume_fuel(&mut func, fuel);
+            }
+            for (position, mut instr) in function.body.into_iter().enumerate() {
+                while let Some((_, location)) = locations.next_if(|(start, _)| *start <= position) {
+                    instruction_offsets.push((func.byte_len(), location));
+                }
+                visit_indices(&mut instr, &mut remap_func, &mut remap_type);
                 func.instruction(&instr);
+                if let (Instruction::Loop(_), Some(fuel)) = (&instr, self.fuel) {
+                    consume_fuel(&mut func, fuel);
+                }
             }
             func.instruction(&Instruction::End);
-            
-            self.code.function(&func);
-            self.func_count += 1;
-        }
-        
-        self.module.section(&self.types);
-        self.module.section(&self.imports);
-        self.module.section(&self.functions);
-        
+            let body_start = code.byte_len() + leb128_len(func.byte_len());
+            code_locations.extend(instruction_offsets.into_iter().map(|(offset, location)| (body_start + offset, location)));
+            code.function(&func);
+        }
+
+        let mut globals = GlobalSection::new();
+        for mut global in self.globals {
+            visit_val_type(&mut global.ty.val_type, &mut remap_type);
+            for instr in &mut global.init {
+                visit_indices(instr, &mut remap_func, &mut remap_type);
+            }
+            globals.global(global.ty, &ConstExpr::extended(global.init));
+        }
+
+        let mut exports = ExportSection::new();
+        for (name, kind, idx) in &self.exports {
+            let idx = match kind {
+                ExportKind::Func => func_map[idx],
+                _ => *idx,
+            };
+            exports.export(name, *kind, idx);
+        }
+
+        let mut module = Module::new();
+        module.section(&types);
+        module.section(&imports);
+        module.section(&function_section);
+
         // Only add memory section if it has content
         if !self.memory.is_empty() {
-            self.module.section(&self.memory);
+            module.section(&self.memory);
+        }
+
+        module.section(&globals);
+        module.section(&exports);
+
+        // The code section starts with its id, its size and the function count
+        let function_count = code.len();
+        let code_size = leb128_len(function_count as usize) + code.byte_len();
+        let functions_start = module.len() + 1 + leb128_len(code_size) + leb128_len(function_count as usize);
+        let mut locations: Vec<(usize, SourceLocation)> = code_locations.into_iter()
+            .map(|(offset, location)| (functions_start + offset, location))
+            .collect();
+        locations.dedup_by(|next, previous| next.1 == previous.1);
+
+        module.section(&code);
+        if !self.data.is_empty() {
+            module.section(&self.data);
+        }
+
+        let mut local_names: Vec<(u32, Vec<(u32, String)>)> = self.local_names.into_iter()
+            .filter_map(|(idx, names)| func_map.get(&idx).map(|final_idx| (*final_idx, names)))
+            .collect();
+        local_names.sort_unstable();
+
+        let mut function_names: Vec<(u32, &String)> = self.function_names.iter()
+            .filter_map(|(idx, name)| func_map.get(idx).map(|final_idx| (*final_idx, name)))
+            .collect();
+        if !function_names.is_empty() || !local_names.is_empty() {
+            let mut name_section = NameSection::new();
+            function_names.sort_unstable();
+            let mut names = NameMap::new();
+            for (idx, name) in function_names {
+                names.append(idx, name);
+            }
+            name_section.functions(&names);
+
+            let mut locals = IndirectNameMap::new();
+            for (idx, mut names) in local_names {
+                names.sort_unstable();
+                names.dedup_by_key(|(local, _)| *local);
+                let mut function_locals = NameMap::new();
+                for (local, name) in &names {
+                    function_locals.append(*local, name);
+                }
+                locals.append(idx, &function_locals);
+            }
+            name_section.locals(&locals);
+            module.section(&name_section);
         }
-        
-        self.module.section(&self.globals);
-        self.module.section(&self.exports);
-        self.module.section(&self.code);
-        self.module.section(&self.data);
-        
-        self.module.finish()
+
+        if let Some(url) = &self.source_map_url {
+            let mut data = Vec::new();
+            url.as_str().encode(&mut data);
+            module.section(&CustomSection { name: Cow::Borrowed("sourceMappingURL"), data: Cow::Owned(data) });
+        }
+
+        (module.finish(), locations)
+    }
+}
+
+/// Take one unit of fuel and trap when there is none left
+fn consume_fuel(func: &mut Function, fuel: u32) {
+    func.instruction(&Instruction::GlobalGet(fuel))
+        .instruction(&Instruction::I64Const(1))
+        .instruction(&Instruction::I64Sub)
+        .instruction(&Instruction::GlobalSet(fuel))
+        .instruction(&Instruction::GlobalGet(fuel))
+        .instruction(&Instruction::I64Const(0))
+        .instruction(&Instruction::I64LtS)
+        .instruction(&Instruction::If(BlockType::Empty))
+        .instruction(&Instruction::Unreachable)
+        .instruction(&Instruction::End);
+}
+
+/// Size of `value` as an unsigned LEB128
+fn leb128_len(value: usize) -> usize {
+    let bits = usize::BITS - value.leading_zeros();
+    (bits.max(1) as usize).div_ceil(7)
+}
+
+fn visit_heap_type(heap_type: &mut HeapType, on_type: &mut dyn FnMut(&mut u32)) {
+    if let HeapType::Concrete(idx) = heap_type {
+        on_type(idx);
+    }
+}
+
+fn visit_val_type(val_type: &mut ValType, on_type: &mut dyn FnMut(&mut u32)) {
+    if let ValType::Ref(ref_type) = val_type {
+        visit_heap_type(&mut ref_type.heap_type, on_type);
+    }
+}
+
+fn visit_storage_type(storage_type: &mut StorageType, on_type: &mut dyn FnMut(&mut u32)) {
+    if let StorageType::Val(val_type) = storage_type {
+        visit_val_type(val_type, on_type);
+    }
+}
+
+fn visit_type_def(def: &mut TypeDef, on_type: &mut dyn FnMut(&mut u32)) {
+    match def {
+        TypeDef::Func(params, results) => {
+            for val_type in params.iter_mut().chain(results.iter_mut()) {
+                visit_val_type(val_type, on_type);
+            }
+        }
+        TypeDef::Struct(struct_type) => {
+            let mut fields = struct_type.fields.to_vec();
+            for field in &mut fields {
+                visit_storage_type(&mut field.element_type, on_type);
+            }
+            struct_type.fields = fields.into_boxed_slice();
+        }
+        TypeDef::Array(array_type) => visit_storage_type(&mut array_type.0.element_type, on_type),
+    }
+}
+
+/// Call `on_func` for every function index and `on_type` for every type index
+/// an instruction refers to. Instructions the compiler does not emit and that
+/// refer to neither (tables, memories, data segments) are left alone.
+fn visit_indices(instr: &mut Instruction<'static>, on_func: &mut dyn FnMut(&mut u32), on_type: &mut dyn FnMut(&mut u32)) {
+    match instr {
+        Instruction::Call(idx) | Instruction::ReturnCall(idx) | Instruction::RefFunc(idx) => on_func(idx),
+        Instruction::Block(block_type) | Instruction::Loop(block_type) | Instruction::If(block_type) => match block_type {
+            BlockType::Result(val_type) => visit_val_type(val_type, on_type),
+            BlockType::FunctionType(idx) => on_type(idx),
+            BlockType::Empty => {}
+        },
+        Instruction::TypedSelect(val_type) => visit_val_type(val_type, on_type),
+        Instruction::CallRef(idx)
+        | Instruction::ReturnCallRef(idx)
+        | Instruction::CallIndirect { type_index: idx, .. }
+        | Instruction::ReturnCallIndirect { type_index: idx, .. }
+        | Instruction::StructNew(idx)
+        | Instruction::StructNewDefault(idx)
+        | Instruction::StructGet { struct_type_index: idx, .. }
+        | Instruction::StructGetS { struct_type_index: idx, .. }
+        | Instruction::StructGetU { struct_type_index: idx, .. }
+        | Instruction::StructSet { struct_type_index: idx, .. }
+        | Instruction::ArrayNew(idx)
+        | Instruction::ArrayNewDefault(idx)
+        | Instruction::ArrayNewFixed { array_type_index: idx, .. }
+        | Instruction::ArrayNewData { array_type_index: idx, .. }
+        | Instruction::ArrayNewElem { array_type_index: idx, .. }
+        | Instruction::ArrayGet(idx)
+        | Instruction::ArrayGetS(idx)
+        | Instruction::ArrayGetU(idx)
+        | Instruction::ArraySet(idx)
+        | Instruction::ArrayFill(idx)
+        | Instruction::ArrayInitData { array_type_index: idx, .. }
+        | Instruction::ArrayInitElem { array_type_index: idx, .. } => on_type(idx),
+        Instruction::ArrayCopy { array_type_index_dst, array_type_index_src } => {
+            on_type(array_type_index_dst);
+            on_type(array_type_index_src);
+        }
+        Instruction::RefNull(heap_type)
+        | Instruction::RefTestNonNull(heap_type)
+        | Instruction::RefTestNullable(heap_type)
+        | Instruction::RefCastNonNull(heap_type)
+        | Instruction::RefCastNullable(heap_type) => visit_heap_type(heap_type, on_type),
+        Instruction::BrOnCast { from_ref_type, to_ref_type, .. }
+        | Instruction::BrOnCastFail { from_ref_type, to_ref_type, .. } => {
+            visit_heap_type(&mut from_ref_type.heap_type, on_type);
+            visit_heap_type(&mut to_ref_type.heap_type, on_type);
+        }
+        _ => {}
     }
 }
 
@@ -210,3 +569,93 @@ pub struct StringRef {
     pub start: u32,
     pub len: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{BackendKind, Compiler, CompilerOptions};
+
+    /// Validate `wasm` and return the function names from its name section
+    fn function_names(wasm: &[u8]) -> Vec<String> {
+        wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all()).validate_all(wasm).unwrap();
+        let mut names = Vec::new();
+        for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+            if let wasmparser::Payload::CustomSection(section) = payload.unwrap() {
+                if let wasmparser::KnownCustom::Name(reader) = section.as_known() {
+                    for name in reader {
+                        if let wasmparser::Name::Function(map) = name.unwrap() {
+                            names.extend(map.into_iter().map(|naming| naming.unwrap().name.to_string()));
+                        }
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    /// Number of types in the type section of `wasm`
+    fn type_count(wasm: &[u8]) -> usize {
+        let mut count = 0;
+        for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+            if let wasmparser::Payload::TypeSection(reader) = payload.unwrap() {
+                for group in reader {
+                    count += group.unwrap().types().len();
+                }
+            }
+        }
+        count
+    }
+
+    fn compile(source: &str, options: CompilerOptions) -> Vec<u8> {
+        Compiler::with_options(options).compile(source).unwrap()
+    }
+
+    #[test]
+    fn test_tree_shaking_drops_unused_helpers() {
+        let source = "<?php echo \"hi\";";
+        let names = function_names(&compile(source, CompilerOptions::new().debug_info(true)));
+        for used in ["php_print_value", "php_to_string", "php_int_to_string", "{main}"] {
+            assert!(names.iter().any(|name| name == used), "{} missing from {:?}", used, names);
+        }
+        for unused in ["php_concat", "php_array_set", "php_count", "php_array_sort", "php_hash_array_get", "php_less_than"] {
+            assert!(!names.iter().any(|name| name == unused), "{} kept in {:?}", unused, names);
+        }
+
+        let shaken = compile(source, CompilerOptions::new());
+        let full = compile(source, CompilerOptions::new().tree_shaking(false));
+        function_names(&full);
+        assert!(shaken.len() < 2048, "echo \"hi\" compiled to {} bytes", shaken.len());
+        assert!(type_count(&shaken) * 2 < type_count(&full), "{} of {} types kept", type_count(&shaken), type_count(&full));
+    }
+
+    #[test]
+    fn test_tree_shaking_linear_memory() {
+        // The linear backend's runtime goes through the same builder
+        let source = "<?php echo \"hi\";";
+        let linear = || CompilerOptions::new().backend(BackendKind::LinearMemory);
+        let names = function_names(&compile(source, linear().debug_info(true)));
+        for used in ["php_echo", "php_alloc", "php_gc_collect", "{main}"] {
+            assert!(names.iter().any(|name| name == used), "{} missing from {:?}", used, names);
+        }
+        for unused in ["php_arr_insert", "php_count", "php_str_replace", "php_explode", "php_gc_destruct", "php_compare"] {
+            assert!(!names.iter().any(|name| name == unused), "{} kept in {:?}", unused, names);
+        }
+
+        let shaken = compile(source, linear());
+        let full = compile(source, linear().tree_shaking(false));
+        assert!(shaken.len() < full.len(), "{} of {} bytes kept", shaken.len(), full.len());
+        assert!(type_count(&shaken) * 2 < type_count(&full), "{} of {} types kept", type_count(&shaken), type_count(&full));
+    }
+
+    #[test]
+    fn test_tree_shaking_keeps_indirect_dependencies() {
+        // Only array_sort calls sort_less, and only sort_less string_compare
+        let source = "<?php $a = [3, \"b\", 1]; sort($a); echo $a[0];";
+        let names = function_names(&compile(source, CompilerOptions::new().debug_info(true)));
+        for used in ["php_array_sort", "php_sort_less", "php_string_compare", "php_string_to_float_if_numeric", "php_less_than"] {
+            assert!(names.iter().any(|name| name == used), "{} missing from {:?}", used, names);
+        }
+        for unused in ["php_array_sum", "php_concat", "php_array_get_int"] {
+            assert!(!names.iter().any(|name| name == unused), "{} kept in {:?}", unused, names);
+        }
+    }
+}
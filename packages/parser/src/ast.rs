@@ -48,6 +48,8 @@ pub enum Statement {
     },
     Class {
         name: String,
+        is_final: bool,
+        is_readonly: bool,
         extends: Option<String>,
         implements: Vec<String>,
         members: Vec<ClassMember>,
@@ -61,13 +63,45 @@ pub enum Statement {
     Foreach {
         array: Expression,
         key: Option<String>,
-        value: String,
+        value: Expression, // Variable or List (destructuring)
         body: Block,
     },
     Switch {
         expr: Expression,
         cases: Vec<SwitchCase>,
     },
+    Global(Vec<String>),
+    Static(Vec<StaticVariable>),
+    Enum {
+        name: String,
+        backing_type: Option<Type>, // int or string for backed enums
+        implements: Vec<String>,
+        members: Vec<ClassMember>,
+    },
+    Interface {
+        name: String,
+        members: Vec<ClassMember>, // Constants and abstract methods
+    },
+    /// `declare(strict_types=1);`
+    Declare(Vec<(String, Expression)>),
+    /// Where the next statement starts; only produced by
+    /// [`crate::parser::parse_with_locations`]
+    Location(SourceLocation),
+}
+
+/// A position in a source file; `line` and `column` count from 1, and
+/// `file` is an index the caller assigns (0 for a single source)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub file: u32,
+    pub line: u32,
+    pub column: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StaticVariable {
+    pub name: String,
+    pub default: Option<Expression>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -117,11 +151,25 @@ pub enum Expression {
         object: Box<Expression>,
         property: String,
     },
+    StaticPropertyAccess {
+        class: String,
+        property: String,
+    },
+    NullsafeMethodCall {
+        object: Box<Expression>,
+        method: String,
+        args: Vec<Expression>,
+    },
+    NullsafePropertyAccess {
+        object: Box<Expression>,
+        property: String,
+    },
     ArrayAccess {
         array: Box<Expression>,
         index: Box<Expression>,
     },
     Array(Vec<ArrayElement>),
+    List(Vec<Option<ArrayElement>>), // Destructuring target; None marks a skipped slot
     New {
         class: String,
         args: Vec<Expression>,
@@ -135,6 +183,63 @@ pub enum Expression {
         cast_type: Type,
         expr: Box<Expression>,
     },
+    Coalesce {
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    ClassConstant {
+        class: String, // Class name, or "self" inside a class body
+        name: String,  // Constant or enum case
+    },
+    StaticMethodCall {
+        class: String,
+        method: String,
+        args: Vec<Expression>,
+    },
+    Clone(Box<Expression>),
+    Invoke {
+        callee: Box<Expression>, // $callable(...) - calls the value of an expression
+        args: Vec<Expression>,
+    },
+    Include {
+        kind: IncludeKind,
+        path: Box<Expression>,
+    },
+    MagicConstant(MagicConstant),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum IncludeKind {
+    Include,
+    IncludeOnce,
+    Require,
+    RequireOnce,
+}
+
+impl IncludeKind {
+    pub fn is_once(self) -> bool {
+        matches!(self, IncludeKind::IncludeOnce | IncludeKind::RequireOnce)
+    }
+
+    pub fn keyword(self) -> &'static str {
+        match self {
+            IncludeKind::Include => "include",
+            IncludeKind::IncludeOnce => "include_once",
+            IncludeKind::Require => "require",
+            IncludeKind::RequireOnce => "require_once",
+        }
+    }
+}
+
+/// `__DIR__`, `__FILE__`, ... - replaced with their values during name resolution
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MagicConstant {
+    Dir,
+    File,
+    Namespace,
+    Class,
+    Function,
+    Method,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -151,6 +256,7 @@ pub enum Literal {
 pub enum InterpolatedPart {
     Text(String),
     Variable(String),
+    Expression(Expression), // "{$obj->a}", "$arr[key]", "${name[expr]}"
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -188,6 +294,14 @@ pub struct Parameter {
     pub name: String,
     pub param_type: Option<Type>,
     pub default: Option<Expression>,
+    pub promoted: Option<PromotedProperty>, // Constructor property promotion
+}
+
+/// Modifiers of a promoted constructor parameter: `private readonly int $id`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromotedProperty {
+    pub visibility: Visibility,
+    pub is_readonly: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -197,21 +311,34 @@ pub enum Type {
     String,
     Bool,
     Array,
-    Object(String),
+    Object(String), // class or interface name; also `object`, `self` and `static`
     Mixed,
     Void,
+    Null,
+    False,
+    True,
+    Never,
+    Iterable,
+    Callable,
+    Nullable(Box<Type>),     // ?T
+    Union(Vec<Type>),        // T|U
+    Intersection(Vec<Type>), // A&B
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ClassMember {
     Property {
         visibility: Visibility,
+        is_static: bool,
+        is_readonly: bool,
         name: String,
         property_type: Option<Type>,
         default: Option<Expression>,
     },
     Method {
         visibility: Visibility,
+        is_static: bool,
+        is_final: bool,
         name: String,
         params: Vec<Parameter>,
         body: Block,
@@ -222,6 +349,22 @@ pub enum ClassMember {
         params: Vec<Parameter>,
         body: Block,
     },
+    AbstractMethod {
+        visibility: Visibility,
+        name: String,
+        params: Vec<Parameter>,
+        return_type: Option<Type>,
+    },
+    Constant {
+        visibility: Visibility,
+        is_final: bool,
+        name: String,
+        value: Expression,
+    },
+    Case {
+        name: String,
+        value: Option<Expression>, // Backing value of a backed enum case
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -244,12 +387,20 @@ pub struct UseStatement {
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UseItem {
-    pub path: String,
+    pub kind: UseKind,
+    pub path: String, // Fully qualified, without the leading backslash
     pub alias: Option<String>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum UseKind {
+    Class, // Also imports namespaces
+    Function,
+    Constant,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct NamespaceStatement {
-    pub name: String,
+    pub name: String, // Empty for the global namespace: `namespace { ... }`
     pub body: Block,
 }
@@ -7,9 +7,10 @@ use nom::{
     bytes::complete::{tag, take_while, take_while1, take_until},
     character::complete::{char, multispace1, digit1},
     combinator::{map, opt, recognize, value},
+    multi::many0,
     sequence::{delimited, pair, preceded, tuple},
 };
-use crate::ast::InterpolatedPart;
+use crate::ast::{Expression, InterpolatedPart, Literal};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
@@ -50,6 +51,20 @@ pub enum Token {
     Switch,
     Case,
     Default,
+    Global,
+    Static,
+    Enum,
+    Interface,
+    Implements,
+    Const,
+    Clone,
+    Declare,
+    Readonly,
+    Final,
+    Include,
+    IncludeOnce,
+    Require,
+    RequireOnce,
 
     // Operators
     Plus,
@@ -77,9 +92,15 @@ pub enum Token {
     Not,
     Dot,
     Arrow,
+    NullsafeArrow,
     DoubleArrow,
     Question,
+    Pipe,      // `|` in union types
+    Ampersand, // `&` in intersection types
+    Coalesce,
+    CoalesceEqual,
     Colon,
+    DoubleColon,
     
     // Delimiters
     LeftParen,
@@ -90,6 +111,7 @@ pub enum Token {
     RightBracket,
     Semicolon,
     Comma,
+    Backslash, // Namespace separator before a group use: `use App\{A, B}`
     
     // Special
     PhpOpen,
@@ -100,11 +122,26 @@ pub enum Token {
 }
 
 pub fn lex(input: &str) -> Result<Vec<Token>, String> {
+    lex_with_offsets(input).map(|(tokens, _)| tokens)
+}
+
+/// Like [`lex`], also returning the byte offset in `input` where each token starts
+pub fn lex_with_offsets(input: &str) -> Result<(Vec<Token>, Vec<usize>), String> {
     let mut tokens = Vec::new();
+    let mut offsets = Vec::new();
     let mut remaining = input;
     let mut in_php = false;
     
     while !remaining.is_empty() {
+        if in_php {
+            remaining = skip_trivia(remaining);
+            if remaining.is_empty() {
+                break;
+            }
+        }
+        // The token this iteration produces starts here
+        let start = input.len() - remaining.len();
+
         if !in_php {
             // Look for PHP opening tag or short echo tag at the current position
             if remaining.starts_with("<?=") {
@@ -160,43 +197,73 @@ pub fn lex(input: &str) -> Result<Vec<Token>, String> {
             }
         } else {
             // Inside PHP code
-            // Skip whitespace
-            if let Ok((rest, _)) = multispace1::<_, nom::error::Error<_>>(remaining) {
-                remaining = rest;
-                continue;
-            }
-            
-            // Skip comments
-            if let Ok((rest, _)) = comment(remaining) {
-                remaining = rest;
-                continue;
-            }
-            
             // Check for PHP closing tag
             if remaining.starts_with("?>") {
                 tokens.push(Token::PhpClose);
                 remaining = &remaining[2..];
+                // A single newline directly after the closing tag belongs to the tag
+                remaining = remaining.strip_prefix("\r\n")
+                    .or_else(|| remaining.strip_prefix('\n'))
+                    .unwrap_or(remaining);
                 in_php = false;
-                continue;
-            }
-            
-            // Try to match tokens
-            match lex_token(remaining) {
-                Ok((rest, token)) => {
-                    tokens.push(token);
-                    remaining = rest;
-                }
-                Err(_) => {
-                    return Err(format!("Unable to parse token at: {}", &remaining[..20.min(remaining.len())]));
-                }
+            } else {
+                let (rest, token) = code_token(remaining)?;
+                tokens.push(token);
+                remaining = rest;
             }
         }
+        offsets.resize(tokens.len(), start);
+    }
+    
+    tokens.push(Token::Eof);
+    offsets.push(input.len());
+    Ok((tokens, offsets))
+}
+
+/// Tokenizes a fragment of PHP code that is not wrapped in `<?php` tags,
+/// such as an expression embedded in an interpolated string.
+pub fn lex_code(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut remaining = skip_trivia(input);
+    
+    while !remaining.is_empty() {
+        let (rest, token) = code_token(remaining)?;
+        tokens.push(token);
+        remaining = skip_trivia(rest);
     }
     
     tokens.push(Token::Eof);
     Ok(tokens)
 }
 
+/// Skip whitespace and comments
+fn skip_trivia(mut input: &str) -> &str {
+    loop {
+        if let Ok((rest, _)) = multispace1::<_, nom::error::Error<_>>(input) {
+            input = rest;
+        } else if let Ok((rest, _)) = comment(input) {
+            input = rest;
+        } else {
+            return input;
+        }
+    }
+}
+
+fn code_token(input: &str) -> Result<(&str, Token), String> {
+    // Double-quoted strings and heredocs are lexed outside of nom so that
+    // errors in escapes and embedded expressions keep their message
+    if let Some(result) = heredoc(input) {
+        return result;
+    }
+    if let Some(body) = input.strip_prefix('"') {
+        let (end, parts) = scan_interpolated(body, Some('"'))?;
+        return Ok((&body[end + 1..], string_token(parts)));
+    }
+    
+    lex_token(input)
+        .map_err(|_| format!("Unable to parse token at: {}", input.chars().take(20).collect::<String>()))
+}
+
 fn lex_token(input: &str) -> IResult<&str, Token> {
     alt((
         // Keywords and identifiers
@@ -230,7 +297,12 @@ fn comment(input: &str) -> IResult<&str, &str> {
 }
 
 fn keyword_or_identifier(input: &str) -> IResult<&str, Token> {
-    let (input, word) = identifier_str(input)?;
+    let (input, word) = name_str(input)?;
+    
+    // Qualified names (`App\Models\User`, `\strlen`) are never keywords
+    if word.contains('\\') {
+        return Ok((input, Token::Identifier(word.to_string())));
+    }
     
     let token = match word {
         "if" => Token::If,
@@ -256,6 +328,20 @@ fn keyword_or_identifier(input: &str) -> IResult<&str, Token> {
         "switch" => Token::Switch,
         "case" => Token::Case,
         "default" => Token::Default,
+        "global" => Token::Global,
+        "static" => Token::Static,
+        "enum" => Token::Enum,
+        "interface" => Token::Interface,
+        "implements" => Token::Implements,
+        "const" => Token::Const,
+        "clone" => Token::Clone,
+        "declare" => Token::Declare,
+        "readonly" => Token::Readonly,
+        "final" => Token::Final,
+        "include" => Token::Include,
+        "include_once" => Token::IncludeOnce,
+        "require" => Token::Require,
+        "require_once" => Token::RequireOnce,
         "true" => Token::True,
         "false" => Token::False,
         "null" => Token::Null,
@@ -274,6 +360,17 @@ fn identifier_str(input: &str) -> IResult<&str, &str> {
     )(input)
 }
 
+/// A plain, qualified or fully qualified name: `User`, `Models\User`, `\App\Models\User`
+fn name_str(input: &str) -> IResult<&str, &str> {
+    recognize(
+        tuple((
+            opt(char('\\')),
+            identifier_str,
+            many0(preceded(char('\\'), identifier_str)),
+        ))
+    )(input)
+}
+
 fn variable(input: &str) -> IResult<&str, Token> {
     map(
         preceded(char('$'), identifier_str),
@@ -302,163 +399,413 @@ fn number(input: &str) -> IResult<&str, Token> {
 }
 
 fn string_literal(input: &str) -> IResult<&str, Token> {
-    alt((
-        // Double-quoted string - handle escape sequences and interpolation
-        map(
-            delimited(
-                char('"'),
-                interpolated_string('"'),
-                char('"')
-            ),
-            |parts: Vec<InterpolatedPart>| {
-                // If there's only one part and it's text, return a simple string
-                if parts.len() == 1 {
-                    if let InterpolatedPart::Text(s) = &parts[0] {
-                        return Token::String(s.clone());
-                    }
-                }
-                Token::InterpolatedString(parts)
-            }
-        ),
-        // Single-quoted string - only handle \' and \\
-        map(
-            delimited(
-                char('\''),
-                single_quoted_string(),
-                char('\'')
-            ),
-            |s: String| Token::String(s)
+    // Single-quoted string - only handle \' and \\
+    // (double-quoted strings and heredocs are handled by code_token)
+    map(
+        delimited(
+            char('\''),
+            single_quoted_string(),
+            char('\'')
         ),
-    ))(input)
+        Token::String
+    )(input)
 }
 
-fn interpolated_string(quote: char) -> impl Fn(&str) -> IResult<&str, Vec<InterpolatedPart>> {
-    move |input: &str| {
-        let mut parts = Vec::new();
-        let mut current_text = String::new();
-        let mut chars = input.char_indices();
+fn string_token(parts: Vec<InterpolatedPart>) -> Token {
+    // A string without interpolation is a plain string literal
+    match parts.as_slice() {
+        [] => Token::String(String::new()),
+        [InterpolatedPart::Text(s)] => Token::String(s.clone()),
+        _ => Token::InterpolatedString(parts),
+    }
+}
+
+// ===== HEREDOC / NOWDOC =====
+
+/// Lex `<<<LABEL`, `<<<"LABEL"` (heredoc) or `<<<'LABEL'` (nowdoc).
+/// Returns `None` if the input does not start a heredoc.
+fn heredoc(input: &str) -> Option<Result<(&str, Token), String>> {
+    let after = input.strip_prefix("<<<")?.trim_start_matches([' ', '\t']);
+    
+    let (label, is_nowdoc, after_label) = if let Some(rest) = after.strip_prefix('\'') {
+        let (rest, label) = identifier_str(rest).ok()?;
+        (label, true, rest.strip_prefix('\'')?)
+    } else if let Some(rest) = after.strip_prefix('"') {
+        let (rest, label) = identifier_str(rest).ok()?;
+        (label, false, rest.strip_prefix('"')?)
+    } else {
+        let (rest, label) = identifier_str(after).ok()?;
+        (label, false, rest)
+    };
+    
+    let body_start = after_label
+        .strip_prefix("\r\n")
+        .or_else(|| after_label.strip_prefix('\n'))?;
+    
+    Some(heredoc_body(body_start, label, is_nowdoc))
+}
+
+fn heredoc_body<'a>(input: &'a str, label: &str, is_nowdoc: bool) -> Result<(&'a str, Token), String> {
+    // Find the closing marker: a line holding only optional indentation followed
+    // by the label, where the label is not the prefix of a longer identifier
+    let mut line_start = 0;
+    let (body_end, indent, rest) = loop {
+        if line_start > input.len() {
+            return Err(format!("Unterminated heredoc, missing closing marker {}", label));
+        }
+        let line = &input[line_start..];
+        let trimmed = line.trim_start_matches([' ', '\t']);
+        if let Some(after) = trimmed.strip_prefix(label) {
+            if !after.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+                let indent = &line[..line.len() - trimmed.len()];
+                break (line_start, indent, after);
+            }
+        }
+        line_start += match line.find('\n') {
+            Some(pos) => pos + 1,
+            None => line.len() + 1,
+        };
+    };
+    
+    // The newline before the closing marker is not part of the string
+    let raw = input[..body_end]
+        .strip_suffix('\n')
+        .map(|s| s.strip_suffix('\r').unwrap_or(s))
+        .unwrap_or("");
+    let body = remove_heredoc_indentation(raw, indent)?;
+    
+    if is_nowdoc {
+        return Ok((rest, Token::String(body)));
+    }
+    let (_, parts) = scan_interpolated(&body, None)?;
+    Ok((rest, string_token(parts)))
+}
+
+/// PHP 7.3 flexible heredoc: the closing marker's indentation is removed
+/// from every line of the body
+fn remove_heredoc_indentation(body: &str, indent: &str) -> Result<String, String> {
+    if indent.is_empty() {
+        return Ok(body.to_string());
+    }
+    if indent.contains(' ') && indent.contains('\t') {
+        return Err("Invalid indentation - tabs and spaces cannot be mixed".to_string());
+    }
+    
+    let lines: Vec<&str> = body
+        .split('\n')
+        .map(|line| {
+            if let Some(stripped) = line.strip_prefix(indent) {
+                Ok(stripped)
+            } else if line.trim_end_matches('\r').trim_start_matches([' ', '\t']).is_empty() {
+                // Whitespace-only lines may be less indented than the marker
+                Ok(line.get(indent.len()..).unwrap_or(""))
+            } else {
+                Err(format!(
+                    "Invalid body indentation level (expecting an indentation level of at least {})",
+                    indent.len()
+                ))
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    
+    Ok(lines.join("\n"))
+}
+
+// ===== STRING INTERPOLATION =====
+
+/// Scan the contents of a double-quoted string or heredoc body, resolving
+/// escape sequences and splitting out interpolated variables and expressions.
+/// Scanning stops at `terminator` (the closing quote) or, for heredocs, at the
+/// end of the input. Returns the byte offset where scanning stopped.
+fn scan_interpolated(input: &str, terminator: Option<char>) -> Result<(usize, Vec<InterpolatedPart>), String> {
+    let bytes = input.as_bytes();
+    let mut parts = Vec::new();
+    // Raw bytes, since \x and octal escapes may produce partial UTF-8 sequences
+    let mut text: Vec<u8> = Vec::new();
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        let ch = bytes[i];
+        if terminator == Some(ch as char) {
+            flush_text(&mut text, &mut parts);
+            return Ok((i, parts));
+        }
         
-        while let Some((idx, ch)) = chars.next() {
-            if ch == quote {
-                // Found closing quote
-                if !current_text.is_empty() {
-                    parts.push(InterpolatedPart::Text(current_text));
-                }
-                return Ok((&input[idx..], parts));
-            } else if ch == '\\' {
-                // Handle escape sequences
-                if let Some((_, escaped)) = chars.next() {
-                    match escaped {
-                        'n' => current_text.push('\n'),
-                        't' => current_text.push('\t'),
-                        'r' => current_text.push('\r'),
-                        '\\' => current_text.push('\\'),
-                        '"' => current_text.push('"'),
-                        '\'' => current_text.push('\''),
-                        '$' => current_text.push('$'), // Escaped dollar sign
-                        _ => {
-                            current_text.push('\\');
-                            current_text.push(escaped);
-                        }
-                    }
+        match ch {
+            b'\\' => {
+                i = read_escape(input, i, terminator, &mut text)?;
+            }
+            b'$' if bytes.get(i + 1).is_some_and(|&c| is_identifier_start(c)) => {
+                flush_text(&mut text, &mut parts);
+                let (end, part) = simple_interpolation(input, i)?;
+                parts.push(part);
+                i = end;
+            }
+            b'$' if bytes.get(i + 1) == Some(&b'{') => {
+                // ${name} or ${name[expr]}
+                flush_text(&mut text, &mut parts);
+                let close = find_closing_brace(input, i + 1)?;
+                parts.push(dollar_brace_interpolation(input[i + 2..close].trim())?);
+                i = close + 1;
+            }
+            b'{' if bytes.get(i + 1) == Some(&b'$') => {
+                // {$expr} - any expression starting with a variable
+                flush_text(&mut text, &mut parts);
+                let close = find_closing_brace(input, i)?;
+                let expr = crate::parser::parse_embedded_expression(&input[i + 1..close])
+                    .map_err(|e| e.to_string())?;
+                parts.push(InterpolatedPart::Expression(expr));
+                i = close + 1;
+            }
+            _ => {
+                text.push(ch);
+                i += 1;
+            }
+        }
+    }
+    
+    if terminator.is_some() {
+        return Err("Unterminated string literal".to_string());
+    }
+    flush_text(&mut text, &mut parts);
+    Ok((i, parts))
+}
+
+fn flush_text(text: &mut Vec<u8>, parts: &mut Vec<InterpolatedPart>) {
+    if !text.is_empty() {
+        parts.push(InterpolatedPart::Text(String::from_utf8_lossy(text).into_owned()));
+        text.clear();
+    }
+}
+
+fn is_identifier_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_' || c >= 0x80
+}
+
+fn is_identifier_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80
+}
+
+fn identifier_end(bytes: &[u8], start: usize) -> usize {
+    let mut end = start;
+    while end < bytes.len() && is_identifier_char(bytes[end]) {
+        end += 1;
+    }
+    end
+}
+
+/// Resolve the escape sequence starting at the backslash at `start`,
+/// returning the offset just past it
+fn read_escape(input: &str, start: usize, terminator: Option<char>, text: &mut Vec<u8>) -> Result<usize, String> {
+    let bytes = input.as_bytes();
+    let Some(&escaped) = bytes.get(start + 1) else {
+        text.push(b'\\');
+        return Ok(start + 1);
+    };
+    
+    let simple = match escaped {
+        b'n' => Some(b'\n'),
+        b't' => Some(b'\t'),
+        b'r' => Some(b'\r'),
+        b'v' => Some(0x0B),
+        b'e' => Some(0x1B),
+        b'f' => Some(0x0C),
+        b'\\' => Some(b'\\'),
+        b'$' => Some(b'$'),
+        // \" only escapes inside double quotes; heredocs keep the backslash
+        b'"' if terminator == Some('"') => Some(b'"'),
+        _ => None,
+    };
+    if let Some(byte) = simple {
+        text.push(byte);
+        return Ok(start + 2);
+    }
+    
+    match escaped {
+        b'0'..=b'7' => {
+            // Octal: \[0-7]{1,3}, overflowing values wrap to a single byte
+            let digits = count_matching(bytes, start + 1, 3, |c| (b'0'..=b'7').contains(&c));
+            let value = u32::from_str_radix(&input[start + 1..start + 1 + digits], 8).unwrap();
+            text.push((value & 0xFF) as u8);
+            Ok(start + 1 + digits)
+        }
+        b'x' if bytes.get(start + 2).is_some_and(|c| c.is_ascii_hexdigit()) => {
+            // Hex: \x[0-9A-Fa-f]{1,2}
+            let digits = count_matching(bytes, start + 2, 2, |c| c.is_ascii_hexdigit());
+            let value = u8::from_str_radix(&input[start + 2..start + 2 + digits], 16).unwrap();
+            text.push(value);
+            Ok(start + 2 + digits)
+        }
+        b'u' if bytes.get(start + 2) == Some(&b'{') => {
+            // Unicode codepoint: \u{[0-9A-Fa-f]+}
+            let digits_start = start + 3;
+            let close = input[digits_start..]
+                .find('}')
+                .map(|pos| digits_start + pos)
+                .ok_or("Invalid UTF-8 codepoint escape sequence: Missing closing curly brace")?;
+            let digits = &input[digits_start..close];
+            if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_hexdigit()) {
+                return Err("Invalid UTF-8 codepoint escape sequence".to_string());
+            }
+            let codepoint = u32::from_str_radix(digits, 16)
+                .ok()
+                .filter(|&cp| cp <= 0x10FFFF)
+                .ok_or("Invalid UTF-8 codepoint escape sequence: Codepoint too large")?;
+            encode_utf8(codepoint, text);
+            Ok(close + 1)
+        }
+        _ => {
+            // Unknown escapes are kept verbatim, including the backslash.
+            // The escaped character is still consumed, so "\{$a}" is not complex syntax.
+            text.extend([b'\\', escaped]);
+            Ok(start + 2)
+        }
+    }
+}
+
+fn count_matching(bytes: &[u8], start: usize, max: usize, pred: impl Fn(u8) -> bool) -> usize {
+    bytes[start..].iter().take(max).take_while(|&&c| pred(c)).count()
+}
+
+/// UTF-8 encode a codepoint; unlike `char`, this accepts surrogates as PHP does
+fn encode_utf8(codepoint: u32, out: &mut Vec<u8>) {
+    match codepoint {
+        0..=0x7F => out.push(codepoint as u8),
+        0x80..=0x7FF => out.extend([
+            0xC0 | (codepoint >> 6) as u8,
+            0x80 | (codepoint & 0x3F) as u8,
+        ]),
+        0x800..=0xFFFF => out.extend([
+            0xE0 | (codepoint >> 12) as u8,
+            0x80 | ((codepoint >> 6) & 0x3F) as u8,
+            0x80 | (codepoint & 0x3F) as u8,
+        ]),
+        _ => out.extend([
+            0xF0 | (codepoint >> 18) as u8,
+            0x80 | ((codepoint >> 12) & 0x3F) as u8,
+            0x80 | ((codepoint >> 6) & 0x3F) as u8,
+            0x80 | (codepoint & 0x3F) as u8,
+        ]),
+    }
+}
+
+/// Simple interpolation syntax: `$name`, `$name[key]`, `$name->prop`
+/// and `$name?->prop`, each at most one level deep
+fn simple_interpolation(input: &str, start: usize) -> Result<(usize, InterpolatedPart), String> {
+    let bytes = input.as_bytes();
+    let name_end = identifier_end(bytes, start + 1);
+    let name = input[start + 1..name_end].to_string();
+    let rest = &input[name_end..];
+    
+    if rest.starts_with('[') {
+        let (end, index) = simple_offset(input, name_end + 1)?;
+        let expr = Expression::ArrayAccess {
+            array: Box::new(Expression::Variable(name)),
+            index: Box::new(index),
+        };
+        return Ok((end, InterpolatedPart::Expression(expr)));
+    }
+    
+    for (arrow, nullsafe) in [("->", false), ("?->", true)] {
+        let prop_start = name_end + arrow.len();
+        if rest.starts_with(arrow) && bytes.get(prop_start).is_some_and(|&c| is_identifier_start(c)) {
+            let prop_end = identifier_end(bytes, prop_start);
+            let object = Box::new(Expression::Variable(name));
+            let property = input[prop_start..prop_end].to_string();
+            let expr = if nullsafe {
+                Expression::NullsafePropertyAccess { object, property }
+            } else {
+                Expression::PropertyAccess { object, property }
+            };
+            return Ok((prop_end, InterpolatedPart::Expression(expr)));
+        }
+    }
+    
+    Ok((name_end, InterpolatedPart::Variable(name)))
+}
+
+/// The offset in `"$name[...]"`: a bare word (string key), an integer or a variable
+fn simple_offset(input: &str, start: usize) -> Result<(usize, Expression), String> {
+    let bytes = input.as_bytes();
+    let mut end = start;
+    
+    let index = match bytes.get(start) {
+        Some(b'$') if bytes.get(start + 1).is_some_and(|&c| is_identifier_start(c)) => {
+            end = identifier_end(bytes, start + 1);
+            Expression::Variable(input[start + 1..end].to_string())
+        }
+        Some(c) if c.is_ascii_digit() || *c == b'-' => {
+            end += 1;
+            while end < bytes.len() && bytes[end].is_ascii_digit() {
+                end += 1;
+            }
+            let digits = &input[start..end];
+            // Integers with leading zeros (or out of range) are string keys, as in PHP
+            let canonical = digits.parse::<i64>().ok().filter(|n| n.to_string() == digits);
+            match canonical {
+                Some(n) => Expression::Literal(Literal::Integer(n)),
+                None => Expression::Literal(Literal::String(digits.to_string())),
+            }
+        }
+        Some(&c) if is_identifier_start(c) => {
+            end = identifier_end(bytes, start);
+            Expression::Literal(Literal::String(input[start..end].to_string()))
+        }
+        _ => return Err("Unexpected character in string offset, expected identifier, number or variable".to_string()),
+    };
+    
+    if bytes.get(end) != Some(&b']') {
+        return Err("Unterminated string offset, expected ']'".to_string());
+    }
+    Ok((end + 1, index))
+}
+
+/// `${name}` and `${name[expr]}`
+fn dollar_brace_interpolation(inner: &str) -> Result<InterpolatedPart, String> {
+    let name_end = identifier_end(inner.as_bytes(), 0);
+    if name_end > 0 && is_identifier_start(inner.as_bytes()[0]) {
+        if name_end == inner.len() {
+            return Ok(InterpolatedPart::Variable(inner.to_string()));
+        }
+        if inner[name_end..].starts_with('[') {
+            let expr = crate::parser::parse_embedded_expression(&format!("${}", inner))
+                .map_err(|e| e.to_string())?;
+            return Ok(InterpolatedPart::Expression(expr));
+        }
+    }
+    Err(format!("Variable variables are not supported in string interpolation: ${{{}}}", inner))
+}
+
+/// Find the `}` matching the `{` at `open`, skipping over quoted strings
+fn find_closing_brace(input: &str, open: usize) -> Result<usize, String> {
+    let bytes = input.as_bytes();
+    let mut depth = 0;
+    let mut i = open;
+    
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i);
                 }
-            } else if ch == '$' {
-                // Check if this is a variable or escaped dollar
-                if let Some((var_start_idx, next_ch)) = chars.next() {
-                    if next_ch == '$' {
-                        // Double dollar sign - escaped dollar
-                        current_text.push('$');
-                    } else if next_ch.is_alphabetic() || next_ch == '_' {
-                        // This is a variable - save current text first
-                        if !current_text.is_empty() {
-                            parts.push(InterpolatedPart::Text(current_text));
-                            current_text = String::new();
-                        }
-                        
-                        // Collect the variable name
-                        let mut var_name = String::new();
-                        var_name.push(next_ch);
-                        
-                        // Continue collecting alphanumeric characters
-                        let mut found_end = false;
-                        let mut end_char = '\0';
-                        let mut continue_from = var_start_idx + 1;
-                        
-                        while let Some((idx, ch)) = chars.next() {
-                            if ch.is_alphanumeric() || ch == '_' {
-                                var_name.push(ch);
-                                continue_from = idx + 1;
-                            } else {
-                                // End of variable name
-                                found_end = true;
-                                end_char = ch;
-                                continue_from = idx;
-                                break;
-                            }
-                        }
-                        
-                        // Add the variable
-                        parts.push(InterpolatedPart::Variable(var_name));
-                        
-                        // Check if we found the end
-                        if found_end {
-                            if end_char == quote {
-                                // If we hit the closing quote, return
-                                return Ok((&input[continue_from..], parts));
-                            } else if end_char == '\\' {
-                                // Handle escape sequence after variable
-                                if let Some((_, escaped)) = chars.next() {
-                                    match escaped {
-                                        'n' => current_text.push('\n'),
-                                        't' => current_text.push('\t'),
-                                        'r' => current_text.push('\r'),
-                                        '\\' => current_text.push('\\'),
-                                        '"' => current_text.push('"'),
-                                        '\'' => current_text.push('\''),
-                                        '$' => current_text.push('$'),
-                                        _ => {
-                                            current_text.push('\\');
-                                            current_text.push(escaped);
-                                        }
-                                    }
-                                }
-                            } else if end_char == '$' {
-                                // Another variable immediately after - reprocess from this position
-                                // We need to continue parsing from the $ character
-                                let remaining = &input[continue_from..];
-                                match interpolated_string(quote)(remaining) {
-                                    Ok((rest, mut more_parts)) => {
-                                        // Add any text we accumulated
-                                        if !current_text.is_empty() {
-                                            parts.push(InterpolatedPart::Text(current_text));
-                                        }
-                                        // Add the additional parts
-                                        parts.append(&mut more_parts);
-                                        return Ok((rest, parts));
-                                    }
-                                    Err(e) => return Err(e),
-                                }
-                            } else {
-                                // Otherwise, continue processing from this character
-                                current_text.push(end_char);
-                            }
-                        }
-                    } else {
-                        // Not a variable, just a dollar sign
-                        current_text.push('$');
-                        current_text.push(next_ch);
+            }
+            quote @ (b'\'' | b'"') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if bytes[i] == b'\\' {
+                        i += 1;
                     }
-                } else {
-                    // Dollar sign at end of string
-                    current_text.push('$');
+                    i += 1;
                 }
-            } else {
-                current_text.push(ch);
             }
+            _ => {}
         }
-        
-        Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Eof)))
+        i += 1;
     }
+    
+    Err("Unterminated interpolation in string, missing '}'".to_string())
 }
 
 fn single_quoted_string() -> impl Fn(&str) -> IResult<&str, String> {
@@ -494,46 +841,23 @@ fn single_quoted_string() -> impl Fn(&str) -> IResult<&str, String> {
     }
 }
 
-fn escaped_string(quote: char) -> impl Fn(&str) -> IResult<&str, String> {
-    move |input: &str| {
-        let mut result = String::new();
-        let mut chars = input.char_indices();
-        
-        while let Some((idx, ch)) = chars.next() {
-            if ch == quote {
-                // Found closing quote, return the rest of the input after this position
-                return Ok((&input[idx..], result));
-            } else if ch == '\\' {
-                if let Some((_, escaped)) = chars.next() {
-                    match escaped {
-                        'n' => result.push('\n'),
-                        't' => result.push('\t'),
-                        'r' => result.push('\r'),
-                        '\\' => result.push('\\'),
-                        '"' => result.push('"'),
-                        '\'' => result.push('\''),
-                        _ => {
-                            result.push('\\');
-                            result.push(escaped);
-                        }
-                    }
-                } else {
-                    result.push('\\');
-                }
-            } else {
-                result.push(ch);
-            }
-        }
-        
-        Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Eof)))
-    }
-}
-
 fn operators(input: &str) -> IResult<&str, Token> {
     // Try multi-character operators first
+    if let Ok((rest, _)) = tag::<_, _, nom::error::Error<_>>("?->")(input) {
+        return Ok((rest, Token::NullsafeArrow));
+    }
+    if let Ok((rest, _)) = tag::<_, _, nom::error::Error<_>>("??=")(input) {
+        return Ok((rest, Token::CoalesceEqual));
+    }
+    if let Ok((rest, _)) = tag::<_, _, nom::error::Error<_>>("??")(input) {
+        return Ok((rest, Token::Coalesce));
+    }
     if let Ok((rest, _)) = tag::<_, _, nom::error::Error<_>>("->")(input) {
         return Ok((rest, Token::Arrow));
     }
+    if let Ok((rest, _)) = tag::<_, _, nom::error::Error<_>>("::")(input) {
+        return Ok((rest, Token::DoubleColon));
+    }
     if let Ok((rest, _)) = tag::<_, _, nom::error::Error<_>>("=>")(input) {
         return Ok((rest, Token::DoubleArrow));
     }
@@ -594,6 +918,8 @@ fn operators(input: &str) -> IResult<&str, Token> {
         value(Token::Dot, char('.')),
         value(Token::Question, char('?')),
         value(Token::Colon, char(':')),
+        value(Token::Pipe, char('|')),
+        value(Token::Ampersand, char('&')),
     ))(input)
 }
 
@@ -607,5 +933,6 @@ fn delimiters(input: &str) -> IResult<&str, Token> {
         value(Token::RightBracket, char(']')),
         value(Token::Semicolon, char(';')),
         value(Token::Comma, char(',')),
+        value(Token::Backslash, char('\\')),
     ))(input)
 }
\ No newline at end of file
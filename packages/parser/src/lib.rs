@@ -5,7 +5,9 @@ pub mod ast;
 pub mod lexer;
 pub mod parser;
 pub mod error;
+pub mod visit;
+pub mod visit_mut;
 
 pub use ast::*;
-pub use parser::parse;
+pub use parser::{parse, parse_with_locations};
 pub use error::ParseError;
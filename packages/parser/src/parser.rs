@@ -1,16 +1,66 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{ast::*, error::ParseError, lexer::{Token, lex}};
+use crate::{ast::*, error::ParseError, lexer::{Token, lex, lex_code, lex_with_offsets}};
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    // Token start offsets and the offsets where lines start, when statements
+    // should be preceded by `Statement::Location`
+    locations: Option<(Vec<usize>, Vec<usize>)>,
+}
+
+/// Modifiers in front of a class member or a constructor parameter
+#[derive(Default)]
+struct MemberModifiers {
+    visibility: Option<Visibility>,
+    is_static: bool,
+    is_readonly: bool,
+    is_final: bool,
+}
+
+fn modifier_name(token: &Token) -> &'static str {
+    match token {
+        Token::Static => "static",
+        Token::Readonly => "readonly",
+        _ => "final",
+    }
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+        Parser { tokens, current: 0, locations: None }
+    }
+
+    /// A parser that records where each statement starts in `source`, given
+    /// the token offsets from [`crate::lexer::lex_with_offsets`]
+    pub fn with_locations(tokens: Vec<Token>, offsets: Vec<usize>, source: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(newline, _)| newline + 1))
+            .collect();
+        Parser { tokens, current: 0, locations: Some((offsets, line_starts)) }
+    }
+
+    /// The location of the current token
+    fn location(&self) -> Option<SourceLocation> {
+        let (offsets, line_starts) = self.locations.as_ref()?;
+        let offset = *offsets.get(self.current)?;
+        let line = line_starts.partition_point(|&start| start <= offset);
+        Some(SourceLocation {
+            file: 0,
+            line: line as u32,
+            column: (offset - line_starts[line - 1]) as u32 + 1,
+        })
+    }
+
+    /// Parse a statement onto `statements`, after its location if recorded
+    fn parse_statement_into(&mut self, statements: &mut Vec<Statement>) -> Result<(), ParseError> {
+        if let Some(location) = self.location() {
+            statements.push(Statement::Location(location));
+        }
+        statements.push(self.parse_statement()?);
+        Ok(())
     }
 
     fn peek(&self) -> &Token {
@@ -65,13 +115,16 @@ impl Parser {
                     
                     if is_short_echo {
                         // Short echo tag - parse a single expression and convert to echo
+                        if let Some(location) = self.location() {
+                            statements.push(Statement::Location(location));
+                        }
                         let expr = self.parse_expression()?;
                         statements.push(Statement::Echo(vec![expr]));
                         // Don't expect semicolon for short echo tag
                     } else {
                         // Regular PHP block - parse statements until closing tag
                         while !self.is_at_end() && !matches!(self.peek(), Token::PhpClose) {
-                            statements.push(self.parse_statement()?);
+                            self.parse_statement_into(&mut statements)?;
                         }
                     }
                     
@@ -102,7 +155,9 @@ impl Parser {
             Token::Foreach => self.parse_foreach(),
             Token::Switch => self.parse_switch(),
             Token::Function => self.parse_function(),
-            Token::Class => self.parse_class(),
+            Token::Class | Token::Final | Token::Readonly => self.parse_class(),
+            Token::Enum => self.parse_enum(),
+            Token::Interface => self.parse_interface(),
             Token::LeftBrace => self.parse_block_statement(),
             Token::Break => {
                 self.advance();
@@ -126,6 +181,10 @@ impl Parser {
             }
             Token::Use => self.parse_use(),
             Token::Namespace => self.parse_namespace(),
+            Token::Global => self.parse_global(),
+            // `static::$x = ...` is an expression, `static $x;` declares a static variable
+            Token::Static if !matches!(self.tokens.get(self.current + 1), Some(Token::DoubleColon)) => self.parse_static(),
+            Token::Declare => self.parse_declare(),
             _ => self.parse_expression_statement(),
         }
     }
@@ -149,6 +208,86 @@ impl Parser {
         Ok(Statement::Echo(expressions))
     }
 
+    fn parse_global(&mut self) -> Result<Statement, ParseError> {
+        self.advance(); // consume 'global'
+        
+        let mut names = Vec::new();
+        loop {
+            match self.advance() {
+                Token::Variable(name) => names.push(name.clone()),
+                _ => return Err(ParseError::InvalidSyntax {
+                    message: "Expected variable after 'global'".to_string(),
+                }),
+            }
+            if !self.match_token(&Token::Comma) {
+                break;
+            }
+        }
+        
+        // Semicolon is optional if followed by PhpClose
+        if !matches!(self.peek(), Token::PhpClose) {
+            self.consume(Token::Semicolon, "Expected ';' after global statement")?;
+        } else {
+            self.match_token(&Token::Semicolon);
+        }
+        Ok(Statement::Global(names))
+    }
+
+    fn parse_declare(&mut self) -> Result<Statement, ParseError> {
+        self.advance(); // consume 'declare'
+        self.consume(Token::LeftParen, "Expected '(' after 'declare'")?;
+
+        let mut directives = Vec::new();
+        loop {
+            let name = match self.advance() {
+                Token::Identifier(name) => name.clone(),
+                _ => return Err(ParseError::InvalidSyntax {
+                    message: "Expected directive name in declare".to_string(),
+                }),
+            };
+            self.consume(Token::Equal, "Expected '=' after declare directive")?;
+            directives.push((name, self.parse_expression()?));
+            if !self.match_token(&Token::Comma) {
+                break;
+            }
+        }
+
+        self.consume(Token::RightParen, "Expected ')' after declare directives")?;
+        self.consume(Token::Semicolon, "Expected ';' after declare statement")?;
+        Ok(Statement::Declare(directives))
+    }
+
+    fn parse_static(&mut self) -> Result<Statement, ParseError> {
+        self.advance(); // consume 'static'
+        
+        let mut variables = Vec::new();
+        loop {
+            let name = match self.advance() {
+                Token::Variable(name) => name.clone(),
+                _ => return Err(ParseError::InvalidSyntax {
+                    message: "Expected variable after 'static'".to_string(),
+                }),
+            };
+            let default = if self.match_token(&Token::Equal) {
+                Some(self.parse_expression()?)
+            } else {
+                None
+            };
+            variables.push(StaticVariable { name, default });
+            if !self.match_token(&Token::Comma) {
+                break;
+            }
+        }
+        
+        // Semicolon is optional if followed by PhpClose
+        if !matches!(self.peek(), Token::PhpClose) {
+            self.consume(Token::Semicolon, "Expected ';' after static statement")?;
+        } else {
+            self.match_token(&Token::Semicolon);
+        }
+        Ok(Statement::Static(variables))
+    }
+
     fn parse_return(&mut self) -> Result<Statement, ParseError> {
         self.advance(); // consume 'return'
         
@@ -288,7 +427,7 @@ impl Parser {
         self.consume(Token::As, "Expected 'as' in foreach")?;
         
         // Check if we have key => value or just value
-        let (key, value) = if matches!(self.tokens.get(self.current + 1), Some(Token::DoubleArrow)) {
+        let key = if matches!(self.tokens.get(self.current + 1), Some(Token::DoubleArrow)) {
             // We have $key => $value
             let key_var = match self.advance() {
                 Token::Variable(k) => k.clone(),
@@ -297,22 +436,25 @@ impl Parser {
                 }),
             };
             self.consume(Token::DoubleArrow, "Expected '=>' after key variable")?;
-            let value_var = match self.advance() {
-                Token::Variable(v) => v.clone(),
-                _ => return Err(ParseError::InvalidSyntax {
-                    message: "Expected variable for foreach value".to_string(),
-                }),
-            };
-            (Some(key_var), value_var)
+            Some(key_var)
         } else {
-            // Just $value
-            let value_var = match self.advance() {
-                Token::Variable(v) => v.clone(),
-                _ => return Err(ParseError::InvalidSyntax {
-                    message: "Expected variable for foreach value".to_string(),
-                }),
-            };
-            (None, value_var)
+            None
+        };
+        
+        // The value is either a plain variable or a destructuring pattern: [$a, $b] / list($a, $b)
+        let value = match self.peek() {
+            Token::Variable(v) => {
+                let value_var = v.clone();
+                self.advance();
+                Expression::Variable(value_var)
+            }
+            Token::LeftBracket | Token::Identifier(_) => {
+                let pattern = self.parse_primary()?;
+                self.list_target(pattern)?
+            }
+            _ => return Err(ParseError::InvalidSyntax {
+                message: "Expected variable for foreach value".to_string(),
+            }),
         };
         
         self.consume(Token::RightParen, "Expected ')' after foreach")?;
@@ -348,7 +490,7 @@ impl Parser {
                     // Parse statements until we hit another case, default, or closing brace
                     let mut statements = Vec::new();
                     while !matches!(self.peek(), Token::Case | Token::Default | Token::RightBrace | Token::Eof) {
-                        statements.push(self.parse_statement()?);
+                        self.parse_statement_into(&mut statements)?;
                     }
 
                     cases.push(SwitchCase {
@@ -363,7 +505,7 @@ impl Parser {
                     // Parse statements until we hit another case, default, or closing brace
                     let mut statements = Vec::new();
                     while !matches!(self.peek(), Token::Case | Token::Default | Token::RightBrace | Token::Eof) {
-                        statements.push(self.parse_statement()?);
+                        self.parse_statement_into(&mut statements)?;
                     }
 
                     cases.push(SwitchCase {
@@ -396,29 +538,52 @@ impl Parser {
         
         self.consume(Token::LeftParen, "Expected '(' after function name")?;
         
-        let mut params = Vec::new();
-        if !matches!(self.peek(), Token::RightParen) {
-            loop {
-                params.push(self.parse_parameter()?);
-                if !self.match_token(&Token::Comma) {
-                    break;
-                }
-            }
-        }
+        let params = self.parse_parameter_list()?;
         
         self.consume(Token::RightParen, "Expected ')' after parameters")?;
         
+        let return_type = self.parse_return_type()?;
         let body = self.parse_block()?;
         
         Ok(Statement::Function {
             name,
             params,
             body,
-            return_type: None, // TODO: Add type parsing
+            return_type,
         })
     }
 
+    /// Parameters up to the closing ')', which may follow a trailing comma
+    fn parse_parameter_list(&mut self) -> Result<Vec<Parameter>, ParseError> {
+        let mut params = Vec::new();
+        while !matches!(self.peek(), Token::RightParen) {
+            params.push(self.parse_parameter()?);
+            if !self.match_token(&Token::Comma) {
+                break;
+            }
+        }
+        Ok(params)
+    }
+
     fn parse_parameter(&mut self) -> Result<Parameter, ParseError> {
+        // Visibility or `readonly` promote a constructor parameter to a property
+        let modifiers = self.parse_member_modifiers()?;
+        if let Some(modifier) = modifiers.is_static.then_some("static").or(modifiers.is_final.then_some("final")) {
+            return Err(ParseError::InvalidSyntax {
+                message: format!("Cannot use '{}' as parameter modifier", modifier),
+            });
+        }
+        let promoted = (modifiers.visibility.is_some() || modifiers.is_readonly).then(|| PromotedProperty {
+            visibility: modifiers.visibility.unwrap_or(Visibility::Public),
+            is_readonly: modifiers.is_readonly,
+        });
+
+        let param_type = if matches!(self.peek(), Token::Variable(_)) {
+            None
+        } else {
+            Some(self.parse_type()?)
+        };
+
         let name = match self.advance() {
             Token::Variable(n) => n.clone(),
             _ => return Err(ParseError::InvalidSyntax {
@@ -434,13 +599,107 @@ impl Parser {
         
         Ok(Parameter {
             name,
-            param_type: None, // TODO: Add type parsing
+            param_type,
             default,
+            promoted,
         })
     }
 
+    /// Parses a type declaration: a single type, `?T`, a union `T|U` or an intersection `A&B`
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        if self.match_token(&Token::Question) {
+            return Ok(Type::Nullable(Box::new(self.parse_single_type()?)));
+        }
+
+        let first = self.parse_single_type()?;
+        if matches!(self.peek(), Token::Pipe) {
+            let mut types = vec![first];
+            while self.match_token(&Token::Pipe) {
+                types.push(self.parse_single_type()?);
+            }
+            return Ok(Type::Union(types));
+        }
+        // `A&B $x` is an intersection, `A &$x` would be a by-reference parameter
+        if matches!(self.peek(), Token::Ampersand)
+            && matches!(self.tokens.get(self.current + 1), Some(Token::Identifier(_))) {
+            let mut types = vec![first];
+            while self.match_token(&Token::Ampersand) {
+                types.push(self.parse_single_type()?);
+            }
+            return Ok(Type::Intersection(types));
+        }
+        Ok(first)
+    }
+
+    fn parse_single_type(&mut self) -> Result<Type, ParseError> {
+        match self.advance().clone() {
+            Token::Identifier(name) => Ok(match name.to_ascii_lowercase().as_str() {
+                "int" => Type::Int,
+                "float" => Type::Float,
+                "string" => Type::String,
+                "bool" => Type::Bool,
+                "array" => Type::Array,
+                "mixed" => Type::Mixed,
+                "void" => Type::Void,
+                "never" => Type::Never,
+                "iterable" => Type::Iterable,
+                "callable" => Type::Callable,
+                _ => Type::Object(name),
+            }),
+            Token::Static => Ok(Type::Object("static".to_string())),
+            Token::Null => Ok(Type::Null),
+            Token::False => Ok(Type::False),
+            Token::True => Ok(Type::True),
+            token => Err(ParseError::InvalidSyntax {
+                message: format!("Expected type, found {:?}", token),
+            }),
+        }
+    }
+
+    /// Optional `: type` after a parameter list
+    fn parse_return_type(&mut self) -> Result<Option<Type>, ParseError> {
+        if self.match_token(&Token::Colon) {
+            Ok(Some(self.parse_type()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Optional `implements A, B` list of a class or enum declaration
+    fn parse_implements(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut interfaces = Vec::new();
+        if self.match_token(&Token::Implements) {
+            loop {
+                match self.advance() {
+                    Token::Identifier(n) => interfaces.push(n.clone()),
+                    _ => return Err(ParseError::InvalidSyntax {
+                        message: "Expected interface name".to_string(),
+                    }),
+                }
+                if !self.match_token(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        Ok(interfaces)
+    }
+
     fn parse_class(&mut self) -> Result<Statement, ParseError> {
-        self.advance(); // consume 'class'
+        // `final` and `readonly` may precede 'class' in any order
+        let mut is_final = false;
+        let mut is_readonly = false;
+        loop {
+            match self.peek() {
+                Token::Final if !is_final => is_final = true,
+                Token::Readonly if !is_readonly => is_readonly = true,
+                token @ (Token::Final | Token::Readonly) => return Err(ParseError::InvalidSyntax {
+                    message: format!("Multiple {} modifiers are not allowed", modifier_name(token)),
+                }),
+                _ => break,
+            }
+            self.advance();
+        }
+        self.consume(Token::Class, "Expected 'class' after class modifiers")?;
         
         let name = match self.advance() {
             Token::Identifier(n) => n.clone(),
@@ -449,7 +708,8 @@ impl Parser {
             }),
         };
         
-        // TODO: Handle extends and implements
+        // TODO: Handle extends
+        let implements = self.parse_implements()?;
         
         self.consume(Token::LeftBrace, "Expected '{' after class name")?;
         
@@ -462,31 +722,161 @@ impl Parser {
         
         Ok(Statement::Class {
             name,
+            is_final,
+            is_readonly,
             extends: None,
-            implements: Vec::new(),
+            implements,
             members,
         })
     }
 
-    fn parse_class_member(&mut self) -> Result<ClassMember, ParseError> {
-        let visibility = match self.peek() {
-            Token::Public => {
-                self.advance();
-                Visibility::Public
+    fn parse_enum(&mut self) -> Result<Statement, ParseError> {
+        self.advance(); // consume 'enum'
+
+        let name = match self.advance() {
+            Token::Identifier(n) => n.clone(),
+            _ => return Err(ParseError::InvalidSyntax {
+                message: "Expected enum name".to_string(),
+            }),
+        };
+
+        let backing_type = if self.match_token(&Token::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let implements = self.parse_implements()?;
+
+        self.consume(Token::LeftBrace, "Expected '{' after enum name")?;
+
+        let mut members = Vec::new();
+        while !matches!(self.peek(), Token::RightBrace) && !self.is_at_end() {
+            if self.match_token(&Token::Case) {
+                let case_name = match self.advance() {
+                    Token::Identifier(n) => n.clone(),
+                    _ => return Err(ParseError::InvalidSyntax {
+                        message: "Expected enum case name".to_string(),
+                    }),
+                };
+                let value = if self.match_token(&Token::Equal) {
+                    Some(self.parse_expression()?)
+                } else {
+                    None
+                };
+                self.consume(Token::Semicolon, "Expected ';' after enum case")?;
+                members.push(ClassMember::Case { name: case_name, value });
+            } else {
+                members.push(self.parse_class_member()?);
             }
-            Token::Private => {
-                self.advance();
-                Visibility::Private
+        }
+
+        self.consume(Token::RightBrace, "Expected '}' after enum body")?;
+
+        Ok(Statement::Enum {
+            name,
+            backing_type,
+            implements,
+            members,
+        })
+    }
+
+    fn parse_interface(&mut self) -> Result<Statement, ParseError> {
+        self.advance(); // consume 'interface'
+
+        let name = match self.advance() {
+            Token::Identifier(n) => n.clone(),
+            _ => return Err(ParseError::InvalidSyntax {
+                message: "Expected interface name".to_string(),
+            }),
+        };
+
+        self.consume(Token::LeftBrace, "Expected '{' after interface name")?;
+
+        let mut members = Vec::new();
+        while !matches!(self.peek(), Token::RightBrace) && !self.is_at_end() {
+            match self.parse_class_member()? {
+                member @ (ClassMember::AbstractMethod { .. } | ClassMember::Constant { .. }) => members.push(member),
+                _ => return Err(ParseError::InvalidSyntax {
+                    message: format!("Interface {} may only contain constants and method declarations", name),
+                }),
             }
-            Token::Protected => {
-                self.advance();
-                Visibility::Protected
+        }
+
+        self.consume(Token::RightBrace, "Expected '}' after interface body")?;
+
+        Ok(Statement::Interface { name, members })
+    }
+
+    /// Visibility, `static`, `readonly` and `final`, in any order
+    fn parse_member_modifiers(&mut self) -> Result<MemberModifiers, ParseError> {
+        let mut modifiers = MemberModifiers::default();
+        loop {
+            let token = self.peek().clone();
+            let visibility = match token {
+                Token::Public => Visibility::Public,
+                Token::Private => Visibility::Private,
+                Token::Protected => Visibility::Protected,
+                Token::Static | Token::Readonly | Token::Final => {
+                    let flag = match token {
+                        Token::Static => &mut modifiers.is_static,
+                        Token::Readonly => &mut modifiers.is_readonly,
+                        _ => &mut modifiers.is_final,
+                    };
+                    if *flag {
+                        return Err(ParseError::InvalidSyntax {
+                            message: format!("Multiple {} modifiers are not allowed", modifier_name(&token)),
+                        });
+                    }
+                    *flag = true;
+                    self.advance();
+                    continue;
+                }
+                _ => return Ok(modifiers),
+            };
+            if modifiers.visibility.is_some() {
+                return Err(ParseError::InvalidSyntax {
+                    message: "Multiple access type modifiers are not allowed".to_string(),
+                });
             }
-            _ => Visibility::Public,
-        };
-        
-        if self.match_token(&Token::Function) {
+            modifiers.visibility = Some(visibility);
+            self.advance();
+        }
+    }
+
+    fn parse_class_member(&mut self) -> Result<ClassMember, ParseError> {
+        let modifiers = self.parse_member_modifiers()?;
+        let visibility = modifiers.visibility.clone().unwrap_or(Visibility::Public);
+        let is_static = modifiers.is_static;
+
+        if self.match_token(&Token::Const) {
+            if let Some(modifier) = modifiers.is_static.then_some("static").or(modifiers.is_readonly.then_some("readonly")) {
+                return Err(ParseError::InvalidSyntax {
+                    message: format!("Cannot use '{}' as constant modifier", modifier),
+                });
+            }
+            let name = match self.advance() {
+                Token::Identifier(n) => n.clone(),
+                _ => return Err(ParseError::InvalidSyntax {
+                    message: "Expected constant name".to_string(),
+                }),
+            };
+            self.consume(Token::Equal, "Expected '=' after constant name")?;
+            let value = self.parse_expression()?;
+            self.consume(Token::Semicolon, "Expected ';' after constant")?;
+
+            Ok(ClassMember::Constant {
+                visibility,
+                is_final: modifiers.is_final,
+                name,
+                value,
+            })
+        } else if self.match_token(&Token::Function) {
             // Method
+            if modifiers.is_readonly {
+                return Err(ParseError::InvalidSyntax {
+                    message: "Cannot use 'readonly' as method modifier".to_string(),
+                });
+            }
             let name = match self.advance() {
                 Token::Identifier(n) => n.clone(),
                 _ => return Err(ParseError::InvalidSyntax {
@@ -496,35 +886,51 @@ impl Parser {
             
             self.consume(Token::LeftParen, "Expected '(' after method name")?;
             
-            let mut params = Vec::new();
-            if !matches!(self.peek(), Token::RightParen) {
-                loop {
-                    params.push(self.parse_parameter()?);
-                    if !self.match_token(&Token::Comma) {
-                        break;
-                    }
-                }
-            }
+            let params = self.parse_parameter_list()?;
             
             self.consume(Token::RightParen, "Expected ')' after parameters")?;
             
+            let return_type = self.parse_return_type()?;
+
+            // Interface methods are declarations without a body
+            if self.match_token(&Token::Semicolon) {
+                return Ok(ClassMember::AbstractMethod {
+                    visibility,
+                    name,
+                    params,
+                    return_type,
+                });
+            }
+
             let body = self.parse_block()?;
             
             Ok(ClassMember::Method {
                 visibility,
+                is_static,
+                is_final: modifiers.is_final,
                 name,
                 params,
                 body,
-                return_type: None,
+                return_type,
             })
         } else {
-            // Property
+            // Property, optionally typed
+            let property_type = if matches!(self.peek(), Token::Variable(_)) {
+                None
+            } else {
+                Some(self.parse_type()?)
+            };
             let name = match self.advance() {
                 Token::Variable(n) => n.clone(),
                 _ => return Err(ParseError::InvalidSyntax {
                     message: "Expected property name".to_string(),
                 }),
             };
+            if modifiers.is_final {
+                return Err(ParseError::InvalidSyntax {
+                    message: format!("Cannot declare property ${} final, the final modifier is allowed only for methods, classes, and class constants", name),
+                });
+            }
             
             let default = if self.match_token(&Token::Equal) {
                 Some(self.parse_expression()?)
@@ -536,32 +942,43 @@ impl Parser {
             
             Ok(ClassMember::Property {
                 visibility,
+                is_static,
+                is_readonly: modifiers.is_readonly,
                 name,
-                property_type: None,
+                property_type,
                 default,
             })
         }
     }
 
+    /// `use A\B [as C], ...;`, `use function A\f;`, `use const A\X;` and group
+    /// uses such as `use A\{B, C as D, function f};`
     fn parse_use(&mut self) -> Result<Statement, ParseError> {
         self.advance(); // consume 'use'
         
+        let kind = self.parse_use_kind().unwrap_or(UseKind::Class);
         let mut items = Vec::new();
         
         loop {
-            let path = match self.advance() {
-                Token::Identifier(p) => p.clone(),
-                _ => return Err(ParseError::InvalidSyntax {
-                    message: "Expected use path".to_string(),
-                }),
-            };
-            
-            // TODO: Handle full namespace paths with \
+            let path = self.parse_use_path()?;
             
-            items.push(UseItem {
-                path,
-                alias: None,
-            });
+            if self.match_token(&Token::Backslash) {
+                // Group use: every item is relative to the prefix
+                self.consume(Token::LeftBrace, "Expected '{' after '\\' in group use")?;
+                while !matches!(self.peek(), Token::RightBrace) {
+                    let item_kind = self.parse_use_kind().unwrap_or(kind);
+                    let name = self.parse_use_path()?;
+                    let alias = self.parse_use_alias()?;
+                    items.push(UseItem { kind: item_kind, path: format!("{}\\{}", path, name), alias });
+                    if !self.match_token(&Token::Comma) {
+                        break;
+                    }
+                }
+                self.consume(Token::RightBrace, "Expected '}' after group use")?;
+            } else {
+                let alias = self.parse_use_alias()?;
+                items.push(UseItem { kind, path, alias });
+            }
             
             if !self.match_token(&Token::Comma) {
                 break;
@@ -573,25 +990,68 @@ impl Parser {
         Ok(Statement::Use(UseStatement { items }))
     }
 
+    fn parse_use_kind(&mut self) -> Option<UseKind> {
+        if self.match_token(&Token::Function) {
+            Some(UseKind::Function)
+        } else if self.match_token(&Token::Const) {
+            Some(UseKind::Constant)
+        } else {
+            None
+        }
+    }
+
+    fn parse_use_path(&mut self) -> Result<String, ParseError> {
+        match self.advance() {
+            Token::Identifier(path) => Ok(path.trim_start_matches('\\').to_string()),
+            _ => Err(ParseError::InvalidSyntax {
+                message: "Expected use path".to_string(),
+            }),
+        }
+    }
+
+    fn parse_use_alias(&mut self) -> Result<Option<String>, ParseError> {
+        if !self.match_token(&Token::As) {
+            return Ok(None);
+        }
+        match self.advance() {
+            Token::Identifier(alias) if !alias.contains('\\') => Ok(Some(alias.clone())),
+            _ => Err(ParseError::InvalidSyntax {
+                message: "Expected alias after 'as'".to_string(),
+            }),
+        }
+    }
+
+    /// `namespace A\B;` applies to the statements that follow it, up to the next
+    /// namespace declaration; `namespace A\B { ... }` and the global
+    /// `namespace { ... }` enclose their statements.
     fn parse_namespace(&mut self) -> Result<Statement, ParseError> {
         self.advance(); // consume 'namespace'
         
-        let name = match self.advance() {
-            Token::Identifier(n) => n.clone(),
+        let name = match self.peek() {
+            Token::Identifier(n) if !n.starts_with('\\') => {
+                let name = n.clone();
+                self.advance();
+                name
+            }
+            Token::LeftBrace => String::new(),
             _ => return Err(ParseError::InvalidSyntax {
                 message: "Expected namespace name".to_string(),
             }),
         };
         
-        self.consume(Token::LeftBrace, "Expected '{' after namespace")?;
-        
         let mut statements = Vec::new();
-        while !matches!(self.peek(), Token::RightBrace) && !self.is_at_end() {
-            statements.push(self.parse_statement()?);
+        if !name.is_empty() && self.match_token(&Token::Semicolon) {
+            while !matches!(self.peek(), Token::Namespace | Token::PhpClose) && !self.is_at_end() {
+                self.parse_statement_into(&mut statements)?;
+            }
+        } else {
+            self.consume(Token::LeftBrace, "Expected '{' or ';' after namespace")?;
+            while !matches!(self.peek(), Token::RightBrace) && !self.is_at_end() {
+                self.parse_statement_into(&mut statements)?;
+            }
+            self.consume(Token::RightBrace, "Expected '}' after namespace body")?;
         }
         
-        self.consume(Token::RightBrace, "Expected '}' after namespace body")?;
-        
         Ok(Statement::Namespace(NamespaceStatement {
             name,
             body: Block { statements },
@@ -603,7 +1063,7 @@ impl Parser {
         
         let mut statements = Vec::new();
         while !matches!(self.peek(), Token::RightBrace) && !self.is_at_end() {
-            statements.push(self.parse_statement()?);
+            self.parse_statement_into(&mut statements)?;
         }
         
         self.consume(Token::RightBrace, "Expected '}'")?;
@@ -620,8 +1080,9 @@ impl Parser {
             self.parse_block()
         } else {
             // Single statement without braces
-            let stmt = self.parse_statement()?;
-            Ok(Block { statements: vec![stmt] })
+            let mut statements = Vec::new();
+            self.parse_statement_into(&mut statements)?;
+            Ok(Block { statements })
         }
     }
 
@@ -649,22 +1110,50 @@ impl Parser {
         if self.match_token(&Token::Equal) {
             // Make sure left side is assignable
             match &expr {
-                Expression::Variable(_) | Expression::ArrayAccess { .. } | Expression::PropertyAccess { .. } => {
+                Expression::Variable(_) | Expression::ArrayAccess { .. } | Expression::PropertyAccess { .. } | Expression::StaticPropertyAccess { .. } => {
                     let right = self.parse_expression()?;
                     Ok(Expression::Assignment {
                         left: Box::new(expr),
                         right: Box::new(right),
                     })
                 }
+                Expression::Array(_) | Expression::List(_) => {
+                    // Destructuring: [$a, $b] = ... / list('x' => $x) = ...
+                    let left = self.list_target(expr)?;
+                    let right = self.parse_expression()?;
+                    Ok(Expression::Assignment {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    })
+                }
                 _ => Err(ParseError::InvalidSyntax {
                     message: "Invalid left-hand side in assignment".to_string(),
                 }),
             }
+        } else if matches!(self.peek(), Token::CoalesceEqual) {
+            // $a ??= b becomes $a = $a ?? b
+            match &expr {
+                Expression::Variable(_) | Expression::ArrayAccess { .. } | Expression::PropertyAccess { .. } | Expression::StaticPropertyAccess { .. } => {
+                    self.advance();
+                    let right = self.parse_expression()?;
+                    let coalesce_expr = Expression::Coalesce {
+                        left: Box::new(expr.clone()),
+                        right: Box::new(right),
+                    };
+                    Ok(Expression::Assignment {
+                        left: Box::new(expr),
+                        right: Box::new(coalesce_expr),
+                    })
+                }
+                _ => Err(ParseError::InvalidSyntax {
+                    message: "Invalid left-hand side in compound assignment".to_string(),
+                }),
+            }
         } else if matches!(self.peek(), Token::PlusEqual | Token::MinusEqual | Token::StarEqual | Token::SlashEqual) {
             // Compound assignment: desugar to regular assignment
             // $a += 5 becomes $a = $a + 5
             match &expr {
-                Expression::Variable(_) | Expression::ArrayAccess { .. } | Expression::PropertyAccess { .. } => {
+                Expression::Variable(_) | Expression::ArrayAccess { .. } | Expression::PropertyAccess { .. } | Expression::StaticPropertyAccess { .. } => {
                     let op_token = self.peek().clone();
                     self.advance();
 
@@ -700,8 +1189,39 @@ impl Parser {
         }
     }
 
+    /// Converts an array literal (or list()) on the left of `=` into a destructuring pattern
+    fn list_target(&self, expr: Expression) -> Result<Expression, ParseError> {
+        let items: Vec<Option<ArrayElement>> = match expr {
+            Expression::Array(elements) => elements.into_iter().map(Some).collect(),
+            Expression::List(items) => items,
+            _ => return Err(ParseError::InvalidSyntax {
+                message: "Expected destructuring pattern".to_string(),
+            }),
+        };
+
+        let mut targets = Vec::with_capacity(items.len());
+        for item in items {
+            let target = match item {
+                Some(element) => {
+                    let value = match element.value {
+                        nested @ (Expression::Array(_) | Expression::List(_)) => self.list_target(nested)?,
+                        target @ (Expression::Variable(_) | Expression::ArrayAccess { .. } | Expression::PropertyAccess { .. } | Expression::StaticPropertyAccess { .. }) => target,
+                        _ => return Err(ParseError::InvalidSyntax {
+                            message: "Cannot assign to this expression in destructuring".to_string(),
+                        }),
+                    };
+                    Some(ArrayElement { key: element.key, value })
+                }
+                None => None,
+            };
+            targets.push(target);
+        }
+
+        Ok(Expression::List(targets))
+    }
+
     fn parse_ternary(&mut self) -> Result<Expression, ParseError> {
-        let mut expr = self.parse_or()?;
+        let mut expr = self.parse_coalesce()?;
         
         if self.match_token(&Token::Question) {
             let then_expr = self.parse_expression()?;
@@ -718,6 +1238,21 @@ impl Parser {
         Ok(expr)
     }
 
+    fn parse_coalesce(&mut self) -> Result<Expression, ParseError> {
+        let expr = self.parse_or()?;
+        
+        // ?? is right-associative: $a ?? $b ?? $c == $a ?? ($b ?? $c)
+        if self.match_token(&Token::Coalesce) {
+            let right = self.parse_coalesce()?;
+            return Ok(Expression::Coalesce {
+                left: Box::new(expr),
+                right: Box::new(right),
+            });
+        }
+        
+        Ok(expr)
+    }
+
     fn parse_or(&mut self) -> Result<Expression, ParseError> {
         let mut expr = self.parse_and()?;
         
@@ -920,6 +1455,10 @@ impl Parser {
                     expr: Box::new(self.parse_unary()?),
                 })
             }
+            Token::Clone => {
+                self.advance();
+                Ok(Expression::Clone(Box::new(self.parse_unary()?)))
+            }
             _ => self.parse_postfix(),
         }
     }
@@ -966,6 +1505,42 @@ impl Parser {
                         }),
                     }
                 }
+                Token::NullsafeArrow => {
+                    self.advance();
+                    let token = self.advance().clone();
+                    match token {
+                        Token::Identifier(method) => {
+                            if matches!(self.peek(), Token::LeftParen) {
+                                self.advance();
+                                let args = self.parse_arguments()?;
+                                self.consume(Token::RightParen, "Expected ')'")?;
+                                expr = Expression::NullsafeMethodCall {
+                                    object: Box::new(expr),
+                                    method,
+                                    args,
+                                };
+                            } else {
+                                expr = Expression::NullsafePropertyAccess {
+                                    object: Box::new(expr),
+                                    property: method,
+                                };
+                            }
+                        }
+                        _ => return Err(ParseError::InvalidSyntax {
+                            message: "Expected property or method name".to_string(),
+                        }),
+                    }
+                }
+                Token::LeftParen => {
+                    // $obj(...) / $callbacks[0](...)
+                    self.advance();
+                    let args = self.parse_arguments()?;
+                    self.consume(Token::RightParen, "Expected ')'")?;
+                    expr = Expression::Invoke {
+                        callee: Box::new(expr),
+                        args,
+                    };
+                }
                 Token::PlusPlus => {
                     self.advance();
                     expr = Expression::Unary {
@@ -1034,15 +1609,25 @@ impl Parser {
             }
             Token::LeftBracket => {
                 self.advance();
-                let elements = self.parse_array_elements()?;
+                let elements = self.parse_array_elements(Token::RightBracket)?;
                 self.consume(Token::RightBracket, "Expected ']'")?;
-                Ok(Expression::Array(elements))
+                
+                // Skipped slots ([, $b]) are only valid in a destructuring pattern
+                if elements.iter().all(|e| e.is_some()) {
+                    Ok(Expression::Array(elements.into_iter().flatten().collect()))
+                } else {
+                    Ok(Expression::List(elements))
+                }
             }
             Token::New => {
                 self.advance();
-                match self.advance() {
-                    Token::Identifier(class) => {
-                        let class_name = class.clone();
+                let class_name = match self.advance() {
+                    Token::Identifier(class) => Some(class.clone()),
+                    Token::Static => Some("static".to_string()),
+                    _ => None,
+                };
+                match class_name {
+                    Some(class_name) => {
                         self.consume(Token::LeftParen, "Expected '(' after class name")?;
                         let args = self.parse_arguments()?;
                         self.consume(Token::RightParen, "Expected ')'")?;
@@ -1051,16 +1636,44 @@ impl Parser {
                             args,
                         })
                     }
-                    _ => Err(ParseError::InvalidSyntax {
+                    None => Err(ParseError::InvalidSyntax {
                         message: "Expected class name after 'new'".to_string(),
                     }),
                 }
             }
+            Token::Static if matches!(self.tokens.get(self.current + 1), Some(Token::DoubleColon)) => {
+                self.advance();
+                self.advance();
+                self.parse_static_access("static".to_string())
+            }
+            Token::Include | Token::IncludeOnce | Token::Require | Token::RequireOnce => {
+                let kind = match self.advance() {
+                    Token::Include => IncludeKind::Include,
+                    Token::IncludeOnce => IncludeKind::IncludeOnce,
+                    Token::Require => IncludeKind::Require,
+                    _ => IncludeKind::RequireOnce,
+                };
+                // The path extends over the rest of the expression: `require __DIR__ . '/a.php'`
+                let path = self.parse_expression()?;
+                Ok(Expression::Include { kind, path: Box::new(path) })
+            }
+            Token::Identifier(name) if magic_constant(name).is_some() && !matches!(self.tokens.get(self.current + 1), Some(Token::LeftParen | Token::DoubleColon)) => {
+                let constant = magic_constant(name).unwrap();
+                self.advance();
+                Ok(Expression::MagicConstant(constant))
+            }
             Token::Identifier(name) => {
                 let func_name = name.clone();
                 self.advance();
                 
-                if matches!(self.peek(), Token::LeftParen) {
+                if self.match_token(&Token::DoubleColon) {
+                    self.parse_static_access(func_name)
+                } else if func_name.eq_ignore_ascii_case("list") && matches!(self.peek(), Token::LeftParen) {
+                    self.advance();
+                    let elements = self.parse_array_elements(Token::RightParen)?;
+                    self.consume(Token::RightParen, "Expected ')' after list")?;
+                    Ok(Expression::List(elements))
+                } else if matches!(self.peek(), Token::LeftParen) {
                     self.advance();
                     let args = self.parse_arguments()?;
                     self.consume(Token::RightParen, "Expected ')'")?;
@@ -1080,26 +1693,56 @@ impl Parser {
         }
     }
 
+    /// `Class::CONSTANT`, `Enum::Case`, `Class::method(...)`, `Class::$property` and `Class::class`
+    fn parse_static_access(&mut self, class: String) -> Result<Expression, ParseError> {
+        match self.advance().clone() {
+            Token::Variable(property) => Ok(Expression::StaticPropertyAccess { class, property }),
+            Token::Identifier(member) => {
+                if self.match_token(&Token::LeftParen) {
+                    let args = self.parse_arguments()?;
+                    self.consume(Token::RightParen, "Expected ')'")?;
+                    Ok(Expression::StaticMethodCall { class, method: member, args })
+                } else {
+                    Ok(Expression::ClassConstant { class, name: member })
+                }
+            }
+            Token::Class => Ok(Expression::ClassConstant { class, name: "class".to_string() }),
+            token => Err(ParseError::InvalidSyntax {
+                message: format!("Expected constant or method name after '::', found {:?}", token),
+            }),
+        }
+    }
+
+    /// Arguments up to the closing ')', which may follow a trailing comma
     fn parse_arguments(&mut self) -> Result<Vec<Expression>, ParseError> {
         let mut args = Vec::new();
-        
-        if !matches!(self.peek(), Token::RightParen) {
-            loop {
-                args.push(self.parse_expression()?);
-                if !self.match_token(&Token::Comma) {
-                    break;
-                }
+        while !matches!(self.peek(), Token::RightParen) {
+            args.push(self.parse_expression()?);
+            if !self.match_token(&Token::Comma) {
+                break;
             }
         }
-        
         Ok(args)
     }
 
-    fn parse_array_elements(&mut self) -> Result<Vec<ArrayElement>, ParseError> {
+    /// Parses array literal elements up to `closing`. Empty slots (as in `[, $b]`)
+    /// are returned as None; a trailing comma does not create a slot.
+    fn parse_array_elements(&mut self, closing: Token) -> Result<Vec<Option<ArrayElement>>, ParseError> {
         let mut elements = Vec::new();
+        let is_closing = |token: &Token| std::mem::discriminant(token) == std::mem::discriminant(&closing);
         
-        if !matches!(self.peek(), Token::RightBracket) {
+        if !is_closing(self.peek()) {
             loop {
+                if matches!(self.peek(), Token::Comma) {
+                    // Skipped slot
+                    self.advance();
+                    elements.push(None);
+                    if is_closing(self.peek()) {
+                        break;
+                    }
+                    continue;
+                }
+                
                 let first_expr = self.parse_expression()?;
                 
                 let element = if self.match_token(&Token::DoubleArrow) {
@@ -1116,9 +1759,9 @@ impl Parser {
                     }
                 };
                 
-                elements.push(element);
+                elements.push(Some(element));
                 
-                if !self.match_token(&Token::Comma) {
+                if !self.match_token(&Token::Comma) || is_closing(self.peek()) {
                     break;
                 }
             }
@@ -1128,8 +1771,41 @@ impl Parser {
     }
 }
 
+fn magic_constant(name: &str) -> Option<MagicConstant> {
+    match name.to_ascii_uppercase().as_str() {
+        "__DIR__" => Some(MagicConstant::Dir),
+        "__FILE__" => Some(MagicConstant::File),
+        "__NAMESPACE__" => Some(MagicConstant::Namespace),
+        "__CLASS__" => Some(MagicConstant::Class),
+        "__FUNCTION__" => Some(MagicConstant::Function),
+        "__METHOD__" => Some(MagicConstant::Method),
+        _ => None,
+    }
+}
+
 pub fn parse(source: &str) -> Result<Program, ParseError> {
     let tokens = lex(source).map_err(|e| ParseError::LexerError { message: e })?;
     let mut parser = Parser::new(tokens);
     parser.parse_program()
 }
+
+/// Parse a program with a `Statement::Location` in front of every statement,
+/// for compilers that map generated code back to the source
+pub fn parse_with_locations(source: &str) -> Result<Program, ParseError> {
+    let (tokens, offsets) = lex_with_offsets(source).map_err(|e| ParseError::LexerError { message: e })?;
+    let mut parser = Parser::with_locations(tokens, offsets, source);
+    parser.parse_program()
+}
+
+/// Parse a single expression embedded in a string, e.g. the `$a['x']` in `"{$a['x']}"`
+pub fn parse_embedded_expression(source: &str) -> Result<Expression, ParseError> {
+    let tokens = lex_code(source).map_err(|e| ParseError::LexerError { message: e })?;
+    let mut parser = Parser::new(tokens);
+    let expr = parser.parse_expression()?;
+    if !parser.is_at_end() {
+        return Err(ParseError::InvalidSyntax {
+            message: format!("Unexpected token in string interpolation: {{{}}}", source),
+        });
+    }
+    Ok(expr)
+}
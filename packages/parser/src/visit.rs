@@ -0,0 +1,223 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+//! Read-only AST traversal.
+//!
+//! Implement [`Visitor`] and override the hooks you care about; the default
+//! implementations walk into every nested statement and expression, including
+//! function, method and constructor bodies. Call the matching `walk_*` function
+//! from an override to keep descending.
+
+use crate::ast::*;
+
+pub trait Visitor {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr);
+    }
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for item in &program.items {
+        if let ProgramItem::PhpBlock { statements } = item {
+            for stmt in statements {
+                visitor.visit_statement(stmt);
+            }
+        }
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &Block) {
+    for stmt in &block.statements {
+        visitor.visit_statement(stmt);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Statement) {
+    match stmt {
+        Statement::Expression(expr) => visitor.visit_expression(expr),
+        Statement::Return(expr) => {
+            if let Some(expr) = expr {
+                visitor.visit_expression(expr);
+            }
+        }
+        Statement::If { condition, then_block, elseif_blocks, else_block } => {
+            visitor.visit_expression(condition);
+            walk_block(visitor, then_block);
+            for elseif in elseif_blocks {
+                visitor.visit_expression(&elseif.condition);
+                walk_block(visitor, &elseif.then_block);
+            }
+            if let Some(else_block) = else_block {
+                walk_block(visitor, else_block);
+            }
+        }
+        Statement::While { condition, body } | Statement::DoWhile { body, condition } => {
+            visitor.visit_expression(condition);
+            walk_block(visitor, body);
+        }
+        Statement::For { init, condition, update, body } => {
+            if let Some(init) = init {
+                visitor.visit_statement(init);
+            }
+            if let Some(condition) = condition {
+                visitor.visit_expression(condition);
+            }
+            if let Some(update) = update {
+                visitor.visit_expression(update);
+            }
+            walk_block(visitor, body);
+        }
+        Statement::Function { params, body, .. } => {
+            walk_parameters(visitor, params);
+            walk_block(visitor, body);
+        }
+        Statement::Class { members, .. }
+        | Statement::Enum { members, .. }
+        | Statement::Interface { members, .. } => {
+            for member in members {
+                walk_class_member(visitor, member);
+            }
+        }
+        Statement::Echo(exprs) => {
+            for expr in exprs {
+                visitor.visit_expression(expr);
+            }
+        }
+        Statement::Block(block) => walk_block(visitor, block),
+        Statement::Namespace(namespace) => walk_block(visitor, &namespace.body),
+        Statement::Foreach { array, value, body, .. } => {
+            visitor.visit_expression(array);
+            visitor.visit_expression(value);
+            walk_block(visitor, body);
+        }
+        Statement::Switch { expr, cases } => {
+            visitor.visit_expression(expr);
+            for case in cases {
+                if let Some(value) = &case.value {
+                    visitor.visit_expression(value);
+                }
+                for stmt in &case.statements {
+                    visitor.visit_statement(stmt);
+                }
+            }
+        }
+        Statement::Static(variables) => {
+            for variable in variables {
+                if let Some(default) = &variable.default {
+                    visitor.visit_expression(default);
+                }
+            }
+        }
+        Statement::Break
+        | Statement::Continue
+        | Statement::Use(_)
+        | Statement::Global(_)
+        | Statement::Declare(_)
+        | Statement::Location(_) => {}
+    }
+}
+
+fn walk_class_member<V: Visitor + ?Sized>(visitor: &mut V, member: &ClassMember) {
+    match member {
+        ClassMember::Property { default, .. } => {
+            if let Some(default) = default {
+                visitor.visit_expression(default);
+            }
+        }
+        ClassMember::Method { params, body, .. }
+        | ClassMember::Constructor { params, body, .. } => {
+            walk_parameters(visitor, params);
+            walk_block(visitor, body);
+        }
+        ClassMember::AbstractMethod { params, .. } => walk_parameters(visitor, params),
+        ClassMember::Constant { value, .. } => visitor.visit_expression(value),
+        ClassMember::Case { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expression(value);
+            }
+        }
+    }
+}
+
+fn walk_parameters<V: Visitor + ?Sized>(visitor: &mut V, params: &[Parameter]) {
+    for param in params {
+        if let Some(default) = &param.default {
+            visitor.visit_expression(default);
+        }
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expression) {
+    match expr {
+        Expression::Variable(_)
+        | Expression::ClassConstant { .. }
+        | Expression::StaticPropertyAccess { .. }
+        | Expression::MagicConstant(_) => {}
+        Expression::Literal(Literal::InterpolatedString(parts)) => {
+            for part in parts {
+                if let InterpolatedPart::Expression(expr) = part {
+                    visitor.visit_expression(expr);
+                }
+            }
+        }
+        Expression::Literal(_) => {}
+        Expression::Binary { left, right, .. }
+        | Expression::Assignment { left, right }
+        | Expression::Coalesce { left, right } => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(right);
+        }
+        Expression::Unary { expr, .. }
+        | Expression::Cast { expr, .. }
+        | Expression::Clone(expr)
+        | Expression::Include { path: expr, .. } => visitor.visit_expression(expr),
+        Expression::FunctionCall { args, .. }
+        | Expression::New { args, .. }
+        | Expression::StaticMethodCall { args, .. } => {
+            for arg in args {
+                visitor.visit_expression(arg);
+            }
+        }
+        Expression::MethodCall { object, args, .. }
+        | Expression::NullsafeMethodCall { object, args, .. }
+        | Expression::Invoke { callee: object, args } => {
+            visitor.visit_expression(object);
+            for arg in args {
+                visitor.visit_expression(arg);
+            }
+        }
+        Expression::PropertyAccess { object, .. } | Expression::NullsafePropertyAccess { object, .. } => {
+            visitor.visit_expression(object);
+        }
+        Expression::ArrayAccess { array, index } => {
+            visitor.visit_expression(array);
+            visitor.visit_expression(index);
+        }
+        Expression::Array(elements) => {
+            for element in elements {
+                walk_array_element(visitor, element);
+            }
+        }
+        Expression::List(items) => {
+            for element in items.iter().flatten() {
+                walk_array_element(visitor, element);
+            }
+        }
+        Expression::Ternary { condition, then_expr, else_expr } => {
+            visitor.visit_expression(condition);
+            visitor.visit_expression(then_expr);
+            visitor.visit_expression(else_expr);
+        }
+    }
+}
+
+fn walk_array_element<V: Visitor + ?Sized>(visitor: &mut V, element: &ArrayElement) {
+    if let Some(key) = &element.key {
+        visitor.visit_expression(key);
+    }
+    visitor.visit_expression(&element.value);
+}
@@ -0,0 +1,267 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+//! In-place AST rewriting.
+//!
+//! The mutable counterpart of [`crate::visit`]: implement [`VisitorMut`],
+//! override the hooks you care about and call the matching `walk_*_mut`
+//! function to keep descending. Besides statements and expressions, class
+//! members and declared types (parameters, return types, properties and casts)
+//! get their own hooks so that passes such as name resolution can rewrite them.
+
+use crate::ast::*;
+
+pub trait VisitorMut {
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        walk_statement_mut(self, stmt);
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr);
+    }
+
+    fn visit_class_member_mut(&mut self, member: &mut ClassMember) {
+        walk_class_member_mut(self, member);
+    }
+
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        walk_type_mut(self, ty);
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    for item in &mut program.items {
+        if let ProgramItem::PhpBlock { statements } = item {
+            for stmt in statements {
+                visitor.visit_statement_mut(stmt);
+            }
+        }
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut Block) {
+    for stmt in &mut block.statements {
+        visitor.visit_statement_mut(stmt);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Statement) {
+    match stmt {
+        Statement::Expression(expr) => visitor.visit_expression_mut(expr),
+        Statement::Return(expr) => {
+            if let Some(expr) = expr {
+                visitor.visit_expression_mut(expr);
+            }
+        }
+        Statement::If { condition, then_block, elseif_blocks, else_block } => {
+            visitor.visit_expression_mut(condition);
+            walk_block_mut(visitor, then_block);
+            for elseif in elseif_blocks {
+                visitor.visit_expression_mut(&mut elseif.condition);
+                walk_block_mut(visitor, &mut elseif.then_block);
+            }
+            if let Some(else_block) = else_block {
+                walk_block_mut(visitor, else_block);
+            }
+        }
+        Statement::While { condition, body } | Statement::DoWhile { body, condition } => {
+            visitor.visit_expression_mut(condition);
+            walk_block_mut(visitor, body);
+        }
+        Statement::For { init, condition, update, body } => {
+            if let Some(init) = init {
+                visitor.visit_statement_mut(init);
+            }
+            if let Some(condition) = condition {
+                visitor.visit_expression_mut(condition);
+            }
+            if let Some(update) = update {
+                visitor.visit_expression_mut(update);
+            }
+            walk_block_mut(visitor, body);
+        }
+        Statement::Function { params, body, return_type, .. } => {
+            walk_parameters_mut(visitor, params);
+            if let Some(return_type) = return_type {
+                visitor.visit_type_mut(return_type);
+            }
+            walk_block_mut(visitor, body);
+        }
+        Statement::Class { members, .. }
+        | Statement::Enum { members, .. }
+        | Statement::Interface { members, .. } => {
+            for member in members {
+                visitor.visit_class_member_mut(member);
+            }
+        }
+        Statement::Echo(exprs) => {
+            for expr in exprs {
+                visitor.visit_expression_mut(expr);
+            }
+        }
+        Statement::Block(block) => walk_block_mut(visitor, block),
+        Statement::Namespace(namespace) => walk_block_mut(visitor, &mut namespace.body),
+        Statement::Foreach { array, value, body, .. } => {
+            visitor.visit_expression_mut(array);
+            visitor.visit_expression_mut(value);
+            walk_block_mut(visitor, body);
+        }
+        Statement::Switch { expr, cases } => {
+            visitor.visit_expression_mut(expr);
+            for case in cases {
+                if let Some(value) = &mut case.value {
+                    visitor.visit_expression_mut(value);
+                }
+                for stmt in &mut case.statements {
+                    visitor.visit_statement_mut(stmt);
+                }
+            }
+        }
+        Statement::Static(variables) => {
+            for variable in variables {
+                if let Some(default) = &mut variable.default {
+                    visitor.visit_expression_mut(default);
+                }
+            }
+        }
+        Statement::Break
+        | Statement::Continue
+        | Statement::Use(_)
+        | Statement::Global(_)
+        | Statement::Declare(_)
+        | Statement::Location(_) => {}
+    }
+}
+
+pub fn walk_class_member_mut<V: VisitorMut + ?Sized>(visitor: &mut V, member: &mut ClassMember) {
+    match member {
+        ClassMember::Property { property_type, default, .. } => {
+            if let Some(property_type) = property_type {
+                visitor.visit_type_mut(property_type);
+            }
+            if let Some(default) = default {
+                visitor.visit_expression_mut(default);
+            }
+        }
+        ClassMember::Method { params, body, return_type, .. } => {
+            walk_parameters_mut(visitor, params);
+            if let Some(return_type) = return_type {
+                visitor.visit_type_mut(return_type);
+            }
+            walk_block_mut(visitor, body);
+        }
+        ClassMember::Constructor { params, body, .. } => {
+            walk_parameters_mut(visitor, params);
+            walk_block_mut(visitor, body);
+        }
+        ClassMember::AbstractMethod { params, return_type, .. } => {
+            walk_parameters_mut(visitor, params);
+            if let Some(return_type) = return_type {
+                visitor.visit_type_mut(return_type);
+            }
+        }
+        ClassMember::Constant { value, .. } => visitor.visit_expression_mut(value),
+        ClassMember::Case { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expression_mut(value);
+            }
+        }
+    }
+}
+
+fn walk_parameters_mut<V: VisitorMut + ?Sized>(visitor: &mut V, params: &mut [Parameter]) {
+    for param in params {
+        if let Some(param_type) = &mut param.param_type {
+            visitor.visit_type_mut(param_type);
+        }
+        if let Some(default) = &mut param.default {
+            visitor.visit_expression_mut(default);
+        }
+    }
+}
+
+pub fn walk_type_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ty: &mut Type) {
+    match ty {
+        Type::Nullable(inner) => visitor.visit_type_mut(inner),
+        Type::Union(types) | Type::Intersection(types) => {
+            for ty in types {
+                visitor.visit_type_mut(ty);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expression) {
+    match expr {
+        Expression::Variable(_)
+        | Expression::ClassConstant { .. }
+        | Expression::StaticPropertyAccess { .. }
+        | Expression::MagicConstant(_) => {}
+        Expression::Literal(Literal::InterpolatedString(parts)) => {
+            for part in parts {
+                if let InterpolatedPart::Expression(expr) = part {
+                    visitor.visit_expression_mut(expr);
+                }
+            }
+        }
+        Expression::Literal(_) => {}
+        Expression::Binary { left, right, .. }
+        | Expression::Assignment { left, right }
+        | Expression::Coalesce { left, right } => {
+            visitor.visit_expression_mut(left);
+            visitor.visit_expression_mut(right);
+        }
+        Expression::Cast { cast_type, expr } => {
+            visitor.visit_type_mut(cast_type);
+            visitor.visit_expression_mut(expr);
+        }
+        Expression::Unary { expr, .. } | Expression::Clone(expr) | Expression::Include { path: expr, .. } => {
+            visitor.visit_expression_mut(expr)
+        }
+        Expression::FunctionCall { args, .. }
+        | Expression::New { args, .. }
+        | Expression::StaticMethodCall { args, .. } => {
+            for arg in args {
+                visitor.visit_expression_mut(arg);
+            }
+        }
+        Expression::MethodCall { object, args, .. }
+        | Expression::NullsafeMethodCall { object, args, .. }
+        | Expression::Invoke { callee: object, args } => {
+            visitor.visit_expression_mut(object);
+            for arg in args {
+                visitor.visit_expression_mut(arg);
+            }
+        }
+        Expression::PropertyAccess { object, .. } | Expression::NullsafePropertyAccess { object, .. } => {
+            visitor.visit_expression_mut(object);
+        }
+        Expression::ArrayAccess { array, index } => {
+            visitor.visit_expression_mut(array);
+            visitor.visit_expression_mut(index);
+        }
+        Expression::Array(elements) => {
+            for element in elements {
+                walk_array_element_mut(visitor, element);
+            }
+        }
+        Expression::List(items) => {
+            for element in items.iter_mut().flatten() {
+                walk_array_element_mut(visitor, element);
+            }
+        }
+        Expression::Ternary { condition, then_expr, else_expr } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_expression_mut(then_expr);
+            visitor.visit_expression_mut(else_expr);
+        }
+    }
+}
+
+fn walk_array_element_mut<V: VisitorMut + ?Sized>(visitor: &mut V, element: &mut ArrayElement) {
+    if let Some(key) = &mut element.key {
+        visitor.visit_expression_mut(key);
+    }
+    visitor.visit_expression_mut(&mut element.value);
+}
@@ -1,14 +1,15 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-/// Execution context for PHP runtime
-/// 
-/// Manages variables, functions, classes, and execution state
+//! Execution context for PHP runtime
+//! 
+//! Manages variables, functions, classes, and execution state
 
 use crate::value::Value;
 use crate::memory::MemoryManager;
 use crate::extension::ExtensionManager;
 use std::collections::HashMap;
+use std::fmt;
 
 pub struct ExecutionContext {
     /// Memory manager
@@ -28,6 +29,12 @@ pub struct ExecutionContext {
     
     /// Class definitions
     pub classes: HashMap<String, ClassDef>,
+
+    /// Class names by `PhpObject::class_id`
+    pub class_names: Vec<String>,
+
+    /// Property names by `ObjectProperty::name_id`
+    pub property_names: Vec<String>,
     
     /// Constants
     pub constants: HashMap<String, *mut Value>,
@@ -107,6 +114,8 @@ pub enum Visibility {
 #[derive(Debug, Clone)]
 pub struct PhpError {
     pub level: ErrorLevel,
+    /// What an uncaught [`ErrorLevel::Error`] reports itself as, such as `TypeError`
+    pub class: &'static str,
     pub message: String,
     pub file: Option<String>,
     pub line: Option<u32>,
@@ -120,6 +129,25 @@ pub enum ErrorLevel {
     Fatal,
 }
 
+impl fmt::Display for PhpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.level {
+            ErrorLevel::Notice => write!(f, "PHP Notice:  {}", self.message)?,
+            ErrorLevel::Warning => write!(f, "PHP Warning:  {}", self.message)?,
+            ErrorLevel::Error => write!(f, "PHP Fatal error:  Uncaught {}: {}", self.class, self.message)?,
+            ErrorLevel::Fatal => write!(f, "PHP Fatal error:  {}", self.message)?,
+        }
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, " in {} on line {}", file, line),
+            (None, Some(line)) => write!(f, " on line {}", line),
+            (Some(file), None) => write!(f, " in {}", file),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for PhpError {}
+
 pub struct Settings {
     pub error_reporting: u32,
     pub display_errors: bool,
@@ -138,6 +166,12 @@ impl Default for Settings {
     }
 }
 
+impl Default for ExecutionContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ExecutionContext {
     pub fn new() -> Self {
         let mut ctx = ExecutionContext {
@@ -147,6 +181,8 @@ impl ExecutionContext {
             functions: HashMap::new(),
             builtins: HashMap::new(),
             classes: HashMap::new(),
+            class_names: Vec::new(),
+            property_names: Vec::new(),
             constants: HashMap::new(),
             include_paths: vec![".".to_string()],
             output_buffer: Vec::new(),
@@ -166,26 +202,95 @@ impl ExecutionContext {
         self.builtins.insert("strlen".to_string(), builtins::strlen);
         self.builtins.insert("substr".to_string(), builtins::substr);
         self.builtins.insert("strpos".to_string(), builtins::strpos);
+        self.builtins.insert("strrpos".to_string(), builtins::strrpos);
+        self.builtins.insert("str_contains".to_string(), builtins::str_contains);
+        self.builtins.insert("str_starts_with".to_string(), builtins::str_starts_with);
+        self.builtins.insert("str_ends_with".to_string(), builtins::str_ends_with);
+        self.builtins.insert("strtolower".to_string(), builtins::strtolower);
+        self.builtins.insert("strtoupper".to_string(), builtins::strtoupper);
+        self.builtins.insert("ucfirst".to_string(), builtins::ucfirst);
+        self.builtins.insert("lcfirst".to_string(), builtins::lcfirst);
+        self.builtins.insert("ucwords".to_string(), builtins::ucwords);
+        self.builtins.insert("trim".to_string(), builtins::trim);
+        self.builtins.insert("ltrim".to_string(), builtins::ltrim);
+        self.builtins.insert("rtrim".to_string(), builtins::rtrim);
+        self.builtins.insert("str_replace".to_string(), builtins::str_replace);
+        self.builtins.insert("explode".to_string(), builtins::explode);
+        self.builtins.insert("implode".to_string(), builtins::implode);
+        self.builtins.insert("join".to_string(), builtins::implode);
+        self.builtins.insert("str_repeat".to_string(), builtins::str_repeat);
+        self.builtins.insert("str_pad".to_string(), builtins::str_pad);
+        self.builtins.insert("strrev".to_string(), builtins::strrev);
+        self.builtins.insert("strcmp".to_string(), builtins::strcmp);
+        self.builtins.insert("ord".to_string(), builtins::ord);
+        self.builtins.insert("chr".to_string(), builtins::chr);
+        self.builtins.insert("sprintf".to_string(), builtins::sprintf);
+        self.builtins.insert("printf".to_string(), builtins::printf);
+        self.builtins.insert("number_format".to_string(), builtins::number_format);
         
         // Array functions
         self.builtins.insert("count".to_string(), builtins::count);
+        self.builtins.insert("sizeof".to_string(), builtins::count);
         self.builtins.insert("array_push".to_string(), builtins::array_push);
+        self.builtins.insert("array_pop".to_string(), builtins::array_pop);
+        self.builtins.insert("array_shift".to_string(), builtins::array_shift);
+        self.builtins.insert("array_unshift".to_string(), builtins::array_unshift);
+        self.builtins.insert("in_array".to_string(), builtins::in_array);
+        self.builtins.insert("array_search".to_string(), builtins::array_search);
+        self.builtins.insert("array_keys".to_string(), builtins::array_keys);
+        self.builtins.insert("array_values".to_string(), builtins::array_values);
         self.builtins.insert("array_merge".to_string(), builtins::array_merge);
+        self.builtins.insert("array_slice".to_string(), builtins::array_slice);
+        self.builtins.insert("array_key_exists".to_string(), builtins::array_key_exists);
+        self.builtins.insert("key_exists".to_string(), builtins::array_key_exists);
+        self.builtins.insert("array_key_first".to_string(), builtins::array_key_first);
+        self.builtins.insert("array_key_last".to_string(), builtins::array_key_last);
+        self.builtins.insert("array_reverse".to_string(), builtins::array_reverse);
+        self.builtins.insert("array_sum".to_string(), builtins::array_sum);
+        self.builtins.insert("array_product".to_string(), builtins::array_product);
+        self.builtins.insert("range".to_string(), builtins::range);
+        self.builtins.insert("sort".to_string(), builtins::sort);
+        self.builtins.insert("rsort".to_string(), builtins::rsort);
+        self.builtins.insert("asort".to_string(), builtins::asort);
+        self.builtins.insert("arsort".to_string(), builtins::arsort);
+        self.builtins.insert("ksort".to_string(), builtins::ksort);
+        self.builtins.insert("krsort".to_string(), builtins::krsort);
+        
+        // Math functions
+        self.builtins.insert("abs".to_string(), builtins::abs);
+        self.builtins.insert("min".to_string(), builtins::min);
+        self.builtins.insert("max".to_string(), builtins::max);
+        self.builtins.insert("round".to_string(), builtins::round);
+        self.builtins.insert("floor".to_string(), builtins::floor);
+        self.builtins.insert("ceil".to_string(), builtins::ceil);
+        self.builtins.insert("sqrt".to_string(), builtins::sqrt);
+        self.builtins.insert("pow".to_string(), builtins::pow);
+        self.builtins.insert("intdiv".to_string(), builtins::intdiv);
+        self.builtins.insert("fmod".to_string(), builtins::fmod);
         
         // Type functions
         self.builtins.insert("gettype".to_string(), builtins::gettype);
         self.builtins.insert("is_null".to_string(), builtins::is_null);
         self.builtins.insert("is_bool".to_string(), builtins::is_bool);
         self.builtins.insert("is_int".to_string(), builtins::is_int);
+        self.builtins.insert("is_integer".to_string(), builtins::is_int);
+        self.builtins.insert("is_long".to_string(), builtins::is_int);
         self.builtins.insert("is_float".to_string(), builtins::is_float);
+        self.builtins.insert("is_double".to_string(), builtins::is_float);
         self.builtins.insert("is_string".to_string(), builtins::is_string);
         self.builtins.insert("is_array".to_string(), builtins::is_array);
         self.builtins.insert("is_object".to_string(), builtins::is_object);
+        self.builtins.insert("is_numeric".to_string(), builtins::is_numeric);
+        self.builtins.insert("intval".to_string(), builtins::intval);
+        self.builtins.insert("floatval".to_string(), builtins::floatval);
+        self.builtins.insert("strval".to_string(), builtins::strval);
+        self.builtins.insert("boolval".to_string(), builtins::boolval);
         
         // Output functions
         self.builtins.insert("echo".to_string(), builtins::echo);
         self.builtins.insert("print".to_string(), builtins::print);
         self.builtins.insert("var_dump".to_string(), builtins::var_dump);
+        self.builtins.insert("print_r".to_string(), builtins::print_r);
     }
     
     /// Register core extensions
@@ -207,7 +312,7 @@ impl ExecutionContext {
         
         // Initialize all extensions
         // We need to temporarily extract extensions to avoid mutable borrow conflict
-        let mut extensions = std::mem::replace(&mut self.extensions, ExtensionManager::new());
+        let mut extensions = std::mem::take(&mut self.extensions);
         if let Err(e) = extensions.initialize_all(self) {
             self.trigger_error(ErrorLevel::Warning, format!("Failed to initialize extensions: {}", e.message));
         }
@@ -290,6 +395,7 @@ impl ExecutionContext {
         
         Err(PhpError {
             level: ErrorLevel::Fatal,
+            class: "Error",
             message: format!("Call to undefined function {}", name),
             file: None,
             line: None,
@@ -305,6 +411,7 @@ impl ExecutionContext {
     pub fn trigger_error(&mut self, level: ErrorLevel, message: String) {
         let error = PhpError {
             level,
+            class: "Error",
             message: message.clone(),
             file: None, // TODO: Track current file
             line: None, // TODO: Track current line
@@ -342,98 +449,1286 @@ impl Scope {
 }
 
 /// Built-in function implementations
+///
+/// Arguments arrive with references resolved. Functions that change an
+/// argument in place, like `array_push`, get the caller's variable itself.
 mod builtins {
     use super::*;
-    
+    use crate::operations;
+    use crate::value::{ArrayKey, Number, PhpArray, PhpObject, ValueType, format_float, parse_numeric};
+    use std::cmp::Ordering;
+
+    type BuiltinResult = Result<*mut Value, PhpError>;
+
+    /// Argument `index`, or null when it was not passed
+    fn arg(args: &[*mut Value], index: usize) -> Value {
+        match args.get(index) {
+            Some(&ptr) => unsafe { *deref(ptr) },
+            None => Value::null(),
+        }
+    }
+
+    fn has_arg(args: &[*mut Value], index: usize) -> bool {
+        args.get(index).is_some_and(|&ptr| unsafe { (*deref(ptr)).get_type() != ValueType::Null })
+    }
+
+    unsafe fn deref(mut ptr: *mut Value) -> *mut Value {
+        while (*ptr).get_type() == ValueType::Reference {
+            ptr = (*ptr).data.ptr as *mut Value;
+        }
+        ptr
+    }
+
+    fn string_arg(args: &[*mut Value], index: usize) -> String {
+        arg(args, index).to_php_string()
+    }
+
+    fn int_arg(args: &[*mut Value], index: usize) -> i64 {
+        arg(args, index).to_int()
+    }
+
+    fn error(class: &'static str, message: String) -> PhpError {
+        PhpError { level: ErrorLevel::Error, class, message, file: None, line: None }
+    }
+
+    fn expect(name: &str, args: &[*mut Value], count: usize) -> Result<(), PhpError> {
+        if args.len() < count {
+            let plural = if count == 1 { "" } else { "s" };
+            return Err(error("ArgumentCountError", format!("{}() expects at least {} argument{}, {} given", name, count, plural, args.len())));
+        }
+        Ok(())
+    }
+
+    /// Argument `index`, which must be an array
+    fn array_arg(name: &str, args: &[*mut Value], index: usize) -> Result<*mut PhpArray, PhpError> {
+        expect(name, args, index + 1)?;
+        let value = arg(args, index);
+        if value.get_type() != ValueType::Array {
+            return Err(error("TypeError", format!(
+                "{}(): Argument #{} must be of type array, {} given",
+                name, index + 1, operations::type_name(value.get_type())
+            )));
+        }
+        Ok(unsafe { value.data.ptr as *mut PhpArray })
+    }
+
+    fn ret(ctx: &mut ExecutionContext, value: Value) -> BuiltinResult {
+        let ptr = ctx.memory.alloc_value();
+        unsafe {
+            *ptr = value;
+        }
+        Ok(ptr)
+    }
+
+    fn ret_string(ctx: &mut ExecutionContext, s: &str) -> BuiltinResult {
+        let string = ctx.memory.alloc_string(s);
+        ret(ctx, Value::string(string as *mut u8))
+    }
+
+    fn ret_array(ctx: &mut ExecutionContext, array: *mut PhpArray) -> BuiltinResult {
+        ret(ctx, Value::array(array))
+    }
+
+    /// Append a copy of `value`
+    unsafe fn push(ctx: &mut ExecutionContext, array: *mut PhpArray, value: *mut Value) {
+        let slot = ctx.memory.alloc_value();
+        *slot = ctx.memory.copy_value(value);
+        ctx.memory.array_push(array, slot);
+    }
+
+    /// Store a copy of `value` under `key`
+    unsafe fn set(ctx: &mut ExecutionContext, array: *mut PhpArray, key: ArrayKey, value: *mut Value) {
+        let slot = ctx.memory.alloc_value();
+        *slot = ctx.memory.copy_value(value);
+        ctx.memory.array_set(array, key, slot);
+    }
+
+    unsafe fn entries(array: *mut PhpArray) -> impl Iterator<Item = (ArrayKey, *mut Value)> {
+        MemoryManager::array_entries(array).map(|entry| ((*entry).key, (*entry).value))
+    }
+
+    /// A new array of `entries`: int keys renumbered from 0, string keys kept
+    unsafe fn renumbered(ctx: &mut ExecutionContext, entries: &[(ArrayKey, *mut Value)]) -> *mut PhpArray {
+        let result = ctx.memory.alloc_array(entries.len());
+        for &(key, value) in entries {
+            match key {
+                ArrayKey::Integer(_) => push(ctx, result, value),
+                ArrayKey::String(_) => set(ctx, result, key, value),
+            }
+        }
+        result
+    }
+
+    fn key_string(key: &ArrayKey) -> String {
+        key.to_value().to_php_string()
+    }
+
+    // String functions
+
     pub fn strlen(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        if args.is_empty() {
-            return Err(PhpError {
-                level: ErrorLevel::Warning,
-                message: "strlen() expects exactly 1 parameter, 0 given".to_string(),
-                file: None,
-                line: None,
+        expect("strlen", args, 1)?;
+        let len = string_arg(args, 0).len();
+        ret(ctx, Value::int(len as i64))
+    }
+
+    pub fn substr(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("substr", args, 2)?;
+        let s = string_arg(args, 0);
+        let len = s.len() as i64;
+        let mut start = int_arg(args, 1);
+        if start < 0 {
+            start = (len + start).max(0);
+        }
+        let start = start.min(len);
+        let end = match has_arg(args, 2) {
+            false => len,
+            true => match int_arg(args, 2) {
+                length if length < 0 => (len + length).max(start),
+                length => start.saturating_add(length).min(len),
+            },
+        };
+        let bytes = &s.as_bytes()[start as usize..end as usize];
+        ret_string(ctx, &String::from_utf8_lossy(bytes))
+    }
+
+    /// Where a search starts for an `$offset` argument, if it is in range
+    fn offset(name: &str, args: &[*mut Value], index: usize, len: usize) -> Result<usize, PhpError> {
+        let offset = int_arg(args, index);
+        let start = if offset < 0 { len as i64 + offset } else { offset };
+        if start < 0 || start > len as i64 {
+            return Err(error("ValueError", format!("{}(): Argument #{} ($offset) must be contained in argument #1 ($haystack)", name, index + 1)));
+        }
+        Ok(start as usize)
+    }
+
+    pub fn strpos(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("strpos", args, 2)?;
+        let (haystack, needle) = (string_arg(args, 0), string_arg(args, 1));
+        let start = offset("strpos", args, 2, haystack.len())?;
+        match find(&haystack.as_bytes()[start..], needle.as_bytes()) {
+            Some(position) => ret(ctx, Value::int((start + position) as i64)),
+            None => ret(ctx, Value::bool(false)),
+        }
+    }
+
+    pub fn strrpos(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("strrpos", args, 2)?;
+        let (haystack, needle) = (string_arg(args, 0), string_arg(args, 1));
+        let start = offset("strrpos", args, 2, haystack.len())?;
+        let found = (start..=haystack.len().saturating_sub(needle.len()))
+            .rev()
+            .find(|&i| haystack.as_bytes()[i..].starts_with(needle.as_bytes()));
+        match found {
+            Some(position) if needle.len() <= haystack.len() => ret(ctx, Value::int(position as i64)),
+            _ => ret(ctx, Value::bool(false)),
+        }
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        if needle.is_empty() {
+            return Some(0);
+        }
+        haystack.windows(needle.len()).position(|window| window == needle)
+    }
+
+    pub fn str_contains(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("str_contains", args, 2)?;
+        let found = find(string_arg(args, 0).as_bytes(), string_arg(args, 1).as_bytes()).is_some();
+        ret(ctx, Value::bool(found))
+    }
+
+    pub fn str_starts_with(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("str_starts_with", args, 2)?;
+        let found = string_arg(args, 0).starts_with(&string_arg(args, 1));
+        ret(ctx, Value::bool(found))
+    }
+
+    pub fn str_ends_with(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("str_ends_with", args, 2)?;
+        let found = string_arg(args, 0).ends_with(&string_arg(args, 1));
+        ret(ctx, Value::bool(found))
+    }
+
+    pub fn strtolower(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("strtolower", args, 1)?;
+        ret_string(ctx, &string_arg(args, 0).to_ascii_lowercase())
+    }
+
+    pub fn strtoupper(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("strtoupper", args, 1)?;
+        ret_string(ctx, &string_arg(args, 0).to_ascii_uppercase())
+    }
+
+    pub fn ucfirst(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("ucfirst", args, 1)?;
+        let mut s = string_arg(args, 0);
+        if let Some(first) = s.get_mut(0..1) {
+            first.make_ascii_uppercase();
+        }
+        ret_string(ctx, &s)
+    }
+
+    pub fn lcfirst(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("lcfirst", args, 1)?;
+        let mut s = string_arg(args, 0);
+        if let Some(first) = s.get_mut(0..1) {
+            first.make_ascii_lowercase();
+        }
+        ret_string(ctx, &s)
+    }
+
+    pub fn ucwords(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("ucwords", args, 1)?;
+        let mut word_start = true;
+        let s: String = string_arg(args, 0).chars().map(|c| {
+            let c = if word_start { c.to_ascii_uppercase() } else { c };
+            word_start = matches!(c, ' ' | '\t' | '\r' | '\n' | '\x0b' | '\x0c');
+            c
+        }).collect();
+        ret_string(ctx, &s)
+    }
+
+    /// The characters of a `trim()` character list, with `a..z` ranges
+    fn char_list(list: &str) -> Vec<char> {
+        let chars: Vec<char> = list.chars().collect();
+        let mut result = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            if i + 3 < chars.len() + 1 && chars.get(i + 1) == Some(&'.') && chars.get(i + 2) == Some(&'.') && i + 3 < chars.len() {
+                result.extend(chars[i]..=chars[i + 3]);
+                i += 4;
+            } else {
+                result.push(chars[i]);
+                i += 1;
+            }
+        }
+        result
+    }
+
+    fn trim_with(args: &[*mut Value], start: bool, end: bool) -> String {
+        let s = string_arg(args, 0);
+        let chars = match args.len() > 1 {
+            true => char_list(&string_arg(args, 1)),
+            false => vec![' ', '\t', '\n', '\r', '\0', '\x0b'],
+        };
+        let mut s = s.as_str();
+        if start {
+            s = s.trim_start_matches(chars.as_slice());
+        }
+        if end {
+            s = s.trim_end_matches(chars.as_slice());
+        }
+        s.to_string()
+    }
+
+    pub fn trim(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("trim", args, 1)?;
+        ret_string(ctx, &trim_with(args, true, true))
+    }
+
+    pub fn ltrim(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("ltrim", args, 1)?;
+        ret_string(ctx, &trim_with(args, true, false))
+    }
+
+    pub fn rtrim(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("rtrim", args, 1)?;
+        ret_string(ctx, &trim_with(args, false, true))
+    }
+
+    pub fn str_replace(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("str_replace", args, 3)?;
+        let strings = |value: Value| -> Option<Vec<String>> {
+            (value.get_type() == ValueType::Array).then(|| unsafe {
+                entries(value.data.ptr as *mut PhpArray).map(|(_, value)| (*value).to_php_string()).collect()
+            })
+        };
+        let (search, replace) = (arg(args, 0), arg(args, 1));
+        let pairs: Vec<(String, String)> = match (strings(search), strings(replace)) {
+            (Some(search), Some(replace)) => search.into_iter()
+                .enumerate()
+                .map(|(i, s)| (s, replace.get(i).cloned().unwrap_or_default()))
+                .collect(),
+            (Some(search), None) => search.into_iter().map(|s| (s, replace.to_php_string())).collect(),
+            _ => vec![(search.to_php_string(), replace.to_php_string())],
+        };
+        let replace_all = |mut subject: String| {
+            for (search, replace) in &pairs {
+                if !search.is_empty() {
+                    subject = subject.replace(search.as_str(), replace);
+                }
+            }
+            subject
+        };
+
+        let subject = arg(args, 2);
+        if subject.get_type() == ValueType::Array {
+            let result = ctx.memory.alloc_array(0);
+            unsafe {
+                for (key, value) in entries(subject.data.ptr as *mut PhpArray).collect::<Vec<_>>() {
+                    let replaced = replace_all((*value).to_php_string());
+                    let string = ctx.memory.alloc_string(&replaced);
+                    let mut value = Value::string(string as *mut u8);
+                    set(ctx, result, key, &mut value);
+                }
+            }
+            return ret_array(ctx, result);
+        }
+        ret_string(ctx, &replace_all(subject.to_php_string()))
+    }
+
+    pub fn explode(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("explode", args, 2)?;
+        let (separator, s) = (string_arg(args, 0), string_arg(args, 1));
+        if separator.is_empty() {
+            return Err(error("ValueError", "explode(): Argument #1 ($separator) cannot be empty".to_string()));
+        }
+        let limit = if has_arg(args, 2) { int_arg(args, 2) } else { i64::MAX };
+        let mut parts: Vec<&str> = match limit {
+            limit if limit > 0 => s.splitn(limit.min(usize::MAX as i64) as usize, separator.as_str()).collect(),
+            0 => vec![s.as_str()],
+            _ => s.split(separator.as_str()).collect(),
+        };
+        if limit < 0 {
+            parts.truncate(parts.len().saturating_sub(limit.unsigned_abs() as usize));
+        }
+
+        let result = ctx.memory.alloc_array(parts.len());
+        for part in parts {
+            let string = ctx.memory.alloc_string(part);
+            let mut value = Value::string(string as *mut u8);
+            unsafe { push(ctx, result, &mut value) };
+        }
+        ret_array(ctx, result)
+    }
+
+    pub fn implode(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("implode", args, 1)?;
+        let (separator, array) = match arg(args, 0).get_type() {
+            ValueType::Array => (String::new(), array_arg("implode", args, 0)?),
+            _ => (string_arg(args, 0), array_arg("implode", args, 1)?),
+        };
+        let parts: Vec<String> = unsafe { entries(array).map(|(_, value)| (*value).to_php_string()).collect() };
+        ret_string(ctx, &parts.join(&separator))
+    }
+
+    pub fn str_repeat(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("str_repeat", args, 2)?;
+        let times = int_arg(args, 1);
+        if times < 0 {
+            return Err(error("ValueError", "str_repeat(): Argument #2 ($times) must be greater than or equal to 0".to_string()));
+        }
+        ret_string(ctx, &string_arg(args, 0).repeat(times as usize))
+    }
+
+    pub fn str_pad(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("str_pad", args, 2)?;
+        let s = string_arg(args, 0);
+        let length = int_arg(args, 1).max(0) as usize;
+        let pad = if args.len() > 2 { string_arg(args, 2) } else { " ".to_string() };
+        if pad.is_empty() {
+            return Err(error("ValueError", "str_pad(): Argument #3 ($pad_string) must be a non-empty string".to_string()));
+        }
+        // STR_PAD_LEFT = 0, STR_PAD_RIGHT = 1, STR_PAD_BOTH = 2
+        let kind = if args.len() > 3 { int_arg(args, 3) } else { 1 };
+        let missing = length.saturating_sub(s.len());
+        let (left, right) = match kind {
+            0 => (missing, 0),
+            2 => (missing / 2, missing - missing / 2),
+            _ => (0, missing),
+        };
+        let padding = |n: usize| pad.chars().cycle().take(n).collect::<String>();
+        ret_string(ctx, &format!("{}{}{}", padding(left), s, padding(right)))
+    }
+
+    pub fn strrev(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("strrev", args, 1)?;
+        let mut bytes = string_arg(args, 0).into_bytes();
+        bytes.reverse();
+        ret_string(ctx, &String::from_utf8_lossy(&bytes))
+    }
+
+    pub fn strcmp(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("strcmp", args, 2)?;
+        let ordering = string_arg(args, 0).as_bytes().cmp(string_arg(args, 1).as_bytes());
+        ret(ctx, Value::int(ordering as i64))
+    }
+
+    pub fn ord(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("ord", args, 1)?;
+        let byte = string_arg(args, 0).bytes().next().unwrap_or(0);
+        ret(ctx, Value::int(byte as i64))
+    }
+
+    pub fn chr(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("chr", args, 1)?;
+        let byte = int_arg(args, 0).rem_euclid(256) as u8;
+        ret_string(ctx, &String::from_utf8_lossy(&[byte]))
+    }
+
+    pub fn sprintf(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("sprintf", args, 1)?;
+        let s = format(args)?;
+        ret_string(ctx, &s)
+    }
+
+    pub fn printf(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("printf", args, 1)?;
+        let s = format(args)?;
+        ctx.output(s.as_bytes());
+        ret(ctx, Value::int(s.len() as i64))
+    }
+
+    /// `sprintf()`: `%[argnum$][flags][width][.precision]specifier`
+    fn format(args: &[*mut Value]) -> Result<String, PhpError> {
+        let template: Vec<char> = string_arg(args, 0).chars().collect();
+        let mut out = String::new();
+        let mut next_arg = 1;
+        let mut i = 0;
+        while i < template.len() {
+            if template[i] != '%' {
+                out.push(template[i]);
+                i += 1;
+                continue;
+            }
+            i += 1;
+            if template.get(i) == Some(&'%') {
+                out.push('%');
+                i += 1;
+                continue;
+            }
+
+            let number = |i: &mut usize| {
+                let start = *i;
+                while template.get(*i).is_some_and(char::is_ascii_digit) {
+                    *i += 1;
+                }
+                template[start..*i].iter().collect::<String>().parse::<usize>().ok()
+            };
+            let mut argnum = None;
+            let save = i;
+            if let Some(n) = number(&mut i) {
+                if template.get(i) == Some(&'$') {
+                    argnum = Some(n);
+                    i += 1;
+                } else {
+                    i = save;
+                }
+            }
+            let (mut left, mut plus, mut pad) = (false, false, ' ');
+            loop {
+                match template.get(i) {
+                    Some('-') => left = true,
+                    Some('+') => plus = true,
+                    Some('0') => pad = '0',
+                    Some(' ') => pad = ' ',
+                    Some('\'') => {
+                        i += 1;
+                        pad = template.get(i).copied().unwrap_or(' ');
+                    }
+                    _ => break,
+                }
+                i += 1;
+            }
+            let width = number(&mut i).unwrap_or(0);
+            let precision = match template.get(i) {
+                Some('.') => {
+                    i += 1;
+                    Some(number(&mut i).unwrap_or(0))
+                }
+                _ => None,
+            };
+            let Some(&specifier) = template.get(i) else {
+                return Err(error("ValueError", "Missing format specifier at end of string".to_string()));
+            };
+            i += 1;
+
+            let index = argnum.unwrap_or_else(|| {
+                next_arg += 1;
+                next_arg - 1
             });
+            if index >= args.len() {
+                return Err(error("ArgumentCountError", format!("{} arguments are required, {} given", index + 1, args.len())));
+            }
+            let value = arg(args, index);
+            let sign = |negative: bool| if negative { "-" } else if plus { "+" } else { "" };
+            let formatted = match specifier {
+                'd' | 'i' => {
+                    let n = value.to_int();
+                    format!("{}{}", sign(n < 0), n.unsigned_abs())
+                }
+                'u' => (value.to_int() as u64).to_string(),
+                'f' | 'F' => {
+                    let f = value.to_float();
+                    format!("{}{:.*}", sign(f < 0.0), precision.unwrap_or(6), f.abs())
+                }
+                'e' | 'E' => {
+                    let f = value.to_float();
+                    let s = format!("{:.*e}", precision.unwrap_or(6), f.abs());
+                    let (mantissa, exponent) = s.split_once('e').unwrap_or((&s, "0"));
+                    let exponent: i32 = exponent.parse().unwrap_or(0);
+                    let e = if specifier == 'e' { 'e' } else { 'E' };
+                    format!("{}{}{}{}{}", sign(f < 0.0), mantissa, e, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+                }
+                'g' | 'G' => format_float(value.to_float()),
+                's' => {
+                    let s = value.to_php_string();
+                    match precision {
+                        Some(p) => s.chars().take(p).collect(),
+                        None => s,
+                    }
+                }
+                'x' => format!("{:x}", value.to_int()),
+                'X' => format!("{:X}", value.to_int()),
+                'o' => format!("{:o}", value.to_int()),
+                'b' => format!("{:b}", value.to_int()),
+                'c' => String::from_utf8_lossy(&[value.to_int() as u8]).into_owned(),
+                other => return Err(error("ValueError", format!("Unknown format specifier \"{}\"", other))),
+            };
+
+            let missing = width.saturating_sub(formatted.chars().count());
+            if left {
+                out.push_str(&formatted);
+                out.extend(std::iter::repeat_n(if pad == '0' { ' ' } else { pad }, missing));
+            } else if pad == '0' && matches!(formatted.chars().next(), Some('-' | '+')) && specifier != 's' {
+                let (sign, digits) = formatted.split_at(1);
+                out.push_str(sign);
+                out.extend(std::iter::repeat_n('0', missing));
+                out.push_str(digits);
+            } else {
+                out.extend(std::iter::repeat_n(pad, missing));
+                out.push_str(&formatted);
+            }
         }
-        
-        // TODO: Implement proper string length calculation
-        let result = ctx.memory.alloc_value();
+        Ok(out)
+    }
+
+    pub fn number_format(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("number_format", args, 1)?;
+        let decimals = if has_arg(args, 1) { int_arg(args, 1).max(0) as usize } else { 0 };
+        let point = if args.len() > 2 { string_arg(args, 2) } else { ".".to_string() };
+        let separator = if args.len() > 3 { string_arg(args, 3) } else { ",".to_string() };
+
+        let f = round_to(arg(args, 0).to_float(), decimals as i64);
+        let fixed = format!("{:.*}", decimals, f.abs());
+        let (whole, fraction) = fixed.split_once('.').unwrap_or((&fixed, ""));
+        let mut grouped = String::new();
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push_str(&separator);
+            }
+            grouped.push(digit);
+        }
+        let mut result = if f < 0.0 { format!("-{}", grouped) } else { grouped };
+        if decimals > 0 {
+            result.push_str(&point);
+            result.push_str(fraction);
+        }
+        ret_string(ctx, &result)
+    }
+
+    // Array functions
+
+    pub fn count(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("count", args, 1)?;
+        let value = arg(args, 0);
+        if value.get_type() != ValueType::Array {
+            return Err(error("TypeError", format!(
+                "count(): Argument #1 ($value) must be of type Countable|array, {} given",
+                operations::type_name(value.get_type())
+            )));
+        }
+        unsafe fn count_recursive(array: *mut PhpArray) -> i64 {
+            entries(array).map(|(_, value)| match (*deref(value)).get_type() {
+                ValueType::Array => 1 + count_recursive((*deref(value)).data.ptr as *mut PhpArray),
+                _ => 1,
+            }).sum()
+        }
+        let array = unsafe { value.data.ptr as *mut PhpArray };
+        // COUNT_RECURSIVE = 1
+        let count = match int_arg(args, 1) {
+            1 => unsafe { count_recursive(array) },
+            _ => unsafe { (*array).size as i64 },
+        };
+        ret(ctx, Value::int(count))
+    }
+
+    pub fn array_push(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_push", args, 0)?;
         unsafe {
-            *result = Value::int(0);
+            for &value in &args[1..] {
+                push(ctx, array, value);
+            }
+            ret(ctx, Value::int((*array).size as i64))
         }
-        Ok(result)
     }
-    
-    pub fn echo(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        for &_arg in args {
-            // TODO: Convert value to string and output
-            ctx.output(b"[echo output]");
+
+    pub fn array_pop(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_pop", args, 0)?;
+        unsafe {
+            let last = (*array).last;
+            if last.is_null() {
+                return ret(ctx, Value::null());
+            }
+            let (key, value) = ((*last).key, *(*last).value);
+            ctx.memory.array_remove(array, &key);
+            if let ArrayKey::Integer(index) = key {
+                if index + 1 == (*array).next_index {
+                    (*array).next_index = index;
+                }
+            }
+            ret(ctx, value)
         }
-        
-        let result = ctx.memory.alloc_value();
+    }
+
+    pub fn array_shift(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_shift", args, 0)?;
         unsafe {
-            *result = Value::null();
+            let mut all: Vec<_> = entries(array).collect();
+            if all.is_empty() {
+                return ret(ctx, Value::null());
+            }
+            let (_, first) = all.remove(0);
+            let first = *first;
+            let rest = renumbered(ctx, &all);
+            *deref(args[0]) = Value::array(rest);
+            ret(ctx, first)
         }
-        Ok(result)
     }
-    
-    // Stub implementations for other functions
-    pub fn substr(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_unshift(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_unshift", args, 0)?;
+        unsafe {
+            let mut all: Vec<_> = args[1..].iter().map(|&value| (ArrayKey::Integer(0), value)).collect();
+            all.extend(entries(array));
+            let result = renumbered(ctx, &all);
+            *deref(args[0]) = Value::array(result);
+            ret(ctx, Value::int((*result).size as i64))
+        }
     }
-    
-    pub fn strpos(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn in_array(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("in_array", args, 1)?;
+        let needle = arg(args, 0);
+        let strict = arg(args, 2).to_bool();
+        let found = unsafe { entries(array).any(|(_, value)| matches(&needle, &*value, strict)) };
+        ret(ctx, Value::bool(found))
     }
-    
-    pub fn count(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    fn matches(needle: &Value, value: &Value, strict: bool) -> bool {
+        match strict {
+            true => operations::strict_equals(needle, value),
+            false => operations::loose_equals(needle, value),
+        }
     }
-    
-    pub fn array_push(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_search(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_search", args, 1)?;
+        let needle = arg(args, 0);
+        let strict = arg(args, 2).to_bool();
+        match unsafe { entries(array).find(|(_, value)| matches(&needle, &**value, strict)) } {
+            Some((key, _)) => ret(ctx, key.to_value()),
+            None => ret(ctx, Value::bool(false)),
+        }
     }
-    
-    pub fn array_merge(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_keys(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_keys", args, 0)?;
+        let filter = (args.len() > 1).then(|| arg(args, 1));
+        let strict = arg(args, 2).to_bool();
+        unsafe {
+            let result = ctx.memory.alloc_array((*array).size as usize);
+            for (key, value) in entries(array).collect::<Vec<_>>() {
+                if filter.as_ref().is_none_or(|filter| matches(filter, &*value, strict)) {
+                    let mut key = key.to_value();
+                    push(ctx, result, &mut key);
+                }
+            }
+            ret_array(ctx, result)
+        }
     }
-    
-    pub fn gettype(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_values(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_values", args, 0)?;
+        unsafe {
+            let result = ctx.memory.alloc_array((*array).size as usize);
+            for (_, value) in entries(array).collect::<Vec<_>>() {
+                push(ctx, result, value);
+            }
+            ret_array(ctx, result)
+        }
     }
-    
-    pub fn is_null(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_merge(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let result = ctx.memory.alloc_array(0);
+        for i in 0..args.len() {
+            let array = array_arg("array_merge", args, i)?;
+            unsafe {
+                for (key, value) in entries(array).collect::<Vec<_>>() {
+                    match key {
+                        ArrayKey::Integer(_) => push(ctx, result, value),
+                        ArrayKey::String(_) => set(ctx, result, key, value),
+                    }
+                }
+            }
+        }
+        ret_array(ctx, result)
     }
-    
-    pub fn is_bool(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_slice(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_slice", args, 0)?;
+        expect("array_slice", args, 2)?;
+        let preserve_keys = arg(args, 3).to_bool();
+        unsafe {
+            let all: Vec<_> = entries(array).collect();
+            let len = all.len() as i64;
+            let mut start = int_arg(args, 1);
+            if start < 0 {
+                start = (len + start).max(0);
+            }
+            let start = start.min(len);
+            let end = match has_arg(args, 2) {
+                false => len,
+                true => match int_arg(args, 2) {
+                    length if length < 0 => (len + length).max(start),
+                    length => start.saturating_add(length).min(len),
+                },
+            };
+
+            let result = ctx.memory.alloc_array((end - start) as usize);
+            for &(key, value) in &all[start as usize..end as usize] {
+                match key {
+                    ArrayKey::Integer(_) if !preserve_keys => push(ctx, result, value),
+                    _ => set(ctx, result, key, value),
+                }
+            }
+            ret_array(ctx, result)
+        }
     }
-    
-    pub fn is_int(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_key_exists(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_key_exists", args, 1)?;
+        let key = ctx.memory.array_key(&arg(args, 0));
+        let exists = unsafe { !ctx.memory.array_get(array, &key).is_null() };
+        ret(ctx, Value::bool(exists))
     }
-    
-    pub fn is_float(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_key_first(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_key_first", args, 0)?;
+        let first = unsafe { (*array).first };
+        match first.is_null() {
+            true => ret(ctx, Value::null()),
+            false => ret(ctx, unsafe { (*first).key.to_value() }),
+        }
     }
-    
-    pub fn is_string(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_key_last(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_key_last", args, 0)?;
+        let last = unsafe { (*array).last };
+        match last.is_null() {
+            true => ret(ctx, Value::null()),
+            false => ret(ctx, unsafe { (*last).key.to_value() }),
+        }
     }
-    
-    pub fn is_array(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_reverse(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_reverse", args, 0)?;
+        let preserve_keys = arg(args, 1).to_bool();
+        unsafe {
+            let all: Vec<_> = entries(array).collect();
+            let result = ctx.memory.alloc_array(all.len());
+            for &(key, value) in all.iter().rev() {
+                match key {
+                    ArrayKey::Integer(_) if !preserve_keys => push(ctx, result, value),
+                    _ => set(ctx, result, key, value),
+                }
+            }
+            ret_array(ctx, result)
+        }
     }
-    
-    pub fn is_object(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_sum(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_sum", args, 0)?;
+        let mut sum = Value::int(0);
+        unsafe {
+            for (_, value) in entries(array).collect::<Vec<_>>() {
+                sum = *operations::add(ctx, &mut sum, value)?;
+            }
+        }
+        ret(ctx, sum)
     }
-    
-    pub fn print(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn array_product(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let array = array_arg("array_product", args, 0)?;
+        let mut product = Value::int(1);
+        unsafe {
+            for (_, value) in entries(array).collect::<Vec<_>>() {
+                product = *operations::multiply(ctx, &mut product, value)?;
+            }
+        }
+        ret(ctx, product)
     }
-    
-    pub fn var_dump(_ctx: &mut ExecutionContext, _args: &[*mut Value]) -> Result<*mut Value, PhpError> {
-        todo!()
+
+    pub fn range(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("range", args, 2)?;
+        let (start, end) = (arg(args, 0), arg(args, 1));
+        let step = if args.len() > 2 { arg(args, 2) } else { Value::int(1) };
+        let result = ctx.memory.alloc_array(0);
+        let floats = [start, end, step].iter().any(|value| matches!(value.to_number(), Some((Number::Float(_), _))));
+
+        if floats {
+            let (start, end, step) = (start.to_float(), end.to_float(), step.to_float().abs());
+            if step == 0.0 {
+                return Err(error("ValueError", "range(): Argument #3 ($step) cannot be 0".to_string()));
+            }
+            let count = ((end - start).abs() / step).floor() as i64;
+            for i in 0..=count {
+                let f = if start <= end { start + i as f64 * step } else { start - i as f64 * step };
+                unsafe { push(ctx, result, &mut Value::float(f)) };
+            }
+        } else {
+            let (start, end, step) = (start.to_int(), end.to_int(), step.to_int().unsigned_abs() as i64);
+            if step == 0 {
+                return Err(error("ValueError", "range(): Argument #3 ($step) cannot be 0".to_string()));
+            }
+            let mut i = start;
+            while (start <= end && i <= end) || (start > end && i >= end) {
+                unsafe { push(ctx, result, &mut Value::int(i)) };
+                i = match if start <= end { i.checked_add(step) } else { i.checked_sub(step) } {
+                    Some(next) => next,
+                    None => break,
+                };
+            }
+        }
+        ret_array(ctx, result)
+    }
+
+    /// Sort an array in place: by value or by key, optionally descending,
+    /// keeping the keys or renumbering them
+    fn sort_array(name: &str, args: &[*mut Value], by_key: bool, descending: bool, keep_keys: bool, ctx: &mut ExecutionContext) -> BuiltinResult {
+        let array = array_arg(name, args, 0)?;
+        unsafe {
+            let mut all: Vec<_> = entries(array).collect();
+            all.sort_by(|a, b| {
+                let ordering = match by_key {
+                    true => operations::compare(&a.0.to_value(), &b.0.to_value()),
+                    false => operations::compare(&*a.1, &*b.1),
+                };
+                let ordering = ordering.unwrap_or(Ordering::Equal);
+                if descending { ordering.reverse() } else { ordering }
+            });
+            let result = ctx.memory.alloc_array(all.len());
+            for (key, value) in all {
+                match keep_keys {
+                    true => ctx.memory.array_set(result, key, value),
+                    false => ctx.memory.array_push(result, value),
+                }
+            }
+            *deref(args[0]) = Value::array(result);
+        }
+        ret(ctx, Value::bool(true))
+    }
+
+    pub fn sort(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        sort_array("sort", args, false, false, false, ctx)
+    }
+
+    pub fn rsort(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        sort_array("rsort", args, false, true, false, ctx)
+    }
+
+    pub fn asort(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        sort_array("asort", args, false, false, true, ctx)
+    }
+
+    pub fn arsort(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        sort_array("arsort", args, false, true, true, ctx)
+    }
+
+    pub fn ksort(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        sort_array("ksort", args, true, false, true, ctx)
+    }
+
+    pub fn krsort(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        sort_array("krsort", args, true, true, true, ctx)
+    }
+
+    // Math functions
+
+    pub fn abs(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("abs", args, 1)?;
+        match arg(args, 0).to_number() {
+            Some((Number::Int(i), _)) => match i.checked_abs() {
+                Some(i) => ret(ctx, Value::int(i)),
+                None => ret(ctx, Value::float(-(i as f64))),
+            },
+            Some((Number::Float(f), _)) => ret(ctx, Value::float(f.abs())),
+            None => Err(error("TypeError", format!("abs(): Argument #1 ($num) must be of type int|float, {} given", operations::type_name(arg(args, 0).get_type())))),
+        }
+    }
+
+    /// The arguments of `min()`/`max()`: one array or several values
+    fn candidates(name: &str, args: &[*mut Value]) -> Result<Vec<*mut Value>, PhpError> {
+        expect(name, args, 1)?;
+        let values: Vec<*mut Value> = match args.len() {
+            1 => unsafe { entries(array_arg(name, args, 0)?).map(|(_, value)| value).collect() },
+            _ => args.to_vec(),
+        };
+        if values.is_empty() {
+            return Err(error("ValueError", format!("{}(): Argument #1 ($value) must contain at least one element", name)));
+        }
+        Ok(values)
+    }
+
+    pub fn min(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let values = candidates("min", args)?;
+        let min = values.into_iter()
+            .reduce(|a, b| unsafe { if operations::compare(&*b, &*a) == Some(Ordering::Less) { b } else { a } })
+            .unwrap();
+        let min = unsafe { ctx.memory.copy_value(min) };
+        ret(ctx, min)
+    }
+
+    pub fn max(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        let values = candidates("max", args)?;
+        let max = values.into_iter()
+            .reduce(|a, b| unsafe { if operations::compare(&*b, &*a) == Some(Ordering::Greater) { b } else { a } })
+            .unwrap();
+        let max = unsafe { ctx.memory.copy_value(max) };
+        ret(ctx, max)
+    }
+
+    /// Round half away from zero to `precision` decimals
+    fn round_to(f: f64, precision: i64) -> f64 {
+        let factor = 10f64.powi(precision.clamp(-308, 308) as i32);
+        let scaled = f * factor;
+        if !scaled.is_finite() {
+            return f;
+        }
+        scaled.round() / factor
+    }
+
+    pub fn round(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("round", args, 1)?;
+        let rounded = round_to(arg(args, 0).to_float(), int_arg(args, 1));
+        ret(ctx, Value::float(rounded))
+    }
+
+    pub fn floor(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("floor", args, 1)?;
+        ret(ctx, Value::float(arg(args, 0).to_float().floor()))
+    }
+
+    pub fn ceil(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("ceil", args, 1)?;
+        ret(ctx, Value::float(arg(args, 0).to_float().ceil()))
+    }
+
+    pub fn sqrt(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("sqrt", args, 1)?;
+        ret(ctx, Value::float(arg(args, 0).to_float().sqrt()))
+    }
+
+    pub fn pow(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("pow", args, 2)?;
+        let (base, exponent) = (arg(args, 0).to_number(), arg(args, 1).to_number());
+        match (base, exponent) {
+            (Some((Number::Int(base), _)), Some((Number::Int(exponent), _))) if exponent >= 0 => {
+                match u32::try_from(exponent).ok().and_then(|exponent| base.checked_pow(exponent)) {
+                    Some(result) => ret(ctx, Value::int(result)),
+                    None => ret(ctx, Value::float((base as f64).powf(exponent as f64))),
+                }
+            }
+            (base, exponent) => {
+                let (base, exponent) = (base.map_or(0.0, |(n, _)| n.to_float()), exponent.map_or(0.0, |(n, _)| n.to_float()));
+                ret(ctx, Value::float(base.powf(exponent)))
+            }
+        }
+    }
+
+    pub fn intdiv(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("intdiv", args, 2)?;
+        let (a, b) = (int_arg(args, 0), int_arg(args, 1));
+        if b == 0 {
+            return Err(error("DivisionByZeroError", "Division by zero".to_string()));
+        }
+        match a.checked_div(b) {
+            Some(result) => ret(ctx, Value::int(result)),
+            None => Err(error("ArithmeticError", "Division of PHP_INT_MIN by -1 is not an integer".to_string())),
+        }
+    }
+
+    pub fn fmod(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("fmod", args, 2)?;
+        ret(ctx, Value::float(arg(args, 0).to_float() % arg(args, 1).to_float()))
+    }
+
+    // Type functions
+
+    pub fn gettype(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("gettype", args, 1)?;
+        let name = match arg(args, 0).get_type() {
+            ValueType::Null => "NULL",
+            ValueType::Bool => "boolean",
+            ValueType::Int => "integer",
+            ValueType::Float => "double",
+            ValueType::String => "string",
+            ValueType::Array => "array",
+            ValueType::Object => "object",
+            ValueType::Resource => "resource",
+            ValueType::Reference => "reference",
+        };
+        ret_string(ctx, name)
+    }
+
+    fn is_type(ctx: &mut ExecutionContext, args: &[*mut Value], name: &str, ty: ValueType) -> BuiltinResult {
+        expect(name, args, 1)?;
+        let matches = arg(args, 0).get_type() == ty;
+        ret(ctx, Value::bool(matches))
+    }
+
+    pub fn is_null(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        is_type(ctx, args, "is_null", ValueType::Null)
+    }
+
+    pub fn is_bool(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        is_type(ctx, args, "is_bool", ValueType::Bool)
+    }
+
+    pub fn is_int(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        is_type(ctx, args, "is_int", ValueType::Int)
+    }
+
+    pub fn is_float(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        is_type(ctx, args, "is_float", ValueType::Float)
+    }
+
+    pub fn is_string(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        is_type(ctx, args, "is_string", ValueType::String)
+    }
+
+    pub fn is_array(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        is_type(ctx, args, "is_array", ValueType::Array)
+    }
+
+    pub fn is_object(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        is_type(ctx, args, "is_object", ValueType::Object)
+    }
+
+    pub fn is_numeric(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("is_numeric", args, 1)?;
+        let value = arg(args, 0);
+        let numeric = match value.get_type() {
+            ValueType::Int | ValueType::Float => true,
+            ValueType::String => matches!(value.as_str().and_then(parse_numeric), Some((_, true))),
+            _ => false,
+        };
+        ret(ctx, Value::bool(numeric))
+    }
+
+    pub fn intval(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("intval", args, 1)?;
+        ret(ctx, Value::int(int_arg(args, 0)))
+    }
+
+    pub fn floatval(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("floatval", args, 1)?;
+        ret(ctx, Value::float(arg(args, 0).to_float()))
+    }
+
+    pub fn strval(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("strval", args, 1)?;
+        ret_string(ctx, &string_arg(args, 0))
+    }
+
+    pub fn boolval(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("boolval", args, 1)?;
+        ret(ctx, Value::bool(arg(args, 0).to_bool()))
+    }
+
+    // Output functions
+
+    pub fn echo(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        for i in 0..args.len() {
+            let s = string_arg(args, i);
+            ctx.output(s.as_bytes());
+        }
+        ret(ctx, Value::null())
+    }
+
+    pub fn print(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("print", args, 1)?;
+        let s = string_arg(args, 0);
+        ctx.output(s.as_bytes());
+        ret(ctx, Value::int(1))
+    }
+
+    pub fn var_dump(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("var_dump", args, 1)?;
+        let mut out = String::new();
+        for &value in args {
+            unsafe { dump(ctx, deref(value), 0, &mut out) };
+        }
+        ctx.output(out.as_bytes());
+        ret(ctx, Value::null())
+    }
+
+    pub fn print_r(ctx: &mut ExecutionContext, args: &[*mut Value]) -> Result<*mut Value, PhpError> {
+        expect("print_r", args, 1)?;
+        let mut out = String::new();
+        unsafe { print_value(ctx, deref(args[0]), 0, &mut Vec::new(), &mut out) };
+        match arg(args, 1).to_bool() {
+            true => ret_string(ctx, &out),
+            false => {
+                ctx.output(out.as_bytes());
+                ret(ctx, Value::bool(true))
+            }
+        }
+    }
+
+    /// A float as `var_dump` shows it: as many digits as it takes to read it
+    /// back exactly
+    fn float_repr(f: f64) -> String {
+        if !f.is_finite() || f == 0.0 {
+            return format_float(f);
+        }
+        let scientific = format!("{:e}", f.abs());
+        let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+        let exponent: i32 = exponent.parse().unwrap_or(0);
+        let digits = mantissa.replace('.', "");
+        let sign = if f < 0.0 { "-" } else { "" };
+        if !(-5..15).contains(&exponent) {
+            let mantissa = if mantissa.contains('.') { mantissa.to_string() } else { format!("{}.0", mantissa) };
+            return format!("{}{}E{}{}", sign, mantissa, if exponent < 0 { '-' } else { '+' }, exponent.abs());
+        }
+        let fixed = if exponent < 0 {
+            format!("0.{}{}", "0".repeat((-exponent - 1) as usize), digits)
+        } else {
+            let whole = exponent as usize + 1;
+            if digits.len() <= whole {
+                format!("{}{}", digits, "0".repeat(whole - digits.len()))
+            } else {
+                format!("{}.{}", &digits[..whole], &digits[whole..])
+            }
+        };
+        format!("{}{}", sign, fixed)
+    }
+
+    /// The class an object belongs to
+    fn class_name(ctx: &ExecutionContext, object: *mut PhpObject) -> String {
+        let id = unsafe { (*object).class_id as usize };
+        ctx.class_names.get(id).cloned().unwrap_or_else(|| "stdClass".to_string())
+    }
+
+    /// Where a property is declared and how visible it is, looking up the
+    /// class's parents
+    fn property_visibility(ctx: &ExecutionContext, class: &str, property: &str) -> (Visibility, String) {
+        let mut class = Some(class.to_string());
+        while let Some(name) = class {
+            let Some(def) = ctx.classes.get(&name) else { break };
+            if let Some(property) = def.properties.get(property) {
+                return (property.visibility, name);
+            }
+            class = def.parent.clone();
+        }
+        (Visibility::Public, String::new())
+    }
+
+    /// An object's initialized properties with their names
+    unsafe fn properties(ctx: &ExecutionContext, object: *mut PhpObject) -> Vec<(String, *mut Value)> {
+        (0..(*object).properties_count as usize)
+            .map(|i| &*(*object).properties.add(i))
+            .filter(|property| !property.value.is_null())
+            .map(|property| (ctx.property_names.get(property.name_id as usize).cloned().unwrap_or_default(), property.value))
+            .collect()
+    }
+
+    unsafe fn dump(ctx: &ExecutionContext, value: *mut Value, indent: usize, out: &mut String) {
+        let pad = " ".repeat(indent);
+        let value = deref(value);
+        match (*value).get_type() {
+            ValueType::Null => out.push_str(&format!("{}NULL\n", pad)),
+            ValueType::Bool => out.push_str(&format!("{}bool({})\n", pad, (*value).to_bool())),
+            ValueType::Int => out.push_str(&format!("{}int({})\n", pad, (*value).to_int())),
+            ValueType::Float => out.push_str(&format!("{}float({})\n", pad, float_repr((*value).to_float()))),
+            ValueType::String => {
+                let s = (*value).to_php_string();
+                out.push_str(&format!("{}string({}) \"{}\"\n", pad, s.len(), s));
+            }
+            ValueType::Array => {
+                let array = (*value).data.ptr as *mut PhpArray;
+                out.push_str(&format!("{}array({}) {{\n", pad, (*array).size));
+                for (key, value) in entries(array) {
+                    match key {
+                        ArrayKey::Integer(i) => out.push_str(&format!("{}  [{}]=>\n", pad, i)),
+                        ArrayKey::String(_) => out.push_str(&format!("{}  [\"{}\"]=>\n", pad, key_string(&key))),
+                    }
+                    dump(ctx, value, indent + 2, out);
+                }
+                out.push_str(&format!("{}}}\n", pad));
+            }
+            ValueType::Object => {
+                let object = (*value).data.ptr as *mut PhpObject;
+                let class = class_name(ctx, object);
+                let properties = properties(ctx, object);
+                out.push_str(&format!("{}object({})#{} ({}) {{\n", pad, class, (*object).handle, properties.len()));
+                for (name, value) in properties {
+                    let label = match property_visibility(ctx, &class, &name) {
+                        (Visibility::Public, _) => format!("\"{}\"", name),
+                        (Visibility::Protected, _) => format!("\"{}\":protected", name),
+                        (Visibility::Private, declaring) => format!("\"{}\":\"{}\":private", name, declaring),
+                    };
+                    out.push_str(&format!("{}  [{}]=>\n", pad, label));
+                    dump(ctx, value, indent + 2, out);
+                }
+                out.push_str(&format!("{}}}\n", pad));
+            }
+            _ => out.push_str(&format!("{}{}\n", pad, (*value).to_php_string())),
+        }
+    }
+
+    unsafe fn print_value(ctx: &ExecutionContext, value: *mut Value, indent: usize, objects: &mut Vec<*mut PhpObject>, out: &mut String) {
+        let value = deref(value);
+        let (title, items) = match (*value).get_type() {
+            ValueType::Array => {
+                let array = (*value).data.ptr as *mut PhpArray;
+                let items: Vec<_> = entries(array).map(|(key, value)| (key_string(&key), value)).collect();
+                ("Array".to_string(), items)
+            }
+            ValueType::Object => {
+                let object = (*value).data.ptr as *mut PhpObject;
+                let class = class_name(ctx, object);
+                if objects.contains(&object) {
+                    out.push_str(&format!("{} Object\n *RECURSION*", class));
+                    return;
+                }
+                objects.push(object);
+                let items = properties(ctx, object).into_iter().map(|(name, value)| {
+                    let label = match property_visibility(ctx, &class, &name) {
+                        (Visibility::Public, _) => name,
+                        (Visibility::Protected, _) => format!("{}:protected", name),
+                        (Visibility::Private, declaring) => format!("{}:{}:private", name, declaring),
+                    };
+                    (label, value)
+                }).collect();
+                (format!("{} Object", class), items)
+            }
+            _ => {
+                out.push_str(&(*value).to_php_string());
+                return;
+            }
+        };
+
+        let pad = " ".repeat(indent);
+        out.push_str(&format!("{}\n{}(\n", title, pad));
+        for (label, item) in items {
+            out.push_str(&format!("{}    [{}] => ", pad, label));
+            print_value(ctx, item, indent + 8, objects, out);
+            out.push('\n');
+        }
+        out.push_str(&format!("{})\n", pad));
+        if (*value).get_type() == ValueType::Object {
+            objects.pop();
+        }
     }
 }
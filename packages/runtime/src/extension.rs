@@ -1,14 +1,49 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-/// Extension loading and management for Edge PHP
-/// 
-/// Supports loading PHP extensions as WASM modules
+//! Extension loading and management for Edge PHP
+//! 
+//! Supports loading PHP extensions as WASM modules
+//!
+//! A Wasm extension describes itself in a custom section named
+//! `edge-php.extension` holding a JSON manifest:
+//!
+//! ```json
+//! {
+//!   "name": "strings",
+//!   "version": "1.0.0",
+//!   "php_version": ">=8.1",
+//!   "dependencies": ["base"],
+//!   "functions": [{ "name": "str_rev", "params": ["string"], "returns": "string" }],
+//!   "classes": [{ "name": "Slug", "methods": [{ "name": "make", "params": ["string"], "returns": "string" }] }],
+//!   "constants": { "STRINGS_MAX": 64 }
+//! }
+//! ```
+//!
+//! Every function is an export named like the function (`Class::method` for
+//! methods) unless `export` names another one. Parameters and results are
+//! `int` (i64), `float` (f64), `bool` (i32) or `string`: a pointer and a
+//! length into the extension's memory, for results too. Extensions passing
+//! strings export their `memory` and `edge_php_alloc(len) -> ptr`, which the
+//! runtime calls to make room for string arguments. An exported
+//! `_initialize` runs once the extension is instantiated, and an extension
+//! may import the exports of its dependencies from a module named after them.
+//!
+//! Compiled PHP calls extension functions and static methods through imports
+//! (see `Runtime::link_extensions`); the constants become literals.
 
 use crate::context::{ExecutionContext, ClassDef, BuiltinFunction, PhpError, ErrorLevel};
 use crate::value::Value;
+use edge_php_compiler::{ConstantValue, ImportType};
+use serde::Deserialize;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// The PHP version compiled code behaves like, checked against `php_version_req`
+pub const PHP_VERSION: &str = "8.3.0";
+
+/// The custom section holding a Wasm extension's manifest
+pub const MANIFEST_SECTION: &str = "edge-php.extension";
 
 /// Extension module definition
 pub struct Extension {
@@ -35,6 +70,183 @@ pub struct Extension {
     
     /// Configuration values
     pub config: HashMap<String, ConfigValue>,
+
+    /// The module and manifest of a Wasm extension
+    pub wasm: Option<WasmExtension>,
+}
+
+/// A Wasm extension as loaded from disk
+#[derive(Debug, Clone)]
+pub struct WasmExtension {
+    pub path: PathBuf,
+    pub bytes: Vec<u8>,
+    pub manifest: ExtensionManifest,
+}
+
+/// What a Wasm extension provides, from its `edge-php.extension` section
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtensionManifest {
+    pub name: String,
+    pub version: String,
+    pub php_version: String,
+    pub dependencies: Vec<String>,
+    pub functions: Vec<ExtensionFunction>,
+    pub classes: Vec<ExtensionClass>,
+    pub constants: Vec<(String, ConstantValue)>,
+}
+
+/// A function or static method and the export implementing it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtensionFunction {
+    pub name: String,
+    pub export: String,
+    pub params: Vec<ImportType>,
+    pub returns: Option<ImportType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtensionClass {
+    pub name: String,
+    /// Static methods; `name` is the method name
+    pub methods: Vec<ExtensionFunction>,
+}
+
+/// The manifest as written, before its types are checked
+#[derive(Deserialize)]
+struct RawManifest {
+    name: String,
+    version: String,
+    #[serde(default)]
+    php_version: String,
+    #[serde(default)]
+    dependencies: Vec<String>,
+    #[serde(default)]
+    functions: Vec<RawFunction>,
+    #[serde(default)]
+    classes: Vec<RawClass>,
+    #[serde(default)]
+    constants: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct RawFunction {
+    name: String,
+    export: Option<String>,
+    #[serde(default)]
+    params: Vec<String>,
+    returns: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawClass {
+    name: String,
+    #[serde(default)]
+    methods: Vec<RawFunction>,
+}
+
+impl ExtensionManifest {
+    /// Read the manifest from a Wasm module's custom section
+    pub fn from_wasm(bytes: &[u8]) -> Result<Self, ExtensionError> {
+        for payload in wasmparser::Parser::new(0).parse_all(bytes) {
+            let payload = payload.map_err(|e| ExtensionError::invalid(format!("Invalid Wasm module: {}", e)))?;
+            if let wasmparser::Payload::CustomSection(section) = payload {
+                if section.name() == MANIFEST_SECTION {
+                    let json = std::str::from_utf8(section.data())
+                        .map_err(|_| ExtensionError::invalid("The extension manifest is not UTF-8"))?;
+                    return Self::parse(json);
+                }
+            }
+        }
+        Err(ExtensionError::invalid(format!("The module has no {} section", MANIFEST_SECTION)))
+    }
+
+    pub fn parse(json: &str) -> Result<Self, ExtensionError> {
+        let raw: RawManifest = serde_json::from_str(json)
+            .map_err(|e| ExtensionError::invalid(format!("Invalid extension manifest: {}", e)))?;
+        let function = |raw: RawFunction, prefix: &str| -> Result<ExtensionFunction, ExtensionError> {
+            let name = format!("{}{}", prefix, raw.name);
+            let parse = |ty: &str| ty.parse::<ImportType>()
+                .map_err(|e| ExtensionError::invalid(format!("{}() in the extension manifest: {}", name, e)));
+            Ok(ExtensionFunction {
+                export: raw.export.unwrap_or_else(|| name.clone()),
+                params: raw.params.iter().map(|ty| parse(ty)).collect::<Result<_, _>>()?,
+                returns: raw.returns.as_deref().map(parse).transpose()?,
+                name,
+            })
+        };
+
+        let mut classes = Vec::new();
+        for class in raw.classes {
+            let prefix = format!("{}::", class.name);
+            let methods = class.methods.into_iter()
+                .map(|method| function(method, &prefix))
+                .collect::<Result<_, _>>()?;
+            classes.push(ExtensionClass { name: class.name, methods });
+        }
+        let constants = raw.constants.into_iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    serde_json::Value::Null => ConstantValue::Null,
+                    serde_json::Value::Bool(b) => ConstantValue::Bool(b),
+                    serde_json::Value::Number(n) => match n.as_i64() {
+                        Some(i) => ConstantValue::Int(i),
+                        None => ConstantValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+                    },
+                    serde_json::Value::String(s) => ConstantValue::String(s),
+                    _ => return Err(ExtensionError::invalid(format!("The constant {} is not a scalar", name))),
+                };
+                Ok((name, value))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(ExtensionManifest {
+            name: raw.name,
+            version: raw.version,
+            php_version: raw.php_version,
+            dependencies: raw.dependencies,
+            functions: raw.functions.into_iter().map(|raw| function(raw, "")).collect::<Result<_, _>>()?,
+            classes,
+            constants,
+        })
+    }
+
+    /// The functions and static methods PHP code can call
+    pub fn callables(&self) -> impl Iterator<Item = &ExtensionFunction> {
+        self.functions.iter().chain(self.classes.iter().flat_map(|class| &class.methods))
+    }
+}
+
+/// Whether `version` meets a requirement like `8.1`, `>=8.1` or `>=8.0, <9`.
+/// A bare version is a minimum.
+pub fn version_satisfies(version: &str, requirement: &str) -> bool {
+    fn parts(version: &str) -> Vec<u64> {
+        version.trim().split('.').map(|part| part.parse().unwrap_or(0)).collect()
+    }
+    fn compare(a: &[u64], b: &[u64]) -> std::cmp::Ordering {
+        let len = a.len().max(b.len());
+        let get = |v: &[u64], i| v.get(i).copied().unwrap_or(0);
+        (0..len).map(|i| get(a, i).cmp(&get(b, i)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    }
+
+    let version = parts(version);
+    requirement.split(',').map(str::trim).filter(|c| !c.is_empty()).all(|constraint| {
+        let (op, required) = ["^", ">=", "<=", "==", ">", "<", "="].iter()
+            .find_map(|op| constraint.strip_prefix(op).map(|rest| (*op, rest)))
+            .unwrap_or((">=", constraint));
+        let required = parts(required);
+        let ordering = compare(&version, &required);
+        match op {
+            ">" => ordering.is_gt(),
+            "<" => ordering.is_lt(),
+            "<=" => ordering.is_le(),
+            "=" | "==" => ordering.is_eq(),
+            // Same major version, at least this one
+            "^" => ordering.is_ge() && version.first() == required.first(),
+            _ => ordering.is_ge(),
+        }
+    })
 }
 
 /// Extension initialization function type
@@ -84,8 +296,24 @@ pub enum ExtensionErrorCode {
     VersionMismatch,
     ConfigError,
     RuntimeError,
+    /// Not a Wasm module, or no valid manifest
+    InvalidModule,
+}
+
+impl ExtensionError {
+    fn invalid(message: impl Into<String>) -> Self {
+        ExtensionError { message: message.into(), code: ExtensionErrorCode::InvalidModule }
+    }
 }
 
+impl std::fmt::Display for ExtensionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ExtensionError {}
+
 /// Extension manager handles loading and lifecycle
 pub struct ExtensionManager {
     /// Loaded extensions
@@ -94,21 +322,27 @@ pub struct ExtensionManager {
     /// Extension load order (for dependencies)
     load_order: Vec<String>,
     
-    /// Extension search paths
-    _search_paths: Vec<String>,
+    /// Where extensions are looked for by name (`<path>/<name>.wasm`)
+    search_paths: Vec<PathBuf>,
     
     /// Global extension configuration
     global_config: HashMap<String, HashMap<String, ConfigValue>>,
 }
 
+impl Default for ExtensionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ExtensionManager {
     pub fn new() -> Self {
         ExtensionManager {
             extensions: HashMap::new(),
             load_order: Vec::new(),
-            _search_paths: vec![
-                "/usr/lib/edge-php/extensions".to_string(),
-                "./extensions".to_string(),
+            search_paths: vec![
+                PathBuf::from("/usr/lib/edge-php/extensions"),
+                PathBuf::from("./extensions"),
             ],
             global_config: HashMap::new(),
         }
@@ -117,6 +351,13 @@ impl ExtensionManager {
     /// Register a built-in extension
     pub fn register_builtin(&mut self, ext: Extension) -> Result<(), ExtensionError> {
         let name = ext.name.clone();
+
+        if !version_satisfies(PHP_VERSION, &ext.php_version_req) {
+            return Err(ExtensionError {
+                message: format!("Extension '{}' requires PHP {}, this is {}", name, ext.php_version_req, PHP_VERSION),
+                code: ExtensionErrorCode::VersionMismatch,
+            });
+        }
         
         // Check dependencies
         for dep in &ext.dependencies {
@@ -133,20 +374,97 @@ impl ExtensionManager {
         Ok(())
     }
     
-    /// Load an extension from a WASM module
-    pub fn load_wasm_extension(&mut self, _path: &Path) -> Result<(), ExtensionError> {
-        // TODO: Implement WASM extension loading
-        // This would:
-        // 1. Load the WASM module
-        // 2. Validate it has the required exports
-        // 3. Create Extension instance
-        // 4. Register with manager
-        
-        Err(ExtensionError {
-            message: "WASM extension loading not yet implemented".to_string(),
+    /// Search `path` too, before the default paths
+    pub fn add_search_path(&mut self, path: impl Into<PathBuf>) {
+        self.search_paths.insert(0, path.into());
+    }
+
+    pub fn search_paths(&self) -> &[PathBuf] {
+        &self.search_paths
+    }
+
+    /// The first `<name>.wasm` on the search paths
+    pub fn find_extension(&self, name: &str) -> Option<PathBuf> {
+        self.search_paths.iter()
+            .map(|dir| dir.join(format!("{}.wasm", name)))
+            .find(|path| path.is_file())
+    }
+
+    /// Load an extension from the search paths by name
+    pub fn load_extension(&mut self, name: &str) -> Result<(), ExtensionError> {
+        let path = self.find_extension(name).ok_or_else(|| ExtensionError {
+            message: format!("Extension '{}' was not found in {:?}", name, self.search_paths),
+            code: ExtensionErrorCode::DependencyMissing,
+        })?;
+        self.load_wasm_extension(&path)
+    }
+
+    /// Load every `.wasm` file on the search paths, returning the names of
+    /// the extensions loaded. Missing directories are skipped.
+    pub fn discover(&mut self) -> Result<Vec<String>, ExtensionError> {
+        let mut paths = Vec::new();
+        for dir in &self.search_paths {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            let mut found: Vec<PathBuf> = entries.filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+                .collect();
+            found.sort();
+            paths.extend(found);
+        }
+
+        let before = self.load_order.len();
+        for path in paths {
+            let loaded = self.wasm_extensions().any(|ext| ext.path == path);
+            if !loaded {
+                self.load_wasm_extension(&path)?;
+            }
+        }
+        Ok(self.load_order[before..].to_vec())
+    }
+
+    /// Load an extension from a WASM module. Dependencies that are not loaded
+    /// yet are loaded from the search paths first.
+    pub fn load_wasm_extension(&mut self, path: &Path) -> Result<(), ExtensionError> {
+        let bytes = std::fs::read(path).map_err(|e| ExtensionError {
+            message: format!("Cannot read {}: {}", path.display(), e),
             code: ExtensionErrorCode::InitFailed,
+        })?;
+        let manifest = ExtensionManifest::from_wasm(&bytes)?;
+        if self.extensions.contains_key(&manifest.name) {
+            return Err(ExtensionError {
+                message: format!("Extension '{}' is already loaded", manifest.name),
+                code: ExtensionErrorCode::InitFailed,
+            });
+        }
+        for dep in &manifest.dependencies {
+            if !self.extensions.contains_key(dep) {
+                self.load_extension(dep).map_err(|e| ExtensionError {
+                    message: format!("Extension '{}' requires '{}': {}", manifest.name, dep, e.message),
+                    code: ExtensionErrorCode::DependencyMissing,
+                })?;
+            }
+        }
+
+        self.register_builtin(Extension {
+            name: manifest.name.clone(),
+            version: manifest.version.clone(),
+            php_version_req: manifest.php_version.clone(),
+            dependencies: manifest.dependencies.clone(),
+            // What a Wasm extension provides is linked into compiled modules
+            init: |_| Ok(()),
+            shutdown: None,
+            state: ExtensionState::Unloaded,
+            config: HashMap::new(),
+            wasm: Some(WasmExtension { path: path.to_path_buf(), bytes, manifest }),
         })
     }
+
+    /// The Wasm extensions, dependencies first
+    pub fn wasm_extensions(&self) -> impl Iterator<Item = &WasmExtension> {
+        self.load_order.iter().filter_map(|name| self.extensions.get(name)?.wasm.as_ref())
+    }
     
     /// Initialize all loaded extensions
     pub fn initialize_all(&mut self, ctx: &mut ExecutionContext) -> Result<(), ExtensionError> {
@@ -253,6 +571,7 @@ macro_rules! define_extension {
             shutdown: $shutdown,
             state: ExtensionState::Unloaded,
             config: HashMap::new(),
+            wasm: None,
         }
     };
 }
@@ -271,6 +590,7 @@ impl<'a> ExtensionContext<'a> {
     
     /// Register a constant
     pub fn register_constant(&mut self, name: &str, value: *mut Value) {
+        self.ctx.memory.add_root(value);
         self.ctx.constants.insert(name.to_string(), value);
     }
     
@@ -326,6 +646,7 @@ pub mod builtin {
             if args.is_empty() {
                 return Err(PhpError {
                     level: ErrorLevel::Warning,
+                    class: "Error",
                     message: "json_encode() expects at least 1 parameter, 0 given".to_string(),
                     file: None,
                     line: None,
@@ -345,6 +666,7 @@ pub mod builtin {
             if args.is_empty() {
                 return Err(PhpError {
                     level: ErrorLevel::Warning,
+                    class: "Error",
                     message: "json_decode() expects at least 1 parameter, 0 given".to_string(),
                     file: None,
                     line: None,
@@ -395,6 +717,7 @@ pub mod builtin {
             if args.is_empty() {
                 return Err(PhpError {
                     level: ErrorLevel::Warning,
+                    class: "Error",
                     message: "abs() expects exactly 1 parameter, 0 given".to_string(),
                     file: None,
                     line: None,
@@ -417,6 +740,7 @@ pub mod builtin {
             if args.is_empty() {
                 return Err(PhpError {
                     level: ErrorLevel::Warning,
+                    class: "Error",
                     message: "sqrt() expects exactly 1 parameter, 0 given".to_string(),
                     file: None,
                     line: None,
@@ -456,10 +780,166 @@ mod tests {
         let json_ext = builtin::json_extension();
         
         assert!(manager.register_builtin(json_ext).is_ok());
-        assert!(manager.is_loaded("json") == false); // Not initialized yet
+        assert!(!manager.is_loaded("json")); // Not initialized yet
         
         let mut ctx = ExecutionContext::new();
         assert!(manager.initialize_all(&mut ctx).is_ok());
         assert!(manager.is_loaded("json"));
     }
+
+    #[test]
+    fn test_version_requirements() {
+        assert!(version_satisfies("8.3.0", "8.0"));
+        assert!(version_satisfies("8.3.0", ">=8.1, <9"));
+        assert!(version_satisfies("8.3.0", "^8.2"));
+        assert!(version_satisfies("8.3.0", ""));
+        assert!(!version_satisfies("8.3.0", ">=9"));
+        assert!(!version_satisfies("8.3.0", "^7.4"));
+        assert!(!version_satisfies("8.3.0", "<8.3"));
+    }
+
+    /// An extension uppercasing strings in place, with `double(int)`,
+    /// `is_even(int)` and `Text::upper(string)`
+    fn test_extension(manifest: &str) -> Vec<u8> {
+        use wasm_encoder::*;
+
+        let mut module = Module::new();
+        let mut types = TypeSection::new();
+        types.ty().function([ValType::I32], [ValType::I32]);
+        types.ty().function([ValType::I64], [ValType::I64]);
+        types.ty().function([ValType::I64], [ValType::I32]);
+        types.ty().function([ValType::I32, ValType::I32], [ValType::I32, ValType::I32]);
+        module.section(&types);
+        let mut functions = FunctionSection::new();
+        for ty in 0..4 {
+            functions.function(ty);
+        }
+        module.section(&functions);
+        let mut memories = MemorySection::new();
+        memories.memory(MemoryType { minimum: 1, maximum: None, memory64: false, shared: false, page_size_log2: None });
+        module.section(&memories);
+        let mut globals = GlobalSection::new();
+        globals.global(GlobalType { val_type: ValType::I32, mutable: true, shared: false }, &ConstExpr::i32_const(1024));
+        module.section(&globals);
+        let mut exports = ExportSection::new();
+        exports.export("memory", ExportKind::Memory, 0);
+        exports.export("edge_php_alloc", ExportKind::Func, 0);
+        exports.export("double", ExportKind::Func, 1);
+        exports.export("is_even", ExportKind::Func, 2);
+        exports.export("upper", ExportKind::Func, 3);
+        module.section(&exports);
+
+        let mut code = CodeSection::new();
+        let mut alloc = Function::new([]);
+        alloc.instructions()
+            .global_get(0).global_get(0).local_get(0).i32_add().global_set(0)
+            .end();
+        code.function(&alloc);
+        let mut double = Function::new([]);
+        double.instructions().local_get(0).i64_const(2).i64_mul().end();
+        code.function(&double);
+        let mut is_even = Function::new([]);
+        is_even.instructions().local_get(0).i64_const(2).i64_rem_s().i64_eqz().end();
+        code.function(&is_even);
+        // for (i = 0; i < len; i++) ptr[i] -= 32 * (ptr[i] - 'a' < 26)
+        let byte = MemArg { offset: 0, align: 0, memory_index: 0 };
+        let mut upper = Function::new([(2, ValType::I32)]);
+        upper.instructions()
+            .block(BlockType::Empty).loop_(BlockType::Empty)
+            .local_get(2).local_get(1).i32_ge_u().br_if(1)
+            .local_get(0).local_get(2).i32_add()
+            .local_get(0).local_get(2).i32_add().i32_load8_u(byte).local_tee(3)
+            .local_get(3).i32_const(97).i32_sub().i32_const(26).i32_lt_u().i32_const(32).i32_mul()
+            .i32_sub().i32_store8(byte)
+            .local_get(2).i32_const(1).i32_add().local_set(2)
+            .br(0)
+            .end().end()
+            .local_get(0).local_get(1)
+            .end();
+        code.function(&upper);
+        module.section(&code);
+        module.section(&CustomSection { name: MANIFEST_SECTION.into(), data: manifest.as_bytes().into() });
+        module.finish()
+    }
+
+    const TEXT_MANIFEST: &str = r#"{
+        "name": "text",
+        "version": "1.2.0",
+        "php_version": ">=8.1",
+        "functions": [
+            { "name": "double", "params": ["int"], "returns": "int" },
+            { "name": "is_even", "params": ["int"], "returns": "bool" },
+            { "name": "text_upper", "export": "upper", "params": ["string"], "returns": "string" }
+        ],
+        "classes": [{ "name": "Text", "methods": [{ "name": "upper", "params": ["string"], "returns": "string", "export": "upper" }] }],
+        "constants": { "TEXT_LIMIT": 64, "TEXT_NAME": "text" }
+    }"#;
+
+    /// A fresh directory holding the given extensions
+    fn extension_dir(test: &str, extensions: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("edge-php-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, manifest) in extensions {
+            std::fs::write(dir.join(format!("{}.wasm", name)), test_extension(manifest)).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_extension_manifest() {
+        let manifest = ExtensionManifest::from_wasm(&test_extension(TEXT_MANIFEST)).unwrap();
+        assert_eq!(manifest.name, "text");
+        assert_eq!(manifest.functions[2].export, "upper");
+        assert_eq!(manifest.functions[1].returns, Some(ImportType::Bool));
+        assert_eq!(manifest.classes[0].methods[0].name, "Text::upper");
+        assert!(manifest.constants.contains(&("TEXT_LIMIT".to_string(), ConstantValue::Int(64))));
+
+        let invalid = r#"{ "name": "x", "version": "1", "functions": [{ "name": "f", "params": ["array"] }] }"#;
+        assert!(matches!(ExtensionManifest::parse(invalid), Err(ExtensionError { code: ExtensionErrorCode::InvalidModule, .. })));
+        assert!(ExtensionManifest::from_wasm(b"\0asm\x01\0\0\0").is_err());
+    }
+
+    #[test]
+    fn test_wasm_extension_loading() {
+        let dependent = r#"{ "name": "dependent", "version": "1.0", "dependencies": ["text"] }"#;
+        let future = r#"{ "name": "future", "version": "1.0", "php_version": ">=9.0" }"#;
+        let orphan = r#"{ "name": "orphan", "version": "1.0", "dependencies": ["missing"] }"#;
+        let dir = extension_dir("loading", &[("dependent", dependent), ("text", TEXT_MANIFEST), ("future", future), ("orphan", orphan)]);
+
+        let mut manager = ExtensionManager::new();
+        manager.add_search_path(&dir);
+        // The dependency is found on the search path and loaded first
+        manager.load_wasm_extension(&dir.join("dependent.wasm")).unwrap();
+        let names: Vec<_> = manager.wasm_extensions().map(|ext| ext.manifest.name.as_str()).collect();
+        assert_eq!(names, ["text", "dependent"]);
+
+        let error = manager.load_extension("future").unwrap_err();
+        assert!(matches!(error.code, ExtensionErrorCode::VersionMismatch));
+        let error = manager.load_extension("orphan").unwrap_err();
+        assert!(matches!(error.code, ExtensionErrorCode::DependencyMissing));
+        assert!(manager.load_extension("nowhere").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_wasm_extension_calls() {
+        let dir = extension_dir("calls", &[("text", TEXT_MANIFEST)]);
+        let mut manager = ExtensionManager::new();
+        manager.add_search_path(&dir);
+        assert_eq!(manager.discover().unwrap(), ["text"]);
+
+        let mut runtime = crate::Runtime::new().unwrap();
+        runtime.link_extensions(&manager).unwrap();
+        let output = runtime.execute_php(r#"<?php
+            function double($x) { return $x * 3; }
+            echo double(2), " ", is_even(4) ? "even" : "odd", " ";
+            $name = "world";
+            echo text_upper("hello " . $name), " ", Text::upper("ok"), " ";
+            echo TEXT_LIMIT + 1, " ", TEXT_NAME;
+        "#).unwrap();
+        // The script's own double() wins over the extension's
+        assert_eq!(output, "6 even HELLO WORLD OK 65 text");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
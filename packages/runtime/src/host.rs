@@ -0,0 +1,257 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host functions: Rust closures compiled PHP calls like builtins
+//!
+//! `Runtime::define_function` takes a PHP-style signature, such as
+//! `kv_get(string $key): string`, and a closure. Scripts compiled by the
+//! runtime import the function as `env.<name>` and call it wherever they have
+//! no function of that name. The closure gets the arguments converted to the
+//! declared types, and its result is converted to the declared return type
+//! the way PHP would.
+//!
+//! Strings are read from the module's memory and results written back at its
+//! exported `extern_buffer`; the same copying serves extension functions.
+
+use anyhow::Result;
+use edge_php_compiler::{ImportType, ImportedFunction};
+use std::sync::Arc;
+use wasmtime::{Caller, Engine, Extern, FuncType, Memory, Val, ValType};
+
+/// A PHP value passed to or returned from a host function
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl HostValue {
+    pub fn to_int(&self) -> i64 {
+        match self {
+            HostValue::Null => 0,
+            HostValue::Bool(b) => *b as i64,
+            HostValue::Int(i) => *i,
+            HostValue::Float(f) => *f as i64,
+            HostValue::String(s) => {
+                let s = s.trim_start();
+                let end = s.char_indices()
+                    .take_while(|&(i, c)| c.is_ascii_digit() || (i == 0 && (c == '-' || c == '+')))
+                    .last()
+                    .map_or(0, |(i, c)| i + c.len_utf8());
+                s[..end].parse().unwrap_or(0)
+            }
+        }
+    }
+
+    pub fn to_float(&self) -> f64 {
+        match self {
+            HostValue::Float(f) => *f,
+            HostValue::String(s) => s.trim().parse().unwrap_or_else(|_| self.to_int() as f64),
+            _ => self.to_int() as f64,
+        }
+    }
+
+    pub fn to_bool(&self) -> bool {
+        match self {
+            HostValue::Null => false,
+            HostValue::Bool(b) => *b,
+            HostValue::Int(i) => *i != 0,
+            HostValue::Float(f) => *f != 0.0,
+            HostValue::String(s) => !s.is_empty() && s != "0",
+        }
+    }
+
+    /// The value as PHP would print it
+    pub fn to_php_string(&self) -> String {
+        match self {
+            HostValue::Null | HostValue::Bool(false) => String::new(),
+            HostValue::Bool(true) => "1".to_string(),
+            HostValue::Int(i) => i.to_string(),
+            HostValue::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => format!("{}", *f as i64),
+            HostValue::Float(f) => f.to_string(),
+            HostValue::String(s) => s.clone(),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            HostValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl From<()> for HostValue {
+    fn from(_: ()) -> Self {
+        HostValue::Null
+    }
+}
+
+impl From<bool> for HostValue {
+    fn from(value: bool) -> Self {
+        HostValue::Bool(value)
+    }
+}
+
+impl From<i64> for HostValue {
+    fn from(value: i64) -> Self {
+        HostValue::Int(value)
+    }
+}
+
+impl From<f64> for HostValue {
+    fn from(value: f64) -> Self {
+        HostValue::Float(value)
+    }
+}
+
+impl From<&str> for HostValue {
+    fn from(value: &str) -> Self {
+        HostValue::String(value.to_string())
+    }
+}
+
+impl From<String> for HostValue {
+    fn from(value: String) -> Self {
+        HostValue::String(value)
+    }
+}
+
+impl<T: Into<HostValue>> From<Option<T>> for HostValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(HostValue::Null, Into::into)
+    }
+}
+
+/// What implements a host function
+pub type HostCallback = Arc<dyn Fn(&[HostValue]) -> Result<HostValue> + Send + Sync>;
+
+/// A host function and its signature
+#[derive(Clone)]
+pub(crate) struct HostFunction {
+    pub signature: ImportedFunction,
+    pub callback: HostCallback,
+}
+
+/// The wasm signature of a function with these PHP types, a string being a
+/// pointer and a length both ways
+pub(crate) fn wasm_type(engine: &Engine, params: &[ImportType], returns: Option<ImportType>) -> FuncType {
+    let types = |ty: &ImportType| match ty {
+        ImportType::Int => vec![ValType::I64],
+        ImportType::Float => vec![ValType::F64],
+        ImportType::Bool => vec![ValType::I32],
+        ImportType::String => vec![ValType::I32, ValType::I32],
+    };
+    FuncType::new(engine, params.iter().flat_map(types), returns.iter().flat_map(types))
+}
+
+/// The signature compiled PHP imports a function with: a string result only
+/// has its length
+pub(crate) fn import_type(engine: &Engine, params: &[ImportType], returns: Option<ImportType>) -> FuncType {
+    let full = wasm_type(engine, params, returns);
+    let results: Vec<ValType> = match returns {
+        Some(ImportType::String) => vec![ValType::I32],
+        _ => full.results().collect(),
+    };
+    FuncType::new(engine, full.params(), results)
+}
+
+/// The calling module's memory
+pub(crate) fn caller_memory<T>(caller: &mut Caller<'_, T>) -> Result<Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => anyhow::bail!("the module exports no memory"),
+    }
+}
+
+/// The bytes of a string argument in the calling module's memory
+pub(crate) fn read_string<T>(caller: &mut Caller<'_, T>, ptr: i32, len: i32) -> Result<Vec<u8>> {
+    let memory = caller_memory(caller)?;
+    let (ptr, len) = (ptr as u32 as usize, len as u32 as usize);
+    memory.data(&*caller).get(ptr..ptr + len)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| anyhow::anyhow!("a string argument is out of bounds"))
+}
+
+/// Hand a string result to the calling module: it goes to `extern_buffer`,
+/// growing the memory as needed, and the import returns its length
+pub(crate) fn write_string<T>(caller: &mut Caller<'_, T>, bytes: &[u8]) -> Result<Val> {
+    let memory = caller_memory(caller)?;
+    let offset = match caller.get_export("extern_buffer") {
+        Some(Extern::Global(global)) => global.get(&mut *caller).unwrap_i32() as u32 as usize,
+        _ => anyhow::bail!("the module exports no extern_buffer"),
+    };
+    let needed = offset + bytes.len();
+    if needed > memory.data_size(&*caller) {
+        let pages = (needed - memory.data_size(&*caller)).div_ceil(1 << 16);
+        memory.grow(&mut *caller, pages as u64)?;
+    }
+    memory.write(&mut *caller, offset, bytes)?;
+    Ok(Val::I32(bytes.len() as i32))
+}
+
+impl HostFunction {
+    /// Call the closure with the import's arguments and store its result
+    pub(crate) fn call<T>(&self, caller: &mut Caller<'_, T>, params: &[Val], results: &mut [Val]) -> Result<()> {
+        let mut args = Vec::with_capacity(self.signature.params.len());
+        let mut params = params.iter();
+        for ty in &self.signature.params {
+            let Some(param) = params.next() else { break };
+            args.push(match ty {
+                ImportType::Int => HostValue::Int(param.unwrap_i64()),
+                ImportType::Float => HostValue::Float(param.unwrap_f64()),
+                ImportType::Bool => HostValue::Bool(param.unwrap_i32() != 0),
+                ImportType::String => {
+                    let len = params.next().map_or(0, Val::unwrap_i32);
+                    let bytes = read_string(caller, param.unwrap_i32(), len)?;
+                    HostValue::String(String::from_utf8_lossy(&bytes).into_owned())
+                }
+            });
+        }
+
+        let value = (self.callback)(&args)?;
+        match self.signature.returns {
+            None => {}
+            Some(ImportType::Int) => results[0] = Val::I64(value.to_int()),
+            Some(ImportType::Float) => results[0] = Val::F64(value.to_float().to_bits()),
+            Some(ImportType::Bool) => results[0] = Val::I32(value.to_bool() as i32),
+            Some(ImportType::String) => results[0] = write_string(caller, value.to_php_string().as_bytes())?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_value_conversions() {
+        assert_eq!(HostValue::from("42abc").to_int(), 42);
+        assert_eq!(HostValue::from(" -7").to_int(), -7);
+        assert_eq!(HostValue::from("1.5").to_float(), 1.5);
+        assert!(!HostValue::from("0").to_bool());
+        assert!(HostValue::from(0.5).to_bool());
+        assert_eq!(HostValue::from(3.0).to_php_string(), "3");
+        assert_eq!(HostValue::from(true).to_php_string(), "1");
+        assert_eq!(HostValue::from(None::<i64>), HostValue::Null);
+    }
+
+    #[test]
+    fn test_host_signatures() {
+        let function = ImportedFunction::host("kv_set(string $key, int $ttl, bool): void").unwrap();
+        assert_eq!(function.module, "env");
+        assert_eq!(function.params, [ImportType::String, ImportType::Int, ImportType::Bool]);
+        assert_eq!(function.returns, None);
+        let function = ImportedFunction::host("function flag(string $name): bool").unwrap();
+        assert_eq!((function.name.as_str(), function.returns), ("flag", Some(ImportType::Bool)));
+
+        assert!(ImportedFunction::host("kv_get").is_err());
+        assert!(ImportedFunction::host("kv_get($key)").is_err());
+        assert!(ImportedFunction::host("kv_get(array $key)").is_err());
+        assert!(ImportedFunction::host("kv_get(string $key) string").is_err());
+    }
+}
@@ -0,0 +1,369 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+//! HTTP requests and responses for modules compiled as request handlers
+//!
+//! A [`Request`] is encoded into the module's memory the way the compiler's
+//! `handle_request` entry point reads it: the superglobals as a list of
+//! entries, the request body for `php://input` and the request time. The
+//! query string, urlencoded form bodies and cookies are parsed here, PHP
+//! style, so `a[b]=1` becomes `$_GET['a']['b']`. What the module returns
+//! becomes a [`Response`].
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Superglobal indices, in the order of the compiler's SUPERGLOBALS
+const SERVER: u8 = 0;
+const GET: u8 = 1;
+const POST: u8 = 2;
+const COOKIE: u8 = 3;
+const FILES: u8 = 4;
+
+/// An uploaded file as it appears in `$_FILES`; the host has already saved it
+/// at `tmp_name`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UploadedFile {
+    pub name: String,
+    pub content_type: String,
+    pub tmp_name: String,
+    pub size: u64,
+    pub error: i64,
+}
+
+/// An HTTP request for [`Runtime::handle_request`](crate::Runtime::handle_request)
+#[derive(Debug, Clone)]
+pub struct Request {
+    method: String,
+    uri: String,
+    protocol: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    server: Vec<(String, String)>,
+    form: Vec<(String, String)>,
+    files: Vec<(String, UploadedFile)>,
+    remote_addr: Option<String>,
+    time: Option<i64>,
+}
+
+impl Request {
+    pub fn new(method: impl Into<String>, uri: impl Into<String>) -> Self {
+        Request {
+            method: method.into(),
+            uri: uri.into(),
+            protocol: "HTTP/1.1".to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            server: Vec::new(),
+            form: Vec::new(),
+            files: Vec::new(),
+            remote_addr: None,
+            time: None,
+        }
+    }
+
+    pub fn protocol(mut self, protocol: impl Into<String>) -> Self {
+        self.protocol = protocol.into();
+        self
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// The body, also parsed into `$_POST` when it is urlencoded
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// An extra `$_SERVER` entry, such as `SCRIPT_NAME` or `HTTPS`
+    pub fn server_var(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.server.push((name.into(), value.into()));
+        self
+    }
+
+    /// A `$_POST` field the host parsed itself, as from a multipart body
+    pub fn form_field(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.form.push((name.into(), value.into()));
+        self
+    }
+
+    /// An entry of `$_FILES`
+    pub fn file(mut self, field: impl Into<String>, file: UploadedFile) -> Self {
+        self.files.push((field.into(), file));
+        self
+    }
+
+    pub fn remote_addr(mut self, addr: impl Into<String>) -> Self {
+        self.remote_addr = Some(addr.into());
+        self
+    }
+
+    /// `$_SERVER['REQUEST_TIME']` as a Unix timestamp, the current time by default
+    pub fn time(mut self, timestamp: i64) -> Self {
+        self.time = Some(timestamp);
+        self
+    }
+
+    fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The request in the layout `handle_request` reads
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let time = self.time.unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as i64)
+        });
+        let mut entries = Entries::default();
+        let query = self.uri.split_once('?').map_or("", |(_, query)| query);
+
+        entries.server("REQUEST_METHOD", &self.method);
+        entries.server("REQUEST_URI", &self.uri);
+        entries.server("QUERY_STRING", query);
+        entries.server("SERVER_PROTOCOL", &self.protocol);
+        if let Some(addr) = &self.remote_addr {
+            entries.server("REMOTE_ADDR", addr);
+        }
+        for (name, value) in &self.headers {
+            let name = name.to_ascii_uppercase().replace('-', "_");
+            match name.as_str() {
+                "CONTENT_TYPE" | "CONTENT_LENGTH" => entries.server(&name, value),
+                _ => entries.server(&format!("HTTP_{}", name), value),
+            }
+        }
+        for (name, value) in &self.server {
+            entries.server(name, value);
+        }
+
+        for (name, value) in parse_pairs(query, '&') {
+            entries.variable(GET, &name, Value::String(value.as_bytes()));
+        }
+        let urlencoded = self.header_value("Content-Type")
+            .is_some_and(|content_type| content_type.starts_with("application/x-www-form-urlencoded"));
+        if urlencoded {
+            for (name, value) in parse_pairs(&String::from_utf8_lossy(&self.body), '&') {
+                entries.variable(POST, &name, Value::String(value.as_bytes()));
+            }
+        }
+        for (name, value) in &self.form {
+            entries.variable(POST, name, Value::String(value.as_bytes()));
+        }
+        if let Some(cookies) = self.header_value("Cookie") {
+            for (name, value) in parse_pairs(cookies, ';') {
+                entries.variable(COOKIE, name.trim_start(), Value::String(value.as_bytes()));
+            }
+        }
+        for (field, file) in &self.files {
+            let field = field.as_bytes();
+            for (key, value) in [
+                ("name", Value::String(file.name.as_bytes())),
+                ("full_path", Value::String(file.name.as_bytes())),
+                ("type", Value::String(file.content_type.as_bytes())),
+                ("tmp_name", Value::String(file.tmp_name.as_bytes())),
+                ("error", Value::Int(file.error)),
+                ("size", Value::Int(file.size as i64)),
+            ] {
+                entries.push(FILES, Shape::Nested(key.as_bytes()), field, value);
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(16 + self.body.len() + entries.bytes.len());
+        bytes.extend_from_slice(&time.to_le_bytes());
+        bytes.extend_from_slice(&(self.body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.body);
+        bytes.extend_from_slice(&entries.count.to_le_bytes());
+        bytes.extend_from_slice(&entries.bytes);
+        bytes
+    }
+}
+
+enum Shape<'a> {
+    Plain,
+    Nested(&'a [u8]),
+    Append,
+}
+
+enum Value<'a> {
+    String(&'a [u8]),
+    Int(i64),
+}
+
+#[derive(Default)]
+struct Entries {
+    count: u32,
+    bytes: Vec<u8>,
+}
+
+impl Entries {
+    fn server(&mut self, name: &str, value: &str) {
+        self.push(SERVER, Shape::Plain, name.as_bytes(), Value::String(value.as_bytes()));
+    }
+
+    /// A request variable, with PHP's name mangling: `a[b]` and `a[]` nest one
+    /// level, and dots and spaces in the base name become underscores
+    fn variable(&mut self, target: u8, name: &str, value: Value) {
+        let (base, shape) = match name.split_once('[') {
+            Some((base, rest)) if !base.is_empty() && rest.contains(']') => {
+                let sub = &rest[..rest.find(']').unwrap()];
+                (base, if sub.is_empty() { Shape::Append } else { Shape::Nested(sub.as_bytes()) })
+            }
+            _ => (name, Shape::Plain),
+        };
+        if base.is_empty() {
+            return;
+        }
+        let base = base.replace(['.', ' ', '['], "_");
+        self.push(target, shape, base.as_bytes(), value);
+    }
+
+    fn push(&mut self, target: u8, shape: Shape, key: &[u8], value: Value) {
+        self.count += 1;
+        self.bytes.push(target);
+        self.bytes.push(match shape {
+            Shape::Plain => 0,
+            Shape::Nested(_) => 1,
+            Shape::Append => 2,
+        });
+        self.string(key);
+        if let Shape::Nested(sub) = shape {
+            self.string(sub);
+        }
+        match value {
+            Value::String(bytes) => {
+                self.bytes.push(0);
+                self.string(bytes);
+            }
+            Value::Int(int) => {
+                self.bytes.push(1);
+                self.bytes.extend_from_slice(&int.to_le_bytes());
+            }
+        }
+    }
+
+    fn string(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+/// `name=value` pairs, urldecoded, skipping empty names
+fn parse_pairs(input: &str, separator: char) -> Vec<(String, String)> {
+    input.split(separator)
+        .filter(|pair| !pair.is_empty())
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let name = urldecode(name);
+            (!name.trim().is_empty()).then(|| (name, urldecode(value)))
+        })
+        .collect()
+}
+
+/// `+` is a space and `%XX` a byte, as in PHP's urldecode()
+pub fn urldecode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// What a request handler sent back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// The first header of that name, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every header of that name, such as each Set-Cookie
+    pub fn headers_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers.iter()
+            .filter(move |(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Read the response `handle_request` wrote at `ptr`
+    pub(crate) fn decode(memory: &[u8], ptr: usize) -> Option<Response> {
+        let mut reader = Reader { memory, position: ptr };
+        let status = reader.u32()? as u16;
+        let header_count = reader.u32()?;
+        let mut headers = Vec::with_capacity(header_count as usize);
+        for _ in 0..header_count {
+            let line = String::from_utf8_lossy(reader.bytes()?).into_owned();
+            let (name, value) = line.split_once(':').unwrap_or((&line, ""));
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        let body = reader.bytes()?.to_vec();
+        Some(Response { status, headers, body })
+    }
+}
+
+struct Reader<'a> {
+    memory: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.memory.get(self.position..self.position + 4)?;
+        self.position += 4;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        let bytes = self.memory.get(self.position..self.position + len)?;
+        self.position += len;
+        Some(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urldecode() {
+        assert_eq!(urldecode("a+b%20c%2Fd%zz"), "a b c/d%zz");
+    }
+
+    #[test]
+    fn test_variable_names() {
+        let mut entries = Entries::default();
+        entries.variable(GET, "a.b", Value::String(b"1"));
+        entries.variable(GET, "list[]", Value::String(b"2"));
+        entries.variable(GET, "map[key]", Value::Int(3));
+        assert_eq!(entries.count, 3);
+        let bytes = &entries.bytes;
+        // target, shape, key "a_b", string "1"
+        assert_eq!(&bytes[..15], &[GET, 0, 3, 0, 0, 0, b'a', b'_', b'b', 0, 1, 0, 0, 0, b'1']);
+        assert_eq!(&bytes[15..23], &[GET, 2, 4, 0, 0, 0, b'l', b'i']);
+        // shape 1 carries the sub key, then an int
+        let nested = &bytes[31..];
+        assert_eq!(&nested[..2], &[GET, 1]);
+        assert_eq!(&nested[2..13], &[3, 0, 0, 0, b'm', b'a', b'p', 3, 0, 0, 0]);
+        assert_eq!(&nested[13..17], b"key\x01");
+    }
+}
@@ -0,0 +1,2979 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tree-walking interpreter
+//!
+//! Runs a parsed program directly on an [`ExecutionContext`] instead of
+//! compiling it to WebAssembly. It starts instantly, accepts constructs the
+//! compiler rejects, and is the reference compiled output is checked against.
+
+use crate::context::{ClassDef, ErrorLevel, ExecutionContext, FunctionDef, MethodDef, ParamDef, PhpError, PropertyDef, Visibility};
+use crate::limits::{FatalError, Limits};
+use crate::memory::MemoryManager;
+use crate::operations;
+use crate::value::{ArrayKey, PhpArray, PhpObject, Value, ValueType, parse_numeric};
+use edge_php_parser::ast::{
+    ArrayElement, BinaryOp, Block, ClassMember, Expression, InterpolatedPart, Literal, MagicConstant, Parameter,
+    Program, ProgramItem, Statement, SwitchCase, Type, UnaryOp, UseKind,
+};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Calls deeper than this are a fatal error rather than a stack overflow
+pub const MAX_CALL_DEPTH: usize = 4096;
+
+/// Live bytes after which the first collection runs; later ones run when the
+/// heap has doubled since the last
+const GC_THRESHOLD: usize = 4 << 20;
+
+/// Statements between two looks at the clock
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Functions whose first argument is the caller's variable, changed in place
+const BY_REFERENCE: &[&str] = &[
+    "array_push", "array_pop", "array_shift", "array_unshift",
+    "sort", "rsort", "asort", "arsort", "ksort", "krsort",
+];
+
+/// Runs PHP programs on an [`ExecutionContext`] within the configured [`Limits`]
+pub struct Interpreter {
+    ctx: ExecutionContext,
+    limits: Limits,
+    /// Function and method bodies, by `FunctionDef::body_addr`
+    bodies: Vec<Rc<Body>>,
+    /// Object layouts, by class name
+    layouts: HashMap<String, Layout>,
+    /// Declared class names by their lowercase form
+    class_lookup: HashMap<String, String>,
+    /// `Class::$property` storage, by declaring class and name
+    static_properties: HashMap<(String, String), *mut Value>,
+    /// `static $x` storage, by body and name
+    static_variables: HashMap<(usize, String), *mut Value>,
+    /// `ObjectProperty::name_id` by property name
+    property_ids: HashMap<String, u32>,
+    frames: Vec<Frame>,
+    /// Values in flight between statements, which the collector must not free
+    temps: Vec<Value>,
+    namespace: String,
+    class_aliases: HashMap<String, String>,
+    function_aliases: HashMap<String, String>,
+    included: HashSet<PathBuf>,
+    strict_types: bool,
+    line: Option<u32>,
+    steps: u64,
+    started: Instant,
+    next_gc: usize,
+}
+
+/// A function or method body, which `FunctionDef::body_addr` indexes
+struct Body {
+    name: String,
+    params: Vec<Parameter>,
+    return_type: Option<Type>,
+    block: Block,
+    /// The class declaring a method
+    class: Option<String>,
+}
+
+/// How a class's objects are laid out
+#[derive(Clone, Default)]
+struct Layout {
+    class_id: u32,
+    /// Instance properties in slot order, inherited ones first
+    slots: Vec<String>,
+    readonly: HashSet<String>,
+    is_abstract: bool,
+    is_interface: bool,
+    /// Enum cases in declaration order
+    cases: Vec<String>,
+}
+
+/// A running function
+struct Frame {
+    body: Option<usize>,
+    function: String,
+    /// The class the running method is declared in
+    class: Option<String>,
+    /// The class it was called on, for `static::`
+    static_class: Option<String>,
+    this: Option<*mut PhpObject>,
+}
+
+/// Why evaluation stopped early
+enum Abort {
+    Error(PhpError),
+    Fatal(FatalError),
+    Exit,
+}
+
+impl From<PhpError> for Abort {
+    fn from(error: PhpError) -> Self {
+        Abort::Error(error)
+    }
+}
+
+type Eval<T> = Result<T, Abort>;
+
+/// How a statement ended
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+/// A method found on a class or one of its parents
+struct MethodRef {
+    body: usize,
+    name: String,
+    class: String,
+    visibility: Visibility,
+    is_static: bool,
+    is_abstract: bool,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_limits(Limits::new())
+    }
+
+    pub fn with_limits(limits: Limits) -> Self {
+        let mut interpreter = Interpreter {
+            ctx: ExecutionContext::new(),
+            limits,
+            bodies: Vec::new(),
+            layouts: HashMap::new(),
+            class_lookup: HashMap::new(),
+            static_properties: HashMap::new(),
+            static_variables: HashMap::new(),
+            property_ids: HashMap::new(),
+            frames: vec![Frame { body: None, function: String::new(), class: None, static_class: None, this: None }],
+            temps: Vec::new(),
+            namespace: String::new(),
+            class_aliases: HashMap::new(),
+            function_aliases: HashMap::new(),
+            included: HashSet::new(),
+            strict_types: false,
+            line: None,
+            steps: 0,
+            started: Instant::now(),
+            next_gc: GC_THRESHOLD,
+        };
+        interpreter.define_constants();
+        interpreter
+    }
+
+    fn define_constants(&mut self) {
+        let constants = [
+            ("PHP_INT_MAX", Value::int(i64::MAX)),
+            ("PHP_INT_MIN", Value::int(i64::MIN)),
+            ("PHP_INT_SIZE", Value::int(8)),
+            ("PHP_FLOAT_EPSILON", Value::float(f64::EPSILON)),
+            ("PHP_FLOAT_MAX", Value::float(f64::MAX)),
+            ("PHP_FLOAT_MIN", Value::float(f64::MIN_POSITIVE)),
+            ("PHP_MAJOR_VERSION", Value::int(8)),
+            ("PHP_MINOR_VERSION", Value::int(3)),
+            ("NAN", Value::float(f64::NAN)),
+            ("INF", Value::float(f64::INFINITY)),
+            ("COUNT_NORMAL", Value::int(0)),
+            ("COUNT_RECURSIVE", Value::int(1)),
+            ("STR_PAD_LEFT", Value::int(0)),
+            ("STR_PAD_RIGHT", Value::int(1)),
+            ("STR_PAD_BOTH", Value::int(2)),
+            ("ARRAY_FILTER_USE_BOTH", Value::int(1)),
+            ("ARRAY_FILTER_USE_KEY", Value::int(2)),
+            ("SORT_REGULAR", Value::int(0)),
+        ];
+        for (name, value) in constants {
+            self.define_constant(name, value);
+        }
+        for (name, text) in [("PHP_EOL", "\n"), ("PHP_VERSION", "8.3.0"), ("PHP_OS", "Linux"), ("PHP_OS_FAMILY", "Linux")] {
+            let string = self.ctx.memory.alloc_string(text);
+            self.define_constant(name, Value::string(string as *mut u8));
+        }
+    }
+
+    fn define_constant(&mut self, name: &str, value: Value) {
+        let slot = self.ctx.memory.alloc_value();
+        unsafe {
+            *slot = value;
+        }
+        self.ctx.memory.add_root(slot);
+        self.ctx.constants.insert(name.to_string(), slot);
+    }
+
+    /// Run a program, leaving what it printed in [`Interpreter::output`].
+    /// Errors are a [`PhpError`] for an uncaught error, or a [`FatalError`]
+    /// when a limit stopped the script.
+    pub fn run(&mut self, program: &Program) -> anyhow::Result<()> {
+        self.started = Instant::now();
+        let result = self.hoist_program(program).and_then(|()| {
+            for item in &program.items {
+                match item {
+                    ProgramItem::InlineContent(text) => self.ctx.output(text.as_bytes()),
+                    ProgramItem::PhpBlock { statements } => {
+                        if let Flow::Return(_) = self.exec_block(statements)? {
+                            break;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) | Err(Abort::Exit) => Ok(()),
+            Err(Abort::Error(error)) => Err(error.into()),
+            Err(Abort::Fatal(error)) => Err(error.into()),
+        }
+    }
+
+    /// What the script printed so far
+    pub fn output(&self) -> std::borrow::Cow<'_, str> {
+        String::from_utf8_lossy(&self.ctx.output_buffer)
+    }
+
+    /// The context the program runs on
+    pub fn context(&mut self) -> &mut ExecutionContext {
+        &mut self.ctx
+    }
+
+    // Declarations
+
+    /// Declare the program's functions and classes before it runs, as PHP
+    /// does for those at the top level
+    fn hoist_program(&mut self, program: &Program) -> Eval<()> {
+        let statements: Vec<&Statement> = program.items.iter()
+            .filter_map(|item| match item {
+                ProgramItem::PhpBlock { statements } => Some(statements),
+                ProgramItem::InlineContent(_) => None,
+            })
+            .flatten()
+            .collect();
+        self.hoist(&statements)
+    }
+
+    fn hoist(&mut self, statements: &[&Statement]) -> Eval<()> {
+        for statement in statements {
+            match statement {
+                Statement::Function { name, params, body, return_type } => {
+                    self.declare_function(name, params, body, return_type.as_ref())?;
+                }
+                Statement::Namespace(namespace) => {
+                    let outer = std::mem::replace(&mut self.namespace, namespace.name.clone());
+                    let result = self.hoist(&namespace.body.statements.iter().collect::<Vec<_>>());
+                    self.namespace = outer;
+                    result?;
+                }
+                _ => {}
+            }
+        }
+
+        // Classes wait for their parents and interfaces, wherever those are declared
+        let mut pending: Vec<&Statement> = statements.iter().copied().filter(|statement| is_class(statement)).collect();
+        loop {
+            let before = pending.len();
+            let mut waiting = Vec::new();
+            for statement in pending {
+                if self.dependencies_declared(statement) {
+                    self.exec_class(statement)?;
+                } else {
+                    waiting.push(statement);
+                }
+            }
+            pending = waiting;
+            if pending.is_empty() || pending.len() == before {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn dependencies_declared(&self, statement: &Statement) -> bool {
+        let (extends, implements) = match statement {
+            Statement::Class { extends, implements, .. } => (extends.as_ref(), implements.as_slice()),
+            Statement::Enum { implements, .. } => (None, implements.as_slice()),
+            _ => (None, [].as_slice()),
+        };
+        extends.into_iter().chain(implements).all(|name| self.resolve_class_name(name).is_some())
+    }
+
+    fn declare_function(&mut self, name: &str, params: &[Parameter], block: &Block, return_type: Option<&Type>) -> Eval<()> {
+        let name = self.qualify(name);
+        let key = name.to_ascii_lowercase();
+        if self.ctx.functions.contains_key(&key) {
+            return Ok(());
+        }
+        let body_addr = self.add_body(&name, params, block, return_type, None);
+        let function = function_def(&name, params, body_addr, return_type);
+        self.ctx.functions.insert(key, function);
+        Ok(())
+    }
+
+    fn add_body(&mut self, name: &str, params: &[Parameter], block: &Block, return_type: Option<&Type>, class: Option<&str>) -> usize {
+        self.bodies.push(Rc::new(Body {
+            name: name.to_string(),
+            params: params.to_vec(),
+            return_type: return_type.cloned(),
+            block: block.clone(),
+            class: class.map(str::to_string),
+        }));
+        self.bodies.len() - 1
+    }
+
+    /// Declare a class, interface or enum statement unless it already is
+    fn exec_class(&mut self, statement: &Statement) -> Eval<()> {
+        match statement {
+            Statement::Class { name, is_readonly, extends, implements, members, .. } => {
+                self.declare_class(name, extends.as_deref(), implements, members, ClassKind::Class { is_readonly: *is_readonly })
+            }
+            Statement::Interface { name, members } => self.declare_class(name, None, &[], members, ClassKind::Interface),
+            Statement::Enum { name, backing_type, implements, members } => {
+                self.declare_class(name, None, implements, members, ClassKind::Enum { is_backed: backing_type.is_some() })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn declare_class(&mut self, name: &str, extends: Option<&str>, implements: &[String], members: &[ClassMember], kind: ClassKind) -> Eval<()> {
+        let name = self.qualify(name);
+        if self.layouts.contains_key(&name) {
+            return Ok(());
+        }
+
+        let parent = match extends {
+            Some(parent) => Some(self.resolve_class_name(parent).ok_or_else(|| self.error(format!("Class \"{}\" not found", parent)))?),
+            None => None,
+        };
+        let mut interfaces = Vec::new();
+        for interface in implements {
+            interfaces.push(self.resolve_class_name(interface).ok_or_else(|| self.error(format!("Interface \"{}\" not found", interface)))?);
+        }
+        if let ClassKind::Enum { is_backed } = kind {
+            interfaces.push("UnitEnum".to_string());
+            if is_backed {
+                interfaces.push("BackedEnum".to_string());
+            }
+        }
+
+        let mut layout = match &parent {
+            Some(parent) => {
+                let layout = self.layouts[parent].clone();
+                Layout { cases: Vec::new(), is_abstract: false, is_interface: false, ..layout }
+            }
+            None => Layout::default(),
+        };
+        layout.class_id = self.ctx.class_names.len() as u32;
+        layout.is_interface = matches!(kind, ClassKind::Interface);
+        self.ctx.class_names.push(name.clone());
+        self.ctx.classes.insert(name.clone(), ClassDef {
+            name: name.clone(),
+            parent,
+            interfaces,
+            traits: Vec::new(),
+            properties: HashMap::new(),
+            methods: HashMap::new(),
+            constants: HashMap::new(),
+        });
+        self.class_lookup.insert(name.to_ascii_lowercase(), name.clone());
+        self.layouts.insert(name.clone(), layout);
+
+        // Constants and defaults are evaluated in the class's scope
+        self.frames.push(Frame {
+            body: None,
+            function: String::new(),
+            class: Some(name.clone()),
+            static_class: Some(name.clone()),
+            this: None,
+        });
+        let result = self.declare_members(&name, members, kind);
+        self.frames.pop();
+        result
+    }
+
+    fn declare_members(&mut self, class: &str, members: &[ClassMember], kind: ClassKind) -> Eval<()> {
+        let class_readonly = matches!(kind, ClassKind::Class { is_readonly: true });
+        if let ClassKind::Enum { is_backed } = kind {
+            self.add_property(class, "name", Visibility::Public, None, true);
+            if is_backed {
+                self.add_property(class, "value", Visibility::Public, None, true);
+            }
+        }
+
+        for member in members {
+            match member {
+                ClassMember::Property { visibility, is_static, is_readonly, name, property_type, default } => {
+                    let value = match default {
+                        Some(default) => Some(self.eval_owned(default)?),
+                        // Untyped properties start out null, typed ones uninitialized
+                        None if property_type.is_none() => Some(Value::null()),
+                        None => None,
+                    };
+                    let slot = value.map(|value| self.rooted(value));
+                    if *is_static {
+                        let slot = slot.unwrap_or_else(|| self.rooted(Value::null()));
+                        self.static_properties.insert((class.to_string(), name.clone()), slot);
+                        self.class_mut(class).properties.insert(name.clone(), PropertyDef {
+                            name: name.clone(),
+                            visibility: visibility_of(visibility),
+                            is_static: true,
+                            type_hint: property_type.as_ref().map(type_string),
+                            default_value: None,
+                        });
+                    } else {
+                        self.add_property(class, name, visibility_of(visibility), slot, *is_readonly || class_readonly);
+                        let property = self.class_mut(class).properties.get_mut(name).unwrap();
+                        property.type_hint = property_type.as_ref().map(type_string);
+                    }
+                }
+                ClassMember::Method { visibility, is_static, is_final, name, params, body, return_type } => {
+                    if name.eq_ignore_ascii_case("__construct") {
+                        self.promote(class, params, class_readonly);
+                    }
+                    self.add_method(class, name, params, Some(body), return_type.as_ref(), visibility_of(visibility), *is_static, *is_final);
+                }
+                ClassMember::Constructor { visibility, params, body } => {
+                    self.promote(class, params, class_readonly);
+                    self.add_method(class, "__construct", params, Some(body), None, visibility_of(visibility), false, false);
+                }
+                ClassMember::AbstractMethod { visibility, name, params, return_type } => {
+                    self.add_method(class, name, params, None, return_type.as_ref(), visibility_of(visibility), false, false);
+                    if !matches!(kind, ClassKind::Interface) {
+                        self.layouts.get_mut(class).unwrap().is_abstract = true;
+                    }
+                }
+                ClassMember::Constant { name, value, .. } => {
+                    let value = self.eval_owned(value)?;
+                    let slot = self.rooted(value);
+                    self.class_mut(class).constants.insert(name.clone(), slot);
+                }
+                ClassMember::Case { name, value } => {
+                    let layout = &self.layouts[class];
+                    let (class_id, slots) = (layout.class_id, layout.slots.len());
+                    let object = self.ctx.memory.alloc_object(class_id, slots);
+                    let case = self.rooted(Value::object(object));
+                    let case_name = self.string_value(name);
+                    self.init_property(object, 0, "name", case_name);
+                    if let Some(value) = value {
+                        let value = self.eval(value)?;
+                        self.init_property(object, 1, "value", value);
+                    }
+                    self.class_mut(class).constants.insert(name.clone(), case);
+                    self.layouts.get_mut(class).unwrap().cases.push(name.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Declare the properties a constructor's promoted parameters stand for
+    fn promote(&mut self, class: &str, params: &[Parameter], class_readonly: bool) {
+        for param in params {
+            if let Some(promoted) = &param.promoted {
+                let readonly = promoted.is_readonly || class_readonly;
+                self.add_property(class, &param.name, visibility_of(&promoted.visibility), None, readonly);
+                let property = self.class_mut(class).properties.get_mut(&param.name).unwrap();
+                property.type_hint = param.param_type.as_ref().map(type_string);
+            }
+        }
+    }
+
+    fn add_property(&mut self, class: &str, name: &str, visibility: Visibility, default: Option<*mut Value>, readonly: bool) {
+        let layout = self.layouts.get_mut(class).unwrap();
+        if !layout.slots.iter().any(|slot| slot == name) {
+            layout.slots.push(name.to_string());
+        }
+        if readonly {
+            layout.readonly.insert(name.to_string());
+        }
+        self.property_id(name);
+        self.class_mut(class).properties.insert(name.to_string(), PropertyDef {
+            name: name.to_string(),
+            visibility,
+            is_static: false,
+            type_hint: None,
+            default_value: default,
+        });
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn add_method(
+        &mut self,
+        class: &str,
+        name: &str,
+        params: &[Parameter],
+        body: Option<&Block>,
+        return_type: Option<&Type>,
+        visibility: Visibility,
+        is_static: bool,
+        is_final: bool,
+    ) {
+        let body_addr = match body {
+            Some(body) => self.add_body(name, params, body, return_type, Some(class)),
+            None => usize::MAX,
+        };
+        let method = MethodDef {
+            name: name.to_string(),
+            visibility,
+            is_static,
+            is_abstract: body.is_none(),
+            is_final,
+            function: function_def(name, params, body_addr, return_type),
+        };
+        self.class_mut(class).methods.insert(name.to_ascii_lowercase(), method);
+    }
+
+    fn class_mut(&mut self, class: &str) -> &mut ClassDef {
+        self.ctx.classes.get_mut(class).expect("declared class")
+    }
+
+    fn property_id(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.property_ids.get(name) {
+            return id;
+        }
+        let id = self.ctx.property_names.len() as u32;
+        self.ctx.property_names.push(name.to_string());
+        self.property_ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// A value kept alive for the rest of the run
+    fn rooted(&mut self, value: Value) -> *mut Value {
+        let slot = self.ctx.memory.alloc_value();
+        unsafe {
+            *slot = value;
+        }
+        self.ctx.memory.add_root(slot);
+        slot
+    }
+
+    // Names
+
+    /// A declared name in the current namespace
+    fn qualify(&self, name: &str) -> String {
+        match self.namespace.is_empty() {
+            true => name.to_string(),
+            false => format!("{}\\{}", self.namespace, name),
+        }
+    }
+
+    /// The declared class a name refers to
+    fn resolve_class_name(&self, name: &str) -> Option<String> {
+        let lookup = |name: &str| self.class_lookup.get(&name.to_ascii_lowercase()).cloned();
+        if let Some(name) = name.strip_prefix('\\') {
+            return lookup(name);
+        }
+        let (first, rest) = match name.split_once('\\') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (name, None),
+        };
+        if let Some(alias) = self.class_aliases.get(&first.to_ascii_lowercase()) {
+            return match rest {
+                Some(rest) => lookup(&format!("{}\\{}", alias, rest)),
+                None => lookup(alias),
+            };
+        }
+        lookup(&self.qualify(name)).or_else(|| lookup(name))
+    }
+
+    /// The user function a name refers to, as a key of `ctx.functions`
+    fn resolve_function_name(&self, name: &str) -> Option<String> {
+        let exists = |name: &str| {
+            let key = name.to_ascii_lowercase();
+            self.ctx.functions.contains_key(&key).then_some(key)
+        };
+        if let Some(name) = name.strip_prefix('\\') {
+            return exists(name);
+        }
+        if let Some(alias) = self.function_aliases.get(&name.to_ascii_lowercase()) {
+            return exists(alias);
+        }
+        exists(&self.qualify(name)).or_else(|| exists(name))
+    }
+
+    /// `self`, `static` and `parent`, or a class name
+    fn class_of_name(&self, name: &str) -> Eval<String> {
+        let frame = self.frames.last().unwrap();
+        match name.to_ascii_lowercase().as_str() {
+            "self" => frame.class.clone().ok_or_else(|| self.error("Cannot use \"self\" when no class scope is active".to_string())),
+            "static" => frame.static_class.clone().ok_or_else(|| self.error("Cannot use \"static\" when no class scope is active".to_string())),
+            "parent" => {
+                let class = frame.class.as_ref().ok_or_else(|| self.error("Cannot use \"parent\" when no class scope is active".to_string()))?;
+                self.ctx.classes[class].parent.clone()
+                    .ok_or_else(|| self.error("Cannot use \"parent\" when current class scope has no parent".to_string()))
+            }
+            _ => self.resolve_class_name(name).ok_or_else(|| self.error(format!("Class \"{}\" not found", name.trim_start_matches('\\')))),
+        }
+    }
+
+    fn is_subclass(&self, class: &str, ancestor: &str) -> bool {
+        let mut current = Some(class.to_string());
+        while let Some(name) = current {
+            if name == ancestor {
+                return true;
+            }
+            let Some(def) = self.ctx.classes.get(&name) else { return false };
+            if def.interfaces.iter().any(|interface| self.is_subclass(interface, ancestor)) {
+                return true;
+            }
+            current = def.parent.clone();
+        }
+        false
+    }
+
+    // Statements
+
+    fn exec_block(&mut self, statements: &[Statement]) -> Eval<Flow> {
+        for statement in statements {
+            match self.exec(statement)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec(&mut self, statement: &Statement) -> Eval<Flow> {
+        if let Statement::Location(location) = statement {
+            self.line = Some(location.line);
+            return Ok(Flow::Normal);
+        }
+        self.tick()?;
+        let mark = self.temps.len();
+        let result = self.exec_statement(statement).map_err(|abort| self.locate(abort));
+        self.temps.truncate(mark);
+        if let Ok(Flow::Return(value)) = result {
+            self.temp(value);
+        }
+        result
+    }
+
+    fn exec_statement(&mut self, statement: &Statement) -> Eval<Flow> {
+        match statement {
+            Statement::Expression(expr) => {
+                self.eval(expr)?;
+            }
+            Statement::Echo(exprs) => {
+                for expr in exprs {
+                    let value = self.eval(expr)?;
+                    let text = self.string_of(value)?;
+                    self.ctx.output(text.as_bytes());
+                }
+            }
+            Statement::Return(expr) => {
+                let value = match expr {
+                    Some(expr) => self.eval_owned(expr)?,
+                    None => Value::null(),
+                };
+                return Ok(Flow::Return(value));
+            }
+            Statement::If { condition, then_block, elseif_blocks, else_block } => {
+                if self.eval(condition)?.to_bool() {
+                    return self.exec_block(&then_block.statements);
+                }
+                for elseif in elseif_blocks {
+                    if self.eval(&elseif.condition)?.to_bool() {
+                        return self.exec_block(&elseif.then_block.statements);
+                    }
+                }
+                if let Some(block) = else_block {
+                    return self.exec_block(&block.statements);
+                }
+            }
+            Statement::While { condition, body } => loop {
+                self.tick()?;
+                let mark = self.temps.len();
+                let condition = self.eval(condition)?.to_bool();
+                self.temps.truncate(mark);
+                if !condition {
+                    break;
+                }
+                match self.exec_block(&body.statements)? {
+                    Flow::Break => break,
+                    Flow::Normal | Flow::Continue => {}
+                    flow => return Ok(flow),
+                }
+            },
+            Statement::DoWhile { body, condition } => loop {
+                self.tick()?;
+                match self.exec_block(&body.statements)? {
+                    Flow::Break => break,
+                    Flow::Normal | Flow::Continue => {}
+                    flow => return Ok(flow),
+                }
+                let mark = self.temps.len();
+                let condition = self.eval(condition)?.to_bool();
+                self.temps.truncate(mark);
+                if !condition {
+                    break;
+                }
+            },
+            Statement::For { init, condition, update, body } => {
+                if let Some(init) = init {
+                    self.exec(init)?;
+                }
+                loop {
+                    self.tick()?;
+                    let mark = self.temps.len();
+                    if let Some(condition) = condition {
+                        let condition = self.eval(condition)?.to_bool();
+                        self.temps.truncate(mark);
+                        if !condition {
+                            break;
+                        }
+                    }
+                    match self.exec_block(&body.statements)? {
+                        Flow::Break => break,
+                        Flow::Normal | Flow::Continue => {}
+                        flow => return Ok(flow),
+                    }
+                    if let Some(update) = update {
+                        self.eval(update)?;
+                        self.temps.truncate(mark);
+                    }
+                }
+            }
+            Statement::Foreach { array, key, value, body } => return self.exec_foreach(array, key.as_deref(), value, body),
+            Statement::Switch { expr, cases } => return self.exec_switch(expr, cases),
+            Statement::Function { name, params, body, return_type } => {
+                self.declare_function(name, params, body, return_type.as_ref())?;
+            }
+            Statement::Class { .. } | Statement::Interface { .. } | Statement::Enum { .. } => self.exec_class(statement)?,
+            Statement::Block(block) => return self.exec_block(&block.statements),
+            Statement::Break => return Ok(Flow::Break),
+            Statement::Continue => return Ok(Flow::Continue),
+            Statement::Use(statement) => {
+                for item in &statement.items {
+                    let alias = item.alias.clone()
+                        .unwrap_or_else(|| item.path.rsplit('\\').next().unwrap_or(&item.path).to_string())
+                        .to_ascii_lowercase();
+                    match item.kind {
+                        UseKind::Class => {
+                            self.class_aliases.insert(alias, item.path.clone());
+                        }
+                        UseKind::Function => {
+                            self.function_aliases.insert(alias, item.path.clone());
+                        }
+                        UseKind::Constant => {}
+                    }
+                }
+            }
+            Statement::Namespace(namespace) => {
+                let outer = std::mem::replace(&mut self.namespace, namespace.name.clone());
+                let result = self.exec_block(&namespace.body.statements);
+                self.namespace = outer;
+                return result;
+            }
+            Statement::Global(names) => {
+                for name in names {
+                    self.bind_global(name);
+                }
+            }
+            Statement::Static(variables) => {
+                let body = self.frames.last().unwrap().body.unwrap_or(usize::MAX);
+                for variable in variables {
+                    let key = (body, variable.name.clone());
+                    let slot = match self.static_variables.get(&key) {
+                        Some(&slot) => slot,
+                        None => {
+                            let value = match &variable.default {
+                                Some(default) => self.eval_owned(default)?,
+                                None => Value::null(),
+                            };
+                            let slot = self.rooted(value);
+                            self.static_variables.insert(key, slot);
+                            slot
+                        }
+                    };
+                    self.bind_reference(&variable.name, slot);
+                }
+            }
+            Statement::Declare(directives) => {
+                for (name, value) in directives {
+                    if name.eq_ignore_ascii_case("strict_types") {
+                        self.strict_types = self.eval(value)?.to_bool();
+                    }
+                }
+            }
+            Statement::Location(location) => self.line = Some(location.line),
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec_foreach(&mut self, array: &Expression, key: Option<&str>, target: &Expression, body: &Block) -> Eval<Flow> {
+        let subject = self.eval(array)?;
+        // The pairs as they were when the loop started; pushed as temps so
+        // that changing the array in the body frees none of them
+        let pairs: Vec<(Value, Value)> = match subject.get_type() {
+            ValueType::Array => unsafe {
+                MemoryManager::array_entries(subject.data.ptr as *mut PhpArray)
+                    .map(|entry| ((*entry).key.to_value(), *deref((*entry).value)))
+                    .collect()
+            },
+            ValueType::Object => {
+                let object = unsafe { subject.data.ptr as *mut PhpObject };
+                let mut pairs = Vec::new();
+                for (index, name) in self.layout_of(object).slots.clone().into_iter().enumerate() {
+                    let slot = unsafe { (*(*object).properties.add(index)).value };
+                    if !slot.is_null() && self.property_visible(object, &name) {
+                        let name = self.string_value(&name);
+                        pairs.push((name, unsafe { *deref(slot) }));
+                    }
+                }
+                pairs
+            }
+            _ => Vec::new(),
+        };
+        for &(key, value) in &pairs {
+            self.temp(key);
+            self.temp(value);
+        }
+
+        for (key_value, value) in pairs {
+            self.tick()?;
+            let mark = self.temps.len();
+            let value = self.owned(value);
+            self.assign_to(target, value)?;
+            if let Some(key) = key {
+                let slot = self.variable_slot(key);
+                write(slot, key_value);
+            }
+            let flow = self.exec_block(&body.statements)?;
+            self.temps.truncate(mark);
+            match flow {
+                Flow::Break => break,
+                Flow::Normal | Flow::Continue => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec_switch(&mut self, expr: &Expression, cases: &[SwitchCase]) -> Eval<Flow> {
+        let subject = self.eval(expr)?;
+        let mut start = None;
+        for (index, case) in cases.iter().enumerate() {
+            if let Some(value) = &case.value {
+                let value = self.eval(value)?;
+                if operations::loose_equals(&subject, &value) {
+                    start = Some(index);
+                    break;
+                }
+            }
+        }
+        let Some(start) = start.or_else(|| cases.iter().position(|case| case.value.is_none())) else {
+            return Ok(Flow::Normal);
+        };
+        for case in &cases[start..] {
+            match self.exec_block(&case.statements)? {
+                Flow::Normal => {}
+                // `continue` in a switch acts like `break`, as in PHP
+                Flow::Break | Flow::Continue => break,
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Make a local variable refer to the global one of the same name
+    fn bind_global(&mut self, name: &str) {
+        if self.ctx.scopes.len() == 1 {
+            return;
+        }
+        let global = self.global_variable(name);
+        self.bind_reference(name, global);
+    }
+
+    /// A global variable's slot, created if it does not exist
+    fn global_variable(&mut self, name: &str) -> *mut Value {
+        if let Some(&slot) = self.ctx.scopes[0].variables.get(name) {
+            return slot;
+        }
+        let slot = self.ctx.memory.alloc_value();
+        self.ctx.memory.add_root(slot);
+        self.ctx.scopes[0].variables.insert(name.to_string(), slot);
+        slot
+    }
+
+    /// `$GLOBALS` read whole: a copy of the global variables, by name
+    fn globals_array(&mut self) -> Value {
+        let mut globals: Vec<(String, *mut Value)> =
+            self.ctx.scopes[0].variables.iter().map(|(name, &slot)| (name.clone(), slot)).collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        let array = self.new_array();
+        for (name, slot) in globals {
+            let value = self.owned(read(slot));
+            let key = self.string_value(&name);
+            let key = self.ctx.memory.array_key(&key);
+            self.array_insert(array, Some(key), value);
+        }
+        Value::array(array)
+    }
+
+    /// The global variable an element of `$GLOBALS` names, if it exists
+    fn global_element(&mut self, index: &Expression) -> Eval<Option<*mut Value>> {
+        let name = self.eval(index)?.to_php_string();
+        Ok(self.ctx.scopes[0].variables.get(&name).copied())
+    }
+
+    fn bind_reference(&mut self, name: &str, target: *mut Value) {
+        let slot = self.ctx.memory.alloc_value();
+        unsafe {
+            *slot = Value::reference(target);
+        }
+        self.ctx.set_variable(name.to_string(), slot);
+    }
+
+    // Limits
+
+    /// Count a step against the fuel, look at the clock now and then, and
+    /// collect garbage once enough has piled up
+    fn tick(&mut self) -> Eval<()> {
+        self.steps += 1;
+        if let Some(fuel) = self.limits.get_fuel() {
+            if self.steps > fuel {
+                return Err(Abort::Fatal(FatalError::OutOfFuel { fuel }));
+            }
+        }
+        if self.steps.is_multiple_of(CLOCK_INTERVAL) {
+            if let Some(limit) = self.limits.get_max_execution_time() {
+                if self.started.elapsed() > limit {
+                    return Err(Abort::Fatal(FatalError::ExecutionTimeExceeded { limit }));
+                }
+            }
+        }
+
+        let live = self.ctx.memory.stats().live_bytes;
+        if live > self.next_gc {
+            self.collect();
+            let live_after = self.ctx.memory.stats().live_bytes;
+            if let Some(limit) = self.limits.get_memory_limit() {
+                if live_after > limit {
+                    let requested = live_after - limit;
+                    return Err(Abort::Fatal(FatalError::MemoryExhausted { limit, requested }));
+                }
+            }
+            self.next_gc = (live_after * 2).max(GC_THRESHOLD);
+            if let Some(limit) = self.limits.get_memory_limit() {
+                // Collect before the limit is passed rather than after
+                self.next_gc = self.next_gc.min(limit.max(live_after + 1));
+            }
+        }
+        Ok(())
+    }
+
+    /// Collect garbage, keeping the values in flight
+    fn collect(&mut self) {
+        let temps: Vec<*mut Value> = self.temps.iter_mut().map(|value| value as *mut Value).collect();
+        for &temp in &temps {
+            self.ctx.memory.add_root(temp);
+        }
+        self.ctx.memory.gc();
+        for &temp in &temps {
+            self.ctx.memory.remove_root(temp);
+        }
+    }
+
+    // Errors
+
+    fn error(&self, message: String) -> Abort {
+        self.error_of("Error", message)
+    }
+
+    /// An uncaught error of a class other than `Error`, such as `TypeError`
+    fn error_of(&self, class: &'static str, message: String) -> Abort {
+        Abort::Error(PhpError { level: ErrorLevel::Error, class, message, file: None, line: self.line })
+    }
+
+    /// Give an error the line it happened on, if it has none yet
+    fn locate(&self, abort: Abort) -> Abort {
+        match abort {
+            Abort::Error(mut error) if error.line.is_none() => {
+                error.line = self.line;
+                Abort::Error(error)
+            }
+            abort => abort,
+        }
+    }
+
+    // Values
+
+    /// Keep a value alive until the statement ends
+    fn temp(&mut self, value: Value) -> Value {
+        if matches!(value.get_type(), ValueType::String | ValueType::Array | ValueType::Object) {
+            self.temps.push(value);
+        }
+        value
+    }
+
+    fn string_value(&mut self, s: &str) -> Value {
+        let string = self.ctx.memory.alloc_string(s);
+        self.temp(Value::string(string as *mut u8))
+    }
+
+    fn new_array(&mut self) -> *mut PhpArray {
+        let array = self.ctx.memory.alloc_array(8);
+        self.temp(Value::array(array));
+        array
+    }
+
+    /// A value to store somewhere new: arrays are copied, as PHP assigns them by value
+    fn owned(&mut self, mut value: Value) -> Value {
+        if value.get_type() != ValueType::Array {
+            return value;
+        }
+        let copy = unsafe { self.ctx.memory.copy_value(&mut value) };
+        self.temp(copy)
+    }
+
+    fn variable(&self, name: &str) -> Option<*mut Value> {
+        self.ctx.scopes.last().and_then(|scope| scope.variables.get(name).copied())
+    }
+
+    /// A variable's slot, created if it does not exist
+    fn variable_slot(&mut self, name: &str) -> *mut Value {
+        if let Some(slot) = self.variable(name) {
+            return slot;
+        }
+        let slot = self.ctx.memory.alloc_value();
+        self.ctx.set_variable(name.to_string(), slot);
+        slot
+    }
+
+    /// A value as PHP prints it: objects through `__toString`
+    fn string_of(&mut self, value: Value) -> Eval<String> {
+        if value.get_type() != ValueType::Object {
+            return Ok(value.to_php_string());
+        }
+        let object = unsafe { value.data.ptr as *mut PhpObject };
+        let class = self.class_name_of(object);
+        match self.find_method(&class, "__toString") {
+            Some(method) => {
+                let result = self.call_body(method.body, Vec::new(), Some(object), Some(class))?;
+                Ok(result.to_php_string())
+            }
+            None => Err(self.error(format!("Object of class {} could not be converted to string", class))),
+        }
+    }
+
+    // Expressions
+
+    fn eval(&mut self, expr: &Expression) -> Eval<Value> {
+        let value = self.eval_expression(expr)?;
+        Ok(self.temp(value))
+    }
+
+    /// Evaluate a value to be stored: arrays read from somewhere are copied,
+    /// ones just built are not
+    fn eval_owned(&mut self, expr: &Expression) -> Eval<Value> {
+        let value = self.eval(expr)?;
+        match expr {
+            Expression::Array(_)
+            | Expression::FunctionCall { .. }
+            | Expression::MethodCall { .. }
+            | Expression::StaticMethodCall { .. }
+            | Expression::NullsafeMethodCall { .. }
+            | Expression::Invoke { .. }
+            | Expression::Binary { .. } => Ok(value),
+            _ => Ok(self.owned(value)),
+        }
+    }
+
+    fn eval_expression(&mut self, expr: &Expression) -> Eval<Value> {
+        match expr {
+            Expression::Variable(name) if name == "GLOBALS" => Ok(self.globals_array()),
+            Expression::Variable(name) => Ok(self.lookup(name)),
+            Expression::Literal(literal) => self.literal(literal),
+            Expression::Binary { op, left, right } => self.binary(op, left, right),
+            Expression::Unary { op, expr } => match op {
+                UnaryOp::Not => Ok(Value::bool(!self.eval(expr)?.to_bool())),
+                UnaryOp::Negate => {
+                    let mut value = self.eval(expr)?;
+                    let result = unsafe { operations::negate(&mut self.ctx, &mut value)? };
+                    Ok(unsafe { *result })
+                }
+                UnaryOp::PreIncrement | UnaryOp::PostIncrement | UnaryOp::PreDecrement | UnaryOp::PostDecrement => {
+                    self.increment(expr, op)
+                }
+            },
+            Expression::Assignment { left, right } => {
+                if let Expression::List(items) = left.as_ref() {
+                    let value = self.eval(right)?;
+                    self.destructure(items, value)?;
+                    return Ok(value);
+                }
+                let value = self.eval_owned(right)?;
+                self.assign_to(left, value)?;
+                Ok(value)
+            }
+            Expression::FunctionCall { name, args } => self.call_function(name, args),
+            Expression::MethodCall { object, method, args } => {
+                let object = self.eval(object)?;
+                self.call_method(object, method, args)
+            }
+            Expression::NullsafeMethodCall { object, method, args } => {
+                let object = self.eval(object)?;
+                match object.get_type() {
+                    ValueType::Null => Ok(Value::null()),
+                    _ => self.call_method(object, method, args),
+                }
+            }
+            Expression::PropertyAccess { object, property } => {
+                let object = self.eval(object)?;
+                self.get_property(object, property)
+            }
+            Expression::NullsafePropertyAccess { object, property } => {
+                let object = self.eval(object)?;
+                match object.get_type() {
+                    ValueType::Null => Ok(Value::null()),
+                    _ => self.get_property(object, property),
+                }
+            }
+            Expression::StaticPropertyAccess { class, property } => {
+                let slot = self.static_property(class, property)?;
+                Ok(read(slot))
+            }
+            Expression::ArrayAccess { array, index } if is_globals(array) => {
+                Ok(self.global_element(index)?.map_or(Value::null(), read))
+            }
+            Expression::ArrayAccess { array, index } => {
+                let base = self.eval(array)?;
+                let index = self.eval(index)?;
+                self.index(base, index)
+            }
+            Expression::Array(elements) => self.array_literal(elements),
+            Expression::List(_) => Err(self.error("Cannot use list() outside of an assignment".to_string())),
+            Expression::New { class, args } => self.instantiate(class, args),
+            Expression::Ternary { condition, then_expr, else_expr } => {
+                match self.eval(condition)?.to_bool() {
+                    true => self.eval(then_expr),
+                    false => self.eval(else_expr),
+                }
+            }
+            Expression::Cast { cast_type, expr } => {
+                let value = self.eval(expr)?;
+                self.cast(cast_type, value)
+            }
+            Expression::Coalesce { left, right } => match self.eval_quiet(left)? {
+                Some(value) if value.get_type() != ValueType::Null => Ok(value),
+                _ => self.eval(right),
+            },
+            Expression::ClassConstant { class, name } => self.class_constant(class, name),
+            Expression::StaticMethodCall { class, method, args } => self.call_static(class, method, args),
+            Expression::Clone(expr) => {
+                let value = self.eval(expr)?;
+                self.clone_object(value)
+            }
+            Expression::Invoke { callee, args } => {
+                let callee = self.eval(callee)?;
+                let args = self.eval_args(args)?;
+                self.call_value(callee, args)
+            }
+            Expression::Include { kind, path } => {
+                let path = self.eval(path)?;
+                let path = self.string_of(path)?;
+                self.include(&path, kind.is_once(), kind.keyword())
+            }
+            Expression::MagicConstant(constant) => {
+                let frame = self.frames.last().unwrap();
+                let text = match constant {
+                    MagicConstant::Dir => ".".to_string(),
+                    MagicConstant::File => String::new(),
+                    MagicConstant::Namespace => self.namespace.clone(),
+                    MagicConstant::Class => frame.class.clone().unwrap_or_default(),
+                    MagicConstant::Function => frame.function.clone(),
+                    MagicConstant::Method => match &frame.class {
+                        Some(class) if !frame.function.is_empty() => format!("{}::{}", class, frame.function),
+                        _ => frame.function.clone(),
+                    },
+                };
+                Ok(self.string_value(&text))
+            }
+        }
+    }
+
+    /// A variable, else a constant: the parser reads bare names as variables
+    fn lookup(&mut self, name: &str) -> Value {
+        if let Some(slot) = self.variable(name) {
+            return read(slot);
+        }
+        if let Some(value) = self.constant(name) {
+            return value;
+        }
+        Value::null()
+    }
+
+    fn constant(&self, name: &str) -> Option<Value> {
+        let name = name.trim_start_matches('\\');
+        let slot = self.ctx.constants.get(name)
+            .or_else(|| self.ctx.constants.get(name.rsplit('\\').next().unwrap_or(name)))?;
+        Some(read(*slot))
+    }
+
+    fn literal(&mut self, literal: &Literal) -> Eval<Value> {
+        Ok(match literal {
+            Literal::Integer(i) => Value::int(*i),
+            Literal::Float(f) => Value::float(*f),
+            Literal::Boolean(b) => Value::bool(*b),
+            Literal::Null => Value::null(),
+            Literal::String(s) => self.string_value(s),
+            Literal::InterpolatedString(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        InterpolatedPart::Text(s) => text.push_str(s),
+                        InterpolatedPart::Variable(name) => {
+                            let value = self.lookup(name);
+                            text.push_str(&self.string_of(value)?);
+                        }
+                        InterpolatedPart::Expression(expr) => {
+                            let value = self.eval(expr)?;
+                            text.push_str(&self.string_of(value)?);
+                        }
+                    }
+                }
+                self.string_value(&text)
+            }
+        })
+    }
+
+    fn binary(&mut self, op: &BinaryOp, left: &Expression, right: &Expression) -> Eval<Value> {
+        match op {
+            BinaryOp::And => return Ok(Value::bool(self.eval(left)?.to_bool() && self.eval(right)?.to_bool())),
+            BinaryOp::Or => return Ok(Value::bool(self.eval(left)?.to_bool() || self.eval(right)?.to_bool())),
+            _ => {}
+        }
+
+        let mut left = self.eval(left)?;
+        let mut right = self.eval(right)?;
+        let ordering = || operations::compare(&left, &right);
+        let result = match op {
+            BinaryOp::Concat => {
+                let text = self.string_of(left)? + &self.string_of(right)?;
+                return Ok(self.string_value(&text));
+            }
+            BinaryOp::Equal => return Ok(Value::bool(operations::loose_equals(&left, &right))),
+            BinaryOp::NotEqual => return Ok(Value::bool(!operations::loose_equals(&left, &right))),
+            BinaryOp::Identical => return Ok(Value::bool(operations::strict_equals(&left, &right))),
+            BinaryOp::NotIdentical => return Ok(Value::bool(!operations::strict_equals(&left, &right))),
+            BinaryOp::LessThan => return Ok(Value::bool(ordering() == Some(Ordering::Less))),
+            BinaryOp::LessThanOrEqual => return Ok(Value::bool(matches!(ordering(), Some(Ordering::Less | Ordering::Equal)))),
+            BinaryOp::GreaterThan => return Ok(Value::bool(ordering() == Some(Ordering::Greater))),
+            BinaryOp::GreaterThanOrEqual => return Ok(Value::bool(matches!(ordering(), Some(Ordering::Greater | Ordering::Equal)))),
+            BinaryOp::Add => unsafe { operations::add(&mut self.ctx, &mut left, &mut right)? },
+            BinaryOp::Subtract => unsafe { operations::subtract(&mut self.ctx, &mut left, &mut right)? },
+            BinaryOp::Multiply => unsafe { operations::multiply(&mut self.ctx, &mut left, &mut right)? },
+            BinaryOp::Divide => unsafe { operations::divide(&mut self.ctx, &mut left, &mut right)? },
+            BinaryOp::Modulo => unsafe { operations::modulo(&mut self.ctx, &mut left, &mut right)? },
+            BinaryOp::And | BinaryOp::Or => unreachable!(),
+        };
+        Ok(unsafe { *result })
+    }
+
+    fn increment(&mut self, target: &Expression, op: &UnaryOp) -> Eval<Value> {
+        let up = matches!(op, UnaryOp::PreIncrement | UnaryOp::PostIncrement);
+        let slot = self.lvalue(target)?;
+        let old = read(slot);
+        let new = match old.get_type() {
+            ValueType::Null if up => Value::int(1),
+            ValueType::Int => {
+                let i = old.to_int();
+                match if up { i.checked_add(1) } else { i.checked_sub(1) } {
+                    Some(i) => Value::int(i),
+                    None => Value::float(i as f64 + if up { 1.0 } else { -1.0 }),
+                }
+            }
+            ValueType::Float => Value::float(old.to_float() + if up { 1.0 } else { -1.0 }),
+            ValueType::String => {
+                let text = old.to_php_string();
+                match parse_numeric(&text) {
+                    Some((_, true)) => {
+                        let mut number = old;
+                        let mut one = Value::int(if up { 1 } else { -1 });
+                        unsafe { *operations::add(&mut self.ctx, &mut number, &mut one)? }
+                    }
+                    _ if text.is_empty() => match up {
+                        true => self.string_value("1"),
+                        false => Value::int(-1),
+                    },
+                    _ if up => self.string_value(&increment_string(&text)),
+                    _ => old,
+                }
+            }
+            _ => old,
+        };
+        write(slot, new);
+        Ok(match op {
+            UnaryOp::PreIncrement | UnaryOp::PreDecrement => new,
+            _ => old,
+        })
+    }
+
+    fn array_literal(&mut self, elements: &[ArrayElement]) -> Eval<Value> {
+        let array = self.new_array();
+        for element in elements {
+            let key = match &element.key {
+                Some(key) => {
+                    let key = self.eval(key)?;
+                    Some(self.ctx.memory.array_key(&key))
+                }
+                None => None,
+            };
+            let value = self.eval_owned(&element.value)?;
+            let slot = self.ctx.memory.alloc_value();
+            unsafe {
+                *slot = value;
+                match key {
+                    Some(key) => self.ctx.memory.array_set(array, key, slot),
+                    None => self.ctx.memory.array_push(array, slot),
+                }
+            }
+        }
+        Ok(Value::array(array))
+    }
+
+    fn index(&mut self, base: Value, index: Value) -> Eval<Value> {
+        match base.get_type() {
+            ValueType::Array => {
+                let key = self.ctx.memory.array_key(&index);
+                let slot = unsafe { self.ctx.memory.array_get(base.data.ptr as *mut PhpArray, &key) };
+                Ok(if slot.is_null() { Value::null() } else { read(slot) })
+            }
+            ValueType::String => {
+                let bytes = base.as_bytes().unwrap_or_default();
+                let offset = index.to_int();
+                let offset = if offset < 0 { bytes.len() as i64 + offset } else { offset };
+                let byte = usize::try_from(offset).ok().and_then(|offset| bytes.get(offset)).copied();
+                let text = byte.map(|byte| String::from_utf8_lossy(&[byte]).into_owned()).unwrap_or_default();
+                Ok(self.string_value(&text))
+            }
+            ValueType::Object => {
+                let class = self.class_name_of(unsafe { base.data.ptr as *mut PhpObject });
+                Err(self.error(format!("Cannot use object of type {} as array", class)))
+            }
+            _ => Ok(Value::null()),
+        }
+    }
+
+    fn cast(&mut self, cast_type: &Type, value: Value) -> Eval<Value> {
+        Ok(match cast_type {
+            Type::Int => Value::int(value.to_int()),
+            Type::Float => Value::float(value.to_float()),
+            Type::Bool => Value::bool(value.to_bool()),
+            Type::String => {
+                let text = self.string_of(value)?;
+                self.string_value(&text)
+            }
+            Type::Null => Value::null(),
+            Type::Array => match value.get_type() {
+                ValueType::Array => self.owned(value),
+                ValueType::Null => Value::array(self.new_array()),
+                ValueType::Object => {
+                    let object = unsafe { value.data.ptr as *mut PhpObject };
+                    let array = self.new_array();
+                    for (index, name) in self.layout_of(object).slots.clone().into_iter().enumerate() {
+                        let slot = unsafe { (*(*object).properties.add(index)).value };
+                        if !slot.is_null() {
+                            let key = self.string_value(&name);
+                            let key = self.ctx.memory.array_key(&key);
+                            let copy = self.owned(read(slot));
+                            self.array_insert(array, Some(key), copy);
+                        }
+                    }
+                    Value::array(array)
+                }
+                _ => {
+                    let array = self.new_array();
+                    self.array_insert(array, None, value);
+                    Value::array(array)
+                }
+            },
+            other => return Err(self.error(format!("Casting to {} is not supported", type_string(other)))),
+        })
+    }
+
+    fn array_insert(&mut self, array: *mut PhpArray, key: Option<ArrayKey>, value: Value) {
+        let slot = self.ctx.memory.alloc_value();
+        unsafe {
+            *slot = value;
+            match key {
+                Some(key) => self.ctx.memory.array_set(array, key, slot),
+                None => self.ctx.memory.array_push(array, slot),
+            }
+        }
+    }
+
+    /// Evaluate without complaining about what is missing: `None` for an
+    /// undefined variable, key or property, as `isset` and `??` need
+    fn eval_quiet(&mut self, expr: &Expression) -> Eval<Option<Value>> {
+        match expr {
+            Expression::Variable(name) if name == "this" => {
+                Ok(self.frames.last().unwrap().this.map(Value::object))
+            }
+            Expression::Variable(name) if name == "GLOBALS" => Ok(Some(self.globals_array())),
+            Expression::Variable(name) => Ok(self.variable(name).map(read)),
+            Expression::ArrayAccess { array, index } if is_globals(array) => Ok(self.global_element(index)?.map(read)),
+            Expression::ArrayAccess { array, index } => {
+                let Some(base) = self.eval_quiet(array)? else { return Ok(None) };
+                let index = self.eval(index)?;
+                match base.get_type() {
+                    ValueType::Array => {
+                        let key = self.ctx.memory.array_key(&index);
+                        let slot = unsafe { self.ctx.memory.array_get(base.data.ptr as *mut PhpArray, &key) };
+                        Ok((!slot.is_null()).then(|| read(slot)))
+                    }
+                    ValueType::String => {
+                        let len = base.as_bytes().unwrap_or_default().len() as i64;
+                        let offset = index.to_int();
+                        match (-len..len).contains(&offset) {
+                            true => self.index(base, index).map(Some),
+                            false => Ok(None),
+                        }
+                    }
+                    _ => Ok(None),
+                }
+            }
+            Expression::PropertyAccess { object, property } | Expression::NullsafePropertyAccess { object, property } => {
+                let Some(object) = self.eval_quiet(object)? else { return Ok(None) };
+                self.property_quiet(object, property, false)
+            }
+            Expression::StaticPropertyAccess { class, property } => {
+                let class = self.class_of_name(class)?;
+                Ok(self.find_static_property(&class, property).map(read))
+            }
+            _ => self.eval(expr).map(Some),
+        }
+    }
+
+    // Assignment
+
+    fn assign_to(&mut self, target: &Expression, value: Value) -> Eval<()> {
+        match target {
+            Expression::List(items) => self.destructure(items, value),
+            Expression::PropertyAccess { object, property } => {
+                let object = self.eval(object)?;
+                self.set_property(object, property, value)
+            }
+            _ => {
+                let slot = self.lvalue(target)?;
+                write(slot, value);
+                Ok(())
+            }
+        }
+    }
+
+    /// `[$a, 'k' => $b] = $value`
+    fn destructure(&mut self, items: &[Option<ArrayElement>], value: Value) -> Eval<()> {
+        let mut next = 0;
+        for item in items {
+            let Some(element) = item else {
+                next += 1;
+                continue;
+            };
+            let element_value = match value.get_type() {
+                ValueType::Array => {
+                    let key = match &element.key {
+                        Some(key) => {
+                            let key = self.eval(key)?;
+                            self.ctx.memory.array_key(&key)
+                        }
+                        None => {
+                            next += 1;
+                            ArrayKey::Integer(next - 1)
+                        }
+                    };
+                    let slot = unsafe { self.ctx.memory.array_get(value.data.ptr as *mut PhpArray, &key) };
+                    if slot.is_null() { Value::null() } else { read(slot) }
+                }
+                _ => Value::null(),
+            };
+            let element_value = self.owned(element_value);
+            self.assign_to(&element.value, element_value)?;
+        }
+        Ok(())
+    }
+
+    /// The slot an assignment writes to, created along the way: missing
+    /// variables, array keys and nested arrays spring into existence
+    fn lvalue(&mut self, expr: &Expression) -> Eval<*mut Value> {
+        match expr {
+            Expression::Variable(name) if name == "this" => Err(self.error("Cannot re-assign $this".to_string())),
+            Expression::Variable(name) => Ok(self.variable_slot(name)),
+            Expression::ArrayAccess { array, index } if is_globals(array) => {
+                let name = self.eval(index)?.to_php_string();
+                Ok(self.global_variable(&name))
+            }
+            Expression::ArrayAccess { array, index } => {
+                let container = unsafe { deref(self.lvalue(array)?) };
+                let current = unsafe { *container };
+                match current.get_type() {
+                    ValueType::Array => {}
+                    ValueType::Null => {
+                        let array = self.ctx.memory.alloc_array(8);
+                        write(container, Value::array(array));
+                    }
+                    ValueType::Bool if !current.to_bool() => {
+                        let array = self.ctx.memory.alloc_array(8);
+                        write(container, Value::array(array));
+                    }
+                    ValueType::String => return Err(self.error("String offsets cannot be written to by the interpreter".to_string())),
+                    ValueType::Object => {
+                        let class = self.class_name_of(unsafe { current.data.ptr as *mut PhpObject });
+                        return Err(self.error(format!("Cannot use object of type {} as array", class)));
+                    }
+                    _ => return Err(self.error("Cannot use a scalar value as an array".to_string())),
+                }
+                let index = self.eval(index)?;
+                let key = self.ctx.memory.array_key(&index);
+                unsafe {
+                    let array = (*container).data.ptr as *mut PhpArray;
+                    let slot = self.ctx.memory.array_get(array, &key);
+                    if !slot.is_null() {
+                        return Ok(slot);
+                    }
+                    let slot = self.ctx.memory.alloc_value();
+                    self.ctx.memory.array_set(array, key, slot);
+                    Ok(slot)
+                }
+            }
+            Expression::PropertyAccess { object, property } => {
+                let value = self.eval(object)?;
+                let object = self.expect_object(value, property, "modify")?;
+                let index = self.accessible_slot(object, property)?;
+                unsafe {
+                    let slot = (*(*object).properties.add(index)).value;
+                    if !slot.is_null() {
+                        return Ok(slot);
+                    }
+                    let slot = self.ctx.memory.alloc_value();
+                    let name_id = self.property_id(property);
+                    self.ctx.memory.object_set_property(object, index, name_id, slot);
+                    Ok(slot)
+                }
+            }
+            Expression::StaticPropertyAccess { class, property } => self.static_property(class, property),
+            Expression::NullsafePropertyAccess { .. } => Err(self.error("Can't use nullsafe operator in write context".to_string())),
+            _ => Err(self.error("Cannot assign to this expression".to_string())),
+        }
+    }
+
+    fn unset(&mut self, target: &Expression) -> Eval<()> {
+        match target {
+            Expression::Variable(name) => {
+                if let Some(slot) = self.ctx.scopes.last_mut().and_then(|scope| scope.variables.remove(name)) {
+                    self.ctx.memory.remove_root(slot);
+                }
+            }
+            Expression::ArrayAccess { array, index } if is_globals(array) => {
+                let name = self.eval(index)?.to_php_string();
+                if let Some(slot) = self.ctx.scopes[0].variables.remove(&name) {
+                    self.ctx.memory.remove_root(slot);
+                }
+            }
+            Expression::ArrayAccess { array, index } => {
+                if let Some(container) = self.eval_quiet(array)? {
+                    let index = self.eval(index)?;
+                    if container.get_type() == ValueType::Array {
+                        let key = self.ctx.memory.array_key(&index);
+                        unsafe {
+                            self.ctx.memory.array_remove(container.data.ptr as *mut PhpArray, &key);
+                        }
+                    }
+                }
+            }
+            Expression::PropertyAccess { object, property } => {
+                let value = self.eval(object)?;
+                if value.get_type() == ValueType::Object {
+                    let object = unsafe { value.data.ptr as *mut PhpObject };
+                    if self.slot_index(object, property).is_none() {
+                        let class = self.class_name_of(object);
+                        if let Some(method) = self.find_method(&class, "__unset") {
+                            let name = self.string_value(property);
+                            self.call_body(method.body, vec![name], Some(object), Some(class))?;
+                        }
+                        return Ok(());
+                    }
+                    let index = self.accessible_slot(object, property)?;
+                    let name_id = self.property_id(property);
+                    unsafe {
+                        self.ctx.memory.object_set_property(object, index, name_id, std::ptr::null_mut());
+                    }
+                }
+            }
+            _ => return Err(self.error("Cannot unset this expression".to_string())),
+        }
+        Ok(())
+    }
+
+    // Objects
+
+    fn class_name_of(&self, object: *mut PhpObject) -> String {
+        let id = unsafe { (*object).class_id as usize };
+        self.ctx.class_names[id].clone()
+    }
+
+    fn layout_of(&self, object: *mut PhpObject) -> &Layout {
+        &self.layouts[&self.ctx.class_names[unsafe { (*object).class_id as usize }]]
+    }
+
+    fn slot_index(&self, object: *mut PhpObject, name: &str) -> Option<usize> {
+        self.layout_of(object).slots.iter().position(|slot| slot == name)
+    }
+
+    /// The declaration of a property, from the object's class or a parent
+    fn property_def(&self, class: &str, name: &str) -> Option<(&PropertyDef, String)> {
+        let mut current = Some(class.to_string());
+        while let Some(class) = current {
+            let def = self.ctx.classes.get(&class)?;
+            if let Some(property) = def.properties.get(name) {
+                return Some((property, class));
+            }
+            current = def.parent.clone();
+        }
+        None
+    }
+
+    fn property_visible(&self, object: *mut PhpObject, name: &str) -> bool {
+        let class = self.class_name_of(object);
+        match self.property_def(&class, name) {
+            Some((property, declaring)) => self.visible(property.visibility, &declaring),
+            None => true,
+        }
+    }
+
+    /// Whether the running code may see a member declared in `declaring`
+    fn visible(&self, visibility: Visibility, declaring: &str) -> bool {
+        let scope = self.frames.last().unwrap().class.as_deref();
+        match (visibility, scope) {
+            (Visibility::Public, _) => true,
+            (_, None) => false,
+            (Visibility::Private, Some(scope)) => scope == declaring,
+            (Visibility::Protected, Some(scope)) => self.is_subclass(scope, declaring) || self.is_subclass(declaring, scope),
+        }
+    }
+
+    /// A property for `isset()`, `empty()` and `??`: `None` if it is unset.
+    /// An undeclared property is set when `__isset()` says so, and is then
+    /// read through `__get()` unless only `isset()` asks.
+    fn property_quiet(&mut self, value: Value, name: &str, isset: bool) -> Eval<Option<Value>> {
+        if value.get_type() != ValueType::Object {
+            return Ok(None);
+        }
+        let object = unsafe { value.data.ptr as *mut PhpObject };
+        let Some(index) = self.slot_index(object, name) else {
+            let class = self.class_name_of(object);
+            let Some(method) = self.find_method(&class, "__isset") else { return Ok(None) };
+            let key = self.string_value(name);
+            if !self.call_body(method.body, vec![key], Some(object), Some(class))?.to_bool() {
+                return Ok(None);
+            }
+            return match isset {
+                true => Ok(Some(Value::bool(true))),
+                false => self.get_property(value, name).map(Some),
+            };
+        };
+        if !self.property_visible(object, name) {
+            return Ok(None);
+        }
+        let slot = unsafe { (*(*object).properties.add(index)).value };
+        Ok((!slot.is_null()).then(|| read(slot)))
+    }
+
+    fn expect_object(&self, value: Value, property: &str, action: &str) -> Eval<*mut PhpObject> {
+        match value.get_type() {
+            ValueType::Object => Ok(unsafe { value.data.ptr as *mut PhpObject }),
+            other => Err(self.error(format!(
+                "Attempt to {} property \"{}\" on {}", action, property, operations::type_name(other)
+            ))),
+        }
+    }
+
+    /// The slot of a declared property the running code may access
+    fn accessible_slot(&self, object: *mut PhpObject, name: &str) -> Eval<usize> {
+        let class = self.class_name_of(object);
+        let Some(index) = self.slot_index(object, name) else {
+            return Err(self.error(format!("Cannot create dynamic property {}::${}", class, name)));
+        };
+        if let Some((property, declaring)) = self.property_def(&class, name) {
+            if !self.visible(property.visibility, &declaring) {
+                let visibility = if property.visibility == Visibility::Private { "private" } else { "protected" };
+                return Err(self.error(format!("Cannot access {} property {}::${}", visibility, class, name)));
+            }
+        }
+        Ok(index)
+    }
+
+    fn get_property(&mut self, value: Value, name: &str) -> Eval<Value> {
+        if value.get_type() != ValueType::Object {
+            return Ok(Value::null());
+        }
+        let object = unsafe { value.data.ptr as *mut PhpObject };
+        let class = self.class_name_of(object);
+        if self.slot_index(object, name).is_none() {
+            if let Some(method) = self.find_method(&class, "__get") {
+                let name = self.string_value(name);
+                return self.call_body(method.body, vec![name], Some(object), Some(class));
+            }
+            return Ok(Value::null());
+        }
+        let index = self.accessible_slot(object, name)?;
+        let slot = unsafe { (*(*object).properties.add(index)).value };
+        if slot.is_null() {
+            return Err(self.error(format!("Typed property {}::${} must not be accessed before initialization", class, name)));
+        }
+        Ok(read(slot))
+    }
+
+    fn set_property(&mut self, value: Value, name: &str, new: Value) -> Eval<()> {
+        let object = self.expect_object(value, name, "assign")?;
+        let class = self.class_name_of(object);
+        if self.slot_index(object, name).is_none() {
+            if let Some(method) = self.find_method(&class, "__set") {
+                let name = self.string_value(name);
+                self.call_body(method.body, vec![name, new], Some(object), Some(class))?;
+                return Ok(());
+            }
+        }
+        let index = self.accessible_slot(object, name)?;
+        let slot = unsafe { (*(*object).properties.add(index)).value };
+        if self.layout_of(object).readonly.contains(name) {
+            let declaring = self.property_def(&class, name).map(|(_, declaring)| declaring).unwrap_or_default();
+            if !slot.is_null() {
+                return Err(self.error(format!("Cannot modify readonly property {}::${}", class, name)));
+            }
+            if self.frames.last().unwrap().class.as_deref() != Some(declaring.as_str()) {
+                let scope = match &self.frames.last().unwrap().class {
+                    Some(scope) => format!("scope {}", scope),
+                    None => "global scope".to_string(),
+                };
+                return Err(self.error(format!("Cannot initialize readonly property {}::${} from {}", class, name, scope)));
+            }
+        }
+        if slot.is_null() {
+            self.init_property(object, index, name, new);
+        } else {
+            write(slot, new);
+        }
+        Ok(())
+    }
+
+    fn init_property(&mut self, object: *mut PhpObject, index: usize, name: &str, value: Value) {
+        let slot = self.ctx.memory.alloc_value();
+        unsafe {
+            *slot = value;
+        }
+        let name_id = self.property_id(name);
+        unsafe {
+            self.ctx.memory.object_set_property(object, index, name_id, slot);
+        }
+    }
+
+    fn instantiate(&mut self, class: &str, args: &[Expression]) -> Eval<Value> {
+        let class = self.class_of_name(class)?;
+        let layout = &self.layouts[&class];
+        if layout.is_interface {
+            return Err(self.error(format!("Cannot instantiate interface {}", class)));
+        }
+        if layout.is_abstract {
+            return Err(self.error(format!("Cannot instantiate abstract class {}", class)));
+        }
+        if !layout.cases.is_empty() || self.is_subclass(&class, "UnitEnum") {
+            return Err(self.error(format!("Cannot instantiate enum {}", class)));
+        }
+        let (class_id, slots) = (layout.class_id, layout.slots.clone());
+
+        let object = self.ctx.memory.alloc_object(class_id, slots.len());
+        let value = self.temp(Value::object(object));
+        for (index, name) in slots.iter().enumerate() {
+            let default = self.property_def(&class, name).and_then(|(property, _)| property.default_value);
+            let name_id = self.property_id(name);
+            match default {
+                Some(default) => {
+                    let value = self.owned(read(default));
+                    self.init_property(object, index, name, value);
+                }
+                None => unsafe {
+                    self.ctx.memory.object_set_property(object, index, name_id, std::ptr::null_mut());
+                },
+            }
+        }
+
+        let args = self.eval_args(args)?;
+        if let Some(constructor) = self.find_method(&class, "__construct") {
+            if !self.visible(constructor.visibility, &constructor.class) {
+                let visibility = if constructor.visibility == Visibility::Private { "private" } else { "protected" };
+                return Err(self.error(format!("Call to {} {}::__construct() from {}", visibility, class, self.scope_name())));
+            }
+            self.call_body(constructor.body, args, Some(object), Some(class))?;
+        }
+        Ok(value)
+    }
+
+    fn clone_object(&mut self, value: Value) -> Eval<Value> {
+        if value.get_type() != ValueType::Object {
+            return Err(self.error("__clone method called on non-object".to_string()));
+        }
+        let object = unsafe { value.data.ptr as *mut PhpObject };
+        let class = self.class_name_of(object);
+        let slots = self.layout_of(object).slots.clone();
+        let copy = self.ctx.memory.alloc_object(unsafe { (*object).class_id }, slots.len());
+        let result = self.temp(Value::object(copy));
+        for (index, name) in slots.iter().enumerate() {
+            let slot = unsafe { (*(*object).properties.add(index)).value };
+            if slot.is_null() {
+                let name_id = self.property_id(name);
+                unsafe { self.ctx.memory.object_set_property(copy, index, name_id, std::ptr::null_mut()) };
+            } else {
+                let value = self.owned(read(slot));
+                self.init_property(copy, index, name, value);
+            }
+        }
+        if let Some(method) = self.find_method(&class, "__clone") {
+            self.call_body(method.body, Vec::new(), Some(copy), Some(class))?;
+        }
+        Ok(result)
+    }
+
+    fn find_static_property(&self, class: &str, name: &str) -> Option<*mut Value> {
+        let mut current = Some(class.to_string());
+        while let Some(class) = current {
+            if let Some(&slot) = self.static_properties.get(&(class.clone(), name.to_string())) {
+                return Some(slot);
+            }
+            current = self.ctx.classes.get(&class)?.parent.clone();
+        }
+        None
+    }
+
+    fn static_property(&mut self, class: &str, name: &str) -> Eval<*mut Value> {
+        let class = self.class_of_name(class)?;
+        let slot = self.find_static_property(&class, name)
+            .ok_or_else(|| self.error(format!("Access to undeclared static property {}::${}", class, name)))?;
+        if let Some((property, declaring)) = self.property_def(&class, name) {
+            if !self.visible(property.visibility, &declaring) {
+                let visibility = if property.visibility == Visibility::Private { "private" } else { "protected" };
+                return Err(self.error(format!("Cannot access {} property {}::${}", visibility, class, name)));
+            }
+        }
+        Ok(slot)
+    }
+
+    fn class_constant(&mut self, class: &str, name: &str) -> Eval<Value> {
+        if name == "class" {
+            let class = match class.to_ascii_lowercase().as_str() {
+                "self" | "static" | "parent" => self.class_of_name(class)?,
+                _ => self.resolve_class_name(class).unwrap_or_else(|| class.trim_start_matches('\\').to_string()),
+            };
+            return Ok(self.string_value(&class));
+        }
+        let class = self.class_of_name(class)?;
+        match self.find_constant(&class, name) {
+            Some(slot) => Ok(read(slot)),
+            None => Err(self.error(format!("Undefined constant {}::{}", class, name))),
+        }
+    }
+
+    fn find_constant(&self, class: &str, name: &str) -> Option<*mut Value> {
+        let def = self.ctx.classes.get(class)?;
+        if let Some(&slot) = def.constants.get(name) {
+            return Some(slot);
+        }
+        def.parent.iter().chain(&def.interfaces).find_map(|class| self.find_constant(class, name))
+    }
+
+    fn scope_name(&self) -> String {
+        match &self.frames.last().unwrap().class {
+            Some(class) => format!("scope {}", class),
+            None => "global scope".to_string(),
+        }
+    }
+
+    // Calls
+
+    fn find_method(&self, class: &str, name: &str) -> Option<MethodRef> {
+        let key = name.to_ascii_lowercase();
+        let mut current = Some(class.to_string());
+        while let Some(class) = current {
+            let def = self.ctx.classes.get(&class)?;
+            if let Some(method) = def.methods.get(&key) {
+                if !method.is_abstract {
+                    return Some(MethodRef {
+                        body: method.function.body_addr,
+                        name: method.name.clone(),
+                        class,
+                        visibility: method.visibility,
+                        is_static: method.is_static,
+                        is_abstract: false,
+                    });
+                }
+            }
+            current = def.parent.clone();
+        }
+        // Only abstract: a call fails, but `method_exists` sees it
+        let def = self.ctx.classes.get(class)?;
+        def.methods.get(&key).map(|method| MethodRef {
+            body: usize::MAX,
+            name: method.name.clone(),
+            class: class.to_string(),
+            visibility: method.visibility,
+            is_static: method.is_static,
+            is_abstract: true,
+        })
+    }
+
+    fn eval_args(&mut self, args: &[Expression]) -> Eval<Vec<Value>> {
+        args.iter().map(|arg| self.eval_owned(arg)).collect()
+    }
+
+    fn call_function(&mut self, name: &str, args: &[Expression]) -> Eval<Value> {
+        let base = name.trim_start_matches('\\');
+        let base = base.rsplit('\\').next().unwrap_or(base).to_ascii_lowercase();
+        if let Some(value) = self.call_special(&base, args)? {
+            return Ok(value);
+        }
+        if let Some(key) = self.resolve_function_name(name) {
+            let body = self.ctx.functions[&key].body_addr;
+            let args = self.eval_args(args)?;
+            return self.call_body(body, args, None, None);
+        }
+        if let Some(&builtin) = self.ctx.builtins.get(&base) {
+            let mut slots = Vec::with_capacity(args.len());
+            for (index, arg) in args.iter().enumerate() {
+                if index == 0 && BY_REFERENCE.contains(&base.as_str()) {
+                    slots.push(self.lvalue(arg)?);
+                } else {
+                    let value = self.eval(arg)?;
+                    let slot = self.ctx.memory.alloc_value();
+                    write(slot, value);
+                    slots.push(slot);
+                }
+            }
+            let result = builtin(&mut self.ctx, &slots)?;
+            return Ok(unsafe { *result });
+        }
+        Err(self.error(format!("Call to undefined function {}()", name.trim_start_matches('\\'))))
+    }
+
+    fn call_builtin(&mut self, name: &str, args: &[Value]) -> Eval<Option<Value>> {
+        let Some(&builtin) = self.ctx.builtins.get(name) else { return Ok(None) };
+        let slots: Vec<*mut Value> = args.iter().map(|&value| {
+            let slot = self.ctx.memory.alloc_value();
+            write(slot, value);
+            slot
+        }).collect();
+        let result = builtin(&mut self.ctx, &slots)?;
+        Ok(Some(unsafe { *result }))
+    }
+
+    fn call_method(&mut self, value: Value, name: &str, args: &[Expression]) -> Eval<Value> {
+        if value.get_type() != ValueType::Object {
+            return Err(self.error(format!(
+                "Call to a member function {}() on {}", name, operations::type_name(value.get_type())
+            )));
+        }
+        let object = unsafe { value.data.ptr as *mut PhpObject };
+        let args = self.eval_args(args)?;
+        self.call_method_values(object, name, args)
+    }
+
+    fn call_method_values(&mut self, object: *mut PhpObject, name: &str, args: Vec<Value>) -> Eval<Value> {
+        let class = self.class_name_of(object);
+        let Some(method) = self.find_method(&class, name) else {
+            if let Some(call) = self.find_method(&class, "__call") {
+                let name = self.string_value(name);
+                let array = self.values_array(&args);
+                return self.call_body(call.body, vec![name, array], Some(object), Some(class));
+            }
+            return Err(self.error(format!("Call to undefined method {}::{}()", class, name)));
+        };
+        self.check_method(&method, &class)?;
+        let this = (!method.is_static).then_some(object);
+        self.call_body(method.body, args, this, Some(class))
+    }
+
+    fn check_method(&self, method: &MethodRef, class: &str) -> Eval<()> {
+        if method.is_abstract {
+            return Err(self.error(format!("Cannot call abstract method {}::{}()", method.class, method.name)));
+        }
+        if !self.visible(method.visibility, &method.class) {
+            let visibility = if method.visibility == Visibility::Private { "private" } else { "protected" };
+            return Err(self.error(format!("Call to {} method {}::{}() from {}", visibility, class, method.name, self.scope_name())));
+        }
+        Ok(())
+    }
+
+    fn call_static(&mut self, class_name: &str, name: &str, args: &[Expression]) -> Eval<Value> {
+        let class = self.class_of_name(class_name)?;
+        let args = self.eval_args(args)?;
+        let forwarding = matches!(class_name.to_ascii_lowercase().as_str(), "self" | "parent" | "static");
+        self.call_static_values(&class, name, args, forwarding)
+    }
+
+    /// `Class::method()`: `parent::` and `self::` keep `$this` and the
+    /// class `static::` refers to
+    fn call_static_values(&mut self, class: &str, name: &str, args: Vec<Value>, forwarding: bool) -> Eval<Value> {
+        let Some(method) = self.find_method(class, name) else {
+            if self.is_subclass(class, "UnitEnum") {
+                return self.enum_method(class, name, &args);
+            }
+            return Err(self.error(format!("Call to undefined method {}::{}()", class, name)));
+        };
+        self.check_method(&method, class)?;
+        let frame = self.frames.last().unwrap();
+        let static_class = match forwarding {
+            true => frame.static_class.clone().unwrap_or_else(|| class.to_string()),
+            false => class.to_string(),
+        };
+        if method.is_static {
+            return self.call_body(method.body, args, None, Some(static_class));
+        }
+        match frame.this {
+            Some(this) if self.is_subclass(&self.class_name_of(this), &method.class) => {
+                self.call_body(method.body, args, Some(this), Some(static_class))
+            }
+            _ => Err(self.error(format!("Non-static method {}::{}() cannot be called statically", method.class, method.name))),
+        }
+    }
+
+    /// `cases()`, `from()` and `tryFrom()`, which every enum has
+    fn enum_method(&mut self, class: &str, name: &str, args: &[Value]) -> Eval<Value> {
+        let cases: Vec<*mut Value> = self.layouts[class].cases.iter()
+            .map(|case| self.ctx.classes[class].constants[case])
+            .collect();
+        match name.to_ascii_lowercase().as_str() {
+            "cases" => {
+                let array = self.new_array();
+                for case in cases {
+                    self.array_insert(array, None, read(case));
+                }
+                Ok(Value::array(array))
+            }
+            method @ ("from" | "tryfrom") if self.is_subclass(class, "BackedEnum") => {
+                let wanted = args.first().copied().unwrap_or_else(Value::null);
+                for case in cases {
+                    let object = unsafe { (*case).data.ptr as *mut PhpObject };
+                    let value = read(unsafe { (*(*object).properties.add(1)).value });
+                    if operations::loose_equals(&value, &wanted) {
+                        return Ok(read(case));
+                    }
+                }
+                match method {
+                    "tryfrom" => Ok(Value::null()),
+                    _ => {
+                        let shown = match wanted.get_type() {
+                            ValueType::String => format!("\"{}\"", wanted.to_php_string()),
+                            _ => wanted.to_php_string(),
+                        };
+                        Err(self.error_of("ValueError", format!("{} is not a valid backing value for enum {}", shown, class)))
+                    }
+                }
+            }
+            _ => Err(self.error(format!("Call to undefined method {}::{}()", class, name))),
+        }
+    }
+
+    /// Call a callable value: a function name, `"Class::method"`, an
+    /// `[object or class, method]` pair or an object with `__invoke`
+    fn call_value(&mut self, callee: Value, args: Vec<Value>) -> Eval<Value> {
+        match callee.get_type() {
+            ValueType::String => {
+                let name = callee.to_php_string();
+                if let Some((class, method)) = name.split_once("::") {
+                    let class = self.class_of_name(class)?;
+                    return self.call_static_values(&class, method, args, false);
+                }
+                if let Some(key) = self.resolve_function_name(&name) {
+                    let body = self.ctx.functions[&key].body_addr;
+                    return self.call_body(body, args, None, None);
+                }
+                let base = name.trim_start_matches('\\').to_ascii_lowercase();
+                if let Some(value) = self.call_builtin(&base, &args)? {
+                    return Ok(value);
+                }
+                Err(self.error(format!("Call to undefined function {}()", name)))
+            }
+            ValueType::Array => {
+                let array = unsafe { callee.data.ptr as *mut PhpArray };
+                let target = self.index(callee, Value::int(0))?;
+                let method = self.index(callee, Value::int(1))?;
+                if unsafe { (*array).size } != 2 || method.get_type() != ValueType::String {
+                    return Err(self.error("Array callback must have exactly two elements".to_string()));
+                }
+                let method = method.to_php_string();
+                match target.get_type() {
+                    ValueType::Object => self.call_method_values(unsafe { target.data.ptr as *mut PhpObject }, &method, args),
+                    _ => {
+                        let class = self.class_of_name(&target.to_php_string())?;
+                        self.call_static_values(&class, &method, args, false)
+                    }
+                }
+            }
+            ValueType::Object => {
+                let object = unsafe { callee.data.ptr as *mut PhpObject };
+                self.call_method_values(object, "__invoke", args)
+            }
+            other => Err(self.error(format!("Value of type {} is not callable", operations::type_name(other)))),
+        }
+    }
+
+    fn is_callable(&self, callee: Value) -> bool {
+        match callee.get_type() {
+            ValueType::String => {
+                let name = callee.to_php_string();
+                match name.split_once("::") {
+                    Some((class, method)) => self.resolve_class_name(class).is_some_and(|class| self.find_method(&class, method).is_some()),
+                    None => self.resolve_function_name(&name).is_some()
+                        || self.ctx.builtins.contains_key(&name.trim_start_matches('\\').to_ascii_lowercase()),
+                }
+            }
+            ValueType::Object => {
+                let object = unsafe { callee.data.ptr as *mut PhpObject };
+                self.find_method(&self.class_name_of(object), "__invoke").is_some()
+            }
+            ValueType::Array => {
+                let array = unsafe { callee.data.ptr as *mut PhpArray };
+                let entries: Vec<Value> = unsafe { MemoryManager::array_entries(array).map(|entry| read((*entry).value)).collect() };
+                let [target, method] = entries.as_slice() else { return false };
+                let class = match target.get_type() {
+                    ValueType::Object => Some(self.class_name_of(unsafe { target.data.ptr as *mut PhpObject })),
+                    _ => self.resolve_class_name(&target.to_php_string()),
+                };
+                class.is_some_and(|class| self.find_method(&class, &method.to_php_string()).is_some())
+            }
+            _ => false,
+        }
+    }
+
+    /// Run a function or method body with its arguments bound
+    fn call_body(&mut self, body: usize, args: Vec<Value>, this: Option<*mut PhpObject>, static_class: Option<String>) -> Eval<Value> {
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(Abort::Error(PhpError {
+                level: ErrorLevel::Fatal,
+                class: "Error",
+                message: format!("Maximum function nesting level of '{}' reached, aborting!", MAX_CALL_DEPTH),
+                file: None,
+                line: self.line,
+            }));
+        }
+        self.tick()?;
+        let body = self.bodies[body].clone();
+        let index = self.bodies.iter().position(|b| Rc::ptr_eq(b, &body));
+        let line = self.line;
+        self.ctx.push_scope(true);
+        self.frames.push(Frame {
+            body: index,
+            function: body.name.clone(),
+            class: body.class.clone(),
+            static_class: static_class.or_else(|| body.class.clone()),
+            this,
+        });
+        let result = self.run_body(&body, args, this);
+        self.frames.pop();
+        self.ctx.pop_scope();
+        self.line = line;
+        let value = result?;
+        Ok(self.temp(value))
+    }
+
+    fn run_body(&mut self, body: &Body, mut args: Vec<Value>, this: Option<*mut PhpObject>) -> Eval<Value> {
+        for &arg in &args {
+            self.temp(arg);
+        }
+        if let Some(this) = this {
+            let slot = self.ctx.memory.alloc_value();
+            write(slot, Value::object(this));
+            self.ctx.set_variable("this".to_string(), slot);
+        }
+
+        let function = match &body.class {
+            Some(class) => format!("{}::{}", class, body.name),
+            None => body.name.clone(),
+        };
+        let required = body.params.iter().rposition(|param| param.default.is_none()).map_or(0, |index| index + 1);
+        if args.len() < required {
+            let expected = if required == body.params.len() { "exactly" } else { "at least" };
+            return Err(self.error_of("ArgumentCountError", format!(
+                "Too few arguments to function {}(), {} passed and {} {} expected", function, args.len(), expected, required
+            )));
+        }
+
+        for (index, param) in body.params.iter().enumerate() {
+            let value = match index < args.len() {
+                true => std::mem::replace(&mut args[index], Value::null()),
+                false => self.eval_owned(param.default.as_ref().unwrap())?,
+            };
+            let value = match &param.param_type {
+                Some(ty) => self.coerce(ty, value).ok_or_else(|| self.error_of("TypeError", format!(
+                    "{}(): Argument #{} (${}) must be of type {}, {} given",
+                    function, index + 1, param.name, type_string(ty), operations::type_name(value.get_type())
+                )))?,
+                None => value,
+            };
+            let slot = self.ctx.memory.alloc_value();
+            write(slot, value);
+            self.ctx.set_variable(param.name.clone(), slot);
+            if let (Some(_), Some(this)) = (&param.promoted, this) {
+                let index = self.slot_index(this, &param.name).expect("promoted property slot");
+                let value = self.owned(value);
+                self.init_property(this, index, &param.name, value);
+            }
+        }
+
+        let value = match self.exec_block(&body.block.statements)? {
+            Flow::Return(value) => value,
+            _ => Value::null(),
+        };
+        match &body.return_type {
+            Some(Type::Void) | None => Ok(value),
+            Some(ty) => self.coerce(ty, value).ok_or_else(|| self.error_of("TypeError", format!(
+                "{}(): Return value must be of type {}, {} returned",
+                function, type_string(ty), operations::type_name(value.get_type())
+            ))),
+        }
+    }
+
+    /// A scalar argument converted to a scalar parameter type as PHP's
+    /// coercive typing mode does; `None` if it cannot be
+    fn coerce(&mut self, ty: &Type, value: Value) -> Option<Value> {
+        let actual = value.get_type();
+        let strict = self.strict_types;
+        match ty {
+            Type::Nullable(inner) => match actual {
+                ValueType::Null => Some(value),
+                _ => self.coerce(inner, value),
+            },
+            Type::Int => match actual {
+                ValueType::Int => Some(value),
+                ValueType::Float if !strict && value.to_float().fract() == 0.0 && value.to_float().is_finite() => Some(Value::int(value.to_int())),
+                ValueType::Bool if !strict => Some(Value::int(value.to_int())),
+                ValueType::String if !strict => match value.to_number() {
+                    Some((crate::value::Number::Int(i), true)) => Some(Value::int(i)),
+                    Some((crate::value::Number::Float(f), true)) if f.fract() == 0.0 => Some(Value::int(f as i64)),
+                    _ => None,
+                },
+                _ => None,
+            },
+            Type::Float => match actual {
+                ValueType::Float => Some(value),
+                ValueType::Int => Some(Value::float(value.to_float())),
+                ValueType::Bool if !strict => Some(Value::float(value.to_float())),
+                ValueType::String if !strict => match value.to_number() {
+                    Some((number, true)) => Some(Value::float(number.to_float())),
+                    _ => None,
+                },
+                _ => None,
+            },
+            Type::String => match actual {
+                ValueType::String => Some(value),
+                ValueType::Int | ValueType::Float | ValueType::Bool if !strict => Some(self.string_value(&value.to_php_string())),
+                _ => None,
+            },
+            Type::Bool => match actual {
+                ValueType::Bool => Some(value),
+                ValueType::Int | ValueType::Float | ValueType::String if !strict => Some(Value::bool(value.to_bool())),
+                _ => None,
+            },
+            Type::Array => (actual == ValueType::Array).then_some(value),
+            _ => Some(value),
+        }
+    }
+
+    /// Functions that work on the interpreter rather than the context:
+    /// language constructs, callbacks and class introspection
+    fn call_special(&mut self, name: &str, args: &[Expression]) -> Eval<Option<Value>> {
+        let value = match name {
+            "isset" => {
+                for arg in args {
+                    let value = match arg {
+                        Expression::PropertyAccess { object, property } | Expression::NullsafePropertyAccess { object, property } => {
+                            match self.eval_quiet(object)? {
+                                Some(object) => self.property_quiet(object, property, true)?,
+                                None => None,
+                            }
+                        }
+                        arg => self.eval_quiet(arg)?,
+                    };
+                    match value {
+                        Some(value) if value.get_type() != ValueType::Null => {}
+                        _ => return Ok(Some(Value::bool(false))),
+                    }
+                }
+                Value::bool(true)
+            }
+            "empty" => {
+                let arg = args.first().ok_or_else(|| self.error("empty() expects exactly 1 argument".to_string()))?;
+                Value::bool(!self.eval_quiet(arg)?.is_some_and(|value| value.to_bool()))
+            }
+            "unset" => {
+                for arg in args {
+                    self.unset(arg)?;
+                }
+                Value::null()
+            }
+            "exit" | "die" => {
+                if let Some(arg) = args.first() {
+                    let value = self.eval(arg)?;
+                    if value.get_type() == ValueType::String {
+                        self.ctx.output(value.as_bytes().unwrap_or_default());
+                    }
+                }
+                return Err(Abort::Exit);
+            }
+            "compact" => {
+                let names = self.eval_args(args)?;
+                let array = self.new_array();
+                for name in names {
+                    let name = name.to_php_string();
+                    if let Some(slot) = self.variable(&name) {
+                        let value = self.owned(read(slot));
+                        let key = self.string_value(&name);
+                        let key = self.ctx.memory.array_key(&key);
+                        self.array_insert(array, Some(key), value);
+                    }
+                }
+                Value::array(array)
+            }
+            "usort" | "uasort" | "uksort" => {
+                if args.len() < 2 {
+                    return Err(self.error_of("ArgumentCountError", format!("{}() expects exactly 2 arguments, {} given", name, args.len())));
+                }
+                let slot = unsafe { deref(self.lvalue(&args[0])?) };
+                let callback = self.eval(&args[1])?;
+                self.sort_with(name, slot, callback)?;
+                Value::bool(true)
+            }
+            _ => {
+                if !SPECIAL_FUNCTIONS.contains(&name) {
+                    return Ok(None);
+                }
+                let args = self.eval_args(args)?;
+                self.call_special_values(name, args)?
+            }
+        };
+        Ok(Some(value))
+    }
+
+    fn call_special_values(&mut self, name: &str, args: Vec<Value>) -> Eval<Value> {
+        let arg = |index: usize| args.get(index).copied().unwrap_or_else(Value::null);
+        let expect = |this: &Self, count: usize| match args.len() < count {
+            true => Err(this.error(format!("{}() expects at least {} argument{}, {} given", name, count, if count == 1 { "" } else { "s" }, args.len()))),
+            false => Ok(()),
+        };
+        Ok(match name {
+            "define" => {
+                expect(self, 2)?;
+                let constant = arg(0).to_php_string();
+                if self.ctx.constants.contains_key(&constant) {
+                    return Ok(Value::bool(false));
+                }
+                let value = self.owned(arg(1));
+                self.define_constant(&constant, value);
+                Value::bool(true)
+            }
+            "defined" => {
+                expect(self, 1)?;
+                Value::bool(self.ctx.constants.contains_key(&arg(0).to_php_string()))
+            }
+            "constant" => {
+                expect(self, 1)?;
+                let constant = arg(0).to_php_string();
+                match constant.split_once("::") {
+                    Some((class, name)) => self.class_constant(class, name)?,
+                    None => self.constant(&constant).ok_or_else(|| self.error(format!("Undefined constant \"{}\"", constant)))?,
+                }
+            }
+            "function_exists" => {
+                expect(self, 1)?;
+                let function = arg(0).to_php_string();
+                Value::bool(self.resolve_function_name(&function).is_some()
+                    || self.ctx.builtins.contains_key(&function.trim_start_matches('\\').to_ascii_lowercase())
+                    || SPECIAL_FUNCTIONS.contains(&function.to_ascii_lowercase().as_str()))
+            }
+            "class_exists" | "interface_exists" | "enum_exists" => {
+                expect(self, 1)?;
+                let class = self.resolve_class_name(&arg(0).to_php_string());
+                Value::bool(class.is_some_and(|class| {
+                    let layout = &self.layouts[&class];
+                    let is_enum = self.is_subclass(&class, "UnitEnum");
+                    match name {
+                        "interface_exists" => layout.is_interface,
+                        "enum_exists" => is_enum,
+                        _ => !layout.is_interface && !is_enum,
+                    }
+                }))
+            }
+            "method_exists" | "property_exists" => {
+                expect(self, 2)?;
+                let class = match arg(0).get_type() {
+                    ValueType::Object => Some(self.class_name_of(unsafe { arg(0).data.ptr as *mut PhpObject })),
+                    _ => self.resolve_class_name(&arg(0).to_php_string()),
+                };
+                let member = arg(1).to_php_string();
+                Value::bool(class.is_some_and(|class| match name {
+                    "method_exists" => self.find_method(&class, &member).is_some(),
+                    _ => self.property_def(&class, &member).is_some(),
+                }))
+            }
+            "get_class" => match arg(0).get_type() {
+                ValueType::Object => {
+                    let class = self.class_name_of(unsafe { arg(0).data.ptr as *mut PhpObject });
+                    self.string_value(&class)
+                }
+                _ if args.is_empty() => match self.frames.last().unwrap().class.clone() {
+                    Some(class) => self.string_value(&class),
+                    None => return Err(self.error("get_class() without arguments must be called from within a class".to_string())),
+                },
+                other => return Err(self.error_of("TypeError", format!(
+                    "get_class(): Argument #1 ($object) must be of type object, {} given", operations::type_name(other)
+                ))),
+            },
+            "get_parent_class" => {
+                let class = match arg(0).get_type() {
+                    ValueType::Object => Some(self.class_name_of(unsafe { arg(0).data.ptr as *mut PhpObject })),
+                    ValueType::Null => self.frames.last().unwrap().class.clone(),
+                    _ => self.resolve_class_name(&arg(0).to_php_string()),
+                };
+                match class.and_then(|class| self.ctx.classes[&class].parent.clone()) {
+                    Some(parent) => self.string_value(&parent),
+                    None => Value::bool(false),
+                }
+            }
+            "is_a" | "is_subclass_of" => {
+                expect(self, 2)?;
+                let class = match arg(0).get_type() {
+                    ValueType::Object => Some(self.class_name_of(unsafe { arg(0).data.ptr as *mut PhpObject })),
+                    _ => self.resolve_class_name(&arg(0).to_php_string()),
+                };
+                let ancestor = self.resolve_class_name(&arg(1).to_php_string());
+                Value::bool(match (class, ancestor) {
+                    (Some(class), Some(ancestor)) => self.is_subclass(&class, &ancestor) && (name == "is_a" || class != ancestor),
+                    _ => false,
+                })
+            }
+            "spl_object_id" => match arg(0).get_type() {
+                ValueType::Object => Value::int(unsafe { (*(arg(0).data.ptr as *mut PhpObject)).handle } as i64),
+                _ => return Err(self.error_of("TypeError", "spl_object_id(): Argument #1 ($object) must be of type object".to_string())),
+            },
+            "is_callable" => {
+                expect(self, 1)?;
+                Value::bool(self.is_callable(arg(0)))
+            }
+            "call_user_func" => {
+                expect(self, 1)?;
+                self.call_value(arg(0), args[1..].to_vec())?
+            }
+            "call_user_func_array" => {
+                expect(self, 2)?;
+                let values = self.array_values(arg(1), name)?;
+                self.call_value(arg(0), values.into_iter().map(|(_, value)| value).collect())?
+            }
+            "array_map" => {
+                expect(self, 2)?;
+                let callback = arg(0);
+                let result = self.new_array();
+                if args.len() == 2 {
+                    for (key, value) in self.array_values(arg(1), name)? {
+                        let mapped = match callback.get_type() {
+                            ValueType::Null => value,
+                            _ => self.call_value(callback, vec![value])?,
+                        };
+                        let key = self.ctx.memory.array_key(&key);
+                        self.array_insert(result, Some(key), mapped);
+                    }
+                } else {
+                    let mut arrays = Vec::new();
+                    for index in 1..args.len() {
+                        arrays.push(self.array_values(arg(index), name)?);
+                    }
+                    let longest = arrays.iter().map(Vec::len).max().unwrap_or(0);
+                    for row in 0..longest {
+                        let values: Vec<Value> = arrays.iter().map(|array| array.get(row).map_or(Value::null(), |&(_, value)| value)).collect();
+                        let mapped = match callback.get_type() {
+                            ValueType::Null => {
+                                let tuple = self.values_array(&values);
+                                self.owned(tuple)
+                            }
+                            _ => self.call_value(callback, values)?,
+                        };
+                        self.array_insert(result, None, mapped);
+                    }
+                }
+                Value::array(result)
+            }
+            "array_filter" => {
+                expect(self, 1)?;
+                let callback = arg(1);
+                let mode = arg(2).to_int();
+                let result = self.new_array();
+                for (key, value) in self.array_values(arg(0), name)? {
+                    let keep = match callback.get_type() {
+                        ValueType::Null => value.to_bool(),
+                        _ => {
+                            let args = match mode {
+                                2 => vec![key],
+                                1 => vec![value, key],
+                                _ => vec![value],
+                            };
+                            self.call_value(callback, args)?.to_bool()
+                        }
+                    };
+                    if keep {
+                        let key = self.ctx.memory.array_key(&key);
+                        self.array_insert(result, Some(key), value);
+                    }
+                }
+                Value::array(result)
+            }
+            "array_reduce" => {
+                expect(self, 2)?;
+                let mut carry = arg(2);
+                for (_, value) in self.array_values(arg(0), name)? {
+                    carry = self.call_value(arg(1), vec![carry, value])?;
+                }
+                carry
+            }
+            "array_walk" => {
+                expect(self, 2)?;
+                for (key, value) in self.array_values(arg(0), name)? {
+                    self.call_value(arg(1), vec![value, key])?;
+                }
+                Value::bool(true)
+            }
+            _ => unreachable!("{} is not special", name),
+        })
+    }
+
+    /// An array argument's pairs, with the values copied
+    fn array_values(&mut self, value: Value, function: &str) -> Eval<Vec<(Value, Value)>> {
+        if value.get_type() != ValueType::Array {
+            return Err(self.error_of("TypeError", format!(
+                "{}(): Argument must be of type array, {} given", function, operations::type_name(value.get_type())
+            )));
+        }
+        let pairs: Vec<(Value, Value)> = unsafe {
+            MemoryManager::array_entries(value.data.ptr as *mut PhpArray)
+                .map(|entry| ((*entry).key.to_value(), read((*entry).value)))
+                .collect()
+        };
+        Ok(pairs.into_iter().map(|(key, value)| (self.temp(key), self.owned(value))).collect())
+    }
+
+    /// A new list of `values`
+    fn values_array(&mut self, values: &[Value]) -> Value {
+        let array = self.new_array();
+        for &value in values {
+            self.array_insert(array, None, value);
+        }
+        Value::array(array)
+    }
+
+    /// `usort`, `uasort` and `uksort`: a stable merge sort, since the
+    /// comparison calls back into PHP and may fail
+    fn sort_with(&mut self, name: &str, slot: *mut Value, callback: Value) -> Eval<()> {
+        let value = read(slot);
+        let pairs = self.array_values(value, name)?;
+        let by_key = name == "uksort";
+        let mut sorted = pairs;
+        let mut width = 1;
+        while width < sorted.len() {
+            let mut merged = Vec::with_capacity(sorted.len());
+            for chunk in sorted.chunks(width * 2) {
+                let (mut left, mut right) = (chunk[..width.min(chunk.len())].iter().peekable(), chunk[width.min(chunk.len())..].iter().peekable());
+                while let (Some(&&a), Some(&&b)) = (left.peek(), right.peek()) {
+                    let args = if by_key { vec![a.0, b.0] } else { vec![a.1, b.1] };
+                    if self.call_value(callback, args)?.to_int() > 0 {
+                        merged.push(b);
+                        right.next();
+                    } else {
+                        merged.push(a);
+                        left.next();
+                    }
+                }
+                merged.extend(left.copied());
+                merged.extend(right.copied());
+            }
+            sorted = merged;
+            width *= 2;
+        }
+
+        let array = self.new_array();
+        for (key, value) in sorted {
+            let key = match name {
+                "usort" => None,
+                _ => Some(self.ctx.memory.array_key(&key)),
+            };
+            self.array_insert(array, key, value);
+        }
+        write(slot, Value::array(array));
+        Ok(())
+    }
+
+    fn include(&mut self, path: &str, once: bool, keyword: &str) -> Eval<Value> {
+        let file = PathBuf::from(path);
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+        if once && self.included.contains(&canonical) {
+            return Ok(Value::bool(true));
+        }
+        let source = std::fs::read_to_string(&file)
+            .map_err(|error| self.error(format!("{}(): Failed opening '{}' for inclusion ({})", keyword, path, error)))?;
+        let program = edge_php_parser::parse_with_locations(&source)
+            .map_err(|error| self.error(format!("{}: {}", path, error)))?;
+        self.included.insert(canonical);
+
+        let line = self.line;
+        let result = self.hoist_program(&program).and_then(|()| {
+            for item in &program.items {
+                match item {
+                    ProgramItem::InlineContent(text) => self.ctx.output(text.as_bytes()),
+                    ProgramItem::PhpBlock { statements } => {
+                        if let Flow::Return(value) = self.exec_block(statements)? {
+                            return Ok(value);
+                        }
+                    }
+                }
+            }
+            Ok(Value::int(1))
+        });
+        self.line = line;
+        result
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a class statement declares
+#[derive(Clone, Copy)]
+enum ClassKind {
+    Class { is_readonly: bool },
+    Interface,
+    Enum { is_backed: bool },
+}
+
+/// Functions the interpreter implements itself, on evaluated arguments
+const SPECIAL_FUNCTIONS: &[&str] = &[
+    "define", "defined", "constant", "function_exists", "class_exists", "interface_exists", "enum_exists",
+    "method_exists", "property_exists", "get_class", "get_parent_class", "is_a", "is_subclass_of",
+    "spl_object_id", "is_callable", "call_user_func", "call_user_func_array", "array_map", "array_filter",
+    "array_reduce", "array_walk",
+];
+
+fn is_class(statement: &Statement) -> bool {
+    matches!(statement, Statement::Class { .. } | Statement::Interface { .. } | Statement::Enum { .. })
+}
+
+fn function_def(name: &str, params: &[Parameter], body_addr: usize, return_type: Option<&Type>) -> FunctionDef {
+    FunctionDef {
+        name: name.to_string(),
+        params: params.iter().map(|param| ParamDef {
+            name: param.name.clone(),
+            type_hint: param.param_type.as_ref().map(type_string),
+            default_value: None,
+            is_reference: false,
+        }).collect(),
+        body_addr,
+        is_variadic: false,
+        return_type: return_type.map(type_string),
+    }
+}
+
+fn visibility_of(visibility: &edge_php_parser::ast::Visibility) -> Visibility {
+    match visibility {
+        edge_php_parser::ast::Visibility::Public => Visibility::Public,
+        edge_php_parser::ast::Visibility::Protected => Visibility::Protected,
+        edge_php_parser::ast::Visibility::Private => Visibility::Private,
+    }
+}
+
+/// A type as PHP writes it in messages
+fn type_string(ty: &Type) -> String {
+    match ty {
+        Type::Int => "int".to_string(),
+        Type::Float => "float".to_string(),
+        Type::String => "string".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Array => "array".to_string(),
+        Type::Object(name) => name.clone(),
+        Type::Mixed => "mixed".to_string(),
+        Type::Void => "void".to_string(),
+        Type::Null => "null".to_string(),
+        Type::False => "false".to_string(),
+        Type::True => "true".to_string(),
+        Type::Never => "never".to_string(),
+        Type::Iterable => "iterable".to_string(),
+        Type::Callable => "callable".to_string(),
+        Type::Nullable(inner) => format!("?{}", type_string(inner)),
+        Type::Union(types) => types.iter().map(type_string).collect::<Vec<_>>().join("|"),
+        Type::Intersection(types) => types.iter().map(type_string).collect::<Vec<_>>().join("&"),
+    }
+}
+
+/// `$s++` on a non-numeric string: `"a"` to `"b"`, `"Az"` to `"Ba"`, `"zz"` to `"aaa"`
+fn increment_string(text: &str) -> String {
+    let mut bytes = text.as_bytes().to_vec();
+    let mut index = bytes.len();
+    while index > 0 {
+        index -= 1;
+        let (wrapped, first) = match bytes[index] {
+            b'z' => (b'a', b'a'),
+            b'Z' => (b'A', b'A'),
+            b'9' => (b'0', b'1'),
+            byte if byte.is_ascii_alphanumeric() => {
+                bytes[index] = byte + 1;
+                return String::from_utf8_lossy(&bytes).into_owned();
+            }
+            _ => return String::from_utf8_lossy(&bytes).into_owned(),
+        };
+        bytes[index] = wrapped;
+        if index == 0 {
+            bytes.insert(0, first);
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+unsafe fn deref(mut slot: *mut Value) -> *mut Value {
+    while (*slot).get_type() == ValueType::Reference {
+        slot = (*slot).data.ptr as *mut Value;
+    }
+    slot
+}
+
+/// Whether an expression is `$GLOBALS`, whose elements are the global variables
+fn is_globals(expr: &Expression) -> bool {
+    matches!(expr, Expression::Variable(name) if name == "GLOBALS")
+}
+
+/// The value in a slot, through references
+fn read(slot: *mut Value) -> Value {
+    unsafe { *deref(slot) }
+}
+
+/// Store a value in a slot, or in what the slot refers to
+fn write(slot: *mut Value, value: Value) {
+    unsafe {
+        *deref(slot) = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Runtime;
+
+    fn interpret(source: &str) -> anyhow::Result<String> {
+        Runtime::new().unwrap().interpret_php(source)
+    }
+
+    /// Compiled and interpreted, a script prints the same
+    fn assert_same_output(source: &str) {
+        let compiled = Runtime::new().unwrap().execute_php(source).unwrap();
+        let interpreted = interpret(source).unwrap();
+        assert_eq!(interpreted, compiled, "for {}", source);
+    }
+
+    #[test]
+    fn test_matches_compiled_output() {
+        assert_same_output(r#"<?php
+function fib($n) { return $n < 2 ? $n : fib($n - 1) + fib($n - 2); }
+for ($i = 0; $i < 10; $i++) { echo fib($i), " "; }
+$total = 0;
+$i = 0;
+while ($i < 100) { $total += $i; $i++; }
+echo $total, "\n";
+"#);
+        assert_same_output(r#"<?php
+$words = ["apple", "pear"];
+$words[2] = "plum";
+foreach ($words as $i => $word) {
+    echo $i, ": ", strtoupper($word), " ", strlen($word) * 2, "\n";
+}
+echo count($words), " ", $words[2], "\n";
+"#);
+        assert_same_output(r#"<?php
+class Counter {
+    private $count = 0;
+    public function add($n) { $this->count = $this->count + $n; return $this; }
+    public function get() { return $this->count; }
+}
+$c = new Counter();
+echo $c->add(2)->add(40)->get(), "\n";
+switch ($c->get()) {
+    case 1: echo "one"; break;
+    case 42: echo "answer";
+    default: echo "!";
+}
+"#);
+    }
+
+    #[test]
+    fn test_arrays_are_values() {
+        let output = interpret(r#"<?php
+$a = [1, 2];
+$b = $a;
+array_push($b, 3);
+$b[0] = 9;
+$nested['x']['y'] = 1;
+echo count($a), count($b), $a[0], $b[0], $nested['x']['y'];
+"#).unwrap();
+        assert_eq!(output, "23191");
+    }
+
+    #[test]
+    fn test_classes_and_enums() {
+        let output = interpret(r#"<?php
+interface Shape { public function area(): int; }
+class Square implements Shape {
+    public function __construct(private readonly int $size) {}
+    public static function make(int $size): static { return new static($size); }
+    public function area(): int { return $this->size * $this->size; }
+    public function describe(): string { return static::class . " " . $this->area(); }
+}
+enum Suit: string {
+    case Hearts = 'H';
+    case Spades = 'S';
+    public function label(): string { return ucfirst(strtolower($this->name)); }
+}
+echo Square::make("3")->describe(), "\n";
+echo Suit::from('S')->label(), " ", count(Suit::cases()), " ";
+var_dump(Suit::tryFrom('X'));
+"#).unwrap();
+        assert_eq!(output, "Square 9\nSpades 2 NULL\n");
+    }
+
+    #[test]
+    fn test_magic_property_methods() {
+        let output = interpret(r#"<?php
+class Bag {
+    private $items = ['a' => 1, 'n' => null];
+    public function __get($name) { return $this->items[$name] ?? 'none'; }
+    public function __set($name, $value) { $this->items[$name] = $value; }
+    public function __isset($name) { echo "isset($name) "; return array_key_exists($name, $this->items); }
+    public function __unset($name) { echo "unset($name) "; unset($this->items[$name]); }
+}
+$bag = new Bag();
+$bag->b = 2;
+var_dump(isset($bag->a), isset($bag->n), isset($bag->z));
+var_dump(empty($bag->a), $bag->b ?? 'default');
+unset($bag->a);
+var_dump(isset($bag->a));
+"#).unwrap();
+        assert_eq!(output, "isset(a) isset(n) isset(z) bool(true)\nbool(true)\nbool(false)\n\
+            isset(a) isset(b) bool(false)\nint(2)\nunset(a) isset(a) bool(false)\n");
+    }
+
+    #[test]
+    fn test_globals_array() {
+        let output = interpret(r#"<?php
+$count = 1;
+function bump() {
+    $GLOBALS['count'] += 100;
+    $GLOBALS['x'] = 'g';
+    return isset($GLOBALS['missing']) ? 'set' : 'unset';
+}
+echo bump(), " ", $count, " ", $x, " ", $GLOBALS['x'], " ", array_key_exists('x', $GLOBALS), "\n";
+unset($GLOBALS['x']);
+var_dump(isset($x));
+"#).unwrap();
+        assert_eq!(output, "unset 101 g g 1\nbool(false)\n");
+    }
+
+    #[test]
+    fn test_errors_carry_the_line() {
+        let error = interpret("<?php\n$a = 1;\nundefined_function($a);\n").unwrap_err();
+        let error = error.downcast::<PhpError>().unwrap();
+        assert_eq!(error.message, "Call to undefined function undefined_function()");
+        assert_eq!(error.line, Some(3));
+
+        let error = interpret(r#"<?php
+class Point { public function __construct(public readonly int $x) {} }
+$p = new Point(1);
+$p->x = 2;
+"#).unwrap_err();
+        assert!(error.to_string().contains("Cannot modify readonly property Point::$x"), "{}", error);
+    }
+
+    #[test]
+    fn test_errors_report_their_class() {
+        let cases = [
+            ("function half(int $n) { return $n / 2; } half('x');", "Uncaught TypeError: half(): Argument #1 ($n) must be of type int, string given"),
+            ("function name(): string { return []; } name();", "Uncaught TypeError: name(): Return value must be of type string, array returned"),
+            ("function two($a, $b) {} two(1);", "Uncaught ArgumentCountError: Too few arguments to function two(), 1 passed and exactly 2 expected"),
+            ("enum Suit: string { case Hearts = 'H'; } Suit::from('X');", "Uncaught ValueError: \"X\" is not a valid backing value for enum Suit"),
+            ("echo 1 % 0;", "Uncaught DivisionByZeroError: Modulo by zero"),
+            ("explode('', 'a');", "Uncaught ValueError: explode(): Argument #1 ($separator) cannot be empty"),
+            ("undefined_function();", "Uncaught Error: Call to undefined function undefined_function()"),
+        ];
+        for (source, message) in cases {
+            let error = interpret(&format!("<?php\n{}\n", source)).unwrap_err();
+            assert_eq!(error.to_string(), format!("PHP Fatal error:  {} on line 2", message));
+        }
+    }
+
+    #[test]
+    fn test_callbacks() {
+        let output = interpret(r#"<?php
+function double($x) { return $x * 2; }
+function by_length($a, $b) { return strlen($a) - strlen($b); }
+$names = ["ccc", "a", "bb"];
+usort($names, 'by_length');
+echo implode(",", $names), " ";
+echo implode(",", array_map('double', [1, 2, 3])), " ";
+echo array_reduce([1, 2, 3, 4], 'max', 0), " ";
+echo call_user_func_array('str_repeat', ["ab", 2]);
+"#).unwrap();
+        assert_eq!(output, "a,bb,ccc 2,4,6 4 abab");
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut runtime = Runtime::with_limits(Limits::new().fuel(10_000)).unwrap();
+        let error = runtime.interpret_php("<?php echo 'a'; while (true) {}").unwrap_err();
+        assert!(matches!(error.downcast_ref::<FatalError>(), Some(FatalError::OutOfFuel { .. })));
+        assert_eq!(runtime.output(), "a");
+
+        let mut runtime = Runtime::with_limits(Limits::new().memory_limit(8 << 20)).unwrap();
+        let error = runtime.interpret_php(r#"<?php
+$a = [];
+for ($i = 0; $i < 10000000; $i++) { $a[$i] = str_repeat("x", 100) . $i; }
+"#).unwrap_err();
+        assert!(matches!(error.downcast_ref::<FatalError>(), Some(FatalError::MemoryExhausted { .. })), "{}", error);
+    }
+
+    #[test]
+    fn test_garbage_is_collected() {
+        let mut interpreter = Interpreter::new();
+        let program = edge_php_parser::parse_with_locations(r#"<?php
+$keep = [];
+for ($i = 0; $i < 200000; $i++) {
+    $tmp = [str_repeat("x", 64), $i];
+    if ($i % 1000 == 0) { array_push($keep, $tmp); }
+}
+echo count($keep), " ", $keep[199][1];
+"#).unwrap();
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.output(), "200 199000");
+        assert!(interpreter.context().memory.stats().live_bytes < GC_THRESHOLD * 2);
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        let output = interpret("<?php function down($n) { return $n == 0 ? 0 : 1 + down($n - 1); } echo down(3000);").unwrap();
+        assert_eq!(output, "3000");
+        let error = interpret("<?php function forever($n) { return forever($n + 1); } forever(0);").unwrap_err();
+        assert!(error.to_string().contains("Maximum function nesting level"), "{}", error);
+    }
+
+    #[test]
+    fn test_increment_string() {
+        assert_eq!(increment_string("a"), "b");
+        assert_eq!(increment_string("Az"), "Ba");
+        assert_eq!(increment_string("zz"), "aaa");
+        assert_eq!(increment_string("a9"), "b0");
+    }
+}
@@ -6,62 +6,402 @@ pub mod memory;
 pub mod context;
 pub mod operations;
 pub mod extension;
+pub mod limits;
+pub mod http;
+pub mod host;
+pub mod interpreter;
+
+pub use host::HostValue;
+pub use http::{Request, Response, UploadedFile};
+pub use limits::{FatalError, Limits};
 
 use anyhow::Result;
+use edge_php_compiler::{CompilerOptions, ImportType, ImportedFunction};
+use extension::{ExtensionManager, ExtensionManifest};
+use host::HostFunction;
+use limits::MemoryLimiter;
+use std::sync::mpsc;
+use std::sync::Arc;
+use wasmtime::{Caller, Config, Engine, ExternType, FuncType, Instance, Linker, Module, Store, Trap, Val};
+
+/// Fuel for handing over buffered output after a script ran out
+const FLUSH_FUEL: i64 = 1 << 20;
+
+/// Stack for the interpreter thread, enough for [`interpreter::MAX_CALL_DEPTH`] calls
+const INTERPRETER_STACK_SIZE: usize = 256 << 20;
 
+/// Runs compiled PHP modules in wasmtime, within the configured [`Limits`]
 pub struct Runtime {
-    wasm_bytes: Vec<u8>,
+    engine: Engine,
+    module: Option<Module>,
+    limits: Limits,
     output: String,
+    extensions: Vec<LinkedExtension>,
+    host_functions: Vec<HostFunction>,
+}
+
+/// A Wasm extension compiled for this runtime's engine
+struct LinkedExtension {
+    module: Module,
+    manifest: ExtensionManifest,
+}
+
+/// What a running module can reach through its store
+struct HostState {
+    output: Vec<u8>,
+    limiter: MemoryLimiter,
 }
 
 impl Runtime {
     pub fn new() -> Result<Self> {
+        Self::with_limits(Limits::new())
+    }
+
+    pub fn with_limits(limits: Limits) -> Result<Self> {
+        let mut config = Config::new();
+        config.wasm_gc(true);
+        config.wasm_function_references(true);
+        config.wasm_reference_types(true);
+        config.wasm_tail_call(true);
+        config.wasm_exceptions(true);
+        // The execution time limit interrupts the module at the next epoch
+        config.epoch_interruption(true);
         Ok(Runtime {
-            wasm_bytes: Vec::new(),
+            engine: Engine::new(&config)?,
+            module: None,
+            limits,
             output: String::new(),
+            extensions: Vec::new(),
+            host_functions: Vec::new(),
         })
     }
 
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Make the manager's Wasm extensions available to the PHP compiled from
+    /// now on: their functions, static methods and constants resolve where
+    /// the script has none of its own. Every run gets fresh instances.
+    pub fn link_extensions(&mut self, manager: &ExtensionManager) -> Result<()> {
+        let mut extensions = Vec::new();
+        for wasm in manager.wasm_extensions() {
+            let manifest = wasm.manifest.clone();
+            let module = Module::new(&self.engine, &wasm.bytes)
+                .map_err(|e| anyhow::anyhow!("extension '{}': {}", manifest.name, e))?;
+            for function in manifest.callables() {
+                let expected = host::wasm_type(&self.engine, &function.params, function.returns);
+                match module.get_export(&function.export) {
+                    Some(ExternType::Func(ty)) if FuncType::eq(&ty, &expected) => {}
+                    _ => anyhow::bail!(
+                        "extension '{}' does not export {} as {}",
+                        manifest.name, function.export, expected
+                    ),
+                }
+            }
+            let strings = manifest.callables()
+                .any(|function| function.params.contains(&ImportType::String) || function.returns == Some(ImportType::String));
+            if strings && (module.get_export("memory").is_none() || module.get_export("edge_php_alloc").is_none()) {
+                anyhow::bail!("extension '{}' passes strings but does not export memory and edge_php_alloc", manifest.name);
+            }
+            extensions.push(LinkedExtension { module, manifest });
+        }
+        self.extensions = extensions;
+        Ok(())
+    }
+
+    /// Let PHP compiled from now on call `callback` as the function declared
+    /// by `signature`, like `kv_get(string $key): string` (see [`host`]).
+    /// Defining a name again replaces the function.
+    pub fn define_function<F>(&mut self, signature: &str, callback: F) -> Result<()>
+    where
+        F: Fn(&[HostValue]) -> Result<HostValue> + Send + Sync + 'static,
+    {
+        let signature = ImportedFunction::host(signature).map_err(|e| anyhow::anyhow!(e))?;
+        self.host_functions.retain(|function| function.signature.name != signature.name);
+        self.host_functions.push(HostFunction { signature, callback: Arc::new(callback) });
+        Ok(())
+    }
+
+    /// The options PHP gets compiled with, declaring the host functions and
+    /// what extensions provide
+    pub fn compiler_options(&self) -> CompilerOptions {
+        let mut options = CompilerOptions::new().fuel_metering(self.limits.get_fuel().is_some());
+        for function in &self.host_functions {
+            options = options.import_function(function.signature.clone());
+        }
+        for extension in &self.extensions {
+            for function in extension.manifest.callables() {
+                options = options.import_function(ImportedFunction {
+                    module: extension.manifest.name.clone(),
+                    name: function.name.clone(),
+                    params: function.params.clone(),
+                    returns: function.returns,
+                });
+            }
+            for (name, value) in &extension.manifest.constants {
+                options = options.constant(name.clone(), value.clone());
+            }
+        }
+        options
+    }
+
     pub fn load_module(&mut self, wasm_bytes: &[u8]) -> Result<()> {
-        self.wasm_bytes = wasm_bytes.to_vec();
-        // In a full implementation, we would use wasmtime here
-        // For now, we'll just store the bytes
-        self.output = format!("Module loaded: {} bytes", wasm_bytes.len());
+        self.module = Some(Module::new(&self.engine, wasm_bytes)?);
         Ok(())
     }
 
+    /// Run the loaded module's `_start` and return what it printed. When a
+    /// limit stops it, the error is a [`FatalError`] and [`Runtime::output`]
+    /// still has what was printed until then.
     pub fn execute(&mut self) -> Result<String> {
-        // Placeholder execution
+        self.run(|store, instance| {
+            let start = instance.get_typed_func::<(), ()>(&mut *store, "_start")?;
+            start.call(store, ())
+        })?;
         Ok(self.output.clone())
     }
 
+    /// Serve one request with a module compiled as an HTTP handler
+    /// (`CompilerOptions::http_handler`). Every request gets a fresh instance.
+    pub fn handle_request(&mut self, request: &Request) -> Result<Response> {
+        let encoded = request.encode();
+        self.run(|store, instance| {
+            let memory = instance.get_memory(&mut *store, "memory")
+                .ok_or_else(|| anyhow::anyhow!("the module exports no memory"))?;
+            let offset = instance.get_global(&mut *store, "request_buffer")
+                .and_then(|global| global.get(&mut *store).i32())
+                .ok_or_else(|| anyhow::anyhow!("the module is not an HTTP handler"))? as usize;
+            let needed = offset + encoded.len();
+            if needed > memory.data_size(&*store) {
+                let pages = (needed - memory.data_size(&*store)).div_ceil(1 << 16);
+                memory.grow(&mut *store, pages as u64)?;
+            }
+            memory.write(&mut *store, offset, &encoded)?;
+
+            let handle = instance.get_typed_func::<i32, i32>(&mut *store, "handle_request")?;
+            let ptr = handle.call(&mut *store, encoded.len() as i32)?;
+            Response::decode(memory.data(&*store), ptr as u32 as usize)
+                .ok_or_else(|| anyhow::anyhow!("the module returned a malformed response"))
+        })
+    }
+
+    /// Instantiate the loaded module and call into it with the limits in place
+    fn run<T>(&mut self, call: impl FnOnce(&mut Store<HostState>, &Instance) -> Result<T>) -> Result<T> {
+        let module = self.module.as_ref().ok_or_else(|| anyhow::anyhow!("no module is loaded"))?;
+        let mut store = Store::new(&self.engine, HostState {
+            output: Vec::new(),
+            limiter: MemoryLimiter { limit: self.limits.get_memory_limit(), exhausted: None },
+        });
+        store.limiter(|state| &mut state.limiter);
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_trap();
+
+        let mut linker = Linker::new(&self.engine);
+        linker.func_wrap("env", "print", |mut caller: Caller<'_, HostState>, ptr: i32| {
+            let Some(memory) = caller.get_export("memory").and_then(|export| export.into_memory()) else {
+                return;
+            };
+            let (data, state) = memory.data_and_store_mut(&mut caller);
+            let text = data.get(ptr as usize..).unwrap_or_default();
+            let len = text.iter().position(|&byte| byte == 0).unwrap_or(text.len());
+            state.output.extend_from_slice(&text[..len]);
+        })?;
+        for function in &self.host_functions {
+            let ty = host::import_type(&self.engine, &function.signature.params, function.signature.returns);
+            let function = function.clone();
+            linker.func_new("env", &function.signature.name.clone(), ty, move |mut caller, params, results| {
+                function.call(&mut caller, params, results)
+            })?;
+        }
+
+        let result = self.link_extension_instances(&mut store, &mut linker)
+            .and_then(|()| linker.instantiate(&mut store, module))
+            .and_then(|instance| {
+            // Modules compiled without fuel metering have no fuel to set
+            if let (Some(fuel), Some(global)) = (self.limits.get_fuel(), instance.get_global(&mut store, "fuel")) {
+                global.set(&mut store, Val::I64(fuel.min(i64::MAX as u64) as i64))?;
+            }
+
+            // A watchdog thread ends the epoch once the time is up, unless the
+            // script finishes first and hangs up
+            let (done, timer) = mpsc::channel::<()>();
+            if let Some(limit) = self.limits.get_max_execution_time() {
+                let engine = self.engine.clone();
+                std::thread::spawn(move || {
+                    if let Err(mpsc::RecvTimeoutError::Timeout) = timer.recv_timeout(limit) {
+                        engine.increment_epoch();
+                    }
+                });
+            }
+            let result = call(&mut store, &instance);
+            drop(done);
+
+            let out_of_fuel = instance.get_global(&mut store, "fuel")
+                .and_then(|global| global.get(&mut store).i64())
+                .is_some_and(|fuel| fuel < 0);
+            let exhausted = store.data_mut().limiter.exhausted.take();
+            if result.is_err() {
+                Self::flush_output(&mut store, &instance);
+            }
+            result.map_err(|error| self.fatal_error(error, out_of_fuel, exhausted))
+        });
+
+        self.output = String::from_utf8_lossy(&store.data().output).into_owned();
+        result
+    }
+
+    /// Instantiate the extensions, dependencies first, and define their
+    /// functions for the PHP module, copying strings between the memories
+    fn link_extension_instances(&self, store: &mut Store<HostState>, linker: &mut Linker<HostState>) -> Result<()> {
+        let mut extension_linker = Linker::new(&self.engine);
+        for extension in &self.extensions {
+            let name = &extension.manifest.name;
+            let instance = extension_linker.instantiate(&mut *store, &extension.module)
+                .map_err(|e| anyhow::anyhow!("extension '{}': {}", name, e))?;
+            if let Ok(initialize) = instance.get_typed_func::<(), ()>(&mut *store, "_initialize") {
+                initialize.call(&mut *store, ())?;
+            }
+            extension_linker.instance(&mut *store, name, instance)?;
+
+            let memory = instance.get_memory(&mut *store, "memory");
+            let alloc = instance.get_typed_func::<i32, i32>(&mut *store, "edge_php_alloc").ok();
+            for function in extension.manifest.callables() {
+                let Some(export) = instance.get_func(&mut *store, &function.export) else {
+                    anyhow::bail!("extension '{}' does not export {}", name, function.export);
+                };
+                let function = function.clone();
+                let ty = host::import_type(&self.engine, &function.params, function.returns);
+                let result_count = host::wasm_type(&self.engine, &function.params, function.returns).results().len();
+                let alloc = alloc.clone();
+                linker.func_new(name, &function.name.clone(), ty, move |mut caller, params, results| {
+                    let strings = || anyhow::anyhow!("{}() passes strings but the extension exports no memory", function.name);
+
+                    let mut args = Vec::with_capacity(params.len());
+                    let mut params = params.iter();
+                    for ty in &function.params {
+                        let Some(param) = params.next() else { break };
+                        if *ty != ImportType::String {
+                            args.push(*param);
+                            continue;
+                        }
+                        let len = params.next().map_or(0, Val::unwrap_i32);
+                        let bytes = host::read_string(&mut caller, param.unwrap_i32(), len)?;
+                        let (Some(memory), Some(alloc)) = (memory, &alloc) else {
+                            return Err(strings());
+                        };
+                        let target = alloc.call(&mut caller, len)?;
+                        memory.write(&mut caller, target as u32 as usize, &bytes)?;
+                        args.push(Val::I32(target));
+                        args.push(Val::I32(len));
+                    }
+
+                    let mut returned = vec![Val::I32(0); result_count];
+                    export.call(&mut caller, &args, &mut returned)?;
+
+                    if function.returns == Some(ImportType::String) {
+                        let memory = memory.ok_or_else(strings)?;
+                        let ptr = returned[0].unwrap_i32() as u32 as usize;
+                        let len = returned[1].unwrap_i32() as u32 as usize;
+                        let bytes = memory.data(&caller).get(ptr..ptr + len)
+                            .ok_or_else(|| anyhow::anyhow!("{}() returned a string out of bounds", function.name))?
+                            .to_vec();
+                        results[0] = host::write_string(&mut caller, &bytes)?;
+                    } else if let Some(value) = returned.pop() {
+                        results[0] = value;
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Output the script still had buffered when it trapped: with a little
+    /// fuel and time to spare, the module hands it over through `flush_output`
+    fn flush_output(store: &mut Store<HostState>, instance: &Instance) {
+        let Ok(flush) = instance.get_typed_func::<(), ()>(&mut *store, "flush_output") else {
+            return;
+        };
+        if let Some(global) = instance.get_global(&mut *store, "fuel") {
+            let _ = global.set(&mut *store, Val::I64(FLUSH_FUEL));
+        }
+        store.set_epoch_deadline(1);
+        // The trap already is the error to report
+        let _ = flush.call(&mut *store, ());
+    }
+
+    /// What the last execution printed, also when it failed
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// Turn a trap caused by one of the limits into the matching fatal error
+    fn fatal_error(&self, error: wasmtime::Error, out_of_fuel: bool, exhausted: Option<FatalError>) -> wasmtime::Error {
+        if error.is::<FatalError>() {
+            return error;
+        }
+        if let Some(exhausted) = exhausted {
+            return exhausted.into();
+        }
+        match (error.downcast_ref::<Trap>(), self.limits.get_max_execution_time(), self.limits.get_fuel()) {
+            (Some(Trap::Interrupt), Some(limit), _) => FatalError::ExecutionTimeExceeded { limit }.into(),
+            (Some(Trap::UnreachableCodeReached), _, Some(fuel)) if out_of_fuel => FatalError::OutOfFuel { fuel }.into(),
+            _ => error,
+        }
+    }
+
+    /// Compile and run a script, with fuel metering when there is a fuel limit
     pub fn execute_php(&mut self, source: &str) -> Result<String> {
         use edge_php_compiler::Compiler;
-        
-        let compiler = Compiler::new();
-        let wasm_bytes = compiler.compile(source)?;
-        
+
+        let wasm_bytes = Compiler::with_options(self.compiler_options()).compile(source)?;
+
         self.load_module(&wasm_bytes)?;
-        
-        // For demonstration, show compilation result
-        Ok(format!(
-            "Compiled PHP to WASM successfully!
-\
-             Source: {} chars
-\
-             WASM: {} bytes
+        self.execute()
+    }
+
+    /// Run a script with the [`interpreter`] instead of compiling it, within
+    /// the same limits. Host functions and Wasm extensions are not available
+    /// to it; errors are reported like [`Runtime::execute`] reports them.
+    pub fn interpret_php(&mut self, source: &str) -> Result<String> {
+        let program = edge_php_parser::parse_with_locations(source)?;
+        let limits = self.limits;
+
+        // Deep PHP recursion is deep Rust recursion here
+        let (output, result) = std::thread::Builder::new()
+            .name("php-interpreter".to_string())
+            .stack_size(INTERPRETER_STACK_SIZE)
+            .spawn(move || {
+                let mut interpreter = interpreter::Interpreter::with_limits(limits);
+                let result = interpreter.run(&program);
+                (interpreter.output().into_owned(), result)
+            })?
+            .join()
+            .map_err(|_| anyhow::anyhow!("interpreter thread panicked"))?;
+
+        self.output = output;
+        result.map(|()| self.output.clone())
+    }
+
+    /// Compile a script as an HTTP handler and load it for [`Runtime::handle_request`]
+    pub fn load_php_handler(&mut self, source: &str) -> Result<()> {
+        use edge_php_compiler::Compiler;
 
-\
-             Note: Full runtime execution requires wasmtime integration.",
-            source.len(),
-            wasm_bytes.len()
-        ))
+        let options = self.compiler_options().http_handler(true);
+        let wasm_bytes = Compiler::with_options(options).compile(source)?;
+        self.load_module(&wasm_bytes)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[test]
     fn test_runtime_basic() {
@@ -74,4 +414,474 @@ echo $x + $y;
         
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_runtime_output() {
+        let mut runtime = Runtime::new().unwrap();
+        let output = runtime.execute_php("<?php echo 5 + 10, \"\\n\";").unwrap();
+        assert_eq!(output, "15\n");
+    }
+
+    #[test]
+    fn test_fuel_limit() {
+        let mut runtime = Runtime::with_limits(Limits::new().fuel(10_000)).unwrap();
+        let error = runtime.execute_php("<?php echo \"start\"; while (true) { }").unwrap_err();
+        assert_eq!(error.downcast_ref::<FatalError>(), Some(&FatalError::OutOfFuel { fuel: 10_000 }));
+        assert_eq!(runtime.output(), "start");
+    }
+
+    #[test]
+    fn test_execution_time_limit() {
+        let limit = Duration::from_millis(100);
+        let mut runtime = Runtime::with_limits(Limits::new().max_execution_time(limit)).unwrap();
+        let error = runtime.execute_php("<?php $i = 0; while (true) { $i++; }").unwrap_err();
+        assert_eq!(error.downcast_ref::<FatalError>(), Some(&FatalError::ExecutionTimeExceeded { limit }));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let script = "<?php $s = \"\"; for ($i = 0; $i < 1000000; $i++) { $s = $s . \"xxxxxxxxxxxxxxxx\"; }";
+        let mut runtime = Runtime::with_limits(Limits::new().memory_limit(1 << 20)).unwrap();
+        let error = runtime.execute_php(script).unwrap_err();
+        assert!(matches!(error.downcast_ref::<FatalError>(), Some(FatalError::MemoryExhausted { limit: 1048576, .. })));
+
+        // The linear-memory backend grows its own memory
+        let options = edge_php_compiler::CompilerOptions::new().backend(edge_php_compiler::BackendKind::LinearMemory);
+        let wasm = edge_php_compiler::Compiler::with_options(options).compile(script).unwrap();
+        runtime.set_limits(Limits::new().memory_limit(4 << 20));
+        runtime.load_module(&wasm).unwrap();
+        let error = runtime.execute().unwrap_err();
+        assert!(matches!(error.downcast_ref::<FatalError>(), Some(FatalError::MemoryExhausted { limit: 4194304, .. })));
+    }
+
+    #[test]
+    fn test_http_handler() {
+        let mut runtime = Runtime::new().unwrap();
+        runtime.load_php_handler(r#"<?php
+function greet() {
+    return "Hello, " . $_GET['name'] . " (" . $_SERVER['REQUEST_METHOD'] . ")";
+}
+if (isset($_COOKIE['seen'])) {
+    http_response_code(404);
+}
+header("Content-Type: text/plain");
+header("X-Tags: a");
+header("X-Tags: b", false);
+setcookie("last", "a b", 86400, "/");
+echo greet(), "\n";
+if (isset($_POST['items'])) {
+    echo $_POST['items'][1], file_get_contents('php://input');
+}
+header("X-Late: ignored");
+"#).unwrap();
+
+        let request = Request::new("POST", "/hello?name=World")
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("items[]=x&items[]=y")
+            .time(0);
+        let response = runtime.handle_request(&request).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("content-type"), Some("text/plain"));
+        assert_eq!(response.headers_named("X-Tags").collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(
+            response.header("Set-Cookie"),
+            Some("last=a%20b; expires=Fri, 02 Jan 1970 00:00:00 GMT; Max-Age=86400; path=/")
+        );
+        assert_eq!(response.header("X-Late"), None);
+        assert_eq!(response.body, b"Hello, World (POST)\nyitems[]=x&items[]=y");
+
+        let response = runtime.handle_request(&Request::new("GET", "/?name=Again").header("Cookie", "seen=1")).unwrap();
+        assert_eq!(response.status, 404);
+        assert!(response.body.starts_with(b"Hello, Again (GET)"));
+    }
+
+    #[test]
+    fn test_http_redirect() {
+        let mut runtime = Runtime::new().unwrap();
+        runtime.load_php_handler("<?php header('Location: /login'); header('HTTP/1.1 418 Teapot'); header('Bad: a\r\nInjected: b');").unwrap();
+        let response = runtime.handle_request(&Request::new("GET", "/")).unwrap();
+        assert_eq!(response.status, 418);
+        assert_eq!(response.headers, [("Location".to_string(), "/login".to_string())]);
+    }
+
+    #[test]
+    fn test_output_buffering() {
+        let mut runtime = Runtime::new().unwrap();
+        let output = runtime.execute_php(r#"<?php
+function shout($buffer, $phase) {
+    return strtoupper($buffer) . "[" . $phase . "]";
+}
+echo "a", ob_get_level();
+ob_start();
+echo "b";
+ob_start("shout");
+echo "c", ob_get_level();
+ob_flush();
+echo "d";
+$inner = ob_get_clean();
+$outer = ob_get_contents();
+ob_end_flush();
+echo ob_get_clean() === false ? "none" : "?", "|", $inner, "|", $outer, "|";
+ob_start("shout");
+echo "e";
+"#).unwrap();
+        assert_eq!(output, "a0bC2[5]none|d|bC2[5]|E[9]");
+    }
+
+    #[test]
+    fn test_output_callback_in_variable() {
+        let mut runtime = Runtime::new().unwrap();
+        let output = runtime.execute_php(r#"<?php
+function wrap($buffer) {
+    return "<" . $buffer . ">";
+}
+function total($a, $b, $c) {
+    return $a + $b + $c;
+}
+$handler = "wrap";
+ob_start($handler);
+echo "a";
+ob_end_flush();
+$none = null;
+ob_start($none);
+echo "b";
+ob_end_flush();
+$handler = "total";
+ob_start($handler);
+"#);
+        assert!(output.is_err());
+        assert_eq!(
+            runtime.output(),
+            "<a>bPHP Fatal error:  Uncaught TypeError: ob_start(): Argument #1 ($callback) must be a valid callback or null, function \"total\" not found or invalid function name\n"
+        );
+    }
+
+    #[test]
+    fn test_output_is_batched() {
+        let wasm = edge_php_compiler::Compiler::new()
+            .compile("<?php for ($i = 0; $i < 1000; $i++) { echo $i, \"\\n\"; }")
+            .unwrap();
+        let engine = Runtime::new().unwrap().engine;
+        let mut store = Store::new(&engine, 0);
+        store.set_epoch_deadline(1);
+        let mut linker = Linker::new(&engine);
+        linker.func_wrap("env", "print", |mut caller: Caller<'_, usize>, _: i32| *caller.data_mut() += 1).unwrap();
+        let instance = linker.instantiate(&mut store, &Module::new(&engine, wasm).unwrap()).unwrap();
+        instance.get_typed_func::<(), ()>(&mut store, "_start").unwrap().call(&mut store, ()).unwrap();
+        assert_eq!(*store.data(), 1);
+    }
+
+    #[test]
+    fn test_host_functions() {
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+
+        let store = Arc::new(Mutex::new(HashMap::new()));
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut runtime = Runtime::new().unwrap();
+        let kv = store.clone();
+        runtime.define_function("kv_set(string $key, string $value): void", move |args| {
+            kv.lock().unwrap().insert(args[0].to_php_string(), args[1].to_php_string());
+            Ok(HostValue::Null)
+        }).unwrap();
+        let kv = store.clone();
+        runtime.define_function("kv_get(string $key): string", move |args| {
+            Ok(kv.lock().unwrap().get(args[0].as_str().unwrap_or_default()).cloned().into())
+        }).unwrap();
+        let lines = log.clone();
+        runtime.define_function("log_info(string $message, int $level)", move |args| {
+            lines.lock().unwrap().push(format!("{}:{}", args[1].to_int(), args[0].to_php_string()));
+            Ok(HostValue::Null)
+        }).unwrap();
+        runtime.define_function("feature_enabled(string $name): bool", |args| {
+            Ok((args[0].as_str() == Some("beta")).into())
+        }).unwrap();
+        runtime.define_function("scale(float $x): float", |args| Ok((args[0].to_float() * 1.5).into())).unwrap();
+        runtime.define_function("fail(): int", |_| anyhow::bail!("backend unavailable")).unwrap();
+
+        let output = runtime.execute_php(r#"<?php
+            kv_set("user", "ada " . 42);
+            echo kv_get("user"), "|", kv_get("nobody"), "|";
+            log_info("started", "3");
+            echo feature_enabled("beta") ? "on" : "off", feature_enabled("alpha") ? "on" : "off", "|";
+            echo scale(2), "|", strlen(kv_get("user"));
+        "#).unwrap();
+        assert_eq!(output, "ada 42||onoff|3|6");
+        assert_eq!(store.lock().unwrap()["user"], "ada 42");
+        assert_eq!(*log.lock().unwrap(), ["3:started"]);
+
+        let error = runtime.execute_php("<?php echo 1; fail();").unwrap_err();
+        assert!(format!("{:?}", error).contains("backend unavailable"));
+        assert!(runtime.execute_php("<?php kv_get();").is_err());
+        assert!(runtime.define_function("print(string $s)", |_| Ok(HostValue::Null)).is_ok());
+        assert!(runtime.execute_php("<?php echo 1;").is_err());
+    }
+
+    #[test]
+    fn test_mid_level_ir_matches_ast_path() {
+        let source = r#"<?php
+function sum_to($n) {
+    $total = 0;
+    for ($i = 1; $i <= $n; $i++) {
+        if ($i % 3 == 0 || $i % 5 == 0) {
+            continue;
+        }
+        $total += $i * 2;
+    }
+    return $total;
+}
+function fib($n) {
+    if ($n < 2) {
+        return $n;
+    }
+    return fib($n - 1) + fib($n - 2);
+}
+function mean($a, $b) {
+    return ($a + $b) / 2;
+}
+function halve($x) {
+    $steps = 0;
+    while ($x > 1) {
+        $x = $x / 2;
+        $steps++;
+    }
+    return $steps . ":" . (int) ($x * 1000);
+}
+function describe($n, $unit = "cm") {
+    $label = $n > 10 && $n < 100 ? "medium" : "other";
+    echo strtoupper($label), " ";
+    return strlen($label) . $unit . substr("abcdef", 1, $n % 4);
+}
+function mixed_math($x) {
+    $y = $x;
+    if ($x > 2) {
+        $y = $x / 4;
+    }
+    return abs($y - 10) + min($x, 3) + max(1.5, $y) + round($y * 1.25) + sqrt(16);
+}
+for ($i = 0; $i < 3; $i++) {
+    echo sum_to(10 * $i), " ", fib($i + 5), " ", (int) (mean($i, 2) * 10), " ", (int) (mixed_math($i + 1) * 1000), "\n";
+}
+echo (int) (mean(1.5, 2) * 100), " ", mean("3", 4) * 2, " ", halve(40), " ", describe(42), describe(7, "mm"), "\n";
+"#;
+        let run = |ir: bool| {
+            let mut runtime = Runtime::new().unwrap();
+            let options = runtime.compiler_options().opt_level(edge_php_compiler::OptLevel::O2).mid_level_ir(ir).ir_dump(true);
+            let module = edge_php_compiler::Compiler::with_options(options).compile_module("ir.php", source).unwrap();
+            runtime.load_module(&module.wasm).unwrap();
+            (runtime.execute().unwrap(), module.ir.unwrap())
+        };
+        let (with_ir, listing) = run(true);
+        let (without_ir, _) = run(false);
+        // Every function goes through the IR, so this compares the two paths
+        for function in ["sum_to", "fib", "mean", "halve", "describe", "mixed_math"] {
+            assert!(listing.contains(&format!("function {}(", function)), "{} fell back:\n{}", function, listing);
+        }
+        assert!(!listing.contains("compiled from the AST"), "{}", listing);
+        assert_eq!(with_ir, without_ir);
+    }
+
+    #[test]
+    fn test_inlined_calls_match_calls() {
+        let source = r#"<?php
+$a = 1;
+function bump() { global $a; $a = 10; return 0; }
+function add_bump($x) { return bump() + $x; }
+function twice($x) { return $x * 2; }
+function pair($x, $y = "y") { return $x . "-" . $y; }
+function both($x, $y) { return $x . "+" . $y; }
+echo add_bump($a), " ", $a, "\n";
+$b = 3;
+echo twice($b), " ", twice($b + 1), "\n";
+echo pair("a"), " ", pair("a", "b", "c"), " ", both("a"), "\n";
+"#;
+        let run = |inlining: bool| {
+            let mut runtime = Runtime::new().unwrap();
+            let options = runtime.compiler_options().opt_level(edge_php_compiler::OptLevel::O2).inlining(inlining);
+            let wasm = edge_php_compiler::Compiler::with_options(options).compile(source).unwrap();
+            runtime.load_module(&wasm).unwrap();
+            runtime.execute().unwrap()
+        };
+        // An argument is read before an expression calling a user function
+        // runs, and a call leaving out a parameter stays a call
+        let inlined = run(true);
+        assert_eq!(inlined, run(false));
+        assert_eq!(inlined, "1 10\n6 8\na-y a-b a+\n");
+    }
+
+    #[test]
+    fn test_loop_optimizations_match_plain_loops() {
+        let source = r#"<?php
+$items = [1, 2, 3];
+function grow() { global $items; $items[count($items)] = 0; return 1; }
+function shrink() { global $items; $items = [1, 2]; return 1; }
+function first_above($values, $n, $limit) {
+    for ($i = 0; $i < $n; $i++) {
+        if ($values[$i] > $limit) {
+            return $i;
+        }
+    }
+    return $n;
+}
+for ($i = 0; $i < count($items); $i++) {
+    if ($i < 4) {
+        grow();
+    }
+    echo $i;
+}
+echo " ";
+$i = 0;
+while ($i < count($items)) {
+    shrink();
+    echo $i++;
+}
+echo " ";
+for ($i = 0; $i < 20; $i++) {
+    if ($i == 6) {
+        break;
+    }
+    if ($i % 2 == 1) {
+        continue;
+    }
+    echo $i;
+}
+echo " ", first_above([5, 1, 9, 2, 7, 8, 3, 6, 4, 0], 10, 6), first_above([1, 2], 2, 6), "\n";
+"#;
+        let run = |optimize: bool| {
+            let mut runtime = Runtime::new().unwrap();
+            let options = runtime.compiler_options().opt_level(edge_php_compiler::OptLevel::O2)
+                .loop_optimizations(optimize)
+                .loop_unrolling(optimize);
+            let wasm = edge_php_compiler::Compiler::with_options(options).compile(source).unwrap();
+            runtime.load_module(&wasm).unwrap();
+            runtime.execute().unwrap()
+        };
+        // Counts of arrays a called function changes are read on every round,
+        // and an unrolled copy that leaves the loop skips the copies after it
+        let optimized = run(true);
+        assert_eq!(optimized, run(false));
+        assert_eq!(optimized, "0123456 01 024 22\n");
+    }
+
+    #[test]
+    fn test_arrays_are_values() {
+        let source = r#"<?php
+class Box { public $items = []; public function set($items) { $this->items = $items; } public function get() { return $this->items; } }
+function inner($values) { $values[0] = 99; return $values; }
+function outer($values) { $changed = inner($values); return $changed[0] + $values[0]; }
+
+$grid = [[1, 2], [3, 4]];
+$copy = $grid;
+$copy[0][1] = 20;
+$row = $grid[1];
+$row[0] = 30;
+foreach ($grid as $cells) { $cells[1] = 0; }
+echo $grid[0][1], " ", $grid[1][0], " ", $copy[0][1], " ", $row[0], "\n";
+
+$list = [1, 2, 3];
+$box = new Box();
+$box->set($list);
+$list[0] = 10;
+$held = $box->get();
+$held[1] = 50;
+$box->items[2] = 70;
+echo $list[0], " ", $box->items[0], " ", $box->items[1], " ", $box->items[2], " ", $held[2], "\n";
+
+$args = [1, 2];
+echo outer($args), " ", $args[0], "\n";
+"#;
+        let run = |opt_level| {
+            let mut runtime = Runtime::new().unwrap();
+            let options = runtime.compiler_options().opt_level(opt_level);
+            let wasm = edge_php_compiler::Compiler::with_options(options).compile(source).unwrap();
+            runtime.load_module(&wasm).unwrap();
+            runtime.execute().unwrap()
+        };
+        // Nested arrays, arrays held by properties and arrays passed down two
+        // calls are copied before a holder changes them
+        let optimized = run(edge_php_compiler::OptLevel::O2);
+        assert_eq!(optimized, run(edge_php_compiler::OptLevel::O0));
+        assert_eq!(optimized, "2 3 20 30\n10 1 2 70 3\n100 1\n");
+    }
+
+    #[test]
+    fn test_typed_arrays_match_boxed_arrays() {
+        let source = r#"<?php
+function total($values) { $sum = 0; foreach ($values as $v) { $sum += $v; } return $sum; }
+function show($values) { $out = ""; foreach ($values as $k => $v) { $out = $out . $k . "=" . $v . " "; } return $out; }
+function poke($values) { $values[0] = 100; return $values[0]; }
+
+$ints = [1, 2, 3];
+$ints[1] = "two";
+echo show($ints), $ints[0] + $ints[2], "\n";
+
+$gap = [1, 2, 3];
+$gap[5] = 6;
+$gap[-1] = 7;
+echo count($gap), " ", $gap[5], " ", $gap[-1], " ", isset($gap[4]) ? "set" : "unset", " ", total($gap), "\n";
+
+$named = ["a", "b"];
+$named["x"] = "c";
+$seen = 0;
+foreach ($named as $v) {
+    $seen = $seen + strlen($v);
+}
+echo count($named), " ", $named[0], $named[1], $named["x"], " ", $seen, "\n";
+
+$a = [1, 2, 3];
+$b = $a;
+$b[0] = 9;
+$b[1] = "s";
+echo show($a), "| ", show($b), "\n";
+
+echo poke($a), " ", $a[0], " ", total($a), "\n";
+
+$floats = [1.5, 2.5];
+$floats[0] = 3;
+echo (int) (total($floats) * 10), " ";
+$pushed = [1, 2];
+array_push($pushed, "three", 4.5);
+echo count($pushed), " ", show([$pushed[0], $pushed[1], $pushed[2]]), (int) ($pushed[3] * 2), "\n";
+
+$mixed = [10, 20, 30];
+$mixed[1] = 2.5;
+$seen = "";
+foreach ($mixed as $k => $v) {
+    $seen = $seen . $k . ":" . (int) ($v * 2) . " ";
+}
+echo $seen, "\n";
+
+$rows = [[1, 2], [3, 4]];
+foreach ($rows as $row) { $row[0] = 0; }
+$copy = $rows;
+$copy[1][1] = 8;
+echo $rows[0][0], $rows[1][1], $copy[1][1], "\n";
+"#;
+        let run = |typed_arrays: bool| {
+            let mut runtime = Runtime::new().unwrap();
+            let options = runtime.compiler_options().opt_level(edge_php_compiler::OptLevel::O2).typed_arrays(typed_arrays);
+            let wasm = edge_php_compiler::Compiler::with_options(options).compile(source).unwrap();
+            runtime.load_module(&wasm).unwrap();
+            runtime.execute().unwrap()
+        };
+        // Every write a typed array cannot hold deoptimizes it, and copies
+        // and callees see the values a boxed array would have
+        let typed = run(true);
+        assert_eq!(typed, run(false));
+        assert_eq!(typed, "0=1 1=two 2=3 4\n\
+5 6 7 unset 19\n\
+3 abc 3\n\
+0=1 1=2 2=3 | 0=9 1=s 2=3 \n\
+100 1 6\n\
+55 4 0=1 1=2 2=three 9\n\
+0:20 1:5 2:60 \n\
+148\n");
+    }
+
+    #[test]
+    fn test_fuel_limit_flushes_buffers() {
+        let mut runtime = Runtime::with_limits(Limits::new().fuel(10_000)).unwrap();
+        runtime.execute_php("<?php echo \"start\"; ob_start(); echo \" buffered\"; while (true) { }").unwrap_err();
+        assert_eq!(runtime.output(), "start buffered");
+    }
 }
@@ -0,0 +1,115 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+//! Resource limits for running untrusted PHP modules
+//!
+//! Fuel bounds how much work a module does: it needs a module compiled with
+//! fuel metering (`CompilerOptions::fuel_metering`), which charges one unit on
+//! every function entry and loop iteration. The memory limit caps linear
+//! memory growth and the execution time is wall-clock time, checked by the
+//! engine while the module runs. Hitting any of them ends the script with a
+//! [`FatalError`] worded like PHP's own.
+
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    fuel: Option<u64>,
+    memory_limit: Option<usize>,
+    max_execution_time: Option<Duration>,
+}
+
+impl Limits {
+    /// No limits at all
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Function entries and loop iterations the module may run
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Largest size in bytes the module's memory may grow to (`memory_limit`)
+    pub fn memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Wall-clock time the script may run (`max_execution_time`)
+    pub fn max_execution_time(mut self, time: Duration) -> Self {
+        self.max_execution_time = Some(time);
+        self
+    }
+
+    pub fn get_fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    pub fn get_memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    pub fn get_max_execution_time(&self) -> Option<Duration> {
+        self.max_execution_time
+    }
+}
+
+/// Why a script was stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FatalError {
+    /// The wall-clock limit passed
+    ExecutionTimeExceeded { limit: Duration },
+    /// The module used up its fuel
+    OutOfFuel { fuel: u64 },
+    /// Growing the memory would have passed the limit
+    MemoryExhausted { limit: usize, requested: usize },
+}
+
+impl fmt::Display for FatalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FatalError::ExecutionTimeExceeded { limit } => write!(
+                f, "PHP Fatal error:  Maximum execution time of {} seconds exceeded", limit.as_secs_f64()
+            ),
+            FatalError::OutOfFuel { fuel } => write!(
+                f, "PHP Fatal error:  Maximum execution time exceeded (ran out of fuel after {} units)", fuel
+            ),
+            FatalError::MemoryExhausted { limit, requested } => write!(
+                f, "PHP Fatal error:  Allowed memory size of {} bytes exhausted (tried to allocate {} bytes)",
+                limit, requested
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FatalError {}
+
+/// Enforces the memory limit as the module's memories and tables grow. The
+/// GC heap grows through it too, but the engine reports a refused GC heap
+/// growth as its own out-of-memory error, so the refusal is also recorded.
+pub(crate) struct MemoryLimiter {
+    pub(crate) limit: Option<usize>,
+    pub(crate) exhausted: Option<FatalError>,
+}
+
+impl wasmtime::ResourceLimiter for MemoryLimiter {
+    fn memory_growing(&mut self, current: usize, desired: usize, maximum: Option<usize>) -> wasmtime::Result<bool> {
+        if let Some(limit) = self.limit {
+            if desired > limit {
+                // An error rather than `false`: the module traps right away
+                // instead of seeing a failed `memory.grow`
+                let error = FatalError::MemoryExhausted { limit, requested: desired - current };
+                self.exhausted = Some(error.clone());
+                return Err(error.into());
+            }
+        }
+        Ok(maximum.is_none_or(|maximum| desired <= maximum))
+    }
+
+    fn table_growing(&mut self, _current: usize, desired: usize, maximum: Option<usize>) -> wasmtime::Result<bool> {
+        Ok(maximum.is_none_or(|maximum| desired <= maximum))
+    }
+}
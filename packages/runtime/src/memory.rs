@@ -1,11 +1,25 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-/// Memory management for Edge PHP runtime
-/// 
-/// Handles allocation, deallocation, and garbage collection
-
-use crate::value::{Value, ValueType, PhpString, PhpArray, PhpObject, ArrayEntry, ObjectProperty};
+//! Memory management for Edge PHP runtime
+//!
+//! Handles allocation, deallocation, and garbage collection
+//!
+//! The heap is carved out of chunks taken from the global allocator. Every
+//! block is rounded up to a size class, and freed blocks wait on their
+//! class's free list to be handed out again before the heap grows. Blocks
+//! too large for any class come straight from the global allocator and go
+//! back to it when freed.
+//!
+//! Array entries hang off hash buckets and are also linked in insertion
+//! order, which is the order PHP iterates them in.
+//!
+//! `gc` is a mark-sweep collector: it traces from the roots through
+//! references, array buckets, keys and values, and object properties, then
+//! frees every block it did not reach, arrays' and objects' own buffers
+//! included. A cycle ends at the first block already marked.
+
+use crate::value::{Value, ValueType, PhpString, PhpArray, PhpObject, ArrayEntry, ArrayKey, ObjectProperty};
 use std::alloc::Layout;
 use std::collections::{HashMap, HashSet};
 use std::ptr;
@@ -16,46 +30,109 @@ pub const RUNTIME_DATA_START: usize = 0x1000;     // Runtime data at 4KB
 pub const STRING_TABLE_START: usize = 0x10000;    // String table at 64KB
 pub const HEAP_START: usize = 0x100000;           // Heap at 1MB
 
+/// Block sizes the heap hands out; larger blocks come from the global allocator
+const SIZE_CLASSES: [usize; 14] = [16, 32, 48, 64, 96, 128, 192, 256, 384, 512, 768, 1024, 2048, 4096];
+
+/// How much the heap grows by at a time
+const CHUNK_SIZE: usize = 0x100000;
+
+/// Alignment of every block
+const BLOCK_ALIGN: usize = 16;
+
+/// Buckets an array gets once it needs any
+const MIN_ARRAY_CAPACITY: usize = 8;
+
+/// What a block holds, which decides how the collector traces it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    Value,
+    String,
+    Array,
+    Object,
+    /// Array buckets and entries, object property slots
+    Buffer,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    /// The requested size
+    size: usize,
+    align: usize,
+    /// Index into `SIZE_CLASSES`, `None` for a large block
+    class: Option<usize>,
+    kind: BlockKind,
+}
+
+/// Allocation and collection counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Blocks handed out
+    pub allocations: usize,
+    /// Of those, blocks taken from a free list
+    pub reused: usize,
+    pub frees: usize,
+    /// Requested bytes handed out and freed
+    pub bytes_allocated: usize,
+    pub bytes_freed: usize,
+    pub live_blocks: usize,
+    pub live_bytes: usize,
+    /// Bytes taken from the global allocator for heap chunks
+    pub heap_size: usize,
+    pub collections: usize,
+    /// Blocks and bytes the last collection freed
+    pub last_collected_blocks: usize,
+    pub last_collected_bytes: usize,
+}
+
 pub struct MemoryManager {
-    /// Next free address in heap
+    /// Next free address in the current chunk, and where the chunk ends
     heap_ptr: usize,
-    
-    /// Free list for recycling memory
-    free_lists: HashMap<usize, Vec<usize>>, // size -> list of addresses
-    
-    /// All allocated values for GC
-    allocated_values: HashMap<usize, Layout>,
-    
+    heap_end: usize,
+
+    /// Heap chunks taken from the global allocator
+    chunks: Vec<usize>,
+
+    /// Free blocks by size class
+    free_lists: Vec<Vec<usize>>,
+
+    /// Every live block, for the sweep
+    allocations: HashMap<usize, Block>,
+
     /// String interning table
     interned_strings: HashMap<String, usize>,
-    
+
     /// GC roots
     gc_roots: HashSet<usize>,
-    
+
     /// Statistics
-    total_allocated: usize,
-    total_freed: usize,
+    stats: MemoryStats,
+
+    /// Handles given to objects so far
+    object_handles: u32,
 }
 
 impl MemoryManager {
     pub fn new() -> Self {
         MemoryManager {
-            heap_ptr: HEAP_START,
-            free_lists: HashMap::new(),
-            allocated_values: HashMap::new(),
+            heap_ptr: 0,
+            heap_end: 0,
+            chunks: Vec::new(),
+            free_lists: vec![Vec::new(); SIZE_CLASSES.len()],
+            allocations: HashMap::new(),
             interned_strings: HashMap::new(),
             gc_roots: HashSet::new(),
-            total_allocated: 0,
-            total_freed: 0,
+            stats: MemoryStats::default(),
+            object_handles: 0,
         }
     }
 
-    /// Allocate memory for a value
+    /// Allocate memory for a value, initialized to null
     pub fn alloc_value(&mut self) -> *mut Value {
-        let layout = Layout::new::<Value>();
-        let ptr = self.alloc_raw(layout);
-        self.allocated_values.insert(ptr as usize, layout);
-        ptr as *mut Value
+        let ptr = self.alloc_raw(Layout::new::<Value>(), BlockKind::Value) as *mut Value;
+        unsafe {
+            ptr::write(ptr, Value::null());
+        }
+        ptr
     }
 
     /// Allocate memory for a string
@@ -67,12 +144,12 @@ impl MemoryManager {
 
         let total_size = std::mem::size_of::<PhpString>() + s.len();
         let layout = Layout::from_size_align(total_size, 4).unwrap();
-        let ptr = self.alloc_raw(layout) as *mut PhpString;
+        let ptr = self.alloc_raw(layout, BlockKind::String) as *mut PhpString;
 
         unsafe {
             (*ptr).len = s.len() as u32;
             (*ptr).hash = hash_string(s);
-            
+
             // Copy string data
             let data_ptr = (*ptr).data.as_mut_ptr();
             ptr::copy_nonoverlapping(s.as_ptr(), data_ptr, s.len());
@@ -80,98 +157,358 @@ impl MemoryManager {
 
         // Intern the string
         self.interned_strings.insert(s.to_string(), ptr as usize);
-        self.allocated_values.insert(ptr as usize, layout);
 
         ptr
     }
 
     /// Allocate memory for an array
     pub fn alloc_array(&mut self, capacity: usize) -> *mut PhpArray {
-        let layout = Layout::new::<PhpArray>();
-        let ptr = self.alloc_raw(layout) as *mut PhpArray;
+        let ptr = self.alloc_raw(Layout::new::<PhpArray>(), BlockKind::Array) as *mut PhpArray;
 
         unsafe {
             (*ptr).size = 0;
             (*ptr).capacity = capacity as u32;
             (*ptr).next_index = 0;
-            
-            // Allocate entries array
-            if capacity > 0 {
-                let entries_layout = Layout::array::<*mut ArrayEntry>(capacity).unwrap();
-                let entries_ptr = self.alloc_raw(entries_layout) as *mut *mut ArrayEntry;
-                (*ptr).entries_ptr = entries_ptr as *mut ArrayEntry;
-                
-                // Initialize to null
-                for i in 0..capacity {
-                    *entries_ptr.add(i) = ptr::null_mut();
-                }
+            (*ptr).first = ptr::null_mut();
+            (*ptr).last = ptr::null_mut();
+
+            // Allocate the buckets, null from the start
+            (*ptr).entries_ptr = if capacity > 0 {
+                self.alloc_buckets(capacity) as *mut ArrayEntry
             } else {
-                (*ptr).entries_ptr = ptr::null_mut();
-            }
+                ptr::null_mut()
+            };
         }
 
-        self.allocated_values.insert(ptr as usize, layout);
         ptr
     }
 
     /// Allocate memory for an object
     pub fn alloc_object(&mut self, class_id: u32, property_count: usize) -> *mut PhpObject {
-        let layout = Layout::new::<PhpObject>();
-        let ptr = self.alloc_raw(layout) as *mut PhpObject;
+        let ptr = self.alloc_raw(Layout::new::<PhpObject>(), BlockKind::Object) as *mut PhpObject;
 
         unsafe {
+            self.object_handles += 1;
             (*ptr).class_id = class_id;
+            (*ptr).handle = self.object_handles;
             (*ptr).properties_count = property_count as u32;
-            
-            // Allocate properties array
+
+            // Allocate properties array; every slot starts without a value
             if property_count > 0 {
                 let props_layout = Layout::array::<ObjectProperty>(property_count).unwrap();
-                let props_ptr = self.alloc_raw(props_layout) as *mut ObjectProperty;
+                let props_ptr = self.alloc_raw(props_layout, BlockKind::Buffer) as *mut ObjectProperty;
                 (*ptr).properties = props_ptr;
             } else {
                 (*ptr).properties = ptr::null_mut();
             }
         }
 
-        self.allocated_values.insert(ptr as usize, layout);
         ptr
     }
 
-    /// Raw memory allocation
-    fn alloc_raw(&mut self, layout: Layout) -> *mut u8 {
-        let size = layout.size();
-        let align = layout.align();
+    /// Set `key` in an array, adding an entry unless the key is there
+    ///
+    /// # Safety
+    ///
+    /// `array` must come from `alloc_array` and not be freed, and a string
+    /// key must point to a live string.
+    pub unsafe fn array_set(&mut self, array: *mut PhpArray, key: ArrayKey, value: *mut Value) {
+        unsafe {
+            if let Some(entry) = Self::find_entry(array, &key) {
+                (*entry).value = value;
+                return;
+            }
+            if (*array).size >= (*array).capacity {
+                self.grow_array(array);
+            }
+            if let ArrayKey::Integer(index) = key {
+                if index >= (*array).next_index {
+                    (*array).next_index = index.saturating_add(1);
+                }
+            }
 
-        // Try to reuse from free list
-        if let Some(free_list) = self.free_lists.get_mut(&size) {
-            if let Some(addr) = free_list.pop() {
-                self.total_allocated += size;
-                return addr as *mut u8;
+            let entry = self.alloc_raw(Layout::new::<ArrayEntry>(), BlockKind::Buffer) as *mut ArrayEntry;
+            let bucket = Self::buckets(array).add(bucket_index(&key, (*array).capacity));
+            ptr::write(entry, ArrayEntry {
+                key,
+                value,
+                next: *bucket,
+                prev_in_order: (*array).last,
+                next_in_order: ptr::null_mut(),
+            });
+            *bucket = entry;
+            match (*array).last.is_null() {
+                true => (*array).first = entry,
+                false => (*(*array).last).next_in_order = entry,
+            }
+            (*array).last = entry;
+            (*array).size += 1;
+        }
+    }
+
+    /// Remove `key` from an array, like `unset($array[$key])`; returns whether
+    /// it was there
+    ///
+    /// # Safety
+    ///
+    /// As for `array_set`.
+    pub unsafe fn array_remove(&mut self, array: *mut PhpArray, key: &ArrayKey) -> bool {
+        unsafe {
+            if (*array).capacity == 0 {
+                return false;
+            }
+            let mut link = Self::buckets(array).add(bucket_index(key, (*array).capacity));
+            while !(*link).is_null() {
+                let entry = *link;
+                if keys_equal(&(*entry).key, key) {
+                    *link = (*entry).next;
+                    match (*entry).prev_in_order.is_null() {
+                        true => (*array).first = (*entry).next_in_order,
+                        false => (*(*entry).prev_in_order).next_in_order = (*entry).next_in_order,
+                    }
+                    match (*entry).next_in_order.is_null() {
+                        true => (*array).last = (*entry).prev_in_order,
+                        false => (*(*entry).next_in_order).prev_in_order = (*entry).prev_in_order,
+                    }
+                    (*array).size -= 1;
+                    self.free(entry as *mut u8);
+                    return true;
+                }
+                link = &mut (*entry).next;
             }
+            false
         }
+    }
 
-        // Align heap pointer
-        let aligned_ptr = (self.heap_ptr + align - 1) & !(align - 1);
-        
-        // Check if we have space
-        let new_heap_ptr = aligned_ptr + size;
-        // In real WASM, we'd call memory.grow() here if needed
+    /// An array's entries in insertion order
+    ///
+    /// # Safety
+    ///
+    /// As for `array_set`; the array must not change while the iterator is
+    /// in use.
+    pub unsafe fn array_entries(array: *mut PhpArray) -> ArrayEntries {
+        unsafe { ArrayEntries { entry: (*array).first } }
+    }
 
-        let ptr = aligned_ptr as *mut u8;
-        self.heap_ptr = new_heap_ptr;
-        self.total_allocated += size;
+    /// Copy an array for assignment: nested arrays are copied too, objects
+    /// stay shared and references are resolved
+    ///
+    /// # Safety
+    ///
+    /// As for `array_set`, for the array and every array in it.
+    pub unsafe fn array_copy(&mut self, array: *mut PhpArray) -> *mut PhpArray {
+        unsafe {
+            let copy = self.alloc_array((*array).size as usize);
+            for entry in Self::array_entries(array) {
+                let value = self.alloc_value();
+                *value = self.copy_value((*entry).value);
+                self.array_set(copy, (*entry).key, value);
+            }
+            (*copy).next_index = (*array).next_index;
+            copy
+        }
+    }
 
-        ptr
+    /// The value to store when assigning `value`: arrays are copied (see
+    /// `array_copy`) and references resolved
+    ///
+    /// # Safety
+    ///
+    /// `value` must be a live value.
+    pub unsafe fn copy_value(&mut self, value: *mut Value) -> Value {
+        unsafe {
+            let mut value = value;
+            while (*value).get_type() == ValueType::Reference {
+                value = (*value).data.ptr as *mut Value;
+            }
+            match (*value).get_type() {
+                ValueType::Array => Value::array(self.array_copy((*value).data.ptr as *mut PhpArray)),
+                _ => Value { flags: 0, refcount: 1, ..*value },
+            }
+        }
+    }
+
+    /// Append to an array, like `$array[] = $value`
+    ///
+    /// # Safety
+    ///
+    /// As for `array_set`.
+    pub unsafe fn array_push(&mut self, array: *mut PhpArray, value: *mut Value) {
+        unsafe {
+            self.array_set(array, ArrayKey::Integer((*array).next_index), value);
+        }
+    }
+
+    /// The value stored under `key`, or null
+    ///
+    /// # Safety
+    ///
+    /// As for `array_set`.
+    pub unsafe fn array_get(&self, array: *mut PhpArray, key: &ArrayKey) -> *mut Value {
+        unsafe { Self::find_entry(array, key).map_or(ptr::null_mut(), |entry| (*entry).value) }
+    }
+
+    /// The key `$array[$value]` uses: strings holding a canonical int and
+    /// floats, bools and null are converted the way PHP does
+    pub fn array_key(&mut self, value: &Value) -> ArrayKey {
+        match value.get_type() {
+            ValueType::Int | ValueType::Bool | ValueType::Float => ArrayKey::Integer(value.to_int()),
+            ValueType::String => {
+                let text = value.as_str().unwrap_or_default();
+                let canonical = text == "0"
+                    || (!text.starts_with('0') && !text.starts_with("-0") && text != "-"
+                        && text.strip_prefix('-').unwrap_or(text).bytes().all(|b| b.is_ascii_digit()));
+                match text.parse() {
+                    Ok(index) if canonical => ArrayKey::Integer(index),
+                    _ => ArrayKey::String(self.alloc_string(text)),
+                }
+            }
+            ValueType::Reference => unsafe { self.array_key(&*(value.data.ptr as *mut Value)) },
+            _ => ArrayKey::String(self.alloc_string(&value.to_php_string())),
+        }
+    }
+
+    /// Store a property value in an object's slot `index`
+    ///
+    /// # Safety
+    ///
+    /// `object` must come from `alloc_object` and not be freed.
+    pub unsafe fn object_set_property(&mut self, object: *mut PhpObject, index: usize, name_id: u32, value: *mut Value) {
+        unsafe {
+            assert!(index < (*object).properties_count as usize, "property slot {} out of bounds", index);
+            ptr::write((*object).properties.add(index), ObjectProperty { name_id, value });
+        }
+    }
+
+    unsafe fn buckets(array: *mut PhpArray) -> *mut *mut ArrayEntry {
+        (*array).entries_ptr as *mut *mut ArrayEntry
+    }
+
+    pub(crate) unsafe fn find_entry(array: *mut PhpArray, key: &ArrayKey) -> Option<*mut ArrayEntry> {
+        if (*array).capacity == 0 {
+            return None;
+        }
+        let mut entry = *Self::buckets(array).add(bucket_index(key, (*array).capacity));
+        while !entry.is_null() {
+            if keys_equal(&(*entry).key, key) {
+                return Some(entry);
+            }
+            entry = (*entry).next;
+        }
+        None
+    }
+
+    fn alloc_buckets(&mut self, capacity: usize) -> *mut *mut ArrayEntry {
+        let layout = Layout::array::<*mut ArrayEntry>(capacity).unwrap();
+        self.alloc_raw(layout, BlockKind::Buffer) as *mut *mut ArrayEntry
+    }
+
+    /// Double the buckets and move every entry over
+    unsafe fn grow_array(&mut self, array: *mut PhpArray) {
+        let old_capacity = (*array).capacity as usize;
+        let old_buckets = Self::buckets(array);
+        let capacity = (old_capacity * 2).max(MIN_ARRAY_CAPACITY);
+        let buckets = self.alloc_buckets(capacity);
+
+        for i in 0..old_capacity {
+            let mut entry = *old_buckets.add(i);
+            while !entry.is_null() {
+                let next = (*entry).next;
+                let bucket = buckets.add(bucket_index(&(*entry).key, capacity as u32));
+                (*entry).next = *bucket;
+                *bucket = entry;
+                entry = next;
+            }
+        }
+
+        (*array).entries_ptr = buckets as *mut ArrayEntry;
+        (*array).capacity = capacity as u32;
+        if !old_buckets.is_null() {
+            self.free(old_buckets as *mut u8);
+        }
+    }
+
+    /// Raw memory allocation, zeroed
+    fn alloc_raw(&mut self, layout: Layout, kind: BlockKind) -> *mut u8 {
+        let size = layout.size().max(1);
+        let align = layout.align();
+        let class = SIZE_CLASSES.iter()
+            .position(|&class_size| class_size >= size)
+            .filter(|_| align <= BLOCK_ALIGN);
+
+        let addr = match class {
+            // Reuse a freed block of the same class before the heap grows
+            Some(class) => match self.free_lists[class].pop() {
+                Some(addr) => {
+                    self.stats.reused += 1;
+                    addr
+                }
+                None => self.bump(SIZE_CLASSES[class]),
+            },
+            None => {
+                let layout = Layout::from_size_align(size, align.max(BLOCK_ALIGN)).unwrap();
+                let ptr = unsafe { std::alloc::alloc(layout) };
+                if ptr.is_null() {
+                    std::alloc::handle_alloc_error(layout);
+                }
+                ptr as usize
+            }
+        };
+
+        unsafe {
+            ptr::write_bytes(addr as *mut u8, 0, class.map_or(size, |class| SIZE_CLASSES[class]));
+        }
+        self.allocations.insert(addr, Block { size, align, class, kind });
+        self.stats.allocations += 1;
+        self.stats.bytes_allocated += size;
+
+        addr as *mut u8
+    }
+
+    /// Take `size` bytes from the current chunk, starting a new one when it is full
+    fn bump(&mut self, size: usize) -> usize {
+        if self.heap_ptr + size > self.heap_end {
+            let layout = Layout::from_size_align(CHUNK_SIZE, BLOCK_ALIGN).unwrap();
+            let chunk = unsafe { std::alloc::alloc(layout) };
+            if chunk.is_null() {
+                std::alloc::handle_alloc_error(layout);
+            }
+            self.chunks.push(chunk as usize);
+            self.heap_ptr = chunk as usize;
+            self.heap_end = self.heap_ptr + CHUNK_SIZE;
+            self.stats.heap_size += CHUNK_SIZE;
+        }
+
+        let addr = self.heap_ptr;
+        self.heap_ptr += size;
+        addr
     }
 
     /// Free memory
     pub fn free(&mut self, ptr: *mut u8) {
         let addr = ptr as usize;
-        if let Some(layout) = self.allocated_values.remove(&addr) {
-            let size = layout.size();
-            self.free_lists.entry(size).or_default().push(addr);
-            self.total_freed += size;
+        let Some(block) = self.allocations.remove(&addr) else {
+            return;
+        };
+
+        // A freed string can no longer be handed out for the same text
+        if block.kind == BlockKind::String {
+            let text = unsafe { string_bytes(ptr as *mut PhpString) };
+            if let Ok(text) = std::str::from_utf8(text) {
+                if self.interned_strings.get(text) == Some(&addr) {
+                    self.interned_strings.remove(text);
+                }
+            }
+        }
+
+        match block.class {
+            Some(class) => self.free_lists[class].push(addr),
+            None => unsafe {
+                std::alloc::dealloc(addr as *mut u8, Layout::from_size_align(block.size, block.align.max(BLOCK_ALIGN)).unwrap());
+            },
         }
+        self.stats.frees += 1;
+        self.stats.bytes_freed += block.size;
     }
 
     /// Add a GC root
@@ -187,75 +524,139 @@ impl MemoryManager {
     /// Run garbage collection
     pub fn gc(&mut self) {
         let mut marked = HashSet::new();
-        
-        // Mark phase - trace from roots
-        for &root in &self.gc_roots {
-            self.mark_value(root as *mut Value, &mut marked);
-        }
 
-        // Sweep phase - free unmarked values
-        let mut to_free = Vec::new();
-        for (&addr, _) in &self.allocated_values {
-            if !marked.contains(&addr) {
-                to_free.push(addr);
+        // Mark phase - trace from roots, with a worklist so that deeply
+        // nested structures cannot overflow the stack
+        let mut pending: Vec<(usize, BlockKind)> = self.gc_roots.iter()
+            .map(|&root| (root, BlockKind::Value))
+            .collect();
+        while let Some((addr, kind)) = pending.pop() {
+            if addr == 0 || !marked.insert(addr) {
+                continue;
+            }
+            unsafe {
+                match kind {
+                    BlockKind::Value => self.mark_value(addr as *mut Value, &mut pending),
+                    BlockKind::Array => self.mark_array(addr as *mut PhpArray, &mut pending),
+                    BlockKind::Object => self.mark_object(addr as *mut PhpObject, &mut pending),
+                    BlockKind::String | BlockKind::Buffer => {}
+                }
             }
         }
 
+        // Sweep phase - free unmarked blocks
+        let to_free: Vec<usize> = self.allocations.keys()
+            .filter(|addr| !marked.contains(addr))
+            .copied()
+            .collect();
+
+        let (frees, bytes_freed) = (self.stats.frees, self.stats.bytes_freed);
         for addr in to_free {
             self.free(addr as *mut u8);
         }
+        self.stats.collections += 1;
+        self.stats.last_collected_blocks = self.stats.frees - frees;
+        self.stats.last_collected_bytes = self.stats.bytes_freed - bytes_freed;
+    }
+
+    /// Allocation and collection counters so far
+    pub fn stats(&self) -> MemoryStats {
+        MemoryStats {
+            live_blocks: self.allocations.len(),
+            live_bytes: self.stats.bytes_allocated - self.stats.bytes_freed,
+            ..self.stats
+        }
+    }
+
+    /// Queue what a value points to
+    unsafe fn mark_value(&self, ptr: *mut Value, pending: &mut Vec<(usize, BlockKind)>) {
+        let target = (*ptr).data.ptr as usize;
+        match (*ptr).get_type() {
+            ValueType::String => pending.push((target, BlockKind::String)),
+            ValueType::Array => pending.push((target, BlockKind::Array)),
+            ValueType::Object => pending.push((target, BlockKind::Object)),
+            // Follow reference
+            ValueType::Reference => pending.push((target, BlockKind::Value)),
+            _ => {} // Primitive types have no children
+        }
     }
 
-    /// Mark a value and its children as reachable
-    fn mark_value(&self, ptr: *mut Value, marked: &mut HashSet<usize>) {
-        if ptr.is_null() {
+    /// Queue an array's buckets and every entry with its key and value
+    unsafe fn mark_array(&self, array: *mut PhpArray, pending: &mut Vec<(usize, BlockKind)>) {
+        let buckets = Self::buckets(array);
+        if buckets.is_null() {
             return;
         }
+        pending.push((buckets as usize, BlockKind::Buffer));
+
+        for i in 0..(*array).capacity as usize {
+            let mut entry = *buckets.add(i);
+            while !entry.is_null() {
+                pending.push((entry as usize, BlockKind::Buffer));
+                if let ArrayKey::String(key) = (*entry).key {
+                    pending.push((key as usize, BlockKind::String));
+                }
+                pending.push(((*entry).value as usize, BlockKind::Value));
+                entry = (*entry).next;
+            }
+        }
+    }
 
-        let addr = ptr as usize;
-        if marked.contains(&addr) {
+    /// Queue an object's property slots and their values
+    unsafe fn mark_object(&self, object: *mut PhpObject, pending: &mut Vec<(usize, BlockKind)>) {
+        let properties = (*object).properties;
+        if properties.is_null() {
             return;
         }
+        pending.push((properties as usize, BlockKind::Buffer));
+
+        for i in 0..(*object).properties_count as usize {
+            pending.push(((*properties.add(i)).value as usize, BlockKind::Value));
+        }
+    }
+}
 
-        marked.insert(addr);
+/// Iterator over an array's entries in insertion order
+pub struct ArrayEntries {
+    entry: *mut ArrayEntry,
+}
 
-        unsafe {
-            match (*ptr).get_type() {
-                ValueType::String => {
-                    // String data is interned, no need to mark
-                }
-                ValueType::Array => {
-                    // Mark array entries
-                    let array_ptr = (*ptr).data.ptr as *mut PhpArray;
-                    if !array_ptr.is_null() {
-                        self.mark_array(array_ptr, marked);
-                    }
-                }
-                ValueType::Object => {
-                    // Mark object properties
-                    let obj_ptr = (*ptr).data.ptr as *mut PhpObject;
-                    if !obj_ptr.is_null() {
-                        self.mark_object(obj_ptr, marked);
-                    }
-                }
-                ValueType::Reference => {
-                    // Follow reference
-                    let ref_ptr = (*ptr).data.ptr as *mut Value;
-                    if !ref_ptr.is_null() {
-                        self.mark_value(ref_ptr, marked);
-                    }
-                }
-                _ => {} // Primitive types have no children
-            }
+impl Iterator for ArrayEntries {
+    type Item = *mut ArrayEntry;
+
+    fn next(&mut self) -> Option<*mut ArrayEntry> {
+        if self.entry.is_null() {
+            return None;
         }
+        let entry = self.entry;
+        self.entry = unsafe { (*entry).next_in_order };
+        Some(entry)
     }
+}
 
-    fn mark_array(&self, _array: *mut PhpArray, _marked: &mut HashSet<usize>) {
-        // TODO: Implement array marking
+impl Default for MemoryManager {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    fn mark_object(&self, _obj: *mut PhpObject, _marked: &mut HashSet<usize>) {
-        // TODO: Implement object marking
+impl Drop for MemoryManager {
+    fn drop(&mut self) {
+        let large: Vec<(usize, Block)> = self.allocations.iter()
+            .filter(|(_, block)| block.class.is_none())
+            .map(|(&addr, &block)| (addr, block))
+            .collect();
+        for (addr, block) in large {
+            unsafe {
+                std::alloc::dealloc(addr as *mut u8, Layout::from_size_align(block.size, block.align.max(BLOCK_ALIGN)).unwrap());
+            }
+        }
+        let layout = Layout::from_size_align(CHUNK_SIZE, BLOCK_ALIGN).unwrap();
+        for &chunk in &self.chunks {
+            unsafe {
+                std::alloc::dealloc(chunk as *mut u8, layout);
+            }
+        }
     }
 }
 
@@ -268,6 +669,26 @@ fn hash_string(s: &str) -> u32 {
     hash
 }
 
+unsafe fn string_bytes<'a>(string: *mut PhpString) -> &'a [u8] {
+    std::slice::from_raw_parts((*string).data.as_ptr(), (*string).len as usize)
+}
+
+unsafe fn bucket_index(key: &ArrayKey, capacity: u32) -> usize {
+    let hash = match key {
+        ArrayKey::Integer(index) => *index as u64,
+        ArrayKey::String(string) => (**string).hash as u64,
+    };
+    (hash % capacity as u64) as usize
+}
+
+unsafe fn keys_equal(a: &ArrayKey, b: &ArrayKey) -> bool {
+    match (a, b) {
+        (ArrayKey::Integer(a), ArrayKey::Integer(b)) => a == b,
+        (ArrayKey::String(a), ArrayKey::String(b)) => a == b || string_bytes(*a) == string_bytes(*b),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -275,10 +696,10 @@ mod tests {
     #[test]
     fn test_memory_allocation() {
         let mut mm = MemoryManager::new();
-        
+
         let val1 = mm.alloc_value();
         let val2 = mm.alloc_value();
-        
+
         assert_ne!(val1, val2);
         assert!(val1 as usize >= HEAP_START);
         assert!(val2 as usize >= HEAP_START);
@@ -287,10 +708,201 @@ mod tests {
     #[test]
     fn test_string_interning() {
         let mut mm = MemoryManager::new();
-        
+
         let str1 = mm.alloc_string("hello");
         let str2 = mm.alloc_string("hello");
-        
+
         assert_eq!(str1, str2); // Should be interned
     }
+
+    /// A rooted value holding `value`
+    fn root(mm: &mut MemoryManager, value: Value) -> *mut Value {
+        let ptr = mm.alloc_value();
+        unsafe {
+            *ptr = value;
+        }
+        mm.add_root(ptr);
+        ptr
+    }
+
+    fn int(mm: &mut MemoryManager, i: i64) -> *mut Value {
+        let ptr = mm.alloc_value();
+        unsafe {
+            *ptr = Value::int(i);
+        }
+        ptr
+    }
+
+    #[test]
+    fn test_array_order_and_removal() {
+        let mut mm = MemoryManager::new();
+        let array = mm.alloc_array(0);
+        for i in [30, 10, 20, 40, 50, 60, 70, 80, 90] {
+            let value = int(&mut mm, i);
+            unsafe { mm.array_set(array, ArrayKey::Integer(i), value); }
+        }
+        let keys = |array| unsafe {
+            MemoryManager::array_entries(array)
+                .map(|entry| match (*entry).key {
+                    ArrayKey::Integer(i) => i,
+                    ArrayKey::String(_) => -1,
+                })
+                .collect::<Vec<_>>()
+        };
+        // Growing the buckets keeps insertion order
+        assert_eq!(keys(array), [30, 10, 20, 40, 50, 60, 70, 80, 90]);
+
+        unsafe {
+            assert!(mm.array_remove(array, &ArrayKey::Integer(30)));
+            assert!(mm.array_remove(array, &ArrayKey::Integer(50)));
+            assert!(mm.array_remove(array, &ArrayKey::Integer(90)));
+            assert!(!mm.array_remove(array, &ArrayKey::Integer(50)));
+            assert!(mm.array_get(array, &ArrayKey::Integer(50)).is_null());
+            assert_eq!((*array).size, 6);
+        }
+        assert_eq!(keys(array), [10, 20, 40, 60, 70, 80]);
+
+        // A copy is independent of the original
+        let copy = unsafe { mm.array_copy(array) };
+        let value = int(&mut mm, 1);
+        unsafe {
+            mm.array_push(copy, value);
+            assert_eq!((*mm.array_get(copy, &ArrayKey::Integer(20))).to_int(), 20);
+            assert_ne!(mm.array_get(copy, &ArrayKey::Integer(20)), mm.array_get(array, &ArrayKey::Integer(20)));
+        }
+        assert_eq!(keys(copy), [10, 20, 40, 60, 70, 80, 91]);
+        assert_eq!(keys(array), [10, 20, 40, 60, 70, 80]);
+    }
+
+    #[test]
+    fn test_gc_keeps_nested_structures() {
+        let mut mm = MemoryManager::new();
+
+        // ['list' => [1, 2, ..., 20], 'user' => object { name: "ada" }]
+        let outer = mm.alloc_array(0);
+        let inner = mm.alloc_array(2);
+        for i in 1..=20 {
+            let value = int(&mut mm, i);
+            unsafe { mm.array_push(inner, value); }
+        }
+        let inner_value = mm.alloc_value();
+        let object = mm.alloc_object(1, 1);
+        let object_value = mm.alloc_value();
+        let name = mm.alloc_value();
+        unsafe {
+            *inner_value = Value::array(inner);
+            *object_value = Value::object(object);
+            *name = Value::string(mm.alloc_string("ada") as *mut u8);
+        }
+        unsafe { mm.object_set_property(object, 0, 7, name); }
+        let list_key = mm.alloc_string("list");
+        let user_key = mm.alloc_string("user");
+        unsafe { mm.array_set(outer, ArrayKey::String(list_key), inner_value); }
+        unsafe { mm.array_set(outer, ArrayKey::String(user_key), object_value); }
+        root(&mut mm, Value::array(outer));
+
+        // Garbage next to it
+        for i in 0..50 {
+            int(&mut mm, i);
+        }
+        let live = mm.stats().live_blocks;
+        mm.gc();
+        let stats = mm.stats();
+        assert_eq!(stats.last_collected_blocks, 50);
+        assert_eq!(stats.live_blocks, live - 50);
+
+        unsafe {
+            let key = mm.alloc_string("list");
+            assert_eq!(key, list_key);
+            let list = mm.array_get(outer, &ArrayKey::String(key));
+            let inner = (*list).data.ptr as *mut PhpArray;
+            assert_eq!((*inner).size, 20);
+            assert_eq!((*mm.array_get(inner, &ArrayKey::Integer(19))).to_int(), 20);
+
+            let user = mm.array_get(outer, &ArrayKey::String(user_key));
+            let object = (*user).data.ptr as *mut PhpObject;
+            let name = (*(*object).properties).value;
+            assert_eq!(string_bytes((*name).data.ptr as *mut PhpString), b"ada");
+        }
+    }
+
+    #[test]
+    fn test_gc_handles_cycles() {
+        let mut mm = MemoryManager::new();
+
+        // $a = []; $a[0] = &$a; reachable through $a
+        let array = mm.alloc_array(0);
+        let slot = root(&mut mm, Value::array(array));
+        let reference = mm.alloc_value();
+        unsafe {
+            *reference = Value::reference(slot);
+        }
+        unsafe { mm.array_push(array, reference); }
+
+        // Two objects pointing at each other, unreachable
+        let a = mm.alloc_object(1, 1);
+        let b = mm.alloc_object(1, 1);
+        let a_value = mm.alloc_value();
+        let b_value = mm.alloc_value();
+        unsafe {
+            *a_value = Value::object(a);
+            *b_value = Value::object(b);
+        }
+        unsafe { mm.object_set_property(a, 0, 1, b_value); }
+        unsafe { mm.object_set_property(b, 0, 1, a_value); }
+
+        mm.gc();
+        // Each object, its property slots and its value
+        assert_eq!(mm.stats().last_collected_blocks, 6);
+        unsafe {
+            let entry = mm.array_get(array, &ArrayKey::Integer(0));
+            assert_eq!((*entry).get_type(), ValueType::Reference);
+            assert_eq!((*entry).data.ptr as *mut Value, slot);
+        }
+
+        // Once the root goes, the self-referencing array goes too
+        mm.remove_root(slot);
+        mm.gc();
+        assert_eq!(mm.stats().live_blocks, 0);
+        assert_eq!(mm.stats().live_bytes, 0);
+    }
+
+    #[test]
+    fn test_gc_frees_strings_and_reuses_blocks() {
+        let mut mm = MemoryManager::new();
+        root(&mut mm, Value::null());
+        mm.alloc_string("temporary");
+        for i in 0..1000 {
+            int(&mut mm, i);
+        }
+        let heap_size = mm.stats().heap_size;
+        mm.gc();
+        assert_eq!(mm.stats().live_blocks, 1);
+
+        // Freed blocks are handed out again before the heap grows
+        for i in 0..1000 {
+            int(&mut mm, i);
+        }
+        let stats = mm.stats();
+        assert_eq!(stats.reused, 1000);
+        assert_eq!(stats.heap_size, heap_size);
+        assert_eq!(stats.collections, 1);
+
+        // The freed string is no longer interned
+        let again = mm.alloc_string("temporary");
+        unsafe {
+            assert_eq!(string_bytes(again), b"temporary");
+        }
+        assert!(mm.allocations.contains_key(&(again as usize)));
+        assert_eq!(mm.alloc_string("temporary"), again);
+
+        // Large blocks live outside the heap
+        let large = "x".repeat(10_000);
+        let string = mm.alloc_string(&large);
+        unsafe {
+            assert_eq!(string_bytes(string).len(), 10_000);
+        }
+        mm.gc();
+        assert_eq!(mm.stats().live_blocks, 1);
+    }
 }
@@ -1,121 +1,103 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-/// PHP operations implementation
-/// 
-/// Handles all PHP operators with proper type coercion
+//! PHP operations implementation
+//!
+//! Handles all PHP operators with proper type coercion
 
-use crate::value::{Value, ValueType};
+use crate::value::{Number, Value, ValueType, PhpArray, parse_numeric};
+use crate::memory::MemoryManager;
 use crate::context::{ExecutionContext, PhpError, ErrorLevel};
+use std::cmp::Ordering;
 
 /// PHP addition operation
 /// Handles: number + number, array + array
-pub fn add(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn add(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
-        let left_type = (*left).get_type();
-        let right_type = (*right).get_type();
-        
-        match (left_type, right_type) {
-            // Numeric addition
-            (ValueType::Int, ValueType::Int) => {
-                let result = (*left).to_int() + (*right).to_int();
-                let val = ctx.memory.alloc_value();
-                *val = Value::int(result);
-                Ok(val)
-            }
-            (ValueType::Float, ValueType::Float) |
-            (ValueType::Int, ValueType::Float) |
-            (ValueType::Float, ValueType::Int) => {
-                let result = (*left).to_float() + (*right).to_float();
-                let val = ctx.memory.alloc_value();
-                *val = Value::float(result);
-                Ok(val)
-            }
-            // Array union
-            (ValueType::Array, ValueType::Array) => {
-                // TODO: Implement array union
-                let val = ctx.memory.alloc_value();
-                *val = Value::null();
-                Ok(val)
-            }
-            // Invalid operation
-            _ => {
-                ctx.trigger_error(
-                    ErrorLevel::Warning,
-                    format!("Unsupported operand types for +: {} and {}", 
-                        type_name(left_type), type_name(right_type))
-                );
-                let val = ctx.memory.alloc_value();
-                *val = Value::null();
-                Ok(val)
+        let (left, right) = (deref(left), deref(right));
+
+        // Array union: the left array plus the right array's other keys
+        if (*left).get_type() == ValueType::Array && (*right).get_type() == ValueType::Array {
+            let union = ctx.memory.array_copy((*left).data.ptr as *mut PhpArray);
+            let right_array = (*right).data.ptr as *mut PhpArray;
+            for entry in MemoryManager::array_entries(right_array) {
+                if ctx.memory.array_get(union, &(*entry).key).is_null() {
+                    let value = ctx.memory.alloc_value();
+                    *value = ctx.memory.copy_value((*entry).value);
+                    ctx.memory.array_set(union, (*entry).key, value);
+                }
             }
+            let val = ctx.memory.alloc_value();
+            *val = Value::array(union);
+            return Ok(val);
         }
+
+        arithmetic(ctx, left, right, "+", i64::checked_add, |a, b| a + b)
     }
 }
 
 /// PHP subtraction operation
-pub fn subtract(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
-    unsafe {
-        let result = (*left).to_float() - (*right).to_float();
-        let val = ctx.memory.alloc_value();
-        
-        // Return int if result is whole number
-        if result.fract() == 0.0 && result.abs() < i64::MAX as f64 {
-            *val = Value::int(result as i64);
-        } else {
-            *val = Value::float(result);
-        }
-        Ok(val)
-    }
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn subtract(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+    unsafe { arithmetic(ctx, deref(left), deref(right), "-", i64::checked_sub, |a, b| a - b) }
 }
 
 /// PHP multiplication operation
-pub fn multiply(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
-    unsafe {
-        let result = (*left).to_float() * (*right).to_float();
-        let val = ctx.memory.alloc_value();
-        
-        if result.fract() == 0.0 && result.abs() < i64::MAX as f64 {
-            *val = Value::int(result as i64);
-        } else {
-            *val = Value::float(result);
-        }
-        Ok(val)
-    }
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn multiply(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+    unsafe { arithmetic(ctx, deref(left), deref(right), "*", i64::checked_mul, |a, b| a * b) }
 }
 
 /// PHP division operation
-pub fn divide(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+/// Ints that divide evenly give an int, everything else a float
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn divide(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
-        let divisor = (*right).to_float();
-        
-        if divisor == 0.0 {
-            ctx.trigger_error(ErrorLevel::Warning, "Division by zero".to_string());
-            let val = ctx.memory.alloc_value();
-            *val = Value::float(f64::INFINITY);
-            return Ok(val);
+        let (left, right) = (deref(left), deref(right));
+        let (a, b) = operands(ctx, left, right, "/")?;
+        if b.to_float() == 0.0 {
+            return Err(error("DivisionByZeroError", "Division by zero"));
         }
-        
-        let result = (*left).to_float() / divisor;
+
+        let result = match (a, b) {
+            (Number::Int(a), Number::Int(b)) if a.checked_rem(b) == Some(0) => Value::int(a / b),
+            _ => Value::float(a.to_float() / b.to_float()),
+        };
         let val = ctx.memory.alloc_value();
-        *val = Value::float(result);
+        *val = result;
         Ok(val)
     }
 }
 
 /// PHP modulo operation
-pub fn modulo(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn modulo(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
+        let (left, right) = (deref(left), deref(right));
+        operands(ctx, left, right, "%")?;
         let divisor = (*right).to_int();
-        
+
         if divisor == 0 {
-            ctx.trigger_error(ErrorLevel::Warning, "Division by zero".to_string());
-            let val = ctx.memory.alloc_value();
-            *val = Value::null();
-            return Ok(val);
+            return Err(error("DivisionByZeroError", "Modulo by zero"));
         }
-        
-        let result = (*left).to_int() % divisor;
+
+        let result = (*left).to_int().wrapping_rem(divisor);
         let val = ctx.memory.alloc_value();
         *val = Value::int(result);
         Ok(val)
@@ -123,15 +105,19 @@ pub fn modulo(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -
 }
 
 /// PHP string concatenation
-pub fn concat(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn concat(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
         // Convert both values to strings
         let left_str = value_to_string(ctx, left);
         let right_str = value_to_string(ctx, right);
-        
+
         let result = format!("{}{}", left_str, right_str);
         let str_ptr = ctx.memory.alloc_string(&result);
-        
+
         let val = ctx.memory.alloc_value();
         *val = Value::string(str_ptr as *mut u8);
         Ok(val)
@@ -139,71 +125,67 @@ pub fn concat(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -
 }
 
 /// PHP equality comparison (==)
-pub fn equal(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn equal(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
-        let result = compare_values(left, right, false);
+        let result = loose_equals(&*left, &*right);
         let val = ctx.memory.alloc_value();
-        *val = Value::bool(result == 0);
+        *val = Value::bool(result);
         Ok(val)
     }
 }
 
 /// PHP identity comparison (===)
-pub fn identical(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn identical(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
-        let left_type = (*left).get_type();
-        let right_type = (*right).get_type();
-        
+        let result = strict_equals(&*left, &*right);
         let val = ctx.memory.alloc_value();
-        
-        if left_type != right_type {
-            *val = Value::bool(false);
-            return Ok(val);
-        }
-        
-        let result = match left_type {
-            ValueType::Null => true,
-            ValueType::Bool => (*left).to_bool() == (*right).to_bool(),
-            ValueType::Int => (*left).to_int() == (*right).to_int(),
-            ValueType::Float => (*left).to_float() == (*right).to_float(),
-            ValueType::String => {
-                // TODO: Compare string contents
-                false
-            }
-            ValueType::Array | ValueType::Object => {
-                // Reference comparison
-                left == right
-            }
-            _ => false,
-        };
-        
         *val = Value::bool(result);
         Ok(val)
     }
 }
 
 /// PHP less than comparison
-pub fn less_than(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn less_than(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
-        let result = compare_values(left, right, false);
+        let result = compare(&*left, &*right);
         let val = ctx.memory.alloc_value();
-        *val = Value::bool(result < 0);
+        *val = Value::bool(result == Some(Ordering::Less));
         Ok(val)
     }
 }
 
 /// PHP greater than comparison
-pub fn greater_than(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn greater_than(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
-        let result = compare_values(left, right, false);
+        let result = compare(&*left, &*right);
         let val = ctx.memory.alloc_value();
-        *val = Value::bool(result > 0);
+        *val = Value::bool(result == Some(Ordering::Greater));
         Ok(val)
     }
 }
 
 /// PHP logical AND (&&)
-pub fn logical_and(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn logical_and(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
         let val = ctx.memory.alloc_value();
         *val = Value::bool((*left).to_bool() && (*right).to_bool());
@@ -212,7 +194,11 @@ pub fn logical_and(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Val
 }
 
 /// PHP logical OR (||)
-pub fn logical_or(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn logical_or(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
         let val = ctx.memory.alloc_value();
         *val = Value::bool((*left).to_bool() || (*right).to_bool());
@@ -221,7 +207,11 @@ pub fn logical_or(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Valu
 }
 
 /// PHP bitwise AND
-pub fn bitwise_and(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn bitwise_and(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
         let result = (*left).to_int() & (*right).to_int();
         let val = ctx.memory.alloc_value();
@@ -231,7 +221,11 @@ pub fn bitwise_and(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Val
 }
 
 /// PHP bitwise OR
-pub fn bitwise_or(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn bitwise_or(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
         let result = (*left).to_int() | (*right).to_int();
         let val = ctx.memory.alloc_value();
@@ -241,7 +235,11 @@ pub fn bitwise_or(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Valu
 }
 
 /// PHP bitwise XOR
-pub fn bitwise_xor(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `left` and `right` must point to live values.
+pub unsafe fn bitwise_xor(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
         let result = (*left).to_int() ^ (*right).to_int();
         let val = ctx.memory.alloc_value();
@@ -251,7 +249,11 @@ pub fn bitwise_xor(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Val
 }
 
 /// PHP NOT operator
-pub fn not(ctx: &mut ExecutionContext, value: *mut Value) -> Result<*mut Value, PhpError> {
+///
+/// # Safety
+///
+/// `value` must point to a live value.
+pub unsafe fn not(ctx: &mut ExecutionContext, value: *mut Value) -> Result<*mut Value, PhpError> {
     unsafe {
         let val = ctx.memory.alloc_value();
         *val = Value::bool(!(*value).to_bool());
@@ -259,105 +261,105 @@ pub fn not(ctx: &mut ExecutionContext, value: *mut Value) -> Result<*mut Value,
     }
 }
 
-/// PHP negate operator (unary minus)
-pub fn negate(ctx: &mut ExecutionContext, value: *mut Value) -> Result<*mut Value, PhpError> {
-    unsafe {
-        let val = ctx.memory.alloc_value();
-        
-        match (*value).get_type() {
-            ValueType::Int => {
-                *val = Value::int(-(*value).to_int());
+/// PHP negate operator (unary minus), which multiplies by -1
+///
+/// # Safety
+///
+/// `value` must point to a live value.
+pub unsafe fn negate(ctx: &mut ExecutionContext, value: *mut Value) -> Result<*mut Value, PhpError> {
+    let minus_one = Value::int(-1);
+    unsafe { arithmetic(ctx, deref(value), &minus_one as *const Value as *mut Value, "*", i64::checked_mul, |a, b| a * b) }
+}
+
+/// Compare two PHP values with PHP 8's loose comparison rules
+///
+/// `None` means the values are not comparable: NaN against anything, arrays
+/// with different keys, and different objects.
+pub fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    let (left, right) = unsafe { (&*deref(left as *const Value as *mut Value), &*deref(right as *const Value as *mut Value)) };
+    let left_type = left.get_type();
+    let right_type = right.get_type();
+
+    // PHP type juggling comparison rules
+    match (left_type, right_type) {
+        (ValueType::Null, ValueType::Null) => Some(Ordering::Equal),
+        (ValueType::Null, ValueType::String) => Some(b"".as_slice().cmp(right.as_bytes().unwrap_or_default())),
+        (ValueType::String, ValueType::Null) => Some(left.as_bytes().unwrap_or_default().cmp(b"".as_slice())),
+        (ValueType::Bool | ValueType::Null, _) | (_, ValueType::Bool | ValueType::Null) => {
+            Some(left.to_bool().cmp(&right.to_bool()))
+        }
+        (ValueType::String, ValueType::String) => {
+            let (a, b) = (left.as_bytes().unwrap_or_default(), right.as_bytes().unwrap_or_default());
+            match (numeric_string(left), numeric_string(right)) {
+                (Some(a), Some(b)) => compare_numbers(a, b),
+                _ => Some(a.cmp(b)),
             }
-            ValueType::Float => {
-                *val = Value::float(-(*value).to_float());
+        }
+        (ValueType::String, ValueType::Int | ValueType::Float) => match numeric_string(left) {
+            Some(a) => compare_numbers(a, number(right)),
+            None => Some(left.as_bytes().unwrap_or_default().cmp(right.to_php_string().as_bytes())),
+        },
+        (ValueType::Int | ValueType::Float, ValueType::String) => compare(right, left).map(Ordering::reverse),
+        (ValueType::Int | ValueType::Float, ValueType::Int | ValueType::Float) => compare_numbers(number(left), number(right)),
+        (ValueType::Array, ValueType::Array) => unsafe {
+            let (a, b) = (left.data.ptr as *mut PhpArray, right.data.ptr as *mut PhpArray);
+            match (*a).size.cmp(&(*b).size) {
+                Ordering::Equal => {}
+                ordering => return Some(ordering),
             }
-            _ => {
-                // Convert to numeric and negate
-                let num = (*value).to_float();
-                if num.fract() == 0.0 && num.abs() < i64::MAX as f64 {
-                    *val = Value::int(-(num as i64));
-                } else {
-                    *val = Value::float(-num);
+            for entry in MemoryManager::array_entries(a) {
+                let other = array_lookup(b, &(*entry).key)?;
+                match compare(&*(*entry).value, &*other)? {
+                    Ordering::Equal => {}
+                    ordering => return Some(ordering),
                 }
             }
-        }
-        
-        Ok(val)
+            Some(Ordering::Equal)
+        },
+        (ValueType::Array, _) => Some(Ordering::Greater),
+        (_, ValueType::Array) => Some(Ordering::Less),
+        (ValueType::Object, ValueType::Object) => unsafe {
+            (left.data.ptr == right.data.ptr).then_some(Ordering::Equal)
+        },
+        (ValueType::Object, _) => Some(Ordering::Greater),
+        (_, ValueType::Object) => Some(Ordering::Less),
+        _ => compare_numbers(number(left), number(right)),
     }
 }
 
-/// Convert a PHP value to string representation
-fn value_to_string(_ctx: &mut ExecutionContext, value: *mut Value) -> String {
-    unsafe {
-        match (*value).get_type() {
-            ValueType::Null => String::new(),
-            ValueType::Bool => {
-                if (*value).to_bool() { "1".to_string() } else { String::new() }
-            }
-            ValueType::Int => (*value).to_int().to_string(),
-            ValueType::Float => {
-                let f = (*value).to_float();
-                // PHP formatting rules for floats
-                if f.fract() == 0.0 {
-                    format!("{:.0}", f)
-                } else {
-                    format!("{}", f)
-                }
-            }
-            ValueType::String => {
-                // TODO: Get actual string content
-                "[string]".to_string()
-            }
-            ValueType::Array => "Array".to_string(),
-            ValueType::Object => {
-                // TODO: Get class name
-                "[object]".to_string()
-            }
-            _ => "[unknown]".to_string(),
-        }
-    }
+/// `==`
+pub fn loose_equals(left: &Value, right: &Value) -> bool {
+    compare(left, right) == Some(Ordering::Equal)
 }
 
-/// Compare two PHP values
-/// Returns: -1 if left < right, 0 if equal, 1 if left > right
-fn compare_values(left: *mut Value, right: *mut Value, strict: bool) -> i32 {
-    unsafe {
-        let left_type = (*left).get_type();
-        let right_type = (*right).get_type();
-        
-        if strict && left_type != right_type {
-            return if (left_type as u8) < (right_type as u8) { -1 } else { 1 };
-        }
-        
-        // PHP type juggling comparison rules
-        match (left_type, right_type) {
-            (ValueType::Null, ValueType::Null) => 0,
-            (ValueType::Bool, _) | (_, ValueType::Bool) => {
-                let left_bool = (*left).to_bool();
-                let right_bool = (*right).to_bool();
-                if left_bool == right_bool { 0 }
-                else if left_bool { 1 }
-                else { -1 }
-            }
-            (ValueType::String, ValueType::String) => {
-                // TODO: Implement string comparison
-                0
-            }
-            _ => {
-                // Numeric comparison
-                let left_num = (*left).to_float();
-                let right_num = (*right).to_float();
-                
-                if left_num < right_num { -1 }
-                else if left_num > right_num { 1 }
-                else { 0 }
-            }
-        }
+/// `===`: same type and value; arrays need the same pairs in the same
+/// order, objects must be the same instance
+pub fn strict_equals(left: &Value, right: &Value) -> bool {
+    let (left, right) = unsafe { (&*deref(left as *const Value as *mut Value), &*deref(right as *const Value as *mut Value)) };
+    let left_type = left.get_type();
+    if left_type != right.get_type() {
+        return false;
+    }
+
+    match left_type {
+        ValueType::Null => true,
+        ValueType::Bool => left.to_bool() == right.to_bool(),
+        ValueType::Int => left.to_int() == right.to_int(),
+        ValueType::Float => left.to_float() == right.to_float(),
+        ValueType::String => left.as_bytes() == right.as_bytes(),
+        ValueType::Array => unsafe {
+            let (a, b) = (left.data.ptr as *mut PhpArray, right.data.ptr as *mut PhpArray);
+            (*a).size == (*b).size
+                && MemoryManager::array_entries(a).zip(MemoryManager::array_entries(b)).all(|(x, y)| {
+                    keys_identical(&(*x).key, &(*y).key) && strict_equals(&*(*x).value, &*(*y).value)
+                })
+        },
+        _ => unsafe { left.data.ptr == right.data.ptr },
     }
 }
 
 /// Get type name for error messages
-fn type_name(typ: ValueType) -> &'static str {
+pub fn type_name(typ: ValueType) -> &'static str {
     match typ {
         ValueType::Null => "null",
         ValueType::Bool => "bool",
@@ -370,3 +372,100 @@ fn type_name(typ: ValueType) -> &'static str {
         ValueType::Reference => "reference",
     }
 }
+
+/// An arithmetic operator: ints stay ints unless `int_op` overflows
+unsafe fn arithmetic(
+    ctx: &mut ExecutionContext,
+    left: *mut Value,
+    right: *mut Value,
+    op: &str,
+    int_op: fn(i64, i64) -> Option<i64>,
+    float_op: fn(f64, f64) -> f64,
+) -> Result<*mut Value, PhpError> {
+    let result = match operands(ctx, left, right, op)? {
+        (Number::Int(a), Number::Int(b)) => match int_op(a, b) {
+            Some(result) => Value::int(result),
+            None => Value::float(float_op(a as f64, b as f64)),
+        },
+        (a, b) => Value::float(float_op(a.to_float(), b.to_float())),
+    };
+    let val = ctx.memory.alloc_value();
+    *val = result;
+    Ok(val)
+}
+
+/// Both operands as numbers: arrays, objects and non-numeric strings are a
+/// `TypeError`, strings with trailing garbage a warning
+unsafe fn operands(ctx: &mut ExecutionContext, left: *mut Value, right: *mut Value, op: &str) -> Result<(Number, Number), PhpError> {
+    match ((*left).to_number(), (*right).to_number()) {
+        (Some((a, a_whole)), Some((b, b_whole))) => {
+            if !a_whole || !b_whole {
+                ctx.trigger_error(ErrorLevel::Warning, "A non-numeric value encountered".to_string());
+            }
+            Ok((a, b))
+        }
+        _ => Err(error("TypeError", &format!(
+            "Unsupported operand types: {} {} {}",
+            type_name((*left).get_type()), op, type_name((*right).get_type())
+        ))),
+    }
+}
+
+/// An uncaught error of `class`, which ends the script
+fn error(class: &'static str, message: &str) -> PhpError {
+    PhpError {
+        level: ErrorLevel::Error,
+        class,
+        message: message.to_string(),
+        file: None,
+        line: None,
+    }
+}
+
+/// The value a reference points to, or the value itself
+unsafe fn deref(mut value: *mut Value) -> *mut Value {
+    while (*value).get_type() == ValueType::Reference {
+        value = (*value).data.ptr as *mut Value;
+    }
+    value
+}
+
+/// Convert a PHP value to string representation
+fn value_to_string(_ctx: &mut ExecutionContext, value: *mut Value) -> String {
+    unsafe { (*value).to_php_string() }
+}
+
+fn number(value: &Value) -> Number {
+    match value.get_type() {
+        ValueType::Float => Number::Float(value.to_float()),
+        _ => Number::Int(value.to_int()),
+    }
+}
+
+/// The number a wholly numeric string holds
+fn numeric_string(value: &Value) -> Option<Number> {
+    match value.as_str().and_then(parse_numeric) {
+        Some((number, true)) => Some(number),
+        _ => None,
+    }
+}
+
+fn compare_numbers(a: Number, b: Number) -> Option<Ordering> {
+    match (a, b) {
+        (Number::Int(a), Number::Int(b)) => Some(a.cmp(&b)),
+        (a, b) => a.to_float().partial_cmp(&b.to_float()),
+    }
+}
+
+unsafe fn array_lookup(array: *mut PhpArray, key: &crate::value::ArrayKey) -> Option<*mut Value> {
+    MemoryManager::find_entry(array, key).map(|entry| (*entry).value)
+}
+
+unsafe fn keys_identical(a: &crate::value::ArrayKey, b: &crate::value::ArrayKey) -> bool {
+    use crate::value::ArrayKey;
+    match (a, b) {
+        (ArrayKey::Integer(a), ArrayKey::Integer(b)) => a == b,
+        (ArrayKey::String(a), ArrayKey::String(b)) => (**a).as_bytes() == (**b).as_bytes(),
+        _ => false,
+    }
+}
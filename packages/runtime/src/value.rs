@@ -1,14 +1,14 @@
 // Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
 // SPDX-License-Identifier: Apache-2.0
 
-/// PHP Value representation in WASM
-/// 
-/// Each value is 16 bytes:
-/// - type_tag (1 byte): The PHP type
-/// - flags (1 byte): GC and other metadata
-/// - reserved (2 bytes): For future use
-/// - data (8 bytes): The actual value or pointer
-/// - refcount (4 bytes): Reference counting for GC
+//! PHP Value representation in WASM
+//! 
+//! Each value is 16 bytes:
+//! - type_tag (1 byte): The PHP type
+//! - flags (1 byte): GC and other metadata
+//! - reserved (2 bytes): For future use
+//! - refcount (4 bytes): Reference counting for GC
+//! - data (8 bytes): The actual value or pointer
 
 use std::mem;
 
@@ -27,15 +27,17 @@ pub enum ValueType {
 }
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Value {
     pub type_tag: u8,
     pub flags: u8,
     pub reserved: u16,
-    pub data: ValueData,
     pub refcount: u32,
+    pub data: ValueData,
 }
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub union ValueData {
     pub null: (),
     pub boolean: bool,
@@ -106,6 +108,39 @@ impl Value {
         }
     }
 
+    /// Create an array value
+    pub fn array(ptr: *mut PhpArray) -> Self {
+        Value {
+            type_tag: ValueType::Array as u8,
+            flags: 0,
+            reserved: 0,
+            data: ValueData { ptr: ptr as *mut u8 },
+            refcount: 1,
+        }
+    }
+
+    /// Create an object value
+    pub fn object(ptr: *mut PhpObject) -> Self {
+        Value {
+            type_tag: ValueType::Object as u8,
+            flags: 0,
+            reserved: 0,
+            data: ValueData { ptr: ptr as *mut u8 },
+            refcount: 1,
+        }
+    }
+
+    /// Create a reference to another value
+    pub fn reference(target: *mut Value) -> Self {
+        Value {
+            type_tag: ValueType::Reference as u8,
+            flags: 0,
+            reserved: 0,
+            data: ValueData { ptr: target as *mut u8 },
+            refcount: 1,
+        }
+    }
+
     /// Get the type of this value
     pub fn get_type(&self) -> ValueType {
         unsafe { mem::transmute(self.type_tag) }
@@ -128,13 +163,12 @@ impl Value {
             ValueType::Null => false,
             ValueType::Bool => unsafe { self.data.boolean },
             ValueType::Int => unsafe { self.data.integer != 0 },
-            ValueType::Float => unsafe { self.data.float != 0.0 && !self.data.float.is_nan() },
-            ValueType::String => {
-                // Empty string is false, "0" is false
-                // Would need to check string content
-                true // Simplified for now
-            }
-            ValueType::Array => true, // Empty array is false in PHP
+            ValueType::Float => unsafe { self.data.float != 0.0 },
+            // Empty string is false, "0" is false
+            ValueType::String => !matches!(self.as_bytes(), Some(b"" | b"0")),
+            // Empty array is false
+            ValueType::Array => unsafe { (*(self.data.ptr as *mut PhpArray)).size != 0 },
+            ValueType::Reference => unsafe { (*(self.data.ptr as *mut Value)).to_bool() },
             _ => true,
         }
     }
@@ -145,23 +179,197 @@ impl Value {
             ValueType::Null => 0,
             ValueType::Bool => if unsafe { self.data.boolean } { 1 } else { 0 },
             ValueType::Int => unsafe { self.data.integer },
-            ValueType::Float => unsafe { self.data.float as i64 },
-            _ => 0, // Would need string parsing, etc.
+            ValueType::Float => float_to_int(unsafe { self.data.float }),
+            ValueType::String => match self.as_str().and_then(parse_numeric) {
+                Some((Number::Int(i), _)) => i,
+                Some((Number::Float(f), _)) => float_to_int(f),
+                None => 0,
+            },
+            ValueType::Array => self.to_bool() as i64,
+            ValueType::Reference => unsafe { (*(self.data.ptr as *mut Value)).to_int() },
+            _ => 1,
         }
     }
 
     /// Convert to float
     pub fn to_float(&self) -> f64 {
         match self.get_type() {
-            ValueType::Null => 0.0,
-            ValueType::Bool => if unsafe { self.data.boolean } { 1.0 } else { 0.0 },
-            ValueType::Int => unsafe { self.data.integer as f64 },
             ValueType::Float => unsafe { self.data.float },
-            _ => 0.0, // Would need string parsing, etc.
+            ValueType::String => match self.as_str().and_then(parse_numeric) {
+                Some((Number::Int(i), _)) => i as f64,
+                Some((Number::Float(f), _)) => f,
+                None => 0.0,
+            },
+            ValueType::Reference => unsafe { (*(self.data.ptr as *mut Value)).to_float() },
+            _ => self.to_int() as f64,
+        }
+    }
+
+    /// The number a value stands for in arithmetic, with whether all of it
+    /// was numeric; `None` for strings without a leading number and for
+    /// arrays and objects
+    pub fn to_number(&self) -> Option<(Number, bool)> {
+        match self.get_type() {
+            ValueType::Null => Some((Number::Int(0), true)),
+            ValueType::Bool | ValueType::Int => Some((Number::Int(self.to_int()), true)),
+            ValueType::Float => Some((Number::Float(self.to_float()), true)),
+            ValueType::String => self.as_str().and_then(parse_numeric),
+            ValueType::Reference => unsafe { (*(self.data.ptr as *mut Value)).to_number() },
+            _ => None,
+        }
+    }
+
+    /// The bytes of a string value
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        if self.get_type() != ValueType::String || unsafe { self.data.ptr.is_null() } {
+            return None;
+        }
+        unsafe { Some((*(self.data.ptr as *mut PhpString)).as_bytes()) }
+    }
+
+    /// The text of a string value
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_bytes().and_then(|bytes| std::str::from_utf8(bytes).ok())
+    }
+
+    /// The value as `echo` prints it; arrays print as `Array` and objects,
+    /// whose class the value does not know, as `Object`
+    pub fn to_php_string(&self) -> String {
+        match self.get_type() {
+            ValueType::Null => String::new(),
+            ValueType::Bool => if self.to_bool() { "1".to_string() } else { String::new() },
+            ValueType::Int => self.to_int().to_string(),
+            ValueType::Float => format_float(self.to_float()),
+            ValueType::String => self.as_str().unwrap_or_default().to_string(),
+            ValueType::Array => "Array".to_string(),
+            ValueType::Object => "Object".to_string(),
+            ValueType::Resource => "Resource id #0".to_string(),
+            ValueType::Reference => unsafe { (*(self.data.ptr as *mut Value)).to_php_string() },
+        }
+    }
+}
+
+/// An int or float, as PHP arithmetic sees a value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    pub fn to_float(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
         }
     }
 }
 
+/// Parse the number a string starts with, after leading whitespace
+///
+/// Returns the number and whether the whole string is numeric (trailing
+/// whitespace allowed), or `None` when it does not start with a number.
+/// Integers too large for an int become floats.
+pub fn parse_numeric(s: &str) -> Option<(Number, bool)> {
+    let trimmed = s.trim_start_matches([' ', '\t', '\n', '\r', '\x0b', '\x0c']);
+    let bytes = trimmed.as_bytes();
+    let mut end = 0;
+    if matches!(bytes.first(), Some(b'+' | b'-')) {
+        end += 1;
+    }
+    let digits_start = end;
+    while bytes.get(end).is_some_and(u8::is_ascii_digit) {
+        end += 1;
+    }
+    let mut int_digits = end - digits_start;
+    let mut is_float = false;
+    if bytes.get(end) == Some(&b'.') {
+        let fraction_start = end + 1;
+        let mut fraction_end = fraction_start;
+        while bytes.get(fraction_end).is_some_and(u8::is_ascii_digit) {
+            fraction_end += 1;
+        }
+        if int_digits > 0 || fraction_end > fraction_start {
+            is_float = true;
+            int_digits += fraction_end - fraction_start;
+            end = fraction_end;
+        }
+    }
+    if int_digits == 0 {
+        return None;
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let mut exponent_end = end + 1;
+        if matches!(bytes.get(exponent_end), Some(b'+' | b'-')) {
+            exponent_end += 1;
+        }
+        if bytes.get(exponent_end).is_some_and(u8::is_ascii_digit) {
+            while bytes.get(exponent_end).is_some_and(u8::is_ascii_digit) {
+                exponent_end += 1;
+            }
+            is_float = true;
+            end = exponent_end;
+        }
+    }
+
+    let text = &trimmed[..end];
+    let number = match is_float {
+        false => match text.parse::<i64>() {
+            Ok(i) => Number::Int(i),
+            Err(_) => Number::Float(text.parse().ok()?),
+        },
+        true => Number::Float(text.parse().ok()?),
+    };
+    let whole = trimmed[end..].trim_end_matches([' ', '\t', '\n', '\r', '\x0b', '\x0c']).is_empty();
+    Some((number, whole))
+}
+
+/// Truncate a float to an int the way PHP casts: NaN and infinities become 0
+/// and out-of-range values wrap
+pub fn float_to_int(f: f64) -> i64 {
+    if !f.is_finite() {
+        0
+    } else if (-9.223_372_036_854_776e18..9.223_372_036_854_776e18).contains(&f) {
+        f as i64
+    } else {
+        (f % 18_446_744_073_709_551_616.0) as i128 as i64
+    }
+}
+
+/// Format a float like `echo` does, with 14 significant digits
+pub fn format_float(f: f64) -> String {
+    if f.is_nan() {
+        return "NAN".to_string();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "INF" } else { "-INF" }.to_string();
+    }
+    if f == 0.0 {
+        return if f.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+
+    let scientific = format!("{:.13e}", f);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    if !(-4..14).contains(&exponent) {
+        let mantissa = mantissa.trim_end_matches('0');
+        let mantissa = match mantissa.strip_suffix('.') {
+            Some(whole) => format!("{}.0", whole),
+            None => mantissa.to_string(),
+        };
+        let sign = if exponent < 0 { '-' } else { '+' };
+        return format!("{}E{}{}", mantissa, sign, exponent.abs());
+    }
+
+    let decimals = (13 - exponent).max(0) as usize;
+    let fixed = format!("{:.*}", decimals, f);
+    if fixed.contains('.') {
+        fixed.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        fixed
+    }
+}
+
 /// String representation in memory
 #[repr(C)]
 pub struct PhpString {
@@ -170,13 +378,24 @@ pub struct PhpString {
     pub data: [u8; 0], // Flexible array member
 }
 
+impl PhpString {
+    /// The `len` bytes following the header
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data.as_ptr(), self.len as usize) }
+    }
+}
+
 /// Array representation in memory
 #[repr(C)]
 pub struct PhpArray {
     pub size: u32,
     pub capacity: u32,
     pub next_index: i64,
+    /// `capacity` buckets, each a pointer to a chain of entries
     pub entries_ptr: *mut ArrayEntry,
+    /// The oldest and newest entries: iteration follows insertion order
+    pub first: *mut ArrayEntry,
+    pub last: *mut ArrayEntry,
 }
 
 #[repr(C)]
@@ -184,18 +403,33 @@ pub struct ArrayEntry {
     pub key: ArrayKey,
     pub value: *mut Value,
     pub next: *mut ArrayEntry, // For hash collision chain
+    pub prev_in_order: *mut ArrayEntry,
+    pub next_in_order: *mut ArrayEntry,
 }
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub enum ArrayKey {
     Integer(i64),
     String(*mut PhpString),
 }
 
+impl ArrayKey {
+    /// The key as a value, like `array_keys` returns it
+    pub fn to_value(&self) -> Value {
+        match *self {
+            ArrayKey::Integer(index) => Value::int(index),
+            ArrayKey::String(string) => Value::string(string as *mut u8),
+        }
+    }
+}
+
 /// Object representation in memory
 #[repr(C)]
 pub struct PhpObject {
     pub class_id: u32,
+    /// The object's number, as `var_dump` and `spl_object_id` show it
+    pub handle: u32,
     pub properties_count: u32,
     pub properties: *mut ObjectProperty,
 }
@@ -220,15 +454,35 @@ mod tests {
     fn test_value_creation() {
         let null = Value::null();
         assert_eq!(null.get_type(), ValueType::Null);
-        assert_eq!(null.to_bool(), false);
+        assert!(!null.to_bool());
         
         let int_val = Value::int(42);
         assert_eq!(int_val.get_type(), ValueType::Int);
         assert_eq!(int_val.to_int(), 42);
         assert_eq!(int_val.to_float(), 42.0);
         
-        let float_val = Value::float(3.14);
+        let float_val = Value::float(2.5);
         assert_eq!(float_val.get_type(), ValueType::Float);
-        assert_eq!(float_val.to_float(), 3.14);
+        assert_eq!(float_val.to_float(), 2.5);
+    }
+
+    #[test]
+    fn test_numeric_strings() {
+        assert!(matches!(parse_numeric("42"), Some((Number::Int(42), true))));
+        assert!(matches!(parse_numeric("  -7"), Some((Number::Int(-7), true))));
+        assert!(matches!(parse_numeric("1.5e3"), Some((Number::Float(f), true)) if f == 1500.0));
+        assert!(matches!(parse_numeric("12abc"), Some((Number::Int(12), false))));
+        assert!(parse_numeric("abc").is_none());
+        assert!(parse_numeric("").is_none());
+    }
+
+    #[test]
+    fn test_float_formatting() {
+        assert_eq!(format_float(1.0), "1");
+        assert_eq!(format_float(0.1 + 0.2), "0.3");
+        assert_eq!(format_float(-2.5), "-2.5");
+        assert_eq!(format_float(1e100), "1.0E+100");
+        assert_eq!(format_float(f64::INFINITY), "INF");
+        assert_eq!(format_float(f64::NAN), "NAN");
     }
 }
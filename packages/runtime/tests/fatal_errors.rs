@@ -0,0 +1,106 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+//! Uncaught errors end a script with the message PHP prints for them, on
+//! both backends.
+
+use edge_php_compiler::{BackendKind, Compiler};
+use edge_php_runtime::Runtime;
+
+const BACKENDS: [BackendKind; 2] = [BackendKind::WasmGc, BackendKind::LinearMemory];
+
+/// Run a script that must fail and return what it printed
+fn run_failing(source: &str, backend: BackendKind) -> String {
+    let mut runtime = Runtime::new().unwrap();
+    let wasm = Compiler::with_options(runtime.compiler_options().backend(backend)).compile(source).unwrap();
+    runtime.load_module(&wasm).unwrap();
+    assert!(runtime.execute().is_err(), "{:?}: the script did not fail", backend);
+    runtime.output().to_string()
+}
+
+/// Check the output of a failing script on both backends
+fn assert_fails_with(source: &str, expected: &str) {
+    for backend in BACKENDS {
+        assert_eq!(run_failing(source, backend), expected, "{:?}", backend);
+    }
+}
+
+const POINT: &str = r#"<?php
+class Point {
+    public readonly int $x;
+    public function __construct(int $x, public readonly string $label = "p") {
+        $this->x = $x;
+    }
+    public function move(): void {
+        $this->x = $this->x + 1;
+    }
+}
+readonly class Size {
+    public int $width;
+    public function __construct(int $width) { $this->width = $width; }
+}
+$point = new Point(3);
+$size = new Size(4);
+echo $point->x, $point->label, $size->width, "\n";
+"#;
+
+#[test]
+fn test_readonly_properties() {
+    let cases = [
+        ("$point->move();", "Cannot modify readonly property Point::$x"),
+        ("$point->x = 5;", "Cannot modify readonly property Point::$x"),
+        ("$point->label = \"q\";", "Cannot modify readonly property Point::$label"),
+        ("$size->width = 5;", "Cannot modify readonly property Size::$width"),
+        ("unset($point->x);", "Cannot unset readonly property Point::$x from global scope"),
+    ];
+    for (statement, message) in cases {
+        let source = format!("{}{}\n", POINT, statement);
+        assert_fails_with(&source, &format!("3p4\nPHP Fatal error:  Uncaught Error: {}\n", message));
+    }
+
+    let uninitialized = "<?php\nclass Point { public readonly int $x; }\n$point = new Point();\n$point->x = 1;\n";
+    assert_fails_with(uninitialized, "PHP Fatal error:  Uncaught Error: Cannot initialize readonly property Point::$x from global scope\n");
+}
+
+const TYPED: &str = r#"
+function takes_int(int $x) { return $x; }
+function returns_int($value): int { return $value; }
+class Box {
+    public int $size = 0;
+    public function scale(float $by): float { return $this->size * $by; }
+}
+$box = new Box();
+$box->size = 2;
+echo takes_int(1), returns_int(2), $box->scale(2), "\n";
+"#;
+
+/// The typed script in coercive or strict mode, followed by `statements`
+fn typed(strict: bool, statements: &str) -> String {
+    let declare = if strict { "declare(strict_types=1);\n" } else { "" };
+    format!("<?php\n{}{}{}\n", declare, TYPED, statements)
+}
+
+#[test]
+fn test_type_errors() {
+    // Coercive mode converts numeric strings and only rejects what it cannot convert
+    let coercive = [
+        ("echo takes_int(\"5\"), \"\\n\";\ntakes_int(\"abc\");", "5\n", "takes_int(): Argument #1 ($x) must be of type int, string given"),
+        ("echo returns_int(\"6\"), \"\\n\";\nreturns_int(\"abc\");", "6\n", "returns_int(): Return value must be of type int, string returned"),
+        ("$box->size = \"7\";\necho $box->size, \"\\n\";\n$box->size = \"abc\";", "7\n", "Cannot assign string to property Box::$size of type int"),
+        ("echo $box->scale(\"1.5\"), \"\\n\";\n$box->scale([]);", "3\n", "Box::scale(): Argument #1 ($by) must be of type float, array given"),
+    ];
+    for (statements, printed, message) in coercive {
+        assert_fails_with(&typed(false, statements), &format!("124\n{}PHP Fatal error:  Uncaught TypeError: {}\n", printed, message));
+    }
+
+    // Strict mode only lets an int through where a float is expected
+    let strict = [
+        ("takes_int(\"5\");", "takes_int(): Argument #1 ($x) must be of type int, string given"),
+        ("returns_int(\"6\");", "returns_int(): Return value must be of type int, string returned"),
+        ("$box->size = \"7\";", "Cannot assign string to property Box::$size of type int"),
+        ("$box->scale(\"1.5\");", "Box::scale(): Argument #1 ($by) must be of type float, string given"),
+    ];
+    for (statements, message) in strict {
+        assert_fails_with(&typed(true, statements), &format!("124\nPHP Fatal error:  Uncaught TypeError: {}\n", message));
+    }
+}
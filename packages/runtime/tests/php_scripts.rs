@@ -0,0 +1,94 @@
+// Copyright 2024 Maravilla Labs, Operated by SOLUTAS GmbH, Switzerland
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compiles and runs the PHP scripts in the repository's `tests/` directory
+//! and compares their output with the `.expected` file next to them.
+//!
+//! `tests/<name>.expected` belongs to the script `tests/<name>.php` or, when
+//! that does not exist, to the project `tests/<name>/index.php`, which is
+//! compiled with its `composer.json`. Scripts without an `.expected` file are
+//! not run, which leaves out the benchmarks that time themselves with
+//! `microtime()`. Every script runs on both backends against the same file,
+//! which is what keeps the linear-memory backend at parity with the WasmGC
+//! one. The scripts one backend still gets wrong are listed in
+//! [`KNOWN_DIVERGENCES`]; they must keep failing there until the entry is
+//! removed, so the list cannot go stale.
+
+use edge_php_compiler::{BackendKind, Compiler, Project};
+use edge_php_runtime::Runtime;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Scripts whose output on a backend is known to differ from PHP, and why
+const KNOWN_DIVERGENCES: &[(&str, BackendKind, &str)] = &[
+    ("test_arithmetic_coercion", BackendKind::WasmGc, "arithmetic reads numeric strings as 0 and floats print through a placeholder formatter"),
+    ("test_array_functions", BackendKind::WasmGc, "array_shift() does not remove the first element"),
+    ("test_array_intensive", BackendKind::WasmGc, "hash tables replace the entry already in a bucket when two keys collide"),
+    ("test_casting", BackendKind::WasmGc, "(int) of a float string keeps the string and floats print through a placeholder formatter"),
+    ("test_escape_benefit", BackendKind::WasmGc, "unboxed integer overflow wraps instead of becoming a float"),
+    ("test_foreach_assoc", BackendKind::WasmGc, "hash tables iterate in bucket order, not insertion order"),
+    ("test_magic_methods", BackendKind::WasmGc, "__destruct only runs when the script ends, as the host collector does not report unreachable objects"),
+];
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tests")
+}
+
+/// Compile the script or project `expected` belongs to with `backend` and run it
+fn run(expected: &Path, backend: BackendKind) -> anyhow::Result<String> {
+    let mut runtime = Runtime::new()?;
+    let compiler = Compiler::with_options(runtime.compiler_options().backend(backend));
+    let script = expected.with_extension("php");
+    let wasm = if script.is_file() {
+        compiler.compile(&fs::read_to_string(&script)?)?
+    } else {
+        let root = expected.with_extension("");
+        let mut project = Project::new(root.join("index.php"));
+        let composer = root.join("composer.json");
+        if composer.is_file() {
+            project.load_composer_json(&composer)?;
+        }
+        compiler.compile_project(&project)?
+    };
+    runtime.load_module(&wasm)?;
+    runtime.execute()
+}
+
+fn check_scripts(backend: BackendKind) {
+    let mut expected_files: Vec<_> = fs::read_dir(tests_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "expected"))
+        .collect();
+    expected_files.sort();
+    assert!(!expected_files.is_empty(), "no .expected files in {}", tests_dir().display());
+
+    let mut failures = Vec::new();
+    for path in &expected_files {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let expected = fs::read_to_string(path).unwrap();
+        let result = run(path, backend);
+        if KNOWN_DIVERGENCES.iter().any(|&(script, diverging, _)| script == name && diverging == backend) {
+            if matches!(&result, Ok(output) if *output == expected) {
+                failures.push(format!("{}: passes now, remove it from KNOWN_DIVERGENCES", name));
+            }
+            continue;
+        }
+        match result {
+            Ok(output) if output == expected => {}
+            Ok(output) => failures.push(format!("{}: expected\n{}\ngot\n{}", name, expected, output)),
+            Err(error) => failures.push(format!("{}: {:#}", name, error)),
+        }
+    }
+    assert!(failures.is_empty(), "{} of {} scripts failed on {:?}:\n\n{}", failures.len(), expected_files.len(), backend, failures.join("\n\n"));
+}
+
+#[test]
+fn test_php_scripts() {
+    check_scripts(BackendKind::WasmGc);
+}
+
+#[test]
+fn test_php_scripts_linear_memory() {
+    check_scripts(BackendKind::LinearMemory);
+}
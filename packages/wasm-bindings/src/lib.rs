@@ -3,8 +3,8 @@
 
 use wasm_bindgen::prelude::*;
 use edge_php_parser::parse;
-use edge_php_compiler::Compiler;
-use serde::Serialize;
+use edge_php_compiler::{BackendKind, Compiler, CompilerOptions, Feature, ImportedFunction, OptLevel};
+use serde::{Deserialize, Serialize};
 
 #[wasm_bindgen]
 extern "C" {
@@ -22,12 +22,128 @@ pub struct CompileResult {
     wasm_bytes: Option<Vec<u8>>,
     error: Option<String>,
     ast: Option<String>,
+    source_map: Option<String>,
+}
+
+/// Options accepted by `compile_php`; every field is optional
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CompileOptions {
+    backend: Option<String>,
+    opt_level: Option<String>,
+    loop_unrolling: Option<bool>,
+    escape_analysis: Option<bool>,
+    unboxing: Option<bool>,
+    tree_shaking: Option<bool>,
+    specialization: Option<bool>,
+    ir: Option<bool>,
+    constant_folding: Option<bool>,
+    inlining: Option<bool>,
+    loop_optimizations: Option<bool>,
+    typed_arrays: Option<bool>,
+    memory_pages: Option<u32>,
+    max_memory_pages: Option<u32>,
+    features: Option<Vec<String>>,
+    debug_info: bool,
+    source_map: bool,
+    fuel_metering: bool,
+    http_handler: bool,
+    /// Host function signatures like `kv_get(string $key): string`, imported from `env`
+    host_functions: Vec<String>,
+}
+
+impl CompileOptions {
+    fn to_compiler_options(&self) -> Result<CompilerOptions, String> {
+        let mut options = CompilerOptions::new()
+            .max_memory_pages(self.max_memory_pages)
+            .debug_info(self.debug_info)
+            .source_map(self.source_map)
+            .fuel_metering(self.fuel_metering)
+            .http_handler(self.http_handler);
+        if let Some(backend) = &self.backend {
+            options = options.backend(backend.parse::<BackendKind>()?);
+        }
+        if let Some(level) = &self.opt_level {
+            options = options.opt_level(level.parse::<OptLevel>()?);
+        }
+        if let Some(enabled) = self.loop_unrolling {
+            options = options.loop_unrolling(enabled);
+        }
+        if let Some(enabled) = self.escape_analysis {
+            options = options.escape_analysis(enabled);
+        }
+        if let Some(enabled) = self.unboxing {
+            options = options.unboxing(enabled);
+        }
+        if let Some(enabled) = self.tree_shaking {
+            options = options.tree_shaking(enabled);
+        }
+        if let Some(enabled) = self.specialization {
+            options = options.specialization(enabled);
+        }
+        if let Some(enabled) = self.ir {
+            options = options.mid_level_ir(enabled);
+        }
+        if let Some(enabled) = self.constant_folding {
+            options = options.constant_folding(enabled);
+        }
+        if let Some(enabled) = self.inlining {
+            options = options.inlining(enabled);
+        }
+        if let Some(enabled) = self.loop_optimizations {
+            options = options.loop_optimizations(enabled);
+        }
+        if let Some(enabled) = self.typed_arrays {
+            options = options.typed_arrays(enabled);
+        }
+        if let Some(pages) = self.memory_pages {
+            options = options.initial_memory_pages(pages);
+        }
+        // The listed features replace the default set
+        if let Some(features) = &self.features {
+            let mut target = edge_php_compiler::TargetFeatures {
+                gc: false,
+                exceptions: false,
+                tail_calls: false,
+                simd: false,
+            };
+            for feature in features {
+                target.set(feature.parse::<Feature>()?, true);
+            }
+            options = options.features(target);
+        }
+        for signature in &self.host_functions {
+            options = options.import_function(ImportedFunction::host(signature)?);
+        }
+        Ok(options)
+    }
 }
 
 #[wasm_bindgen]
-pub fn compile_php(source: &str) -> JsValue {
+pub fn compile_php(source: &str, options: JsValue) -> JsValue {
     console_log!("Compiling PHP source: {} bytes", source.len());
     
+    let options = if options.is_undefined() || options.is_null() {
+        Ok(CompilerOptions::new())
+    } else {
+        serde_wasm_bindgen::from_value::<CompileOptions>(options)
+            .map_err(|e| e.to_string())
+            .and_then(|options| options.to_compiler_options())
+    };
+    let options = match options {
+        Ok(options) => options,
+        Err(e) => {
+            let result = CompileResult {
+                success: false,
+                wasm_bytes: None,
+                error: Some(format!("Invalid options: {}", e)),
+                ast: None,
+                source_map: None,
+            };
+            return serde_wasm_bindgen::to_value(&result).unwrap();
+        }
+    };
+    
     // First try to parse
     let ast = match parse(source) {
         Ok(ast) => {
@@ -41,6 +157,7 @@ pub fn compile_php(source: &str) -> JsValue {
                 wasm_bytes: None,
                 error: Some(format!("Parse error: {:?}", e)),
                 ast: None,
+                source_map: None,
             };
             return serde_wasm_bindgen::to_value(&result).unwrap();
         }
@@ -50,15 +167,16 @@ pub fn compile_php(source: &str) -> JsValue {
     let ast_string = format!("{:#?}", ast);
     
     // Then compile
-    let compiler = Compiler::new();
-    match compiler.compile(source) {
-        Ok(wasm_bytes) => {
-            console_log!("Compilation successful: {} bytes", wasm_bytes.len());
+    let compiler = Compiler::with_options(options);
+    match compiler.compile_module("input.php", source) {
+        Ok(module) => {
+            console_log!("Compilation successful: {} bytes", module.wasm.len());
             let result = CompileResult {
                 success: true,
-                wasm_bytes: Some(wasm_bytes),
+                wasm_bytes: Some(module.wasm),
                 error: None,
                 ast: Some(ast_string),
+                source_map: module.source_map,
             };
             serde_wasm_bindgen::to_value(&result).unwrap()
         }
@@ -69,6 +187,7 @@ pub fn compile_php(source: &str) -> JsValue {
                 wasm_bytes: None,
                 error: Some(format!("Compilation error: {:?}", e)),
                 ast: Some(ast_string),
+                source_map: None,
             };
             serde_wasm_bindgen::to_value(&result).unwrap()
         }
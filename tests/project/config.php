@@ -0,0 +1,2 @@
+<?php
+return ['name' => 'fixture', 'version' => 2];
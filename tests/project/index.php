@@ -0,0 +1,29 @@
+<?php
+// Multi-file project: compile with
+//   edge-php compile tests/project/index.php --composer tests/project/composer.json
+declare(strict_types=1);
+
+namespace App;
+
+use App\Models\User;
+use App\Models\{Admin, Role as R};
+use function App\Support\shout;
+
+require_once __DIR__ . '/lib/functions.php';
+require_once 'lib/functions.php';
+$config = require __DIR__ . '/config.php';
+
+$user = new User("ada");
+echo greet($user->name), "\n";
+echo $user->label(), "\n";
+echo shout("hi"), " ", Support\whisper("HI"), "\n";
+echo strlen("abc"), " ", \strtoupper("x"), " ", counter(), counter(), "\n";
+echo User::class, " ", __NAMESPACE__, "\n";
+echo $config['name'], " v", $config['version'], "\n";
+
+$admin = new Admin("root", R::Owner);
+echo $admin->describe(), "\n";
+
+if ($admin->role === R::Owner) {
+    include 'partials/footer.php';
+}
@@ -0,0 +1,12 @@
+<?php
+namespace App\Support;
+
+function shout(string $text): string
+{
+    return strtoupper($text) . "!";
+}
+
+function whisper(string $text): string
+{
+    return strtolower($text) . "...";
+}
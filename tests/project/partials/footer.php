@@ -0,0 +1,4 @@
+<footer>edge-php</footer>
+<?php function footer_year(): int { return 2026; } ?>
+year <?= footer_year() ?>
+
@@ -0,0 +1,7 @@
+<?php
+namespace App\Contracts;
+
+interface Describable
+{
+    public function describe(): string;
+}
@@ -0,0 +1,14 @@
+<?php
+namespace App\Models;
+
+use App\Contracts\Describable;
+
+final class Admin implements Describable
+{
+    public function __construct(public string $name, public Role $role) {}
+
+    public function describe(): string
+    {
+        return __METHOD__ . ": " . $this->name . " is " . $this->role->name;
+    }
+}
@@ -0,0 +1,8 @@
+<?php
+namespace App\Models;
+
+enum Role
+{
+    case Owner;
+    case Guest;
+}
@@ -0,0 +1,12 @@
+<?php
+namespace App\Models;
+
+class User
+{
+    public function __construct(public string $name) {}
+
+    public function label(): string
+    {
+        return __CLASS__ . "::" . $this->name;
+    }
+}
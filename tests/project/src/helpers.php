@@ -0,0 +1,14 @@
+<?php
+namespace App;
+
+function greet(string $name): string
+{
+    return "Hello, " . $name;
+}
+
+function counter(): int
+{
+    static $count = 0;
+    $count++;
+    return $count;
+}
@@ -14,4 +14,13 @@ $arr2 = [1, 2, 3];
 $result = in_array(2, $arr2);
 echo "in_array(2, [1,2,3]): ", $result, "\n";
 
+// Test 3: unset
+echo "\n=== Test 3: unset() ===\n";
+$arr3 = [1, 2, 3];
+unset($arr3[1]);
+$map = ["a" => 1, "b" => 2];
+unset($map["a"], $map["missing"]);
+echo "count after unset: ", count($arr3), " ", count($map), "\n";
+echo "isset(\$arr3[1]): ", isset($arr3[1]) ? "yes" : "no", "\n";
+
 echo "\n=== Done! ===\n";
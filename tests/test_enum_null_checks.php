@@ -0,0 +1,29 @@
+<?php
+// Null checks and ?? in a script that declares an enum; the enum cases are
+// created before the first statement runs
+enum Num: int {
+    case One = 1;
+    case Two = 2;
+}
+
+enum Mode {
+    case Read;
+    case Write;
+}
+
+$y = null;
+echo $y ?? "default", "\n";
+echo is_null($y) ? "null" : "not null", "\n";
+echo isset($y) ? "set" : "unset", "\n";
+echo $y === null ? "identical" : "different", "\n";
+
+echo Num::tryFrom(3) ?? 'none', "\n";
+echo Num::tryFrom(2)?->name ?? 'none', "\n";
+$found = Num::tryFrom(1);
+echo $found === null ? "missing" : $found->name, "\n";
+echo Num::from(2)->value, "\n";
+
+$mode = Mode::Read;
+$other = null;
+echo ($other ?? $mode)->name, "\n";
+echo $mode === Mode::Read ? "read" : "write", "\n";
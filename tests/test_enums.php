@@ -0,0 +1,74 @@
+<?php
+// Test pure and backed enums, interfaces and class constants
+interface HasLabel {
+    const PREFIX = "Suit: ";
+    public function label(): string;
+}
+
+enum Suit: string implements HasLabel {
+    case Hearts = 'H';
+    case Diamonds = 'D';
+    case Clubs = 'C';
+    case Spades = 'S';
+
+    const Wild = self::Spades;
+
+    public function color(): string {
+        switch ($this) {
+            case self::Hearts:
+                return "Red";
+            case self::Diamonds:
+                return "Red";
+        }
+        return "Black";
+    }
+
+    public function label(): string {
+        return self::PREFIX . $this->name;
+    }
+
+    public function next() {
+        return self::tryFrom($this->value . "") ?? self::Clubs;
+    }
+}
+
+enum Status {
+    case Active;
+    case Inactive;
+}
+
+enum Priority: int {
+    case Low = 1;
+    case High = 10;
+}
+
+echo Suit::Hearts->value, " ", Suit::Hearts->name, "\n";
+echo Suit::Hearts->color(), " ", Suit::Spades->color(), "\n";
+$s = Suit::from('D');
+echo $s->label(), "\n";
+echo $s === Suit::Diamonds ? "same" : "different", "\n";
+echo $s === Suit::Hearts ? "same" : "different", "\n";
+echo Suit::tryFrom('X') === null ? "null" : "case", "\n";
+echo Suit::Wild->name, "\n";
+echo count(Suit::cases()), "\n";
+foreach (Suit::cases() as $case) {
+    echo $case->name, "=", $case->value, " ";
+}
+echo "\n";
+echo Suit::Hearts->next()->name, "\n";
+echo Status::Active->name, "\n";
+echo Status::Active === Status::Inactive ? "yes" : "no", "\n";
+echo Status::Active == Status::Active ? "yes" : "no", "\n";
+echo Priority::from("10")->name, " ", Priority::High->value + 1, "\n";
+echo Suit::class, " ", HasLabel::PREFIX, "\n";
+
+function describe($p) {
+    switch ($p) {
+        case Priority::Low: return "low";
+        case Priority::High: return "high";
+    }
+    return "?";
+}
+echo describe(Priority::High), "\n";
+$items = [Status::Inactive, Status::Active];
+echo $items[1]->name, "\n";
@@ -0,0 +1,68 @@
+<?php
+// Test global, static and $GLOBALS variable scoping
+$counter = 0;
+$name = "world";
+$items = [1, 2];
+
+function bump() {
+    global $counter;
+    $counter++;
+    $counter = $counter + 10;
+}
+
+function greet() {
+    return "Hello, " . $GLOBALS['name'];
+}
+
+function rename($n) {
+    $GLOBALS['name'] = $n;
+    $GLOBALS['items'][2] = 3;
+}
+
+function tick() {
+    static $calls = 0;
+    $calls++;
+    return $calls;
+}
+
+function nothing() {
+    static $v;
+    $result = $v === null ? "null" : "set";
+    $v = 1;
+    return $result;
+}
+
+function isolated() {
+    return isset($counter) ? "leaked" : "isolated";
+}
+
+class Counter {
+    public function next() {
+        static $n = 100;
+        $n = $n + 1;
+        return $n;
+    }
+}
+
+bump();
+bump();
+echo $counter, "\n";
+echo greet(), "\n";
+rename("PHP");
+echo greet(), " ", count($items), "\n";
+echo tick(), tick(), tick(), "\n";
+echo nothing(), " ", nothing(), "\n";
+echo isolated(), "\n";
+$c = new Counter();
+echo $c->next(), " ", $c->next(), "\n";
+foreach ([7, 8, 9] as $counter) {
+}
+echo $counter, "\n";
+$alpha = 5;
+$beta = "b";
+function lookup($k) {
+    return $GLOBALS[$k];
+}
+echo lookup("alpha"), lookup("beta"), "\n";
+$all = $GLOBALS;
+echo count($all), "\n";
@@ -0,0 +1,161 @@
+<?php
+// Test magic methods: __toString, property hooks, __call/__callStatic,
+// __invoke, clone/__clone and __destruct
+class Money {
+    public $amount;
+    public $currency;
+
+    public function __construct($amount, $currency) {
+        $this->amount = $amount;
+        $this->currency = $currency;
+    }
+
+    public function __toString() {
+        return $this->amount . " " . $this->currency;
+    }
+}
+
+class Config {
+    private $data = [];
+
+    public function __construct() {
+        $this->data = ["debug" => "off"];
+    }
+
+    public function __get($name) {
+        echo "get $name\n";
+        return $this->data[$name] ?? "unset";
+    }
+
+    public function __set($name, $value) {
+        echo "set $name\n";
+        $this->data[$name] = $value;
+    }
+
+    public function __isset($name) {
+        return isset($this->data[$name]);
+    }
+
+    public function __unset($name) {
+        echo "unset $name\n";
+        unset($this->data[$name]);
+    }
+}
+
+class Proxy {
+    public function __call($name, $arguments) {
+        return $name . "(" . count($arguments) . ")";
+    }
+
+    public static function __callStatic($name, $arguments) {
+        return "static " . $name . ":" . $arguments[0];
+    }
+}
+
+class Multiplier {
+    public $factor;
+
+    public function __construct($factor) {
+        $this->factor = $factor;
+    }
+
+    public function __invoke($value) {
+        return $value * $this->factor;
+    }
+}
+
+class Point {
+    public $x;
+
+    public function __construct($x) {
+        $this->x = $x;
+    }
+}
+
+class Shape {
+    public $origin;
+    public $tags;
+
+    public function __construct() {
+        $this->origin = new Point(1);
+        $this->tags = ["a", "b"];
+    }
+
+    public function __clone() {
+        echo "cloning shape\n";
+        $this->origin = clone $this->origin;
+    }
+}
+
+class Resource {
+    public $name;
+
+    public function __construct($name) {
+        $this->name = $name;
+    }
+
+    public function __destruct() {
+        echo "destroying " . $this->name . "\n";
+    }
+}
+
+// __toString in echo, concatenation and interpolation
+$price = new Money(5, "EUR");
+echo $price, "\n";
+echo "Price: " . $price . "\n";
+echo "Interpolated: $price\n";
+echo strlen($price), "\n";
+
+// __get / __set / __isset / __unset
+$config = new Config();
+echo $config->debug, "\n";
+$config->level = 3;
+echo $config->level, "\n";
+echo isset($config->level) ? "level set" : "level missing", "\n";
+echo isset($config->other) ? "other set" : "other missing", "\n";
+unset($config->level);
+echo isset($config->level) ? "level set" : "level missing", "\n";
+
+// __call / __callStatic
+$proxy = new Proxy();
+echo $proxy->fetch(1, 2), "\n";
+echo Proxy::make("x"), "\n";
+
+// __invoke
+$double = new Multiplier(2);
+echo $double(21), "\n";
+
+// clone is shallow for objects (until __clone copies them), arrays are copied
+$shape = new Shape();
+$copy = clone $shape;
+$copy->origin->x = 10;
+$copy->tags[0] = "z";
+echo $shape->origin->x, " ", $copy->origin->x, "\n";
+echo $shape->tags[0], " ", $copy->tags[0], "\n";
+
+$p1 = new Point(3);
+$p2 = clone $p1;
+$p2->x = 4;
+echo $p1->x, " ", $p2->x, "\n";
+
+// Objects whose class is only known at runtime
+function describe($value) {
+    return "[" . $value . "]";
+}
+echo describe($price), "\n";
+
+function read($object) {
+    return $object->missing;
+}
+echo read($config), "\n";
+
+// __destruct runs once the last reference is gone, or when the script ends
+$temp = new Resource("tmp");
+$alias = $temp;
+$temp = null;
+echo "alias keeps tmp\n";
+$alias = null;
+echo "after tmp\n";
+
+$resource = new Resource("db");
+echo "end of script\n";
@@ -0,0 +1,88 @@
+<?php
+// Namespaces, use imports and aliases, function fallback and magic constants
+
+namespace Shop\Pricing {
+    interface Priced
+    {
+        public function price(): int;
+    }
+
+    class Money
+    {
+        public function __construct(public int $cents) {}
+
+        public function format(): string
+        {
+            return number_format_cents($this->cents);
+        }
+    }
+
+    function number_format_cents(int $cents): string
+    {
+        $fraction = $cents % 100;
+        return (($cents - $fraction) / 100) . "." . ($fraction < 10 ? "0" : "") . $fraction;
+    }
+
+    function tax(int $cents): int
+    {
+        return $cents * 20 / 100;
+    }
+}
+
+namespace Shop\Catalog {
+    use Shop\Pricing\{Money, Priced};
+    use Shop\Pricing as P;
+    use function Shop\Pricing\tax as vat;
+
+    enum Category: string
+    {
+        case Books = 'books';
+        case Games = 'games';
+    }
+
+    final class Product implements Priced
+    {
+        public function __construct(
+            public string $name,
+            public Category $category,
+            private int $cents,
+        ) {}
+
+        public function price(): int
+        {
+            return $this->cents + vat($this->cents);
+        }
+
+        public function money(): Money
+        {
+            return new P\Money($this->price());
+        }
+
+        public function describe(): string
+        {
+            return __METHOD__ . " " . $this->name;
+        }
+    }
+
+    function strlen(string $text): int
+    {
+        return 42;
+    }
+
+    function catalog_name(): string
+    {
+        return __FUNCTION__ . " in " . __NAMESPACE__;
+    }
+}
+
+namespace {
+    use Shop\Catalog\Product;
+    use Shop\Catalog\Category as Kind;
+
+    $book = new Product("Dune", Kind::Books, 1000);
+    echo $book->money()->format(), " ", $book->category->value, "\n";
+    echo $book->describe(), "\n";
+    echo Product::class, " ", Shop\Pricing\Money::class, " ", \Shop\Catalog\Category::Games->value, "\n";
+    echo Shop\Catalog\catalog_name(), "\n";
+    echo strlen("abc"), " ", Shop\Catalog\strlen("abc"), " ", \strlen("abcd"), "\n";
+}
@@ -0,0 +1,67 @@
+<?php
+// Test nullsafe operator, null coalescing, list() destructuring and isset/empty on nested access
+class Address {
+    public $city = "Zurich";
+    function getCity() { return $this->city; }
+}
+class User {
+    public $name = "Ann";
+    public $address = null;
+    function getAddress() { return $this->address; }
+}
+$u = new User();
+echo $u?->getAddress()?->getCity() ?? "no city";
+echo "\n";
+$u->address = new Address();
+echo $u?->getAddress()?->getCity();
+echo "\n";
+$n = null;
+echo $n?->name ?? "null user";
+echo "\n";
+$cfg = ['db' => ['host' => 'localhost']];
+echo $cfg['db']['host'] ?? 'none';
+echo "\n";
+echo $cfg['db']['port'] ?? 3306;
+echo "\n";
+echo $cfg['cache']['ttl'] ?? 'no ttl';
+echo "\n";
+echo $undefined ?? "undef";
+echo "\n";
+[$a, $b] = [10, 20];
+echo $a + $b;
+echo "\n";
+[, $second] = [1, 2];
+echo $second . "\n";
+['x' => $x, 'y' => $y] = ['x' => 5, 'y' => 6];
+echo $x * $y;
+echo "\n";
+[[$p, $q], $r] = [[1, 2], 3];
+echo $p . $q . $r . "\n";
+list($l1, $l2) = [7, 8];
+echo $l1 . $l2 . "\n";
+$pairs = [[1, 'one'], [2, 'two']];
+foreach ($pairs as [$num, $word]) {
+    echo $num . "=" . $word . "\n";
+}
+foreach ($pairs as $i => list($num, $word)) {
+    echo $i . ":" . $word . "\n";
+}
+$m = [1];
+[$m0, $m1] = $m;
+echo isset($m1) ? "set" : "unset";
+echo "\n";
+echo isset($cfg['db']['host'], $cfg['db']) ? "yes" : "no";
+echo "\n";
+echo isset($cfg['db']['nope']) ? "yes" : "no";
+echo "\n";
+echo empty($cfg['x']['y']) ? "empty" : "full";
+echo "\n";
+echo isset($u->address->city) ? "has city" : "no city";
+echo "\n";
+$v = null;
+$v ??= "default";
+echo $v . "\n";
+$cnt = 5;
+[$cnt, $other] = [6, 7];
+echo $cnt + $other;
+echo "\n";
@@ -0,0 +1,82 @@
+<?php
+// Test constructor promotion, readonly properties and classes, static properties,
+// `new` in parameter defaults and the final modifier
+declare(strict_types=1);
+
+interface Logger {
+    public function log(string $message): void;
+}
+
+final class EchoLogger implements Logger {
+    public function __construct(private string $prefix = "log") {}
+
+    public function log(string $message): void {
+        echo "[", $this->prefix, "] ", $message, "\n";
+    }
+}
+
+final readonly class Money {
+    public function __construct(
+        public int $amount,
+        public string $currency = "EUR",
+    ) {}
+
+    public function add(Money $other): static {
+        return new static($this->amount + $other->amount, $this->currency);
+    }
+}
+
+class User {
+    private static int $count = 0;
+    public static array $names = [];
+    public readonly int $id;
+
+    public function __construct(
+        public readonly string $name,
+        private Logger $logger = new EchoLogger(),
+    ) {
+        self::$count++;
+        $this->id = self::$count;
+        static::$names[count(static::$names)] = $name;
+        $this->logger->log("created " . $name);
+    }
+
+    public static function count(): int {
+        return self::$count;
+    }
+
+    final public function describe(): string {
+        return $this->id . ":" . $this->name;
+    }
+}
+
+$a = new User("ada");
+$b = new User("bob", new EchoLogger("audit"));
+echo $a->describe(), " ", $b->describe(), "\n";
+echo User::count(), " ", count(User::$names), " ", User::$names[1], "\n";
+
+$m = new Money(5);
+$n = $m->add(new Money(7));
+echo $n->amount, " ", $n->currency, "\n";
+
+function make(Logger $logger = new EchoLogger("default")): Logger {
+    return $logger;
+}
+make()->log("first");
+make()->log("second");
+
+class Counter {
+    public static $hits = 10;
+    public $value = 1;
+}
+Counter::$hits += 5;
+$before = Counter::$hits--;
+echo $before, " ", Counter::$hits, " ", ++Counter::$hits, "\n";
+$c = new Counter();
+$c->value++;
+++$c->value;
+echo $c->value, "\n";
+
+$copy = clone $a;
+echo $copy->name, " ", $copy->id, "\n";
+echo isset($a->name) ? "set" : "unset", "\n";
@@ -0,0 +1,21 @@
+<?php
+declare(strict_types=1);
+// Test strict_types: exact parameter and return checks, int widens to float
+function scale(float $x, int $factor): float {
+    return $x * $factor;
+}
+
+function label(?string $name = null): string {
+    return $name ?? "anonymous";
+}
+
+function pick(int|string $key): string {
+    return "key " . $key;
+}
+
+echo scale(3, 2), "\n";
+echo scale(0.5, 3), "\n";
+echo label(), "\n";
+echo label("edge"), "\n";
+echo pick(4), "\n";
+echo pick("four"), "\n";
@@ -0,0 +1,38 @@
+<?php
+// Test heredoc/nowdoc, complex interpolation and escape sequences
+class User {
+    public $name = "Ann";
+    public $tags = ['x' => 'admin'];
+    function greet($who) { return "hi " . $who; }
+}
+$user = new User();
+$arr = ['key' => 'value', 3 => 'three']; $nested = ['b' => ['x' => ['y' => 'deep']]];
+$i = 3;
+$k = 'key';
+echo "Name: {$user->name}\n";
+echo "Simple: $user->name!\n";
+echo "Arr: $arr[key] / $arr[3] / $arr[$k]\n";
+echo "Deep: {$nested['b']['x']['y']}\n";
+echo "Dollar brace: ${k} and ${arr['key']}\n";
+echo "Method: {$user->greet("Bob")}\n";
+echo "Index var: {$arr[$i]}\n";
+echo "Escapes: \x41\101\u{1F600}\u{e9}\t|\v|\e[0m|\f|\$k|\\|\q\n";
+echo "Bytes: \xC3\xA9\n";
+$html = <<<HTML
+    <div class="user">
+      <span>{$user->name}</span>
+      <em>$arr[key]</em> "quoted" \"kept\"
+    </div>
+    HTML;
+echo $html, "\n";
+$sql = <<<'SQL'
+SELECT * FROM t WHERE name = '$name' AND x = {$y}\n
+SQL;
+echo $sql, "\n";
+echo <<<"EOT"
+  Count: {$i}
+  EOT . "!\n";
+$empty = <<<EOT
+EOT;
+echo "[", $empty, "]\n";
+echo "Literal: {\$k} \{$k} $ {$k}\n";
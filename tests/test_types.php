@@ -0,0 +1,125 @@
+<?php
+// Test type declarations: coercive-mode parameters and returns, nullable,
+// union and intersection types, typed properties and unboxed typed parameters
+interface Shape {
+    public function area(): float;
+}
+
+interface Named {
+    public function name(): string;
+}
+
+class Square implements Shape, Named {
+    public int $side = 0;
+    public ?string $label = null;
+
+    public function __construct(int $side) {
+        $this->side = $side;
+    }
+
+    public function area(): float {
+        return $this->side * $this->side;
+    }
+
+    public function name(): string {
+        return "square";
+    }
+
+    public function withSide(int $side): static {
+        $copy = clone $this;
+        $copy->side = $side;
+        return $copy;
+    }
+}
+
+class Label {
+    public function __toString(): string {
+        return "label";
+    }
+}
+
+function add(int $a, int $b): int {
+    return $a + $b;
+}
+
+function half(float $x): float {
+    return $x / 2;
+}
+
+function describe(int|string $value): string {
+    return "value " . $value;
+}
+
+function maybe(?int $x = null): string {
+    return $x === null ? "none" : "int " . $x;
+}
+
+function flag(bool $on): string {
+    return $on ? "on" : "off";
+}
+
+function shout(string $s): string {
+    return $s . "!";
+}
+
+function total(Shape&Named $shape): string {
+    return $shape->name() . " " . $shape->area();
+}
+
+function first(array $items): mixed {
+    return $items[0];
+}
+
+function nothing(): void {
+    echo "void\n";
+}
+
+function countdown(int $n): int {
+    $sum = 0;
+    while ($n > 0) {
+        $sum = $sum + $n;
+        $n = $n - 1;
+    }
+    return $sum;
+}
+
+function onlyFalse(int|false $x): string {
+    return $x === false ? "false" : "int";
+}
+
+// Coercive mode: numeric strings, floats and bools convert
+echo add(2, 3), "\n";
+echo add("4", "5"), "\n";
+echo add(1.0, true), "\n";
+echo half(3), "\n";
+echo half("3"), "\n";
+echo describe(7), "\n";
+echo describe("seven"), "\n";
+echo describe(1.5), "\n";
+echo maybe(), "\n";
+echo maybe(null), "\n";
+echo maybe("12"), "\n";
+echo flag(1), "\n";
+echo flag(""), "\n";
+echo shout(42), "\n";
+echo shout(new Label()), "\n";
+echo total(new Square(3)), "\n";
+echo first([10, 20]), "\n";
+nothing();
+echo countdown(4), "\n";
+echo onlyFalse(false), "\n";
+echo onlyFalse(3), "\n";
+
+// Typed properties coerce on assignment
+$sq = new Square("6");
+echo $sq->side, "\n";
+$sq->side = "8";
+echo $sq->side + 1, "\n";
+$sq->label = 5;
+echo $sq->label, "\n";
+$big = $sq->withSide(10);
+echo $big->side, " ", $sq->side, "\n";
+
+// Declared return types are facts for type inference
+$n = add(20, 22);
+echo $n * 2, "\n";